@@ -9,11 +9,35 @@ use clap::{Args, Parser, Subcommand};
 use pkcs8::der::Decode;
 use plazer_service::{
     config::{
-        LogLevel, ServiceConfigBuilder, DEFAULT_ADDRESS, DEFAULT_CONFIG_PATH, DEFAULT_DATABASE,
-        DEFAULT_HOST, DEFAULT_LOG_DIR, DEFAULT_LOG_LEVEL_FILE, DEFAULT_LOG_LEVEL_STDOUT,
-        DEFAULT_NAMESPACE, DEFAULT_PORT, DEFAULT_PRIVATE_KEY_PATH,
+        AltTextPolicy, IpStorage, LogLevel, MetadataVisibility, PostVisibility, ServeConfig,
+        ServiceConfigBuilder, DEFAULT_ADDRESS, DEFAULT_ADMIN_SESSION_IDLE_TIMEOUT_SECS,
+        DEFAULT_ADMIN_SESSION_MAX_LIFETIME_SECS, DEFAULT_ALLOW_CONFUSABLE_USER_IDS,
+        DEFAULT_ALT_TEXT_POLICY, DEFAULT_ARGON2_ITERATIONS, DEFAULT_ARGON2_MEMORY_KIB,
+        DEFAULT_ARGON2_PARALLELISM, DEFAULT_AUTO_MIGRATE, DEFAULT_CONFIG_PATH, DEFAULT_DATABASE,
+        DEFAULT_DB_POOL_SIZE, DEFAULT_DB_QUERY_TIMEOUT_SECS, DEFAULT_DELETION_GRACE_DAYS,
+        DEFAULT_DEV_AUTH, DEFAULT_DEV_AUTH_ALLOW_RELEASE, DEFAULT_DORMANCY_GRACE_DAYS,
+        DEFAULT_DORMANT_AFTER_DAYS, DEFAULT_EMAIL_FROM, DEFAULT_HOST, DEFAULT_IP_STORAGE,
+        DEFAULT_LOG_DIR, DEFAULT_LOG_LEVEL_FILE, DEFAULT_LOG_LEVEL_STDOUT,
+        DEFAULT_MAX_BOARD_DESCRIPTION_LENGTH, DEFAULT_MAX_BOARD_NAME_LENGTH,
+        DEFAULT_MAX_CONCURRENCY, DEFAULT_MAX_GRAPHQL_CONCURRENCY, DEFAULT_MAX_MEDIA_BYTES,
+        DEFAULT_MAX_POST_CONTENT_LENGTH, DEFAULT_MAX_POST_TITLE_LENGTH,
+        DEFAULT_MAX_POST_VISIBILITY, DEFAULT_MAX_QUERY_COMPLEXITY, DEFAULT_MAX_QUERY_DEPTH,
+        DEFAULT_MAX_QUEUE_MS, DEFAULT_MEDIA_DIR, DEFAULT_MEDIA_GC_GRACE_SECS,
+        DEFAULT_MEDIA_URL_TTL_SECS, DEFAULT_METADATA_RETENTION_DAYS, DEFAULT_METADATA_VISIBILITY,
+        DEFAULT_MIN_AGE, DEFAULT_NAMESPACE, DEFAULT_PERSISTED_QUERY_CACHE_SIZE,
+        DEFAULT_PERSIST_QUERIES, DEFAULT_PORT, DEFAULT_POST_VISIBILITY, DEFAULT_PRIVATE_KEY_PATH,
+        DEFAULT_PROXY_CACHE_BYTES, DEFAULT_PROXY_CACHE_TTL_SECS, DEFAULT_PROXY_MAX_MEDIA_BYTES,
+        DEFAULT_PROXY_REMOTE_CONTENT, DEFAULT_PUBLIC_STATS, DEFAULT_QUOTA_BOARDS,
+        DEFAULT_QUOTA_LISTS, DEFAULT_QUOTA_POSTS, DEFAULT_QUOTA_STORAGE_BYTES,
+        DEFAULT_RATE_LIMIT_AUTH, DEFAULT_RATE_LIMIT_READ, DEFAULT_RATE_LIMIT_WRITE,
+        DEFAULT_READ_ONLY, DEFAULT_READ_ONLY_AFTER_FAILURES, DEFAULT_READ_ONLY_COOLDOWN_SECS,
+        DEFAULT_RELEASE_DORMANT_USER_IDS, DEFAULT_REQUIRE_VERIFIED_EMAIL,
+        DEFAULT_SESSION_IDLE_TIMEOUT_SECS, DEFAULT_SESSION_MAX_LIFETIME_SECS,
+        DEFAULT_SIGNUP_HONEYPOT_SCORE, DEFAULT_SIGNUP_MIN_FORM_SECS, DEFAULT_SIGNUP_TOO_FAST_SCORE,
+        DEFAULT_SPAM_LIMIT_THRESHOLD, DEFAULT_SPAM_REVIEW_THRESHOLD, DEFAULT_TRANSLATIONS_PER_HOUR,
     },
-    init_logging, schema, serve,
+    doctor::diagnose,
+    init_logging, migrate, schema, serve,
 };
 use ring::{rand, signature};
 
@@ -30,6 +54,8 @@ struct Cli {
 }
 
 #[derive(Subcommand)]
+// This is only created once, so its size doesn't matter.
+#[allow(clippy::large_enum_variant)]
 enum Commands {
     #[command(about = "Run server (default)")]
     Run(RunCommand),
@@ -37,11 +63,22 @@ enum Commands {
     Schema(SchemaCommand),
     #[command(about = "Generate JWT signing key")]
     GenerateKey(GenerateKeyCommand),
+    #[command(about = "Check the deployment without starting the server")]
+    Doctor(DoctorCommand),
+    #[command(about = "Run, roll back or inspect database migrations")]
+    Migrate(MigrateCommand),
 }
 
 #[derive(Args)]
 #[command(about = "Starts the server")]
 struct RunCommand {
+    #[arg(
+        short,
+        long,
+        help = format!("The config file to load. Unlike the default one, it has to exist\n\n[default: {DEFAULT_CONFIG_PATH}]")
+    )]
+    config: Option<String>,
+
     #[arg(
         short,
         long,
@@ -55,6 +92,12 @@ struct RunCommand {
     )]
     host: Option<String>,
 
+    #[arg(
+        long,
+        help = "The origins, comma-separated, that browsers can call the API from, such as https://example.com. `*` allows any origin. Without any, only the API's own origin can"
+    )]
+    cors_origins: Option<String>,
+
     #[arg(
         short,
         long,
@@ -76,6 +119,24 @@ struct RunCommand {
     )]
     database: Option<String>,
 
+    #[arg(
+        long,
+        help = format!("How many connections to open to the database. Embedded databases only use one\n\n[default: {DEFAULT_DB_POOL_SIZE}]")
+    )]
+    db_pool_size: Option<usize>,
+
+    #[arg(
+        long,
+        help = format!("How long a database query can take, in seconds, before it fails. 0 never times out\n\n[default: {DEFAULT_DB_QUERY_TIMEOUT_SECS}]")
+    )]
+    db_query_timeout_secs: Option<u64>,
+
+    #[arg(
+        long,
+        help = format!("Whether migrations are run when the server starts. When they aren't, the server won't start until they've been run with the migrate command\n\n[default: {DEFAULT_AUTO_MIGRATE}]")
+    )]
+    auto_migrate: Option<bool>,
+
     #[arg(
         long,
         help = "The private key for authenticating (overrides --private-key-path)"
@@ -95,6 +156,12 @@ struct RunCommand {
     )]
     log_dir: Option<String>,
 
+    #[arg(
+        long,
+        help = format!("The directory to store uploaded media in\n\n[default: {DEFAULT_MEDIA_DIR}]")
+    )]
+    media_dir: Option<String>,
+
     #[arg(
         long,
         help = format!("The level of logs to show on stdout\n\n[default: {}]", DEFAULT_LOG_LEVEL_STDOUT),
@@ -109,6 +176,398 @@ struct RunCommand {
     )]
     log_level_file: Option<LogLevel>,
 
+    #[arg(
+        long,
+        help = format!("Whether to show coarse instance statistics publicly\n\n[default: {DEFAULT_PUBLIC_STATS}]")
+    )]
+    public_stats: Option<bool>,
+
+    #[arg(
+        long,
+        help = format!("The spam score (0-100) at which content is sent for review\n\n[default: {DEFAULT_SPAM_REVIEW_THRESHOLD}]")
+    )]
+    spam_review_threshold: Option<u8>,
+
+    #[arg(
+        long,
+        help = format!("The spam score (0-100) at which content is hidden from others\n\n[default: {DEFAULT_SPAM_LIMIT_THRESHOLD}]")
+    )]
+    spam_limit_threshold: Option<u8>,
+
+    #[arg(long, help = "The URL of an external spam classifier")]
+    spam_classifier_url: Option<String>,
+
+    #[arg(
+        long,
+        help = format!("Whether to seed development accounts that can be logged into without credentials\n\n[default: {DEFAULT_DEV_AUTH}]")
+    )]
+    dev_auth: Option<bool>,
+
+    #[arg(
+        long,
+        help = format!("Whether to allow development authentication in release builds\n\n[default: {DEFAULT_DEV_AUTH_ALLOW_RELEASE}]")
+    )]
+    dev_auth_allow_release: Option<bool>,
+
+    #[arg(
+        long,
+        help = format!("The maximum number of requests handled at once\n\n[default: {DEFAULT_MAX_CONCURRENCY}]")
+    )]
+    max_concurrency: Option<usize>,
+
+    #[arg(
+        long,
+        help = format!("The maximum number of GraphQL requests over HTTP handled at once\n\n[default: {DEFAULT_MAX_GRAPHQL_CONCURRENCY}]")
+    )]
+    max_graphql_concurrency: Option<usize>,
+
+    #[arg(
+        long,
+        help = format!("How long, in milliseconds, a request can wait to be handled before it is rejected\n\n[default: {DEFAULT_MAX_QUEUE_MS}]")
+    )]
+    max_queue_ms: Option<u64>,
+
+    #[arg(
+        long,
+        help = format!("How many requests a client can make a minute to sign in, register or use other credentials, or 0 for no limit\n\n[default: {DEFAULT_RATE_LIMIT_AUTH}]")
+    )]
+    rate_limit_auth: Option<u32>,
+
+    #[arg(
+        long,
+        help = format!("How many queries and other reads a client can make a minute, or 0 for no limit\n\n[default: {DEFAULT_RATE_LIMIT_READ}]")
+    )]
+    rate_limit_read: Option<u32>,
+
+    #[arg(
+        long,
+        help = format!("How many mutations and other writes a client can make a minute, or 0 for no limit\n\n[default: {DEFAULT_RATE_LIMIT_WRITE}]")
+    )]
+    rate_limit_write: Option<u32>,
+
+    #[arg(
+        long,
+        help = format!("The most a GraphQL query can cost, counting each field it selects and each item in pages of results, or 0 for no limit\n\n[default: {DEFAULT_MAX_QUERY_COMPLEXITY}]")
+    )]
+    max_query_complexity: Option<usize>,
+
+    #[arg(
+        long,
+        help = format!("The most deeply fields can be nested in a GraphQL query, or 0 for no limit\n\n[default: {DEFAULT_MAX_QUERY_DEPTH}]")
+    )]
+    max_query_depth: Option<usize>,
+
+    #[arg(
+        long,
+        help = format!("How many queries sent by their hash are kept in memory, or 0 for none\n\n[default: {DEFAULT_PERSISTED_QUERY_CACHE_SIZE}]")
+    )]
+    persisted_query_cache_size: Option<usize>,
+
+    #[arg(
+        long,
+        help = format!("Stores queries sent by their hash in the database as well, so that they outlive restarts\n\n[default: {DEFAULT_PERSIST_QUERIES}]")
+    )]
+    persist_queries: Option<bool>,
+
+    #[arg(
+        long,
+        help = "The path to a JSON object of queries by their SHA-256 hash, which are then the only queries that can be run"
+    )]
+    persisted_query_allow_list: Option<String>,
+
+    #[arg(
+        long,
+        help = "The public URL clients reach the instance at, used in link previews"
+    )]
+    public_url: Option<String>,
+
+    #[arg(
+        long,
+        help = "The region this server runs in, when the instance is deployed across several"
+    )]
+    region: Option<String>,
+
+    #[arg(
+        long,
+        help = format!("The minimum age, in years, that people must be to register, or 0 for no minimum\n\n[default: {DEFAULT_MIN_AGE}]")
+    )]
+    min_age: Option<u8>,
+
+    #[arg(
+        long,
+        help = format!("How many days deleted accounts can be restored for before they're purged\n\n[default: {DEFAULT_DELETION_GRACE_DAYS}]")
+    )]
+    deletion_grace_days: Option<u32>,
+
+    #[arg(
+        long,
+        help = format!("How many days an account can go without signing in before it's sent a dormancy notice, or 0 to leave inactive accounts alone\n\n[default: {DEFAULT_DORMANT_AFTER_DAYS}]")
+    )]
+    dormant_after_days: Option<u32>,
+
+    #[arg(
+        long,
+        help = format!("How many days dormant accounts have to sign in after their notice before their user IDs can be released\n\n[default: {DEFAULT_DORMANCY_GRACE_DAYS}]")
+    )]
+    dormancy_grace_days: Option<u32>,
+
+    #[arg(
+        long,
+        help = format!("Whether the user IDs of dormant accounts are released for others to register once their grace period ends\n\n[default: {DEFAULT_RELEASE_DORMANT_USER_IDS}]")
+    )]
+    release_dormant_user_ids: Option<bool>,
+
+    #[arg(
+        long,
+        help = format!("Whether user IDs can be registered that look like ones already in use or reserved\n\n[default: {DEFAULT_ALLOW_CONFUSABLE_USER_IDS}]")
+    )]
+    allow_confusable_user_ids: Option<bool>,
+
+    #[arg(
+        long,
+        help = format!("Whether accounts have to verify their email address before they can post, create boards or upload media\n\n[default: {DEFAULT_REQUIRE_VERIFIED_EMAIL}]")
+    )]
+    require_verified_email: Option<bool>,
+
+    #[arg(
+        long,
+        help = "The `host:port` of the SMTP server that email, such as password reset codes, is sent through. Without one, no email is sent"
+    )]
+    smtp_address: Option<String>,
+
+    #[arg(
+        long,
+        help = format!("The address that email is sent from\n\n[default: {DEFAULT_EMAIL_FROM}]")
+    )]
+    email_from: Option<String>,
+
+    #[arg(
+        long,
+        help = format!("How client IP addresses are stored with sign-ins\n\n[default: {DEFAULT_IP_STORAGE}]"),
+        value_enum
+    )]
+    ip_storage: Option<IpStorage>,
+
+    #[arg(
+        long,
+        help = format!("Who can see the IP addresses and user agents of sign-ins\n\n[default: {DEFAULT_METADATA_VISIBILITY}]"),
+        value_enum
+    )]
+    metadata_visibility: Option<MetadataVisibility>,
+
+    #[arg(
+        long,
+        help = format!("How many days the IP addresses and user agents of sign-ins are kept for, or 0 to keep them forever\n\n[default: {DEFAULT_METADATA_RETENTION_DAYS}]")
+    )]
+    metadata_retention_days: Option<u32>,
+
+    #[arg(
+        long,
+        help = format!("The number of posts each account can make, or 0 for no limit\n\n[default: {DEFAULT_QUOTA_POSTS}]")
+    )]
+    quota_posts: Option<u32>,
+
+    #[arg(
+        long,
+        help = format!("The number of boards each account can create, or 0 for no limit\n\n[default: {DEFAULT_QUOTA_BOARDS}]")
+    )]
+    quota_boards: Option<u32>,
+
+    #[arg(
+        long,
+        help = format!("The number of lists each account can create, or 0 for no limit\n\n[default: {DEFAULT_QUOTA_LISTS}]")
+    )]
+    quota_lists: Option<u32>,
+
+    #[arg(
+        long,
+        help = format!("The number of bytes of post titles and content, and of uploaded media, each account can store, or 0 for no limit\n\n[default: {DEFAULT_QUOTA_STORAGE_BYTES}]")
+    )]
+    quota_storage_bytes: Option<u64>,
+
+    #[arg(
+        long,
+        help = format!("The most characters a post title can have\n\n[default: {DEFAULT_MAX_POST_TITLE_LENGTH}]")
+    )]
+    max_post_title_length: Option<u32>,
+
+    #[arg(
+        long,
+        help = format!("The most characters a post's content can have\n\n[default: {DEFAULT_MAX_POST_CONTENT_LENGTH}]")
+    )]
+    max_post_content_length: Option<u32>,
+
+    #[arg(
+        long,
+        help = format!("The most characters a board name can have\n\n[default: {DEFAULT_MAX_BOARD_NAME_LENGTH}]")
+    )]
+    max_board_name_length: Option<u32>,
+
+    #[arg(
+        long,
+        help = format!("The most characters a board description can have\n\n[default: {DEFAULT_MAX_BOARD_DESCRIPTION_LENGTH}]")
+    )]
+    max_board_description_length: Option<u32>,
+
+    #[arg(
+        long,
+        help = format!("The spam score, out of 100, given to registrations that fill in the honeypot field\n\n[default: {DEFAULT_SIGNUP_HONEYPOT_SCORE}]")
+    )]
+    signup_honeypot_score: Option<u8>,
+
+    #[arg(
+        long,
+        help = format!("How many seconds people take to fill in the registration form at the least. Faster registrations are scored as spam\n\n[default: {DEFAULT_SIGNUP_MIN_FORM_SECS}]")
+    )]
+    signup_min_form_secs: Option<u32>,
+
+    #[arg(
+        long,
+        help = format!("The spam score, out of 100, given to registrations that are made faster than --signup-min-form-secs\n\n[default: {DEFAULT_SIGNUP_TOO_FAST_SCORE}]")
+    )]
+    signup_too_fast_score: Option<u8>,
+
+    #[arg(
+        long,
+        help = format!("Starts the instance in read-only mode, where mutations fail with a ReadOnly error\n\n[default: {DEFAULT_READ_ONLY}]")
+    )]
+    read_only: Option<bool>,
+
+    #[arg(
+        long,
+        help = format!("Switches to read-only mode after this many writes fail in a row. 0 never switches automatically\n\n[default: {DEFAULT_READ_ONLY_AFTER_FAILURES}]")
+    )]
+    read_only_after_failures: Option<u32>,
+
+    #[arg(
+        long,
+        help = format!("How long read-only mode lasts, in seconds, after switching to it automatically\n\n[default: {DEFAULT_READ_ONLY_COOLDOWN_SECS}]")
+    )]
+    read_only_cooldown_secs: Option<u64>,
+
+    #[arg(
+        long,
+        help = "Comma-separated name=version pairs giving the oldest version of each app that can still be used. Older versions are turned away with a ClientOutdated error"
+    )]
+    min_client_versions: Option<String>,
+
+    #[arg(
+        long,
+        help = format!("The most bytes an uploaded media file can have\n\n[default: {DEFAULT_MAX_MEDIA_BYTES}]")
+    )]
+    max_media_bytes: Option<u64>,
+
+    #[arg(
+        long,
+        help = format!("How long, in seconds, stored media is kept after nothing refers to it\n\n[default: {DEFAULT_MEDIA_GC_GRACE_SECS}]")
+    )]
+    media_gc_grace_secs: Option<u64>,
+
+    #[arg(
+        long,
+        help = format!("How long, in seconds, signed media URLs work for\n\n[default: {DEFAULT_MEDIA_URL_TTL_SECS}]")
+    )]
+    media_url_ttl_secs: Option<u64>,
+
+    #[arg(
+        long,
+        help = "Comma-separated hosts that pages embedding media can be on. When set, media requests from pages on other hosts are refused"
+    )]
+    media_referer_hosts: Option<String>,
+
+    #[arg(
+        long,
+        help = format!("Whether images attached to posts need alt text describing them\n\n[default: {DEFAULT_ALT_TEXT_POLICY}]"),
+        value_enum
+    )]
+    alt_text_policy: Option<AltTextPolicy>,
+
+    #[arg(
+        long,
+        help = format!("Who new posts are shown to when they don't say\n\n[default: {DEFAULT_POST_VISIBILITY}]"),
+        value_enum
+    )]
+    post_visibility: Option<PostVisibility>,
+
+    #[arg(
+        long,
+        help = format!("The most open visibility that new posts can have\n\n[default: {DEFAULT_MAX_POST_VISIBILITY}]"),
+        value_enum
+    )]
+    max_post_visibility: Option<PostVisibility>,
+
+    #[arg(
+        long,
+        help = format!("How long a session can go without its tokens being refreshed before it ends, in seconds. 0 never ends it for being idle\n\n[default: {DEFAULT_SESSION_IDLE_TIMEOUT_SECS}]")
+    )]
+    session_idle_timeout_secs: Option<u64>,
+
+    #[arg(
+        long,
+        help = format!("How long a session can last after signing in, however much it's used, in seconds. 0 lets it last forever\n\n[default: {DEFAULT_SESSION_MAX_LIFETIME_SECS}]")
+    )]
+    session_max_lifetime_secs: Option<u64>,
+
+    #[arg(
+        long,
+        help = format!("The idle timeout for admins' sessions, if it's shorter than everyone else's. 0 uses everyone else's\n\n[default: {DEFAULT_ADMIN_SESSION_IDLE_TIMEOUT_SECS}]")
+    )]
+    admin_session_idle_timeout_secs: Option<u64>,
+
+    #[arg(
+        long,
+        help = format!("The maximum lifetime of admins' sessions, if it's shorter than everyone else's. 0 uses everyone else's\n\n[default: {DEFAULT_ADMIN_SESSION_MAX_LIFETIME_SECS}]")
+    )]
+    admin_session_max_lifetime_secs: Option<u64>,
+
+    #[arg(
+        long,
+        help = format!("How much memory hashing a password uses, in KiB. Passwords hashed with other parameters are hashed again when their accounts next log in\n\n[default: {DEFAULT_ARGON2_MEMORY_KIB}]")
+    )]
+    argon2_memory_kib: Option<u32>,
+
+    #[arg(
+        long,
+        help = format!("How many passes hashing a password makes over its memory\n\n[default: {DEFAULT_ARGON2_ITERATIONS}]")
+    )]
+    argon2_iterations: Option<u32>,
+
+    #[arg(
+        long,
+        help = format!("How many lanes the memory for hashing a password is split into\n\n[default: {DEFAULT_ARGON2_PARALLELISM}]")
+    )]
+    argon2_parallelism: Option<u32>,
+
+    #[arg(
+        long,
+        help = format!("How many posts each account can have translated an hour, or 0 for no limit. Posts that have already been translated don't count\n\n[default: {DEFAULT_TRANSLATIONS_PER_HOUR}]")
+    )]
+    translations_per_hour: Option<u32>,
+
+    #[arg(
+        long,
+        help = format!("Whether outbound links and remote media are sent through the instance, so that remote servers don't see readers' addresses\n\n[default: {DEFAULT_PROXY_REMOTE_CONTENT}]")
+    )]
+    proxy_remote_content: Option<bool>,
+
+    #[arg(
+        long,
+        help = format!("The biggest remote media that's fetched when proxying, in bytes\n\n[default: {DEFAULT_PROXY_MAX_MEDIA_BYTES}]")
+    )]
+    proxy_max_media_bytes: Option<u64>,
+
+    #[arg(
+        long,
+        help = format!("How much proxied remote media is kept in memory at once, in bytes\n\n[default: {DEFAULT_PROXY_CACHE_BYTES}]")
+    )]
+    proxy_cache_bytes: Option<u64>,
+
+    #[arg(
+        long,
+        help = format!("How long proxied remote media is kept before it's fetched again\n\n[default: {DEFAULT_PROXY_CACHE_TTL_SECS}]")
+    )]
+    proxy_cache_ttl_secs: Option<u64>,
+
     #[arg(
         short,
         long,
@@ -137,6 +596,46 @@ struct GenerateKeyCommand {
     output: String,
 }
 
+#[derive(Args)]
+#[command(about = "Check the deployment without starting the server")]
+struct DoctorCommand {
+    #[arg(long, help = "Print the report as JSON", default_value_t = false)]
+    json: bool,
+
+    #[clap(flatten)]
+    run: RunCommand,
+}
+
+#[derive(Args)]
+#[command(about = "Run, roll back or inspect database migrations")]
+struct MigrateCommand {
+    #[command(subcommand)]
+    action: MigrateAction,
+
+    #[arg(long, help = "Print the status as JSON", default_value_t = false)]
+    json: bool,
+
+    #[clap(flatten)]
+    run: RunCommand,
+}
+
+#[derive(Subcommand)]
+enum MigrateAction {
+    #[command(about = "Show which migrations have been run")]
+    Status,
+    #[command(about = "Run every migration that hasn't been")]
+    Run,
+    #[command(about = "Roll back migration scripts")]
+    Rollback {
+        #[arg(
+            long,
+            help = "The script version to roll back to. 0 rolls back every script",
+            default_value_t = 0
+        )]
+        to: u32,
+    },
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
@@ -147,51 +646,250 @@ async fn main() -> anyhow::Result<()> {
         Commands::GenerateKey(cmd) => {
             generate_key(cmd.output)?;
         }
+        Commands::Doctor(cmd) => doctor(cmd).await?,
+        Commands::Migrate(cmd) => migrate(cmd).await?,
     };
 
     Ok(())
 }
 
-async fn run(
+async fn run(cmd: RunCommand) -> anyhow::Result<()> {
+    let write_config = cmd.write_config;
+    let config_path = cmd
+        .config
+        .clone()
+        .unwrap_or_else(|| DEFAULT_CONFIG_PATH.to_owned());
+    let builder = config_builder(cmd);
+    let config = builder.clone().build()?;
+
+    if write_config {
+        fs::write(config_path, toml::to_string(&config)?)?;
+        return Ok(());
+    }
+
+    let (mut serve_config, log_config): (ServeConfig, _) = config.try_into()?;
+    serve_config.reload_from = Some(builder);
+
+    let _guard = init_logging(log_config);
+    serve(serve_config).await?;
+
+    Ok(())
+}
+
+async fn doctor(DoctorCommand { json, run }: DoctorCommand) -> anyhow::Result<()> {
+    let report = diagnose(config_builder(run)).await;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        println!("{report}");
+    }
+
+    if !report.is_healthy() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+async fn migrate(MigrateCommand { action, json, run }: MigrateCommand) -> anyhow::Result<()> {
+    let builder = config_builder(run);
+
+    match action {
+        MigrateAction::Status => {
+            let report = migrate::status(builder).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                println!("{report}");
+            }
+        }
+        MigrateAction::Run => {
+            migrate::run(builder).await?;
+            println!("Migrations complete");
+        }
+        MigrateAction::Rollback { to } => {
+            let rolled_back = migrate::rollback(builder, to).await?;
+            if rolled_back.is_empty() {
+                println!("Nothing to roll back");
+            }
+            for version in rolled_back {
+                println!("Rolled back {version:04}");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn config_builder(
     RunCommand {
+        config,
         port,
         host,
+        cors_origins,
         address,
         namespace,
         database,
+        db_pool_size,
+        db_query_timeout_secs,
+        auto_migrate,
         private_key,
         private_key_path,
         log_dir,
+        media_dir,
         log_level_stdout,
         log_level_file,
-        write_config,
+        public_stats,
+        spam_review_threshold,
+        spam_limit_threshold,
+        spam_classifier_url,
+        dev_auth,
+        dev_auth_allow_release,
+        max_concurrency,
+        max_graphql_concurrency,
+        max_queue_ms,
+        rate_limit_auth,
+        rate_limit_read,
+        rate_limit_write,
+        max_query_complexity,
+        max_query_depth,
+        persisted_query_cache_size,
+        persist_queries,
+        persisted_query_allow_list,
+        public_url,
+        region,
+        min_age,
+        deletion_grace_days,
+        dormant_after_days,
+        dormancy_grace_days,
+        release_dormant_user_ids,
+        allow_confusable_user_ids,
+        require_verified_email,
+        smtp_address,
+        email_from,
+        ip_storage,
+        metadata_visibility,
+        metadata_retention_days,
+        quota_posts,
+        quota_boards,
+        quota_lists,
+        quota_storage_bytes,
+        max_post_title_length,
+        max_post_content_length,
+        max_board_name_length,
+        max_board_description_length,
+        signup_honeypot_score,
+        signup_min_form_secs,
+        signup_too_fast_score,
+        read_only,
+        read_only_after_failures,
+        read_only_cooldown_secs,
+        min_client_versions,
+        max_media_bytes,
+        media_gc_grace_secs,
+        media_url_ttl_secs,
+        media_referer_hosts,
+        alt_text_policy,
+        post_visibility,
+        max_post_visibility,
+        session_idle_timeout_secs,
+        session_max_lifetime_secs,
+        admin_session_idle_timeout_secs,
+        admin_session_max_lifetime_secs,
+        argon2_memory_kib,
+        argon2_iterations,
+        argon2_parallelism,
+        translations_per_hour,
+        proxy_remote_content,
+        proxy_max_media_bytes,
+        proxy_cache_bytes,
+        proxy_cache_ttl_secs,
+        write_config: _,
     }: RunCommand,
-) -> anyhow::Result<()> {
-    let config = ServiceConfigBuilder::new()
+) -> ServiceConfigBuilder {
+    ServiceConfigBuilder::new()
+        .set_config_path(config)
         .set_port(port)
         .set_host(host)
+        .set_cors_origins(cors_origins)
         .set_address(address)
         .set_namespace(namespace)
         .set_database(database)
+        .set_db_pool_size(db_pool_size)
+        .set_db_query_timeout_secs(db_query_timeout_secs)
+        .set_auto_migrate(auto_migrate)
         .set_private_key(private_key)
         .set_private_key_path(private_key_path)
         .private_key_create(|path| generate_key(path))
         .set_log_dir(log_dir)
+        .set_media_dir(media_dir)
         .set_log_level_stdout(log_level_stdout)
         .set_log_level_file(log_level_file)
-        .build()?;
-
-    if write_config {
-        fs::write(DEFAULT_CONFIG_PATH, toml::to_string(&config)?)?;
-        return Ok(());
-    }
-
-    let (serve_config, log_config) = config.try_into()?;
-
-    let _guard = init_logging(log_config);
-    serve(serve_config).await?;
-
-    Ok(())
+        .set_public_stats(public_stats)
+        .set_spam_review_threshold(spam_review_threshold)
+        .set_spam_limit_threshold(spam_limit_threshold)
+        .set_spam_classifier_url(spam_classifier_url)
+        .set_dev_auth(dev_auth)
+        .set_dev_auth_allow_release(dev_auth_allow_release)
+        .set_max_concurrency(max_concurrency)
+        .set_max_graphql_concurrency(max_graphql_concurrency)
+        .set_max_queue_ms(max_queue_ms)
+        .set_rate_limit_auth(rate_limit_auth)
+        .set_rate_limit_read(rate_limit_read)
+        .set_rate_limit_write(rate_limit_write)
+        .set_max_query_complexity(max_query_complexity)
+        .set_max_query_depth(max_query_depth)
+        .set_persisted_query_cache_size(persisted_query_cache_size)
+        .set_persist_queries(persist_queries)
+        .set_persisted_query_allow_list(persisted_query_allow_list)
+        .set_public_url(public_url)
+        .set_region(region)
+        .set_min_age(min_age)
+        .set_deletion_grace_days(deletion_grace_days)
+        .set_dormant_after_days(dormant_after_days)
+        .set_dormancy_grace_days(dormancy_grace_days)
+        .set_release_dormant_user_ids(release_dormant_user_ids)
+        .set_allow_confusable_user_ids(allow_confusable_user_ids)
+        .set_require_verified_email(require_verified_email)
+        .set_smtp_address(smtp_address)
+        .set_email_from(email_from)
+        .set_ip_storage(ip_storage)
+        .set_metadata_visibility(metadata_visibility)
+        .set_metadata_retention_days(metadata_retention_days)
+        .set_quota_posts(quota_posts)
+        .set_quota_boards(quota_boards)
+        .set_quota_lists(quota_lists)
+        .set_quota_storage_bytes(quota_storage_bytes)
+        .set_max_post_title_length(max_post_title_length)
+        .set_max_post_content_length(max_post_content_length)
+        .set_max_board_name_length(max_board_name_length)
+        .set_max_board_description_length(max_board_description_length)
+        .set_signup_honeypot_score(signup_honeypot_score)
+        .set_signup_min_form_secs(signup_min_form_secs)
+        .set_signup_too_fast_score(signup_too_fast_score)
+        .set_read_only(read_only)
+        .set_read_only_after_failures(read_only_after_failures)
+        .set_read_only_cooldown_secs(read_only_cooldown_secs)
+        .set_min_client_versions(min_client_versions)
+        .set_max_media_bytes(max_media_bytes)
+        .set_media_gc_grace_secs(media_gc_grace_secs)
+        .set_media_url_ttl_secs(media_url_ttl_secs)
+        .set_media_referer_hosts(media_referer_hosts)
+        .set_alt_text_policy(alt_text_policy)
+        .set_post_visibility(post_visibility)
+        .set_max_post_visibility(max_post_visibility)
+        .set_session_idle_timeout_secs(session_idle_timeout_secs)
+        .set_session_max_lifetime_secs(session_max_lifetime_secs)
+        .set_admin_session_idle_timeout_secs(admin_session_idle_timeout_secs)
+        .set_admin_session_max_lifetime_secs(admin_session_max_lifetime_secs)
+        .set_argon2_memory_kib(argon2_memory_kib)
+        .set_argon2_iterations(argon2_iterations)
+        .set_argon2_parallelism(argon2_parallelism)
+        .set_translations_per_hour(translations_per_hour)
+        .set_proxy_remote_content(proxy_remote_content)
+        .set_proxy_max_media_bytes(proxy_max_media_bytes)
+        .set_proxy_cache_bytes(proxy_cache_bytes)
+        .set_proxy_cache_ttl_secs(proxy_cache_ttl_secs)
 }
 
 fn output_schema(SchemaCommand { output }: SchemaCommand) -> anyhow::Result<()> {
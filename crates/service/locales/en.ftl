@@ -0,0 +1,29 @@
+# Notification titles
+
+notification-quote = @{ $actor } quoted your post
+notification-quote-anonymous = Someone quoted your post
+notification-quote-batch = { $others ->
+    [one] @{ $actor } and 1 other quoted your post
+   *[other] @{ $actor } and { $others } others quoted your post
+}
+notification-quote-batch-anonymous = { $count } people quoted your post
+notification-security-new-device = Your account was signed into from a new device. Was this you?
+notification-security = There was security activity on your account. Was this you?
+notification-account-recovery = @{ $account } reported activity on their account that they didn't recognize
+notification-account-recovery-deleted = An account reported activity that it didn't recognize
+notification-restricted-suspended = Your account has been suspended: { $reason }
+notification-restricted-silenced = Your account has been silenced, so only your followers can see your posts: { $reason }
+notification-restricted-lifted = A restriction on your account has ended
+notification-data-export-ready = Your data export is ready to download
+notification-recovery-approval = @{ $account } is being recovered and needs you to approve it. Check with them first!
+notification-recovery-approval-ended = A recovery you were asked to approve has ended
+notification-recovery-codes-low = You're running out of two-factor recovery codes. Generate new ones before they're all used
+notification-dormant = You haven't signed in for a while. Sign in to keep your user ID
+notification-test = This is a test notification. Your notifications are working!
+
+# Link previews
+
+share-post-title = Post by @{ $author }
+share-post-title-anonymous = Post
+share-profile-bot = A bot account
+share-profile-affiliation = Member of { $organization } ({ $domain })
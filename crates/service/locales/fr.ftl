@@ -0,0 +1,29 @@
+# Notification titles
+
+notification-quote = @{ $actor } a cité votre publication
+notification-quote-anonymous = Quelqu’un a cité votre publication
+notification-quote-batch = { $others ->
+    [one] @{ $actor } et 1 autre personne ont cité votre publication
+   *[other] @{ $actor } et { $others } autres personnes ont cité votre publication
+}
+notification-quote-batch-anonymous = { $count } personnes ont cité votre publication
+notification-security-new-device = Votre compte a été connecté depuis un nouvel appareil. Était-ce vous ?
+notification-security = Il y a eu une activité de sécurité sur votre compte. Était-ce vous ?
+notification-account-recovery = @{ $account } a signalé une activité sur son compte qu’il ne reconnaît pas
+notification-account-recovery-deleted = Un compte a signalé une activité qu’il ne reconnaît pas
+notification-restricted-suspended = Votre compte a été suspendu : { $reason }
+notification-restricted-silenced = Votre compte a été restreint, seuls vos abonnés peuvent voir vos publications : { $reason }
+notification-restricted-lifted = Une restriction sur votre compte a pris fin
+notification-data-export-ready = Votre export de données est prêt à être téléchargé
+notification-recovery-approval = Le compte @{ $account } est en cours de récupération et a besoin de votre accord. Vérifiez d’abord auprès de son propriétaire !
+notification-recovery-approval-ended = Une récupération de compte que vous deviez approuver est terminée
+notification-recovery-codes-low = Il vous reste peu de codes de récupération pour la double authentification. Générez-en de nouveaux avant de tous les utiliser
+notification-dormant = Vous ne vous êtes pas connecté depuis longtemps. Connectez-vous pour conserver votre identifiant
+notification-test = Ceci est une notification de test. Vos notifications fonctionnent !
+
+# Link previews
+
+share-post-title = Publication de @{ $author }
+share-post-title-anonymous = Publication
+share-profile-bot = Un compte robot
+share-profile-affiliation = Membre de { $organization } ({ $domain })
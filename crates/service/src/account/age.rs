@@ -0,0 +1,70 @@
+//! Age checks for new accounts.
+//!
+//! Birthdates are only used while registering. All that is kept afterwards
+//! is whether the account was old enough for age-restricted boards.
+
+use chrono::NaiveDate;
+
+use crate::prelude::*;
+
+/// The age, in years, that accounts must be to see age-restricted boards.
+pub const ADULT_AGE: u32 = 18;
+
+/// Checks a birthdate given while registering against the instance's minimum
+/// age, returning whether the account is an adult.
+///
+/// Without a birthdate the account isn't treated as an adult, and it can only
+/// register if the instance has no minimum age.
+pub fn check_birthdate(
+    birthdate: Option<NaiveDate>,
+    min_age: u8,
+    today: NaiveDate,
+) -> Result<bool> {
+    let Some(birthdate) = birthdate else {
+        return if min_age == 0 {
+            Ok(false)
+        } else {
+            Err(Error::BirthdateRequired)
+        };
+    };
+
+    let Some(age) = today.years_since(birthdate) else {
+        return Err(Error::InputInvalid("birthdate is in the future".into()));
+    };
+    if age < u32::from(min_age) {
+        return Err(Error::UnderMinimumAge);
+    }
+
+    Ok(age >= ADULT_AGE)
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test_case(None, 0 => Ok(false); "no birthdate or minimum")]
+    #[test_case(None, 13 => Err(Error::BirthdateRequired); "no birthdate")]
+    #[test_case(Some(date(2000, 1, 1)), 13 => Ok(true); "adult")]
+    #[test_case(Some(date(2005, 6, 2)), 13 => Ok(false); "day before 18th birthday")]
+    #[test_case(Some(date(2005, 6, 1)), 13 => Ok(true); "18th birthday")]
+    #[test_case(Some(date(2010, 6, 2)), 13 => Err(Error::UnderMinimumAge); "under minimum")]
+    #[test_case(Some(date(2010, 6, 1)), 13 => Ok(false); "at minimum")]
+    fn test_check_birthdate(birthdate: Option<NaiveDate>, min_age: u8) -> Result<bool> {
+        check_birthdate(birthdate, min_age, date(2023, 6, 1))
+    }
+
+    #[test]
+    fn test_future_birthdate() {
+        let res = check_birthdate(Some(date(2024, 1, 1)), 0, date(2023, 6, 1));
+        assert_eq!(
+            res,
+            Err(Error::InputInvalid("birthdate is in the future".into()))
+        );
+    }
+}
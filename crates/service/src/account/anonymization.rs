@@ -0,0 +1,331 @@
+use std::fmt::Write as _;
+
+use serde::Deserialize;
+use surrealdb::sql::Thing;
+use tracing::instrument;
+
+use super::{ACC_TABLE_NAME, RECOVERY_CONTACTS_TABLE_NAME, RECOVERY_REQUEST_TABLE_NAME};
+use crate::{
+    audience::AUDIENCE_TABLE_NAME,
+    bulk::BULK_JOB_TABLE_NAME,
+    event::{ACCOUNT_COUNTS_TABLE_NAME, DOMAIN_EVENT_TABLE_NAME},
+    list::LIST_TABLE_NAME,
+    moderation::MODERATION_TABLE_NAME,
+    organization::ORGANIZATION_ACTIVITY_TABLE_NAME,
+    persist::Persist,
+    post::POST_TABLE_NAME,
+    prelude::*,
+    security::{SecurityEvent, SecurityEventKind},
+    translation::POST_TRANSLATION_TABLE_NAME,
+    tx::Tx,
+};
+
+/// Accounts that have been purged, kept until the references left to them
+/// have been dealt with.
+pub static DEPARTED_ACCOUNT_TABLE_NAME: &str = "departed_account";
+
+/// The ID that a departed account's ID is replaced with where it's
+/// anonymized. Every departed account is replaced with the same one, so
+/// what they did can't be told apart.
+pub static ANONYMOUS_ACCOUNT_ID: &str = "departed";
+
+/// The most departed accounts that are anonymized in one go.
+const ANONYMIZE_BATCH_SIZE: u32 = 20;
+
+/// What's done with a reference to a departed account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Residual {
+    /// The account is taken out of a list of accounts, such as the ones a
+    /// post mentions.
+    Unlink,
+    /// The account is replaced with [`ANONYMOUS_ACCOUNT_ID`], for records
+    /// that are kept as a log of what happened.
+    Anonymize,
+}
+
+/// The references to an account that other accounts' records, and the
+/// instance's logs, can still have once it's been purged, as the tables
+/// they're in, the field that refers to the account, and what's done with
+/// them. This is the instance's retention policy for departed accounts.
+const RESIDUAL_REFERENCES: &[(&str, &str, Residual)] = &[
+    (POST_TABLE_NAME, "mention_ids", Residual::Unlink),
+    (LIST_TABLE_NAME, "member_ids", Residual::Unlink),
+    (AUDIENCE_TABLE_NAME, "member_ids", Residual::Unlink),
+    (
+        RECOVERY_CONTACTS_TABLE_NAME,
+        "contact_ids",
+        Residual::Unlink,
+    ),
+    (RECOVERY_REQUEST_TABLE_NAME, "contact_ids", Residual::Unlink),
+    (
+        RECOVERY_REQUEST_TABLE_NAME,
+        "approved_ids",
+        Residual::Unlink,
+    ),
+    (DOMAIN_EVENT_TABLE_NAME, "actor_id", Residual::Anonymize),
+    (DOMAIN_EVENT_TABLE_NAME, "subject_id", Residual::Anonymize),
+    (
+        ORGANIZATION_ACTIVITY_TABLE_NAME,
+        "actor_id",
+        Residual::Anonymize,
+    ),
+    (
+        ORGANIZATION_ACTIVITY_TABLE_NAME,
+        "subject_id",
+        Residual::Anonymize,
+    ),
+    (BULK_JOB_TABLE_NAME, "actor_id", Residual::Anonymize),
+    (MODERATION_TABLE_NAME, "target_id", Residual::Anonymize),
+    (
+        POST_TRANSLATION_TABLE_NAME,
+        "translated_by",
+        Residual::Anonymize,
+    ),
+];
+
+/// The tables whose records for a departed account have the account's ID as
+/// their own, and are deleted.
+const RESIDUAL_KEYED_RECORDS: &[&str] = &[ACCOUNT_COUNTS_TABLE_NAME];
+
+#[derive(Debug, Deserialize)]
+struct Record {
+    id: Thing,
+}
+
+/// How many of one kind of reference to a departed account were found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResidualCount {
+    pub table: &'static str,
+    pub field: Option<&'static str>,
+    pub count: usize,
+}
+
+/// What was found and dealt with for a departed account.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AnonymizationReport {
+    pub unlinked: Vec<ResidualCount>,
+    pub anonymized: Vec<ResidualCount>,
+    pub deleted: Vec<ResidualCount>,
+}
+
+impl AnonymizationReport {
+    /// Summarises the report for the audit log, leaving out kinds of
+    /// reference that weren't found.
+    fn detail(&self) -> String {
+        let mut detail = String::new();
+        for (action, counts) in [
+            ("unlinked", &self.unlinked),
+            ("anonymized", &self.anonymized),
+            ("deleted", &self.deleted),
+        ] {
+            let total: usize = counts.iter().map(|count| count.count).sum();
+            if total == 0 {
+                continue;
+            }
+            if !detail.is_empty() {
+                detail.push_str("; ");
+            }
+            write!(detail, "{total} {action} (").expect("writing to a string can't fail");
+            let mut first = true;
+            for ResidualCount {
+                table,
+                field,
+                count,
+            } in counts.iter().filter(|count| count.count > 0)
+            {
+                if !first {
+                    detail.push_str(", ");
+                }
+                first = false;
+                match field {
+                    Some(field) => write!(detail, "{table}.{field}: {count}"),
+                    None => write!(detail, "{table}: {count}"),
+                }
+                .expect("writing to a string can't fail");
+            }
+            detail.push(')');
+        }
+        if detail.is_empty() {
+            detail.push_str("No references were left");
+        }
+        detail
+    }
+}
+
+/// Records that an account was purged, so that the references left to it
+/// are dealt with afterwards. This should be run in the same transaction as
+/// the purge.
+pub fn record_departed(account_id: &Thing, persist: &Persist) -> srql::Statement {
+    srql::Statement::Create(srql::CreateStatement {
+        what: srql::thing(Thing {
+            tb: DEPARTED_ACCOUNT_TABLE_NAME.to_owned(),
+            id: account_id.id.clone(),
+        }),
+        data: srql::Data::SetExpression(vec![(
+            srql::field("purged_at"),
+            srql::Operator::Equal,
+            srql::Value::Datetime(srql::Datetime(persist.clock().now())),
+        )])
+        .into(),
+        output: srql::Output::None.into(),
+        ..Default::default()
+    })
+}
+
+/// Finds the references that are left to accounts that have been purged,
+/// and unlinks or anonymizes them, logging what was done to the audit
+/// trail. Returns how many departed accounts were dealt with.
+///
+/// Each reference is dealt with on its own, so if this stops part way, the
+/// account is looked at again next time and whatever's left is dealt with.
+#[instrument(skip_all)]
+pub async fn anonymize_departed_accounts(persist: &Persist) -> Result<usize> {
+    let due: Vec<Record> = persist
+        .db()
+        .query(srql::SelectStatement {
+            expr: srql::Fields(
+                vec![srql::Field::Single {
+                    expr: srql::field("id").into(),
+                    alias: None,
+                }],
+                false,
+            ),
+            what: srql::table(DEPARTED_ACCOUNT_TABLE_NAME),
+            cond: srql::Cond(
+                srql::Expression::Binary {
+                    l: srql::field("anonymized_at").into(),
+                    o: srql::Operator::Equal,
+                    r: srql::Value::None,
+                }
+                .into(),
+            )
+            .into(),
+            limit: Some(srql::Limit(ANONYMIZE_BATCH_SIZE.into())),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+
+    for departed in &due {
+        let account_id = Thing {
+            tb: ACC_TABLE_NAME.to_owned(),
+            id: departed.id.id.clone(),
+        };
+        let report = anonymize_account(persist, &account_id).await?;
+        complete(persist, &departed.id, account_id, &report).await?;
+    }
+    Ok(due.len())
+}
+
+/// Deals with every reference left to a departed account.
+async fn anonymize_account(persist: &Persist, account_id: &Thing) -> Result<AnonymizationReport> {
+    let mut report = AnonymizationReport::default();
+    for (table, field, residual) in RESIDUAL_REFERENCES {
+        let (o, data) = match residual {
+            Residual::Unlink => (
+                srql::Operator::Contain,
+                (
+                    srql::field(*field),
+                    srql::Operator::Dec,
+                    account_id.clone().into(),
+                ),
+            ),
+            Residual::Anonymize => (
+                srql::Operator::Equal,
+                (
+                    srql::field(*field),
+                    srql::Operator::Equal,
+                    srql::Thing::from((ACC_TABLE_NAME, ANONYMOUS_ACCOUNT_ID)).into(),
+                ),
+            ),
+        };
+        let touched: Vec<Record> = persist
+            .db()
+            .query(srql::UpdateStatement {
+                what: srql::table(*table),
+                data: srql::Data::SetExpression(vec![data]).into(),
+                cond: srql::Cond(
+                    srql::Expression::Binary {
+                        l: srql::field(*field).into(),
+                        o,
+                        r: account_id.clone().into(),
+                    }
+                    .into(),
+                )
+                .into(),
+                output: srql::Output::Fields(srql::Fields(
+                    vec![srql::Field::Single {
+                        expr: srql::field("id").into(),
+                        alias: None,
+                    }],
+                    false,
+                ))
+                .into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        let count = ResidualCount {
+            table,
+            field: Some(field),
+            count: touched.len(),
+        };
+        match residual {
+            Residual::Unlink => report.unlinked.push(count),
+            Residual::Anonymize => report.anonymized.push(count),
+        }
+    }
+    for table in RESIDUAL_KEYED_RECORDS {
+        let deleted: Option<Record> = persist
+            .db()
+            .query(srql::DeleteStatement {
+                what: srql::thing(Thing {
+                    tb: (*table).to_owned(),
+                    id: account_id.id.clone(),
+                }),
+                output: srql::Output::Before.into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        report.deleted.push(ResidualCount {
+            table,
+            field: None,
+            count: usize::from(deleted.is_some()),
+        });
+    }
+    Ok(report)
+}
+
+/// Marks the departed account as dealt with, and logs the report to the
+/// audit trail, in one transaction.
+async fn complete(
+    persist: &Persist,
+    departed_id: &Thing,
+    account_id: Thing,
+    report: &AnonymizationReport,
+) -> Result<()> {
+    let mut tx = Tx::new();
+    tx.push(srql::Statement::Update(srql::UpdateStatement {
+        what: srql::thing(departed_id.clone()),
+        data: srql::Data::SetExpression(vec![(
+            srql::field("anonymized_at"),
+            srql::Operator::Equal,
+            srql::Value::Datetime(srql::Datetime(persist.clock().now())),
+        )])
+        .into(),
+        output: srql::Output::None.into(),
+        ..Default::default()
+    }));
+    tx.push(srql::Statement::Create(SecurityEvent::create_with_detail(
+        account_id,
+        SecurityEventKind::AccountAnonymized,
+        None,
+        persist.region().map(Into::into),
+        Some(report.detail()),
+        persist.clock(),
+        persist.ids(),
+    )));
+    tx.commit(persist).await?;
+    Ok(())
+}
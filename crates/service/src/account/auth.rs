@@ -1,22 +1,63 @@
-use std::borrow::Cow;
+pub mod api_key;
+pub mod oidc;
+pub mod passkey;
+mod recovery;
+mod refresh;
+mod reset;
+mod throttle;
+pub mod totp;
+mod verification;
+
+use std::{borrow::Cow, sync::Arc};
 
 use async_graphql::ID;
+use async_trait::async_trait;
 use axum::{
+    extract::{FromRef, FromRequestParts},
     headers::{authorization::Bearer, Authorization},
+    http::request::Parts,
     TypedHeader,
 };
 use base64::prelude::*;
 use chrono::{DateTime, Duration, LocalResult, TimeZone, Utc};
 use jsonwebtoken::{Algorithm, DecodingKey, Validation};
 use ring::{
-    digest, pbkdf2,
+    digest,
     rand::{SecureRandom as _, SystemRandom},
 };
-use secrecy::{ExposeSecret as _, SecretString};
 use serde::{Deserialize, Serialize};
 
+pub use self::api_key::{
+    authenticate_api_key, create_api_key, is_api_key, list_api_keys, revoke_api_key, ApiKey,
+    ApiKeyScope, ApiKeyScopeGuard, CreateApiKey, CreatedApiKey, API_KEY_TABLE_NAME,
+};
+pub use self::oidc::{
+    begin_external_flow, finish_external_flow, link_external_identity, list_external_identities,
+    prune_oidc_flows, unlink_external_identity, use_external_identity, ExternalIdentity,
+    ExternalLoginRedirect, ExternalProfile, HttpOidcClient, MemoryOidcClient, OidcClient,
+    SharedOidcClient, EXTERNAL_IDENTITY_TABLE_NAME,
+};
+pub use self::passkey::{
+    begin_passkey_login, begin_passkey_registration, finish_passkey_login,
+    finish_passkey_registration, list_passkeys, prune_passkey_challenges, remove_passkey, Passkey,
+    PasskeyAssertion, PasskeyCreationOptions, PasskeyRegistration, PasskeyRequestOptions,
+    RelyingParty, PASSKEY_TABLE_NAME,
+};
+pub use self::recovery::*;
+pub use self::refresh::*;
+pub use self::reset::*;
+pub use self::throttle::*;
+pub use self::totp::{
+    begin_totp_enrollment, confirm_totp_enrollment, recovery_codes_remaining,
+    regenerate_recovery_codes, remove_totp, totp_enabled, verify_totp, TotpEnrollment,
+    TotpVerified, LOW_RECOVERY_CODES, TOTP_TABLE_NAME,
+};
+pub use self::verification::*;
 use super::{CurrentAccount, PartialAccount};
-use crate::prelude::*;
+use crate::{
+    error::ErrorResponse, persist::Persist, prelude::*, provider::SharedClock,
+    session::check_session,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 struct JwtClaims {
@@ -27,38 +68,99 @@ struct JwtClaims {
     nbf: i64, // Optional. Not Before (as UTC timestamp)
     // sub: String, // Optional. Subject (whom token refers to)
     kind: JwtKind,
+    /// The version of the claims' schema that the token was issued with.
+    /// Tokens issued before claims were versioned are version 0.
+    #[serde(default)]
+    ver: u32,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 enum JwtKind {
     Access,
     Refresh,
+    Disown,
+    TwoFactor,
+}
+
+/// The version of the claims' schema that tokens are issued with. This is
+/// bumped when the meaning of the claims changes, and the minimum version of
+/// the affected kinds of token is raised to match, so that tokens with the
+/// old claims fail with `JwtOutdated` instead of being misread.
+const JWT_CLAIMS_VERSION: u32 = 1;
+
+impl JwtKind {
+    /// The oldest version of the claims' schema that is still accepted for
+    /// this kind of token.
+    fn min_version(&self) -> u32 {
+        match self {
+            // Access tokens only last a few minutes, so ones without a
+            // version are turned away rather than trusted to mean the same.
+            Self::Access | Self::TwoFactor => 1,
+            // JWT refresh tokens aren't issued any more, and disown tokens
+            // are handed out days before they're used, so old ones are
+            // still accepted.
+            Self::Refresh | Self::Disown => 0,
+        }
+    }
 }
 
+/// How far a token's timestamps can be off before it is rejected, to allow for
+/// clock drift between instances. This matches `jsonwebtoken`'s default.
+const JWT_LEEWAY_SECS: i64 = 60;
+
 impl JwtClaims {
-    fn new(duration: Duration, kind: JwtKind) -> Self {
-        let now = Utc::now();
+    fn new(now: DateTime<Utc>, duration: Duration, kind: JwtKind) -> Self {
         Self {
             exp: (now + duration).timestamp(),
             iat: now.timestamp(),
             nbf: now.timestamp(),
             kind,
+            ver: JWT_CLAIMS_VERSION,
+        }
+    }
+
+    /// Makes the token expire by the given time, if it wouldn't already.
+    fn expire_by(&mut self, expires_by: Option<DateTime<Utc>>) {
+        if let Some(expires_by) = expires_by {
+            self.exp = self.exp.min(expires_by.timestamp());
+        }
+    }
+
+    /// Checks that the token is currently valid. This is done here rather than
+    /// by `jsonwebtoken` so that the service's clock is used.
+    fn validate(&self, clock: &dyn Clock) -> Result<()> {
+        let now = clock.now().timestamp();
+        if self.exp < now - JWT_LEEWAY_SECS {
+            return Err(Error::JwtExpired);
+        }
+        if self.nbf > now + JWT_LEEWAY_SECS {
+            return Err(Error::JwtInvalid);
+        }
+        if self.ver < self.kind.min_version() {
+            return Err(Error::JwtOutdated);
         }
+        Ok(())
     }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RefreshClaims {
     id: ID,
+    /// The session the token was issued for. Tokens issued before sessions
+    /// had lifetimes don't have one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sid: Option<ID>,
     #[serde(flatten)]
     jwt: JwtClaims,
 }
 
 impl RefreshClaims {
-    pub fn new(id: ID) -> Self {
+    #[cfg(test)]
+    pub fn new(id: ID, session_id: Option<ID>, now: DateTime<Utc>) -> Self {
         Self {
             id,
-            jwt: JwtClaims::new(Duration::days(30), JwtKind::Refresh),
+            sid: session_id,
+            jwt: JwtClaims::new(now, Duration::days(30), JwtKind::Refresh),
         }
     }
 
@@ -66,11 +168,53 @@ impl RefreshClaims {
         &self.id
     }
 
+    pub fn session_id(&self) -> Option<&str> {
+        self.sid.as_ref().map(|sid| sid.as_str())
+    }
+
     pub fn issued_at(&self) -> Result<DateTime<Utc>> {
         into_utc(self.jwt.iat)
     }
 }
 
+/// How long after a security event it can be reported as unrecognized.
+pub const DISOWN_TOKEN_DAYS: i64 = 7;
+
+/// Claims for a token that reports a security event as unrecognized. It's
+/// given to the account instead of being sent with requests, so that whoever
+/// has it can freeze the account without being signed in.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DisownClaims {
+    id: ID,
+    evt: ID,
+    #[serde(flatten)]
+    jwt: JwtClaims,
+}
+
+impl DisownClaims {
+    /// Token lifetimes start from when the event happened, so that tokens for
+    /// old events can't be made.
+    pub fn new(id: ID, event_id: ID, occurred_at: DateTime<Utc>) -> Self {
+        Self {
+            id,
+            evt: event_id,
+            jwt: JwtClaims::new(
+                occurred_at,
+                Duration::days(DISOWN_TOKEN_DAYS),
+                JwtKind::Disown,
+            ),
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn event_id(&self) -> &str {
+        &self.evt
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AccessClaims<'a> {
     #[serde(flatten)]
@@ -80,32 +224,35 @@ pub struct AccessClaims<'a> {
 }
 
 impl<'a> AccessClaims<'a> {
-    pub fn new(acc: impl Into<Cow<'a, PartialAccount>>) -> Self {
+    pub fn new(acc: impl Into<Cow<'a, PartialAccount>>, now: DateTime<Utc>) -> Self {
         Self {
             acc: acc.into(),
-            jwt: JwtClaims::new(Duration::minutes(15), JwtKind::Access),
+            jwt: JwtClaims::new(now, Duration::minutes(15), JwtKind::Access),
         }
     }
-}
-
-impl TryFrom<AccessClaims<'_>> for CurrentAccount {
-    type Error = Error;
 
-    fn try_from(claims: AccessClaims) -> Result<Self> {
-        Ok(Self::new(
-            claims.acc.into_owned(),
-            into_utc(claims.jwt.exp)?,
+    fn into_current(self, clock: SharedClock) -> Result<CurrentAccount> {
+        Ok(CurrentAccount::new(
+            self.acc.into_owned(),
+            into_utc(self.jwt.exp)?,
+            clock,
         ))
     }
 }
 
+/// Issues an access token, which expires by `expires_by` if it's given, so
+/// that it doesn't outlive the session it's for.
 pub fn create_access_token(
     acc: &PartialAccount,
+    expires_by: Option<DateTime<Utc>>,
     enc_key: &jsonwebtoken::EncodingKey,
+    clock: &dyn Clock,
 ) -> Result<String> {
+    let mut claims = AccessClaims::new(acc, clock.now());
+    claims.jwt.expire_by(expires_by);
     let token = jsonwebtoken::encode(
         &jsonwebtoken::Header::new(Algorithm::EdDSA),
-        &AccessClaims::new(acc),
+        &claims,
         enc_key,
     )?;
 
@@ -115,59 +262,106 @@ pub fn create_access_token(
 pub fn authenticate(
     input: impl Into<AuthenticateInput>,
     dec_key: &DecodingKey,
+    clock: &SharedClock,
 ) -> Result<CurrentAccount> {
-    fn inner(input: &AuthenticateInput, dec_key: &DecodingKey) -> Result<CurrentAccount> {
-        let token = match input {
-            AuthenticateInput::Header(header) => header.as_ref().map(|h| h.0.token()),
-            AuthenticateInput::Init(init) => {
-                let token = match init.as_object() {
-                    Some(obj) => obj.get("token"),
-                    None => {
-                        if init.is_null() {
-                            None
-                        } else {
-                            return Err(Error::WsInitNotObject);
-                        }
-                    }
-                };
-
-                match token.map(serde_json::Value::as_str) {
-                    Some(Some(token)) => Some(token),
-                    Some(None) => return Err(Error::WsInitTokenNotString),
-                    None => None,
-                }
-            }
-        };
-
-        let Some(token) = token else {
+    fn inner(
+        input: &AuthenticateInput,
+        dec_key: &DecodingKey,
+        clock: &SharedClock,
+    ) -> Result<CurrentAccount> {
+        let Some(token) = input.token()? else {
             return Ok(CurrentAccount::default());
         };
 
         let validation = default_validation();
         let token_data = jsonwebtoken::decode::<AccessClaims>(token, dec_key, &validation)?;
+        token_data.claims.jwt.validate(&**clock)?;
 
         match token_data.claims.jwt.kind {
-            JwtKind::Access => Ok(token_data.claims.try_into()?),
+            JwtKind::Access => token_data.claims.into_current(clock.clone()),
             _ => Err(Error::JwtInvalid),
         }
     }
 
-    inner(&input.into(), dec_key)
+    inner(&input.into(), dec_key, clock)
+}
+
+/// Authenticates a request from its bearer token, which can be an access
+/// token or an API key. Access tokens issued for a session that has since
+/// been signed out are turned away.
+pub async fn authenticate_request(
+    input: impl Into<AuthenticateInput>,
+    persist: &Persist,
+    dec_key: &DecodingKey,
+) -> Result<CurrentAccount> {
+    let input = input.into();
+    if let Some(token) = input.token()?.filter(|token| is_api_key(token)) {
+        return authenticate_api_key(persist, token).await;
+    }
+    let current = authenticate(input, dec_key, &persist.shared_clock())?;
+    check_session(persist, &current).await?;
+    Ok(current)
+}
+
+/// The account making a request, from its bearer token if it has one, which
+/// can be an access token or an API key.
+pub struct Authenticated(pub CurrentAccount);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Authenticated
+where
+    S: Send + Sync,
+    Persist: FromRef<S>,
+    Arc<DecodingKey>: FromRef<S>,
+{
+    type Rejection = ErrorResponse;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &S,
+    ) -> std::result::Result<Self, Self::Rejection> {
+        let header = Option::<TypedHeader<Authorization<Bearer>>>::from_request_parts(parts, state)
+            .await
+            .ok()
+            .flatten();
+        let current =
+            authenticate_request(header, &Persist::from_ref(state), &Arc::from_ref(state)).await?;
+        Ok(Self(current))
+    }
 }
 
-pub fn create_refresh_token(id: ID, enc_key: &jsonwebtoken::EncodingKey) -> Result<String> {
+/// Issues a JWT refresh token for a session, which expires by `expires_by` if
+/// it's given.
+///
+/// These aren't issued any more, as they can't be revoked on use, but are
+/// still accepted until they expire.
+#[cfg(test)]
+pub fn create_refresh_token(
+    id: ID,
+    session_id: Option<ID>,
+    expires_by: Option<DateTime<Utc>>,
+    enc_key: &jsonwebtoken::EncodingKey,
+    clock: &dyn Clock,
+) -> Result<String> {
+    let mut claims = RefreshClaims::new(id, session_id, clock.now());
+    claims.jwt.expire_by(expires_by);
     let token = jsonwebtoken::encode(
         &jsonwebtoken::Header::new(Algorithm::EdDSA),
-        &RefreshClaims::new(id),
+        &claims,
         enc_key,
     )?;
 
     Ok(token)
 }
 
-pub fn verify_refresh_token(token: &str, dec_key: &DecodingKey) -> Result<RefreshClaims> {
+pub fn verify_refresh_token(
+    token: &str,
+    dec_key: &DecodingKey,
+    clock: &dyn Clock,
+) -> Result<RefreshClaims> {
     let validation = default_validation();
     let token_data = jsonwebtoken::decode::<RefreshClaims>(token, dec_key, &validation)?;
+    token_data.claims.jwt.validate(clock)?;
 
     match token_data.claims.jwt.kind {
         JwtKind::Refresh => Ok(token_data.claims),
@@ -175,57 +369,124 @@ pub fn verify_refresh_token(token: &str, dec_key: &DecodingKey) -> Result<Refres
     }
 }
 
-fn default_validation() -> Validation {
-    let mut validation = Validation::new(Algorithm::EdDSA);
-    validation.validate_nbf = true;
-    validation
+pub fn create_disown_token(
+    claims: &DisownClaims,
+    enc_key: &jsonwebtoken::EncodingKey,
+) -> Result<String> {
+    let token = jsonwebtoken::encode(
+        &jsonwebtoken::Header::new(Algorithm::EdDSA),
+        claims,
+        enc_key,
+    )?;
+
+    Ok(token)
 }
 
-static PBKDF2_ITERS: u32 = 100_000;
+pub fn verify_disown_token(
+    token: &str,
+    dec_key: &DecodingKey,
+    clock: &dyn Clock,
+) -> Result<DisownClaims> {
+    let validation = default_validation();
+    let token_data = jsonwebtoken::decode::<DisownClaims>(token, dec_key, &validation)?;
+    token_data.claims.jwt.validate(clock)?;
 
-pub struct StoredPword {
-    pub salt: SecretString,
-    pub hash: SecretString,
+    match token_data.claims.jwt.kind {
+        JwtKind::Disown => Ok(token_data.claims),
+        _ => Err(Error::JwtInvalid),
+    }
 }
 
-pub fn create_creds(csrng: &SystemRandom, pword: &str) -> Result<StoredPword> {
-    const CREDENTIAL_LEN: usize = digest::SHA512_OUTPUT_LEN;
-
-    let mut pword_salt = [0u8; CREDENTIAL_LEN];
-    csrng.fill(&mut pword_salt)?;
+/// How long after signing in with a password the second factor can be given.
+pub const TWO_FACTOR_CHALLENGE_MINUTES: i64 = 5;
 
-    let mut pword_hash = [0u8; CREDENTIAL_LEN];
-    pbkdf2::derive(
-        pbkdf2::PBKDF2_HMAC_SHA512,
-        PBKDF2_ITERS.try_into().unwrap(),
-        &pword_salt,
-        pword.as_bytes(),
-        &mut pword_hash,
-    );
+/// Claims for a token that says an account has signed in with its password,
+/// and still needs to give its second factor. It can't be used to access the
+/// account.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TwoFactorClaims {
+    id: ID,
+    #[serde(flatten)]
+    jwt: JwtClaims,
+}
 
-    let pword_salt = BASE64_STANDARD_NO_PAD.encode(pword_salt);
-    let pword_hash = BASE64_STANDARD_NO_PAD.encode(pword_hash);
+impl TwoFactorClaims {
+    pub fn new(id: ID, now: DateTime<Utc>) -> Self {
+        Self {
+            id,
+            jwt: JwtClaims::new(
+                now,
+                Duration::minutes(TWO_FACTOR_CHALLENGE_MINUTES),
+                JwtKind::TwoFactor,
+            ),
+        }
+    }
 
-    Ok(StoredPword {
-        salt: pword_salt.into(),
-        hash: pword_hash.into(),
-    })
+    pub fn id(&self) -> &str {
+        &self.id
+    }
 }
 
-pub fn verify_creds(
-    pword: &SecretString,
-    pword_salt: &SecretString,
-    pword_hash: &SecretString,
-) -> Result<()> {
-    pbkdf2::verify(
-        pbkdf2::PBKDF2_HMAC_SHA512,
-        PBKDF2_ITERS.try_into().unwrap(),
-        &BASE64_STANDARD_NO_PAD.decode(pword_salt.expose_secret())?,
-        pword.expose_secret().as_bytes(),
-        &BASE64_STANDARD_NO_PAD.decode(pword_hash.expose_secret())?,
+pub fn create_two_factor_token(
+    claims: &TwoFactorClaims,
+    enc_key: &jsonwebtoken::EncodingKey,
+) -> Result<String> {
+    let token = jsonwebtoken::encode(
+        &jsonwebtoken::Header::new(Algorithm::EdDSA),
+        claims,
+        enc_key,
     )?;
 
-    Ok(())
+    Ok(token)
+}
+
+pub fn verify_two_factor_token(
+    token: &str,
+    dec_key: &DecodingKey,
+    clock: &dyn Clock,
+) -> Result<TwoFactorClaims> {
+    let validation = default_validation();
+    let token_data = jsonwebtoken::decode::<TwoFactorClaims>(token, dec_key, &validation)?;
+    token_data.claims.jwt.validate(clock)?;
+
+    match token_data.claims.jwt.kind {
+        JwtKind::TwoFactor => Ok(token_data.claims),
+        _ => Err(Error::JwtInvalid),
+    }
+}
+
+fn default_validation() -> Validation {
+    let mut validation = Validation::new(Algorithm::EdDSA);
+    // Timestamps are checked by `JwtClaims::validate` instead.
+    validation.validate_exp = false;
+    validation
+}
+
+/// How many random bytes are in the secret of an opaque token.
+const SECRET_LEN: usize = 32;
+
+/// Generates the secret of an opaque token, which is stored as a record
+/// and given out as `<record ID>.<secret>`.
+fn generate_secret(csrng: &SystemRandom) -> Result<String> {
+    let mut secret = [0u8; SECRET_LEN];
+    csrng.fill(&mut secret)?;
+    Ok(BASE64_URL_SAFE_NO_PAD.encode(secret))
+}
+
+/// Splits an opaque token into the ID of its record and its secret. JWTs
+/// have more than one `.`, so aren't mistaken for opaque tokens.
+fn parse_opaque_token(token: &str) -> Option<(&str, &str)> {
+    let (id, secret) = token.split_once('.')?;
+    if id.is_empty() || secret.is_empty() || secret.contains('.') {
+        return None;
+    }
+    Some((id, secret))
+}
+
+/// Hashes the secret of an opaque token. Only the hash is stored, so the
+/// table can't be used to sign in.
+fn hash_secret(secret: &str) -> String {
+    BASE64_STANDARD_NO_PAD.encode(digest::digest(&digest::SHA256, secret.as_bytes()))
 }
 
 #[derive(Debug, Clone)]
@@ -234,6 +495,33 @@ pub enum AuthenticateInput {
     Init(serde_json::Value),
 }
 
+impl AuthenticateInput {
+    /// The bearer token that was given, if there was one.
+    fn token(&self) -> Result<Option<&str>> {
+        match self {
+            Self::Header(header) => Ok(header.as_ref().map(|h| h.0.token())),
+            Self::Init(init) => {
+                let token = match init.as_object() {
+                    Some(obj) => obj.get("token"),
+                    None => {
+                        if init.is_null() {
+                            None
+                        } else {
+                            return Err(Error::WsInitNotObject);
+                        }
+                    }
+                };
+
+                match token.map(serde_json::Value::as_str) {
+                    Some(Some(token)) => Ok(Some(token)),
+                    Some(None) => Err(Error::WsInitTokenNotString),
+                    None => Ok(None),
+                }
+            }
+        }
+    }
+}
+
 impl From<Option<TypedHeader<Authorization<Bearer>>>> for AuthenticateInput {
     fn from(header: Option<TypedHeader<Authorization<Bearer>>>) -> AuthenticateInput {
         AuthenticateInput::Header(header)
@@ -255,27 +543,15 @@ fn into_utc(timestamp: i64) -> Result<DateTime<Utc>> {
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+
     use serde_json::json;
 
     use super::{testing::*, *};
+    use crate::provider::{MockClock, SystemClock};
 
-    #[test]
-    fn test_creds_valid() {
-        let creds = create_creds(&SystemRandom::new(), "password").unwrap();
-
-        let res = verify_creds(&"password".to_owned().into(), &creds.salt, &creds.hash);
-
-        assert!(res.is_ok());
-    }
-
-    #[test]
-    fn test_creds_invalid() {
-        let creds = create_creds(&SystemRandom::new(), "password").unwrap();
-
-        let res = verify_creds(&"password1".to_owned().into(), &creds.salt, &creds.hash);
-
-        println!("{res:?}");
-        assert!(res.is_err());
+    fn clock() -> SharedClock {
+        Arc::new(SystemClock)
     }
 
     #[test]
@@ -283,13 +559,13 @@ mod tests {
         let (enc_key, dec_key) = generate_keys();
 
         let acc = PartialAccount::new("id".into(), "user_id".into());
-        let token = create_access_token(&acc, &enc_key).unwrap();
+        let token = create_access_token(&acc, None, &enc_key, &SystemClock).unwrap();
 
         for inp in [
             Into::<AuthenticateInput>::into(json!({ "token": token })),
             Some(TypedHeader(Authorization::bearer(&token).unwrap())).into(),
         ] {
-            let auth = authenticate(inp, &dec_key);
+            let auth = authenticate(inp, &dec_key, &clock());
             println!("{auth:?}");
             assert!(auth.is_ok());
 
@@ -308,7 +584,7 @@ mod tests {
         let (enc_key_b, dec_key_b) = generate_keys();
 
         // Invalid token
-        let auth = authenticate(json!({ "token": "not a token" }), &dec_key_a);
+        let auth = authenticate(json!({ "token": "not a token" }), &dec_key_a, &clock());
         println!("{auth:?}");
         assert!(auth.is_err());
         assert_eq!(auth.unwrap_err(), Error::JwtMalformed);
@@ -316,14 +592,14 @@ mod tests {
         // Invalid signature
         let acc = PartialAccount::new("id".into(), "user_id".into());
 
-        let token = create_access_token(&acc, &enc_key_a).unwrap();
-        let auth = authenticate(json!({ "token": token }), &dec_key_b);
+        let token = create_access_token(&acc, None, &enc_key_a, &SystemClock).unwrap();
+        let auth = authenticate(json!({ "token": token }), &dec_key_b, &clock());
         println!("{auth:?}");
         assert!(auth.is_err());
         assert_eq!(auth.unwrap_err(), Error::JwtInvalid);
 
         // Expired token
-        let mut access_claims = AccessClaims::new(acc);
+        let mut access_claims = AccessClaims::new(&acc, Utc::now());
         access_claims.jwt.iat -= 300;
         access_claims.jwt.nbf -= 300;
         access_claims.jwt.exp = (Utc::now().timestamp()) - 100;
@@ -333,14 +609,29 @@ mod tests {
             &enc_key_b,
         )
         .unwrap();
-        let auth = authenticate(json!({ "token": token }), &dec_key_b);
+        let auth = authenticate(json!({ "token": token }), &dec_key_b, &clock());
         println!("{auth:?}");
         assert!(auth.is_err());
         assert_eq!(auth.unwrap_err(), Error::JwtExpired);
 
+        // Outdated claims
+        let mut access_claims = AccessClaims::new(acc, Utc::now());
+        access_claims.jwt.ver = 0;
+        let token = jsonwebtoken::encode(
+            &jsonwebtoken::Header::new(Algorithm::EdDSA),
+            &access_claims,
+            &enc_key_b,
+        )
+        .unwrap();
+        let auth = authenticate(json!({ "token": token }), &dec_key_b, &clock());
+        println!("{auth:?}");
+        assert!(auth.is_err());
+        assert_eq!(auth.unwrap_err(), Error::JwtOutdated);
+
         // Refresh token
-        let token = create_refresh_token("id".into(), &enc_key_b).unwrap();
-        let auth = authenticate(json!({ "token": token }), &dec_key_b);
+        let token =
+            create_refresh_token("id".into(), None, None, &enc_key_b, &SystemClock).unwrap();
+        let auth = authenticate(json!({ "token": token }), &dec_key_b, &clock());
         println!("{auth:?}");
         assert!(auth.is_err());
         assert_eq!(auth.unwrap_err(), Error::JwtInvalid);
@@ -350,9 +641,9 @@ mod tests {
     fn test_refresh_token_valid() {
         let (enc_key, dec_key) = generate_keys();
 
-        let token = create_refresh_token("id".into(), &enc_key).unwrap();
+        let token = create_refresh_token("id".into(), None, None, &enc_key, &SystemClock).unwrap();
 
-        let auth = verify_refresh_token(&token, &dec_key);
+        let auth = verify_refresh_token(&token, &dec_key, &SystemClock);
         println!("{auth:?}");
         assert!(auth.is_ok());
 
@@ -366,20 +657,21 @@ mod tests {
         let (enc_key_b, dec_key_b) = generate_keys();
 
         // Invalid token
-        let auth = verify_refresh_token("not a token", &dec_key_a);
+        let auth = verify_refresh_token("not a token", &dec_key_a, &SystemClock);
         println!("{auth:?}");
         assert!(auth.is_err());
         assert_eq!(auth.unwrap_err(), Error::JwtMalformed);
 
         // Invalid signature
-        let token = create_refresh_token("id".into(), &enc_key_a).unwrap();
-        let auth = verify_refresh_token(&token, &dec_key_b);
+        let token =
+            create_refresh_token("id".into(), None, None, &enc_key_a, &SystemClock).unwrap();
+        let auth = verify_refresh_token(&token, &dec_key_b, &SystemClock);
         println!("{auth:?}");
         assert!(auth.is_err());
         assert_eq!(auth.unwrap_err(), Error::JwtInvalid);
 
         // Expired token
-        let mut refresh_claims = RefreshClaims::new("id".into());
+        let mut refresh_claims = RefreshClaims::new("id".into(), None, Utc::now());
         refresh_claims.jwt.iat -= 300;
         refresh_claims.jwt.nbf -= 300;
         refresh_claims.jwt.exp = (Utc::now().timestamp()) - 100;
@@ -389,24 +681,191 @@ mod tests {
             &enc_key_b,
         )
         .unwrap();
-        let auth = verify_refresh_token(&token, &dec_key_b);
+        let auth = verify_refresh_token(&token, &dec_key_b, &SystemClock);
         println!("{auth:?}");
         assert!(auth.is_err());
         assert_eq!(auth.unwrap_err(), Error::JwtExpired);
 
         // Access token
         let acc = PartialAccount::new("id".into(), "user_id".into());
-        let token = create_access_token(&acc, &enc_key_b).unwrap();
-        let auth = verify_refresh_token(&token, &dec_key_b);
+        let token = create_access_token(&acc, None, &enc_key_b, &SystemClock).unwrap();
+        let auth = verify_refresh_token(&token, &dec_key_b, &SystemClock);
         println!("{auth:?}");
         assert!(auth.is_err());
         assert_eq!(auth.unwrap_err(), Error::JwtInvalid);
     }
+
+    #[test]
+    fn test_disown_token() {
+        let (enc_key, dec_key) = generate_keys();
+        let clock = MockClock::default();
+
+        let claims = DisownClaims::new("id".into(), "event".into(), clock.now());
+        let token = create_disown_token(&claims, &enc_key).unwrap();
+        let claims = verify_disown_token(&token, &dec_key, &clock).unwrap();
+        assert_eq!(claims.id(), "id");
+        assert_eq!(claims.event_id(), "event");
+
+        // Disown tokens can't be used as any other kind, or the other way
+        // around.
+        assert_eq!(
+            verify_refresh_token(&token, &dec_key, &clock).unwrap_err(),
+            Error::JwtInvalid
+        );
+        let refresh_token =
+            create_refresh_token("id".into(), None, None, &enc_key, &clock).unwrap();
+        assert!(verify_disown_token(&refresh_token, &dec_key, &clock).is_err());
+
+        clock.advance(Duration::days(DISOWN_TOKEN_DAYS) + Duration::minutes(2));
+        assert_eq!(
+            verify_disown_token(&token, &dec_key, &clock).unwrap_err(),
+            Error::JwtExpired
+        );
+    }
+
+    /// Tokens issued by earlier versions must keep working, or fail with
+    /// `JwtOutdated`, so the claims shouldn't change shape without bumping
+    /// their version.
+    #[test]
+    fn test_claims_wire_format() {
+        let now = Utc.timestamp_opt(1_672_531_200, 0).unwrap();
+        let clock: SharedClock = Arc::new(MockClock::new(now));
+        let acc = PartialAccount::new("id".into(), "user_id".into());
+
+        let access = json!({
+            "id": "id",
+            "uid": "user_id",
+            "exp": 1_672_532_100,
+            "iat": 1_672_531_200,
+            "nbf": 1_672_531_200,
+            "kind": "Access",
+            "ver": 1,
+        });
+        let value = serde_json::to_value(AccessClaims::new(&acc, now)).unwrap();
+        assert_eq!(value, access);
+        let claims: AccessClaims = serde_json::from_value(access.clone()).unwrap();
+        claims.jwt.validate(&*clock).unwrap();
+        let current = claims.into_current(clock.clone()).unwrap();
+        assert_eq!(current.account(), Ok(&acc));
+
+        // Access tokens from before claims were versioned have to be
+        // replaced.
+        let mut unversioned = access;
+        unversioned.as_object_mut().unwrap().remove("ver");
+        let claims: AccessClaims = serde_json::from_value(unversioned).unwrap();
+        assert_eq!(claims.jwt.validate(&*clock), Err(Error::JwtOutdated));
+
+        let refresh = json!({
+            "id": "id",
+            "exp": 1_675_123_200,
+            "iat": 1_672_531_200,
+            "nbf": 1_672_531_200,
+            "kind": "Refresh",
+            "ver": 1,
+        });
+        let value = serde_json::to_value(RefreshClaims::new("id".into(), None, now)).unwrap();
+        assert_eq!(value, refresh);
+        let mut unversioned = refresh;
+        unversioned.as_object_mut().unwrap().remove("ver");
+        let claims: RefreshClaims = serde_json::from_value(unversioned).unwrap();
+        claims.jwt.validate(&*clock).unwrap();
+        assert_eq!(claims.id(), "id");
+        assert_eq!(claims.issued_at(), Ok(now));
+    }
+
+    #[test]
+    fn test_tokens_expire_with_clock() {
+        let (enc_key, dec_key) = generate_keys();
+        let clock = MockClock::default();
+        let shared: SharedClock = Arc::new(clock.clone());
+
+        let acc = PartialAccount::new("id".into(), "user_id".into());
+        let access_token = create_access_token(&acc, None, &enc_key, &clock).unwrap();
+        let refresh_token =
+            create_refresh_token("id".into(), None, None, &enc_key, &clock).unwrap();
+
+        // Tokens are still accepted within the leeway.
+        clock.advance(Duration::minutes(16));
+        let auth = authenticate(json!({ "token": access_token }), &dec_key, &shared);
+        println!("{auth:?}");
+        assert!(auth.is_ok());
+        // But the account itself has expired.
+        assert_eq!(auth.unwrap().id(), Err(Error::Unauthenticated));
+
+        clock.advance(Duration::minutes(1));
+        let auth = authenticate(json!({ "token": access_token }), &dec_key, &shared);
+        assert_eq!(auth, Err(Error::JwtExpired));
+        assert!(verify_refresh_token(&refresh_token, &dec_key, &clock).is_ok());
+
+        clock.advance(Duration::days(30));
+        let auth = verify_refresh_token(&refresh_token, &dec_key, &clock);
+        println!("{auth:?}");
+        assert_eq!(auth.unwrap_err(), Error::JwtExpired);
+    }
+
+    #[test]
+    fn test_access_token_session() {
+        let (enc_key, dec_key) = generate_keys();
+        let clock = MockClock::default();
+        let shared: SharedClock = Arc::new(clock.clone());
+
+        let acc =
+            PartialAccount::new("id".into(), "user_id".into()).with_session_id(Some("sid".into()));
+        let access_token = create_access_token(&acc, None, &enc_key, &clock).unwrap();
+        let auth = authenticate(json!({ "token": access_token }), &dec_key, &shared).unwrap();
+        assert_eq!(auth.session_id(), Ok(Some(&ID::from("sid"))));
+
+        let acc = PartialAccount::new("id".into(), "user_id".into());
+        let access_token = create_access_token(&acc, None, &enc_key, &clock).unwrap();
+        let auth = authenticate(json!({ "token": access_token }), &dec_key, &shared).unwrap();
+        assert_eq!(auth.session_id(), Ok(None));
+    }
+
+    #[test]
+    fn test_tokens_not_yet_valid() {
+        let (enc_key, dec_key) = generate_keys();
+        let clock = MockClock::default();
+
+        let token = create_refresh_token("id".into(), None, None, &enc_key, &clock).unwrap();
+        clock.advance(-Duration::minutes(5));
+        let auth = verify_refresh_token(&token, &dec_key, &clock);
+        println!("{auth:?}");
+        assert_eq!(auth.unwrap_err(), Error::JwtInvalid);
+    }
+
+    #[test]
+    fn test_tokens_expire_by_session() {
+        let (enc_key, dec_key) = generate_keys();
+        let clock = MockClock::default();
+        let shared: SharedClock = Arc::new(clock.clone());
+        let expires_by = Some(clock.now() + Duration::minutes(5));
+
+        let acc = PartialAccount::new("id".into(), "user_id".into());
+        let access_token = create_access_token(&acc, expires_by, &enc_key, &clock).unwrap();
+        let refresh_token = create_refresh_token(
+            "id".into(),
+            Some("sid".into()),
+            expires_by,
+            &enc_key,
+            &clock,
+        )
+        .unwrap();
+        let claims = verify_refresh_token(&refresh_token, &dec_key, &clock).unwrap();
+        assert_eq!(claims.session_id(), Some("sid"));
+
+        clock.advance(Duration::minutes(7));
+        let auth = authenticate(json!({ "token": access_token }), &dec_key, &shared);
+        assert_eq!(auth, Err(Error::JwtExpired));
+        let auth = verify_refresh_token(&refresh_token, &dec_key, &clock);
+        assert_eq!(auth.unwrap_err(), Error::JwtExpired);
+    }
 }
 
 #[cfg(test)]
 pub mod testing {
-    use chrono::{Duration, Utc};
+    use std::sync::Arc;
+
+    use chrono::Duration;
     use ring::{
         rand::SystemRandom,
         signature::{self, KeyPair as _},
@@ -416,8 +875,10 @@ pub mod testing {
 
     use crate::{
         account::{Account, AccountPersist, CurrentAccount, PartialAccount},
+        email::MemoryEmailSender,
         persist::{testing::persist, Persist},
         prelude::*,
+        provider::MockClock,
     };
 
     pub fn generate_keys() -> (jsonwebtoken::EncodingKey, jsonwebtoken::DecodingKey) {
@@ -457,19 +918,52 @@ pub mod testing {
             }
         }
 
+        /// Creates test data where time only moves when the given clock is
+        /// told to.
+        pub async fn with_clock(clock: MockClock) -> Self {
+            let mut data = Self::new().await;
+            data.persist = data.persist.with_clock(Arc::new(clock));
+            data
+        }
+
         pub async fn with_user() -> (Self, AccData) {
             let mut data = Self::new().await;
             let account = data.account();
             let acc = account.create_test_user().await;
-            data.current = CurrentAccount::new(
+            data.login_as(&acc);
+            (data, acc)
+        }
+
+        /// Switches the current account to the given one.
+        pub fn login_as(&mut self, acc: &AccData) {
+            self.current = CurrentAccount::new(
                 PartialAccount::new(acc.acc.id.to_gql_id(), acc.user_id.clone()),
-                Utc::now() + Duration::minutes(30),
+                self.persist.clock().now() + Duration::minutes(30),
+                self.persist.shared_clock(),
+            );
+        }
+
+        /// Signs in as the account with a token issued for one of its
+        /// sessions.
+        pub fn login_in_session(&mut self, acc: &AccData, session_id: &Thing) {
+            self.current = CurrentAccount::new(
+                PartialAccount::new(acc.acc.id.to_gql_id(), acc.user_id.clone())
+                    .with_session_id(Some(session_id.to_gql_id())),
+                self.persist.clock().now() + Duration::minutes(30),
+                self.persist.shared_clock(),
             );
-            (data, acc)
         }
 
         pub fn account(&self) -> AccountPersist<'_> {
             AccountPersist::new(&self.persist, &self.current, &self.csrng, &self.jwt_dec_key)
         }
+
+        /// Keeps email that the instance sends in memory instead of dropping
+        /// it.
+        pub fn record_emails(&mut self) -> MemoryEmailSender {
+            let email = MemoryEmailSender::default();
+            self.persist = self.persist.clone().with_email(Arc::new(email.clone()));
+            email
+        }
     }
 }
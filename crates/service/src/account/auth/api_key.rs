@@ -0,0 +1,323 @@
+//! API keys, for bots and scripts that use the API without signing in.
+//!
+//! Keys are long-lived opaque tokens, sent as bearer tokens in place of an
+//! access token. Only a hash of each key's secret is stored. Each key is
+//! limited to the scopes it was created with, which are checked for every
+//! GraphQL field at the root of an operation and every REST request.
+
+use std::sync::Arc;
+
+use async_graphql::{
+    extensions::{Extension, ExtensionContext, ExtensionFactory, NextResolve, ResolveInfo},
+    ComplexObject, Enum, ErrorExtensions as _, InputObject, Pos, ServerResult, SimpleObject, Value,
+    ID,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use ring::rand::SystemRandom;
+use serde::{Deserialize, Serialize};
+use surrealdb::sql::Thing;
+
+use super::{generate_secret, hash_secret, parse_opaque_token};
+use crate::{
+    account::{Account, CurrentAccount, PartialAccount},
+    persist::Persist,
+    prelude::*,
+};
+
+pub static API_KEY_TABLE_NAME: &str = "api_key";
+
+/// What every API key starts with, which tells them apart from access tokens.
+pub static API_KEY_PREFIX: &str = "plz_";
+
+/// How many API keys an account can have at once.
+pub const MAX_API_KEYS: usize = 20;
+
+/// The longest that an API key can last for, in days.
+pub const API_KEY_MAX_DAYS: u32 = 365 * 5;
+
+/// What an API key is allowed to do.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiKeyScope {
+    /// Run queries and subscriptions, and make REST requests that don't
+    /// change anything.
+    Read,
+    /// Run mutations, and make REST requests that change things.
+    Write,
+}
+
+impl QueryValue for Vec<ApiKeyScope> {
+    fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
+        srql::to_value(self)
+            .ok()
+            .map(|value| (field, srql::Operator::Equal, value))
+    }
+}
+
+/// An API key that an account created. Its secret is only given out when
+/// it's created.
+#[derive(SimpleObject, Debug, Clone, PartialEq, Eq, Deserialize)]
+#[graphql(complex)]
+pub struct ApiKey {
+    #[graphql(skip)]
+    pub id: Thing,
+    #[graphql(skip)]
+    pub account_id: Thing,
+    #[graphql(skip)]
+    secret_hash: String,
+    /// A name for the key, to tell it apart from others.
+    pub name: String,
+    /// What the key is allowed to do.
+    pub scopes: Vec<ApiKeyScope>,
+    /// When the key was created.
+    pub created_at: DateTime<Utc>,
+    /// When the key stops working, if it ever does.
+    pub expires_at: Option<DateTime<Utc>>,
+    /// When the key was last used.
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+#[ComplexObject]
+impl ApiKey {
+    /// The key's unique ID. This isn't secret, and is the start of the key.
+    async fn id(&self) -> ID {
+        self.id.to_gql_id()
+    }
+}
+
+/// The details of an API key to create.
+#[derive(InputObject, Debug, Clone)]
+pub struct CreateApiKey {
+    /// A name for the key, to tell it apart from others.
+    #[graphql(validator(min_length = 1, max_length = 64))]
+    pub name: String,
+    /// What the key is allowed to do. At least one scope has to be given.
+    pub scopes: Vec<ApiKeyScope>,
+    /// How many days the key lasts for. Keys without one last until they're
+    /// revoked.
+    #[graphql(validator(minimum = 1, maximum = 1825))]
+    pub expires_in_days: Option<u32>,
+}
+
+/// An API key that has just been created, along with its secret.
+#[derive(SimpleObject, Debug, Clone)]
+pub struct CreatedApiKey {
+    pub api_key: ApiKey,
+    /// The key to send as a bearer token. This is only shown once, as only a
+    /// hash of it is kept.
+    pub key: String,
+}
+
+/// Creates an API key for an account.
+pub async fn create_api_key(
+    persist: &Persist,
+    csrng: &SystemRandom,
+    account_id: &Thing,
+    input: CreateApiKey,
+) -> Result<CreatedApiKey> {
+    let CreateApiKey {
+        name,
+        mut scopes,
+        expires_in_days,
+    } = input;
+    scopes.sort();
+    scopes.dedup();
+    if scopes.is_empty() {
+        return Err(Error::InputInvalid(
+            "at least one scope must be given".into(),
+        ));
+    }
+    if list_api_keys(persist, account_id).await?.len() >= MAX_API_KEYS {
+        return Err(Error::QuotaExceeded("API keys".into()));
+    }
+
+    let secret = generate_secret(csrng)?;
+    let now = persist.clock().now();
+    let expires_at =
+        expires_in_days.map(|days| now + Duration::days(i64::from(days.min(API_KEY_MAX_DAYS))));
+
+    let id = persist.ids().next_id();
+    let mut create = vec![];
+    account_id
+        .clone()
+        .push_field(srql::field("account_id"), &mut create);
+    hash_secret(&secret).push_field(srql::field("secret_hash"), &mut create);
+    name.push_field(srql::field("name"), &mut create);
+    scopes.push_field(srql::field("scopes"), &mut create);
+    now.push_field(srql::field("created_at"), &mut create);
+    expires_at.push_field(srql::field("expires_at"), &mut create);
+
+    let created: Option<ApiKey> = persist
+        .db()
+        .query(srql::obj_create_query_id(
+            API_KEY_TABLE_NAME,
+            create,
+            id.clone().into(),
+        ))
+        .await?
+        .take(0)?;
+    let Some(api_key) = created else {
+        return Err(Error::InternalServerError("API key wasn't created".into()));
+    };
+    Ok(CreatedApiKey {
+        api_key,
+        key: format!("{API_KEY_PREFIX}{id}.{secret}"),
+    })
+}
+
+/// Lists an account's API keys, oldest first.
+pub async fn list_api_keys(persist: &Persist, account_id: &Thing) -> Result<Vec<ApiKey>> {
+    let api_keys = persist
+        .db()
+        .query(srql::SelectStatement {
+            expr: srql::Fields::all(),
+            what: srql::table(API_KEY_TABLE_NAME),
+            cond: srql::Cond(
+                srql::Expression::Binary {
+                    l: srql::field("account_id").into(),
+                    o: srql::Operator::Equal,
+                    r: account_id.clone().into(),
+                }
+                .into(),
+            )
+            .into(),
+            order: Some(srql::Orders(vec![srql::Order {
+                order: srql::field("id"),
+                direction: true,
+                ..Default::default()
+            }])),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    Ok(api_keys)
+}
+
+/// Revokes one of an account's API keys, returning it if it was there. It
+/// stops working straight away.
+pub async fn revoke_api_key(
+    persist: &Persist,
+    account_id: &Thing,
+    id: &str,
+) -> Result<Option<ApiKey>> {
+    let revoked: Option<ApiKey> = persist
+        .db()
+        .query(srql::DeleteStatement {
+            what: srql::thing(Thing::from((API_KEY_TABLE_NAME, id))),
+            cond: srql::Cond(
+                srql::Expression::Binary {
+                    l: srql::field("account_id").into(),
+                    o: srql::Operator::Equal,
+                    r: account_id.clone().into(),
+                }
+                .into(),
+            )
+            .into(),
+            output: srql::Output::Before.into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    Ok(revoked)
+}
+
+/// Whether a bearer token is an API key, rather than an access token.
+#[must_use]
+pub fn is_api_key(token: &str) -> bool {
+    token.starts_with(API_KEY_PREFIX)
+}
+
+/// Authenticates a request made with an API key, as the account that created
+/// it and limited to its scopes. Keys that are unknown or expired fail with
+/// `CredentialsInvalid`.
+pub async fn authenticate_api_key(persist: &Persist, token: &str) -> Result<CurrentAccount> {
+    let Some((id, secret)) = token
+        .strip_prefix(API_KEY_PREFIX)
+        .and_then(parse_opaque_token)
+    else {
+        return Err(Error::CredentialsInvalid);
+    };
+    let stored: Option<ApiKey> = persist.db().select((API_KEY_TABLE_NAME, id)).await?;
+    let Some(stored) = stored.filter(|stored| stored.secret_hash == hash_secret(secret)) else {
+        return Err(Error::CredentialsInvalid);
+    };
+    let now = persist.clock().now();
+    if stored
+        .expires_at
+        .is_some_and(|expires_at| expires_at <= now)
+    {
+        return Err(Error::CredentialsInvalid);
+    }
+    let account: Option<Account> = persist.load(stored.account_id.clone()).await?;
+    let Some(account) = account else {
+        return Err(Error::CredentialsInvalid);
+    };
+    if account.is_suspended(now) {
+        return Err(Error::AccountSuspended);
+    }
+    if account.deleted_at.is_some() {
+        return Err(Error::AccountDeleted);
+    }
+
+    // Only note that the key was used every so often, rather than writing
+    // on every request.
+    if stored
+        .last_used_at
+        .is_none_or(|last_used_at| now - last_used_at >= Duration::minutes(5))
+    {
+        let mut update = vec![];
+        now.push_field(srql::field("last_used_at"), &mut update);
+        if let Some(update) = srql::obj_update_query(stored.id.clone(), update) {
+            persist.db().query(update).await?.check()?;
+        }
+    }
+
+    Ok(CurrentAccount::new(
+        PartialAccount::new(account.id.to_gql_id(), account.user_id),
+        stored.expires_at.unwrap_or(DateTime::<Utc>::MAX_UTC),
+        persist.shared_clock(),
+    )
+    .with_scopes(stored.scopes))
+}
+
+/// Turns away GraphQL fields that the API key a request was made with isn't
+/// scoped for. Mutations need [`ApiKeyScope::Write`], and queries and
+/// subscriptions need [`ApiKeyScope::Read`]. Requests made with an access
+/// token can do anything.
+pub struct ApiKeyScopeGuard;
+
+impl ExtensionFactory for ApiKeyScopeGuard {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(ApiKeyScopeExtension)
+    }
+}
+
+struct ApiKeyScopeExtension;
+
+#[async_trait]
+impl Extension for ApiKeyScopeExtension {
+    async fn resolve(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        info: ResolveInfo<'_>,
+        next: NextResolve<'_>,
+    ) -> ServerResult<Option<Value>> {
+        if info.path_node.parent.is_none() {
+            let current = ctx
+                .data_opt::<CurrentAccount>()
+                .or_else(|| ctx.data_opt::<Arc<CurrentAccount>>().map(AsRef::as_ref));
+            let scope = if info.parent_type == "Mutation" {
+                ApiKeyScope::Write
+            } else {
+                ApiKeyScope::Read
+            };
+            if current.is_some_and(|current| !current.has_scope(scope)) {
+                return Err(Error::ScopeMissing
+                    .extend()
+                    .into_server_error(Pos::default()));
+            }
+        }
+        next.run(ctx, info).await
+    }
+}
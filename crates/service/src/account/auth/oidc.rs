@@ -0,0 +1,645 @@
+//! Logging in with external identity providers, such as Google and GitHub,
+//! using the `OAuth` 2.0 authorization code flow with PKCE.
+//!
+//! A flow starts by sending the browser to the provider with a random state
+//! and the challenge of a random code verifier, which are kept here for a few
+//! minutes. The provider sends the browser back to the redirect URI with the
+//! state and a code, which are given to finish the flow. The state can only
+//! be used once, and the code is exchanged along with the verifier, so a code
+//! that's intercepted is useless on its own.
+//!
+//! Identities are only ever linked to existing accounts, by an account that's
+//! signed in. Logging in with an identity that isn't linked fails, rather
+//! than creating an account.
+
+use std::{
+    collections::HashMap,
+    fmt::{self, Debug},
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration as StdDuration,
+};
+
+use async_graphql::{ComplexObject, SimpleObject, ID};
+use async_trait::async_trait;
+use base64::prelude::*;
+use chrono::{DateTime, Duration, Utc};
+use hyper::{Body, Method, Request};
+use ring::{
+    digest,
+    rand::{SecureRandom as _, SystemRandom},
+};
+use secrecy::ExposeSecret as _;
+use serde::Deserialize;
+use surrealdb::sql::Thing;
+
+use crate::{
+    config::{OidcConfig, OidcProvider},
+    http::{http_client, HttpClient},
+    persist::Persist,
+    prelude::*,
+};
+
+pub type SharedOidcClient = Arc<dyn OidcClient>;
+
+pub static EXTERNAL_IDENTITY_TABLE_NAME: &str = "external_identity";
+pub static OIDC_FLOW_TABLE_NAME: &str = "oidc_flow";
+
+/// How long the browser has to come back from the provider before the flow
+/// stops working.
+pub const OIDC_FLOW_MINUTES: i64 = 10;
+
+/// How many random bytes are in states and code verifiers.
+const OIDC_RANDOM_LEN: usize = 32;
+
+/// How long to wait for the provider before giving up.
+const EXCHANGE_TIMEOUT: StdDuration = StdDuration::from_secs(10);
+
+/// Responses from the provider any bigger than this aren't tokens or
+/// profiles, so aren't read any further.
+const MAX_RESPONSE_BYTES: usize = 64 * 1024;
+
+/// What a provider says about the person who signed in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExternalProfile {
+    /// The provider's ID for the person, which never changes.
+    pub subject: String,
+    pub email: Option<String>,
+    /// Whether the provider has checked that the email address is theirs.
+    pub email_verified: bool,
+    pub name: Option<String>,
+}
+
+/// Something that finishes flows with providers, exchanging codes for what
+/// the providers say about who signed in.
+#[async_trait]
+pub trait OidcClient: Debug + Send + Sync {
+    /// Exchanges a code that the provider gave for the profile of whoever
+    /// signed in. Codes that the provider rejects fail with
+    /// `ExternalLoginInvalid`.
+    async fn exchange(
+        &self,
+        provider: &OidcProvider,
+        code: &str,
+        redirect_uri: &str,
+        code_verifier: &str,
+    ) -> Result<ExternalProfile>;
+}
+
+/// Exchanges codes with the providers' token endpoints, and fetches profiles
+/// from their userinfo endpoints.
+#[derive(Clone)]
+pub struct HttpOidcClient {
+    client: HttpClient,
+}
+
+impl HttpOidcClient {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            client: http_client(),
+        }
+    }
+
+    async fn send(&self, req: Request<Body>) -> Result<Vec<u8>> {
+        let res = tokio::time::timeout(EXCHANGE_TIMEOUT, self.client.request(req))
+            .await
+            .map_err(|_| Error::from("Identity provider timed out"))?
+            .map_err(Error::from_err)?;
+        if res.status().is_client_error() {
+            return Err(Error::ExternalLoginInvalid);
+        }
+        if !res.status().is_success() {
+            return Err(format!("Identity provider failed with {}", res.status()).into());
+        }
+
+        let body = tokio::time::timeout(EXCHANGE_TIMEOUT, hyper::body::to_bytes(res.into_body()))
+            .await
+            .map_err(|_| Error::from("Identity provider timed out"))?
+            .map_err(Error::from_err)?;
+        if body.len() > MAX_RESPONSE_BYTES {
+            return Err("Identity provider's response is too big".into());
+        }
+        Ok(body.to_vec())
+    }
+}
+
+impl Default for HttpOidcClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for HttpOidcClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpOidcClient").finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: Option<String>,
+}
+
+/// A profile from a userinfo endpoint. `OpenID Connect` providers give `sub`,
+/// and GitHub gives a numeric `id` and a `login`.
+#[derive(Debug, Deserialize)]
+struct UserInfo {
+    sub: Option<String>,
+    id: Option<serde_json::Value>,
+    email: Option<String>,
+    #[serde(default)]
+    email_verified: bool,
+    name: Option<String>,
+    login: Option<String>,
+}
+
+impl UserInfo {
+    fn into_profile(self) -> Option<ExternalProfile> {
+        let subject = match (self.sub, self.id) {
+            (Some(sub), _) => sub,
+            (None, Some(serde_json::Value::String(id))) => id,
+            (None, Some(serde_json::Value::Number(id))) => id.to_string(),
+            _ => return None,
+        };
+        Some(ExternalProfile {
+            subject,
+            email: self.email,
+            email_verified: self.email_verified,
+            name: self.name.or(self.login),
+        })
+    }
+}
+
+#[async_trait]
+impl OidcClient for HttpOidcClient {
+    async fn exchange(
+        &self,
+        provider: &OidcProvider,
+        code: &str,
+        redirect_uri: &str,
+        code_verifier: &str,
+    ) -> Result<ExternalProfile> {
+        let form = serde_urlencoded::to_string([
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", redirect_uri),
+            ("client_id", &provider.client_id),
+            ("client_secret", provider.client_secret.expose_secret()),
+            ("code_verifier", code_verifier),
+        ])
+        .map_err(Error::from_err)?;
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri(&provider.token_url)
+            .header("accept", "application/json")
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body(Body::from(form))
+            .map_err(Error::from_err)?;
+        let token: TokenResponse = serde_json::from_slice(&self.send(req).await?)?;
+        // GitHub answers rejected codes with an error and a success status.
+        let Some(access_token) = token.access_token else {
+            return Err(Error::ExternalLoginInvalid);
+        };
+
+        let req = Request::builder()
+            .method(Method::GET)
+            .uri(&provider.userinfo_url)
+            .header("accept", "application/json")
+            .header("authorization", format!("Bearer {access_token}"))
+            // GitHub rejects requests without a user agent.
+            .header("user-agent", concat!("plazer/", env!("CARGO_PKG_VERSION")))
+            .body(Body::empty())
+            .map_err(Error::from_err)?;
+        let info: UserInfo = serde_json::from_slice(&self.send(req).await?)?;
+        info.into_profile()
+            .ok_or_else(|| Error::from("Identity provider didn't say who signed in"))
+    }
+}
+
+/// Keeps the profiles that codes are exchanged for in memory instead of
+/// asking providers, so flows can be finished without them.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryOidcClient(Arc<Mutex<HashMap<(String, String), ExternalProfile>>>);
+
+impl MemoryOidcClient {
+    fn codes(&self) -> std::sync::MutexGuard<'_, HashMap<(String, String), ExternalProfile>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Makes the provider give a code that's exchanged for the profile.
+    pub fn authorize(&self, provider: &str, code: &str, profile: ExternalProfile) {
+        self.codes()
+            .insert((provider.to_owned(), code.to_owned()), profile);
+    }
+}
+
+#[async_trait]
+impl OidcClient for MemoryOidcClient {
+    async fn exchange(
+        &self,
+        provider: &OidcProvider,
+        code: &str,
+        _redirect_uri: &str,
+        _code_verifier: &str,
+    ) -> Result<ExternalProfile> {
+        // Providers only let codes be exchanged once.
+        self.codes()
+            .remove(&(provider.id.clone(), code.to_owned()))
+            .ok_or(Error::ExternalLoginInvalid)
+    }
+}
+
+/// An external identity that an account can log in with.
+#[derive(SimpleObject, Debug, Clone, Deserialize)]
+#[graphql(complex)]
+pub struct ExternalIdentity {
+    #[graphql(skip)]
+    pub id: Thing,
+    #[graphql(skip)]
+    pub account_id: Thing,
+    /// The ID of the provider that the identity is from.
+    pub provider: String,
+    /// The provider's ID for the identity.
+    pub subject: String,
+    /// The identity's email address, as the provider gave it when it was
+    /// linked.
+    pub email: Option<String>,
+    /// The identity's name, as the provider gave it when it was linked.
+    pub name: Option<String>,
+    /// When the identity was linked to the account.
+    pub linked_at: DateTime<Utc>,
+    /// When the identity was last used to log in.
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+#[ComplexObject]
+impl ExternalIdentity {
+    async fn id(&self) -> ID {
+        self.id.to_gql_id()
+    }
+}
+
+/// Says where to send the browser to sign in with an identity provider. The
+/// provider sends it back to the redirect URI with a `state` and a `code`,
+/// which finish the flow.
+#[derive(SimpleObject, Debug, Clone, PartialEq, Eq)]
+pub struct ExternalLoginRedirect {
+    /// The provider's authorization URL, to send the browser to.
+    pub url: String,
+    /// The state that the provider sends back, which should be checked
+    /// against the one the browser comes back with.
+    pub state: String,
+    /// When the flow stops working, and has to start again.
+    pub expires_at: DateTime<Utc>,
+}
+
+/// A flow that has started and not finished yet. Its record's ID is its
+/// state.
+#[derive(Debug, Clone, Deserialize)]
+struct OidcFlow {
+    provider: String,
+    redirect_uri: String,
+    code_verifier: String,
+    /// The account that the identity is being linked to, or `None` if the
+    /// flow is for logging in.
+    account_id: Option<Thing>,
+    expires_at: DateTime<Utc>,
+}
+
+/// Starts a flow with a provider, returning where to send the browser. When
+/// an account is given, the flow links the identity to it rather than
+/// logging in.
+pub async fn begin_external_flow(
+    persist: &Persist,
+    csrng: &SystemRandom,
+    provider: &OidcProvider,
+    redirect_uri: &str,
+    account_id: Option<Thing>,
+) -> Result<ExternalLoginRedirect> {
+    if !is_redirect_uri(redirect_uri) {
+        return Err(Error::InputInvalid(
+            "redirectUri must be an absolute HTTP or HTTPS URL".into(),
+        ));
+    }
+    let state = random_token(csrng)?;
+    let code_verifier = random_token(csrng)?;
+    let expires_at = persist.clock().now() + Duration::minutes(OIDC_FLOW_MINUTES);
+
+    let mut create = vec![];
+    provider
+        .id
+        .clone()
+        .push_field(srql::field("provider"), &mut create);
+    redirect_uri
+        .to_owned()
+        .push_field(srql::field("redirect_uri"), &mut create);
+    code_verifier
+        .clone()
+        .push_field(srql::field("code_verifier"), &mut create);
+    account_id.push_field(srql::field("account_id"), &mut create);
+    expires_at.push_field(srql::field("expires_at"), &mut create);
+    let mut create = srql::obj_create_query_id(OIDC_FLOW_TABLE_NAME, create, state.clone().into());
+    create.output = srql::Output::None.into();
+    persist.db().query(create).await?.check()?;
+
+    Ok(ExternalLoginRedirect {
+        url: authorization_url(provider, redirect_uri, &state, &code_verifier)?,
+        state,
+        expires_at,
+    })
+}
+
+/// Finishes a flow with the state and code that the provider sent the
+/// browser back with, returning the provider and what it says about who
+/// signed in. Link flows can only be finished by the account that started
+/// them, and login flows only by logging in.
+pub async fn finish_external_flow<'c>(
+    persist: &Persist,
+    config: &'c OidcConfig,
+    state: &str,
+    code: &str,
+    account_id: Option<&Thing>,
+) -> Result<(&'c OidcProvider, ExternalProfile)> {
+    // Only whoever deletes the flow gets to finish it, so a state can't be
+    // replayed.
+    let flow: Option<OidcFlow> = persist
+        .db()
+        .query(srql::DeleteStatement {
+            what: srql::thing(Thing::from((OIDC_FLOW_TABLE_NAME, state))),
+            output: srql::Output::Before.into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    let Some(flow) = flow else {
+        return Err(Error::ExternalLoginInvalid);
+    };
+    if flow.expires_at <= persist.clock().now() || flow.account_id.as_ref() != account_id {
+        return Err(Error::ExternalLoginInvalid);
+    }
+    let Some(provider) = config.provider(&flow.provider) else {
+        return Err(Error::ExternalLoginInvalid);
+    };
+
+    let profile = persist
+        .oidc_client()
+        .exchange(provider, code, &flow.redirect_uri, &flow.code_verifier)
+        .await?;
+    Ok((provider, profile))
+}
+
+/// Finds the identity that a provider's subject is linked as, marking it as
+/// used.
+pub async fn use_external_identity(
+    persist: &Persist,
+    provider: &str,
+    subject: &str,
+) -> Result<Option<ExternalIdentity>> {
+    let identity: Option<ExternalIdentity> = persist
+        .db()
+        .select((
+            EXTERNAL_IDENTITY_TABLE_NAME,
+            identity_id(provider, subject).as_str(),
+        ))
+        .await?;
+    let Some(identity) = identity else {
+        return Ok(None);
+    };
+
+    let mut update = vec![];
+    persist
+        .clock()
+        .now()
+        .push_field(srql::field("last_used_at"), &mut update);
+    let Some(update) = srql::obj_update_query(identity.id, update) else {
+        return Err("".into());
+    };
+    Ok(persist.db().query(update).await?.take(0)?)
+}
+
+/// Links an identity to an account. An identity can only be linked to one
+/// account, and linking it again fails with `UnavailableIdent`.
+pub async fn link_external_identity(
+    persist: &Persist,
+    account_id: &Thing,
+    provider: &str,
+    profile: ExternalProfile,
+) -> Result<ExternalIdentity> {
+    let id = identity_id(provider, &profile.subject);
+    let mut create = vec![];
+    account_id
+        .clone()
+        .push_field(srql::field("account_id"), &mut create);
+    provider
+        .to_owned()
+        .push_field(srql::field("provider"), &mut create);
+    profile
+        .subject
+        .push_field(srql::field("subject"), &mut create);
+    profile.email.push_field(srql::field("email"), &mut create);
+    profile.name.push_field(srql::field("name"), &mut create);
+    persist
+        .clock()
+        .now()
+        .push_field(srql::field("linked_at"), &mut create);
+    let create = srql::obj_create_query_id(EXTERNAL_IDENTITY_TABLE_NAME, create, id.into());
+
+    // Identities' record IDs come from their providers and subjects, so the
+    // same identity can't be linked twice, even to different accounts.
+    let created: Option<ExternalIdentity> = match persist.db().query(create).await?.take(0) {
+        Ok(created) => created,
+        Err(SrlError::Db(SrlDbError::RecordExists { .. })) => return Err(Error::UnavailableIdent),
+        Err(err) => return Err(err.into()),
+    };
+    created.ok_or_else(|| Error::InternalServerError("external identity wasn't linked".into()))
+}
+
+/// Lists the identities linked to an account, oldest first.
+pub async fn list_external_identities(
+    persist: &Persist,
+    account_id: &Thing,
+) -> Result<Vec<ExternalIdentity>> {
+    let identities = persist
+        .db()
+        .query(srql::SelectStatement {
+            expr: srql::Fields::all(),
+            what: srql::table(EXTERNAL_IDENTITY_TABLE_NAME),
+            cond: srql::Cond(
+                srql::Expression::Binary {
+                    l: srql::field("account_id").into(),
+                    o: srql::Operator::Equal,
+                    r: account_id.clone().into(),
+                }
+                .into(),
+            )
+            .into(),
+            order: Some(srql::Orders(vec![srql::Order {
+                order: srql::field("linked_at"),
+                direction: true,
+                ..Default::default()
+            }])),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    Ok(identities)
+}
+
+/// Unlinks one of an account's identities, returning it if it was there.
+pub async fn unlink_external_identity(
+    persist: &Persist,
+    account_id: &Thing,
+    id: &str,
+) -> Result<Option<ExternalIdentity>> {
+    let removed: Option<ExternalIdentity> = persist
+        .db()
+        .query(srql::DeleteStatement {
+            what: srql::thing(Thing::from((EXTERNAL_IDENTITY_TABLE_NAME, id))),
+            cond: srql::Cond(
+                srql::Expression::Binary {
+                    l: srql::field("account_id").into(),
+                    o: srql::Operator::Equal,
+                    r: account_id.clone().into(),
+                }
+                .into(),
+            )
+            .into(),
+            output: srql::Output::Before.into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    Ok(removed)
+}
+
+/// Deletes the flows that have expired without being finished. Returns how
+/// many were deleted.
+pub async fn prune_oidc_flows(persist: &Persist) -> Result<usize> {
+    let pruned: Vec<OidcFlow> = persist
+        .db()
+        .query(srql::DeleteStatement {
+            what: srql::table(OIDC_FLOW_TABLE_NAME),
+            cond: srql::Cond(
+                srql::Expression::Binary {
+                    l: srql::field("expires_at").into(),
+                    o: srql::Operator::LessThanOrEqual,
+                    r: srql::Value::Datetime(srql::Datetime(persist.clock().now())),
+                }
+                .into(),
+            )
+            .into(),
+            output: srql::Output::Before.into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    Ok(pruned.len())
+}
+
+/// The record ID of an identity, which is the same whenever the same
+/// identity is linked.
+fn identity_id(provider: &str, subject: &str) -> String {
+    let mut key = Vec::with_capacity(provider.len() + subject.len() + 1);
+    key.extend_from_slice(provider.as_bytes());
+    key.push(0);
+    key.extend_from_slice(subject.as_bytes());
+    hex::encode(digest::digest(&digest::SHA256, &key))
+}
+
+fn random_token(csrng: &SystemRandom) -> Result<String> {
+    let mut bytes = [0u8; OIDC_RANDOM_LEN];
+    csrng.fill(&mut bytes)?;
+    Ok(BASE64_URL_SAFE_NO_PAD.encode(bytes))
+}
+
+/// The S256 challenge of a PKCE code verifier.
+fn pkce_challenge(code_verifier: &str) -> String {
+    BASE64_URL_SAFE_NO_PAD.encode(digest::digest(&digest::SHA256, code_verifier.as_bytes()))
+}
+
+fn is_redirect_uri(redirect_uri: &str) -> bool {
+    redirect_uri.parse::<hyper::Uri>().is_ok_and(|uri| {
+        matches!(uri.scheme_str(), Some("http" | "https")) && uri.authority().is_some()
+    })
+}
+
+/// The URL that sends the browser to the provider to sign in.
+fn authorization_url(
+    provider: &OidcProvider,
+    redirect_uri: &str,
+    state: &str,
+    code_verifier: &str,
+) -> Result<String> {
+    let query = serde_urlencoded::to_string([
+        ("response_type", "code"),
+        ("client_id", &provider.client_id),
+        ("redirect_uri", redirect_uri),
+        ("scope", &provider.scopes.join(" ")),
+        ("state", state),
+        ("code_challenge", &pkce_challenge(code_verifier)),
+        ("code_challenge_method", "S256"),
+    ])
+    .map_err(Error::from_err)?;
+    let separator = if provider.authorization_url.contains('?') {
+        '&'
+    } else {
+        '?'
+    };
+    Ok(format!("{}{separator}{query}", provider.authorization_url))
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_pkce_challenge() {
+        // The unpadded base64url of the verifier's SHA-256 hash.
+        assert_eq!(
+            pkce_challenge("a-code-verifier"),
+            "9akY8bBtN6iIG3JmWR84SzfTNLwmIrpbXoP7Wy6d0JE"
+        );
+    }
+
+    #[test]
+    fn test_user_info() {
+        let oidc: UserInfo = serde_json::from_str(
+            r#"{"sub": "1234", "email": "a@example.com", "email_verified": true, "name": "A"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            oidc.into_profile(),
+            Some(ExternalProfile {
+                subject: "1234".into(),
+                email: Some("a@example.com".into()),
+                email_verified: true,
+                name: Some("A".into()),
+            })
+        );
+
+        let github: UserInfo =
+            serde_json::from_str(r#"{"id": 5678, "login": "octocat", "email": null}"#).unwrap();
+        assert_eq!(
+            github.into_profile(),
+            Some(ExternalProfile {
+                subject: "5678".into(),
+                email: None,
+                email_verified: false,
+                name: Some("octocat".into()),
+            })
+        );
+
+        let anonymous: UserInfo = serde_json::from_str(r#"{"name": "A"}"#).unwrap();
+        assert_eq!(anonymous.into_profile(), None);
+    }
+
+    #[test]
+    fn test_is_redirect_uri() {
+        assert!(is_redirect_uri("https://example.com/callback"));
+        assert!(is_redirect_uri("http://localhost:3000/callback"));
+        assert!(!is_redirect_uri("/callback"));
+        assert!(!is_redirect_uri("javascript:alert(1)"));
+    }
+}
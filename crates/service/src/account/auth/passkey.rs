@@ -0,0 +1,855 @@
+//! Passkeys (`WebAuthn` credentials) for logging in without a password, or in
+//! place of a code for two-factor authentication.
+//!
+//! Browsers do most of the work: the options returned here are passed to
+//! `navigator.credentials.create()` or `.get()`, and what they return is
+//! passed back. Attestation isn't checked, so the public key is taken from
+//! `getPublicKey()` rather than parsed out of the attestation object. ES256
+//! and Ed25519 keys are supported, which covers the platform authenticators
+//! and security keys that browsers create passkeys with by default.
+
+use async_graphql::{ComplexObject, InputObject, SimpleObject, ID};
+use base64::prelude::*;
+use chrono::{DateTime, Duration, Utc};
+use ring::{
+    digest,
+    rand::{SecureRandom as _, SystemRandom},
+    signature::{self, UnparsedPublicKey},
+};
+use serde::Deserialize;
+use surrealdb::sql::Thing;
+
+use crate::{persist::Persist, prelude::*};
+
+pub static PASSKEY_TABLE_NAME: &str = "passkey";
+pub static PASSKEY_CHALLENGE_TABLE_NAME: &str = "passkey_challenge";
+
+/// How long a ceremony can take before its challenge stops working.
+pub const PASSKEY_CHALLENGE_MINUTES: i64 = 5;
+
+/// How many random bytes are in a challenge.
+const PASSKEY_CHALLENGE_LEN: usize = 32;
+
+/// The longest credential ID that authenticators can create, in bytes.
+const PASSKEY_CREDENTIAL_ID_MAX_LEN: usize = 1023;
+
+/// The COSE algorithm IDs of the keys that are supported, in order of
+/// preference.
+const COSE_ES256: i32 = -7;
+const COSE_EDDSA: i32 = -8;
+
+/// The start of the DER `SubjectPublicKeyInfo` of a P-256 key, which is
+/// followed by the uncompressed point.
+const SPKI_P256_PREFIX: &[u8] = &[
+    0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x08, 0x2a,
+    0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07, 0x03, 0x42, 0x00,
+];
+
+/// The start of the DER `SubjectPublicKeyInfo` of an Ed25519 key, which is
+/// followed by the key itself.
+const SPKI_ED25519_PREFIX: &[u8] = &[
+    0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+];
+
+/// Authenticator data flags, as in the `WebAuthn` spec.
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_CREDENTIAL: u8 = 0x40;
+
+/// The instance, as the party that passkeys are created for. Passkeys only
+/// work for the origin they were created on, so the instance needs a public
+/// URL to use them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelyingParty {
+    id: String,
+    origin: String,
+}
+
+impl RelyingParty {
+    /// Works out the relying party from the instance's public URL.
+    #[must_use]
+    pub fn from_public_url(public_url: &str) -> Option<Self> {
+        let uri: hyper::Uri = public_url.parse().ok()?;
+        let scheme = uri.scheme_str()?;
+        let authority = uri.authority()?;
+        Some(Self {
+            id: authority.host().to_ascii_lowercase(),
+            origin: format!("{scheme}://{authority}").to_ascii_lowercase(),
+        })
+    }
+}
+
+/// A passkey that an account can log in with.
+#[derive(SimpleObject, Debug, Clone, Deserialize)]
+#[graphql(complex)]
+pub struct Passkey {
+    /// The passkey's credential ID is its record's ID.
+    #[graphql(skip)]
+    pub id: Thing,
+    #[graphql(skip)]
+    pub account_id: Thing,
+    /// The name the account gave the passkey, to tell it apart from others.
+    pub name: String,
+    #[graphql(skip)]
+    public_key: String,
+    #[graphql(skip)]
+    algorithm: i32,
+    #[graphql(skip)]
+    sign_count: u32,
+    /// When the passkey was added.
+    pub created_at: DateTime<Utc>,
+    /// When the passkey was last used to log in.
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+#[ComplexObject]
+impl Passkey {
+    /// The passkey's credential ID, in base64url.
+    async fn id(&self) -> ID {
+        self.id.to_gql_id()
+    }
+}
+
+/// Options for `navigator.credentials.create()`. Binary values are in
+/// base64url, and need decoding before they're passed on.
+#[derive(SimpleObject, Debug, Clone, PartialEq, Eq)]
+pub struct PasskeyCreationOptions {
+    pub challenge: String,
+    pub rp_id: String,
+    pub rp_name: String,
+    pub user_handle: String,
+    pub user_name: String,
+    /// The COSE algorithms of the keys that can be created, in order of
+    /// preference.
+    pub algorithms: Vec<i32>,
+    /// The credential IDs of the account's passkeys, which shouldn't be
+    /// created again.
+    pub exclude_credential_ids: Vec<String>,
+    pub timeout_ms: u32,
+}
+
+/// Options for `navigator.credentials.get()`. Binary values are in base64url,
+/// and need decoding before they're passed on.
+#[derive(SimpleObject, Debug, Clone, PartialEq, Eq)]
+pub struct PasskeyRequestOptions {
+    pub challenge: String,
+    pub rp_id: String,
+    /// The credential IDs of the passkeys that can be used. When empty, the
+    /// browser offers any passkey it has for the instance.
+    pub allow_credential_ids: Vec<String>,
+    /// Whether the authenticator has to verify who's using it, such as with
+    /// a PIN or fingerprint: `required` or `preferred`.
+    pub user_verification: String,
+    pub timeout_ms: u32,
+}
+
+/// A passkey that the browser created. Binary values are in base64url.
+#[derive(InputObject, Debug, Clone)]
+pub struct PasskeyRegistration {
+    /// A name for the passkey, to tell it apart from others.
+    #[graphql(validator(min_length = 1, max_length = 64))]
+    pub name: String,
+    /// The credential's `rawId`.
+    #[graphql(validator(min_length = 1, max_length = 2048))]
+    pub credential_id: String,
+    /// `response.clientDataJSON`.
+    #[graphql(validator(min_length = 1, max_length = 4096))]
+    pub client_data_json: String,
+    /// `response.getAuthenticatorData()`.
+    #[graphql(validator(min_length = 1, max_length = 8192))]
+    pub authenticator_data: String,
+    /// `response.getPublicKey()`.
+    #[graphql(validator(min_length = 1, max_length = 1024))]
+    pub public_key: String,
+    /// `response.getPublicKeyAlgorithm()`.
+    pub public_key_algorithm: i32,
+}
+
+/// A passkey assertion that the browser made. Binary values are in
+/// base64url.
+#[derive(InputObject, Debug, Clone)]
+pub struct PasskeyAssertion {
+    /// The credential's `rawId`.
+    #[graphql(validator(min_length = 1, max_length = 2048))]
+    pub credential_id: String,
+    /// `response.clientDataJSON`.
+    #[graphql(validator(min_length = 1, max_length = 4096))]
+    pub client_data_json: String,
+    /// `response.authenticatorData`.
+    #[graphql(validator(min_length = 1, max_length = 8192))]
+    pub authenticator_data: String,
+    /// `response.signature`.
+    #[graphql(validator(min_length = 1, max_length = 1024))]
+    pub signature: String,
+}
+
+/// What a challenge was issued for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Ceremony {
+    Registration,
+    Authentication,
+}
+
+impl Ceremony {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Registration => "registration",
+            Self::Authentication => "authentication",
+        }
+    }
+
+    fn client_data_type(self) -> &'static str {
+        match self {
+            Self::Registration => "webauthn.create",
+            Self::Authentication => "webauthn.get",
+        }
+    }
+}
+
+/// A challenge that has been issued and not used yet. Its record's ID is the
+/// challenge itself.
+#[derive(Debug, Clone, Deserialize)]
+struct PasskeyChallenge {
+    ceremony: Ceremony,
+    account_id: Option<Thing>,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    kind: String,
+    challenge: String,
+    origin: String,
+    #[serde(default, rename = "crossOrigin")]
+    cross_origin: bool,
+}
+
+/// The parts of authenticator data that are checked.
+#[derive(Debug)]
+struct AuthenticatorData<'a> {
+    flags: u8,
+    sign_count: u32,
+    /// The credential ID, if the authenticator data is from creating a
+    /// credential.
+    credential_id: Option<&'a [u8]>,
+}
+
+/// Starts adding a passkey to an account, returning the options to create it
+/// with.
+pub async fn begin_passkey_registration(
+    persist: &Persist,
+    csrng: &SystemRandom,
+    rp: &RelyingParty,
+    account_id: &Thing,
+    user_id: &str,
+) -> Result<PasskeyCreationOptions> {
+    let exclude_credential_ids = list_passkeys(persist, account_id)
+        .await?
+        .into_iter()
+        .map(|passkey| passkey.id.to_gql_id().0)
+        .collect();
+    let challenge = issue_challenge(
+        persist,
+        csrng,
+        Ceremony::Registration,
+        Some(account_id.clone()),
+    )
+    .await?;
+    Ok(PasskeyCreationOptions {
+        challenge,
+        rp_id: rp.id.clone(),
+        rp_name: rp.id.clone(),
+        user_handle: BASE64_URL_SAFE_NO_PAD.encode(account_id.id.to_raw()),
+        user_name: user_id.to_owned(),
+        algorithms: vec![COSE_ES256, COSE_EDDSA],
+        exclude_credential_ids,
+        timeout_ms: challenge_timeout_ms(),
+    })
+}
+
+/// Adds a passkey that the browser created to an account, if it was created
+/// for one of the account's registration challenges. Passkeys that can't be
+/// checked fail with `PasskeyInvalid`.
+pub async fn finish_passkey_registration(
+    persist: &Persist,
+    rp: &RelyingParty,
+    account_id: &Thing,
+    registration: PasskeyRegistration,
+) -> Result<Passkey> {
+    let client_data_json = decode(&registration.client_data_json)?;
+    let authenticator_data = decode(&registration.authenticator_data)?;
+    let credential_id = decode(&registration.credential_id)?;
+    if credential_id.len() > PASSKEY_CREDENTIAL_ID_MAX_LEN {
+        return Err(Error::PasskeyInvalid);
+    }
+
+    let client_data = check_client_data(rp, &client_data_json, Ceremony::Registration)?;
+    let auth_data = check_authenticator_data(rp, &authenticator_data)?;
+    if auth_data.credential_id != Some(credential_id.as_slice()) {
+        return Err(Error::PasskeyInvalid);
+    }
+    let spki = decode(&registration.public_key)?;
+    let Some(public_key) = parse_public_key(&spki, registration.public_key_algorithm) else {
+        return Err(Error::PasskeyInvalid);
+    };
+    use_challenge(
+        persist,
+        &client_data.challenge,
+        Ceremony::Registration,
+        Some(account_id),
+    )
+    .await?;
+
+    let mut create = vec![];
+    account_id
+        .clone()
+        .push_field(srql::field("account_id"), &mut create);
+    registration
+        .name
+        .push_field(srql::field("name"), &mut create);
+    BASE64_URL_SAFE_NO_PAD
+        .encode(public_key)
+        .push_field(srql::field("public_key"), &mut create);
+    registration
+        .public_key_algorithm
+        .push_field(srql::field("algorithm"), &mut create);
+    auth_data
+        .sign_count
+        .push_field(srql::field("sign_count"), &mut create);
+    persist
+        .clock()
+        .now()
+        .push_field(srql::field("created_at"), &mut create);
+    let create = srql::obj_create_query_id(
+        PASSKEY_TABLE_NAME,
+        create,
+        BASE64_URL_SAFE_NO_PAD.encode(credential_id).into(),
+    );
+
+    // Credential IDs are the passkeys' record IDs, so the same passkey can't
+    // be added twice, even to different accounts.
+    let created: Option<Passkey> = match persist.db().query(create).await?.take(0) {
+        Ok(created) => created,
+        Err(SrlError::Db(SrlDbError::RecordExists { .. })) => return Err(Error::UnavailableIdent),
+        Err(err) => return Err(err.into()),
+    };
+    created.ok_or_else(|| Error::InternalServerError("passkey wasn't created".into()))
+}
+
+/// Starts logging in with a passkey, returning the options to ask the
+/// browser for one with.
+///
+/// To log in with a passkey alone, no account is given, and the browser
+/// offers whichever passkeys it has for the instance. Those have to verify
+/// who's using them, as they stand in for a password.
+///
+/// To use a passkey as a second factor, the account that has already given
+/// its password is given, and only its passkeys can be used.
+pub async fn begin_passkey_login(
+    persist: &Persist,
+    csrng: &SystemRandom,
+    rp: &RelyingParty,
+    account_id: Option<&Thing>,
+) -> Result<PasskeyRequestOptions> {
+    let allow_credential_ids = match account_id {
+        Some(account_id) => {
+            let passkeys = list_passkeys(persist, account_id).await?;
+            if passkeys.is_empty() {
+                return Err(Error::PasskeyInvalid);
+            }
+            passkeys
+                .into_iter()
+                .map(|passkey| passkey.id.to_gql_id().0)
+                .collect()
+        }
+        None => vec![],
+    };
+    let challenge = issue_challenge(
+        persist,
+        csrng,
+        Ceremony::Authentication,
+        account_id.cloned(),
+    )
+    .await?;
+    Ok(PasskeyRequestOptions {
+        challenge,
+        rp_id: rp.id.clone(),
+        allow_credential_ids,
+        user_verification: if account_id.is_some() {
+            "preferred"
+        } else {
+            "required"
+        }
+        .to_owned(),
+        timeout_ms: challenge_timeout_ms(),
+    })
+}
+
+/// Checks a passkey assertion against one of the challenges from
+/// [`begin_passkey_login`], returning the account that the passkey belongs
+/// to. Assertions that can't be checked fail with `PasskeyInvalid`.
+pub async fn finish_passkey_login(
+    persist: &Persist,
+    rp: &RelyingParty,
+    assertion: &PasskeyAssertion,
+) -> Result<Thing> {
+    let client_data_json = decode(&assertion.client_data_json)?;
+    let authenticator_data = decode(&assertion.authenticator_data)?;
+    let signature = decode(&assertion.signature)?;
+
+    let client_data = check_client_data(rp, &client_data_json, Ceremony::Authentication)?;
+    let auth_data = check_authenticator_data(rp, &authenticator_data)?;
+    let passkey: Option<Passkey> = persist
+        .db()
+        .select((PASSKEY_TABLE_NAME, assertion.credential_id.as_str()))
+        .await?;
+    let Some(passkey) = passkey else {
+        return Err(Error::PasskeyInvalid);
+    };
+    let challenge = use_challenge(
+        persist,
+        &client_data.challenge,
+        Ceremony::Authentication,
+        None,
+    )
+    .await?;
+    let allowed = match &challenge.account_id {
+        // A second factor has to be one of the account's own passkeys.
+        Some(account_id) => *account_id == passkey.account_id,
+        // A passkey on its own has to have checked who's using it.
+        None => auth_data.flags & FLAG_USER_VERIFIED != 0,
+    };
+    if !allowed {
+        return Err(Error::PasskeyInvalid);
+    }
+
+    let mut signed = authenticator_data.clone();
+    signed.extend_from_slice(digest::digest(&digest::SHA256, &client_data_json).as_ref());
+    let algorithm: &dyn signature::VerificationAlgorithm = match passkey.algorithm {
+        COSE_ES256 => &signature::ECDSA_P256_SHA256_ASN1,
+        COSE_EDDSA => &signature::ED25519,
+        _ => return Err(Error::PasskeyInvalid),
+    };
+    let public_key = decode(&passkey.public_key)?;
+    if UnparsedPublicKey::new(algorithm, public_key)
+        .verify(&signed, &signature)
+        .is_err()
+    {
+        return Err(Error::PasskeyInvalid);
+    }
+
+    // Authenticators that count signatures only count up, so a count that
+    // hasn't gone up means the passkey has been copied. Only whoever moves
+    // the count forward gets to log in, so racing requests can't both.
+    let mut update = vec![];
+    auth_data
+        .sign_count
+        .push_field(srql::field("sign_count"), &mut update);
+    persist
+        .clock()
+        .now()
+        .push_field(srql::field("last_used_at"), &mut update);
+    let cond = (auth_data.sign_count > 0 || passkey.sign_count > 0).then(|| {
+        srql::Cond(
+            srql::Expression::Binary {
+                l: srql::field("sign_count").into(),
+                o: srql::Operator::LessThan,
+                r: auth_data.sign_count.into(),
+            }
+            .into(),
+        )
+    });
+    let used: Option<Passkey> = persist
+        .db()
+        .query(srql::UpdateStatement {
+            what: srql::thing(passkey.id),
+            data: srql::Data::SetExpression(update).into(),
+            cond,
+            output: srql::Output::After.into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    match used {
+        Some(used) => Ok(used.account_id),
+        None => Err(Error::PasskeyInvalid),
+    }
+}
+
+/// Lists an account's passkeys, oldest first.
+pub async fn list_passkeys(persist: &Persist, account_id: &Thing) -> Result<Vec<Passkey>> {
+    let passkeys = persist
+        .db()
+        .query(srql::SelectStatement {
+            expr: srql::Fields::all(),
+            what: srql::table(PASSKEY_TABLE_NAME),
+            cond: srql::Cond(
+                srql::Expression::Binary {
+                    l: srql::field("account_id").into(),
+                    o: srql::Operator::Equal,
+                    r: account_id.clone().into(),
+                }
+                .into(),
+            )
+            .into(),
+            order: Some(srql::Orders(vec![srql::Order {
+                order: srql::field("created_at"),
+                direction: true,
+                ..Default::default()
+            }])),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    Ok(passkeys)
+}
+
+/// Removes one of an account's passkeys, returning it if it was there.
+pub async fn remove_passkey(
+    persist: &Persist,
+    account_id: &Thing,
+    credential_id: &str,
+) -> Result<Option<Passkey>> {
+    let removed: Option<Passkey> = persist
+        .db()
+        .query(srql::DeleteStatement {
+            what: srql::thing(Thing::from((PASSKEY_TABLE_NAME, credential_id))),
+            cond: srql::Cond(
+                srql::Expression::Binary {
+                    l: srql::field("account_id").into(),
+                    o: srql::Operator::Equal,
+                    r: account_id.clone().into(),
+                }
+                .into(),
+            )
+            .into(),
+            output: srql::Output::Before.into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    Ok(removed)
+}
+
+/// Deletes the challenges that have expired without being used. Returns how
+/// many were deleted.
+pub async fn prune_passkey_challenges(persist: &Persist) -> Result<usize> {
+    let pruned: Vec<PasskeyChallenge> = persist
+        .db()
+        .query(srql::DeleteStatement {
+            what: srql::table(PASSKEY_CHALLENGE_TABLE_NAME),
+            cond: srql::Cond(
+                srql::Expression::Binary {
+                    l: srql::field("expires_at").into(),
+                    o: srql::Operator::LessThanOrEqual,
+                    r: srql::Value::Datetime(srql::Datetime(persist.clock().now())),
+                }
+                .into(),
+            )
+            .into(),
+            output: srql::Output::Before.into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    Ok(pruned.len())
+}
+
+async fn issue_challenge(
+    persist: &Persist,
+    csrng: &SystemRandom,
+    ceremony: Ceremony,
+    account_id: Option<Thing>,
+) -> Result<String> {
+    let mut challenge = [0u8; PASSKEY_CHALLENGE_LEN];
+    csrng.fill(&mut challenge)?;
+    let challenge = BASE64_URL_SAFE_NO_PAD.encode(challenge);
+
+    let mut create = vec![(
+        srql::field("ceremony"),
+        srql::Operator::Equal,
+        srql::string(ceremony.as_str()).into(),
+    )];
+    account_id.push_field(srql::field("account_id"), &mut create);
+    (persist.clock().now() + Duration::minutes(PASSKEY_CHALLENGE_MINUTES))
+        .push_field(srql::field("expires_at"), &mut create);
+    let mut create = srql::obj_create_query_id(
+        PASSKEY_CHALLENGE_TABLE_NAME,
+        create,
+        challenge.clone().into(),
+    );
+    create.output = srql::Output::None.into();
+    persist.db().query(create).await?.check()?;
+    Ok(challenge)
+}
+
+/// Uses up a challenge, which has to be for the same ceremony and, if one is
+/// given, the same account.
+async fn use_challenge(
+    persist: &Persist,
+    challenge: &str,
+    ceremony: Ceremony,
+    account_id: Option<&Thing>,
+) -> Result<PasskeyChallenge> {
+    // Only whoever deletes the challenge gets to use it, so an assertion
+    // can't be replayed.
+    let used: Option<PasskeyChallenge> = persist
+        .db()
+        .query(srql::DeleteStatement {
+            what: srql::thing(Thing::from((PASSKEY_CHALLENGE_TABLE_NAME, challenge))),
+            output: srql::Output::Before.into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    let Some(used) = used else {
+        return Err(Error::PasskeyInvalid);
+    };
+    if used.ceremony != ceremony
+        || used.expires_at <= persist.clock().now()
+        || account_id.is_some_and(|account_id| used.account_id.as_ref() != Some(account_id))
+    {
+        return Err(Error::PasskeyInvalid);
+    }
+    Ok(used)
+}
+
+fn challenge_timeout_ms() -> u32 {
+    // This is a handful of minutes, which fits easily.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let timeout = Duration::minutes(PASSKEY_CHALLENGE_MINUTES).num_milliseconds() as u32;
+    timeout
+}
+
+fn decode(value: &str) -> Result<Vec<u8>> {
+    BASE64_URL_SAFE_NO_PAD
+        .decode(value.trim_end_matches('='))
+        .map_err(|_| Error::PasskeyInvalid)
+}
+
+/// Checks that the browser made the client data for this ceremony, on the
+/// instance.
+fn check_client_data(
+    rp: &RelyingParty,
+    client_data_json: &[u8],
+    ceremony: Ceremony,
+) -> Result<ClientData> {
+    let Ok(client_data) = serde_json::from_slice::<ClientData>(client_data_json) else {
+        return Err(Error::PasskeyInvalid);
+    };
+    if client_data.kind != ceremony.client_data_type()
+        || client_data.origin != rp.origin
+        || client_data.cross_origin
+    {
+        return Err(Error::PasskeyInvalid);
+    }
+    Ok(client_data)
+}
+
+/// Checks that the authenticator data was made for the instance, with
+/// someone there to make it.
+fn check_authenticator_data<'a>(
+    rp: &RelyingParty,
+    authenticator_data: &'a [u8],
+) -> Result<AuthenticatorData<'a>> {
+    parse_authenticator_data(authenticator_data)
+        .filter(|(rp_id_hash, auth_data)| {
+            *rp_id_hash == digest::digest(&digest::SHA256, rp.id.as_bytes()).as_ref()
+                && auth_data.flags & FLAG_USER_PRESENT != 0
+        })
+        .map(|(_, auth_data)| auth_data)
+        .ok_or(Error::PasskeyInvalid)
+}
+
+/// Splits authenticator data into the hash of its relying party's ID and the
+/// rest. The credential's public key that can follow the credential ID is
+/// COSE, and isn't parsed.
+fn parse_authenticator_data(data: &[u8]) -> Option<(&[u8], AuthenticatorData<'_>)> {
+    let rp_id_hash = data.get(..32)?;
+    let flags = *data.get(32)?;
+    let sign_count = u32::from_be_bytes(data.get(33..37)?.try_into().ok()?);
+    let credential_id = if flags & FLAG_ATTESTED_CREDENTIAL == 0 {
+        None
+    } else {
+        // The credential ID comes after the authenticator's 16 byte AAGUID
+        // and its own length.
+        let len = usize::from(u16::from_be_bytes(data.get(53..55)?.try_into().ok()?));
+        Some(data.get(55..55 + len)?)
+    };
+    Some((
+        rp_id_hash,
+        AuthenticatorData {
+            flags,
+            sign_count,
+            credential_id,
+        },
+    ))
+}
+
+/// Takes the key out of a DER `SubjectPublicKeyInfo`, in the form `ring`
+/// checks signatures with.
+fn parse_public_key(spki: &[u8], algorithm: i32) -> Option<&[u8]> {
+    let (prefix, len) = match algorithm {
+        COSE_ES256 => (SPKI_P256_PREFIX, 65),
+        COSE_EDDSA => (SPKI_ED25519_PREFIX, 32),
+        _ => return None,
+    };
+    spki.strip_prefix(prefix).filter(|key| key.len() == len)
+}
+
+#[cfg(test)]
+pub mod testing {
+    use base64::prelude::*;
+    use ring::{
+        digest,
+        rand::SystemRandom,
+        signature::{EcdsaKeyPair, KeyPair as _, ECDSA_P256_SHA256_ASN1_SIGNING},
+    };
+    use serde_json::json;
+
+    use super::{
+        PasskeyAssertion, PasskeyRegistration, COSE_ES256, FLAG_ATTESTED_CREDENTIAL,
+        FLAG_USER_PRESENT, FLAG_USER_VERIFIED, SPKI_P256_PREFIX,
+    };
+
+    pub const TEST_PUBLIC_URL: &str = "https://plazer.test";
+
+    /// Plays an authenticator with a single ES256 passkey for
+    /// `TEST_PUBLIC_URL`.
+    pub struct TestAuthenticator {
+        key: EcdsaKeyPair,
+        pub credential_id: Vec<u8>,
+        pub sign_count: u32,
+        pub user_verified: bool,
+    }
+
+    impl TestAuthenticator {
+        pub fn new(credential_id: &[u8]) -> Self {
+            let csrng = SystemRandom::new();
+            let pkcs8 =
+                EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &csrng).unwrap();
+            Self {
+                key: EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref())
+                    .unwrap(),
+                credential_id: credential_id.to_vec(),
+                sign_count: 0,
+                user_verified: true,
+            }
+        }
+
+        pub fn credential_id(&self) -> String {
+            BASE64_URL_SAFE_NO_PAD.encode(&self.credential_id)
+        }
+
+        /// Creates the passkey, as `navigator.credentials.create()` would.
+        pub fn create(&self, challenge: &str) -> PasskeyRegistration {
+            let mut auth_data = self.auth_data(FLAG_ATTESTED_CREDENTIAL);
+            auth_data.extend_from_slice(&[0; 16]);
+            auth_data.extend_from_slice(
+                &u16::try_from(self.credential_id.len())
+                    .unwrap()
+                    .to_be_bytes(),
+            );
+            auth_data.extend_from_slice(&self.credential_id);
+            let mut spki = SPKI_P256_PREFIX.to_vec();
+            spki.extend_from_slice(self.key.public_key().as_ref());
+            PasskeyRegistration {
+                name: "Test".into(),
+                credential_id: self.credential_id(),
+                client_data_json: client_data("webauthn.create", challenge),
+                authenticator_data: BASE64_URL_SAFE_NO_PAD.encode(auth_data),
+                public_key: BASE64_URL_SAFE_NO_PAD.encode(spki),
+                public_key_algorithm: COSE_ES256,
+            }
+        }
+
+        /// Makes an assertion, as `navigator.credentials.get()` would.
+        pub fn get(&mut self, challenge: &str) -> PasskeyAssertion {
+            self.sign_count += 1;
+            let auth_data = self.auth_data(0);
+            let client_data_json = client_data("webauthn.get", challenge);
+            let mut signed = auth_data.clone();
+            signed.extend_from_slice(
+                digest::digest(
+                    &digest::SHA256,
+                    &BASE64_URL_SAFE_NO_PAD.decode(&client_data_json).unwrap(),
+                )
+                .as_ref(),
+            );
+            let signature = self.key.sign(&SystemRandom::new(), &signed).unwrap();
+            PasskeyAssertion {
+                credential_id: self.credential_id(),
+                client_data_json,
+                authenticator_data: BASE64_URL_SAFE_NO_PAD.encode(auth_data),
+                signature: BASE64_URL_SAFE_NO_PAD.encode(signature),
+            }
+        }
+
+        fn auth_data(&self, flags: u8) -> Vec<u8> {
+            let mut flags = flags | FLAG_USER_PRESENT;
+            if self.user_verified {
+                flags |= FLAG_USER_VERIFIED;
+            }
+            let mut auth_data = digest::digest(&digest::SHA256, b"plazer.test")
+                .as_ref()
+                .to_vec();
+            auth_data.push(flags);
+            auth_data.extend_from_slice(&self.sign_count.to_be_bytes());
+            auth_data
+        }
+    }
+
+    fn client_data(kind: &str, challenge: &str) -> String {
+        let client_data = json!({
+            "type": kind,
+            "challenge": challenge,
+            "origin": TEST_PUBLIC_URL,
+            "crossOrigin": false,
+        });
+        BASE64_URL_SAFE_NO_PAD.encode(client_data.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relying_party() {
+        let rp = RelyingParty::from_public_url("https://Plazer.example:8443/app").unwrap();
+        assert_eq!(rp.id, "plazer.example");
+        assert_eq!(rp.origin, "https://plazer.example:8443");
+        assert_eq!(RelyingParty::from_public_url("/relative"), None);
+    }
+
+    #[test]
+    fn test_parse_authenticator_data() {
+        let mut data = vec![7; 32];
+        data.push(FLAG_USER_PRESENT | FLAG_ATTESTED_CREDENTIAL);
+        data.extend_from_slice(&5u32.to_be_bytes());
+        data.extend_from_slice(&[0; 16]);
+        data.extend_from_slice(&3u16.to_be_bytes());
+        data.extend_from_slice(b"abc");
+        let (rp_id_hash, auth_data) = parse_authenticator_data(&data).unwrap();
+        assert_eq!(rp_id_hash, &[7; 32]);
+        assert_eq!(auth_data.sign_count, 5);
+        assert_eq!(auth_data.credential_id, Some(&b"abc"[..]));
+
+        // The credential ID can't run past the end.
+        data.truncate(data.len() - 1);
+        assert!(parse_authenticator_data(&data).is_none());
+        assert!(parse_authenticator_data(&data[..36]).is_none());
+    }
+
+    #[test]
+    fn test_parse_public_key() {
+        let mut spki = SPKI_ED25519_PREFIX.to_vec();
+        spki.extend_from_slice(&[1; 32]);
+        assert_eq!(parse_public_key(&spki, COSE_EDDSA), Some(&[1; 32][..]));
+        assert_eq!(parse_public_key(&spki, COSE_ES256), None);
+        assert_eq!(parse_public_key(&spki[..40], COSE_EDDSA), None);
+        assert_eq!(parse_public_key(&spki, -257), None);
+    }
+}
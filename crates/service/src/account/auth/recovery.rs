@@ -0,0 +1,396 @@
+//! Recovery through trusted contacts, for accounts that have lost both their
+//! password and their second factor.
+//!
+//! An account picks some accounts it trusts and how many of them have to
+//! agree. Whoever is recovering it asks for a recovery and gets a token,
+//! which is exchanged for a password reset once enough of the contacts have
+//! approved it within the window. Each contact should check with the account's
+//! owner some other way before approving.
+
+use async_graphql::{ComplexObject, SimpleObject, ID};
+use chrono::{DateTime, Duration, Utc};
+use ring::rand::SystemRandom;
+use serde::Deserialize;
+use surrealdb::sql::Thing;
+
+use super::{generate_secret, hash_secret, parse_opaque_token};
+use crate::{persist::Persist, prelude::*};
+
+pub static RECOVERY_CONTACTS_TABLE_NAME: &str = "recovery_contacts";
+pub static RECOVERY_REQUEST_TABLE_NAME: &str = "recovery_request";
+
+/// The most trusted contacts an account can have.
+pub const MAX_RECOVERY_CONTACTS: usize = 10;
+
+/// How long the contacts have to approve a recovery, after which it has to
+/// be asked for again.
+pub const RECOVERY_WINDOW_HOURS: i64 = 72;
+
+/// How many recoveries of an account can be waiting for approval at once, so
+/// that contacts can't be flooded with them.
+pub const MAX_PENDING_RECOVERIES: usize = 3;
+
+/// The accounts that can approve recovering an account, and how many of them
+/// need to.
+#[derive(SimpleObject, Debug, Clone, PartialEq, Eq, Deserialize)]
+#[graphql(complex)]
+pub struct RecoveryContacts {
+    #[graphql(skip)]
+    pub account_id: Thing,
+    #[graphql(skip)]
+    pub contact_ids: Vec<Thing>,
+
+    /// How many of the contacts need to approve a recovery.
+    pub threshold: u32,
+    /// When the contacts were last changed.
+    pub updated_at: DateTime<Utc>,
+}
+
+#[ComplexObject]
+impl RecoveryContacts {
+    /// The IDs of the trusted contacts.
+    async fn contact_ids(&self) -> Vec<ID> {
+        self.contact_ids.iter().map(ToGqlId::to_gql_id).collect()
+    }
+}
+
+/// A recovery of an account that is waiting for its trusted contacts to
+/// approve it. Only a hash of its token's secret is kept.
+#[derive(SimpleObject, Debug, Clone, PartialEq, Eq, Deserialize)]
+#[graphql(complex)]
+pub struct RecoveryRequest {
+    #[graphql(skip)]
+    pub id: Thing,
+    #[graphql(skip)]
+    pub account_id: Thing,
+    #[graphql(skip)]
+    secret_hash: String,
+    /// The contacts that the account had when the recovery was asked for.
+    #[graphql(skip)]
+    pub contact_ids: Vec<Thing>,
+    #[graphql(skip)]
+    #[serde(default)]
+    pub approved_ids: Vec<Thing>,
+
+    /// How many of the contacts need to approve the recovery.
+    pub threshold: u32,
+    /// When the recovery was asked for.
+    pub issued_at: DateTime<Utc>,
+    /// When the recovery stops being able to be approved or used.
+    pub expires_at: DateTime<Utc>,
+}
+
+#[ComplexObject]
+impl RecoveryRequest {
+    /// The recovery's unique ID.
+    async fn id(&self) -> ID {
+        self.id.to_gql_id()
+    }
+
+    /// The ID of the account being recovered.
+    async fn account_id(&self) -> ID {
+        self.account_id.to_gql_id()
+    }
+
+    /// How many of the contacts have approved the recovery so far.
+    async fn approvals(&self) -> usize {
+        self.approved_ids.len()
+    }
+
+    /// Whether enough contacts have approved the recovery for it to be used.
+    async fn approved(&self) -> bool {
+        self.is_approved()
+    }
+}
+
+impl RecoveryRequest {
+    pub fn is_approved(&self) -> bool {
+        self.approved_ids.len() >= self.threshold as usize
+    }
+}
+
+/// A recovery that has just been asked for, along with its token.
+#[derive(SimpleObject, Debug, Clone)]
+pub struct StartedRecovery {
+    pub recovery: RecoveryRequest,
+    /// The token to check on the recovery with, and to use it with once
+    /// it's been approved. This is only shown once, as only a hash of it is
+    /// kept.
+    pub token: String,
+}
+
+fn contacts_thing(account_id: &Thing) -> Thing {
+    Thing::from((
+        RECOVERY_CONTACTS_TABLE_NAME,
+        account_id.id.to_raw().as_str(),
+    ))
+}
+
+fn account_cond(account_id: &Thing) -> srql::Cond {
+    srql::Cond(
+        srql::Expression::Binary {
+            l: srql::field("account_id").into(),
+            o: srql::Operator::Equal,
+            r: account_id.clone().into(),
+        }
+        .into(),
+    )
+}
+
+/// Gets the trusted contacts an account has chosen, if it has.
+pub async fn get_recovery_contacts(
+    persist: &Persist,
+    account_id: &Thing,
+) -> Result<Option<RecoveryContacts>> {
+    Ok(persist.db().select(contacts_thing(account_id)).await?)
+}
+
+/// Replaces an account's trusted contacts, or removes them if none are
+/// given. Recoveries that were waiting for approval are cancelled, as they
+/// were asked of the old contacts.
+pub async fn set_recovery_contacts(
+    persist: &Persist,
+    account_id: &Thing,
+    contact_ids: Vec<Thing>,
+    threshold: u32,
+) -> Result<Option<RecoveryContacts>> {
+    let thing = contacts_thing(account_id);
+    let mut query = vec![
+        srql::trans_begin(),
+        srql::Statement::Delete(srql::DeleteStatement {
+            what: srql::table(RECOVERY_REQUEST_TABLE_NAME),
+            cond: account_cond(account_id).into(),
+            output: srql::Output::None.into(),
+            ..Default::default()
+        }),
+        srql::Statement::Delete(srql::DeleteStatement {
+            what: srql::thing(thing.clone()),
+            output: srql::Output::None.into(),
+            ..Default::default()
+        }),
+    ];
+    if !contact_ids.is_empty() {
+        let mut create = vec![];
+        account_id
+            .clone()
+            .push_field(srql::field("account_id"), &mut create);
+        create.push((
+            srql::field("contact_ids"),
+            srql::Operator::Equal,
+            srql::array(
+                contact_ids
+                    .into_iter()
+                    .map(srql::Value::from)
+                    .collect::<Vec<_>>(),
+            ),
+        ));
+        threshold.push_field(srql::field("threshold"), &mut create);
+        query.push(srql::Statement::Create(srql::obj_create_query_id(
+            RECOVERY_CONTACTS_TABLE_NAME,
+            create,
+            thing.id,
+        )));
+    }
+    query.push(srql::trans_end());
+
+    persist.db().query(srql::query(query)).await?.check()?;
+    get_recovery_contacts(persist, account_id).await
+}
+
+/// Lists the recoveries of an account that haven't expired, oldest first.
+pub async fn pending_recovery_requests(
+    persist: &Persist,
+    account_id: &Thing,
+) -> Result<Vec<RecoveryRequest>> {
+    let pending: Vec<RecoveryRequest> = persist
+        .db()
+        .query(srql::SelectStatement {
+            expr: srql::Fields::all(),
+            what: srql::table(RECOVERY_REQUEST_TABLE_NAME),
+            cond: account_cond(account_id).into(),
+            order: srql::Orders(vec![srql::Order {
+                order: srql::field("id"),
+                direction: true,
+                ..Default::default()
+            }])
+            .into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    let now = persist.clock().now();
+    Ok(pending
+        .into_iter()
+        .filter(|request| request.expires_at > now)
+        .collect())
+}
+
+/// Starts recovering an account, asking its trusted contacts to approve it.
+/// Returns the recovery along with the token that it can be used with once
+/// it's been approved.
+pub async fn issue_recovery_request(
+    persist: &Persist,
+    csrng: &SystemRandom,
+    contacts: &RecoveryContacts,
+) -> Result<(RecoveryRequest, String)> {
+    let secret = generate_secret(csrng)?;
+    let now = persist.clock().now();
+
+    let id = persist.ids().next_id();
+    let mut create = vec![];
+    contacts
+        .account_id
+        .clone()
+        .push_field(srql::field("account_id"), &mut create);
+    hash_secret(&secret).push_field(srql::field("secret_hash"), &mut create);
+    create.push((
+        srql::field("contact_ids"),
+        srql::Operator::Equal,
+        srql::array(
+            contacts
+                .contact_ids
+                .iter()
+                .cloned()
+                .map(srql::Value::from)
+                .collect::<Vec<_>>(),
+        ),
+    ));
+    create.push((
+        srql::field("approved_ids"),
+        srql::Operator::Equal,
+        srql::array(Vec::<srql::Value>::new()),
+    ));
+    contacts
+        .threshold
+        .push_field(srql::field("threshold"), &mut create);
+    now.push_field(srql::field("issued_at"), &mut create);
+    (now + Duration::hours(RECOVERY_WINDOW_HOURS))
+        .push_field(srql::field("expires_at"), &mut create);
+
+    let request: Option<RecoveryRequest> = persist
+        .db()
+        .query(srql::obj_create_query_id(
+            RECOVERY_REQUEST_TABLE_NAME,
+            create,
+            id.clone().into(),
+        ))
+        .await?
+        .take(0)?;
+    match request {
+        Some(request) => Ok((request, format!("{id}.{secret}"))),
+        None => Err(Error::UnavailableIdent),
+    }
+}
+
+/// Gets a recovery that hasn't expired by its ID.
+pub async fn get_recovery_request(persist: &Persist, id: &str) -> Result<Option<RecoveryRequest>> {
+    let request: Option<RecoveryRequest> = persist
+        .db()
+        .select((RECOVERY_REQUEST_TABLE_NAME, id))
+        .await?;
+    let now = persist.clock().now();
+    Ok(request.filter(|request| request.expires_at > now))
+}
+
+/// Gets the recovery that a token was issued for. Tokens that are unknown or
+/// expired fail with `RecoveryInvalid`.
+pub async fn find_recovery_request(persist: &Persist, token: &str) -> Result<RecoveryRequest> {
+    let Some((id, secret)) = parse_opaque_token(token) else {
+        return Err(Error::RecoveryInvalid);
+    };
+    get_recovery_request(persist, id)
+        .await?
+        .filter(|request| request.secret_hash == hash_secret(secret))
+        .ok_or(Error::RecoveryInvalid)
+}
+
+/// Records a trusted contact's approval of a recovery, returning it. Approving
+/// the same recovery again has no effect.
+pub async fn approve_recovery_request(
+    persist: &Persist,
+    request: RecoveryRequest,
+    contact_id: &Thing,
+) -> Result<RecoveryRequest> {
+    if !request.contact_ids.contains(contact_id) {
+        return Err(Error::Unauthorized);
+    }
+    if request.approved_ids.contains(contact_id) {
+        return Ok(request);
+    }
+
+    let approved: Option<RecoveryRequest> = persist
+        .db()
+        .query(srql::UpdateStatement {
+            what: srql::thing(request.id),
+            data: srql::Data::SetExpression(vec![(
+                srql::field("approved_ids"),
+                srql::Operator::Inc,
+                contact_id.clone().into(),
+            )])
+            .into(),
+            cond: srql::Cond(
+                srql::Expression::Binary {
+                    l: srql::field("approved_ids").into(),
+                    o: srql::Operator::NotContain,
+                    r: contact_id.clone().into(),
+                }
+                .into(),
+            )
+            .into(),
+            output: srql::Output::After.into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    approved.ok_or(Error::RecoveryInvalid)
+}
+
+/// Uses up an approved recovery, returning the account it was for. Tokens
+/// that are unknown, expired or already used fail with `RecoveryInvalid`, and
+/// those that haven't been approved by enough contacts yet fail with
+/// `RecoveryNotApproved`.
+pub async fn use_recovery_request(persist: &Persist, token: &str) -> Result<Thing> {
+    let request = find_recovery_request(persist, token).await?;
+    if !request.is_approved() {
+        return Err(Error::RecoveryNotApproved);
+    }
+
+    // Only whoever deletes the recovery gets to use it, so two requests
+    // racing with the same token can't both succeed.
+    let used: Vec<RecoveryRequest> = persist
+        .db()
+        .query(srql::DeleteStatement {
+            what: srql::thing(request.id),
+            output: srql::Output::Before.into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    match used.into_iter().next() {
+        Some(used) => Ok(used.account_id),
+        None => Err(Error::RecoveryInvalid),
+    }
+}
+
+/// Deletes the recoveries that have expired. Returns how many were deleted.
+pub async fn prune_recovery_requests(persist: &Persist) -> Result<usize> {
+    let pruned: Vec<RecoveryRequest> = persist
+        .db()
+        .query(srql::DeleteStatement {
+            what: srql::table(RECOVERY_REQUEST_TABLE_NAME),
+            cond: srql::Cond(
+                srql::Expression::Binary {
+                    l: srql::field("expires_at").into(),
+                    o: srql::Operator::LessThanOrEqual,
+                    r: srql::Value::Datetime(srql::Datetime(persist.clock().now())),
+                }
+                .into(),
+            )
+            .into(),
+            output: srql::Output::Before.into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    Ok(pruned.len())
+}
@@ -0,0 +1,173 @@
+use chrono::{DateTime, Duration, Utc};
+use ring::rand::SystemRandom;
+use serde::Deserialize;
+use surrealdb::sql::Thing;
+use tracing::warn;
+
+use super::{generate_secret, hash_secret, parse_opaque_token};
+use crate::{persist::Persist, prelude::*};
+
+pub static REFRESH_TOKEN_TABLE_NAME: &str = "refresh_token";
+
+/// How long a refresh token can be used for, if its session doesn't end
+/// first. Each use issues a new token, so a session that's in use doesn't
+/// run out.
+const REFRESH_TOKEN_DAYS: i64 = 30;
+
+/// A refresh token that has been issued. Only a hash of its secret is kept,
+/// so the table can't be used to sign in.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RefreshToken {
+    pub id: Thing,
+    pub account_id: Thing,
+    /// The session the token was issued for, if there was one.
+    pub session_id: Option<Thing>,
+    /// The first token of the chain that this one was rotated from. Every
+    /// token in the chain is revoked if a used one is presented again.
+    pub family_id: Thing,
+    secret_hash: String,
+    pub issued_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Issues an opaque refresh token and stores it. The token expires by
+/// `expires_by` if it's given, so that it doesn't outlive its session.
+///
+/// A token issued in exchange for another continues its family, otherwise
+/// it starts a new one.
+pub async fn issue_refresh_token(
+    persist: &Persist,
+    csrng: &SystemRandom,
+    account_id: Thing,
+    session_id: Option<Thing>,
+    family_id: Option<Thing>,
+    expires_by: Option<DateTime<Utc>>,
+) -> Result<String> {
+    let secret = generate_secret(csrng)?;
+
+    let now = persist.clock().now();
+    let mut expires_at = now + Duration::days(REFRESH_TOKEN_DAYS);
+    if let Some(expires_by) = expires_by {
+        expires_at = expires_at.min(expires_by);
+    }
+
+    let id = persist.ids().next_id();
+    let family_id =
+        family_id.unwrap_or_else(|| Thing::from((REFRESH_TOKEN_TABLE_NAME, id.as_str())));
+    let mut create = vec![];
+    account_id.push_field(srql::field("account_id"), &mut create);
+    session_id.push_field(srql::field("session_id"), &mut create);
+    family_id.push_field(srql::field("family_id"), &mut create);
+    hash_secret(&secret).push_field(srql::field("secret_hash"), &mut create);
+    now.push_field(srql::field("issued_at"), &mut create);
+    expires_at.push_field(srql::field("expires_at"), &mut create);
+    let mut create = srql::obj_create_query_id(REFRESH_TOKEN_TABLE_NAME, create, id.clone().into());
+    create.output = srql::Output::None.into();
+    persist.db().query(create).await?.check()?;
+
+    Ok(format!("{id}.{secret}"))
+}
+
+/// Whether a refresh token is an opaque one, rather than a JWT from before
+/// they were stored.
+#[must_use]
+pub fn is_opaque_refresh_token(token: &str) -> bool {
+    parse_opaque_token(token).is_some()
+}
+
+/// Exchanges a refresh token, so it can't be used again. The returned record
+/// says who it was issued to; the caller issues the new token.
+///
+/// If a token that has already been used is presented again, it has been
+/// copied, so every token in its family is revoked and whoever is using the
+/// session has to sign in again.
+pub async fn use_refresh_token(persist: &Persist, token: &str) -> Result<RefreshToken> {
+    let Some((id, secret)) = parse_opaque_token(token) else {
+        return Err(Error::CredentialsInvalid);
+    };
+    let stored: Option<RefreshToken> = persist.db().select((REFRESH_TOKEN_TABLE_NAME, id)).await?;
+    let Some(stored) = stored.filter(|stored| stored.secret_hash == hash_secret(secret)) else {
+        return Err(Error::CredentialsInvalid);
+    };
+    let now = persist.clock().now();
+    if stored.expires_at <= now {
+        return Err(Error::CredentialsInvalid);
+    }
+
+    // A token that has been exchanged can't be used again. Only marking it
+    // as used if it hasn't been means that two requests racing with the same
+    // token can't both succeed.
+    let mut update = vec![];
+    now.push_field(srql::field("used_at"), &mut update);
+    let used: Option<RefreshToken> = persist
+        .db()
+        .query(srql::UpdateStatement {
+            what: srql::thing(stored.id.clone()),
+            data: srql::Data::SetExpression(update).into(),
+            cond: srql::Cond(
+                srql::Expression::Binary {
+                    l: srql::field("used_at").into(),
+                    o: srql::Operator::Equal,
+                    r: srql::Value::None,
+                }
+                .into(),
+            )
+            .into(),
+            output: srql::Output::After.into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    let Some(used) = used else {
+        warn!(family_id = %stored.family_id, "Refresh token reused, revoking its family");
+        revoke_family(persist, &stored.family_id).await?;
+        return Err(Error::CredentialsInvalid);
+    };
+    Ok(used)
+}
+
+/// Deletes the refresh tokens that have expired. Returns how many were
+/// deleted.
+pub async fn prune_refresh_tokens(persist: &Persist) -> Result<usize> {
+    let pruned: Vec<RefreshToken> = persist
+        .db()
+        .query(srql::DeleteStatement {
+            what: srql::table(REFRESH_TOKEN_TABLE_NAME),
+            cond: srql::Cond(
+                srql::Expression::Binary {
+                    l: srql::field("expires_at").into(),
+                    o: srql::Operator::LessThanOrEqual,
+                    r: srql::Value::Datetime(srql::Datetime(persist.clock().now())),
+                }
+                .into(),
+            )
+            .into(),
+            output: srql::Output::Before.into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    Ok(pruned.len())
+}
+
+async fn revoke_family(persist: &Persist, family_id: &Thing) -> Result<()> {
+    persist
+        .db()
+        .query(srql::DeleteStatement {
+            what: srql::table(REFRESH_TOKEN_TABLE_NAME),
+            cond: srql::Cond(
+                srql::Expression::Binary {
+                    l: srql::field("family_id").into(),
+                    o: srql::Operator::Equal,
+                    r: family_id.clone().into(),
+                }
+                .into(),
+            )
+            .into(),
+            output: srql::Output::None.into(),
+            ..Default::default()
+        })
+        .await?
+        .check()?;
+    Ok(())
+}
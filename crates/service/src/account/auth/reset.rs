@@ -0,0 +1,129 @@
+use chrono::{DateTime, Duration, Utc};
+use ring::rand::SystemRandom;
+use serde::Deserialize;
+use surrealdb::sql::Thing;
+
+use super::{generate_secret, hash_secret, parse_opaque_token};
+use crate::{persist::Persist, prelude::*};
+
+pub static PASSWORD_RESET_TABLE_NAME: &str = "password_reset";
+
+/// How long a password reset token can be used for.
+pub const PASSWORD_RESET_MINUTES: i64 = 60;
+
+/// A password reset token that has been issued and not used yet. Only a
+/// hash of its secret is kept.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PasswordReset {
+    pub id: Thing,
+    pub account_id: Thing,
+    secret_hash: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Issues a token that can be used once to choose a new password for the
+/// account, and stores it. Any the account was issued before stop working.
+pub async fn issue_password_reset(
+    persist: &Persist,
+    csrng: &SystemRandom,
+    account_id: Thing,
+) -> Result<String> {
+    let secret = generate_secret(csrng)?;
+    let now = persist.clock().now();
+
+    let id = persist.ids().next_id();
+    let mut create = vec![];
+    account_id
+        .clone()
+        .push_field(srql::field("account_id"), &mut create);
+    hash_secret(&secret).push_field(srql::field("secret_hash"), &mut create);
+    now.push_field(srql::field("issued_at"), &mut create);
+    (now + Duration::minutes(PASSWORD_RESET_MINUTES))
+        .push_field(srql::field("expires_at"), &mut create);
+    let mut create =
+        srql::obj_create_query_id(PASSWORD_RESET_TABLE_NAME, create, id.clone().into());
+    create.output = srql::Output::None.into();
+
+    persist
+        .db()
+        .query(srql::query([
+            srql::trans_begin(),
+            srql::Statement::Delete(srql::DeleteStatement {
+                what: srql::table(PASSWORD_RESET_TABLE_NAME),
+                cond: srql::Cond(
+                    srql::Expression::Binary {
+                        l: srql::field("account_id").into(),
+                        o: srql::Operator::Equal,
+                        r: account_id.into(),
+                    }
+                    .into(),
+                )
+                .into(),
+                output: srql::Output::None.into(),
+                ..Default::default()
+            }),
+            srql::Statement::Create(create),
+            srql::trans_end(),
+        ]))
+        .await?
+        .check()?;
+
+    Ok(format!("{id}.{secret}"))
+}
+
+/// Uses up a password reset token, returning the account it was issued
+/// for. Tokens that are unknown, expired or already used fail with
+/// `PasswordResetInvalid`.
+pub async fn use_password_reset(persist: &Persist, token: &str) -> Result<Thing> {
+    let Some((id, secret)) = parse_opaque_token(token) else {
+        return Err(Error::PasswordResetInvalid);
+    };
+    let stored: Option<PasswordReset> =
+        persist.db().select((PASSWORD_RESET_TABLE_NAME, id)).await?;
+    let Some(stored) = stored.filter(|stored| stored.secret_hash == hash_secret(secret)) else {
+        return Err(Error::PasswordResetInvalid);
+    };
+    if stored.expires_at <= persist.clock().now() {
+        return Err(Error::PasswordResetInvalid);
+    }
+
+    // Only whoever deletes the token gets to use it, so two requests racing
+    // with the same token can't both succeed.
+    let used: Vec<PasswordReset> = persist
+        .db()
+        .query(srql::DeleteStatement {
+            what: srql::thing(stored.id),
+            output: srql::Output::Before.into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    match used.into_iter().next() {
+        Some(used) => Ok(used.account_id),
+        None => Err(Error::PasswordResetInvalid),
+    }
+}
+
+/// Deletes the password reset tokens that have expired. Returns how many
+/// were deleted.
+pub async fn prune_password_resets(persist: &Persist) -> Result<usize> {
+    let pruned: Vec<PasswordReset> = persist
+        .db()
+        .query(srql::DeleteStatement {
+            what: srql::table(PASSWORD_RESET_TABLE_NAME),
+            cond: srql::Cond(
+                srql::Expression::Binary {
+                    l: srql::field("expires_at").into(),
+                    o: srql::Operator::LessThanOrEqual,
+                    r: srql::Value::Datetime(srql::Datetime(persist.clock().now())),
+                }
+                .into(),
+            )
+            .into(),
+            output: srql::Output::Before.into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    Ok(pruned.len())
+}
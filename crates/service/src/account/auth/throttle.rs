@@ -0,0 +1,357 @@
+//! Throttling of failed logins, both to each account and from each address.
+//!
+//! Failures are counted over a sliding window, and too many within it lock
+//! logging in for a while. Each lockout after the first lasts twice as long
+//! as the one before, until a login succeeds or the lockouts are forgotten.
+
+use std::net::IpAddr;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Deserialize;
+use surrealdb::sql::Thing;
+
+use crate::{persist::Persist, prelude::*, tx::Tx};
+
+pub static LOGIN_THROTTLE_TABLE_NAME: &str = "login_throttle";
+
+/// How long failed logins are counted for.
+pub const LOGIN_FAILURE_WINDOW_MINUTES: i64 = 15;
+
+/// How many failed logins to an account within the window lock it.
+pub const ACCOUNT_MAX_LOGIN_FAILURES: usize = 5;
+
+/// How many failed logins from an address within the window lock it. This
+/// is higher than for accounts, as many people can share an address.
+pub const IP_MAX_LOGIN_FAILURES: usize = 20;
+
+/// How long the first lockout lasts.
+pub const LOGIN_LOCKOUT_BASE_SECS: i64 = 60;
+
+/// The longest that a lockout can last.
+pub const LOGIN_LOCKOUT_MAX_SECS: i64 = 60 * 60;
+
+/// How long after their last failure or lockout that accounts and addresses
+/// are forgotten, and lockouts start from the shortest again.
+pub const LOGIN_THROTTLE_HOURS: i64 = 24;
+
+/// What failed logins are counted against.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoginThrottleKey {
+    Account(Thing),
+    Ip(IpAddr),
+}
+
+impl LoginThrottleKey {
+    fn max_failures(&self) -> usize {
+        match self {
+            Self::Account(_) => ACCOUNT_MAX_LOGIN_FAILURES,
+            Self::Ip(_) => IP_MAX_LOGIN_FAILURES,
+        }
+    }
+
+    /// Each key has at most one record, stored under an ID made from it.
+    fn thing(&self) -> Thing {
+        let id = match self {
+            Self::Account(account_id) => format!("account_{}", account_id.id.to_raw()),
+            Self::Ip(ip) => format!("ip_{ip}"),
+        };
+        Thing::from((LOGIN_THROTTLE_TABLE_NAME, id.as_str()))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct LoginThrottle {
+    #[serde(default)]
+    failures: Vec<DateTime<Utc>>,
+    locked_until: Option<DateTime<Utc>>,
+    expires_at: DateTime<Utc>,
+}
+
+/// Fails with `TooManyAttempts` if logging in is locked for any of the keys,
+/// with how many seconds until it's unlocked for all of them.
+pub async fn check_login_throttle(persist: &Persist, keys: &[LoginThrottleKey]) -> Result<()> {
+    let now = persist.clock().now();
+    let mut locked_until = None;
+    for key in keys {
+        let Some(throttle) = get_throttle(persist, key, now).await? else {
+            continue;
+        };
+        locked_until = locked_until.max(throttle.locked_until.filter(|until| *until > now));
+    }
+
+    match locked_until {
+        Some(until) => Err(Error::TooManyAttempts(retry_after_secs(until - now))),
+        None => Ok(()),
+    }
+}
+
+/// Counts a failed login against a key, returning whether it locked logging
+/// in.
+///
+/// The failure is counted and the lockout decided in a single update, so
+/// that failures made at the same time are all counted, rather than some
+/// overwriting others.
+pub async fn record_login_failure(persist: &Persist, key: &LoginThrottleKey) -> Result<bool> {
+    let now = persist.clock().now();
+    let mut tx = Tx::new();
+    tx.push(srql::Statement::Update(srql::UpdateStatement {
+        what: srql::thing(key.thing()),
+        data: srql::Data::SetExpression(failure_update(key, now)).into(),
+        output: srql::Output::After.into(),
+        ..Default::default()
+    }));
+    let throttle: Option<LoginThrottle> = tx.commit(persist).await?.take(0)?;
+    // Failures are only cleared when they lock logging in, as otherwise
+    // there's always this one.
+    Ok(throttle.is_some_and(|throttle| throttle.failures.is_empty()))
+}
+
+/// Counts a failure made at `now`, locking logging in if there are too many
+/// of them.
+fn failure_update(key: &LoginThrottleKey, now: DateTime<Utc>) -> srql::SetExpr {
+    let window_start = now - Duration::minutes(LOGIN_FAILURE_WINDOW_MINUTES);
+    let now_value = || srql::Value::Datetime(srql::Datetime(now));
+    // Throttles that have been forgotten start again from nothing.
+    let remembered = || binary(field("expires_at"), srql::Operator::MoreThan, now_value());
+    let locks = || {
+        binary(
+            func("array::len", vec![field("failures")]),
+            srql::Operator::MoreThanOrEqual,
+            srql::Value::from(key.max_failures()),
+        )
+    };
+    let forget_after = srql::Value::Duration(
+        Duration::hours(LOGIN_THROTTLE_HOURS)
+            .to_std()
+            .unwrap_or_default()
+            .into(),
+    );
+
+    // Each expression sees the fields set by the ones before it.
+    vec![
+        (
+            srql::field("lockouts"),
+            srql::Operator::Equal,
+            if_else(
+                remembered(),
+                binary(field("lockouts"), srql::Operator::Nco, 0.into()),
+                0.into(),
+            ),
+        ),
+        (
+            srql::field("failures"),
+            srql::Operator::Equal,
+            func(
+                "array::append",
+                vec![
+                    if_else(
+                        remembered(),
+                        recent_failures(window_start),
+                        srql::Value::Array(srql::Array::new()),
+                    ),
+                    now_value(),
+                ],
+            ),
+        ),
+        (
+            srql::field("locked_until"),
+            srql::Operator::Equal,
+            if_else(
+                locks(),
+                binary(now_value(), srql::Operator::Add, lockout_duration()),
+                field("locked_until"),
+            ),
+        ),
+        (
+            srql::field("lockouts"),
+            srql::Operator::Equal,
+            if_else(
+                locks(),
+                binary(field("lockouts"), srql::Operator::Add, 1.into()),
+                field("lockouts"),
+            ),
+        ),
+        (
+            srql::field("expires_at"),
+            srql::Operator::Equal,
+            binary(
+                if_else(locks(), field("locked_until"), now_value()),
+                srql::Operator::Add,
+                forget_after,
+            ),
+        ),
+        (
+            srql::field("failures"),
+            srql::Operator::Equal,
+            if_else(
+                locks(),
+                srql::Value::Array(srql::Array::new()),
+                field("failures"),
+            ),
+        ),
+    ]
+}
+
+/// The failures made since the window started.
+fn recent_failures(window_start: DateTime<Utc>) -> srql::Value {
+    srql::Value::Idiom(srql::Idiom(vec![
+        srql::Part::Start(binary(
+            field("failures"),
+            srql::Operator::Nco,
+            srql::Value::Array(srql::Array::new()),
+        )),
+        srql::Part::Where(binary(
+            srql::Value::Param("this".into()),
+            srql::Operator::MoreThan,
+            srql::Value::Datetime(srql::Datetime(window_start)),
+        )),
+    ]))
+}
+
+/// How long a lockout lasts, doubling with each one before it, up to the
+/// longest a lockout can last.
+fn lockout_duration() -> srql::Value {
+    func(
+        "duration::from::secs",
+        vec![func(
+            "math::min",
+            vec![srql::array([
+                binary(
+                    func("math::pow", vec![2.into(), field("lockouts")]),
+                    srql::Operator::Mul,
+                    LOGIN_LOCKOUT_BASE_SECS.into(),
+                ),
+                LOGIN_LOCKOUT_MAX_SECS.into(),
+            ])],
+        )],
+    )
+}
+
+/// Forgets the failed logins and lockouts counted against a key.
+pub async fn clear_login_throttle(persist: &Persist, key: &LoginThrottleKey) -> Result<()> {
+    persist
+        .db()
+        .query(srql::DeleteStatement {
+            what: srql::thing(key.thing()),
+            output: srql::Output::None.into(),
+            ..Default::default()
+        })
+        .await?
+        .check()?;
+    Ok(())
+}
+
+/// Deletes the throttles that have been forgotten. Returns how many were
+/// deleted.
+pub async fn prune_login_throttles(persist: &Persist) -> Result<usize> {
+    let pruned: Vec<LoginThrottle> = persist
+        .db()
+        .query(srql::DeleteStatement {
+            what: srql::table(LOGIN_THROTTLE_TABLE_NAME),
+            cond: srql::Cond(
+                srql::Expression::Binary {
+                    l: srql::field("expires_at").into(),
+                    o: srql::Operator::LessThanOrEqual,
+                    r: srql::Value::Datetime(srql::Datetime(persist.clock().now())),
+                }
+                .into(),
+            )
+            .into(),
+            output: srql::Output::Before.into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    Ok(pruned.len())
+}
+
+/// Gets the throttle for a key, if it hasn't been forgotten yet.
+async fn get_throttle(
+    persist: &Persist,
+    key: &LoginThrottleKey,
+    now: DateTime<Utc>,
+) -> Result<Option<LoginThrottle>> {
+    let throttle: Option<LoginThrottle> = persist.db().select(key.thing()).await?;
+    Ok(throttle.filter(|throttle| throttle.expires_at > now))
+}
+
+fn field(name: &str) -> srql::Value {
+    srql::Value::Idiom(srql::field(name))
+}
+
+fn binary(l: srql::Value, o: srql::Operator, r: srql::Value) -> srql::Value {
+    srql::Expression::Binary { l, o, r }.into()
+}
+
+fn func(name: &str, args: Vec<srql::Value>) -> srql::Value {
+    srql::Function::Normal(name.into(), args).into()
+}
+
+fn if_else(cond: srql::Value, then: srql::Value, otherwise: srql::Value) -> srql::Value {
+    srql::Value::Subquery(Box::new(srql::Subquery::Ifelse(srql::IfelseStatement {
+        exprs: vec![(cond, then)],
+        close: Some(otherwise),
+    })))
+}
+
+/// Rounds up, so that retrying after the given time always works.
+fn retry_after_secs(remaining: Duration) -> u64 {
+    let millis = remaining.num_milliseconds().max(0);
+    u64::try_from((millis + 999) / 1000).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{persist::testing::persist, provider::MockClock};
+
+    fn account_key() -> LoginThrottleKey {
+        LoginThrottleKey::Account(Thing::from(("account", "throttled")))
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_failures() {
+        let persist = persist().await;
+        let key = account_key();
+
+        let locked = futures::future::join_all(
+            (0..ACCOUNT_MAX_LOGIN_FAILURES).map(|_| record_login_failure(&persist, &key)),
+        )
+        .await;
+        // Every failure is counted, so exactly one of them locks.
+        let locked: Vec<_> = locked.into_iter().map(Result::unwrap).collect();
+        assert_eq!(locked.iter().filter(|locked| **locked).count(), 1);
+        assert_eq!(
+            check_login_throttle(&persist, &[key]).await,
+            Err(Error::TooManyAttempts(60))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lockout_doubles() {
+        let clock = MockClock::default();
+        let persist = persist().await.with_clock(Arc::new(clock.clone()));
+        let key = account_key();
+
+        for expected in [60, 120, 240, 480, 960, 1920, 3600, 3600] {
+            for _ in 1..ACCOUNT_MAX_LOGIN_FAILURES {
+                assert!(!record_login_failure(&persist, &key).await.unwrap());
+            }
+            assert!(record_login_failure(&persist, &key).await.unwrap());
+            assert_eq!(
+                check_login_throttle(&persist, std::slice::from_ref(&key)).await,
+                Err(Error::TooManyAttempts(expected))
+            );
+            clock.advance(Duration::seconds(i64::try_from(expected).unwrap()));
+        }
+    }
+
+    #[test]
+    fn test_retry_after_secs() {
+        assert_eq!(retry_after_secs(Duration::seconds(60)), 60);
+        assert_eq!(retry_after_secs(Duration::milliseconds(59_001)), 60);
+        assert_eq!(retry_after_secs(Duration::seconds(-1)), 0);
+    }
+}
@@ -0,0 +1,525 @@
+//! Time-based one-time passwords (RFC 6238) for two-factor authentication.
+//!
+//! Accounts enroll by adding a secret to an authenticator app, then confirm
+//! it with a code, which turns two-factor authentication on and hands out
+//! recovery codes. Recovery codes can be used once each instead of a code,
+//! and only hashes of them are kept. They can be replaced with new ones at
+//! any time, such as when they're running out.
+
+use async_graphql::SimpleObject;
+use chrono::{DateTime, Duration, Utc};
+use data_encoding::BASE32_NOPAD;
+use ring::{
+    hmac,
+    rand::{SecureRandom as _, SystemRandom},
+};
+use serde::Deserialize;
+use surrealdb::sql::Thing;
+
+use super::hash_secret;
+use crate::{persist::Persist, prelude::*};
+
+pub static TOTP_TABLE_NAME: &str = "totp";
+
+/// The name that authenticator apps show accounts under.
+const TOTP_ISSUER: &str = "Plazer";
+
+/// How many random bytes are in a secret. This is the length of an HMAC-SHA1
+/// key, as RFC 4226 recommends.
+const TOTP_SECRET_LEN: usize = 20;
+
+/// How long each code lasts, in seconds.
+const TOTP_STEP_SECS: i64 = 30;
+
+/// How many digits are in each code.
+const TOTP_DIGITS: u32 = 6;
+
+/// How many steps either side of now a code is accepted for, to allow for
+/// clocks that have drifted.
+const TOTP_SKEW_STEPS: i64 = 1;
+
+/// How many codes can be wrong in a row before checking is locked.
+const TOTP_MAX_FAILURES: u32 = 5;
+
+/// How long checking is locked for after too many wrong codes.
+const TOTP_LOCK_MINUTES: i64 = 15;
+
+/// How many recovery codes are handed out when two-factor authentication is
+/// turned on.
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+/// How many recovery codes an account can have left before it's warned to
+/// generate new ones.
+pub const LOW_RECOVERY_CODES: usize = 3;
+
+/// How many random bytes are in a recovery code.
+const RECOVERY_CODE_LEN: usize = 10;
+
+/// An account's TOTP secret, which is only used once it's confirmed.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TotpSecret {
+    pub id: Thing,
+    secret: String,
+    /// When two-factor authentication was turned on, if it has been.
+    pub enabled_at: Option<DateTime<Utc>>,
+    locked_until: Option<DateTime<Utc>>,
+    #[serde(default)]
+    recovery_code_hashes: Vec<String>,
+}
+
+/// How a two-factor code was checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TotpVerified {
+    /// The code was from the account's authenticator app.
+    Code,
+    /// The code was one of the account's recovery codes, and it has this
+    /// many left.
+    RecoveryCode { remaining: usize },
+}
+
+/// What an authenticator app needs to add an account.
+#[derive(SimpleObject, Debug, Clone, PartialEq, Eq)]
+pub struct TotpEnrollment {
+    /// The secret, in base32, for typing into an authenticator app.
+    pub secret: String,
+    /// An `otpauth://` URI with the secret, for showing as a QR code.
+    pub provisioning_uri: String,
+}
+
+/// Starts enrolling an account in two-factor authentication with a new
+/// secret, replacing any that hasn't been confirmed yet. Fails with
+/// `TotpAlreadyEnabled` if the account has already confirmed one.
+pub async fn begin_totp_enrollment(
+    persist: &Persist,
+    csrng: &SystemRandom,
+    account_id: &Thing,
+    user_id: &str,
+) -> Result<TotpEnrollment> {
+    if get_totp(persist, account_id)
+        .await?
+        .is_some_and(|totp| totp.enabled_at.is_some())
+    {
+        return Err(Error::TotpAlreadyEnabled);
+    }
+
+    let mut secret = [0u8; TOTP_SECRET_LEN];
+    csrng.fill(&mut secret)?;
+    let secret = BASE32_NOPAD.encode(&secret);
+
+    let mut update = vec![];
+    secret
+        .clone()
+        .push_field(srql::field("secret"), &mut update);
+    update.push((
+        srql::field("enabled_at"),
+        srql::Operator::Equal,
+        srql::Value::None,
+    ));
+    update.push((
+        srql::field("recovery_code_hashes"),
+        srql::Operator::Equal,
+        srql::array(vec![]),
+    ));
+    persist
+        .db()
+        .query(srql::UpdateStatement {
+            what: srql::thing(totp_thing(account_id)),
+            data: srql::Data::SetExpression(update).into(),
+            output: srql::Output::None.into(),
+            ..Default::default()
+        })
+        .await?
+        .check()?;
+
+    // User IDs are limited to characters that don't need escaping in URIs.
+    let provisioning_uri = format!(
+        "otpauth://totp/{TOTP_ISSUER}:{user_id}?secret={secret}&issuer={TOTP_ISSUER}&algorithm=SHA1&digits={TOTP_DIGITS}&period={TOTP_STEP_SECS}"
+    );
+    Ok(TotpEnrollment {
+        secret,
+        provisioning_uri,
+    })
+}
+
+/// Turns on two-factor authentication for an account that has started
+/// enrolling, if the code is right. Returns the recovery codes, which can't
+/// be seen again.
+pub async fn confirm_totp_enrollment(
+    persist: &Persist,
+    csrng: &SystemRandom,
+    account_id: &Thing,
+    code: &str,
+) -> Result<Vec<String>> {
+    let Some(totp) = get_totp(persist, account_id).await? else {
+        return Err(Error::TotpNotEnrolled);
+    };
+    if totp.enabled_at.is_some() {
+        return Err(Error::TotpAlreadyEnabled);
+    }
+    let now = persist.clock().now();
+    let Some(step) = find_step(&totp.secret, code, now) else {
+        return Err(Error::TotpInvalid);
+    };
+
+    let (codes, hashes) = generate_recovery_codes(csrng)?;
+    let mut update = vec![];
+    now.push_field(srql::field("enabled_at"), &mut update);
+    update.push((
+        srql::field("recovery_code_hashes"),
+        srql::Operator::Equal,
+        hashes,
+    ));
+    update.push((
+        srql::field("last_step"),
+        srql::Operator::Equal,
+        srql::Value::from(step),
+    ));
+    persist
+        .db()
+        .query(srql::UpdateStatement {
+            what: srql::thing(totp.id),
+            data: srql::Data::SetExpression(update).into(),
+            output: srql::Output::None.into(),
+            ..Default::default()
+        })
+        .await?
+        .check()?;
+    Ok(codes)
+}
+
+/// Whether an account has turned on two-factor authentication.
+pub async fn totp_enabled(persist: &Persist, account_id: &Thing) -> Result<bool> {
+    Ok(get_totp(persist, account_id)
+        .await?
+        .is_some_and(|totp| totp.enabled_at.is_some()))
+}
+
+/// How many recovery codes an account has left, if it has turned on
+/// two-factor authentication.
+pub async fn recovery_codes_remaining(
+    persist: &Persist,
+    account_id: &Thing,
+) -> Result<Option<usize>> {
+    Ok(get_totp(persist, account_id)
+        .await?
+        .filter(|totp| totp.enabled_at.is_some())
+        .map(|totp| totp.recovery_code_hashes.len()))
+}
+
+/// Replaces an account's recovery codes with new ones, which are returned
+/// and can't be seen again. The old ones stop working.
+pub async fn regenerate_recovery_codes(
+    persist: &Persist,
+    csrng: &SystemRandom,
+    account_id: &Thing,
+) -> Result<Vec<String>> {
+    let Some(totp) = get_totp(persist, account_id)
+        .await?
+        .filter(|totp| totp.enabled_at.is_some())
+    else {
+        return Err(Error::TotpNotEnrolled);
+    };
+
+    let (codes, hashes) = generate_recovery_codes(csrng)?;
+    persist
+        .db()
+        .query(srql::UpdateStatement {
+            what: srql::thing(totp.id),
+            data: srql::Data::SetExpression(vec![(
+                srql::field("recovery_code_hashes"),
+                srql::Operator::Equal,
+                hashes,
+            )])
+            .into(),
+            output: srql::Output::None.into(),
+            ..Default::default()
+        })
+        .await?
+        .check()?;
+    Ok(codes)
+}
+
+/// Checks a code from an account's authenticator app, or one of its
+/// recovery codes, which can't be used again. Wrong codes fail with
+/// `TotpInvalid`, and after too many in a row checking is locked for a while
+/// and fails with `RateLimited`.
+pub async fn verify_totp(
+    persist: &Persist,
+    account_id: &Thing,
+    code: &str,
+) -> Result<TotpVerified> {
+    let Some(totp) = get_totp(persist, account_id)
+        .await?
+        .filter(|totp| totp.enabled_at.is_some())
+    else {
+        return Err(Error::TotpNotEnrolled);
+    };
+    let now = persist.clock().now();
+    if totp
+        .locked_until
+        .is_some_and(|locked_until| locked_until > now)
+    {
+        return Err(Error::RateLimited);
+    }
+
+    // Codes are only used once. The last step that a code was accepted for
+    // is kept, and only whoever moves it forward gets to use the code, even
+    // if requests race each other. Recovery codes are removed as they're
+    // used.
+    if let Some(step) = find_step(&totp.secret, code, now) {
+        if use_totp(
+            persist,
+            &totp,
+            srql::Expression::Binary {
+                l: srql::field("last_step").into(),
+                o: srql::Operator::LessThan,
+                r: step.into(),
+            },
+            (srql::field("last_step"), srql::Operator::Equal, step.into()),
+        )
+        .await?
+        .is_some()
+        {
+            return Ok(TotpVerified::Code);
+        }
+    } else {
+        let hash = srql::Value::from(srql::string(hash_recovery_code(code)));
+        if let Some(used) = use_totp(
+            persist,
+            &totp,
+            srql::Expression::Binary {
+                l: srql::field("recovery_code_hashes").into(),
+                o: srql::Operator::Contain,
+                r: hash.clone(),
+            },
+            (
+                srql::field("recovery_code_hashes"),
+                srql::Operator::Dec,
+                hash,
+            ),
+        )
+        .await?
+        {
+            return Ok(TotpVerified::RecoveryCode {
+                remaining: used.recovery_code_hashes.len(),
+            });
+        }
+    }
+
+    // The failure is counted, and checking locked, in the database, so that
+    // wrong codes sent at the same time are all counted.
+    let locks = || {
+        srql::Value::from(srql::Expression::Binary {
+            l: srql::field("failures").into(),
+            o: srql::Operator::MoreThanOrEqual,
+            r: TOTP_MAX_FAILURES.into(),
+        })
+    };
+    let if_locks = |then: srql::Value, otherwise: srql::Value| {
+        srql::Value::Subquery(Box::new(srql::Subquery::Ifelse(srql::IfelseStatement {
+            exprs: vec![(locks(), then)],
+            close: Some(otherwise),
+        })))
+    };
+    let update = vec![
+        (srql::field("failures"), srql::Operator::Inc, 1.into()),
+        (
+            srql::field("locked_until"),
+            srql::Operator::Equal,
+            if_locks(
+                srql::Value::Datetime(srql::Datetime(now + Duration::minutes(TOTP_LOCK_MINUTES))),
+                srql::field("locked_until").into(),
+            ),
+        ),
+        (
+            srql::field("failures"),
+            srql::Operator::Equal,
+            if_locks(0.into(), srql::field("failures").into()),
+        ),
+    ];
+    persist
+        .db()
+        .query(srql::UpdateStatement {
+            what: srql::thing(totp.id),
+            data: srql::Data::SetExpression(update).into(),
+            output: srql::Output::None.into(),
+            ..Default::default()
+        })
+        .await?
+        .check()?;
+    Err(Error::TotpInvalid)
+}
+
+/// Turns off two-factor authentication for an account, returning whether it
+/// was on or being enrolled in.
+pub async fn remove_totp(persist: &Persist, account_id: &Thing) -> Result<bool> {
+    let removed: Vec<TotpSecret> = persist
+        .db()
+        .query(srql::DeleteStatement {
+            what: srql::thing(totp_thing(account_id)),
+            output: srql::Output::Before.into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    Ok(!removed.is_empty())
+}
+
+async fn get_totp(persist: &Persist, account_id: &Thing) -> Result<Option<TotpSecret>> {
+    Ok(persist.db().select(totp_thing(account_id)).await?)
+}
+
+/// Applies an update that uses up a code, if the condition still holds, and
+/// clears any failures. Returns the secret after the update if it was made.
+async fn use_totp(
+    persist: &Persist,
+    totp: &TotpSecret,
+    cond: srql::Expression,
+    update: srql::SetExprItem,
+) -> Result<Option<TotpSecret>> {
+    let mut updates = vec![update];
+    0u32.push_field(srql::field("failures"), &mut updates);
+    let used: Option<TotpSecret> = persist
+        .db()
+        .query(srql::UpdateStatement {
+            what: srql::thing(totp.id.clone()),
+            data: srql::Data::SetExpression(updates).into(),
+            cond: srql::Cond(cond.into()).into(),
+            output: srql::Output::After.into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    Ok(used)
+}
+
+/// Each account has at most one secret, stored under the account's ID.
+fn totp_thing(account_id: &Thing) -> Thing {
+    Thing::from((TOTP_TABLE_NAME, account_id.id.to_raw().as_str()))
+}
+
+/// Finds the step that a code is right for, near to now.
+fn find_step(secret: &str, code: &str, now: DateTime<Utc>) -> Option<i64> {
+    let code = code.trim();
+    if code.len() != TOTP_DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let secret = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+    let now = now.timestamp().div_euclid(TOTP_STEP_SECS);
+    (now - TOTP_SKEW_STEPS..=now + TOTP_SKEW_STEPS).find(|&step| totp_code(&secret, step) == code)
+}
+
+/// Works out the code for a step, as in RFC 4226.
+fn totp_code(secret: &[u8], step: i64) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, secret);
+    let tag = hmac::sign(&key, &step.to_be_bytes());
+    let tag = tag.as_ref();
+    let offset = usize::from(tag[tag.len() - 1] & 0xf);
+    let value = u32::from_be_bytes([
+        tag[offset] & 0x7f,
+        tag[offset + 1],
+        tag[offset + 2],
+        tag[offset + 3],
+    ]);
+    format!(
+        "{:0width$}",
+        value % 10u32.pow(TOTP_DIGITS),
+        width = TOTP_DIGITS as usize
+    )
+}
+
+/// Generates a full set of recovery codes, along with the hashes of them to
+/// store.
+fn generate_recovery_codes(csrng: &SystemRandom) -> Result<(Vec<String>, srql::Value)> {
+    let codes = (0..RECOVERY_CODE_COUNT)
+        .map(|_| generate_recovery_code(csrng))
+        .collect::<Result<Vec<_>>>()?;
+    let hashes = codes
+        .iter()
+        .map(|code| srql::Value::from(srql::string(hash_recovery_code(code))))
+        .collect::<Vec<_>>();
+    Ok((codes, srql::array(hashes)))
+}
+
+/// Generates a recovery code, such as `abcd-efgh-ijkl-mnop`.
+fn generate_recovery_code(csrng: &SystemRandom) -> Result<String> {
+    let mut code = [0u8; RECOVERY_CODE_LEN];
+    csrng.fill(&mut code)?;
+    let code = BASE32_NOPAD.encode(&code).to_ascii_lowercase();
+    Ok(code
+        .as_bytes()
+        .chunks(4)
+        .map(|chunk| String::from_utf8_lossy(chunk))
+        .collect::<Vec<_>>()
+        .join("-"))
+}
+
+/// Hashes a recovery code, ignoring case, spaces and dashes so that it can
+/// be typed in however it was written down.
+fn hash_recovery_code(code: &str) -> String {
+    let code: String = code
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    hash_secret(&code)
+}
+
+#[cfg(test)]
+pub mod testing {
+    use chrono::{DateTime, Utc};
+    use data_encoding::BASE32_NOPAD;
+
+    use super::{totp_code, TOTP_STEP_SECS};
+
+    /// Works out the code an authenticator app would show for a secret.
+    pub fn totp_code_at(secret: &str, at: DateTime<Utc>) -> String {
+        let secret = BASE32_NOPAD.decode(secret.as_bytes()).unwrap();
+        totp_code(&secret, at.timestamp().div_euclid(TOTP_STEP_SECS))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone as _, Utc};
+    use test_case::test_case;
+
+    use super::*;
+
+    // The SHA-1 test vectors from RFC 6238, truncated to six digits.
+    #[test_case(59 => "287082")]
+    #[test_case(1_111_111_109 => "081804")]
+    #[test_case(1_234_567_890 => "005924")]
+    #[test_case(20_000_000_000 => "353130")]
+    fn test_totp_code(time: i64) -> String {
+        totp_code(b"12345678901234567890", time / TOTP_STEP_SECS)
+    }
+
+    #[test]
+    fn test_find_step() {
+        let secret = BASE32_NOPAD.encode(b"12345678901234567890");
+        let now = Utc.timestamp_opt(1_111_111_109, 0).unwrap();
+        let step = now.timestamp() / TOTP_STEP_SECS;
+        assert_eq!(find_step(&secret, "081804", now), Some(step));
+        assert_eq!(find_step(&secret, " 081804 ", now), Some(step));
+
+        // Codes from the steps either side are accepted, but no further.
+        let next = now + Duration::seconds(TOTP_STEP_SECS);
+        assert_eq!(find_step(&secret, "081804", next), Some(step));
+        let later = now + Duration::seconds(TOTP_STEP_SECS * 2);
+        assert_eq!(find_step(&secret, "081804", later), None);
+
+        assert_eq!(find_step(&secret, "81804", now), None);
+        assert_eq!(find_step(&secret, "08180a", now), None);
+    }
+
+    #[test]
+    fn test_recovery_code() {
+        let code = generate_recovery_code(&SystemRandom::new()).unwrap();
+        assert_eq!(code.len(), 19);
+        assert_eq!(
+            hash_recovery_code(&code),
+            hash_recovery_code(&code.replace('-', " ").to_uppercase())
+        );
+    }
+}
@@ -0,0 +1,169 @@
+use chrono::{DateTime, Duration, Utc};
+use ring::rand::SystemRandom;
+use serde::Deserialize;
+use surrealdb::sql::Thing;
+
+use super::{generate_secret, hash_secret, parse_opaque_token};
+use crate::{persist::Persist, prelude::*};
+
+pub static EMAIL_VERIFICATION_TABLE_NAME: &str = "email_verification";
+
+/// How long an email verification token can be used for.
+pub const EMAIL_VERIFICATION_HOURS: i64 = 24;
+
+/// How long an account has to wait between verification emails, so that
+/// it can't be used to flood an address.
+pub const EMAIL_VERIFICATION_RESEND_MINUTES: i64 = 5;
+
+/// An email verification token that has been issued and not used yet. Only
+/// a hash of its secret is kept, along with the address it was sent to.
+#[derive(Debug, Clone, Deserialize)]
+pub struct EmailVerification {
+    pub id: Thing,
+    pub account_id: Thing,
+    pub email: String,
+    secret_hash: String,
+    pub issued_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Gets the verification token the account was issued last, if it hasn't
+/// been used.
+pub async fn pending_email_verification(
+    persist: &Persist,
+    account_id: &Thing,
+) -> Result<Option<EmailVerification>> {
+    let pending: Option<EmailVerification> = persist
+        .db()
+        .query(srql::SelectStatement {
+            expr: srql::Fields::all(),
+            what: srql::table(EMAIL_VERIFICATION_TABLE_NAME),
+            cond: srql::Cond(
+                srql::Expression::Binary {
+                    l: srql::field("account_id").into(),
+                    o: srql::Operator::Equal,
+                    r: account_id.clone().into(),
+                }
+                .into(),
+            )
+            .into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    Ok(pending)
+}
+
+/// Issues a token that can be used once to verify that the account can be
+/// emailed at an address, and stores it. Any the account was issued before
+/// stop working.
+pub async fn issue_email_verification(
+    persist: &Persist,
+    csrng: &SystemRandom,
+    account_id: Thing,
+    email: &str,
+) -> Result<String> {
+    let secret = generate_secret(csrng)?;
+    let now = persist.clock().now();
+
+    let id = persist.ids().next_id();
+    let mut create = vec![];
+    account_id
+        .clone()
+        .push_field(srql::field("account_id"), &mut create);
+    email
+        .to_owned()
+        .push_field(srql::field("email"), &mut create);
+    hash_secret(&secret).push_field(srql::field("secret_hash"), &mut create);
+    now.push_field(srql::field("issued_at"), &mut create);
+    (now + Duration::hours(EMAIL_VERIFICATION_HOURS))
+        .push_field(srql::field("expires_at"), &mut create);
+    let mut create =
+        srql::obj_create_query_id(EMAIL_VERIFICATION_TABLE_NAME, create, id.clone().into());
+    create.output = srql::Output::None.into();
+
+    persist
+        .db()
+        .query(srql::query([
+            srql::trans_begin(),
+            srql::Statement::Delete(srql::DeleteStatement {
+                what: srql::table(EMAIL_VERIFICATION_TABLE_NAME),
+                cond: srql::Cond(
+                    srql::Expression::Binary {
+                        l: srql::field("account_id").into(),
+                        o: srql::Operator::Equal,
+                        r: account_id.into(),
+                    }
+                    .into(),
+                )
+                .into(),
+                output: srql::Output::None.into(),
+                ..Default::default()
+            }),
+            srql::Statement::Create(create),
+            srql::trans_end(),
+        ]))
+        .await?
+        .check()?;
+
+    Ok(format!("{id}.{secret}"))
+}
+
+/// Uses up an email verification token, returning the account it was issued
+/// for and the address it was sent to. Tokens that are unknown, expired or
+/// already used fail with `EmailVerificationInvalid`.
+pub async fn use_email_verification(persist: &Persist, token: &str) -> Result<(Thing, String)> {
+    let Some((id, secret)) = parse_opaque_token(token) else {
+        return Err(Error::EmailVerificationInvalid);
+    };
+    let stored: Option<EmailVerification> = persist
+        .db()
+        .select((EMAIL_VERIFICATION_TABLE_NAME, id))
+        .await?;
+    let Some(stored) = stored.filter(|stored| stored.secret_hash == hash_secret(secret)) else {
+        return Err(Error::EmailVerificationInvalid);
+    };
+    if stored.expires_at <= persist.clock().now() {
+        return Err(Error::EmailVerificationInvalid);
+    }
+
+    // Only whoever deletes the token gets to use it, so two requests racing
+    // with the same token can't both succeed.
+    let used: Vec<EmailVerification> = persist
+        .db()
+        .query(srql::DeleteStatement {
+            what: srql::thing(stored.id),
+            output: srql::Output::Before.into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    match used.into_iter().next() {
+        Some(used) => Ok((used.account_id, used.email)),
+        None => Err(Error::EmailVerificationInvalid),
+    }
+}
+
+/// Deletes the email verification tokens that have expired. Returns how
+/// many were deleted.
+pub async fn prune_email_verifications(persist: &Persist) -> Result<usize> {
+    let pruned: Vec<EmailVerification> = persist
+        .db()
+        .query(srql::DeleteStatement {
+            what: srql::table(EMAIL_VERIFICATION_TABLE_NAME),
+            cond: srql::Cond(
+                srql::Expression::Binary {
+                    l: srql::field("expires_at").into(),
+                    o: srql::Operator::LessThanOrEqual,
+                    r: srql::Value::Datetime(srql::Datetime(persist.clock().now())),
+                }
+                .into(),
+            )
+            .into(),
+            output: srql::Output::Before.into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    Ok(pruned.len())
+}
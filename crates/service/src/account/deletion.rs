@@ -0,0 +1,180 @@
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use surrealdb::sql::Thing;
+
+use super::{
+    record_departed, ACC_TABLE_NAME, API_KEY_TABLE_NAME, EMAIL_VERIFICATION_TABLE_NAME,
+    EXTERNAL_IDENTITY_TABLE_NAME, PASSKEY_TABLE_NAME, PASSWORD_RESET_TABLE_NAME,
+    RECOVERY_CONTACTS_TABLE_NAME, RECOVERY_REQUEST_TABLE_NAME, REFRESH_TOKEN_TABLE_NAME,
+    TOTP_TABLE_NAME,
+};
+use crate::{
+    audience::AUDIENCE_TABLE_NAME,
+    board::BOARD_MEMBER_TABLE_NAME,
+    client_state::CLIENT_STATE_TABLE_NAME,
+    conversation::CONVERSATION_MUTE_TABLE_NAME,
+    export::{delete_export_archives, EXPORT_TABLE_NAME},
+    integration::INTEGRATION_TABLE_NAME,
+    list::LIST_TABLE_NAME,
+    media::MEDIA_TABLE_NAME,
+    notification::{NOTIFICATION_SETTINGS_TABLE_NAME, NOTIFICATION_TABLE_NAME},
+    organization::AFFILIATION_TABLE_NAME,
+    persist::Persist,
+    policy::ACCEPTANCE_TABLE_NAME,
+    post::POST_TABLE_NAME,
+    prelude::*,
+    quota::QUOTA_TABLE_NAME,
+    read_marker::READ_MARKER_TABLE_NAME,
+    security::SECURITY_EVENT_TABLE_NAME,
+    session::SESSION_TABLE_NAME,
+    tx::Tx,
+    webhook::WEBHOOK_TABLE_NAME,
+};
+
+/// The most accounts that are purged in one go, so that a backlog of them
+/// doesn't hold the database up.
+const PURGE_BATCH_SIZE: u32 = 50;
+
+/// The records that belong to an account, as the tables they're in and the
+/// field that refers to the account. These are purged along with it.
+const OWNED_RECORDS: &[(&str, &str)] = &[
+    (SESSION_TABLE_NAME, "account_id"),
+    (REFRESH_TOKEN_TABLE_NAME, "account_id"),
+    (PASSWORD_RESET_TABLE_NAME, "account_id"),
+    (EMAIL_VERIFICATION_TABLE_NAME, "account_id"),
+    (RECOVERY_REQUEST_TABLE_NAME, "account_id"),
+    (PASSKEY_TABLE_NAME, "account_id"),
+    (API_KEY_TABLE_NAME, "account_id"),
+    (EXTERNAL_IDENTITY_TABLE_NAME, "account_id"),
+    (SECURITY_EVENT_TABLE_NAME, "account_id"),
+    (CLIENT_STATE_TABLE_NAME, "account_id"),
+    (ACCEPTANCE_TABLE_NAME, "account_id"),
+    (READ_MARKER_TABLE_NAME, "account_id"),
+    (CONVERSATION_MUTE_TABLE_NAME, "account_id"),
+    (AFFILIATION_TABLE_NAME, "account_id"),
+    (BOARD_MEMBER_TABLE_NAME, "account_id"),
+    (EXPORT_TABLE_NAME, "account_id"),
+    (NOTIFICATION_TABLE_NAME, "account_id"),
+    (NOTIFICATION_TABLE_NAME, "actor_id"),
+    (POST_TABLE_NAME, "creator_id"),
+    (MEDIA_TABLE_NAME, "owner_id"),
+    (LIST_TABLE_NAME, "owner_id"),
+    (AUDIENCE_TABLE_NAME, "owner_id"),
+    (INTEGRATION_TABLE_NAME, "owner_id"),
+    (WEBHOOK_TABLE_NAME, "owner_id"),
+];
+
+/// The tables whose records for an account have the account's ID as their
+/// own.
+const KEYED_RECORDS: &[&str] = &[
+    TOTP_TABLE_NAME,
+    RECOVERY_CONTACTS_TABLE_NAME,
+    QUOTA_TABLE_NAME,
+    NOTIFICATION_SETTINGS_TABLE_NAME,
+];
+
+#[derive(Debug, Deserialize)]
+struct DeletedAccount {
+    id: Thing,
+}
+
+/// Purges the accounts whose grace period after being deleted has ended,
+/// along with everything that belongs to them. Their user IDs can then be
+/// registered again. Returns how many accounts were purged.
+///
+/// Media blobs are left for media collection to delete, once nothing refers
+/// to them, but the account's export archives are deleted with it.
+pub async fn purge_deleted_accounts(persist: &Persist) -> Result<usize> {
+    let binary = |l: srql::Idiom, o, r| -> srql::Value {
+        srql::Expression::Binary { l: l.into(), o, r }.into()
+    };
+    let due: Vec<DeletedAccount> = persist
+        .db()
+        .query(srql::SelectStatement {
+            expr: srql::Fields(
+                vec![srql::Field::Single {
+                    expr: srql::field("id").into(),
+                    alias: None,
+                }],
+                false,
+            ),
+            what: srql::table(ACC_TABLE_NAME),
+            cond: srql::Cond(
+                srql::Expression::Binary {
+                    l: binary(
+                        srql::field("purge_at"),
+                        srql::Operator::NotEqual,
+                        srql::Value::None,
+                    ),
+                    o: srql::Operator::And,
+                    r: binary(
+                        srql::field("purge_at"),
+                        srql::Operator::LessThanOrEqual,
+                        srql::Value::Datetime(srql::Datetime(persist.clock().now())),
+                    ),
+                }
+                .into(),
+            )
+            .into(),
+            limit: Some(srql::Limit(PURGE_BATCH_SIZE.into())),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+
+    for account in &due {
+        purge_account(persist, &account.id).await?;
+    }
+    Ok(due.len())
+}
+
+/// Deletes an account and everything that belongs to it, in one
+/// transaction. Deleting the account's record deletes the follows and other
+/// relations it's part of too. The account is recorded as departed, so that
+/// what other records still refer to it is anonymized afterwards.
+async fn purge_account(persist: &Persist, account_id: &Thing) -> Result<()> {
+    delete_export_archives(persist, account_id).await?;
+
+    let mut tx = Tx::new();
+    for (table, field) in OWNED_RECORDS {
+        tx.push(srql::Statement::Delete(srql::DeleteStatement {
+            what: srql::table(*table),
+            cond: srql::Cond(
+                srql::Expression::Binary {
+                    l: srql::field(*field).into(),
+                    o: srql::Operator::Equal,
+                    r: account_id.clone().into(),
+                }
+                .into(),
+            )
+            .into(),
+            output: srql::Output::None.into(),
+            ..Default::default()
+        }));
+    }
+    for table in KEYED_RECORDS {
+        tx.push(srql::Statement::Delete(srql::DeleteStatement {
+            what: srql::thing(Thing {
+                tb: (*table).to_owned(),
+                id: account_id.id.clone(),
+            }),
+            output: srql::Output::None.into(),
+            ..Default::default()
+        }));
+    }
+    tx.push(srql::Statement::Delete(srql::DeleteStatement {
+        what: srql::thing(account_id.clone()),
+        output: srql::Output::None.into(),
+        ..Default::default()
+    }));
+    tx.push(record_departed(account_id, persist));
+
+    tx.commit(persist).await?;
+    Ok(())
+}
+
+/// When a deleted account will be purged, given when it was deleted.
+#[must_use]
+pub fn purge_time(deleted_at: DateTime<Utc>, grace_days: u32) -> DateTime<Utc> {
+    deleted_at + chrono::Duration::days(grace_days.into())
+}
@@ -0,0 +1,65 @@
+//! A stand-in for the full credential flow, so clients can be developed
+//! against a local instance without registering and logging in by hand.
+//!
+//! This is only enabled by [`DevAuthConfig`](crate::config::DevAuthConfig),
+//! and the server refuses to start with it in release builds unless that is
+//! explicitly allowed.
+
+use chrono::NaiveDate;
+use tracing::{info, instrument};
+
+use super::{AccountPersist, AuthenticatedAccount, CreateAccount};
+use crate::prelude::*;
+
+/// The accounts that are created when development authentication is enabled.
+pub static DEV_ACCOUNTS: &[&str] = &["alice", "bob", "carol"];
+
+/// The password shared by all development accounts, so they can also be
+/// logged into normally.
+pub static DEV_PASSWORD: &str = "password";
+
+impl AccountPersist<'_> {
+    /// Creates any development accounts that don't exist yet.
+    #[instrument(skip_all)]
+    pub async fn seed_dev_accounts(&self) -> Result<()> {
+        for user_id in DEV_ACCOUNTS {
+            if self.get_by_user_id(user_id).await?.is_some() {
+                continue;
+            }
+
+            // Development accounts agree to whatever the instance has
+            // published, so they're usable straight away.
+            let accepted_policy_ids = self.current_policy_ids().await?;
+            self.create(CreateAccount {
+                user_id: (*user_id).into(),
+                pword: DEV_PASSWORD.to_owned().into(),
+                invite: None,
+                bot: None,
+                accepted_policy_ids: Some(accepted_policy_ids),
+                birthdate: NaiveDate::from_ymd_opt(2000, 1, 1),
+                honeypot: None,
+                form_shown_at: None,
+                allow_confusable: None,
+                email: None,
+            })
+            .await?;
+            info!(user_id, "Created development account");
+        }
+
+        Ok(())
+    }
+
+    /// Logs into a development account without a password.
+    #[instrument(skip_all)]
+    pub async fn dev_login(&self, user_id: &str) -> Result<AuthenticatedAccount> {
+        if !DEV_ACCOUNTS.contains(&user_id) {
+            return Err(Error::CredentialsInvalid);
+        }
+
+        let Some(acc) = self.get_by_user_id(user_id).await? else {
+            return Err(Error::CredentialsInvalid);
+        };
+
+        Ok(self.touch(acc).await?.into())
+    }
+}
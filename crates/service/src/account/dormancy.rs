@@ -0,0 +1,471 @@
+//! Accounts that have gone without signing in for a long time.
+//!
+//! Once an account has gone the instance's dormancy period without signing
+//! in, it's sent a notice. If it signs in again the notice is cleared, but if
+//! it doesn't before the grace period after the notice ends, and the instance
+//! releases dormant user IDs, its user ID is replaced so that someone else can
+//! register it. Each of these steps is recorded as a security event, so they
+//! appear in the audit trail.
+//!
+//! Admins and bots are never dormant.
+
+use async_graphql::SimpleObject;
+use chrono::{DateTime, Duration, Utc};
+use serde::Deserialize;
+use surrealdb::sql::Thing;
+use tracing::instrument;
+
+use super::{user_id_skeleton, Account, CurrentAccount, ACC_TABLE_NAME};
+use crate::{
+    email::Email,
+    notification::{CreateNotification, NotificationKind, NotificationPersist},
+    persist::Persist,
+    prelude::*,
+    security::{SecurityEvent, SecurityEventKind},
+    tx::Tx,
+};
+
+/// How many accounts are noticed or released at a time.
+pub const DORMANCY_BATCH_SIZE: u32 = 100;
+
+/// The start of the user IDs that released accounts are given.
+pub static RELEASED_USER_ID_PREFIX: &str = "released-";
+
+/// When accounts become dormant, and what happens to them after.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DormancyPolicy {
+    /// How many days an account can go without signing in before it's
+    /// dormant, or 0 if accounts never are.
+    pub after_days: u32,
+    /// How many days a dormant account has to sign in after being sent a
+    /// notice, before its user ID can be released.
+    pub grace_days: u32,
+    /// Whether user IDs are released once the grace period ends.
+    pub release_user_ids: bool,
+}
+
+impl DormancyPolicy {
+    /// Whether accounts can become dormant at all.
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        self.after_days > 0
+    }
+
+    /// When the user ID of an account that was sent a notice at the given
+    /// time is released, if they ever are.
+    #[must_use]
+    pub fn release_at(&self, noticed_at: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.release_user_ids
+            .then(|| noticed_at + Duration::days(self.grace_days.into()))
+    }
+}
+
+/// An account that was sent a dormancy notice and hasn't signed in since.
+#[derive(SimpleObject, Debug, Clone)]
+pub struct DormantAccount {
+    pub account: Account,
+    /// When the account was sent its notice.
+    pub noticed_at: DateTime<Utc>,
+    /// When the account's user ID was released, if it has been.
+    pub released_at: Option<DateTime<Utc>>,
+}
+
+impl DormantAccount {
+    /// The account as a dormant one, if it was sent a notice.
+    #[must_use]
+    pub fn new(account: Account) -> Option<Self> {
+        Some(Self {
+            noticed_at: account.dormancy_noticed_at?,
+            released_at: account.user_id_released_at,
+            account,
+        })
+    }
+}
+
+/// The user ID that an account is given when its own is released. It's based
+/// on the account's ID, so that it can't already be taken.
+#[must_use]
+pub fn released_user_id(account_id: &Thing) -> String {
+    format!(
+        "{RELEASED_USER_ID_PREFIX}{}",
+        account_id.id.to_raw().to_ascii_lowercase()
+    )
+}
+
+/// Takes each step of the dormancy policy: clears the notices of accounts
+/// that have signed in since, sends notices to accounts that have become
+/// dormant, and releases the user IDs of those whose grace period has ended.
+/// Returns how many accounts were changed.
+#[instrument(skip(persist))]
+pub async fn process_dormant_accounts(
+    persist: &Persist,
+    policy: DormancyPolicy,
+    public_url: Option<&str>,
+) -> Result<usize> {
+    let mut changed = clear_dormancy_notices(persist).await?;
+    if !policy.is_enabled() {
+        return Ok(changed);
+    }
+    changed += notice_dormant_accounts(persist, policy, public_url).await?;
+    if policy.release_user_ids {
+        changed += release_dormant_user_ids(persist, policy).await?;
+    }
+    Ok(changed)
+}
+
+/// Clears the notices of accounts that have signed in since they were sent
+/// one. Returns how many were cleared.
+#[instrument(skip_all)]
+pub async fn clear_dormancy_notices(persist: &Persist) -> Result<usize> {
+    let binary = |l: srql::Idiom, o, r| -> srql::Value {
+        srql::Expression::Binary { l: l.into(), o, r }.into()
+    };
+    let active = select_ids(
+        persist,
+        srql::Expression::Binary {
+            l: binary(
+                srql::field("dormancy_noticed_at"),
+                srql::Operator::NotEqual,
+                srql::Value::None,
+            ),
+            o: srql::Operator::And,
+            r: binary(
+                srql::field("last_active_at"),
+                srql::Operator::MoreThan,
+                srql::field("dormancy_noticed_at").into(),
+            ),
+        },
+    )
+    .await?;
+
+    for account_id in &active {
+        let mut tx = Tx::new();
+        tx.push(srql::Statement::Update(srql::UpdateStatement {
+            what: srql::thing(account_id.clone()),
+            data: srql::Data::SetExpression(vec![
+                (
+                    srql::field("dormancy_noticed_at"),
+                    srql::Operator::Equal,
+                    srql::Value::None,
+                ),
+                (
+                    srql::field("user_id_released_at"),
+                    srql::Operator::Equal,
+                    srql::Value::None,
+                ),
+            ])
+            .into(),
+            output: srql::Output::None.into(),
+            ..Default::default()
+        }));
+        tx.push(srql::Statement::Create(SecurityEvent::create_with_detail(
+            account_id.clone(),
+            SecurityEventKind::DormancyCleared,
+            None,
+            persist.region().map(Into::into),
+            None,
+            persist.clock(),
+            persist.ids(),
+        )));
+        tx.commit(persist).await?;
+    }
+    Ok(active.len())
+}
+
+/// Sends a notice to the accounts that have gone the policy's dormancy period
+/// without signing in, in-app and by email if they have an address. Returns
+/// how many were sent one.
+#[instrument(skip_all)]
+pub async fn notice_dormant_accounts(
+    persist: &Persist,
+    policy: DormancyPolicy,
+    public_url: Option<&str>,
+) -> Result<usize> {
+    let binary = |l: srql::Idiom, o, r| -> srql::Value {
+        srql::Expression::Binary { l: l.into(), o, r }.into()
+    };
+    let and = |l: srql::Value, r: srql::Value| -> srql::Value {
+        srql::Expression::Binary {
+            l,
+            o: srql::Operator::And,
+            r,
+        }
+        .into()
+    };
+    let now = persist.clock().now();
+    let cutoff = now - Duration::days(policy.after_days.into());
+    let cond = and(
+        and(
+            eligible(),
+            binary(
+                srql::field("dormancy_noticed_at"),
+                srql::Operator::Equal,
+                srql::Value::None,
+            ),
+        ),
+        srql::Expression::Binary {
+            l: binary(
+                srql::field("last_active_at"),
+                srql::Operator::Equal,
+                srql::Value::None,
+            ),
+            o: srql::Operator::Or,
+            r: binary(
+                srql::field("last_active_at"),
+                srql::Operator::LessThanOrEqual,
+                srql::Value::Datetime(srql::Datetime(cutoff)),
+            ),
+        }
+        .into(),
+    );
+    let dormant: Vec<Account> = persist
+        .db()
+        .query(srql::SelectStatement {
+            expr: srql::Fields::all(),
+            what: srql::table(ACC_TABLE_NAME),
+            cond: srql::Cond(cond).into(),
+            limit: Some(srql::Limit(DORMANCY_BATCH_SIZE.into())),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+
+    let current = CurrentAccount::default();
+    let notifications = NotificationPersist::new(persist, &current);
+    for account in &dormant {
+        let mut tx = Tx::new();
+        tx.push(srql::Statement::Update(srql::UpdateStatement {
+            what: srql::thing(account.id.clone()),
+            data: srql::Data::SetExpression(vec![(
+                srql::field("dormancy_noticed_at"),
+                srql::Operator::Equal,
+                srql::Value::Datetime(srql::Datetime(now)),
+            )])
+            .into(),
+            output: srql::Output::None.into(),
+            ..Default::default()
+        }));
+        tx.push(srql::Statement::Create(SecurityEvent::create_with_detail(
+            account.id.clone(),
+            SecurityEventKind::DormancyNoticed,
+            None,
+            persist.region().map(Into::into),
+            None,
+            persist.clock(),
+            persist.ids(),
+        )));
+        tx.commit(persist).await?;
+
+        notifications
+            .notify(CreateNotification {
+                account_id: account.id.clone(),
+                kind: NotificationKind::Dormant,
+                actor_id: None,
+                post_id: None,
+                subject_id: None,
+            })
+            .await?;
+        if let Some(email) = &account.email {
+            persist
+                .email()
+                .send(notice_email(account, email, policy, now, public_url));
+        }
+    }
+    Ok(dormant.len())
+}
+
+/// The email telling an account that it's dormant.
+fn notice_email(
+    account: &Account,
+    to: &str,
+    policy: DormancyPolicy,
+    now: DateTime<Utc>,
+    public_url: Option<&str>,
+) -> Email {
+    let what_next = match policy.release_at(now) {
+        Some(release_at) => format!(
+            "If you don't sign in by {}, your user ID will be released for someone else \
+             to register, and your account will be renamed to {}.",
+            release_at.format("%Y-%m-%d"),
+            released_user_id(&account.id),
+        ),
+        None => "Sign in to let us know that you're still using it.".to_owned(),
+    };
+    let link = public_url
+        .map(|public_url| format!("\n\n{public_url}"))
+        .unwrap_or_default();
+    let body = format!(
+        "Your account, {}, hasn't been signed into for over {} days.\n\n{what_next}{link}",
+        account.user_id, policy.after_days,
+    );
+    Email {
+        to: to.to_owned(),
+        subject: "Your account hasn't been used in a while".into(),
+        body,
+    }
+}
+
+/// Releases the user IDs of the accounts whose grace period after being sent
+/// a notice has ended, renaming them so that someone else can register their
+/// old user IDs. Returns how many were released.
+#[instrument(skip_all)]
+pub async fn release_dormant_user_ids(persist: &Persist, policy: DormancyPolicy) -> Result<usize> {
+    #[derive(Deserialize)]
+    struct Due {
+        id: Thing,
+        user_id: String,
+    }
+
+    let binary = |l: srql::Idiom, o, r| -> srql::Value {
+        srql::Expression::Binary { l: l.into(), o, r }.into()
+    };
+    let noticed_before = persist.clock().now() - Duration::days(policy.grace_days.into());
+    let due: Vec<Due> = persist
+        .db()
+        .query(srql::SelectStatement {
+            expr: srql::Fields(
+                vec![
+                    srql::Field::Single {
+                        expr: srql::field("id").into(),
+                        alias: None,
+                    },
+                    srql::Field::Single {
+                        expr: srql::field("user_id").into(),
+                        alias: None,
+                    },
+                ],
+                false,
+            ),
+            what: srql::table(ACC_TABLE_NAME),
+            cond: srql::Cond(
+                srql::Expression::Binary {
+                    l: srql::Expression::Binary {
+                        l: eligible(),
+                        o: srql::Operator::And,
+                        r: binary(
+                            srql::field("user_id_released_at"),
+                            srql::Operator::Equal,
+                            srql::Value::None,
+                        ),
+                    }
+                    .into(),
+                    o: srql::Operator::And,
+                    r: srql::Expression::Binary {
+                        l: binary(
+                            srql::field("dormancy_noticed_at"),
+                            srql::Operator::NotEqual,
+                            srql::Value::None,
+                        ),
+                        o: srql::Operator::And,
+                        r: binary(
+                            srql::field("dormancy_noticed_at"),
+                            srql::Operator::LessThanOrEqual,
+                            srql::Value::Datetime(srql::Datetime(noticed_before)),
+                        ),
+                    }
+                    .into(),
+                }
+                .into(),
+            )
+            .into(),
+            limit: Some(srql::Limit(DORMANCY_BATCH_SIZE.into())),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+
+    for Due { id, user_id } in &due {
+        let released = released_user_id(id);
+        let mut tx = Tx::new();
+        tx.push(srql::Statement::Update(srql::UpdateStatement {
+            what: srql::thing(id.clone()),
+            data: srql::Data::SetExpression(vec![
+                (
+                    srql::field("user_id_skeleton"),
+                    srql::Operator::Equal,
+                    srql::string(user_id_skeleton(&released)).into(),
+                ),
+                // The old user ID's skeleton is free for someone else.
+                (
+                    srql::field("user_id_claim"),
+                    srql::Operator::Equal,
+                    srql::Value::None,
+                ),
+                (
+                    srql::field("user_id"),
+                    srql::Operator::Equal,
+                    srql::string(released).into(),
+                ),
+                (
+                    srql::field("user_id_released_at"),
+                    srql::Operator::Equal,
+                    srql::Value::Datetime(srql::Datetime(persist.clock().now())),
+                ),
+            ])
+            .into(),
+            output: srql::Output::None.into(),
+            ..Default::default()
+        }));
+        tx.push(srql::Statement::Create(SecurityEvent::create_with_detail(
+            id.clone(),
+            SecurityEventKind::UserIdReleased,
+            None,
+            persist.region().map(Into::into),
+            Some(user_id.clone()),
+            persist.clock(),
+            persist.ids(),
+        )));
+        tx.commit(persist).await?;
+    }
+    Ok(due.len())
+}
+
+/// The condition for accounts that can become dormant, which excludes
+/// admins, bots and deleted accounts.
+fn eligible() -> srql::Value {
+    let binary = |l: srql::Idiom, o, r| -> srql::Value {
+        srql::Expression::Binary { l: l.into(), o, r }.into()
+    };
+    srql::Expression::Binary {
+        l: srql::Expression::Binary {
+            l: binary(srql::field("admin"), srql::Operator::NotEqual, true.into()),
+            o: srql::Operator::And,
+            r: binary(srql::field("bot"), srql::Operator::NotEqual, true.into()),
+        }
+        .into(),
+        o: srql::Operator::And,
+        r: binary(
+            srql::field("deleted_at"),
+            srql::Operator::Equal,
+            srql::Value::None,
+        ),
+    }
+    .into()
+}
+
+/// Gets the IDs of the accounts that match a condition, a batch at a time.
+async fn select_ids(persist: &Persist, cond: srql::Expression) -> Result<Vec<Thing>> {
+    #[derive(Deserialize)]
+    struct Selected {
+        id: Thing,
+    }
+
+    let selected: Vec<Selected> = persist
+        .db()
+        .query(srql::SelectStatement {
+            expr: srql::Fields(
+                vec![srql::Field::Single {
+                    expr: srql::field("id").into(),
+                    alias: None,
+                }],
+                false,
+            ),
+            what: srql::table(ACC_TABLE_NAME),
+            cond: srql::Cond(cond.into()).into(),
+            limit: Some(srql::Limit(DORMANCY_BATCH_SIZE.into())),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    Ok(selected.into_iter().map(|selected| selected.id).collect())
+}
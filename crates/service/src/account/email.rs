@@ -0,0 +1,54 @@
+//! The email addresses that accounts can be reached at, such as to reset
+//! their passwords.
+
+use crate::prelude::*;
+
+/// The most characters an email address can have.
+pub const EMAIL_MAX_LEN: usize = 254;
+
+/// Normalizes an email address given for an account, and checks that it
+/// looks like one. Surrounding whitespace is removed and the domain is
+/// lowercased; the part before the `@` is kept as it is, as some mail
+/// servers treat it as case sensitive.
+///
+/// Whether mail can be delivered to the address isn't checked.
+pub fn normalize_email(email: &str) -> Result<String> {
+    let email = email.trim();
+    let invalid = || Error::InputInvalid("email is not a valid email address".into());
+    if email.chars().count() > EMAIL_MAX_LEN {
+        return Err(invalid());
+    }
+    let Some((local, domain)) = email.rsplit_once('@') else {
+        return Err(invalid());
+    };
+    if local.is_empty()
+        || domain.is_empty()
+        || domain.starts_with('.')
+        || domain.ends_with('.')
+        || email
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || matches!(c, '<' | '>' | ','))
+    {
+        return Err(invalid());
+    }
+    Ok(format!("{local}@{}", domain.to_lowercase()))
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::*;
+
+    #[test_case("alice@example.com" => Ok("alice@example.com".into()); "simple")]
+    #[test_case(" Alice@Example.COM " => Ok("Alice@example.com".into()); "normalized")]
+    #[test_case("alice" => matches Err(Error::InputInvalid(_)); "no at")]
+    #[test_case("@example.com" => matches Err(Error::InputInvalid(_)); "no local part")]
+    #[test_case("alice@" => matches Err(Error::InputInvalid(_)); "no domain")]
+    #[test_case("alice@.com" => matches Err(Error::InputInvalid(_)); "leading dot")]
+    #[test_case("al ice@example.com" => matches Err(Error::InputInvalid(_)); "space")]
+    #[test_case("alice@example.com>\r\nBcc:<bob@example.com" => matches Err(Error::InputInvalid(_)); "header injection")]
+    fn test_normalize_email(email: &str) -> Result<String> {
+        normalize_email(email)
+    }
+}
@@ -0,0 +1,201 @@
+use std::time::Duration;
+
+use tokio::{
+    task::JoinHandle,
+    time::{interval, MissedTickBehavior},
+};
+use tracing::{debug, error, trace};
+
+use super::{
+    anonymize_departed_accounts, expire_restrictions, process_dormant_accounts,
+    prune_email_verifications, prune_login_throttles, prune_oidc_flows, prune_passkey_challenges,
+    prune_password_resets, prune_recovery_requests, prune_refresh_tokens, purge_deleted_accounts,
+    DormancyPolicy,
+};
+use crate::{persist::Persist, prelude::*};
+
+/// How often expired refresh, password reset and email verification tokens,
+/// recoveries, passkey challenges, identity provider flows and login
+/// throttles are deleted.
+pub const REFRESH_TOKEN_PRUNE_INTERVAL: Duration = Duration::from_hours(1);
+
+static REFRESH_TOKEN_PRUNE_LOCK: &str = "refresh_token_prune";
+
+/// How often expired account restrictions are cleared.
+pub const RESTRICTION_EXPIRY_INTERVAL: Duration = Duration::from_mins(1);
+
+static RESTRICTION_EXPIRY_LOCK: &str = "account_restriction_expiry";
+
+/// How often deleted accounts whose grace period has ended are purged.
+pub const ACCOUNT_PURGE_INTERVAL: Duration = Duration::from_hours(1);
+
+static ACCOUNT_PURGE_LOCK: &str = "account_purge";
+
+/// How often the references left to purged accounts are looked for.
+pub const ACCOUNT_ANONYMIZATION_INTERVAL: Duration = Duration::from_hours(1);
+
+static ACCOUNT_ANONYMIZATION_LOCK: &str = "account_anonymization";
+
+/// How often accounts are checked against the instance's dormancy policy.
+pub const DORMANCY_CHECK_INTERVAL: Duration = Duration::from_hours(1);
+
+static DORMANCY_CHECK_LOCK: &str = "account_dormancy";
+
+/// Spawns a task that periodically deletes refresh tokens that have
+/// expired, and so can't be used or tell that they've been reused, along
+/// with expired password reset and email verification tokens, passkey
+/// challenges, identity provider flows and login throttles.
+pub fn spawn_refresh_token_pruning(persist: Persist) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = interval(REFRESH_TOKEN_PRUNE_INTERVAL);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+
+            let res = persist
+                .execute_in_lock(REFRESH_TOKEN_PRUNE_LOCK, || async {
+                    let _job = persist.metrics().track_job();
+                    prune(&persist).await
+                })
+                .await;
+
+            match res {
+                Ok(Some(Ok(count))) => {
+                    debug!(count, "Expired tokens and passkey challenges pruned");
+                }
+                Ok(Some(Err(err))) => error!(error = ?err, "Failed to prune refresh tokens"),
+                Ok(None) => trace!("Refresh tokens are already being pruned"),
+                Err(err) => error!(error = ?err, "Failed to lock refresh token pruning"),
+            }
+        }
+    })
+}
+
+/// Deletes the tokens, recoveries, challenges, flows and login throttles
+/// that have expired, returning how many were deleted.
+async fn prune(persist: &Persist) -> Result<usize> {
+    Ok(prune_refresh_tokens(persist).await?
+        + prune_password_resets(persist).await?
+        + prune_email_verifications(persist).await?
+        + prune_recovery_requests(persist).await?
+        + prune_passkey_challenges(persist).await?
+        + prune_oidc_flows(persist).await?
+        + prune_login_throttles(persist).await?)
+}
+
+/// Spawns a task that periodically clears the restrictions that admins put
+/// on accounts once they've expired. Expired restrictions already stop
+/// applying, so this only keeps accounts tidy.
+pub fn spawn_restriction_expiry(persist: Persist) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = interval(RESTRICTION_EXPIRY_INTERVAL);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+
+            let res = persist
+                .execute_in_lock(RESTRICTION_EXPIRY_LOCK, || async {
+                    let _job = persist.metrics().track_job();
+                    expire_restrictions(&persist).await
+                })
+                .await;
+
+            match res {
+                Ok(Some(Ok(count))) => debug!(count, "Expired account restrictions cleared"),
+                Ok(Some(Err(err))) => error!(error = ?err, "Failed to clear account restrictions"),
+                Ok(None) => trace!("Account restrictions are already being cleared"),
+                Err(err) => error!(error = ?err, "Failed to lock account restriction expiry"),
+            }
+        }
+    })
+}
+
+/// Spawns a task that periodically purges the accounts that were deleted
+/// and whose grace period has since ended, along with their content.
+pub fn spawn_account_purges(persist: Persist) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = interval(ACCOUNT_PURGE_INTERVAL);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+
+            let res = persist
+                .execute_in_lock(ACCOUNT_PURGE_LOCK, || async {
+                    let _job = persist.metrics().track_job();
+                    purge_deleted_accounts(&persist).await
+                })
+                .await;
+
+            match res {
+                Ok(Some(Ok(count))) => debug!(count, "Deleted accounts purged"),
+                Ok(Some(Err(err))) => error!(error = ?err, "Failed to purge deleted accounts"),
+                Ok(None) => trace!("Deleted accounts are already being purged"),
+                Err(err) => error!(error = ?err, "Failed to lock account purging"),
+            }
+        }
+    })
+}
+
+/// Spawns a task that periodically looks for the references that are left to
+/// accounts after they've been purged, such as mentions in other accounts'
+/// posts and entries in the event log, and unlinks or anonymizes them.
+pub fn spawn_account_anonymization(persist: Persist) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = interval(ACCOUNT_ANONYMIZATION_INTERVAL);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+
+            let res = persist
+                .execute_in_lock(ACCOUNT_ANONYMIZATION_LOCK, || async {
+                    let _job = persist.metrics().track_job();
+                    anonymize_departed_accounts(&persist).await
+                })
+                .await;
+
+            match res {
+                Ok(Some(Ok(count))) => debug!(count, "Departed accounts anonymized"),
+                Ok(Some(Err(err))) => error!(error = ?err, "Failed to anonymize departed accounts"),
+                Ok(None) => trace!("Departed accounts are already being anonymized"),
+                Err(err) => error!(error = ?err, "Failed to lock account anonymization"),
+            }
+        }
+    })
+}
+
+/// Spawns a task that periodically applies the instance's dormancy policy,
+/// sending notices to accounts that haven't signed in for too long and
+/// releasing the user IDs of those that still haven't once their grace
+/// period ends.
+pub fn spawn_dormancy_checks(
+    persist: Persist,
+    policy: DormancyPolicy,
+    public_url: Option<String>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = interval(DORMANCY_CHECK_INTERVAL);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+
+            let res = persist
+                .execute_in_lock(DORMANCY_CHECK_LOCK, || async {
+                    let _job = persist.metrics().track_job();
+                    process_dormant_accounts(&persist, policy, public_url.as_deref()).await
+                })
+                .await;
+
+            match res {
+                Ok(Some(Ok(count))) => debug!(count, "Dormant accounts checked"),
+                Ok(Some(Err(err))) => error!(error = ?err, "Failed to check dormant accounts"),
+                Ok(None) => trace!("Dormant accounts are already being checked"),
+                Err(err) => error!(error = ?err, "Failed to lock dormancy checks"),
+            }
+        }
+    })
+}
@@ -1,12 +1,17 @@
 use serde::{Deserialize, Serialize};
 
-use super::ACC_TABLE_NAME;
+use super::{ACC_TABLE_NAME, API_KEY_TABLE_NAME, EXTERNAL_IDENTITY_TABLE_NAME, PASSKEY_TABLE_NAME};
 use crate::{migration::Migration, prelude::*};
 
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum AccountMigration {
     #[default]
     Init,
+    UserIdSkeleton,
+    Passkeys,
+    ApiKeys,
+    ExternalIdentities,
+    UserIdClaim,
 }
 
 impl Migration for AccountMigration {
@@ -14,7 +19,12 @@ impl Migration for AccountMigration {
 
     fn next(self) -> Option<Self> {
         match self {
-            Self::Init => None,
+            Self::Init => Some(Self::UserIdSkeleton),
+            Self::UserIdSkeleton => Some(Self::Passkeys),
+            Self::Passkeys => Some(Self::ApiKeys),
+            Self::ApiKeys => Some(Self::ExternalIdentities),
+            Self::ExternalIdentities => Some(Self::UserIdClaim),
+            Self::UserIdClaim => None,
         }
     }
 
@@ -22,6 +32,11 @@ impl Migration for AccountMigration {
         use AccountMigration as S;
         match self {
             S::Init => Self::build_init(statements),
+            S::UserIdSkeleton => Self::build_user_id_skeleton(statements),
+            S::Passkeys => Self::build_passkeys(statements),
+            S::ApiKeys => Self::build_api_keys(statements),
+            S::ExternalIdentities => Self::build_external_identities(statements),
+            S::UserIdClaim => Self::build_user_id_claim(statements),
         }
     }
 }
@@ -34,4 +49,59 @@ impl AccountMigration {
             [srql::field("user_id")],
         ));
     }
+
+    /// Indexes the skeletons of user IDs, so that new ones can be checked
+    /// against them. Existing accounts are given skeletons at startup, as
+    /// they can't be worked out by the database.
+    fn build_user_id_skeleton(statements: &mut Vec<srql::Statement>) {
+        statements.push(srql::define_index(
+            "account_user_id_skeleton_index",
+            ACC_TABLE_NAME,
+            [srql::field("user_id_skeleton")],
+        ));
+    }
+
+    /// Indexes passkeys by their accounts, so that accounts' passkeys can be
+    /// listed. Passkeys are looked up by their credential IDs, which are
+    /// their records' IDs.
+    fn build_passkeys(statements: &mut Vec<srql::Statement>) {
+        statements.push(srql::define_index(
+            "passkey_account_id_index",
+            PASSKEY_TABLE_NAME,
+            [srql::field("account_id")],
+        ));
+    }
+
+    /// Indexes API keys by their accounts, so that accounts' keys can be
+    /// listed and counted.
+    fn build_api_keys(statements: &mut Vec<srql::Statement>) {
+        statements.push(srql::define_index(
+            "api_key_account_id_index",
+            API_KEY_TABLE_NAME,
+            [srql::field("account_id")],
+        ));
+    }
+
+    /// Indexes external identities by their accounts, so that accounts'
+    /// identities can be listed. Identities are looked up by their providers
+    /// and subjects, which their records' IDs are made from.
+    fn build_external_identities(statements: &mut Vec<srql::Statement>) {
+        statements.push(srql::define_index(
+            "external_identity_account_id_index",
+            EXTERNAL_IDENTITY_TABLE_NAME,
+            [srql::field("account_id")],
+        ));
+    }
+
+    /// Makes the skeletons that accounts claim unique, so that lookalike
+    /// user IDs registered at the same time can't both succeed. Accounts
+    /// allowed a lookalike don't claim theirs, and ones registered before
+    /// this are only checked by their indexed skeletons.
+    fn build_user_id_claim(statements: &mut Vec<srql::Statement>) {
+        statements.push(srql::define_uniq_index(
+            "account_user_id_claim_index",
+            ACC_TABLE_NAME,
+            [srql::field("user_id_claim")],
+        ));
+    }
 }
@@ -1,13 +1,34 @@
+mod age;
+mod anonymization;
 mod auth;
+mod deletion;
+mod dev;
+mod dormancy;
+mod email;
+mod job;
 mod migration;
 mod models;
 mod persist;
+mod repo;
+mod restriction;
+mod role;
 mod schema;
+mod user_id;
 
+pub use age::*;
+pub use anonymization::*;
 pub use auth::*;
+pub use deletion::*;
+pub use dormancy::*;
+pub use email::*;
+pub use job::*;
 pub use migration::*;
 pub use models::*;
 pub use persist::*;
+pub use repo::*;
+pub use restriction::*;
+pub use role::*;
 pub use schema::*;
+pub use user_id::*;
 
-static ACC_TABLE_NAME: &str = "account";
+pub static ACC_TABLE_NAME: &str = "account";
@@ -1,12 +1,38 @@
-use async_graphql::{ComplexObject, Context, InputObject, SimpleObject, ID};
-use chrono::{DateTime, Utc};
+use async_graphql::{
+    connection::Connection, ComplexObject, Context, InputObject, MaybeUndefined, SimpleObject,
+    Union, ID,
+};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use ring::rand::SystemRandom;
 use secrecy::SecretString;
 use serde::Deserialize;
 use surrealdb::sql::Thing;
 use tracing::instrument;
 
-use super::{create_access_token, create_refresh_token, StoredPword};
-use crate::{id_obj_impls, prelude::*, EncodingKey};
+use super::{
+    create_access_token, create_two_factor_token, get_recovery_contacts, issue_refresh_token,
+    list_api_keys, list_external_identities, list_passkeys, recovery_codes_remaining,
+    require_admin, totp_enabled, user_id_skeleton, AccountRestriction, AccountRole, ApiKey,
+    ExternalIdentity, ExternalLoginRedirect, Passkey, RecoveryContacts, RestrictionKind,
+    TwoFactorClaims, TWO_FACTOR_CHALLENGE_MINUTES,
+};
+use crate::{
+    credentials::StoredPword,
+    event::{account_counts, AccountCounts},
+    id_obj_impls,
+    license::ContentLicense,
+    locale::{parse_timezone, TimeZoneInfo},
+    organization::{affiliation_of, Organization},
+    persist::Persist,
+    prelude::*,
+    query::{page_complexity, PaginationArgs},
+    quota::{Quota, QuotaUsage},
+    read_marker::ReadMarker,
+    security::{SecurityEvent, SecurityEventCursor, SecurityEventKind},
+    session::Session,
+    spam::SignupSignals,
+    EncodingKey,
+};
 
 static TABLE_NAME: &str = "account";
 
@@ -20,9 +46,20 @@ impl ToAccountThing for ID {
     }
 }
 
+impl ToAccountThing for str {
+    fn to_account_thing(&self) -> Thing {
+        (TABLE_NAME.to_owned(), self.to_owned()).into()
+    }
+}
+
 /// A registered account.
-#[derive(SimpleObject, Debug, Deserialize)]
+///
+/// Fields that only some accounts can see are null for everyone else, with an
+/// error for just that field, so the rest of the account is still returned.
+#[derive(SimpleObject, Debug, Clone, Deserialize)]
 #[graphql(complex)]
+// These are independent flags stored on the record, not a state machine.
+#[allow(clippy::struct_excessive_bools)]
 pub struct Account {
     #[graphql(skip)]
     pub id: Thing,
@@ -38,6 +75,81 @@ pub struct Account {
     /// This is used to invalidate all tokens that were issued before the
     /// revocation.
     pub revoked_at: Option<DateTime<Utc>>,
+    /// Whether other accounts are prevented from quoting this account's posts.
+    #[serde(default)]
+    pub quotes_disabled: bool,
+    /// Whether the account is an administrator of the instance.
+    ///
+    /// The first account to be registered is made an administrator.
+    #[serde(default)]
+    pub admin: bool,
+    /// The account's role, which is ignored for admins. Use
+    /// `effective_role` instead.
+    #[graphql(skip)]
+    #[serde(default)]
+    pub role: AccountRole,
+    /// Whether the account is a bot, run by software rather than a person.
+    /// This is set when the account is created and can't be changed.
+    #[serde(default)]
+    pub bot: bool,
+    /// The account that owns this one, if it is a bot.
+    #[graphql(skip)]
+    pub owner_id: Option<Thing>,
+    /// Whether the account was old enough for age-restricted boards when it
+    /// registered. Bots take this from their owner.
+    #[graphql(skip)]
+    #[serde(default)]
+    pub adult: bool,
+    /// Whether the account has been limited for spam, hiding its posts from
+    /// everyone else.
+    #[graphql(skip)]
+    #[serde(default)]
+    pub limited: bool,
+    /// The restriction that an admin has put on the account, if any. This
+    /// stays until it's cleared after it expires, so check
+    /// `active_restriction` instead.
+    #[graphql(skip)]
+    pub restriction: Option<AccountRestriction>,
+    /// When the account was deleted, if it has been. It can be restored
+    /// until `purge_at`.
+    #[graphql(skip)]
+    pub deleted_at: Option<DateTime<Utc>>,
+    /// When the account and everything that belongs to it will be purged,
+    /// if it has been deleted.
+    #[graphql(skip)]
+    pub purge_at: Option<DateTime<Utc>>,
+    /// A timestamp indicating the last time the account logged in or
+    /// refreshed its tokens. This is only used for instance statistics.
+    #[graphql(skip)]
+    pub last_active_at: Option<DateTime<Utc>>,
+    /// When the account was sent a notice for going without signing in for
+    /// the instance's dormancy period, if it hasn't signed in since.
+    #[graphql(skip)]
+    pub dormancy_noticed_at: Option<DateTime<Utc>>,
+    /// When the account's user ID was released for others to register,
+    /// because it stayed dormant past its grace period.
+    #[graphql(skip)]
+    pub user_id_released_at: Option<DateTime<Utc>>,
+    /// The locale that the server writes text for the account in, such as
+    /// notification titles. Without one, the client's `Accept-Language`
+    /// header is used.
+    #[graphql(skip)]
+    pub locale: Option<String>,
+    /// The IANA timezone that the account is in, if it has chosen one.
+    #[graphql(skip)]
+    pub timezone: Option<String>,
+    /// The address that the account can be emailed at, such as to reset its
+    /// password.
+    #[graphql(skip)]
+    pub email: Option<String>,
+    /// When the account verified that it can be emailed at `email`, if it
+    /// has. This is cleared whenever the address changes.
+    #[graphql(skip)]
+    pub email_verified_at: Option<DateTime<Utc>>,
+    /// The license that the account's posts and media are published under,
+    /// unless they're given their own.
+    #[serde(default)]
+    pub default_license: ContentLicense,
     /// A timestamp indicating the last time the account was updated.
     pub updated_at: DateTime<Utc>,
 
@@ -55,21 +167,280 @@ impl Account {
     async fn id(&self) -> ID {
         self.id.to_gql_id()
     }
+
+    /// The account's role on the instance, which decides what it can do
+    /// beyond posting.
+    async fn role(&self) -> AccountRole {
+        self.effective_role()
+    }
+
+    /// The ID of the account that owns this one, if it is a bot. Every bot has
+    /// an owner.
+    async fn owner_id(&self) -> Option<ID> {
+        self.owner_id.as_ref().map(ToGqlId::to_gql_id)
+    }
+
+    /// The verified organization that the account has confirmed it belongs
+    /// to, if any, shown as a badge on its profile.
+    async fn affiliation(&self, ctx: &Context<'_>) -> GqlResult<Option<Organization>> {
+        affiliation_of(ctx.data_unchecked::<Persist>(), &self.id)
+            .await
+            .extend()
+    }
+
+    /// How many followers the account has, how many accounts it follows and
+    /// how many posts it has published. These are built from the event log,
+    /// so can take a few seconds to catch up with changes.
+    async fn counts(&self, ctx: &Context<'_>) -> GqlResult<AccountCounts> {
+        account_counts(ctx.data_unchecked::<Persist>(), &self.id)
+            .await
+            .extend()
+    }
+
+    /// Whether the account can see age-restricted boards. This can only be
+    /// seen by the account itself.
+    async fn adult(&self, ctx: &Context<'_>) -> GqlResult<Option<bool>> {
+        if ctx.current_account().id().extend()?.to_account_thing() != self.id {
+            return Err(Error::Unauthorized).extend();
+        }
+        Ok(Some(self.adult))
+    }
+
+    /// The account's read markers, used to show how many posts haven't been
+    /// read yet. These can only be seen by the account itself.
+    async fn read_markers(&self, ctx: &Context<'_>) -> GqlResult<Option<Vec<ReadMarker>>> {
+        if ctx.current_account().id().extend()?.to_account_thing() != self.id {
+            return Err(Error::Unauthorized).extend();
+        }
+        ctx.read_marker_persist().list().await.map(Some).extend()
+    }
+
+    /// The account's sign-ins, newest first. These can only be seen by the
+    /// account itself, though admins can list them with `admin.sessions`.
+    async fn sessions(&self, ctx: &Context<'_>) -> GqlResult<Option<Vec<Session>>> {
+        if ctx.current_account().id().extend()?.to_account_thing() != self.id {
+            return Err(Error::Unauthorized).extend();
+        }
+        ctx.session_persist().list().await.map(Some).extend()
+    }
+
+    /// The locale that the server writes text for the account in, if it has
+    /// chosen one. This can only be seen by the account itself.
+    async fn locale(&self, ctx: &Context<'_>) -> GqlResult<Option<&str>> {
+        if ctx.current_account().id().extend()?.to_account_thing() != self.id {
+            return Err(Error::Unauthorized).extend();
+        }
+        Ok(self.locale.as_deref())
+    }
+
+    /// The address that the account can be emailed at, if it has given one.
+    /// This can only be seen by the account itself.
+    async fn email(&self, ctx: &Context<'_>) -> GqlResult<Option<&str>> {
+        if ctx.current_account().id().extend()?.to_account_thing() != self.id {
+            return Err(Error::Unauthorized).extend();
+        }
+        Ok(self.email.as_deref())
+    }
+
+    /// Whether the account has verified its email address, with the link
+    /// or code that was sent to it. This can only be seen by the account
+    /// itself.
+    async fn email_verified(&self, ctx: &Context<'_>) -> GqlResult<Option<bool>> {
+        if ctx.current_account().id().extend()?.to_account_thing() != self.id {
+            return Err(Error::Unauthorized).extend();
+        }
+        Ok(Some(self.is_email_verified()))
+    }
+
+    /// The restriction that an admin has put on the account, if it has one
+    /// that hasn't expired. This can only be seen by the account itself and
+    /// admins, and accounts aren't shown that they've been shadow-limited.
+    async fn restriction(&self, ctx: &Context<'_>) -> GqlResult<Option<&AccountRestriction>> {
+        let persist = ctx.data_unchecked::<Persist>();
+        let own = ctx.current_account().id().extend()?.to_account_thing() == self.id;
+        if !own {
+            require_admin(persist, ctx.current_account())
+                .await
+                .extend()?;
+        }
+        Ok(self
+            .active_restriction(persist.clock().now())
+            .filter(|restriction| !own || restriction.kind != RestrictionKind::ShadowLimited))
+    }
+
+    /// Whether the account has turned on two-factor authentication. This can
+    /// only be seen by the account itself.
+    async fn two_factor_enabled(&self, ctx: &Context<'_>) -> GqlResult<Option<bool>> {
+        if ctx.current_account().id().extend()?.to_account_thing() != self.id {
+            return Err(Error::Unauthorized).extend();
+        }
+        totp_enabled(ctx.data_unchecked::<Persist>(), &self.id)
+            .await
+            .map(Some)
+            .extend()
+    }
+
+    /// The passkeys the account can log in with, oldest first. These can
+    /// only be seen by the account itself.
+    async fn passkeys(&self, ctx: &Context<'_>) -> GqlResult<Option<Vec<Passkey>>> {
+        if ctx.current_account().id().extend()?.to_account_thing() != self.id {
+            return Err(Error::Unauthorized).extend();
+        }
+        list_passkeys(ctx.data_unchecked::<Persist>(), &self.id)
+            .await
+            .map(Some)
+            .extend()
+    }
+
+    /// How many unused recovery codes the account has, if it has turned on
+    /// two-factor authentication. This can only be seen by the account
+    /// itself.
+    async fn recovery_codes_remaining(&self, ctx: &Context<'_>) -> GqlResult<Option<usize>> {
+        if ctx.current_account().id().extend()?.to_account_thing() != self.id {
+            return Err(Error::Unauthorized).extend();
+        }
+        recovery_codes_remaining(ctx.data_unchecked::<Persist>(), &self.id)
+            .await
+            .extend()
+    }
+
+    /// The accounts that can approve recovering the account, if it has
+    /// chosen any. These can only be seen by the account itself.
+    async fn recovery_contacts(&self, ctx: &Context<'_>) -> GqlResult<Option<RecoveryContacts>> {
+        if ctx.current_account().id().extend()?.to_account_thing() != self.id {
+            return Err(Error::Unauthorized).extend();
+        }
+        get_recovery_contacts(ctx.data_unchecked::<Persist>(), &self.id)
+            .await
+            .extend()
+    }
+
+    /// The identities from external providers that the account can log in
+    /// with, oldest first. These can only be seen by the account itself.
+    async fn external_identities(
+        &self,
+        ctx: &Context<'_>,
+    ) -> GqlResult<Option<Vec<ExternalIdentity>>> {
+        if ctx.current_account().id().extend()?.to_account_thing() != self.id {
+            return Err(Error::Unauthorized).extend();
+        }
+        list_external_identities(ctx.data_unchecked::<Persist>(), &self.id)
+            .await
+            .map(Some)
+            .extend()
+    }
+
+    /// The API keys the account has created, oldest first. These can only
+    /// be seen by the account itself.
+    async fn api_keys(&self, ctx: &Context<'_>) -> GqlResult<Option<Vec<ApiKey>>> {
+        if ctx.current_account().id().extend()?.to_account_thing() != self.id {
+            return Err(Error::Unauthorized).extend();
+        }
+        list_api_keys(ctx.data_unchecked::<Persist>(), &self.id)
+            .await
+            .map(Some)
+            .extend()
+    }
+
+    /// The timezone that the account has chosen, and its current UTC offset.
+    /// This can only be seen by the account itself.
+    async fn timezone(&self, ctx: &Context<'_>) -> GqlResult<Option<TimeZoneInfo>> {
+        if ctx.current_account().id().extend()?.to_account_thing() != self.id {
+            return Err(Error::Unauthorized).extend();
+        }
+        let now = ctx.data_unchecked::<Persist>().clock().now();
+        Ok(self
+            .timezone
+            .as_deref()
+            .and_then(parse_timezone)
+            .map(|tz| TimeZoneInfo::new(tz, now)))
+    }
+
+    /// How much the account can create. This can only be seen by the account
+    /// itself and admins.
+    async fn quota(&self, ctx: &Context<'_>) -> GqlResult<Option<Quota>> {
+        ctx.quota_persist().get(&self.id).await.map(Some).extend()
+    }
+
+    /// How much of its quota the account has used. This can only be seen by
+    /// the account itself and admins.
+    async fn quota_usage(&self, ctx: &Context<'_>) -> GqlResult<Option<QuotaUsage>> {
+        ctx.quota_persist().usage(&self.id).await.map(Some).extend()
+    }
+
+    /// The account's security log, newest first: sign-ins, sign-ins from new
+    /// devices, and token revocations. When `kinds` is given, only events of
+    /// those kinds are listed. This can only be seen by the account itself.
+    #[graphql(complexity = "page_complexity(first, last, child_complexity)")]
+    async fn security_events(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+        kinds: Option<Vec<SecurityEventKind>>,
+    ) -> GqlResult<Connection<SecurityEventCursor, SecurityEvent>> {
+        if ctx.current_account().id().extend()?.to_account_thing() != self.id {
+            return Err(Error::Unauthorized).extend();
+        }
+        ctx.security_event_persist()
+            .list()
+            .extend()?
+            .with_kinds(kinds)
+            .with_pagination(
+                PaginationArgs {
+                    after,
+                    before,
+                    first,
+                    last,
+                }
+                .warn_if_clamped(ctx)
+                .validate()
+                .extend()?,
+            )
+            .execute()
+            .await
+            .extend()
+    }
 }
 
 id_obj_impls!(Account);
 
 impl Account {
-    pub fn create(creds: StoredPword, params: CreateAccount) -> srql::CreateStatement {
+    /// Whether the account has an email address that it has verified.
+    #[must_use]
+    pub fn is_email_verified(&self) -> bool {
+        self.email.is_some() && self.email_verified_at.is_some()
+    }
+
+    pub fn create(
+        creds: StoredPword,
+        admin: bool,
+        owner_id: Option<Thing>,
+        adult: bool,
+        params: CreateAccount,
+        clock: &dyn Clock,
+        ids: &dyn IdGen,
+    ) -> srql::CreateStatement {
         let mut create = vec![];
         params.append(&mut create);
+        admin.push_field(srql::field("admin"), &mut create);
+        owner_id
+            .is_some()
+            .push_field(srql::field("bot"), &mut create);
+        owner_id.push_field(srql::field("owner_id"), &mut create);
+        adult.push_field(srql::field("adult"), &mut create);
+        clock
+            .now()
+            .push_field(srql::field("last_active_at"), &mut create);
         creds
             .salt
             .push_field(srql::field("pword_salt"), &mut create);
         creds
             .hash
             .push_field(srql::field("pword_hash"), &mut create);
-        srql::obj_create_query(TABLE_NAME, create)
+        srql::obj_create_query(TABLE_NAME, create, ids)
     }
 }
 
@@ -79,34 +450,142 @@ impl Account {
 pub struct AuthenticatedAccount {
     /// The account that has been authenticated.
     pub account: Account,
+    /// The session the tokens are for. Tokens don't outlive it.
+    #[serde(default)]
+    pub session: Option<Session>,
+    /// The family of refresh tokens that a new refresh token continues, if
+    /// the account was authenticated with one.
+    #[graphql(skip)]
+    #[serde(skip)]
+    pub refresh_family_id: Option<Thing>,
 }
 
 #[ComplexObject]
 impl AuthenticatedAccount {
-    /// A refresh token accociated with the account.
+    /// A refresh token accociated with the account. It can be exchanged for
+    /// new tokens once, with `refreshToken`.
+    ///
+    /// A new token is issued each time this is requested.
     #[instrument(skip_all)]
     async fn refresh_token(&self, ctx: &Context<'_>) -> GqlResult<String> {
-        create_refresh_token(
-            self.account.id.to_gql_id(),
-            ctx.data_unchecked::<EncodingKey>(),
+        let persist = ctx.data_unchecked::<Persist>();
+        issue_refresh_token(
+            persist,
+            ctx.data_unchecked::<SystemRandom>(),
+            self.account.id.clone(),
+            self.session.as_ref().map(|session| session.id.clone()),
+            self.refresh_family_id.clone(),
+            self.expires_by(persist),
         )
+        .await
         .extend()
     }
 
     /// An access token accociated with the account.
     #[instrument(skip_all)]
     async fn access_token(&self, ctx: &Context<'_>) -> GqlResult<String> {
+        let persist = ctx.data_unchecked::<Persist>();
         create_access_token(
-            &PartialAccount::new(self.account.id.to_gql_id(), self.account.user_id.clone()),
+            &self.partial().with_region(persist.region()),
+            self.expires_by(persist),
             ctx.data_unchecked::<EncodingKey>(),
+            persist.clock(),
         )
         .extend()
     }
 }
 
+impl AuthenticatedAccount {
+    #[must_use]
+    pub fn with_session(mut self, session: Session) -> Self {
+        self.session = Some(session);
+        self
+    }
+
+    /// What the account's access tokens say about it, including the session
+    /// they're for so that they stop working once it's revoked.
+    #[must_use]
+    pub fn partial(&self) -> PartialAccount {
+        PartialAccount::new(self.account.id.to_gql_id(), self.account.user_id.clone())
+            .with_session_id(self.session.as_ref().map(|session| session.id.to_gql_id()))
+    }
+
+    /// When the account's tokens have to expire by, so that they don't
+    /// outlive the session.
+    pub fn expires_by(&self, persist: &Persist) -> Option<DateTime<Utc>> {
+        self.session
+            .as_ref()?
+            .expiry(persist.sessions(), self.account.admin)
+    }
+}
+
 impl From<Account> for AuthenticatedAccount {
     fn from(account: Account) -> Self {
-        Self { account }
+        Self {
+            account,
+            session: None,
+            refresh_family_id: None,
+        }
+    }
+}
+
+/// The result of logging in. Accounts with two-factor authentication turned
+/// on still need to give a code with `verifyTwoFactor`, and logging in with
+/// an identity provider starts by sending the browser to it.
+#[derive(Union, Debug)]
+pub enum LoginResult {
+    Authenticated(Box<AuthenticatedAccount>),
+    TwoFactorRequired(TwoFactorRequired),
+    ExternalLoginRedirect(ExternalLoginRedirect),
+}
+
+#[cfg(test)]
+impl LoginResult {
+    /// Gets the account that was logged into, panicking if it needs a second
+    /// factor.
+    pub fn unwrap_authenticated(self) -> AuthenticatedAccount {
+        match self {
+            Self::Authenticated(acc) => *acc,
+            Self::TwoFactorRequired(_) => panic!("Login needs a second factor"),
+            Self::ExternalLoginRedirect(_) => panic!("Login needs a redirect"),
+        }
+    }
+}
+
+/// Says that the password was right, but a code from an authenticator app or
+/// a recovery code is needed to finish logging in.
+#[derive(SimpleObject, Debug, Clone)]
+#[graphql(complex)]
+pub struct TwoFactorRequired {
+    #[graphql(skip)]
+    pub account_id: Thing,
+    /// When `challengeToken` stops working, and logging in has to start again.
+    pub expires_at: DateTime<Utc>,
+}
+
+#[ComplexObject]
+impl TwoFactorRequired {
+    /// A token to give to `verifyTwoFactor` along with the code, or to
+    /// `beginPasskeyLogin` to use a passkey instead. It can't be used to
+    /// access the account.
+    #[instrument(skip_all)]
+    async fn challenge_token(&self, ctx: &Context<'_>) -> GqlResult<String> {
+        let persist = ctx.data_unchecked::<Persist>();
+        create_two_factor_token(
+            &TwoFactorClaims::new(self.account_id.to_gql_id(), persist.clock().now()),
+            ctx.data_unchecked::<EncodingKey>(),
+        )
+        .extend()
+    }
+}
+
+impl TwoFactorRequired {
+    #[must_use]
+    pub fn new(account_id: Thing, now: DateTime<Utc>) -> Self {
+        Self {
+            account_id,
+            expires_at: now + Duration::minutes(TWO_FACTOR_CHALLENGE_MINUTES),
+        }
     }
 }
 
@@ -125,15 +604,116 @@ pub struct CreateAccount {
     /// Whether this is required will depend on the server's configuration.
     #[graphql(validator(min_length = 1, max_length = 1024))]
     pub invite: Option<String>,
+    /// Whether the account is a bot. Bots must be created while logged into
+    /// the (non-bot) account that will own them. Defaults to `false`.
+    pub bot: Option<bool>,
+    /// The IDs of the policies being accepted. The latest version of every
+    /// policy the instance has published must be included.
+    pub accepted_policy_ids: Option<Vec<ID>>,
+    /// The person's date of birth, which is only used to check their age and
+    /// isn't stored. Whether this is required depends on the instance's
+    /// minimum age. Bots don't need one.
+    pub birthdate: Option<NaiveDate>,
+    /// The value of a field that the registration form hides from people,
+    /// such as one positioned off-screen. People leave it empty, but bots
+    /// that fill in every field don't, so registrations that fill it in are
+    /// more likely to be flagged as spam.
+    #[graphql(validator(max_length = 1024))]
+    pub honeypot: Option<String>,
+    /// When the registration form was shown. Registrations that are made
+    /// faster than a person could fill in the form are more likely to be
+    /// flagged as spam.
+    pub form_shown_at: Option<DateTime<Utc>>,
+    /// Registers the user ID even if it looks like one that's already in use
+    /// or reserved. Only admins can do this. Defaults to `false`.
+    pub allow_confusable: Option<bool>,
+    /// The address that the account can be emailed at. A link to verify it
+    /// is sent there once the account is registered.
+    #[graphql(validator(max_length = 254))]
+    pub email: Option<String>,
+}
+
+impl CreateAccount {
+    /// The bot signals that the client sent with the registration.
+    #[must_use]
+    pub fn signup_signals(&self) -> SignupSignals {
+        SignupSignals {
+            honeypot_filled: self
+                .honeypot
+                .as_deref()
+                .is_some_and(|v| !v.trim().is_empty()),
+            form_shown_at: self.form_shown_at,
+        }
+    }
 }
 
 impl CreateObject for CreateAccount {
     fn append(self, expr: &mut srql::SetExpr) {
+        let skeleton = user_id_skeleton(&self.user_id);
+        // Accounts that were checked for lookalikes claim their skeleton, so
+        // that lookalikes registered at the same time can't both succeed.
+        if !self.allow_confusable.unwrap_or_default() {
+            skeleton
+                .clone()
+                .push_field(srql::field("user_id_claim"), expr);
+        }
+        skeleton.push_field(srql::field("user_id_skeleton"), expr);
         self.user_id.push_field(srql::field("user_id"), expr);
+        self.email.push_field(srql::field("email"), expr);
         // Other fields are intentionally omitted.
     }
 }
 
+/// The account settings to change.
+#[derive(InputObject, Debug, Default, Clone, PartialEq, Eq)]
+pub struct UpdateAccount {
+    /// Whether other accounts are prevented from quoting this account's posts.
+    /// If not given, the setting is not changed.
+    pub quotes_disabled: Option<bool>,
+    /// The locale that the server writes text for the account in, such as
+    /// `en-GB`. If not given, the locale is not changed. If null is given, the
+    /// client's `Accept-Language` header is used instead.
+    #[graphql(validator(max_length = 35))]
+    pub locale: MaybeUndefined<String>,
+    /// The IANA timezone that the account is in, such as `Europe/London`. If
+    /// not given, the timezone is not changed. If null is given, the timezone
+    /// is cleared.
+    #[graphql(validator(max_length = 64))]
+    pub timezone: MaybeUndefined<String>,
+    /// The license that new posts and media are published under, unless
+    /// they're given their own. Content that has already been published keeps
+    /// its license. If not given, the default is not changed.
+    pub default_license: Option<ContentLicense>,
+    /// The address that the account can be emailed at, which is needed to
+    /// reset its password. If not given, the address is not changed. If null
+    /// is given, the address is removed. A new address has to be verified
+    /// again, with the link that's sent to it.
+    #[graphql(validator(max_length = 254))]
+    pub email: MaybeUndefined<String>,
+}
+
+impl IntoUpdateQuery for UpdateAccount {
+    fn into_update(self, thing: srql::Thing) -> Option<srql::UpdateStatement> {
+        let mut update = vec![];
+        self.quotes_disabled
+            .push_field(srql::field("quotes_disabled"), &mut update);
+        self.locale.push_field(srql::field("locale"), &mut update);
+        self.timezone
+            .push_field(srql::field("timezone"), &mut update);
+        self.default_license
+            .push_field(srql::field("default_license"), &mut update);
+        if !self.email.is_undefined() {
+            update.push((
+                srql::field("email_verified_at"),
+                srql::Operator::Equal,
+                srql::Value::None,
+            ));
+        }
+        self.email.push_field(srql::field("email"), &mut update);
+        srql::obj_update_query(thing, update)
+    }
+}
+
 /// The information needed to authenticate an account.
 #[derive(InputObject, Debug)]
 pub struct AuthCreds {
@@ -155,28 +735,71 @@ mod private {
     use chrono::{DateTime, Utc};
     use serde::{Deserialize, Serialize};
 
-    use crate::error::{Error, Result};
+    use crate::{
+        account::ApiKeyScope,
+        error::{Error, Result},
+        provider::SharedClock,
+    };
 
+    /// The account a request was made as. Requests made with an API key are
+    /// limited to its scopes.
     #[derive(Debug, Default, Clone, PartialEq, Eq)]
-    pub struct CurrentAccount(Inner);
+    pub struct CurrentAccount(Inner, Option<Vec<ApiKeyScope>>);
 
-    #[derive(Debug, Default, Clone, PartialEq, Eq)]
+    #[derive(Debug, Default, Clone)]
     enum Inner {
         #[default]
         Unauthenticated,
-        Authenticated(PartialAccount, DateTime<Utc>),
+        Authenticated(PartialAccount, DateTime<Utc>, SharedClock),
+    }
+
+    // The clock is only used to check the expiry, so it isn't compared.
+    impl PartialEq for Inner {
+        fn eq(&self, other: &Self) -> bool {
+            match (self, other) {
+                (Self::Unauthenticated, Self::Unauthenticated) => true,
+                (Self::Authenticated(a, a_expiry, _), Self::Authenticated(b, b_expiry, _)) => {
+                    a == b && a_expiry == b_expiry
+                }
+                _ => false,
+            }
+        }
     }
 
+    impl Eq for Inner {}
+
     impl CurrentAccount {
-        pub fn new(acc: PartialAccount, expiry: DateTime<Utc>) -> Self {
-            Self(Inner::Authenticated(acc, expiry))
+        /// Creates an account that is authenticated until the given expiry,
+        /// according to the given clock.
+        pub fn new(acc: PartialAccount, expiry: DateTime<Utc>, clock: SharedClock) -> Self {
+            Self(Inner::Authenticated(acc, expiry, clock), None)
+        }
+
+        /// Limits the account to the scopes of the API key the request was
+        /// made with.
+        #[must_use]
+        pub fn with_scopes(mut self, scopes: Vec<ApiKeyScope>) -> Self {
+            self.1 = Some(scopes);
+            self
+        }
+
+        /// Whether the request was made with an API key, rather than by
+        /// signing in.
+        pub fn is_api_key(&self) -> bool {
+            self.1.is_some()
+        }
+
+        /// Whether the account can do what needs the given scope. Accounts
+        /// that signed in can do anything.
+        pub fn has_scope(&self, scope: ApiKeyScope) -> bool {
+            self.1.as_ref().is_none_or(|scopes| scopes.contains(&scope))
         }
 
         pub fn account(&self) -> Result<&PartialAccount> {
             match &self.0 {
                 Inner::Unauthenticated => Err(Error::Unauthenticated),
-                Inner::Authenticated(acc, expiry) => {
-                    if Utc::now() >= *expiry {
+                Inner::Authenticated(acc, expiry, clock) => {
+                    if clock.now() >= *expiry {
                         Err(Error::Unauthenticated)
                     } else {
                         Ok(acc)
@@ -192,6 +815,12 @@ mod private {
         pub fn user_id(&self) -> Result<&str> {
             self.account().map(|acc| acc.uid.as_str())
         }
+
+        /// The session that the account's token was issued for, if it was
+        /// issued for one.
+        pub fn session_id(&self) -> Result<Option<&ID>> {
+            self.account().map(|acc| acc.sid.as_ref())
+        }
     }
 
     /// Account information stored in the JWT.
@@ -199,11 +828,44 @@ mod private {
     pub struct PartialAccount {
         id: ID,
         uid: String,
+        /// The session the token was issued for. Tokens issued before
+        /// sessions were tracked don't have one.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sid: Option<ID>,
+        /// The region of the server that issued the token, when the
+        /// instance is deployed across several.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        rgn: Option<String>,
     }
 
     impl PartialAccount {
         pub fn new(id: ID, uid: String) -> Self {
-            Self { id, uid }
+            Self {
+                id,
+                uid,
+                sid: None,
+                rgn: None,
+            }
+        }
+
+        #[must_use]
+        pub fn with_session_id(mut self, session_id: Option<ID>) -> Self {
+            self.sid = session_id;
+            self
+        }
+
+        pub fn session_id(&self) -> Option<&ID> {
+            self.sid.as_ref()
+        }
+
+        #[must_use]
+        pub fn with_region(mut self, region: Option<&str>) -> Self {
+            self.rgn = region.map(Into::into);
+            self
+        }
+
+        pub fn region(&self) -> Option<&str> {
+            self.rgn.as_deref()
         }
 
         pub fn id(&self) -> &ID {
@@ -230,16 +892,19 @@ mod private {
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+
     use async_graphql::ID;
     use chrono::Utc;
 
     use super::*;
+    use crate::provider::{MockClock, SystemClock};
 
     #[test]
     fn current_account() {
         let acc = PartialAccount::new("test".to_owned().into(), "test".into());
         let expiry = Utc::now() + chrono::Duration::minutes(5);
-        let current = CurrentAccount::new(acc.clone(), expiry);
+        let current = CurrentAccount::new(acc.clone(), expiry, Arc::new(SystemClock));
 
         println!("{acc:?}\n{expiry:?}\n{current:?}");
 
@@ -252,7 +917,7 @@ mod tests {
     fn current_account_expired() {
         let acc = PartialAccount::new("test".to_owned().into(), "test".to_owned());
         let expiry = Utc::now();
-        let current = CurrentAccount::new(acc.clone(), expiry);
+        let current = CurrentAccount::new(acc.clone(), expiry, Arc::new(SystemClock));
 
         println!("{acc:?}\n{expiry:?}\n{current:?}");
 
@@ -261,6 +926,18 @@ mod tests {
         assert!(current.user_id().is_err());
     }
 
+    #[test]
+    fn current_account_expires() {
+        let acc = PartialAccount::new("test".to_owned().into(), "test".to_owned());
+        let clock = MockClock::default();
+        let expiry = clock.now() + chrono::Duration::minutes(5);
+        let current = CurrentAccount::new(acc, expiry, Arc::new(clock.clone()));
+        assert!(current.account().is_ok());
+
+        clock.advance(chrono::Duration::minutes(5));
+        assert!(current.account().is_err());
+    }
+
     #[test]
     fn current_account_default() {
         let current = CurrentAccount::default();
@@ -1,26 +1,68 @@
 #[cfg(test)]
 mod tests;
 
+use std::sync::Arc;
+
+use async_graphql::{MaybeUndefined, ID};
 #[cfg(test)]
 use base64::{prelude::BASE64_STANDARD_NO_PAD, Engine as _};
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
+use futures::Stream;
 #[cfg(test)]
 use ring::rand::SecureRandom as _;
 use ring::rand::SystemRandom;
-use secrecy::ExposeSecret as _;
-use tracing::instrument;
+use secrecy::{ExposeSecret as _, SecretString};
+use serde::Deserialize;
+use tracing::{instrument, warn};
 
 use super::{
-    create_creds, verify_creds, verify_refresh_token, Account, AuthCreds, AuthenticatedAccount,
-    CreateAccount, CurrentAccount, ACC_TABLE_NAME,
+    approve_recovery_request, begin_external_flow, begin_passkey_login, begin_passkey_registration,
+    begin_totp_enrollment, check_birthdate, check_login_throttle, clear_login_throttle,
+    confirm_totp_enrollment, create_api_key, find_recovery_request, finish_external_flow,
+    finish_passkey_login, finish_passkey_registration, get_recovery_contacts, get_recovery_request,
+    is_opaque_refresh_token, is_reserved_lookalike, issue_email_verification, issue_password_reset,
+    issue_recovery_request, link_external_identity, normalize_email, normalize_user_id,
+    pending_email_verification, pending_recovery_requests, purge_time, record_login_failure,
+    regenerate_recovery_codes, remove_passkey, remove_totp, require_permission, require_role,
+    require_verified_email, revoke_api_key, set_recovery_contacts, totp_enabled,
+    unlink_external_identity, use_email_verification, use_external_identity, use_password_reset,
+    use_recovery_request, use_refresh_token, user_id_skeleton, verify_disown_token,
+    verify_refresh_token, verify_totp, verify_two_factor_token, Account, AccountRestriction,
+    AccountRole, ApiKey, AuthCreds, AuthenticatedAccount, CreateAccount, CreateApiKey,
+    CreatedApiKey, CurrentAccount, DormantAccount, ExternalIdentity, ExternalLoginRedirect,
+    LoginResult, LoginThrottleKey, Passkey, PasskeyAssertion, PasskeyCreationOptions,
+    PasskeyRegistration, PasskeyRequestOptions, Permission, RecoveryContacts, RecoveryRequest,
+    RelyingParty, RestrictionKind, SharedAccountRepo, StartedRecovery, SurrealAccountRepo,
+    TotpEnrollment, TotpVerified, TwoFactorRequired, UpdateAccount, ACC_TABLE_NAME,
+    EMAIL_VERIFICATION_HOURS, EMAIL_VERIFICATION_RESEND_MINUTES, LOW_RECOVERY_CODES,
+    MAX_PENDING_RECOVERIES, MAX_RECOVERY_CONTACTS, PASSWORD_RESET_MINUTES, RESTRICTION_MAX_HOURS,
+};
+use crate::{
+    config::{OidcConfig, OidcProvider, DEFAULT_DELETION_GRACE_DAYS},
+    email::Email,
+    event::{DomainEvent, DomainEventKind},
+    locale::{parse_locale, parse_timezone},
+    notification::{CreateNotification, NotificationKind, NotificationPersist},
+    persist::Persist,
+    policy::PolicyPersist,
+    prelude::*,
+    security::{get_any, SecurityEventKind, SecurityEventPersist, SECURITY_EVENT_TABLE_NAME},
+    session::{resume_session, revoke_sessions_of, ClientMeta},
 };
-use crate::{persist::Persist, prelude::*};
 
 pub struct AccountPersist<'a> {
     persist: &'a Persist,
     current: &'a CurrentAccount,
     csrng: &'a SystemRandom,
     jwt_dec_key: &'a jsonwebtoken::DecodingKey,
+    min_age: u8,
+    allow_confusable_user_ids: bool,
+    rp: Option<RelyingParty>,
+    public_url: Option<&'a str>,
+    oidc: Option<&'a OidcConfig>,
+    deletion_grace_days: u32,
+    verified_email_required: bool,
+    repo: SharedAccountRepo,
 }
 
 impl<'a> AccountPersist<'a> {
@@ -35,53 +77,1015 @@ impl<'a> AccountPersist<'a> {
             current,
             csrng,
             jwt_dec_key,
+            min_age: 0,
+            allow_confusable_user_ids: false,
+            rp: None,
+            public_url: None,
+            oidc: None,
+            deletion_grace_days: DEFAULT_DELETION_GRACE_DAYS,
+            verified_email_required: false,
+            repo: Arc::new(SurrealAccountRepo::new(persist.clone())),
         }
     }
 
+    /// Sets where accounts are looked up from, instead of the database.
+    #[must_use]
+    pub fn with_repo(mut self, repo: SharedAccountRepo) -> Self {
+        self.repo = repo;
+        self
+    }
+
+    /// Sets the minimum age, in years, that people must be to register.
+    #[must_use]
+    pub fn with_min_age(mut self, min_age: u8) -> Self {
+        self.min_age = min_age;
+        self
+    }
+
+    /// Sets whether user IDs can be registered that look like ones that are
+    /// already in use or reserved.
+    #[must_use]
+    pub fn with_confusable_user_ids(mut self, allow: bool) -> Self {
+        self.allow_confusable_user_ids = allow;
+        self
+    }
+
+    /// Sets the URL the instance is reached at, which passkeys are created
+    /// for and email verification links point to. Passkeys can't be used
+    /// without one, and verification emails have a code instead of a link.
+    #[must_use]
+    pub fn with_public_url(mut self, public_url: Option<&'a str>) -> Self {
+        self.rp = public_url.and_then(RelyingParty::from_public_url);
+        self.public_url = public_url;
+        self
+    }
+
+    /// Sets the identity providers that accounts can log in with.
+    #[must_use]
+    pub fn with_oidc(mut self, oidc: Option<&'a OidcConfig>) -> Self {
+        self.oidc = oidc;
+        self
+    }
+
+    /// Sets how many days deleted accounts can be restored for before
+    /// they're purged.
+    #[must_use]
+    pub fn with_deletion_grace_days(mut self, days: u32) -> Self {
+        self.deletion_grace_days = days;
+        self
+    }
+
+    /// Sets whether accounts have to verify their email address before
+    /// they can do what `require_verified_email` guards.
+    #[must_use]
+    pub fn with_verified_email_required(mut self, required: bool) -> Self {
+        self.verified_email_required = required;
+        self
+    }
+
     #[instrument(skip_all)]
     pub async fn current(&self) -> Result<Option<Account>> {
         let id = self.current.id()?;
         self.get(id).await
     }
 
+    /// Logs into an account with its password. If the account has turned on
+    /// two-factor authentication, it isn't logged into until a code is given
+    /// with `verify_two_factor`.
+    ///
+    /// Failed logins are counted against the account and the client's
+    /// address, and too many lock logging in with `TooManyAttempts`.
     #[instrument(skip_all)]
-    pub async fn login(&self, creds: AuthCreds) -> Result<AuthenticatedAccount> {
-        let Some(acc) = self.get_by_user_id(&creds.user_id).await? else {
+    pub async fn login(
+        &self,
+        creds: AuthCreds,
+        client: Option<&ClientMeta>,
+    ) -> Result<LoginResult> {
+        let acc = self.get_by_user_id(&creds.user_id).await?;
+        let mut keys = vec![];
+        if let Some(acc) = &acc {
+            keys.push(LoginThrottleKey::Account(acc.id.clone()));
+        }
+        if let Some(ip) = client.and_then(|client| client.ip) {
+            keys.push(LoginThrottleKey::Ip(ip));
+        }
+        check_login_throttle(self.persist, &keys).await?;
+
+        let Some(acc) = acc else {
+            self.login_failed(&keys).await?;
             return Err(Error::CredentialsInvalid);
         };
+        if let Err(err) = self.check_pword(&acc, &creds.pword).await {
+            if err == Error::CredentialsInvalid {
+                self.login_failed(&keys).await?;
+            }
+            return Err(err);
+        }
+        clear_login_throttle(self.persist, &LoginThrottleKey::Account(acc.id.clone())).await?;
+        let now = self.persist.clock().now();
+        if acc.is_suspended(now) {
+            return Err(Error::AccountSuspended);
+        }
+        if acc.deleted_at.is_some() {
+            return Err(Error::AccountDeleted);
+        }
+        if totp_enabled(self.persist, &acc.id).await? {
+            return Ok(LoginResult::TwoFactorRequired(TwoFactorRequired::new(
+                acc.id, now,
+            )));
+        }
 
-        verify_creds(&creds.pword, &acc.pword_salt, &acc.pword_hash)?;
+        Ok(LoginResult::Authenticated(Box::new(
+            self.touch(acc).await?.into(),
+        )))
+    }
 
-        Ok(acc.into())
+    /// Counts a failed login against each key, noting any that it locks.
+    async fn login_failed(&self, keys: &[LoginThrottleKey]) -> Result<()> {
+        for key in keys {
+            if record_login_failure(self.persist, key).await? {
+                self.persist.metrics().count_login_lockout();
+                warn!(?key, "Too many failed logins, logging in is locked");
+            }
+        }
+        Ok(())
     }
 
+    /// Finishes logging into an account with two-factor authentication,
+    /// using the challenge token from `login` and a code from its
+    /// authenticator app or one of its recovery codes.
     #[instrument(skip_all)]
-    pub async fn refresh(&self, refresh_token: String) -> Result<AuthenticatedAccount> {
-        let Ok(claims) = verify_refresh_token(&refresh_token, self.jwt_dec_key) else {
+    pub async fn verify_two_factor(
+        &self,
+        challenge_token: &str,
+        code: &str,
+    ) -> Result<AuthenticatedAccount> {
+        let claims =
+            verify_two_factor_token(challenge_token, self.jwt_dec_key, self.persist.clock())?;
+        let Some(acc) = self.get(claims.id()).await? else {
             return Err(Error::CredentialsInvalid);
         };
+        if acc.is_suspended(self.persist.clock().now()) {
+            return Err(Error::AccountSuspended);
+        }
+        let verified = self.check_totp(&acc.id, code).await?;
+        if let TotpVerified::RecoveryCode { remaining } = verified {
+            if remaining <= LOW_RECOVERY_CODES {
+                NotificationPersist::new(self.persist, self.current)
+                    .notify(CreateNotification {
+                        account_id: acc.id.clone(),
+                        kind: NotificationKind::RecoveryCodesLow,
+                        actor_id: None,
+                        post_id: None,
+                        subject_id: None,
+                    })
+                    .await?;
+            }
+        }
 
-        let Some(acc) = self.get(claims.id()).await? else {
+        Ok(self.touch(acc).await?.into())
+    }
+
+    /// Starts turning on two-factor authentication for the current account.
+    /// It isn't turned on until a code from the authenticator app is given to
+    /// `confirm_two_factor`.
+    #[instrument(skip_all)]
+    pub async fn enroll_two_factor(&self) -> Result<TotpEnrollment> {
+        let Some(acc) = self.current().await? else {
+            return Err(Error::Unauthenticated);
+        };
+        begin_totp_enrollment(self.persist, self.csrng, &acc.id, &acc.user_id).await
+    }
+
+    /// Turns on two-factor authentication for the current account, if the
+    /// code from its authenticator app is right. Returns its recovery codes.
+    #[instrument(skip_all)]
+    pub async fn confirm_two_factor(&self, code: &str) -> Result<Vec<String>> {
+        let account_id = self.current.id()?.to_account_thing();
+        let codes = confirm_totp_enrollment(self.persist, self.csrng, &account_id, code).await?;
+        SecurityEventPersist::new(self.persist, self.current)
+            .log(account_id, SecurityEventKind::TwoFactorEnabled, None)
+            .await?;
+        Ok(codes)
+    }
+
+    /// Turns off two-factor authentication for the current account. A code
+    /// from its authenticator app or a recovery code is needed, so that
+    /// whoever finds the account signed in can't turn it off.
+    #[instrument(skip_all)]
+    pub async fn disable_two_factor(&self, code: &str) -> Result<bool> {
+        let account_id = self.current.id()?.to_account_thing();
+        self.check_totp(&account_id, code).await?;
+        let removed = remove_totp(self.persist, &account_id).await?;
+        SecurityEventPersist::new(self.persist, self.current)
+            .log(account_id, SecurityEventKind::TwoFactorDisabled, None)
+            .await?;
+        Ok(removed)
+    }
+
+    /// Replaces the current account's recovery codes with new ones, which are
+    /// returned. A code from its authenticator app or one of the old recovery
+    /// codes is needed, so that whoever finds the account signed in can't
+    /// take them.
+    #[instrument(skip_all)]
+    pub async fn regenerate_recovery_codes(&self, code: &str) -> Result<Vec<String>> {
+        let account_id = self.current.id()?.to_account_thing();
+        self.check_totp(&account_id, code).await?;
+        let codes = regenerate_recovery_codes(self.persist, self.csrng, &account_id).await?;
+        SecurityEventPersist::new(self.persist, self.current)
+            .log(
+                account_id,
+                SecurityEventKind::RecoveryCodesRegenerated,
+                None,
+            )
+            .await?;
+        Ok(codes)
+    }
+
+    /// Checks a code from an account's authenticator app or one of its
+    /// recovery codes, recording when a recovery code is used.
+    async fn check_totp(&self, account_id: &srql::Thing, code: &str) -> Result<TotpVerified> {
+        let verified = verify_totp(self.persist, account_id, code).await?;
+        if let TotpVerified::RecoveryCode { .. } = verified {
+            SecurityEventPersist::new(self.persist, self.current)
+                .log(
+                    account_id.clone(),
+                    SecurityEventKind::RecoveryCodeUsed,
+                    None,
+                )
+                .await?;
+        }
+        Ok(verified)
+    }
+
+    /// Starts adding a passkey to the current account, returning the options
+    /// to create it with. It isn't added until the browser's response is
+    /// given to `finish_passkey_registration`.
+    #[instrument(skip_all)]
+    pub async fn begin_passkey_registration(&self) -> Result<PasskeyCreationOptions> {
+        let rp = self.relying_party()?;
+        let Some(acc) = self.current().await? else {
+            return Err(Error::Unauthenticated);
+        };
+        begin_passkey_registration(self.persist, self.csrng, rp, &acc.id, &acc.user_id).await
+    }
+
+    /// Adds a passkey that the browser created to the current account.
+    #[instrument(skip_all)]
+    pub async fn finish_passkey_registration(
+        &self,
+        registration: PasskeyRegistration,
+    ) -> Result<Passkey> {
+        let rp = self.relying_party()?;
+        let account_id = self.current.id()?.to_account_thing();
+        let passkey =
+            finish_passkey_registration(self.persist, rp, &account_id, registration).await?;
+        SecurityEventPersist::new(self.persist, self.current)
+            .log(account_id, SecurityEventKind::PasskeyAdded, None)
+            .await?;
+        Ok(passkey)
+    }
+
+    /// Starts logging in with a passkey, returning the options to ask the
+    /// browser for one with. With the challenge token from `login`, the
+    /// passkey is used as a second factor, and has to be one of that
+    /// account's.
+    #[instrument(skip_all)]
+    pub async fn begin_passkey_login(
+        &self,
+        challenge_token: Option<&str>,
+    ) -> Result<PasskeyRequestOptions> {
+        let rp = self.relying_party()?;
+        let account_id = match challenge_token {
+            Some(challenge_token) => {
+                let claims = verify_two_factor_token(
+                    challenge_token,
+                    self.jwt_dec_key,
+                    self.persist.clock(),
+                )?;
+                Some(claims.id().to_account_thing())
+            }
+            None => None,
+        };
+        begin_passkey_login(self.persist, self.csrng, rp, account_id.as_ref()).await
+    }
+
+    /// Logs into the account that a passkey belongs to.
+    #[instrument(skip_all)]
+    pub async fn finish_passkey_login(
+        &self,
+        assertion: &PasskeyAssertion,
+    ) -> Result<AuthenticatedAccount> {
+        let rp = self.relying_party()?;
+        let account_id = finish_passkey_login(self.persist, rp, assertion).await?;
+        let Some(acc) = self.get(&account_id.id.to_raw()).await? else {
+            return Err(Error::PasskeyInvalid);
+        };
+        if acc.is_suspended(self.persist.clock().now()) {
+            return Err(Error::AccountSuspended);
+        }
+
+        Ok(self.touch(acc).await?.into())
+    }
+
+    /// Removes one of the current account's passkeys, returning it if it was
+    /// there.
+    #[instrument(skip_all)]
+    pub async fn remove_passkey(&self, id: &str) -> Result<Option<Passkey>> {
+        let account_id = self.current.id()?.to_account_thing();
+        let removed = remove_passkey(self.persist, &account_id, id).await?;
+        if removed.is_some() {
+            SecurityEventPersist::new(self.persist, self.current)
+                .log(account_id, SecurityEventKind::PasskeyRemoved, None)
+                .await?;
+        }
+        Ok(removed)
+    }
+
+    /// Creates an API key for the current account. API keys can't be used to
+    /// create more of them.
+    #[instrument(skip_all)]
+    pub async fn create_api_key(&self, input: CreateApiKey) -> Result<CreatedApiKey> {
+        let account_id = self.current.id()?.to_account_thing();
+        if self.current.is_api_key() {
+            return Err(Error::Unauthorized);
+        }
+        let created = create_api_key(self.persist, self.csrng, &account_id, input).await?;
+        SecurityEventPersist::new(self.persist, self.current)
+            .log(account_id, SecurityEventKind::ApiKeyCreated, None)
+            .await?;
+        Ok(created)
+    }
+
+    /// Revokes one of the current account's API keys, returning it if it was
+    /// there. API keys can't be used to revoke them.
+    #[instrument(skip_all)]
+    pub async fn revoke_api_key(&self, id: &str) -> Result<Option<ApiKey>> {
+        let account_id = self.current.id()?.to_account_thing();
+        if self.current.is_api_key() {
+            return Err(Error::Unauthorized);
+        }
+        let revoked = revoke_api_key(self.persist, &account_id, id).await?;
+        if revoked.is_some() {
+            SecurityEventPersist::new(self.persist, self.current)
+                .log(account_id, SecurityEventKind::ApiKeyRevoked, None)
+                .await?;
+        }
+        Ok(revoked)
+    }
+
+    fn relying_party(&self) -> Result<&RelyingParty> {
+        self.rp.as_ref().ok_or(Error::PasskeysUnavailable)
+    }
+
+    /// Starts logging in with an identity provider, returning where to send
+    /// the browser. The provider sends it back to the redirect URI, and
+    /// logging in is finished with `finish_external_login`.
+    #[instrument(skip_all)]
+    pub async fn begin_external_login(
+        &self,
+        provider: &str,
+        redirect_uri: &str,
+    ) -> Result<LoginResult> {
+        let provider = self.oidc_provider(provider)?;
+        let redirect =
+            begin_external_flow(self.persist, self.csrng, provider, redirect_uri, None).await?;
+        Ok(LoginResult::ExternalLoginRedirect(redirect))
+    }
+
+    /// Logs into the account that an identity is linked to, using the state
+    /// and code that the provider sent the browser back with. If the account
+    /// has turned on two-factor authentication, it isn't logged into until a
+    /// code is given with `verify_two_factor`.
+    #[instrument(skip_all)]
+    pub async fn finish_external_login(&self, state: &str, code: &str) -> Result<LoginResult> {
+        let Some(oidc) = self.oidc else {
+            return Err(Error::ExternalLoginInvalid);
+        };
+        let (provider, profile) =
+            finish_external_flow(self.persist, oidc, state, code, None).await?;
+        let Some(identity) =
+            use_external_identity(self.persist, &provider.id, &profile.subject).await?
+        else {
+            return Err(Error::ExternalIdentityNotLinked);
+        };
+        let Some(acc) = self.get(&identity.account_id.id.to_raw()).await? else {
+            return Err(Error::ExternalIdentityNotLinked);
+        };
+
+        let now = self.persist.clock().now();
+        if acc.is_suspended(now) {
+            return Err(Error::AccountSuspended);
+        }
+        if totp_enabled(self.persist, &acc.id).await? {
+            return Ok(LoginResult::TwoFactorRequired(TwoFactorRequired::new(
+                acc.id, now,
+            )));
+        }
+
+        Ok(LoginResult::Authenticated(Box::new(
+            self.touch(acc).await?.into(),
+        )))
+    }
+
+    /// Starts linking an identity from a provider to the current account,
+    /// returning where to send the browser. It isn't linked until the state
+    /// and code that the provider sends back are given to
+    /// `finish_external_link`.
+    #[instrument(skip_all)]
+    pub async fn begin_external_link(
+        &self,
+        provider: &str,
+        redirect_uri: &str,
+    ) -> Result<ExternalLoginRedirect> {
+        let account_id = self.current.id()?.to_account_thing();
+        if self.current.is_api_key() {
+            return Err(Error::Unauthorized);
+        }
+        let provider = self.oidc_provider(provider)?;
+        begin_external_flow(
+            self.persist,
+            self.csrng,
+            provider,
+            redirect_uri,
+            Some(account_id),
+        )
+        .await
+    }
+
+    /// Links the identity that signed into the provider to the current
+    /// account, so that it can log in with it.
+    #[instrument(skip_all)]
+    pub async fn finish_external_link(&self, state: &str, code: &str) -> Result<ExternalIdentity> {
+        let account_id = self.current.id()?.to_account_thing();
+        if self.current.is_api_key() {
+            return Err(Error::Unauthorized);
+        }
+        let Some(oidc) = self.oidc else {
+            return Err(Error::ExternalLoginInvalid);
+        };
+        let (provider, profile) =
+            finish_external_flow(self.persist, oidc, state, code, Some(&account_id)).await?;
+        let identity =
+            link_external_identity(self.persist, &account_id, &provider.id, profile).await?;
+        SecurityEventPersist::new(self.persist, self.current)
+            .log(account_id, SecurityEventKind::ExternalIdentityLinked, None)
+            .await?;
+        Ok(identity)
+    }
+
+    /// Unlinks one of the current account's identities, returning it if it
+    /// was there.
+    #[instrument(skip_all)]
+    pub async fn unlink_external_identity(&self, id: &str) -> Result<Option<ExternalIdentity>> {
+        let account_id = self.current.id()?.to_account_thing();
+        if self.current.is_api_key() {
+            return Err(Error::Unauthorized);
+        }
+        let removed = unlink_external_identity(self.persist, &account_id, id).await?;
+        if removed.is_some() {
+            SecurityEventPersist::new(self.persist, self.current)
+                .log(
+                    account_id,
+                    SecurityEventKind::ExternalIdentityUnlinked,
+                    None,
+                )
+                .await?;
+        }
+        Ok(removed)
+    }
+
+    fn oidc_provider(&self, id: &str) -> Result<&'a OidcProvider> {
+        self.oidc
+            .and_then(|oidc| oidc.provider(id))
+            .ok_or_else(|| Error::InputInvalid(format!("{id:?} is not an identity provider")))
+    }
+
+    /// Exchanges a refresh token for new tokens. The token can't be used
+    /// again, and the account's new refresh token continues its family.
+    ///
+    /// JWT refresh tokens, which were issued before refresh tokens were
+    /// stored, are still accepted until they expire.
+    #[instrument(skip_all)]
+    pub async fn refresh(&self, refresh_token: String) -> Result<AuthenticatedAccount> {
+        let (account_id, session_id, issued_at, family_id) =
+            if is_opaque_refresh_token(&refresh_token) {
+                let used = use_refresh_token(self.persist, &refresh_token).await?;
+                (
+                    used.account_id.id.to_raw(),
+                    used.session_id.map(|session_id| session_id.id.to_raw()),
+                    used.issued_at,
+                    Some(used.family_id),
+                )
+            } else {
+                let Ok(claims) =
+                    verify_refresh_token(&refresh_token, self.jwt_dec_key, self.persist.clock())
+                else {
+                    return Err(Error::CredentialsInvalid);
+                };
+                (
+                    claims.id().to_owned(),
+                    claims.session_id().map(ToOwned::to_owned),
+                    claims.issued_at()?,
+                    None,
+                )
+            };
+
+        let Some(acc) = self.get(&account_id).await? else {
             return Err(Error::CredentialsInvalid);
         };
 
         if let Some(revoked_at) = acc.revoked_at {
-            if revoked_at >= claims.issued_at()? {
+            if revoked_at >= issued_at {
                 return Err(Error::CredentialsInvalid);
             }
         }
+        if acc.is_suspended(self.persist.clock().now()) {
+            return Err(Error::AccountSuspended);
+        }
+
+        let session = match session_id {
+            Some(session_id) => Some(resume_session(self.persist, &session_id, &acc).await?),
+            None => None,
+        };
+        let mut acc = AuthenticatedAccount::from(self.touch(acc).await?);
+        acc.refresh_family_id = family_id;
+        Ok(match session {
+            Some(session) => acc.with_session(session),
+            None => acc,
+        })
+    }
+
+    pub async fn get(&self, id: &str) -> Result<Option<Account>> {
+        self.repo.get(id).await
+    }
+
+    /// Finds an account by its user ID, also looking for it in lowercase.
+    pub async fn get_by_user_id(&self, user_id: &str) -> Result<Option<Account>> {
+        self.repo.get_by_user_id(user_id).await
+    }
+
+    /// Registers an account. The user ID is normalized and checked first,
+    /// and if another account already has it, this fails with
+    /// `UnavailableIdent`. That's enforced by a unique index, so it holds for
+    /// registrations that race each other too.
+    ///
+    /// If the user ID only looks like one that's in use or reserved, this
+    /// fails with `UserIdConfusable`, unless the instance allows it or an
+    /// admin is registering the account and asks to. Accounts that are
+    /// checked claim their user ID's skeleton with a unique index, so this
+    /// holds for registrations that race each other too.
+    #[instrument(skip_all)]
+    pub async fn create(&self, mut acc: CreateAccount) -> Result<AuthenticatedAccount> {
+        acc.user_id = normalize_user_id(&acc.user_id)?;
+        if let Some(email) = &acc.email {
+            let email = normalize_email(email)?;
+            self.check_email_available(&email, None).await?;
+            acc.email = Some(email);
+        }
+        let allow_confusable = if acc.allow_confusable.unwrap_or_default() {
+            require_admin(self.persist, self.current).await?;
+            true
+        } else {
+            self.allow_confusable_user_ids
+        };
+        if !allow_confusable {
+            self.check_confusable(&acc.user_id).await?;
+        }
+        acc.allow_confusable = Some(allow_confusable);
+        let user_id = acc.user_id.clone();
+        let (owner_id, adult) = if acc.bot.unwrap_or_default() {
+            let owner = self.bot_owner().await?;
+            (Some(owner.id), owner.adult)
+        } else {
+            let today = self.persist.clock().now().date_naive();
+            (None, check_birthdate(acc.birthdate, self.min_age, today)?)
+        };
+        let policies = PolicyPersist::new(self.persist, self.current)
+            .check_registration(acc.accepted_policy_ids.as_deref().unwrap_or_default())
+            .await?;
+        let creds = self
+            .persist
+            .password_hasher()
+            .hash(self.csrng, acc.pword.expose_secret())?;
+
+        // The first account on an instance has to be an admin, otherwise
+        // there'd be no way to administer it.
+        let existing: Option<usize> = self
+            .persist
+            .db()
+            .query(srql::count_query(ACC_TABLE_NAME, None))
+            .await?
+            .take("count")?;
+        let admin = existing.unwrap_or_default() == 0;
+
+        // TODO: support invites and reject if required/invalid
+        let create = Account::create(
+            creds,
+            admin,
+            owner_id,
+            adult,
+            acc,
+            self.persist.clock(),
+            self.persist.ids(),
+        );
+        let id = srql::created_thing(&create);
+        let mut query = vec![srql::trans_begin(), srql::Statement::Create(create)];
+        if let Some(id) = id {
+            query.push(srql::Statement::Create(DomainEvent::create(
+                DomainEventKind::AccountCreated,
+                None,
+                id,
+                vec![],
+                self.persist.clock(),
+                self.persist.ids(),
+            )));
+        }
+        query.push(srql::trans_end());
+        let acc: Result<Option<Account>> = match self.persist.db().query(query).await {
+            Ok(mut res) => res.take(0).map_err(Into::into),
+            Err(err) => Err(err.into()),
+        };
+        let acc = match acc {
+            Ok(Some(acc)) => acc,
+            // Another registration took the user ID or its skeleton first,
+            // and now that it's stored the two can be told apart.
+            Ok(None) | Err(Error::UnavailableIdent) => {
+                if !allow_confusable {
+                    self.check_confusable(&user_id).await?;
+                }
+                return Err(Error::UnavailableIdent);
+            }
+            Err(err) => return Err(err),
+        };
+
+        PolicyPersist::new(self.persist, self.current)
+            .record(acc.id.clone(), &policies.iter().collect::<Vec<_>>())
+            .await?;
+        if let Some(email) = &acc.email {
+            self.send_verification(&acc, email).await?;
+        }
 
         Ok(acc.into())
     }
 
+    /// Fails if a user ID looks like one that's reserved, or that another
+    /// account has.
+    async fn check_confusable(&self, user_id: &str) -> Result<()> {
+        #[derive(Deserialize)]
+        struct Existing {
+            user_id: String,
+        }
+
+        if is_reserved_lookalike(user_id) {
+            return Err(Error::UserIdConfusable);
+        }
+        let existing: Vec<Existing> = self
+            .persist
+            .db()
+            .query(srql::SelectStatement {
+                expr: srql::Fields(
+                    vec![srql::Field::Single {
+                        expr: srql::field("user_id").into(),
+                        alias: None,
+                    }],
+                    false,
+                ),
+                what: srql::table(ACC_TABLE_NAME),
+                cond: srql::Cond(
+                    srql::Expression::Binary {
+                        l: srql::field("user_id_skeleton").into(),
+                        o: srql::Operator::Equal,
+                        r: srql::string(user_id_skeleton(user_id)).into(),
+                    }
+                    .into(),
+                )
+                .into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        // Accounts registered before user IDs were normalized can differ
+        // only by case, which is the same user ID rather than a lookalike.
+        match existing.first() {
+            Some(existing) if existing.user_id.to_lowercase() == user_id => {
+                Err(Error::UnavailableIdent)
+            }
+            Some(_) => Err(Error::UserIdConfusable),
+            None => Ok(()),
+        }
+    }
+
+    /// The IDs of the latest policies, which new accounts have to accept.
+    pub(super) async fn current_policy_ids(&self) -> Result<Vec<ID>> {
+        let policies = PolicyPersist::new(self.persist, self.current)
+            .current()
+            .await?;
+        Ok(policies
+            .iter()
+            .map(|policy| policy.id.to_gql_id())
+            .collect())
+    }
+
     #[instrument(skip_all)]
-    pub async fn get(&self, id: &str) -> Result<Option<Account>> {
-        Ok(self.persist.db().select((ACC_TABLE_NAME, id)).await?)
+    pub async fn update(&self, mut update: UpdateAccount) -> Result<Option<Account>> {
+        let id = self.current.id()?;
+        if let MaybeUndefined::Value(locale) = &mut update.locale {
+            let Some(parsed) = parse_locale(locale) else {
+                return Err(Error::InputInvalid(
+                    "locale is not a valid locale tag".into(),
+                ));
+            };
+            *locale = parsed.to_string();
+        }
+        if let MaybeUndefined::Value(timezone) = &mut update.timezone {
+            let Some(parsed) = parse_timezone(timezone) else {
+                return Err(Error::InputInvalid(
+                    "timezone is not a known timezone".into(),
+                ));
+            };
+            parsed.name().clone_into(timezone);
+        }
+        // A new address has to be verified, so a link is sent to it, but
+        // giving the same address again leaves it verified.
+        let mut verify = None;
+        if let MaybeUndefined::Value(email) = &update.email {
+            let email = normalize_email(email)?;
+            let account_id = id.to_account_thing();
+            let current = self.get(id).await?.and_then(|acc| acc.email);
+            if current.as_deref() == Some(email.as_str()) {
+                update.email = MaybeUndefined::Undefined;
+            } else {
+                self.check_email_available(&email, Some(&account_id))
+                    .await?;
+                self.check_verification_cooldown(&account_id).await?;
+                update.email = MaybeUndefined::Value(email.clone());
+                verify = Some(email);
+            }
+        }
+
+        let acc: Option<Account> = if let Some(update) = update.into_update(id.to_account_thing()) {
+            self.persist.db().query(update).await?.take(0)?
+        } else {
+            self.get(id).await?
+        };
+        if let (Some(acc), Some(email)) = (&acc, &verify) {
+            self.send_verification(acc, email).await?;
+        }
+        if let Some(acc) = &acc {
+            self.persist.account_feed().publish(acc);
+        }
+
+        Ok(acc)
+    }
+
+    /// Streams an account each time it's changed, from now on. Changes that
+    /// accounts aren't told about, such as being shadow-limited, aren't
+    /// sent. This needs to be signed in.
+    pub fn subscribe(&self, id: &str) -> Result<impl Stream<Item = Account> + Send + 'static> {
+        self.current.id()?;
+        let id = id.to_account_thing();
+        Ok(self
+            .persist
+            .account_feed()
+            .subscribe(move |acc| acc.id == id))
     }
 
+    /// Fails with `EmailAlreadyInUse` if an account other than `except` has
+    /// the email address.
+    async fn check_email_available(&self, email: &str, except: Option<&srql::Thing>) -> Result<()> {
+        let existing: Vec<Account> = self
+            .persist
+            .db()
+            .query(srql::SelectStatement {
+                expr: srql::Fields::all(),
+                what: srql::table(ACC_TABLE_NAME),
+                cond: srql::Cond(
+                    srql::Expression::Binary {
+                        l: srql::field("email").into(),
+                        o: srql::Operator::Equal,
+                        r: srql::string(email).into(),
+                    }
+                    .into(),
+                )
+                .into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        if existing.iter().any(|acc| Some(&acc.id) != except) {
+            return Err(Error::EmailAlreadyInUse);
+        }
+        Ok(())
+    }
+
+    /// Fails with `RateLimited` if the account was sent a verification email
+    /// too recently to be sent another.
+    async fn check_verification_cooldown(&self, account_id: &srql::Thing) -> Result<()> {
+        let Some(pending) = pending_email_verification(self.persist, account_id).await? else {
+            return Ok(());
+        };
+        let resend_at = pending.issued_at + Duration::minutes(EMAIL_VERIFICATION_RESEND_MINUTES);
+        if resend_at > self.persist.clock().now() {
+            return Err(Error::RateLimited);
+        }
+        Ok(())
+    }
+
+    /// Emails the account a token that verifies it can be emailed at the
+    /// address. With a public URL, the token is sent as a link to
+    /// `/verify-email` under it, for a client to give to `verify_email`.
+    async fn send_verification(&self, acc: &Account, email: &str) -> Result<()> {
+        let token =
+            issue_email_verification(self.persist, self.csrng, acc.id.clone(), email).await?;
+        let how = match self.public_url {
+            Some(public_url) => format!(
+                "open this link within {EMAIL_VERIFICATION_HOURS} hours:\n\n\
+                 {public_url}/verify-email?token={token}"
+            ),
+            None => format!("use this code within {EMAIL_VERIFICATION_HOURS} hours:\n\n{token}"),
+        };
+        self.persist.email().send(Email {
+            to: email.to_owned(),
+            subject: "Verify your email address".into(),
+            body: format!(
+                "This address was given for the account {}.\n\n\
+                 To verify that it's yours, {how}\n\n\
+                 If it wasn't you, you can ignore this email and the address won't be verified.",
+                acc.user_id,
+            ),
+        });
+        Ok(())
+    }
+
+    /// Sends the current account another email to verify its address with.
+    /// Only the newest one that was sent works, and another can't be sent
+    /// until a few minutes after the last.
     #[instrument(skip_all)]
-    pub async fn get_by_user_id(&self, user_id: &str) -> Result<Option<Account>> {
-        let acc = self
+    pub async fn resend_verification(&self) -> Result<()> {
+        let Some(acc) = self.current().await? else {
+            return Err(Error::Unauthenticated);
+        };
+        let Some(email) = &acc.email else {
+            return Err(Error::InputInvalid(
+                "the account doesn't have an email address".into(),
+            ));
+        };
+        if acc.is_email_verified() {
+            return Err(Error::InputInvalid(
+                "the account's email address is already verified".into(),
+            ));
+        }
+        self.check_verification_cooldown(&acc.id).await?;
+        self.send_verification(&acc, email).await
+    }
+
+    /// Verifies the email address that a verification token was sent to,
+    /// returning when. The token can't be used again, and stops working if
+    /// the account's address has changed since it was sent.
+    #[instrument(skip_all)]
+    pub async fn verify_email(&self, token: &str) -> Result<DateTime<Utc>> {
+        let (account_id, email) = use_email_verification(self.persist, token).await?;
+        let Some(acc) = self
+            .get(&account_id.id.to_raw())
+            .await?
+            .filter(|acc| acc.email.as_deref() == Some(email.as_str()))
+        else {
+            return Err(Error::EmailVerificationInvalid);
+        };
+
+        let now = self.persist.clock().now();
+        let mut updates = vec![];
+        now.push_field(srql::field("email_verified_at"), &mut updates);
+        let Some(update) = srql::obj_update_query(acc.id, updates) else {
+            return Err("".into());
+        };
+        self.persist.db().query(update).await?.check()?;
+        Ok(now)
+    }
+
+    /// Fails with `EmailNotVerified` if the instance requires verified email
+    /// addresses and the current account hasn't verified its own. Bots go
+    /// by their owner's, and admins don't need one.
+    #[instrument(skip_all)]
+    pub async fn require_verified_email(&self) -> Result<()> {
+        if !self.verified_email_required {
+            return Ok(());
+        }
+        require_verified_email(&*self.repo, self.current).await
+    }
+
+    #[instrument(skip_all)]
+    pub async fn revoke_tokens(&self) -> Result<DateTime<Utc>> {
+        let acc = self.current.id()?.to_account_thing();
+        let now = self.revoke_tokens_of(&acc).await?;
+        SecurityEventPersist::new(self.persist, self.current)
+            .log(acc, SecurityEventKind::TokensRevoked, None)
+            .await?;
+
+        Ok(now)
+    }
+
+    /// Deletes the current account, once its password has been confirmed,
+    /// and the bots it owns along with it. Every token issued for them is
+    /// revoked and they can't be signed into, and once the instance's grace
+    /// period ends they're purged along with their content, which frees
+    /// their user IDs. Returns when that will be.
+    ///
+    /// Until then, the account can be restored with `restore`.
+    #[instrument(skip_all)]
+    pub async fn delete(&self, pword: &SecretString) -> Result<DateTime<Utc>> {
+        if self.current.is_api_key() {
+            return Err(Error::Unauthorized);
+        }
+        let Some(acc) = self.current().await? else {
+            return Err(Error::Unauthenticated);
+        };
+        self.check_pword(&acc, pword).await?;
+
+        let now = self.persist.clock().now();
+        let purge_at = purge_time(now, self.deletion_grace_days);
+        let mut accounts = vec![acc.id.clone()];
+        if !acc.bot {
+            accounts.extend(self.bots_of(&acc.id).await?.into_iter().map(|bot| bot.id));
+        }
+        for id in &accounts {
+            let mut updates = vec![];
+            now.push_field(srql::field("deleted_at"), &mut updates);
+            purge_at.push_field(srql::field("purge_at"), &mut updates);
+            now.push_field(srql::field("revoked_at"), &mut updates);
+            let Some(update) = srql::obj_update_query(id.clone(), updates) else {
+                return Err("".into());
+            };
+            self.persist.db().query(update).await?.check()?;
+            revoke_sessions_of(self.persist, id, None).await?;
+        }
+
+        SecurityEventPersist::new(self.persist, self.current)
+            .log(acc.id, SecurityEventKind::AccountDeleted, None)
+            .await?;
+        Ok(purge_at)
+    }
+
+    /// Restores an account that was deleted, before it's purged, along with
+    /// the bots that were deleted with it. This doesn't sign the account in,
+    /// so that it still has to pass two-factor authentication when it's
+    /// next logged into.
+    #[instrument(skip_all)]
+    pub async fn restore(&self, creds: AuthCreds) -> Result<Account> {
+        let Some(acc) = self.get_by_user_id(&creds.user_id).await? else {
+            return Err(Error::CredentialsInvalid);
+        };
+        self.check_pword(&acc, &creds.pword).await?;
+        let (Some(deleted_at), Some(purge_at)) = (acc.deleted_at, acc.purge_at) else {
+            return Err(Error::InputInvalid(
+                "the account hasn't been deleted".into(),
+            ));
+        };
+        if purge_at <= self.persist.clock().now() {
+            return Err(Error::AccountDeleted);
+        }
+
+        let mut accounts = vec![acc.id.clone()];
+        if !acc.bot {
+            accounts.extend(
+                self.bots_of(&acc.id)
+                    .await?
+                    .into_iter()
+                    .filter(|bot| bot.deleted_at == Some(deleted_at))
+                    .map(|bot| bot.id),
+            );
+        }
+        let mut restored = None;
+        for id in accounts {
+            let updates = vec![
+                (
+                    srql::field("deleted_at"),
+                    srql::Operator::Equal,
+                    srql::Value::None,
+                ),
+                (
+                    srql::field("purge_at"),
+                    srql::Operator::Equal,
+                    srql::Value::None,
+                ),
+            ];
+            let Some(update) = srql::obj_update_query(id, updates) else {
+                return Err("".into());
+            };
+            let acc: Option<Account> = self.persist.db().query(update).await?.take(0)?;
+            restored = restored.or(acc);
+        }
+
+        SecurityEventPersist::new(self.persist, self.current)
+            .log(acc.id.clone(), SecurityEventKind::AccountRestored, None)
+            .await?;
+        Ok(restored.unwrap_or(acc))
+    }
+
+    /// Lists the bots that an account owns.
+    async fn bots_of(&self, owner: &srql::Thing) -> Result<Vec<Account>> {
+        let bots = self
             .persist
             .db()
             .query(srql::SelectStatement {
@@ -89,9 +1093,9 @@ impl<'a> AccountPersist<'a> {
                 what: srql::table(ACC_TABLE_NAME),
                 cond: srql::Cond(
                     srql::Expression::Binary {
-                        l: srql::field("user_id").into(),
+                        l: srql::field("owner_id").into(),
                         o: srql::Operator::Equal,
-                        r: srql::string(user_id).into(),
+                        r: owner.clone().into(),
                     }
                     .into(),
                 )
@@ -100,50 +1104,745 @@ impl<'a> AccountPersist<'a> {
             })
             .await?
             .take(0)?;
-        Ok(acc)
+        Ok(bots)
+    }
+
+    /// Emails the account a token that can be used to choose a new
+    /// password, if it has an email address. This succeeds whether or not
+    /// the account exists or has an address, so that it can't be used to
+    /// find out which do.
+    #[instrument(skip_all)]
+    pub async fn request_password_reset(&self, user_id: &str) -> Result<()> {
+        let Some(acc) = self.get_by_user_id(user_id).await? else {
+            return Ok(());
+        };
+        let Some(email) = acc.email else {
+            return Ok(());
+        };
+
+        let token = issue_password_reset(self.persist, self.csrng, acc.id).await?;
+        self.persist.email().send(Email {
+            to: email,
+            subject: "Reset your password".into(),
+            body: format!(
+                "Someone asked to reset the password of your account, {}.\n\n\
+                 To choose a new password, use this code within {PASSWORD_RESET_MINUTES} minutes:\n\n\
+                 {token}\n\n\
+                 If it wasn't you, you can ignore this email and your password won't change.",
+                acc.user_id,
+            ),
+        });
+        Ok(())
     }
 
+    /// Sets a new password for the account that a password reset token was
+    /// issued to. The token can't be used again, and every token issued for
+    /// the account is revoked, so whoever knew the old password is signed
+    /// out.
     #[instrument(skip_all)]
-    pub async fn create(&self, acc: CreateAccount) -> Result<AuthenticatedAccount> {
-        let creds = create_creds(self.csrng, acc.pword.expose_secret())?;
+    pub async fn reset_password(&self, token: &str, pword: &SecretString) -> Result<DateTime<Utc>> {
+        let account_id = use_password_reset(self.persist, token).await?;
+        let creds = self
+            .persist
+            .password_hasher()
+            .hash(self.csrng, pword.expose_secret())?;
 
-        // TODO: support invites and reject if required/invalid
+        let now = self.persist.clock().now();
+        let mut updates = vec![];
+        creds
+            .salt
+            .push_field(srql::field("pword_salt"), &mut updates);
+        creds
+            .hash
+            .push_field(srql::field("pword_hash"), &mut updates);
+        now.push_field(srql::field("revoked_at"), &mut updates);
+        let Some(update) = srql::obj_update_query(account_id.clone(), updates) else {
+            return Err("".into());
+        };
+        self.persist.db().query(update).await?.check()?;
+        revoke_sessions_of(self.persist, &account_id, None).await?;
+
+        SecurityEventPersist::new(self.persist, self.current)
+            .log(account_id, SecurityEventKind::PasswordReset, None)
+            .await?;
+        Ok(now)
+    }
+
+    /// Replaces the current account's trusted contacts, `threshold` of whom
+    /// have to approve recovering it. Giving no contacts turns recovery
+    /// through them off. Recoveries that were waiting for approval are
+    /// cancelled.
+    #[instrument(skip_all)]
+    pub async fn set_recovery_contacts(
+        &self,
+        contact_ids: Vec<ID>,
+        threshold: u32,
+    ) -> Result<Option<RecoveryContacts>> {
+        let Some(acc) = self.current().await? else {
+            return Err(Error::Unauthenticated);
+        };
+        if acc.bot {
+            return Err(Error::Unauthorized);
+        }
+        if contact_ids.len() > MAX_RECOVERY_CONTACTS {
+            return Err(Error::InputInvalid(format!(
+                "at most {MAX_RECOVERY_CONTACTS} trusted contacts can be chosen"
+            )));
+        }
+        if !contact_ids.is_empty() && (threshold == 0 || threshold as usize > contact_ids.len()) {
+            return Err(Error::InputInvalid(
+                "threshold must be between 1 and the number of contacts".into(),
+            ));
+        }
+
+        let mut contacts: Vec<srql::Thing> = vec![];
+        for id in &contact_ids {
+            let contact = self
+                .get(id)
+                .await?
+                .filter(|contact| !contact.bot && contact.deleted_at.is_none());
+            match contact {
+                Some(contact) if contact.id != acc.id && !contacts.contains(&contact.id) => {
+                    contacts.push(contact.id);
+                }
+                _ => {
+                    return Err(Error::InputInvalid(format!(
+                        "{} can't be a trusted contact",
+                        id.as_str()
+                    )))
+                }
+            }
+        }
+
+        let contacts = set_recovery_contacts(self.persist, &acc.id, contacts, threshold).await?;
+        SecurityEventPersist::new(self.persist, self.current)
+            .log(acc.id, SecurityEventKind::RecoveryContactsChanged, None)
+            .await?;
+        Ok(contacts)
+    }
+
+    /// Starts recovering an account with its trusted contacts, who are each
+    /// asked to approve it. This doesn't need the account to be signed in.
+    /// Returns the recovery along with the token to use it with once enough
+    /// contacts have approved.
+    #[instrument(skip_all)]
+    pub async fn request_recovery(&self, user_id: &str) -> Result<StartedRecovery> {
+        let acc = self
+            .get_by_user_id(user_id)
+            .await?
+            .filter(|acc| acc.deleted_at.is_none());
+        let contacts = match &acc {
+            Some(acc) => get_recovery_contacts(self.persist, &acc.id).await?,
+            None => None,
+        };
+        let Some(contacts) = contacts else {
+            return Err(Error::InputInvalid(
+                "this account can't be recovered with trusted contacts".into(),
+            ));
+        };
+        if pending_recovery_requests(self.persist, &contacts.account_id)
+            .await?
+            .len()
+            >= MAX_PENDING_RECOVERIES
+        {
+            return Err(Error::RateLimited);
+        }
+
+        let (request, token) = issue_recovery_request(self.persist, self.csrng, &contacts).await?;
+        let notifications = NotificationPersist::new(self.persist, self.current);
+        for contact_id in &request.contact_ids {
+            notifications
+                .notify(CreateNotification {
+                    account_id: contact_id.clone(),
+                    kind: NotificationKind::RecoveryApproval,
+                    actor_id: None,
+                    post_id: None,
+                    subject_id: Some(request.id.clone()),
+                })
+                .await?;
+        }
+        SecurityEventPersist::new(self.persist, self.current)
+            .log(
+                request.account_id.clone(),
+                SecurityEventKind::RecoveryRequested,
+                None,
+            )
+            .await?;
+        Ok(StartedRecovery {
+            recovery: request,
+            token,
+        })
+    }
+
+    /// Approves recovering an account as one of its trusted contacts, and
+    /// returns the recovery.
+    #[instrument(skip_all)]
+    pub async fn approve_recovery(&self, id: &str) -> Result<Option<RecoveryRequest>> {
+        let contact_id = self.current.id()?.to_account_thing();
+        let Some(request) = get_recovery_request(self.persist, id).await? else {
+            return Ok(None);
+        };
+        approve_recovery_request(self.persist, request, &contact_id)
+            .await
+            .map(Some)
+    }
+
+    /// Gets the recovery that a token from `request_recovery` is for, so
+    /// that whoever is recovering the account can see how many contacts have
+    /// approved it.
+    #[instrument(skip_all)]
+    pub async fn recovery_status(&self, token: &str) -> Result<RecoveryRequest> {
+        find_recovery_request(self.persist, token).await
+    }
+
+    /// Recovers an account once enough of its trusted contacts have approved,
+    /// returning a password reset token to choose a new password with.
+    /// Two-factor authentication is turned off, as whoever is recovering the
+    /// account may have lost it too.
+    #[instrument(skip_all)]
+    pub async fn recover_account(&self, token: &str) -> Result<String> {
+        let account_id = use_recovery_request(self.persist, token).await?;
+        remove_totp(self.persist, &account_id).await?;
+        let reset = issue_password_reset(self.persist, self.csrng, account_id.clone()).await?;
+        SecurityEventPersist::new(self.persist, self.current)
+            .log(account_id, SecurityEventKind::AccountRecovered, None)
+            .await?;
+        Ok(reset)
+    }
+
+    /// Reports a security event as unrecognized, using a token from the
+    /// event. This doesn't need the account to be signed in, as whoever took
+    /// it over may have signed its owner out.
+    ///
+    /// Every token issued for the account is revoked, so whoever took it over
+    /// is signed out too, and the instance's admins are asked to help recover
+    /// it.
+    #[instrument(skip_all)]
+    pub async fn disown(&self, token: &str) -> Result<DateTime<Utc>> {
+        let claims = verify_disown_token(token, self.jwt_dec_key, self.persist.clock())?;
+        let account_id = claims.id().to_account_thing();
+        let event_id = srql::Thing::from((SECURITY_EVENT_TABLE_NAME, claims.event_id()));
+        match get_any(self.persist, &event_id).await? {
+            Some(event) if event.account_id == account_id && event.kind.alerts() => {}
+            _ => return Err(Error::JwtInvalid),
+        }
+
+        let now = self.revoke_tokens_of(&account_id).await?;
+        SecurityEventPersist::new(self.persist, self.current)
+            .log(account_id.clone(), SecurityEventKind::Disowned, None)
+            .await?;
+
+        let notifications = NotificationPersist::new(self.persist, self.current);
+        for admin in self.admins().await? {
+            notifications
+                .notify(CreateNotification {
+                    account_id: admin.id,
+                    kind: NotificationKind::AccountRecovery,
+                    actor_id: None,
+                    post_id: None,
+                    subject_id: Some(account_id.clone()),
+                })
+                .await?;
+        }
+
+        Ok(now)
+    }
+
+    /// Restricts an account for a number of hours, replacing any restriction
+    /// it already has. Only moderators and admins can do this, admins can't
+    /// be restricted, and moderators can only be restricted by admins.
+    ///
+    /// Suspending an account signs it out. The account is notified, unless
+    /// it's being shadow-limited.
+    #[instrument(skip_all)]
+    pub async fn restrict(
+        &self,
+        id: &str,
+        kind: RestrictionKind,
+        hours: u32,
+        reason: String,
+    ) -> Result<Option<Account>> {
+        restrict_account(self.persist, self.current, id, kind, hours, reason).await
+    }
+
+    /// Lifts an account's restriction before it expires. Only moderators and
+    /// admins can do this, and only admins can for moderators.
+    #[instrument(skip_all)]
+    pub async fn unrestrict(&self, id: &str) -> Result<Option<Account>> {
+        let actor =
+            require_permission(self.persist, self.current, Permission::RestrictAccounts).await?;
+        let Some(before) = restrictable(self.persist, &actor, id).await? else {
+            return Ok(None);
+        };
         let acc: Option<Account> = self
             .persist
             .db()
-            .query(Account::create(creds, acc))
+            .query(srql::UpdateStatement {
+                what: srql::thing(id.to_account_thing()),
+                data: srql::Data::SetExpression(vec![(
+                    srql::field("restriction"),
+                    srql::Operator::Equal,
+                    srql::Value::None,
+                )])
+                .into(),
+                output: srql::Output::After.into(),
+                ..Default::default()
+            })
             .await?
             .take(0)?;
-
-        match acc {
-            Some(acc) => Ok(acc.into()),
-            None => Err(Error::UnavailableIdent),
+        let shadow_limited = before
+            .restriction
+            .is_some_and(|restriction| restriction.kind == RestrictionKind::ShadowLimited);
+        if let Some(acc) = acc.as_ref().filter(|_| !shadow_limited) {
+            self.persist.account_feed().publish(acc);
         }
+        Ok(acc)
     }
 
+    /// Changes an account's role, and returns the account. Only admins can
+    /// do this, and they can't change their own role, so that an instance is
+    /// never left without one.
     #[instrument(skip_all)]
-    pub async fn revoke_tokens(&self) -> Result<DateTime<Utc>> {
-        let acc = self.current.id()?;
-        let now = Utc::now();
+    pub async fn set_role(&self, id: &str, role: AccountRole) -> Result<Option<Account>> {
+        let actor = require_permission(self.persist, self.current, Permission::ManageRoles).await?;
+        let id = id.to_account_thing();
+        if id == actor.id {
+            return Err(Error::InputInvalid(
+                "accounts can't change their own role".into(),
+            ));
+        }
+        if self.get(&id.to_gql_id()).await?.is_none() {
+            return Ok(None);
+        }
+
+        let mut update = vec![];
+        role.push_field(srql::field("role"), &mut update);
+        (role == AccountRole::Admin).push_field(srql::field("admin"), &mut update);
+        let acc: Option<Account> = self
+            .persist
+            .db()
+            .query(srql::UpdateStatement {
+                what: srql::thing(id),
+                data: srql::Data::SetExpression(update).into(),
+                output: srql::Output::After.into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        if let Some(acc) = &acc {
+            self.persist.account_feed().publish(acc);
+        }
+        Ok(acc)
+    }
+
+    /// Makes every token issued for an account before now stop working,
+    /// returning when that was. Its sessions are signed out too, so access
+    /// tokens stop working straight away.
+    async fn revoke_tokens_of(&self, acc: &srql::Thing) -> Result<DateTime<Utc>> {
+        let now = self.persist.clock().now();
 
         let mut updates = vec![];
         now.push_field(srql::field("revoked_at"), &mut updates);
-        let Some(update) = srql::obj_update_query((ACC_TABLE_NAME, &***acc).into(), updates) else {
+        let Some(update) = srql::obj_update_query(acc.clone(), updates) else {
             return Err("".into());
         };
 
         self.persist.db().query(update).await?;
-
+        revoke_sessions_of(self.persist, acc, None).await?;
         Ok(now)
     }
+
+    async fn admins(&self) -> Result<Vec<Account>> {
+        let admins = self
+            .persist
+            .db()
+            .query(srql::SelectStatement {
+                expr: srql::Fields::all(),
+                what: srql::table(ACC_TABLE_NAME),
+                cond: srql::Cond(
+                    srql::Expression::Binary {
+                        l: srql::field("admin").into(),
+                        o: srql::Operator::Equal,
+                        r: true.into(),
+                    }
+                    .into(),
+                )
+                .into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        Ok(admins)
+    }
+
+    /// Gets the account that will own a new bot, which is the current
+    /// account. Bots can't own other bots.
+    async fn bot_owner(&self) -> Result<Account> {
+        let id = self.current.id()?;
+        match self.get(id).await? {
+            Some(owner) if !owner.bot => Ok(owner),
+            _ => Err(Error::BotOwnerInvalid),
+        }
+    }
+
+    /// Lists all of the bot accounts on the instance. Only admins can see
+    /// these.
+    #[instrument(skip_all)]
+    pub async fn bots(&self) -> Result<Vec<Account>> {
+        require_admin(self.persist, self.current).await?;
+        let bots = self
+            .persist
+            .db()
+            .query(srql::SelectStatement {
+                expr: srql::Fields::all(),
+                what: srql::table(ACC_TABLE_NAME),
+                cond: srql::Cond(
+                    srql::Expression::Binary {
+                        l: srql::field("bot").into(),
+                        o: srql::Operator::Equal,
+                        r: true.into(),
+                    }
+                    .into(),
+                )
+                .into(),
+                order: srql::Orders(vec![srql::Order {
+                    order: srql::field("id"),
+                    direction: true,
+                    ..Default::default()
+                }])
+                .into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        Ok(bots)
+    }
+
+    /// Lists the accounts that were sent a dormancy notice and haven't
+    /// signed in since, longest noticed first. Only admins can see these.
+    #[instrument(skip_all)]
+    pub async fn dormant(&self) -> Result<Vec<DormantAccount>> {
+        require_admin(self.persist, self.current).await?;
+        let dormant: Vec<Account> = self
+            .persist
+            .db()
+            .query(srql::SelectStatement {
+                expr: srql::Fields::all(),
+                what: srql::table(ACC_TABLE_NAME),
+                cond: srql::Cond(
+                    srql::Expression::Binary {
+                        l: srql::field("dormancy_noticed_at").into(),
+                        o: srql::Operator::NotEqual,
+                        r: srql::Value::None,
+                    }
+                    .into(),
+                )
+                .into(),
+                order: srql::Orders(vec![srql::Order {
+                    order: srql::field("dormancy_noticed_at"),
+                    direction: true,
+                    ..Default::default()
+                }])
+                .into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        Ok(dormant
+            .into_iter()
+            .filter_map(DormantAccount::new)
+            .collect())
+    }
+
+    /// Checks an account's password. If it matches a hash that wasn't made
+    /// with the current hashing parameters, the hash is replaced.
+    async fn check_pword(&self, acc: &Account, pword: &SecretString) -> Result<()> {
+        let hasher = self.persist.password_hasher();
+        hasher.verify(pword, &acc.pword_salt, &acc.pword_hash)?;
+        if !hasher.needs_rehash(&acc.pword_hash) {
+            return Ok(());
+        }
+
+        let creds = hasher.hash(self.csrng, pword.expose_secret())?;
+        let mut update = vec![];
+        creds
+            .salt
+            .push_field(srql::field("pword_salt"), &mut update);
+        creds
+            .hash
+            .push_field(srql::field("pword_hash"), &mut update);
+        self.persist
+            .db()
+            .query(srql::UpdateStatement {
+                what: srql::thing(acc.id.clone()),
+                data: srql::Data::SetExpression(update).into(),
+                output: srql::Output::None.into(),
+                ..Default::default()
+            })
+            .await?
+            .check()?;
+        Ok(())
+    }
+
+    /// Records that the account has just been used.
+    ///
+    /// This intentionally doesn't change `updated_at`, as nothing about the
+    /// account itself has changed.
+    ///
+    /// Every way of signing in goes through this, so it's where deleted
+    /// accounts are turned away.
+    pub(super) async fn touch(&self, acc: Account) -> Result<Account> {
+        if acc.deleted_at.is_some() {
+            return Err(Error::AccountDeleted);
+        }
+        let mut update = vec![];
+        self.persist
+            .clock()
+            .now()
+            .push_field(srql::field("last_active_at"), &mut update);
+        let touched: Option<Account> = self
+            .persist
+            .db()
+            .query(srql::UpdateStatement {
+                what: srql::thing(acc.id.clone()),
+                data: srql::Data::SetExpression(update).into(),
+                output: srql::Output::After.into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        Ok(touched.unwrap_or(acc))
+    }
+}
+
+/// Restricts an account for a number of hours on behalf of the current
+/// account, as [`AccountPersist::restrict`] does. This doesn't need the rest
+/// of [`AccountPersist`], so background jobs can restrict accounts for the
+/// admin that asked them to.
+pub async fn restrict_account(
+    persist: &Persist,
+    current: &CurrentAccount,
+    id: &str,
+    kind: RestrictionKind,
+    hours: u32,
+    reason: String,
+) -> Result<Option<Account>> {
+    let actor = require_permission(persist, current, Permission::RestrictAccounts).await?;
+    if !(1..=RESTRICTION_MAX_HOURS).contains(&hours) {
+        return Err(Error::InputInvalid(format!(
+            "restrictions must last between 1 and {RESTRICTION_MAX_HOURS} hours"
+        )));
+    }
+    let reason = reason.trim().to_owned();
+    if reason.is_empty() {
+        return Err(Error::InputInvalid(
+            "a reason must be given for restricting an account".into(),
+        ));
+    }
+    if restrictable(persist, &actor, id).await?.is_none() {
+        return Ok(None);
+    }
+
+    let now = persist.clock().now();
+    let mut update = vec![];
+    AccountRestriction {
+        kind,
+        reason,
+        restricted_at: now,
+        expires_at: now + Duration::hours(hours.into()),
+    }
+    .push_field(srql::field("restriction"), &mut update);
+    if kind == RestrictionKind::Suspended {
+        now.push_field(srql::field("revoked_at"), &mut update);
+    }
+    let acc: Option<Account> = persist
+        .db()
+        .query(srql::UpdateStatement {
+            what: srql::thing(id.to_account_thing()),
+            data: srql::Data::SetExpression(update).into(),
+            output: srql::Output::After.into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+
+    if let Some(acc) = &acc {
+        if kind == RestrictionKind::Suspended {
+            revoke_sessions_of(persist, &acc.id, None).await?;
+        }
+        if kind != RestrictionKind::ShadowLimited {
+            NotificationPersist::new(persist, current)
+                .notify(CreateNotification {
+                    account_id: acc.id.clone(),
+                    kind: NotificationKind::Restricted,
+                    actor_id: None,
+                    post_id: None,
+                    subject_id: Some(acc.id.clone()),
+                })
+                .await?;
+        }
+        // Accounts aren't shown that they've been shadow-limited, so nobody
+        // else is told either.
+        if kind != RestrictionKind::ShadowLimited {
+            persist.account_feed().publish(acc);
+        }
+    }
+    Ok(acc)
+}
+
+/// Gets an account that the actor can restrict, which has to have a lower
+/// role than the actor. Admins can't be restricted at all.
+async fn restrictable(persist: &Persist, actor: &Account, id: &str) -> Result<Option<Account>> {
+    let Some(acc) = persist
+        .load::<Account>(srql::Thing::from((ACC_TABLE_NAME, id)))
+        .await?
+    else {
+        return Ok(None);
+    };
+    match acc.effective_role() {
+        AccountRole::Admin => Err(Error::InputInvalid("admins can't be restricted".into())),
+        role if role >= actor.effective_role() => Err(Error::Unauthorized),
+        _ => Ok(Some(acc)),
+    }
+}
+
+/// Gets the current account, failing if it isn't an instance admin.
+#[instrument(skip_all)]
+pub async fn require_admin(persist: &Persist, current: &CurrentAccount) -> Result<Account> {
+    require_role(persist, current, AccountRole::Admin).await
+}
+
+/// Stores the skeletons of the user IDs of accounts registered before they
+/// were kept, so that new user IDs are checked against them. Returns how
+/// many were stored.
+#[instrument(skip_all)]
+pub async fn backfill_user_id_skeletons(persist: &Persist) -> Result<usize> {
+    #[derive(Deserialize)]
+    struct Missing {
+        id: srql::Thing,
+        user_id: String,
+    }
+
+    let missing: Vec<Missing> = persist
+        .db()
+        .query(srql::SelectStatement {
+            expr: srql::Fields(
+                vec![
+                    srql::Field::Single {
+                        expr: srql::field("id").into(),
+                        alias: None,
+                    },
+                    srql::Field::Single {
+                        expr: srql::field("user_id").into(),
+                        alias: None,
+                    },
+                ],
+                false,
+            ),
+            what: srql::table(ACC_TABLE_NAME),
+            cond: srql::Cond(
+                srql::Expression::Binary {
+                    l: srql::field("user_id_skeleton").into(),
+                    o: srql::Operator::Equal,
+                    r: srql::Value::None,
+                }
+                .into(),
+            )
+            .into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    if missing.is_empty() {
+        return Ok(0);
+    }
+
+    let mut statements = vec![srql::trans_begin()];
+    for Missing { id, user_id } in &missing {
+        statements.push(srql::Statement::Update(srql::UpdateStatement {
+            what: srql::thing(id.clone()),
+            data: srql::Data::SetExpression(vec![(
+                srql::field("user_id_skeleton"),
+                srql::Operator::Equal,
+                srql::string(user_id_skeleton(user_id)).into(),
+            )])
+            .into(),
+            output: srql::Output::None.into(),
+            ..Default::default()
+        }));
+    }
+    statements.push(srql::trans_end());
+    persist.db().query(srql::query(statements)).await?.check()?;
+    Ok(missing.len())
+}
+
+/// Clears the restrictions that have expired from accounts. Returns how
+/// many were cleared.
+#[instrument(skip_all)]
+pub async fn expire_restrictions(persist: &Persist) -> Result<usize> {
+    let binary = |l: srql::Idiom, o, r| -> srql::Value {
+        srql::Expression::Binary { l: l.into(), o, r }.into()
+    };
+    let expired: Vec<Account> = persist
+        .db()
+        .query(srql::UpdateStatement {
+            what: srql::table(ACC_TABLE_NAME),
+            data: srql::Data::SetExpression(vec![(
+                srql::field("restriction"),
+                srql::Operator::Equal,
+                srql::Value::None,
+            )])
+            .into(),
+            cond: srql::Cond(
+                srql::Expression::Binary {
+                    l: binary(
+                        srql::field("restriction"),
+                        srql::Operator::NotEqual,
+                        srql::Value::None,
+                    ),
+                    o: srql::Operator::And,
+                    r: binary(
+                        srql::path(&["restriction", "expires_at"]),
+                        srql::Operator::LessThanOrEqual,
+                        srql::Value::Datetime(srql::Datetime(persist.clock().now())),
+                    ),
+                }
+                .into(),
+            )
+            .into(),
+            output: srql::Output::Before.into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    Ok(expired.len())
+}
+
+/// Whether an account can see age-restricted boards. Anonymous viewers
+/// can't.
+pub async fn is_adult(persist: &Persist, viewer: Option<&srql::Thing>) -> Result<bool> {
+    let Some(viewer) = viewer else {
+        return Ok(false);
+    };
+    let acc: Option<Account> = persist.load(viewer.clone()).await?;
+    Ok(acc.is_some_and(|acc| acc.adult))
 }
 
 #[cfg(test)]
 impl AccountPersist<'_> {
+    /// Creates an account for an adult.
     pub async fn create_test_user(&self) -> super::testing::AccData {
+        self.create_test_user_born(chrono::NaiveDate::from_ymd_opt(2000, 1, 1))
+            .await
+    }
+
+    pub async fn create_test_user_born(
+        &self,
+        birthdate: Option<chrono::NaiveDate>,
+    ) -> super::testing::AccData {
         let mut user_id = [0u8; 16];
         self.csrng.fill(&mut user_id).unwrap();
-        let user_id = BASE64_STANDARD_NO_PAD.encode(user_id);
+        let user_id = hex::encode(user_id);
         let mut pword = [0u8; 16];
         self.csrng.fill(&mut pword).unwrap();
         let pword = BASE64_STANDARD_NO_PAD.encode(pword);
@@ -152,6 +1851,40 @@ impl AccountPersist<'_> {
             user_id: user_id.clone(),
             pword: pword.clone().into(),
             invite: None,
+            bot: None,
+            accepted_policy_ids: Some(self.current_policy_ids().await.unwrap()),
+            birthdate,
+            honeypot: None,
+            form_shown_at: None,
+            allow_confusable: None,
+            email: None,
+        };
+
+        let acc = self.create(acc).await.unwrap().account;
+        super::testing::AccData {
+            id: acc.id.clone(),
+            user_id,
+            pword: pword.into(),
+            acc,
+        }
+    }
+
+    /// Creates a bot owned by the current account.
+    pub async fn create_test_bot(&self) -> super::testing::AccData {
+        let user_id = format!("bot-{}", self.persist.ids().next_id());
+        let pword = "bot-password".to_owned();
+
+        let acc = CreateAccount {
+            user_id: user_id.clone(),
+            pword: pword.clone().into(),
+            invite: None,
+            bot: Some(true),
+            accepted_policy_ids: Some(self.current_policy_ids().await.unwrap()),
+            birthdate: None,
+            honeypot: None,
+            form_shown_at: None,
+            allow_confusable: None,
+            email: None,
         };
 
         let acc = self.create(acc).await.unwrap().account;
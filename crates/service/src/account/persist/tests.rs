@@ -1,5 +1,43 @@
+use std::sync::Arc;
+
+use chrono::{Duration, NaiveDate, TimeZone as _, Utc};
+use futures::StreamExt as _;
+
 use super::*;
-use crate::account::{create_refresh_token, testing::*};
+use crate::{
+    account::{
+        anonymize_departed_accounts, authenticate_api_key, create_disown_token,
+        create_refresh_token, create_two_factor_token,
+        dev::{DEV_ACCOUNTS, DEV_PASSWORD},
+        expire_restrictions, issue_refresh_token, list_api_keys, list_external_identities,
+        list_passkeys,
+        passkey::testing::{TestAuthenticator, TEST_PUBLIC_URL},
+        process_dormant_accounts, prune_email_verifications, prune_login_throttles,
+        prune_password_resets, prune_recovery_requests, prune_refresh_tokens,
+        purge_deleted_accounts, recovery_codes_remaining, released_user_id,
+        testing::*,
+        totp::{testing::totp_code_at, LOW_RECOVERY_CODES, RECOVERY_CODE_COUNT},
+        AccountRestriction, AccountRole, ApiKeyScope, AuthContext, CreateApiKey, DisownClaims,
+        DormancyPolicy, ExternalProfile, LoginResult, MemoryOidcClient, Permission,
+        RestrictionKind, TwoFactorClaims, ACCOUNT_MAX_LOGIN_FAILURES, EMAIL_VERIFICATION_HOURS,
+        EMAIL_VERIFICATION_RESEND_MINUTES, IP_MAX_LOGIN_FAILURES, LOGIN_FAILURE_WINDOW_MINUTES,
+        LOGIN_THROTTLE_HOURS, PASSWORD_RESET_MINUTES, RECOVERY_WINDOW_HOURS,
+        RELEASED_USER_ID_PREFIX,
+    },
+    config::{
+        OidcConfig, OidcProviderConfig, OidcProviderKind, PasswordHashConfig, PrivacyConfig,
+        SessionConfig,
+    },
+    credentials::{testing::pbkdf2_creds, Argon2Hasher},
+    follow::testing::FollowTestData as _,
+    moderation::testing::ModerationTestData as _,
+    notification::testing::NotificationTestData as _,
+    post::{testing::PostTestData as _, CreatePost},
+    provider::{MockClock, MockIdGen},
+    query::PaginationInput,
+    security::{SecurityEvent, SecurityEventKind},
+    session::{testing::SessionTestData as _, ClientMeta, Session},
+};
 
 #[tokio::test]
 async fn test_create() {
@@ -10,6 +48,13 @@ async fn test_create() {
         user_id: "test".into(),
         pword: "test".to_owned().into(),
         invite: None,
+        bot: None,
+        accepted_policy_ids: None,
+        birthdate: None,
+        honeypot: None,
+        form_shown_at: None,
+        allow_confusable: None,
+        email: None,
     };
 
     let res = acc_persist.create(acc).await;
@@ -20,6 +65,244 @@ async fn test_create() {
     assert_eq!(res.account.user_id, "test");
 }
 
+#[tokio::test]
+async fn test_create_user_id_rules() {
+    let data = TestData::new().await;
+    let acc_persist = data.account();
+    let create = |user_id: &str| {
+        acc_persist.create(CreateAccount {
+            user_id: user_id.into(),
+            pword: "test".to_owned().into(),
+            invite: None,
+            bot: None,
+            accepted_policy_ids: None,
+            birthdate: None,
+            honeypot: None,
+            form_shown_at: None,
+            allow_confusable: None,
+            email: None,
+        })
+    };
+
+    let acc = create(" Mixed.Case ").await.unwrap().account;
+    assert_eq!(acc.user_id, "mixed.case");
+    assert_eq!(
+        create("MIXED.case").await.unwrap_err(),
+        Error::UnavailableIdent
+    );
+    assert!(matches!(
+        create("not valid").await,
+        Err(Error::InputInvalid(_))
+    ));
+    assert!(matches!(create("me").await, Err(Error::InputInvalid(_))));
+
+    let res = acc_persist
+        .login(
+            AuthCreds {
+                user_id: "Mixed.Case".into(),
+                pword: "test".to_owned().into(),
+            },
+            None,
+        )
+        .await;
+    assert_eq!(res.unwrap().unwrap_authenticated().account.id, acc.id);
+}
+
+#[tokio::test]
+async fn test_create_confusable() {
+    let (mut data, _) = TestData::with_user().await;
+    let create = |user_id: &str, allow_confusable| CreateAccount {
+        user_id: user_id.into(),
+        pword: "test".to_owned().into(),
+        invite: None,
+        bot: None,
+        accepted_policy_ids: None,
+        birthdate: None,
+        honeypot: None,
+        form_shown_at: None,
+        allow_confusable,
+        email: None,
+    };
+
+    data.account()
+        .create(create("mallory", None))
+        .await
+        .unwrap();
+    assert_eq!(
+        data.account()
+            .create(create("rnallory", None))
+            .await
+            .unwrap_err(),
+        Error::UserIdConfusable
+    );
+    assert_eq!(
+        data.account()
+            .create(create("adrnin", None))
+            .await
+            .unwrap_err(),
+        Error::UserIdConfusable
+    );
+    assert_eq!(
+        data.account()
+            .create(create("mallory", None))
+            .await
+            .unwrap_err(),
+        Error::UnavailableIdent
+    );
+
+    // Admins can register lookalikes, such as for an organization's own
+    // accounts.
+    let acc = data
+        .account()
+        .create(create("rnallory", Some(true)))
+        .await
+        .unwrap()
+        .account;
+    assert_eq!(acc.user_id, "rnallory");
+
+    let other = data.account().create_test_user().await;
+    data.login_as(&other);
+    assert_eq!(
+        data.account()
+            .create(create("rna11ory", Some(true)))
+            .await
+            .unwrap_err(),
+        Error::Unauthorized
+    );
+
+    // The instance can allow them for everyone.
+    let acc = data
+        .account()
+        .with_confusable_user_ids(true)
+        .create(create("rna11ory", None))
+        .await
+        .unwrap()
+        .account;
+    assert_eq!(acc.user_id, "rna11ory");
+}
+
+#[tokio::test]
+async fn test_backfill_user_id_skeletons() {
+    let data = TestData::new().await;
+    let acc_persist = data.account();
+    let create = |user_id: &str| {
+        acc_persist.create(CreateAccount {
+            user_id: user_id.into(),
+            pword: "test".to_owned().into(),
+            invite: None,
+            bot: None,
+            accepted_policy_ids: None,
+            birthdate: None,
+            honeypot: None,
+            form_shown_at: None,
+            allow_confusable: None,
+            email: None,
+        })
+    };
+
+    // Accounts registered before skeletons were kept don't have them.
+    create("mallory").await.unwrap();
+    data.persist
+        .db()
+        .query(srql::UpdateStatement {
+            what: srql::table(ACC_TABLE_NAME),
+            data: srql::Data::UnsetExpression(vec![
+                srql::field("user_id_skeleton"),
+                srql::field("user_id_claim"),
+            ])
+            .into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+    create("rnallory").await.unwrap();
+
+    assert_eq!(backfill_user_id_skeletons(&data.persist).await, Ok(1));
+    assert_eq!(backfill_user_id_skeletons(&data.persist).await, Ok(0));
+    assert_eq!(
+        create("rna11ory").await.unwrap_err(),
+        Error::UserIdConfusable
+    );
+}
+
+#[tokio::test]
+async fn test_create_concurrent() {
+    let data = TestData::new().await;
+    let acc_persist = data.account();
+    let create = || {
+        acc_persist.create(CreateAccount {
+            user_id: "racing".into(),
+            pword: "test".to_owned().into(),
+            invite: None,
+            bot: None,
+            accepted_policy_ids: None,
+            birthdate: None,
+            honeypot: None,
+            form_shown_at: None,
+            allow_confusable: None,
+            email: None,
+        })
+    };
+
+    // Only one of the registrations gets the user ID, however they race.
+    let (a, b) = tokio::join!(create(), create());
+    let mut errors: Vec<_> = [a, b].into_iter().filter_map(Result::err).collect();
+    assert_eq!(errors.pop(), Some(Error::UnavailableIdent));
+    assert!(errors.is_empty());
+}
+
+#[tokio::test]
+async fn test_create_confusable_concurrent() {
+    let data = TestData::new().await;
+    let acc_persist = data.account();
+    let create = |user_id: &str| {
+        acc_persist.create(CreateAccount {
+            user_id: user_id.into(),
+            pword: "test".to_owned().into(),
+            invite: None,
+            bot: None,
+            accepted_policy_ids: None,
+            birthdate: None,
+            honeypot: None,
+            form_shown_at: None,
+            allow_confusable: None,
+            email: None,
+        })
+    };
+
+    // Only one of a set of lookalikes gets registered, however they race.
+    let res = futures::future::join_all(["mallory", "rnallory", "rna11ory"].map(create)).await;
+    let errors: Vec<_> = res.into_iter().filter_map(Result::err).collect();
+    assert_eq!(errors, vec![Error::UserIdConfusable; 2]);
+}
+
+#[tokio::test]
+async fn test_create_deterministic() {
+    let start = Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
+    let mut data = TestData::with_clock(MockClock::new(start)).await;
+    data.persist = data.persist.with_ids(Arc::new(MockIdGen::new(start)));
+    let acc_persist = data.account();
+
+    let acc = CreateAccount {
+        user_id: "test".into(),
+        pword: "test".to_owned().into(),
+        invite: None,
+        bot: None,
+        accepted_policy_ids: None,
+        birthdate: None,
+        honeypot: None,
+        form_shown_at: None,
+        allow_confusable: None,
+        email: None,
+    };
+
+    let res = acc_persist.create(acc).await.unwrap();
+    assert_eq!(res.account.id.id.to_raw(), MockIdGen::new(start).next_id());
+    assert_eq!(res.account.last_active_at, Some(start));
+}
+
 #[tokio::test]
 async fn test_get() {
     let data = TestData::new().await;
@@ -64,6 +347,13 @@ async fn test_duplicate_user_id() {
         user_id,
         pword: "test2".to_owned().into(),
         invite: None,
+        bot: None,
+        accepted_policy_ids: None,
+        birthdate: None,
+        honeypot: None,
+        form_shown_at: None,
+        allow_confusable: None,
+        email: None,
     };
 
     let res = acc_persist.create(acc).await;
@@ -81,18 +371,167 @@ async fn test_login() {
     let AccData { user_id, pword, .. } = acc_persist.create_test_user().await;
 
     let res = acc_persist
-        .login(AuthCreds {
-            user_id: user_id.clone(),
-            pword,
-        })
+        .login(
+            AuthCreds {
+                user_id: user_id.clone(),
+                pword,
+            },
+            None,
+        )
         .await;
     println!("{res:?}");
     assert!(res.is_ok());
 
-    let res = res.unwrap();
+    let res = res.unwrap().unwrap_authenticated();
     assert_eq!(res.account.user_id, user_id);
 }
 
+async fn login_rehash(data: &TestData, acc: &AccData) {
+    data.account()
+        .login(
+            AuthCreds {
+                user_id: acc.user_id.clone(),
+                pword: acc.pword.clone(),
+            },
+            None,
+        )
+        .await
+        .unwrap();
+}
+
+async fn stored_hash(data: &TestData, acc: &AccData) -> SecretString {
+    let acc = data.account().get(&acc.id.to_gql_id()).await.unwrap();
+    acc.unwrap().pword_hash
+}
+
+#[tokio::test]
+async fn test_login_rehash() {
+    let mut data = TestData::new().await;
+    let acc = data.account().create_test_user().await;
+    let creds = pbkdf2_creds(&data.csrng, acc.pword.expose_secret());
+    let mut update = vec![];
+    creds
+        .salt
+        .push_field(srql::field("pword_salt"), &mut update);
+    creds
+        .hash
+        .push_field(srql::field("pword_hash"), &mut update);
+    data.persist
+        .db()
+        .query(srql::obj_update_query(acc.id.clone(), update).unwrap())
+        .await
+        .unwrap();
+    // Passwords hashed before Argon2id still work, and are hashed again.
+    login_rehash(&data, &acc).await;
+    let rehashed = stored_hash(&data, &acc).await;
+    assert!(rehashed.expose_secret().starts_with("$argon2id$"));
+    login_rehash(&data, &acc).await;
+    assert_eq!(
+        stored_hash(&data, &acc).await.expose_secret(),
+        rehashed.expose_secret()
+    );
+
+    // So are hashes made with other parameters.
+    data.persist = data.persist.with_password_hasher(Arc::new(
+        Argon2Hasher::new(PasswordHashConfig {
+            memory_kib: 16,
+            iterations: 1,
+            parallelism: 1,
+        })
+        .unwrap(),
+    ));
+    login_rehash(&data, &acc).await;
+    assert!(stored_hash(&data, &acc)
+        .await
+        .expose_secret()
+        .starts_with("$argon2id$v=19$m=16,t=1,p=1$"));
+}
+
+async fn login_from(
+    data: &TestData,
+    user_id: &str,
+    pword: &str,
+    ip: [u8; 4],
+) -> Result<LoginResult> {
+    let client = ClientMeta {
+        ip: Some(ip.into()),
+        ..Default::default()
+    };
+    data.account()
+        .login(
+            AuthCreds {
+                user_id: user_id.to_owned(),
+                pword: pword.to_owned().into(),
+            },
+            Some(&client),
+        )
+        .await
+}
+
+#[tokio::test]
+async fn test_login_throttle() {
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap());
+    let data = TestData::with_clock(clock.clone()).await;
+    let acc = data.account().create_test_user().await;
+    let pword = acc.pword.expose_secret();
+    let home = [192, 0, 2, 1];
+
+    for _ in 0..ACCOUNT_MAX_LOGIN_FAILURES {
+        let res = login_from(&data, &acc.user_id, "wrong-password", home).await;
+        assert_eq!(res.unwrap_err(), Error::CredentialsInvalid);
+    }
+    assert_eq!(data.persist.metrics().login_lockouts(), 1);
+    // Even the right password is turned away until the lockout ends, from
+    // any address.
+    let res = login_from(&data, &acc.user_id, pword, [192, 0, 2, 2]).await;
+    assert_eq!(res.unwrap_err(), Error::TooManyAttempts(60));
+    clock.advance(Duration::seconds(30));
+    let res = login_from(&data, &acc.user_id, pword, home).await;
+    assert_eq!(res.unwrap_err(), Error::TooManyAttempts(30));
+
+    // Each lockout lasts twice as long as the one before.
+    clock.advance(Duration::seconds(30));
+    for _ in 0..ACCOUNT_MAX_LOGIN_FAILURES {
+        let res = login_from(&data, &acc.user_id, "wrong-password", home).await;
+        assert_eq!(res.unwrap_err(), Error::CredentialsInvalid);
+    }
+    let res = login_from(&data, &acc.user_id, pword, home).await;
+    assert_eq!(res.unwrap_err(), Error::TooManyAttempts(120));
+
+    // Failures outside the window aren't counted, and logging in clears them.
+    clock.advance(Duration::minutes(2));
+    for _ in 1..ACCOUNT_MAX_LOGIN_FAILURES {
+        let res = login_from(&data, &acc.user_id, "wrong-password", home).await;
+        assert_eq!(res.unwrap_err(), Error::CredentialsInvalid);
+    }
+    clock.advance(Duration::minutes(LOGIN_FAILURE_WINDOW_MINUTES));
+    let res = login_from(&data, &acc.user_id, "wrong-password", home).await;
+    assert_eq!(res.unwrap_err(), Error::CredentialsInvalid);
+    assert!(login_from(&data, &acc.user_id, pword, home).await.is_ok());
+    for _ in 1..ACCOUNT_MAX_LOGIN_FAILURES {
+        let res = login_from(&data, &acc.user_id, "wrong-password", home).await;
+        assert_eq!(res.unwrap_err(), Error::CredentialsInvalid);
+    }
+    assert!(login_from(&data, &acc.user_id, pword, home).await.is_ok());
+    assert_eq!(data.persist.metrics().login_lockouts(), 2);
+
+    // Addresses are locked after trying lots of accounts, even ones that
+    // don't exist.
+    let other = [198, 51, 100, 1];
+    for i in 0..IP_MAX_LOGIN_FAILURES {
+        let res = login_from(&data, &format!("missing{i}"), "wrong-password", other).await;
+        assert_eq!(res.unwrap_err(), Error::CredentialsInvalid);
+    }
+    let res = login_from(&data, &acc.user_id, pword, other).await;
+    assert_eq!(res.unwrap_err(), Error::TooManyAttempts(60));
+    assert!(login_from(&data, &acc.user_id, pword, home).await.is_ok());
+
+    // Both addresses are forgotten after a day, but the account was cleared
+    // by logging in.
+    clock.advance(Duration::hours(LOGIN_THROTTLE_HOURS + 1));
+    assert_eq!(prune_login_throttles(&data.persist).await.unwrap(), 2);
+}
+
 #[tokio::test]
 async fn test_login_fail() {
     let data = TestData::new().await;
@@ -100,10 +539,13 @@ async fn test_login_fail() {
     let AccData { user_id, .. } = acc_persist.create_test_user().await;
 
     let res = acc_persist
-        .login(AuthCreds {
-            user_id,
-            pword: "bad password".to_owned().into(),
-        })
+        .login(
+            AuthCreds {
+                user_id,
+                pword: "bad password".to_owned().into(),
+            },
+            None,
+        )
         .await;
     println!("{res:?}");
     assert!(res.is_err());
@@ -117,7 +559,14 @@ async fn test_refresh() {
     let data = TestData::new().await;
     let acc_persist = data.account();
     let AccData { user_id, acc, .. } = acc_persist.create_test_user().await;
-    let refresh_token = create_refresh_token(acc.id.to_gql_id(), &data.jwt_enc_key).unwrap();
+    let refresh_token = create_refresh_token(
+        acc.id.to_gql_id(),
+        None,
+        None,
+        &data.jwt_enc_key,
+        data.persist.clock(),
+    )
+    .unwrap();
 
     let res = acc_persist.refresh(refresh_token).await;
     println!("{res:?}");
@@ -128,6 +577,1372 @@ async fn test_refresh() {
     assert_eq!(res.account.user_id, user_id);
 }
 
+#[tokio::test]
+async fn test_refresh_rotation() {
+    let (data, AccData { acc, .. }) = TestData::with_user().await;
+    let issue = |family_id| {
+        issue_refresh_token(
+            &data.persist,
+            &data.csrng,
+            acc.id.clone(),
+            None,
+            family_id,
+            None,
+        )
+    };
+    let first = issue(None).await.unwrap();
+
+    let res = data.account().refresh(first.clone()).await;
+    println!("{res:?}");
+    let refreshed = res.unwrap();
+    assert_eq!(refreshed.account.id, acc.id);
+    let family_id = refreshed.refresh_family_id.unwrap();
+    let second = issue(Some(family_id)).await.unwrap();
+
+    // A token can only be exchanged once, and reusing it revokes every token
+    // rotated from it.
+    let res = data.account().refresh(first).await;
+    assert_eq!(res.unwrap_err(), Error::CredentialsInvalid);
+    let res = data.account().refresh(second).await;
+    assert_eq!(res.unwrap_err(), Error::CredentialsInvalid);
+
+    // Other families aren't affected.
+    let other = issue(None).await.unwrap();
+    let (id, _) = other.split_once('.').unwrap();
+    let res = data.account().refresh(format!("{id}.wrong")).await;
+    assert_eq!(res.unwrap_err(), Error::CredentialsInvalid);
+    assert!(data.account().refresh(other).await.is_ok());
+}
+
+#[tokio::test]
+async fn test_refresh_expired() {
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap());
+    let data = TestData::with_clock(clock.clone()).await;
+    let AccData { acc, .. } = data.account().create_test_user().await;
+    let issue = |expires_by| {
+        issue_refresh_token(
+            &data.persist,
+            &data.csrng,
+            acc.id.clone(),
+            None,
+            None,
+            expires_by,
+        )
+    };
+    let token = issue(None).await.unwrap();
+    let short = issue(Some(clock.now() + Duration::days(1))).await.unwrap();
+
+    clock.advance(Duration::days(2));
+    let res = data.account().refresh(short).await;
+    assert_eq!(res.unwrap_err(), Error::CredentialsInvalid);
+    assert_eq!(prune_refresh_tokens(&data.persist).await.unwrap(), 1);
+
+    clock.advance(Duration::days(29));
+    let res = data.account().refresh(token).await;
+    assert_eq!(res.unwrap_err(), Error::CredentialsInvalid);
+    assert_eq!(prune_refresh_tokens(&data.persist).await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn test_password_reset() {
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap());
+    let mut data = TestData::with_clock(clock.clone()).await;
+    let emails = data.record_emails();
+    let acc = data.account().create_test_user().await;
+    data.login_as(&acc);
+    data.account()
+        .update(UpdateAccount {
+            email: MaybeUndefined::Value("alice@example.com".into()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    emails.take();
+    let refresh_token =
+        issue_refresh_token(&data.persist, &data.csrng, acc.id.clone(), None, None, None)
+            .await
+            .unwrap();
+    let request = || async {
+        data.account()
+            .request_password_reset(&acc.user_id)
+            .await
+            .unwrap();
+        let mut sent = emails.take();
+        assert_eq!(sent.len(), 1);
+        let email = sent.remove(0);
+        assert_eq!(email.to, "alice@example.com");
+        email
+            .body
+            .lines()
+            .find(|line| !line.is_empty() && !line.contains(' '))
+            .unwrap()
+            .to_owned()
+    };
+    let new_pword: SecretString = "new password".to_owned().into();
+
+    // Requesting another token replaces the first.
+    let replaced = request().await;
+    let token = request().await;
+    let res = data.account().reset_password(&replaced, &new_pword).await;
+    assert_eq!(res.unwrap_err(), Error::PasswordResetInvalid);
+
+    clock.advance(Duration::minutes(1));
+    data.account()
+        .reset_password(&token, &new_pword)
+        .await
+        .unwrap();
+    let res = data.account().reset_password(&token, &new_pword).await;
+    assert_eq!(res.unwrap_err(), Error::PasswordResetInvalid);
+
+    // The old password and tokens stop working.
+    let acc_persist = data.account();
+    let login = |pword: &SecretString| {
+        acc_persist.login(
+            AuthCreds {
+                user_id: acc.user_id.clone(),
+                pword: pword.clone(),
+            },
+            None,
+        )
+    };
+    assert_eq!(
+        login(&acc.pword).await.unwrap_err(),
+        Error::CredentialsInvalid
+    );
+    login(&new_pword).await.unwrap();
+    let res = data.account().refresh(refresh_token).await;
+    assert_eq!(res.unwrap_err(), Error::CredentialsInvalid);
+
+    let token = request().await;
+    clock.advance(Duration::minutes(PASSWORD_RESET_MINUTES));
+    let res = data.account().reset_password(&token, &new_pword).await;
+    assert_eq!(res.unwrap_err(), Error::PasswordResetInvalid);
+    assert_eq!(prune_password_resets(&data.persist).await, Ok(1));
+}
+
+#[tokio::test]
+async fn test_password_reset_not_sent() {
+    let mut data = TestData::new().await;
+    let emails = data.record_emails();
+    let acc = data.account().create_test_user().await;
+
+    // Nothing says whether the account exists or has an email address.
+    let acc_persist = data.account();
+    acc_persist.request_password_reset("missing").await.unwrap();
+    acc_persist
+        .request_password_reset(&acc.user_id)
+        .await
+        .unwrap();
+    assert_eq!(emails.take(), vec![]);
+
+    let res = acc_persist
+        .reset_password("not.a-token", &"new password".to_owned().into())
+        .await;
+    assert_eq!(res.unwrap_err(), Error::PasswordResetInvalid);
+}
+
+/// Takes the one email that was sent, returning who it was sent to and the
+/// code in it.
+fn take_code(emails: &crate::email::MemoryEmailSender) -> (String, String) {
+    let mut sent = emails.take();
+    assert_eq!(sent.len(), 1);
+    let email = sent.remove(0);
+    let code = email
+        .body
+        .lines()
+        .find(|line| !line.is_empty() && !line.contains(' '))
+        .unwrap()
+        .to_owned();
+    (email.to, code)
+}
+
+async fn set_email(data: &TestData, email: &str) -> Result<Option<Account>> {
+    data.account()
+        .update(UpdateAccount {
+            email: MaybeUndefined::Value(email.into()),
+            ..Default::default()
+        })
+        .await
+}
+
+async fn email_verified(data: &TestData) -> bool {
+    data.account()
+        .current()
+        .await
+        .unwrap()
+        .unwrap()
+        .is_email_verified()
+}
+
+async fn require_verified(data: &TestData) -> Result<()> {
+    data.account()
+        .with_verified_email_required(true)
+        .require_verified_email()
+        .await
+}
+
+#[tokio::test]
+async fn test_email_verification() {
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap());
+    let mut data = TestData::with_clock(clock.clone()).await;
+    let emails = data.record_emails();
+    let acc = data.account().create_test_user().await;
+    data.login_as(&acc);
+
+    set_email(&data, "alice@example.com").await.unwrap();
+    let (to, token) = take_code(&emails);
+    assert_eq!(to, "alice@example.com");
+    assert!(!email_verified(&data).await);
+
+    // Another can't be sent straight away, and sending another replaces the
+    // first.
+    let res = data.account().resend_verification().await;
+    assert_eq!(res.unwrap_err(), Error::RateLimited);
+    clock.advance(Duration::minutes(EMAIL_VERIFICATION_RESEND_MINUTES));
+    data.account().resend_verification().await.unwrap();
+    let (_, replacement) = take_code(&emails);
+    let res = data.account().verify_email(&token).await;
+    assert_eq!(res.unwrap_err(), Error::EmailVerificationInvalid);
+
+    data.current = CurrentAccount::default();
+    data.account().verify_email(&replacement).await.unwrap();
+    let res = data.account().verify_email(&replacement).await;
+    assert_eq!(res.unwrap_err(), Error::EmailVerificationInvalid);
+    data.login_as(&acc);
+    assert!(email_verified(&data).await);
+    let res = data.account().resend_verification().await;
+    assert!(matches!(res, Err(Error::InputInvalid(_))));
+
+    // Giving the same address keeps it verified, but a new one has to be
+    // verified again.
+    set_email(&data, " alice@EXAMPLE.com").await.unwrap();
+    assert_eq!(emails.take(), vec![]);
+    assert!(email_verified(&data).await);
+    clock.advance(Duration::minutes(EMAIL_VERIFICATION_RESEND_MINUTES));
+    set_email(&data, "bob@example.com").await.unwrap();
+    let (to, token) = take_code(&emails);
+    assert_eq!(to, "bob@example.com");
+    assert!(!email_verified(&data).await);
+
+    // Tokens stop working once the address changes, or they expire.
+    clock.advance(Duration::minutes(EMAIL_VERIFICATION_RESEND_MINUTES));
+    set_email(&data, "carol@example.com").await.unwrap();
+    let (_, carol_token) = take_code(&emails);
+    let res = data.account().verify_email(&token).await;
+    assert_eq!(res.unwrap_err(), Error::EmailVerificationInvalid);
+    clock.advance(Duration::hours(EMAIL_VERIFICATION_HOURS));
+    let res = data.account().verify_email(&carol_token).await;
+    assert_eq!(res.unwrap_err(), Error::EmailVerificationInvalid);
+    assert_eq!(prune_email_verifications(&data.persist).await, Ok(1));
+
+    // With a public URL, the token is sent as a link.
+    data.login_as(&acc);
+    data.account()
+        .with_public_url(Some(TEST_PUBLIC_URL))
+        .resend_verification()
+        .await
+        .unwrap();
+    let (_, link) = take_code(&emails);
+    let token = link
+        .strip_prefix(&format!("{TEST_PUBLIC_URL}/verify-email?token="))
+        .unwrap();
+    data.account().verify_email(token).await.unwrap();
+    assert!(email_verified(&data).await);
+}
+
+#[tokio::test]
+async fn test_email_already_in_use() {
+    let mut data = TestData::new().await;
+    let emails = data.record_emails();
+    let acc = data.account().create_test_user().await;
+    let other = data.account().create_test_user().await;
+    data.login_as(&acc);
+    set_email(&data, "alice@example.com").await.unwrap();
+    emails.take();
+
+    data.login_as(&other);
+    let res = set_email(&data, "alice@Example.com").await;
+    assert_eq!(res.unwrap_err(), Error::EmailAlreadyInUse);
+
+    // Registering with an address sends a link to verify it.
+    data.current = CurrentAccount::default();
+    let acc_persist = data.account();
+    let create = |email: &str| {
+        acc_persist.create(CreateAccount {
+            user_id: format!("new-{}", data.persist.ids().next_id()),
+            pword: "password".to_owned().into(),
+            invite: None,
+            bot: None,
+            accepted_policy_ids: None,
+            birthdate: None,
+            honeypot: None,
+            form_shown_at: None,
+            allow_confusable: None,
+            email: Some(email.into()),
+        })
+    };
+    let res = create("alice@example.com").await;
+    assert_eq!(res.unwrap_err(), Error::EmailAlreadyInUse);
+    let created = create("bob@example.com").await.unwrap().account;
+    assert_eq!(created.email.as_deref(), Some("bob@example.com"));
+    assert!(!created.is_email_verified());
+    let (to, token) = take_code(&emails);
+    assert_eq!(to, "bob@example.com");
+    acc_persist.verify_email(&token).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_require_verified_email() {
+    let mut data = TestData::new().await;
+    let emails = data.record_emails();
+    let admin = data.account().create_test_user().await;
+    let acc = data.account().create_test_user().await;
+    data.login_as(&acc);
+    let bot = data.account().create_test_bot().await;
+
+    // Nothing is required unless the instance asks for it.
+    data.account().require_verified_email().await.unwrap();
+    assert_eq!(require_verified(&data).await, Err(Error::EmailNotVerified));
+    set_email(&data, "alice@example.com").await.unwrap();
+    assert_eq!(require_verified(&data).await, Err(Error::EmailNotVerified));
+
+    // Bots go by their owner.
+    data.login_as(&bot);
+    assert_eq!(require_verified(&data).await, Err(Error::EmailNotVerified));
+    let (_, token) = take_code(&emails);
+    data.account().verify_email(&token).await.unwrap();
+    assert_eq!(require_verified(&data).await, Ok(()));
+    data.login_as(&acc);
+    assert_eq!(require_verified(&data).await, Ok(()));
+
+    // Admins don't need one.
+    data.login_as(&admin);
+    assert_eq!(require_verified(&data).await, Ok(()));
+}
+
+#[tokio::test]
+async fn test_two_factor() {
+    async fn login(data: &TestData, acc: &AccData) -> LoginResult {
+        data.account()
+            .login(
+                AuthCreds {
+                    user_id: acc.user_id.clone(),
+                    pword: acc.pword.clone(),
+                },
+                None,
+            )
+            .await
+            .unwrap()
+    }
+
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap());
+    let mut data = TestData::with_clock(clock.clone()).await;
+    let acc = data.account().create_test_user().await;
+    data.login_as(&acc);
+
+    let enrollment = data.account().enroll_two_factor().await.unwrap();
+    assert!(enrollment.provisioning_uri.starts_with(&format!(
+        "otpauth://totp/Plazer:{}?secret={}&",
+        acc.user_id, enrollment.secret
+    )));
+    let code = || totp_code_at(&enrollment.secret, clock.now());
+
+    // Two-factor authentication isn't needed until it's confirmed.
+    assert!(matches!(
+        login(&data, &acc).await,
+        LoginResult::Authenticated(_)
+    ));
+    let res = data.account().confirm_two_factor("000000").await;
+    assert_eq!(res.unwrap_err(), Error::TotpInvalid);
+    let recovery_codes = data.account().confirm_two_factor(&code()).await.unwrap();
+    assert_eq!(recovery_codes.len(), RECOVERY_CODE_COUNT);
+    let res = data.account().enroll_two_factor().await;
+    assert_eq!(res.unwrap_err(), Error::TotpAlreadyEnabled);
+
+    let LoginResult::TwoFactorRequired(required) = login(&data, &acc).await else {
+        panic!("Login didn't need a second factor");
+    };
+    assert_eq!(required.account_id, acc.id);
+    let challenge = || {
+        let claims = TwoFactorClaims::new(acc.id.to_gql_id(), clock.now());
+        create_two_factor_token(&claims, &data.jwt_enc_key).unwrap()
+    };
+
+    // Codes can't be used twice.
+    let res = data
+        .account()
+        .verify_two_factor(&challenge(), &code())
+        .await;
+    assert_eq!(res.unwrap_err(), Error::TotpInvalid);
+    clock.advance(Duration::seconds(30));
+    let res = data
+        .account()
+        .verify_two_factor(&challenge(), &code())
+        .await;
+    assert_eq!(res.unwrap().account.id, acc.id);
+
+    let recovery_code = recovery_codes[0].to_uppercase();
+    let res = data
+        .account()
+        .verify_two_factor(&challenge(), &recovery_code)
+        .await;
+    assert!(res.is_ok());
+    let res = data
+        .account()
+        .verify_two_factor(&challenge(), &recovery_code)
+        .await;
+    assert_eq!(res.unwrap_err(), Error::TotpInvalid);
+
+    // Too many wrong codes lock checking for a while.
+    for _ in 1..5 {
+        let res = data
+            .account()
+            .verify_two_factor(&challenge(), "wrong")
+            .await;
+        assert_eq!(res.unwrap_err(), Error::TotpInvalid);
+    }
+    clock.advance(Duration::seconds(30));
+    let res = data
+        .account()
+        .verify_two_factor(&challenge(), &code())
+        .await;
+    assert_eq!(res.unwrap_err(), Error::RateLimited);
+
+    clock.advance(Duration::minutes(15));
+    data.login_as(&acc);
+    assert!(data.account().disable_two_factor(&code()).await.unwrap());
+    assert!(matches!(
+        login(&data, &acc).await,
+        LoginResult::Authenticated(_)
+    ));
+}
+
+#[tokio::test]
+async fn test_two_factor_concurrent_failures() {
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap());
+    let mut data = TestData::with_clock(clock.clone()).await;
+    let acc = data.account().create_test_user().await;
+    data.login_as(&acc);
+    let enrollment = data.account().enroll_two_factor().await.unwrap();
+    data.account()
+        .confirm_two_factor(&totp_code_at(&enrollment.secret, clock.now()))
+        .await
+        .unwrap();
+    clock.advance(Duration::seconds(30));
+
+    // Wrong codes sent at the same time are all counted.
+    let results =
+        futures::future::join_all((0..5).map(|_| verify_totp(&data.persist, &acc.id, "wrong")))
+            .await;
+    for res in results {
+        assert_eq!(res.unwrap_err(), Error::TotpInvalid);
+    }
+    let res = verify_totp(
+        &data.persist,
+        &acc.id,
+        &totp_code_at(&enrollment.secret, clock.now()),
+    )
+    .await;
+    assert_eq!(res.unwrap_err(), Error::RateLimited);
+}
+
+#[tokio::test]
+async fn test_recovery_codes() {
+    async fn remaining(data: &TestData) -> Option<usize> {
+        let id = data.current.id().unwrap().to_account_thing();
+        recovery_codes_remaining(&data.persist, &id).await.unwrap()
+    }
+
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap());
+    let mut data = TestData::with_clock(clock.clone()).await;
+    let acc = data.account().create_test_user().await;
+    data.login_as(&acc);
+
+    let res = data.account().regenerate_recovery_codes("000000").await;
+    assert_eq!(res.unwrap_err(), Error::TotpNotEnrolled);
+    assert_eq!(remaining(&data).await, None);
+    let enrollment = data.account().enroll_two_factor().await.unwrap();
+    let recovery_codes = data
+        .account()
+        .confirm_two_factor(&totp_code_at(&enrollment.secret, clock.now()))
+        .await
+        .unwrap();
+    assert_eq!(remaining(&data).await, Some(RECOVERY_CODE_COUNT));
+
+    // Using recovery codes is logged, and running low sends a warning.
+    let challenge = || {
+        let claims = TwoFactorClaims::new(acc.id.to_gql_id(), clock.now());
+        create_two_factor_token(&claims, &data.jwt_enc_key).unwrap()
+    };
+    let low = RECOVERY_CODE_COUNT - LOW_RECOVERY_CODES;
+    for code in &recovery_codes[..low] {
+        let res = data.account().verify_two_factor(&challenge(), code).await;
+        assert!(res.is_ok());
+    }
+    assert_eq!(remaining(&data).await, Some(LOW_RECOVERY_CODES));
+    let events: Vec<SecurityEvent> = data
+        .persist
+        .db()
+        .select(SECURITY_EVENT_TABLE_NAME)
+        .await
+        .unwrap();
+    let used = events
+        .iter()
+        .filter(|event| event.kind == SecurityEventKind::RecoveryCodeUsed)
+        .count();
+    assert_eq!(used, low);
+    let notifications = data
+        .notification()
+        .list()
+        .unwrap()
+        .with_pagination(PaginationInput::new().forward(10))
+        .execute()
+        .await
+        .unwrap();
+    let warnings = notifications
+        .edges
+        .iter()
+        .filter(|edge| edge.node.kind == NotificationKind::RecoveryCodesLow)
+        .count();
+    assert_eq!(warnings, 1);
+
+    // Regenerating them needs a code, and the old ones stop working.
+    let res = data.account().regenerate_recovery_codes("wrong").await;
+    assert_eq!(res.unwrap_err(), Error::TotpInvalid);
+    clock.advance(Duration::seconds(30));
+    let new_codes = data
+        .account()
+        .regenerate_recovery_codes(&totp_code_at(&enrollment.secret, clock.now()))
+        .await
+        .unwrap();
+    assert_eq!(new_codes.len(), RECOVERY_CODE_COUNT);
+    assert_eq!(remaining(&data).await, Some(RECOVERY_CODE_COUNT));
+    let res = data
+        .account()
+        .verify_two_factor(&challenge(), &recovery_codes[low])
+        .await;
+    assert_eq!(res.unwrap_err(), Error::TotpInvalid);
+    let res = data
+        .account()
+        .verify_two_factor(&challenge(), &new_codes[0])
+        .await;
+    assert!(res.is_ok());
+}
+
+#[tokio::test]
+async fn test_recovery_contacts() {
+    let (mut data, admin) = TestData::with_user().await;
+    let acc = data.account().create_test_user().await;
+    let alice = data.account().create_test_user().await;
+    let bob = data.account().create_test_user().await;
+    data.login_as(&acc);
+    let ids = |accs: &[&AccData]| accs.iter().map(|acc| acc.id.to_gql_id()).collect();
+
+    let res = data
+        .account()
+        .set_recovery_contacts(ids(&[&alice, &bob]), 3)
+        .await;
+    assert!(matches!(res.unwrap_err(), Error::InputInvalid(_)));
+    let res = data
+        .account()
+        .set_recovery_contacts(ids(&[&alice, &bob]), 0)
+        .await;
+    assert!(matches!(res.unwrap_err(), Error::InputInvalid(_)));
+    let res = data.account().set_recovery_contacts(ids(&[&acc]), 1).await;
+    assert!(matches!(res.unwrap_err(), Error::InputInvalid(_)));
+    let res = data
+        .account()
+        .set_recovery_contacts(ids(&[&alice, &alice]), 1)
+        .await;
+    assert!(matches!(res.unwrap_err(), Error::InputInvalid(_)));
+    let res = data
+        .account()
+        .set_recovery_contacts(vec![ID::from("missing")], 1)
+        .await;
+    assert!(matches!(res.unwrap_err(), Error::InputInvalid(_)));
+
+    let contacts = data
+        .account()
+        .set_recovery_contacts(ids(&[&alice, &bob, &admin]), 2)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        contacts.contact_ids,
+        vec![alice.id.clone(), bob.id.clone(), admin.id.clone()]
+    );
+    assert_eq!(contacts.threshold, 2);
+
+    // Removing the contacts turns recovery through them off.
+    let res = data.account().set_recovery_contacts(vec![], 0).await;
+    assert_eq!(res, Ok(None));
+    data.current = CurrentAccount::default();
+    let res = data.account().request_recovery(&acc.user_id).await;
+    assert!(matches!(res.unwrap_err(), Error::InputInvalid(_)));
+}
+
+#[tokio::test]
+async fn test_recovery() {
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap());
+    let mut data = TestData::with_clock(clock.clone()).await;
+    let acc = data.account().create_test_user().await;
+    let alice = data.account().create_test_user().await;
+    let bob = data.account().create_test_user().await;
+    let carol = data.account().create_test_user().await;
+    data.login_as(&acc);
+    data.account()
+        .set_recovery_contacts(vec![alice.id.to_gql_id(), bob.id.to_gql_id()], 2)
+        .await
+        .unwrap();
+    let enrollment = data.account().enroll_two_factor().await.unwrap();
+    data.account()
+        .confirm_two_factor(&totp_code_at(&enrollment.secret, clock.now()))
+        .await
+        .unwrap();
+
+    data.current = CurrentAccount::default();
+    let started = data.account().request_recovery(&acc.user_id).await.unwrap();
+    let id = started.recovery.id.id.to_raw();
+    let token = started.token;
+
+    // Each contact is asked to approve it.
+    data.login_as(&alice);
+    let notifications = data
+        .notification()
+        .list()
+        .unwrap()
+        .with_pagination(PaginationInput::new().forward(10))
+        .execute()
+        .await
+        .unwrap();
+    assert_eq!(notifications.edges.len(), 1);
+    let notification = &notifications.edges[0].node;
+    assert_eq!(notification.kind, NotificationKind::RecoveryApproval);
+    assert_eq!(notification.subject_id.as_ref(), Some(&started.recovery.id));
+
+    let res = data.account().recover_account(&token).await;
+    assert_eq!(res.unwrap_err(), Error::RecoveryNotApproved);
+    let res = data.account().approve_recovery(&id).await.unwrap().unwrap();
+    assert_eq!(res.approved_ids, vec![alice.id.clone()]);
+    let res = data.account().approve_recovery(&id).await.unwrap().unwrap();
+    assert_eq!(res.approved_ids, vec![alice.id.clone()]);
+    assert!(!res.is_approved());
+
+    data.login_as(&carol);
+    let res = data.account().approve_recovery(&id).await;
+    assert_eq!(res.unwrap_err(), Error::Unauthorized);
+
+    data.login_as(&bob);
+    data.account().approve_recovery(&id).await.unwrap();
+    data.current = CurrentAccount::default();
+    assert!(data
+        .account()
+        .recovery_status(&token)
+        .await
+        .unwrap()
+        .is_approved());
+
+    // Recovering turns two-factor authentication off, and gives a code for
+    // choosing a new password.
+    let reset = data.account().recover_account(&token).await.unwrap();
+    let res = data.account().recover_account(&token).await;
+    assert_eq!(res.unwrap_err(), Error::RecoveryInvalid);
+    let new_pword: SecretString = "new password".to_owned().into();
+    data.account()
+        .reset_password(&reset, &new_pword)
+        .await
+        .unwrap();
+    let res = data
+        .account()
+        .login(
+            AuthCreds {
+                user_id: acc.user_id.clone(),
+                pword: new_pword,
+            },
+            None,
+        )
+        .await;
+    assert!(matches!(res.unwrap(), LoginResult::Authenticated(_)));
+}
+
+#[tokio::test]
+async fn test_recovery_expiry() {
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap());
+    let mut data = TestData::with_clock(clock.clone()).await;
+    let acc = data.account().create_test_user().await;
+    let alice = data.account().create_test_user().await;
+    data.login_as(&acc);
+    data.account()
+        .set_recovery_contacts(vec![alice.id.to_gql_id()], 1)
+        .await
+        .unwrap();
+
+    // Only a few recoveries can be waiting at once.
+    data.current = CurrentAccount::default();
+    let mut started = vec![];
+    for _ in 0..MAX_PENDING_RECOVERIES {
+        started.push(data.account().request_recovery(&acc.user_id).await.unwrap());
+    }
+    let res = data.account().request_recovery(&acc.user_id).await;
+    assert_eq!(res.unwrap_err(), Error::RateLimited);
+
+    clock.advance(Duration::hours(RECOVERY_WINDOW_HOURS));
+    data.login_as(&alice);
+    let id = started[0].recovery.id.id.to_raw();
+    assert_eq!(data.account().approve_recovery(&id).await, Ok(None));
+    data.current = CurrentAccount::default();
+    let res = data.account().recovery_status(&started[0].token).await;
+    assert_eq!(res.unwrap_err(), Error::RecoveryInvalid);
+    assert_eq!(
+        prune_recovery_requests(&data.persist).await,
+        Ok(MAX_PENDING_RECOVERIES)
+    );
+    data.account().request_recovery(&acc.user_id).await.unwrap();
+}
+
+fn passkeys(data: &TestData) -> AccountPersist<'_> {
+    data.account().with_public_url(Some(TEST_PUBLIC_URL))
+}
+
+#[tokio::test]
+async fn test_passkeys() {
+    let mut data = TestData::new().await;
+    let acc = data.account().create_test_user().await;
+    let other = data.account().create_test_user().await;
+
+    data.login_as(&acc);
+    let res = data.account().begin_passkey_registration().await;
+    assert_eq!(res.unwrap_err(), Error::PasskeysUnavailable);
+
+    let mut key = TestAuthenticator::new(b"key-1");
+    let options = passkeys(&data).begin_passkey_registration().await.unwrap();
+    assert_eq!(options.rp_id, "plazer.test");
+    assert_eq!(options.user_name, acc.user_id);
+    let res = passkeys(&data)
+        .finish_passkey_registration(key.create("unknown"))
+        .await;
+    assert_eq!(res.unwrap_err(), Error::PasskeyInvalid);
+    let passkey = passkeys(&data)
+        .finish_passkey_registration(key.create(&options.challenge))
+        .await
+        .unwrap();
+    assert_eq!(passkey.account_id, acc.id);
+
+    // Challenges can't be used twice.
+    let res = passkeys(&data)
+        .finish_passkey_registration(key.create(&options.challenge))
+        .await;
+    assert_eq!(res.unwrap_err(), Error::PasskeyInvalid);
+    let options = passkeys(&data).begin_passkey_registration().await.unwrap();
+    assert_eq!(options.exclude_credential_ids, vec![key.credential_id()]);
+
+    // A passkey on its own logs in, if it verified who's using it.
+    data.current = CurrentAccount::default();
+    let options = passkeys(&data).begin_passkey_login(None).await.unwrap();
+    assert!(options.allow_credential_ids.is_empty());
+    assert_eq!(options.user_verification, "required");
+    let assertion = key.get(&options.challenge);
+    let res = passkeys(&data).finish_passkey_login(&assertion).await;
+    assert_eq!(res.unwrap().account.id, acc.id);
+    let res = passkeys(&data).finish_passkey_login(&assertion).await;
+    assert_eq!(res.unwrap_err(), Error::PasskeyInvalid);
+
+    key.user_verified = false;
+    let options = passkeys(&data).begin_passkey_login(None).await.unwrap();
+    let res = passkeys(&data)
+        .finish_passkey_login(&key.get(&options.challenge))
+        .await;
+    assert_eq!(res.unwrap_err(), Error::PasskeyInvalid);
+
+    // Signature counts that don't go up mean the passkey has been copied.
+    key.user_verified = true;
+    key.sign_count = 0;
+    let options = passkeys(&data).begin_passkey_login(None).await.unwrap();
+    let res = passkeys(&data)
+        .finish_passkey_login(&key.get(&options.challenge))
+        .await;
+    assert_eq!(res.unwrap_err(), Error::PasskeyInvalid);
+    key.sign_count = 10;
+
+    // As a second factor, only the account's own passkeys can be used, and
+    // they don't have to verify who's using them.
+    data.login_as(&other);
+    let mut other_key = TestAuthenticator::new(b"key-2");
+    let options = passkeys(&data).begin_passkey_registration().await.unwrap();
+    passkeys(&data)
+        .finish_passkey_registration(other_key.create(&options.challenge))
+        .await
+        .unwrap();
+    data.current = CurrentAccount::default();
+    let claims = TwoFactorClaims::new(acc.id.to_gql_id(), data.persist.clock().now());
+    let challenge_token = create_two_factor_token(&claims, &data.jwt_enc_key).unwrap();
+    let options = passkeys(&data)
+        .begin_passkey_login(Some(&challenge_token))
+        .await
+        .unwrap();
+    assert_eq!(options.allow_credential_ids, vec![key.credential_id()]);
+    assert_eq!(options.user_verification, "preferred");
+    let res = passkeys(&data)
+        .finish_passkey_login(&other_key.get(&options.challenge))
+        .await;
+    assert_eq!(res.unwrap_err(), Error::PasskeyInvalid);
+    key.user_verified = false;
+    let options = passkeys(&data)
+        .begin_passkey_login(Some(&challenge_token))
+        .await
+        .unwrap();
+    let res = passkeys(&data)
+        .finish_passkey_login(&key.get(&options.challenge))
+        .await;
+    assert_eq!(res.unwrap().account.id, acc.id);
+
+    // Passkeys can only be removed by their own accounts.
+    data.login_as(&acc);
+    let res = data
+        .account()
+        .remove_passkey(&other_key.credential_id())
+        .await;
+    assert!(res.unwrap().is_none());
+    let res = data.account().remove_passkey(&key.credential_id()).await;
+    assert!(res.unwrap().is_some());
+    assert!(list_passkeys(&data.persist, &acc.id)
+        .await
+        .unwrap()
+        .is_empty());
+    let res = passkeys(&data)
+        .begin_passkey_login(Some(&challenge_token))
+        .await;
+    assert_eq!(res.unwrap_err(), Error::PasskeyInvalid);
+}
+
+fn external<'a>(data: &'a TestData, oidc: &'a OidcConfig) -> AccountPersist<'a> {
+    data.account().with_oidc(Some(oidc))
+}
+
+fn external_profile(subject: &str) -> ExternalProfile {
+    ExternalProfile {
+        subject: subject.into(),
+        email: None,
+        email_verified: false,
+        name: None,
+    }
+}
+
+const EXTERNAL_REDIRECT_URI: &str = "https://plazer.test/callback";
+
+/// Creates two accounts, with a single test provider backed by the returned
+/// client.
+async fn external_data() -> (TestData, MemoryOidcClient, OidcConfig, AccData, AccData) {
+    let mut data = TestData::new().await;
+    let acc = data.account().create_test_user().await;
+    let other = data.account().create_test_user().await;
+    let client = MemoryOidcClient::default();
+    data.persist = data
+        .persist
+        .clone()
+        .with_oidc_client(Arc::new(client.clone()));
+    let oidc = OidcConfig::new(vec![OidcProviderConfig {
+        id: "test".into(),
+        kind: OidcProviderKind::Oidc,
+        name: None,
+        client_id: "client".into(),
+        client_secret: "secret".into(),
+        authorization_url: Some("https://idp.test/authorize".into()),
+        token_url: Some("https://idp.test/token".into()),
+        userinfo_url: Some("https://idp.test/userinfo".into()),
+        scopes: None,
+    }])
+    .unwrap();
+    (data, client, oidc, acc, other)
+}
+
+#[tokio::test]
+async fn test_external_link() {
+    let (mut data, client, oidc, acc, other) = external_data().await;
+
+    data.login_as(&acc);
+    let res = external(&data, &oidc)
+        .begin_external_link("unknown", EXTERNAL_REDIRECT_URI)
+        .await;
+    assert!(matches!(res, Err(Error::InputInvalid(_))));
+    let res = external(&data, &oidc)
+        .begin_external_link("test", "/callback")
+        .await;
+    assert!(matches!(res, Err(Error::InputInvalid(_))));
+
+    let redirect = external(&data, &oidc)
+        .begin_external_link("test", EXTERNAL_REDIRECT_URI)
+        .await
+        .unwrap();
+    assert!(redirect
+        .url
+        .starts_with("https://idp.test/authorize?response_type=code&client_id=client&"));
+    assert!(redirect
+        .url
+        .contains(&format!("&state={}&", redirect.state)));
+    assert!(redirect.url.ends_with("&code_challenge_method=S256"));
+    let res = external(&data, &oidc)
+        .finish_external_link(&redirect.state, "code-1")
+        .await;
+    assert_eq!(res.unwrap_err(), Error::ExternalLoginInvalid);
+
+    // States can't be used twice.
+    let redirect = external(&data, &oidc)
+        .begin_external_link("test", EXTERNAL_REDIRECT_URI)
+        .await
+        .unwrap();
+    client.authorize("test", "code-1", external_profile("1234"));
+    let identity = external(&data, &oidc)
+        .finish_external_link(&redirect.state, "code-1")
+        .await
+        .unwrap();
+    assert_eq!(identity.account_id, acc.id);
+    assert_eq!(identity.subject, "1234");
+    client.authorize("test", "code-1", external_profile("1234"));
+    let res = external(&data, &oidc)
+        .finish_external_link(&redirect.state, "code-1")
+        .await;
+    assert_eq!(res.unwrap_err(), Error::ExternalLoginInvalid);
+
+    // An identity can only be linked to one account.
+    data.login_as(&other);
+    let redirect = external(&data, &oidc)
+        .begin_external_link("test", EXTERNAL_REDIRECT_URI)
+        .await
+        .unwrap();
+    client.authorize("test", "code-2", external_profile("1234"));
+    let res = external(&data, &oidc)
+        .finish_external_link(&redirect.state, "code-2")
+        .await;
+    assert_eq!(res.unwrap_err(), Error::UnavailableIdent);
+}
+
+#[tokio::test]
+async fn test_external_login() {
+    let (mut data, client, oidc, acc, _) = external_data().await;
+
+    data.login_as(&acc);
+    let redirect = external(&data, &oidc)
+        .begin_external_link("test", EXTERNAL_REDIRECT_URI)
+        .await
+        .unwrap();
+    client.authorize("test", "code-1", external_profile("1234"));
+    external(&data, &oidc)
+        .finish_external_link(&redirect.state, "code-1")
+        .await
+        .unwrap();
+
+    // Link flows can't be finished by logging in.
+    let redirect = external(&data, &oidc)
+        .begin_external_link("test", EXTERNAL_REDIRECT_URI)
+        .await
+        .unwrap();
+    data.current = CurrentAccount::default();
+    client.authorize("test", "code-3", external_profile("1234"));
+    let res = external(&data, &oidc)
+        .finish_external_login(&redirect.state, "code-3")
+        .await;
+    assert_eq!(res.unwrap_err(), Error::ExternalLoginInvalid);
+
+    let LoginResult::ExternalLoginRedirect(redirect) = external(&data, &oidc)
+        .begin_external_login("test", EXTERNAL_REDIRECT_URI)
+        .await
+        .unwrap()
+    else {
+        panic!("Logging in with a provider didn't redirect");
+    };
+    let res = external(&data, &oidc)
+        .finish_external_login(&redirect.state, "code-3")
+        .await;
+    assert_eq!(res.unwrap().unwrap_authenticated().account.id, acc.id);
+
+    let redirect = external(&data, &oidc)
+        .begin_external_login("test", EXTERNAL_REDIRECT_URI)
+        .await
+        .unwrap();
+    let LoginResult::ExternalLoginRedirect(redirect) = redirect else {
+        panic!("Logging in with a provider didn't redirect");
+    };
+    client.authorize("test", "code-4", external_profile("5678"));
+    let res = external(&data, &oidc)
+        .finish_external_login(&redirect.state, "code-4")
+        .await;
+    assert_eq!(res.unwrap_err(), Error::ExternalIdentityNotLinked);
+
+    data.login_as(&acc);
+    let identities = list_external_identities(&data.persist, &acc.id)
+        .await
+        .unwrap();
+    assert_eq!(identities.len(), 1);
+    assert!(identities[0].last_used_at.is_some());
+    let id = identities[0].id.id.to_raw();
+    let res = external(&data, &oidc).unlink_external_identity(&id).await;
+    assert_eq!(res.unwrap().unwrap().subject, "1234");
+    let res = external(&data, &oidc).unlink_external_identity(&id).await;
+    assert!(res.unwrap().is_none());
+
+    let events: Vec<SecurityEvent> = data
+        .persist
+        .db()
+        .select(SECURITY_EVENT_TABLE_NAME)
+        .await
+        .unwrap();
+    let events: Vec<_> = events.into_iter().map(|event| event.kind).collect();
+    assert!(events.contains(&SecurityEventKind::ExternalIdentityLinked));
+    assert!(events.contains(&SecurityEventKind::ExternalIdentityUnlinked));
+}
+
+#[tokio::test]
+async fn test_api_keys() {
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap());
+    let mut data = TestData::with_clock(clock.clone()).await;
+    let admin = data.account().create_test_user().await;
+    let acc = data.account().create_test_user().await;
+    let create = |scopes: Vec<ApiKeyScope>, expires_in_days| CreateApiKey {
+        name: "Test bot".into(),
+        scopes,
+        expires_in_days,
+    };
+
+    data.login_as(&acc);
+    let res = data.account().create_api_key(create(vec![], None)).await;
+    assert!(matches!(res, Err(Error::InputInvalid(_))));
+    let read = data
+        .account()
+        .create_api_key(create(vec![ApiKeyScope::Read, ApiKeyScope::Read], Some(1)))
+        .await
+        .unwrap();
+    assert!(read.key.starts_with("plz_"));
+    assert_eq!(read.api_key.scopes, vec![ApiKeyScope::Read]);
+    assert_eq!(
+        read.api_key.expires_at,
+        Some(clock.now() + Duration::days(1))
+    );
+    let write = data
+        .account()
+        .create_api_key(create(vec![ApiKeyScope::Write, ApiKeyScope::Read], None))
+        .await
+        .unwrap();
+    assert_eq!(
+        write.api_key.scopes,
+        vec![ApiKeyScope::Read, ApiKeyScope::Write]
+    );
+    let keys = list_api_keys(&data.persist, &acc.id).await.unwrap();
+    assert_eq!(keys, vec![read.api_key.clone(), write.api_key.clone()]);
+
+    // Keys act as the account that made them, limited to their scopes.
+    let current = authenticate_api_key(&data.persist, &read.key)
+        .await
+        .unwrap();
+    assert_eq!(current.id().unwrap(), &acc.id.to_gql_id());
+    assert!(current.is_api_key());
+    assert!(current.has_scope(ApiKeyScope::Read));
+    assert!(!current.has_scope(ApiKeyScope::Write));
+    assert!(data.current.has_scope(ApiKeyScope::Write));
+    let keys = list_api_keys(&data.persist, &acc.id).await.unwrap();
+    assert_eq!(keys[0].last_used_at, Some(clock.now()));
+    let res = authenticate_api_key(&data.persist, &format!("{}x", read.key)).await;
+    assert_eq!(res.unwrap_err(), Error::CredentialsInvalid);
+
+    // Keys can't be used to make or revoke keys.
+    data.current = current;
+    let res = data
+        .account()
+        .create_api_key(create(vec![ApiKeyScope::Read], None))
+        .await;
+    assert_eq!(res.unwrap_err(), Error::Unauthorized);
+    let res = data
+        .account()
+        .revoke_api_key(&write.api_key.id.id.to_raw())
+        .await;
+    assert_eq!(res.unwrap_err(), Error::Unauthorized);
+
+    clock.advance(Duration::days(1));
+    let res = authenticate_api_key(&data.persist, &read.key).await;
+    assert_eq!(res.unwrap_err(), Error::CredentialsInvalid);
+
+    // Suspended accounts' keys stop working until the suspension ends.
+    data.login_as(&admin);
+    data.account()
+        .restrict(
+            &acc.id.to_gql_id(),
+            RestrictionKind::Suspended,
+            1,
+            "Spam".into(),
+        )
+        .await
+        .unwrap();
+    let res = authenticate_api_key(&data.persist, &write.key).await;
+    assert_eq!(res.unwrap_err(), Error::AccountSuspended);
+    clock.advance(Duration::hours(1));
+    authenticate_api_key(&data.persist, &write.key)
+        .await
+        .unwrap();
+
+    // Only the account that made a key can revoke it.
+    data.login_as(&admin);
+    let write_id = write.api_key.id.id.to_raw();
+    let res = data.account().revoke_api_key(&write_id).await.unwrap();
+    assert_eq!(res, None);
+    data.login_as(&acc);
+    let res = data.account().revoke_api_key(&write_id).await.unwrap();
+    assert_eq!(res.unwrap().id, write.api_key.id);
+    let res = authenticate_api_key(&data.persist, &write.key).await;
+    assert_eq!(res.unwrap_err(), Error::CredentialsInvalid);
+}
+
+async fn auth_context(data: &TestData) -> AuthContext {
+    AuthContext::resolve(&data.persist, &data.current)
+        .await
+        .unwrap()
+}
+
+/// Creates an admin, two moderators and an account without a role, signed
+/// in as the admin.
+async fn role_accounts() -> (TestData, [AccData; 4]) {
+    let mut data = TestData::new().await;
+    let admin = data.account().create_test_user().await;
+    let moderator = data.account().create_test_user().await;
+    let other_moderator = data.account().create_test_user().await;
+    let acc = data.account().create_test_user().await;
+    data.login_as(&admin);
+    for id in [&moderator.id, &other_moderator.id] {
+        data.account()
+            .set_role(&id.to_gql_id(), AccountRole::Moderator)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+    (data, [admin, moderator, other_moderator, acc])
+}
+
+#[tokio::test]
+async fn test_roles() {
+    let mut data = TestData::new().await;
+    let admin = data.account().create_test_user().await;
+    let moderator = data.account().create_test_user().await;
+    let acc = data.account().create_test_user().await;
+
+    data.login_as(&acc);
+    assert_eq!(auth_context(&data).await.role, AccountRole::User);
+    assert!(auth_context(&data).await.permissions.is_empty());
+    let res = data
+        .account()
+        .set_role(&moderator.id.to_gql_id(), AccountRole::Moderator)
+        .await;
+    assert_eq!(res.unwrap_err(), Error::Unauthorized);
+
+    data.login_as(&admin);
+    assert_eq!(auth_context(&data).await.role, AccountRole::Admin);
+    let res = data
+        .account()
+        .set_role(&admin.id.to_gql_id(), AccountRole::User)
+        .await;
+    assert!(matches!(res, Err(Error::InputInvalid(_))));
+    let res = data
+        .account()
+        .set_role(&moderator.id.to_gql_id(), AccountRole::Moderator)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(res.effective_role(), AccountRole::Moderator);
+    assert!(!res.admin);
+}
+
+#[tokio::test]
+async fn test_moderator_permissions() {
+    let (mut data, [admin, moderator, other_moderator, acc]) = role_accounts().await;
+    let reason = || "Spamming replies".to_owned();
+
+    // Moderators can work through the moderation queue and restrict
+    // accounts, but not other moderators or admins.
+    data.login_as(&moderator);
+    let auth_ctx = auth_context(&data).await;
+    assert!(auth_ctx.has_role(AccountRole::Moderator));
+    assert!(!auth_ctx.has_role(AccountRole::Admin));
+    assert!(auth_ctx.has_permission(Permission::ModerateContent));
+    assert!(!auth_ctx.has_permission(Permission::ManageInstance));
+    data.moderation().list().await.unwrap();
+    let res = data
+        .account()
+        .restrict(&acc.id.to_gql_id(), RestrictionKind::Silenced, 1, reason())
+        .await;
+    assert!(res.unwrap().unwrap().restriction.is_some());
+    data.account()
+        .unrestrict(&acc.id.to_gql_id())
+        .await
+        .unwrap()
+        .unwrap();
+    let res = data
+        .account()
+        .restrict(
+            &other_moderator.id.to_gql_id(),
+            RestrictionKind::Silenced,
+            1,
+            reason(),
+        )
+        .await;
+    assert_eq!(res.unwrap_err(), Error::Unauthorized);
+    let res = data
+        .account()
+        .restrict(
+            &admin.id.to_gql_id(),
+            RestrictionKind::Silenced,
+            1,
+            reason(),
+        )
+        .await;
+    assert!(matches!(res, Err(Error::InputInvalid(_))));
+    let res = data
+        .account()
+        .set_role(&acc.id.to_gql_id(), AccountRole::Moderator)
+        .await;
+    assert_eq!(res.unwrap_err(), Error::Unauthorized);
+    let res = require_admin(&data.persist, &data.current).await;
+    assert_eq!(res.unwrap_err(), Error::Unauthorized);
+}
+
+#[tokio::test]
+async fn test_admin_roles() {
+    let (mut data, [admin, moderator, other_moderator, _]) = role_accounts().await;
+
+    // Admins can restrict moderators, and promoted admins can demote the
+    // first one.
+    let res = data
+        .account()
+        .restrict(
+            &other_moderator.id.to_gql_id(),
+            RestrictionKind::Silenced,
+            1,
+            "Spamming replies".to_owned(),
+        )
+        .await;
+    assert!(res.unwrap().unwrap().restriction.is_some());
+    let res = data
+        .account()
+        .set_role(&moderator.id.to_gql_id(), AccountRole::Admin)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(res.admin);
+    data.login_as(&moderator);
+    let res = data
+        .account()
+        .set_role(&admin.id.to_gql_id(), AccountRole::User)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(res.effective_role(), AccountRole::User);
+    data.login_as(&admin);
+    let res = data.moderation().list().await;
+    assert!(matches!(res, Err(Error::Unauthorized)));
+}
+
+#[tokio::test]
+async fn test_restrict() {
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap());
+    let mut data = TestData::with_clock(clock.clone()).await;
+    let admin = data.account().create_test_user().await;
+    let acc = data.account().create_test_user().await;
+    let refresh_token =
+        issue_refresh_token(&data.persist, &data.csrng, acc.id.clone(), None, None, None)
+            .await
+            .unwrap();
+    clock.advance(Duration::minutes(1));
+    let acc_id = acc.id.to_gql_id();
+    let reason = || "Spamming replies".to_owned();
+
+    data.login_as(&acc);
+    let res = data
+        .account()
+        .restrict(&acc_id, RestrictionKind::Silenced, 1, reason())
+        .await;
+    assert_eq!(res.unwrap_err(), Error::Unauthorized);
+
+    data.login_as(&admin);
+    let acc_persist = data.account();
+    let res = acc_persist
+        .restrict(
+            &admin.id.to_gql_id(),
+            RestrictionKind::Suspended,
+            1,
+            reason(),
+        )
+        .await;
+    assert!(matches!(res, Err(Error::InputInvalid(_))));
+    let res = acc_persist
+        .restrict(&acc_id, RestrictionKind::Suspended, 0, reason())
+        .await;
+    assert!(matches!(res, Err(Error::InputInvalid(_))));
+    let res = acc_persist
+        .restrict(&acc_id, RestrictionKind::Suspended, 1, " ".into())
+        .await;
+    assert!(matches!(res, Err(Error::InputInvalid(_))));
+
+    let res = acc_persist
+        .restrict(&acc_id, RestrictionKind::Suspended, 2, reason())
+        .await
+        .unwrap()
+        .unwrap();
+    let now = clock.now();
+    assert_eq!(
+        res.restriction,
+        Some(AccountRestriction {
+            kind: RestrictionKind::Suspended,
+            reason: reason(),
+            restricted_at: now,
+            expires_at: now + Duration::hours(2),
+        })
+    );
+
+    // Suspended accounts are signed out and can't sign back in.
+    let login = || {
+        acc_persist.login(
+            AuthCreds {
+                user_id: acc.user_id.clone(),
+                pword: acc.pword.clone(),
+            },
+            None,
+        )
+    };
+    assert_eq!(login().await.unwrap_err(), Error::AccountSuspended);
+    let res = acc_persist.refresh(refresh_token).await;
+    assert_eq!(res.unwrap_err(), Error::CredentialsInvalid);
+
+    clock.advance(Duration::hours(2));
+    login().await.unwrap();
+    assert_eq!(expire_restrictions(&data.persist).await, Ok(1));
+    assert_eq!(expire_restrictions(&data.persist).await, Ok(0));
+    let res = acc_persist.get(&acc_id).await.unwrap().unwrap();
+    assert_eq!(res.restriction, None);
+
+    // Restrictions can be lifted early, and shadow limits aren't notified.
+    data.login_as(&admin);
+    let acc_persist = data.account();
+    let res = acc_persist
+        .restrict(&acc_id, RestrictionKind::ShadowLimited, 1, reason())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(res.restriction.is_some());
+    let res = acc_persist.unrestrict(&acc_id).await.unwrap().unwrap();
+    assert_eq!(res.restriction, None);
+
+    data.login_as(&acc);
+    let notifications = data
+        .notification()
+        .list()
+        .unwrap()
+        .with_pagination(PaginationInput::new().forward(10))
+        .execute()
+        .await
+        .unwrap();
+    assert_eq!(notifications.edges.len(), 1);
+    let notification = &notifications.edges[0].node;
+    assert_eq!(notification.kind, NotificationKind::Restricted);
+    assert_eq!(notification.subject_id, Some(acc.id));
+}
+
 #[tokio::test]
 async fn test_access_token_fail() {
     let data = TestData::new().await;
@@ -145,7 +1960,14 @@ async fn test_access_token_fail() {
 async fn test_revoke_tokens() {
     let (data, AccData { acc, .. }) = TestData::with_user().await;
     let acc_persist = data.account();
-    let refresh_token = create_refresh_token(acc.id.into_gql_id(), &data.jwt_enc_key).unwrap();
+    let refresh_token = create_refresh_token(
+        acc.id.into_gql_id(),
+        None,
+        None,
+        &data.jwt_enc_key,
+        data.persist.clock(),
+    )
+    .unwrap();
 
     let res = acc_persist.revoke_tokens().await;
     println!("{res:?}");
@@ -171,3 +1993,858 @@ async fn test_revoke_tokens_fail() {
     let res = res.unwrap_err();
     assert_eq!(res, Error::Unauthenticated);
 }
+
+#[tokio::test]
+async fn test_update() {
+    let (data, acc) = TestData::with_user().await;
+    assert!(!acc.acc.quotes_disabled);
+
+    let res = data
+        .account()
+        .update(UpdateAccount {
+            quotes_disabled: Some(true),
+            ..Default::default()
+        })
+        .await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+
+    let res = res.unwrap().unwrap();
+    assert!(res.quotes_disabled);
+}
+
+#[tokio::test]
+async fn test_subscribe() {
+    let (mut data, admin) = TestData::with_user().await;
+    let user = data.account().create_test_user().await;
+    let user_id = user.id.to_gql_id();
+    let mut updated = Box::pin(data.account().subscribe(&user_id).unwrap());
+
+    // Changes to other accounts aren't sent.
+    data.account()
+        .update(UpdateAccount {
+            quotes_disabled: Some(true),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    data.login_as(&user);
+    data.account()
+        .update(UpdateAccount {
+            quotes_disabled: Some(true),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let acc = updated.next().await.unwrap();
+    assert_eq!(acc.id, user.id);
+    assert!(acc.quotes_disabled);
+
+    // Nobody is told about shadow limits.
+    data.login_as(&admin);
+    data.account()
+        .restrict(&user_id, RestrictionKind::ShadowLimited, 1, "Spam".into())
+        .await
+        .unwrap();
+    let next = tokio::time::timeout(std::time::Duration::from_millis(100), updated.next()).await;
+    assert!(next.is_err(), "{next:?}");
+    data.account()
+        .set_role(&user_id, AccountRole::Moderator)
+        .await
+        .unwrap();
+    assert_eq!(updated.next().await.unwrap().role, AccountRole::Moderator);
+
+    data.current = CurrentAccount::default();
+    let res = data.account().subscribe(&user_id).map(|_| ());
+    assert_eq!(res, Err(Error::Unauthenticated));
+}
+
+#[tokio::test]
+async fn test_update_email() {
+    let (data, _) = TestData::with_user().await;
+    let update = |email| UpdateAccount {
+        email,
+        ..Default::default()
+    };
+
+    let acc = data
+        .account()
+        .update(update(MaybeUndefined::Value(" Alice@Example.com ".into())))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(acc.email.as_deref(), Some("Alice@example.com"));
+
+    let res = data
+        .account()
+        .update(update(MaybeUndefined::Value("not an address".into())))
+        .await;
+    assert!(matches!(res, Err(Error::InputInvalid(_))));
+
+    let acc = data
+        .account()
+        .update(update(MaybeUndefined::Null))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(acc.email, None);
+}
+
+#[tokio::test]
+async fn test_update_locale() {
+    let (data, _) = TestData::with_user().await;
+    let update = |locale| UpdateAccount {
+        locale,
+        ..Default::default()
+    };
+
+    let acc = data
+        .account()
+        .update(update(MaybeUndefined::Value("en-gb".into())))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(acc.locale.as_deref(), Some("en-GB"));
+
+    let res = data
+        .account()
+        .update(update(MaybeUndefined::Value("not a locale".into())))
+        .await;
+    assert!(matches!(res, Err(Error::InputInvalid(_))));
+
+    let acc = data
+        .account()
+        .update(update(MaybeUndefined::Null))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(acc.locale, None);
+}
+
+#[tokio::test]
+async fn test_update_timezone() {
+    let (data, _) = TestData::with_user().await;
+    let update = |timezone| UpdateAccount {
+        timezone,
+        ..Default::default()
+    };
+
+    let acc = data
+        .account()
+        .update(update(MaybeUndefined::Value(" Europe/London".into())))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(acc.timezone.as_deref(), Some("Europe/London"));
+
+    let res = data
+        .account()
+        .update(update(MaybeUndefined::Value("Europe/Atlantis".into())))
+        .await;
+    assert!(matches!(res, Err(Error::InputInvalid(_))));
+
+    let acc = data
+        .account()
+        .update(update(MaybeUndefined::Null))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(acc.timezone, None);
+}
+
+#[tokio::test]
+async fn test_first_account_admin() {
+    let (data, acc) = TestData::with_user().await;
+    assert!(acc.acc.admin);
+
+    let other = data.account().create_test_user().await;
+    assert!(!other.acc.admin);
+}
+
+#[tokio::test]
+async fn test_login_last_active() {
+    let (data, acc) = TestData::with_user().await;
+
+    let res = data
+        .account()
+        .login(
+            AuthCreds {
+                user_id: acc.user_id.clone(),
+                pword: acc.pword.clone(),
+            },
+            None,
+        )
+        .await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+
+    let res = res.unwrap().unwrap_authenticated().account;
+    assert!(res.last_active_at > acc.acc.last_active_at);
+    assert_eq!(res.updated_at, acc.acc.updated_at);
+}
+
+#[tokio::test]
+async fn test_seed_dev_accounts() {
+    let data = TestData::new().await;
+    let acc_persist = data.account();
+
+    acc_persist.seed_dev_accounts().await.unwrap();
+    // Seeding again shouldn't fail on the existing accounts.
+    acc_persist.seed_dev_accounts().await.unwrap();
+
+    for user_id in DEV_ACCOUNTS {
+        let res = acc_persist
+            .login(
+                AuthCreds {
+                    user_id: (*user_id).into(),
+                    pword: DEV_PASSWORD.to_owned().into(),
+                },
+                None,
+            )
+            .await;
+        println!("{res:?}");
+        assert!(res.is_ok());
+    }
+}
+
+#[tokio::test]
+async fn test_dev_login() {
+    let data = TestData::new().await;
+    let acc_persist = data.account();
+    acc_persist.seed_dev_accounts().await.unwrap();
+
+    let res = acc_persist.dev_login(DEV_ACCOUNTS[0]).await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap().account.user_id, DEV_ACCOUNTS[0]);
+
+    // Only seeded accounts can be logged into without a password.
+    let AccData { user_id, .. } = acc_persist.create_test_user().await;
+    let res = acc_persist.dev_login(&user_id).await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap_err(), Error::CredentialsInvalid);
+}
+
+#[tokio::test]
+async fn test_create_bot() {
+    let (data, owner) = TestData::with_user().await;
+
+    let bot = data.account().create_test_bot().await;
+    assert!(bot.acc.bot);
+    assert!(!bot.acc.admin);
+    assert_eq!(bot.acc.owner_id, Some(owner.id));
+    assert!(!owner.acc.bot);
+}
+
+#[tokio::test]
+async fn test_create_bot_anon() {
+    let data = TestData::new().await;
+    data.account().create_test_user().await;
+
+    let res = data
+        .account()
+        .create(CreateAccount {
+            user_id: "bot".into(),
+            pword: "bot-password".to_owned().into(),
+            invite: None,
+            bot: Some(true),
+            accepted_policy_ids: None,
+            birthdate: None,
+            honeypot: None,
+            form_shown_at: None,
+            allow_confusable: None,
+            email: None,
+        })
+        .await;
+    assert_eq!(res.unwrap_err(), Error::Unauthenticated);
+}
+
+#[tokio::test]
+async fn test_bots_cannot_own_bots() {
+    let (mut data, _) = TestData::with_user().await;
+    let bot = data.account().create_test_bot().await;
+    data.login_as(&bot);
+
+    let res = data
+        .account()
+        .create(CreateAccount {
+            user_id: "sub-bot".into(),
+            pword: "bot-password".to_owned().into(),
+            invite: None,
+            bot: Some(true),
+            accepted_policy_ids: None,
+            birthdate: None,
+            honeypot: None,
+            form_shown_at: None,
+            allow_confusable: None,
+            email: None,
+        })
+        .await;
+    assert_eq!(res.unwrap_err(), Error::BotOwnerInvalid);
+}
+
+#[tokio::test]
+async fn test_bots_admin_only() {
+    let (mut data, _) = TestData::with_user().await;
+    let bot = data.account().create_test_bot().await;
+
+    let bots = data.account().bots().await.unwrap();
+    assert_eq!(bots, vec![bot.acc]);
+
+    let other = data.account().create_test_user().await;
+    data.login_as(&other);
+    assert_eq!(
+        data.account().bots().await.unwrap_err(),
+        Error::Unauthorized
+    );
+}
+
+#[tokio::test]
+async fn test_create_min_age() {
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2023, 6, 1, 0, 0, 0).unwrap());
+    let data = TestData::with_clock(clock).await;
+    let acc_persist = data.account().with_min_age(13);
+
+    let create = |user_id: &str, birthdate| CreateAccount {
+        user_id: user_id.into(),
+        pword: "password".to_owned().into(),
+        invite: None,
+        bot: None,
+        accepted_policy_ids: None,
+        birthdate,
+        honeypot: None,
+        form_shown_at: None,
+        allow_confusable: None,
+        email: None,
+    };
+
+    let res = acc_persist.create(create("none", None)).await;
+    assert_eq!(res.unwrap_err(), Error::BirthdateRequired);
+    let res = acc_persist
+        .create(create("young", NaiveDate::from_ymd_opt(2010, 6, 2)))
+        .await;
+    assert_eq!(res.unwrap_err(), Error::UnderMinimumAge);
+
+    let teen = acc_persist
+        .create(create("teen", NaiveDate::from_ymd_opt(2010, 6, 1)))
+        .await
+        .unwrap();
+    assert!(!teen.account.adult);
+    let adult = acc_persist
+        .create(create("adult", NaiveDate::from_ymd_opt(2005, 6, 1)))
+        .await
+        .unwrap();
+    assert!(adult.account.adult);
+
+    // Only the derived flag is kept.
+    let stored: Option<serde_json::Value> = data
+        .persist
+        .db()
+        .select((ACC_TABLE_NAME, &*adult.account.id.to_gql_id()))
+        .await
+        .unwrap();
+    assert!(stored.unwrap().get("birthdate").is_none());
+}
+
+#[tokio::test]
+async fn test_bot_adult_from_owner() {
+    let (mut data, _) = TestData::with_user().await;
+    assert!(data.account().create_test_bot().await.acc.adult);
+
+    let minor = data.account().create_test_user_born(None).await;
+    data.login_as(&minor);
+    assert!(!data.account().create_test_bot().await.acc.adult);
+}
+
+#[tokio::test]
+async fn test_disown() {
+    let (mut data, admin) = TestData::with_user().await;
+    let acc = data.account().create_test_user().await;
+    let refresh_token = create_refresh_token(
+        acc.id.to_gql_id(),
+        None,
+        None,
+        &data.jwt_enc_key,
+        data.persist.clock(),
+    )
+    .unwrap();
+    let events = SecurityEventPersist::new(&data.persist, &data.current);
+    let new_device = events
+        .log(acc.id.clone(), SecurityEventKind::NewDevice, None)
+        .await
+        .unwrap();
+    let signed_in = events
+        .log(acc.id.clone(), SecurityEventKind::SignedIn, None)
+        .await
+        .unwrap();
+    let token = |acc_id: &srql::Thing, event: &SecurityEvent| {
+        let claims = DisownClaims::new(acc_id.to_gql_id(), event.id.to_gql_id(), event.occurred_at);
+        create_disown_token(&claims, &data.jwt_enc_key).unwrap()
+    };
+
+    // Tokens only work for events of their own account that alert it.
+    data.current = CurrentAccount::default();
+    let res = data.account().disown(&token(&admin.id, &new_device)).await;
+    assert_eq!(res.unwrap_err(), Error::JwtInvalid);
+    let res = data.account().disown(&token(&acc.id, &signed_in)).await;
+    assert_eq!(res.unwrap_err(), Error::JwtInvalid);
+
+    let res = data.account().disown(&token(&acc.id, &new_device)).await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+
+    let res = data.account().refresh(refresh_token).await;
+    assert_eq!(res.unwrap_err(), Error::CredentialsInvalid);
+
+    data.login_as(&admin);
+    let notifications = data
+        .notification()
+        .list()
+        .unwrap()
+        .with_pagination(PaginationInput::new().forward(10))
+        .execute()
+        .await
+        .unwrap();
+    assert_eq!(notifications.edges.len(), 1);
+    let notification = &notifications.edges[0].node;
+    assert_eq!(notification.kind, NotificationKind::AccountRecovery);
+    assert_eq!(notification.subject_id.as_ref(), Some(&acc.id));
+}
+
+#[tokio::test]
+async fn test_refresh_session() {
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap());
+    let mut data = TestData::with_clock(clock.clone()).await;
+    data.persist = data.persist.with_sessions(SessionConfig {
+        idle_timeout: Some(std::time::Duration::from_hours(1)),
+        ..Default::default()
+    });
+    let acc = data.account().create_test_user().await;
+    let session = data
+        .session(&PrivacyConfig::default())
+        .record(acc.id.clone(), None)
+        .await
+        .unwrap();
+    let token = |session: &Session| {
+        create_refresh_token(
+            acc.id.to_gql_id(),
+            Some(session.id.to_gql_id()),
+            None,
+            &data.jwt_enc_key,
+            data.persist.clock(),
+        )
+        .unwrap()
+    };
+
+    clock.advance(Duration::minutes(30));
+    let refreshed = data.account().refresh(token(&session)).await.unwrap();
+    let resumed = refreshed.session.unwrap();
+    assert_eq!(resumed.id, session.id);
+    assert_eq!(
+        resumed.expiry(data.persist.sessions(), refreshed.account.admin),
+        Some(clock.now() + Duration::hours(1))
+    );
+
+    let stale = token(&session);
+    clock.advance(Duration::minutes(61));
+    let res = data.account().refresh(stale).await;
+    assert_eq!(res.unwrap_err(), Error::SessionExpired);
+}
+
+#[tokio::test]
+async fn test_delete_and_restore() {
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap());
+    let mut data = TestData::with_clock(clock.clone()).await;
+    let acc = data.account().create_test_user().await;
+    data.login_as(&acc);
+    let bot = data.account().create_test_bot().await;
+    let acc_persist = data.account().with_deletion_grace_days(7);
+    let login = || {
+        acc_persist.login(
+            AuthCreds {
+                user_id: acc.user_id.clone(),
+                pword: acc.pword.clone(),
+            },
+            None,
+        )
+    };
+
+    let res = acc_persist
+        .delete(&"wrong-password".to_owned().into())
+        .await;
+    assert_eq!(res.unwrap_err(), Error::CredentialsInvalid);
+    let purge_at = acc_persist.delete(&acc.pword).await.unwrap();
+    assert_eq!(purge_at, clock.now() + Duration::days(7));
+
+    // Deleted accounts, and the bots they own, can't be signed into.
+    assert_eq!(login().await.unwrap_err(), Error::AccountDeleted);
+    let res = acc_persist
+        .login(
+            AuthCreds {
+                user_id: bot.user_id.clone(),
+                pword: bot.pword.clone(),
+            },
+            None,
+        )
+        .await;
+    assert_eq!(res.unwrap_err(), Error::AccountDeleted);
+
+    let restored = acc_persist
+        .restore(AuthCreds {
+            user_id: acc.user_id.clone(),
+            pword: acc.pword.clone(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(restored.deleted_at, None);
+    assert_eq!(restored.purge_at, None);
+    let bot_acc = acc_persist.get(&bot.id.id.to_raw()).await.unwrap().unwrap();
+    assert_eq!(bot_acc.deleted_at, None);
+    login().await.unwrap();
+
+    let res = acc_persist
+        .restore(AuthCreds {
+            user_id: acc.user_id.clone(),
+            pword: acc.pword.clone(),
+        })
+        .await;
+    assert!(matches!(res, Err(Error::InputInvalid(_))));
+}
+
+#[tokio::test]
+async fn test_purge_deleted() {
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap());
+    let mut data = TestData::with_clock(clock.clone()).await;
+    let other = data.account().create_test_user().await;
+    let acc = data.account().create_test_user().await;
+    data.login_as(&acc);
+    let post = data.generate_post().await;
+    let followed = data.generate_followed().await;
+    data.login_as(&other);
+    data.follow().follow(&acc.id.id.to_raw()).await.unwrap();
+
+    data.login_as(&acc);
+    let acc_persist = data.account().with_deletion_grace_days(7);
+    acc_persist.delete(&acc.pword).await.unwrap();
+
+    // Nothing is purged until the grace period ends.
+    clock.advance(Duration::days(6));
+    assert_eq!(purge_deleted_accounts(&data.persist).await, Ok(0));
+    clock.advance(Duration::days(1));
+    assert_eq!(purge_deleted_accounts(&data.persist).await, Ok(1));
+    assert_eq!(purge_deleted_accounts(&data.persist).await, Ok(0));
+
+    assert!(acc_persist
+        .get(&acc.id.id.to_raw())
+        .await
+        .unwrap()
+        .is_none());
+    assert!(data
+        .post()
+        .get(&post.id.id.to_raw())
+        .await
+        .unwrap()
+        .is_none());
+    assert!(acc_persist
+        .get(&followed.id.id.to_raw())
+        .await
+        .unwrap()
+        .is_some());
+    data.login_as(&other);
+    assert!(data.follow().following().await.unwrap().is_empty());
+
+    // The user ID is free to be registered again.
+    let res = data
+        .account()
+        .create(CreateAccount {
+            user_id: acc.user_id.clone(),
+            pword: "another-password".to_owned().into(),
+            invite: None,
+            bot: None,
+            accepted_policy_ids: Some(data.account().current_policy_ids().await.unwrap()),
+            birthdate: None,
+            honeypot: None,
+            form_shown_at: None,
+            allow_confusable: None,
+            email: None,
+        })
+        .await;
+    assert!(res.is_ok());
+}
+
+#[tokio::test]
+async fn test_anonymize_departed() {
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap());
+    let mut data = TestData::with_clock(clock.clone()).await;
+    let other = data.account().create_test_user().await;
+    let acc = data.account().create_test_user().await;
+    data.login_as(&other);
+    let post = data
+        .post()
+        .create(CreatePost {
+            content: Some("Hello".into()),
+            mention_ids: Some(vec![acc.id.to_gql_id(), other.id.to_gql_id()]),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    data.login_as(&acc);
+    data.account()
+        .with_deletion_grace_days(0)
+        .delete(&acc.pword)
+        .await
+        .unwrap();
+    assert_eq!(anonymize_departed_accounts(&data.persist).await, Ok(0));
+    assert_eq!(purge_deleted_accounts(&data.persist).await, Ok(1));
+
+    assert_eq!(anonymize_departed_accounts(&data.persist).await, Ok(1));
+    assert_eq!(anonymize_departed_accounts(&data.persist).await, Ok(0));
+
+    data.login_as(&other);
+    let post = data
+        .post()
+        .get(&post.id.id.to_raw())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(post.mention_ids, vec![other.id.clone()]);
+
+    // What was done is kept in the audit trail.
+    let events: Vec<SecurityEvent> = data
+        .persist
+        .db()
+        .query("SELECT * FROM security_event WHERE kind = 'account_anonymized'")
+        .await
+        .unwrap()
+        .take(0)
+        .unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].kind, SecurityEventKind::AccountAnonymized);
+    assert_eq!(events[0].account_id, acc.id);
+    assert!(events[0]
+        .detail
+        .as_deref()
+        .unwrap()
+        .contains("post.mention_ids: 1"));
+}
+
+#[tokio::test]
+async fn test_dormancy() {
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap());
+    let mut data = TestData::with_clock(clock.clone()).await;
+    let emails = data.record_emails();
+    let admin = data.account().create_test_user().await;
+    let dormant = data.account().create_test_user().await;
+    let active = data.account().create_test_user().await;
+    data.login_as(&dormant);
+    data.account()
+        .update(UpdateAccount {
+            email: MaybeUndefined::Value("dormant@example.com".into()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    emails.take();
+    let policy = DormancyPolicy {
+        after_days: 90,
+        grace_days: 30,
+        release_user_ids: true,
+    };
+
+    clock.advance(Duration::days(60));
+    data.account()
+        .login(
+            AuthCreds {
+                user_id: active.user_id.clone(),
+                pword: active.pword.clone(),
+            },
+            None,
+        )
+        .await
+        .unwrap();
+    clock.advance(Duration::days(29));
+    assert_eq!(
+        process_dormant_accounts(&data.persist, policy, None).await,
+        Ok(0)
+    );
+
+    // Admins are never dormant, and the active account signed in since.
+    clock.advance(Duration::days(1));
+    assert_eq!(
+        process_dormant_accounts(&data.persist, policy, None).await,
+        Ok(1)
+    );
+    assert_eq!(
+        process_dormant_accounts(&data.persist, policy, None).await,
+        Ok(0)
+    );
+    let sent = emails.take();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].to, "dormant@example.com");
+    assert!(sent[0].body.contains(&released_user_id(&dormant.id)));
+    data.login_as(&dormant);
+    let notifications = data
+        .notification()
+        .list()
+        .unwrap()
+        .with_pagination(PaginationInput::new().forward(10))
+        .execute()
+        .await
+        .unwrap();
+    assert!(notifications
+        .edges
+        .iter()
+        .any(|edge| edge.node.kind == NotificationKind::Dormant));
+
+    data.login_as(&admin);
+    let listed = data.account().dormant().await.unwrap();
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].account.id, dormant.id);
+    assert_eq!(listed[0].released_at, None);
+
+    // The user ID is released once the grace period ends.
+    clock.advance(Duration::days(30));
+    assert_eq!(
+        process_dormant_accounts(&data.persist, policy, None).await,
+        Ok(1)
+    );
+    data.login_as(&admin);
+    let listed = data.account().dormant().await.unwrap();
+    let released = listed
+        .iter()
+        .find(|listed| listed.account.id == dormant.id)
+        .unwrap();
+    assert_eq!(released.account.user_id, released_user_id(&dormant.id));
+    assert!(released.released_at.is_some());
+    let taken = data.account().get_by_user_id(&dormant.user_id).await;
+    assert!(taken.unwrap().is_none());
+
+    // Each step is kept in the audit trail.
+    let events: Vec<SecurityEvent> = data
+        .persist
+        .db()
+        .query("SELECT * FROM security_event WHERE account_id = $id ORDER BY occurred_at")
+        .bind(("id", dormant.id.clone()))
+        .await
+        .unwrap()
+        .take(0)
+        .unwrap();
+    let kinds: Vec<_> = events.iter().map(|event| event.kind).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            SecurityEventKind::DormancyNoticed,
+            SecurityEventKind::UserIdReleased
+        ]
+    );
+    assert_eq!(events[1].detail.as_deref(), Some(dormant.user_id.as_str()));
+}
+
+#[tokio::test]
+async fn test_dormancy_release_claim() {
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap());
+    let data = TestData::with_clock(clock.clone()).await;
+    data.account().create_test_user().await;
+    let dormant = data.account().create_test_user().await;
+    let policy = DormancyPolicy {
+        after_days: 1,
+        grace_days: 1,
+        release_user_ids: true,
+    };
+    for _ in 0..2 {
+        clock.advance(Duration::days(1));
+        assert_eq!(
+            process_dormant_accounts(&data.persist, policy, None).await,
+            Ok(1)
+        );
+    }
+
+    // Released user IDs don't keep their skeletons claimed.
+    let res = data
+        .account()
+        .create(CreateAccount {
+            user_id: dormant.user_id.clone(),
+            pword: "test".to_owned().into(),
+            invite: None,
+            bot: None,
+            accepted_policy_ids: None,
+            birthdate: None,
+            honeypot: None,
+            form_shown_at: None,
+            allow_confusable: None,
+            email: None,
+        })
+        .await;
+    assert_eq!(res.unwrap().account.user_id, dormant.user_id);
+}
+
+#[tokio::test]
+async fn test_dormancy_cleared() {
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap());
+    let mut data = TestData::with_clock(clock.clone()).await;
+    let admin = data.account().create_test_user().await;
+    let acc = data.account().create_test_user().await;
+    let policy = DormancyPolicy {
+        after_days: 90,
+        grace_days: 30,
+        release_user_ids: true,
+    };
+
+    clock.advance(Duration::days(90));
+    assert_eq!(
+        process_dormant_accounts(&data.persist, policy, None).await,
+        Ok(1)
+    );
+
+    // Only admins can see which accounts are dormant.
+    data.login_as(&acc);
+    assert!(matches!(
+        data.account().dormant().await,
+        Err(Error::Unauthorized)
+    ));
+
+    // Signing in again clears the notice, so the user ID isn't released.
+    clock.advance(Duration::days(1));
+    data.account()
+        .login(
+            AuthCreds {
+                user_id: acc.user_id.clone(),
+                pword: acc.pword.clone(),
+            },
+            None,
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        process_dormant_accounts(&data.persist, policy, None).await,
+        Ok(1)
+    );
+    clock.advance(Duration::days(30));
+    assert_eq!(
+        process_dormant_accounts(&data.persist, policy, None).await,
+        Ok(0)
+    );
+    data.login_as(&admin);
+    assert!(data.account().dormant().await.unwrap().is_empty());
+    let acc_persist = data.account();
+    let acc = acc_persist.get(&acc.id.id.to_raw()).await.unwrap().unwrap();
+    assert!(!acc.user_id.starts_with(RELEASED_USER_ID_PREFIX));
+
+    let events: Vec<SecurityEvent> = data
+        .persist
+        .db()
+        .query("SELECT * FROM security_event WHERE account_id = $id ORDER BY occurred_at")
+        .bind(("id", acc.id.clone()))
+        .await
+        .unwrap()
+        .take(0)
+        .unwrap();
+    let kinds: Vec<_> = events.iter().map(|event| event.kind).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            SecurityEventKind::DormancyNoticed,
+            SecurityEventKind::DormancyCleared
+        ]
+    );
+}
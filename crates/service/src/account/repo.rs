@@ -0,0 +1,300 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Debug},
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use async_trait::async_trait;
+use tracing::instrument;
+
+use super::{Account, CurrentAccount, ACC_TABLE_NAME};
+use crate::{persist::Persist, prelude::*};
+
+pub type SharedAccountRepo = Arc<dyn AccountRepo>;
+
+/// Where accounts are looked up from. Resolvers and the rules built on top of
+/// them go through this rather than the database, so that they can be tested
+/// with a [`MemoryAccountRepo`].
+///
+/// Requests use the one in the GraphQL context's data, if there is one, and
+/// the database otherwise.
+#[async_trait]
+pub trait AccountRepo: Debug + Send + Sync {
+    /// Gets an account by its ID.
+    async fn get(&self, id: &str) -> Result<Option<Account>>;
+
+    /// Gets an account by its user ID, exactly as given.
+    async fn get_by_exact_user_id(&self, user_id: &str) -> Result<Option<Account>>;
+
+    /// Finds an account by its user ID. User IDs are registered in lowercase,
+    /// so one that isn't found as given is looked for in lowercase too.
+    /// Accounts registered before then can have other cases.
+    async fn get_by_user_id(&self, user_id: &str) -> Result<Option<Account>> {
+        let acc = self.get_by_exact_user_id(user_id).await?;
+        if acc.is_some() {
+            return Ok(acc);
+        }
+        let normalized = user_id.trim().to_ascii_lowercase();
+        if normalized == user_id {
+            return Ok(None);
+        }
+        self.get_by_exact_user_id(&normalized).await
+    }
+}
+
+/// Fails with [`Error::EmailNotVerified`] unless the current account has
+/// verified its email address. Admins don't need to, and bots go by their
+/// owner's.
+pub async fn require_verified_email(
+    repo: &dyn AccountRepo,
+    current: &CurrentAccount,
+) -> Result<()> {
+    let Some(mut acc) = repo.get(current.id()?).await? else {
+        return Err(Error::Unauthenticated);
+    };
+    if let Some(owner_id) = acc.owner_id.clone().filter(|_| acc.bot) {
+        let Some(owner) = repo.get(&owner_id.id.to_raw()).await? else {
+            return Err(Error::EmailNotVerified);
+        };
+        acc = owner;
+    }
+    if acc.admin || acc.is_email_verified() {
+        Ok(())
+    } else {
+        Err(Error::EmailNotVerified)
+    }
+}
+
+/// Looks accounts up in the database.
+#[derive(Clone)]
+pub struct SurrealAccountRepo {
+    persist: Persist,
+}
+
+impl SurrealAccountRepo {
+    #[must_use]
+    pub fn new(persist: Persist) -> Self {
+        Self { persist }
+    }
+}
+
+impl Debug for SurrealAccountRepo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SurrealAccountRepo").finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl AccountRepo for SurrealAccountRepo {
+    #[instrument(skip_all)]
+    async fn get(&self, id: &str) -> Result<Option<Account>> {
+        Ok(self
+            .persist
+            .load(srql::Thing::from((ACC_TABLE_NAME, id)))
+            .await?)
+    }
+
+    #[instrument(skip_all)]
+    async fn get_by_exact_user_id(&self, user_id: &str) -> Result<Option<Account>> {
+        let acc = self
+            .persist
+            .db()
+            .query(srql::SelectStatement {
+                expr: srql::Fields::all(),
+                what: srql::table(ACC_TABLE_NAME),
+                cond: srql::Cond(
+                    srql::Expression::Binary {
+                        l: srql::field("user_id").into(),
+                        o: srql::Operator::Equal,
+                        r: srql::string(user_id).into(),
+                    }
+                    .into(),
+                )
+                .into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        Ok(acc)
+    }
+}
+
+/// Keeps accounts in memory, keyed by their ID, so that what's built on top
+/// of [`AccountRepo`] can be tested without a database.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryAccountRepo(Arc<Mutex<HashMap<String, Account>>>);
+
+impl MemoryAccountRepo {
+    /// Adds an account, replacing any that has the same ID.
+    pub fn insert(&self, acc: Account) {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(acc.id.id.to_raw(), acc);
+    }
+}
+
+#[async_trait]
+impl AccountRepo for MemoryAccountRepo {
+    async fn get(&self, id: &str) -> Result<Option<Account>> {
+        Ok(self
+            .0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(id)
+            .cloned())
+    }
+
+    async fn get_by_exact_user_id(&self, user_id: &str) -> Result<Option<Account>> {
+        Ok(self
+            .0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .values()
+            .find(|acc| acc.user_id == user_id)
+            .cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_graphql::{EmptyMutation, EmptySubscription, Request, Schema};
+    use chrono::{Duration, Utc};
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        account::{AccountQuery, PartialAccount},
+        provider::SystemClock,
+    };
+
+    fn account(id: &str, user_id: &str) -> Account {
+        serde_json::from_value(json!({
+            "id": { "tb": ACC_TABLE_NAME, "id": { "String": id } },
+            "user_id": user_id,
+            "revoked_at": null,
+            "owner_id": null,
+            "restriction": null,
+            "deleted_at": null,
+            "purge_at": null,
+            "last_active_at": null,
+            "locale": null,
+            "timezone": null,
+            "email": null,
+            "email_verified_at": null,
+            "updated_at": "2023-01-01T00:00:00Z",
+            "pword_salt": "",
+            "pword_hash": "",
+        }))
+        .unwrap()
+    }
+
+    fn signed_in(acc: &Account) -> CurrentAccount {
+        CurrentAccount::new(
+            PartialAccount::new(acc.id.to_gql_id(), acc.user_id.clone()),
+            Utc::now() + Duration::hours(1),
+            Arc::new(SystemClock),
+        )
+    }
+
+    fn verified(mut acc: Account) -> Account {
+        acc.email = Some("someone@example.com".into());
+        acc.email_verified_at = Some("2023-01-01T00:00:00Z".parse().unwrap());
+        acc
+    }
+
+    fn bot_of(mut acc: Account, owner: &Account) -> Account {
+        acc.bot = true;
+        acc.owner_id = Some(owner.id.clone());
+        acc
+    }
+
+    #[tokio::test]
+    async fn test_get_by_user_id() {
+        let repo = MemoryAccountRepo::default();
+        repo.insert(account("one", "alice"));
+        repo.insert(account("two", "Bob"));
+
+        let found = |user_id: &'static str| {
+            let repo = repo.clone();
+            async move {
+                repo.get_by_user_id(user_id)
+                    .await
+                    .unwrap()
+                    .map(|acc| acc.id.id.to_raw())
+            }
+        };
+        assert_eq!(found("alice").await.as_deref(), Some("one"));
+        assert_eq!(found(" ALICE ").await.as_deref(), Some("one"));
+        // Accounts registered before user IDs were lowercased are still found
+        // by their exact user ID, but not by the lowercase one.
+        assert_eq!(found("Bob").await.as_deref(), Some("two"));
+        assert_eq!(found("bob").await, None);
+        assert_eq!(found("carol").await, None);
+    }
+
+    #[tokio::test]
+    async fn test_require_verified_email() {
+        let repo = MemoryAccountRepo::default();
+        let unverified = account("one", "alice");
+        let owner = verified(account("two", "bob"));
+        let mut admin = account("three", "carol");
+        admin.admin = true;
+        let bot = bot_of(account("four", "bot"), &owner);
+        let unverified_bot = bot_of(verified(account("five", "bot2")), &unverified);
+        let orphaned_bot = bot_of(verified(account("six", "bot3")), &account("x", "gone"));
+        for acc in [
+            &unverified,
+            &owner,
+            &admin,
+            &bot,
+            &unverified_bot,
+            &orphaned_bot,
+        ] {
+            repo.insert(acc.clone());
+        }
+
+        let check = |acc: &Account| {
+            let repo = repo.clone();
+            let current = signed_in(acc);
+            async move { require_verified_email(&repo, &current).await }
+        };
+        assert!(matches!(
+            check(&unverified).await,
+            Err(Error::EmailNotVerified)
+        ));
+        check(&owner).await.unwrap();
+        check(&admin).await.unwrap();
+        check(&bot).await.unwrap();
+        assert!(matches!(
+            check(&unverified_bot).await,
+            Err(Error::EmailNotVerified)
+        ));
+        assert!(matches!(
+            check(&orphaned_bot).await,
+            Err(Error::EmailNotVerified)
+        ));
+        assert!(matches!(
+            check(&account("seven", "dave")).await,
+            Err(Error::Unauthenticated)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_injected_into_context() {
+        let repo = MemoryAccountRepo::default();
+        let acc = account("one", "alice");
+        repo.insert(acc.clone());
+        let schema = Schema::build(AccountQuery, EmptyMutation, EmptySubscription)
+            .data::<SharedAccountRepo>(Arc::new(repo))
+            .finish();
+
+        let res = schema
+            .execute(Request::new("{ me { userId } }").data(signed_in(&acc)))
+            .await;
+        assert_eq!(
+            res.data.into_json().unwrap(),
+            json!({ "me": { "userId": "alice" } })
+        );
+    }
+}
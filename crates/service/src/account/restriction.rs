@@ -0,0 +1,152 @@
+//! Restrictions that admins put on accounts that break the instance's rules.
+//!
+//! Restrictions last for a set time. They stop applying as soon as they
+//! expire, and are cleared from accounts by a background job afterwards.
+
+use async_graphql::{Enum, SimpleObject};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use surrealdb::sql::Thing;
+
+use super::Account;
+use crate::{follow::FOLLOWS_TABLE_NAME, prelude::*};
+
+/// The longest that an account can be restricted for, in hours.
+pub const RESTRICTION_MAX_HOURS: u32 = 24 * 365;
+
+/// How an account has been restricted.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RestrictionKind {
+    /// The account can't sign in, and its posts are hidden from everyone.
+    Suspended,
+    /// The account's posts can only be seen by its followers.
+    Silenced,
+    /// The account's posts are hidden from everyone else, without it being
+    /// told.
+    ShadowLimited,
+}
+
+impl RestrictionKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Suspended => "suspended",
+            Self::Silenced => "silenced",
+            Self::ShadowLimited => "shadow_limited",
+        }
+    }
+}
+
+/// A restriction put on an account by an admin.
+#[derive(SimpleObject, Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct AccountRestriction {
+    /// How the account is restricted.
+    pub kind: RestrictionKind,
+    /// Why the account was restricted, as given by the admin.
+    pub reason: String,
+    /// When the account was restricted.
+    pub restricted_at: DateTime<Utc>,
+    /// When the restriction ends.
+    pub expires_at: DateTime<Utc>,
+}
+
+impl QueryValue for AccountRestriction {
+    fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
+        Some((
+            field,
+            srql::Operator::Equal,
+            srql::object([
+                ("kind".to_owned(), srql::string(self.kind.as_str()).into()),
+                ("reason".to_owned(), srql::string(self.reason).into()),
+                (
+                    "restricted_at".to_owned(),
+                    srql::Value::Datetime(srql::Datetime(self.restricted_at)),
+                ),
+                (
+                    "expires_at".to_owned(),
+                    srql::Value::Datetime(srql::Datetime(self.expires_at)),
+                ),
+            ]),
+        ))
+    }
+}
+
+impl Account {
+    /// The account's restriction, if it has one that hasn't expired yet.
+    #[must_use]
+    pub fn active_restriction(&self, now: DateTime<Utc>) -> Option<&AccountRestriction> {
+        self.restriction
+            .as_ref()
+            .filter(|restriction| restriction.expires_at > now)
+    }
+
+    /// Whether the account can't sign in because it has been suspended.
+    #[must_use]
+    pub fn is_suspended(&self, now: DateTime<Utc>) -> bool {
+        self.active_restriction(now)
+            .is_some_and(|restriction| restriction.kind == RestrictionKind::Suspended)
+    }
+}
+
+/// Matches the records whose creators' restrictions let the viewer see them.
+/// Accounts always see their own records, and followers of silenced accounts
+/// see theirs.
+pub fn restriction_visible_cond(viewer: Option<&Thing>, now: DateTime<Utc>) -> srql::Cond {
+    let binary = |l: srql::Value, o, r: srql::Value| -> srql::Value {
+        srql::Expression::Binary { l, o, r }.into()
+    };
+
+    // Records without a creator, or whose creator isn't restricted, have no
+    // expiry to compare, which sorts before any time.
+    let mut visible = binary(
+        srql::path(&["creator_id", "restriction", "expires_at"]).into(),
+        srql::Operator::LessThanOrEqual,
+        srql::Value::Datetime(srql::Datetime(now)),
+    );
+    if let Some(viewer) = viewer {
+        let following = srql::SelectStatement {
+            expr: srql::Fields(
+                vec![srql::Field::Single {
+                    expr: srql::field("out").into(),
+                    alias: None,
+                }],
+                true,
+            ),
+            what: srql::table(FOLLOWS_TABLE_NAME),
+            cond: srql::Cond(binary(
+                srql::field("in").into(),
+                srql::Operator::Equal,
+                viewer.clone().into(),
+            ))
+            .into(),
+            ..Default::default()
+        };
+        let silenced_followed = binary(
+            binary(
+                srql::path(&["creator_id", "restriction", "kind"]).into(),
+                srql::Operator::Equal,
+                srql::string(RestrictionKind::Silenced.as_str()).into(),
+            ),
+            srql::Operator::And,
+            binary(
+                srql::field("creator_id").into(),
+                srql::Operator::Inside,
+                srql::Value::Subquery(srql::Subquery::Select(following).into()),
+            ),
+        );
+        visible = binary(
+            binary(
+                visible,
+                srql::Operator::Or,
+                binary(
+                    srql::field("creator_id").into(),
+                    srql::Operator::Equal,
+                    viewer.clone().into(),
+                ),
+            ),
+            srql::Operator::Or,
+            silenced_followed,
+        );
+    }
+    srql::Cond(visible)
+}
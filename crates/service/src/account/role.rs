@@ -0,0 +1,212 @@
+//! Roles that accounts are given on the instance, and what each allows.
+//!
+//! Admins can do anything, and moderators can work through the moderation
+//! queue and restrict accounts. Resolvers declare what they need with
+//! [`RoleGuard`] or [`PermissionGuard`], and persistence checks it again with
+//! [`require_role`] or [`require_permission`], so that it's enforced however
+//! they're reached.
+
+use async_graphql::{Context, Enum, Guard};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use super::{Account, CurrentAccount, ACC_TABLE_NAME};
+use crate::{persist::Persist, prelude::*};
+
+/// An account's role on the instance.
+#[derive(
+    Enum, Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountRole {
+    #[default]
+    User,
+    /// Can work through the moderation queue and restrict accounts.
+    Moderator,
+    /// Can do anything, including changing other accounts' roles.
+    Admin,
+}
+
+impl AccountRole {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::User => "user",
+            Self::Moderator => "moderator",
+            Self::Admin => "admin",
+        }
+    }
+
+    /// What accounts with this role are allowed to do.
+    #[must_use]
+    pub fn permissions(self) -> &'static [Permission] {
+        match self {
+            Self::User => &[],
+            Self::Moderator => &[Permission::ModerateContent, Permission::RestrictAccounts],
+            Self::Admin => &[
+                Permission::ModerateContent,
+                Permission::RestrictAccounts,
+                Permission::ManageRoles,
+                Permission::ManageInstance,
+            ],
+        }
+    }
+}
+
+impl QueryValue for AccountRole {
+    fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
+        Some((
+            field,
+            srql::Operator::Equal,
+            srql::string(self.as_str()).into(),
+        ))
+    }
+}
+
+/// Something that only some roles are allowed to do.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    /// See and resolve the items in the moderation queue.
+    ModerateContent,
+    /// Restrict and unrestrict accounts that aren't moderators or admins.
+    RestrictAccounts,
+    /// Change other accounts' roles.
+    ManageRoles,
+    /// Everything else that runs the instance, such as statistics,
+    /// projections and read-only mode.
+    ManageInstance,
+}
+
+impl Account {
+    /// The role the account acts with. The `admin` flag that the first
+    /// account is given always makes it an admin.
+    #[must_use]
+    pub fn effective_role(&self) -> AccountRole {
+        if self.admin {
+            AccountRole::Admin
+        } else {
+            self.role
+        }
+    }
+}
+
+/// The current account, along with its role and what that allows.
+#[derive(Debug, Clone)]
+pub struct AuthContext {
+    pub account: Account,
+    pub role: AccountRole,
+    pub permissions: &'static [Permission],
+}
+
+impl AuthContext {
+    /// Loads the current account and resolves its permissions. Accounts are
+    /// loaded through the request's memo, so resolving this in several
+    /// guards only reads the account once.
+    pub async fn resolve(persist: &Persist, current: &CurrentAccount) -> Result<Self> {
+        let id = current.id()?;
+        let account: Option<Account> = persist
+            .load(srql::Thing::from((ACC_TABLE_NAME, id.as_str())))
+            .await?;
+        let Some(account) = account else {
+            return Err(Error::Unauthorized);
+        };
+        let role = account.effective_role();
+        Ok(Self {
+            account,
+            role,
+            permissions: role.permissions(),
+        })
+    }
+
+    /// Whether the account's role is at least the given one.
+    #[must_use]
+    pub fn has_role(&self, role: AccountRole) -> bool {
+        self.role >= role
+    }
+
+    /// Whether the account's role allows the given permission.
+    #[must_use]
+    pub fn has_permission(&self, permission: Permission) -> bool {
+        self.permissions.contains(&permission)
+    }
+}
+
+/// Gets the current account, failing if its role isn't at least the given
+/// one.
+pub async fn require_role(
+    persist: &Persist,
+    current: &CurrentAccount,
+    role: AccountRole,
+) -> Result<Account> {
+    let auth = AuthContext::resolve(persist, current).await?;
+    if auth.has_role(role) {
+        Ok(auth.account)
+    } else {
+        Err(Error::Unauthorized)
+    }
+}
+
+/// Gets the current account, failing if its role doesn't allow the given
+/// permission.
+pub async fn require_permission(
+    persist: &Persist,
+    current: &CurrentAccount,
+    permission: Permission,
+) -> Result<Account> {
+    let auth = AuthContext::resolve(persist, current).await?;
+    if auth.has_permission(permission) {
+        Ok(auth.account)
+    } else {
+        Err(Error::Unauthorized)
+    }
+}
+
+/// Rejects requests from accounts whose role isn't at least the given one,
+/// with [`Error::Unauthorized`].
+pub struct RoleGuard(AccountRole);
+
+impl RoleGuard {
+    #[must_use]
+    pub fn new(role: AccountRole) -> Self {
+        Self(role)
+    }
+}
+
+#[async_trait]
+impl Guard for RoleGuard {
+    async fn check(&self, ctx: &Context<'_>) -> GqlResult<()> {
+        require_role(
+            ctx.data_unchecked::<Persist>(),
+            ctx.current_account(),
+            self.0,
+        )
+        .await
+        .extend()?;
+        Ok(())
+    }
+}
+
+/// Rejects requests from accounts whose role doesn't allow the given
+/// permission, with [`Error::Unauthorized`].
+pub struct PermissionGuard(Permission);
+
+impl PermissionGuard {
+    #[must_use]
+    pub fn new(permission: Permission) -> Self {
+        Self(permission)
+    }
+}
+
+#[async_trait]
+impl Guard for PermissionGuard {
+    async fn check(&self, ctx: &Context<'_>) -> GqlResult<()> {
+        require_permission(
+            ctx.data_unchecked::<Persist>(),
+            ctx.current_account(),
+            self.0,
+        )
+        .await
+        .extend()?;
+        Ok(())
+    }
+}
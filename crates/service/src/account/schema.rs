@@ -1,9 +1,36 @@
-use async_graphql::{Context, Object};
+use async_graphql::{Context, Guard, Object, Subscription, ID};
+use async_trait::async_trait;
 use chrono::{DateTime, Utc};
+use futures::Stream;
+use secrecy::SecretString;
 use tracing::instrument;
 
-use super::{Account, AuthCreds, AuthenticatedAccount, CreateAccount};
-use crate::prelude::*;
+use super::{
+    require_verified_email, Account, ApiKey, AuthCreds, AuthenticatedAccount, CreateAccount,
+    CreateApiKey, CreatedApiKey, ExternalIdentity, ExternalLoginRedirect, LoginResult, Passkey,
+    PasskeyAssertion, PasskeyCreationOptions, PasskeyRegistration, PasskeyRequestOptions,
+    RecoveryContacts, RecoveryRequest, StartedRecovery, TotpEnrollment, UpdateAccount,
+};
+use crate::{config::DevAuthConfig, prelude::*, reload::LiveConfig, session::ClientMeta};
+
+/// Rejects requests from accounts that haven't verified their email address,
+/// with [`Error::EmailNotVerified`], if the instance requires it.
+pub struct EmailVerified;
+
+#[async_trait]
+impl Guard for EmailVerified {
+    async fn check(&self, ctx: &Context<'_>) -> GqlResult<()> {
+        if !ctx
+            .data_opt::<LiveConfig>()
+            .is_some_and(|live| live.load().features.require_verified_email)
+        {
+            return Ok(());
+        }
+        require_verified_email(&*ctx.account_repo(), ctx.current_account())
+            .await
+            .extend()
+    }
+}
 
 #[derive(Default)]
 pub struct AccountQuery;
@@ -16,7 +43,20 @@ impl AccountQuery {
     /// the current session has been deleted.
     #[instrument(skip_all)]
     async fn me(&self, ctx: &Context<'_>) -> GqlResult<Option<Account>> {
-        ctx.account_persist().current().await.extend()
+        let id = ctx.current_account().id().extend()?;
+        ctx.account_repo().get(id).await.extend()
+    }
+
+    /// Check on a recovery using the token from `requestRecovery`, to see
+    /// how many of the account's trusted contacts have approved it. This
+    /// works without being signed in.
+    #[instrument(skip_all)]
+    async fn recovery_status(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(validator(min_length = 1, max_length = 1024))] token: String,
+    ) -> GqlResult<RecoveryRequest> {
+        ctx.account_persist().recovery_status(&token).await.extend()
     }
 }
 
@@ -25,13 +65,294 @@ pub struct AccountMutation;
 
 #[Object]
 impl AccountMutation {
-    /// Log into the target account.
+    /// Log into the target account. If the account has turned on two-factor
+    /// authentication, this returns `TwoFactorRequired` instead, and logging
+    /// in is finished with `verifyTwoFactor`.
+    #[instrument(skip_all)]
+    async fn login(&self, ctx: &Context<'_>, creds: AuthCreds) -> GqlResult<LoginResult> {
+        match ctx
+            .account_persist()
+            .login(creds, ctx.data_opt::<ClientMeta>())
+            .await
+            .extend()?
+        {
+            LoginResult::Authenticated(acc) => Ok(LoginResult::Authenticated(Box::new(
+                record_session(ctx, *acc).await?,
+            ))),
+            required @ (LoginResult::TwoFactorRequired(_)
+            | LoginResult::ExternalLoginRedirect(_)) => Ok(required),
+        }
+    }
+
+    /// Finish logging into an account with two-factor authentication, using
+    /// the `challengeToken` from `login` and a code from the authenticator
+    /// app or a recovery code. Recovery codes can only be used once.
+    #[instrument(skip_all)]
+    async fn verify_two_factor(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(validator(min_length = 1, max_length = 1024))] challenge_token: String,
+        #[graphql(validator(min_length = 1, max_length = 64))] code: String,
+    ) -> GqlResult<AuthenticatedAccount> {
+        let acc = ctx
+            .account_persist()
+            .verify_two_factor(&challenge_token, &code)
+            .await
+            .extend()?;
+        record_session(ctx, acc).await
+    }
+
+    /// Start turning on two-factor authentication for the current account,
+    /// returning the secret to add to an authenticator app. It isn't turned
+    /// on until a code from the app is given to `confirmTwoFactor`.
+    #[instrument(skip_all)]
+    async fn enroll_two_factor(&self, ctx: &Context<'_>) -> GqlResult<TotpEnrollment> {
+        ctx.account_persist().enroll_two_factor().await.extend()
+    }
+
+    /// Turn on two-factor authentication for the current account with a code
+    /// from the authenticator app. Returns recovery codes, which can each be
+    /// used once instead of a code, and can't be seen again.
+    #[instrument(skip_all)]
+    async fn confirm_two_factor(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(validator(min_length = 1, max_length = 64))] code: String,
+    ) -> GqlResult<Vec<String>> {
+        ctx.account_persist()
+            .confirm_two_factor(&code)
+            .await
+            .extend()
+    }
+
+    /// Turn off two-factor authentication for the current account, with a
+    /// code from the authenticator app or a recovery code.
+    #[instrument(skip_all)]
+    async fn disable_two_factor(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(validator(min_length = 1, max_length = 64))] code: String,
+    ) -> GqlResult<bool> {
+        ctx.account_persist()
+            .disable_two_factor(&code)
+            .await
+            .extend()
+    }
+
+    /// Replace the current account's recovery codes with new ones, with a
+    /// code from the authenticator app or one of the old recovery codes. The
+    /// new codes are only ever shown here, so they should be saved somewhere
+    /// safe straight away.
+    #[instrument(skip_all)]
+    async fn regenerate_recovery_codes(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(validator(min_length = 1, max_length = 64))] code: String,
+    ) -> GqlResult<Vec<String>> {
+        ctx.account_persist()
+            .regenerate_recovery_codes(&code)
+            .await
+            .extend()
+    }
+
+    /// Start adding a passkey to the current account, returning the options
+    /// for `navigator.credentials.create()`. The passkey isn't added until
+    /// the browser's response is given to `finishPasskeyRegistration`.
     #[instrument(skip_all)]
-    async fn login(&self, ctx: &Context<'_>, creds: AuthCreds) -> GqlResult<AuthenticatedAccount> {
-        ctx.account_persist().login(creds).await.extend()
+    async fn begin_passkey_registration(
+        &self,
+        ctx: &Context<'_>,
+    ) -> GqlResult<PasskeyCreationOptions> {
+        ctx.account_persist()
+            .begin_passkey_registration()
+            .await
+            .extend()
+    }
+
+    /// Add a passkey that the browser created to the current account.
+    #[instrument(skip_all)]
+    async fn finish_passkey_registration(
+        &self,
+        ctx: &Context<'_>,
+        registration: PasskeyRegistration,
+    ) -> GqlResult<Passkey> {
+        ctx.account_persist()
+            .finish_passkey_registration(registration)
+            .await
+            .extend()
+    }
+
+    /// Start logging in with a passkey, returning the options for
+    /// `navigator.credentials.get()`. This works without being signed in.
+    ///
+    /// Without a `challengeToken`, any account's passkey can be used, and
+    /// logs in on its own. With the `challengeToken` from `login`, the
+    /// passkey is used instead of a two-factor code, and has to be one of
+    /// that account's.
+    #[instrument(skip_all)]
+    async fn begin_passkey_login(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(validator(min_length = 1, max_length = 1024))] challenge_token: Option<String>,
+    ) -> GqlResult<PasskeyRequestOptions> {
+        ctx.account_persist()
+            .begin_passkey_login(challenge_token.as_deref())
+            .await
+            .extend()
+    }
+
+    /// Finish logging in with the passkey that the browser gave. The
+    /// challenge from `beginPasskeyLogin` can only be used once.
+    #[instrument(skip_all)]
+    async fn finish_passkey_login(
+        &self,
+        ctx: &Context<'_>,
+        assertion: PasskeyAssertion,
+    ) -> GqlResult<AuthenticatedAccount> {
+        let acc = ctx
+            .account_persist()
+            .finish_passkey_login(&assertion)
+            .await
+            .extend()?;
+        record_session(ctx, acc).await
+    }
+
+    /// Remove one of the current account's passkeys, so that it can't be
+    /// logged in with.
+    #[instrument(skip_all)]
+    async fn remove_passkey(&self, ctx: &Context<'_>, id: ID) -> GqlResult<Option<Passkey>> {
+        ctx.account_persist().remove_passkey(&id).await.extend()
+    }
+
+    /// Start logging in with one of the instance's identity providers,
+    /// listed in `instanceInfo { externalLoginProviders }`. This returns
+    /// `ExternalLoginRedirect`, with the URL to send the browser to. The
+    /// provider sends it back to `redirectUri` with a `state` and a `code`,
+    /// which are given to `finishExternalLogin`.
+    #[instrument(skip_all)]
+    async fn begin_external_login(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(validator(min_length = 1, max_length = 64))] provider: String,
+        #[graphql(validator(min_length = 1, max_length = 2048))] redirect_uri: String,
+    ) -> GqlResult<LoginResult> {
+        ctx.account_persist()
+            .begin_external_login(&provider, &redirect_uri)
+            .await
+            .extend()
+    }
+
+    /// Finish logging in with an identity provider, using the `state` and
+    /// `code` it sent the browser back with. The identity has to have been
+    /// linked to an account with `finishExternalLink`. If the account has
+    /// turned on two-factor authentication, this returns `TwoFactorRequired`
+    /// instead.
+    #[instrument(skip_all)]
+    async fn finish_external_login(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(validator(min_length = 1, max_length = 1024))] state: String,
+        #[graphql(validator(min_length = 1, max_length = 2048))] code: String,
+    ) -> GqlResult<LoginResult> {
+        match ctx
+            .account_persist()
+            .finish_external_login(&state, &code)
+            .await
+            .extend()?
+        {
+            LoginResult::Authenticated(acc) => Ok(LoginResult::Authenticated(Box::new(
+                record_session(ctx, *acc).await?,
+            ))),
+            required @ (LoginResult::TwoFactorRequired(_)
+            | LoginResult::ExternalLoginRedirect(_)) => Ok(required),
+        }
+    }
+
+    /// Start linking an identity from one of the instance's identity
+    /// providers to the current account, returning the URL to send the
+    /// browser to. It isn't linked until the `state` and `code` that the
+    /// provider sends back are given to `finishExternalLink`.
+    #[instrument(skip_all)]
+    async fn begin_external_link(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(validator(min_length = 1, max_length = 64))] provider: String,
+        #[graphql(validator(min_length = 1, max_length = 2048))] redirect_uri: String,
+    ) -> GqlResult<ExternalLoginRedirect> {
+        ctx.account_persist()
+            .begin_external_link(&provider, &redirect_uri)
+            .await
+            .extend()
+    }
+
+    /// Link the identity that signed into the provider to the current
+    /// account, so that it can be logged in with. An identity can only be
+    /// linked to one account.
+    #[instrument(skip_all)]
+    async fn finish_external_link(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(validator(min_length = 1, max_length = 1024))] state: String,
+        #[graphql(validator(min_length = 1, max_length = 2048))] code: String,
+    ) -> GqlResult<ExternalIdentity> {
+        ctx.account_persist()
+            .finish_external_link(&state, &code)
+            .await
+            .extend()
+    }
+
+    /// Unlink one of the current account's external identities, so that it
+    /// can't be logged in with.
+    #[instrument(skip_all)]
+    async fn unlink_external_identity(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+    ) -> GqlResult<Option<ExternalIdentity>> {
+        ctx.account_persist()
+            .unlink_external_identity(&id)
+            .await
+            .extend()
+    }
+
+    /// Create an API key for the current account, for bots and scripts to
+    /// use the API with. The key is only returned here, and can't be seen
+    /// again.
+    #[instrument(skip_all)]
+    async fn create_api_key(
+        &self,
+        ctx: &Context<'_>,
+        input: CreateApiKey,
+    ) -> GqlResult<CreatedApiKey> {
+        ctx.account_persist().create_api_key(input).await.extend()
+    }
+
+    /// Revoke one of the current account's API keys, so that it stops
+    /// working straight away.
+    #[instrument(skip_all)]
+    async fn revoke_api_key(&self, ctx: &Context<'_>, id: ID) -> GqlResult<Option<ApiKey>> {
+        ctx.account_persist().revoke_api_key(&id).await.extend()
+    }
+
+    /// Log into one of the seeded development accounts without a password.
+    ///
+    /// This is only available when the server has development authentication
+    /// enabled.
+    #[instrument(skip_all)]
+    async fn dev_login(
+        &self,
+        ctx: &Context<'_>,
+        user_id: String,
+    ) -> GqlResult<AuthenticatedAccount> {
+        if !ctx.data_opt::<DevAuthConfig>().is_some_and(|c| c.enabled) {
+            return Err(Error::DevAuthDisabled).extend();
+        }
+        let acc = ctx.account_persist().dev_login(&user_id).await.extend()?;
+        record_session(ctx, acc).await
     }
 
     /// Refresh tokens and account data.
+    #[graphql(deprecation = "Use `refreshToken`, which does the same thing.")]
     #[instrument(skip_all)]
     async fn refresh(
         &self,
@@ -41,6 +362,20 @@ impl AccountMutation {
         ctx.account_persist().refresh(refresh_token).await.extend()
     }
 
+    /// Exchange a refresh token for new tokens and account data. The refresh
+    /// token is revoked, so the new one has to be used next time.
+    ///
+    /// Using a refresh token that has already been exchanged signs out the
+    /// session it was issued for, as it means the token has been copied.
+    #[instrument(skip_all)]
+    async fn refresh_token(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(validator(min_length = 1, max_length = 1024))] refresh_token: String,
+    ) -> GqlResult<AuthenticatedAccount> {
+        ctx.account_persist().refresh(refresh_token).await.extend()
+    }
+
     /// Register a new account.
     #[instrument(skip_all)]
     async fn create_account(
@@ -48,7 +383,24 @@ impl AccountMutation {
         ctx: &Context<'_>,
         create: CreateAccount,
     ) -> GqlResult<AuthenticatedAccount> {
-        ctx.account_persist().create(create).await.extend()
+        let signals = create.signup_signals();
+        let acc = ctx.account_persist().create(create).await.extend()?;
+        let account = ctx
+            .spam_persist()
+            .check_account(acc.account, signals)
+            .await
+            .extend()?;
+        record_session(ctx, account.into()).await
+    }
+
+    /// Update the current account's settings.
+    #[instrument(skip_all)]
+    async fn update_account(
+        &self,
+        ctx: &Context<'_>,
+        update: UpdateAccount,
+    ) -> GqlResult<Option<Account>> {
+        ctx.account_persist().update(update).await.extend()
     }
 
     /// Revoke all tokens issued for the current account.
@@ -56,4 +408,175 @@ impl AccountMutation {
     async fn revoke_tokens(&self, ctx: &Context<'_>) -> GqlResult<DateTime<Utc>> {
         ctx.account_persist().revoke_tokens().await.extend()
     }
+
+    /// Send the current account another email to verify its address with.
+    /// Another can't be sent until a few minutes after the last.
+    #[instrument(skip_all)]
+    async fn resend_verification(&self, ctx: &Context<'_>) -> GqlResult<bool> {
+        ctx.account_persist().resend_verification().await.extend()?;
+        Ok(true)
+    }
+
+    /// Verify an account's email address using the token that was emailed
+    /// to it, returning when it was verified. This works without being
+    /// signed in, and the token can only be used once.
+    #[instrument(skip_all)]
+    async fn verify_email(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(validator(min_length = 1, max_length = 1024))] token: String,
+    ) -> GqlResult<DateTime<Utc>> {
+        ctx.account_persist().verify_email(&token).await.extend()
+    }
+
+    /// Email a code for choosing a new password to the account, if it has
+    /// an email address. This works without being signed in, and succeeds
+    /// whether or not the account exists.
+    #[instrument(skip_all)]
+    async fn request_password_reset(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(validator(min_length = 1, max_length = 64))] user_id: String,
+    ) -> GqlResult<bool> {
+        ctx.account_persist()
+            .request_password_reset(&user_id)
+            .await
+            .extend()?;
+        Ok(true)
+    }
+
+    /// Choose a new password using the code from `requestPasswordReset`.
+    /// This works without being signed in. The code can only be used once,
+    /// and every token issued for the account is revoked.
+    #[instrument(skip_all)]
+    async fn reset_password(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(validator(min_length = 1, max_length = 1024))] token: String,
+        #[graphql(validator(min_length = 8, max_length = 1024), secret)] pword: SecretString,
+    ) -> GqlResult<DateTime<Utc>> {
+        ctx.account_persist()
+            .reset_password(&token, &pword)
+            .await
+            .extend()
+    }
+
+    /// Choose the accounts that can approve recovering the current account
+    /// if it loses access, and how many of them need to. Giving no contacts
+    /// turns this off. Recoveries waiting for approval are cancelled.
+    #[instrument(skip_all)]
+    async fn set_recovery_contacts(
+        &self,
+        ctx: &Context<'_>,
+        contact_ids: Vec<ID>,
+        threshold: u32,
+    ) -> GqlResult<Option<RecoveryContacts>> {
+        ctx.account_persist()
+            .set_recovery_contacts(contact_ids, threshold)
+            .await
+            .extend()
+    }
+
+    /// Start recovering an account with its trusted contacts, who are each
+    /// notified and asked to approve it. This works without being signed in.
+    #[instrument(skip_all)]
+    async fn request_recovery(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(validator(min_length = 1, max_length = 64))] user_id: String,
+    ) -> GqlResult<StartedRecovery> {
+        ctx.account_persist()
+            .request_recovery(&user_id)
+            .await
+            .extend()
+    }
+
+    /// Approve recovering an account as one of its trusted contacts.
+    #[instrument(skip_all)]
+    async fn approve_recovery(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+    ) -> GqlResult<Option<RecoveryRequest>> {
+        ctx.account_persist().approve_recovery(&id).await.extend()
+    }
+
+    /// Recover an account using the token from `requestRecovery`, once
+    /// enough of its trusted contacts have approved. This works without
+    /// being signed in, and returns a code to use with `resetPassword`.
+    /// Two-factor authentication is turned off for the account.
+    #[instrument(skip_all)]
+    async fn recover_account(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(validator(min_length = 1, max_length = 1024))] token: String,
+    ) -> GqlResult<String> {
+        ctx.account_persist().recover_account(&token).await.extend()
+    }
+
+    /// Report a security event as unrecognized, using its `disownToken`.
+    /// This works without being signed in.
+    ///
+    /// Every token issued for the account is revoked, which signs out
+    /// whoever caused the event, and the instance's admins are asked to help
+    /// recover the account.
+    #[instrument(skip_all)]
+    async fn disown_security_event(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(validator(min_length = 1, max_length = 1024))] token: String,
+    ) -> GqlResult<DateTime<Utc>> {
+        ctx.account_persist().disown(&token).await.extend()
+    }
+
+    /// Delete the current account, and the bots it owns, confirming it with
+    /// the account's password. The account is signed out and can be restored
+    /// with `restoreAccount` until the returned time, when it's purged along
+    /// with everything it posted and its user ID becomes free.
+    #[instrument(skip_all)]
+    async fn delete_account(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(validator(min_length = 8, max_length = 1024), secret)] pword: SecretString,
+    ) -> GqlResult<DateTime<Utc>> {
+        ctx.account_persist().delete(&pword).await.extend()
+    }
+
+    /// Restore a deleted account before it's purged. This works without being
+    /// signed in, and the account has to be logged into afterwards.
+    #[instrument(skip_all)]
+    async fn restore_account(&self, ctx: &Context<'_>, creds: AuthCreds) -> GqlResult<Account> {
+        ctx.account_persist().restore(creds).await.extend()
+    }
+}
+
+/// Records a sign-in, keeping whatever the instance allows about the client.
+async fn record_session(
+    ctx: &Context<'_>,
+    acc: AuthenticatedAccount,
+) -> GqlResult<AuthenticatedAccount> {
+    let session = ctx
+        .session_persist()
+        .record(acc.account.id.clone(), ctx.data_opt::<ClientMeta>())
+        .await
+        .extend()?;
+    Ok(acc.with_session(session))
+}
+
+#[derive(Default)]
+pub struct AccountSubscription;
+
+#[Subscription]
+impl AccountSubscription {
+    /// Sends an account each time it's changed, such as when it updates its
+    /// profile or its role changes, so that it can be shown without polling
+    /// it. This needs to be signed in.
+    #[allow(clippy::unused_async)]
+    async fn account_updated(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+    ) -> async_graphql::Result<impl Stream<Item = Account>> {
+        ctx.account_persist().subscribe(&id).extend()
+    }
 }
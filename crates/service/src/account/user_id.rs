@@ -0,0 +1,129 @@
+//! Rules for the user IDs that accounts register with.
+//!
+//! User IDs appear in URLs and are typed in to log in, so they're kept to a
+//! small set of characters and compared without regard to case. They're
+//! also compared by their skeletons, so that one can't be registered that
+//! only looks like another, such as `rnallory` for `mallory`.
+
+use unicode_security::confusable_detection::skeleton;
+
+use crate::prelude::*;
+
+/// The fewest characters a user ID can have.
+pub const USER_ID_MIN_LEN: usize = 3;
+
+/// The most characters a user ID can have.
+pub const USER_ID_MAX_LEN: usize = 64;
+
+/// User IDs that can't be registered, because they would be confused with
+/// the instance itself or clash with paths such as `/api/v1/accounts/me`.
+static RESERVED_USER_IDS: &[&str] = &[
+    "admin",
+    "administrator",
+    "api",
+    "anonymous",
+    "help",
+    "instance",
+    "me",
+    "moderator",
+    "null",
+    "root",
+    "security",
+    "staff",
+    "support",
+    "system",
+    "undefined",
+];
+
+/// Normalizes a user ID given while registering, and checks it against the
+/// rules for user IDs. Surrounding whitespace is removed and letters are
+/// lowercased.
+///
+/// User IDs can contain ASCII letters, digits, `-`, `_` and `.`, and must
+/// start and end with a letter or digit.
+pub fn normalize_user_id(user_id: &str) -> Result<String> {
+    let user_id = user_id.trim().to_ascii_lowercase();
+
+    let len = user_id.chars().count();
+    if !(USER_ID_MIN_LEN..=USER_ID_MAX_LEN).contains(&len) {
+        return Err(Error::InputInvalid(format!(
+            "user ID must be between {USER_ID_MIN_LEN} and {USER_ID_MAX_LEN} characters"
+        )));
+    }
+    if !user_id
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '-' | '_' | '.'))
+    {
+        return Err(Error::InputInvalid(
+            "user ID can only contain letters, digits, '-', '_' and '.'".into(),
+        ));
+    }
+    let is_alphanumeric = |c: Option<char>| c.is_some_and(|c| c.is_ascii_alphanumeric());
+    if !is_alphanumeric(user_id.chars().next()) || !is_alphanumeric(user_id.chars().last()) {
+        return Err(Error::InputInvalid(
+            "user ID must start and end with a letter or digit".into(),
+        ));
+    }
+    if RESERVED_USER_IDS.contains(&user_id.as_str()) {
+        return Err(Error::InputInvalid("user ID is reserved".into()));
+    }
+
+    Ok(user_id)
+}
+
+/// The skeleton of a user ID, which is the same for user IDs that look
+/// alike, as defined by Unicode's confusable detection. Letters are
+/// lowercased first, as user IDs are compared without regard to case.
+#[must_use]
+pub fn user_id_skeleton(user_id: &str) -> String {
+    skeleton(&user_id.trim().to_lowercase())
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// Whether a user ID looks like one of the reserved user IDs, without being
+/// one.
+#[must_use]
+pub fn is_reserved_lookalike(user_id: &str) -> bool {
+    let user_id_skeleton = user_id_skeleton(user_id);
+    RESERVED_USER_IDS
+        .iter()
+        .any(|reserved| self::user_id_skeleton(reserved) == user_id_skeleton)
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::*;
+
+    #[test_case("alice" => Ok("alice".into()); "simple")]
+    #[test_case("  Alice.B_c-1 " => Ok("alice.b_c-1".into()); "normalized")]
+    #[test_case("ab" => matches Err(Error::InputInvalid(_)); "too short")]
+    #[test_case(&"a".repeat(65) => matches Err(Error::InputInvalid(_)); "too long")]
+    #[test_case("al ice" => matches Err(Error::InputInvalid(_)); "space")]
+    #[test_case("alicé" => matches Err(Error::InputInvalid(_)); "non-ascii")]
+    #[test_case("-alice" => matches Err(Error::InputInvalid(_)); "leading separator")]
+    #[test_case("alice." => matches Err(Error::InputInvalid(_)); "trailing separator")]
+    #[test_case("Admin" => Err(Error::InputInvalid("user ID is reserved".into())); "reserved")]
+    fn test_normalize_user_id(user_id: &str) -> Result<String> {
+        normalize_user_id(user_id)
+    }
+
+    #[test_case("mallory", "rnallory" => true; "rn for m")]
+    #[test_case("paypal", "paypa1" => true; "digit for letter")]
+    #[test_case("alice", "Alice" => true; "case")]
+    #[test_case("alice", "аlice" => true; "cyrillic")]
+    #[test_case("alice", "alicia" => false; "different")]
+    fn test_user_id_skeleton(a: &str, b: &str) -> bool {
+        user_id_skeleton(a) == user_id_skeleton(b)
+    }
+
+    #[test_case("adrnin" => true; "lookalike")]
+    #[test_case("r00t" => true; "digits")]
+    #[test_case("admin" => true; "reserved")]
+    #[test_case("alice" => false; "unreserved")]
+    fn test_is_reserved_lookalike(user_id: &str) -> bool {
+        is_reserved_lookalike(user_id)
+    }
+}
@@ -0,0 +1,3 @@
+mod schema;
+
+pub use schema::*;
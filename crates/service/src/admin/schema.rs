@@ -0,0 +1,410 @@
+use std::time::Duration;
+
+use async_graphql::{connection::Connection, Context, Object, Subscription, ID};
+use chrono::{DateTime, NaiveDate, Utc};
+use futures::Stream;
+use tracing::instrument;
+
+use crate::{
+    account::{
+        Account, AccountRole, DormantAccount, Permission, PermissionGuard, RestrictionKind,
+        RoleGuard,
+    },
+    audit::AuditSinkStatus,
+    bulk::{AccountFilter, BulkJob},
+    capability::CapabilityReport,
+    client_version::MinClientVersion,
+    complexity::SCAN_COMPLEXITY,
+    event::ProjectionStatus,
+    media::AccessibilityReport,
+    moderation::{ModerationCursor, ModerationItem},
+    persist::Persist,
+    prelude::*,
+    query::{page_complexity, PaginationArgs},
+    reload::{ConfigReload, LiveConfig},
+    session::Session,
+    stats::{
+        sample_live_metrics, DailyStats, LiveMetricsSample, StatsBucket, StatsMetric, StatsPoint,
+        UsageRecord,
+    },
+};
+
+#[derive(Default)]
+pub struct AdminQuery;
+
+#[Object]
+impl AdminQuery {
+    /// Instance administration. This can only be accessed by moderators and
+    /// admins, and most of it only by admins.
+    ///
+    /// This is only null if it can't be accessed, so that a field in it that
+    /// fails doesn't take the rest of the response with it.
+    #[graphql(guard = "RoleGuard::new(AccountRole::Moderator)")]
+    #[instrument(skip_all)]
+    async fn admin(&self) -> Option<AdminNamespace> {
+        Some(AdminNamespace)
+    }
+}
+
+#[derive(Default)]
+pub struct AdminMutation;
+
+#[Object]
+impl AdminMutation {
+    /// Makes the instance read-only, so that changes are turned away with a
+    /// `ReadOnly` error while queries keep working, or makes it writable
+    /// again. Returns whether the instance is now read-only. Only admins can
+    /// do this.
+    #[graphql(guard = "PermissionGuard::new(Permission::ManageInstance)")]
+    #[instrument(skip_all)]
+    async fn set_read_only(&self, ctx: &Context<'_>, enabled: bool) -> bool {
+        let persist = ctx.data_unchecked::<Persist>();
+        persist.read_only().set(enabled);
+        persist.read_only().is_read_only()
+    }
+
+    /// Reloads the rate limits, feature flags and log levels from where the
+    /// config was loaded from when the server started, without restarting
+    /// it. The rest of the config isn't reloaded. If the config has problems,
+    /// the one already in use is kept and the problems are returned. Only
+    /// admins can do this.
+    #[graphql(guard = "PermissionGuard::new(Permission::ManageInstance)")]
+    #[instrument(skip_all)]
+    async fn reload_config(&self, ctx: &Context<'_>) -> GqlResult<ConfigReload> {
+        ctx.data_unchecked::<LiveConfig>()
+            .reload()
+            .ok_or_else(|| Error::InputInvalid("There's no config to reload".into()))
+            .extend()
+    }
+
+    /// Sets the oldest version of an app that can still be used, or lets
+    /// every version of it be used when `minVersion` is `null`. Requests from
+    /// older versions fail with a `ClientOutdated` error naming the version to
+    /// update to. Returns every app's oldest supported version. Only admins
+    /// can do this.
+    #[graphql(guard = "PermissionGuard::new(Permission::ManageInstance)")]
+    #[instrument(skip_all)]
+    async fn set_min_client_version(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(validator(max_length = 100))] name: String,
+        #[graphql(validator(max_length = 100))] min_version: Option<String>,
+    ) -> GqlResult<Vec<MinClientVersion>> {
+        let versions = ctx.data_unchecked::<Persist>().client_versions();
+        versions.set(&name, min_version.as_deref()).extend()?;
+        Ok(versions.list())
+    }
+
+    /// Restricts an account for `hours` hours, replacing any restriction it
+    /// already has, and returns it. Suspended accounts can't sign in and
+    /// their posts are hidden, silenced accounts' posts are only shown to
+    /// their followers, and shadow-limited accounts' posts are only shown to
+    /// themselves. The account is told why, except when shadow-limited.
+    /// Admins can't be restricted, and moderators can only be restricted by
+    /// admins. Only moderators and admins can do this.
+    #[graphql(guard = "PermissionGuard::new(Permission::RestrictAccounts)")]
+    #[instrument(skip_all)]
+    async fn restrict_account(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        kind: RestrictionKind,
+        hours: u32,
+        #[graphql(validator(max_length = 1000))] reason: String,
+    ) -> GqlResult<Option<Account>> {
+        ctx.account_persist()
+            .restrict(&id, kind, hours, reason)
+            .await
+            .extend()
+    }
+
+    /// Lifts an account's restriction before it expires, and returns the
+    /// account. Only moderators and admins can do this, and only admins can
+    /// for moderators.
+    #[graphql(guard = "PermissionGuard::new(Permission::RestrictAccounts)")]
+    #[instrument(skip_all)]
+    async fn unrestrict_account(&self, ctx: &Context<'_>, id: ID) -> GqlResult<Option<Account>> {
+        ctx.account_persist().unrestrict(&id).await.extend()
+    }
+
+    /// Changes an account's role, and returns the account. Admins can't
+    /// change their own role, so that the instance always has one. Only
+    /// admins can do this.
+    #[graphql(guard = "PermissionGuard::new(Permission::ManageRoles)")]
+    #[instrument(skip_all)]
+    async fn set_account_role(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        role: AccountRole,
+    ) -> GqlResult<Option<Account>> {
+        ctx.account_persist().set_role(&id, role).await.extend()
+    }
+
+    /// Throws away a projection's read model and builds it again from the
+    /// start of the event log. Returns `null` if the projection is already
+    /// being updated, in which case try again shortly. Only admins can do
+    /// this.
+    #[graphql(guard = "PermissionGuard::new(Permission::ManageInstance)")]
+    #[instrument(skip_all)]
+    async fn rebuild_projection(
+        &self,
+        ctx: &Context<'_>,
+        name: String,
+    ) -> GqlResult<Option<ProjectionStatus>> {
+        ctx.event_persist().rebuild(&name).await.extend()
+    }
+
+    /// Starts a job that suspends every account matching `filter` for
+    /// `hours` hours, and returns it. Each account is suspended as
+    /// `restrictAccount` would, in the background, and accounts that can't
+    /// be, such as admins, are listed in its `failures`. Only moderators and
+    /// admins can do this.
+    #[graphql(guard = "PermissionGuard::new(Permission::RestrictAccounts)")]
+    #[instrument(skip_all)]
+    async fn suspend_accounts(
+        &self,
+        ctx: &Context<'_>,
+        filter: AccountFilter,
+        hours: u32,
+        #[graphql(validator(max_length = 1000))] reason: String,
+    ) -> GqlResult<BulkJob> {
+        ctx.bulk_persist()
+            .suspend_accounts(filter, hours, reason)
+            .await
+            .extend()
+    }
+
+    /// Starts a job that deletes every post linking to `domain` or one of
+    /// its subdomains, and returns it. Only moderators and admins can do
+    /// this.
+    #[graphql(guard = "PermissionGuard::new(Permission::ModerateContent)")]
+    #[instrument(skip_all)]
+    async fn purge_content(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(validator(max_length = 253))] domain: String,
+    ) -> GqlResult<BulkJob> {
+        ctx.bulk_persist().purge_content(&domain).await.extend()
+    }
+
+    /// Cancels a bulk job before its next batch, and returns it. Items it
+    /// has already dealt with stay dealt with. Only accounts that could have
+    /// started the job can do this.
+    #[graphql(guard = "RoleGuard::new(AccountRole::Moderator)")]
+    #[instrument(skip_all)]
+    async fn cancel_bulk_job(&self, ctx: &Context<'_>, id: ID) -> GqlResult<Option<BulkJob>> {
+        ctx.bulk_persist().cancel(&id).await.extend()
+    }
+}
+
+#[derive(Default)]
+pub struct AdminSubscription;
+
+#[Subscription]
+impl AdminSubscription {
+    /// Streams the instance's live health every `intervalSecs` seconds, so
+    /// it can be watched without scraping it. Only admins can do this.
+    ///
+    /// These are sampled from this server process only.
+    #[graphql(guard = "PermissionGuard::new(Permission::ManageInstance)")]
+    async fn live_metrics(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 5, validator(minimum = 1, maximum = 60))] interval_secs: u64,
+    ) -> impl Stream<Item = LiveMetricsSample> {
+        sample_live_metrics(
+            ctx.data_unchecked::<Persist>().clone(),
+            Duration::from_secs(interval_secs),
+        )
+    }
+}
+
+/// Operations that are only available to instance moderators and admins.
+pub struct AdminNamespace;
+
+#[Object]
+impl AdminNamespace {
+    /// Lists the daily statistics between two days, inclusive. If no end day
+    /// is given, statistics up to the current day are listed.
+    #[graphql(guard = "PermissionGuard::new(Permission::ManageInstance)")]
+    #[instrument(skip_all)]
+    async fn stats(
+        &self,
+        ctx: &Context<'_>,
+        from: NaiveDate,
+        to: Option<NaiveDate>,
+    ) -> GqlResult<Vec<DailyStats>> {
+        ctx.stats_persist()
+            .daily(
+                from,
+                to.unwrap_or_else(|| ctx.data_unchecked::<Persist>().clock().now().date_naive()),
+            )
+            .await
+            .extend()
+    }
+
+    /// Charts a statistic between two times, split into hours, days or weeks,
+    /// for dashboards. If no end is given, it's charted up to now. Buckets
+    /// that haven't been rolled up are left out, and hourly rollups only go
+    /// back to when the instance started keeping them.
+    #[graphql(guard = "PermissionGuard::new(Permission::ManageInstance)")]
+    #[instrument(skip_all)]
+    async fn stats_series(
+        &self,
+        ctx: &Context<'_>,
+        metric: StatsMetric,
+        bucket: StatsBucket,
+        from: DateTime<Utc>,
+        to: Option<DateTime<Utc>>,
+    ) -> GqlResult<Vec<StatsPoint>> {
+        ctx.stats_persist()
+            .series(
+                metric,
+                bucket,
+                from,
+                to.unwrap_or_else(|| ctx.data_unchecked::<Persist>().clock().now()),
+            )
+            .await
+            .extend()
+    }
+
+    /// Lists the tenant's usage between two days, inclusive, for billing or
+    /// capacity planning. If no end day is given, usage up to the current day
+    /// is listed. `/api/v1/admin/usage` exports the same records as CSV,
+    /// JSON or `OpenMetrics`.
+    #[graphql(guard = "PermissionGuard::new(Permission::ManageInstance)")]
+    #[instrument(skip_all)]
+    async fn usage(
+        &self,
+        ctx: &Context<'_>,
+        from: NaiveDate,
+        to: Option<NaiveDate>,
+    ) -> GqlResult<Vec<UsageRecord>> {
+        ctx.stats_persist()
+            .usage(
+                from,
+                to.unwrap_or_else(|| ctx.data_unchecked::<Persist>().clock().now().date_naive()),
+            )
+            .await
+            .extend()
+    }
+
+    /// What the instance is running with, such as its version, features,
+    /// settings and migrations, as was logged when it started. Secrets
+    /// aren't included, so this can be attached to bug reports.
+    #[graphql(guard = "PermissionGuard::new(Permission::ManageInstance)")]
+    #[instrument(skip_all)]
+    async fn capabilities<'a>(&self, ctx: &Context<'a>) -> GqlResult<&'a CapabilityReport> {
+        ctx.data::<CapabilityReport>()
+    }
+
+    /// Lists the read models built from the event log, and how far each has
+    /// got through it.
+    #[graphql(guard = "PermissionGuard::new(Permission::ManageInstance)")]
+    #[instrument(skip_all)]
+    async fn projections(&self, ctx: &Context<'_>) -> GqlResult<Vec<ProjectionStatus>> {
+        ctx.event_persist().projections().await.extend()
+    }
+
+    /// Lists the sinks that the audit trail is shipped to, and how far each
+    /// has got through it.
+    #[graphql(guard = "PermissionGuard::new(Permission::ManageInstance)")]
+    #[instrument(skip_all)]
+    async fn audit_sinks(&self, ctx: &Context<'_>) -> GqlResult<Vec<AuditSinkStatus>> {
+        ctx.audit_persist().sinks().await.extend()
+    }
+
+    /// Lists the bulk jobs that moderators and admins have started, newest
+    /// first, with how far each has got.
+    #[instrument(skip_all)]
+    async fn bulk_jobs(&self, ctx: &Context<'_>) -> GqlResult<Vec<BulkJob>> {
+        ctx.bulk_persist().list().await.extend()
+    }
+
+    /// Gets a bulk job.
+    #[instrument(skip_all)]
+    async fn bulk_job(&self, ctx: &Context<'_>, id: ID) -> GqlResult<Option<BulkJob>> {
+        ctx.bulk_persist().get(&id).await.extend()
+    }
+
+    /// Lists the bot accounts registered on the instance, along with who owns
+    /// them.
+    #[graphql(guard = "PermissionGuard::new(Permission::ManageInstance)")]
+    #[instrument(skip_all)]
+    async fn bots(&self, ctx: &Context<'_>) -> GqlResult<Vec<Account>> {
+        ctx.account_persist().bots().await.extend()
+    }
+
+    /// Lists the accounts that were sent a notice for going without signing
+    /// in for the instance's dormancy period, and haven't signed in since,
+    /// along with whether their user IDs have been released.
+    #[graphql(guard = "PermissionGuard::new(Permission::ManageInstance)")]
+    #[instrument(skip_all)]
+    async fn dormant_accounts(&self, ctx: &Context<'_>) -> GqlResult<Vec<DormantAccount>> {
+        ctx.account_persist().dormant().await.extend()
+    }
+
+    /// Lists an account's sign-ins, newest first. Whether their IP addresses
+    /// and user agents can be seen depends on the instance's settings.
+    #[graphql(guard = "PermissionGuard::new(Permission::ManageInstance)")]
+    #[instrument(skip_all)]
+    async fn sessions(&self, ctx: &Context<'_>, account_id: ID) -> GqlResult<Vec<Session>> {
+        ctx.session_persist().list_for(&account_id).await.extend()
+    }
+
+    /// Lists the items in the moderation queue. By default only items that
+    /// haven't been dealt with are listed.
+    #[graphql(
+        guard = "PermissionGuard::new(Permission::ModerateContent)",
+        complexity = "page_complexity(first, last, child_complexity)"
+    )]
+    #[instrument(skip_all)]
+    async fn moderation_queue(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+        #[graphql(default = false)] resolved: bool,
+    ) -> GqlResult<Connection<ModerationCursor, ModerationItem>> {
+        ctx.moderation_persist()
+            .list()
+            .await
+            .extend()?
+            .with_resolved(resolved)
+            .with_pagination(
+                PaginationArgs {
+                    after,
+                    before,
+                    first,
+                    last,
+                }
+                .warn_if_clamped(ctx)
+                .validate()
+                .extend()?,
+            )
+            .execute()
+            .await
+            .extend()
+    }
+
+    /// Reports how many of the images attached to posts have alt text
+    /// describing them, across the instance or in a single board.
+    #[graphql(
+        guard = "PermissionGuard::new(Permission::ModerateContent)",
+        complexity = "SCAN_COMPLEXITY + child_complexity"
+    )]
+    #[instrument(skip_all)]
+    async fn accessibility_report(
+        &self,
+        ctx: &Context<'_>,
+        board_id: Option<ID>,
+    ) -> GqlResult<AccessibilityReport> {
+        ctx.media_persist()
+            .accessibility_report(board_id.as_ref().map(|id| id.as_str()))
+            .await
+            .extend()
+    }
+}
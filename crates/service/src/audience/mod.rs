@@ -0,0 +1,16 @@
+//! Audiences that accounts put together, such as their close friends, so
+//! that they can publish posts that only those accounts can see.
+//!
+//! Who can see a post is checked against the audience's members whenever
+//! the post is read, rather than when it's published, so removing someone
+//! from an audience hides the posts that were already shared with them.
+
+mod models;
+mod persist;
+mod schema;
+
+pub use models::*;
+pub use persist::*;
+pub use schema::*;
+
+pub static AUDIENCE_TABLE_NAME: &str = "audience";
@@ -0,0 +1,99 @@
+use async_graphql::{ComplexObject, InputObject, SimpleObject, ID};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use surrealdb::sql::Thing;
+
+use super::AUDIENCE_TABLE_NAME;
+use crate::{id_obj_impls, prelude::*};
+
+/// A group of accounts that its owner can publish posts to, such as their
+/// close friends. Audiences are only visible to their owner.
+#[derive(SimpleObject, Debug, Clone, Deserialize)]
+#[graphql(complex)]
+pub struct Audience {
+    #[graphql(skip)]
+    pub id: Thing,
+    #[graphql(skip)]
+    pub owner_id: Thing,
+    #[graphql(skip)]
+    #[serde(default)]
+    pub member_ids: Vec<Thing>,
+
+    /// The audience's name.
+    pub name: String,
+
+    /// A timestamp indicating the last time the audience was updated.
+    pub updated_at: DateTime<Utc>,
+}
+
+#[ComplexObject]
+impl Audience {
+    /// The audience's unique ID.
+    async fn id(&self) -> ID {
+        self.id.to_gql_id()
+    }
+
+    /// The ID of the account that owns this audience.
+    async fn owner_id(&self) -> ID {
+        self.owner_id.to_gql_id()
+    }
+
+    /// The IDs of the accounts in this audience.
+    async fn member_ids(&self) -> Vec<ID> {
+        self.member_ids.iter().map(ToGqlId::to_gql_id).collect()
+    }
+}
+
+id_obj_impls!(Audience);
+
+impl Audience {
+    pub fn create(
+        owner_id: Thing,
+        params: CreateAudience,
+        ids: &dyn IdGen,
+    ) -> srql::CreateStatement {
+        let mut create = vec![];
+        owner_id.push_field(srql::field("owner_id"), &mut create);
+        create.push((
+            srql::field("member_ids"),
+            srql::Operator::Equal,
+            srql::array(vec![]),
+        ));
+        params.append(&mut create);
+        srql::obj_create_query(AUDIENCE_TABLE_NAME, create, ids)
+    }
+
+    /// Whether the given account can see posts published to the audience,
+    /// which its owner and members can.
+    pub fn includes(&self, account: &Thing) -> bool {
+        self.owner_id == *account || self.member_ids.contains(account)
+    }
+}
+
+#[derive(InputObject, Debug, Default, Clone, PartialEq, Eq)]
+pub struct CreateAudience {
+    /// The audience's name.
+    #[graphql(validator(min_length = 1, max_length = 256))]
+    pub name: String,
+}
+
+impl CreateObject for CreateAudience {
+    fn append(self, expr: &mut srql::SetExpr) {
+        self.name.push_field(srql::field("name"), expr);
+    }
+}
+
+#[derive(InputObject, Debug, Default, Clone, PartialEq, Eq)]
+pub struct UpdateAudience {
+    /// The new name. If not given, the name is not changed.
+    #[graphql(validator(min_length = 1, max_length = 256))]
+    pub name: Option<String>,
+}
+
+impl IntoUpdateQuery for UpdateAudience {
+    fn into_update(self, thing: srql::Thing) -> Option<srql::UpdateStatement> {
+        let mut update = vec![];
+        self.name.push_field(srql::field("name"), &mut update);
+        srql::obj_update_query(thing, update)
+    }
+}
@@ -0,0 +1,241 @@
+#[cfg(test)]
+mod tests;
+
+use tracing::instrument;
+
+use super::{Audience, CreateAudience, UpdateAudience, AUDIENCE_TABLE_NAME};
+use crate::{
+    account::{Account, CurrentAccount},
+    persist::Persist,
+    prelude::*,
+};
+
+pub struct AudiencePersist<'a> {
+    persist: &'a Persist,
+    current: &'a CurrentAccount,
+}
+
+impl<'a> AudiencePersist<'a> {
+    pub fn new(persist: &'a Persist, current: &'a CurrentAccount) -> Self {
+        Self { persist, current }
+    }
+
+    /// Gets one of the current account's audiences. Other accounts'
+    /// audiences aren't returned, so that members can't tell which audiences
+    /// they're in.
+    #[instrument(skip_all)]
+    pub async fn get(&self, id: &str) -> Result<Option<Audience>> {
+        let owner = self.current.id()?.to_account_thing();
+        let audience: Option<Audience> =
+            self.persist.db().select((AUDIENCE_TABLE_NAME, id)).await?;
+        Ok(audience.filter(|audience| audience.owner_id == owner))
+    }
+
+    /// Lists all of the audiences owned by the current account.
+    #[instrument(skip_all)]
+    pub async fn owned(&self) -> Result<Vec<Audience>> {
+        let owner = self.current.id()?.to_account_thing();
+        let audiences = self
+            .persist
+            .db()
+            .query(srql::SelectStatement {
+                expr: srql::Fields::all(),
+                what: srql::table(AUDIENCE_TABLE_NAME),
+                cond: srql::Cond(
+                    srql::Expression::Binary {
+                        l: srql::field("owner_id").into(),
+                        o: srql::Operator::Equal,
+                        r: owner.into(),
+                    }
+                    .into(),
+                )
+                .into(),
+                order: srql::Orders(vec![srql::Order {
+                    order: srql::field("id"),
+                    direction: true,
+                    ..Default::default()
+                }])
+                .into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        Ok(audiences)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn create(&self, audience: CreateAudience) -> Result<Audience> {
+        let owner = self.current.id()?.to_account_thing();
+        let audience = self
+            .persist
+            .db()
+            .query(Audience::create(owner, audience, self.persist.ids()))
+            .await?
+            .take(0)?;
+
+        match audience {
+            Some(audience) => Ok(audience),
+            None => Err(Error::UnavailableIdent),
+        }
+    }
+
+    #[instrument(skip_all)]
+    pub async fn update(&self, id: &str, update: UpdateAudience) -> Result<Option<Audience>> {
+        let Some(audience) = self.get(id).await? else {
+            return Ok(None);
+        };
+
+        let audience = if let Some(update) = update.into_update(audience.id.clone()) {
+            self.persist.db().query(update).await?.take(0)?
+        } else {
+            Some(audience)
+        };
+
+        Ok(audience)
+    }
+
+    /// Deletes an audience. Posts that were published to it stay hidden from
+    /// everyone but their authors.
+    #[instrument(skip_all)]
+    pub async fn delete(&self, id: &str) -> Result<Option<Audience>> {
+        if self.get(id).await?.is_none() {
+            return Ok(None);
+        }
+
+        let audience = self.persist.db().delete((AUDIENCE_TABLE_NAME, id)).await?;
+        Ok(audience)
+    }
+
+    /// Adds an account to an audience, which lets it see the posts that have
+    /// been and will be published to the audience.
+    #[instrument(skip_all)]
+    pub async fn add_member(&self, id: &str, account_id: &str) -> Result<Option<Audience>> {
+        let Some(audience) = self.get(id).await? else {
+            return Ok(None);
+        };
+
+        let member = account_id.to_account_thing();
+        let account: Option<Account> = self.persist.load(member.clone()).await?;
+        if member == audience.owner_id || account.is_none_or(|acc| acc.deleted_at.is_some()) {
+            return Err(Error::InputInvalid("account cannot be added".into()));
+        }
+
+        self.update_members(audience, srql::Operator::Ext, member)
+            .await
+    }
+
+    /// Removes an account from an audience. It can no longer see any of the
+    /// audience's posts, including ones published while it was a member,
+    /// other than replies that it made itself.
+    #[instrument(skip_all)]
+    pub async fn remove_member(&self, id: &str, account_id: &str) -> Result<Option<Audience>> {
+        let Some(audience) = self.get(id).await? else {
+            return Ok(None);
+        };
+
+        self.update_members(audience, srql::Operator::Dec, account_id.to_account_thing())
+            .await
+    }
+
+    async fn update_members(
+        &self,
+        audience: Audience,
+        op: srql::Operator,
+        account_id: srql::Thing,
+    ) -> Result<Option<Audience>> {
+        let Some(update) = srql::obj_update_query(
+            audience.id,
+            vec![(srql::field("member_ids"), op, account_id.into())],
+        ) else {
+            return Err("".into());
+        };
+
+        Ok(self.persist.db().query(update).await?.take(0)?)
+    }
+}
+
+/// Whether an account can see posts published to an audience. Posts stay
+/// hidden if the audience has been deleted.
+pub async fn can_see_audience(
+    persist: &Persist,
+    audience_id: &srql::Thing,
+    viewer: Option<&srql::Thing>,
+) -> Result<bool> {
+    let Some(viewer) = viewer else {
+        return Ok(false);
+    };
+    let audience: Option<Audience> = persist.load(audience_id.clone()).await?;
+    Ok(audience.is_some_and(|audience| audience.includes(viewer)))
+}
+
+/// A condition that only matches posts that the viewer can see, given the
+/// audiences they were published to. Authors always see their own posts.
+pub fn audience_visible_cond(viewer: Option<&srql::Thing>) -> srql::Cond {
+    let public = srql::Expression::Binary {
+        l: srql::field("audience_id").into(),
+        o: srql::Operator::Equal,
+        r: srql::Value::None,
+    };
+    let Some(viewer) = viewer else {
+        return srql::Cond(public.into());
+    };
+
+    let binary = |l: srql::Value, o, r: srql::Value| -> srql::Value {
+        srql::Expression::Binary { l, o, r }.into()
+    };
+    let either = |l, r| binary(l, srql::Operator::Or, r);
+    srql::Cond(either(
+        either(
+            public.into(),
+            binary(
+                srql::field("creator_id").into(),
+                srql::Operator::Equal,
+                viewer.clone().into(),
+            ),
+        ),
+        either(
+            binary(
+                srql::path(&["audience_id", "owner_id"]).into(),
+                srql::Operator::Equal,
+                viewer.clone().into(),
+            ),
+            binary(
+                srql::path(&["audience_id", "member_ids"]).into(),
+                srql::Operator::Contain,
+                viewer.clone().into(),
+            ),
+        ),
+    ))
+}
+
+#[cfg(test)]
+pub mod testing {
+    use async_trait::async_trait;
+
+    use crate::{
+        account::testing::TestData,
+        audience::{Audience, CreateAudience},
+    };
+
+    use super::AudiencePersist;
+
+    #[async_trait]
+    pub trait AudienceTestData {
+        fn audience(&self) -> AudiencePersist<'_>;
+
+        async fn generate_audience(&self) -> Audience {
+            self.audience()
+                .create(CreateAudience {
+                    name: "Close friends".into(),
+                })
+                .await
+                .unwrap()
+        }
+    }
+
+    impl AudienceTestData for TestData {
+        fn audience(&self) -> AudiencePersist<'_> {
+            AudiencePersist::new(&self.persist, &self.current)
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use pretty_assertions::assert_eq;
+
+use super::{testing::AudienceTestData as _, *};
+use crate::{
+    account::testing::*,
+    post::{testing::PostTestData as _, CreatePost, Post},
+    query::PaginationInput,
+};
+
+async fn audience_post(data: &TestData, audience: &Audience) -> Post {
+    data.post()
+        .create(CreatePost {
+            content: Some("Close friends only".into()),
+            audience_id: Some(audience.id.to_gql_id()),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+}
+
+async fn listed(data: &TestData) -> Vec<Post> {
+    data.post()
+        .list()
+        .with_pagination(PaginationInput::new().forward(10))
+        .execute()
+        .await
+        .unwrap()
+        .edges
+        .into_iter()
+        .map(|edge| edge.node)
+        .collect()
+}
+
+#[tokio::test]
+async fn test_members() {
+    let (mut data, acc) = TestData::with_user().await;
+    let member = data.account().create_test_user().await;
+    let audience = data.generate_audience().await;
+    let id = audience.id.id.to_raw();
+
+    let res = data
+        .audience()
+        .add_member(&id, &member.id.id.to_raw())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(res.member_ids, vec![member.id.clone()]);
+    assert_eq!(data.audience().owned().await.unwrap(), vec![res]);
+
+    // Owners can't add themselves.
+    assert!(matches!(
+        data.audience().add_member(&id, &acc.id.id.to_raw()).await,
+        Err(Error::InputInvalid(_))
+    ));
+
+    // Other accounts can't see or change the audience.
+    data.login_as(&member);
+    assert_eq!(data.audience().get(&id).await, Ok(None));
+    assert_eq!(
+        data.audience()
+            .remove_member(&id, &member.id.id.to_raw())
+            .await,
+        Ok(None)
+    );
+    data.login_as(&acc);
+
+    let res = data
+        .audience()
+        .remove_member(&id, &member.id.id.to_raw())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(res.member_ids.is_empty());
+}
+
+#[tokio::test]
+async fn test_visibility() {
+    let (mut data, acc) = TestData::with_user().await;
+    let member = data.account().create_test_user().await;
+    let other = data.account().create_test_user().await;
+    let audience = data.generate_audience().await;
+    data.audience()
+        .add_member(&audience.id.id.to_raw(), &member.id.id.to_raw())
+        .await
+        .unwrap();
+    let post = audience_post(&data, &audience).await;
+    let public = data.generate_post().await;
+    let post_id = post.id.id.to_raw();
+
+    data.login_as(&member);
+    assert!(data.post().get(&post_id).await.unwrap().is_some());
+    assert_eq!(listed(&data).await, vec![public.clone(), post.clone()]);
+
+    data.login_as(&other);
+    assert_eq!(data.post().get(&post_id).await, Ok(None));
+    assert_eq!(listed(&data).await, vec![public.clone()]);
+
+    data.current = CurrentAccount::default();
+    assert_eq!(data.post().get(&post_id).await, Ok(None));
+    assert_eq!(listed(&data).await, vec![public.clone()]);
+
+    // Only the owner's audiences can be posted to.
+    data.login_as(&other);
+    let res = data
+        .post()
+        .create(CreatePost {
+            content: Some("Test".into()),
+            audience_id: Some(audience.id.to_gql_id()),
+            ..Default::default()
+        })
+        .await;
+    assert_eq!(res.unwrap_err(), Error::AudienceInvalid);
+
+    data.login_as(&acc);
+    assert_eq!(listed(&data).await, vec![public, post]);
+}
+
+#[tokio::test]
+async fn test_removed_member() {
+    let (mut data, acc) = TestData::with_user().await;
+    let member = data.account().create_test_user().await;
+    let audience = data.generate_audience().await;
+    let (audience_id, member_id) = (audience.id.id.to_raw(), member.id.id.to_raw());
+    data.audience()
+        .add_member(&audience_id, &member_id)
+        .await
+        .unwrap();
+    let post = audience_post(&data, &audience).await;
+
+    // Replies are published to the same audience, even when their authors
+    // don't own it.
+    data.login_as(&member);
+    let reply = data.generate_reply(&post.id).await;
+    assert_eq!(reply.audience_id, Some(audience.id.clone()));
+
+    // Posts that were shared before the member was removed are hidden from
+    // them, other than their own replies.
+    data.login_as(&acc);
+    assert!(data
+        .post()
+        .get(&reply.id.id.to_raw())
+        .await
+        .unwrap()
+        .is_some());
+    data.audience()
+        .remove_member(&audience_id, &member_id)
+        .await
+        .unwrap();
+    data.login_as(&member);
+    assert_eq!(data.post().get(&post.id.id.to_raw()).await, Ok(None));
+    assert_eq!(listed(&data).await, vec![reply.clone()]);
+    let res = data
+        .post()
+        .create(CreatePost {
+            reply_to_id: Some(post.id.to_gql_id()),
+            ..Default::default()
+        })
+        .await;
+    assert_eq!(res.unwrap_err(), Error::ReplyInvalid);
+
+    // Deleting the audience leaves its posts to their authors.
+    data.login_as(&acc);
+    data.audience().delete(&audience_id).await.unwrap();
+    assert!(data
+        .post()
+        .get(&post.id.id.to_raw())
+        .await
+        .unwrap()
+        .is_some());
+    assert_eq!(data.post().get(&reply.id.id.to_raw()).await, Ok(None));
+}
@@ -0,0 +1,87 @@
+use async_graphql::{Context, Object, ID};
+use tracing::instrument;
+
+use super::{Audience, CreateAudience, UpdateAudience};
+use crate::{policy::PoliciesAccepted, prelude::*};
+
+#[derive(Default)]
+pub struct AudienceQuery;
+
+#[Object]
+impl AudienceQuery {
+    /// Gets one of the current account's audiences by its ID.
+    #[instrument(skip_all)]
+    async fn audience(&self, ctx: &Context<'_>, id: ID) -> GqlResult<Option<Audience>> {
+        ctx.audience_persist().get(&id).await.extend()
+    }
+
+    /// Lists the audiences owned by the current account.
+    #[instrument(skip_all)]
+    async fn audiences(&self, ctx: &Context<'_>) -> GqlResult<Vec<Audience>> {
+        ctx.audience_persist().owned().await.extend()
+    }
+}
+
+#[derive(Default)]
+pub struct AudienceMutation;
+
+#[Object(guard = "PoliciesAccepted")]
+impl AudienceMutation {
+    /// Creates a new audience.
+    #[instrument(skip_all)]
+    async fn create_audience(
+        &self,
+        ctx: &Context<'_>,
+        create: CreateAudience,
+    ) -> GqlResult<Audience> {
+        ctx.audience_persist().create(create).await.extend()
+    }
+
+    /// Updates an audience.
+    #[instrument(skip_all)]
+    async fn update_audience(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        update: UpdateAudience,
+    ) -> GqlResult<Option<Audience>> {
+        ctx.audience_persist().update(&id, update).await.extend()
+    }
+
+    /// Deletes an audience. Posts that were published to it are hidden from
+    /// everyone but their authors.
+    #[instrument(skip_all)]
+    async fn delete_audience(&self, ctx: &Context<'_>, id: ID) -> GqlResult<Option<Audience>> {
+        ctx.audience_persist().delete(&id).await.extend()
+    }
+
+    /// Adds an account to an audience, letting it see the posts published to
+    /// the audience.
+    #[instrument(skip_all)]
+    async fn add_audience_member(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        account_id: ID,
+    ) -> GqlResult<Option<Audience>> {
+        ctx.audience_persist()
+            .add_member(&id, &account_id)
+            .await
+            .extend()
+    }
+
+    /// Removes an account from an audience. It stops being able to see the
+    /// audience's posts straight away, including ones it could see before.
+    #[instrument(skip_all)]
+    async fn remove_audience_member(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        account_id: ID,
+    ) -> GqlResult<Option<Audience>> {
+        ctx.audience_persist()
+            .remove_member(&id, &account_id)
+            .await
+            .extend()
+    }
+}
@@ -0,0 +1,46 @@
+use std::time::Duration;
+
+use tokio::{
+    task::JoinHandle,
+    time::{interval, MissedTickBehavior},
+};
+use tracing::{debug, error, trace};
+
+use super::{audit_lock_id, ship_audit_trail};
+use crate::persist::Persist;
+
+/// How often new audit entries are shipped to each sink.
+pub const AUDIT_SHIPPING_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Spawns a task that periodically ships new audit entries to every sink
+/// the instance is configured with. Sinks are shipped to one at a time, so
+/// a slow or failing one doesn't hold up the others for more than its
+/// timeout.
+pub fn spawn_audit_shipping(persist: Persist) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = interval(AUDIT_SHIPPING_INTERVAL);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+
+            for sink in persist.audit_sinks() {
+                let id = sink.id();
+                let res = persist
+                    .execute_in_lock(&audit_lock_id(&**sink), || async {
+                        let _job = persist.metrics().track_job();
+                        ship_audit_trail(&persist, &**sink).await
+                    })
+                    .await;
+
+                match res {
+                    Ok(Some(Ok(0))) => trace!(id, "No new audit entries to ship"),
+                    Ok(Some(Ok(count))) => debug!(id, count, "Audit entries shipped"),
+                    Ok(Some(Err(err))) => error!(error = ?err, id, "Failed to ship audit entries"),
+                    Ok(None) => trace!(id, "Audit entries are already being shipped"),
+                    Err(err) => error!(error = ?err, id, "Failed to lock audit sink"),
+                }
+            }
+        }
+    })
+}
@@ -0,0 +1,23 @@
+//! Shipping of the instance's audit trail to sinks outside its database, so
+//! that it can be kept for as long as compliance needs without the instance
+//! keeping it.
+//!
+//! The audit trail is the security log. Each sink keeps a checkpoint of the
+//! last entry it was sent, like a projection of the event log, which only
+//! moves once a batch has been delivered. A sink that fails, or a server that
+//! stops part way, is sent the same entries again, so delivery is at least
+//! once and sinks should drop entries whose IDs they've already seen.
+
+mod job;
+mod models;
+mod persist;
+mod s3;
+mod sink;
+
+pub use job::*;
+pub use models::*;
+pub use persist::*;
+pub use s3::*;
+pub use sink::*;
+
+static AUDIT_SINK_TABLE_NAME: &str = "audit_sink";
@@ -0,0 +1,64 @@
+use async_graphql::SimpleObject;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use surrealdb::sql::Thing;
+
+use crate::security::{SecurityEvent, SecurityEventKind};
+
+/// An entry in the audit trail, as it's sent to sinks. IDs increase in the
+/// order entries were logged.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AuditEntry {
+    pub id: String,
+    /// What happened.
+    pub kind: SecurityEventKind,
+    /// The account it happened to.
+    pub account_id: String,
+    /// The session it happened in, if there was one.
+    pub session_id: Option<String>,
+    pub occurred_at: DateTime<Utc>,
+    /// The region of the server that it happened on, when the instance is
+    /// deployed across several.
+    pub region: Option<String>,
+    /// More about what happened, for entries that the instance logs about
+    /// itself. Left out for other entries, so that they're sent as they
+    /// always have been.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl From<SecurityEvent> for AuditEntry {
+    fn from(event: SecurityEvent) -> Self {
+        Self {
+            id: event.id.id.to_raw(),
+            kind: event.kind,
+            account_id: event.account_id.id.to_raw(),
+            session_id: event.session_id.map(|session_id| session_id.id.to_raw()),
+            occurred_at: event.occurred_at,
+            region: event.region,
+            detail: event.detail,
+        }
+    }
+}
+
+/// How far an audit sink has got through the audit trail.
+#[derive(SimpleObject, Debug, Clone, Default, Deserialize)]
+pub struct AuditSinkStatus {
+    /// The sink's ID, as it's configured.
+    #[serde(default)]
+    pub name: String,
+    /// What kind of sink it is, such as `file` or `s3`.
+    #[serde(skip)]
+    pub kind: String,
+    /// How many entries have been delivered to the sink.
+    #[serde(default)]
+    pub entries_shipped: u64,
+    /// When entries were last delivered to the sink.
+    pub shipped_at: Option<DateTime<Utc>>,
+    /// Why delivering to the sink last failed, if it hasn't succeeded since.
+    pub last_error: Option<String>,
+    /// When delivering to the sink last failed.
+    pub failed_at: Option<DateTime<Utc>>,
+    #[graphql(skip)]
+    pub last_entry_id: Option<Thing>,
+}
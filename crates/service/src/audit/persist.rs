@@ -0,0 +1,182 @@
+#[cfg(test)]
+mod tests;
+
+use tracing::instrument;
+
+use super::{AuditEntry, AuditSink, AuditSinkStatus, AUDIT_SINK_TABLE_NAME};
+use crate::{
+    account::{require_admin, CurrentAccount},
+    persist::Persist,
+    prelude::*,
+    query::SRQL_ORDER_ASC,
+    security::{SecurityEvent, SECURITY_EVENT_TABLE_NAME},
+};
+
+/// How many entries are sent to a sink at once.
+const BATCH_SIZE: u64 = 500;
+
+pub struct AuditPersist<'a> {
+    persist: &'a Persist,
+    current: &'a CurrentAccount,
+}
+
+impl<'a> AuditPersist<'a> {
+    pub fn new(persist: &'a Persist, current: &'a CurrentAccount) -> Self {
+        Self { persist, current }
+    }
+
+    /// Lists the sinks that the audit trail is shipped to, and how far each
+    /// has got through it.
+    ///
+    /// Only admins can see these.
+    #[instrument(skip_all)]
+    pub async fn sinks(&self) -> Result<Vec<AuditSinkStatus>> {
+        require_admin(self.persist, self.current).await?;
+        let mut statuses = Vec::with_capacity(self.persist.audit_sinks().len());
+        for sink in self.persist.audit_sinks() {
+            statuses.push(status(self.persist, &**sink).await?);
+        }
+        Ok(statuses)
+    }
+}
+
+/// Sends the entries that the sink hasn't been sent yet, in the order they
+/// were logged, moving its checkpoint past each batch once it's delivered.
+/// Returns how many were sent. If the sink fails, the failure is kept with
+/// its checkpoint and the batch is tried again next time.
+#[instrument(skip_all, fields(sink = sink.id()))]
+pub async fn ship_audit_trail(persist: &Persist, sink: &dyn AuditSink) -> Result<usize> {
+    let mut last_entry_id = status(persist, sink).await?.last_entry_id;
+    let mut shipped = 0;
+    loop {
+        let events: Vec<SecurityEvent> = persist
+            .db()
+            .query(srql::SelectStatement {
+                expr: srql::Fields::all(),
+                what: srql::table(SECURITY_EVENT_TABLE_NAME),
+                cond: last_entry_id.clone().map(|last_entry_id| {
+                    srql::Cond(
+                        srql::Expression::Binary {
+                            l: srql::field("id").into(),
+                            o: srql::Operator::MoreThan,
+                            r: last_entry_id.into(),
+                        }
+                        .into(),
+                    )
+                }),
+                order: srql::Orders(vec![srql::Order {
+                    order: srql::field("id"),
+                    direction: SRQL_ORDER_ASC,
+                    ..Default::default()
+                }])
+                .into(),
+                limit: srql::Limit(BATCH_SIZE.into()).into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        let Some(last_id) = events.last().map(|event| event.id.clone()) else {
+            return Ok(shipped);
+        };
+        let entries: Vec<AuditEntry> = events.into_iter().map(Into::into).collect();
+
+        let now = persist.clock().now();
+        if let Err(err) = sink.ship(&entries).await {
+            // Only admins see this, so it can say what went wrong.
+            let message = match &err {
+                Error::InternalServerError(message) => message.clone(),
+                err => err.to_string(),
+            };
+            let mut update = vec![];
+            message.push_field(srql::field("last_error"), &mut update);
+            now.push_field(srql::field("failed_at"), &mut update);
+            checkpoint(persist, sink, update).await?;
+            return Err(err);
+        }
+        checkpoint(
+            persist,
+            sink,
+            vec![
+                (
+                    srql::field("last_entry_id"),
+                    srql::Operator::Equal,
+                    last_id.clone().into(),
+                ),
+                (
+                    srql::field("entries_shipped"),
+                    srql::Operator::Inc,
+                    entries.len().into(),
+                ),
+                (
+                    srql::field("shipped_at"),
+                    srql::Operator::Equal,
+                    srql::Value::Datetime(srql::Datetime(now)),
+                ),
+                (
+                    srql::field("last_error"),
+                    srql::Operator::Equal,
+                    srql::Value::None,
+                ),
+            ],
+        )
+        .await?;
+
+        shipped += entries.len();
+        last_entry_id = Some(last_id);
+    }
+}
+
+async fn status(persist: &Persist, sink: &dyn AuditSink) -> Result<AuditSinkStatus> {
+    let status: Option<AuditSinkStatus> = persist
+        .db()
+        .select((AUDIT_SINK_TABLE_NAME, sink.id()))
+        .await?;
+    Ok(AuditSinkStatus {
+        kind: sink.kind().to_owned(),
+        ..status.unwrap_or_else(|| AuditSinkStatus {
+            name: sink.id().to_owned(),
+            ..Default::default()
+        })
+    })
+}
+
+async fn checkpoint(
+    persist: &Persist,
+    sink: &dyn AuditSink,
+    mut update: srql::SetExpr,
+) -> Result<()> {
+    update.push((srql::field("name"), srql::Operator::Equal, sink.id().into()));
+    persist
+        .db()
+        .query(srql::UpdateStatement {
+            what: srql::thing((AUDIT_SINK_TABLE_NAME, sink.id())),
+            data: srql::Data::SetExpression(update).into(),
+            output: srql::Output::None.into(),
+            ..Default::default()
+        })
+        .await?
+        .check()?;
+    Ok(())
+}
+
+/// The lock held while entries are being sent to a sink.
+pub fn audit_lock_id(sink: &dyn AuditSink) -> String {
+    format!("audit_sink_{}", sink.id())
+}
+
+#[cfg(test)]
+pub mod testing {
+    use crate::account::testing::TestData;
+
+    use super::AuditPersist;
+
+    pub trait AuditTestData {
+        fn audit(&self) -> AuditPersist<'_>;
+    }
+
+    impl AuditTestData for TestData {
+        fn audit(&self) -> AuditPersist<'_> {
+            AuditPersist::new(&self.persist, &self.current)
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use std::sync::Arc;
+
+use pretty_assertions::assert_eq;
+
+use super::{testing::AuditTestData as _, *};
+use crate::{
+    account::testing::*,
+    audit::MemoryAuditSink,
+    security::{testing::SecurityEventTestData as _, SecurityEventKind},
+};
+
+async fn log(data: &TestData, acc: &AccData, kind: SecurityEventKind) -> AuditEntry {
+    data.security_event()
+        .log(acc.id.clone(), kind, None)
+        .await
+        .unwrap()
+        .into()
+}
+
+#[tokio::test]
+async fn test_ship() {
+    let (data, acc) = TestData::with_user().await;
+    let sink = MemoryAuditSink::new("memory");
+    ship_audit_trail(&data.persist, &sink).await.unwrap();
+    sink.take();
+
+    let first = log(&data, &acc, SecurityEventKind::SignedIn).await;
+    let second = log(&data, &acc, SecurityEventKind::PasswordReset).await;
+    assert_eq!(ship_audit_trail(&data.persist, &sink).await, Ok(2));
+    assert_eq!(sink.take(), vec![first, second.clone()]);
+
+    // Only entries logged since are shipped next time.
+    assert_eq!(ship_audit_trail(&data.persist, &sink).await, Ok(0));
+    let third = log(&data, &acc, SecurityEventKind::SignedIn).await;
+    assert_eq!(ship_audit_trail(&data.persist, &sink).await, Ok(1));
+    assert_eq!(sink.take(), vec![third.clone()]);
+
+    let status = status(&data.persist, &sink).await.unwrap();
+    assert_eq!(status.name, "memory");
+    assert_eq!(status.kind, "memory");
+    assert_eq!(status.last_error, None);
+    assert!(status.shipped_at.is_some());
+    assert_eq!(
+        status.last_entry_id.map(|id| id.id.to_raw()),
+        Some(third.id)
+    );
+}
+
+#[tokio::test]
+async fn test_ship_failing() {
+    let (data, acc) = TestData::with_user().await;
+    let sink = MemoryAuditSink::new("memory");
+    ship_audit_trail(&data.persist, &sink).await.unwrap();
+    sink.take();
+    let shipped = status(&data.persist, &sink).await.unwrap().entries_shipped;
+
+    let entry = log(&data, &acc, SecurityEventKind::SignedIn).await;
+    sink.set_failing(true);
+    let res = ship_audit_trail(&data.persist, &sink).await;
+    println!("{res:?}");
+    assert!(res.is_err());
+
+    // The checkpoint doesn't move, and the failure is kept with it.
+    let status = super::status(&data.persist, &sink).await.unwrap();
+    assert_eq!(status.entries_shipped, shipped);
+    assert_eq!(
+        status.last_error.as_deref(),
+        Some("memory audit sink is failing")
+    );
+    assert!(status.failed_at.is_some());
+
+    // The same entries are sent again once the sink recovers.
+    sink.set_failing(false);
+    assert_eq!(ship_audit_trail(&data.persist, &sink).await, Ok(1));
+    assert_eq!(sink.take(), vec![entry]);
+    let status = super::status(&data.persist, &sink).await.unwrap();
+    assert_eq!(status.entries_shipped, shipped + 1);
+    assert_eq!(status.last_error, None);
+}
+
+#[tokio::test]
+async fn test_sinks() {
+    let (mut data, acc) = TestData::with_user().await;
+    let sink = MemoryAuditSink::new("memory");
+    data.persist = data
+        .persist
+        .clone()
+        .with_audit_sinks(vec![Arc::new(sink.clone())]);
+    log(&data, &acc, SecurityEventKind::SignedIn).await;
+    let shipped = ship_audit_trail(&data.persist, &sink).await.unwrap();
+
+    let res = data.audit().sinks().await;
+    println!("{res:?}");
+    let res = res.unwrap();
+    assert_eq!(res.len(), 1);
+    assert_eq!(res[0].name, "memory");
+    assert_eq!(res[0].kind, "memory");
+    assert_eq!(res[0].entries_shipped, shipped as u64);
+
+    let other = data.account().create_test_user().await;
+    data.login_as(&other);
+    let res = data.audit().sinks().await;
+    println!("{res:?}");
+    assert!(matches!(res, Err(Error::Unauthorized)));
+}
@@ -0,0 +1,256 @@
+use std::fmt::{self, Debug, Write as _};
+
+use anyhow::Context as _;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use hyper::{http::uri::Authority, Body, Method, Request, Uri};
+use ring::{digest, hmac};
+use secrecy::{ExposeSecret as _, SecretString};
+
+use super::{json_lines, AuditEntry, AuditSink, SHIP_TIMEOUT};
+use crate::{
+    http::{http_client, HttpClient},
+    prelude::*,
+    provider::SharedClock,
+};
+
+/// Where an [`S3AuditSink`] puts entries, and the credentials it signs its
+/// requests with.
+#[derive(Debug, Clone)]
+pub struct S3Bucket {
+    /// The S3-compatible service, such as `https://s3.eu-west-1.amazonaws.com`.
+    pub endpoint: String,
+    pub bucket: String,
+    pub region: String,
+    /// Put in front of every object's key, such as `audit/`.
+    pub prefix: String,
+    pub access_key_id: String,
+    pub secret_access_key: SecretString,
+}
+
+/// Puts each batch of entries in an S3 bucket as an object of JSON lines,
+/// named after the IDs of its first and last entries. A batch that's sent
+/// again replaces the object it was put in before, unless entries were
+/// logged in between. Requests are signed with AWS Signature Version 4, and
+/// the bucket is addressed by path so that other S3-compatible services
+/// work too.
+#[derive(Clone)]
+pub struct S3AuditSink {
+    id: String,
+    bucket: S3Bucket,
+    endpoint: Uri,
+    host: Authority,
+    clock: SharedClock,
+    client: HttpClient,
+}
+
+impl S3AuditSink {
+    pub fn new(
+        id: impl Into<String>,
+        bucket: S3Bucket,
+        clock: SharedClock,
+    ) -> anyhow::Result<Self> {
+        let endpoint: Uri = bucket
+            .endpoint
+            .trim_end_matches('/')
+            .parse()
+            .context("S3 endpoint is invalid")?;
+        let host = endpoint
+            .authority()
+            .cloned()
+            .context("S3 endpoint has no host")?;
+        Ok(Self {
+            id: id.into(),
+            bucket,
+            endpoint,
+            host,
+            clock,
+            client: http_client(),
+        })
+    }
+
+    /// Builds the signed request that puts an object.
+    fn put_request(&self, key: &str, body: Vec<u8>, now: DateTime<Utc>) -> Result<Request<Body>> {
+        let path = format!(
+            "{}/{}/{}",
+            self.endpoint.path().trim_end_matches('/'),
+            uri_encode(&self.bucket.bucket, true),
+            uri_encode(key, false)
+        );
+        let payload_hash = hex::encode(digest::digest(&digest::SHA256, &body));
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let scope = format!("{date}/{}/s3/aws4_request", self.bucket.region);
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+
+        let canonical_request = format!(
+            "PUT\n{path}\n\nhost:{}\nx-amz-content-sha256:{payload_hash}\nx-amz-date:{amz_date}\n\n{signed_headers}\n{payload_hash}",
+            self.host
+        );
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+            hex::encode(digest::digest(
+                &digest::SHA256,
+                canonical_request.as_bytes()
+            ))
+        );
+        let key = signing_key(
+            self.bucket.secret_access_key.expose_secret(),
+            &date,
+            &self.bucket.region,
+            "s3",
+        );
+        let signature = hex::encode(hmac::sign(
+            &hmac::Key::new(hmac::HMAC_SHA256, key.as_ref()),
+            string_to_sign.as_bytes(),
+        ));
+
+        let scheme = self.endpoint.scheme_str().unwrap_or("https");
+        Request::builder()
+            .method(Method::PUT)
+            .uri(format!("{scheme}://{}{path}", self.host))
+            .header("host", self.host.as_str())
+            .header("content-type", "application/x-ndjson")
+            .header("x-amz-content-sha256", payload_hash)
+            .header("x-amz-date", amz_date)
+            .header(
+                "authorization",
+                format!(
+                    "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+                    self.bucket.access_key_id
+                ),
+            )
+            .body(Body::from(body))
+            .map_err(Error::from_err)
+    }
+}
+
+impl Debug for S3AuditSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("S3AuditSink")
+            .field("id", &self.id)
+            .field("bucket", &self.bucket)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl AuditSink for S3AuditSink {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn kind(&self) -> &'static str {
+        "s3"
+    }
+
+    async fn ship(&self, entries: &[AuditEntry]) -> Result<()> {
+        let (Some(first), Some(last)) = (entries.first(), entries.last()) else {
+            return Ok(());
+        };
+        let key = format!("{}{}-{}.jsonl", self.bucket.prefix, first.id, last.id);
+        let req = self.put_request(&key, json_lines(entries)?, self.clock.now())?;
+
+        match tokio::time::timeout(SHIP_TIMEOUT, self.client.request(req)).await {
+            Ok(Ok(res)) if res.status().is_success() => Ok(()),
+            Ok(Ok(res)) => Err(format!("S3 responded with {}", res.status()).into()),
+            Ok(Err(err)) => Err(Error::from_err(err)),
+            Err(_) => Err("S3 timed out".into()),
+        }
+    }
+}
+
+/// Derives the key that requests are signed with for a day, region and
+/// service.
+fn signing_key(secret: &str, date: &str, region: &str, service: &str) -> hmac::Tag {
+    let sign = |key: &[u8], data: &str| {
+        hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data.as_bytes())
+    };
+    let key = sign(format!("AWS4{secret}").as_bytes(), date);
+    let key = sign(key.as_ref(), region);
+    let key = sign(key.as_ref(), service);
+    sign(key.as_ref(), "aws4_request")
+}
+
+/// Percent-encodes everything but the characters that S3 leaves as they
+/// are, and slashes unless `encode_slash` is set.
+fn uri_encode(value: &str, encode_slash: bool) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte.into());
+            }
+            b'/' if !encode_slash => encoded.push('/'),
+            // Writing to a string can't fail.
+            _ => write!(encoded, "%{byte:02X}").unwrap_or_default(),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::TimeZone as _;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::provider::MockClock;
+
+    #[test]
+    fn test_signing_key() {
+        // The example from AWS's documentation on deriving signing keys.
+        let key = signing_key(
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "20120215",
+            "us-east-1",
+            "iam",
+        );
+        assert_eq!(
+            hex::encode(key.as_ref()),
+            "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d"
+        );
+    }
+
+    #[test]
+    fn test_uri_encode() {
+        assert_eq!(
+            uri_encode("audit/2023 01~x.jsonl", false),
+            "audit/2023%2001~x.jsonl"
+        );
+        assert_eq!(uri_encode("a/b", true), "a%2Fb");
+    }
+
+    #[test]
+    fn test_put_request() {
+        let now = Utc.with_ymd_and_hms(2023, 1, 2, 3, 4, 5).unwrap();
+        let sink = S3AuditSink::new(
+            "s3",
+            S3Bucket {
+                endpoint: "https://s3.example.com/".into(),
+                bucket: "logs".into(),
+                region: "eu-west-1".into(),
+                prefix: "audit/".into(),
+                access_key_id: "AKID".into(),
+                secret_access_key: "secret".to_owned().into(),
+            },
+            Arc::new(MockClock::new(now)),
+        )
+        .unwrap();
+
+        let req = sink.put_request("audit/1-2.jsonl", vec![], now).unwrap();
+        assert_eq!(req.method(), Method::PUT);
+        assert_eq!(
+            req.uri().to_string(),
+            "https://s3.example.com/logs/audit/1-2.jsonl"
+        );
+        assert_eq!(req.headers()["x-amz-date"], "20230102T030405Z");
+        let authorization = req.headers()["authorization"].to_str().unwrap();
+        assert!(authorization.starts_with(
+            "AWS4-HMAC-SHA256 Credential=AKID/20230102/eu-west-1/s3/aws4_request, \
+             SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature="
+        ));
+    }
+}
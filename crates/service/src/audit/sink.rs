@@ -0,0 +1,381 @@
+use std::{
+    fmt::{self, Debug},
+    io,
+    path::PathBuf,
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use chrono::SecondsFormat;
+use hyper::{Body, Method, Request};
+use secrecy::{ExposeSecret as _, SecretString};
+use tokio::{
+    fs::{self, OpenOptions},
+    io::AsyncWriteExt as _,
+    net::TcpStream,
+};
+
+use super::AuditEntry;
+use crate::{
+    http::{http_client, HttpClient},
+    prelude::*,
+};
+
+pub type SharedAuditSink = Arc<dyn AuditSink>;
+
+/// How long a sink has to take a batch before it's counted as failed.
+pub(super) const SHIP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The syslog priority that entries are sent with: the `log audit` facility
+/// (13) at `notice` severity (5).
+const SYSLOG_PRIORITY: u8 = 13 * 8 + 5;
+
+/// Somewhere outside the database that the audit trail is delivered to.
+#[async_trait]
+pub trait AuditSink: Debug + Send + Sync {
+    /// The name that the sink's checkpoint is kept under.
+    fn id(&self) -> &str;
+
+    /// What kind of sink it is, such as `file` or `s3`.
+    fn kind(&self) -> &'static str;
+
+    /// Delivers a batch of entries, oldest first. Entries that have been
+    /// delivered before can be delivered again if the checkpoint didn't
+    /// move past them.
+    async fn ship(&self, entries: &[AuditEntry]) -> Result<()>;
+}
+
+/// Appends entries to a file as JSON lines. Once the file would grow past
+/// `max_bytes` it's moved aside as `<path>.1`, with older files moving along
+/// to `<path>.2` and so on, and only `keep` of them are kept.
+#[derive(Debug, Clone)]
+pub struct FileAuditSink {
+    id: String,
+    path: PathBuf,
+    max_bytes: u64,
+    keep: usize,
+}
+
+impl FileAuditSink {
+    #[must_use]
+    pub fn new(
+        id: impl Into<String>,
+        path: impl Into<PathBuf>,
+        max_bytes: u64,
+        keep: usize,
+    ) -> Self {
+        Self {
+            id: id.into(),
+            path: path.into(),
+            max_bytes,
+            keep,
+        }
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{n}"));
+        path.into()
+    }
+
+    async fn rotate(&self) -> io::Result<()> {
+        if self.keep == 0 {
+            return fs::remove_file(&self.path).await;
+        }
+        ignore_missing(fs::remove_file(self.rotated(self.keep)).await)?;
+        for n in (1..self.keep).rev() {
+            ignore_missing(fs::rename(self.rotated(n), self.rotated(n + 1)).await)?;
+        }
+        fs::rename(&self.path, self.rotated(1)).await
+    }
+
+    async fn append(&self, lines: &[u8]) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).await?;
+        }
+        let len = match fs::metadata(&self.path).await {
+            Ok(metadata) => metadata.len(),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => 0,
+            Err(err) => return Err(err),
+        };
+        if len > 0 && len + lines.len() as u64 > self.max_bytes {
+            self.rotate().await?;
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        file.write_all(lines).await?;
+        // The checkpoint moves once this returns, so the entries have to be
+        // on disk by then.
+        file.sync_data().await
+    }
+}
+
+#[async_trait]
+impl AuditSink for FileAuditSink {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn kind(&self) -> &'static str {
+        "file"
+    }
+
+    async fn ship(&self, entries: &[AuditEntry]) -> Result<()> {
+        self.append(&json_lines(entries)?)
+            .await
+            .map_err(Error::from_err)
+    }
+}
+
+/// Sends entries to a syslog server over TCP, as RFC 5424 messages framed by
+/// their length as in RFC 6587. Each message's ID is the entry's kind, and
+/// its content is the entry as JSON.
+#[derive(Debug, Clone)]
+pub struct SyslogAuditSink {
+    id: String,
+    /// The `host:port` of the syslog server.
+    address: String,
+}
+
+impl SyslogAuditSink {
+    #[must_use]
+    pub fn new(id: impl Into<String>, address: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            address: address.into(),
+        }
+    }
+
+    async fn send(&self, frames: &[u8]) -> io::Result<()> {
+        let mut stream = TcpStream::connect(&self.address).await?;
+        stream.write_all(frames).await?;
+        stream.shutdown().await
+    }
+}
+
+#[async_trait]
+impl AuditSink for SyslogAuditSink {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn kind(&self) -> &'static str {
+        "syslog"
+    }
+
+    async fn ship(&self, entries: &[AuditEntry]) -> Result<()> {
+        let mut frames = vec![];
+        for entry in entries {
+            let message = syslog_message(entry)?;
+            frames.extend_from_slice(format!("{} {message}", message.len()).as_bytes());
+        }
+        match tokio::time::timeout(SHIP_TIMEOUT, self.send(&frames)).await {
+            Ok(res) => res.map_err(Error::from_err),
+            Err(_) => Err("syslog server timed out".into()),
+        }
+    }
+}
+
+/// Formats an entry as an RFC 5424 syslog message.
+fn syslog_message(entry: &AuditEntry) -> Result<String> {
+    let kind = serde_json::to_value(entry.kind).map_err(Error::from_err)?;
+    Ok(format!(
+        "<{SYSLOG_PRIORITY}>1 {} - plazer - {} - {}",
+        entry
+            .occurred_at
+            .to_rfc3339_opts(SecondsFormat::Micros, true),
+        kind.as_str().unwrap_or("-"),
+        serde_json::to_string(entry).map_err(Error::from_err)?,
+    ))
+}
+
+/// `POST`s each batch of entries to a URL as a JSON array, with a bearer
+/// token if it's given one. Any response other than a success counts as a
+/// failure.
+#[derive(Clone)]
+pub struct HttpAuditSink {
+    id: String,
+    url: String,
+    token: Option<SecretString>,
+    client: HttpClient,
+}
+
+impl HttpAuditSink {
+    #[must_use]
+    pub fn new(id: impl Into<String>, url: impl Into<String>, token: Option<SecretString>) -> Self {
+        Self {
+            id: id.into(),
+            url: url.into(),
+            token,
+            client: http_client(),
+        }
+    }
+}
+
+impl Debug for HttpAuditSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpAuditSink")
+            .field("id", &self.id)
+            .field("url", &self.url)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl AuditSink for HttpAuditSink {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn kind(&self) -> &'static str {
+        "http"
+    }
+
+    async fn ship(&self, entries: &[AuditEntry]) -> Result<()> {
+        let body = serde_json::to_vec(entries).map_err(Error::from_err)?;
+        let mut req = Request::builder()
+            .method(Method::POST)
+            .uri(&self.url)
+            .header("content-type", "application/json");
+        if let Some(token) = &self.token {
+            req = req.header("authorization", format!("Bearer {}", token.expose_secret()));
+        }
+        let req = req.body(Body::from(body)).map_err(Error::from_err)?;
+
+        match tokio::time::timeout(SHIP_TIMEOUT, self.client.request(req)).await {
+            Ok(Ok(res)) if res.status().is_success() => Ok(()),
+            Ok(Ok(res)) => Err(format!("audit endpoint responded with {}", res.status()).into()),
+            Ok(Err(err)) => Err(Error::from_err(err)),
+            Err(_) => Err("audit endpoint timed out".into()),
+        }
+    }
+}
+
+/// Keeps entries in memory instead of shipping them, so they can be checked
+/// later. It can be made to fail, to check that entries are sent again.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryAuditSink {
+    id: String,
+    entries: Arc<Mutex<Vec<AuditEntry>>>,
+    failing: Arc<Mutex<bool>>,
+}
+
+impl MemoryAuditSink {
+    #[must_use]
+    pub fn new(id: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Takes every entry shipped so far.
+    pub fn take(&self) -> Vec<AuditEntry> {
+        std::mem::take(&mut *self.entries.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Sets whether shipping fails.
+    pub fn set_failing(&self, failing: bool) {
+        *self.failing.lock().unwrap_or_else(PoisonError::into_inner) = failing;
+    }
+}
+
+#[async_trait]
+impl AuditSink for MemoryAuditSink {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn kind(&self) -> &'static str {
+        "memory"
+    }
+
+    async fn ship(&self, entries: &[AuditEntry]) -> Result<()> {
+        if *self.failing.lock().unwrap_or_else(PoisonError::into_inner) {
+            return Err("memory audit sink is failing".into());
+        }
+        self.entries
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .extend_from_slice(entries);
+        Ok(())
+    }
+}
+
+/// Serialises entries as JSON, one to a line.
+pub(super) fn json_lines(entries: &[AuditEntry]) -> Result<Vec<u8>> {
+    let mut lines = vec![];
+    for entry in entries {
+        serde_json::to_writer(&mut lines, entry).map_err(Error::from_err)?;
+        lines.push(b'\n');
+    }
+    Ok(lines)
+}
+
+fn ignore_missing(res: io::Result<()>) -> io::Result<()> {
+    match res {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        res => res,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone as _, Utc};
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::security::SecurityEventKind;
+
+    fn entry(id: &str) -> AuditEntry {
+        AuditEntry {
+            id: id.into(),
+            kind: SecurityEventKind::SignedIn,
+            account_id: "alice".into(),
+            session_id: None,
+            occurred_at: Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap(),
+            region: None,
+            detail: None,
+        }
+    }
+
+    #[test]
+    fn test_syslog_message() {
+        assert_eq!(
+            syslog_message(&entry("1")).unwrap(),
+            "<109>1 2023-01-01T00:00:00.000000Z - plazer - signed_in - \
+             {\"id\":\"1\",\"kind\":\"signed_in\",\"account_id\":\"alice\",\
+             \"session_id\":null,\"occurred_at\":\"2023-01-01T00:00:00Z\",\"region\":null}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_file_rotation() {
+        let dir = std::env::temp_dir().join(format!("plazer-audit-{}", ulid::Ulid::new()));
+        let path = dir.join("audit.log");
+        let line_len = json_lines(&[entry("1")]).unwrap().len() as u64;
+        let sink = FileAuditSink::new("file", &path, line_len * 2, 2);
+
+        for id in ["1", "2", "3", "4", "5", "6", "7"] {
+            sink.ship(&[entry(id)]).await.unwrap();
+        }
+        let ids = |path: PathBuf| async move {
+            let content = fs::read_to_string(path).await.unwrap();
+            content
+                .lines()
+                .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["id"].clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(path.clone()).await, ["7"]);
+        assert_eq!(ids(sink.rotated(1)).await, ["5", "6"]);
+        assert_eq!(ids(sink.rotated(2)).await, ["3", "4"]);
+        assert!(!sink.rotated(3).exists());
+
+        fs::remove_dir_all(dir).await.unwrap();
+    }
+}
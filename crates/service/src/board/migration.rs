@@ -1,12 +1,13 @@
 use serde::{Deserialize, Serialize};
 
-use super::BOARD_TABLE_NAME;
+use super::{BOARD_MEMBER_TABLE_NAME, BOARD_ROLE_TABLE_NAME, BOARD_TABLE_NAME};
 use crate::{migration::Migration, prelude::*};
 
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum BoardMigration {
     #[default]
     Init,
+    Roles,
 }
 
 impl Migration for BoardMigration {
@@ -14,7 +15,8 @@ impl Migration for BoardMigration {
 
     fn next(self) -> Option<Self> {
         match self {
-            Self::Init => None,
+            Self::Init => Some(Self::Roles),
+            Self::Roles => None,
         }
     }
 
@@ -22,6 +24,7 @@ impl Migration for BoardMigration {
         use BoardMigration as S;
         match self {
             S::Init => Self::build_init(statements),
+            S::Roles => Self::build_roles(statements),
         }
     }
 }
@@ -34,4 +37,17 @@ impl BoardMigration {
             [srql::field("handle")],
         ));
     }
+
+    fn build_roles(statements: &mut Vec<srql::Statement>) {
+        statements.push(srql::define_uniq_index(
+            "board_role_board_name_index",
+            BOARD_ROLE_TABLE_NAME,
+            [srql::field("board_id"), srql::field("name")],
+        ));
+        statements.push(srql::define_uniq_index(
+            "board_member_board_account_index",
+            BOARD_MEMBER_TABLE_NAME,
+            [srql::field("board_id"), srql::field("account_id")],
+        ));
+    }
 }
@@ -1,11 +1,15 @@
 mod migration;
 mod models;
 mod persist;
+mod role;
 mod schema;
 
 pub use migration::*;
 pub use models::*;
 pub use persist::*;
+pub use role::*;
 pub use schema::*;
 
 pub static BOARD_TABLE_NAME: &str = "board";
+pub static BOARD_ROLE_TABLE_NAME: &str = "board_role";
+pub static BOARD_MEMBER_TABLE_NAME: &str = "board_member";
@@ -1,10 +1,13 @@
-use async_graphql::{ComplexObject, InputObject, MaybeUndefined, SimpleObject, ID};
+use async_graphql::{ComplexObject, Context, InputObject, MaybeUndefined, SimpleObject, ID};
 use chrono::{DateTime, Utc};
 use serde::Deserialize;
 use surrealdb::sql::Thing;
 
-use super::BOARD_TABLE_NAME;
-use crate::{id_obj_impls, prelude::*, query::OpaqueCursor};
+use super::{BoardPermission, BoardRole, BOARD_TABLE_NAME};
+use crate::{
+    config::LimitsConfig, event::post_count, id_obj_impls, persist::Persist, prelude::*,
+    query::OpaqueCursor,
+};
 
 pub type BoardCursor = OpaqueCursor<String>;
 
@@ -21,9 +24,16 @@ pub struct Board {
     /// and by users. It must be unique, but can be changed (if the server allows it).
     pub handle: String,
     /// The board's display name. If not present, the handle is (usually) used instead.
+    ///
+    /// This can be at most `instanceInfo.limits.boardName` characters long.
     pub name: Option<String>,
-    /// The board's description.
+    /// The board's description. This can be at most
+    /// `instanceInfo.limits.boardDescription` characters long.
     pub description: Option<String>,
+    /// Whether the board is only available to adults. Age-restricted boards
+    /// and their posts are hidden from everyone else.
+    #[serde(default)]
+    pub age_restricted: bool,
 
     /// A timestamp indicating the last time the board was updated.
     pub updated_at: DateTime<Utc>,
@@ -44,16 +54,38 @@ impl Board {
     async fn creator_id(&self) -> Option<ID> {
         self.creator_id.as_ref().map(ToGqlId::to_gql_id)
     }
+
+    /// How many posts are in the board. This is built from the event log, so
+    /// can take a few seconds to catch up with changes.
+    async fn post_count(&self, ctx: &Context<'_>) -> GqlResult<u64> {
+        post_count(ctx.data_unchecked::<Persist>(), &self.id)
+            .await
+            .extend()
+    }
+
+    /// The custom roles that the board gives its members.
+    async fn roles(&self, ctx: &Context<'_>) -> GqlResult<Vec<BoardRole>> {
+        ctx.board_persist().roles(self).await.extend()
+    }
+
+    /// What the current account can do in the board.
+    async fn permissions(&self, ctx: &Context<'_>) -> GqlResult<Vec<BoardPermission>> {
+        ctx.board_persist().current_permissions(self).await.extend()
+    }
 }
 
 id_obj_impls!(Board);
 
 impl Board {
-    pub fn create(creator_id: Option<Thing>, params: CreateBoard) -> srql::CreateStatement {
+    pub fn create(
+        creator_id: Option<Thing>,
+        params: CreateBoard,
+        ids: &dyn IdGen,
+    ) -> srql::CreateStatement {
         let mut create = vec![];
         creator_id.push_field(srql::field("creator_id"), &mut create);
         params.append(&mut create);
-        srql::obj_create_query(BOARD_TABLE_NAME, create)
+        srql::obj_create_query(BOARD_TABLE_NAME, create, ids)
     }
 }
 
@@ -66,11 +98,22 @@ pub struct CreateBoard {
     #[graphql(validator(max_length = 128))]
     pub handle: Option<String>,
     /// The board's display name. If not present, the handle is (usually) used instead.
-    #[graphql(validator(max_length = 1024))]
     pub name: Option<String>,
     /// The board's description.
-    #[graphql(validator(max_length = 32_768))]
     pub description: Option<String>,
+    /// Whether the board is only available to adults. Defaults to `false`.
+    pub age_restricted: Option<bool>,
+}
+
+impl CreateBoard {
+    pub fn check_limits(&self, limits: &LimitsConfig) -> Result<()> {
+        LimitsConfig::check("name", self.name.as_deref(), limits.board_name)?;
+        LimitsConfig::check(
+            "description",
+            self.description.as_deref(),
+            limits.board_description,
+        )
+    }
 }
 
 impl CreateObject for CreateBoard {
@@ -79,6 +122,9 @@ impl CreateObject for CreateBoard {
         self.name.push_field(srql::field("name"), expr);
         self.description
             .push_field(srql::field("description"), expr);
+        self.age_restricted
+            .unwrap_or_default()
+            .push_field(srql::field("age_restricted"), expr);
     }
 }
 
@@ -89,12 +135,28 @@ pub struct UpdateBoard {
     pub handle: Option<String>,
     /// The new name. If not given, the name is not changed. If null is given,
     /// the name is cleared.
-    #[graphql(validator(max_length = 1024))]
     pub name: MaybeUndefined<String>,
     /// The new description. If not given, the description is not changed. If
     /// null is given, the description is cleared.
-    #[graphql(validator(max_length = 32_768))]
     pub description: MaybeUndefined<String>,
+    /// Whether the board is only available to adults. If not given, this is
+    /// not changed.
+    pub age_restricted: Option<bool>,
+}
+
+impl UpdateBoard {
+    pub fn check_limits(&self, limits: &LimitsConfig) -> Result<()> {
+        LimitsConfig::check(
+            "name",
+            self.name.as_opt_deref().flatten(),
+            limits.board_name,
+        )?;
+        LimitsConfig::check(
+            "description",
+            self.description.as_opt_deref().flatten(),
+            limits.board_description,
+        )
+    }
 }
 
 impl IntoUpdateQuery for UpdateBoard {
@@ -104,6 +166,8 @@ impl IntoUpdateQuery for UpdateBoard {
         self.name.push_field(srql::field("name"), &mut update);
         self.description
             .push_field(srql::field("description"), &mut update);
+        self.age_restricted
+            .push_field(srql::field("age_restricted"), &mut update);
         srql::obj_update_query(thing, update)
     }
 }
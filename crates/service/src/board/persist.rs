@@ -4,12 +4,17 @@ mod tests;
 use async_graphql::connection::{Connection, Edge};
 use tracing::instrument;
 
-use super::{Board, BoardCursor, CreateBoard, UpdateBoard, BOARD_TABLE_NAME};
+use super::{
+    board_permissions, member_role, require_board_permission, Board, BoardCursor, BoardMember,
+    BoardPermission, BoardRole, CreateBoard, CreateBoardRole, UpdateBoard, UpdateBoardRole,
+    BOARD_MEMBER_TABLE_NAME, BOARD_ROLE_TABLE_NAME, BOARD_TABLE_NAME,
+};
 use crate::{
-    account::CurrentAccount,
+    account::{is_adult, Account, CurrentAccount, ACC_TABLE_NAME},
     persist::Persist,
     prelude::*,
     query::{OpaqueCursor, PaginationInput, PaginationOptions, ResultSlice},
+    quota::{QuotaPersist, QuotaResource},
 };
 
 pub struct BoardPersist<'a> {
@@ -24,7 +29,11 @@ impl<'a> BoardPersist<'a> {
 
     #[instrument(skip_all)]
     pub async fn get(&self, id: &str) -> Result<Option<Board>> {
-        Ok(self.persist.db().select((BOARD_TABLE_NAME, id)).await?)
+        let board = self
+            .persist
+            .load(srql::Thing::from((BOARD_TABLE_NAME, id)))
+            .await?;
+        self.visible(board).await
     }
 
     #[instrument(skip_all)]
@@ -48,12 +57,39 @@ impl<'a> BoardPersist<'a> {
             })
             .await?
             .take(0)?;
-        Ok(board)
+        self.visible(board).await
+    }
+
+    /// Hides age-restricted boards from anyone who isn't an adult.
+    async fn visible(&self, board: Option<Board>) -> Result<Option<Board>> {
+        match board {
+            Some(board)
+                if board.age_restricted
+                    && !is_adult(self.persist, self.viewer().as_ref()).await? =>
+            {
+                Ok(None)
+            }
+            board => Ok(board),
+        }
+    }
+
+    /// Only adults can mark boards as age-restricted, otherwise they'd lose
+    /// access to them.
+    async fn require_adult(&self) -> Result<()> {
+        if is_adult(self.persist, self.viewer().as_ref()).await? {
+            Ok(())
+        } else {
+            Err(Error::AgeRestricted)
+        }
+    }
+
+    fn viewer(&self) -> Option<srql::Thing> {
+        self.current.id().ok().map(ToAccountThing::to_account_thing)
     }
 
     #[instrument(skip_all)]
     pub fn list(&self) -> BoardListRequest<'_> {
-        BoardListRequest::new(self.persist)
+        BoardListRequest::new(self.persist, self.viewer())
     }
 
     #[instrument(skip_all)]
@@ -61,6 +97,16 @@ impl<'a> BoardPersist<'a> {
         // TODO: check config to see if anon users can create boards
         // TODO: check perms to see if authd user can create boards
 
+        board.check_limits(self.persist.limits())?;
+        if board.age_restricted == Some(true) {
+            self.require_adult().await?;
+        }
+        if let Some(creator_id) = self.viewer() {
+            QuotaPersist::new(self.persist, self.current)
+                .require_available(&creator_id, &[(QuotaResource::Boards, 1)])
+                .await?;
+        }
+
         if board.handle.is_none() {
             board.handle = self
                 .current
@@ -76,6 +122,7 @@ impl<'a> BoardPersist<'a> {
             .query(Board::create(
                 self.current.id().map(ToAccountThing::to_account_thing).ok(),
                 board,
+                self.persist.ids(),
             ))
             .await?
             .take(0)?;
@@ -91,6 +138,11 @@ impl<'a> BoardPersist<'a> {
         // TODO: check config to see if anon users can update boards
         // TODO: check perms to see if authd user can update boards
 
+        update.check_limits(self.persist.limits())?;
+        if update.age_restricted == Some(true) {
+            self.require_adult().await?;
+        }
+
         let board = if let Some(update) = update.into_update((BOARD_TABLE_NAME, id).into()) {
             self.persist.db().query(update).await?.take(0)?
         } else {
@@ -105,20 +157,288 @@ impl<'a> BoardPersist<'a> {
         // TODO: check config to see if anon users can delete boards
         // TODO: check perms to see if authd user can delete boards
 
-        let board = self.persist.db().delete((BOARD_TABLE_NAME, id)).await?;
+        let board: Option<Board> = self.persist.db().delete((BOARD_TABLE_NAME, id)).await?;
+        if let Some(board) = &board {
+            let mut query = vec![srql::trans_begin()];
+            for table in [BOARD_ROLE_TABLE_NAME, BOARD_MEMBER_TABLE_NAME] {
+                query.push(srql::Statement::Delete(srql::DeleteStatement {
+                    what: srql::table(table),
+                    cond: board_cond(&board.id).into(),
+                    output: srql::Output::None.into(),
+                    ..Default::default()
+                }));
+            }
+            query.push(srql::trans_end());
+            self.persist.db().query(query).await?.check()?;
+        }
         Ok(board)
     }
+
+    /// What the current account can do in the board.
+    #[instrument(skip_all)]
+    pub async fn current_permissions(&self, board: &Board) -> Result<Vec<BoardPermission>> {
+        board_permissions(self.persist, board, self.current).await
+    }
+
+    /// Lists the board's custom roles.
+    #[instrument(skip_all)]
+    pub async fn roles(&self, board: &Board) -> Result<Vec<BoardRole>> {
+        let roles = self
+            .persist
+            .db()
+            .query(srql::SelectStatement {
+                expr: srql::Fields::all(),
+                what: srql::table(BOARD_ROLE_TABLE_NAME),
+                cond: board_cond(&board.id).into(),
+                order: srql::Orders(vec![srql::Order {
+                    order: srql::field("name"),
+                    direction: true,
+                    ..Default::default()
+                }])
+                .into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        Ok(roles)
+    }
+
+    /// Creates a custom role in the board. Only accounts that can moderate
+    /// the board can do this, and they can't create roles that allow more
+    /// than they can do themselves.
+    #[instrument(skip_all)]
+    pub async fn create_role(&self, board_id: &str, create: CreateBoardRole) -> Result<BoardRole> {
+        let board = require_board_permission(
+            self.persist,
+            self.current,
+            &srql::Thing::from((BOARD_TABLE_NAME, board_id)),
+            BoardPermission::Moderate,
+        )
+        .await?;
+        self.require_grantable(&board, &create.permissions).await?;
+
+        let role: Option<BoardRole> = self
+            .persist
+            .db()
+            .query(BoardRole::create(board.id, create, self.persist.ids()))
+            .await?
+            .take(0)?;
+        role.ok_or(Error::UnavailableIdent)
+    }
+
+    /// Changes a board's custom role. Only accounts that can moderate the
+    /// board can do this, and they can't make roles allow more than they can
+    /// do themselves.
+    #[instrument(skip_all)]
+    pub async fn update_role(
+        &self,
+        id: &str,
+        update: UpdateBoardRole,
+    ) -> Result<Option<BoardRole>> {
+        let Some(role) = self.get_role(id).await? else {
+            return Ok(None);
+        };
+        let board = require_board_permission(
+            self.persist,
+            self.current,
+            &role.board_id,
+            BoardPermission::Moderate,
+        )
+        .await?;
+        if let Some(permissions) = &update.permissions {
+            self.require_grantable(&board, permissions).await?;
+        }
+
+        match update.into_update(role.id.clone()) {
+            Some(update) => Ok(self.persist.db().query(update).await?.take(0)?),
+            None => Ok(Some(role)),
+        }
+    }
+
+    /// Deletes a board's custom role. The accounts that had it go back to
+    /// what members can do. Only accounts that can moderate the board can do
+    /// this.
+    #[instrument(skip_all)]
+    pub async fn delete_role(&self, id: &str) -> Result<Option<BoardRole>> {
+        let Some(role) = self.get_role(id).await? else {
+            return Ok(None);
+        };
+        require_board_permission(
+            self.persist,
+            self.current,
+            &role.board_id,
+            BoardPermission::Moderate,
+        )
+        .await?;
+
+        self.persist
+            .db()
+            .query(srql::query([
+                srql::trans_begin(),
+                srql::Statement::Delete(srql::DeleteStatement {
+                    what: srql::table(BOARD_MEMBER_TABLE_NAME),
+                    cond: srql::Cond(
+                        srql::Expression::Binary {
+                            l: srql::field("role_id").into(),
+                            o: srql::Operator::Equal,
+                            r: role.id.clone().into(),
+                        }
+                        .into(),
+                    )
+                    .into(),
+                    output: srql::Output::None.into(),
+                    ..Default::default()
+                }),
+                srql::Statement::Delete(srql::DeleteStatement {
+                    what: srql::thing(role.id.clone()),
+                    output: srql::Output::None.into(),
+                    ..Default::default()
+                }),
+                srql::trans_end(),
+            ]))
+            .await?
+            .check()?;
+        Ok(Some(role))
+    }
+
+    /// Gives an account one of a board's custom roles, replacing any role it
+    /// had in the board. Only accounts that can invite to the board can do
+    /// this, and only with roles that don't allow more than they can do.
+    #[instrument(skip_all)]
+    pub async fn assign_role(&self, role_id: &str, account_id: &str) -> Result<BoardMember> {
+        let Some(role) = self.get_role(role_id).await? else {
+            return Err(Error::NotFound);
+        };
+        let board = require_board_permission(
+            self.persist,
+            self.current,
+            &role.board_id,
+            BoardPermission::Invite,
+        )
+        .await?;
+        self.require_grantable(&board, &role.permissions).await?;
+        let account: Option<Account> = self
+            .persist
+            .load(srql::Thing::from((ACC_TABLE_NAME, account_id)))
+            .await?;
+        let account = account.ok_or(Error::NotFound)?;
+
+        self.remove_member(&board.id, &account.id).await?;
+        let member: Option<BoardMember> = self
+            .persist
+            .db()
+            .query(BoardMember::create(
+                board.id,
+                account.id,
+                role.id,
+                self.persist.ids(),
+            ))
+            .await?
+            .take(0)?;
+        member.ok_or(Error::UnavailableIdent)
+    }
+
+    /// Takes an account's custom role in a board away, so that it goes back
+    /// to what members can do. Only accounts that can invite to the board can
+    /// do this, and only for roles that don't allow more than they can do.
+    #[instrument(skip_all)]
+    pub async fn unassign_role(
+        &self,
+        board_id: &str,
+        account_id: &str,
+    ) -> Result<Option<BoardMember>> {
+        let board = require_board_permission(
+            self.persist,
+            self.current,
+            &srql::Thing::from((BOARD_TABLE_NAME, board_id)),
+            BoardPermission::Invite,
+        )
+        .await?;
+        let account_id = account_id.to_account_thing();
+        if let Some(role) = member_role(self.persist, &board.id, &account_id).await? {
+            self.require_grantable(&board, &role.permissions).await?;
+        }
+        self.remove_member(&board.id, &account_id).await
+    }
+
+    async fn get_role(&self, id: &str) -> Result<Option<BoardRole>> {
+        Ok(self
+            .persist
+            .load(srql::Thing::from((BOARD_ROLE_TABLE_NAME, id)))
+            .await?)
+    }
+
+    /// Accounts can't give out more than they're allowed to do in a board,
+    /// otherwise they could give it to themselves.
+    async fn require_grantable(
+        &self,
+        board: &Board,
+        permissions: &[BoardPermission],
+    ) -> Result<()> {
+        let allowed = self.current_permissions(board).await?;
+        if permissions
+            .iter()
+            .all(|permission| allowed.contains(permission))
+        {
+            Ok(())
+        } else {
+            Err(Error::Unauthorized)
+        }
+    }
+
+    async fn remove_member(
+        &self,
+        board_id: &srql::Thing,
+        account_id: &srql::Thing,
+    ) -> Result<Option<BoardMember>> {
+        let member = self
+            .persist
+            .db()
+            .query(srql::DeleteStatement {
+                what: srql::table(BOARD_MEMBER_TABLE_NAME),
+                cond: srql::cond_and(
+                    board_cond(board_id).into(),
+                    srql::Cond(
+                        srql::Expression::Binary {
+                            l: srql::field("account_id").into(),
+                            o: srql::Operator::Equal,
+                            r: account_id.clone().into(),
+                        }
+                        .into(),
+                    )
+                    .into(),
+                ),
+                output: srql::Output::Before.into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        Ok(member)
+    }
+}
+
+fn board_cond(board_id: &srql::Thing) -> srql::Cond {
+    srql::Cond(
+        srql::Expression::Binary {
+            l: srql::field("board_id").into(),
+            o: srql::Operator::Equal,
+            r: board_id.clone().into(),
+        }
+        .into(),
+    )
 }
 
 pub struct BoardListRequest<'a> {
     persist: &'a Persist,
+    viewer: Option<srql::Thing>,
     pagination: Option<PaginationInput<OpaqueCursor<String>>>,
 }
 
 impl<'a> BoardListRequest<'a> {
-    fn new(persist: &'a Persist) -> Self {
+    fn new(persist: &'a Persist, viewer: Option<srql::Thing>) -> Self {
         Self {
             persist,
+            viewer,
             pagination: None,
         }
     }
@@ -140,11 +460,22 @@ impl<'a> BoardListRequest<'a> {
             result_slice_opts,
         } = (self.pagination, BOARD_TABLE_NAME).into();
 
+        let restricted_cond = (!is_adult(self.persist, self.viewer.as_ref()).await?).then(|| {
+            srql::Cond(
+                srql::Expression::Binary {
+                    l: srql::field("age_restricted").into(),
+                    o: srql::Operator::NotEqual,
+                    r: true.into(),
+                }
+                .into(),
+            )
+        });
+
         let query = srql::SelectStatement {
             expr: srql::Fields::all(),
             what: srql::table(BOARD_TABLE_NAME),
             order: srql::Orders(order.into_iter().collect()).into(),
-            cond,
+            cond: srql::cond_and(cond, restricted_cond),
             limit,
             ..Default::default()
         };
@@ -188,6 +519,7 @@ pub mod testing {
                     handle: Some("test".into()),
                     name: Some("Test".into()),
                     description: Some("Test".into()),
+                    age_restricted: None,
                 })
                 .await
                 .unwrap()
@@ -201,6 +533,7 @@ pub mod testing {
                     handle: Some(format!("test-{i}")),
                     name: Some(format!("Test {i}")),
                     description: Some(format!("Test {i}")),
+                    age_restricted: None,
                 };
 
                 let res = board_persist.create(board).await;
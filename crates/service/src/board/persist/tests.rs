@@ -3,7 +3,10 @@ use pretty_assertions::assert_eq;
 use std::collections::VecDeque;
 
 use super::{testing::BoardTestData as _, *};
-use crate::{account::testing::*, query::testing::Paginator};
+use crate::{
+    account::{testing::*, CurrentAccount},
+    query::testing::Paginator,
+};
 
 #[tokio::test]
 async fn test_create() {
@@ -14,6 +17,7 @@ async fn test_create() {
         handle: Some("test".into()),
         name: Some("Test".into()),
         description: Some("Test".into()),
+        age_restricted: None,
     };
 
     let res = board_persist.create(board).await;
@@ -81,6 +85,7 @@ async fn test_duplicate_handle() {
         handle: Some(board.handle),
         name: Some("Test".into()),
         description: Some("Test".into()),
+        age_restricted: None,
     };
 
     let res = board_persist.create(create).await;
@@ -101,6 +106,7 @@ async fn test_anon_create_default_fail() {
         handle: None,
         name: Some("Test".into()),
         description: Some("Test".into()),
+        age_restricted: None,
     };
 
     let res = board_persist.create(board).await;
@@ -121,6 +127,7 @@ async fn test_authed_create_default() {
         handle: None,
         name: Some("Test".into()),
         description: Some("Test".into()),
+        age_restricted: None,
     };
 
     let res = board_persist.create(board).await;
@@ -376,6 +383,7 @@ async fn test_update_all() {
         handle: Some("test".into()),
         name: MaybeUndefined::Value("Test".into()),
         description: MaybeUndefined::Value("Test".into()),
+        age_restricted: None,
     };
 
     let res = board_persist.update(&board.id.id.to_raw(), update).await;
@@ -405,6 +413,7 @@ async fn test_update_nonexistent() {
         handle: Some("test".into()),
         name: MaybeUndefined::Value("Test".into()),
         description: MaybeUndefined::Value("Test".into()),
+        age_restricted: None,
     };
 
     let res = board_persist.update("test", update).await;
@@ -472,3 +481,117 @@ async fn test_delete_nonexistent() {
     let res = res.unwrap();
     assert!(res.is_none());
 }
+
+#[tokio::test]
+async fn test_age_restricted() {
+    let (mut data, adult) = TestData::with_user().await;
+    let create = CreateBoard {
+        handle: Some("restricted".into()),
+        age_restricted: Some(true),
+        ..Default::default()
+    };
+    let board = data.board().create(create.clone()).await.unwrap();
+    assert!(board.age_restricted);
+    let id = board.id.to_gql_id();
+
+    let minor = data.account().create_test_user_born(None).await;
+    data.login_as(&minor);
+    let minor = data.current.clone();
+
+    for current in [CurrentAccount::default(), minor] {
+        data.current = current;
+        assert_eq!(data.board().get(&id).await.unwrap(), None);
+        assert_eq!(
+            data.board().get_by_handle("restricted").await.unwrap(),
+            None
+        );
+        let res = data.board().list().execute().await.unwrap();
+        assert!(res.edges.is_empty());
+    }
+
+    // Boards can't be restricted by anyone who'd lose access to them.
+    let res = data.board().create(create).await;
+    assert_eq!(res.unwrap_err(), Error::AgeRestricted);
+
+    data.login_as(&adult);
+    assert_eq!(data.board().get(&id).await.unwrap(), Some(board));
+}
+
+#[tokio::test]
+async fn test_roles() {
+    let mut data = TestData::new().await;
+    // The first account is an admin, which can do anything in any board.
+    data.account().create_test_user().await;
+    let owner = data.account().create_test_user().await;
+    let member = data.account().create_test_user().await;
+    let other = data.account().create_test_user().await;
+    data.login_as(&owner);
+    let board = data.generate_board().await;
+    let board_id = board.id.id.to_raw();
+
+    let res = data.board().current_permissions(&board).await.unwrap();
+    assert_eq!(res, BoardPermission::ALL.to_vec());
+    let inviter = data
+        .board()
+        .create_role(
+            &board_id,
+            CreateBoardRole {
+                name: "Inviter".into(),
+                permissions: vec![BoardPermission::Comment, BoardPermission::Invite],
+            },
+        )
+        .await
+        .unwrap();
+    let res = data
+        .board()
+        .create_role(
+            &board_id,
+            CreateBoardRole {
+                name: "Inviter".into(),
+                permissions: vec![],
+            },
+        )
+        .await;
+    assert_eq!(res.unwrap_err(), Error::UnavailableIdent);
+    let res = data
+        .board()
+        .assign_role(&inviter.id.id.to_raw(), &member.id.id.to_raw())
+        .await
+        .unwrap();
+    assert_eq!(res.role_id, inviter.id);
+
+    // Members with a custom role can do only what it allows, and can't give
+    // out more than that.
+    data.login_as(&member);
+    let res = data.board().current_permissions(&board).await.unwrap();
+    assert_eq!(res, vec![BoardPermission::Comment, BoardPermission::Invite]);
+    let res = data
+        .board()
+        .create_role(
+            &board_id,
+            CreateBoardRole {
+                name: "Moderator".into(),
+                permissions: vec![BoardPermission::Moderate],
+            },
+        )
+        .await;
+    assert_eq!(res.unwrap_err(), Error::Unauthorized);
+    let res = data
+        .board()
+        .assign_role(&inviter.id.id.to_raw(), &other.id.id.to_raw())
+        .await;
+    assert!(res.is_ok());
+
+    data.login_as(&other);
+    let res = data.board().current_permissions(&board).await.unwrap();
+    assert_eq!(res, vec![BoardPermission::Comment, BoardPermission::Invite]);
+
+    // Deleting the role puts everyone who had it back to what members can do.
+    data.login_as(&owner);
+    let res = data.board().delete_role(&inviter.id.id.to_raw()).await;
+    assert!(res.unwrap().is_some());
+    data.login_as(&member);
+    let res = data.board().current_permissions(&board).await.unwrap();
+    assert_eq!(res, BoardPermission::MEMBER.to_vec());
+    assert!(data.board().roles(&board).await.unwrap().is_empty());
+}
@@ -0,0 +1,313 @@
+//! Roles that boards give their members, and what each allows in the board.
+//!
+//! A board's creator can do anything in it, as can instance moderators and
+//! admins. Everyone else can post, comment and upload, unless they've been
+//! given one of the board's custom roles, whose permissions replace those.
+//! Resolvers declare what they need with [`BoardPermissionGuard`], and
+//! persistence checks it again with [`require_board_permission`], the same
+//! as instance roles.
+
+use async_graphql::{ComplexObject, Context, Enum, Guard, InputObject, SimpleObject, ID};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use surrealdb::sql::Thing;
+
+use super::{Board, BOARD_MEMBER_TABLE_NAME, BOARD_ROLE_TABLE_NAME, BOARD_TABLE_NAME};
+use crate::{
+    account::{AuthContext, CurrentAccount, Permission},
+    id_obj_impls,
+    persist::Persist,
+    prelude::*,
+};
+
+/// Something that a board's roles can allow in it.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BoardPermission {
+    /// Create posts in the board.
+    Post,
+    /// Reply to posts in the board.
+    Comment,
+    /// Upload media to the board.
+    Upload,
+    /// Pin posts in the board.
+    Pin,
+    /// Give the board's roles to accounts, and take them away.
+    Invite,
+    /// Create, change and delete the board's roles.
+    Moderate,
+}
+
+impl BoardPermission {
+    pub const ALL: [Self; 6] = [
+        Self::Post,
+        Self::Comment,
+        Self::Upload,
+        Self::Pin,
+        Self::Invite,
+        Self::Moderate,
+    ];
+
+    /// What accounts without a custom role can do in a board.
+    pub const MEMBER: [Self; 3] = [Self::Post, Self::Comment, Self::Upload];
+}
+
+impl QueryValue for Vec<BoardPermission> {
+    fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
+        srql::to_value(self)
+            .ok()
+            .map(|value| (field, srql::Operator::Equal, value))
+    }
+}
+
+/// A role that a board has defined, and what it allows.
+#[derive(SimpleObject, Debug, Clone, Deserialize)]
+#[graphql(complex)]
+pub struct BoardRole {
+    #[graphql(skip)]
+    pub id: Thing,
+    #[graphql(skip)]
+    pub board_id: Thing,
+
+    /// The role's name, which is unique within the board.
+    pub name: String,
+    /// What accounts with the role can do in the board.
+    #[serde(default)]
+    pub permissions: Vec<BoardPermission>,
+
+    /// A timestamp indicating the last time the role was updated.
+    pub updated_at: DateTime<Utc>,
+}
+
+#[ComplexObject]
+impl BoardRole {
+    /// The role's unique ID.
+    async fn id(&self) -> ID {
+        self.id.to_gql_id()
+    }
+
+    /// The ID of the board that the role belongs to.
+    async fn board_id(&self) -> ID {
+        self.board_id.to_gql_id()
+    }
+}
+
+id_obj_impls!(BoardRole);
+
+impl BoardRole {
+    pub fn create(
+        board_id: Thing,
+        params: CreateBoardRole,
+        ids: &dyn IdGen,
+    ) -> srql::CreateStatement {
+        let mut create = vec![];
+        board_id.push_field(srql::field("board_id"), &mut create);
+        params.append(&mut create);
+        srql::obj_create_query(BOARD_ROLE_TABLE_NAME, create, ids)
+    }
+}
+
+#[derive(InputObject, Debug, Default, Clone, PartialEq, Eq)]
+pub struct CreateBoardRole {
+    /// The role's name, which must be unique within the board.
+    #[graphql(validator(min_length = 1, max_length = 64))]
+    pub name: String,
+    /// What accounts with the role can do in the board.
+    pub permissions: Vec<BoardPermission>,
+}
+
+impl CreateObject for CreateBoardRole {
+    fn append(self, expr: &mut srql::SetExpr) {
+        self.name.push_field(srql::field("name"), expr);
+        self.permissions
+            .push_field(srql::field("permissions"), expr);
+    }
+}
+
+#[derive(InputObject, Debug, Default, Clone, PartialEq, Eq)]
+pub struct UpdateBoardRole {
+    /// The new name. If not given, the name is not changed.
+    #[graphql(validator(min_length = 1, max_length = 64))]
+    pub name: Option<String>,
+    /// What accounts with the role can do. If not given, this is not changed.
+    pub permissions: Option<Vec<BoardPermission>>,
+}
+
+impl IntoUpdateQuery for UpdateBoardRole {
+    fn into_update(self, thing: srql::Thing) -> Option<srql::UpdateStatement> {
+        let mut update = vec![];
+        self.name.push_field(srql::field("name"), &mut update);
+        self.permissions
+            .push_field(srql::field("permissions"), &mut update);
+        srql::obj_update_query(thing, update)
+    }
+}
+
+/// An account that has been given one of a board's roles.
+#[derive(SimpleObject, Debug, Clone, Deserialize)]
+#[graphql(complex)]
+pub struct BoardMember {
+    #[graphql(skip)]
+    pub id: Thing,
+    #[graphql(skip)]
+    pub board_id: Thing,
+    #[graphql(skip)]
+    pub account_id: Thing,
+    #[graphql(skip)]
+    pub role_id: Thing,
+
+    /// A timestamp indicating the last time the member's role was changed.
+    pub updated_at: DateTime<Utc>,
+}
+
+#[ComplexObject]
+impl BoardMember {
+    /// The membership's unique ID.
+    async fn id(&self) -> ID {
+        self.id.to_gql_id()
+    }
+
+    /// The ID of the board.
+    async fn board_id(&self) -> ID {
+        self.board_id.to_gql_id()
+    }
+
+    /// The ID of the account that has the role.
+    async fn account_id(&self) -> ID {
+        self.account_id.to_gql_id()
+    }
+
+    /// The ID of the role.
+    async fn role_id(&self) -> ID {
+        self.role_id.to_gql_id()
+    }
+}
+
+id_obj_impls!(BoardMember);
+
+impl BoardMember {
+    pub fn create(
+        board_id: Thing,
+        account_id: Thing,
+        role_id: Thing,
+        ids: &dyn IdGen,
+    ) -> srql::CreateStatement {
+        let mut create = vec![];
+        board_id.push_field(srql::field("board_id"), &mut create);
+        account_id.push_field(srql::field("account_id"), &mut create);
+        role_id.push_field(srql::field("role_id"), &mut create);
+        srql::obj_create_query(BOARD_MEMBER_TABLE_NAME, create, ids)
+    }
+}
+
+fn field_cond(field: &str, value: impl Into<srql::Value>) -> srql::Cond {
+    srql::Cond(
+        srql::Expression::Binary {
+            l: srql::field(field).into(),
+            o: srql::Operator::Equal,
+            r: value.into(),
+        }
+        .into(),
+    )
+}
+
+/// Gets the role that an account has been given in a board, if any.
+pub async fn member_role(
+    persist: &Persist,
+    board_id: &Thing,
+    account: &Thing,
+) -> Result<Option<BoardRole>> {
+    let member: Option<BoardMember> = persist
+        .db()
+        .query(srql::SelectStatement {
+            expr: srql::Fields::all(),
+            what: srql::table(BOARD_MEMBER_TABLE_NAME),
+            cond: srql::cond_and(
+                Some(field_cond("board_id", board_id.clone())),
+                Some(field_cond("account_id", account.clone())),
+            ),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    match member {
+        Some(member) => Ok(persist.load(member.role_id).await?),
+        None => Ok(None),
+    }
+}
+
+/// What the current account can do in a board. Creators, instance
+/// moderators and admins can do everything, accounts with a custom role can
+/// do what it allows, and everyone else, including anonymous accounts, can
+/// do what members can.
+pub async fn board_permissions(
+    persist: &Persist,
+    board: &Board,
+    current: &CurrentAccount,
+) -> Result<Vec<BoardPermission>> {
+    let Ok(id) = current.id() else {
+        return Ok(BoardPermission::MEMBER.to_vec());
+    };
+    let account = id.to_account_thing();
+    if board.creator_id.as_ref() == Some(&account) {
+        return Ok(BoardPermission::ALL.to_vec());
+    }
+    let auth = AuthContext::resolve(persist, current).await?;
+    if auth.has_permission(Permission::ModerateContent) {
+        return Ok(BoardPermission::ALL.to_vec());
+    }
+    Ok(match member_role(persist, &board.id, &account).await? {
+        Some(role) => role.permissions,
+        None => BoardPermission::MEMBER.to_vec(),
+    })
+}
+
+/// Gets a board, failing if the current account isn't allowed to do
+/// something in it.
+pub async fn require_board_permission(
+    persist: &Persist,
+    current: &CurrentAccount,
+    board_id: &Thing,
+    permission: BoardPermission,
+) -> Result<Board> {
+    if board_id.tb != BOARD_TABLE_NAME {
+        return Err(Error::NotFound);
+    }
+    let board: Option<Board> = persist.load(board_id.clone()).await?;
+    let board = board.ok_or(Error::NotFound)?;
+    if board_permissions(persist, &board, current)
+        .await?
+        .contains(&permission)
+    {
+        Ok(board)
+    } else {
+        Err(Error::Unauthorized)
+    }
+}
+
+/// Rejects requests from accounts that aren't allowed to do something in a
+/// board, with [`Error::Unauthorized`].
+pub struct BoardPermissionGuard(BoardPermission, ID);
+
+impl BoardPermissionGuard {
+    #[must_use]
+    pub fn new(permission: BoardPermission, board_id: ID) -> Self {
+        Self(permission, board_id)
+    }
+}
+
+#[async_trait]
+impl Guard for BoardPermissionGuard {
+    async fn check(&self, ctx: &Context<'_>) -> GqlResult<()> {
+        require_board_permission(
+            ctx.data_unchecked::<Persist>(),
+            ctx.current_account(),
+            &srql::Thing::from((BOARD_TABLE_NAME, self.1.as_str())),
+            self.0,
+        )
+        .await
+        .extend()?;
+        Ok(())
+    }
+}
@@ -1,8 +1,16 @@
 use async_graphql::{connection::Connection, Context, Object, ID};
 use tracing::instrument;
 
-use super::{Board, BoardCursor, CreateBoard, UpdateBoard};
-use crate::{prelude::*, query::PaginationArgs};
+use super::{
+    Board, BoardCursor, BoardMember, BoardPermission, BoardPermissionGuard, BoardRole, CreateBoard,
+    CreateBoardRole, UpdateBoard, UpdateBoardRole,
+};
+use crate::{
+    account::EmailVerified,
+    policy::PoliciesAccepted,
+    prelude::*,
+    query::{page_complexity, PaginationArgs},
+};
 
 #[derive(Default)]
 pub struct BoardQuery;
@@ -16,6 +24,7 @@ impl BoardQuery {
     }
 
     /// Lists boards.
+    #[graphql(complexity = "page_complexity(first, last, child_complexity)")]
     #[instrument(skip_all)]
     async fn boards(
         &self,
@@ -34,6 +43,7 @@ impl BoardQuery {
                     first,
                     last,
                 }
+                .warn_if_clamped(ctx)
                 .validate()
                 .extend()?,
             )
@@ -46,9 +56,10 @@ impl BoardQuery {
 #[derive(Default)]
 pub struct BoardMutation;
 
-#[Object]
+#[Object(guard = "PoliciesAccepted")]
 impl BoardMutation {
     /// Creates a new board.
+    #[graphql(guard = "PoliciesAccepted.and(EmailVerified)")]
     #[instrument(skip_all)]
     async fn create_board(&self, ctx: &Context<'_>, create: CreateBoard) -> GqlResult<Board> {
         ctx.board_persist().create(create).await.extend()
@@ -70,4 +81,75 @@ impl BoardMutation {
     async fn delete_board(&self, ctx: &Context<'_>, id: ID) -> GqlResult<Option<Board>> {
         ctx.board_persist().delete(&id).await.extend()
     }
+
+    /// Creates a custom role in a board, which decides what the accounts
+    /// given it can do there. Only accounts that can moderate the board can
+    /// do this, and the role can't allow more than they can do.
+    #[graphql(guard = "PoliciesAccepted.and(BoardPermissionGuard::new(
+        BoardPermission::Moderate,
+        board_id.clone(),
+    ))")]
+    #[instrument(skip_all)]
+    async fn create_board_role(
+        &self,
+        ctx: &Context<'_>,
+        board_id: ID,
+        create: CreateBoardRole,
+    ) -> GqlResult<BoardRole> {
+        ctx.board_persist()
+            .create_role(&board_id, create)
+            .await
+            .extend()
+    }
+
+    /// Changes a board's custom role.
+    #[instrument(skip_all)]
+    async fn update_board_role(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        update: UpdateBoardRole,
+    ) -> GqlResult<Option<BoardRole>> {
+        ctx.board_persist().update_role(&id, update).await.extend()
+    }
+
+    /// Deletes a board's custom role. The accounts that had it can do what
+    /// members can again.
+    #[instrument(skip_all)]
+    async fn delete_board_role(&self, ctx: &Context<'_>, id: ID) -> GqlResult<Option<BoardRole>> {
+        ctx.board_persist().delete_role(&id).await.extend()
+    }
+
+    /// Gives an account one of a board's custom roles, replacing any it had
+    /// there. Only accounts that can invite to the board can do this.
+    #[instrument(skip_all)]
+    async fn assign_board_role(
+        &self,
+        ctx: &Context<'_>,
+        role_id: ID,
+        account_id: ID,
+    ) -> GqlResult<BoardMember> {
+        ctx.board_persist()
+            .assign_role(&role_id, &account_id)
+            .await
+            .extend()
+    }
+
+    /// Takes an account's custom role in a board away.
+    #[graphql(guard = "PoliciesAccepted.and(BoardPermissionGuard::new(
+        BoardPermission::Invite,
+        board_id.clone(),
+    ))")]
+    #[instrument(skip_all)]
+    async fn unassign_board_role(
+        &self,
+        ctx: &Context<'_>,
+        board_id: ID,
+        account_id: ID,
+    ) -> GqlResult<Option<BoardMember>> {
+        ctx.board_persist()
+            .unassign_role(&board_id, &account_id)
+            .await
+            .extend()
+    }
 }
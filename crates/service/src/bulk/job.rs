@@ -0,0 +1,43 @@
+use std::time::Duration;
+
+use tokio::{
+    task::JoinHandle,
+    time::{interval, MissedTickBehavior},
+};
+use tracing::{debug, error, trace};
+
+use super::run_bulk_jobs;
+use crate::persist::Persist;
+
+/// How often bulk jobs that have been started are looked for.
+pub const BULK_JOB_INTERVAL: Duration = Duration::from_secs(10);
+
+static BULK_JOB_LOCK: &str = "bulk_job";
+
+/// Spawns a task that periodically works through the bulk jobs that
+/// moderators and admins have started.
+pub fn spawn_bulk_jobs(persist: Persist) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = interval(BULK_JOB_INTERVAL);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+
+            let res = persist
+                .execute_in_lock(BULK_JOB_LOCK, || async {
+                    let _job = persist.metrics().track_job();
+                    run_bulk_jobs(&persist).await
+                })
+                .await;
+
+            match res {
+                Ok(Some(Ok(0))) => trace!("No bulk job items to process"),
+                Ok(Some(Ok(processed))) => debug!(processed, "Bulk job items processed"),
+                Ok(Some(Err(err))) => error!(error = ?err, "Failed to run bulk jobs"),
+                Ok(None) => trace!("Bulk jobs are already being run"),
+                Err(err) => error!(error = ?err, "Failed to lock bulk jobs"),
+            }
+        }
+    })
+}
@@ -0,0 +1,20 @@
+//! Actions that moderators and admins take on many accounts or posts at
+//! once, such as suspending every account registered with a spammer's email
+//! domain, instead of one mutation for each.
+//!
+//! Bulk jobs run in the background, in batches, acting as the account that
+//! started them. Each keeps a cursor of the last item it got to, so it picks
+//! up where it left off after a restart, and counts how many items it has
+//! dealt with so that its progress can be watched. Items that can't be dealt
+//! with are recorded as failures rather than stopping the job, and a job can
+//! be cancelled between batches.
+
+mod job;
+mod models;
+mod persist;
+
+pub use job::*;
+pub use models::*;
+pub use persist::*;
+
+pub static BULK_JOB_TABLE_NAME: &str = "bulk_job";
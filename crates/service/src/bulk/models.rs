@@ -0,0 +1,398 @@
+use async_graphql::{ComplexObject, Enum, InputObject, SimpleObject, ID};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use surrealdb::sql::Thing;
+
+use super::BULK_JOB_TABLE_NAME;
+use crate::{account::ACC_TABLE_NAME, id_obj_impls, prelude::*};
+
+/// The most failures that are kept for a job. Any more are still counted.
+pub const BULK_MAX_FAILURES: usize = 100;
+
+/// What a bulk job does to each item it finds.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkAction {
+    /// Suspends the accounts that match a filter.
+    SuspendAccounts,
+    /// Deletes the posts that link to a domain.
+    PurgeContent,
+}
+
+impl QueryValue for BulkAction {
+    fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
+        srql::to_value(self)
+            .ok()
+            .map(|value| (field, srql::Operator::Equal, value))
+    }
+}
+
+/// Where a bulk job is up to.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkJobStatus {
+    /// The job is waiting to be started.
+    Pending,
+    /// The job has been started, and is part way through its items.
+    Running,
+    /// The job has been through every item. Some of them may have failed.
+    Completed,
+    /// The job was cancelled before it got through every item. The items it
+    /// had already dealt with stay dealt with.
+    Cancelled,
+}
+
+impl QueryValue for BulkJobStatus {
+    fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
+        srql::to_value(self)
+            .ok()
+            .map(|value| (field, srql::Operator::Equal, value))
+    }
+}
+
+/// Which accounts a bulk suspension applies to. At least one criterion has
+/// to be given, and an account has to match all of them. Deleted accounts
+/// never match.
+#[derive(
+    SimpleObject, InputObject, Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[graphql(input_name = "AccountFilterInput")]
+pub struct AccountFilter {
+    /// Only accounts registered at or after this time.
+    pub registered_after: Option<DateTime<Utc>>,
+    /// Only accounts registered before this time.
+    pub registered_before: Option<DateTime<Utc>>,
+    /// Only accounts whose email address is at this domain, such as
+    /// `example.com`.
+    pub email_domain: Option<String>,
+    /// Only accounts that have, or haven't, been limited for spam.
+    pub limited: Option<bool>,
+}
+
+impl AccountFilter {
+    /// Checks the filter, and puts the email domain in lowercase.
+    pub fn normalize(mut self) -> Result<Self> {
+        self.email_domain = self
+            .email_domain
+            .map(|domain| normalize_domain(&domain))
+            .transpose()?;
+        if self == Self::default() {
+            return Err(Error::InputInvalid(
+                "an account filter needs at least one criterion".into(),
+            ));
+        }
+        if let (Some(after), Some(before)) = (self.registered_after, self.registered_before) {
+            if after >= before {
+                return Err(Error::InputInvalid(
+                    "registeredAfter must be before registeredBefore".into(),
+                ));
+            }
+        }
+        Ok(self)
+    }
+
+    /// The condition that accounts matching the filter meet.
+    pub fn cond(&self) -> Option<srql::Cond> {
+        let binary = |l: srql::Value, o, r: srql::Value| -> Option<srql::Cond> {
+            Some(srql::Cond(srql::Expression::Binary { l, o, r }.into()))
+        };
+        let registered = |o, time: DateTime<Utc>| {
+            binary(
+                srql::field("id").into(),
+                o,
+                Thing::from((ACC_TABLE_NAME, srql::ulid_at(time).as_str())).into(),
+            )
+        };
+
+        let mut cond = binary(
+            srql::field("deleted_at").into(),
+            srql::Operator::Equal,
+            srql::Value::None,
+        );
+        if let Some(after) = self.registered_after {
+            cond = srql::cond_and(cond, registered(srql::Operator::MoreThanOrEqual, after));
+        }
+        if let Some(before) = self.registered_before {
+            cond = srql::cond_and(cond, registered(srql::Operator::LessThan, before));
+        }
+        if let Some(domain) = &self.email_domain {
+            // Accounts don't have to have an email, and `string::endsWith`
+            // fails on one that's missing rather than not matching.
+            cond = srql::cond_and(
+                cond,
+                binary(
+                    srql::field("email").into(),
+                    srql::Operator::NotEqual,
+                    srql::Value::None,
+                ),
+            );
+            cond = srql::cond_and(
+                cond,
+                binary(
+                    srql::Function::Normal(
+                        "string::endsWith".into(),
+                        vec![
+                            srql::field("email").into(),
+                            srql::string(format!("@{domain}")).into(),
+                        ],
+                    )
+                    .into(),
+                    srql::Operator::Equal,
+                    true.into(),
+                ),
+            );
+        }
+        if let Some(limited) = self.limited {
+            cond = srql::cond_and(
+                cond,
+                binary(
+                    srql::field("limited").into(),
+                    if limited {
+                        srql::Operator::Equal
+                    } else {
+                        srql::Operator::NotEqual
+                    },
+                    true.into(),
+                ),
+            );
+        }
+        cond
+    }
+}
+
+impl QueryValue for AccountFilter {
+    fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
+        srql::to_value(self)
+            .ok()
+            .map(|value| (field, srql::Operator::Equal, value))
+    }
+}
+
+/// An item that a bulk job couldn't deal with.
+#[derive(SimpleObject, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BulkFailure {
+    /// The ID of the account or post.
+    pub subject_id: ID,
+    /// Why it couldn't be dealt with.
+    pub reason: String,
+}
+
+impl QueryValue for Vec<BulkFailure> {
+    fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
+        srql::to_value(self)
+            .ok()
+            .map(|value| (field, srql::Operator::Equal, value))
+    }
+}
+
+/// A bulk action that a moderator or admin started, and how far it has got.
+#[derive(SimpleObject, Debug, Clone, Deserialize)]
+#[graphql(complex)]
+pub struct BulkJob {
+    #[graphql(skip)]
+    pub id: Thing,
+    #[graphql(skip)]
+    pub actor_id: Thing,
+    /// The last item the job got to, which it carries on after.
+    #[graphql(skip)]
+    pub last_subject_id: Option<Thing>,
+
+    /// What the job does to each item.
+    pub action: BulkAction,
+    /// Where the job is up to.
+    pub status: BulkJobStatus,
+    /// The accounts that a suspension applies to.
+    pub filter: Option<AccountFilter>,
+    /// How many hours accounts are suspended for.
+    pub hours: Option<u32>,
+    /// Why accounts are being suspended, which they're told.
+    pub reason: Option<String>,
+    /// The domain that posts being purged link to.
+    pub domain: Option<String>,
+
+    /// How many items were found when the job was started. Items can be
+    /// added or removed while it runs, so this is a guide to its progress.
+    #[serde(default)]
+    pub total: u64,
+    /// How many items the job has looked at.
+    #[serde(default)]
+    pub processed: u64,
+    /// How many items the job has dealt with.
+    #[serde(default)]
+    pub succeeded: u64,
+    /// How many items the job looked at but left alone, such as posts that
+    /// mention the domain without linking to it.
+    #[serde(default)]
+    pub skipped: u64,
+    /// How many items the job couldn't deal with.
+    #[serde(default)]
+    pub failed: u64,
+    /// The first items that the job couldn't deal with, and why.
+    #[serde(default)]
+    pub failures: Vec<BulkFailure>,
+
+    /// When the job started working through its items.
+    pub started_at: Option<DateTime<Utc>>,
+    /// When the job completed or was cancelled.
+    pub finished_at: Option<DateTime<Utc>>,
+
+    /// A timestamp indicating the last time the job was updated.
+    pub updated_at: DateTime<Utc>,
+}
+
+#[ComplexObject]
+impl BulkJob {
+    /// The job's unique ID.
+    async fn id(&self) -> ID {
+        self.id.to_gql_id()
+    }
+
+    /// The ID of the account that started the job.
+    async fn actor_id(&self) -> ID {
+        self.actor_id.to_gql_id()
+    }
+
+    /// Whether the job can still be cancelled.
+    async fn cancellable(&self) -> bool {
+        self.is_active()
+    }
+}
+
+id_obj_impls!(BulkJob);
+
+impl BulkJob {
+    #[must_use]
+    pub fn is_active(&self) -> bool {
+        matches!(self.status, BulkJobStatus::Pending | BulkJobStatus::Running)
+    }
+
+    /// Creates a job that suspends the accounts matching the filter.
+    pub fn create_suspension(
+        actor_id: Thing,
+        filter: AccountFilter,
+        hours: u32,
+        reason: String,
+        total: u64,
+        ids: &dyn IdGen,
+    ) -> srql::CreateStatement {
+        let mut create = Self::create(actor_id, BulkAction::SuspendAccounts, total);
+        filter.push_field(srql::field("filter"), &mut create);
+        hours.push_field(srql::field("hours"), &mut create);
+        reason.push_field(srql::field("reason"), &mut create);
+        srql::obj_create_query(BULK_JOB_TABLE_NAME, create, ids)
+    }
+
+    /// Creates a job that deletes the posts linking to the domain.
+    pub fn create_purge(
+        actor_id: Thing,
+        domain: String,
+        total: u64,
+        ids: &dyn IdGen,
+    ) -> srql::CreateStatement {
+        let mut create = Self::create(actor_id, BulkAction::PurgeContent, total);
+        domain.push_field(srql::field("domain"), &mut create);
+        srql::obj_create_query(BULK_JOB_TABLE_NAME, create, ids)
+    }
+
+    fn create(actor_id: Thing, action: BulkAction, total: u64) -> srql::SetExpr {
+        let mut create = vec![];
+        actor_id.push_field(srql::field("actor_id"), &mut create);
+        action.push_field(srql::field("action"), &mut create);
+        BulkJobStatus::Pending.push_field(srql::field("status"), &mut create);
+        total.push_field(srql::field("total"), &mut create);
+        create
+    }
+}
+
+/// Checks that a domain looks like one, such as `example.com`, and puts it
+/// in lowercase.
+pub fn normalize_domain(domain: &str) -> Result<String> {
+    let domain = domain.trim().trim_end_matches('.').to_ascii_lowercase();
+    let valid = domain.len() <= 253
+        && domain.contains('.')
+        && domain.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        });
+    if valid {
+        Ok(domain)
+    } else {
+        Err(Error::InputInvalid(format!(
+            "{domain:?} isn't a domain, such as example.com"
+        )))
+    }
+}
+
+/// Whether the text has a link to the domain or one of its subdomains.
+#[must_use]
+pub fn links_to_domain(text: &str, domain: &str) -> bool {
+    let lower = text.to_ascii_lowercase();
+    ["http://", "https://"].iter().any(|scheme| {
+        lower.match_indices(scheme).any(|(i, _)| {
+            let rest = &lower[i + scheme.len()..];
+            let authority = rest
+                .split(|c: char| matches!(c, '/' | '?' | '#') || c.is_whitespace())
+                .next()
+                .unwrap_or_default();
+            // Drop any credentials and port, and trailing punctuation from
+            // the sentence the link is in.
+            let host = authority.rsplit('@').next().unwrap_or_default();
+            let host = host.split(':').next().unwrap_or_default();
+            let host = host.trim_end_matches(|c: char| !c.is_ascii_alphanumeric());
+            host == domain
+                || host
+                    .strip_suffix(domain)
+                    .is_some_and(|sub| sub.ends_with('.'))
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_normalize_domain() {
+        assert_eq!(normalize_domain(" Example.COM. "), Ok("example.com".into()));
+        assert_eq!(
+            normalize_domain("spam.example.co.uk"),
+            Ok("spam.example.co.uk".into())
+        );
+        for invalid in [
+            "example",
+            "",
+            "exa mple.com",
+            "-a.com",
+            "a..com",
+            "https://a.com",
+        ] {
+            assert!(normalize_domain(invalid).is_err(), "{invalid:?}");
+        }
+    }
+
+    #[test]
+    fn test_links_to_domain() {
+        for text in [
+            "buy now https://example.com",
+            "see http://www.example.com/deals?x=1.",
+            "(https://shop.example.com)",
+            "HTTPS://EXAMPLE.COM:8080/a",
+            "https://user@example.com",
+        ] {
+            assert!(links_to_domain(text, "example.com"), "{text:?}");
+        }
+        for text in [
+            "example.com without a link",
+            "https://notexample.com",
+            "https://example.com.evil.net",
+            "https://evil.net/?r=example.com",
+        ] {
+            assert!(!links_to_domain(text, "example.com"), "{text:?}");
+        }
+    }
+}
@@ -0,0 +1,515 @@
+#[cfg(test)]
+mod tests;
+
+use chrono::Duration;
+use serde::Deserialize;
+use surrealdb::sql::Thing;
+use tracing::instrument;
+
+use super::{
+    links_to_domain, normalize_domain, AccountFilter, BulkAction, BulkFailure, BulkJob,
+    BulkJobStatus, BULK_JOB_TABLE_NAME, BULK_MAX_FAILURES,
+};
+use crate::{
+    account::{
+        require_permission, require_role, restrict_account, Account, AccountRole, CurrentAccount,
+        PartialAccount, Permission, RestrictionKind, ACC_TABLE_NAME, RESTRICTION_MAX_HOURS,
+    },
+    persist::Persist,
+    post::{PostPersist, POST_TABLE_NAME},
+    prelude::*,
+    query::{SRQL_ORDER_ASC, SRQL_ORDER_DESC},
+};
+
+/// How many items a job deals with before it saves its progress and checks
+/// whether it has been cancelled.
+const BATCH_SIZE: u64 = 100;
+
+pub struct BulkPersist<'a> {
+    persist: &'a Persist,
+    current: &'a CurrentAccount,
+}
+
+impl<'a> BulkPersist<'a> {
+    pub fn new(persist: &'a Persist, current: &'a CurrentAccount) -> Self {
+        Self { persist, current }
+    }
+
+    /// Starts a job that suspends every account matching the filter for a
+    /// number of hours, as if each had been suspended on its own. Only
+    /// moderators and admins can do this, and accounts that they couldn't
+    /// suspend one at a time, such as admins, are recorded as failures.
+    #[instrument(skip_all)]
+    pub async fn suspend_accounts(
+        &self,
+        filter: AccountFilter,
+        hours: u32,
+        reason: String,
+    ) -> Result<BulkJob> {
+        let actor =
+            require_permission(self.persist, self.current, Permission::RestrictAccounts).await?;
+        if !(1..=RESTRICTION_MAX_HOURS).contains(&hours) {
+            return Err(Error::InputInvalid(format!(
+                "restrictions must last between 1 and {RESTRICTION_MAX_HOURS} hours"
+            )));
+        }
+        let reason = reason.trim().to_owned();
+        if reason.is_empty() {
+            return Err(Error::InputInvalid(
+                "a reason must be given for restricting an account".into(),
+            ));
+        }
+        let filter = filter.normalize()?;
+
+        let total = count(self.persist, ACC_TABLE_NAME, filter.cond()).await?;
+        self.create(BulkJob::create_suspension(
+            actor.id,
+            filter,
+            hours,
+            reason,
+            total,
+            self.persist.ids(),
+        ))
+        .await
+    }
+
+    /// Starts a job that deletes every post linking to the domain or one of
+    /// its subdomains. Only moderators and admins can do this.
+    #[instrument(skip_all)]
+    pub async fn purge_content(&self, domain: &str) -> Result<BulkJob> {
+        let actor =
+            require_permission(self.persist, self.current, Permission::ModerateContent).await?;
+        let domain = normalize_domain(domain)?;
+
+        let total = count(self.persist, POST_TABLE_NAME, Some(mentions_cond(&domain))).await?;
+        self.create(BulkJob::create_purge(
+            actor.id,
+            domain,
+            total,
+            self.persist.ids(),
+        ))
+        .await
+    }
+
+    async fn create(&self, create: srql::CreateStatement) -> Result<BulkJob> {
+        let job: Option<BulkJob> = self.persist.db().query(create).await?.take(0)?;
+        job.ok_or(Error::UnavailableIdent)
+    }
+
+    /// Gets a bulk job. Only moderators and admins can see these.
+    #[instrument(skip_all)]
+    pub async fn get(&self, id: &str) -> Result<Option<BulkJob>> {
+        require_role(self.persist, self.current, AccountRole::Moderator).await?;
+        Ok(self.persist.db().select((BULK_JOB_TABLE_NAME, id)).await?)
+    }
+
+    /// Lists the bulk jobs, newest first. Only moderators and admins can see
+    /// these.
+    #[instrument(skip_all)]
+    pub async fn list(&self) -> Result<Vec<BulkJob>> {
+        require_role(self.persist, self.current, AccountRole::Moderator).await?;
+        let jobs = self
+            .persist
+            .db()
+            .query(srql::SelectStatement {
+                expr: srql::Fields::all(),
+                what: srql::table(BULK_JOB_TABLE_NAME),
+                order: srql::Orders(vec![srql::Order {
+                    order: srql::field("id"),
+                    direction: SRQL_ORDER_DESC,
+                    ..Default::default()
+                }])
+                .into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        Ok(jobs)
+    }
+
+    /// Cancels a job that hasn't finished, which stops it before its next
+    /// batch. The items it has already dealt with stay dealt with. Jobs that
+    /// have finished are returned as they are. Only accounts that could start
+    /// the job can cancel it.
+    #[instrument(skip_all)]
+    pub async fn cancel(&self, id: &str) -> Result<Option<BulkJob>> {
+        let Some(job) = self.get(id).await? else {
+            return Ok(None);
+        };
+        require_permission(self.persist, self.current, permission(job.action)).await?;
+        if !job.is_active() {
+            return Ok(Some(job));
+        }
+
+        let mut update = vec![];
+        BulkJobStatus::Cancelled.push_field(srql::field("status"), &mut update);
+        self.persist
+            .clock()
+            .now()
+            .push_field(srql::field("finished_at"), &mut update);
+        update.push((
+            srql::field("updated_at"),
+            srql::Operator::Equal,
+            srql::time_now(),
+        ));
+        let cancelled: Option<BulkJob> = self
+            .persist
+            .db()
+            .query(srql::UpdateStatement {
+                what: srql::thing(job.id.clone()),
+                data: srql::Data::SetExpression(update).into(),
+                cond: active_cond().into(),
+                output: srql::Output::After.into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        // The job may have finished in the meantime.
+        match cancelled {
+            Some(job) => Ok(Some(job)),
+            None => self.get(id).await,
+        }
+    }
+}
+
+/// Works through the jobs that haven't finished, oldest first, until each
+/// has completed or been cancelled. Returns how many items were looked at.
+#[instrument(skip_all)]
+pub async fn run_bulk_jobs(persist: &Persist) -> Result<u64> {
+    let jobs: Vec<BulkJob> = persist
+        .db()
+        .query(srql::SelectStatement {
+            expr: srql::Fields::all(),
+            what: srql::table(BULK_JOB_TABLE_NAME),
+            cond: active_cond().into(),
+            order: srql::Orders(vec![srql::Order {
+                order: srql::field("id"),
+                direction: SRQL_ORDER_ASC,
+                ..Default::default()
+            }])
+            .into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+
+    let mut processed = 0;
+    for job in jobs {
+        processed += run_job(persist, job).await?;
+    }
+    Ok(processed)
+}
+
+#[instrument(skip_all, fields(job = %job.id))]
+async fn run_job(persist: &Persist, mut job: BulkJob) -> Result<u64> {
+    if job.status == BulkJobStatus::Pending {
+        let mut update = vec![];
+        BulkJobStatus::Running.push_field(srql::field("status"), &mut update);
+        persist
+            .clock()
+            .now()
+            .push_field(srql::field("started_at"), &mut update);
+        if let Some(update) = srql::obj_update_query(job.id.clone(), update) {
+            persist.db().query(update).await?.check()?;
+        }
+    }
+
+    let mut processed = 0;
+    loop {
+        let batch = match job.action {
+            BulkAction::SuspendAccounts => suspend_batch(persist, &job).await?,
+            BulkAction::PurgeContent => purge_batch(persist, &job).await?,
+        };
+        let Some(last_subject_id) = batch.last_subject_id else {
+            complete(persist, &job).await?;
+            return Ok(processed);
+        };
+
+        let mut update = vec![
+            (
+                srql::field("last_subject_id"),
+                srql::Operator::Equal,
+                last_subject_id.into(),
+            ),
+            (
+                srql::field("processed"),
+                srql::Operator::Inc,
+                batch.processed.into(),
+            ),
+            (
+                srql::field("succeeded"),
+                srql::Operator::Inc,
+                batch.succeeded.into(),
+            ),
+            (
+                srql::field("skipped"),
+                srql::Operator::Inc,
+                batch.skipped.into(),
+            ),
+            (
+                srql::field("failed"),
+                srql::Operator::Inc,
+                batch.failed.into(),
+            ),
+        ];
+        if !batch.failures.is_empty() {
+            let mut failures = job.failures;
+            failures.extend(batch.failures);
+            failures.truncate(BULK_MAX_FAILURES);
+            failures.push_field(srql::field("failures"), &mut update);
+        }
+        let updated: Option<BulkJob> = persist
+            .db()
+            .query(srql::UpdateStatement {
+                what: srql::thing(job.id.clone()),
+                data: srql::Data::SetExpression(update).into(),
+                output: srql::Output::After.into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        processed += batch.processed;
+
+        match updated {
+            Some(updated) if updated.is_active() => job = updated,
+            _ => return Ok(processed),
+        }
+    }
+}
+
+/// What a job did with a batch of items.
+#[derive(Default)]
+struct Batch {
+    last_subject_id: Option<Thing>,
+    processed: u64,
+    succeeded: u64,
+    skipped: u64,
+    failed: u64,
+    failures: Vec<BulkFailure>,
+}
+
+impl Batch {
+    fn record(&mut self, job: &BulkJob, subject_id: Thing, res: Result<bool>) {
+        self.processed += 1;
+        match res {
+            Ok(true) => self.succeeded += 1,
+            Ok(false) => self.skipped += 1,
+            Err(err) => {
+                self.failed += 1;
+                if job.failures.len() + self.failures.len() < BULK_MAX_FAILURES {
+                    self.failures.push(BulkFailure {
+                        subject_id: subject_id.to_gql_id(),
+                        reason: err.to_string(),
+                    });
+                }
+            }
+        }
+        self.last_subject_id = Some(subject_id);
+    }
+}
+
+async fn suspend_batch(persist: &Persist, job: &BulkJob) -> Result<Batch> {
+    #[derive(Deserialize)]
+    struct Item {
+        id: Thing,
+    }
+
+    let filter = job.filter.clone().unwrap_or_default();
+    let items: Vec<Item> = next_items(persist, job, ACC_TABLE_NAME, filter.cond()).await?;
+    let current = acting_as(persist, job).await?;
+
+    let mut batch = Batch::default();
+    for Item { id } in items {
+        let res = restrict_account(
+            persist,
+            &current,
+            &id.id.to_raw(),
+            RestrictionKind::Suspended,
+            job.hours.unwrap_or(1),
+            job.reason.clone().unwrap_or_default(),
+        )
+        .await
+        .map(|acc| acc.is_some());
+        batch.record(job, id, res);
+    }
+    Ok(batch)
+}
+
+async fn purge_batch(persist: &Persist, job: &BulkJob) -> Result<Batch> {
+    #[derive(Deserialize)]
+    struct Item {
+        id: Thing,
+        content: Option<String>,
+        attribution_url: Option<String>,
+    }
+
+    let domain = job.domain.as_deref().unwrap_or_default();
+    let items: Vec<Item> =
+        next_items(persist, job, POST_TABLE_NAME, Some(mentions_cond(domain))).await?;
+    let current = acting_as(persist, job).await?;
+
+    let mut batch = Batch::default();
+    for item in items {
+        let links = [&item.content, &item.attribution_url]
+            .into_iter()
+            .flatten()
+            .any(|text| links_to_domain(text, domain));
+        let res = if links {
+            PostPersist::new(persist, &current)
+                .delete(&item.id.id.to_raw())
+                .await
+                .map(|post| post.is_some())
+        } else {
+            Ok(false)
+        };
+        batch.record(job, item.id, res);
+    }
+    Ok(batch)
+}
+
+/// Gets the next batch of items matching the condition, after the last one
+/// the job got to.
+async fn next_items<T>(
+    persist: &Persist,
+    job: &BulkJob,
+    table: &str,
+    cond: Option<srql::Cond>,
+) -> Result<Vec<T>>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let after = job.last_subject_id.clone().map(|last_subject_id| {
+        srql::Cond(
+            srql::Expression::Binary {
+                l: srql::field("id").into(),
+                o: srql::Operator::MoreThan,
+                r: last_subject_id.into(),
+            }
+            .into(),
+        )
+    });
+    let items = persist
+        .db()
+        .query(srql::SelectStatement {
+            expr: srql::Fields::all(),
+            what: srql::table(table),
+            cond: srql::cond_and(cond, after),
+            order: srql::Orders(vec![srql::Order {
+                order: srql::field("id"),
+                direction: SRQL_ORDER_ASC,
+                ..Default::default()
+            }])
+            .into(),
+            limit: srql::Limit(BATCH_SIZE.into()).into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    Ok(items)
+}
+
+/// Signs in as the account that started the job, so that each item is
+/// checked against what it's allowed to do now. If it no longer exists,
+/// every item fails.
+async fn acting_as(persist: &Persist, job: &BulkJob) -> Result<CurrentAccount> {
+    let actor: Option<Account> = persist.load(job.actor_id.clone()).await?;
+    Ok(actor.map_or_else(CurrentAccount::default, |actor| {
+        CurrentAccount::new(
+            PartialAccount::new(actor.id.to_gql_id(), actor.user_id),
+            persist.clock().now() + Duration::minutes(30),
+            persist.shared_clock(),
+        )
+    }))
+}
+
+/// Marks the job as completed, unless it was cancelled in the meantime.
+async fn complete(persist: &Persist, job: &BulkJob) -> Result<()> {
+    let mut update = vec![];
+    BulkJobStatus::Completed.push_field(srql::field("status"), &mut update);
+    persist
+        .clock()
+        .now()
+        .push_field(srql::field("finished_at"), &mut update);
+    persist
+        .db()
+        .query(srql::UpdateStatement {
+            what: srql::thing(job.id.clone()),
+            data: srql::Data::SetExpression(update).into(),
+            cond: active_cond().into(),
+            output: srql::Output::None.into(),
+            ..Default::default()
+        })
+        .await?
+        .check()?;
+    Ok(())
+}
+
+/// The permission that starting or cancelling a job needs.
+fn permission(action: BulkAction) -> Permission {
+    match action {
+        BulkAction::SuspendAccounts => Permission::RestrictAccounts,
+        BulkAction::PurgeContent => Permission::ModerateContent,
+    }
+}
+
+/// Jobs that are waiting or running.
+fn active_cond() -> srql::Cond {
+    srql::Cond(
+        srql::Expression::Binary {
+            l: srql::field("status").into(),
+            o: srql::Operator::Inside,
+            r: srql::array(vec![
+                srql::to_value(BulkJobStatus::Pending).unwrap_or_default(),
+                srql::to_value(BulkJobStatus::Running).unwrap_or_default(),
+            ]),
+        }
+        .into(),
+    )
+}
+
+/// Posts whose text mentions the domain, which are checked for links to it
+/// one at a time.
+fn mentions_cond(domain: &str) -> srql::Cond {
+    let mentions = |field: &str| -> srql::Value {
+        srql::Expression::Binary {
+            l: srql::field(field).into(),
+            o: srql::Operator::Contain,
+            r: srql::string(domain).into(),
+        }
+        .into()
+    };
+    srql::Cond(
+        srql::Expression::Binary {
+            l: mentions("content"),
+            o: srql::Operator::Or,
+            r: mentions("attribution_url"),
+        }
+        .into(),
+    )
+}
+
+async fn count(persist: &Persist, table: &str, cond: Option<srql::Cond>) -> Result<u64> {
+    let count: Option<u64> = persist
+        .db()
+        .query(srql::count_query(table, cond))
+        .await?
+        .take("count")?;
+    Ok(count.unwrap_or_default())
+}
+
+#[cfg(test)]
+pub mod testing {
+    use crate::account::testing::TestData;
+
+    use super::BulkPersist;
+
+    pub trait BulkTestData {
+        fn bulk(&self) -> BulkPersist<'_>;
+    }
+
+    impl BulkTestData for TestData {
+        fn bulk(&self) -> BulkPersist<'_> {
+            BulkPersist::new(&self.persist, &self.current)
+        }
+    }
+}
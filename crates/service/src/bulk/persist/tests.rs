@@ -0,0 +1,225 @@
+use chrono::Duration;
+use pretty_assertions::assert_eq;
+
+use super::{testing::BulkTestData as _, *};
+use crate::{
+    account::testing::*,
+    post::{testing::PostTestData as _, CreatePost, Post},
+};
+
+async fn set_email(persist: &Persist, acc: &AccData, email: &str) {
+    let update = srql::obj_update_query(
+        acc.id.clone(),
+        vec![(srql::field("email"), srql::Operator::Equal, email.into())],
+    )
+    .unwrap();
+    persist.db().query(update).await.unwrap().check().unwrap();
+}
+
+async fn account(persist: &Persist, acc: &AccData) -> Account {
+    persist.db().select(acc.id.clone()).await.unwrap().unwrap()
+}
+
+async fn post(data: &TestData, content: &str) -> Post {
+    data.post()
+        .create(CreatePost {
+            content: Some(content.into()),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_suspend_accounts() {
+    let (data, _) = TestData::with_user().await;
+    let spam = [
+        data.account().create_test_user().await,
+        data.account().create_test_user().await,
+    ];
+    let other = data.account().create_test_user().await;
+    for acc in &spam {
+        set_email(&data.persist, acc, &format!("{}@spam.example", acc.user_id)).await;
+    }
+    set_email(&data.persist, &other, "other@ok.example").await;
+
+    let res = data
+        .bulk()
+        .suspend_accounts(
+            AccountFilter {
+                email_domain: Some("Spam.Example".into()),
+                ..Default::default()
+            },
+            24,
+            "Spam".into(),
+        )
+        .await;
+    println!("{res:?}");
+    let job = res.unwrap();
+    assert_eq!(job.action, BulkAction::SuspendAccounts);
+    assert_eq!(job.status, BulkJobStatus::Pending);
+    assert_eq!(
+        job.filter.as_ref().unwrap().email_domain.as_deref(),
+        Some("spam.example")
+    );
+    assert_eq!(job.total, 2);
+
+    assert_eq!(run_bulk_jobs(&data.persist).await, Ok(2));
+    let job = data.bulk().get(&job.id.id.to_raw()).await.unwrap().unwrap();
+    assert_eq!(job.status, BulkJobStatus::Completed);
+    assert_eq!(
+        (job.processed, job.succeeded, job.skipped, job.failed),
+        (2, 2, 0, 0)
+    );
+    assert!(job.started_at.is_some());
+    assert!(job.finished_at.is_some());
+
+    let now = data.persist.clock().now();
+    for acc in &spam {
+        assert!(account(&data.persist, acc).await.is_suspended(now));
+    }
+    assert!(!account(&data.persist, &other).await.is_suspended(now));
+
+    // Finished jobs aren't run again.
+    assert_eq!(run_bulk_jobs(&data.persist).await, Ok(0));
+}
+
+#[tokio::test]
+async fn test_suspend_accounts_failures() {
+    let (data, admin) = TestData::with_user().await;
+    let acc = data.account().create_test_user().await;
+
+    let job = data
+        .bulk()
+        .suspend_accounts(
+            AccountFilter {
+                registered_after: Some(data.persist.clock().now() - Duration::hours(1)),
+                ..Default::default()
+            },
+            24,
+            "Spam wave".into(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(job.total, 2);
+
+    // The admin that started the job can't be suspended, which doesn't stop
+    // the other account from being.
+    run_bulk_jobs(&data.persist).await.unwrap();
+    let job = data.bulk().get(&job.id.id.to_raw()).await.unwrap().unwrap();
+    assert_eq!(job.status, BulkJobStatus::Completed);
+    assert_eq!((job.succeeded, job.failed), (1, 1));
+    assert_eq!(job.failures.len(), 1);
+    assert_eq!(job.failures[0].subject_id, admin.id.to_gql_id());
+    assert!(job.failures[0]
+        .reason
+        .contains("admins can't be restricted"));
+    assert!(account(&data.persist, &acc)
+        .await
+        .is_suspended(data.persist.clock().now()));
+}
+
+#[tokio::test]
+async fn test_suspend_accounts_invalid() {
+    let (mut data, _) = TestData::with_user().await;
+
+    let res = data
+        .bulk()
+        .suspend_accounts(AccountFilter::default(), 24, "Spam".into())
+        .await;
+    println!("{res:?}");
+    assert!(matches!(res, Err(Error::InputInvalid(_))));
+
+    let res = data
+        .bulk()
+        .suspend_accounts(
+            AccountFilter {
+                email_domain: Some("not a domain".into()),
+                ..Default::default()
+            },
+            24,
+            "Spam".into(),
+        )
+        .await;
+    println!("{res:?}");
+    assert!(matches!(res, Err(Error::InputInvalid(_))));
+
+    let other = data.account().create_test_user().await;
+    data.login_as(&other);
+    let res = data
+        .bulk()
+        .suspend_accounts(
+            AccountFilter {
+                limited: Some(true),
+                ..Default::default()
+            },
+            24,
+            "Spam".into(),
+        )
+        .await;
+    println!("{res:?}");
+    assert_eq!(res.map(|job| job.id), Err(Error::Unauthorized));
+}
+
+#[tokio::test]
+async fn test_purge_content() {
+    let (data, _) = TestData::with_user().await;
+    let linked = [
+        post(&data, "Deals at https://spam.example/now").await,
+        post(&data, "See (https://www.spam.example)").await,
+    ];
+    let kept = [
+        post(&data, "Don't go to spam.example").await,
+        post(&data, "https://notspam.example is fine").await,
+        post(&data, "Unrelated").await,
+    ];
+
+    let job = data.bulk().purge_content("spam.example").await.unwrap();
+    assert_eq!(job.action, BulkAction::PurgeContent);
+    assert_eq!(job.domain.as_deref(), Some("spam.example"));
+    assert_eq!(job.total, 4);
+
+    assert_eq!(run_bulk_jobs(&data.persist).await, Ok(4));
+    let job = data.bulk().get(&job.id.id.to_raw()).await.unwrap().unwrap();
+    assert_eq!(job.status, BulkJobStatus::Completed);
+    assert_eq!(
+        (job.processed, job.succeeded, job.skipped, job.failed),
+        (4, 2, 2, 0)
+    );
+
+    for post in &linked {
+        let found: Option<Post> = data.persist.db().select(post.id.clone()).await.unwrap();
+        assert!(found.is_none());
+    }
+    for post in &kept {
+        let found: Option<Post> = data.persist.db().select(post.id.clone()).await.unwrap();
+        assert!(found.is_some());
+    }
+}
+
+#[tokio::test]
+async fn test_cancel() {
+    let (mut data, admin) = TestData::with_user().await;
+    data.generate_post().await;
+    let job = data.bulk().purge_content("spam.example").await.unwrap();
+    let id = job.id.id.to_raw();
+
+    let other = data.account().create_test_user().await;
+    data.login_as(&other);
+    let res = data.bulk().cancel(&id).await;
+    println!("{res:?}");
+    assert_eq!(res.map(|job| job.is_some()), Err(Error::Unauthorized));
+
+    data.login_as(&admin);
+    let job = data.bulk().cancel(&id).await.unwrap().unwrap();
+    assert_eq!(job.status, BulkJobStatus::Cancelled);
+    assert!(job.finished_at.is_some());
+
+    // Cancelled jobs aren't run, and cancelling again changes nothing.
+    assert_eq!(run_bulk_jobs(&data.persist).await, Ok(0));
+    let again = data.bulk().cancel(&id).await.unwrap().unwrap();
+    assert_eq!(again.status, BulkJobStatus::Cancelled);
+    assert_eq!(again.finished_at, job.finished_at);
+
+    assert_eq!(data.bulk().list().await.unwrap().len(), 1);
+}
@@ -0,0 +1,210 @@
+//! A summary of what an instance is running with, so that problems can be
+//! reported along with it.
+//!
+//! The report is logged once when the server starts, and admins can fetch
+//! the same one with `admin { capabilities }`. Secrets, such as keys and the
+//! database's credentials, are never part of it.
+
+use std::net::SocketAddr;
+
+use async_graphql::SimpleObject;
+use ring::digest;
+use serde::Serialize;
+
+use crate::{config::ServeConfig, instance::ContentLimits, schema::schema};
+
+/// The cargo features that the server can be built with.
+const FEATURES: &[(&str, bool)] = &[
+    ("graphiql", cfg!(feature = "graphiql")),
+    ("backend-mem", cfg!(feature = "backend-mem")),
+    ("backend-file", cfg!(feature = "backend-file")),
+    ("backend-ws", cfg!(feature = "backend-ws")),
+];
+
+/// The paths that the server answers on.
+const PATHS: &[&str] = &[
+    "/api/graphql",
+    "/api/graphql/ws",
+    "/api/v1",
+    "/api/openapi.json",
+    "/api/oembed",
+    "/users",
+    "/posts",
+];
+
+/// What the instance is running with.
+#[derive(SimpleObject, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct CapabilityReport {
+    /// The version of the server.
+    pub version: String,
+    /// The cargo features that the server was built with.
+    pub features: Vec<String>,
+    /// The settings the server was started with.
+    pub config: ConfigSummary,
+    /// A SHA-256 hash of the GraphQL schema, in hex, which changes whenever
+    /// the API does.
+    pub schema_hash: String,
+    /// The last migration that each subsystem ran.
+    pub migrations: Vec<MigrationVersion>,
+    /// The address the server is listening on.
+    pub address: String,
+    /// The URLs the server answers on.
+    pub endpoints: Vec<String>,
+}
+
+/// The settings the server was started with, without any secrets.
+#[derive(SimpleObject, Serialize, Debug, Clone, PartialEq, Eq)]
+// These are independent settings, not a state machine.
+#[allow(clippy::struct_excessive_bools)]
+pub struct ConfigSummary {
+    /// The database's address, with any credentials taken out.
+    pub database_address: String,
+    pub namespace: String,
+    pub database: String,
+    pub db_pool_size: usize,
+    pub db_query_timeout_secs: Option<u64>,
+    pub public_url: Option<String>,
+    pub region: Option<String>,
+    pub public_stats: bool,
+    pub dev_auth: bool,
+    /// Whether the server was started read-only. It can be made read-only
+    /// or writable again while it runs.
+    pub read_only: bool,
+    /// Whether only the queries on the instance's allow-list can be run.
+    pub persisted_queries_only: bool,
+    pub media_dir: Option<String>,
+    pub max_concurrency: usize,
+    pub limits: ContentLimits,
+    /// The IDs of the identity providers that accounts can log in with.
+    pub oidc_providers: Vec<String>,
+    /// The name of the provider that posts are translated with, if there is
+    /// one.
+    pub translation_provider: Option<String>,
+    /// Whether outbound links and remote media are sent through the instance.
+    pub proxy_remote_content: bool,
+}
+
+impl From<&ServeConfig> for ConfigSummary {
+    fn from(config: &ServeConfig) -> Self {
+        Self {
+            database_address: redact_address(&config.address),
+            namespace: config.namespace.clone(),
+            database: config.database.clone(),
+            db_pool_size: config.db.pool_size,
+            db_query_timeout_secs: config.db.query_timeout.map(|timeout| timeout.as_secs()),
+            public_url: config.instance.public_url.clone(),
+            region: config.instance.region.clone(),
+            public_stats: config.features.public_stats,
+            dev_auth: config.dev_auth.enabled,
+            read_only: config.read_only.enabled,
+            persisted_queries_only: config.persisted_queries.allow_list.is_some(),
+            media_dir: config
+                .media
+                .dir
+                .as_ref()
+                .map(|dir| dir.display().to_string()),
+            max_concurrency: config.overload.max_concurrency,
+            limits: (&config.limits).into(),
+            oidc_providers: config
+                .oidc
+                .providers
+                .iter()
+                .map(|provider| provider.id.clone())
+                .collect(),
+            translation_provider: config
+                .translation
+                .provider
+                .as_ref()
+                .map(|provider| provider.name().into()),
+            proxy_remote_content: config.proxy.enabled,
+        }
+    }
+}
+
+/// The last migration that a subsystem ran.
+#[derive(SimpleObject, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct MigrationVersion {
+    pub subsystem: String,
+    /// `None` if the subsystem hasn't run any migrations.
+    pub version: Option<String>,
+}
+
+impl CapabilityReport {
+    #[must_use]
+    pub fn new(
+        config: ConfigSummary,
+        migrations: Vec<(&'static str, Option<String>)>,
+        address: SocketAddr,
+    ) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").into(),
+            features: FEATURES
+                .iter()
+                .filter(|(_, enabled)| *enabled)
+                .map(|(name, _)| (*name).into())
+                .collect(),
+            config,
+            schema_hash: schema_hash(),
+            migrations: migrations
+                .into_iter()
+                .map(|(subsystem, version)| MigrationVersion {
+                    subsystem: subsystem.into(),
+                    version,
+                })
+                .collect(),
+            address: address.to_string(),
+            endpoints: PATHS
+                .iter()
+                .map(|path| format!("http://{address}{path}"))
+                .collect(),
+        }
+    }
+}
+
+/// Hashes the GraphQL schema's SDL.
+#[must_use]
+pub fn schema_hash() -> String {
+    hex::encode(digest::digest(
+        &digest::SHA256,
+        schema(|s| s).sdl().as_bytes(),
+    ))
+}
+
+/// Takes the credentials out of a database address, if it has any.
+#[must_use]
+pub fn redact_address(address: &str) -> String {
+    let Some((scheme, rest)) = address.split_once("://") else {
+        return address.into();
+    };
+    let authority_end = rest.find('/').unwrap_or(rest.len());
+    match rest[..authority_end].rfind('@') {
+        Some(at) => format!("{scheme}://[redacted]{}", &rest[at..]),
+        None => address.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_address() {
+        assert_eq!(redact_address("mem://"), "mem://");
+        assert_eq!(redact_address("file://data/db"), "file://data/db");
+        assert_eq!(
+            redact_address("ws://root:hunter2@db.example:8000"),
+            "ws://[redacted]@db.example:8000"
+        );
+        assert_eq!(
+            redact_address("wss://user@db.example/rpc@x"),
+            "wss://[redacted]@db.example/rpc@x"
+        );
+    }
+
+    #[test]
+    fn test_schema_hash() {
+        let hash = schema_hash();
+        assert_eq!(hash.len(), 64);
+        assert_eq!(hash, schema_hash());
+    }
+}
@@ -0,0 +1,37 @@
+use serde::{Deserialize, Serialize};
+
+use super::CLIENT_STATE_TABLE_NAME;
+use crate::{migration::Migration, prelude::*};
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ClientStateMigration {
+    #[default]
+    Init,
+}
+
+impl Migration for ClientStateMigration {
+    const SUBSYSTEM: &'static str = "subsys_client_state";
+
+    fn next(self) -> Option<Self> {
+        match self {
+            Self::Init => None,
+        }
+    }
+
+    fn build(&self, statements: &mut Vec<srql::Statement>) {
+        use ClientStateMigration as S;
+        match self {
+            S::Init => Self::build_init(statements),
+        }
+    }
+}
+
+impl ClientStateMigration {
+    fn build_init(statements: &mut Vec<srql::Statement>) {
+        statements.push(srql::define_uniq_index(
+            "client_state_account_key_index",
+            CLIENT_STATE_TABLE_NAME,
+            [srql::field("account_id"), srql::field("key")],
+        ));
+    }
+}
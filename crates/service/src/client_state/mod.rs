@@ -0,0 +1,28 @@
+//! Small pieces of client state, such as compose drafts, scroll positions and
+//! last-viewed markers, synced between an account's devices.
+//!
+//! Each key holds an opaque string. Devices send back the version vector they
+//! last saw with each change, so that the server can tell when two devices
+//! changed a key without seeing each other's changes. When that happens the
+//! change that was made last wins, and the device is told about the conflict
+//! so that it can merge the values itself if it wants to.
+
+mod migration;
+mod models;
+mod persist;
+mod schema;
+
+pub use migration::*;
+pub use models::*;
+pub use persist::*;
+pub use schema::*;
+
+use crate::feed::Feed;
+
+/// Tells subscribers about changes to client state as they're made.
+pub type ClientStateFeed = Feed<ClientState>;
+
+pub static CLIENT_STATE_TABLE_NAME: &str = "client_state";
+
+/// The most keys that each account can store.
+pub const MAX_CLIENT_STATE_KEYS: usize = 256;
@@ -0,0 +1,170 @@
+use std::collections::BTreeMap;
+
+use async_graphql::{ComplexObject, InputObject, SimpleObject, ID};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use surrealdb::sql::Thing;
+
+use crate::{id_obj_impls, prelude::*};
+
+/// How many changes a device has made to a piece of client state.
+#[derive(SimpleObject, InputObject, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[graphql(input_name = "VectorHintInput")]
+pub struct VectorHint {
+    /// The ID that the device chose for itself.
+    pub device_id: String,
+    /// How many of the device's changes have been seen.
+    pub counter: u64,
+}
+
+impl QueryValue for Vec<VectorHint> {
+    fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
+        srql::to_value(self)
+            .ok()
+            .map(|value| (field, srql::Operator::Equal, value))
+    }
+}
+
+/// Whether the changes described by `seen` include every change described
+/// by `vector`.
+pub(super) fn dominates(seen: &[VectorHint], vector: &[VectorHint]) -> bool {
+    vector.iter().all(|hint| {
+        seen.iter()
+            .any(|seen| seen.device_id == hint.device_id && seen.counter >= hint.counter)
+    })
+}
+
+/// Combines two version vectors, then counts a new change by `device_id`.
+pub(super) fn advance(
+    seen: &[VectorHint],
+    vector: &[VectorHint],
+    device_id: &str,
+) -> Vec<VectorHint> {
+    let mut merged = BTreeMap::new();
+    for hint in seen.iter().chain(vector) {
+        let counter = merged.entry(hint.device_id.as_str()).or_insert(0);
+        *counter = hint.counter.max(*counter);
+    }
+    *merged.entry(device_id).or_insert(0) += 1;
+
+    merged
+        .into_iter()
+        .map(|(device_id, counter)| VectorHint {
+            device_id: device_id.to_owned(),
+            counter,
+        })
+        .collect()
+}
+
+/// A piece of state that an account's clients keep in sync.
+#[derive(SimpleObject, Debug, Clone, Deserialize)]
+#[graphql(complex)]
+pub struct ClientState {
+    #[graphql(skip)]
+    pub id: Thing,
+    #[graphql(skip)]
+    pub account_id: Thing,
+
+    /// The key that the state is stored under.
+    pub key: String,
+    /// The stored value, or null if it has been deleted.
+    pub value: Option<String>,
+    /// How many times the value has been changed.
+    pub version: u64,
+    /// The device that made the latest change.
+    pub device_id: String,
+    /// How many changes each device has made. Send this back as `seen` with
+    /// the next change.
+    pub vector: Vec<VectorHint>,
+    /// When the latest change was made, according to the device that made it.
+    pub edited_at: DateTime<Utc>,
+    /// A timestamp indicating the last time the state was stored.
+    pub updated_at: DateTime<Utc>,
+}
+
+#[ComplexObject]
+impl ClientState {
+    /// The client state's unique ID.
+    async fn id(&self) -> ID {
+        self.id.to_gql_id()
+    }
+}
+
+id_obj_impls!(ClientState);
+
+/// A change to a piece of client state.
+#[derive(InputObject, Debug, Clone, PartialEq, Eq)]
+pub struct SetClientState {
+    /// The key to store the state under.
+    #[graphql(validator(min_length = 1, max_length = 128))]
+    pub key: String,
+    /// The new value. If null is given, the value is deleted, and other
+    /// devices are told about that like any other change.
+    #[graphql(validator(max_length = 65_536))]
+    pub value: Option<String>,
+    /// An ID that the device chose for itself, which stays the same between
+    /// changes.
+    #[graphql(validator(min_length = 1, max_length = 64))]
+    pub device_id: String,
+    /// The `vector` of the state that the device last saw. Leave this empty
+    /// if the device has never seen the key.
+    #[graphql(default)]
+    pub seen: Vec<VectorHint>,
+    /// When the device made the change, if it's being sent later than that.
+    /// Defaults to now, and times in the future are treated as now.
+    pub edited_at: Option<DateTime<Utc>>,
+}
+
+/// The outcome of changing a piece of client state.
+#[derive(SimpleObject, Debug, Clone, PartialEq, Eq)]
+pub struct SetClientStateResult {
+    /// The state as it is now stored.
+    pub state: ClientState,
+    /// Whether the change was stored. It isn't if it conflicted with a change
+    /// that was made after it.
+    pub accepted: bool,
+    /// Whether another device changed the state without the change being
+    /// seen first.
+    pub conflict: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn hints(hints: &[(&str, u64)]) -> Vec<VectorHint> {
+        hints
+            .iter()
+            .map(|(device_id, counter)| VectorHint {
+                device_id: (*device_id).to_owned(),
+                counter: *counter,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_dominates() {
+        assert!(dominates(&[], &[]));
+        assert!(dominates(&hints(&[("a", 2)]), &hints(&[("a", 1)])));
+        assert!(dominates(
+            &hints(&[("a", 1), ("b", 1)]),
+            &hints(&[("a", 1)])
+        ));
+        assert!(!dominates(&hints(&[("a", 1)]), &hints(&[("a", 2)])));
+        assert!(!dominates(
+            &hints(&[("a", 1)]),
+            &hints(&[("a", 1), ("b", 1)])
+        ));
+    }
+
+    #[test]
+    fn test_advance() {
+        assert_eq!(advance(&[], &[], "a"), hints(&[("a", 1)]));
+        assert_eq!(
+            advance(&hints(&[("a", 1)]), &hints(&[("a", 2), ("b", 1)]), "b"),
+            hints(&[("a", 2), ("b", 2)])
+        );
+    }
+}
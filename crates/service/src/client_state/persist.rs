@@ -0,0 +1,199 @@
+#[cfg(test)]
+mod tests;
+
+use async_graphql::MaybeUndefined;
+use futures::Stream;
+use tracing::instrument;
+
+use super::{
+    advance, dominates, ClientState, SetClientState, SetClientStateResult, CLIENT_STATE_TABLE_NAME,
+    MAX_CLIENT_STATE_KEYS,
+};
+use crate::{account::CurrentAccount, persist::Persist, prelude::*, query::SRQL_ORDER_ASC};
+
+pub struct ClientStatePersist<'a> {
+    persist: &'a Persist,
+    current: &'a CurrentAccount,
+}
+
+impl<'a> ClientStatePersist<'a> {
+    pub fn new(persist: &'a Persist, current: &'a CurrentAccount) -> Self {
+        Self { persist, current }
+    }
+
+    /// Gets one of the current account's pieces of client state.
+    #[instrument(skip_all)]
+    pub async fn get(&self, key: &str) -> Result<Option<ClientState>> {
+        let account_id = self.current.id()?.to_account_thing();
+        let states = self.select(account_id, Some(&[key.to_owned()])).await?;
+        Ok(states.into_iter().next())
+    }
+
+    /// Lists the current account's client state, by key. When `keys` is
+    /// given, only those keys are listed.
+    #[instrument(skip_all)]
+    pub async fn list(&self, keys: Option<&[String]>) -> Result<Vec<ClientState>> {
+        let account_id = self.current.id()?.to_account_thing();
+        self.select(account_id, keys).await
+    }
+
+    /// Changes a piece of the current account's client state.
+    ///
+    /// If the device hadn't seen the latest change to the state, the change
+    /// that was made last is kept.
+    #[instrument(skip_all)]
+    pub async fn set(&self, set: SetClientState) -> Result<SetClientStateResult> {
+        let account_id = self.current.id()?.to_account_thing();
+        let now = self.persist.clock().now();
+        let edited_at = set.edited_at.map_or(now, |edited_at| edited_at.min(now));
+
+        let existing = self.get(&set.key).await?;
+        let (query, conflict) = if let Some(existing) = existing {
+            let conflict = !dominates(&set.seen, &existing.vector);
+            if conflict
+                && (edited_at, set.device_id.as_str())
+                    <= (existing.edited_at, existing.device_id.as_str())
+            {
+                return Ok(SetClientStateResult {
+                    state: existing,
+                    accepted: false,
+                    conflict,
+                });
+            }
+
+            let mut update = vec![];
+            set.value
+                .map_or(MaybeUndefined::Null, MaybeUndefined::Value)
+                .push_field(srql::field("value"), &mut update);
+            (existing.version + 1).push_field(srql::field("version"), &mut update);
+            advance(&set.seen, &existing.vector, &set.device_id)
+                .push_field(srql::field("vector"), &mut update);
+            set.device_id
+                .push_field(srql::field("device_id"), &mut update);
+            edited_at.push_field(srql::field("edited_at"), &mut update);
+            let Some(update) = srql::obj_update_query(existing.id, update) else {
+                return Err("".into());
+            };
+            (srql::Statement::Update(update), conflict)
+        } else {
+            let count: Option<usize> = self
+                .persist
+                .db()
+                .query(srql::count_query(
+                    CLIENT_STATE_TABLE_NAME,
+                    owned_by(account_id.clone()).into(),
+                ))
+                .await?
+                .take("count")?;
+            if count.unwrap_or_default() >= MAX_CLIENT_STATE_KEYS {
+                return Err(Error::QuotaExceeded("client state".into()));
+            }
+
+            let mut create = vec![];
+            account_id.push_field(srql::field("account_id"), &mut create);
+            advance(&set.seen, &[], &set.device_id).push_field(srql::field("vector"), &mut create);
+            set.key.push_field(srql::field("key"), &mut create);
+            set.value.push_field(srql::field("value"), &mut create);
+            1u64.push_field(srql::field("version"), &mut create);
+            set.device_id
+                .push_field(srql::field("device_id"), &mut create);
+            edited_at.push_field(srql::field("edited_at"), &mut create);
+            let create =
+                srql::obj_create_query(CLIENT_STATE_TABLE_NAME, create, self.persist.ids());
+            (srql::Statement::Create(create), false)
+        };
+
+        let state: Option<ClientState> = self.persist.db().query(query).await?.take(0)?;
+        let Some(state) = state else {
+            return Err(Error::UnavailableIdent);
+        };
+        self.persist.client_state_feed().publish(&state);
+
+        Ok(SetClientStateResult {
+            state,
+            accepted: true,
+            conflict,
+        })
+    }
+
+    /// Streams changes to the current account's client state as they're
+    /// made. When `keys` is given, only changes to those keys are sent.
+    pub fn subscribe(
+        &self,
+        keys: Option<Vec<String>>,
+    ) -> Result<impl Stream<Item = ClientState> + Send + 'static> {
+        let account_id = self.current.id()?.to_account_thing();
+        Ok(self.persist.client_state_feed().subscribe(move |state| {
+            state.account_id == account_id
+                && keys.as_ref().is_none_or(|keys| keys.contains(&state.key))
+        }))
+    }
+
+    async fn select(
+        &self,
+        account_id: srql::Thing,
+        keys: Option<&[String]>,
+    ) -> Result<Vec<ClientState>> {
+        let keys_cond = keys.map(|keys| {
+            srql::Cond(
+                srql::Expression::Binary {
+                    l: srql::field("key").into(),
+                    o: srql::Operator::Inside,
+                    r: srql::array(
+                        keys.iter()
+                            .map(|key| srql::string(key.as_str()).into())
+                            .collect::<Vec<srql::Value>>(),
+                    ),
+                }
+                .into(),
+            )
+        });
+
+        let states = self
+            .persist
+            .db()
+            .query(srql::SelectStatement {
+                expr: srql::Fields::all(),
+                what: srql::table(CLIENT_STATE_TABLE_NAME),
+                cond: srql::cond_and(owned_by(account_id).into(), keys_cond),
+                order: srql::Orders(vec![srql::Order {
+                    order: srql::field("key"),
+                    direction: SRQL_ORDER_ASC,
+                    ..Default::default()
+                }])
+                .into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        Ok(states)
+    }
+}
+
+fn owned_by(account_id: srql::Thing) -> srql::Cond {
+    srql::Cond(
+        srql::Expression::Binary {
+            l: srql::field("account_id").into(),
+            o: srql::Operator::Equal,
+            r: account_id.into(),
+        }
+        .into(),
+    )
+}
+
+#[cfg(test)]
+pub mod testing {
+    use crate::account::testing::TestData;
+
+    use super::ClientStatePersist;
+
+    pub trait ClientStateTestData {
+        fn client_state(&self) -> ClientStatePersist<'_>;
+    }
+
+    impl ClientStateTestData for TestData {
+        fn client_state(&self) -> ClientStatePersist<'_> {
+            ClientStatePersist::new(&self.persist, &self.current)
+        }
+    }
+}
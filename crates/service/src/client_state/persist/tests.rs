@@ -0,0 +1,184 @@
+use chrono::Duration;
+use futures::StreamExt as _;
+use pretty_assertions::assert_eq;
+
+use super::{testing::ClientStateTestData as _, *};
+use crate::{account::testing::*, client_state::VectorHint, provider::MockClock};
+
+fn set(key: &str, value: &str, device_id: &str, seen: &[VectorHint]) -> SetClientState {
+    SetClientState {
+        key: key.into(),
+        value: Some(value.into()),
+        device_id: device_id.into(),
+        seen: seen.to_vec(),
+        edited_at: None,
+    }
+}
+
+#[tokio::test]
+async fn test_set_and_get() {
+    let (data, _) = TestData::with_user().await;
+    let client_state = data.client_state();
+
+    let res = client_state
+        .set(set("draft", "Hello", "phone", &[]))
+        .await
+        .unwrap();
+    assert!(res.accepted);
+    assert!(!res.conflict);
+    assert_eq!(res.state.version, 1);
+
+    // Changes made after seeing the latest one always win.
+    let res = client_state
+        .set(set("draft", "Hello, world", "laptop", &res.state.vector))
+        .await
+        .unwrap();
+    assert!(res.accepted);
+    assert!(!res.conflict);
+    assert_eq!(res.state.version, 2);
+    assert_eq!(res.state.device_id, "laptop");
+    assert_eq!(
+        res.state.vector,
+        vec![
+            VectorHint {
+                device_id: "laptop".into(),
+                counter: 1,
+            },
+            VectorHint {
+                device_id: "phone".into(),
+                counter: 1,
+            },
+        ]
+    );
+
+    let state = client_state.get("draft").await.unwrap().unwrap();
+    assert_eq!(state.value.as_deref(), Some("Hello, world"));
+    assert_eq!(client_state.get("other").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_conflicts() {
+    let clock = MockClock::default();
+    let mut data = TestData::with_clock(clock.clone()).await;
+    let acc = data.account().create_test_user().await;
+    data.login_as(&acc);
+
+    let first = data
+        .client_state()
+        .set(set("draft", "Original", "phone", &[]))
+        .await
+        .unwrap()
+        .state;
+    let started_offline = data.persist.clock().now();
+    clock.advance(Duration::minutes(1));
+    data.client_state()
+        .set(set("draft", "From the laptop", "laptop", &first.vector))
+        .await
+        .unwrap();
+
+    // The phone made its change before the laptop did, without seeing it.
+    clock.advance(Duration::minutes(1));
+    let res = data
+        .client_state()
+        .set(SetClientState {
+            edited_at: Some(started_offline),
+            ..set("draft", "From the phone", "phone", &first.vector)
+        })
+        .await
+        .unwrap();
+    assert!(!res.accepted);
+    assert!(res.conflict);
+    assert_eq!(res.state.value.as_deref(), Some("From the laptop"));
+
+    // Changes made later than the one they didn't see still win.
+    let res = data
+        .client_state()
+        .set(set("draft", "Newer from the phone", "phone", &first.vector))
+        .await
+        .unwrap();
+    assert!(res.accepted);
+    assert!(res.conflict);
+    assert_eq!(res.state.value.as_deref(), Some("Newer from the phone"));
+    assert_eq!(res.state.version, 3);
+}
+
+#[tokio::test]
+async fn test_delete_and_list() {
+    let (mut data, _) = TestData::with_user().await;
+    for key in ["b", "a", "c"] {
+        data.client_state()
+            .set(set(key, "Value", "phone", &[]))
+            .await
+            .unwrap();
+    }
+
+    let b = data.client_state().get("b").await.unwrap().unwrap();
+    let res = data
+        .client_state()
+        .set(SetClientState {
+            value: None,
+            ..set("b", "", "phone", &b.vector)
+        })
+        .await
+        .unwrap();
+    assert_eq!(res.state.value, None);
+
+    let keys = |states: Vec<ClientState>| -> Vec<String> {
+        states.into_iter().map(|state| state.key).collect()
+    };
+    let states = data.client_state().list(None).await.unwrap();
+    assert_eq!(keys(states), vec!["a", "b", "c"]);
+    let states = data
+        .client_state()
+        .list(Some(&["c".into(), "a".into()]))
+        .await
+        .unwrap();
+    assert_eq!(keys(states), vec!["a", "c"]);
+
+    // Other accounts have their own state.
+    let other = data.account().create_test_user().await;
+    data.login_as(&other);
+    assert!(data.client_state().list(None).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_subscribe() {
+    let (mut data, acc) = TestData::with_user().await;
+    let mut changes = Box::pin(
+        data.client_state()
+            .subscribe(Some(vec!["draft".into()]))
+            .unwrap(),
+    );
+
+    data.client_state()
+        .set(set("scroll", "100", "phone", &[]))
+        .await
+        .unwrap();
+    let other = data.account().create_test_user().await;
+    data.login_as(&other);
+    data.client_state()
+        .set(set("draft", "Not mine", "phone", &[]))
+        .await
+        .unwrap();
+    data.login_as(&acc);
+    data.client_state()
+        .set(set("draft", "Mine", "phone", &[]))
+        .await
+        .unwrap();
+
+    let change = changes.next().await.unwrap();
+    assert_eq!(change.account_id, acc.id);
+    assert_eq!(change.value.as_deref(), Some("Mine"));
+}
+
+#[tokio::test]
+async fn test_anonymous() {
+    let (mut data, _) = TestData::with_user().await;
+    data.current = CurrentAccount::default();
+    let res = data
+        .client_state()
+        .set(set("draft", "Hi", "phone", &[]))
+        .await;
+    assert_eq!(res.unwrap_err(), Error::Unauthenticated);
+    assert!(data.client_state().subscribe(None).is_err());
+}
@@ -0,0 +1,70 @@
+use async_graphql::{Context, Object, Subscription};
+use futures::Stream;
+use tracing::instrument;
+
+use super::{ClientState, SetClientState, SetClientStateResult};
+use crate::prelude::*;
+
+#[derive(Default)]
+pub struct ClientStateQuery;
+
+#[Object]
+impl ClientStateQuery {
+    /// Gets one of the current account's pieces of client state.
+    #[instrument(skip_all)]
+    async fn client_state(&self, ctx: &Context<'_>, key: String) -> GqlResult<Option<ClientState>> {
+        ctx.client_state_persist().get(&key).await.extend()
+    }
+
+    /// Lists the current account's client state, by key. When `keys` is
+    /// given, only those keys are listed.
+    #[instrument(skip_all)]
+    async fn client_states(
+        &self,
+        ctx: &Context<'_>,
+        keys: Option<Vec<String>>,
+    ) -> GqlResult<Vec<ClientState>> {
+        ctx.client_state_persist()
+            .list(keys.as_deref())
+            .await
+            .extend()
+    }
+}
+
+#[derive(Default)]
+pub struct ClientStateMutation;
+
+#[Object]
+impl ClientStateMutation {
+    /// Changes a piece of the current account's client state, and tells the
+    /// account's other devices about it.
+    ///
+    /// If the device hadn't seen the latest change to the state, the change
+    /// that was made last is kept.
+    #[instrument(skip_all)]
+    async fn set_client_state(
+        &self,
+        ctx: &Context<'_>,
+        set: SetClientState,
+    ) -> GqlResult<SetClientStateResult> {
+        ctx.client_state_persist().set(set).await.extend()
+    }
+}
+
+#[derive(Default)]
+pub struct ClientStateSubscription;
+
+#[Subscription]
+impl ClientStateSubscription {
+    /// Sends changes to the current account's client state as they're made,
+    /// including those made by the same device. When `keys` is given, only
+    /// changes to those keys are sent.
+    #[allow(clippy::unused_async)]
+    async fn client_state_changed(
+        &self,
+        ctx: &Context<'_>,
+        keys: Option<Vec<String>>,
+    ) -> async_graphql::Result<impl Stream<Item = ClientState>> {
+        ctx.client_state_persist().subscribe(keys).extend()
+    }
+}
@@ -0,0 +1,306 @@
+//! Turning away versions of apps that are too old to work with the instance.
+//!
+//! Clients say which app they are in `X-Client-Name`, `X-Client-Version` and
+//! `X-Client-Platform` headers, or in a `clientInfo` object in the GraphQL
+//! WebSocket init (see [`ClientApp`]). Each app can have an oldest version
+//! that's still supported, which admins can change while the instance is
+//! running. Requests from older versions fail with a `ClientOutdated` error
+//! naming it, so that the app can ask to be updated rather than break in
+//! stranger ways. Clients that don't say which app they are aren't checked.
+//!
+//! Versions are compared by their dot-separated numbers, so `1.10` is newer
+//! than `1.9`, and anything after a `-` or `+` is ignored.
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, PoisonError, RwLock},
+};
+
+use async_graphql::{
+    extensions::{Extension, ExtensionContext, ExtensionFactory, NextParseQuery},
+    parser::types::ExecutableDocument,
+    ErrorExtensions as _, Pos, ServerResult, SimpleObject, Variables,
+};
+use axum::{extract::State, http::Request, middleware::Next, response::IntoResponse as _};
+use tracing::debug;
+
+use crate::{
+    config::ClientVersionConfig,
+    error::ErrorResponse,
+    prelude::*,
+    session::{ClientApp, ClientMeta},
+};
+
+/// Parses the numbers at the start of a version, such as `[1, 2, 0]` for
+/// `v1.2.0-beta`.
+#[must_use]
+pub fn parse_version(version: &str) -> Option<Vec<u64>> {
+    let version = version.trim();
+    let version = version.strip_prefix('v').unwrap_or(version);
+    let version = version.split(['-', '+']).next()?;
+    version.split('.').map(|part| part.parse().ok()).collect()
+}
+
+/// Whether a version is older than another. Missing numbers count as 0, so
+/// `1.2` is the same as `1.2.0`.
+fn is_older(version: &[u64], than: &[u64]) -> bool {
+    let len = version.len().max(than.len());
+    let padded = |version: &[u64]| {
+        let mut version = version.to_vec();
+        version.resize(len, 0);
+        version
+    };
+    padded(version) < padded(than)
+}
+
+/// The oldest version of an app that the instance still supports.
+#[derive(SimpleObject, Debug, Clone, PartialEq, Eq)]
+pub struct MinClientVersion {
+    /// The app's name, as it gives it in `X-Client-Name`.
+    pub name: String,
+    /// The oldest version that can still be used.
+    pub min_version: String,
+}
+
+/// The oldest supported version of each app, shared between everything that
+/// checks them.
+#[derive(Clone, Default)]
+pub struct ClientVersions {
+    min_versions: Arc<RwLock<BTreeMap<String, String>>>,
+}
+
+impl ClientVersions {
+    #[must_use]
+    pub fn new(config: ClientVersionConfig) -> Self {
+        Self {
+            min_versions: Arc::new(RwLock::new(config.min_versions)),
+        }
+    }
+
+    /// The oldest supported version of each app, by name.
+    pub fn list(&self) -> Vec<MinClientVersion> {
+        self.min_versions
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(|(name, min_version)| MinClientVersion {
+                name: name.clone(),
+                min_version: min_version.clone(),
+            })
+            .collect()
+    }
+
+    /// Sets the oldest supported version of an app, or lets every version of
+    /// it be used when there isn't one.
+    pub fn set(&self, name: &str, min_version: Option<&str>) -> Result<()> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(Error::InputInvalid("App name can't be empty".into()));
+        }
+
+        let mut min_versions = self
+            .min_versions
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        match min_version.map(str::trim) {
+            Some(min_version) => {
+                if parse_version(min_version).is_none() {
+                    return Err(Error::InputInvalid(format!(
+                        "{min_version} isn't a version"
+                    )));
+                }
+                min_versions.insert(name.to_owned(), min_version.to_owned());
+            }
+            None => {
+                min_versions.remove(name);
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that an app's version is still supported. Versions that can't
+    /// be parsed are let through, as there's no telling how old they are.
+    pub fn check(&self, app: Option<&ClientApp>) -> Result<()> {
+        let Some(app) = app else {
+            return Ok(());
+        };
+        let min_versions = self
+            .min_versions
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        let Some(min_version) = min_versions.get(&app.name) else {
+            return Ok(());
+        };
+        let outdated = parse_version(&app.version)
+            .zip(parse_version(min_version))
+            .is_some_and(|(version, min)| is_older(&version, &min));
+        if outdated {
+            debug!(
+                app = app.name,
+                version = app.version,
+                min_version,
+                "Turning away outdated client"
+            );
+            return Err(Error::ClientOutdated(min_version.clone()));
+        }
+        Ok(())
+    }
+}
+
+/// A GraphQL extension that turns away requests from versions of apps that
+/// are no longer supported.
+pub struct ClientVersionGuard(pub ClientVersions);
+
+impl ExtensionFactory for ClientVersionGuard {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(ClientVersionExtension {
+            versions: self.0.clone(),
+        })
+    }
+}
+
+struct ClientVersionExtension {
+    versions: ClientVersions,
+}
+
+#[async_trait::async_trait]
+impl Extension for ClientVersionExtension {
+    async fn parse_query(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        query: &str,
+        variables: &Variables,
+        next: NextParseQuery<'_>,
+    ) -> ServerResult<ExecutableDocument> {
+        let app = ctx
+            .data_opt::<ClientMeta>()
+            .and_then(|client| client.app.as_ref());
+        self.versions
+            .check(app)
+            .map_err(|err| err.extend().into_server_error(Pos::default()))?;
+        next.run(ctx, query, variables).await
+    }
+}
+
+/// Middleware that turns away REST requests from versions of apps that are
+/// no longer supported.
+pub async fn require_client_version<B>(
+    State(versions): State<ClientVersions>,
+    client: ClientMeta,
+    req: Request<B>,
+    next: Next<B>,
+) -> axum::response::Response {
+    if let Err(err) = versions.check(client.app.as_ref()) {
+        let res: ErrorResponse = err.into();
+        return res.into_response();
+    }
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use async_graphql::Value as GqlValue;
+    use axum::http::HeaderMap;
+    use pretty_assertions::assert_eq;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::schema::schema;
+
+    fn app(name: &str, version: &str) -> ClientApp {
+        ClientApp::new(name, version, None).unwrap()
+    }
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(parse_version("1.2.3"), Some(vec![1, 2, 3]));
+        assert_eq!(parse_version(" v2.0-beta.1 "), Some(vec![2, 0]));
+        assert_eq!(parse_version("3+build.5"), Some(vec![3]));
+        assert_eq!(parse_version("1.x"), None);
+        assert_eq!(parse_version(""), None);
+
+        assert!(is_older(&[1, 9], &[1, 10]));
+        assert!(!is_older(&[1, 2], &[1, 2, 0]));
+        assert!(!is_older(&[2], &[1, 99, 99]));
+    }
+
+    #[test]
+    fn test_client_app() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-client-name", " plazer-ios ".parse().unwrap());
+        headers.insert("x-client-version", "2.0.1".parse().unwrap());
+        assert_eq!(
+            ClientApp::from_headers(&headers),
+            Some(app("plazer-ios", "2.0.1"))
+        );
+        headers.insert("x-client-platform", "ios".parse().unwrap());
+        assert_eq!(
+            ClientApp::from_headers(&headers)
+                .unwrap()
+                .platform
+                .as_deref(),
+            Some("ios")
+        );
+        headers.remove("x-client-version");
+        assert_eq!(ClientApp::from_headers(&headers), None);
+
+        let init = json!({
+            "token": "abc",
+            "clientInfo": { "name": "plazer-web", "version": "1.0" },
+        });
+        assert_eq!(ClientApp::from_init(&init), Some(app("plazer-web", "1.0")));
+        assert_eq!(ClientApp::from_init(&json!({ "token": "abc" })), None);
+        assert_eq!(ClientApp::from_init(&Value::Null), None);
+    }
+
+    #[test]
+    fn test_check() {
+        let versions = ClientVersions::new(ClientVersionConfig {
+            min_versions: BTreeMap::from([("plazer-ios".to_owned(), "2.1".to_owned())]),
+        });
+
+        assert!(versions.check(None).is_ok());
+        assert!(versions.check(Some(&app("plazer-ios", "2.1.0"))).is_ok());
+        assert!(versions.check(Some(&app("plazer-ios", "10.0"))).is_ok());
+        assert!(versions.check(Some(&app("plazer-ios", "nightly"))).is_ok());
+        assert!(versions.check(Some(&app("plazer-web", "0.1"))).is_ok());
+        assert!(matches!(
+            versions.check(Some(&app("plazer-ios", "2.0.9"))),
+            Err(Error::ClientOutdated(min_version)) if min_version == "2.1"
+        ));
+
+        assert!(versions.set("plazer-ios", Some("soon")).is_err());
+        versions.set("plazer-web", Some("1.0")).unwrap();
+        versions.set("plazer-ios", None).unwrap();
+        assert_eq!(
+            versions.list(),
+            vec![MinClientVersion {
+                name: "plazer-web".into(),
+                min_version: "1.0".into(),
+            }]
+        );
+        assert!(versions.check(Some(&app("plazer-ios", "2.0.9"))).is_ok());
+        assert!(versions.check(Some(&app("plazer-web", "0.1"))).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_guard() {
+        let versions = ClientVersions::default();
+        versions.set("plazer-ios", Some("2.1")).unwrap();
+        let schema = schema(|s| s.extension(ClientVersionGuard(versions.clone())));
+        let request = |version: &str| {
+            async_graphql::Request::new("{ __typename }").data(ClientMeta {
+                app: Some(app("plazer-ios", version)),
+                ..Default::default()
+            })
+        };
+
+        let res = schema.execute(request("2.0")).await;
+        let ext = res.errors[0].extensions.as_ref().unwrap();
+        assert_eq!(ext.get("code"), Some(&GqlValue::from("ClientOutdated")));
+        assert_eq!(ext.get("minVersion"), Some(&GqlValue::from("2.1")));
+
+        let res = schema.execute(request("2.1")).await;
+        assert!(res.errors.is_empty(), "{:?}", res.errors);
+    }
+}
@@ -0,0 +1,141 @@
+//! Limits on how much work a single GraphQL query can ask for.
+//!
+//! Every field selected in a query costs 1, plus whatever is selected on it.
+//! Fields that list a page of a connection cost what is selected on each
+//! item for every item that can be on the page, and fields that count or
+//! scan whole tables cost [`SCAN_COMPLEXITY`]. Queries that cost more than
+//! the configured complexity, or that nest fields deeper than the configured
+//! depth, are turned away with a `QueryTooComplex` error reporting both
+//! before anything is resolved, so that clients can ask for less.
+
+use std::sync::Arc;
+
+use async_graphql::{
+    extensions::{Extension, ExtensionContext, ExtensionFactory, NextValidation},
+    ErrorExtensions as _, Pos, ServerError, ValidationResult,
+};
+use tracing::debug;
+
+use crate::{config::QueryLimitsConfig, prelude::*};
+
+/// The cost of a field that counts or scans a whole table, rather than
+/// looking up records by ID or listing a page of them.
+pub const SCAN_COMPLEXITY: usize = 100;
+
+/// A GraphQL extension that turns away queries that are too complex or too
+/// deep.
+pub struct ComplexityGuard(pub QueryLimitsConfig);
+
+impl ExtensionFactory for ComplexityGuard {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(ComplexityExtension(self.0.clone()))
+    }
+}
+
+struct ComplexityExtension(QueryLimitsConfig);
+
+#[async_trait::async_trait]
+impl Extension for ComplexityExtension {
+    async fn validation(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        next: NextValidation<'_>,
+    ) -> std::result::Result<ValidationResult, Vec<ServerError>> {
+        let res = next.run(ctx).await?;
+        let limits = &self.0;
+        let exceeds = |value, max| max > 0 && value > max;
+        if exceeds(res.complexity, limits.max_complexity) || exceeds(res.depth, limits.max_depth) {
+            debug!(
+                complexity = res.complexity,
+                depth = res.depth,
+                "Turning away query that is too complex"
+            );
+            return Err(vec![Error::QueryTooComplex {
+                complexity: res.complexity,
+                max_complexity: limits.max_complexity,
+                depth: res.depth,
+                max_depth: limits.max_depth,
+            }
+            .extend()
+            .into_server_error(Pos::default())]);
+        }
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_graphql::Value;
+
+    use super::*;
+    use crate::schema::{schema, ServiceSchema};
+
+    fn limited(max_complexity: usize, max_depth: usize) -> ServiceSchema {
+        schema(|s| {
+            s.extension(ComplexityGuard(QueryLimitsConfig {
+                max_complexity,
+                max_depth,
+            }))
+        })
+    }
+
+    async fn error(schema: &ServiceSchema, query: &str) -> Option<async_graphql::ServerError> {
+        let res = schema.execute(query).await;
+        println!("{res:?}");
+        res.errors.into_iter().next()
+    }
+
+    fn extension(err: &async_graphql::ServerError, name: &str) -> Option<Value> {
+        err.extensions
+            .as_ref()
+            .and_then(|ext| ext.get(name))
+            .cloned()
+    }
+
+    #[tokio::test]
+    async fn test_complexity() {
+        // id costs 1, node 2, edges 3, and each of the 10 posts costs that.
+        let query = "{ posts(first: 10) { edges { node { id } } } }";
+        let schema = limited(29, 0);
+        let err = error(&schema, query).await.unwrap();
+        assert_eq!(
+            extension(&err, "code"),
+            Some(Value::from("QueryTooComplex"))
+        );
+        assert_eq!(extension(&err, "complexity"), Some(Value::from(30)));
+        assert_eq!(extension(&err, "maxComplexity"), Some(Value::from(29)));
+
+        // Without a page size, a page can have as many posts as are allowed.
+        let err = error(&schema, "{ posts { edges { node { id } } } }")
+            .await
+            .unwrap();
+        assert_eq!(extension(&err, "complexity"), Some(Value::from(300)));
+
+        // Public statistics count whole tables.
+        let err = error(&limited(100, 0), "{ instanceInfo { stats { posts } } }")
+            .await
+            .unwrap();
+        assert_eq!(
+            extension(&err, "complexity"),
+            Some(Value::from(2 + SCAN_COMPLEXITY))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_depth() {
+        let query = "{ posts { edges { node { id } } } }";
+        let err = error(&limited(0, 3), query).await.unwrap();
+        assert_eq!(
+            extension(&err, "code"),
+            Some(Value::from("QueryTooComplex"))
+        );
+        assert_eq!(extension(&err, "depth"), Some(Value::from(4)));
+        assert_eq!(extension(&err, "maxDepth"), Some(Value::from(3)));
+    }
+
+    #[tokio::test]
+    async fn test_within_limits() {
+        assert!(error(&limited(1, 1), "{ __typename }").await.is_none());
+        assert!(error(&limited(0, 0), "{ __typename }").await.is_none());
+    }
+}
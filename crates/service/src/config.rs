@@ -1,12 +1,43 @@
-use std::{env, fmt, fs, net::IpAddr, path::Path};
+use std::{
+    collections::{BTreeMap, HashMap},
+    env, fmt, fs,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
 
 use anyhow::Context as _;
 use cfg_if::cfg_if;
 use name_variant::NamedVariant;
-use ring::signature::{self, KeyPair as _};
+use ring::{
+    digest, hmac,
+    signature::{self, KeyPair as _},
+};
+use secrecy::SecretString;
 use serde::{Deserialize, Serialize};
 use tracing::Level;
 
+use crate::{
+    account::{HttpOidcClient, SharedOidcClient},
+    audit::{
+        FileAuditSink, HttpAuditSink, S3AuditSink, S3Bucket, SharedAuditSink, SyslogAuditSink,
+    },
+    client_version::parse_version,
+    credentials::{Argon2Hasher, SharedPasswordHasher},
+    email::{NoEmailSender, SharedEmailSender, SmtpEmailSender},
+    error::Error,
+    notification::{NoNotificationTransport, SharedNotificationTransport},
+    organization::{HttpDomainVerifier, SharedDomainVerifier},
+    persisted_query::query_hash,
+    provider::{SharedClock, SharedIdGen, SystemClock, UlidGen},
+    translation::{
+        DeeplProvider, LibreTranslateProvider, SharedTranslationProvider, StubTranslationProvider,
+    },
+    webhook::{HttpWebhookSender, SharedWebhookSender},
+};
+
 // Defaults
 
 pub static DEFAULT_ADDRESS: &str = "file:./data/db";
@@ -14,6 +45,7 @@ pub static DEFAULT_NAMESPACE: &str = "plazer";
 pub static DEFAULT_DATABASE: &str = "plazer";
 pub static DEFAULT_PRIVATE_KEY_PATH: &str = "./data/private_key.pem";
 pub static DEFAULT_LOG_DIR: &str = "./data/logs";
+pub static DEFAULT_MEDIA_DIR: &str = "./data/media";
 
 cfg_if! {
 
@@ -29,6 +61,69 @@ if #[cfg(debug_assertions)] {
 
 pub static DEFAULT_HOST: &str = "0.0.0.0";
 pub const DEFAULT_PORT: u16 = 8080;
+pub const DEFAULT_PUBLIC_STATS: bool = false;
+pub const DEFAULT_SPAM_REVIEW_THRESHOLD: u8 = 50;
+pub const DEFAULT_SPAM_LIMIT_THRESHOLD: u8 = 90;
+pub const DEFAULT_DEV_AUTH: bool = false;
+pub const DEFAULT_DEV_AUTH_ALLOW_RELEASE: bool = false;
+pub const DEFAULT_MAX_CONCURRENCY: usize = 1024;
+pub const DEFAULT_MAX_GRAPHQL_CONCURRENCY: usize = 512;
+pub const DEFAULT_MAX_QUEUE_MS: u64 = 1000;
+pub const DEFAULT_RATE_LIMIT_AUTH: u32 = 20;
+pub const DEFAULT_RATE_LIMIT_READ: u32 = 600;
+pub const DEFAULT_RATE_LIMIT_WRITE: u32 = 120;
+pub const DEFAULT_MAX_QUERY_COMPLEXITY: usize = 10_000;
+pub const DEFAULT_MAX_QUERY_DEPTH: usize = 16;
+pub const DEFAULT_PERSISTED_QUERY_CACHE_SIZE: usize = 1000;
+pub const DEFAULT_PERSIST_QUERIES: bool = false;
+pub const DEFAULT_MIN_AGE: u8 = 0;
+pub const DEFAULT_ALLOW_CONFUSABLE_USER_IDS: bool = false;
+pub const DEFAULT_REQUIRE_VERIFIED_EMAIL: bool = false;
+pub const DEFAULT_DELETION_GRACE_DAYS: u32 = 30;
+pub const DEFAULT_DORMANT_AFTER_DAYS: u32 = 0;
+pub const DEFAULT_DORMANCY_GRACE_DAYS: u32 = 30;
+pub const DEFAULT_RELEASE_DORMANT_USER_IDS: bool = false;
+pub static DEFAULT_EMAIL_FROM: &str = "noreply@localhost";
+pub const DEFAULT_IP_STORAGE: IpStorage = IpStorage::Truncated;
+pub const DEFAULT_METADATA_VISIBILITY: MetadataVisibility = MetadataVisibility::Owner;
+pub const DEFAULT_METADATA_RETENTION_DAYS: u32 = 30;
+pub const DEFAULT_QUOTA_POSTS: u32 = 0;
+pub const DEFAULT_QUOTA_BOARDS: u32 = 0;
+pub const DEFAULT_QUOTA_LISTS: u32 = 0;
+pub const DEFAULT_QUOTA_STORAGE_BYTES: u64 = 0;
+pub const DEFAULT_MAX_POST_TITLE_LENGTH: u32 = 1024;
+pub const DEFAULT_MAX_POST_CONTENT_LENGTH: u32 = 32_768;
+pub const DEFAULT_MAX_BOARD_NAME_LENGTH: u32 = 1024;
+pub const DEFAULT_MAX_BOARD_DESCRIPTION_LENGTH: u32 = 32_768;
+pub const DEFAULT_SIGNUP_HONEYPOT_SCORE: u8 = 100;
+pub const DEFAULT_SIGNUP_MIN_FORM_SECS: u32 = 3;
+pub const DEFAULT_SIGNUP_TOO_FAST_SCORE: u8 = 60;
+pub const DEFAULT_READ_ONLY: bool = false;
+pub const DEFAULT_READ_ONLY_AFTER_FAILURES: u32 = 5;
+pub const DEFAULT_READ_ONLY_COOLDOWN_SECS: u64 = 60;
+pub const DEFAULT_MAX_MEDIA_BYTES: u64 = 8_388_608;
+pub const DEFAULT_MEDIA_GC_GRACE_SECS: u64 = 86_400;
+pub const DEFAULT_MEDIA_URL_TTL_SECS: u64 = 3_600;
+pub const DEFAULT_ALT_TEXT_POLICY: AltTextPolicy = AltTextPolicy::Off;
+pub const DEFAULT_POST_VISIBILITY: PostVisibility = PostVisibility::Public;
+pub const DEFAULT_MAX_POST_VISIBILITY: PostVisibility = PostVisibility::Public;
+pub const DEFAULT_SESSION_IDLE_TIMEOUT_SECS: u64 = 0;
+pub const DEFAULT_SESSION_MAX_LIFETIME_SECS: u64 = 0;
+pub const DEFAULT_ADMIN_SESSION_IDLE_TIMEOUT_SECS: u64 = 0;
+pub const DEFAULT_ADMIN_SESSION_MAX_LIFETIME_SECS: u64 = 0;
+pub const DEFAULT_DB_POOL_SIZE: usize = 4;
+pub const DEFAULT_DB_QUERY_TIMEOUT_SECS: u64 = 30;
+pub const DEFAULT_AUTO_MIGRATE: bool = true;
+pub const DEFAULT_ARGON2_MEMORY_KIB: u32 = 19_456;
+pub const DEFAULT_ARGON2_ITERATIONS: u32 = 2;
+pub const DEFAULT_ARGON2_PARALLELISM: u32 = 1;
+pub const DEFAULT_TRANSLATIONS_PER_HOUR: u32 = 30;
+pub const DEFAULT_PROXY_REMOTE_CONTENT: bool = false;
+pub const DEFAULT_PROXY_MAX_MEDIA_BYTES: u64 = 16 * 1024 * 1024;
+pub const DEFAULT_PROXY_CACHE_BYTES: u64 = 256 * 1024 * 1024;
+pub const DEFAULT_PROXY_CACHE_TTL_SECS: u64 = 86_400;
+pub const DEFAULT_AUDIT_FILE_MAX_BYTES: u64 = 10 * 1024 * 1024;
+pub const DEFAULT_AUDIT_FILE_KEEP: usize = 5;
 
 pub static DEFAULT_CONFIG_PATH: &str = "./config.toml";
 
@@ -37,13 +132,84 @@ pub static DEFAULT_CONFIG_PATH: &str = "./config.toml";
 pub static ENV_VAR_ADDRESS: &str = "PLAZER_DB_ADDRESS";
 pub static ENV_VAR_NAMESPACE: &str = "PLAZER_DB_NAMESPACE";
 pub static ENV_VAR_DATABASE: &str = "PLAZER_DB_DATABASE";
+pub static ENV_VAR_DB_POOL_SIZE: &str = "PLAZER_DB_POOL_SIZE";
+pub static ENV_VAR_DB_QUERY_TIMEOUT_SECS: &str = "PLAZER_DB_QUERY_TIMEOUT_SECS";
+pub static ENV_VAR_AUTO_MIGRATE: &str = "PLAZER_AUTO_MIGRATE";
 pub static ENV_VAR_PRIVATE_KEY: &str = "PLAZER_PRIVATE_KEY";
 pub static ENV_VAR_PRIVATE_KEY_PATH: &str = "PLAZER_PRIVATE_KEY_PATH";
 pub static ENV_VAR_LOG_DIR: &str = "PLAZER_LOG_DIR";
+pub static ENV_VAR_MEDIA_DIR: &str = "PLAZER_MEDIA_DIR";
 pub static ENV_VAR_LOG_LEVEL_STDOUT: &str = "PLAZER_LOG_LEVEL_STDOUT";
 pub static ENV_VAR_LOG_LEVEL_FILE: &str = "PLAZER_LOG_LEVEL_FILE";
 pub static ENV_VAR_HOST: &str = "PLAZER_HOST";
 pub static ENV_VAR_PORT: &str = "PLAZER_PORT";
+pub static ENV_VAR_PUBLIC_STATS: &str = "PLAZER_PUBLIC_STATS";
+pub static ENV_VAR_SPAM_REVIEW_THRESHOLD: &str = "PLAZER_SPAM_REVIEW_THRESHOLD";
+pub static ENV_VAR_SPAM_LIMIT_THRESHOLD: &str = "PLAZER_SPAM_LIMIT_THRESHOLD";
+pub static ENV_VAR_SPAM_CLASSIFIER_URL: &str = "PLAZER_SPAM_CLASSIFIER_URL";
+pub static ENV_VAR_DEV_AUTH: &str = "PLAZER_DEV_AUTH";
+pub static ENV_VAR_DEV_AUTH_ALLOW_RELEASE: &str = "PLAZER_DEV_AUTH_ALLOW_RELEASE";
+pub static ENV_VAR_MAX_CONCURRENCY: &str = "PLAZER_MAX_CONCURRENCY";
+pub static ENV_VAR_MAX_GRAPHQL_CONCURRENCY: &str = "PLAZER_MAX_GRAPHQL_CONCURRENCY";
+pub static ENV_VAR_MAX_QUEUE_MS: &str = "PLAZER_MAX_QUEUE_MS";
+pub static ENV_VAR_RATE_LIMIT_AUTH: &str = "PLAZER_RATE_LIMIT_AUTH";
+pub static ENV_VAR_RATE_LIMIT_READ: &str = "PLAZER_RATE_LIMIT_READ";
+pub static ENV_VAR_RATE_LIMIT_WRITE: &str = "PLAZER_RATE_LIMIT_WRITE";
+pub static ENV_VAR_MAX_QUERY_COMPLEXITY: &str = "PLAZER_MAX_QUERY_COMPLEXITY";
+pub static ENV_VAR_MAX_QUERY_DEPTH: &str = "PLAZER_MAX_QUERY_DEPTH";
+pub static ENV_VAR_PERSISTED_QUERY_CACHE_SIZE: &str = "PLAZER_PERSISTED_QUERY_CACHE_SIZE";
+pub static ENV_VAR_PERSIST_QUERIES: &str = "PLAZER_PERSIST_QUERIES";
+pub static ENV_VAR_PERSISTED_QUERY_ALLOW_LIST: &str = "PLAZER_PERSISTED_QUERY_ALLOW_LIST";
+pub static ENV_VAR_PUBLIC_URL: &str = "PLAZER_PUBLIC_URL";
+pub static ENV_VAR_REGION: &str = "PLAZER_REGION";
+pub static ENV_VAR_MIN_AGE: &str = "PLAZER_MIN_AGE";
+pub static ENV_VAR_ALLOW_CONFUSABLE_USER_IDS: &str = "PLAZER_ALLOW_CONFUSABLE_USER_IDS";
+pub static ENV_VAR_REQUIRE_VERIFIED_EMAIL: &str = "PLAZER_REQUIRE_VERIFIED_EMAIL";
+pub static ENV_VAR_DELETION_GRACE_DAYS: &str = "PLAZER_DELETION_GRACE_DAYS";
+pub static ENV_VAR_DORMANT_AFTER_DAYS: &str = "PLAZER_DORMANT_AFTER_DAYS";
+pub static ENV_VAR_DORMANCY_GRACE_DAYS: &str = "PLAZER_DORMANCY_GRACE_DAYS";
+pub static ENV_VAR_RELEASE_DORMANT_USER_IDS: &str = "PLAZER_RELEASE_DORMANT_USER_IDS";
+pub static ENV_VAR_SMTP_ADDRESS: &str = "PLAZER_SMTP_ADDRESS";
+pub static ENV_VAR_EMAIL_FROM: &str = "PLAZER_EMAIL_FROM";
+pub static ENV_VAR_IP_STORAGE: &str = "PLAZER_IP_STORAGE";
+pub static ENV_VAR_METADATA_VISIBILITY: &str = "PLAZER_METADATA_VISIBILITY";
+pub static ENV_VAR_METADATA_RETENTION_DAYS: &str = "PLAZER_METADATA_RETENTION_DAYS";
+pub static ENV_VAR_QUOTA_POSTS: &str = "PLAZER_QUOTA_POSTS";
+pub static ENV_VAR_QUOTA_BOARDS: &str = "PLAZER_QUOTA_BOARDS";
+pub static ENV_VAR_QUOTA_LISTS: &str = "PLAZER_QUOTA_LISTS";
+pub static ENV_VAR_QUOTA_STORAGE_BYTES: &str = "PLAZER_QUOTA_STORAGE_BYTES";
+pub static ENV_VAR_MAX_POST_TITLE_LENGTH: &str = "PLAZER_MAX_POST_TITLE_LENGTH";
+pub static ENV_VAR_MAX_POST_CONTENT_LENGTH: &str = "PLAZER_MAX_POST_CONTENT_LENGTH";
+pub static ENV_VAR_MAX_BOARD_NAME_LENGTH: &str = "PLAZER_MAX_BOARD_NAME_LENGTH";
+pub static ENV_VAR_MAX_BOARD_DESCRIPTION_LENGTH: &str = "PLAZER_MAX_BOARD_DESCRIPTION_LENGTH";
+pub static ENV_VAR_SIGNUP_HONEYPOT_SCORE: &str = "PLAZER_SIGNUP_HONEYPOT_SCORE";
+pub static ENV_VAR_SIGNUP_MIN_FORM_SECS: &str = "PLAZER_SIGNUP_MIN_FORM_SECS";
+pub static ENV_VAR_SIGNUP_TOO_FAST_SCORE: &str = "PLAZER_SIGNUP_TOO_FAST_SCORE";
+pub static ENV_VAR_READ_ONLY: &str = "PLAZER_READ_ONLY";
+pub static ENV_VAR_READ_ONLY_AFTER_FAILURES: &str = "PLAZER_READ_ONLY_AFTER_FAILURES";
+pub static ENV_VAR_READ_ONLY_COOLDOWN_SECS: &str = "PLAZER_READ_ONLY_COOLDOWN_SECS";
+pub static ENV_VAR_MIN_CLIENT_VERSIONS: &str = "PLAZER_MIN_CLIENT_VERSIONS";
+pub static ENV_VAR_MAX_MEDIA_BYTES: &str = "PLAZER_MAX_MEDIA_BYTES";
+pub static ENV_VAR_MEDIA_GC_GRACE_SECS: &str = "PLAZER_MEDIA_GC_GRACE_SECS";
+pub static ENV_VAR_MEDIA_URL_TTL_SECS: &str = "PLAZER_MEDIA_URL_TTL_SECS";
+pub static ENV_VAR_MEDIA_REFERER_HOSTS: &str = "PLAZER_MEDIA_REFERER_HOSTS";
+pub static ENV_VAR_ALT_TEXT_POLICY: &str = "PLAZER_ALT_TEXT_POLICY";
+pub static ENV_VAR_POST_VISIBILITY: &str = "PLAZER_POST_VISIBILITY";
+pub static ENV_VAR_MAX_POST_VISIBILITY: &str = "PLAZER_MAX_POST_VISIBILITY";
+pub static ENV_VAR_SESSION_IDLE_TIMEOUT_SECS: &str = "PLAZER_SESSION_IDLE_TIMEOUT_SECS";
+pub static ENV_VAR_SESSION_MAX_LIFETIME_SECS: &str = "PLAZER_SESSION_MAX_LIFETIME_SECS";
+pub static ENV_VAR_ADMIN_SESSION_IDLE_TIMEOUT_SECS: &str = "PLAZER_ADMIN_SESSION_IDLE_TIMEOUT_SECS";
+pub static ENV_VAR_ADMIN_SESSION_MAX_LIFETIME_SECS: &str = "PLAZER_ADMIN_SESSION_MAX_LIFETIME_SECS";
+pub static ENV_VAR_ARGON2_MEMORY_KIB: &str = "PLAZER_ARGON2_MEMORY_KIB";
+pub static ENV_VAR_ARGON2_ITERATIONS: &str = "PLAZER_ARGON2_ITERATIONS";
+pub static ENV_VAR_ARGON2_PARALLELISM: &str = "PLAZER_ARGON2_PARALLELISM";
+pub static ENV_VAR_TRANSLATIONS_PER_HOUR: &str = "PLAZER_TRANSLATIONS_PER_HOUR";
+pub static ENV_VAR_PROXY_REMOTE_CONTENT: &str = "PLAZER_PROXY_REMOTE_CONTENT";
+pub static ENV_VAR_PROXY_MAX_MEDIA_BYTES: &str = "PLAZER_PROXY_MAX_MEDIA_BYTES";
+pub static ENV_VAR_PROXY_CACHE_BYTES: &str = "PLAZER_PROXY_CACHE_BYTES";
+pub static ENV_VAR_PROXY_CACHE_TTL_SECS: &str = "PLAZER_PROXY_CACHE_TTL_SECS";
+pub static ENV_VAR_CONFIG_PATH: &str = "PLAZER_CONFIG";
+pub static ENV_VAR_CORS_ORIGINS: &str = "PLAZER_CORS_ORIGINS";
 
 // Config
 
@@ -83,22 +249,285 @@ impl From<LogLevel> for tracing::Level {
     }
 }
 
+/// How client IP addresses are stored.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, NamedVariant)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum IpStorage {
+    /// Store addresses as they are
+    Full,
+    /// Store only the network part of addresses (/24 for IPv4, /48 for IPv6)
+    Truncated,
+    /// Store a keyed hash of addresses, so they can be told apart but not
+    /// recovered
+    Hashed,
+    /// Don't store addresses at all
+    None,
+}
+
+impl fmt::Display for IpStorage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.variant_name().to_ascii_lowercase())
+    }
+}
+
+impl FromStr for IpStorage {
+    type Err = UnknownValue;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match &*value.to_ascii_lowercase() {
+            "full" => Ok(Self::Full),
+            "truncated" => Ok(Self::Truncated),
+            "hashed" => Ok(Self::Hashed),
+            "none" => Ok(Self::None),
+            _ => Err(UnknownValue(value.to_owned())),
+        }
+    }
+}
+
+/// Who can see the IP addresses and user agents recorded with sign-ins.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, NamedVariant)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum MetadataVisibility {
+    /// Nobody
+    None,
+    /// Only the account that signed in
+    Owner,
+    /// Only instance admins
+    Admins,
+    /// The account that signed in and instance admins
+    All,
+}
+
+impl MetadataVisibility {
+    #[must_use]
+    pub fn owner_can_see(self) -> bool {
+        matches!(self, Self::Owner | Self::All)
+    }
+
+    #[must_use]
+    pub fn admins_can_see(self) -> bool {
+        matches!(self, Self::Admins | Self::All)
+    }
+}
+
+impl fmt::Display for MetadataVisibility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.variant_name().to_ascii_lowercase())
+    }
+}
+
+impl FromStr for MetadataVisibility {
+    type Err = UnknownValue;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match &*value.to_ascii_lowercase() {
+            "none" => Ok(Self::None),
+            "owner" => Ok(Self::Owner),
+            "admins" => Ok(Self::Admins),
+            "all" => Ok(Self::All),
+            _ => Err(UnknownValue(value.to_owned())),
+        }
+    }
+}
+
+/// Whether images attached to posts need alt text describing them.
+#[derive(
+    async_graphql::Enum, Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, NamedVariant,
+)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum AltTextPolicy {
+    /// Alt text is optional
+    Off,
+    /// Clients should remind authors to add alt text, but can post without it
+    Nag,
+    /// Posts with images that don't have alt text are rejected
+    Require,
+}
+
+impl fmt::Display for AltTextPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.variant_name().to_ascii_lowercase())
+    }
+}
+
+impl FromStr for AltTextPolicy {
+    type Err = UnknownValue;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match &*value.to_ascii_lowercase() {
+            "off" => Ok(Self::Off),
+            "nag" => Ok(Self::Nag),
+            "require" => Ok(Self::Require),
+            _ => Err(UnknownValue(value.to_owned())),
+        }
+    }
+}
+
+/// Who can see a post. Levels are ordered from the most open to the least.
+#[derive(
+    async_graphql::Enum,
+    Debug,
+    Default,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+    NamedVariant,
+)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum PostVisibility {
+    /// Anyone can see the post, including people who aren't signed in
+    #[default]
+    Public,
+    /// Anyone can see the post, but it's left out of lists of posts
+    Unlisted,
+    /// Only accounts signed in to this instance can see the post
+    Instance,
+}
+
+impl fmt::Display for PostVisibility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.variant_name().to_ascii_lowercase())
+    }
+}
+
+impl FromStr for PostVisibility {
+    type Err = UnknownValue;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match &*value.to_ascii_lowercase() {
+            "public" => Ok(Self::Public),
+            "unlisted" => Ok(Self::Unlisted),
+            "instance" => Ok(Self::Instance),
+            _ => Err(UnknownValue(value.to_owned())),
+        }
+    }
+}
+
+/// A config value that isn't one of the allowed options.
+#[derive(Debug, thiserror::Error)]
+#[error("unknown value {0:?}")]
+pub struct UnknownValue(String);
+
+/// Everything that's wrong with a config, so that it can all be fixed at once
+/// rather than one problem per restart.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigProblems(pub Vec<String>);
+
+impl fmt::Display for ConfigProblems {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.len() {
+            1 => write!(f, "1 problem with the config:")?,
+            count => write!(f, "{count} problems with the config:")?,
+        }
+        for problem in &self.0 {
+            write!(f, "\n  - {problem}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigProblems {}
+
 pub type PrivateKeyCreate = fn(&Path) -> anyhow::Result<String>;
 
 #[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ServiceConfigBuilder {
+    #[serde(skip)]
+    config_path: Option<String>,
     address: Option<String>,
     namespace: Option<String>,
     database: Option<String>,
+    db_pool_size: Option<usize>,
+    db_query_timeout_secs: Option<u64>,
+    auto_migrate: Option<bool>,
     private_key: Option<String>,
     private_key_path: Option<String>,
     #[serde(skip)]
     private_key_create: Option<PrivateKeyCreate>,
     log_dir: Option<String>,
+    media_dir: Option<String>,
     log_level_stdout: Option<LogLevel>,
     log_level_file: Option<LogLevel>,
     host: Option<String>,
     port: Option<u16>,
+    cors_origins: Option<String>,
+    public_stats: Option<bool>,
+    spam_review_threshold: Option<u8>,
+    spam_limit_threshold: Option<u8>,
+    spam_classifier_url: Option<String>,
+    dev_auth: Option<bool>,
+    dev_auth_allow_release: Option<bool>,
+    max_concurrency: Option<usize>,
+    max_graphql_concurrency: Option<usize>,
+    max_queue_ms: Option<u64>,
+    rate_limit_auth: Option<u32>,
+    rate_limit_read: Option<u32>,
+    rate_limit_write: Option<u32>,
+    max_query_complexity: Option<usize>,
+    max_query_depth: Option<usize>,
+    persisted_query_cache_size: Option<usize>,
+    persist_queries: Option<bool>,
+    persisted_query_allow_list: Option<String>,
+    public_url: Option<String>,
+    region: Option<String>,
+    min_age: Option<u8>,
+    allow_confusable_user_ids: Option<bool>,
+    require_verified_email: Option<bool>,
+    deletion_grace_days: Option<u32>,
+    dormant_after_days: Option<u32>,
+    dormancy_grace_days: Option<u32>,
+    release_dormant_user_ids: Option<bool>,
+    smtp_address: Option<String>,
+    email_from: Option<String>,
+    ip_storage: Option<IpStorage>,
+    metadata_visibility: Option<MetadataVisibility>,
+    metadata_retention_days: Option<u32>,
+    quota_posts: Option<u32>,
+    quota_boards: Option<u32>,
+    quota_lists: Option<u32>,
+    quota_storage_bytes: Option<u64>,
+    max_post_title_length: Option<u32>,
+    max_post_content_length: Option<u32>,
+    max_board_name_length: Option<u32>,
+    max_board_description_length: Option<u32>,
+    signup_honeypot_score: Option<u8>,
+    signup_min_form_secs: Option<u32>,
+    signup_too_fast_score: Option<u8>,
+    read_only: Option<bool>,
+    read_only_after_failures: Option<u32>,
+    read_only_cooldown_secs: Option<u64>,
+    min_client_versions: Option<String>,
+    max_media_bytes: Option<u64>,
+    media_gc_grace_secs: Option<u64>,
+    media_url_ttl_secs: Option<u64>,
+    media_referer_hosts: Option<String>,
+    alt_text_policy: Option<AltTextPolicy>,
+    post_visibility: Option<PostVisibility>,
+    max_post_visibility: Option<PostVisibility>,
+    session_idle_timeout_secs: Option<u64>,
+    session_max_lifetime_secs: Option<u64>,
+    admin_session_idle_timeout_secs: Option<u64>,
+    admin_session_max_lifetime_secs: Option<u64>,
+    argon2_memory_kib: Option<u32>,
+    argon2_iterations: Option<u32>,
+    argon2_parallelism: Option<u32>,
+    translations_per_hour: Option<u32>,
+    proxy_remote_content: Option<bool>,
+    proxy_max_media_bytes: Option<u64>,
+    proxy_cache_bytes: Option<u64>,
+    proxy_cache_ttl_secs: Option<u64>,
+    oidc_providers: Option<Vec<OidcProviderConfig>>,
+    audit_sinks: Option<Vec<AuditSinkConfig>>,
+    translation: Option<TranslationProviderConfig>,
 }
 
 impl ServiceConfigBuilder {
@@ -107,6 +536,19 @@ impl ServiceConfigBuilder {
         Self::default()
     }
 
+    /// Sets the config file to load. Unlike the default one, it has to exist.
+    #[must_use]
+    pub fn config_path(mut self, config_path: impl Into<String>) -> Self {
+        self.config_path = Some(config_path.into());
+        self
+    }
+
+    #[must_use]
+    pub fn set_config_path(mut self, config_path: Option<String>) -> Self {
+        self.config_path = config_path;
+        self
+    }
+
     #[must_use]
     pub fn address(mut self, address: impl Into<String>) -> Self {
         self.address = Some(address.into());
@@ -143,6 +585,42 @@ impl ServiceConfigBuilder {
         self
     }
 
+    #[must_use]
+    pub fn db_pool_size(mut self, db_pool_size: usize) -> Self {
+        self.db_pool_size = Some(db_pool_size);
+        self
+    }
+
+    #[must_use]
+    pub fn set_db_pool_size(mut self, db_pool_size: Option<usize>) -> Self {
+        self.db_pool_size = db_pool_size;
+        self
+    }
+
+    #[must_use]
+    pub fn db_query_timeout_secs(mut self, db_query_timeout_secs: u64) -> Self {
+        self.db_query_timeout_secs = Some(db_query_timeout_secs);
+        self
+    }
+
+    #[must_use]
+    pub fn set_db_query_timeout_secs(mut self, db_query_timeout_secs: Option<u64>) -> Self {
+        self.db_query_timeout_secs = db_query_timeout_secs;
+        self
+    }
+
+    #[must_use]
+    pub fn auto_migrate(mut self, auto_migrate: bool) -> Self {
+        self.auto_migrate = Some(auto_migrate);
+        self
+    }
+
+    #[must_use]
+    pub fn set_auto_migrate(mut self, auto_migrate: Option<bool>) -> Self {
+        self.auto_migrate = auto_migrate;
+        self
+    }
+
     #[must_use]
     pub fn private_key(mut self, private_key: impl Into<String>) -> Self {
         self.private_key = Some(private_key.into());
@@ -191,6 +669,18 @@ impl ServiceConfigBuilder {
         self
     }
 
+    #[must_use]
+    pub fn media_dir(mut self, media_dir: impl Into<String>) -> Self {
+        self.media_dir = Some(media_dir.into());
+        self
+    }
+
+    #[must_use]
+    pub fn set_media_dir(mut self, media_dir: Option<String>) -> Self {
+        self.media_dir = media_dir;
+        self
+    }
+
     #[must_use]
     pub fn log_level_stdout(mut self, log_level_stdout: impl Into<LogLevel>) -> Self {
         self.log_level_stdout = Some(log_level_stdout.into());
@@ -239,169 +729,1732 @@ impl ServiceConfigBuilder {
         self
     }
 
-    pub fn build(self) -> anyhow::Result<ServiceConfig> {
-        let file_config = match fs::read_to_string(DEFAULT_CONFIG_PATH) {
-            Ok(file_config) => toml::from_str(&file_config).context("Config invalid")?,
-            Err(err) => {
-                if let std::io::ErrorKind::NotFound = err.kind() {
-                    ServiceConfigBuilder::default()
-                } else {
-                    return Err(err).context("Unable to read config file");
-                }
-            }
-        };
+    /// Sets the origins, comma-separated, that browsers can call the API
+    /// from. `*` allows any origin.
+    #[must_use]
+    pub fn cors_origins(mut self, cors_origins: impl Into<String>) -> Self {
+        self.cors_origins = Some(cors_origins.into());
+        self
+    }
 
-        Ok(ServiceConfig {
-            address: config_str_value(
-                self.address,
-                ENV_VAR_ADDRESS,
-                file_config.address,
-                DEFAULT_ADDRESS,
-            )?,
-            namespace: config_str_value(
-                self.namespace,
-                ENV_VAR_NAMESPACE,
-                file_config.namespace,
-                DEFAULT_NAMESPACE,
-            )?,
-            database: config_str_value(
-                self.database,
-                ENV_VAR_DATABASE,
-                file_config.database,
-                DEFAULT_DATABASE,
-            )?,
-            private_key: match self.private_key {
-                Some(private_key) => Some(private_key),
-                None => env_value(ENV_VAR_PRIVATE_KEY)?.or(file_config.private_key),
-            },
-            private_key_path: config_str_value(
-                self.private_key_path,
-                ENV_VAR_PRIVATE_KEY_PATH,
-                file_config.private_key_path,
-                DEFAULT_PRIVATE_KEY_PATH,
-            )?,
-            private_key_create: self.private_key_create,
-            log_dir: config_str_value(
-                self.log_dir,
-                ENV_VAR_LOG_DIR,
-                file_config.log_dir,
-                DEFAULT_LOG_DIR,
-            )?,
-            log_level_stdout: config_level_value(
-                self.log_level_stdout,
-                ENV_VAR_LOG_LEVEL_STDOUT,
-                file_config.log_level_stdout,
-                DEFAULT_LOG_LEVEL_STDOUT,
-            )?,
-            log_level_file: config_level_value(
-                self.log_level_file,
-                ENV_VAR_LOG_LEVEL_FILE,
-                file_config.log_level_file,
-                DEFAULT_LOG_LEVEL_FILE,
-            )?,
-            host: config_str_value(self.host, ENV_VAR_HOST, file_config.host, DEFAULT_HOST)?,
-            port: config_parsed_value(self.port, ENV_VAR_PORT, file_config.port, DEFAULT_PORT)?,
-        })
+    #[must_use]
+    pub fn set_cors_origins(mut self, cors_origins: Option<String>) -> Self {
+        self.cors_origins = cors_origins;
+        self
     }
-}
 
-fn config_str_value(
-    arg: Option<String>,
-    env_var: &str,
-    file: Option<String>,
-    default: &str,
-) -> anyhow::Result<String> {
-    let value = match arg {
-        Some(arg) => arg,
-        None => env_value(env_var)?
-            .or(file)
-            .unwrap_or_else(|| default.to_owned()),
-    };
+    #[must_use]
+    pub fn public_stats(mut self, public_stats: bool) -> Self {
+        self.public_stats = Some(public_stats);
+        self
+    }
 
-    Ok(value)
-}
+    #[must_use]
+    pub fn set_public_stats(mut self, public_stats: Option<bool>) -> Self {
+        self.public_stats = public_stats;
+        self
+    }
 
-fn config_parsed_value<T>(
-    arg: Option<T>,
-    env_var: &str,
-    file: Option<T>,
-    default: T,
-) -> anyhow::Result<T>
-where
-    T: std::str::FromStr,
-    T::Err: std::error::Error + Send + Sync + 'static,
-{
-    let value = match arg {
-        Some(arg) => arg,
-        None => match env_value(env_var)? {
-            Some(value) => value.parse().with_context(|| {
-                format!("Invalid value `{value}` in environment variable {env_var}")
-            })?,
-            None => file.unwrap_or(default),
-        },
-    };
+    #[must_use]
+    pub fn spam_review_threshold(mut self, spam_review_threshold: impl Into<u8>) -> Self {
+        self.spam_review_threshold = Some(spam_review_threshold.into());
+        self
+    }
 
-    Ok(value)
-}
+    #[must_use]
+    pub fn set_spam_review_threshold(mut self, spam_review_threshold: Option<u8>) -> Self {
+        self.spam_review_threshold = spam_review_threshold;
+        self
+    }
 
-fn config_level_value(
-    arg: Option<LogLevel>,
-    env_var: &str,
-    file: Option<LogLevel>,
-    default: LogLevel,
-) -> anyhow::Result<LogLevel> {
-    let value = match arg {
-        Some(arg) => arg,
-        None => match env_value(env_var)? {
-            Some(value) => match &*value.to_ascii_lowercase() {
-                "error" => LogLevel::Error,
-                "warn" => LogLevel::Warn,
-                "info" => LogLevel::Info,
-                "debug" => LogLevel::Debug,
-                "trace" => LogLevel::Trace,
-                value => {
-                    return Err(anyhow::anyhow!(
-                        "Invalid log level {value:?} in environment variable {env_var}"
-                    ))
-                }
-            },
-            None => file.unwrap_or(default),
-        },
-    };
+    #[must_use]
+    pub fn spam_limit_threshold(mut self, spam_limit_threshold: impl Into<u8>) -> Self {
+        self.spam_limit_threshold = Some(spam_limit_threshold.into());
+        self
+    }
 
-    Ok(value)
-}
+    #[must_use]
+    pub fn set_spam_limit_threshold(mut self, spam_limit_threshold: Option<u8>) -> Self {
+        self.spam_limit_threshold = spam_limit_threshold;
+        self
+    }
 
-fn env_value(env_var: &str) -> anyhow::Result<Option<String>> {
-    match env::var(env_var) {
-        Ok(value) => Ok(Some(value)),
-        Err(env::VarError::NotPresent) => Ok(None),
-        Err(env::VarError::NotUnicode(_)) => Err(anyhow::anyhow!(
-            "Environment variable {env_var} is not valid unicode",
-        )),
+    #[must_use]
+    pub fn spam_classifier_url(mut self, spam_classifier_url: impl Into<String>) -> Self {
+        self.spam_classifier_url = Some(spam_classifier_url.into());
+        self
     }
-}
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-pub struct ServiceConfig {
-    address: String,
-    namespace: String,
-    database: String,
-    private_key: Option<String>,
-    private_key_path: String,
-    #[serde(skip)]
-    private_key_create: Option<PrivateKeyCreate>,
-    log_dir: String,
-    log_level_stdout: LogLevel,
-    log_level_file: LogLevel,
-    host: String,
-    port: u16,
-}
+    #[must_use]
+    pub fn set_spam_classifier_url(mut self, spam_classifier_url: Option<String>) -> Self {
+        self.spam_classifier_url = spam_classifier_url;
+        self
+    }
 
-impl TryFrom<ServiceConfig> for (ServeConfig, LogConfig) {
-    type Error = anyhow::Error;
+    #[must_use]
+    pub fn dev_auth(mut self, dev_auth: bool) -> Self {
+        self.dev_auth = Some(dev_auth);
+        self
+    }
+
+    #[must_use]
+    pub fn set_dev_auth(mut self, dev_auth: Option<bool>) -> Self {
+        self.dev_auth = dev_auth;
+        self
+    }
+
+    #[must_use]
+    pub fn dev_auth_allow_release(mut self, dev_auth_allow_release: bool) -> Self {
+        self.dev_auth_allow_release = Some(dev_auth_allow_release);
+        self
+    }
+
+    #[must_use]
+    pub fn set_dev_auth_allow_release(mut self, dev_auth_allow_release: Option<bool>) -> Self {
+        self.dev_auth_allow_release = dev_auth_allow_release;
+        self
+    }
+
+    #[must_use]
+    pub fn max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.max_concurrency = Some(max_concurrency);
+        self
+    }
+
+    #[must_use]
+    pub fn set_max_concurrency(mut self, max_concurrency: Option<usize>) -> Self {
+        self.max_concurrency = max_concurrency;
+        self
+    }
+
+    #[must_use]
+    pub fn max_graphql_concurrency(mut self, max_graphql_concurrency: usize) -> Self {
+        self.max_graphql_concurrency = Some(max_graphql_concurrency);
+        self
+    }
+
+    #[must_use]
+    pub fn set_max_graphql_concurrency(mut self, max_graphql_concurrency: Option<usize>) -> Self {
+        self.max_graphql_concurrency = max_graphql_concurrency;
+        self
+    }
+
+    #[must_use]
+    pub fn max_queue_ms(mut self, max_queue_ms: u64) -> Self {
+        self.max_queue_ms = Some(max_queue_ms);
+        self
+    }
+
+    #[must_use]
+    pub fn set_max_queue_ms(mut self, max_queue_ms: Option<u64>) -> Self {
+        self.max_queue_ms = max_queue_ms;
+        self
+    }
+
+    #[must_use]
+    pub fn rate_limit_auth(mut self, rate_limit_auth: u32) -> Self {
+        self.rate_limit_auth = Some(rate_limit_auth);
+        self
+    }
+
+    #[must_use]
+    pub fn set_rate_limit_auth(mut self, rate_limit_auth: Option<u32>) -> Self {
+        self.rate_limit_auth = rate_limit_auth;
+        self
+    }
+
+    #[must_use]
+    pub fn rate_limit_read(mut self, rate_limit_read: u32) -> Self {
+        self.rate_limit_read = Some(rate_limit_read);
+        self
+    }
+
+    #[must_use]
+    pub fn set_rate_limit_read(mut self, rate_limit_read: Option<u32>) -> Self {
+        self.rate_limit_read = rate_limit_read;
+        self
+    }
+
+    #[must_use]
+    pub fn rate_limit_write(mut self, rate_limit_write: u32) -> Self {
+        self.rate_limit_write = Some(rate_limit_write);
+        self
+    }
+
+    #[must_use]
+    pub fn set_rate_limit_write(mut self, rate_limit_write: Option<u32>) -> Self {
+        self.rate_limit_write = rate_limit_write;
+        self
+    }
+
+    #[must_use]
+    pub fn max_query_complexity(mut self, max_query_complexity: usize) -> Self {
+        self.max_query_complexity = Some(max_query_complexity);
+        self
+    }
+
+    #[must_use]
+    pub fn set_max_query_complexity(mut self, max_query_complexity: Option<usize>) -> Self {
+        self.max_query_complexity = max_query_complexity;
+        self
+    }
+
+    #[must_use]
+    pub fn max_query_depth(mut self, max_query_depth: usize) -> Self {
+        self.max_query_depth = Some(max_query_depth);
+        self
+    }
+
+    #[must_use]
+    pub fn set_max_query_depth(mut self, max_query_depth: Option<usize>) -> Self {
+        self.max_query_depth = max_query_depth;
+        self
+    }
+
+    #[must_use]
+    pub fn persisted_query_cache_size(mut self, persisted_query_cache_size: usize) -> Self {
+        self.persisted_query_cache_size = Some(persisted_query_cache_size);
+        self
+    }
+
+    #[must_use]
+    pub fn set_persisted_query_cache_size(
+        mut self,
+        persisted_query_cache_size: Option<usize>,
+    ) -> Self {
+        self.persisted_query_cache_size = persisted_query_cache_size;
+        self
+    }
+
+    #[must_use]
+    pub fn persist_queries(mut self, persist_queries: bool) -> Self {
+        self.persist_queries = Some(persist_queries);
+        self
+    }
+
+    #[must_use]
+    pub fn set_persist_queries(mut self, persist_queries: Option<bool>) -> Self {
+        self.persist_queries = persist_queries;
+        self
+    }
+
+    #[must_use]
+    pub fn persisted_query_allow_list(
+        mut self,
+        persisted_query_allow_list: impl Into<String>,
+    ) -> Self {
+        self.persisted_query_allow_list = Some(persisted_query_allow_list.into());
+        self
+    }
+
+    #[must_use]
+    pub fn set_persisted_query_allow_list(
+        mut self,
+        persisted_query_allow_list: Option<String>,
+    ) -> Self {
+        self.persisted_query_allow_list = persisted_query_allow_list;
+        self
+    }
+
+    #[must_use]
+    pub fn public_url(mut self, public_url: impl Into<String>) -> Self {
+        self.public_url = Some(public_url.into());
+        self
+    }
+
+    #[must_use]
+    pub fn set_public_url(mut self, public_url: Option<String>) -> Self {
+        self.public_url = public_url;
+        self
+    }
+
+    #[must_use]
+    pub fn region(mut self, region: impl Into<String>) -> Self {
+        self.region = Some(region.into());
+        self
+    }
+
+    #[must_use]
+    pub fn set_region(mut self, region: Option<String>) -> Self {
+        self.region = region;
+        self
+    }
+
+    #[must_use]
+    pub fn deletion_grace_days(mut self, deletion_grace_days: u32) -> Self {
+        self.deletion_grace_days = Some(deletion_grace_days);
+        self
+    }
+
+    #[must_use]
+    pub fn set_deletion_grace_days(mut self, deletion_grace_days: Option<u32>) -> Self {
+        self.deletion_grace_days = deletion_grace_days;
+        self
+    }
+
+    #[must_use]
+    pub fn dormant_after_days(mut self, dormant_after_days: u32) -> Self {
+        self.dormant_after_days = Some(dormant_after_days);
+        self
+    }
+
+    #[must_use]
+    pub fn set_dormant_after_days(mut self, dormant_after_days: Option<u32>) -> Self {
+        self.dormant_after_days = dormant_after_days;
+        self
+    }
+
+    #[must_use]
+    pub fn dormancy_grace_days(mut self, dormancy_grace_days: u32) -> Self {
+        self.dormancy_grace_days = Some(dormancy_grace_days);
+        self
+    }
+
+    #[must_use]
+    pub fn set_dormancy_grace_days(mut self, dormancy_grace_days: Option<u32>) -> Self {
+        self.dormancy_grace_days = dormancy_grace_days;
+        self
+    }
+
+    #[must_use]
+    pub fn release_dormant_user_ids(mut self, release_dormant_user_ids: bool) -> Self {
+        self.release_dormant_user_ids = Some(release_dormant_user_ids);
+        self
+    }
+
+    #[must_use]
+    pub fn set_release_dormant_user_ids(mut self, release_dormant_user_ids: Option<bool>) -> Self {
+        self.release_dormant_user_ids = release_dormant_user_ids;
+        self
+    }
+
+    #[must_use]
+    pub fn min_age(mut self, min_age: u8) -> Self {
+        self.min_age = Some(min_age);
+        self
+    }
+
+    #[must_use]
+    pub fn set_min_age(mut self, min_age: Option<u8>) -> Self {
+        self.min_age = min_age;
+        self
+    }
+
+    #[must_use]
+    pub fn allow_confusable_user_ids(mut self, allow_confusable_user_ids: bool) -> Self {
+        self.allow_confusable_user_ids = Some(allow_confusable_user_ids);
+        self
+    }
+
+    #[must_use]
+    pub fn set_allow_confusable_user_ids(
+        mut self,
+        allow_confusable_user_ids: Option<bool>,
+    ) -> Self {
+        self.allow_confusable_user_ids = allow_confusable_user_ids;
+        self
+    }
+
+    #[must_use]
+    pub fn require_verified_email(mut self, require_verified_email: bool) -> Self {
+        self.require_verified_email = Some(require_verified_email);
+        self
+    }
+
+    #[must_use]
+    pub fn set_require_verified_email(mut self, require_verified_email: Option<bool>) -> Self {
+        self.require_verified_email = require_verified_email;
+        self
+    }
+
+    #[must_use]
+    pub fn smtp_address(mut self, smtp_address: impl Into<String>) -> Self {
+        self.smtp_address = Some(smtp_address.into());
+        self
+    }
+
+    #[must_use]
+    pub fn set_smtp_address(mut self, smtp_address: Option<String>) -> Self {
+        self.smtp_address = smtp_address;
+        self
+    }
+
+    #[must_use]
+    pub fn email_from(mut self, email_from: impl Into<String>) -> Self {
+        self.email_from = Some(email_from.into());
+        self
+    }
+
+    #[must_use]
+    pub fn set_email_from(mut self, email_from: Option<String>) -> Self {
+        self.email_from = email_from;
+        self
+    }
+
+    #[must_use]
+    pub fn ip_storage(mut self, ip_storage: IpStorage) -> Self {
+        self.ip_storage = Some(ip_storage);
+        self
+    }
+
+    #[must_use]
+    pub fn set_ip_storage(mut self, ip_storage: Option<IpStorage>) -> Self {
+        self.ip_storage = ip_storage;
+        self
+    }
+
+    #[must_use]
+    pub fn metadata_visibility(mut self, metadata_visibility: MetadataVisibility) -> Self {
+        self.metadata_visibility = Some(metadata_visibility);
+        self
+    }
+
+    #[must_use]
+    pub fn set_metadata_visibility(
+        mut self,
+        metadata_visibility: Option<MetadataVisibility>,
+    ) -> Self {
+        self.metadata_visibility = metadata_visibility;
+        self
+    }
+
+    #[must_use]
+    pub fn metadata_retention_days(mut self, metadata_retention_days: u32) -> Self {
+        self.metadata_retention_days = Some(metadata_retention_days);
+        self
+    }
+
+    #[must_use]
+    pub fn set_metadata_retention_days(mut self, metadata_retention_days: Option<u32>) -> Self {
+        self.metadata_retention_days = metadata_retention_days;
+        self
+    }
+
+    #[must_use]
+    pub fn quota_posts(mut self, quota_posts: u32) -> Self {
+        self.quota_posts = Some(quota_posts);
+        self
+    }
+
+    #[must_use]
+    pub fn set_quota_posts(mut self, quota_posts: Option<u32>) -> Self {
+        self.quota_posts = quota_posts;
+        self
+    }
+
+    #[must_use]
+    pub fn quota_boards(mut self, quota_boards: u32) -> Self {
+        self.quota_boards = Some(quota_boards);
+        self
+    }
+
+    #[must_use]
+    pub fn set_quota_boards(mut self, quota_boards: Option<u32>) -> Self {
+        self.quota_boards = quota_boards;
+        self
+    }
+
+    #[must_use]
+    pub fn quota_lists(mut self, quota_lists: u32) -> Self {
+        self.quota_lists = Some(quota_lists);
+        self
+    }
+
+    #[must_use]
+    pub fn set_quota_lists(mut self, quota_lists: Option<u32>) -> Self {
+        self.quota_lists = quota_lists;
+        self
+    }
+
+    #[must_use]
+    pub fn quota_storage_bytes(mut self, quota_storage_bytes: u64) -> Self {
+        self.quota_storage_bytes = Some(quota_storage_bytes);
+        self
+    }
+
+    #[must_use]
+    pub fn set_quota_storage_bytes(mut self, quota_storage_bytes: Option<u64>) -> Self {
+        self.quota_storage_bytes = quota_storage_bytes;
+        self
+    }
+
+    #[must_use]
+    pub fn max_post_title_length(mut self, max_post_title_length: u32) -> Self {
+        self.max_post_title_length = Some(max_post_title_length);
+        self
+    }
+
+    #[must_use]
+    pub fn set_max_post_title_length(mut self, max_post_title_length: Option<u32>) -> Self {
+        self.max_post_title_length = max_post_title_length;
+        self
+    }
+
+    #[must_use]
+    pub fn max_post_content_length(mut self, max_post_content_length: u32) -> Self {
+        self.max_post_content_length = Some(max_post_content_length);
+        self
+    }
+
+    #[must_use]
+    pub fn set_max_post_content_length(mut self, max_post_content_length: Option<u32>) -> Self {
+        self.max_post_content_length = max_post_content_length;
+        self
+    }
+
+    #[must_use]
+    pub fn max_board_name_length(mut self, max_board_name_length: u32) -> Self {
+        self.max_board_name_length = Some(max_board_name_length);
+        self
+    }
+
+    #[must_use]
+    pub fn set_max_board_name_length(mut self, max_board_name_length: Option<u32>) -> Self {
+        self.max_board_name_length = max_board_name_length;
+        self
+    }
+
+    #[must_use]
+    pub fn max_board_description_length(mut self, max_board_description_length: u32) -> Self {
+        self.max_board_description_length = Some(max_board_description_length);
+        self
+    }
+
+    #[must_use]
+    pub fn set_max_board_description_length(
+        mut self,
+        max_board_description_length: Option<u32>,
+    ) -> Self {
+        self.max_board_description_length = max_board_description_length;
+        self
+    }
+
+    #[must_use]
+    pub fn signup_honeypot_score(mut self, signup_honeypot_score: u8) -> Self {
+        self.signup_honeypot_score = Some(signup_honeypot_score);
+        self
+    }
+
+    #[must_use]
+    pub fn set_signup_honeypot_score(mut self, signup_honeypot_score: Option<u8>) -> Self {
+        self.signup_honeypot_score = signup_honeypot_score;
+        self
+    }
+
+    #[must_use]
+    pub fn signup_min_form_secs(mut self, signup_min_form_secs: u32) -> Self {
+        self.signup_min_form_secs = Some(signup_min_form_secs);
+        self
+    }
+
+    #[must_use]
+    pub fn set_signup_min_form_secs(mut self, signup_min_form_secs: Option<u32>) -> Self {
+        self.signup_min_form_secs = signup_min_form_secs;
+        self
+    }
+
+    #[must_use]
+    pub fn signup_too_fast_score(mut self, signup_too_fast_score: u8) -> Self {
+        self.signup_too_fast_score = Some(signup_too_fast_score);
+        self
+    }
+
+    #[must_use]
+    pub fn set_signup_too_fast_score(mut self, signup_too_fast_score: Option<u8>) -> Self {
+        self.signup_too_fast_score = signup_too_fast_score;
+        self
+    }
+
+    #[must_use]
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = Some(read_only);
+        self
+    }
+
+    #[must_use]
+    pub fn set_read_only(mut self, read_only: Option<bool>) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    #[must_use]
+    pub fn read_only_after_failures(mut self, read_only_after_failures: u32) -> Self {
+        self.read_only_after_failures = Some(read_only_after_failures);
+        self
+    }
+
+    #[must_use]
+    pub fn set_read_only_after_failures(mut self, read_only_after_failures: Option<u32>) -> Self {
+        self.read_only_after_failures = read_only_after_failures;
+        self
+    }
+
+    #[must_use]
+    pub fn read_only_cooldown_secs(mut self, read_only_cooldown_secs: u64) -> Self {
+        self.read_only_cooldown_secs = Some(read_only_cooldown_secs);
+        self
+    }
+
+    #[must_use]
+    pub fn set_read_only_cooldown_secs(mut self, read_only_cooldown_secs: Option<u64>) -> Self {
+        self.read_only_cooldown_secs = read_only_cooldown_secs;
+        self
+    }
+
+    #[must_use]
+    pub fn min_client_versions(mut self, min_client_versions: impl Into<String>) -> Self {
+        self.min_client_versions = Some(min_client_versions.into());
+        self
+    }
+
+    #[must_use]
+    pub fn set_min_client_versions(mut self, min_client_versions: Option<String>) -> Self {
+        self.min_client_versions = min_client_versions;
+        self
+    }
+
+    #[must_use]
+    pub fn max_media_bytes(mut self, max_media_bytes: u64) -> Self {
+        self.max_media_bytes = Some(max_media_bytes);
+        self
+    }
+
+    #[must_use]
+    pub fn set_max_media_bytes(mut self, max_media_bytes: Option<u64>) -> Self {
+        self.max_media_bytes = max_media_bytes;
+        self
+    }
+
+    #[must_use]
+    pub fn media_gc_grace_secs(mut self, media_gc_grace_secs: u64) -> Self {
+        self.media_gc_grace_secs = Some(media_gc_grace_secs);
+        self
+    }
+
+    #[must_use]
+    pub fn set_media_gc_grace_secs(mut self, media_gc_grace_secs: Option<u64>) -> Self {
+        self.media_gc_grace_secs = media_gc_grace_secs;
+        self
+    }
+
+    #[must_use]
+    pub fn media_url_ttl_secs(mut self, media_url_ttl_secs: u64) -> Self {
+        self.media_url_ttl_secs = Some(media_url_ttl_secs);
+        self
+    }
+
+    #[must_use]
+    pub fn set_media_url_ttl_secs(mut self, media_url_ttl_secs: Option<u64>) -> Self {
+        self.media_url_ttl_secs = media_url_ttl_secs;
+        self
+    }
+
+    #[must_use]
+    pub fn media_referer_hosts(mut self, media_referer_hosts: impl Into<String>) -> Self {
+        self.media_referer_hosts = Some(media_referer_hosts.into());
+        self
+    }
+
+    #[must_use]
+    pub fn set_media_referer_hosts(mut self, media_referer_hosts: Option<String>) -> Self {
+        self.media_referer_hosts = media_referer_hosts;
+        self
+    }
+
+    #[must_use]
+    pub fn alt_text_policy(mut self, alt_text_policy: AltTextPolicy) -> Self {
+        self.alt_text_policy = Some(alt_text_policy);
+        self
+    }
+
+    #[must_use]
+    pub fn set_alt_text_policy(mut self, alt_text_policy: Option<AltTextPolicy>) -> Self {
+        self.alt_text_policy = alt_text_policy;
+        self
+    }
+
+    #[must_use]
+    pub fn post_visibility(mut self, post_visibility: PostVisibility) -> Self {
+        self.post_visibility = Some(post_visibility);
+        self
+    }
+
+    #[must_use]
+    pub fn set_post_visibility(mut self, post_visibility: Option<PostVisibility>) -> Self {
+        self.post_visibility = post_visibility;
+        self
+    }
+
+    #[must_use]
+    pub fn max_post_visibility(mut self, max_post_visibility: PostVisibility) -> Self {
+        self.max_post_visibility = Some(max_post_visibility);
+        self
+    }
+
+    #[must_use]
+    pub fn set_max_post_visibility(mut self, max_post_visibility: Option<PostVisibility>) -> Self {
+        self.max_post_visibility = max_post_visibility;
+        self
+    }
+
+    #[must_use]
+    pub fn session_idle_timeout_secs(mut self, session_idle_timeout_secs: u64) -> Self {
+        self.session_idle_timeout_secs = Some(session_idle_timeout_secs);
+        self
+    }
+
+    #[must_use]
+    pub fn set_session_idle_timeout_secs(mut self, session_idle_timeout_secs: Option<u64>) -> Self {
+        self.session_idle_timeout_secs = session_idle_timeout_secs;
+        self
+    }
+
+    #[must_use]
+    pub fn session_max_lifetime_secs(mut self, session_max_lifetime_secs: u64) -> Self {
+        self.session_max_lifetime_secs = Some(session_max_lifetime_secs);
+        self
+    }
+
+    #[must_use]
+    pub fn set_session_max_lifetime_secs(mut self, session_max_lifetime_secs: Option<u64>) -> Self {
+        self.session_max_lifetime_secs = session_max_lifetime_secs;
+        self
+    }
+
+    #[must_use]
+    pub fn admin_session_idle_timeout_secs(mut self, admin_session_idle_timeout_secs: u64) -> Self {
+        self.admin_session_idle_timeout_secs = Some(admin_session_idle_timeout_secs);
+        self
+    }
+
+    #[must_use]
+    pub fn set_admin_session_idle_timeout_secs(
+        mut self,
+        admin_session_idle_timeout_secs: Option<u64>,
+    ) -> Self {
+        self.admin_session_idle_timeout_secs = admin_session_idle_timeout_secs;
+        self
+    }
+
+    #[must_use]
+    pub fn admin_session_max_lifetime_secs(mut self, admin_session_max_lifetime_secs: u64) -> Self {
+        self.admin_session_max_lifetime_secs = Some(admin_session_max_lifetime_secs);
+        self
+    }
+
+    #[must_use]
+    pub fn set_admin_session_max_lifetime_secs(
+        mut self,
+        admin_session_max_lifetime_secs: Option<u64>,
+    ) -> Self {
+        self.admin_session_max_lifetime_secs = admin_session_max_lifetime_secs;
+        self
+    }
+
+    #[must_use]
+    pub fn argon2_memory_kib(mut self, argon2_memory_kib: u32) -> Self {
+        self.argon2_memory_kib = Some(argon2_memory_kib);
+        self
+    }
+
+    #[must_use]
+    pub fn set_argon2_memory_kib(mut self, argon2_memory_kib: Option<u32>) -> Self {
+        self.argon2_memory_kib = argon2_memory_kib;
+        self
+    }
+
+    #[must_use]
+    pub fn argon2_iterations(mut self, argon2_iterations: u32) -> Self {
+        self.argon2_iterations = Some(argon2_iterations);
+        self
+    }
+
+    #[must_use]
+    pub fn set_argon2_iterations(mut self, argon2_iterations: Option<u32>) -> Self {
+        self.argon2_iterations = argon2_iterations;
+        self
+    }
+
+    #[must_use]
+    pub fn argon2_parallelism(mut self, argon2_parallelism: u32) -> Self {
+        self.argon2_parallelism = Some(argon2_parallelism);
+        self
+    }
+
+    #[must_use]
+    pub fn set_argon2_parallelism(mut self, argon2_parallelism: Option<u32>) -> Self {
+        self.argon2_parallelism = argon2_parallelism;
+        self
+    }
+
+    #[must_use]
+    pub fn translations_per_hour(mut self, translations_per_hour: u32) -> Self {
+        self.translations_per_hour = Some(translations_per_hour);
+        self
+    }
+
+    #[must_use]
+    pub fn set_translations_per_hour(mut self, translations_per_hour: Option<u32>) -> Self {
+        self.translations_per_hour = translations_per_hour;
+        self
+    }
+
+    #[must_use]
+    pub fn proxy_remote_content(mut self, proxy_remote_content: bool) -> Self {
+        self.proxy_remote_content = Some(proxy_remote_content);
+        self
+    }
+
+    #[must_use]
+    pub fn set_proxy_remote_content(mut self, proxy_remote_content: Option<bool>) -> Self {
+        self.proxy_remote_content = proxy_remote_content;
+        self
+    }
+
+    #[must_use]
+    pub fn proxy_max_media_bytes(mut self, proxy_max_media_bytes: u64) -> Self {
+        self.proxy_max_media_bytes = Some(proxy_max_media_bytes);
+        self
+    }
+
+    #[must_use]
+    pub fn set_proxy_max_media_bytes(mut self, proxy_max_media_bytes: Option<u64>) -> Self {
+        self.proxy_max_media_bytes = proxy_max_media_bytes;
+        self
+    }
+
+    #[must_use]
+    pub fn proxy_cache_bytes(mut self, proxy_cache_bytes: u64) -> Self {
+        self.proxy_cache_bytes = Some(proxy_cache_bytes);
+        self
+    }
+
+    #[must_use]
+    pub fn set_proxy_cache_bytes(mut self, proxy_cache_bytes: Option<u64>) -> Self {
+        self.proxy_cache_bytes = proxy_cache_bytes;
+        self
+    }
+
+    #[must_use]
+    pub fn proxy_cache_ttl_secs(mut self, proxy_cache_ttl_secs: u64) -> Self {
+        self.proxy_cache_ttl_secs = Some(proxy_cache_ttl_secs);
+        self
+    }
+
+    #[must_use]
+    pub fn set_proxy_cache_ttl_secs(mut self, proxy_cache_ttl_secs: Option<u64>) -> Self {
+        self.proxy_cache_ttl_secs = proxy_cache_ttl_secs;
+        self
+    }
+
+    /// Adds an external identity provider that accounts can sign in with.
+    #[must_use]
+    pub fn oidc_provider(mut self, provider: OidcProviderConfig) -> Self {
+        self.oidc_providers
+            .get_or_insert_with(Vec::new)
+            .push(provider);
+        self
+    }
+
+    #[must_use]
+    pub fn set_oidc_providers(mut self, oidc_providers: Option<Vec<OidcProviderConfig>>) -> Self {
+        self.oidc_providers = oidc_providers;
+        self
+    }
+
+    /// Adds a sink that the audit trail is shipped to.
+    #[must_use]
+    pub fn audit_sink(mut self, sink: AuditSinkConfig) -> Self {
+        self.audit_sinks.get_or_insert_with(Vec::new).push(sink);
+        self
+    }
+
+    #[must_use]
+    pub fn set_audit_sinks(mut self, audit_sinks: Option<Vec<AuditSinkConfig>>) -> Self {
+        self.audit_sinks = audit_sinks;
+        self
+    }
+
+    /// Sets the provider that posts are translated with.
+    #[must_use]
+    pub fn translation(mut self, translation: TranslationProviderConfig) -> Self {
+        self.translation = Some(translation);
+        self
+    }
+
+    #[must_use]
+    pub fn set_translation(mut self, translation: Option<TranslationProviderConfig>) -> Self {
+        self.translation = translation;
+        self
+    }
+
+    /// Loads the config, layering the arguments given to the builder over
+    /// environment variables, over the config file, over the defaults. Every
+    /// problem with it is reported at once, as a [`ConfigProblems`] under an
+    /// [`Error::ServerMisconfigured`], rather than just the first.
+    #[allow(clippy::too_many_lines)]
+    pub fn build(self) -> anyhow::Result<ServiceConfig> {
+        let mut problems = vec![];
+        let config_path = match self.config_path {
+            Some(config_path) => Some(config_path),
+            None => env_value(ENV_VAR_CONFIG_PATH, &mut problems),
+        };
+        let file_config =
+            match fs::read_to_string(config_path.as_deref().unwrap_or(DEFAULT_CONFIG_PATH)) {
+                Ok(file_config) => toml::from_str(&file_config).unwrap_or_else(|err| {
+                    problems.push(format!("Config file is invalid: {err}"));
+                    ServiceConfigBuilder::default()
+                }),
+                // Only the default config file is optional.
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound && config_path.is_none() => {
+                    ServiceConfigBuilder::default()
+                }
+                Err(err) => return Err(err).context("Unable to read config file"),
+            };
+
+        let config = ServiceConfig {
+            address: config_str_value(
+                self.address,
+                ENV_VAR_ADDRESS,
+                file_config.address,
+                DEFAULT_ADDRESS,
+                &mut problems,
+            ),
+            namespace: config_str_value(
+                self.namespace,
+                ENV_VAR_NAMESPACE,
+                file_config.namespace,
+                DEFAULT_NAMESPACE,
+                &mut problems,
+            ),
+            database: config_str_value(
+                self.database,
+                ENV_VAR_DATABASE,
+                file_config.database,
+                DEFAULT_DATABASE,
+                &mut problems,
+            ),
+            db_pool_size: config_parsed_value(
+                self.db_pool_size,
+                ENV_VAR_DB_POOL_SIZE,
+                file_config.db_pool_size,
+                DEFAULT_DB_POOL_SIZE,
+                &mut problems,
+            ),
+            db_query_timeout_secs: config_parsed_value(
+                self.db_query_timeout_secs,
+                ENV_VAR_DB_QUERY_TIMEOUT_SECS,
+                file_config.db_query_timeout_secs,
+                DEFAULT_DB_QUERY_TIMEOUT_SECS,
+                &mut problems,
+            ),
+            auto_migrate: config_parsed_value(
+                self.auto_migrate,
+                ENV_VAR_AUTO_MIGRATE,
+                file_config.auto_migrate,
+                DEFAULT_AUTO_MIGRATE,
+                &mut problems,
+            ),
+            private_key: match self.private_key {
+                Some(private_key) => Some(private_key),
+                None => env_value(ENV_VAR_PRIVATE_KEY, &mut problems).or(file_config.private_key),
+            },
+            private_key_path: config_str_value(
+                self.private_key_path,
+                ENV_VAR_PRIVATE_KEY_PATH,
+                file_config.private_key_path,
+                DEFAULT_PRIVATE_KEY_PATH,
+                &mut problems,
+            ),
+            private_key_create: self.private_key_create,
+            log_dir: config_str_value(
+                self.log_dir,
+                ENV_VAR_LOG_DIR,
+                file_config.log_dir,
+                DEFAULT_LOG_DIR,
+                &mut problems,
+            ),
+            media_dir: config_str_value(
+                self.media_dir,
+                ENV_VAR_MEDIA_DIR,
+                file_config.media_dir,
+                DEFAULT_MEDIA_DIR,
+                &mut problems,
+            ),
+            log_level_stdout: config_level_value(
+                self.log_level_stdout,
+                ENV_VAR_LOG_LEVEL_STDOUT,
+                file_config.log_level_stdout,
+                DEFAULT_LOG_LEVEL_STDOUT,
+                &mut problems,
+            ),
+            log_level_file: config_level_value(
+                self.log_level_file,
+                ENV_VAR_LOG_LEVEL_FILE,
+                file_config.log_level_file,
+                DEFAULT_LOG_LEVEL_FILE,
+                &mut problems,
+            ),
+            host: config_str_value(
+                self.host,
+                ENV_VAR_HOST,
+                file_config.host,
+                DEFAULT_HOST,
+                &mut problems,
+            ),
+            port: config_parsed_value(
+                self.port,
+                ENV_VAR_PORT,
+                file_config.port,
+                DEFAULT_PORT,
+                &mut problems,
+            ),
+            cors_origins: match self.cors_origins {
+                Some(cors_origins) => Some(cors_origins),
+                None => env_value(ENV_VAR_CORS_ORIGINS, &mut problems).or(file_config.cors_origins),
+            },
+            public_stats: config_parsed_value(
+                self.public_stats,
+                ENV_VAR_PUBLIC_STATS,
+                file_config.public_stats,
+                DEFAULT_PUBLIC_STATS,
+                &mut problems,
+            ),
+            spam_review_threshold: config_parsed_value(
+                self.spam_review_threshold,
+                ENV_VAR_SPAM_REVIEW_THRESHOLD,
+                file_config.spam_review_threshold,
+                DEFAULT_SPAM_REVIEW_THRESHOLD,
+                &mut problems,
+            ),
+            spam_limit_threshold: config_parsed_value(
+                self.spam_limit_threshold,
+                ENV_VAR_SPAM_LIMIT_THRESHOLD,
+                file_config.spam_limit_threshold,
+                DEFAULT_SPAM_LIMIT_THRESHOLD,
+                &mut problems,
+            ),
+            spam_classifier_url: match self.spam_classifier_url {
+                Some(spam_classifier_url) => Some(spam_classifier_url),
+                None => env_value(ENV_VAR_SPAM_CLASSIFIER_URL, &mut problems)
+                    .or(file_config.spam_classifier_url),
+            },
+            dev_auth: config_parsed_value(
+                self.dev_auth,
+                ENV_VAR_DEV_AUTH,
+                file_config.dev_auth,
+                DEFAULT_DEV_AUTH,
+                &mut problems,
+            ),
+            dev_auth_allow_release: config_parsed_value(
+                self.dev_auth_allow_release,
+                ENV_VAR_DEV_AUTH_ALLOW_RELEASE,
+                file_config.dev_auth_allow_release,
+                DEFAULT_DEV_AUTH_ALLOW_RELEASE,
+                &mut problems,
+            ),
+            max_concurrency: config_parsed_value(
+                self.max_concurrency,
+                ENV_VAR_MAX_CONCURRENCY,
+                file_config.max_concurrency,
+                DEFAULT_MAX_CONCURRENCY,
+                &mut problems,
+            ),
+            max_graphql_concurrency: config_parsed_value(
+                self.max_graphql_concurrency,
+                ENV_VAR_MAX_GRAPHQL_CONCURRENCY,
+                file_config.max_graphql_concurrency,
+                DEFAULT_MAX_GRAPHQL_CONCURRENCY,
+                &mut problems,
+            ),
+            max_queue_ms: config_parsed_value(
+                self.max_queue_ms,
+                ENV_VAR_MAX_QUEUE_MS,
+                file_config.max_queue_ms,
+                DEFAULT_MAX_QUEUE_MS,
+                &mut problems,
+            ),
+            rate_limit_auth: config_parsed_value(
+                self.rate_limit_auth,
+                ENV_VAR_RATE_LIMIT_AUTH,
+                file_config.rate_limit_auth,
+                DEFAULT_RATE_LIMIT_AUTH,
+                &mut problems,
+            ),
+            rate_limit_read: config_parsed_value(
+                self.rate_limit_read,
+                ENV_VAR_RATE_LIMIT_READ,
+                file_config.rate_limit_read,
+                DEFAULT_RATE_LIMIT_READ,
+                &mut problems,
+            ),
+            rate_limit_write: config_parsed_value(
+                self.rate_limit_write,
+                ENV_VAR_RATE_LIMIT_WRITE,
+                file_config.rate_limit_write,
+                DEFAULT_RATE_LIMIT_WRITE,
+                &mut problems,
+            ),
+            max_query_complexity: config_parsed_value(
+                self.max_query_complexity,
+                ENV_VAR_MAX_QUERY_COMPLEXITY,
+                file_config.max_query_complexity,
+                DEFAULT_MAX_QUERY_COMPLEXITY,
+                &mut problems,
+            ),
+            max_query_depth: config_parsed_value(
+                self.max_query_depth,
+                ENV_VAR_MAX_QUERY_DEPTH,
+                file_config.max_query_depth,
+                DEFAULT_MAX_QUERY_DEPTH,
+                &mut problems,
+            ),
+            persisted_query_cache_size: config_parsed_value(
+                self.persisted_query_cache_size,
+                ENV_VAR_PERSISTED_QUERY_CACHE_SIZE,
+                file_config.persisted_query_cache_size,
+                DEFAULT_PERSISTED_QUERY_CACHE_SIZE,
+                &mut problems,
+            ),
+            persist_queries: config_parsed_value(
+                self.persist_queries,
+                ENV_VAR_PERSIST_QUERIES,
+                file_config.persist_queries,
+                DEFAULT_PERSIST_QUERIES,
+                &mut problems,
+            ),
+            persisted_query_allow_list: match self.persisted_query_allow_list {
+                Some(persisted_query_allow_list) => Some(persisted_query_allow_list),
+                None => env_value(ENV_VAR_PERSISTED_QUERY_ALLOW_LIST, &mut problems)
+                    .or(file_config.persisted_query_allow_list),
+            },
+            public_url: match self.public_url {
+                Some(public_url) => Some(public_url),
+                None => env_value(ENV_VAR_PUBLIC_URL, &mut problems).or(file_config.public_url),
+            },
+            region: match self.region {
+                Some(region) => Some(region),
+                None => env_value(ENV_VAR_REGION, &mut problems).or(file_config.region),
+            },
+            min_age: config_parsed_value(
+                self.min_age,
+                ENV_VAR_MIN_AGE,
+                file_config.min_age,
+                DEFAULT_MIN_AGE,
+                &mut problems,
+            ),
+            deletion_grace_days: config_parsed_value(
+                self.deletion_grace_days,
+                ENV_VAR_DELETION_GRACE_DAYS,
+                file_config.deletion_grace_days,
+                DEFAULT_DELETION_GRACE_DAYS,
+                &mut problems,
+            ),
+            dormant_after_days: config_parsed_value(
+                self.dormant_after_days,
+                ENV_VAR_DORMANT_AFTER_DAYS,
+                file_config.dormant_after_days,
+                DEFAULT_DORMANT_AFTER_DAYS,
+                &mut problems,
+            ),
+            dormancy_grace_days: config_parsed_value(
+                self.dormancy_grace_days,
+                ENV_VAR_DORMANCY_GRACE_DAYS,
+                file_config.dormancy_grace_days,
+                DEFAULT_DORMANCY_GRACE_DAYS,
+                &mut problems,
+            ),
+            release_dormant_user_ids: config_parsed_value(
+                self.release_dormant_user_ids,
+                ENV_VAR_RELEASE_DORMANT_USER_IDS,
+                file_config.release_dormant_user_ids,
+                DEFAULT_RELEASE_DORMANT_USER_IDS,
+                &mut problems,
+            ),
+            allow_confusable_user_ids: config_parsed_value(
+                self.allow_confusable_user_ids,
+                ENV_VAR_ALLOW_CONFUSABLE_USER_IDS,
+                file_config.allow_confusable_user_ids,
+                DEFAULT_ALLOW_CONFUSABLE_USER_IDS,
+                &mut problems,
+            ),
+            require_verified_email: config_parsed_value(
+                self.require_verified_email,
+                ENV_VAR_REQUIRE_VERIFIED_EMAIL,
+                file_config.require_verified_email,
+                DEFAULT_REQUIRE_VERIFIED_EMAIL,
+                &mut problems,
+            ),
+            smtp_address: match self.smtp_address {
+                Some(smtp_address) => Some(smtp_address),
+                None => env_value(ENV_VAR_SMTP_ADDRESS, &mut problems).or(file_config.smtp_address),
+            },
+            email_from: config_str_value(
+                self.email_from,
+                ENV_VAR_EMAIL_FROM,
+                file_config.email_from,
+                DEFAULT_EMAIL_FROM,
+                &mut problems,
+            ),
+            ip_storage: config_parsed_value(
+                self.ip_storage,
+                ENV_VAR_IP_STORAGE,
+                file_config.ip_storage,
+                DEFAULT_IP_STORAGE,
+                &mut problems,
+            ),
+            metadata_visibility: config_parsed_value(
+                self.metadata_visibility,
+                ENV_VAR_METADATA_VISIBILITY,
+                file_config.metadata_visibility,
+                DEFAULT_METADATA_VISIBILITY,
+                &mut problems,
+            ),
+            metadata_retention_days: config_parsed_value(
+                self.metadata_retention_days,
+                ENV_VAR_METADATA_RETENTION_DAYS,
+                file_config.metadata_retention_days,
+                DEFAULT_METADATA_RETENTION_DAYS,
+                &mut problems,
+            ),
+            quota_posts: config_parsed_value(
+                self.quota_posts,
+                ENV_VAR_QUOTA_POSTS,
+                file_config.quota_posts,
+                DEFAULT_QUOTA_POSTS,
+                &mut problems,
+            ),
+            quota_boards: config_parsed_value(
+                self.quota_boards,
+                ENV_VAR_QUOTA_BOARDS,
+                file_config.quota_boards,
+                DEFAULT_QUOTA_BOARDS,
+                &mut problems,
+            ),
+            quota_lists: config_parsed_value(
+                self.quota_lists,
+                ENV_VAR_QUOTA_LISTS,
+                file_config.quota_lists,
+                DEFAULT_QUOTA_LISTS,
+                &mut problems,
+            ),
+            quota_storage_bytes: config_parsed_value(
+                self.quota_storage_bytes,
+                ENV_VAR_QUOTA_STORAGE_BYTES,
+                file_config.quota_storage_bytes,
+                DEFAULT_QUOTA_STORAGE_BYTES,
+                &mut problems,
+            ),
+            max_post_title_length: config_parsed_value(
+                self.max_post_title_length,
+                ENV_VAR_MAX_POST_TITLE_LENGTH,
+                file_config.max_post_title_length,
+                DEFAULT_MAX_POST_TITLE_LENGTH,
+                &mut problems,
+            ),
+            max_post_content_length: config_parsed_value(
+                self.max_post_content_length,
+                ENV_VAR_MAX_POST_CONTENT_LENGTH,
+                file_config.max_post_content_length,
+                DEFAULT_MAX_POST_CONTENT_LENGTH,
+                &mut problems,
+            ),
+            max_board_name_length: config_parsed_value(
+                self.max_board_name_length,
+                ENV_VAR_MAX_BOARD_NAME_LENGTH,
+                file_config.max_board_name_length,
+                DEFAULT_MAX_BOARD_NAME_LENGTH,
+                &mut problems,
+            ),
+            max_board_description_length: config_parsed_value(
+                self.max_board_description_length,
+                ENV_VAR_MAX_BOARD_DESCRIPTION_LENGTH,
+                file_config.max_board_description_length,
+                DEFAULT_MAX_BOARD_DESCRIPTION_LENGTH,
+                &mut problems,
+            ),
+            signup_honeypot_score: config_parsed_value(
+                self.signup_honeypot_score,
+                ENV_VAR_SIGNUP_HONEYPOT_SCORE,
+                file_config.signup_honeypot_score,
+                DEFAULT_SIGNUP_HONEYPOT_SCORE,
+                &mut problems,
+            ),
+            signup_min_form_secs: config_parsed_value(
+                self.signup_min_form_secs,
+                ENV_VAR_SIGNUP_MIN_FORM_SECS,
+                file_config.signup_min_form_secs,
+                DEFAULT_SIGNUP_MIN_FORM_SECS,
+                &mut problems,
+            ),
+            signup_too_fast_score: config_parsed_value(
+                self.signup_too_fast_score,
+                ENV_VAR_SIGNUP_TOO_FAST_SCORE,
+                file_config.signup_too_fast_score,
+                DEFAULT_SIGNUP_TOO_FAST_SCORE,
+                &mut problems,
+            ),
+            read_only: config_parsed_value(
+                self.read_only,
+                ENV_VAR_READ_ONLY,
+                file_config.read_only,
+                DEFAULT_READ_ONLY,
+                &mut problems,
+            ),
+            read_only_after_failures: config_parsed_value(
+                self.read_only_after_failures,
+                ENV_VAR_READ_ONLY_AFTER_FAILURES,
+                file_config.read_only_after_failures,
+                DEFAULT_READ_ONLY_AFTER_FAILURES,
+                &mut problems,
+            ),
+            read_only_cooldown_secs: config_parsed_value(
+                self.read_only_cooldown_secs,
+                ENV_VAR_READ_ONLY_COOLDOWN_SECS,
+                file_config.read_only_cooldown_secs,
+                DEFAULT_READ_ONLY_COOLDOWN_SECS,
+                &mut problems,
+            ),
+            min_client_versions: match self.min_client_versions {
+                Some(min_client_versions) => Some(min_client_versions),
+                None => env_value(ENV_VAR_MIN_CLIENT_VERSIONS, &mut problems)
+                    .or(file_config.min_client_versions),
+            },
+            max_media_bytes: config_parsed_value(
+                self.max_media_bytes,
+                ENV_VAR_MAX_MEDIA_BYTES,
+                file_config.max_media_bytes,
+                DEFAULT_MAX_MEDIA_BYTES,
+                &mut problems,
+            ),
+            media_gc_grace_secs: config_parsed_value(
+                self.media_gc_grace_secs,
+                ENV_VAR_MEDIA_GC_GRACE_SECS,
+                file_config.media_gc_grace_secs,
+                DEFAULT_MEDIA_GC_GRACE_SECS,
+                &mut problems,
+            ),
+            media_url_ttl_secs: config_parsed_value(
+                self.media_url_ttl_secs,
+                ENV_VAR_MEDIA_URL_TTL_SECS,
+                file_config.media_url_ttl_secs,
+                DEFAULT_MEDIA_URL_TTL_SECS,
+                &mut problems,
+            ),
+            media_referer_hosts: match self.media_referer_hosts {
+                Some(media_referer_hosts) => Some(media_referer_hosts),
+                None => env_value(ENV_VAR_MEDIA_REFERER_HOSTS, &mut problems)
+                    .or(file_config.media_referer_hosts),
+            },
+            alt_text_policy: config_parsed_value(
+                self.alt_text_policy,
+                ENV_VAR_ALT_TEXT_POLICY,
+                file_config.alt_text_policy,
+                DEFAULT_ALT_TEXT_POLICY,
+                &mut problems,
+            ),
+            post_visibility: config_parsed_value(
+                self.post_visibility,
+                ENV_VAR_POST_VISIBILITY,
+                file_config.post_visibility,
+                DEFAULT_POST_VISIBILITY,
+                &mut problems,
+            ),
+            max_post_visibility: config_parsed_value(
+                self.max_post_visibility,
+                ENV_VAR_MAX_POST_VISIBILITY,
+                file_config.max_post_visibility,
+                DEFAULT_MAX_POST_VISIBILITY,
+                &mut problems,
+            ),
+            session_idle_timeout_secs: config_parsed_value(
+                self.session_idle_timeout_secs,
+                ENV_VAR_SESSION_IDLE_TIMEOUT_SECS,
+                file_config.session_idle_timeout_secs,
+                DEFAULT_SESSION_IDLE_TIMEOUT_SECS,
+                &mut problems,
+            ),
+            session_max_lifetime_secs: config_parsed_value(
+                self.session_max_lifetime_secs,
+                ENV_VAR_SESSION_MAX_LIFETIME_SECS,
+                file_config.session_max_lifetime_secs,
+                DEFAULT_SESSION_MAX_LIFETIME_SECS,
+                &mut problems,
+            ),
+            admin_session_idle_timeout_secs: config_parsed_value(
+                self.admin_session_idle_timeout_secs,
+                ENV_VAR_ADMIN_SESSION_IDLE_TIMEOUT_SECS,
+                file_config.admin_session_idle_timeout_secs,
+                DEFAULT_ADMIN_SESSION_IDLE_TIMEOUT_SECS,
+                &mut problems,
+            ),
+            admin_session_max_lifetime_secs: config_parsed_value(
+                self.admin_session_max_lifetime_secs,
+                ENV_VAR_ADMIN_SESSION_MAX_LIFETIME_SECS,
+                file_config.admin_session_max_lifetime_secs,
+                DEFAULT_ADMIN_SESSION_MAX_LIFETIME_SECS,
+                &mut problems,
+            ),
+            argon2_memory_kib: config_parsed_value(
+                self.argon2_memory_kib,
+                ENV_VAR_ARGON2_MEMORY_KIB,
+                file_config.argon2_memory_kib,
+                DEFAULT_ARGON2_MEMORY_KIB,
+                &mut problems,
+            ),
+            argon2_iterations: config_parsed_value(
+                self.argon2_iterations,
+                ENV_VAR_ARGON2_ITERATIONS,
+                file_config.argon2_iterations,
+                DEFAULT_ARGON2_ITERATIONS,
+                &mut problems,
+            ),
+            argon2_parallelism: config_parsed_value(
+                self.argon2_parallelism,
+                ENV_VAR_ARGON2_PARALLELISM,
+                file_config.argon2_parallelism,
+                DEFAULT_ARGON2_PARALLELISM,
+                &mut problems,
+            ),
+            translations_per_hour: config_parsed_value(
+                self.translations_per_hour,
+                ENV_VAR_TRANSLATIONS_PER_HOUR,
+                file_config.translations_per_hour,
+                DEFAULT_TRANSLATIONS_PER_HOUR,
+                &mut problems,
+            ),
+            proxy_remote_content: config_parsed_value(
+                self.proxy_remote_content,
+                ENV_VAR_PROXY_REMOTE_CONTENT,
+                file_config.proxy_remote_content,
+                DEFAULT_PROXY_REMOTE_CONTENT,
+                &mut problems,
+            ),
+            proxy_max_media_bytes: config_parsed_value(
+                self.proxy_max_media_bytes,
+                ENV_VAR_PROXY_MAX_MEDIA_BYTES,
+                file_config.proxy_max_media_bytes,
+                DEFAULT_PROXY_MAX_MEDIA_BYTES,
+                &mut problems,
+            ),
+            proxy_cache_bytes: config_parsed_value(
+                self.proxy_cache_bytes,
+                ENV_VAR_PROXY_CACHE_BYTES,
+                file_config.proxy_cache_bytes,
+                DEFAULT_PROXY_CACHE_BYTES,
+                &mut problems,
+            ),
+            proxy_cache_ttl_secs: config_parsed_value(
+                self.proxy_cache_ttl_secs,
+                ENV_VAR_PROXY_CACHE_TTL_SECS,
+                file_config.proxy_cache_ttl_secs,
+                DEFAULT_PROXY_CACHE_TTL_SECS,
+                &mut problems,
+            ),
+            oidc_providers: self
+                .oidc_providers
+                .or(file_config.oidc_providers)
+                .unwrap_or_default(),
+            audit_sinks: self
+                .audit_sinks
+                .or(file_config.audit_sinks)
+                .unwrap_or_default(),
+            translation: self.translation.or(file_config.translation),
+        };
+
+        config.check(&mut problems);
+        if problems.is_empty() {
+            Ok(config)
+        } else {
+            let problems = ConfigProblems(problems);
+            let message = problems.to_string();
+            Err(anyhow::Error::new(problems).context(Error::ServerMisconfigured(message)))
+        }
+    }
+}
+
+fn config_str_value(
+    arg: Option<String>,
+    env_var: &str,
+    file: Option<String>,
+    default: &str,
+    problems: &mut Vec<String>,
+) -> String {
+    match arg {
+        Some(arg) => arg,
+        None => env_value(env_var, problems)
+            .or(file)
+            .unwrap_or_else(|| default.to_owned()),
+    }
+}
+
+/// Gets a value that's parsed from its environment variable, if it's set.
+/// One that can't be parsed is reported, and the value from the config file
+/// or the default is used instead, so that later problems are found too.
+fn config_parsed_value<T>(
+    arg: Option<T>,
+    env_var: &str,
+    file: Option<T>,
+    default: T,
+    problems: &mut Vec<String>,
+) -> T
+where
+    T: std::str::FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    let value = match arg {
+        Some(arg) => Some(arg),
+        None => env_value(env_var, problems).and_then(|value| match value.parse() {
+            Ok(value) => Some(value),
+            Err(err) => {
+                problems.push(format!(
+                    "Invalid value `{value}` in environment variable {env_var}: {err}"
+                ));
+                None
+            }
+        }),
+    };
+
+    value.or(file).unwrap_or(default)
+}
+
+fn config_level_value(
+    arg: Option<LogLevel>,
+    env_var: &str,
+    file: Option<LogLevel>,
+    default: LogLevel,
+    problems: &mut Vec<String>,
+) -> LogLevel {
+    let value = match arg {
+        Some(arg) => Some(arg),
+        None => env_value(env_var, problems).and_then(|value| match &*value.to_ascii_lowercase() {
+            "error" => Some(LogLevel::Error),
+            "warn" => Some(LogLevel::Warn),
+            "info" => Some(LogLevel::Info),
+            "debug" => Some(LogLevel::Debug),
+            "trace" => Some(LogLevel::Trace),
+            value => {
+                problems.push(format!(
+                    "Invalid log level {value:?} in environment variable {env_var}"
+                ));
+                None
+            }
+        }),
+    };
+
+    value.or(file).unwrap_or(default)
+}
+
+fn env_value(env_var: &str, problems: &mut Vec<String>) -> Option<String> {
+    match env::var(env_var) {
+        Ok(value) => Some(value),
+        Err(env::VarError::NotPresent) => None,
+        Err(env::VarError::NotUnicode(_)) => {
+            problems.push(format!(
+                "Environment variable {env_var} is not valid unicode"
+            ));
+            None
+        }
+    }
+}
+
+/// Turns a number of seconds into a duration, where 0 means there isn't one.
+/// Reads an allow-list of persisted queries, a JSON object of queries by
+/// their SHA-256 hash in hex, checking that each hash matches its query.
+fn read_persisted_query_allow_list(path: &str) -> anyhow::Result<HashMap<String, String>> {
+    let allow_list = fs::read_to_string(path)
+        .with_context(|| format!("Unable to read persisted query allow-list at {path}"))?;
+    let allow_list: HashMap<String, String> = serde_json::from_str(&allow_list)
+        .with_context(|| format!("Invalid persisted query allow-list at {path}"))?;
+    if let Some(hash) = allow_list
+        .iter()
+        .find(|(hash, query)| **hash != query_hash(query))
+        .map(|(hash, _)| hash)
+    {
+        return Err(anyhow::anyhow!(
+            "Persisted query {hash} in the allow-list at {path} doesn't match its hash"
+        ));
+    }
+    Ok(allow_list)
+}
+
+/// Parses the oldest supported version of each app, given as comma-separated
+/// `name=version` pairs.
+fn parse_min_client_versions(min_versions: &str) -> anyhow::Result<BTreeMap<String, String>> {
+    min_versions
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, version) = pair
+                .split_once('=')
+                .map(|(name, version)| (name.trim(), version.trim()))
+                .filter(|(name, _)| !name.is_empty())
+                .ok_or_else(|| {
+                    anyhow::anyhow!("Minimum client version {pair} must be a name=version pair")
+                })?;
+            if parse_version(version).is_none() {
+                return Err(anyhow::anyhow!(
+                    "Minimum client version {version} for {name} isn't a version"
+                ));
+            }
+            Ok((name.to_owned(), version.to_owned()))
+        })
+        .collect()
+}
+
+/// Whether a CORS origin is a scheme and host, with an optional port, and
+/// nothing after them.
+fn is_origin(origin: &str) -> bool {
+    let Some((scheme, host)) = origin.split_once("://") else {
+        return false;
+    };
+    matches!(scheme, "http" | "https")
+        && !host.is_empty()
+        && !host.contains(['/', '?', '#', '@'])
+        && host.parse::<hyper::http::uri::Authority>().is_ok()
+}
+
+fn non_zero_secs(secs: u64) -> Option<Duration> {
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+/// Checks that a region label can be sent in a header, which it can if it's
+/// made of ASCII letters, digits and hyphens.
+fn check_region(region: String) -> anyhow::Result<String> {
+    if region.is_empty()
+        || region.len() > 64
+        || !region
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-')
+    {
+        return Err(anyhow::anyhow!(
+            "Region {region:?} must be up to 64 letters, digits and hyphens"
+        ));
+    }
+    Ok(region)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct ServiceConfig {
+    address: String,
+    namespace: String,
+    database: String,
+    db_pool_size: usize,
+    db_query_timeout_secs: u64,
+    auto_migrate: bool,
+    private_key: Option<String>,
+    private_key_path: String,
+    #[serde(skip)]
+    private_key_create: Option<PrivateKeyCreate>,
+    log_dir: String,
+    media_dir: String,
+    log_level_stdout: LogLevel,
+    log_level_file: LogLevel,
+    host: String,
+    port: u16,
+    cors_origins: Option<String>,
+    public_stats: bool,
+    spam_review_threshold: u8,
+    spam_limit_threshold: u8,
+    spam_classifier_url: Option<String>,
+    dev_auth: bool,
+    dev_auth_allow_release: bool,
+    max_concurrency: usize,
+    max_graphql_concurrency: usize,
+    max_queue_ms: u64,
+    rate_limit_auth: u32,
+    rate_limit_read: u32,
+    rate_limit_write: u32,
+    max_query_complexity: usize,
+    max_query_depth: usize,
+    persisted_query_cache_size: usize,
+    persist_queries: bool,
+    persisted_query_allow_list: Option<String>,
+    public_url: Option<String>,
+    region: Option<String>,
+    min_age: u8,
+    allow_confusable_user_ids: bool,
+    require_verified_email: bool,
+    deletion_grace_days: u32,
+    dormant_after_days: u32,
+    dormancy_grace_days: u32,
+    release_dormant_user_ids: bool,
+    smtp_address: Option<String>,
+    email_from: String,
+    ip_storage: IpStorage,
+    metadata_visibility: MetadataVisibility,
+    metadata_retention_days: u32,
+    quota_posts: u32,
+    quota_boards: u32,
+    quota_lists: u32,
+    quota_storage_bytes: u64,
+    max_post_title_length: u32,
+    max_post_content_length: u32,
+    max_board_name_length: u32,
+    max_board_description_length: u32,
+    signup_honeypot_score: u8,
+    signup_min_form_secs: u32,
+    signup_too_fast_score: u8,
+    read_only: bool,
+    read_only_after_failures: u32,
+    read_only_cooldown_secs: u64,
+    min_client_versions: Option<String>,
+    max_media_bytes: u64,
+    media_gc_grace_secs: u64,
+    media_url_ttl_secs: u64,
+    media_referer_hosts: Option<String>,
+    alt_text_policy: AltTextPolicy,
+    post_visibility: PostVisibility,
+    max_post_visibility: PostVisibility,
+    session_idle_timeout_secs: u64,
+    session_max_lifetime_secs: u64,
+    admin_session_idle_timeout_secs: u64,
+    admin_session_max_lifetime_secs: u64,
+    argon2_memory_kib: u32,
+    argon2_iterations: u32,
+    argon2_parallelism: u32,
+    translations_per_hour: u32,
+    proxy_remote_content: bool,
+    proxy_max_media_bytes: u64,
+    proxy_cache_bytes: u64,
+    proxy_cache_ttl_secs: u64,
+    oidc_providers: Vec<OidcProviderConfig>,
+    audit_sinks: Vec<AuditSinkConfig>,
+    translation: Option<TranslationProviderConfig>,
+}
+
+impl ServiceConfig {
+    /// The parts of the config that can be reloaded while the server is
+    /// running.
+    #[must_use]
+    pub fn reloadable(&self) -> ReloadableConfig {
+        ReloadableConfig {
+            rate_limits: RateLimitConfig {
+                auth: RateBudget::per_minute(self.rate_limit_auth),
+                read: RateBudget::per_minute(self.rate_limit_read),
+                write: RateBudget::per_minute(self.rate_limit_write),
+            },
+            features: FeatureFlags {
+                public_stats: self.public_stats,
+                allow_confusable_user_ids: self.allow_confusable_user_ids,
+                require_verified_email: self.require_verified_email,
+            },
+            log_levels: LogLevels {
+                stdout: self.log_level_stdout,
+                file: self.log_level_file,
+            },
+        }
+    }
+}
+
+impl TryFrom<ServiceConfig> for (ServeConfig, LogConfig) {
+    type Error = anyhow::Error;
 
+    #[allow(clippy::too_many_lines)]
     fn try_from(value: ServiceConfig) -> Result<Self, Self::Error> {
+        let reloadable = value.reloadable();
         let private_key = if let Some(private_key) = value.private_key {
             private_key
         } else {
@@ -426,15 +2479,168 @@ impl TryFrom<ServiceConfig> for (ServeConfig, LogConfig) {
         };
 
         let (enc_key, dec_key) = create_key_pair(&private_key)?;
+        let clock: SharedClock = Arc::new(SystemClock);
+        let audit_sinks = audit_sinks(value.audit_sinks, &clock)?;
 
         let serve_config = ServeConfig {
             address: value.address,
             namespace: value.namespace,
             database: value.database,
+            db: DbConfig {
+                pool_size: value.db_pool_size,
+                query_timeout: non_zero_secs(value.db_query_timeout_secs),
+                auto_migrate: value.auto_migrate,
+            },
             jwt_enc_key: enc_key,
             jwt_dec_key: dec_key,
             host: value.host.parse()?,
             port: value.port,
+            cors: CorsConfig {
+                origins: value
+                    .cors_origins
+                    .iter()
+                    .flat_map(|origins| origins.split(','))
+                    .map(|origin| origin.trim().trim_end_matches('/').to_owned())
+                    .filter(|origin| !origin.is_empty())
+                    .collect(),
+            },
+            instance: InstanceConfig {
+                public_url: value
+                    .public_url
+                    .map(|url| url.trim_end_matches('/').to_owned()),
+                region: value.region.map(check_region).transpose()?,
+                min_age: value.min_age,
+                deletion_grace_days: value.deletion_grace_days,
+                dormant_after_days: value.dormant_after_days,
+                dormancy_grace_days: value.dormancy_grace_days,
+                release_dormant_user_ids: value.release_dormant_user_ids,
+            },
+            spam: SpamConfig {
+                review_threshold: value.spam_review_threshold,
+                limit_threshold: value.spam_limit_threshold,
+                classifier_url: value
+                    .spam_classifier_url
+                    .map(|url| url.parse())
+                    .transpose()
+                    .context("Spam classifier URL is invalid")?,
+                signup: SignupRiskConfig {
+                    honeypot_score: value.signup_honeypot_score,
+                    min_form_secs: value.signup_min_form_secs,
+                    too_fast_score: value.signup_too_fast_score,
+                },
+            },
+            dev_auth: DevAuthConfig {
+                enabled: value.dev_auth,
+                allow_release: value.dev_auth_allow_release,
+            },
+            overload: OverloadConfig {
+                max_concurrency: value.max_concurrency,
+                max_graphql_concurrency: value.max_graphql_concurrency,
+                max_queue_time: Duration::from_millis(value.max_queue_ms),
+            },
+            rate_limits: reloadable.rate_limits,
+            features: reloadable.features,
+            log_levels: reloadable.log_levels,
+            reload_from: None,
+            query_limits: QueryLimitsConfig {
+                max_complexity: value.max_query_complexity,
+                max_depth: value.max_query_depth,
+            },
+            persisted_queries: PersistedQueryConfig {
+                cache_size: value.persisted_query_cache_size,
+                persist: value.persist_queries,
+                allow_list: value
+                    .persisted_query_allow_list
+                    .as_deref()
+                    .map(read_persisted_query_allow_list)
+                    .transpose()?
+                    .map(Arc::new),
+            },
+            privacy: PrivacyConfig {
+                ip_storage: value.ip_storage,
+                metadata_visibility: value.metadata_visibility,
+                retention_days: value.metadata_retention_days,
+                hash_key: PrivacyConfig::derive_hash_key(&private_key),
+            },
+            quotas: QuotaConfig {
+                posts: value.quota_posts,
+                boards: value.quota_boards,
+                lists: value.quota_lists,
+                storage_bytes: value.quota_storage_bytes,
+            },
+            limits: LimitsConfig {
+                post_title: value.max_post_title_length,
+                post_content: value.max_post_content_length,
+                board_name: value.max_board_name_length,
+                board_description: value.max_board_description_length,
+                media_bytes: value.max_media_bytes,
+            },
+            media: MediaConfig {
+                dir: Some(value.media_dir.into()),
+                collect_after: Duration::from_secs(value.media_gc_grace_secs),
+                url_ttl: Duration::from_secs(value.media_url_ttl_secs),
+                referer_hosts: value
+                    .media_referer_hosts
+                    .iter()
+                    .flat_map(|hosts| hosts.split(','))
+                    .map(|host| host.trim().to_ascii_lowercase())
+                    .filter(|host| !host.is_empty())
+                    .collect(),
+                alt_text: value.alt_text_policy,
+            },
+            post_visibility: PostVisibilityConfig {
+                default: value.post_visibility,
+                max: value.max_post_visibility,
+            },
+            read_only: ReadOnlyConfig {
+                enabled: value.read_only,
+                after_failures: value.read_only_after_failures,
+                cooldown: Duration::from_secs(value.read_only_cooldown_secs),
+            },
+            client_versions: ClientVersionConfig {
+                min_versions: value
+                    .min_client_versions
+                    .as_deref()
+                    .map(parse_min_client_versions)
+                    .transpose()?
+                    .unwrap_or_default(),
+            },
+            sessions: SessionConfig {
+                idle_timeout: non_zero_secs(value.session_idle_timeout_secs),
+                max_lifetime: non_zero_secs(value.session_max_lifetime_secs),
+                admin_idle_timeout: non_zero_secs(value.admin_session_idle_timeout_secs),
+                admin_max_lifetime: non_zero_secs(value.admin_session_max_lifetime_secs),
+            },
+            ids: Arc::new(UlidGen::new(clock.clone())),
+            clock,
+            password_hasher: Arc::new(
+                Argon2Hasher::new(PasswordHashConfig {
+                    memory_kib: value.argon2_memory_kib,
+                    iterations: value.argon2_iterations,
+                    parallelism: value.argon2_parallelism,
+                })
+                .context("Password hashing parameters are invalid")?,
+            ),
+            webhooks: Arc::new(HttpWebhookSender::new()),
+            domains: Arc::new(HttpDomainVerifier::new()),
+            oidc: OidcConfig::new(value.oidc_providers)?,
+            audit_sinks,
+            translation: TranslationConfig {
+                provider: value.translation.map(translation_provider),
+                per_hour: value.translations_per_hour,
+            },
+            proxy: ProxyConfig {
+                enabled: value.proxy_remote_content,
+                max_media_bytes: value.proxy_max_media_bytes,
+                cache_bytes: value.proxy_cache_bytes,
+                cache_ttl: Duration::from_secs(value.proxy_cache_ttl_secs),
+            },
+            oidc_client: Arc::new(HttpOidcClient::new()),
+            notification_transport: Arc::new(NoNotificationTransport),
+            email: match value.smtp_address {
+                Some(address) => Arc::new(SmtpEmailSender::new(address, value.email_from)),
+                None => Arc::new(NoEmailSender),
+            },
         };
 
         let log_config = LogConfig {
@@ -447,15 +2653,1016 @@ impl TryFrom<ServiceConfig> for (ServeConfig, LogConfig) {
     }
 }
 
+impl ServiceConfig {
+    /// Finds the problems that would stop the config from being served,
+    /// without reading any files.
+    fn check(&self, problems: &mut Vec<String>) {
+        if self.host.parse::<IpAddr>().is_err() {
+            problems.push(format!("Host {:?} isn't an IP address", self.host));
+        }
+        if self.db_pool_size == 0 {
+            problems.push("Database pool size must be at least 1".into());
+        }
+        for origin in self
+            .cors_origins
+            .iter()
+            .flat_map(|origins| origins.split(','))
+        {
+            let origin = origin.trim().trim_end_matches('/');
+            if !origin.is_empty() && origin != "*" && !is_origin(origin) {
+                problems.push(format!(
+                    "CORS origin {origin:?} must be `*` or a scheme and host, such as https://example.com"
+                ));
+            }
+        }
+        if let Some(public_url) = &self.public_url {
+            if !public_url.starts_with("https://") && !public_url.starts_with("http://") {
+                problems.push(format!("Public URL {public_url:?} must be an http(s) URL"));
+            }
+        }
+        if let Some(Err(err)) = self.region.clone().map(check_region) {
+            problems.push(err.to_string());
+        }
+        if self.spam_review_threshold > 100 || self.spam_limit_threshold > 100 {
+            problems.push("Spam thresholds must be scores out of 100".into());
+        }
+        if self.post_visibility < self.max_post_visibility {
+            problems.push(format!(
+                "The default post visibility ({}) can't be more open than the maximum ({})",
+                self.post_visibility, self.max_post_visibility
+            ));
+        }
+        if self.spam_review_threshold > self.spam_limit_threshold {
+            problems.push(
+                "The spam review threshold must be no higher than the limit threshold".into(),
+            );
+        }
+        if let Some(Err(err)) = self
+            .spam_classifier_url
+            .as_deref()
+            .map(str::parse::<hyper::Uri>)
+        {
+            problems.push(format!("Spam classifier URL is invalid: {err}"));
+        }
+        if let Some(Err(err)) = self
+            .min_client_versions
+            .as_deref()
+            .map(parse_min_client_versions)
+        {
+            problems.push(err.to_string());
+        }
+        if let Some(smtp_address) = &self.smtp_address {
+            if !smtp_address.contains(':') {
+                problems.push(format!(
+                    "SMTP address {smtp_address:?} must be a host:port pair"
+                ));
+            }
+        }
+        if !self.email_from.contains('@') {
+            problems.push(format!(
+                "Email from address {:?} isn't an email address",
+                self.email_from
+            ));
+        }
+        if let Err(err) = Argon2Hasher::new(PasswordHashConfig {
+            memory_kib: self.argon2_memory_kib,
+            iterations: self.argon2_iterations,
+            parallelism: self.argon2_parallelism,
+        }) {
+            problems.push(format!("Password hashing parameters are invalid: {err}"));
+        }
+        if let Err(err) = OidcConfig::new(self.oidc_providers.clone()) {
+            problems.push(err.to_string());
+        }
+    }
+
+    /// The `host:port` of the SMTP server that email is sent through, if
+    /// there is one.
+    #[must_use]
+    pub fn smtp_address(&self) -> Option<&str> {
+        self.smtp_address.as_deref()
+    }
+
+    /// Reads the private key and checks that tokens can be signed with it.
+    /// Unlike serving, a missing key isn't created.
+    pub fn check_private_key(&self) -> anyhow::Result<()> {
+        let private_key = match &self.private_key {
+            Some(private_key) => private_key.clone(),
+            None => fs::read_to_string(&self.private_key_path).with_context(|| {
+                format!("Unable to read private key at {}", self.private_key_path)
+            })?,
+        };
+        create_key_pair(&private_key)?;
+        Ok(())
+    }
+
+    /// Stops a missing private key from being created when the config is
+    /// turned into a [`ServeConfig`].
+    #[must_use]
+    pub fn without_private_key_create(mut self) -> Self {
+        self.private_key_create = None;
+        self
+    }
+}
+
 #[derive(Clone)]
 pub struct ServeConfig {
     pub address: String,
     pub namespace: String,
     pub database: String,
+    pub db: DbConfig,
     pub jwt_enc_key: jsonwebtoken::EncodingKey,
     pub jwt_dec_key: jsonwebtoken::DecodingKey,
     pub host: IpAddr,
     pub port: u16,
+    pub cors: CorsConfig,
+    pub instance: InstanceConfig,
+    pub spam: SpamConfig,
+    pub dev_auth: DevAuthConfig,
+    pub overload: OverloadConfig,
+    pub rate_limits: RateLimitConfig,
+    /// The parts of the instance that are turned on.
+    pub features: FeatureFlags,
+    /// How much is logged. This is only used when the config is reloaded, as
+    /// logging is set up before the server starts.
+    pub log_levels: LogLevels,
+    /// What the config is reloaded from, when the server is sent `SIGHUP` or
+    /// an admin asks. Only the parts in [`ReloadableConfig`] are reloaded.
+    /// Without it, the config can't be reloaded.
+    pub reload_from: Option<ServiceConfigBuilder>,
+    pub query_limits: QueryLimitsConfig,
+    pub persisted_queries: PersistedQueryConfig,
+    pub privacy: PrivacyConfig,
+    pub quotas: QuotaConfig,
+    pub limits: LimitsConfig,
+    pub media: MediaConfig,
+    /// Who new posts can be shown to.
+    pub post_visibility: PostVisibilityConfig,
+    pub read_only: ReadOnlyConfig,
+    pub client_versions: ClientVersionConfig,
+    pub sessions: SessionConfig,
+    /// The source of time for token expiry, jobs and stored records.
+    pub clock: SharedClock,
+    /// How new record IDs are generated.
+    pub ids: SharedIdGen,
+    /// How account passwords are hashed.
+    pub password_hasher: SharedPasswordHasher,
+    /// How webhook deliveries are sent.
+    pub webhooks: SharedWebhookSender,
+    /// How organizations' domains are checked.
+    pub domains: SharedDomainVerifier,
+    /// The external identity providers that accounts can sign in with.
+    pub oidc: OidcConfig,
+    /// How sign-ins with external identity providers are finished.
+    pub oidc_client: SharedOidcClient,
+    /// How notifications are pushed and emailed. By default they're only
+    /// shown in the app.
+    pub notification_transport: SharedNotificationTransport,
+    /// How the instance sends its own email, such as password reset codes.
+    /// By default it doesn't send any.
+    pub email: SharedEmailSender,
+    /// Where the audit trail is shipped to. By default it's only kept in the
+    /// database.
+    pub audit_sinks: Vec<SharedAuditSink>,
+    /// How posts are translated. By default they can't be.
+    pub translation: TranslationConfig,
+    /// Whether outbound links and remote media are sent through the
+    /// instance. By default they aren't.
+    pub proxy: ProxyConfig,
+}
+
+/// The Argon2id parameters that passwords are hashed with. Hashes made with
+/// other parameters are replaced when their accounts next log in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PasswordHashConfig {
+    /// How much memory hashing a password uses, in KiB.
+    pub memory_kib: u32,
+    /// How many passes are made over the memory.
+    pub iterations: u32,
+    /// How many lanes the memory is split into.
+    pub parallelism: u32,
+}
+
+impl Default for PasswordHashConfig {
+    fn default() -> Self {
+        Self {
+            memory_kib: DEFAULT_ARGON2_MEMORY_KIB,
+            iterations: DEFAULT_ARGON2_ITERATIONS,
+            parallelism: DEFAULT_ARGON2_PARALLELISM,
+        }
+    }
+}
+
+/// How the instance uses its database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DbConfig {
+    /// How many connections are opened and shared between requests.
+    /// Embedded databases only ever have one.
+    pub pool_size: usize,
+    /// How long a query can take before it fails, if there's a limit.
+    pub query_timeout: Option<Duration>,
+    /// Whether migrations are run when the server starts. When they aren't,
+    /// the server won't start until they've been run by hand.
+    pub auto_migrate: bool,
+}
+
+impl Default for DbConfig {
+    fn default() -> Self {
+        Self {
+            pool_size: DEFAULT_DB_POOL_SIZE,
+            query_timeout: non_zero_secs(DEFAULT_DB_QUERY_TIMEOUT_SECS),
+            auto_migrate: DEFAULT_AUTO_MIGRATE,
+        }
+    }
+}
+
+/// Settings that affect how the instance presents itself to clients.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct InstanceConfig {
+    /// The URL the instance is reached at, without a trailing slash. Link
+    /// previews use relative URLs when it isn't set.
+    pub public_url: Option<String>,
+    /// The region this server runs in, when the instance is deployed across
+    /// several. It's sent back with every response, and kept with sessions,
+    /// security events and metrics.
+    pub region: Option<String>,
+    /// The minimum age, in years, that people must be to register. Birthdates
+    /// are only required when this isn't 0.
+    pub min_age: u8,
+    /// How many days deleted accounts can be restored for, before they and
+    /// their content are purged and their user IDs can be registered again.
+    pub deletion_grace_days: u32,
+    /// How many days an account can go without signing in before it's
+    /// dormant and sent a notice, or 0 to leave inactive accounts alone.
+    pub dormant_after_days: u32,
+    /// How many days dormant accounts have to sign in after being sent a
+    /// notice, before their user IDs can be released.
+    pub dormancy_grace_days: u32,
+    /// Whether the user IDs of dormant accounts are released once their
+    /// grace period ends, so that others can register them.
+    pub release_dormant_user_ids: bool,
+}
+
+/// Parts of the instance that can be turned on and off while the server is
+/// running.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FeatureFlags {
+    /// Whether coarse instance statistics are shown to everyone.
+    pub public_stats: bool,
+    /// Whether user IDs can be registered that look like ones that are
+    /// already in use or reserved.
+    pub allow_confusable_user_ids: bool,
+    /// Whether accounts have to verify their email address before they can
+    /// post, create boards or upload media. Admins don't.
+    pub require_verified_email: bool,
+}
+
+/// How much is logged to each place.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogLevels {
+    pub stdout: LogLevel,
+    pub file: LogLevel,
+}
+
+impl Default for LogLevels {
+    fn default() -> Self {
+        Self {
+            stdout: DEFAULT_LOG_LEVEL_STDOUT,
+            file: DEFAULT_LOG_LEVEL_FILE,
+        }
+    }
+}
+
+/// The parts of the config that can be reloaded while the server is running,
+/// without restarting it.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ReloadableConfig {
+    pub rate_limits: RateLimitConfig,
+    pub features: FeatureFlags,
+    pub log_levels: LogLevels,
+}
+
+/// Which kind of identity provider an [`OidcProviderConfig`] is for. Google
+/// and GitHub have their endpoints filled in.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OidcProviderKind {
+    Google,
+    #[serde(rename = "github")]
+    GitHub,
+    /// Any other `OpenID Connect` provider, which has to be given its
+    /// endpoints.
+    #[default]
+    Oidc,
+}
+
+/// An external identity provider that accounts can sign in with, as it's
+/// configured.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OidcProviderConfig {
+    /// A short name for the provider, such as `google`, that clients give to
+    /// start signing in with it.
+    pub id: String,
+    #[serde(default)]
+    pub kind: OidcProviderKind,
+    /// The name shown to people signing in. Defaults to the ID.
+    pub name: Option<String>,
+    pub client_id: String,
+    pub client_secret: String,
+    pub authorization_url: Option<String>,
+    pub token_url: Option<String>,
+    pub userinfo_url: Option<String>,
+    /// The scopes to ask for, which default to the ones needed for the
+    /// account's ID, email and name.
+    pub scopes: Option<Vec<String>>,
+}
+
+/// An external identity provider that accounts can sign in with.
+#[derive(Debug, Clone)]
+pub struct OidcProvider {
+    pub id: String,
+    pub name: String,
+    pub client_id: String,
+    pub client_secret: SecretString,
+    pub authorization_url: String,
+    pub token_url: String,
+    pub userinfo_url: String,
+    pub scopes: Vec<String>,
+}
+
+impl TryFrom<OidcProviderConfig> for OidcProvider {
+    type Error = anyhow::Error;
+
+    fn try_from(config: OidcProviderConfig) -> Result<Self, Self::Error> {
+        let (authorization_url, token_url, userinfo_url, scopes): (_, _, _, &[&str]) =
+            match config.kind {
+                OidcProviderKind::Google => (
+                    Some("https://accounts.google.com/o/oauth2/v2/auth"),
+                    Some("https://oauth2.googleapis.com/token"),
+                    Some("https://openidconnect.googleapis.com/v1/userinfo"),
+                    &["openid", "email", "profile"],
+                ),
+                OidcProviderKind::GitHub => (
+                    Some("https://github.com/login/oauth/authorize"),
+                    Some("https://github.com/login/oauth/access_token"),
+                    Some("https://api.github.com/user"),
+                    &["read:user", "user:email"],
+                ),
+                OidcProviderKind::Oidc => (None, None, None, &["openid", "email", "profile"]),
+            };
+        let endpoint = |configured: Option<String>, default: Option<&str>, name: &str| {
+            configured
+                .or_else(|| default.map(Into::into))
+                .ok_or_else(|| anyhow::anyhow!("Identity provider {:?} has no {name}", config.id))
+        };
+
+        Ok(Self {
+            authorization_url: endpoint(
+                config.authorization_url.clone(),
+                authorization_url,
+                "authorization URL",
+            )?,
+            token_url: endpoint(config.token_url.clone(), token_url, "token URL")?,
+            userinfo_url: endpoint(config.userinfo_url.clone(), userinfo_url, "userinfo URL")?,
+            scopes: config
+                .scopes
+                .unwrap_or_else(|| scopes.iter().map(|&scope| scope.into()).collect()),
+            name: config.name.unwrap_or_else(|| config.id.clone()),
+            id: config.id,
+            client_id: config.client_id,
+            client_secret: config.client_secret.into(),
+        })
+    }
+}
+
+/// The external identity providers that accounts can sign in with.
+#[derive(Debug, Default, Clone)]
+pub struct OidcConfig {
+    pub providers: Vec<OidcProvider>,
+}
+
+impl OidcConfig {
+    /// Fills in the providers' endpoints, failing if any are missing or two
+    /// providers have the same ID.
+    pub fn new(providers: Vec<OidcProviderConfig>) -> anyhow::Result<Self> {
+        let providers = providers
+            .into_iter()
+            .map(OidcProvider::try_from)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        for (i, provider) in providers.iter().enumerate() {
+            if providers[..i].iter().any(|other| other.id == provider.id) {
+                return Err(anyhow::anyhow!(
+                    "Identity provider {:?} is configured more than once",
+                    provider.id
+                ));
+            }
+        }
+        Ok(Self { providers })
+    }
+
+    /// Gets a provider by its ID.
+    #[must_use]
+    pub fn provider(&self, id: &str) -> Option<&OidcProvider> {
+        self.providers.iter().find(|provider| provider.id == id)
+    }
+}
+
+/// A sink that the audit trail is shipped to, as it's configured.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditSinkConfig {
+    /// A short name for the sink, which its checkpoint is kept under. Changing
+    /// it starts the sink again from the beginning of the audit trail.
+    pub id: String,
+    #[serde(flatten)]
+    pub kind: AuditSinkKind,
+}
+
+/// Where an [`AuditSinkConfig`] ships entries to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AuditSinkKind {
+    /// A file of JSON lines, which is rotated once it grows past `max_bytes`,
+    /// keeping `keep` old files.
+    File {
+        path: String,
+        max_bytes: Option<u64>,
+        keep: Option<usize>,
+    },
+    /// A syslog server, given as `host:port`, that accepts TCP.
+    Syslog { address: String },
+    /// A URL that batches of entries are `POST`ed to.
+    Http { url: String, token: Option<String> },
+    /// An S3-compatible bucket.
+    S3 {
+        endpoint: String,
+        bucket: String,
+        region: String,
+        access_key_id: String,
+        secret_access_key: String,
+        prefix: Option<String>,
+    },
+}
+
+/// Creates the configured audit sinks, failing if any are invalid or two
+/// have the same ID.
+fn audit_sinks(
+    configs: Vec<AuditSinkConfig>,
+    clock: &SharedClock,
+) -> anyhow::Result<Vec<SharedAuditSink>> {
+    let mut sinks: Vec<SharedAuditSink> = Vec::with_capacity(configs.len());
+    for AuditSinkConfig { id, kind } in configs {
+        if sinks.iter().any(|sink| sink.id() == id) {
+            return Err(anyhow::anyhow!(
+                "Audit sink {id:?} is configured more than once"
+            ));
+        }
+        sinks.push(match kind {
+            AuditSinkKind::File {
+                path,
+                max_bytes,
+                keep,
+            } => Arc::new(FileAuditSink::new(
+                id,
+                path,
+                max_bytes.unwrap_or(DEFAULT_AUDIT_FILE_MAX_BYTES),
+                keep.unwrap_or(DEFAULT_AUDIT_FILE_KEEP),
+            )),
+            AuditSinkKind::Syslog { address } => Arc::new(SyslogAuditSink::new(id, address)),
+            AuditSinkKind::Http { url, token } => {
+                Arc::new(HttpAuditSink::new(id, url, token.map(Into::into)))
+            }
+            AuditSinkKind::S3 {
+                endpoint,
+                bucket,
+                region,
+                access_key_id,
+                secret_access_key,
+                prefix,
+            } => Arc::new(
+                S3AuditSink::new(
+                    id.clone(),
+                    S3Bucket {
+                        endpoint,
+                        bucket,
+                        region,
+                        prefix: prefix.unwrap_or_default(),
+                        access_key_id,
+                        secret_access_key: secret_access_key.into(),
+                    },
+                    clock.clone(),
+                )
+                .with_context(|| format!("Audit sink {id:?} is invalid"))?,
+            ),
+        });
+    }
+    Ok(sinks)
+}
+
+/// The provider that posts are translated with, as it's configured.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TranslationProviderConfig {
+    /// Marks posts with the language they were meant to be translated into
+    /// without changing them, for trying translation out.
+    Stub,
+    /// A `LibreTranslate` server at `url`.
+    LibreTranslate {
+        url: String,
+        api_key: Option<String>,
+    },
+    /// The `DeepL` API. The free or pro API is used depending on which the key
+    /// is for, unless `url` is given.
+    Deepl {
+        api_key: String,
+        url: Option<String>,
+    },
+}
+
+fn translation_provider(config: TranslationProviderConfig) -> SharedTranslationProvider {
+    match config {
+        TranslationProviderConfig::Stub => Arc::new(StubTranslationProvider),
+        TranslationProviderConfig::LibreTranslate { url, api_key } => {
+            Arc::new(LibreTranslateProvider::new(url, api_key.map(Into::into)))
+        }
+        TranslationProviderConfig::Deepl { api_key, url } => {
+            Arc::new(DeeplProvider::new(url, api_key.into()))
+        }
+    }
+}
+
+/// How posts are translated.
+#[derive(Debug, Clone)]
+pub struct TranslationConfig {
+    /// What posts are translated with. Posts can't be translated without one.
+    pub provider: Option<SharedTranslationProvider>,
+    /// How many posts each account can have translated an hour, or 0 for no
+    /// limit. Posts that had already been translated don't count.
+    pub per_hour: u32,
+}
+
+impl Default for TranslationConfig {
+    fn default() -> Self {
+        Self {
+            provider: None,
+            per_hour: DEFAULT_TRANSLATIONS_PER_HOUR,
+        }
+    }
+}
+
+/// Whether outbound links and remote media are sent through the instance,
+/// and how much remote media it keeps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProxyConfig {
+    /// Whether links and media are proxied at all.
+    pub enabled: bool,
+    /// The biggest remote media that's fetched.
+    pub max_media_bytes: u64,
+    /// How much remote media is kept in memory at once, after which what was
+    /// used longest ago is evicted.
+    pub cache_bytes: u64,
+    /// How long remote media is kept before it's fetched again.
+    pub cache_ttl: Duration,
+}
+
+impl Default for ProxyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: DEFAULT_PROXY_REMOTE_CONTENT,
+            max_media_bytes: DEFAULT_PROXY_MAX_MEDIA_BYTES,
+            cache_bytes: DEFAULT_PROXY_CACHE_BYTES,
+            cache_ttl: Duration::from_secs(DEFAULT_PROXY_CACHE_TTL_SECS),
+        }
+    }
+}
+
+/// Which browser origins can call the API. Requests from other origins still
+/// reach it, as CORS is only enforced by browsers.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CorsConfig {
+    /// Origins such as `https://example.com`, or `*` for any. Without any,
+    /// no CORS headers are sent.
+    pub origins: Vec<String>,
+}
+
+/// Whether clients can log into seeded accounts without credentials.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DevAuthConfig {
+    /// Whether development accounts are created and can be logged into with
+    /// `devLogin`.
+    pub enabled: bool,
+    /// Whether development authentication can be enabled in a release build.
+    pub allow_release: bool,
+}
+
+/// How many requests are handled at once before new ones are turned away.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OverloadConfig {
+    /// The maximum number of requests handled at once.
+    pub max_concurrency: usize,
+    /// The maximum number of GraphQL requests over HTTP handled at once.
+    pub max_graphql_concurrency: usize,
+    /// How long a request can wait for a slot before it is rejected.
+    pub max_queue_time: Duration,
+}
+
+impl Default for OverloadConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrency: DEFAULT_MAX_CONCURRENCY,
+            max_graphql_concurrency: DEFAULT_MAX_GRAPHQL_CONCURRENCY,
+            max_queue_time: Duration::from_millis(DEFAULT_MAX_QUEUE_MS),
+        }
+    }
+}
+
+/// How many requests each client can make to each group of routes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimitConfig {
+    /// Signing in, registering, and other requests that guess at
+    /// credentials.
+    pub auth: RateBudget,
+    /// Queries, and REST requests that don't change anything.
+    pub read: RateBudget,
+    /// Mutations, and REST requests that change something.
+    pub write: RateBudget,
+}
+
+impl RateLimitConfig {
+    /// Doesn't limit any requests.
+    #[must_use]
+    pub fn unlimited() -> Self {
+        Self {
+            auth: RateBudget::per_minute(0),
+            read: RateBudget::per_minute(0),
+            write: RateBudget::per_minute(0),
+        }
+    }
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            auth: RateBudget::per_minute(DEFAULT_RATE_LIMIT_AUTH),
+            read: RateBudget::per_minute(DEFAULT_RATE_LIMIT_READ),
+            write: RateBudget::per_minute(DEFAULT_RATE_LIMIT_WRITE),
+        }
+    }
+}
+
+/// How many requests can be made over a window of time. They can all be
+/// made at once, after which they're let through as the window moves on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateBudget {
+    /// How many requests can be made. 0 doesn't limit them.
+    pub requests: u32,
+    /// The window that the requests can be made over.
+    pub per: Duration,
+}
+
+impl RateBudget {
+    #[must_use]
+    pub fn per_minute(requests: u32) -> Self {
+        Self {
+            requests,
+            per: Duration::from_mins(1),
+        }
+    }
+}
+
+/// How much work a single GraphQL query can ask for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryLimitsConfig {
+    /// The most a query can cost. 0 doesn't limit it.
+    pub max_complexity: usize,
+    /// The most deeply fields can be nested in a query. 0 doesn't limit it.
+    pub max_depth: usize,
+}
+
+impl Default for QueryLimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_complexity: DEFAULT_MAX_QUERY_COMPLEXITY,
+            max_depth: DEFAULT_MAX_QUERY_DEPTH,
+        }
+    }
+}
+
+/// How queries sent by their hash are remembered, and whether only those on
+/// an allow-list can be run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PersistedQueryConfig {
+    /// How many queries are kept in memory. 0 doesn't keep any.
+    pub cache_size: usize,
+    /// Whether queries are stored in the database as well, so that they
+    /// outlive restarts and are shared between servers.
+    pub persist: bool,
+    /// The only queries that can be run, by their SHA-256 hash in hex. When
+    /// this is set, clients can't add to it.
+    pub allow_list: Option<Arc<HashMap<String, String>>>,
+}
+
+impl Default for PersistedQueryConfig {
+    fn default() -> Self {
+        Self {
+            cache_size: DEFAULT_PERSISTED_QUERY_CACHE_SIZE,
+            persist: DEFAULT_PERSIST_QUERIES,
+            allow_list: None,
+        }
+    }
+}
+
+/// Where uploaded media is stored, how long it's kept once nothing refers to
+/// it, and how it can be fetched.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaConfig {
+    /// The directory media is stored in. When it isn't set, media is only
+    /// kept in memory, which is only suitable for tests.
+    pub dir: Option<PathBuf>,
+    /// How long media that nothing refers to is kept before it's removed, in
+    /// case it's uploaded again.
+    pub collect_after: Duration,
+    /// How long signed media URLs work for.
+    pub url_ttl: Duration,
+    /// The hosts that pages embedding media can be on. When empty, media can
+    /// be embedded anywhere.
+    pub referer_hosts: Vec<String>,
+    /// Whether images attached to posts need alt text.
+    pub alt_text: AltTextPolicy,
+}
+
+impl Default for MediaConfig {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            collect_after: Duration::from_secs(DEFAULT_MEDIA_GC_GRACE_SECS),
+            url_ttl: Duration::from_secs(DEFAULT_MEDIA_URL_TTL_SECS),
+            referer_hosts: Vec::new(),
+            alt_text: DEFAULT_ALT_TEXT_POLICY,
+        }
+    }
+}
+
+/// When mutations are turned away so that the instance stays readable while
+/// its storage is having problems.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadOnlyConfig {
+    /// Whether the instance starts out read-only. Admins can change this
+    /// while it's running.
+    pub enabled: bool,
+    /// How many writes in a row have to fail before the instance becomes
+    /// read-only by itself. 0 never does.
+    pub after_failures: u32,
+    /// How long the instance stays read-only after writes keep failing,
+    /// before writes are tried again.
+    pub cooldown: Duration,
+}
+
+impl Default for ReadOnlyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: DEFAULT_READ_ONLY,
+            after_failures: DEFAULT_READ_ONLY_AFTER_FAILURES,
+            cooldown: Duration::from_secs(DEFAULT_READ_ONLY_COOLDOWN_SECS),
+        }
+    }
+}
+
+/// The oldest version of each app that the instance still supports.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ClientVersionConfig {
+    /// The oldest supported version of each app, by the name it gives.
+    /// Admins can change these while the instance is running.
+    pub min_versions: BTreeMap<String, String>,
+}
+
+/// How long sessions last before their account has to sign in again.
+///
+/// Sessions are idle while their tokens aren't refreshed, which clients do
+/// every few minutes while they're in use.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SessionConfig {
+    /// How long a session can be idle before it ends, if there's a limit.
+    pub idle_timeout: Option<Duration>,
+    /// How long a session can last after signing in, if there's a limit.
+    pub max_lifetime: Option<Duration>,
+    /// The idle timeout for admins, who get whichever is shorter of this and
+    /// everyone else's.
+    pub admin_idle_timeout: Option<Duration>,
+    /// The maximum lifetime for admins, who get whichever is shorter of this
+    /// and everyone else's.
+    pub admin_max_lifetime: Option<Duration>,
+}
+
+impl SessionConfig {
+    /// The idle timeout for an account, depending on whether it's an admin.
+    #[must_use]
+    pub fn idle_timeout(&self, admin: bool) -> Option<Duration> {
+        Self::for_role(self.idle_timeout, self.admin_idle_timeout, admin)
+    }
+
+    /// The maximum lifetime for an account, depending on whether it's an
+    /// admin.
+    #[must_use]
+    pub fn max_lifetime(&self, admin: bool) -> Option<Duration> {
+        Self::for_role(self.max_lifetime, self.admin_max_lifetime, admin)
+    }
+
+    fn for_role(
+        everyone: Option<Duration>,
+        admins: Option<Duration>,
+        admin: bool,
+    ) -> Option<Duration> {
+        match (everyone, admins) {
+            (Some(everyone), Some(admins)) if admin => Some(everyone.min(admins)),
+            (everyone, admins) if admin => everyone.or(admins),
+            (everyone, _) => everyone,
+        }
+    }
+}
+
+/// How much of what clients send, like their IP address and user agent, is
+/// kept and who can see it.
+#[derive(Debug, Clone)]
+pub struct PrivacyConfig {
+    /// How IP addresses are stored.
+    pub ip_storage: IpStorage,
+    /// Who can see stored IP addresses and user agents.
+    pub metadata_visibility: MetadataVisibility,
+    /// How many days IP addresses and user agents are kept for before they
+    /// are removed. 0 keeps them forever.
+    pub retention_days: u32,
+    /// The key IP addresses are hashed with when they are stored hashed.
+    pub hash_key: hmac::Key,
+}
+
+impl PrivacyConfig {
+    /// Derives the key for hashing IP addresses from the instance's private
+    /// key, so that hashes stay the same across restarts.
+    #[must_use]
+    pub fn derive_hash_key(private_key: &str) -> hmac::Key {
+        let mut ctx = digest::Context::new(&digest::SHA256);
+        ctx.update(b"plazer ip hash\0");
+        ctx.update(private_key.as_bytes());
+        hmac::Key::new(hmac::HMAC_SHA256, ctx.finish().as_ref())
+    }
+}
+
+impl Default for PrivacyConfig {
+    fn default() -> Self {
+        Self {
+            ip_storage: DEFAULT_IP_STORAGE,
+            metadata_visibility: DEFAULT_METADATA_VISIBILITY,
+            retention_days: DEFAULT_METADATA_RETENTION_DAYS,
+            hash_key: hmac::Key::new(hmac::HMAC_SHA256, &[]),
+        }
+    }
+}
+
+/// How much each account can create by default. Admins can change these for
+/// individual accounts. A limit of 0 means there is no limit.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct QuotaConfig {
+    /// The number of posts each account can make.
+    pub posts: u32,
+    /// The number of boards each account can create.
+    pub boards: u32,
+    /// The number of lists each account can create.
+    pub lists: u32,
+    /// The number of bytes of post titles and content, and of uploaded media,
+    /// each account can store.
+    pub storage_bytes: u64,
+}
+
+/// Who new posts can be shown to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PostVisibilityConfig {
+    /// The visibility of posts that don't ask for one.
+    pub default: PostVisibility,
+    /// The most open visibility that posts can have.
+    pub max: PostVisibility,
+}
+
+impl PostVisibilityConfig {
+    /// Every visibility that posts can have, from the most open.
+    #[must_use]
+    pub fn allowed(&self) -> Vec<PostVisibility> {
+        [
+            PostVisibility::Public,
+            PostVisibility::Unlisted,
+            PostVisibility::Instance,
+        ]
+        .into_iter()
+        .filter(|visibility| *visibility >= self.max)
+        .collect()
+    }
+
+    /// The visibility a new post gets, failing if it asked for one that's
+    /// more open than the instance allows.
+    pub fn resolve(&self, requested: Option<PostVisibility>) -> Result<PostVisibility, Error> {
+        match requested {
+            Some(visibility) if visibility < self.max => Err(Error::VisibilityDisallowed),
+            Some(visibility) => Ok(visibility),
+            None => Ok(self.default),
+        }
+    }
+}
+
+impl Default for PostVisibilityConfig {
+    fn default() -> Self {
+        Self {
+            default: DEFAULT_POST_VISIBILITY,
+            max: DEFAULT_MAX_POST_VISIBILITY,
+        }
+    }
+}
+
+/// The most characters each piece of content can have.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct LimitsConfig {
+    /// The most characters a post's title can have.
+    pub post_title: u32,
+    /// The most characters a post's content can have.
+    pub post_content: u32,
+    /// The most characters a board's name can have.
+    pub board_name: u32,
+    /// The most characters a board's description can have.
+    pub board_description: u32,
+    /// The most bytes an uploaded media file can have.
+    pub media_bytes: u64,
+}
+
+impl LimitsConfig {
+    /// Checks that `value` is no longer than `max` characters, naming the
+    /// input field that was too long if it isn't.
+    pub fn check(field: &str, value: Option<&str>, max: u32) -> Result<(), Error> {
+        match value {
+            Some(value) if value.chars().count() > max as usize => {
+                Err(Error::TooLong(field.to_owned()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            post_title: DEFAULT_MAX_POST_TITLE_LENGTH,
+            post_content: DEFAULT_MAX_POST_CONTENT_LENGTH,
+            board_name: DEFAULT_MAX_BOARD_NAME_LENGTH,
+            board_description: DEFAULT_MAX_BOARD_DESCRIPTION_LENGTH,
+            media_bytes: DEFAULT_MAX_MEDIA_BYTES,
+        }
+    }
+}
+
+/// How content is checked for spam.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpamConfig {
+    /// The spam score, out of 100, at which content is sent for review.
+    pub review_threshold: u8,
+    /// The spam score, out of 100, at which content is hidden from everyone
+    /// but its author.
+    pub limit_threshold: u8,
+    /// The address of an external classifier to check content with.
+    pub classifier_url: Option<hyper::Uri>,
+    /// How registrations are scored from the signals that clients send with
+    /// them.
+    pub signup: SignupRiskConfig,
+}
+
+impl Default for SpamConfig {
+    fn default() -> Self {
+        Self {
+            review_threshold: DEFAULT_SPAM_REVIEW_THRESHOLD,
+            limit_threshold: DEFAULT_SPAM_LIMIT_THRESHOLD,
+            classifier_url: None,
+            signup: SignupRiskConfig::default(),
+        }
+    }
+}
+
+/// The scores given to registrations for each bot signal they show. A
+/// registration's risk score is the total of its signals' scores.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignupRiskConfig {
+    /// The score given when the honeypot field is filled in.
+    pub honeypot_score: u8,
+    /// How long the registration form needs to have been shown for, at the
+    /// least, in seconds.
+    pub min_form_secs: u32,
+    /// The score given when the form was filled in faster than that.
+    pub too_fast_score: u8,
+}
+
+impl Default for SignupRiskConfig {
+    fn default() -> Self {
+        Self {
+            honeypot_score: DEFAULT_SIGNUP_HONEYPOT_SCORE,
+            min_form_secs: DEFAULT_SIGNUP_MIN_FORM_SECS,
+            too_fast_score: DEFAULT_SIGNUP_TOO_FAST_SCORE,
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -477,3 +3684,117 @@ pub fn create_key_pair(
 
     Ok((enc_key, dec_key))
 }
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn config_file(contents: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("plazer-config-{}.toml", ulid::Ulid::new()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn problems(err: &anyhow::Error) -> Vec<String> {
+        assert!(
+            matches!(err.downcast_ref(), Some(Error::ServerMisconfigured(_))),
+            "{err:?}"
+        );
+        err.downcast_ref::<ConfigProblems>().unwrap().0.clone()
+    }
+
+    #[test]
+    fn test_layering() {
+        let path = config_file("port = 9000\nhost = \"127.0.0.1\"\nemail_from = \"a@example.com\"");
+        let config = ServiceConfigBuilder::new()
+            .config_path(path.display().to_string())
+            .port(9001u16)
+            .build();
+        fs::remove_file(path).unwrap();
+
+        let config = config.unwrap();
+        assert_eq!(config.port, 9001);
+        assert_eq!(config.host, "127.0.0.1");
+        assert_eq!(config.email_from, "a@example.com");
+        assert_eq!(config.namespace, DEFAULT_NAMESPACE);
+    }
+
+    #[test]
+    fn test_every_problem() {
+        let err = ServiceConfigBuilder::new()
+            .host("localhost")
+            .db_pool_size(0)
+            .spam_review_threshold(95u8)
+            .email_from("nobody")
+            .cors_origins("https://example.com, example.com/app")
+            .build()
+            .unwrap_err();
+
+        let problems = problems(&err);
+        assert_eq!(problems.len(), 5, "{problems:#?}");
+        assert!(problems[0].contains("\"localhost\""));
+        assert!(problems[2].contains("\"example.com/app\""));
+        assert!(format!("{err:#}").contains("5 problems with the config:\n  - Host"));
+    }
+
+    #[test]
+    fn test_invalid_file() {
+        let path = config_file("port = \"not a port\"");
+        let err = ServiceConfigBuilder::new()
+            .config_path(path.display().to_string())
+            .email_from("nobody")
+            .build()
+            .unwrap_err();
+        fs::remove_file(path).unwrap();
+
+        let problems = problems(&err);
+        assert_eq!(problems.len(), 2, "{problems:#?}");
+        assert!(problems[0].starts_with("Config file is invalid"));
+
+        // A config file that was asked for has to exist.
+        let err = ServiceConfigBuilder::new()
+            .config_path("./does-not-exist.toml")
+            .build()
+            .unwrap_err();
+        assert!(err.downcast_ref::<Error>().is_none(), "{err:?}");
+    }
+
+    #[test]
+    fn test_post_visibility() {
+        let err = ServiceConfigBuilder::new()
+            .post_visibility(PostVisibility::Public)
+            .max_post_visibility(PostVisibility::Instance)
+            .build()
+            .unwrap_err();
+        assert_eq!(
+            problems(&err),
+            vec!["The default post visibility (public) can't be more open than the maximum (instance)"]
+        );
+
+        let config = PostVisibilityConfig {
+            default: PostVisibility::Instance,
+            max: PostVisibility::Unlisted,
+        };
+        assert_eq!(
+            config.allowed(),
+            vec![PostVisibility::Unlisted, PostVisibility::Instance]
+        );
+        assert_eq!(config.resolve(None), Ok(PostVisibility::Instance));
+        assert_eq!(
+            config.resolve(Some(PostVisibility::Public)),
+            Err(Error::VisibilityDisallowed)
+        );
+    }
+
+    #[test]
+    fn test_is_origin() {
+        assert!(is_origin("https://example.com"));
+        assert!(is_origin("http://localhost:3000"));
+        assert!(!is_origin("example.com"));
+        assert!(!is_origin("https://example.com/app"));
+        assert!(!is_origin("ftp://example.com"));
+        assert!(!is_origin("https://"));
+    }
+}
@@ -0,0 +1,41 @@
+use serde::{Deserialize, Serialize};
+
+use super::CONVERSATION_MUTE_TABLE_NAME;
+use crate::{migration::Migration, prelude::*};
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConversationMigration {
+    #[default]
+    Init,
+}
+
+impl Migration for ConversationMigration {
+    const SUBSYSTEM: &'static str = "subsys_conversation";
+
+    fn next(self) -> Option<Self> {
+        match self {
+            Self::Init => None,
+        }
+    }
+
+    fn build(&self, statements: &mut Vec<srql::Statement>) {
+        use ConversationMigration as S;
+        match self {
+            S::Init => Self::build_init(statements),
+        }
+    }
+}
+
+impl ConversationMigration {
+    fn build_init(statements: &mut Vec<srql::Statement>) {
+        statements.push(srql::define_uniq_index(
+            "conversation_mute_index",
+            CONVERSATION_MUTE_TABLE_NAME,
+            [
+                srql::field("account_id"),
+                srql::field("conversation_id"),
+                srql::field("participant_id"),
+            ],
+        ));
+    }
+}
@@ -0,0 +1,15 @@
+//! Conversations are the replies to a post. Accounts can mute someone taking
+//! part in one, which collapses their replies there and stops notifications
+//! about them, without muting or blocking them anywhere else.
+
+mod migration;
+mod models;
+mod persist;
+mod schema;
+
+pub use migration::*;
+pub use models::*;
+pub use persist::*;
+pub use schema::*;
+
+pub static CONVERSATION_MUTE_TABLE_NAME: &str = "conversation_mute";
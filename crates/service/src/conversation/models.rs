@@ -0,0 +1,44 @@
+use async_graphql::{ComplexObject, Context, SimpleObject, ID};
+use surrealdb::sql::Thing;
+
+use crate::{account::Account, prelude::*};
+
+/// Someone taking part in a conversation, as the current account sees them.
+#[derive(SimpleObject, Debug, Clone, PartialEq, Eq)]
+#[graphql(complex)]
+pub struct ConversationParticipant {
+    #[graphql(skip)]
+    pub conversation_id: Thing,
+    #[graphql(skip)]
+    pub account_id: Thing,
+
+    /// Whether the account started the conversation.
+    pub started: bool,
+    /// How many replies the account has made in the conversation.
+    pub reply_count: u64,
+    /// Whether the current account has muted the account in this
+    /// conversation. Their replies in it are collapsed, and the current
+    /// account isn't notified about them.
+    pub muted: bool,
+}
+
+#[ComplexObject]
+impl ConversationParticipant {
+    /// The ID of the post that the conversation is the replies to.
+    async fn conversation_id(&self) -> ID {
+        self.conversation_id.to_gql_id()
+    }
+
+    /// The ID of the account taking part.
+    async fn account_id(&self) -> ID {
+        self.account_id.to_gql_id()
+    }
+
+    /// The account taking part, if it still exists.
+    async fn account(&self, ctx: &Context<'_>) -> GqlResult<Option<Account>> {
+        ctx.account_persist()
+            .get(&self.account_id.to_gql_id())
+            .await
+            .extend()
+    }
+}
@@ -0,0 +1,309 @@
+#[cfg(test)]
+mod tests;
+
+use serde::{de::IgnoredAny, Deserialize};
+use tracing::instrument;
+
+use super::{ConversationParticipant, CONVERSATION_MUTE_TABLE_NAME};
+use crate::{
+    account::{restriction_visible_cond, Account, CurrentAccount, ACC_TABLE_NAME},
+    persist::Persist,
+    post::{Post, PostPersist, POST_TABLE_NAME},
+    prelude::*,
+};
+
+pub struct ConversationPersist<'a> {
+    persist: &'a Persist,
+    current: &'a CurrentAccount,
+}
+
+impl<'a> ConversationPersist<'a> {
+    pub fn new(persist: &'a Persist, current: &'a CurrentAccount) -> Self {
+        Self { persist, current }
+    }
+
+    /// Lists the accounts taking part in the conversation under a post. The
+    /// post's author comes first, followed by everyone who replied, most
+    /// active first. Replies the current account can't see aren't counted.
+    #[instrument(skip_all)]
+    pub async fn participants(&self, post_id: &str) -> Result<Vec<ConversationParticipant>> {
+        #[derive(Deserialize)]
+        struct Count {
+            key: srql::Thing,
+            count: i64,
+        }
+
+        let post = self.conversation(post_id).await?;
+        let viewer = self.current.id().ok().map(ToAccountThing::to_account_thing);
+
+        // Limited replies are only counted for their authors.
+        let not_limited = srql::Expression::Binary {
+            l: srql::field("limited").into(),
+            o: srql::Operator::NotEqual,
+            r: true.into(),
+        };
+        let limited_cond = srql::Cond(match &viewer {
+            Some(viewer) => srql::Expression::Binary {
+                l: not_limited.into(),
+                o: srql::Operator::Or,
+                r: srql::Expression::Binary {
+                    l: srql::field("creator_id").into(),
+                    o: srql::Operator::Equal,
+                    r: viewer.clone().into(),
+                }
+                .into(),
+            }
+            .into(),
+            None => not_limited.into(),
+        });
+        let reply_cond = srql::Cond(
+            srql::Expression::Binary {
+                l: srql::field("reply_to_id").into(),
+                o: srql::Operator::Equal,
+                r: post.id.clone().into(),
+            }
+            .into(),
+        );
+        let restriction_cond =
+            restriction_visible_cond(viewer.as_ref(), self.persist.clock().now());
+
+        let mut query = srql::count_by_query(POST_TABLE_NAME, "creator_id");
+        query.cond = srql::cond_and(
+            srql::cond_and(query.cond, reply_cond.into()),
+            srql::cond_and(limited_cond.into(), restriction_cond.into()),
+        );
+        let mut counts: Vec<Count> = self.persist.db().query(query).await?.take(0)?;
+        counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.key.cmp(&b.key)));
+
+        let muted = match &viewer {
+            Some(viewer) => self.muted(viewer, &post.id).await?,
+            None => vec![],
+        };
+        let participant =
+            |account_id: srql::Thing, started: bool, count: i64| ConversationParticipant {
+                conversation_id: post.id.clone(),
+                muted: muted.contains(&account_id),
+                account_id,
+                started,
+                reply_count: count.try_into().unwrap_or_default(),
+            };
+
+        let mut participants = Vec::with_capacity(counts.len() + 1);
+        if let Some(author_id) = &post.creator_id {
+            let count = counts
+                .iter()
+                .find(|count| count.key == *author_id)
+                .map_or(0, |count| count.count);
+            participants.push(participant(author_id.clone(), true, count));
+        }
+        participants.extend(
+            counts
+                .into_iter()
+                .filter(|count| post.creator_id.as_ref() != Some(&count.key))
+                .map(|count| participant(count.key, false, count.count)),
+        );
+        Ok(participants)
+    }
+
+    /// Mutes an account in the conversation under a post for the current
+    /// account. Their replies there are collapsed, and the current account
+    /// isn't notified about them. Nothing changes anywhere else.
+    ///
+    /// Returns `false` if the account was already muted there, doesn't
+    /// exist, or is the current account.
+    #[instrument(skip_all)]
+    pub async fn mute(&self, post_id: &str, account_id: &str) -> Result<bool> {
+        let current_id = self.current.id()?.to_account_thing();
+        let post = self.conversation(post_id).await?;
+        let participant_id = account_id.to_account_thing();
+        if participant_id == current_id {
+            return Ok(false);
+        }
+
+        let participant: Option<Account> = self
+            .persist
+            .load(srql::Thing::from((ACC_TABLE_NAME, account_id)))
+            .await?;
+        if participant.is_none() {
+            return Ok(false);
+        }
+
+        let mut create = vec![];
+        current_id.push_field(srql::field("account_id"), &mut create);
+        post.id
+            .push_field(srql::field("conversation_id"), &mut create);
+        participant_id.push_field(srql::field("participant_id"), &mut create);
+        self.persist
+            .clock()
+            .now()
+            .push_field(srql::field("muted_at"), &mut create);
+
+        let res: Result<Option<IgnoredAny>> = self
+            .persist
+            .db()
+            .query(srql::obj_create_query(
+                CONVERSATION_MUTE_TABLE_NAME,
+                create,
+                self.persist.ids(),
+            ))
+            .await
+            .and_then(|mut r| r.take(0))
+            .map_err(Into::into);
+
+        match res {
+            Ok(mute) => Ok(mute.is_some()),
+            // The unique index means that muting twice is a no-op rather
+            // than an error.
+            Err(Error::UnavailableIdent) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Unmutes an account in the conversation under a post.
+    ///
+    /// Returns `false` if the account wasn't muted there.
+    #[instrument(skip_all)]
+    pub async fn unmute(&self, post_id: &str, account_id: &str) -> Result<bool> {
+        let current_id = self.current.id()?.to_account_thing();
+        let conversation_id = srql::Thing::from((POST_TABLE_NAME, post_id));
+
+        let removed: Vec<IgnoredAny> = self
+            .persist
+            .db()
+            .query(srql::DeleteStatement {
+                what: srql::table(CONVERSATION_MUTE_TABLE_NAME),
+                cond: mute_cond(
+                    current_id,
+                    conversation_id,
+                    Some(account_id.to_account_thing()),
+                )
+                .into(),
+                output: srql::Output::Before.into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        Ok(!removed.is_empty())
+    }
+
+    /// Whether a post should be collapsed for the current account, which it
+    /// should be if it is a reply by someone the account has muted in that
+    /// conversation.
+    #[instrument(skip_all)]
+    pub async fn is_collapsed(&self, post: &Post) -> Result<bool> {
+        let Ok(current_id) = self.current.id() else {
+            return Ok(false);
+        };
+        is_muted_reply(self.persist, &current_id.to_account_thing(), post).await
+    }
+
+    /// Loads the post that a conversation is the replies to, failing if the
+    /// current account can't see it.
+    async fn conversation(&self, post_id: &str) -> Result<Post> {
+        PostPersist::new(self.persist, self.current)
+            .get(post_id)
+            .await?
+            .ok_or(Error::ReadTargetInvalid)
+    }
+
+    /// The accounts that an account has muted in a conversation.
+    async fn muted(
+        &self,
+        account_id: &srql::Thing,
+        conversation_id: &srql::Thing,
+    ) -> Result<Vec<srql::Thing>> {
+        let muted = self
+            .persist
+            .db()
+            .query(srql::SelectStatement {
+                expr: srql::Fields(
+                    vec![srql::Field::Single {
+                        expr: srql::field("participant_id").into(),
+                        alias: None,
+                    }],
+                    true,
+                ),
+                what: srql::table(CONVERSATION_MUTE_TABLE_NAME),
+                cond: mute_cond(account_id.clone(), conversation_id.clone(), None).into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        Ok(muted)
+    }
+}
+
+/// Whether a post is a reply by someone that an account has muted in the
+/// conversation it was made in.
+pub async fn is_muted_reply(
+    persist: &Persist,
+    account_id: &srql::Thing,
+    post: &Post,
+) -> Result<bool> {
+    let (Some(conversation_id), Some(creator_id)) = (&post.reply_to_id, &post.creator_id) else {
+        return Ok(false);
+    };
+    let mutes: Vec<IgnoredAny> = persist
+        .db()
+        .query(srql::SelectStatement {
+            expr: srql::Fields::all(),
+            what: srql::table(CONVERSATION_MUTE_TABLE_NAME),
+            cond: mute_cond(
+                account_id.clone(),
+                conversation_id.clone(),
+                Some(creator_id.clone()),
+            )
+            .into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    Ok(!mutes.is_empty())
+}
+
+fn mute_cond(
+    account_id: srql::Thing,
+    conversation_id: srql::Thing,
+    participant_id: Option<srql::Thing>,
+) -> srql::Cond {
+    let eq = |field: &str, thing: srql::Thing| -> srql::Value {
+        srql::Expression::Binary {
+            l: srql::field(field).into(),
+            o: srql::Operator::Equal,
+            r: thing.into(),
+        }
+        .into()
+    };
+    let mut cond = srql::Expression::Binary {
+        l: eq("account_id", account_id),
+        o: srql::Operator::And,
+        r: eq("conversation_id", conversation_id),
+    }
+    .into();
+    if let Some(participant_id) = participant_id {
+        cond = srql::Expression::Binary {
+            l: cond,
+            o: srql::Operator::And,
+            r: eq("participant_id", participant_id),
+        }
+        .into();
+    }
+    srql::Cond(cond)
+}
+
+#[cfg(test)]
+pub mod testing {
+    use crate::account::testing::TestData;
+
+    use super::ConversationPersist;
+
+    pub trait ConversationTestData {
+        fn conversation(&self) -> ConversationPersist<'_>;
+    }
+
+    impl ConversationTestData for TestData {
+        fn conversation(&self) -> ConversationPersist<'_> {
+            ConversationPersist::new(&self.persist, &self.current)
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use async_graphql::ID;
+use pretty_assertions::assert_eq;
+
+use super::{testing::ConversationTestData as _, *};
+use crate::{
+    account::testing::*,
+    notification::testing::NotificationTestData as _,
+    post::{testing::PostTestData as _, CreatePost},
+    query::PaginationInput,
+};
+
+fn summary(participants: &[ConversationParticipant]) -> Vec<(srql::Thing, bool, u64, bool)> {
+    participants
+        .iter()
+        .map(|p| (p.account_id.clone(), p.started, p.reply_count, p.muted))
+        .collect()
+}
+
+#[tokio::test]
+async fn test_participants() {
+    let (mut data, author) = TestData::with_user().await;
+    let busy = data.account().create_test_user().await;
+    let quiet = data.account().create_test_user().await;
+    let post = data.generate_post().await;
+    let post_id = post.id.to_gql_id();
+
+    data.login_as(&quiet);
+    data.generate_reply(&post.id).await;
+    data.login_as(&busy);
+    data.generate_reply(&post.id).await;
+    data.generate_reply(&post.id).await;
+    data.login_as(&author);
+    data.generate_reply(&post.id).await;
+
+    let res = data
+        .conversation()
+        .participants(&post_id)
+        .await
+        .map(|p| summary(&p));
+    println!("{res:?}");
+    assert_eq!(
+        res,
+        Ok(vec![
+            (author.id.clone(), true, 1, false),
+            (busy.id.clone(), false, 2, false),
+            (quiet.id.clone(), false, 1, false),
+        ])
+    );
+
+    // Everyone can see who's taking part, but only accounts can mute them.
+    data.current = CurrentAccount::default();
+    let res = data
+        .conversation()
+        .participants(&post_id)
+        .await
+        .map(|p| p.len());
+    println!("{res:?}");
+    assert_eq!(res, Ok(3));
+}
+
+#[tokio::test]
+async fn test_participants_missing() {
+    let (data, _) = TestData::with_user().await;
+
+    let res = data.conversation().participants("missing").await;
+    println!("{res:?}");
+    assert!(matches!(res, Err(Error::ReadTargetInvalid)));
+}
+
+#[tokio::test]
+async fn test_mute() {
+    let (mut data, author) = TestData::with_user().await;
+    let muted = data.account().create_test_user().await;
+    let other = data.account().create_test_user().await;
+    let post = data.generate_post().await;
+    let post_id = post.id.to_gql_id();
+    let muted_id = muted.id.id.to_raw();
+
+    data.login_as(&muted);
+    let muted_reply = data.generate_reply(&post.id).await;
+    data.login_as(&other);
+    let other_reply = data.generate_reply(&post.id).await;
+
+    data.login_as(&author);
+    let res = data.conversation().mute(&post_id, &muted_id).await;
+    println!("{res:?}");
+    assert_eq!(res, Ok(true));
+
+    let res = data.conversation().mute(&post_id, &muted_id).await;
+    println!("{res:?}");
+    assert_eq!(res, Ok(false));
+
+    let res = data
+        .conversation()
+        .participants(&post_id)
+        .await
+        .map(|p| summary(&p));
+    println!("{res:?}");
+    assert_eq!(
+        res,
+        Ok(vec![
+            (author.id.clone(), true, 0, false),
+            (muted.id.clone(), false, 1, true),
+            (other.id.clone(), false, 1, false),
+        ])
+    );
+
+    let res = data.conversation().is_collapsed(&muted_reply).await;
+    assert_eq!(res, Ok(true));
+    let res = data.conversation().is_collapsed(&other_reply).await;
+    assert_eq!(res, Ok(false));
+
+    // The mute only applies to the account that made it, and only in that
+    // conversation.
+    data.login_as(&other);
+    let res = data.conversation().is_collapsed(&muted_reply).await;
+    assert_eq!(res, Ok(false));
+
+    data.login_as(&muted);
+    let elsewhere = data.generate_post().await;
+    let elsewhere_reply = data.generate_reply(&elsewhere.id).await;
+    data.login_as(&author);
+    let res = data.conversation().is_collapsed(&elsewhere_reply).await;
+    assert_eq!(res, Ok(false));
+
+    let res = data.conversation().unmute(&post_id, &muted_id).await;
+    println!("{res:?}");
+    assert_eq!(res, Ok(true));
+
+    let res = data.conversation().unmute(&post_id, &muted_id).await;
+    println!("{res:?}");
+    assert_eq!(res, Ok(false));
+
+    let res = data.conversation().is_collapsed(&muted_reply).await;
+    assert_eq!(res, Ok(false));
+}
+
+#[tokio::test]
+async fn test_mute_invalid() {
+    let (mut data, acc) = TestData::with_user().await;
+    let post = data.generate_post().await;
+    let post_id = post.id.to_gql_id();
+
+    let res = data
+        .conversation()
+        .mute(&post_id, &acc.id.id.to_raw())
+        .await;
+    println!("{res:?}");
+    assert_eq!(res, Ok(false));
+
+    let res = data.conversation().mute(&post_id, "missing").await;
+    println!("{res:?}");
+    assert_eq!(res, Ok(false));
+
+    let other = data.account().create_test_user().await;
+    let res = data
+        .conversation()
+        .mute("missing", &other.id.id.to_raw())
+        .await;
+    println!("{res:?}");
+    assert!(matches!(res, Err(Error::ReadTargetInvalid)));
+
+    data.current = CurrentAccount::default();
+    let res = data
+        .conversation()
+        .mute(&post_id, &other.id.id.to_raw())
+        .await;
+    println!("{res:?}");
+    assert!(matches!(res, Err(Error::Unauthenticated)));
+}
+
+#[tokio::test]
+async fn test_mute_notifications() {
+    async fn quote(data: &TestData, post: &Post, reply_to_id: Option<ID>) {
+        data.post()
+            .create(CreatePost {
+                quote_id: Some(post.id.to_gql_id()),
+                reply_to_id,
+                content: Some("Test".into()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+    }
+    async fn notification_count(data: &TestData) -> usize {
+        data.notification()
+            .list()
+            .unwrap()
+            .with_pagination(PaginationInput::new().forward(10))
+            .execute()
+            .await
+            .unwrap()
+            .edges
+            .len()
+    }
+
+    let (mut data, author) = TestData::with_user().await;
+    let muted = data.account().create_test_user().await;
+    let post = data.generate_post().await;
+
+    data.login_as(&author);
+    let res = data
+        .conversation()
+        .mute(&post.id.to_gql_id(), &muted.id.id.to_raw())
+        .await;
+    assert_eq!(res, Ok(true));
+
+    // Quoting the author in a reply to the muted conversation doesn't notify
+    // them, but quoting them anywhere else still does.
+    data.login_as(&muted);
+    quote(&data, &post, Some(post.id.to_gql_id())).await;
+    data.login_as(&author);
+    assert_eq!(notification_count(&data).await, 0);
+
+    data.login_as(&muted);
+    quote(&data, &post, None).await;
+    data.login_as(&author);
+    assert_eq!(notification_count(&data).await, 1);
+}
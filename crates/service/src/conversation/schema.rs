@@ -0,0 +1,66 @@
+use async_graphql::{Context, Object, ID};
+use tracing::instrument;
+
+use super::ConversationParticipant;
+use crate::{policy::PoliciesAccepted, prelude::*};
+
+#[derive(Default)]
+pub struct ConversationQuery;
+
+#[Object]
+impl ConversationQuery {
+    /// The accounts taking part in the conversation under a post, with the
+    /// post's author first.
+    #[instrument(skip_all)]
+    async fn conversation_participants(
+        &self,
+        ctx: &Context<'_>,
+        post_id: ID,
+    ) -> GqlResult<Vec<ConversationParticipant>> {
+        ctx.conversation_persist()
+            .participants(&post_id)
+            .await
+            .extend()
+    }
+}
+
+#[derive(Default)]
+pub struct ConversationMutation;
+
+#[Object(guard = "PoliciesAccepted")]
+impl ConversationMutation {
+    /// Mutes an account in the conversation under a post, without muting or
+    /// blocking them anywhere else. Their replies there are collapsed, and
+    /// the current account isn't notified about them.
+    ///
+    /// Returns `false` if the account was already muted there or does not
+    /// exist.
+    #[instrument(skip_all)]
+    async fn mute_conversation_participant(
+        &self,
+        ctx: &Context<'_>,
+        post_id: ID,
+        account_id: ID,
+    ) -> GqlResult<bool> {
+        ctx.conversation_persist()
+            .mute(&post_id, &account_id)
+            .await
+            .extend()
+    }
+
+    /// Unmutes an account in the conversation under a post.
+    ///
+    /// Returns `false` if the account was not muted there.
+    #[instrument(skip_all)]
+    async fn unmute_conversation_participant(
+        &self,
+        ctx: &Context<'_>,
+        post_id: ID,
+        account_id: ID,
+    ) -> GqlResult<bool> {
+        ctx.conversation_persist()
+            .unmute(&post_id, &account_id)
+            .await
+            .extend()
+    }
+}
@@ -0,0 +1,81 @@
+//! Letting browsers call the API from other origins.
+//!
+//! Only the origins in the config are allowed, or any if it has `*`. Without
+//! any, no CORS headers are sent, so browsers only allow the API to be called
+//! from the same origin as it.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::{
+        header::{
+            ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS,
+            ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_EXPOSE_HEADERS, ACCESS_CONTROL_MAX_AGE,
+            ACCESS_CONTROL_REQUEST_HEADERS, ACCESS_CONTROL_REQUEST_METHOD, ORIGIN, VARY,
+        },
+        HeaderMap, HeaderValue, Method, Request, StatusCode,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::config::CorsConfig;
+
+static ALLOW_METHODS: &str = "GET, POST, PUT, PATCH, DELETE";
+/// Headers that clients read, which browsers otherwise hide from them.
+static EXPOSE_HEADERS: &str = "retry-after, plazer-region";
+/// How long, in seconds, browsers can cache a preflight response for.
+static MAX_AGE: &str = "86400";
+
+/// Answers preflight requests from allowed origins, and adds CORS headers to
+/// the responses to their other requests.
+pub async fn apply_cors<B>(
+    State(cors): State<Arc<CorsConfig>>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let Some(origin) = allowed_origin(&cors, req.headers()) else {
+        return next.run(req).await;
+    };
+
+    if req.method() == Method::OPTIONS && req.headers().contains_key(ACCESS_CONTROL_REQUEST_METHOD)
+    {
+        let mut res = StatusCode::NO_CONTENT.into_response();
+        let headers = res.headers_mut();
+        headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+        headers.insert(
+            ACCESS_CONTROL_ALLOW_METHODS,
+            HeaderValue::from_static(ALLOW_METHODS),
+        );
+        if let Some(requested) = req.headers().get(ACCESS_CONTROL_REQUEST_HEADERS) {
+            headers.insert(ACCESS_CONTROL_ALLOW_HEADERS, requested.clone());
+        }
+        headers.insert(ACCESS_CONTROL_MAX_AGE, HeaderValue::from_static(MAX_AGE));
+        headers.append(VARY, HeaderValue::from_static("origin"));
+        return res;
+    }
+
+    let mut res = next.run(req).await;
+    let headers = res.headers_mut();
+    headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+    headers.insert(
+        ACCESS_CONTROL_EXPOSE_HEADERS,
+        HeaderValue::from_static(EXPOSE_HEADERS),
+    );
+    headers.append(VARY, HeaderValue::from_static("origin"));
+    res
+}
+
+/// The value to allow a request's origin with, if it's allowed.
+fn allowed_origin(cors: &CorsConfig, headers: &HeaderMap) -> Option<HeaderValue> {
+    let origin = headers.get(ORIGIN)?;
+    if cors.origins.iter().any(|allowed| allowed == "*") {
+        return Some(HeaderValue::from_static("*"));
+    }
+    let value = origin.to_str().ok()?;
+    cors.origins
+        .iter()
+        .any(|allowed| allowed.eq_ignore_ascii_case(value))
+        .then(|| origin.clone())
+}
@@ -0,0 +1,239 @@
+//! How account passwords are hashed and checked.
+//!
+//! New hashes are Argon2id PHC strings, which carry their own salt and
+//! parameters. Accounts registered before that have a PBKDF2 hash and a
+//! separate salt, which are still checked, and replaced the next time the
+//! account's password is given.
+
+use std::{fmt::Debug, sync::Arc};
+
+use argon2::{
+    password_hash::SaltString, Algorithm, Argon2, Params, PasswordHash, PasswordHasher as _,
+    PasswordVerifier as _, Version,
+};
+use base64::prelude::*;
+use ring::{
+    pbkdf2,
+    rand::{SecureRandom as _, SystemRandom},
+};
+use secrecy::{ExposeSecret as _, SecretString};
+
+use crate::{config::PasswordHashConfig, prelude::*};
+
+pub type SharedPasswordHasher = Arc<dyn PasswordHasher>;
+
+static PBKDF2_ITERS: u32 = 100_000;
+
+/// A password hash as it's stored on an account. The salt is only used by
+/// legacy PBKDF2 hashes, and is empty for hashes that carry their own.
+pub struct StoredPword {
+    pub salt: SecretString,
+    pub hash: SecretString,
+}
+
+/// Something that hashes passwords and checks them against stored hashes.
+pub trait PasswordHasher: Debug + Send + Sync {
+    /// Hashes a password to be stored.
+    fn hash(&self, csrng: &SystemRandom, pword: &str) -> Result<StoredPword>;
+
+    /// Checks a password against a stored hash, failing with
+    /// `CredentialsInvalid` if it doesn't match.
+    fn verify(
+        &self,
+        pword: &SecretString,
+        pword_salt: &SecretString,
+        pword_hash: &SecretString,
+    ) -> Result<()>;
+
+    /// Whether a stored hash should be replaced by hashing the password
+    /// again, as it wasn't made the way this hasher makes them now.
+    fn needs_rehash(&self, pword_hash: &SecretString) -> bool;
+}
+
+/// Hashes passwords with Argon2id, and checks the PBKDF2 hashes that were
+/// stored before it.
+#[derive(Debug, Clone, Default)]
+pub struct Argon2Hasher {
+    params: Params,
+}
+
+impl Argon2Hasher {
+    pub fn new(config: PasswordHashConfig) -> std::result::Result<Self, argon2::Error> {
+        let params = Params::new(
+            config.memory_kib,
+            config.iterations,
+            config.parallelism,
+            None,
+        )?;
+        Ok(Self { params })
+    }
+
+    fn argon2(&self) -> Argon2<'static> {
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params.clone())
+    }
+}
+
+impl PasswordHasher for Argon2Hasher {
+    fn hash(&self, csrng: &SystemRandom, pword: &str) -> Result<StoredPword> {
+        let mut salt = [0u8; 16];
+        csrng.fill(&mut salt)?;
+        let salt = SaltString::encode_b64(&salt).map_err(Error::from_err)?;
+        let hash = self
+            .argon2()
+            .hash_password(pword.as_bytes(), &salt)
+            .map_err(Error::from_err)?;
+
+        Ok(StoredPword {
+            salt: String::new().into(),
+            hash: hash.to_string().into(),
+        })
+    }
+
+    fn verify(
+        &self,
+        pword: &SecretString,
+        pword_salt: &SecretString,
+        pword_hash: &SecretString,
+    ) -> Result<()> {
+        let Ok(hash) = PasswordHash::new(pword_hash.expose_secret()) else {
+            return verify_pbkdf2(pword, pword_salt, pword_hash);
+        };
+        // The hash's own parameters are used, so that hashes made with older
+        // ones still match.
+        self.argon2()
+            .verify_password(pword.expose_secret().as_bytes(), &hash)
+            .map_err(|_| Error::CredentialsInvalid)
+    }
+
+    fn needs_rehash(&self, pword_hash: &SecretString) -> bool {
+        let Ok(hash) = PasswordHash::new(pword_hash.expose_secret()) else {
+            return true;
+        };
+        let Ok(params) = Params::try_from(&hash) else {
+            return true;
+        };
+        hash.algorithm != Algorithm::Argon2id.ident()
+            || hash.version != Some(Version::V0x13.into())
+            || params.m_cost() != self.params.m_cost()
+            || params.t_cost() != self.params.t_cost()
+            || params.p_cost() != self.params.p_cost()
+    }
+}
+
+/// Checks a password against a hash stored before passwords were hashed with
+/// Argon2id.
+fn verify_pbkdf2(
+    pword: &SecretString,
+    pword_salt: &SecretString,
+    pword_hash: &SecretString,
+) -> Result<()> {
+    pbkdf2::verify(
+        pbkdf2::PBKDF2_HMAC_SHA512,
+        PBKDF2_ITERS.try_into().unwrap(),
+        &BASE64_STANDARD_NO_PAD.decode(pword_salt.expose_secret())?,
+        pword.expose_secret().as_bytes(),
+        &BASE64_STANDARD_NO_PAD.decode(pword_hash.expose_secret())?,
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod testing {
+    use ring::digest;
+
+    use super::*;
+
+    /// The cheapest parameters Argon2id allows, so that tests that register
+    /// lots of accounts stay quick.
+    pub fn test_hasher() -> SharedPasswordHasher {
+        Arc::new(
+            Argon2Hasher::new(PasswordHashConfig {
+                memory_kib: Params::MIN_M_COST,
+                iterations: Params::MIN_T_COST,
+                parallelism: Params::MIN_P_COST,
+            })
+            .unwrap(),
+        )
+    }
+
+    /// Hashes a password the way it was hashed before Argon2id.
+    pub fn pbkdf2_creds(csrng: &SystemRandom, pword: &str) -> StoredPword {
+        const CREDENTIAL_LEN: usize = digest::SHA512_OUTPUT_LEN;
+
+        let mut pword_salt = [0u8; CREDENTIAL_LEN];
+        csrng.fill(&mut pword_salt).unwrap();
+        let mut pword_hash = [0u8; CREDENTIAL_LEN];
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA512,
+            PBKDF2_ITERS.try_into().unwrap(),
+            &pword_salt,
+            pword.as_bytes(),
+            &mut pword_hash,
+        );
+
+        StoredPword {
+            salt: BASE64_STANDARD_NO_PAD.encode(pword_salt).into(),
+            hash: BASE64_STANDARD_NO_PAD.encode(pword_hash).into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{testing::*, *};
+
+    fn pword(pword: &str) -> SecretString {
+        pword.to_owned().into()
+    }
+
+    #[test]
+    fn test_creds_valid() {
+        let hasher = test_hasher();
+        let creds = hasher.hash(&SystemRandom::new(), "password").unwrap();
+
+        let res = hasher.verify(&pword("password"), &creds.salt, &creds.hash);
+
+        assert!(res.is_ok());
+        assert!(!hasher.needs_rehash(&creds.hash));
+    }
+
+    #[test]
+    fn test_creds_invalid() {
+        let hasher = test_hasher();
+        let creds = hasher.hash(&SystemRandom::new(), "password").unwrap();
+
+        let res = hasher.verify(&pword("password1"), &creds.salt, &creds.hash);
+
+        assert_eq!(res.unwrap_err(), Error::CredentialsInvalid);
+    }
+
+    #[test]
+    fn test_pbkdf2_creds() {
+        let hasher = test_hasher();
+        let creds = pbkdf2_creds(&SystemRandom::new(), "password");
+
+        let res = hasher.verify(&pword("password"), &creds.salt, &creds.hash);
+        assert!(res.is_ok());
+        let res = hasher.verify(&pword("password1"), &creds.salt, &creds.hash);
+        assert_eq!(res.unwrap_err(), Error::CredentialsInvalid);
+        assert!(hasher.needs_rehash(&creds.hash));
+    }
+
+    #[test]
+    fn test_changed_params() {
+        let old = test_hasher();
+        let creds = old.hash(&SystemRandom::new(), "password").unwrap();
+        let new = Argon2Hasher::new(PasswordHashConfig {
+            memory_kib: Params::MIN_M_COST * 2,
+            iterations: Params::MIN_T_COST,
+            parallelism: Params::MIN_P_COST,
+        })
+        .unwrap();
+
+        // Hashes made with the old parameters still match, but are upgraded.
+        let res = new.verify(&pword("password"), &creds.salt, &creds.hash);
+        assert!(res.is_ok());
+        assert!(new.needs_rehash(&creds.hash));
+    }
+}
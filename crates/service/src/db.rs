@@ -0,0 +1,336 @@
+//! Connections to the database, shared between requests.
+//!
+//! Each database client runs its requests one after another, so with a
+//! single client every request waits for the queries of the ones before it.
+//! [`DbPool`] opens several clients and hands them out in turn, so that
+//! queries from different requests can run at the same time. Every query is
+//! also given a deadline, so that one slow query can't hold up a request
+//! forever.
+//!
+//! Queries are built as statements rather than text, so there is nothing for
+//! the database to parse or prepare again when they're reused.
+//!
+//! When a request has a [`RequestMemo`], anything other than a `SELECT`
+//! clears it before it's sent.
+
+use std::{
+    future::{Future, IntoFuture},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use cfg_if::cfg_if;
+use surrealdb::{
+    engine, method,
+    opt::{capabilities::Capabilities, Config as SrlConfig, IntoQuery, IntoResource},
+    sql::Statement,
+    Error as SrlError, Result as SrlResult, Surreal,
+};
+use tokio::time::Timeout;
+use tracing::{debug, warn};
+
+use crate::{config::DbConfig, error::SrlDbError, memo::RequestMemo};
+
+fn config() -> SrlConfig {
+    SrlConfig::new().capabilities(Capabilities::all())
+}
+
+cfg_if! {
+
+if #[cfg(all(
+    feature = "backend-mem",
+    not(feature = "backend-file"),
+    not(feature = "backend-tikv")
+))] {
+    pub type DbEngine = engine::local::Db;
+
+    async fn connect(_: String) -> SrlResult<DbLayer> {
+        Surreal::new::<engine::local::Mem>(config()).await
+    }
+
+    /// Every in-memory client has its own database.
+    fn poolable(_: &str) -> bool {
+        false
+    }
+} else if #[cfg(all(
+    not(feature = "backend-mem"),
+    feature = "backend-file",
+    not(feature = "backend-tikv")
+))] {
+    pub type DbEngine = engine::local::Db;
+
+    async fn connect(address: String) -> SrlResult<DbLayer> {
+        Surreal::new::<engine::local::RocksDb>((address, config())).await
+    }
+
+    /// Only one client can open a RocksDB directory.
+    fn poolable(_: &str) -> bool {
+        false
+    }
+} else if #[cfg(all(
+    not(feature = "backend-mem"),
+    not(feature = "backend-file"),
+    feature = "backend-tikv"
+))] {
+    pub type DbEngine = engine::local::Db;
+
+    async fn connect(address: String) -> SrlResult<DbLayer> {
+        Surreal::new::<engine::local::TiKv>((address, config())).await
+    }
+
+    fn poolable(_: &str) -> bool {
+        true
+    }
+} else {
+    pub type DbEngine = engine::any::Any;
+
+    async fn connect(address: String) -> SrlResult<DbLayer> {
+        engine::any::connect((address, config())).await
+    }
+
+    /// Only databases reached over the network can be connected to more than
+    /// once.
+    fn poolable(address: &str) -> bool {
+        ["ws://", "wss://", "http://", "https://", "tikv://"]
+            .iter()
+            .any(|scheme| address.starts_with(scheme))
+    }
+}
+
+}
+
+pub type DbLayer = Surreal<DbEngine>;
+
+/// A set of clients connected to the same database.
+#[derive(Clone)]
+pub struct DbPool {
+    conns: Arc<[DbLayer]>,
+    next: Arc<AtomicUsize>,
+    query_timeout: Option<Duration>,
+}
+
+impl DbPool {
+    /// Opens the pool's clients, using the given namespace and database. Only
+    /// one is opened for embedded databases, however large the pool is.
+    pub async fn connect(
+        address: String,
+        namespace: &str,
+        database: &str,
+        config: &DbConfig,
+    ) -> SrlResult<Self> {
+        let size = if poolable(&address) {
+            config.pool_size.max(1)
+        } else {
+            if config.pool_size > 1 {
+                warn!(
+                    pool_size = config.pool_size,
+                    "Embedded databases can only have one connection"
+                );
+            }
+            1
+        };
+
+        debug!(size, "Connecting to database");
+        let mut conns = Vec::with_capacity(size);
+        for _ in 0..size {
+            let db = connect(address.clone()).await?;
+            db.use_ns(namespace).use_db(database).await?;
+            conns.push(db);
+        }
+        Ok(Self {
+            conns: conns.into(),
+            next: Arc::default(),
+            query_timeout: config.query_timeout,
+        })
+    }
+
+    /// Picks the next client to use. Writes made with it clear `memo`.
+    pub fn get<'a>(&'a self, memo: Option<&'a RequestMemo>) -> Db<'a> {
+        let i = self.next.fetch_add(1, Ordering::Relaxed) % self.conns.len();
+        Db {
+            conn: &self.conns[i],
+            timeout: self.query_timeout,
+            memo,
+        }
+    }
+}
+
+/// One of the pool's clients. Its queries fail with
+/// [`SrlDbError::QueryTimedout`] if they take too long.
+#[derive(Clone, Copy)]
+pub struct Db<'a> {
+    conn: &'a DbLayer,
+    timeout: Option<Duration>,
+    memo: Option<&'a RequestMemo>,
+}
+
+impl<'a> Db<'a> {
+    pub fn query(self, query: impl IntoQuery) -> Timed<method::Query<'a, DbEngine>> {
+        let statements = query.into_query().inspect(|statements| {
+            let reads_only = statements
+                .iter()
+                .all(|statement| matches!(statement, Statement::Select(_)));
+            if !reads_only {
+                self.clear_memo();
+            }
+        });
+        Timed {
+            inner: statements.map(|statements| self.conn.query(statements)),
+            timeout: self.timeout,
+        }
+    }
+
+    pub fn select<R>(
+        self,
+        resource: impl IntoResource<R>,
+    ) -> Timed<method::Select<'a, DbEngine, R>> {
+        self.timed(self.conn.select(resource))
+    }
+
+    pub fn delete<R>(
+        self,
+        resource: impl IntoResource<R>,
+    ) -> Timed<method::Delete<'a, DbEngine, R>> {
+        self.clear_memo();
+        self.timed(self.conn.delete(resource))
+    }
+
+    fn timed<F>(self, inner: F) -> Timed<F> {
+        Timed {
+            inner: Ok(inner),
+            timeout: self.timeout,
+        }
+    }
+
+    fn clear_memo(self) {
+        if let Some(memo) = self.memo {
+            memo.clear();
+        }
+    }
+}
+
+/// A query that gives up once its deadline passes.
+#[must_use]
+pub struct Timed<F> {
+    /// The query, or why it couldn't be built.
+    inner: SrlResult<F>,
+    timeout: Option<Duration>,
+}
+
+// Queries are otherwise built as statements, so only tests bind variables.
+#[cfg(test)]
+impl Timed<method::Query<'_, DbEngine>> {
+    pub fn bind(self, bindings: impl serde::Serialize) -> Self {
+        Self {
+            inner: self.inner.map(|inner| inner.bind(bindings)),
+            timeout: self.timeout,
+        }
+    }
+}
+
+impl<F, T> IntoFuture for Timed<F>
+where
+    F: IntoFuture<Output = SrlResult<T>>,
+    F::IntoFuture: Unpin,
+{
+    type Output = SrlResult<T>;
+    type IntoFuture = TimedFuture<F::IntoFuture>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        let inner = match self.inner {
+            Ok(inner) => inner.into_future(),
+            Err(err) => return TimedFuture::Failed(Some(err)),
+        };
+        match self.timeout {
+            Some(timeout) => TimedFuture::Limited(Box::pin(tokio::time::timeout(timeout, inner))),
+            None => TimedFuture::Unlimited(inner),
+        }
+    }
+}
+
+pub enum TimedFuture<F> {
+    Failed(Option<SrlError>),
+    Unlimited(F),
+    Limited(Pin<Box<Timeout<F>>>),
+}
+
+impl<F, T> Future for TimedFuture<F>
+where
+    F: Future<Output = SrlResult<T>> + Unpin,
+{
+    type Output = SrlResult<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.get_mut() {
+            Self::Failed(err) => Poll::Ready(Err(err
+                .take()
+                .expect("TimedFuture polled after completion"))),
+            Self::Unlimited(inner) => Pin::new(inner).poll(cx),
+            Self::Limited(inner) => inner
+                .as_mut()
+                .poll(cx)
+                .map(|res| res.unwrap_or_else(|_| Err(SrlDbError::QueryTimedout.into()))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn db_config(pool_size: usize, query_timeout: Option<Duration>) -> DbConfig {
+        DbConfig {
+            pool_size,
+            query_timeout,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_embedded_single_connection() {
+        let pool = DbPool::connect("memory".into(), "test", "test", &db_config(4, None))
+            .await
+            .unwrap();
+        assert_eq!(pool.conns.len(), 1);
+
+        pool.get(None)
+            .query("CREATE item:one SET value = 1")
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+        let value: Option<u64> = pool
+            .get(None)
+            .query("SELECT VALUE value FROM item:one")
+            .await
+            .unwrap()
+            .take(0)
+            .unwrap();
+        assert_eq!(value, Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_query_timeout() {
+        let pool = DbPool::connect(
+            "memory".into(),
+            "test",
+            "test",
+            &db_config(1, Some(Duration::from_millis(10))),
+        )
+        .await
+        .unwrap();
+
+        let res = pool.get(None).query("SLEEP 1s").await;
+        assert!(
+            matches!(res, Err(surrealdb::Error::Db(SrlDbError::QueryTimedout))),
+            "{res:?}"
+        );
+    }
+}
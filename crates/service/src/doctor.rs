@@ -0,0 +1,425 @@
+//! Checks that an instance is deployed correctly, without serving it.
+//!
+//! Every check is run and reported, rather than stopping at the first
+//! problem, so that a deployment can be fixed in one go. Checks that need
+//! something an earlier one found broken are skipped.
+
+use std::{fmt, time::Duration};
+
+use axum::body::Bytes;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::time::timeout;
+
+use crate::{
+    config::{ServeConfig, ServiceConfigBuilder},
+    email::check_smtp,
+    media::{blob_key, BlobStore as _, FsBlobStore},
+    migration::Migrations,
+    persist::Persist,
+    prelude::*,
+};
+
+/// How long connecting to the database or SMTP server can take.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How far the clock can be from the database's before it's a problem.
+/// Tokens and sessions expire by the clock, so a large skew ends them early
+/// or late.
+const MAX_CLOCK_SKEW: Duration = Duration::from_secs(30);
+
+/// How a check turned out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Ok,
+    /// Something isn't quite right, but serving will work.
+    Warning,
+    /// Serving won't work, or part of it won't.
+    Failed,
+    /// The check couldn't be run, because of an earlier one.
+    Skipped,
+}
+
+impl fmt::Display for CheckStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Self::Ok => "ok",
+            Self::Warning => "warning",
+            Self::Failed => "failed",
+            Self::Skipped => "skipped",
+        })
+    }
+}
+
+/// The result of one check.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: &'static str, status: CheckStatus, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status,
+            detail: detail.into(),
+        }
+    }
+
+    fn ok(name: &'static str, detail: impl Into<String>) -> Self {
+        Self::new(name, CheckStatus::Ok, detail)
+    }
+
+    fn warning(name: &'static str, detail: impl Into<String>) -> Self {
+        Self::new(name, CheckStatus::Warning, detail)
+    }
+
+    fn failed(name: &'static str, err: impl fmt::Display) -> Self {
+        Self::new(name, CheckStatus::Failed, format!("{err:#}"))
+    }
+
+    fn skipped(name: &'static str, detail: impl Into<String>) -> Self {
+        Self::new(name, CheckStatus::Skipped, detail)
+    }
+}
+
+/// The results of every check, in the order they were run.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DoctorReport {
+    pub checks: Vec<Check>,
+}
+
+impl DoctorReport {
+    /// Whether serving should work, which it should unless a check failed.
+    #[must_use]
+    pub fn is_healthy(&self) -> bool {
+        self.checks
+            .iter()
+            .all(|check| check.status != CheckStatus::Failed)
+    }
+
+    /// The check with the given name, if it was run.
+    #[must_use]
+    pub fn check(&self, name: &str) -> Option<&Check> {
+        self.checks.iter().find(|check| check.name == name)
+    }
+}
+
+impl fmt::Display for DoctorReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .checks
+            .iter()
+            .map(|check| check.name.len())
+            .max()
+            .unwrap_or_default();
+        for Check {
+            name,
+            status,
+            detail,
+        } in &self.checks
+        {
+            writeln!(f, "[{status:^7}] {name:<width$}  {detail}")?;
+        }
+        let failed = self
+            .checks
+            .iter()
+            .filter(|check| check.status == CheckStatus::Failed)
+            .count();
+        match failed {
+            0 => write!(f, "All checks passed"),
+            1 => write!(f, "1 check failed"),
+            _ => write!(f, "{failed} checks failed"),
+        }
+    }
+}
+
+/// Checks the config, the private key, the database and its migrations, the
+/// clock, the SMTP server and the media directory. Nothing is changed, apart
+/// from a blob being written to the media directory and removed again.
+pub async fn diagnose(builder: ServiceConfigBuilder) -> DoctorReport {
+    let mut checks = vec![];
+
+    let config = match builder.build() {
+        Ok(config) => config,
+        Err(err) => {
+            checks.push(Check::failed("config", err));
+            for name in [
+                "private_key",
+                "database",
+                "migrations",
+                "clock",
+                "email",
+                "media",
+            ] {
+                checks.push(Check::skipped(name, "The config is invalid"));
+            }
+            return DoctorReport { checks };
+        }
+    };
+
+    let key = config.check_private_key();
+    let smtp_address = config.smtp_address().map(ToOwned::to_owned);
+    let serve: Option<ServeConfig> = if key.is_ok() {
+        match config.without_private_key_create().try_into() {
+            Ok((serve, _)) => {
+                checks.push(Check::ok("config", "Valid"));
+                Some(serve)
+            }
+            Err(err) => {
+                checks.push(Check::failed("config", err));
+                None
+            }
+        }
+    } else {
+        checks.push(Check::warning(
+            "config",
+            "Parsed, but can't be fully checked without the private key",
+        ));
+        None
+    };
+    checks.push(match key {
+        Ok(()) => Check::ok("private_key", "Loaded, and can sign tokens"),
+        Err(err) => Check::failed("private_key", err),
+    });
+
+    let persist = if let Some(serve) = &serve {
+        check_database(serve, &mut checks).await
+    } else {
+        checks.push(Check::skipped("database", "The config couldn't be loaded"));
+        None
+    };
+    if let Some(persist) = &persist {
+        let auto_migrate = serve.as_ref().is_some_and(|serve| serve.db.auto_migrate);
+        checks.push(check_migrations(persist, auto_migrate).await);
+        checks.push(check_clock(persist).await);
+    } else {
+        for name in ["migrations", "clock"] {
+            checks.push(Check::skipped(name, "The database couldn't be reached"));
+        }
+    }
+
+    checks.push(match smtp_address {
+        Some(address) => match timeout(CONNECT_TIMEOUT, check_smtp(&address)).await {
+            Ok(Ok(())) => Check::ok("email", format!("SMTP server at {address} is reachable")),
+            Ok(Err(err)) => Check::failed(
+                "email",
+                format!("Unable to reach SMTP server at {address}: {err}"),
+            ),
+            Err(_) => Check::failed(
+                "email",
+                format!("Timed out reaching SMTP server at {address}"),
+            ),
+        },
+        None => Check::skipped("email", "No SMTP server is set, so email isn't sent"),
+    });
+
+    checks.push(match serve.as_ref().map(|serve| &serve.media.dir) {
+        Some(Some(dir)) => {
+            let dir = dir.display().to_string();
+            match check_media(FsBlobStore::new(&dir)).await {
+                Ok(()) => Check::ok("media", format!("Blobs can be stored in {dir}")),
+                Err(err) => {
+                    Check::failed("media", format!("Unable to store blobs in {dir}: {err}"))
+                }
+            }
+        }
+        Some(None) => Check::skipped("media", "Media is kept in memory"),
+        None => Check::skipped("media", "The config couldn't be loaded"),
+    });
+
+    DoctorReport { checks }
+}
+
+async fn check_database(serve: &ServeConfig, checks: &mut Vec<Check>) -> Option<Persist> {
+    let connect = Persist::new(
+        serve.address.clone(),
+        serve.namespace.clone(),
+        serve.database.clone(),
+        &serve.db,
+    );
+    let (check, persist) = match timeout(CONNECT_TIMEOUT, connect).await {
+        Ok(Ok(persist)) => (
+            Check::ok(
+                "database",
+                format!("Connected to {}/{}", serve.namespace, serve.database),
+            ),
+            Some(persist),
+        ),
+        Ok(Err(err)) => (Check::failed("database", err), None),
+        Err(_) => (
+            Check::failed("database", "Timed out connecting to the database"),
+            None,
+        ),
+    };
+    checks.push(check);
+    persist
+}
+
+async fn check_migrations(persist: &Persist, auto_migrate: bool) -> Check {
+    match Migrations::pending(persist).await {
+        Ok(pending) if pending.is_empty() => Check::ok("migrations", "Up to date"),
+        Ok(pending) if !auto_migrate => Check::failed(
+            "migrations",
+            format!(
+                "Serving won't start until {} subsystems are migrated: {}",
+                pending.len(),
+                pending.join(", ")
+            ),
+        ),
+        Ok(pending) => Check::warning(
+            "migrations",
+            format!(
+                "Serving will migrate {} subsystems: {}",
+                pending.len(),
+                pending.join(", ")
+            ),
+        ),
+        Err(err) => Check::failed(
+            "migrations",
+            format!("Unable to read migrations, which may be from a newer version: {err}"),
+        ),
+    }
+}
+
+async fn check_clock(persist: &Persist) -> Check {
+    let db_now = match db_now(persist).await {
+        Ok(Some(db_now)) => db_now,
+        Ok(None) => return Check::failed("clock", "The database didn't give its time"),
+        Err(err) => return Check::failed("clock", err),
+    };
+    let skew = (persist.clock().now() - db_now).abs();
+    let skew_ms = skew.num_milliseconds();
+    if skew.to_std().is_ok_and(|skew| skew > MAX_CLOCK_SKEW) {
+        Check::warning(
+            "clock",
+            format!("{skew_ms}ms from the database's, so tokens may expire early or late"),
+        )
+    } else {
+        Check::ok("clock", format!("Within {skew_ms}ms of the database's"))
+    }
+}
+
+pub(crate) async fn db_now(persist: &Persist) -> Result<Option<DateTime<Utc>>> {
+    Ok(persist
+        .db()
+        .query(srql::Statement::Output(srql::OutputStatement {
+            what: srql::time_now(),
+            fetch: None,
+        }))
+        .await?
+        .take(0)?)
+}
+
+async fn check_media(store: FsBlobStore) -> Result<()> {
+    // Blobs are stored by their content, so a probe that no upload could
+    // match is used.
+    let probe = Bytes::from(format!("plazer doctor {}", ulid::Ulid::new()));
+    let key = blob_key(&probe);
+    store.put(&key, probe.clone()).await?;
+    let read = store.get(&key, None).await;
+    store.delete(&key).await?;
+    if read? == Some(probe) {
+        Ok(())
+    } else {
+        Err(Error::InternalServerError(
+            "blob read back differently".into(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use ring::{rand::SystemRandom, signature::Ed25519KeyPair};
+    use tokio::{
+        io::{AsyncBufReadExt as _, AsyncWriteExt as _, BufReader},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    fn private_key() -> String {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let pem = pkcs8::Document::try_from(pkcs8.as_ref())
+            .unwrap()
+            .to_pem("PRIVATE KEY", pkcs8::LineEnding::LF)
+            .unwrap();
+        String::from(&*pem)
+    }
+
+    fn builder(media_dir: &Path) -> ServiceConfigBuilder {
+        ServiceConfigBuilder::new()
+            .address("mem://")
+            .media_dir(media_dir.display().to_string())
+    }
+
+    fn media_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("plazer-doctor-{}", ulid::Ulid::new()))
+    }
+
+    fn status(report: &DoctorReport, name: &str) -> CheckStatus {
+        report.check(name).unwrap().status
+    }
+
+    #[tokio::test]
+    async fn test_diagnose() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let smtp = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let (read, mut write) = stream.split();
+            write.write_all(b"220 test ready\r\n").await.unwrap();
+            let mut line = String::new();
+            BufReader::new(read).read_line(&mut line).await.unwrap();
+            write.write_all(b"221 bye\r\n").await.unwrap();
+            line
+        });
+
+        let dir = media_dir();
+        let report = diagnose(
+            builder(&dir)
+                .private_key(private_key())
+                .smtp_address(address),
+        )
+        .await;
+        let _ = std::fs::remove_dir_all(dir);
+        assert_eq!(smtp.await.unwrap(), "QUIT\r\n");
+        assert!(report.is_healthy(), "{report}");
+        for name in [
+            "config",
+            "private_key",
+            "database",
+            "clock",
+            "email",
+            "media",
+        ] {
+            assert_eq!(status(&report, name), CheckStatus::Ok, "{report}");
+        }
+        // A new database hasn't been migrated yet.
+        assert_eq!(status(&report, "migrations"), CheckStatus::Warning);
+        assert!(report.to_string().ends_with("All checks passed"));
+    }
+
+    #[tokio::test]
+    async fn test_diagnose_unhealthy() {
+        let report = diagnose(
+            builder(&media_dir())
+                .private_key("not a key")
+                .smtp_address("127.0.0.1:1"),
+        )
+        .await;
+        assert!(!report.is_healthy());
+        assert_eq!(status(&report, "config"), CheckStatus::Warning);
+        assert_eq!(status(&report, "private_key"), CheckStatus::Failed);
+        assert_eq!(status(&report, "email"), CheckStatus::Failed);
+        for name in ["database", "migrations", "clock", "media"] {
+            assert_eq!(status(&report, name), CheckStatus::Skipped);
+        }
+        assert!(report.to_string().ends_with("2 checks failed"));
+    }
+}
@@ -0,0 +1,288 @@
+//! Email sent to accounts by the instance itself, such as password reset
+//! codes. Notifications are emailed by their own transport.
+
+use std::{
+    fmt::Debug,
+    io,
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+
+use chrono::Utc;
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt as _, AsyncWrite, AsyncWriteExt as _, BufReader},
+    net::TcpStream,
+};
+use tracing::{debug, warn};
+
+pub type SharedEmailSender = Arc<dyn EmailSender>;
+
+/// How long to wait for the SMTP server to take an email before giving up.
+const SEND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A plain text email to a single address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Something that sends email.
+pub trait EmailSender: Debug + Send + Sync {
+    /// Sends an email in the background. Nothing waits on the result, so
+    /// failures are logged rather than returned.
+    fn send(&self, email: Email);
+}
+
+/// Drops email, for instances that don't send any.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoEmailSender;
+
+impl EmailSender for NoEmailSender {
+    fn send(&self, email: Email) {
+        debug!(subject = email.subject, "No email sender, dropping");
+    }
+}
+
+/// Sends email through an SMTP server, such as a relay running alongside
+/// the instance. The connection isn't encrypted and doesn't authenticate,
+/// so the server should be on a trusted network.
+#[derive(Debug, Clone)]
+pub struct SmtpEmailSender {
+    /// The `host:port` of the SMTP server.
+    address: String,
+    /// The address that email is sent from.
+    from: String,
+}
+
+impl SmtpEmailSender {
+    #[must_use]
+    pub fn new(address: impl Into<String>, from: impl Into<String>) -> Self {
+        Self {
+            address: address.into(),
+            from: from.into(),
+        }
+    }
+}
+
+impl EmailSender for SmtpEmailSender {
+    fn send(&self, email: Email) {
+        let sender = self.clone();
+        tokio::spawn(async move {
+            match tokio::time::timeout(SEND_TIMEOUT, sender.deliver(&email)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(err)) => {
+                    warn!(error = ?err, address = sender.address, "Failed to send email");
+                }
+                Err(_) => warn!(address = sender.address, "Sending email timed out"),
+            }
+        });
+    }
+}
+
+impl SmtpEmailSender {
+    async fn deliver(&self, email: &Email) -> io::Result<()> {
+        // Addresses are written into commands and headers as they are, so
+        // anything that could end a line or an address is refused.
+        for address in [&self.from, &email.to] {
+            if address.contains(['\r', '\n', '<', '>']) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "email address is invalid",
+                ));
+            }
+        }
+        let subject = email.subject.replace(['\r', '\n'], " ");
+        let domain = self.from.rsplit_once('@').map_or("localhost", |(_, d)| d);
+
+        let stream = TcpStream::connect(&self.address).await?;
+        let (read, mut write) = stream.into_split();
+        let mut read = BufReader::new(read);
+
+        expect_reply(&mut read, 220).await?;
+        command(&mut write, &mut read, &format!("EHLO {domain}"), 250).await?;
+        let from = format!("MAIL FROM:<{}>", self.from);
+        command(&mut write, &mut read, &from, 250).await?;
+        let to = format!("RCPT TO:<{}>", email.to);
+        command(&mut write, &mut read, &to, 250).await?;
+        command(&mut write, &mut read, "DATA", 354).await?;
+
+        let mut message = format!(
+            "From: <{}>\r\nTo: <{}>\r\nSubject: {subject}\r\nDate: {}\r\nMIME-Version: 1.0\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: 8bit\r\n\r\n",
+            self.from,
+            email.to,
+            Utc::now().to_rfc2822(),
+        );
+        for line in email.body.lines() {
+            // Lines starting with a dot are escaped, as a lone dot ends the
+            // message.
+            if line.starts_with('.') {
+                message.push('.');
+            }
+            message.push_str(line);
+            message.push_str("\r\n");
+        }
+        message.push_str(".\r\n");
+        write.write_all(message.as_bytes()).await?;
+        expect_reply(&mut read, 250).await?;
+
+        command(&mut write, &mut read, "QUIT", 221).await
+    }
+}
+
+/// Checks that an SMTP server can be reached, by waiting for it to greet a
+/// connection and then leaving. Nothing is sent.
+pub async fn check_smtp(address: &str) -> io::Result<()> {
+    let stream = TcpStream::connect(address).await?;
+    let (read, mut write) = stream.into_split();
+    let mut read = BufReader::new(read);
+    expect_reply(&mut read, 220).await?;
+    command(&mut write, &mut read, "QUIT", 221).await
+}
+
+async fn command(
+    write: &mut (impl AsyncWrite + Unpin),
+    read: &mut (impl AsyncBufRead + Unpin),
+    command: &str,
+    code: u16,
+) -> io::Result<()> {
+    write.write_all(command.as_bytes()).await?;
+    write.write_all(b"\r\n").await?;
+    expect_reply(read, code).await
+}
+
+/// Reads a reply, which can span several lines, and checks its code.
+async fn expect_reply(read: &mut (impl AsyncBufRead + Unpin), code: u16) -> io::Result<()> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if read.read_line(&mut line).await? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        // Every line but the last has a `-` after the code.
+        if line.as_bytes().get(3) != Some(&b'-') {
+            break;
+        }
+    }
+    if line.get(..3).and_then(|got| got.parse().ok()) == Some(code) {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "SMTP server replied {:?}",
+            line.trim_end()
+        )))
+    }
+}
+
+/// Keeps email in memory instead of sending it, so it can be checked later.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryEmailSender(Arc<Mutex<Vec<Email>>>);
+
+impl MemoryEmailSender {
+    /// Takes every email sent so far.
+    pub fn take(&self) -> Vec<Email> {
+        std::mem::take(&mut *self.0.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+impl EmailSender for MemoryEmailSender {
+    fn send(&self, email: Email) {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(email);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    /// Plays an SMTP server that takes one email, returning what it was
+    /// sent.
+    async fn serve_once(listener: TcpListener) -> String {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (read, mut write) = stream.into_split();
+        let mut read = BufReader::new(read);
+        let mut received = String::new();
+        write.write_all(b"220 test ready\r\n").await.unwrap();
+        let mut in_data = false;
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if read.read_line(&mut line).await.unwrap() == 0 {
+                return received;
+            }
+            received.push_str(&line);
+            let reply: &[u8] = if in_data {
+                if line != ".\r\n" {
+                    continue;
+                }
+                in_data = false;
+                b"250 queued\r\n"
+            } else if line.starts_with("EHLO") {
+                b"250-test\r\n250 8BITMIME\r\n"
+            } else if line.starts_with("DATA") {
+                in_data = true;
+                b"354 go ahead\r\n"
+            } else if line.starts_with("QUIT") {
+                write.write_all(b"221 bye\r\n").await.unwrap();
+                return received;
+            } else {
+                b"250 ok\r\n"
+            };
+            write.write_all(reply).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_smtp_deliver() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = tokio::spawn(serve_once(listener));
+
+        let sender = SmtpEmailSender::new(address, "noreply@example.com");
+        sender
+            .deliver(&Email {
+                to: "alice@example.com".into(),
+                subject: "Hello".into(),
+                body: "Hi\n.hidden".into(),
+            })
+            .await
+            .unwrap();
+
+        let received = server.await.unwrap();
+        assert!(received.starts_with("EHLO example.com\r\n"));
+        assert!(received.contains("MAIL FROM:<noreply@example.com>\r\n"));
+        assert!(received.contains("RCPT TO:<alice@example.com>\r\n"));
+        assert!(received.contains("Subject: Hello\r\n"));
+        assert!(received.contains("\r\nHi\r\n..hidden\r\n.\r\n"));
+        assert!(received.ends_with("QUIT\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_check_smtp() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = tokio::spawn(serve_once(listener));
+
+        check_smtp(&address).await.unwrap();
+        assert_eq!(server.await.unwrap(), "QUIT\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_smtp_rejects_injection() {
+        let sender = SmtpEmailSender::new("127.0.0.1:1", "noreply@example.com");
+        let res = sender
+            .deliver(&Email {
+                to: "alice@example.com>\r\nRCPT TO:<bob@example.com".into(),
+                subject: "Hello".into(),
+                body: String::new(),
+            })
+            .await;
+        assert_eq!(res.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+}
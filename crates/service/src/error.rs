@@ -1,6 +1,12 @@
+use std::borrow::Cow;
+
 use async_graphql::ErrorExtensions;
 pub use async_graphql::{Error as GqlError, Result as GqlResult};
-use axum::Json;
+use axum::{
+    http::header::RETRY_AFTER,
+    response::{IntoResponse, Response},
+    Json,
+};
 use base64::DecodeError as Base64DecodeError;
 use hyper::StatusCode;
 use jsonwebtoken::errors::{Error as JwtError, ErrorKind as JwtErrorKind};
@@ -8,7 +14,7 @@ use name_variant::NamedVariant;
 use serde::{Deserialize, Serialize};
 pub use surrealdb::{error::Db as SrlDbError, Error as SrlError};
 use thiserror::Error;
-use tracing::error;
+use tracing::{error, warn};
 use typeshare::typeshare;
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -23,13 +29,92 @@ pub enum Error {
     Unauthorized,
     #[error("Credentials are invalid")]
     CredentialsInvalid,
+    #[error("Development authentication is not enabled")]
+    DevAuthDisabled,
+    #[error("The signature is missing or invalid")]
+    SignatureInvalid,
+    #[error("The session has expired, sign in again")]
+    SessionExpired,
+    #[error("The session has been signed out, sign in again")]
+    SessionRevoked,
 
     #[error("This identifier is already in use")]
     UnavailableIdent,
+    #[error("This user ID looks too much like one that is already in use or reserved")]
+    UserIdConfusable,
+    #[error("This email address is already in use by another account")]
+    EmailAlreadyInUse,
     #[error("Missing identifier")]
     MissingIdent,
+    #[error("The resource does not exist")]
+    NotFound,
+    #[error("Input is invalid: {0}")]
+    InputInvalid(String),
+    #[error("Only followed accounts can be added to lists")]
+    NotFollowing,
+    #[error("The latest terms and policies must be accepted first")]
+    PoliciesNotAccepted,
+    #[error("Only the latest version of a policy can be accepted")]
+    PolicyInvalid,
+    #[error("A birthdate is required to register on this instance")]
+    BirthdateRequired,
+    #[error("You are too young to register on this instance")]
+    UnderMinimumAge,
+    #[error("This board is only available to adults")]
+    AgeRestricted,
+    #[error("The account's email address must be verified first")]
+    EmailNotVerified,
+    #[error("Not enough trusted contacts have approved this recovery yet")]
+    RecoveryNotApproved,
+    #[error("This media can't be embedded on this site")]
+    HotlinkDisallowed,
+    #[error("The {0} quota for this account has been used up")]
+    QuotaExceeded(String),
+    #[error("The {0} field is longer than this instance allows")]
+    TooLong(String),
+    #[error("The image attached as {0} needs alt text describing it")]
+    AltTextMissing(String),
+    #[error("Bots must be created by a logged in account that isn't a bot")]
+    BotOwnerInvalid,
+    #[error("The quoted post does not exist")]
+    QuoteInvalid,
+    #[error("The author of the quoted post does not allow quoting")]
+    QuoteDisallowed,
+    #[error("The post being replied to does not exist")]
+    ReplyInvalid,
+    #[error("The author of the post does not allow you to reply")]
+    ReplyDisallowed,
+    #[error("The board or conversation does not exist")]
+    ReadTargetInvalid,
+    #[error("The board does not exist")]
+    BoardInvalid,
+    #[error("The audience does not exist")]
+    AudienceInvalid,
+    #[error("Posts can't be that visible on this instance")]
+    VisibilityDisallowed,
+    #[error("The organization's domain has not been verified")]
+    DomainUnverified,
+    #[error("Posts can't be translated on this instance")]
+    TranslationUnavailable,
+    #[error("The remote content couldn't be fetched")]
+    RemoteContentUnavailable,
     #[error("Pagination arguments are invalid: {0}")]
     PaginationInvalid(String),
+    #[error(
+        "The query is too complex, it costs {complexity} with a depth of {depth}, ask for fewer items or fields"
+    )]
+    QueryTooComplex {
+        complexity: usize,
+        max_complexity: usize,
+        depth: usize,
+        max_depth: usize,
+    },
+    // Apollo's clients look for this exact message to know to send the query
+    // again in full.
+    #[error("PersistedQueryNotFound")]
+    PersistedQueryNotFound,
+    #[error("Only operations on the instance's allow-list can be run")]
+    PersistedQueryNotAllowed,
 
     #[error("JSON is malformed: {0}")]
     ParseError(String),
@@ -40,12 +125,59 @@ pub enum Error {
     JwtExpired,
     #[error("JWT is invalid")]
     JwtInvalid,
+    #[error("JWT was issued by an older version of the service, please log in again")]
+    JwtOutdated,
+    #[error("Password reset token is invalid, expired or already used")]
+    PasswordResetInvalid,
+    #[error("Email verification token is invalid, expired or already used")]
+    EmailVerificationInvalid,
+    #[error("Recovery token is invalid, expired or already used")]
+    RecoveryInvalid,
+    #[error("This account has been suspended")]
+    AccountSuspended,
+    #[error("This account has been deleted")]
+    AccountDeleted,
+    #[error("A code from an authenticator app or a recovery code is required")]
+    TwoFactorRequired,
+    #[error("The two-factor code is invalid")]
+    TotpInvalid,
+    #[error("Two-factor authentication is already turned on")]
+    TotpAlreadyEnabled,
+    #[error("Two-factor authentication has not been set up")]
+    TotpNotEnrolled,
+    #[error("The passkey is invalid, or its challenge has expired or already been used")]
+    PasskeyInvalid,
+    #[error("Passkeys are not available on this instance")]
+    PasskeysUnavailable,
+    #[error(
+        "The sign-in with the identity provider is invalid, or has expired or already been used"
+    )]
+    ExternalLoginInvalid,
+    #[error("The identity isn't linked to an account")]
+    ExternalIdentityNotLinked,
+    #[error("The API key isn't allowed to do this")]
+    ScopeMissing,
 
     #[error("GraphQL WebSocket init must be an object, null, or undefined")]
     WsInitNotObject,
     #[error("GraphQL WebSocket init `token` must be a string or undefined")]
     WsInitTokenNotString,
 
+    #[error("This version of the app is no longer supported, update it to {0} or later")]
+    ClientOutdated(String),
+
+    #[error("Too many requests, try again later")]
+    RateLimited,
+    #[error("Too many failed attempts, try again in {0} seconds")]
+    TooManyAttempts(u64),
+    #[error("The server is overloaded, try again later")]
+    Overloaded,
+    #[error("The database took too long to respond, try again later")]
+    DatabaseTimeout,
+    #[error("The change conflicted with another made at the same time, try again")]
+    TransactionConflict,
+    #[error("The instance is read-only for now, try again later")]
+    ReadOnly,
     #[error("The server is misconfigured")]
     ServerMisconfigured(String),
     #[error("An internal server error occurred")]
@@ -62,8 +194,8 @@ impl Error {
         Self::InternalServerError(err.to_string())
     }
 
-    pub fn code(&self) -> String {
-        self.variant_name().into()
+    pub fn code(&self) -> &'static str {
+        self.variant_name()
     }
 
     fn log(&self) {
@@ -71,6 +203,7 @@ impl Error {
             Self::ServerMisconfigured(err) => error!("Server misconfigured: {}", err),
             Self::InternalServerError(err) => error!("Internal server error: {}", err),
             Self::NotImplemented => error!("Unimplemented feature called"),
+            Self::DatabaseTimeout => warn!("Database query timed out"),
             _ => (),
         };
     }
@@ -102,6 +235,27 @@ impl ErrorExtensions for Error {
 
         GqlError::new(self.to_string()).extend_with(|_, e| {
             e.set("code", self.code());
+            if let Error::TooLong(field) | Error::AltTextMissing(field) = self {
+                e.set("field", field.as_str());
+            }
+            if let Error::TooManyAttempts(retry_after) = self {
+                e.set("retryAfter", *retry_after);
+            }
+            if let Error::ClientOutdated(min_version) = self {
+                e.set("minVersion", min_version.as_str());
+            }
+            if let Error::QueryTooComplex {
+                complexity,
+                max_complexity,
+                depth,
+                max_depth,
+            } = self
+            {
+                e.set("complexity", *complexity);
+                e.set("maxComplexity", *max_complexity);
+                e.set("depth", *depth);
+                e.set("maxDepth", *max_depth);
+            }
         })
     }
 }
@@ -133,6 +287,7 @@ impl From<SrlError> for Error {
             // This error only occurs when SurrealDB is misconfigured.
             SrlError::Db(SrlDbError::Ds(err)) => Self::ServerMisconfigured(err),
             SrlError::Db(SrlDbError::IndexExists { .. }) => Self::UnavailableIdent,
+            SrlError::Db(SrlDbError::QueryTimedout) => Self::DatabaseTimeout,
             // All other errors are either transient or incorrect logic.
             err => Self::from_err(err),
         }
@@ -158,9 +313,16 @@ impl From<serde_json::Error> for Error {
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ErrorData {
-    code: String,
+    code: Cow<'static, str>,
     message: String,
+    /// How many seconds to wait before trying again, if the error says.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    retry_after: Option<u64>,
+    /// The oldest version of the app that's still supported, if it's too old.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    min_version: Option<String>,
 }
 
 pub type ErrorResponse = (StatusCode, Json<ErrorData>);
@@ -171,15 +333,70 @@ impl Error {
             Error::Unauthenticated
             | Error::CredentialsInvalid
             | Error::JwtExpired
-            | Error::JwtInvalid => StatusCode::UNAUTHORIZED,
-            Error::Unauthorized => StatusCode::FORBIDDEN,
-            Error::UnavailableIdent => StatusCode::CONFLICT,
+            | Error::JwtInvalid
+            | Error::JwtOutdated
+            | Error::PasswordResetInvalid
+            | Error::EmailVerificationInvalid
+            | Error::RecoveryInvalid
+            | Error::TwoFactorRequired
+            | Error::TotpInvalid
+            | Error::PasskeyInvalid
+            | Error::ExternalLoginInvalid
+            | Error::ExternalIdentityNotLinked
+            | Error::SignatureInvalid
+            | Error::SessionExpired
+            | Error::SessionRevoked => StatusCode::UNAUTHORIZED,
+            Error::Unauthorized
+            | Error::AccountSuspended
+            | Error::AccountDeleted
+            | Error::DevAuthDisabled
+            | Error::PasskeysUnavailable
+            | Error::ScopeMissing
+            | Error::QuoteDisallowed
+            | Error::ReplyDisallowed
+            | Error::VisibilityDisallowed
+            | Error::PoliciesNotAccepted
+            | Error::UnderMinimumAge
+            | Error::AgeRestricted
+            | Error::EmailNotVerified
+            | Error::RecoveryNotApproved
+            | Error::HotlinkDisallowed
+            | Error::DomainUnverified
+            | Error::TranslationUnavailable
+            | Error::PersistedQueryNotAllowed
+            | Error::QuotaExceeded(_) => StatusCode::FORBIDDEN,
+            Error::UnavailableIdent
+            | Error::UserIdConfusable
+            | Error::EmailAlreadyInUse
+            | Error::TotpAlreadyEnabled
+            | Error::TransactionConflict => StatusCode::CONFLICT,
+            Error::NotFound | Error::PersistedQueryNotFound => StatusCode::NOT_FOUND,
             Error::MissingIdent
+            | Error::InputInvalid(_)
+            | Error::TotpNotEnrolled
+            | Error::TooLong(_)
+            | Error::AltTextMissing(_)
             | Error::JwtMalformed
+            | Error::NotFollowing
+            | Error::PolicyInvalid
+            | Error::BirthdateRequired
+            | Error::BotOwnerInvalid
+            | Error::QuoteInvalid
+            | Error::ReplyInvalid
+            | Error::ReadTargetInvalid
+            | Error::BoardInvalid
+            | Error::AudienceInvalid
             | Error::PaginationInvalid(_)
+            | Error::QueryTooComplex { .. }
             | Error::ParseError(_)
             | Error::WsInitNotObject
             | Error::WsInitTokenNotString => StatusCode::BAD_REQUEST,
+            Error::ClientOutdated(_) => StatusCode::UPGRADE_REQUIRED,
+            Error::RateLimited | Error::TooManyAttempts(_) => StatusCode::TOO_MANY_REQUESTS,
+            Error::RemoteContentUnavailable => StatusCode::BAD_GATEWAY,
+            Error::Overloaded | Error::ReadOnly | Error::DatabaseTimeout => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
             Error::ServerMisconfigured(_)
             | Error::InternalServerError(_)
             | Error::NotImplemented => StatusCode::INTERNAL_SERVER_ERROR,
@@ -195,9 +412,27 @@ impl From<Error> for ErrorResponse {
 
         let code = err.as_status_code();
         let data = ErrorData {
-            code: err.code(),
+            code: err.code().into(),
             message: err.to_string(),
+            retry_after: match err {
+                Error::TooManyAttempts(retry_after) => Some(retry_after),
+                _ => None,
+            },
+            min_version: match &err {
+                Error::ClientOutdated(min_version) => Some(min_version.clone()),
+                _ => None,
+            },
         };
         (code, Json(data))
     }
 }
+
+impl Error {
+    /// The response for an error that can be retried after `retry_after`
+    /// seconds, which is given in a `Retry-After` header as well as the body.
+    pub fn into_retry_response(self, retry_after: u64) -> Response {
+        let (status, Json(mut data)): ErrorResponse = self.into();
+        data.retry_after = Some(retry_after);
+        (status, [(RETRY_AFTER, retry_after.to_string())], Json(data)).into_response()
+    }
+}
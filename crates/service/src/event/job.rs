@@ -0,0 +1,99 @@
+use std::time::Duration;
+
+use tokio::{
+    task::JoinHandle,
+    time::{interval, MissedTickBehavior},
+};
+use tracing::{debug, error, info, trace};
+
+use super::{catch_up, lock_id, reconcile, PROJECTIONS};
+use crate::{persist::Persist, read_marker::reconcile_read_counts};
+
+/// How often projections are caught up with the event log.
+pub const PROJECTION_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How often counters are recounted to correct any drift.
+pub const RECONCILE_INTERVAL: Duration = Duration::from_hours(1);
+
+static READ_COUNT_LOCK: &str = "read_marker_reconcile";
+
+/// Spawns a task that periodically applies new events to every projection.
+pub fn spawn_projections(persist: Persist) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = interval(PROJECTION_INTERVAL);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+
+            for projection in PROJECTIONS {
+                let name = projection.name();
+                let res = persist
+                    .execute_in_lock(&lock_id(*projection), || async {
+                        let _job = persist.metrics().track_job();
+                        catch_up(&persist, *projection).await
+                    })
+                    .await;
+
+                match res {
+                    Ok(Some(Ok(0))) => trace!(name, "No new events for projection"),
+                    Ok(Some(Ok(count))) => debug!(name, count, "Projection caught up"),
+                    Ok(Some(Err(err))) => error!(error = ?err, name, "Failed to update projection"),
+                    Ok(None) => trace!(name, "Projection is already being updated"),
+                    Err(err) => error!(error = ?err, name, "Failed to lock projection"),
+                }
+            }
+        }
+    })
+}
+
+/// Spawns a task that periodically recounts every counter, correcting those
+/// that have drifted. The first recount happens straight away, so counters
+/// for records that were created before they were kept are filled in.
+pub fn spawn_reconciliation(persist: Persist) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = interval(RECONCILE_INTERVAL);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+
+            for projection in PROJECTIONS {
+                if projection.counters().is_empty() {
+                    continue;
+                }
+                let name = projection.name();
+                let res = persist
+                    .execute_in_lock(&lock_id(*projection), || async {
+                        let _job = persist.metrics().track_job();
+                        catch_up(&persist, *projection).await?;
+                        reconcile(&persist, *projection).await
+                    })
+                    .await;
+
+                match res {
+                    Ok(Some(Ok(0))) => trace!(name, "No counters drifted"),
+                    Ok(Some(Ok(count))) => info!(name, count, "Drifted counters corrected"),
+                    Ok(Some(Err(err))) => error!(error = ?err, name, "Failed to recount counters"),
+                    Ok(None) => trace!(name, "Projection is already being updated"),
+                    Err(err) => error!(error = ?err, name, "Failed to lock projection"),
+                }
+            }
+
+            let res = persist
+                .execute_in_lock(READ_COUNT_LOCK, || async {
+                    let _job = persist.metrics().track_job();
+                    reconcile_read_counts(&persist).await
+                })
+                .await;
+
+            match res {
+                Ok(Some(Ok(0))) => trace!("No read counts drifted"),
+                Ok(Some(Ok(count))) => info!(count, "Drifted read counts corrected"),
+                Ok(Some(Err(err))) => error!(error = ?err, "Failed to recount read counts"),
+                Ok(None) => trace!("Read counts are already being recounted"),
+                Err(err) => error!(error = ?err, "Failed to lock read count recount"),
+            }
+        }
+    })
+}
@@ -0,0 +1,190 @@
+use serde::{Deserialize, Serialize};
+
+use super::{DomainEventKind, DOMAIN_EVENT_TABLE_NAME};
+use crate::{
+    account::ACC_TABLE_NAME, follow::FOLLOWS_TABLE_NAME, migration::Migration,
+    post::POST_TABLE_NAME, prelude::*,
+};
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EventMigration {
+    #[default]
+    Init,
+}
+
+impl Migration for EventMigration {
+    const SUBSYSTEM: &'static str = "subsys_event";
+
+    fn next(self) -> Option<Self> {
+        match self {
+            Self::Init => None,
+        }
+    }
+
+    fn build(&self, statements: &mut Vec<srql::Statement>) {
+        use EventMigration as S;
+        match self {
+            S::Init => Self::build_init(statements),
+        }
+    }
+}
+
+impl EventMigration {
+    /// Starts the log with an event for everything that happened before
+    /// there was one, so projections can be built from it.
+    fn build_init(statements: &mut Vec<srql::Statement>) {
+        statements.push(backfill(
+            ACC_TABLE_NAME,
+            DomainEventKind::AccountCreated,
+            srql::Value::None,
+            "id",
+        ));
+        statements.push(backfill(
+            POST_TABLE_NAME,
+            DomainEventKind::PostPublished,
+            srql::field("creator_id").into(),
+            "id",
+        ));
+        statements.push(backfill(
+            FOLLOWS_TABLE_NAME,
+            DomainEventKind::FollowAdded,
+            srql::field("in").into(),
+            "out",
+        ));
+    }
+}
+
+/// Inserts an event for every record in a table.
+///
+/// The events are given new ULIDs, so they sort before any event written
+/// after the migration, but their order among themselves is arbitrary.
+fn backfill(
+    table: &str,
+    kind: DomainEventKind,
+    actor_id: srql::Value,
+    subject_id: &str,
+) -> srql::Statement {
+    let func = |name: &str, args: Vec<srql::Value>| {
+        srql::Value::Function(Box::new(srql::Function::Normal(name.to_owned(), args)))
+    };
+    let field = |expr: srql::Value, alias: &str| srql::Field::Single {
+        expr,
+        alias: Some(srql::field(alias)),
+    };
+
+    let now = srql::time_now();
+    let select = srql::SelectStatement {
+        expr: srql::Fields(
+            vec![
+                field(
+                    func("string::lowercase", vec![func("rand::ulid", vec![])]),
+                    "id",
+                ),
+                field(srql::to_value(kind).unwrap_or_default(), "kind"),
+                field(actor_id, "actor_id"),
+                field(srql::field(subject_id).into(), "subject_id"),
+                field(now.clone(), "occurred_at"),
+                field(now, "updated_at"),
+            ],
+            false,
+        ),
+        what: srql::table(table),
+        ..Default::default()
+    };
+    srql::Statement::Insert(srql::InsertStatement {
+        into: srql::Table(DOMAIN_EVENT_TABLE_NAME.to_owned()).into(),
+        data: srql::Data::SingleExpression(srql::Value::Subquery(Box::new(
+            srql::Subquery::Select(select),
+        ))),
+        output: srql::Output::None.into(),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::{
+        account::testing::*,
+        event::{catch_up, rebuild, AccountCountsProjection, DomainEvent},
+        follow::testing::FollowTestData as _,
+    };
+
+    #[tokio::test]
+    async fn test_backfill() {
+        let (data, acc) = TestData::with_user().await;
+        let other = data.account().create_test_user().await;
+        // Records written before there was an event log.
+        data.persist
+            .db()
+            .query(srql::DeleteStatement {
+                what: srql::table(DOMAIN_EVENT_TABLE_NAME),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        data.persist
+            .db()
+            .query(srql::RelateStatement {
+                kind: srql::Table(FOLLOWS_TABLE_NAME.to_owned()).into(),
+                from: acc.id.clone().into(),
+                with: other.id.clone().into(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let mut statements = vec![];
+        EventMigration::Init.build(&mut statements);
+        data.persist
+            .db()
+            .query(srql::query(statements))
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+
+        let mut events: Vec<DomainEvent> = data
+            .persist
+            .db()
+            .query(srql::SelectStatement {
+                expr: srql::Fields::all(),
+                what: srql::table(DOMAIN_EVENT_TABLE_NAME),
+                ..Default::default()
+            })
+            .await
+            .unwrap()
+            .take(0)
+            .unwrap();
+        events.sort_by_key(|event| (format!("{:?}", event.kind), event.subject_id.to_string()));
+        let events: Vec<_> = events
+            .into_iter()
+            .map(|event| (event.kind, event.actor_id, event.subject_id))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                (DomainEventKind::AccountCreated, None, acc.id.clone()),
+                (DomainEventKind::AccountCreated, None, other.id.clone()),
+                (
+                    DomainEventKind::FollowAdded,
+                    Some(acc.id.clone()),
+                    other.id.clone()
+                ),
+            ]
+        );
+
+        // Events written after the backfill sort after it.
+        let applied = rebuild(&data.persist, &AccountCountsProjection)
+            .await
+            .unwrap();
+        assert_eq!(applied, 3);
+        data.generate_followed().await;
+        let applied = catch_up(&data.persist, &AccountCountsProjection)
+            .await
+            .unwrap();
+        assert_eq!(applied, 2);
+    }
+}
@@ -0,0 +1,31 @@
+//! An append-only log of what has happened on the instance, such as accounts
+//! being created, posts being published and accounts being followed.
+//!
+//! Events are written in the same transaction as the change they record, so
+//! the log is a complete history. Read models are built from it as
+//! projections, which each keep a checkpoint of the last event they applied.
+//! A new projection starts from the beginning of the log, and an existing one
+//! can be rebuilt from scratch, instead of backfilling it from the tables
+//! it's derived from.
+//!
+//! Counters, such as an account's followers, are kept this way so they can
+//! be read without counting records each time. They can drift, for example
+//! when a reconciliation and an event race, so they're periodically recounted
+//! from the tables they're derived from.
+
+mod job;
+mod migration;
+mod models;
+mod persist;
+mod projection;
+
+pub use job::*;
+pub use migration::*;
+pub use models::*;
+pub use persist::*;
+pub use projection::*;
+
+pub static DOMAIN_EVENT_TABLE_NAME: &str = "domain_event";
+static PROJECTION_TABLE_NAME: &str = "projection";
+pub static ACCOUNT_COUNTS_TABLE_NAME: &str = "account_counts";
+static POST_COUNTS_TABLE_NAME: &str = "post_counts";
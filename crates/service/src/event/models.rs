@@ -0,0 +1,111 @@
+use async_graphql::SimpleObject;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use surrealdb::sql::Thing;
+
+use super::DOMAIN_EVENT_TABLE_NAME;
+use crate::prelude::*;
+
+/// Something that happened to the instance's accounts or content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DomainEventKind {
+    /// An account was registered. The subject is the account.
+    AccountCreated,
+    /// A post was published. The actor is its creator, if it has one, and
+    /// the subject is the post.
+    PostPublished,
+    /// A post was deleted. The actor is its creator, if it has one, and the
+    /// subject is the post.
+    PostDeleted,
+    /// The actor followed the subject.
+    FollowAdded,
+    /// The actor unfollowed the subject.
+    FollowRemoved,
+}
+
+impl QueryValue for DomainEventKind {
+    fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
+        srql::to_value(self)
+            .ok()
+            .map(|value| (field, srql::Operator::Equal, value))
+    }
+}
+
+/// An entry in the event log. Entries are never changed once they're
+/// written, and their IDs increase in the order they were written.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct DomainEvent {
+    pub id: Thing,
+    pub kind: DomainEventKind,
+    /// Who caused the event.
+    pub actor_id: Option<Thing>,
+    /// What the event happened to.
+    pub subject_id: Thing,
+    /// Where the subject is. For posts, these are the board it's in and the
+    /// post it replies to.
+    #[serde(default)]
+    pub context_ids: Vec<Thing>,
+    pub occurred_at: DateTime<Utc>,
+}
+
+impl DomainEvent {
+    /// Builds the statement that appends an event to the log. This should be
+    /// run in the same transaction as the change it records, so that the log
+    /// never misses a change or records one that didn't happen.
+    pub fn create(
+        kind: DomainEventKind,
+        actor_id: Option<Thing>,
+        subject_id: Thing,
+        context_ids: Vec<Thing>,
+        clock: &dyn Clock,
+        ids: &dyn IdGen,
+    ) -> srql::CreateStatement {
+        let mut create = vec![];
+        kind.push_field(srql::field("kind"), &mut create);
+        actor_id.push_field(srql::field("actor_id"), &mut create);
+        subject_id.push_field(srql::field("subject_id"), &mut create);
+        context_ids.push_field(srql::field("context_ids"), &mut create);
+        clock
+            .now()
+            .push_field(srql::field("occurred_at"), &mut create);
+        let mut create = srql::obj_create_query(DOMAIN_EVENT_TABLE_NAME, create, ids);
+        create.output = srql::Output::None.into();
+        create
+    }
+}
+
+/// How far a projection has got through the event log.
+#[derive(SimpleObject, Debug, Clone, Default, Deserialize)]
+pub struct ProjectionStatus {
+    /// The projection's name.
+    #[serde(default)]
+    pub name: String,
+    /// How many events have been applied since the projection was last
+    /// rebuilt.
+    #[serde(default)]
+    pub events_applied: u64,
+    /// When the projection was last rebuilt from the start of the log, if it
+    /// ever has been.
+    pub rebuilt_at: Option<DateTime<Utc>>,
+    #[graphql(skip)]
+    pub last_event_id: Option<Thing>,
+}
+
+/// Counts of an account's followers, follows and posts.
+#[derive(SimpleObject, Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct AccountCounts {
+    /// How many accounts follow the account.
+    #[serde(default)]
+    pub followers: u64,
+    /// How many accounts the account follows.
+    #[serde(default)]
+    pub following: u64,
+    /// How many posts the account has.
+    #[serde(default)]
+    pub posts: u64,
+    /// How many posts the account has published, including any it has since
+    /// deleted.
+    #[serde(default)]
+    pub posts_published: u64,
+}
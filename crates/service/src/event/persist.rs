@@ -0,0 +1,353 @@
+#[cfg(test)]
+mod tests;
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use tracing::instrument;
+
+use super::{
+    projection, AccountCounts, Counter, DomainEvent, Projection, ProjectionStatus,
+    ACCOUNT_COUNTS_TABLE_NAME, DOMAIN_EVENT_TABLE_NAME, POST_COUNTS_TABLE_NAME, PROJECTIONS,
+    PROJECTION_TABLE_NAME,
+};
+use crate::{
+    account::{require_admin, CurrentAccount},
+    persist::Persist,
+    prelude::*,
+    query::SRQL_ORDER_ASC,
+};
+
+/// How many events are applied to a projection in each transaction.
+const BATCH_SIZE: u64 = 500;
+
+pub struct EventPersist<'a> {
+    persist: &'a Persist,
+    current: &'a CurrentAccount,
+}
+
+impl<'a> EventPersist<'a> {
+    pub fn new(persist: &'a Persist, current: &'a CurrentAccount) -> Self {
+        Self { persist, current }
+    }
+
+    /// Lists how far each projection has got through the event log.
+    ///
+    /// Only admins can see these.
+    #[instrument(skip_all)]
+    pub async fn projections(&self) -> Result<Vec<ProjectionStatus>> {
+        require_admin(self.persist, self.current).await?;
+        let mut statuses = Vec::with_capacity(PROJECTIONS.len());
+        for projection in PROJECTIONS {
+            statuses.push(status(self.persist, *projection).await?);
+        }
+        Ok(statuses)
+    }
+
+    /// Throws away everything a projection has built and builds it again
+    /// from the start of the event log. Returns `None` if the projection is
+    /// already being updated.
+    ///
+    /// Only admins can do this.
+    #[instrument(skip(self))]
+    pub async fn rebuild(&self, name: &str) -> Result<Option<ProjectionStatus>> {
+        require_admin(self.persist, self.current).await?;
+        let Some(projection) = projection(name) else {
+            return Err(Error::NotFound);
+        };
+
+        let rebuilt = self
+            .persist
+            .execute_in_lock(&lock_id(projection), || rebuild(self.persist, projection))
+            .await?;
+        match rebuilt {
+            Some(rebuilt) => {
+                rebuilt?;
+                Ok(Some(status(self.persist, projection).await?))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// Applies the events that the projection hasn't seen yet, in the order
+/// they were written. Returns how many were applied.
+#[instrument(skip_all, fields(projection = projection.name()))]
+pub async fn catch_up(persist: &Persist, projection: &dyn Projection) -> Result<usize> {
+    let mut last_event_id = status(persist, projection).await?.last_event_id;
+    let mut applied = 0;
+    loop {
+        let events: Vec<DomainEvent> = persist
+            .db()
+            .query(srql::SelectStatement {
+                expr: srql::Fields::all(),
+                what: srql::table(DOMAIN_EVENT_TABLE_NAME),
+                cond: last_event_id.clone().map(|last_event_id| {
+                    srql::Cond(
+                        srql::Expression::Binary {
+                            l: srql::field("id").into(),
+                            o: srql::Operator::MoreThan,
+                            r: last_event_id.into(),
+                        }
+                        .into(),
+                    )
+                }),
+                order: srql::Orders(vec![srql::Order {
+                    order: srql::field("id"),
+                    direction: SRQL_ORDER_ASC,
+                    ..Default::default()
+                }])
+                .into(),
+                limit: srql::Limit(BATCH_SIZE.into()).into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        let Some(last) = events.last() else {
+            return Ok(applied);
+        };
+
+        let mut statements = vec![srql::trans_begin()];
+        for event in &events {
+            projection.apply(event, &mut statements);
+        }
+        statements.push(checkpoint(
+            projection,
+            vec![
+                (
+                    srql::field("last_event_id"),
+                    srql::Operator::Equal,
+                    last.id.clone().into(),
+                ),
+                (
+                    srql::field("events_applied"),
+                    srql::Operator::Inc,
+                    events.len().into(),
+                ),
+            ],
+        ));
+        statements.push(srql::trans_end());
+        persist.db().query(srql::query(statements)).await?.check()?;
+
+        applied += events.len();
+        last_event_id = Some(last.id.clone());
+    }
+}
+
+/// Resets the projection and applies the whole event log to it, then
+/// recounts its counters. Returns how many events were applied.
+#[instrument(skip_all, fields(projection = projection.name()))]
+pub async fn rebuild(persist: &Persist, projection: &dyn Projection) -> Result<usize> {
+    let mut statements = vec![srql::trans_begin()];
+    projection.reset(&mut statements);
+    statements.push(checkpoint(
+        projection,
+        vec![
+            (
+                srql::field("last_event_id"),
+                srql::Operator::Equal,
+                srql::Value::None,
+            ),
+            (
+                srql::field("events_applied"),
+                srql::Operator::Equal,
+                0.into(),
+            ),
+            (
+                srql::field("rebuilt_at"),
+                srql::Operator::Equal,
+                srql::time_now(),
+            ),
+        ],
+    ));
+    statements.push(srql::trans_end());
+    persist.db().query(srql::query(statements)).await?.check()?;
+
+    let applied = catch_up(persist, projection).await?;
+    reconcile(persist, projection).await?;
+    Ok(applied)
+}
+
+/// Recounts the projection's counters from the tables they're derived from,
+/// and corrects any that have drifted. Returns how many were corrected.
+///
+/// This should be done while holding the projection's lock, once it has
+/// caught up. Events written while the counts are taken can still be
+/// counted twice, which the next reconciliation corrects.
+#[instrument(skip_all, fields(projection = projection.name()))]
+pub async fn reconcile(persist: &Persist, projection: &dyn Projection) -> Result<usize> {
+    #[derive(Deserialize)]
+    struct Count {
+        key: srql::Thing,
+        count: i64,
+    }
+
+    // Counters that share a field are summed, such as a board's posts and a
+    // post's replies.
+    let mut fields: Vec<(&str, &str, Vec<&Counter>)> = vec![];
+    for counter in projection.counters() {
+        match fields
+            .iter_mut()
+            .find(|(table, field, _)| *table == counter.table && *field == counter.field)
+        {
+            Some((_, _, counters)) => counters.push(counter),
+            None => fields.push((counter.table, counter.field, vec![counter])),
+        }
+    }
+
+    let mut corrected = 0;
+    for (table, field, counters) in fields {
+        // Keyed by the raw ID, as record IDs can't be hashed soundly.
+        let mut counts: HashMap<String, (srql::Id, i64)> = HashMap::new();
+        for counter in counters {
+            let found: Vec<Count> = persist
+                .db()
+                .query(srql::count_by_query(counter.source, counter.key))
+                .await?
+                .take(0)?;
+            for Count { key, count } in found {
+                counts.entry(key.id.to_raw()).or_insert((key.id, 0)).1 += count;
+            }
+        }
+
+        let stored: Vec<Count> = persist
+            .db()
+            .query(srql::SelectStatement {
+                expr: srql::Fields(
+                    vec![
+                        srql::Field::Single {
+                            expr: srql::field("id").into(),
+                            alias: Some(srql::field("key")),
+                        },
+                        srql::Field::Single {
+                            expr: srql::field(field).into(),
+                            alias: Some(srql::field("count")),
+                        },
+                    ],
+                    false,
+                ),
+                what: srql::table(table),
+                cond: srql::Cond(
+                    srql::Expression::Binary {
+                        l: srql::field(field).into(),
+                        o: srql::Operator::NotEqual,
+                        r: srql::Value::None,
+                    }
+                    .into(),
+                )
+                .into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        let mut stored: HashMap<String, (srql::Id, i64)> = stored
+            .into_iter()
+            .map(|Count { key, count }| (key.id.to_raw(), (key.id, count)))
+            .collect();
+
+        let mut statements = vec![srql::trans_begin()];
+        for (raw, (id, count)) in counts {
+            if stored.remove(&raw).map(|(_, stored)| stored) != Some(count) {
+                statements.push(set_counter(table, id, field, count));
+            }
+        }
+        for (id, count) in stored.into_values() {
+            if count != 0 {
+                statements.push(set_counter(table, id, field, 0));
+            }
+        }
+        if statements.len() > 1 {
+            corrected += statements.len() - 1;
+            statements.push(srql::trans_end());
+            persist.db().query(srql::query(statements)).await?.check()?;
+        }
+    }
+    Ok(corrected)
+}
+
+/// Gets the account's counts from the account counts projection. These can
+/// lag a little behind the event log.
+pub async fn account_counts(persist: &Persist, account_id: &srql::Thing) -> Result<AccountCounts> {
+    let counts: Option<AccountCounts> = persist
+        .db()
+        .select((ACCOUNT_COUNTS_TABLE_NAME, account_id.id.clone()))
+        .await?;
+    Ok(counts.unwrap_or_default())
+}
+
+/// Gets the number of posts in a board, or of replies to a post, from the
+/// post counts projection. This can lag a little behind the event log.
+pub async fn post_count(persist: &Persist, id: &srql::Thing) -> Result<u64> {
+    #[derive(Deserialize)]
+    struct PostCount {
+        #[serde(default)]
+        posts: u64,
+    }
+
+    let count: Option<PostCount> = persist
+        .db()
+        .select((POST_COUNTS_TABLE_NAME, id.id.clone()))
+        .await?;
+    Ok(count.map_or(0, |count| count.posts))
+}
+
+async fn status(persist: &Persist, projection: &dyn Projection) -> Result<ProjectionStatus> {
+    let status: Option<ProjectionStatus> = persist
+        .db()
+        .select((PROJECTION_TABLE_NAME, projection.name()))
+        .await?;
+    Ok(status.unwrap_or_else(|| ProjectionStatus {
+        name: projection.name().to_owned(),
+        ..Default::default()
+    }))
+}
+
+fn set_counter(table: &str, id: srql::Id, field: &str, count: i64) -> srql::Statement {
+    srql::Statement::Update(srql::UpdateStatement {
+        what: srql::thing((table.to_owned(), id)),
+        data: srql::Data::SetExpression(vec![(
+            srql::field(field),
+            srql::Operator::Equal,
+            count.into(),
+        )])
+        .into(),
+        output: srql::Output::None.into(),
+        ..Default::default()
+    })
+}
+
+fn checkpoint(projection: &dyn Projection, mut update: srql::SetExpr) -> srql::Statement {
+    update.push((
+        srql::field("name"),
+        srql::Operator::Equal,
+        projection.name().into(),
+    ));
+    srql::Statement::Update(srql::UpdateStatement {
+        what: srql::thing((PROJECTION_TABLE_NAME, projection.name())),
+        data: srql::Data::SetExpression(update).into(),
+        output: srql::Output::None.into(),
+        ..Default::default()
+    })
+}
+
+/// The lock held while a projection is being updated.
+pub fn lock_id(projection: &dyn Projection) -> String {
+    format!("projection_{}", projection.name())
+}
+
+#[cfg(test)]
+pub mod testing {
+    use crate::account::testing::TestData;
+
+    use super::EventPersist;
+
+    pub trait EventTestData {
+        fn events(&self) -> EventPersist<'_>;
+    }
+
+    impl EventTestData for TestData {
+        fn events(&self) -> EventPersist<'_> {
+            EventPersist::new(&self.persist, &self.current)
+        }
+    }
+}
@@ -0,0 +1,302 @@
+use pretty_assertions::assert_eq;
+
+use super::{testing::EventTestData as _, *};
+use crate::{
+    account::testing::*,
+    board::testing::BoardTestData as _,
+    event::{AccountCountsProjection, DomainEventKind, PostCountsProjection},
+    follow::testing::FollowTestData as _,
+    post::testing::PostTestData as _,
+};
+
+async fn events(persist: &Persist) -> Vec<DomainEvent> {
+    persist
+        .db()
+        .query(srql::SelectStatement {
+            expr: srql::Fields::all(),
+            what: srql::table(DOMAIN_EVENT_TABLE_NAME),
+            order: srql::Orders(vec![srql::Order {
+                order: srql::field("id"),
+                direction: SRQL_ORDER_ASC,
+                ..Default::default()
+            }])
+            .into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .take(0)
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_events_written() {
+    let (data, acc) = TestData::with_user().await;
+    let followed = data.generate_followed().await;
+    let post = data.generate_post().await;
+    // Following again or unfollowing an account that isn't followed doesn't
+    // change anything, so isn't recorded.
+    let id = followed.id.id.to_raw();
+    assert!(!data.follow().follow(&id).await.unwrap());
+    assert!(data.follow().unfollow(&id).await.unwrap());
+    assert!(!data.follow().unfollow(&id).await.unwrap());
+    data.post().delete(&post.id.id.to_raw()).await.unwrap();
+    assert!(data
+        .post()
+        .delete(&post.id.id.to_raw())
+        .await
+        .unwrap()
+        .is_none());
+
+    let events: Vec<_> = events(&data.persist)
+        .await
+        .into_iter()
+        .map(|event| (event.kind, event.actor_id, event.subject_id))
+        .collect();
+    assert_eq!(
+        events,
+        vec![
+            (DomainEventKind::AccountCreated, None, acc.id.clone()),
+            (DomainEventKind::AccountCreated, None, followed.id.clone()),
+            (
+                DomainEventKind::FollowAdded,
+                Some(acc.id.clone()),
+                followed.id.clone()
+            ),
+            (
+                DomainEventKind::PostPublished,
+                Some(acc.id.clone()),
+                post.id.clone()
+            ),
+            (
+                DomainEventKind::FollowRemoved,
+                Some(acc.id.clone()),
+                followed.id.clone()
+            ),
+            (
+                DomainEventKind::PostDeleted,
+                Some(acc.id.clone()),
+                post.id.clone()
+            ),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_catch_up() {
+    let (data, acc) = TestData::with_user().await;
+    let followed = data.generate_followed().await;
+    data.generate_posts(2).await;
+
+    let applied = catch_up(&data.persist, &AccountCountsProjection)
+        .await
+        .unwrap();
+    assert_eq!(applied, 5);
+    assert_eq!(
+        account_counts(&data.persist, &acc.id).await.unwrap(),
+        AccountCounts {
+            followers: 0,
+            following: 1,
+            posts: 2,
+            posts_published: 2,
+        }
+    );
+    assert_eq!(
+        account_counts(&data.persist, &followed.id).await.unwrap(),
+        AccountCounts {
+            followers: 1,
+            following: 0,
+            posts: 0,
+            posts_published: 0,
+        }
+    );
+
+    // Events that have been applied aren't applied again.
+    let applied = catch_up(&data.persist, &AccountCountsProjection)
+        .await
+        .unwrap();
+    assert_eq!(applied, 0);
+
+    data.follow()
+        .unfollow(&followed.id.id.to_raw())
+        .await
+        .unwrap();
+    let applied = catch_up(&data.persist, &AccountCountsProjection)
+        .await
+        .unwrap();
+    assert_eq!(applied, 1);
+    assert_eq!(
+        account_counts(&data.persist, &followed.id).await.unwrap(),
+        AccountCounts::default()
+    );
+}
+
+#[tokio::test]
+async fn test_rebuild() {
+    let (mut data, acc) = TestData::with_user().await;
+    data.generate_followed().await;
+    catch_up(&data.persist, &AccountCountsProjection)
+        .await
+        .unwrap();
+
+    // A read model that has drifted from the log is put right by rebuilding
+    // it.
+    data.persist
+        .db()
+        .query(srql::UpdateStatement {
+            what: srql::table(ACCOUNT_COUNTS_TABLE_NAME),
+            data: srql::Data::SetExpression(vec![(
+                srql::field("following"),
+                srql::Operator::Equal,
+                10.into(),
+            )])
+            .into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let status = data
+        .events()
+        .rebuild("account_counts")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(status.name, "account_counts");
+    assert_eq!(status.events_applied, 3);
+    assert!(status.rebuilt_at.is_some());
+    assert_eq!(
+        account_counts(&data.persist, &acc.id)
+            .await
+            .unwrap()
+            .following,
+        1
+    );
+    assert_eq!(
+        data.events()
+            .projections()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|status| (status.name, status.events_applied))
+            .collect::<Vec<_>>(),
+        vec![
+            ("account_counts".to_owned(), 3),
+            ("post_counts".to_owned(), 0)
+        ]
+    );
+
+    assert!(matches!(
+        data.events().rebuild("missing").await,
+        Err(Error::NotFound)
+    ));
+
+    let other = data.account().create_test_user().await;
+    data.login_as(&other);
+    assert!(matches!(
+        data.events().rebuild("account_counts").await,
+        Err(Error::Unauthorized)
+    ));
+}
+
+#[tokio::test]
+async fn test_post_counts() {
+    let (data, _) = TestData::with_user().await;
+    let board = data.generate_board().await;
+    let post = data.generate_post_in(&board.id).await;
+    let reply = data.generate_reply(&post.id).await;
+    data.generate_post_in(&board.id).await;
+
+    catch_up(&data.persist, &PostCountsProjection)
+        .await
+        .unwrap();
+    // Replies aren't in a board unless they're posted to one.
+    assert_eq!(post_count(&data.persist, &board.id).await.unwrap(), 2);
+    assert_eq!(post_count(&data.persist, &post.id).await.unwrap(), 1);
+    assert_eq!(post_count(&data.persist, &reply.id).await.unwrap(), 0);
+
+    data.post().delete(&reply.id.id.to_raw()).await.unwrap();
+    data.post().delete(&post.id.id.to_raw()).await.unwrap();
+    catch_up(&data.persist, &PostCountsProjection)
+        .await
+        .unwrap();
+    assert_eq!(post_count(&data.persist, &board.id).await.unwrap(), 1);
+    assert_eq!(post_count(&data.persist, &post.id).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn test_reconcile() {
+    let (data, acc) = TestData::with_user().await;
+    let board = data.generate_board().await;
+    let post = data.generate_post_in(&board.id).await;
+    data.generate_reply(&post.id).await;
+    catch_up(&data.persist, &AccountCountsProjection)
+        .await
+        .unwrap();
+    catch_up(&data.persist, &PostCountsProjection)
+        .await
+        .unwrap();
+
+    // Counters that agree with the tables are left alone.
+    assert_eq!(
+        reconcile(&data.persist, &AccountCountsProjection)
+            .await
+            .unwrap(),
+        0
+    );
+    assert_eq!(
+        reconcile(&data.persist, &PostCountsProjection)
+            .await
+            .unwrap(),
+        0
+    );
+
+    // Drifted counters are recounted, including ones for records that no
+    // longer have anything to count.
+    let drift = |table: &str, id: &srql::Thing, field: &str, count: i64| {
+        srql::Statement::Update(srql::UpdateStatement {
+            what: srql::thing((table.to_owned(), id.id.clone())),
+            data: srql::Data::SetExpression(vec![(
+                srql::field(field),
+                srql::Operator::Equal,
+                count.into(),
+            )])
+            .into(),
+            ..Default::default()
+        })
+    };
+    let other = data.account().create_test_user().await;
+    data.persist
+        .db()
+        .query(srql::query([
+            drift(ACCOUNT_COUNTS_TABLE_NAME, &acc.id, "posts", 5),
+            drift(ACCOUNT_COUNTS_TABLE_NAME, &other.id, "followers", 2),
+            drift(POST_COUNTS_TABLE_NAME, &board.id, "posts", 0),
+        ]))
+        .await
+        .unwrap();
+    assert_eq!(
+        reconcile(&data.persist, &AccountCountsProjection)
+            .await
+            .unwrap(),
+        2
+    );
+    assert_eq!(
+        reconcile(&data.persist, &PostCountsProjection)
+            .await
+            .unwrap(),
+        1
+    );
+    assert_eq!(
+        account_counts(&data.persist, &acc.id).await.unwrap().posts,
+        2
+    );
+    assert_eq!(
+        account_counts(&data.persist, &other.id)
+            .await
+            .unwrap()
+            .followers,
+        0
+    );
+    assert_eq!(post_count(&data.persist, &board.id).await.unwrap(), 1);
+    assert_eq!(post_count(&data.persist, &post.id).await.unwrap(), 1);
+}
@@ -0,0 +1,178 @@
+use super::{DomainEvent, DomainEventKind, ACCOUNT_COUNTS_TABLE_NAME, POST_COUNTS_TABLE_NAME};
+use crate::{follow::FOLLOWS_TABLE_NAME, post::POST_TABLE_NAME, prelude::*};
+
+/// A read model built from the event log.
+///
+/// A projection only ever sees each event once: the statements it builds
+/// for a batch of events are run in the same transaction that moves its
+/// checkpoint past them.
+pub trait Projection: Send + Sync {
+    /// The name that the projection's checkpoint is kept under.
+    fn name(&self) -> &'static str;
+
+    /// Builds the statements that remove everything the projection has
+    /// built, before it's rebuilt from the start of the log.
+    fn reset(&self, statements: &mut Vec<srql::Statement>);
+
+    /// Builds the statements that apply an event to the read model.
+    fn apply(&self, event: &DomainEvent, statements: &mut Vec<srql::Statement>);
+
+    /// The counters in the read model that can be recounted from the tables
+    /// they're derived from, to correct any drift.
+    fn counters(&self) -> &'static [Counter] {
+        &[]
+    }
+}
+
+/// A field in a read model that counts the records of another table that
+/// refer to the same ID.
+pub struct Counter {
+    /// The read model's table. Its records have the same IDs as the records
+    /// being counted for.
+    pub table: &'static str,
+    pub field: &'static str,
+    /// The table of the records being counted.
+    pub source: &'static str,
+    /// The field of the source records that refers to what they're counted
+    /// for.
+    pub key: &'static str,
+}
+
+/// Every projection that is kept up to date. A projection added here is
+/// built from the whole log the first time it's caught up, so new read
+/// models don't need a backfill.
+pub static PROJECTIONS: &[&dyn Projection] = &[&AccountCountsProjection, &PostCountsProjection];
+
+/// Finds a projection by its name.
+pub fn projection(name: &str) -> Option<&'static dyn Projection> {
+    PROJECTIONS
+        .iter()
+        .copied()
+        .find(|projection| projection.name() == name)
+}
+
+/// Builds the statement that adds to one of the counters kept for a record.
+fn add(table: &str, id: &srql::Thing, field: &str, by: i64) -> srql::Statement {
+    srql::Statement::Update(srql::UpdateStatement {
+        what: srql::thing((table.to_owned(), id.id.clone())),
+        data: srql::Data::SetExpression(vec![(srql::field(field), srql::Operator::Inc, by.into())])
+            .into(),
+        output: srql::Output::None.into(),
+        ..Default::default()
+    })
+}
+
+fn delete_all(table: &str) -> srql::Statement {
+    srql::Statement::Delete(srql::DeleteStatement {
+        what: srql::table(table),
+        output: srql::Output::None.into(),
+        ..Default::default()
+    })
+}
+
+/// Counts each account's followers, follows and posts.
+pub struct AccountCountsProjection;
+
+impl Projection for AccountCountsProjection {
+    fn name(&self) -> &'static str {
+        "account_counts"
+    }
+
+    fn reset(&self, statements: &mut Vec<srql::Statement>) {
+        statements.push(delete_all(ACCOUNT_COUNTS_TABLE_NAME));
+    }
+
+    fn apply(&self, event: &DomainEvent, statements: &mut Vec<srql::Statement>) {
+        let add = |id, field, by| add(ACCOUNT_COUNTS_TABLE_NAME, id, field, by);
+        let by = match event.kind {
+            DomainEventKind::AccountCreated => return,
+            DomainEventKind::PostPublished => {
+                if let Some(creator_id) = &event.actor_id {
+                    statements.push(add(creator_id, "posts", 1));
+                    statements.push(add(creator_id, "posts_published", 1));
+                }
+                return;
+            }
+            DomainEventKind::PostDeleted => {
+                if let Some(creator_id) = &event.actor_id {
+                    statements.push(add(creator_id, "posts", -1));
+                }
+                return;
+            }
+            DomainEventKind::FollowAdded => 1,
+            DomainEventKind::FollowRemoved => -1,
+        };
+        if let Some(follower_id) = &event.actor_id {
+            statements.push(add(follower_id, "following", by));
+        }
+        statements.push(add(&event.subject_id, "followers", by));
+    }
+
+    fn counters(&self) -> &'static [Counter] {
+        ACCOUNT_COUNTERS
+    }
+}
+
+static ACCOUNT_COUNTERS: &[Counter] = &[
+    Counter {
+        table: ACCOUNT_COUNTS_TABLE_NAME,
+        field: "followers",
+        source: FOLLOWS_TABLE_NAME,
+        key: "out",
+    },
+    Counter {
+        table: ACCOUNT_COUNTS_TABLE_NAME,
+        field: "following",
+        source: FOLLOWS_TABLE_NAME,
+        key: "in",
+    },
+    Counter {
+        table: ACCOUNT_COUNTS_TABLE_NAME,
+        field: "posts",
+        source: POST_TABLE_NAME,
+        key: "creator_id",
+    },
+];
+
+/// Counts the posts in each board and the replies to each post.
+pub struct PostCountsProjection;
+
+impl Projection for PostCountsProjection {
+    fn name(&self) -> &'static str {
+        "post_counts"
+    }
+
+    fn reset(&self, statements: &mut Vec<srql::Statement>) {
+        statements.push(delete_all(POST_COUNTS_TABLE_NAME));
+    }
+
+    fn apply(&self, event: &DomainEvent, statements: &mut Vec<srql::Statement>) {
+        let by = match event.kind {
+            DomainEventKind::PostPublished => 1,
+            DomainEventKind::PostDeleted => -1,
+            _ => return,
+        };
+        for context_id in &event.context_ids {
+            statements.push(add(POST_COUNTS_TABLE_NAME, context_id, "posts", by));
+        }
+    }
+
+    fn counters(&self) -> &'static [Counter] {
+        POST_COUNTERS
+    }
+}
+
+static POST_COUNTERS: &[Counter] = &[
+    Counter {
+        table: POST_COUNTS_TABLE_NAME,
+        field: "posts",
+        source: POST_TABLE_NAME,
+        key: "board_id",
+    },
+    Counter {
+        table: POST_COUNTS_TABLE_NAME,
+        field: "posts",
+        source: POST_TABLE_NAME,
+        key: "reply_to_id",
+    },
+];
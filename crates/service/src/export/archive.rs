@@ -0,0 +1,174 @@
+use chrono::{DateTime, Utc};
+use serde_json::{json, Map, Value as JsonValue};
+use surrealdb::sql::Thing;
+
+use crate::{
+    account::{Account, ACC_TABLE_NAME},
+    audience::AUDIENCE_TABLE_NAME,
+    follow::FOLLOWS_TABLE_NAME,
+    list::LIST_TABLE_NAME,
+    media::MEDIA_TABLE_NAME,
+    persist::Persist,
+    post::{Post, POST_TABLE_NAME},
+    prelude::*,
+    security::SECURITY_EVENT_TABLE_NAME,
+};
+
+/// The version of the archive's layout, which is bumped whenever something
+/// is moved or removed so that importers can tell them apart.
+pub const ARCHIVE_VERSION: u32 = 1;
+
+static ACTIVITY_STREAMS_CONTEXT: &str = "https://www.w3.org/ns/activitystreams";
+
+/// The fields of an account's record that are never exported.
+static SECRET_ACCOUNT_FIELDS: &[&str] = &["pword_salt", "pword_hash"];
+
+/// Builds an account's archive, as a JSON document of its profile and
+/// everything it has created. Media is described, but its content isn't
+/// included.
+///
+/// If `activity_pub` is set, the archive also has the account as an
+/// `ActivityPub` actor and its posts as the actor's outbox. Their IDs are the
+/// instance's REST URLs, under `public_url` if it's set.
+pub async fn build_archive(
+    persist: &Persist,
+    account_id: &Thing,
+    activity_pub: bool,
+    public_url: Option<&str>,
+) -> Result<Vec<u8>> {
+    let account: Option<Account> = persist.load(account_id.clone()).await?;
+    let account = account.ok_or(Error::NotFound)?;
+
+    let Some(JsonValue::Object(mut profile)) = records(persist, ACC_TABLE_NAME, "id", account_id)
+        .await?
+        .pop()
+    else {
+        return Err(Error::NotFound);
+    };
+    for field in SECRET_ACCOUNT_FIELDS {
+        profile.remove(*field);
+    }
+
+    let mut archive = Map::new();
+    archive.insert("version".into(), ARCHIVE_VERSION.into());
+    archive.insert(
+        "exported_at".into(),
+        persist.clock().now().to_rfc3339().into(),
+    );
+    archive.insert("account".into(), profile.into());
+    for (key, table, field) in [
+        ("posts", POST_TABLE_NAME, "creator_id"),
+        ("media", MEDIA_TABLE_NAME, "owner_id"),
+        ("lists", LIST_TABLE_NAME, "owner_id"),
+        ("audiences", AUDIENCE_TABLE_NAME, "owner_id"),
+        ("following", FOLLOWS_TABLE_NAME, "in"),
+        ("security_events", SECURITY_EVENT_TABLE_NAME, "account_id"),
+    ] {
+        let records = records(persist, table, field, account_id).await?;
+        archive.insert(key.into(), records.into());
+    }
+
+    if activity_pub {
+        let posts: Vec<Post> = persist
+            .db()
+            .query(owned_select(POST_TABLE_NAME, "creator_id", account_id))
+            .await?
+            .take(0)?;
+        archive.insert(
+            "activity_pub".into(),
+            activity_pub_objects(&account, &posts, public_url.unwrap_or_default()),
+        );
+    }
+
+    serde_json::to_vec_pretty(&archive).map_err(Error::from_err)
+}
+
+fn owned_select(table: &str, field: &str, account_id: &Thing) -> srql::SelectStatement {
+    srql::SelectStatement {
+        expr: srql::Fields::all(),
+        what: srql::table(table),
+        cond: srql::Cond(
+            srql::Expression::Binary {
+                l: srql::field(field).into(),
+                o: srql::Operator::Equal,
+                r: account_id.clone().into(),
+            }
+            .into(),
+        )
+        .into(),
+        order: srql::Orders(vec![srql::Order {
+            order: srql::field("id"),
+            direction: true,
+            ..Default::default()
+        }])
+        .into(),
+        ..Default::default()
+    }
+}
+
+/// Gets the records in a table that refer to the account, as JSON. Record
+/// IDs are written as `table:id`.
+async fn records(
+    persist: &Persist,
+    table: &str,
+    field: &str,
+    account_id: &Thing,
+) -> Result<Vec<JsonValue>> {
+    let records: srql::Value = persist
+        .db()
+        .query(owned_select(table, field, account_id))
+        .await?
+        .take(0)?;
+    Ok(match records.into_json() {
+        JsonValue::Array(records) => records,
+        JsonValue::Null => vec![],
+        record => vec![record],
+    })
+}
+
+/// Describes the account as an `ActivityPub` actor, along with an outbox of
+/// its posts.
+fn activity_pub_objects(account: &Account, posts: &[Post], base: &str) -> JsonValue {
+    let actor_id = format!("{base}/api/v1/accounts/{}", account.id.id.to_raw());
+    let post_url = |id: &Thing| format!("{base}/api/v1/posts/{}", id.id.to_raw());
+    let timestamp = |at: Option<DateTime<Utc>>| at.map(|at| at.to_rfc3339());
+
+    let items: Vec<JsonValue> = posts
+        .iter()
+        .map(|post| {
+            let note = json!({
+                "id": post_url(&post.id),
+                "type": "Note",
+                "attributedTo": actor_id,
+                "name": post.title,
+                "content": post.content,
+                "inReplyTo": post.reply_to_id.as_ref().map(post_url),
+                "updated": timestamp(post.updated_at),
+            });
+            json!({
+                "id": format!("{}/activity", post_url(&post.id)),
+                "type": "Create",
+                "actor": actor_id,
+                "object": note,
+            })
+        })
+        .collect();
+
+    json!({
+        "actor": {
+            "@context": ACTIVITY_STREAMS_CONTEXT,
+            "id": actor_id,
+            "type": if account.bot { "Service" } else { "Person" },
+            "preferredUsername": account.user_id,
+            "outbox": format!("{actor_id}/outbox"),
+            "updated": timestamp(Some(account.updated_at)),
+        },
+        "outbox": {
+            "@context": ACTIVITY_STREAMS_CONTEXT,
+            "id": format!("{actor_id}/outbox"),
+            "type": "OrderedCollection",
+            "totalItems": items.len(),
+            "orderedItems": items,
+        },
+    })
+}
@@ -0,0 +1,44 @@
+use std::time::Duration;
+
+use tokio::{
+    task::JoinHandle,
+    time::{interval, MissedTickBehavior},
+};
+use tracing::{debug, error, trace};
+
+use super::{build_pending_exports, prune_expired_exports};
+use crate::{persist::Persist, prelude::*};
+
+/// How often waiting exports are built and expired ones removed.
+pub const EXPORT_INTERVAL: Duration = Duration::from_mins(1);
+
+static EXPORT_LOCK: &str = "data_export";
+
+/// Spawns a task that periodically builds the exports that accounts have
+/// asked for, and removes the ones that have expired.
+pub fn spawn_data_exports(persist: Persist, public_url: Option<String>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = interval(EXPORT_INTERVAL);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+
+            let res = persist
+                .execute_in_lock(EXPORT_LOCK, || async {
+                    let _job = persist.metrics().track_job();
+                    let built = build_pending_exports(&persist, public_url.as_deref()).await?;
+                    let pruned = prune_expired_exports(&persist).await?;
+                    Ok::<_, Error>((built, pruned))
+                })
+                .await;
+
+            match res {
+                Ok(Some(Ok((built, pruned)))) => debug!(built, pruned, "Data exports processed"),
+                Ok(Some(Err(err))) => error!(error = ?err, "Failed to process data exports"),
+                Ok(None) => trace!("Data exports are already being processed"),
+                Err(err) => error!(error = ?err, "Failed to lock data exports"),
+            }
+        }
+    })
+}
@@ -0,0 +1,20 @@
+//! Exports of everything an account has stored on the instance, so that it
+//! can keep a copy of its data or take it elsewhere.
+//!
+//! Exports are built in the background, as accounts with a lot of posts can
+//! take a while to gather. Once one is ready, the account is notified and can
+//! download it until it expires, after which it's removed.
+
+mod archive;
+mod job;
+mod models;
+mod persist;
+mod schema;
+
+pub use archive::*;
+pub use job::*;
+pub use models::*;
+pub use persist::*;
+pub use schema::*;
+
+pub static EXPORT_TABLE_NAME: &str = "data_export";
@@ -0,0 +1,86 @@
+use async_graphql::{ComplexObject, Enum, SimpleObject, ID};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use surrealdb::sql::Thing;
+
+use super::EXPORT_TABLE_NAME;
+use crate::{id_obj_impls, prelude::*};
+
+/// How long a finished export can be downloaded for before it's removed.
+pub const EXPORT_EXPIRY_DAYS: i64 = 7;
+
+/// Where an export is up to.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportStatus {
+    /// The export is waiting to be built.
+    Pending,
+    /// The export has been built and can be downloaded.
+    Ready,
+    /// The export couldn't be built. Another can be requested.
+    Failed,
+}
+
+impl QueryValue for ExportStatus {
+    fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
+        srql::to_value(self)
+            .ok()
+            .map(|value| (field, srql::Operator::Equal, value))
+    }
+}
+
+/// An export of an account's data that it requested.
+#[derive(SimpleObject, Debug, Clone, Deserialize)]
+#[graphql(complex)]
+pub struct DataExport {
+    #[graphql(skip)]
+    pub id: Thing,
+    #[graphql(skip)]
+    pub account_id: Thing,
+    /// The key that the finished archive is stored under.
+    #[graphql(skip)]
+    pub blob_key: Option<String>,
+
+    /// Where the export is up to.
+    pub status: ExportStatus,
+    /// Whether the archive includes the account and its posts as `ActivityPub`
+    /// objects, for importing into other servers.
+    #[serde(default)]
+    pub activity_pub: bool,
+    /// The size of the archive in bytes, once it's ready.
+    pub size: Option<u64>,
+    /// When the archive was finished.
+    pub ready_at: Option<DateTime<Utc>>,
+    /// When the archive will be removed, after which it can't be downloaded.
+    pub expires_at: Option<DateTime<Utc>>,
+
+    /// A timestamp indicating the last time the export was updated.
+    pub updated_at: DateTime<Utc>,
+}
+
+#[ComplexObject]
+impl DataExport {
+    /// The export's unique ID.
+    async fn id(&self) -> ID {
+        self.id.to_gql_id()
+    }
+
+    /// The path that the archive can be downloaded from by the account, once
+    /// it's ready.
+    async fn download_path(&self) -> Option<String> {
+        (self.status == ExportStatus::Ready)
+            .then(|| format!("/api/v1/exports/{}", self.id.to_gql_id().as_str()))
+    }
+}
+
+id_obj_impls!(DataExport);
+
+impl DataExport {
+    pub fn create(account_id: Thing, activity_pub: bool, ids: &dyn IdGen) -> srql::CreateStatement {
+        let mut create = vec![];
+        account_id.push_field(srql::field("account_id"), &mut create);
+        ExportStatus::Pending.push_field(srql::field("status"), &mut create);
+        activity_pub.push_field(srql::field("activity_pub"), &mut create);
+        srql::obj_create_query(EXPORT_TABLE_NAME, create, ids)
+    }
+}
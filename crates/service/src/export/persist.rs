@@ -0,0 +1,288 @@
+#[cfg(test)]
+mod tests;
+
+use axum::body::Bytes;
+use chrono::Duration;
+use surrealdb::sql::Thing;
+use tracing::{instrument, warn};
+
+use super::{build_archive, DataExport, ExportStatus, EXPORT_EXPIRY_DAYS, EXPORT_TABLE_NAME};
+use crate::{
+    account::CurrentAccount,
+    media::blob_key,
+    notification::{CreateNotification, NotificationKind, NotificationPersist},
+    persist::Persist,
+    prelude::*,
+};
+
+/// The most exports that are built in one go, as each can be large.
+const BUILD_BATCH_SIZE: u32 = 10;
+
+pub struct ExportPersist<'a> {
+    persist: &'a Persist,
+    current: &'a CurrentAccount,
+}
+
+impl<'a> ExportPersist<'a> {
+    pub fn new(persist: &'a Persist, current: &'a CurrentAccount) -> Self {
+        Self { persist, current }
+    }
+
+    /// Gets one of the current account's exports.
+    #[instrument(skip_all)]
+    pub async fn get(&self, id: &str) -> Result<Option<DataExport>> {
+        let account_id = self.current.id()?.to_account_thing();
+        let export: Option<DataExport> = self.persist.db().select((EXPORT_TABLE_NAME, id)).await?;
+        Ok(export.filter(|export| export.account_id == account_id))
+    }
+
+    /// Lists the current account's exports, newest first.
+    #[instrument(skip_all)]
+    pub async fn list(&self) -> Result<Vec<DataExport>> {
+        let account_id = self.current.id()?.to_account_thing();
+        let exports = self
+            .persist
+            .db()
+            .query(srql::SelectStatement {
+                expr: srql::Fields::all(),
+                what: srql::table(EXPORT_TABLE_NAME),
+                cond: field_eq("account_id", account_id).into(),
+                order: srql::Orders(vec![srql::Order {
+                    order: srql::field("id"),
+                    direction: false,
+                    ..Default::default()
+                }])
+                .into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        Ok(exports)
+    }
+
+    /// Asks for an export of the current account's data, which is built in
+    /// the background. Only one export is built at a time, so if one is
+    /// already waiting, that's returned instead.
+    #[instrument(skip_all)]
+    pub async fn request(&self, activity_pub: bool) -> Result<DataExport> {
+        let account_id = self.current.id()?.to_account_thing();
+        if let Some(pending) = self
+            .list()
+            .await?
+            .into_iter()
+            .find(|export| export.status == ExportStatus::Pending)
+        {
+            return Ok(pending);
+        }
+
+        let export = self
+            .persist
+            .db()
+            .query(DataExport::create(
+                account_id,
+                activity_pub,
+                self.persist.ids(),
+            ))
+            .await?
+            .take(0)?;
+        match export {
+            Some(export) => Ok(export),
+            None => Err(Error::UnavailableIdent),
+        }
+    }
+
+    /// Gets one of the current account's finished exports along with its
+    /// archive.
+    #[instrument(skip_all)]
+    pub async fn download(&self, id: &str) -> Result<Option<(DataExport, Bytes)>> {
+        let Some(export) = self.get(id).await? else {
+            return Ok(None);
+        };
+        let Some(key) = export.blob_key.as_deref() else {
+            return Ok(None);
+        };
+        if export.status != ExportStatus::Ready {
+            return Ok(None);
+        }
+        Ok(self
+            .persist
+            .blobs()
+            .get(key, None)
+            .await?
+            .map(|archive| (export, archive)))
+    }
+}
+
+/// Builds the exports that are waiting, and notifies their accounts that
+/// they're ready. Exports that can't be built are marked as failed, so that
+/// another can be requested. Returns how many were built.
+#[instrument(skip(persist))]
+pub async fn build_pending_exports(persist: &Persist, public_url: Option<&str>) -> Result<usize> {
+    let pending: Vec<DataExport> = persist
+        .db()
+        .query(srql::SelectStatement {
+            expr: srql::Fields::all(),
+            what: srql::table(EXPORT_TABLE_NAME),
+            cond: field_eq(
+                "status",
+                srql::to_value(ExportStatus::Pending).map_err(Error::from_err)?,
+            )
+            .into(),
+            order: srql::Orders(vec![srql::Order {
+                order: srql::field("id"),
+                direction: true,
+                ..Default::default()
+            }])
+            .into(),
+            limit: Some(srql::Limit(BUILD_BATCH_SIZE.into())),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+
+    let mut built = 0;
+    for export in pending {
+        let archive =
+            match build_archive(persist, &export.account_id, export.activity_pub, public_url).await
+            {
+                Ok(archive) => archive,
+                Err(err) => {
+                    warn!(error = ?err, export = %export.id, "Failed to build export");
+                    let mut update = vec![];
+                    ExportStatus::Failed.push_field(srql::field("status"), &mut update);
+                    if let Some(update) = srql::obj_update_query(export.id, update) {
+                        persist.db().query(update).await?;
+                    }
+                    continue;
+                }
+            };
+
+        let key = blob_key(&archive);
+        let size = archive.len() as u64;
+        persist.blobs().put(&key, archive.into()).await?;
+
+        let now = persist.clock().now();
+        let mut update = vec![];
+        ExportStatus::Ready.push_field(srql::field("status"), &mut update);
+        key.push_field(srql::field("blob_key"), &mut update);
+        size.push_field(srql::field("size"), &mut update);
+        now.push_field(srql::field("ready_at"), &mut update);
+        (now + Duration::days(EXPORT_EXPIRY_DAYS))
+            .push_field(srql::field("expires_at"), &mut update);
+        if let Some(update) = srql::obj_update_query(export.id.clone(), update) {
+            persist.db().query(update).await?;
+        }
+
+        NotificationPersist::new(persist, &CurrentAccount::default())
+            .notify(CreateNotification {
+                account_id: export.account_id.clone(),
+                kind: NotificationKind::DataExportReady,
+                actor_id: None,
+                post_id: None,
+                subject_id: Some(export.id),
+            })
+            .await?;
+        built += 1;
+    }
+    Ok(built)
+}
+
+/// Removes the exports that have expired, along with their archives.
+/// Returns how many were removed.
+#[instrument(skip(persist))]
+pub async fn prune_expired_exports(persist: &Persist) -> Result<usize> {
+    let binary = |l: srql::Idiom, o, r| -> srql::Value {
+        srql::Expression::Binary { l: l.into(), o, r }.into()
+    };
+    let expired: Vec<DataExport> = persist
+        .db()
+        .query(srql::SelectStatement {
+            expr: srql::Fields::all(),
+            what: srql::table(EXPORT_TABLE_NAME),
+            cond: srql::Cond(
+                srql::Expression::Binary {
+                    l: binary(
+                        srql::field("expires_at"),
+                        srql::Operator::NotEqual,
+                        srql::Value::None,
+                    ),
+                    o: srql::Operator::And,
+                    r: binary(
+                        srql::field("expires_at"),
+                        srql::Operator::LessThanOrEqual,
+                        srql::Value::Datetime(srql::Datetime(persist.clock().now())),
+                    ),
+                }
+                .into(),
+            )
+            .into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+
+    for export in &expired {
+        delete_export(persist, export).await?;
+    }
+    Ok(expired.len())
+}
+
+/// Removes the archives of an account's exports, before the account is
+/// purged along with the exports themselves.
+pub(crate) async fn delete_export_archives(persist: &Persist, account_id: &Thing) -> Result<()> {
+    let exports: Vec<DataExport> = persist
+        .db()
+        .query(srql::SelectStatement {
+            expr: srql::Fields::all(),
+            what: srql::table(EXPORT_TABLE_NAME),
+            cond: field_eq("account_id", account_id.clone()).into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    for export in exports {
+        if let Some(key) = &export.blob_key {
+            persist.blobs().delete(key).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn delete_export(persist: &Persist, export: &DataExport) -> Result<()> {
+    if let Some(key) = &export.blob_key {
+        persist.blobs().delete(key).await?;
+    }
+    persist
+        .db()
+        .delete::<Option<DataExport>>(export.id.clone())
+        .await?;
+    Ok(())
+}
+
+fn field_eq(field: &str, value: impl Into<srql::Value>) -> srql::Cond {
+    srql::Cond(
+        srql::Expression::Binary {
+            l: srql::field(field).into(),
+            o: srql::Operator::Equal,
+            r: value.into(),
+        }
+        .into(),
+    )
+}
+
+#[cfg(test)]
+pub mod testing {
+    use crate::account::testing::TestData;
+
+    use super::ExportPersist;
+
+    pub trait ExportTestData {
+        fn export(&self) -> ExportPersist<'_>;
+    }
+
+    impl ExportTestData for TestData {
+        fn export(&self) -> ExportPersist<'_> {
+            ExportPersist::new(&self.persist, &self.current)
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use chrono::{TimeZone as _, Utc};
+use pretty_assertions::assert_eq;
+
+use super::{testing::ExportTestData as _, *};
+use crate::{
+    account::testing::*, notification::testing::NotificationTestData as _,
+    post::testing::PostTestData as _, provider::MockClock, query::PaginationInput,
+};
+
+#[tokio::test]
+async fn test_request() {
+    let (data, _) = TestData::with_user().await;
+
+    let export = data.export().request(false).await.unwrap();
+    assert_eq!(export.status, ExportStatus::Pending);
+    assert!(!export.activity_pub);
+
+    // Asking again while one is waiting gives back the same export.
+    let again = data.export().request(true).await.unwrap();
+    assert_eq!(again, export);
+    assert_eq!(data.export().list().await.unwrap(), vec![export]);
+}
+
+#[tokio::test]
+async fn test_request_anon() {
+    let data = TestData::new().await;
+    assert_eq!(
+        data.export().request(false).await.unwrap_err(),
+        Error::Unauthenticated
+    );
+}
+
+#[tokio::test]
+async fn test_build() {
+    let (mut data, acc) = TestData::with_user().await;
+    let post = data.generate_post().await;
+    let export = data.export().request(true).await.unwrap();
+    let id = export.id.id.to_raw();
+
+    // Other accounts can't see or download the export.
+    let other = data.account().create_test_user().await;
+    data.login_as(&other);
+    assert_eq!(data.export().get(&id).await, Ok(None));
+    data.login_as(&acc);
+
+    assert_eq!(data.export().download(&id).await, Ok(None));
+    assert_eq!(
+        build_pending_exports(&data.persist, Some("https://example.com")).await,
+        Ok(1)
+    );
+    assert_eq!(build_pending_exports(&data.persist, None).await, Ok(0));
+
+    let export = data.export().get(&id).await.unwrap().unwrap();
+    assert_eq!(export.status, ExportStatus::Ready);
+    assert!(export.expires_at.is_some());
+
+    let (_, archive) = data.export().download(&id).await.unwrap().unwrap();
+    assert_eq!(Some(archive.len() as u64), export.size);
+    let archive: serde_json::Value = serde_json::from_slice(&archive).unwrap();
+    assert_eq!(archive["account"]["user_id"], acc.user_id.as_str());
+    assert!(archive["account"].get("pword_hash").is_none());
+    assert_eq!(archive["posts"][0]["id"], post.id.to_string().as_str());
+    assert_eq!(
+        archive["activity_pub"]["outbox"]["orderedItems"][0]["object"]["id"],
+        format!("https://example.com/api/v1/posts/{}", post.id.id.to_raw()).as_str()
+    );
+
+    let notifications = data
+        .notification()
+        .list()
+        .unwrap()
+        .with_pagination(PaginationInput::new().forward(10))
+        .execute()
+        .await
+        .unwrap()
+        .edges;
+    assert_eq!(notifications.len(), 1);
+    assert_eq!(
+        notifications[0].node.kind,
+        NotificationKind::DataExportReady
+    );
+}
+
+#[tokio::test]
+async fn test_prune() {
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap());
+    let mut data = TestData::with_clock(clock.clone()).await;
+    let acc = data.account().create_test_user().await;
+    data.login_as(&acc);
+    let export = data.export().request(false).await.unwrap();
+    let id = export.id.id.to_raw();
+    build_pending_exports(&data.persist, None).await.unwrap();
+    let key = data
+        .export()
+        .get(&id)
+        .await
+        .unwrap()
+        .unwrap()
+        .blob_key
+        .unwrap();
+
+    clock.advance(Duration::days(EXPORT_EXPIRY_DAYS - 1));
+    assert_eq!(prune_expired_exports(&data.persist).await, Ok(0));
+    clock.advance(Duration::days(1));
+    assert_eq!(prune_expired_exports(&data.persist).await, Ok(1));
+
+    // The session has expired by now, so look for the export directly.
+    let export: Option<DataExport> = data
+        .persist
+        .db()
+        .select((EXPORT_TABLE_NAME, id.as_str()))
+        .await
+        .unwrap();
+    assert!(export.is_none());
+    assert_eq!(data.persist.blobs().contains(&key).await, Ok(false));
+}
@@ -0,0 +1,42 @@
+use async_graphql::{Context, Object, ID};
+use tracing::instrument;
+
+use super::DataExport;
+use crate::prelude::*;
+
+#[derive(Default)]
+pub struct ExportQuery;
+
+#[Object]
+impl ExportQuery {
+    /// Gets one of the current account's data exports.
+    #[instrument(skip_all)]
+    async fn data_export(&self, ctx: &Context<'_>, id: ID) -> GqlResult<Option<DataExport>> {
+        ctx.export_persist().get(&id).await.extend()
+    }
+
+    /// Lists the current account's data exports, newest first.
+    #[instrument(skip_all)]
+    async fn data_exports(&self, ctx: &Context<'_>) -> GqlResult<Vec<DataExport>> {
+        ctx.export_persist().list().await.extend()
+    }
+}
+
+#[derive(Default)]
+pub struct ExportMutation;
+
+#[Object]
+impl ExportMutation {
+    /// Asks for an export of everything the current account has stored on
+    /// the instance. It's built in the background, and the account is
+    /// notified when it can be downloaded. If an export is already waiting to
+    /// be built, that's returned instead.
+    #[instrument(skip_all)]
+    async fn export_account_data(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] activity_pub: bool,
+    ) -> GqlResult<DataExport> {
+        ctx.export_persist().request(activity_pub).await.extend()
+    }
+}
@@ -0,0 +1,196 @@
+//! Telling subscribers about events as they happen.
+//!
+//! Events are carried by a [`FeedBroker`]. The [`LocalBroker`] only reaches
+//! subscribers connected to the same server, which is all of them while the
+//! service runs as a single instance. Running several would need a broker
+//! that carries events between them, such as a message bus, which feeds can
+//! be given with [`Feed::with_broker`].
+//!
+//! Events are published by hand rather than coming from `SurrealDB` `LIVE
+//! SELECT` queries, as the version of the SDK in use can't receive their
+//! notifications: `Surreal::live` is hidden and unsupported, only giving back
+//! the query's ID, and neither the embedded nor the remote engine hands
+//! notifications on. Once it does, a broker can be built on them that
+//! subscribes to the table a feed covers and publishes each notification,
+//! keeping the filtering and backpressure that feeds already have.
+
+use std::sync::{Arc, Mutex, PoisonError};
+
+use futures::{stream::BoxStream, Stream, StreamExt as _};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tracing::warn;
+
+/// How many events can be waiting for a slow subscriber before it starts
+/// missing them.
+const FEED_CAPACITY: usize = 256;
+
+pub type Filter<T> = Box<dyn Fn(&T) -> bool + Send + Sync>;
+
+/// Carries a feed's events to its subscribers.
+pub trait FeedBroker<T>: Send + Sync {
+    /// Sends an event to the subscribers whose filter it passes.
+    fn publish(&self, event: &T);
+
+    /// Streams the events that pass the filter, from now on.
+    fn subscribe(&self, filter: Filter<T>) -> BoxStream<'static, T>;
+}
+
+struct Subscriber<T> {
+    filter: Filter<T>,
+    sender: mpsc::Sender<T>,
+}
+
+/// Tells subscribers about events as they happen.
+///
+/// Each subscriber gives a filter when it subscribes, so that it's only sent
+/// the events that it wants rather than every event to throw most of them
+/// away.
+pub struct Feed<T> {
+    broker: Arc<dyn FeedBroker<T>>,
+}
+
+impl<T: Clone + Send + 'static> Feed<T> {
+    /// A feed that only reaches subscribers connected to this server.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_broker(Arc::new(LocalBroker::new()))
+    }
+
+    #[must_use]
+    pub fn with_broker(broker: Arc<dyn FeedBroker<T>>) -> Self {
+        Self { broker }
+    }
+
+    pub fn publish(&self, event: &T) {
+        self.broker.publish(event);
+    }
+
+    /// Streams the events that pass the filter, from now on.
+    pub fn subscribe(
+        &self,
+        filter: impl Fn(&T) -> bool + Send + Sync + 'static,
+    ) -> impl Stream<Item = T> + Send + 'static {
+        self.broker.subscribe(Box::new(filter))
+    }
+}
+
+impl<T> Clone for Feed<T> {
+    fn clone(&self) -> Self {
+        Self {
+            broker: self.broker.clone(),
+        }
+    }
+}
+
+impl<T: Clone + Send + 'static> Default for Feed<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Fans events out to subscribers connected to this server, checking each
+/// subscriber's filter as it goes.
+pub struct LocalBroker<T> {
+    subscribers: Mutex<Vec<Subscriber<T>>>,
+}
+
+impl<T> LocalBroker<T> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            subscribers: Mutex::default(),
+        }
+    }
+
+    /// How many subscribers are connected, including ones that have gone
+    /// away since the last event.
+    #[cfg(test)]
+    fn subscriber_count(&self) -> usize {
+        self.subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+}
+
+impl<T> Default for LocalBroker<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone + Send + 'static> FeedBroker<T> for LocalBroker<T> {
+    fn publish(&self, event: &T) {
+        let mut subscribers = self
+            .subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        // Subscribers that have gone away are dropped along the way, and
+        // nobody listening isn't an error.
+        subscribers.retain(|subscriber| {
+            if !(subscriber.filter)(event) {
+                return !subscriber.sender.is_closed();
+            }
+            match subscriber.sender.try_send(event.clone()) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    warn!("Feed subscriber fell behind");
+                    true
+                }
+                Err(TrySendError::Closed(_)) => false,
+            }
+        });
+    }
+
+    fn subscribe(&self, filter: Filter<T>) -> BoxStream<'static, T> {
+        let (sender, mut receiver) = mpsc::channel(FEED_CAPACITY);
+        self.subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Subscriber { filter, sender });
+
+        async_stream::stream! {
+            while let Some(event) = receiver.recv().await {
+                yield event;
+            }
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt as _;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_filtered_fan_out() {
+        let feed = Feed::new();
+        let evens = feed.subscribe(|n: &u32| n.is_multiple_of(2));
+        let all = feed.subscribe(|_| true);
+        for n in 1..=4 {
+            feed.publish(&n);
+        }
+
+        assert_eq!(evens.take(2).collect::<Vec<_>>().await, [2, 4]);
+        assert_eq!(all.take(4).collect::<Vec<_>>().await, [1, 2, 3, 4]);
+    }
+
+    #[tokio::test]
+    async fn test_drops_closed_subscribers() {
+        let broker = Arc::new(LocalBroker::new());
+        let feed = Feed::with_broker(broker.clone());
+        let kept = feed.subscribe(|_: &u32| true);
+        drop(feed.subscribe(|_| false));
+        drop(feed.subscribe(|_| true));
+        assert_eq!(broker.subscriber_count(), 3);
+
+        feed.publish(&1);
+        assert_eq!(broker.subscriber_count(), 1);
+        drop(kept);
+        feed.publish(&2);
+        assert_eq!(broker.subscriber_count(), 0);
+    }
+}
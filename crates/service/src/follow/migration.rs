@@ -0,0 +1,37 @@
+use serde::{Deserialize, Serialize};
+
+use super::FOLLOWS_TABLE_NAME;
+use crate::{migration::Migration, prelude::*};
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FollowMigration {
+    #[default]
+    Init,
+}
+
+impl Migration for FollowMigration {
+    const SUBSYSTEM: &'static str = "subsys_follow";
+
+    fn next(self) -> Option<Self> {
+        match self {
+            Self::Init => None,
+        }
+    }
+
+    fn build(&self, statements: &mut Vec<srql::Statement>) {
+        use FollowMigration as S;
+        match self {
+            S::Init => Self::build_init(statements),
+        }
+    }
+}
+
+impl FollowMigration {
+    fn build_init(statements: &mut Vec<srql::Statement>) {
+        statements.push(srql::define_uniq_index(
+            "follows_in_out_index",
+            FOLLOWS_TABLE_NAME,
+            [srql::field("in"), srql::field("out")],
+        ));
+    }
+}
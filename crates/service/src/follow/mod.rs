@@ -0,0 +1,9 @@
+mod migration;
+mod persist;
+mod schema;
+
+pub use migration::*;
+pub use persist::*;
+pub use schema::*;
+
+pub static FOLLOWS_TABLE_NAME: &str = "follows";
@@ -0,0 +1,257 @@
+#[cfg(test)]
+mod tests;
+
+use serde::de::IgnoredAny;
+use tracing::instrument;
+
+use super::FOLLOWS_TABLE_NAME;
+use crate::{
+    account::{Account, CurrentAccount, ACC_TABLE_NAME},
+    event::{DomainEvent, DomainEventKind},
+    list::LIST_TABLE_NAME,
+    persist::Persist,
+    prelude::*,
+    webhook::{send_webhooks, WebhookEvent},
+};
+
+pub struct FollowPersist<'a> {
+    persist: &'a Persist,
+    current: &'a CurrentAccount,
+}
+
+impl<'a> FollowPersist<'a> {
+    pub fn new(persist: &'a Persist, current: &'a CurrentAccount) -> Self {
+        Self { persist, current }
+    }
+
+    #[instrument(skip_all)]
+    pub async fn is_following(&self, id: &str) -> Result<bool> {
+        let from = self.current.id()?.to_account_thing();
+        let edges: Vec<IgnoredAny> = self
+            .persist
+            .db()
+            .query(srql::SelectStatement {
+                expr: srql::Fields::all(),
+                what: srql::table(FOLLOWS_TABLE_NAME),
+                cond: edge_cond(from, id.to_account_thing()).into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        Ok(!edges.is_empty())
+    }
+
+    /// Lists the IDs of every account that the current account follows.
+    #[instrument(skip_all)]
+    pub async fn following(&self) -> Result<Vec<srql::Thing>> {
+        let from = self.current.id()?.to_account_thing();
+        let following = self
+            .persist
+            .db()
+            .query(srql::SelectStatement {
+                expr: srql::Fields(
+                    vec![srql::Field::Single {
+                        expr: srql::field("out").into(),
+                        alias: None,
+                    }],
+                    true,
+                ),
+                what: srql::table(FOLLOWS_TABLE_NAME),
+                cond: srql::Cond(
+                    srql::Expression::Binary {
+                        l: srql::field("in").into(),
+                        o: srql::Operator::Equal,
+                        r: from.into(),
+                    }
+                    .into(),
+                )
+                .into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        Ok(following)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn follow(&self, id: &str) -> Result<bool> {
+        let from = self.current.id()?.to_account_thing();
+        let to = id.to_account_thing();
+        if from == to {
+            return Ok(false);
+        }
+
+        let target: Option<Account> = self
+            .persist
+            .load(srql::Thing::from((ACC_TABLE_NAME, id)))
+            .await?;
+        if target.is_none() {
+            return Ok(false);
+        }
+
+        let res: Result<Vec<IgnoredAny>> = self
+            .persist
+            .db()
+            .query(srql::query([
+                srql::trans_begin(),
+                srql::Statement::Relate(srql::RelateStatement {
+                    kind: srql::Table(FOLLOWS_TABLE_NAME.to_owned()).into(),
+                    from: from.clone().into(),
+                    with: to.clone().into(),
+                    output: srql::Output::After.into(),
+                    ..Default::default()
+                }),
+                srql::Statement::Create(DomainEvent::create(
+                    DomainEventKind::FollowAdded,
+                    Some(from.clone()),
+                    to.clone(),
+                    vec![],
+                    self.persist.clock(),
+                    self.persist.ids(),
+                )),
+                srql::trans_end(),
+            ]))
+            .await
+            .and_then(|mut r| r.take(0))
+            .map_err(Into::into);
+
+        match res {
+            Ok(edges) if edges.is_empty() => Ok(false),
+            Ok(_) => {
+                send_webhooks(
+                    self.persist,
+                    WebhookEvent::FollowerGained,
+                    &to,
+                    Some(&from),
+                    None,
+                )
+                .await;
+                Ok(true)
+            }
+            // The unique index on the edge means that following twice is a
+            // no-op rather than an error.
+            Err(Error::UnavailableIdent) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    #[instrument(skip_all)]
+    pub async fn unfollow(&self, id: &str) -> Result<bool> {
+        let from = self.current.id()?.to_account_thing();
+        let to = id.to_account_thing();
+
+        let edges: Vec<IgnoredAny> = self
+            .persist
+            .db()
+            .query(srql::query([
+                srql::trans_begin(),
+                // Only record the unfollow if there was a follow to remove.
+                srql::Statement::Ifelse(srql::IfelseStatement {
+                    exprs: vec![(
+                        srql::Value::Subquery(Box::new(srql::Subquery::Select(
+                            srql::SelectStatement {
+                                expr: srql::Fields::all(),
+                                what: srql::table(FOLLOWS_TABLE_NAME),
+                                cond: edge_cond(from.clone(), to.clone()).into(),
+                                ..Default::default()
+                            },
+                        ))),
+                        srql::Value::Subquery(Box::new(srql::Subquery::Create(
+                            DomainEvent::create(
+                                DomainEventKind::FollowRemoved,
+                                Some(from.clone()),
+                                to.clone(),
+                                vec![],
+                                self.persist.clock(),
+                                self.persist.ids(),
+                            ),
+                        ))),
+                    )],
+                    close: None,
+                }),
+                srql::Statement::Delete(srql::DeleteStatement {
+                    what: srql::table(FOLLOWS_TABLE_NAME),
+                    cond: edge_cond(from.clone(), to.clone()).into(),
+                    output: srql::Output::Before.into(),
+                    ..Default::default()
+                }),
+                // Lists can only contain followed accounts, so clean up
+                // any that this account was in.
+                srql::Statement::Update(srql::UpdateStatement {
+                    what: srql::table(LIST_TABLE_NAME),
+                    data: srql::Data::SetExpression(vec![(
+                        srql::field("member_ids"),
+                        srql::Operator::Dec,
+                        to.into(),
+                    )])
+                    .into(),
+                    cond: srql::Cond(
+                        srql::Expression::Binary {
+                            l: srql::field("owner_id").into(),
+                            o: srql::Operator::Equal,
+                            r: from.into(),
+                        }
+                        .into(),
+                    )
+                    .into(),
+                    output: srql::Output::None.into(),
+                    ..Default::default()
+                }),
+                srql::trans_end(),
+            ]))
+            .await?
+            .take(1)?;
+        Ok(!edges.is_empty())
+    }
+}
+
+fn edge_cond(from: srql::Thing, to: srql::Thing) -> srql::Cond {
+    srql::Cond(
+        srql::Expression::Binary {
+            l: srql::Expression::Binary {
+                l: srql::field("in").into(),
+                o: srql::Operator::Equal,
+                r: from.into(),
+            }
+            .into(),
+            o: srql::Operator::And,
+            r: srql::Expression::Binary {
+                l: srql::field("out").into(),
+                o: srql::Operator::Equal,
+                r: to.into(),
+            }
+            .into(),
+        }
+        .into(),
+    )
+}
+
+#[cfg(test)]
+pub mod testing {
+    use async_trait::async_trait;
+
+    use crate::account::testing::{AccData, TestData};
+
+    use super::FollowPersist;
+
+    #[async_trait]
+    pub trait FollowTestData {
+        fn follow(&self) -> FollowPersist<'_>;
+
+        /// Creates a new account that the current account follows.
+        async fn generate_followed(&self) -> AccData;
+    }
+
+    #[async_trait]
+    impl FollowTestData for TestData {
+        fn follow(&self) -> FollowPersist<'_> {
+            FollowPersist::new(&self.persist, &self.current)
+        }
+
+        async fn generate_followed(&self) -> AccData {
+            let acc = self.account().create_test_user().await;
+            assert!(self.follow().follow(&acc.id.id.to_raw()).await.unwrap());
+            acc
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use super::{testing::FollowTestData as _, *};
+use crate::{account::testing::*, list::testing::ListTestData as _};
+
+#[tokio::test]
+async fn test_follow() {
+    let (data, _) = TestData::with_user().await;
+    let follow_persist = data.follow();
+    let other = data.account().create_test_user().await;
+    let other_id = other.id.id.to_raw();
+
+    let res = follow_persist.is_following(&other_id).await;
+    println!("{res:?}");
+    assert_eq!(res, Ok(false));
+
+    let res = follow_persist.follow(&other_id).await;
+    println!("{res:?}");
+    assert_eq!(res, Ok(true));
+
+    let res = follow_persist.is_following(&other_id).await;
+    println!("{res:?}");
+    assert_eq!(res, Ok(true));
+
+    let res = follow_persist.following().await;
+    println!("{res:?}");
+    assert_eq!(res, Ok(vec![other.id]));
+}
+
+#[tokio::test]
+async fn test_follow_twice() {
+    let (data, _) = TestData::with_user().await;
+    let other = data.generate_followed().await;
+
+    let res = data.follow().follow(&other.id.id.to_raw()).await;
+    println!("{res:?}");
+    assert_eq!(res, Ok(false));
+
+    let res = data.follow().following().await;
+    println!("{res:?}");
+    assert_eq!(res.map(|f| f.len()), Ok(1));
+}
+
+#[tokio::test]
+async fn test_follow_self() {
+    let (data, acc) = TestData::with_user().await;
+
+    let res = data.follow().follow(&acc.id.id.to_raw()).await;
+    println!("{res:?}");
+    assert_eq!(res, Ok(false));
+}
+
+#[tokio::test]
+async fn test_follow_missing() {
+    let (data, _) = TestData::with_user().await;
+
+    let res = data.follow().follow("missing").await;
+    println!("{res:?}");
+    assert_eq!(res, Ok(false));
+}
+
+#[tokio::test]
+async fn test_follow_anon() {
+    let data = TestData::new().await;
+    let other = data.account().create_test_user().await;
+
+    let res = data.follow().follow(&other.id.id.to_raw()).await;
+    println!("{res:?}");
+    assert_eq!(res, Err(Error::Unauthenticated));
+}
+
+#[tokio::test]
+async fn test_unfollow() {
+    let (data, _) = TestData::with_user().await;
+    let other = data.generate_followed().await;
+    let other_id = other.id.id.to_raw();
+
+    let res = data.follow().unfollow(&other_id).await;
+    println!("{res:?}");
+    assert_eq!(res, Ok(true));
+
+    let res = data.follow().is_following(&other_id).await;
+    println!("{res:?}");
+    assert_eq!(res, Ok(false));
+
+    let res = data.follow().unfollow(&other_id).await;
+    println!("{res:?}");
+    assert_eq!(res, Ok(false));
+}
+
+#[tokio::test]
+async fn test_unfollow_removes_from_lists() {
+    let (mut data, acc) = TestData::with_user().await;
+    let other = data.generate_followed().await;
+    let other_id = other.id.id.to_raw();
+    let list = data.generate_list().await;
+    let list_id = list.id.id.to_raw();
+
+    data.list().add_member(&list_id, &other_id).await.unwrap();
+
+    // Lists owned by other accounts should be left alone.
+    let third = data.account().create_test_user().await;
+    data.login_as(&third);
+    data.follow().follow(&other_id).await.unwrap();
+    let third_list = data.generate_list().await;
+    data.list()
+        .add_member(&third_list.id.id.to_raw(), &other_id)
+        .await
+        .unwrap();
+    data.login_as(&acc);
+
+    let res = data.follow().unfollow(&other_id).await;
+    println!("{res:?}");
+    assert_eq!(res, Ok(true));
+
+    let res = data.list().get(&list_id).await.unwrap().unwrap();
+    println!("{res:?}");
+    assert!(res.member_ids.is_empty());
+
+    let res = data
+        .list()
+        .get(&third_list.id.id.to_raw())
+        .await
+        .unwrap()
+        .unwrap();
+    println!("{res:?}");
+    assert_eq!(res.member_ids, vec![other.id]);
+}
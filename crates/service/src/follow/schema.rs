@@ -0,0 +1,39 @@
+use async_graphql::{Context, Object, ID};
+use tracing::instrument;
+
+use crate::{policy::PoliciesAccepted, prelude::*};
+
+#[derive(Default)]
+pub struct FollowQuery;
+
+#[Object]
+impl FollowQuery {
+    /// Whether the current account follows the given account.
+    #[instrument(skip_all)]
+    async fn is_following(&self, ctx: &Context<'_>, id: ID) -> GqlResult<bool> {
+        ctx.follow_persist().is_following(&id).await.extend()
+    }
+}
+
+#[derive(Default)]
+pub struct FollowMutation;
+
+#[Object(guard = "PoliciesAccepted")]
+impl FollowMutation {
+    /// Follows an account.
+    ///
+    /// Returns `false` if the account was already followed or does not exist.
+    #[instrument(skip_all)]
+    async fn follow_account(&self, ctx: &Context<'_>, id: ID) -> GqlResult<bool> {
+        ctx.follow_persist().follow(&id).await.extend()
+    }
+
+    /// Unfollows an account. The account is also removed from any of the
+    /// current account's lists.
+    ///
+    /// Returns `false` if the account was not followed.
+    #[instrument(skip_all)]
+    async fn unfollow_account(&self, ctx: &Context<'_>, id: ID) -> GqlResult<bool> {
+        ctx.follow_persist().unfollow(&id).await.extend()
+    }
+}
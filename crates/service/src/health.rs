@@ -0,0 +1,242 @@
+//! Liveness and readiness probes, for orchestrators like Kubernetes.
+//!
+//! `/healthz` answers as soon as the server can answer anything, so that a
+//! server that's busy isn't restarted. `/readyz` checks what serving
+//! requests depends on, so that traffic is only sent to a server that can
+//! handle it. Neither is rate limited or shed when the server is
+//! overloaded, as a probe that fails because of load would take the server
+//! out of rotation when it's needed most.
+
+use std::time::{Duration, Instant};
+
+use axum::{extract::State, http::StatusCode, routing::get, Json, Router};
+use jsonwebtoken::{Algorithm, Header, Validation};
+use serde::{Deserialize, Serialize};
+use tokio::time::timeout;
+use tracing::{error, instrument, warn};
+
+use crate::{
+    doctor::{db_now, CheckStatus},
+    migration::Migrations,
+    persist::Persist,
+    DecodingKey, EncodingKey,
+};
+
+/// How long a check can take before it's failed. Probes are usually given a
+/// second or so to answer, so this keeps a slow database from making the
+/// probe itself time out without saying why.
+const PROBE_TIMEOUT: Duration = Duration::from_millis(800);
+
+/// Everything the readiness checks need.
+#[derive(Clone)]
+pub struct HealthState {
+    pub persist: Persist,
+    pub jwt_enc_key: EncodingKey,
+    pub jwt_dec_key: DecodingKey,
+}
+
+pub fn router(state: HealthState) -> Router {
+    Router::new()
+        .route("/healthz", get(live))
+        .route("/readyz", get(ready))
+        .with_state(state)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Liveness {
+    pub status: CheckStatus,
+}
+
+/// Whether the server can handle requests, and what each check found.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Readiness {
+    /// Failed if any check failed.
+    pub status: CheckStatus,
+    pub checks: Vec<Probe>,
+}
+
+/// The result of one readiness check.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Probe {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub latency_ms: u64,
+    pub detail: String,
+}
+
+impl Readiness {
+    /// Runs every check, one after the other so that the latency of each is
+    /// its own.
+    pub async fn check(state: &HealthState) -> Self {
+        let checks = vec![
+            probe("database", check_database(&state.persist)).await,
+            probe("jwt_keys", async { check_jwt_keys(state) }).await,
+            probe("migrations", check_migrations(&state.persist)).await,
+        ];
+        let status = if checks
+            .iter()
+            .all(|check| check.status != CheckStatus::Failed)
+        {
+            CheckStatus::Ok
+        } else {
+            CheckStatus::Failed
+        };
+        Self { status, checks }
+    }
+
+    #[must_use]
+    pub fn is_ready(&self) -> bool {
+        self.status != CheckStatus::Failed
+    }
+}
+
+async fn live() -> Json<Liveness> {
+    Json(Liveness {
+        status: CheckStatus::Ok,
+    })
+}
+
+#[instrument(skip_all)]
+async fn ready(State(state): State<HealthState>) -> (StatusCode, Json<Readiness>) {
+    let readiness = Readiness::check(&state).await;
+    if readiness.is_ready() {
+        (StatusCode::OK, Json(readiness))
+    } else {
+        warn!(?readiness, "Not ready");
+        (StatusCode::SERVICE_UNAVAILABLE, Json(readiness))
+    }
+}
+
+/// Runs a check, failing it if it takes too long, and times it.
+async fn probe(
+    name: &'static str,
+    check: impl std::future::Future<Output = Result<String, String>>,
+) -> Probe {
+    let start = Instant::now();
+    let res = timeout(PROBE_TIMEOUT, check).await;
+    let latency_ms = u64::try_from(start.elapsed().as_millis()).unwrap_or(u64::MAX);
+    let (status, detail) = match res {
+        Ok(Ok(detail)) => (CheckStatus::Ok, detail),
+        Ok(Err(detail)) => (CheckStatus::Failed, detail),
+        Err(_) => (
+            CheckStatus::Failed,
+            format!("Timed out after {}ms", PROBE_TIMEOUT.as_millis()),
+        ),
+    };
+    Probe {
+        name,
+        status,
+        latency_ms,
+        detail,
+    }
+}
+
+/// Errors from the database can say more about it than probes should see,
+/// so they're only logged.
+async fn check_database(persist: &Persist) -> Result<String, String> {
+    match db_now(persist).await {
+        Ok(Some(_)) => Ok("Reachable".into()),
+        Ok(None) => Err("The database didn't answer the query".into()),
+        Err(err) => {
+            error!(error = ?err, "Readiness check failed to query the database");
+            Err("Database unavailable".into())
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ProbeClaims {
+    sub: String,
+}
+
+/// Signs a token and verifies it again, which catches a key pair that
+/// doesn't match as well as one that's missing.
+fn check_jwt_keys(state: &HealthState) -> Result<String, String> {
+    let claims = ProbeClaims {
+        sub: "readyz".into(),
+    };
+    let token = jsonwebtoken::encode(&Header::new(Algorithm::EdDSA), &claims, &state.jwt_enc_key)
+        .map_err(|err| format!("Unable to sign tokens: {err}"))?;
+    let mut validation = Validation::new(Algorithm::EdDSA);
+    validation.required_spec_claims.clear();
+    validation.validate_exp = false;
+    jsonwebtoken::decode::<ProbeClaims>(&token, &state.jwt_dec_key, &validation)
+        .map_err(|err| format!("Unable to verify signed tokens: {err}"))?;
+    Ok("Tokens can be signed and verified".into())
+}
+
+async fn check_migrations(persist: &Persist) -> Result<String, String> {
+    match Migrations::pending(persist).await {
+        Ok(pending) if pending.is_empty() => Ok("Up to date".into()),
+        Ok(pending) => Err(format!(
+            "{} subsystems need migrating: {}",
+            pending.len(),
+            pending.join(", ")
+        )),
+        Err(err) => {
+            error!(error = ?err, "Readiness check failed to read migrations");
+            Err("Unable to read migrations, which may be from a newer version".into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{account::testing::generate_keys, config::DbConfig, persist::testing::persist};
+
+    async fn state() -> HealthState {
+        let (jwt_enc_key, jwt_dec_key) = generate_keys();
+        HealthState {
+            persist: persist().await,
+            jwt_enc_key: Arc::new(jwt_enc_key),
+            jwt_dec_key: Arc::new(jwt_dec_key),
+        }
+    }
+
+    fn status(readiness: &Readiness, name: &str) -> CheckStatus {
+        readiness
+            .checks
+            .iter()
+            .find(|check| check.name == name)
+            .unwrap()
+            .status
+    }
+
+    #[tokio::test]
+    async fn test_ready() {
+        let readiness = Readiness::check(&state().await).await;
+        assert!(readiness.is_ready(), "{readiness:#?}");
+        for name in ["database", "jwt_keys", "migrations"] {
+            assert_eq!(status(&readiness, name), CheckStatus::Ok);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mismatched_keys() {
+        let mut state = state().await;
+        state.jwt_dec_key = Arc::new(generate_keys().1);
+        let readiness = Readiness::check(&state).await;
+        assert!(!readiness.is_ready());
+        assert_eq!(readiness.status, CheckStatus::Failed);
+        assert_eq!(status(&readiness, "jwt_keys"), CheckStatus::Failed);
+        assert_eq!(status(&readiness, "database"), CheckStatus::Ok);
+    }
+
+    #[tokio::test]
+    async fn test_unmigrated() {
+        let (jwt_enc_key, jwt_dec_key) = generate_keys();
+        let state = HealthState {
+            persist: Persist::new("memory", "test", "test", &DbConfig::default())
+                .await
+                .unwrap(),
+            jwt_enc_key: Arc::new(jwt_enc_key),
+            jwt_dec_key: Arc::new(jwt_dec_key),
+        };
+        let readiness = Readiness::check(&state).await;
+        assert!(!readiness.is_ready());
+        assert_eq!(status(&readiness, "migrations"), CheckStatus::Failed);
+    }
+}
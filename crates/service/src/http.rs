@@ -0,0 +1,111 @@
+use std::{
+    future::Future,
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    task::{Context, Poll},
+    vec,
+};
+
+use hyper::{
+    client::{connect::dns::Name, HttpConnector},
+    service::Service,
+    Client,
+};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use rustls::{ClientConfig, OwnedTrustAnchor, RootCertStore};
+
+use crate::proxy::is_public;
+
+/// A client for making outgoing requests to other servers.
+pub type HttpClient = Client<HttpsConnector<HttpConnector>>;
+
+/// A client that only connects to public addresses.
+pub type PublicHttpClient = Client<HttpsConnector<HttpConnector<PublicResolver>>>;
+
+/// Builds a client that can make both HTTP and HTTPS requests, trusting the
+/// Mozilla root certificates.
+pub fn http_client() -> HttpClient {
+    client_with(HttpConnector::new())
+}
+
+/// Builds a client like [`http_client`], but that refuses to connect to
+/// anything but public addresses, for requests to URLs that accounts give.
+///
+/// The addresses a host resolves to are checked as the connection is made,
+/// so a host can't resolve to a public address when it's checked beforehand
+/// and a private one when it's connected to.
+pub fn public_http_client() -> PublicHttpClient {
+    client_with(HttpConnector::new_with_resolver(PublicResolver))
+}
+
+fn client_with<R>(mut http: HttpConnector<R>) -> Client<HttpsConnector<HttpConnector<R>>>
+where
+    HttpsConnector<HttpConnector<R>>: hyper::client::connect::Connect + Clone,
+{
+    let mut roots = RootCertStore::empty();
+    roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|anchor| {
+        OwnedTrustAnchor::from_subject_spki_name_constraints(
+            anchor.subject,
+            anchor.spki,
+            anchor.name_constraints,
+        )
+    }));
+    let tls = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    http.enforce_http(false);
+    let connector = HttpsConnectorBuilder::new()
+        .with_tls_config(tls)
+        .https_or_http()
+        .enable_http1()
+        .wrap_connector(http);
+
+    Client::builder().build(connector)
+}
+
+/// Resolves hosts, keeping only the addresses that are public. Hosts with no
+/// public addresses fail to resolve.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PublicResolver;
+
+impl Service<Name> for PublicResolver {
+    type Response = vec::IntoIter<SocketAddr>;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = io::Result<Self::Response>> + Send>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        Box::pin(async move {
+            let addrs: Vec<_> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| is_public(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!("{name} has no public addresses"),
+                ));
+            }
+            Ok(addrs.into_iter())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_public_resolver() {
+        let err = PublicResolver
+            .call("localhost".parse().unwrap())
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    }
+}
@@ -0,0 +1,5 @@
+mod models;
+mod schema;
+
+pub use models::*;
+pub use schema::*;
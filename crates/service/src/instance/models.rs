@@ -0,0 +1,142 @@
+use async_graphql::{ComplexObject, Context, SimpleObject};
+use serde::Serialize;
+
+use crate::{
+    client_version::MinClientVersion,
+    complexity::SCAN_COMPLEXITY,
+    config::{AltTextPolicy, LimitsConfig, OidcConfig, PostVisibility},
+    persist::Persist,
+    prelude::*,
+    proxy::ProxyUrls,
+    stats::PublicStats,
+};
+
+/// Information about this instance.
+#[derive(SimpleObject, Debug, Clone)]
+#[graphql(complex)]
+pub struct InstanceInfo {
+    /// The version of the server that the instance is running.
+    pub version: String,
+
+    #[graphql(skip)]
+    pub public_stats: bool,
+}
+
+#[ComplexObject]
+impl InstanceInfo {
+    /// Coarse statistics about the instance, if it has chosen to share them.
+    #[graphql(complexity = "SCAN_COMPLEXITY + child_complexity")]
+    async fn stats(&self, ctx: &Context<'_>) -> GqlResult<Option<PublicStats>> {
+        if !self.public_stats {
+            return Ok(None);
+        }
+        ctx.stats_persist().public().await.map(Some).extend()
+    }
+
+    /// Whether the instance is read-only for now, turning changes away with a
+    /// `ReadOnly` error. It still answers queries while it is.
+    async fn read_only(&self, ctx: &Context<'_>) -> bool {
+        ctx.data_unchecked::<Persist>().read_only().is_read_only()
+    }
+
+    /// The oldest version of each app that can still be used. Older versions
+    /// are turned away with a `ClientOutdated` error, so apps can use this to
+    /// ask to be updated before that happens.
+    async fn min_client_versions(&self, ctx: &Context<'_>) -> Vec<MinClientVersion> {
+        ctx.data_unchecked::<Persist>().client_versions().list()
+    }
+
+    /// The most characters each piece of content can have, so that it can be
+    /// checked before it is sent.
+    async fn limits(&self, ctx: &Context<'_>) -> ContentLimits {
+        ctx.data_unchecked::<Persist>().limits().into()
+    }
+
+    /// Whether images attached to posts need alt text. When it's `NAG`,
+    /// clients should remind authors to add it before posting, and when it's
+    /// `REQUIRE`, posts without it are rejected with an `AltTextMissing`
+    /// error naming the attachment.
+    async fn alt_text_policy(&self, ctx: &Context<'_>) -> AltTextPolicy {
+        ctx.data_unchecked::<Persist>().alt_text_policy()
+    }
+
+    /// The visibility that new posts get when they don't ask for one, so
+    /// that clients can preselect it.
+    async fn default_post_visibility(&self, ctx: &Context<'_>) -> PostVisibility {
+        ctx.data_unchecked::<Persist>().post_visibility().default
+    }
+
+    /// The visibilities that new posts can have, from the most open. Posts
+    /// asking for any other are rejected with a `VisibilityDisallowed` error.
+    async fn allowed_post_visibilities(&self, ctx: &Context<'_>) -> Vec<PostVisibility> {
+        ctx.data_unchecked::<Persist>().post_visibility().allowed()
+    }
+
+    /// Whether posts can be translated with `translatePost`.
+    async fn translation_available(&self, ctx: &Context<'_>) -> bool {
+        ctx.data_unchecked::<Persist>()
+            .translation()
+            .provider
+            .is_some()
+    }
+
+    /// Whether outbound links and remote media should be sent through the
+    /// instance, using the URLs from `proxiedLinkUrl` and `proxiedMediaUrl`.
+    async fn remote_content_proxied(&self, ctx: &Context<'_>) -> bool {
+        ctx.data_unchecked::<ProxyUrls>().enabled()
+    }
+
+    /// The identity providers that accounts can log in with, using
+    /// `beginExternalLogin`.
+    async fn external_login_providers(&self, ctx: &Context<'_>) -> Vec<ExternalLoginProvider> {
+        ctx.data_opt::<OidcConfig>()
+            .map(|config| {
+                config
+                    .providers
+                    .iter()
+                    .map(|provider| ExternalLoginProvider {
+                        id: provider.id.clone(),
+                        name: provider.name.clone(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// An identity provider that accounts can log in with.
+#[derive(SimpleObject, Debug, Clone, PartialEq, Eq)]
+pub struct ExternalLoginProvider {
+    /// The ID to give to `beginExternalLogin`.
+    pub id: String,
+    /// The name to show for the provider.
+    pub name: String,
+}
+
+/// How large each piece of content can be on this instance. Content that is
+/// too long is rejected with a `TooLong` error naming the field.
+#[derive(SimpleObject, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentLimits {
+    /// The most characters a post's title can have.
+    pub post_title: u32,
+    /// The most characters a post's content can have.
+    pub post_content: u32,
+    /// The most characters a board's name can have.
+    pub board_name: u32,
+    /// The most characters a board's description can have.
+    pub board_description: u32,
+    /// The most bytes an uploaded media file can have.
+    pub media_bytes: u64,
+}
+
+impl From<&LimitsConfig> for ContentLimits {
+    fn from(limits: &LimitsConfig) -> Self {
+        Self {
+            post_title: limits.post_title,
+            post_content: limits.post_content,
+            board_name: limits.board_name,
+            board_description: limits.board_description,
+            media_bytes: limits.media_bytes,
+        }
+    }
+}
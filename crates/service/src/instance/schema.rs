@@ -0,0 +1,21 @@
+use async_graphql::{Context, Object};
+use tracing::instrument;
+
+use super::InstanceInfo;
+use crate::reload::LiveConfig;
+
+#[derive(Default)]
+pub struct InstanceQuery;
+
+#[Object]
+impl InstanceQuery {
+    /// Gets information about this instance.
+    #[instrument(skip_all)]
+    async fn instance_info(&self, ctx: &Context<'_>) -> InstanceInfo {
+        let live = ctx.data_opt::<LiveConfig>();
+        InstanceInfo {
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            public_stats: live.is_some_and(|live| live.load().features.public_stats),
+        }
+    }
+}
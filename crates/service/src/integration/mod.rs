@@ -0,0 +1,18 @@
+//! Integrations let external services (CI, monitoring, feed bridges) post
+//! into a board by sending signed JSON payloads, which are turned into posts
+//! using the integration's templates.
+
+mod models;
+mod persist;
+mod schema;
+mod template;
+
+pub use models::*;
+pub use persist::*;
+pub use schema::*;
+pub use template::*;
+
+pub static INTEGRATION_TABLE_NAME: &str = "integration";
+
+/// The header that carries a delivery's signature.
+pub static SIGNATURE_HEADER: &str = "x-plazer-signature";
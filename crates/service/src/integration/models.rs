@@ -0,0 +1,136 @@
+use async_graphql::{ComplexObject, InputObject, SimpleObject, ID};
+use chrono::{DateTime, Utc};
+use secrecy::SecretString;
+use serde::Deserialize;
+use surrealdb::sql::Thing;
+
+use super::INTEGRATION_TABLE_NAME;
+use crate::{board::BOARD_TABLE_NAME, id_obj_impls, prelude::*};
+
+/// How many deliveries an integration accepts each hour if not given.
+pub const DEFAULT_MAX_PER_HOUR: u32 = 60;
+
+/// A way for an external service to post into a board.
+#[derive(SimpleObject, Debug, Clone, Deserialize)]
+#[graphql(complex)]
+pub struct Integration {
+    #[graphql(skip)]
+    pub id: Thing,
+    #[graphql(skip)]
+    pub owner_id: Thing,
+    #[graphql(skip)]
+    pub board_id: Thing,
+    /// The key that deliveries are signed with.
+    #[graphql(skip)]
+    pub secret: SecretString,
+
+    /// The integration's name, for its owner to tell integrations apart.
+    pub name: String,
+    /// The template that titles of created posts are rendered from.
+    pub title_template: Option<String>,
+    /// The template that the content of created posts is rendered from.
+    pub content_template: String,
+    /// How many deliveries are accepted each hour. Any more are rejected
+    /// until the hour is up.
+    pub max_per_hour: u32,
+
+    /// When the current hour of deliveries started.
+    #[graphql(skip)]
+    pub window_started_at: Option<DateTime<Utc>>,
+    /// How many deliveries have been accepted in the current hour.
+    #[graphql(skip)]
+    #[serde(default)]
+    pub window_deliveries: u32,
+
+    /// A timestamp indicating the last time the integration was updated.
+    pub updated_at: DateTime<Utc>,
+}
+
+#[ComplexObject]
+impl Integration {
+    /// The integration's unique ID.
+    async fn id(&self) -> ID {
+        self.id.to_gql_id()
+    }
+
+    /// The ID of the account that owns this integration. Posts are created
+    /// on its behalf.
+    async fn owner_id(&self) -> ID {
+        self.owner_id.to_gql_id()
+    }
+
+    /// The ID of the board that posts are created in.
+    async fn board_id(&self) -> ID {
+        self.board_id.to_gql_id()
+    }
+
+    /// The path that deliveries are sent to.
+    async fn delivery_path(&self) -> String {
+        format!("/api/v1/integrations/{}/deliveries", self.id.to_gql_id().0)
+    }
+}
+
+id_obj_impls!(Integration);
+
+impl Integration {
+    pub fn create(
+        owner_id: Thing,
+        secret: SecretString,
+        params: CreateIntegration,
+        ids: &dyn IdGen,
+    ) -> srql::CreateStatement {
+        let mut create = vec![];
+        owner_id.push_field(srql::field("owner_id"), &mut create);
+        secret.push_field(srql::field("secret"), &mut create);
+        0u32.push_field(srql::field("window_deliveries"), &mut create);
+        params.append(&mut create);
+        srql::obj_create_query(INTEGRATION_TABLE_NAME, create, ids)
+    }
+}
+
+#[derive(InputObject, Debug, Clone, PartialEq, Eq)]
+pub struct CreateIntegration {
+    /// The ID of the board that posts are created in.
+    pub board_id: ID,
+    /// The integration's name.
+    #[graphql(validator(min_length = 1, max_length = 1024))]
+    pub name: String,
+    /// The template that titles of created posts are rendered from. Posts
+    /// have no title if not given.
+    ///
+    /// `{{ path.to.field }}` is replaced with that field of the delivered
+    /// payload.
+    #[graphql(validator(max_length = 1024))]
+    pub title_template: Option<String>,
+    /// The template that the content of created posts is rendered from.
+    #[graphql(validator(min_length = 1, max_length = 32_768))]
+    pub content_template: String,
+    /// How many deliveries are accepted each hour. Defaults to 60.
+    #[graphql(validator(minimum = 1, maximum = 3600))]
+    pub max_per_hour: Option<u32>,
+}
+
+impl CreateObject for CreateIntegration {
+    fn append(self, expr: &mut srql::SetExpr) {
+        (BOARD_TABLE_NAME, self.board_id).push_field(srql::field("board_id"), expr);
+        self.name.push_field(srql::field("name"), expr);
+        self.title_template
+            .push_field(srql::field("title_template"), expr);
+        self.content_template
+            .push_field(srql::field("content_template"), expr);
+        self.max_per_hour
+            .unwrap_or(DEFAULT_MAX_PER_HOUR)
+            .push_field(srql::field("max_per_hour"), expr);
+    }
+}
+
+/// A newly created integration, along with the secret for signing
+/// deliveries. The secret can't be fetched again later.
+#[derive(SimpleObject, Debug)]
+pub struct CreatedIntegration {
+    pub integration: Integration,
+    /// The key to sign deliveries with, using HMAC-SHA256 over the request
+    /// body. The hex-encoded signature is sent in the `X-Plazer-Signature`
+    /// header as `sha256=<signature>`.
+    pub secret: String,
+}
@@ -0,0 +1,251 @@
+#[cfg(test)]
+mod tests;
+
+use base64::prelude::*;
+use chrono::{DateTime, Duration, Utc};
+use ring::{
+    hmac,
+    rand::{SecureRandom as _, SystemRandom},
+};
+use secrecy::{ExposeSecret as _, SecretString};
+use tracing::instrument;
+
+use super::{render, CreateIntegration, CreatedIntegration, Integration, INTEGRATION_TABLE_NAME};
+use crate::{
+    account::{Account, CurrentAccount, PartialAccount},
+    board::BoardPersist,
+    persist::Persist,
+    policy::PolicyPersist,
+    post::{CreatePost, Post, PostPersist},
+    prelude::*,
+};
+
+/// How many random bytes go into a delivery secret.
+const SECRET_LEN: usize = 32;
+
+pub struct IntegrationPersist<'a> {
+    persist: &'a Persist,
+    current: &'a CurrentAccount,
+    csrng: &'a SystemRandom,
+}
+
+impl<'a> IntegrationPersist<'a> {
+    pub fn new(persist: &'a Persist, current: &'a CurrentAccount, csrng: &'a SystemRandom) -> Self {
+        Self {
+            persist,
+            current,
+            csrng,
+        }
+    }
+
+    /// Gets an integration by its ID, if it's owned by the current account.
+    #[instrument(skip_all)]
+    pub async fn get(&self, id: &str) -> Result<Option<Integration>> {
+        let owner = self.current.id()?.to_account_thing();
+        let integration: Option<Integration> = self
+            .persist
+            .db()
+            .select((INTEGRATION_TABLE_NAME, id))
+            .await?;
+        Ok(integration.filter(|integration| integration.owner_id == owner))
+    }
+
+    /// Lists all of the integrations owned by the current account.
+    #[instrument(skip_all)]
+    pub async fn owned(&self) -> Result<Vec<Integration>> {
+        let owner = self.current.id()?.to_account_thing();
+        let integrations = self
+            .persist
+            .db()
+            .query(srql::SelectStatement {
+                expr: srql::Fields::all(),
+                what: srql::table(INTEGRATION_TABLE_NAME),
+                cond: srql::Cond(
+                    srql::Expression::Binary {
+                        l: srql::field("owner_id").into(),
+                        o: srql::Operator::Equal,
+                        r: owner.into(),
+                    }
+                    .into(),
+                )
+                .into(),
+                order: srql::Orders(vec![srql::Order {
+                    order: srql::field("id"),
+                    direction: true,
+                    ..Default::default()
+                }])
+                .into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        Ok(integrations)
+    }
+
+    /// Creates an integration that posts into a board on behalf of the
+    /// current account.
+    #[instrument(skip_all)]
+    pub async fn create(&self, integration: CreateIntegration) -> Result<CreatedIntegration> {
+        let owner = self.current.id()?.to_account_thing();
+        if BoardPersist::new(self.persist, self.current)
+            .get(&integration.board_id)
+            .await?
+            .is_none()
+        {
+            return Err(Error::BoardInvalid);
+        }
+
+        let mut secret = [0u8; SECRET_LEN];
+        self.csrng.fill(&mut secret)?;
+        let secret = BASE64_URL_SAFE_NO_PAD.encode(secret);
+
+        let integration: Option<Integration> = self
+            .persist
+            .db()
+            .query(Integration::create(
+                owner,
+                secret.clone().into(),
+                integration,
+                self.persist.ids(),
+            ))
+            .await?
+            .take(0)?;
+
+        match integration {
+            Some(integration) => Ok(CreatedIntegration {
+                integration,
+                secret,
+            }),
+            None => Err(Error::UnavailableIdent),
+        }
+    }
+
+    #[instrument(skip_all)]
+    pub async fn delete(&self, id: &str) -> Result<Option<Integration>> {
+        if self.get(id).await?.is_none() {
+            return Ok(None);
+        }
+
+        let integration = self
+            .persist
+            .db()
+            .delete((INTEGRATION_TABLE_NAME, id))
+            .await?;
+        Ok(integration)
+    }
+
+    /// Turns a delivered payload into a post in the integration's board.
+    ///
+    /// This is done on behalf of the integration's owner, so there doesn't
+    /// need to be a current account. Instead, the payload must be signed
+    /// with the integration's secret.
+    #[instrument(skip_all)]
+    pub async fn deliver(&self, id: &str, body: &[u8], signature: Option<&str>) -> Result<Post> {
+        let Some(integration): Option<Integration> = self
+            .persist
+            .db()
+            .select((INTEGRATION_TABLE_NAME, id))
+            .await?
+        else {
+            return Err(Error::NotFound);
+        };
+
+        verify_signature(&integration.secret, body, signature)?;
+        let payload: serde_json::Value =
+            serde_json::from_slice(body).map_err(|err| Error::ParseError(err.to_string()))?;
+
+        self.count_delivery(&integration).await?;
+
+        let Some(owner): Option<Account> = self.persist.load(integration.owner_id.clone()).await?
+        else {
+            return Err(Error::NotFound);
+        };
+        let owner = CurrentAccount::new(
+            PartialAccount::new(owner.id.to_gql_id(), owner.user_id),
+            self.persist.clock().now() + Duration::minutes(1),
+            self.persist.shared_clock(),
+        );
+
+        PolicyPersist::new(self.persist, &owner)
+            .require_accepted()
+            .await?;
+        PostPersist::new(self.persist, &owner)
+            .create(CreatePost {
+                board_id: Some(integration.board_id.to_gql_id()),
+                title: integration
+                    .title_template
+                    .map(|template| render(&template, &payload)),
+                content: Some(render(&integration.content_template, &payload)),
+                ..Default::default()
+            })
+            .await
+    }
+
+    /// Counts a delivery against the integration's hourly limit, failing if
+    /// the limit has already been reached.
+    async fn count_delivery(&self, integration: &Integration) -> Result<()> {
+        let now = self.persist.clock().now();
+        let (window_started_at, window_deliveries) = match integration.window_started_at {
+            Some(started_at) if !window_elapsed(started_at, now) => {
+                if integration.window_deliveries >= integration.max_per_hour {
+                    return Err(Error::RateLimited);
+                }
+                (started_at, integration.window_deliveries + 1)
+            }
+            _ => (now, 1),
+        };
+
+        let mut update = vec![];
+        window_started_at.push_field(srql::field("window_started_at"), &mut update);
+        window_deliveries.push_field(srql::field("window_deliveries"), &mut update);
+        self.persist
+            .db()
+            .query(srql::UpdateStatement {
+                what: srql::thing(integration.id.clone()),
+                data: srql::Data::SetExpression(update).into(),
+                ..Default::default()
+            })
+            .await?;
+
+        Ok(())
+    }
+}
+
+fn window_elapsed(started_at: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+    now - started_at >= Duration::hours(1)
+}
+
+/// Checks a `sha256=<hex>` signature of `body`.
+fn verify_signature(secret: &SecretString, body: &[u8], signature: Option<&str>) -> Result<()> {
+    let signature = signature
+        .and_then(|signature| signature.strip_prefix("sha256="))
+        .and_then(|signature| hex::decode(signature).ok())
+        .ok_or(Error::SignatureInvalid)?;
+
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.expose_secret().as_bytes());
+    hmac::verify(&key, body, &signature).map_err(|_| Error::SignatureInvalid)
+}
+
+#[cfg(test)]
+pub mod testing {
+    use ring::hmac;
+
+    use super::IntegrationPersist;
+    use crate::account::testing::TestData;
+
+    pub trait IntegrationTestData {
+        fn integration(&self) -> IntegrationPersist<'_>;
+    }
+
+    impl IntegrationTestData for TestData {
+        fn integration(&self) -> IntegrationPersist<'_> {
+            IntegrationPersist::new(&self.persist, &self.current, &self.csrng)
+        }
+    }
+
+    /// Signs a delivery the way an external service would.
+    pub fn sign(secret: &str, body: &[u8]) -> String {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+        format!("sha256={}", hex::encode(hmac::sign(&key, body)))
+    }
+}
@@ -0,0 +1,138 @@
+use chrono::{TimeZone as _, Utc};
+use pretty_assertions::assert_eq;
+
+use super::{
+    testing::{sign, IntegrationTestData as _},
+    *,
+};
+use crate::{
+    account::testing::*,
+    board::{testing::BoardTestData as _, Board},
+    provider::MockClock,
+};
+
+fn create(board: &Board) -> CreateIntegration {
+    CreateIntegration {
+        board_id: board.id.to_gql_id(),
+        name: "CI".into(),
+        title_template: Some("Build {{ status }}".into()),
+        content_template: "{{ repo.name }} at {{ commit }}".into(),
+        max_per_hour: Some(2),
+    }
+}
+
+#[tokio::test]
+async fn test_create() {
+    let (data, acc) = TestData::with_user().await;
+    let board = data.generate_board().await;
+
+    let res = data.integration().create(create(&board)).await;
+    println!("{res:?}");
+    let created = res.unwrap();
+    assert_eq!(created.integration.owner_id, acc.id);
+    assert_eq!(created.integration.board_id, board.id);
+    assert_eq!(created.integration.max_per_hour, 2);
+    assert_eq!(created.integration.secret.expose_secret(), &created.secret);
+
+    let owned = data.integration().owned().await.unwrap();
+    assert_eq!(owned, vec![created.integration]);
+}
+
+#[tokio::test]
+async fn test_create_invalid_board() {
+    let (data, _) = TestData::with_user().await;
+    let board = data.generate_board().await;
+    data.board().delete(&board.id.to_gql_id()).await.unwrap();
+
+    let res = data.integration().create(create(&board)).await;
+    assert_eq!(res.unwrap_err(), Error::BoardInvalid);
+}
+
+#[tokio::test]
+async fn test_owned_only() {
+    let (mut data, _) = TestData::with_user().await;
+    let board = data.generate_board().await;
+    let created = data.integration().create(create(&board)).await.unwrap();
+    let id = created.integration.id.to_gql_id();
+
+    let other = data.account().create_test_user().await;
+    data.login_as(&other);
+    assert_eq!(data.integration().get(&id).await.unwrap(), None);
+    assert_eq!(data.integration().delete(&id).await.unwrap(), None);
+    assert!(data.integration().owned().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_deliver() {
+    let (mut data, acc) = TestData::with_user().await;
+    let board = data.generate_board().await;
+    let created = data.integration().create(create(&board)).await.unwrap();
+    let id = created.integration.id.to_gql_id();
+
+    // Deliveries don't come from a logged in account.
+    data.current = CurrentAccount::default();
+
+    let body = br#"{"status":"passed","repo":{"name":"plazer"},"commit":"abc123"}"#;
+    let post = data
+        .integration()
+        .deliver(&id, body, Some(&sign(&created.secret, body)))
+        .await
+        .unwrap();
+    assert_eq!(post.creator_id, Some(acc.id));
+    assert_eq!(post.board_id, Some(board.id));
+    assert_eq!(post.title.as_deref(), Some("Build passed"));
+    assert_eq!(post.content.as_deref(), Some("plazer at abc123"));
+}
+
+#[tokio::test]
+async fn test_deliver_signature() {
+    let (data, _) = TestData::with_user().await;
+    let board = data.generate_board().await;
+    let created = data.integration().create(create(&board)).await.unwrap();
+    let id = created.integration.id.to_gql_id();
+    let body = br#"{"status":"passed"}"#;
+
+    for signature in [
+        None,
+        Some("not-a-signature".to_owned()),
+        Some(sign("wrong-secret", body)),
+        Some(sign(&created.secret, b"{}")),
+    ] {
+        let res = data
+            .integration()
+            .deliver(&id, body, signature.as_deref())
+            .await;
+        assert_eq!(res.unwrap_err(), Error::SignatureInvalid, "{signature:?}");
+    }
+
+    let res = data
+        .integration()
+        .deliver("missing", body, Some(&sign(&created.secret, body)))
+        .await;
+    assert_eq!(res.unwrap_err(), Error::NotFound);
+}
+
+#[tokio::test]
+async fn test_deliver_rate_limited() {
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap());
+    let mut data = TestData::with_clock(clock.clone()).await;
+    let acc = data.account().create_test_user().await;
+    data.login_as(&acc);
+    let board = data.generate_board().await;
+    let created = data.integration().create(create(&board)).await.unwrap();
+    let id = created.integration.id.to_gql_id();
+
+    let body = b"{}";
+    let signature = sign(&created.secret, body);
+    let integrations = data.integration();
+    let deliver = || integrations.deliver(&id, body, Some(&signature));
+
+    deliver().await.unwrap();
+    clock.advance(Duration::minutes(30));
+    deliver().await.unwrap();
+    assert_eq!(deliver().await.unwrap_err(), Error::RateLimited);
+
+    // The limit resets an hour after the first delivery.
+    clock.advance(Duration::minutes(30));
+    deliver().await.unwrap();
+}
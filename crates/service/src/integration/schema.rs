@@ -0,0 +1,46 @@
+use async_graphql::{Context, Object, ID};
+use tracing::instrument;
+
+use super::{CreateIntegration, CreatedIntegration, Integration};
+use crate::{policy::PoliciesAccepted, prelude::*};
+
+#[derive(Default)]
+pub struct IntegrationQuery;
+
+#[Object]
+impl IntegrationQuery {
+    /// Lists the integrations owned by the current account.
+    #[instrument(skip_all)]
+    async fn integrations(&self, ctx: &Context<'_>) -> GqlResult<Vec<Integration>> {
+        ctx.integration_persist().owned().await.extend()
+    }
+}
+
+#[derive(Default)]
+pub struct IntegrationMutation;
+
+#[Object(guard = "PoliciesAccepted")]
+impl IntegrationMutation {
+    /// Creates an integration that posts into a board on behalf of the
+    /// current account. The returned secret is needed to sign deliveries, and
+    /// can't be fetched again.
+    #[instrument(skip_all)]
+    async fn create_integration(
+        &self,
+        ctx: &Context<'_>,
+        create: CreateIntegration,
+    ) -> GqlResult<CreatedIntegration> {
+        ctx.integration_persist().create(create).await.extend()
+    }
+
+    /// Deletes an integration. Deliveries to it are rejected afterwards, but
+    /// posts it already created are kept.
+    #[instrument(skip_all)]
+    async fn delete_integration(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+    ) -> GqlResult<Option<Integration>> {
+        ctx.integration_persist().delete(&id).await.extend()
+    }
+}
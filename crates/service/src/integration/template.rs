@@ -0,0 +1,66 @@
+use serde_json::Value;
+
+/// Fills in a template from a JSON payload.
+///
+/// `{{ path }}` is replaced with the value at `path`, a `.`-separated list of
+/// object keys and array indices (such as `{{ commits.0.message }}`). Strings
+/// are inserted as-is, other values as JSON, and missing values as nothing. A
+/// `{{` without a closing `}}` is left alone.
+#[must_use]
+pub fn render(template: &str, payload: &Value) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start..].find("}}") else {
+            break;
+        };
+        rendered.push_str(&rest[..start]);
+
+        let path = rest[start + 2..start + len].trim();
+        match lookup(payload, path) {
+            Some(Value::String(s)) => rendered.push_str(s),
+            Some(Value::Null) | None => (),
+            Some(value) => rendered.push_str(&value.to_string()),
+        }
+
+        rest = &rest[start + len + 2..];
+    }
+    rendered.push_str(rest);
+
+    rendered
+}
+
+fn lookup<'a>(payload: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(payload, |value, key| match value {
+        Value::Object(obj) => obj.get(key),
+        Value::Array(arr) => arr.get(key.parse::<usize>().ok()?),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use test_case::test_case;
+
+    use super::*;
+
+    #[test_case("plain", "plain" ; "no placeholders")]
+    #[test_case("{{ status }}: {{repo.name}}", "failed: plazer" ; "nested keys")]
+    #[test_case("{{ commits.1.id }}", "b" ; "array index")]
+    #[test_case("{{ count }} {{ ok }}", "3 false" ; "non-strings")]
+    #[test_case("[{{ missing.key }}] [{{ none }}]", "[] []" ; "missing and null")]
+    #[test_case("{{ status", "{{ status" ; "unclosed")]
+    fn test_render(template: &str, expected: &str) {
+        let payload = json!({
+            "status": "failed",
+            "repo": { "name": "plazer" },
+            "commits": [{ "id": "a" }, { "id": "b" }],
+            "count": 3,
+            "ok": false,
+            "none": null,
+        });
+        assert_eq!(render(template, &payload), expected);
+    }
+}
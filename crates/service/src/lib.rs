@@ -7,19 +7,72 @@
 #![forbid(unsafe_code)]
 
 mod account;
+mod admin;
+mod audience;
+mod audit;
 mod board;
+mod bulk;
+mod capability;
+mod client_state;
+mod client_version;
+mod complexity;
 pub mod config;
 mod conv;
+mod conversation;
+mod cors;
+mod credentials;
+mod db;
+pub mod doctor;
+mod email;
 mod error;
+mod event;
+mod export;
+mod feed;
+mod follow;
+mod health;
+mod http;
+mod instance;
+mod integration;
+mod license;
+mod list;
+mod loader;
+mod locale;
 mod macros;
+mod media;
+mod memo;
+pub mod migrate;
 mod migration;
+mod moderation;
+mod notification;
+mod organization;
+mod overload;
+mod partial;
 mod persist;
+mod persisted_query;
+mod policy;
 mod post;
 mod prelude;
+pub mod provider;
+mod proxy;
 mod query;
+mod quota;
+mod rate_limit;
+mod read_marker;
+mod read_only;
+mod reload;
+mod rest;
 mod schema;
+mod security;
+mod session;
+mod share;
+mod spam;
+mod stats;
+mod translation;
+mod tx;
+mod warning;
+mod webhook;
 
-use std::{io, net::SocketAddr, sync::Arc};
+use std::{future::Future, io, net::SocketAddr, sync::Arc};
 
 #[cfg(feature = "graphiql")]
 use async_graphql::http::GraphiQLSource;
@@ -27,22 +80,66 @@ use async_graphql::{http::ALL_WEBSOCKET_PROTOCOLS, Data, ResultExt as _};
 use async_graphql_axum::{GraphQLBatchRequest, GraphQLProtocol, GraphQLResponse, GraphQLWebSocket};
 use axum::{
     extract::{FromRef, State, WebSocketUpgrade},
-    headers::{authorization::Bearer, Authorization},
+    http::{HeaderValue, Request},
+    middleware::{self, Next},
+    response::Response,
     routing::{get, post},
-    Router, Server, TypedHeader,
+    Router, Server,
 };
 use config::LogConfig;
+use hyper::server::conn::AddrIncoming;
 use ring::rand::{SecureRandom as _, SystemRandom};
 use thiserror::Error;
 use tokio::signal;
-use tracing::{debug, error, info, instrument, metadata::LevelFilter, trace};
+use tracing::{debug, error, info, instrument, metadata::LevelFilter, trace, warn};
 use tracing_appender::non_blocking::WorkerGuard;
 use tracing_subscriber::{fmt, layer::SubscriberExt as _, Layer as _};
 
+pub use crate::account::{
+    AccountRepo, ExternalProfile, HttpOidcClient, MemoryAccountRepo, MemoryOidcClient, OidcClient,
+    SharedAccountRepo, SharedOidcClient, SurrealAccountRepo,
+};
+pub use crate::audit::{
+    AuditEntry, AuditSink, FileAuditSink, HttpAuditSink, MemoryAuditSink, S3AuditSink, S3Bucket,
+    SharedAuditSink, SyslogAuditSink,
+};
+pub use crate::credentials::{Argon2Hasher, PasswordHasher, SharedPasswordHasher, StoredPword};
+pub use crate::email::{
+    Email, EmailSender, MemoryEmailSender, NoEmailSender, SharedEmailSender, SmtpEmailSender,
+};
+pub use crate::notification::{
+    MemoryNotificationTransport, NoNotificationTransport, NotificationChannel,
+    NotificationDelivery, NotificationKind, NotificationTransport, SharedNotificationTransport,
+};
+pub use crate::organization::{
+    DomainVerifier, HttpDomainVerifier, MemoryDomainVerifier, SharedDomainVerifier,
+};
 pub use crate::schema::schema;
+pub use crate::webhook::{
+    HttpWebhookSender, MemoryWebhookSender, SharedWebhookSender, WebhookDelivery, WebhookSender,
+};
 use crate::{
-    account::authenticate, config::ServeConfig, error::ErrorResponse, migration::Migrations,
+    account::{
+        authenticate_request, AccountPersist, ApiKeyScopeGuard, Authenticated, CurrentAccount,
+    },
+    capability::{CapabilityReport, ConfigSummary},
+    client_version::ClientVersionGuard,
+    complexity::ComplexityGuard,
+    config::{ReloadableConfig, ServeConfig},
+    cors::apply_cors,
+    error::ErrorResponse,
+    migration::Migrations,
+    overload::{limit_concurrency, ConcurrencyLimit},
+    partial::PartialResults,
+    persisted_query::PersistedQueries,
+    provider::SharedClock,
+    rate_limit::{RateLimitGuard, RateLimiter},
+    read_only::ReadOnlyGuard,
+    reload::LiveConfig,
     schema::ServiceSchema,
+    session::{ClientApp, ClientMeta},
+    stats::{count_requests, LiveMetrics},
+    warning::WarningCollector,
 };
 
 /// Initialise logging.
@@ -60,38 +157,117 @@ pub fn init_logging(
     let file_appender = tracing_appender::rolling::hourly(path, "service.log");
     let (non_blocking, guard) = tracing_appender::non_blocking(file_appender);
 
+    // The filters can be swapped out when the log levels are reloaded.
+    let (filter_stdout, handle_stdout) =
+        tracing_subscriber::reload::Layer::new(LevelFilter::from_level(level_stdout));
+    let (filter_file, handle_file) =
+        tracing_subscriber::reload::Layer::new(LevelFilter::from_level(level_file));
     let collector = tracing_subscriber::registry()
         .with(
             fmt::Layer::new()
                 .with_writer(io::stdout)
                 .pretty()
-                .with_filter(LevelFilter::from_level(level_stdout)),
+                .with_filter(filter_stdout),
         )
         .with(
             fmt::Layer::new()
                 .with_writer(non_blocking)
                 .json()
-                .with_filter(LevelFilter::from_level(level_file)),
+                .with_filter(filter_file),
         );
     tracing::subscriber::set_global_default(collector).expect("Unable to set a global subscriber");
+    reload::register_log_filters(
+        move |level| {
+            if let Err(err) = handle_stdout.reload(LevelFilter::from_level(level)) {
+                warn!(error = ?err, "Unable to change the stdout log level");
+            }
+        },
+        move |level| {
+            if let Err(err) = handle_file.reload(LevelFilter::from_level(level)) {
+                warn!(error = ?err, "Unable to change the file log level");
+            }
+        },
+    );
 
     trace!(?level_stdout, ?level_file, "Logging initialised");
 
     guard
 }
 
-#[instrument(skip(jwt_enc_key, jwt_dec_key))]
-pub async fn serve(
-    ServeConfig {
+/// Starts the server on the configured host and port, shutting down when a
+/// termination signal is received.
+pub async fn serve(config: ServeConfig) -> Result<(), ServeError> {
+    let addr = SocketAddr::new(config.host, config.port);
+    serve_with(Server::try_bind(&addr)?, addr, config, shutdown_signal()).await
+}
+
+/// Starts the server on an existing listener, shutting down when `shutdown`
+/// completes. The host and port in the config are ignored.
+///
+/// This is mostly useful for tests, which can bind to a random port and find
+/// out what it is before the server starts.
+pub async fn serve_on(
+    listener: std::net::TcpListener,
+    config: ServeConfig,
+    shutdown: impl Future<Output = ()>,
+) -> Result<(), ServeError> {
+    let addr = listener.local_addr()?;
+    serve_with(Server::from_tcp(listener)?, addr, config, shutdown).await
+}
+
+#[instrument(skip_all, fields(%addr))]
+async fn serve_with(
+    builder: hyper::server::Builder<AddrIncoming>,
+    addr: SocketAddr,
+    config: ServeConfig,
+    shutdown: impl Future<Output = ()>,
+) -> Result<(), ServeError> {
+    let summary = ConfigSummary::from(&config);
+    let ServeConfig {
         address,
         namespace,
         database,
+        db,
         jwt_enc_key,
         jwt_dec_key,
-        host,
-        port,
-    }: ServeConfig,
-) -> Result<(), ServeError> {
+        host: _,
+        port: _,
+        cors,
+        instance,
+        spam,
+        dev_auth,
+        overload,
+        rate_limits,
+        features,
+        log_levels,
+        reload_from,
+        query_limits,
+        persisted_queries,
+        privacy,
+        quotas,
+        limits,
+        read_only,
+        client_versions,
+        media,
+        post_visibility,
+        sessions,
+        clock,
+        ids,
+        password_hasher,
+        webhooks,
+        domains,
+        oidc,
+        oidc_client,
+        notification_transport,
+        email,
+        audit_sinks,
+        translation,
+        proxy,
+    } = config;
+    if dev_auth.enabled && !cfg!(debug_assertions) && !dev_auth.allow_release {
+        return Err(ServeError::DevAuthInRelease);
+    }
+
     debug!("Initialising RNG");
     // Call fill once before starting to initialize the RNG.
     let csrng = SystemRandom::new();
@@ -100,43 +276,230 @@ pub async fn serve(
 
     let jwt_enc_key = Arc::new(jwt_enc_key);
     let jwt_dec_key = Arc::new(jwt_dec_key);
-    let persist = persist::Persist::new(address, namespace, database).await?;
+    let blobs: media::SharedBlobStore = match &media.dir {
+        Some(dir) => Arc::new(media::FsBlobStore::new(dir)),
+        None => Arc::new(media::MemoryBlobStore::default()),
+    };
+    let persist = persist::Persist::new(address, namespace, database, &db)
+        .await?
+        .with_clock(clock)
+        .with_ids(ids)
+        .with_password_hasher(password_hasher)
+        .with_quotas(quotas)
+        .with_region(instance.region.clone())
+        .with_limits(limits)
+        .with_alt_text_policy(media.alt_text)
+        .with_post_visibility(post_visibility)
+        .with_read_only(read_only)
+        .with_client_versions(client_versions)
+        .with_blobs(blobs)
+        .with_sessions(sessions)
+        .with_webhooks(webhooks)
+        .with_domains(domains)
+        .with_oidc_client(oidc_client)
+        .with_notification_transport(notification_transport)
+        .with_email(email)
+        .with_audit_sinks(audit_sinks)
+        .with_translation(translation);
 
-    info!("Configuring database...");
-    if let Err(err) = Migrations::run(&persist).await {
-        error!(
-            error = ?err,
-            "Failed to complete configuration, database may be corrupt"
-        );
-        return Err(err.into());
+    if db.auto_migrate {
+        info!("Configuring database...");
+        if let Err(err) = Migrations::run(&persist).await {
+            error!(
+                error = ?err,
+                "Failed to complete configuration, database may be corrupt"
+            );
+            return Err(err.into());
+        }
+        info!("Database configuration complete");
+    } else {
+        let pending = Migrations::pending(&persist).await?;
+        if !pending.is_empty() {
+            return Err(ServeError::MigrationsPending(pending.join(", ")));
+        }
+    }
+
+    let capabilities = CapabilityReport::new(summary, Migrations::versions(&persist).await?, addr);
+    match serde_json::to_string(&capabilities) {
+        Ok(report) => info!(%report, "Capabilities"),
+        Err(err) => warn!(error = ?err, "Failed to serialise the capability report"),
+    }
+
+    let backfilled = account::backfill_user_id_skeletons(&persist)
+        .await
+        .map_err(|err| ServeError::BackfillError(err.to_string()))?;
+    if backfilled > 0 {
+        info!(backfilled, "Stored the skeletons of existing user IDs");
+    }
+
+    if dev_auth.enabled {
+        warn!("Development authentication is enabled, anyone can log into the seeded accounts");
+        let current = CurrentAccount::default();
+        AccountPersist::new(&persist, &current, &csrng, &jwt_dec_key)
+            .seed_dev_accounts()
+            .await
+            .map_err(|err| ServeError::DevAuthSeedError(err.to_string()))?;
     }
-    info!("Database configuration complete");
+
+    stats::spawn_rollups(persist.clone());
+    session::spawn_redactions(persist.clone(), privacy.clone());
+    account::spawn_refresh_token_pruning(persist.clone());
+    account::spawn_restriction_expiry(persist.clone());
+    account::spawn_account_purges(persist.clone());
+    account::spawn_account_anonymization(persist.clone());
+    account::spawn_dormancy_checks(
+        persist.clone(),
+        account::DormancyPolicy {
+            after_days: instance.dormant_after_days,
+            grace_days: instance.dormancy_grace_days,
+            release_user_ids: instance.release_dormant_user_ids,
+        },
+        instance.public_url.clone(),
+    );
+    media::spawn_collection(persist.clone(), media.collect_after);
+    organization::spawn_domain_checks(persist.clone());
+    notification::spawn_releases(persist.clone());
+    event::spawn_projections(persist.clone());
+    event::spawn_reconciliation(persist.clone());
+    export::spawn_data_exports(persist.clone(), instance.public_url.clone());
+    audit::spawn_audit_shipping(persist.clone());
+    bulk::spawn_bulk_jobs(persist.clone());
+    let media_urls = media::MediaUrls::new(
+        &media,
+        jwt_enc_key.clone(),
+        jwt_dec_key.clone(),
+        persist.shared_clock(),
+        instance.public_url.clone(),
+    );
+    let proxy_urls = proxy::ProxyUrls::new(
+        &proxy,
+        jwt_enc_key.clone(),
+        jwt_dec_key.clone(),
+        instance.public_url.clone(),
+    );
+    let live = LiveConfig::new(
+        ReloadableConfig {
+            rate_limits,
+            features,
+            log_levels,
+        },
+        reload_from,
+    );
+    #[cfg(unix)]
+    reload::spawn_reload_on_hangup(live.clone());
+    reload::spawn_log_level_updates(&live);
+    let rate_limiter = RateLimiter::new(live.clone(), persist.shared_clock());
+    let rest = rest::RestState {
+        persist: persist.clone(),
+        csrng: csrng.clone(),
+        jwt_enc_key: jwt_enc_key.clone(),
+        jwt_dec_key: jwt_dec_key.clone(),
+        spam: Arc::new(spam::SpamPipeline::new(&spam)),
+        min_age: instance.min_age,
+        live: live.clone(),
+        privacy: Arc::new(privacy.clone()),
+        media_urls: media_urls.clone(),
+        proxy_urls: proxy_urls.clone(),
+        remote_media: proxy::RemoteMedia::new(&proxy, persist.shared_clock()),
+        rate_limiter: rate_limiter.clone(),
+    };
+    let localizer = Arc::new(locale::Localizer::new());
+    let share = share::ShareState {
+        persist: persist.clone(),
+        csrng: csrng.clone(),
+        jwt_dec_key: jwt_dec_key.clone(),
+        public_url: instance.public_url.clone(),
+        localizer: localizer.clone(),
+    };
+    let health = health::HealthState {
+        persist: persist.clone(),
+        jwt_enc_key: jwt_enc_key.clone(),
+        jwt_dec_key: jwt_dec_key.clone(),
+    };
+    let clock = persist.shared_clock();
+    let requests = persist.requests().clone();
+    let metrics = persist.metrics().clone();
+    let state_persist = persist.clone();
+    let region = persist
+        .region()
+        .and_then(|region| HeaderValue::from_str(region).ok());
+    let read_only = ReadOnlyGuard(persist.read_only().clone());
+    let client_versions = ClientVersionGuard(persist.client_versions().clone());
 
     let schema = schema(|s| {
-        s.data(persist)
+        s.extension(client_versions)
+            .extension(PersistedQueries::new(persisted_queries))
+            .extension(ComplexityGuard(query_limits))
+            .extension(RateLimitGuard(rate_limiter))
+            .extension(read_only)
+            .extension(ApiKeyScopeGuard)
+            .extension(PartialResults)
+            .extension(WarningCollector)
+            .data(persist)
+            .data(instance)
+            .data(live)
+            .data(oidc)
+            .data(capabilities)
+            .data(spam::SpamPipeline::new(&spam))
+            .data(dev_auth)
+            .data(privacy)
+            .data(localizer)
+            .data(media_urls)
+            .data(proxy_urls)
             .data(csrng)
             .data(jwt_enc_key.clone())
             .data(jwt_dec_key.clone())
     });
 
-    let state = ServiceState::new(schema, jwt_enc_key, jwt_dec_key);
+    let state = ServiceState::new(
+        schema,
+        state_persist,
+        jwt_enc_key,
+        jwt_dec_key,
+        clock,
+        metrics,
+    );
 
     let router = Router::new();
     #[cfg(feature = "graphiql")]
     let router = router.route("/", get(graphiql));
     let app = router
-        .route("/api/graphql", post(graphql_handler))
+        .route(
+            "/api/graphql",
+            post(graphql_handler).layer(middleware::from_fn_with_state(
+                ConcurrencyLimit::graphql(&overload),
+                limit_concurrency,
+            )),
+        )
         .route("/api/graphql/ws", get(graphql_ws_handler))
-        .with_state(state);
+        .merge(rest::router(rest))
+        .merge(share::router(share))
+        .layer(middleware::from_fn_with_state(
+            ConcurrencyLimit::global(&overload),
+            limit_concurrency,
+        ))
+        .layer(middleware::from_fn_with_state(requests, count_requests))
+        .with_state(state)
+        // Probes skip the limits above, so that load doesn't fail them.
+        .merge(health::router(health));
+    let app = match region {
+        Some(region) => app.layer(middleware::from_fn_with_state(region, add_region_header)),
+        None => app,
+    };
+    // Outermost, so that preflight requests are answered before anything else
+    // runs.
+    let app = if cors.origins.is_empty() {
+        app
+    } else {
+        app.layer(middleware::from_fn_with_state(Arc::new(cors), apply_cors))
+    };
 
-    let addr = SocketAddr::new(host, port);
+    let server = builder.serve(app.into_make_service_with_connect_info::<SocketAddr>());
+    let addr = server.local_addr();
     info!("Listening on {}", addr);
     #[cfg(feature = "graphiql")]
     info!("GraphQL Playground: http://localhost:{}/", addr.port());
-    Server::try_bind(&addr)?
-        .serve(app.into_make_service())
-        .with_graceful_shutdown(shutdown_signal())
-        .await?;
+    server.with_graceful_shutdown(shutdown).await?;
 
     Ok(())
 }
@@ -167,28 +530,60 @@ async fn shutdown_signal() {
     info!("Shutting down");
 }
 
+/// The header naming the region of the server that handled a request, so
+/// that load balancers can send a client's later requests to the same one.
+pub static REGION_HEADER: &str = "plazer-region";
+
+async fn add_region_header<B>(
+    State(region): State<HeaderValue>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let mut res = next.run(req).await;
+    res.headers_mut().insert(REGION_HEADER, region);
+    res
+}
+
 #[derive(Error, Debug)]
 pub enum ServeError {
     #[error("Invalid host: {0}")]
     InvalidHost(#[from] std::net::AddrParseError),
     #[error("Failed to start server: {0}")]
     ServeError(#[from] hyper::Error),
+    #[error("Failed to read the listener's address: {0}")]
+    ListenerError(#[from] std::io::Error),
     #[error("Failed to initialise database: {0}")]
     PersistError(#[from] surrealdb::Error),
     #[error("Failed to initialise cryptography")]
     CryptoError(#[from] ring::error::Unspecified),
+    #[error("Development authentication cannot be enabled in release builds without explicitly allowing it")]
+    DevAuthInRelease,
+    #[error("Failed to create development accounts: {0}")]
+    DevAuthSeedError(String),
+    #[error("Failed to store the skeletons of existing user IDs: {0}")]
+    BackfillError(String),
+    #[error("Migrations haven't been run for: {0}")]
+    MigrationsPending(String),
 }
 
 #[instrument(skip_all)]
 async fn graphql_handler(
     State(schema): State<ServiceSchema>,
-    State(dec_key): State<DecodingKey>,
-    auth_header: Option<TypedHeader<Authorization<Bearer>>>,
+    State(persist): State<persist::Persist>,
+    Authenticated(current): Authenticated,
+    client: ClientMeta,
     req: GraphQLBatchRequest,
 ) -> Result<GraphQLResponse, ErrorResponse> {
-    let current = authenticate(auth_header, &dec_key)?;
+    // This shadows the schema's persist for the request, giving it a memo
+    // that lasts until the request is done.
+    let persist = persist.with_memo();
     Ok(schema
-        .execute_batch(req.into_inner().data(Arc::new(current)))
+        .execute_batch(
+            req.into_inner()
+                .data(Arc::new(current))
+                .data(client)
+                .data(persist),
+        )
         .await
         .into())
 }
@@ -196,21 +591,38 @@ async fn graphql_handler(
 #[instrument(skip_all)]
 async fn graphql_ws_handler(
     State(schema): State<ServiceSchema>,
+    State(persist): State<persist::Persist>,
     State(dec_key): State<DecodingKey>,
+    State(metrics): State<LiveMetrics>,
     protocol: GraphQLProtocol,
     upgrade: WebSocketUpgrade,
 ) -> axum::response::Response {
     upgrade
         .protocols(ALL_WEBSOCKET_PROTOCOLS)
-        .on_upgrade(move |stream| {
+        .on_upgrade(move |stream| async move {
+            let _connection = metrics.track_ws_connection();
             GraphQLWebSocket::new(stream, schema, protocol)
                 .on_connection_init(|init| async move {
                     let mut data = Data::default();
-                    let current = authenticate(init, &dec_key).extend()?;
+                    // Outdated apps are turned away before they can start any
+                    // subscriptions, as well as by each request.
+                    let client = ClientMeta {
+                        app: ClientApp::from_init(&init),
+                        ..Default::default()
+                    };
+                    persist
+                        .client_versions()
+                        .check(client.app.as_ref())
+                        .extend()?;
+                    let current = authenticate_request(init, &persist, &dec_key)
+                        .await
+                        .extend()?;
                     data.insert(current);
+                    data.insert(client);
                     Ok(data)
                 })
                 .serve()
+                .await;
         })
 }
 
@@ -231,20 +643,29 @@ type DecodingKey = Arc<jsonwebtoken::DecodingKey>;
 #[derive(Clone)]
 struct ServiceState {
     schema: ServiceSchema,
+    persist: persist::Persist,
     jwt_enc_key: EncodingKey,
     jwt_dec_key: DecodingKey,
+    clock: SharedClock,
+    metrics: LiveMetrics,
 }
 
 impl ServiceState {
     fn new(
         schema: ServiceSchema,
+        persist: persist::Persist,
         jwt_enc_key: impl Into<EncodingKey>,
         jwt_dec_key: impl Into<DecodingKey>,
+        clock: SharedClock,
+        metrics: LiveMetrics,
     ) -> Self {
         Self {
             schema,
+            persist,
             jwt_enc_key: jwt_enc_key.into(),
             jwt_dec_key: jwt_dec_key.into(),
+            clock,
+            metrics,
         }
     }
 }
@@ -255,6 +676,12 @@ impl FromRef<ServiceState> for ServiceSchema {
     }
 }
 
+impl FromRef<ServiceState> for persist::Persist {
+    fn from_ref(state: &ServiceState) -> Self {
+        state.persist.clone()
+    }
+}
+
 impl FromRef<ServiceState> for EncodingKey {
     fn from_ref(state: &ServiceState) -> Self {
         state.jwt_enc_key.clone()
@@ -266,3 +693,15 @@ impl FromRef<ServiceState> for DecodingKey {
         state.jwt_dec_key.clone()
     }
 }
+
+impl FromRef<ServiceState> for SharedClock {
+    fn from_ref(state: &ServiceState) -> Self {
+        state.clock.clone()
+    }
+}
+
+impl FromRef<ServiceState> for LiveMetrics {
+    fn from_ref(state: &ServiceState) -> Self {
+        state.metrics.clone()
+    }
+}
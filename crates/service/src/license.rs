@@ -0,0 +1,125 @@
+//! The licenses that posts and media are published under, and who they should
+//! be credited to.
+//!
+//! Accounts choose a default license for everything they publish, and can
+//! pick a different one for a single post or upload. The license is stored
+//! with the content when it's created, so changing the default later doesn't
+//! change the terms that earlier content was published under.
+
+use async_graphql::Enum;
+use serde::{Deserialize, Serialize};
+
+use crate::{account::Account, persist::Persist, prelude::*};
+
+/// How long attribution URLs can be.
+const MAX_ATTRIBUTION_URL_LEN: usize = 2048;
+
+/// The license that content is published under.
+#[derive(Enum, Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentLicense {
+    /// Nobody else can reuse the content without asking.
+    #[default]
+    AllRightsReserved,
+    /// Dedicated to the public domain (CC0 1.0).
+    Cc0,
+    /// Attribution (CC BY 4.0).
+    CcBy,
+    /// Attribution-ShareAlike (CC BY-SA 4.0).
+    CcBySa,
+    /// Attribution-NoDerivatives (CC BY-ND 4.0).
+    CcByNd,
+    /// Attribution-NonCommercial (CC BY-NC 4.0).
+    CcByNc,
+    /// Attribution-NonCommercial-ShareAlike (CC BY-NC-SA 4.0).
+    CcByNcSa,
+    /// Attribution-NonCommercial-NoDerivatives (CC BY-NC-ND 4.0).
+    CcByNcNd,
+}
+
+impl ContentLicense {
+    /// The URL of the license's text, if it has one.
+    #[must_use]
+    pub fn url(self) -> Option<&'static str> {
+        Some(match self {
+            Self::AllRightsReserved => return None,
+            Self::Cc0 => "https://creativecommons.org/publicdomain/zero/1.0/",
+            Self::CcBy => "https://creativecommons.org/licenses/by/4.0/",
+            Self::CcBySa => "https://creativecommons.org/licenses/by-sa/4.0/",
+            Self::CcByNd => "https://creativecommons.org/licenses/by-nd/4.0/",
+            Self::CcByNc => "https://creativecommons.org/licenses/by-nc/4.0/",
+            Self::CcByNcSa => "https://creativecommons.org/licenses/by-nc-sa/4.0/",
+            Self::CcByNcNd => "https://creativecommons.org/licenses/by-nc-nd/4.0/",
+        })
+    }
+}
+
+impl QueryValue for ContentLicense {
+    fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
+        srql::to_value(self)
+            .ok()
+            .map(|value| (field, srql::Operator::Equal, value))
+    }
+}
+
+/// Picks the license for new content: the one asked for, or else the
+/// account's default.
+pub async fn resolve_license(
+    persist: &Persist,
+    requested: Option<ContentLicense>,
+    account_id: Option<&srql::Thing>,
+) -> Result<ContentLicense> {
+    if let Some(license) = requested {
+        return Ok(license);
+    }
+    let Some(account_id) = account_id else {
+        return Ok(ContentLicense::default());
+    };
+    let account: Option<Account> = persist.load(account_id.clone()).await?;
+    Ok(account.map(|acc| acc.default_license).unwrap_or_default())
+}
+
+/// Checks that an attribution URL is an absolute `http` or `https` URL, so
+/// that clients can link to it safely.
+pub fn check_attribution_url(url: Option<&str>) -> Result<()> {
+    let Some(url) = url else {
+        return Ok(());
+    };
+    let valid = url.len() <= MAX_ATTRIBUTION_URL_LEN
+        && url.parse::<hyper::Uri>().is_ok_and(|uri| {
+            matches!(uri.scheme_str(), Some("http" | "https")) && uri.host().is_some()
+        });
+    if !valid {
+        return Err(Error::InputInvalid(
+            "attributionUrl must be an http or https URL".into(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use test_case::test_case;
+
+    use super::*;
+
+    #[test_case(None, true)]
+    #[test_case(Some("https://example.com/photo"), true)]
+    #[test_case(Some("http://example.com"), true)]
+    #[test_case(Some("javascript:alert(1)"), false)]
+    #[test_case(Some("/relative"), false)]
+    #[test_case(Some("ftp://example.com/file"), false)]
+    fn test_check_attribution_url(url: Option<&str>, valid: bool) {
+        assert_eq!(check_attribution_url(url).is_ok(), valid);
+    }
+
+    #[test]
+    fn test_license_url() {
+        assert_eq!(ContentLicense::AllRightsReserved.url(), None);
+        assert_eq!(
+            ContentLicense::CcBySa.url(),
+            Some("https://creativecommons.org/licenses/by-sa/4.0/")
+        );
+    }
+}
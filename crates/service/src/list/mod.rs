@@ -0,0 +1,9 @@
+mod models;
+mod persist;
+mod schema;
+
+pub use models::*;
+pub use persist::*;
+pub use schema::*;
+
+pub static LIST_TABLE_NAME: &str = "list";
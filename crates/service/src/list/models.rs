@@ -0,0 +1,118 @@
+use async_graphql::{ComplexObject, InputObject, MaybeUndefined, SimpleObject, ID};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use surrealdb::sql::Thing;
+
+use super::LIST_TABLE_NAME;
+use crate::{id_obj_impls, prelude::*};
+
+/// A named group of followed accounts, curated by its owner.
+#[derive(SimpleObject, Debug, Clone, Deserialize)]
+#[graphql(complex)]
+pub struct List {
+    #[graphql(skip)]
+    pub id: Thing,
+    #[graphql(skip)]
+    pub owner_id: Thing,
+    #[graphql(skip)]
+    pub member_ids: Vec<Thing>,
+
+    /// The list's name.
+    pub name: String,
+    /// The list's description.
+    pub description: Option<String>,
+    /// Whether the list is private. Private lists can only be seen by their
+    /// owner.
+    pub private: bool,
+
+    /// A timestamp indicating the last time the list was updated.
+    pub updated_at: DateTime<Utc>,
+}
+
+#[ComplexObject]
+impl List {
+    /// The list's unique ID.
+    ///
+    /// This cannot change, and can be safely used to refer to the list permanently.
+    async fn id(&self) -> ID {
+        self.id.to_gql_id()
+    }
+
+    /// The ID of the account that owns this list.
+    async fn owner_id(&self) -> ID {
+        self.owner_id.to_gql_id()
+    }
+
+    /// The IDs of the accounts in this list.
+    async fn member_ids(&self) -> Vec<ID> {
+        self.member_ids.iter().map(ToGqlId::to_gql_id).collect()
+    }
+}
+
+id_obj_impls!(List);
+
+impl List {
+    pub fn create(owner_id: Thing, params: CreateList, ids: &dyn IdGen) -> srql::CreateStatement {
+        let mut create = vec![];
+        owner_id.push_field(srql::field("owner_id"), &mut create);
+        create.push((
+            srql::field("member_ids"),
+            srql::Operator::Equal,
+            srql::array(vec![]),
+        ));
+        params.append(&mut create);
+        srql::obj_create_query(LIST_TABLE_NAME, create, ids)
+    }
+
+    /// Whether the list can be seen by the given account.
+    pub fn visible_to(&self, account: Option<&Thing>) -> bool {
+        !self.private || account == Some(&self.owner_id)
+    }
+}
+
+#[derive(InputObject, Debug, Default, Clone, PartialEq, Eq)]
+pub struct CreateList {
+    /// The list's name.
+    #[graphql(validator(min_length = 1, max_length = 1024))]
+    pub name: String,
+    /// The list's description.
+    #[graphql(validator(max_length = 32_768))]
+    pub description: Option<String>,
+    /// Whether the list is private. Defaults to `false`.
+    pub private: Option<bool>,
+}
+
+impl CreateObject for CreateList {
+    fn append(self, expr: &mut srql::SetExpr) {
+        self.name.push_field(srql::field("name"), expr);
+        self.description
+            .push_field(srql::field("description"), expr);
+        self.private
+            .unwrap_or_default()
+            .push_field(srql::field("private"), expr);
+    }
+}
+
+#[derive(InputObject, Debug, Default, Clone, PartialEq, Eq)]
+pub struct UpdateList {
+    /// The new name. If not given, the name is not changed.
+    #[graphql(validator(min_length = 1, max_length = 1024))]
+    pub name: Option<String>,
+    /// The new description. If not given, the description is not changed. If
+    /// null is given, the description is cleared.
+    #[graphql(validator(max_length = 32_768))]
+    pub description: MaybeUndefined<String>,
+    /// The new privacy setting. If not given, the privacy is not changed.
+    pub private: Option<bool>,
+}
+
+impl IntoUpdateQuery for UpdateList {
+    fn into_update(self, thing: srql::Thing) -> Option<srql::UpdateStatement> {
+        let mut update = vec![];
+        self.name.push_field(srql::field("name"), &mut update);
+        self.description
+            .push_field(srql::field("description"), &mut update);
+        self.private.push_field(srql::field("private"), &mut update);
+        srql::obj_update_query(thing, update)
+    }
+}
@@ -0,0 +1,218 @@
+#[cfg(test)]
+mod tests;
+
+use tracing::instrument;
+
+use super::{CreateList, List, UpdateList, LIST_TABLE_NAME};
+use crate::{
+    account::CurrentAccount,
+    follow::FollowPersist,
+    persist::Persist,
+    post::{PostListRequest, PostPersist},
+    prelude::*,
+    quota::{QuotaPersist, QuotaResource},
+};
+
+pub struct ListPersist<'a> {
+    persist: &'a Persist,
+    current: &'a CurrentAccount,
+}
+
+impl<'a> ListPersist<'a> {
+    pub fn new(persist: &'a Persist, current: &'a CurrentAccount) -> Self {
+        Self { persist, current }
+    }
+
+    /// Gets a list by its ID. Private lists are only returned to their owner.
+    #[instrument(skip_all)]
+    pub async fn get(&self, id: &str) -> Result<Option<List>> {
+        let list: Option<List> = self.persist.db().select((LIST_TABLE_NAME, id)).await?;
+        let current = self.current.id().ok().map(ToAccountThing::to_account_thing);
+        Ok(list.filter(|list| list.visible_to(current.as_ref())))
+    }
+
+    /// Lists all of the lists owned by the current account.
+    #[instrument(skip_all)]
+    pub async fn owned(&self) -> Result<Vec<List>> {
+        let owner = self.current.id()?.to_account_thing();
+        let lists = self
+            .persist
+            .db()
+            .query(srql::SelectStatement {
+                expr: srql::Fields::all(),
+                what: srql::table(LIST_TABLE_NAME),
+                cond: srql::Cond(
+                    srql::Expression::Binary {
+                        l: srql::field("owner_id").into(),
+                        o: srql::Operator::Equal,
+                        r: owner.into(),
+                    }
+                    .into(),
+                )
+                .into(),
+                order: srql::Orders(vec![srql::Order {
+                    order: srql::field("id"),
+                    direction: true,
+                    ..Default::default()
+                }])
+                .into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        Ok(lists)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn create(&self, list: CreateList) -> Result<List> {
+        let owner = self.current.id()?.to_account_thing();
+        QuotaPersist::new(self.persist, self.current)
+            .require_available(&owner, &[(QuotaResource::Lists, 1)])
+            .await?;
+
+        let list = self
+            .persist
+            .db()
+            .query(List::create(owner, list, self.persist.ids()))
+            .await?
+            .take(0)?;
+
+        match list {
+            Some(list) => Ok(list),
+            None => Err(Error::UnavailableIdent),
+        }
+    }
+
+    #[instrument(skip_all)]
+    pub async fn update(&self, id: &str, update: UpdateList) -> Result<Option<List>> {
+        let Some(list) = self.get_owned(id).await? else {
+            return Ok(None);
+        };
+
+        let list = if let Some(update) = update.into_update(list.id.clone()) {
+            self.persist.db().query(update).await?.take(0)?
+        } else {
+            Some(list)
+        };
+
+        Ok(list)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn delete(&self, id: &str) -> Result<Option<List>> {
+        if self.get_owned(id).await?.is_none() {
+            return Ok(None);
+        }
+
+        let list = self.persist.db().delete((LIST_TABLE_NAME, id)).await?;
+        Ok(list)
+    }
+
+    /// Adds an account to a list. The current account must follow the
+    /// account being added.
+    #[instrument(skip_all)]
+    pub async fn add_member(&self, id: &str, account_id: &str) -> Result<Option<List>> {
+        let Some(list) = self.get_owned(id).await? else {
+            return Ok(None);
+        };
+
+        if !FollowPersist::new(self.persist, self.current)
+            .is_following(account_id)
+            .await?
+        {
+            return Err(Error::NotFollowing);
+        }
+
+        self.update_members(list, srql::Operator::Ext, account_id)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    pub async fn remove_member(&self, id: &str, account_id: &str) -> Result<Option<List>> {
+        let Some(list) = self.get_owned(id).await? else {
+            return Ok(None);
+        };
+
+        self.update_members(list, srql::Operator::Dec, account_id)
+            .await
+    }
+
+    /// Builds a request for the posts made by the members of a list.
+    #[instrument(skip_all)]
+    pub async fn timeline(&self, id: &str) -> Result<Option<PostListRequest<'a>>> {
+        let Some(list) = self.get(id).await? else {
+            return Ok(None);
+        };
+
+        Ok(Some(
+            PostPersist::new(self.persist, self.current)
+                .list()
+                .with_creators(list.member_ids),
+        ))
+    }
+
+    /// Gets a list that the current account is allowed to modify.
+    async fn get_owned(&self, id: &str) -> Result<Option<List>> {
+        let owner = self.current.id()?.to_account_thing();
+        let list: Option<List> = self.persist.db().select((LIST_TABLE_NAME, id)).await?;
+        match list {
+            Some(list) if list.owner_id != owner => Err(Error::Unauthorized),
+            list => Ok(list),
+        }
+    }
+
+    async fn update_members(
+        &self,
+        list: List,
+        op: srql::Operator,
+        account_id: &str,
+    ) -> Result<Option<List>> {
+        let Some(update) = srql::obj_update_query(
+            list.id,
+            vec![(
+                srql::field("member_ids"),
+                op,
+                account_id.to_account_thing().into(),
+            )],
+        ) else {
+            return Err("".into());
+        };
+
+        Ok(self.persist.db().query(update).await?.take(0)?)
+    }
+}
+
+#[cfg(test)]
+pub mod testing {
+    use async_trait::async_trait;
+
+    use crate::{
+        account::testing::TestData,
+        list::{CreateList, List},
+    };
+
+    use super::ListPersist;
+
+    #[async_trait]
+    pub trait ListTestData {
+        fn list(&self) -> ListPersist<'_>;
+
+        async fn generate_list(&self) -> List {
+            self.list()
+                .create(CreateList {
+                    name: "Test".into(),
+                    description: Some("Test".into()),
+                    private: None,
+                })
+                .await
+                .unwrap()
+        }
+    }
+
+    #[async_trait]
+    impl ListTestData for TestData {
+        fn list(&self) -> ListPersist<'_> {
+            ListPersist::new(&self.persist, &self.current)
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use async_graphql::MaybeUndefined;
+use pretty_assertions::assert_eq;
+
+use super::{testing::ListTestData as _, *};
+use crate::{
+    account::testing::*,
+    follow::testing::FollowTestData as _,
+    post::{testing::PostTestData as _, CreatePost},
+    query::PaginationInput,
+};
+
+#[tokio::test]
+async fn test_create() {
+    let (data, acc) = TestData::with_user().await;
+    let list_persist = data.list();
+
+    let list = CreateList {
+        name: "Test".into(),
+        description: Some("Test".into()),
+        private: Some(true),
+    };
+
+    let res = list_persist.create(list).await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+
+    let res = res.unwrap();
+    assert_eq!(res.owner_id, acc.id);
+    assert_eq!(res.name, "Test");
+    assert_eq!(res.description, Some("Test".to_owned()));
+    assert!(res.private);
+    assert!(res.member_ids.is_empty());
+}
+
+#[tokio::test]
+async fn test_create_anon() {
+    let data = TestData::new().await;
+    let list_persist = data.list();
+
+    let res = list_persist
+        .create(CreateList {
+            name: "Test".into(),
+            ..Default::default()
+        })
+        .await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap_err(), Error::Unauthenticated);
+}
+
+#[tokio::test]
+async fn test_owned() {
+    let (mut data, acc) = TestData::with_user().await;
+    let a = data.generate_list().await;
+    let b = data.generate_list().await;
+
+    let other = data.account().create_test_user().await;
+    data.login_as(&other);
+    data.generate_list().await;
+    data.login_as(&acc);
+
+    let mut expected = vec![a, b];
+    expected.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let res = data.list().owned().await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+    assert_eq!(res.unwrap(), expected);
+}
+
+#[tokio::test]
+async fn test_private_visibility() {
+    let (mut data, acc) = TestData::with_user().await;
+    let list = data
+        .list()
+        .create(CreateList {
+            name: "Private".into(),
+            private: Some(true),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let list_id = list.id.id.to_raw();
+
+    let res = data.list().get(&list_id).await;
+    println!("{res:?}");
+    assert!(res.unwrap().is_some());
+
+    let other = data.account().create_test_user().await;
+    data.login_as(&other);
+    let res = data.list().get(&list_id).await;
+    println!("{res:?}");
+    assert!(res.unwrap().is_none());
+
+    let res = data.list().timeline(&list_id).await;
+    assert!(res.unwrap().is_none());
+
+    data.login_as(&acc);
+    let res = data
+        .list()
+        .update(
+            &list_id,
+            UpdateList {
+                private: Some(false),
+                ..Default::default()
+            },
+        )
+        .await;
+    println!("{res:?}");
+    assert!(!res.unwrap().unwrap().private);
+
+    data.login_as(&other);
+    let res = data.list().get(&list_id).await;
+    println!("{res:?}");
+    assert!(res.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn test_update() {
+    let (data, _) = TestData::with_user().await;
+    let list = data.generate_list().await;
+
+    let res = data
+        .list()
+        .update(
+            &list.id.id.to_raw(),
+            UpdateList {
+                name: Some("Updated".into()),
+                description: MaybeUndefined::Null,
+                private: None,
+            },
+        )
+        .await;
+    println!("{res:?}");
+
+    let res = res.unwrap().unwrap();
+    assert_eq!(res.id, list.id);
+    assert_eq!(res.name, "Updated");
+    assert_eq!(res.description, None);
+    assert_eq!(res.private, list.private);
+    assert_ne!(res.updated_at, list.updated_at);
+}
+
+#[tokio::test]
+async fn test_update_not_owner() {
+    let (mut data, _) = TestData::with_user().await;
+    let list = data.generate_list().await;
+
+    let other = data.account().create_test_user().await;
+    data.login_as(&other);
+
+    let res = data
+        .list()
+        .update(
+            &list.id.id.to_raw(),
+            UpdateList {
+                name: Some("Updated".into()),
+                ..Default::default()
+            },
+        )
+        .await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap_err(), Error::Unauthorized);
+
+    let res = data.list().delete(&list.id.id.to_raw()).await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap_err(), Error::Unauthorized);
+}
+
+#[tokio::test]
+async fn test_delete() {
+    let (data, _) = TestData::with_user().await;
+    let list = data.generate_list().await;
+
+    let res = data.list().delete(&list.id.id.to_raw()).await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap(), Some(list.clone()));
+
+    let res = data.list().get(&list.id.id.to_raw()).await;
+    println!("{res:?}");
+    assert!(res.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_members() {
+    let (data, _) = TestData::with_user().await;
+    let list = data.generate_list().await;
+    let list_id = list.id.id.to_raw();
+    let a = data.generate_followed().await;
+    let b = data.generate_followed().await;
+
+    for acc in [&a, &b, &a] {
+        let res = data.list().add_member(&list_id, &acc.id.id.to_raw()).await;
+        println!("{res:?}");
+        assert!(res.is_ok());
+    }
+
+    let res = data.list().get(&list_id).await.unwrap().unwrap();
+    assert_eq!(res.member_ids, vec![a.id.clone(), b.id.clone()]);
+
+    let res = data.list().remove_member(&list_id, &a.id.id.to_raw()).await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap().unwrap().member_ids, vec![b.id]);
+}
+
+#[tokio::test]
+async fn test_add_unfollowed_member() {
+    let (data, _) = TestData::with_user().await;
+    let list = data.generate_list().await;
+    let other = data.account().create_test_user().await;
+
+    let res = data
+        .list()
+        .add_member(&list.id.id.to_raw(), &other.id.id.to_raw())
+        .await;
+    println!("{res:?}");
+    assert_eq!(res.unwrap_err(), Error::NotFollowing);
+}
+
+#[tokio::test]
+async fn test_timeline() {
+    let (mut data, acc) = TestData::with_user().await;
+    let list = data.generate_list().await;
+    let list_id = list.id.id.to_raw();
+    let member = data.generate_followed().await;
+    let followed = data.generate_followed().await;
+    data.list()
+        .add_member(&list_id, &member.id.id.to_raw())
+        .await
+        .unwrap();
+
+    // Posts by the list owner and followed accounts outside the list should
+    // not show up.
+    data.generate_post().await;
+    data.login_as(&followed);
+    data.generate_post().await;
+    data.login_as(&member);
+    let mut expected = vec![];
+    for i in 0..3 {
+        expected.push(
+            data.post()
+                .create(CreatePost {
+                    content: Some(format!("Test {i}")),
+                    ..Default::default()
+                })
+                .await
+                .unwrap(),
+        );
+    }
+    expected.sort_by(|a, b| b.id.cmp(&a.id));
+    data.login_as(&acc);
+
+    let res = data
+        .list()
+        .timeline(&list_id)
+        .await
+        .unwrap()
+        .unwrap()
+        .with_pagination(PaginationInput::new().forward(10))
+        .execute()
+        .await;
+    assert!(res.is_ok());
+
+    let res: Vec<_> = res.unwrap().edges.into_iter().map(|e| e.node).collect();
+    println!("{res:?}");
+    assert_eq!(res, expected);
+}
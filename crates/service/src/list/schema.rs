@@ -0,0 +1,121 @@
+use async_graphql::{connection::Connection, Context, Object, ID};
+use tracing::instrument;
+
+use super::{CreateList, List, UpdateList};
+use crate::{
+    policy::PoliciesAccepted,
+    post::{Post, PostCursor},
+    prelude::*,
+    query::{page_complexity, PaginationArgs},
+};
+
+#[derive(Default)]
+pub struct ListQuery;
+
+#[Object]
+impl ListQuery {
+    /// Gets a list by its ID.
+    ///
+    /// Private lists can only be fetched by their owner.
+    #[instrument(skip_all)]
+    async fn list(&self, ctx: &Context<'_>, id: ID) -> GqlResult<Option<List>> {
+        ctx.list_persist().get(&id).await.extend()
+    }
+
+    /// Lists the lists owned by the current account.
+    #[instrument(skip_all)]
+    async fn lists(&self, ctx: &Context<'_>) -> GqlResult<Vec<List>> {
+        ctx.list_persist().owned().await.extend()
+    }
+
+    /// Lists the posts made by the members of a list.
+    #[graphql(complexity = "page_complexity(first, last, child_complexity)")]
+    #[instrument(skip_all)]
+    async fn list_timeline(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> GqlResult<Option<Connection<PostCursor, Post>>> {
+        let pagination = PaginationArgs {
+            after,
+            before,
+            first,
+            last,
+        }
+        .warn_if_clamped(ctx)
+        .validate()
+        .extend()?;
+
+        let Some(timeline) = ctx.list_persist().timeline(&id).await.extend()? else {
+            return Ok(None);
+        };
+
+        timeline
+            .with_pagination(pagination)
+            .execute()
+            .await
+            .map(Some)
+            .extend()
+    }
+}
+
+#[derive(Default)]
+pub struct ListMutation;
+
+#[Object(guard = "PoliciesAccepted")]
+impl ListMutation {
+    /// Creates a new list.
+    #[instrument(skip_all)]
+    async fn create_list(&self, ctx: &Context<'_>, create: CreateList) -> GqlResult<List> {
+        ctx.list_persist().create(create).await.extend()
+    }
+
+    /// Updates a list.
+    #[instrument(skip_all)]
+    async fn update_list(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        update: UpdateList,
+    ) -> GqlResult<Option<List>> {
+        ctx.list_persist().update(&id, update).await.extend()
+    }
+
+    /// Deletes a list.
+    #[instrument(skip_all)]
+    async fn delete_list(&self, ctx: &Context<'_>, id: ID) -> GqlResult<Option<List>> {
+        ctx.list_persist().delete(&id).await.extend()
+    }
+
+    /// Adds a followed account to a list.
+    #[instrument(skip_all)]
+    async fn add_list_member(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        account_id: ID,
+    ) -> GqlResult<Option<List>> {
+        ctx.list_persist()
+            .add_member(&id, &account_id)
+            .await
+            .extend()
+    }
+
+    /// Removes an account from a list.
+    #[instrument(skip_all)]
+    async fn remove_list_member(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        account_id: ID,
+    ) -> GqlResult<Option<List>> {
+        ctx.list_persist()
+            .remove_member(&id, &account_id)
+            .await
+            .extend()
+    }
+}
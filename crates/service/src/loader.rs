@@ -0,0 +1,125 @@
+//! Batching the records that resolvers load while handling a single GraphQL
+//! request.
+//!
+//! GraphQL resolves the fields of every item in a list at the same time, so
+//! a page of posts that each look up their quoted post or author would
+//! otherwise make a query per post. Instead, each load queues its record ID
+//! and gives way to the resolvers running alongside it, and the first to
+//! carry on selects every queued record of that type at once. What it loads
+//! is kept in the request's [`RequestMemo`], which the rest then find it in.
+//!
+//! Any type with an `id` can be loaded like this by implementing
+//! [`Loadable`], which `id_obj_impls!` does for the types it's used with.
+
+use std::{
+    any::TypeId,
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+};
+
+use serde::de::DeserializeOwned;
+use surrealdb::{sql::Thing, Result as SrlResult};
+
+use crate::{
+    db::Db,
+    memo::{Memoized, RequestMemo},
+    prelude::*,
+};
+
+/// A record that can be loaded in batches by its ID.
+pub trait Loadable: DeserializeOwned + Clone + Send + Sync + 'static {
+    /// The ID that the record was loaded by.
+    fn record_id(&self) -> &Thing;
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct RequestLoader(Arc<LoaderState>);
+
+#[derive(Debug, Default)]
+struct LoaderState {
+    /// The records waiting to be loaded, by their type.
+    queued: Mutex<HashMap<TypeId, Vec<Thing>>>,
+    /// Held while a batch is loaded, so that loads waiting on it don't start
+    /// batches of their own.
+    loading: tokio::sync::Mutex<()>,
+    batches: AtomicUsize,
+}
+
+impl RequestLoader {
+    /// Loads a record by its ID, along with the other records of its type
+    /// that are being loaded at the same time.
+    pub async fn load<T: Loadable>(
+        &self,
+        db: Db<'_>,
+        memo: &RequestMemo,
+        thing: Thing,
+    ) -> SrlResult<Option<T>> {
+        if let Memoized::Hit(record) = memo.get::<T>(&thing) {
+            return Ok(record);
+        }
+
+        self.queue::<T>(thing.clone());
+        tokio::task::yield_now().await;
+
+        let _loading = self.0.loading.lock().await;
+        let generation = match memo.get::<T>(&thing) {
+            Memoized::Hit(record) => return Ok(record),
+            Memoized::Miss(generation) => generation,
+        };
+        // If a write cleared the memo after an earlier batch loaded this
+        // record, it has to be loaded again.
+        let mut batch = self.take::<T>();
+        if !batch.contains(&thing) {
+            batch.push(thing.clone());
+        }
+
+        self.0.batches.fetch_add(1, Ordering::Relaxed);
+        let records: Vec<T> = db
+            .query(srql::SelectStatement {
+                expr: srql::Fields::all(),
+                what: srql::Values(batch.iter().cloned().map(Into::into).collect()),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        // Things are keyed by their text, as they can hold values that clippy
+        // won't let be used as keys.
+        let mut records: HashMap<String, T> = records
+            .into_iter()
+            .map(|record| (record.record_id().to_string(), record))
+            .collect();
+
+        let record = records.get(&thing.to_string()).cloned();
+        for id in batch {
+            let loaded = records.remove(&id.to_string());
+            memo.store(id, generation, loaded);
+        }
+        Ok(record)
+    }
+
+    fn queue<T: Loadable>(&self, thing: Thing) {
+        let mut queued = self.0.queued.lock().unwrap_or_else(PoisonError::into_inner);
+        let queued = queued.entry(TypeId::of::<T>()).or_default();
+        if !queued.contains(&thing) {
+            queued.push(thing);
+        }
+    }
+
+    fn take<T: Loadable>(&self) -> Vec<Thing> {
+        self.0
+            .queued
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&TypeId::of::<T>())
+            .unwrap_or_default()
+    }
+
+    /// How many batches have been loaded.
+    #[cfg(test)]
+    pub fn batches(&self) -> usize {
+        self.0.batches.load(Ordering::Relaxed)
+    }
+}
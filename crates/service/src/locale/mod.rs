@@ -0,0 +1,281 @@
+//! Text that the server writes itself, such as notification titles and link
+//! previews, rendered in the reader's language.
+//!
+//! Messages live in Fluent catalogs under `locales/`, one for each language,
+//! and are built into the server. A message is looked up in each of the
+//! reader's preferred locales in turn, then in the language of each of them
+//! without its region, and finally in the default locale, so a catalog that
+//! is missing a message never leaves the reader without any text.
+//!
+//! Accounts can also choose a timezone, which is returned with the account so
+//! that clients can show times in it.
+
+mod timezone;
+
+use async_graphql::Context;
+use fluent_bundle::{concurrent::FluentBundle, FluentArgs, FluentResource, FluentValue};
+use tracing::warn;
+pub use unic_langid::LanguageIdentifier;
+
+pub use self::timezone::*;
+
+use crate::{prelude::*, session::ClientMeta};
+
+/// The catalogs built into the server. The first is the default locale.
+static CATALOGS: &[(&str, &str)] = &[
+    ("en", include_str!("../../locales/en.ftl")),
+    ("fr", include_str!("../../locales/fr.ftl")),
+];
+
+/// The longest locale tag that an account can choose.
+pub const MAX_LOCALE_LENGTH: usize = 35;
+
+type Bundle = FluentBundle<FluentResource>;
+
+/// Renders messages from the built-in catalogs.
+pub struct Localizer {
+    bundles: Vec<(LanguageIdentifier, Bundle)>,
+}
+
+impl Localizer {
+    /// Loads the built-in catalogs.
+    ///
+    /// # Panics
+    ///
+    /// If one of the catalogs is invalid, which the tests check for.
+    #[must_use]
+    pub fn new() -> Self {
+        let bundles = CATALOGS
+            .iter()
+            .map(|(locale, source)| {
+                let locale: LanguageIdentifier = locale.parse().expect("catalog locale is invalid");
+                let resource = FluentResource::try_new((*source).to_owned())
+                    .unwrap_or_else(|_| panic!("catalog for {locale} is invalid"));
+                let mut bundle = Bundle::new_concurrent(vec![locale.clone()]);
+                // The text ends up in plain text and HTML attributes, where
+                // the bidi isolation marks would show up as junk.
+                bundle.set_use_isolating(false);
+                bundle
+                    .add_resource(resource)
+                    .unwrap_or_else(|_| panic!("catalog for {locale} has duplicate messages"));
+                (locale, bundle)
+            })
+            .collect();
+        Self { bundles }
+    }
+
+    /// The catalogs to look messages up in, most preferred first.
+    fn chain(&self, requested: &[LanguageIdentifier]) -> Vec<&Bundle> {
+        let exact =
+            |locale: &LanguageIdentifier| self.bundles.iter().position(|(l, _)| l == locale);
+        let language = |locale: &LanguageIdentifier| {
+            self.bundles
+                .iter()
+                .position(|(l, _)| l.language == locale.language)
+        };
+
+        let mut order: Vec<usize> = vec![];
+        let candidates = requested
+            .iter()
+            .flat_map(|locale| [exact(locale), language(locale)])
+            .chain([Some(0)])
+            .flatten();
+        for index in candidates {
+            if !order.contains(&index) {
+                order.push(index);
+            }
+        }
+
+        order
+            .into_iter()
+            .filter_map(|index| self.bundles.get(index).map(|(_, bundle)| bundle))
+            .collect()
+    }
+
+    /// Renders a message in the most preferred locale that has it. If no
+    /// catalog has the message, its key is returned instead.
+    ///
+    /// Arguments that look like numbers are passed to the message as
+    /// numbers, so that it can pick the right plural form.
+    #[must_use]
+    pub fn render(
+        &self,
+        requested: &[LanguageIdentifier],
+        key: &str,
+        args: &[(&str, &str)],
+    ) -> String {
+        let mut fluent_args = FluentArgs::new();
+        for (name, value) in args {
+            fluent_args.set(*name, FluentValue::try_number(value));
+        }
+
+        for bundle in self.chain(requested) {
+            let Some(pattern) = bundle.get_message(key).and_then(|msg| msg.value()) else {
+                continue;
+            };
+            let mut errors = vec![];
+            let text = bundle.format_pattern(pattern, Some(&fluent_args), &mut errors);
+            if !errors.is_empty() {
+                warn!(key, ?errors, "Message rendered with errors");
+            }
+            return text.into_owned();
+        }
+
+        warn!(key, "Message is missing from every catalog");
+        key.to_owned()
+    }
+}
+
+impl Default for Localizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Parses a locale tag such as `en-GB`, if it is valid.
+#[must_use]
+pub fn parse_locale(tag: &str) -> Option<LanguageIdentifier> {
+    if tag.len() > MAX_LOCALE_LENGTH {
+        return None;
+    }
+    tag.trim().parse().ok()
+}
+
+/// Parses an `Accept-Language` header into the locales it lists, most
+/// preferred first. Wildcards and invalid tags are skipped.
+#[must_use]
+pub fn parse_accept_language(header: &str) -> Vec<LanguageIdentifier> {
+    let mut locales: Vec<(f32, LanguageIdentifier)> = header
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.split(';');
+            let locale = parse_locale(parts.next()?.trim())?;
+            let quality = parts
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.trim().parse().ok())?;
+            (quality > 0.0).then_some((quality, locale))
+        })
+        .collect();
+    // A stable sort keeps the header's order for equal qualities.
+    locales.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+    locales.into_iter().map(|(_, locale)| locale).collect()
+}
+
+/// The locales to render messages in for the current request: the current
+/// account's chosen locale, followed by those the client asked for.
+pub async fn viewer_locales(ctx: &Context<'_>) -> Result<Vec<LanguageIdentifier>> {
+    let mut locales = vec![];
+    if let Ok(id) = ctx.current_account().id() {
+        if let Some(locale) = ctx
+            .account_persist()
+            .get(id)
+            .await?
+            .and_then(|acc| acc.locale)
+            .as_deref()
+            .and_then(parse_locale)
+        {
+            locales.push(locale);
+        }
+    }
+    if let Some(client) = ctx.data_opt::<ClientMeta>() {
+        locales.extend(client.locales.iter().cloned());
+    }
+    Ok(locales)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    /// The IDs of the messages in a catalog. Attributes and multiline
+    /// values are indented, and terms start with `-`, so only messages
+    /// start a line with a letter.
+    fn keys(source: &str) -> BTreeSet<&str> {
+        source
+            .lines()
+            .filter(|line| line.starts_with(|c: char| c.is_ascii_alphabetic()))
+            .filter_map(|line| line.split_once('=').map(|(key, _)| key.trim()))
+            .collect()
+    }
+
+    fn locales(tags: &[&str]) -> Vec<LanguageIdentifier> {
+        tags.iter().map(|tag| tag.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_catalogs_have_every_key() {
+        let (default, source) = CATALOGS[0];
+        let expected = keys(source);
+        assert!(!expected.is_empty());
+        for (locale, source) in &CATALOGS[1..] {
+            assert_eq!(
+                keys(source),
+                expected,
+                "{locale} has different messages to {default}"
+            );
+        }
+        // Loading every catalog also checks that none of them are invalid.
+        assert_eq!(Localizer::new().bundles.len(), CATALOGS.len());
+    }
+
+    #[test]
+    fn test_fallback_chain() {
+        let localizer = Localizer::new();
+        let render = |tags: &[&str]| {
+            localizer.render(&locales(tags), "notification-quote", &[("actor", "alice")])
+        };
+
+        assert_eq!(render(&[]), "@alice quoted your post");
+        assert_eq!(render(&["fr"]), "@alice a cité votre publication");
+        // Regional variants fall back to their language.
+        assert_eq!(render(&["fr-CA"]), "@alice a cité votre publication");
+        // Unknown locales are skipped in favour of the next preference.
+        assert_eq!(render(&["xx", "fr"]), "@alice a cité votre publication");
+        assert_eq!(render(&["de"]), "@alice quoted your post");
+
+        assert_eq!(
+            localizer.render(&locales(&["fr"]), "missing-key", &[]),
+            "missing-key"
+        );
+    }
+
+    #[test]
+    fn test_plurals() {
+        let localizer = Localizer::new();
+        let render = |tags: &[&str], others: &str| {
+            localizer.render(
+                &locales(tags),
+                "notification-quote-batch",
+                &[("actor", "alice"), ("others", others)],
+            )
+        };
+
+        assert_eq!(render(&[], "1"), "@alice and 1 other quoted your post");
+        assert_eq!(render(&[], "11"), "@alice and 11 others quoted your post");
+        assert_eq!(
+            render(&["fr"], "1"),
+            "@alice et 1 autre personne ont cité votre publication"
+        );
+        assert_eq!(
+            render(&["fr"], "2"),
+            "@alice et 2 autres personnes ont cité votre publication"
+        );
+    }
+
+    #[test]
+    fn test_accept_language() {
+        assert_eq!(
+            parse_accept_language("fr-CH, fr;q=0.9, en;q=0.8, de;q=0.7, *;q=0.5"),
+            locales(&["fr-CH", "fr", "en", "de"])
+        );
+        assert_eq!(
+            parse_accept_language("en;q=0.5, de, fr;q=0"),
+            locales(&["de", "en"])
+        );
+        assert!(parse_accept_language("").is_empty());
+    }
+}
@@ -0,0 +1,68 @@
+use async_graphql::SimpleObject;
+use chrono::{DateTime, Offset as _, TimeZone as _, Utc};
+pub use chrono_tz::Tz;
+
+/// Parses an IANA timezone name such as `Europe/London`, if it is one.
+#[must_use]
+pub fn parse_timezone(name: &str) -> Option<Tz> {
+    name.trim().parse().ok()
+}
+
+/// A timezone, and what it looks like at the time of the request, so that
+/// clients can show times in it.
+#[derive(SimpleObject, Debug, Clone, PartialEq, Eq)]
+pub struct TimeZoneInfo {
+    /// The IANA name of the timezone, such as `Europe/London`.
+    pub name: String,
+    /// How far ahead of UTC the timezone currently is, in seconds. This is
+    /// negative for timezones behind UTC.
+    pub utc_offset: i32,
+    /// The timezone's current abbreviation, such as `BST`. Some timezones
+    /// only have numeric abbreviations, such as `+03`.
+    pub abbreviation: String,
+}
+
+impl TimeZoneInfo {
+    #[must_use]
+    pub fn new(tz: Tz, at: DateTime<Utc>) -> Self {
+        let offset = tz.offset_from_utc_datetime(&at.naive_utc());
+        Self {
+            name: tz.name().to_owned(),
+            utc_offset: offset.fix().local_minus_utc(),
+            abbreviation: offset.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse_timezone("Europe/London"), Some(Tz::Europe__London));
+        assert_eq!(parse_timezone(" UTC "), Some(Tz::UTC));
+        assert_eq!(parse_timezone("Mars/Olympus_Mons"), None);
+        assert_eq!(parse_timezone(""), None);
+    }
+
+    #[test]
+    fn test_daylight_saving() {
+        let winter = Utc.with_ymd_and_hms(2023, 1, 15, 12, 0, 0).unwrap();
+        let summer = Utc.with_ymd_and_hms(2023, 7, 15, 12, 0, 0).unwrap();
+
+        let info = TimeZoneInfo::new(Tz::Europe__London, winter);
+        assert_eq!(info.utc_offset, 0);
+        assert_eq!(info.abbreviation, "GMT");
+
+        let info = TimeZoneInfo::new(Tz::Europe__London, summer);
+        assert_eq!(info.utc_offset, 3600);
+        assert_eq!(info.abbreviation, "BST");
+
+        let info = TimeZoneInfo::new(Tz::America__New_York, summer);
+        assert_eq!(info.name, "America/New_York");
+        assert_eq!(info.utc_offset, -4 * 3600);
+    }
+}
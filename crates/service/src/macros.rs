@@ -33,5 +33,11 @@ macro_rules! id_obj_impls {
                 other.eq(self)
             }
         }
+
+        impl $crate::loader::Loadable for $ty {
+            fn record_id(&self) -> &surrealdb::sql::Thing {
+                &self.id
+            }
+        }
     };
 }
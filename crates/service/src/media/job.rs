@@ -0,0 +1,42 @@
+use std::time::Duration;
+
+use tokio::{
+    task::JoinHandle,
+    time::{interval, MissedTickBehavior},
+};
+use tracing::{debug, error, trace};
+
+use super::collect_garbage;
+use crate::persist::Persist;
+
+/// How often unreferenced blobs are looked for.
+pub const COLLECTION_INTERVAL: Duration = Duration::from_hours(1);
+
+static COLLECTION_LOCK: &str = "media_collection";
+
+/// Spawns a task that periodically removes the blobs that haven't been used
+/// by any media for at least `collect_after`.
+pub fn spawn_collection(persist: Persist, collect_after: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = interval(COLLECTION_INTERVAL);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+
+            let res = persist
+                .execute_in_lock(COLLECTION_LOCK, || async {
+                    let _job = persist.metrics().track_job();
+                    collect_garbage(&persist, collect_after).await
+                })
+                .await;
+
+            match res {
+                Ok(Some(Ok(collected))) => debug!(collected, "Unreferenced media collected"),
+                Ok(Some(Err(err))) => error!(error = ?err, "Failed to collect unreferenced media"),
+                Ok(None) => trace!("Unreferenced media is already being collected"),
+                Err(err) => error!(error = ?err, "Failed to lock media collection"),
+            }
+        }
+    })
+}
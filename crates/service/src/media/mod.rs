@@ -0,0 +1,27 @@
+//! Media that accounts upload, such as images to go with their posts.
+//!
+//! Media is stored by the hash of its content, so when the same image is
+//! uploaded many times, as popular images tend to be, its bytes are only
+//! stored once. Each stored blob counts the media that use it, and once none
+//! do it's collected after a grace period.
+//!
+//! Content is fetched through signed URLs that stop working after a while, so
+//! that it can't be hotlinked forever. They can be limited to a single account
+//! or to pages on certain hosts as well.
+
+mod job;
+mod models;
+mod persist;
+mod schema;
+mod store;
+mod url;
+
+pub use job::*;
+pub use models::*;
+pub use persist::*;
+pub use schema::*;
+pub use store::*;
+pub use url::*;
+
+pub static MEDIA_TABLE_NAME: &str = "media";
+static BLOB_TABLE_NAME: &str = "media_blob";
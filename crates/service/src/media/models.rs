@@ -0,0 +1,234 @@
+use async_graphql::{ComplexObject, Context, InputObject, MaybeUndefined, SimpleObject, ID};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use surrealdb::sql::Thing;
+
+use super::{MediaUrlScope, MediaUrls};
+use crate::{
+    id_obj_impls,
+    license::{check_attribution_url, ContentLicense},
+    prelude::*,
+};
+
+/// The kinds of media that can be uploaded, by the top-level part of their
+/// content type.
+static MEDIA_TYPES: &[&str] = &["image", "video", "audio"];
+
+/// Puts a content type into the form that it's stored in, lowercased and
+/// without parameters, so that uploads of the same media are described the
+/// same way. Content types that aren't for media are rejected.
+pub fn canonical_content_type(content_type: &str) -> Option<String> {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    let (top, sub) = essence.split_once('/')?;
+    let token = |part: &str| {
+        !part.is_empty()
+            && part
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "!#$&-^_.+".contains(c))
+    };
+    // SVGs can contain scripts, which would run if they were opened from the
+    // instance's origin.
+    (MEDIA_TYPES.contains(&top) && token(sub) && essence != "image/svg+xml").then_some(essence)
+}
+
+/// A media file that an account uploaded.
+///
+/// Uploads with the same content share the same stored bytes, but each has
+/// its own ID and owner.
+#[derive(SimpleObject, Debug, Clone, Deserialize)]
+#[graphql(complex)]
+pub struct Media {
+    #[graphql(skip)]
+    pub id: Thing,
+    #[graphql(skip)]
+    pub owner_id: Thing,
+    #[graphql(skip)]
+    pub blob_id: Thing,
+
+    /// The media's content type, such as `image/png`.
+    pub content_type: String,
+    /// The size of the media in bytes.
+    pub size: u64,
+    /// The license that the media is published under.
+    #[serde(default)]
+    pub license: ContentLicense,
+    /// Who the media should be credited to, if it isn't the account that
+    /// uploaded it.
+    pub attribution: Option<String>,
+    /// Where the media came from, such as the page it was first published on.
+    pub attribution_url: Option<String>,
+    /// A description of the media for people who can't see or hear it.
+    pub alt_text: Option<String>,
+
+    /// A timestamp indicating the last time the media was updated.
+    pub updated_at: DateTime<Utc>,
+}
+
+#[ComplexObject]
+impl Media {
+    /// The media's unique ID.
+    async fn id(&self) -> ID {
+        self.id.to_gql_id()
+    }
+
+    /// The ID of the account that uploaded the media.
+    async fn owner_id(&self) -> ID {
+        self.owner_id.to_gql_id()
+    }
+
+    /// The hex-encoded SHA-256 hash of the media's content.
+    async fn hash(&self) -> ID {
+        self.blob_id.to_gql_id()
+    }
+
+    /// The URL of the text of the media's license, if it has one.
+    async fn license_url(&self) -> Option<&'static str> {
+        self.license.url()
+    }
+
+    /// A signed URL that the media's content can be fetched from, until it
+    /// expires. Account-scoped URLs only work for requests authenticated as
+    /// the current account, while public ones work for anyone with the URL.
+    async fn url(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] scope: MediaUrlScope,
+    ) -> GqlResult<String> {
+        let account_id = match scope {
+            MediaUrlScope::Account => Some(ctx.current_account().id().extend()?.0.as_str()),
+            MediaUrlScope::Public => None,
+        };
+        ctx.data_unchecked::<MediaUrls>()
+            .sign(self, account_id)
+            .extend()
+    }
+}
+
+id_obj_impls!(Media);
+
+impl Media {
+    pub fn is_image(&self) -> bool {
+        self.content_type.starts_with("image/")
+    }
+
+    /// Whether the media is an image without any alt text describing it.
+    pub fn missing_alt_text(&self) -> bool {
+        self.is_image()
+            && self
+                .alt_text
+                .as_deref()
+                .is_none_or(|alt_text| alt_text.trim().is_empty())
+    }
+}
+
+#[derive(InputObject, Debug, Default, Clone, PartialEq, Eq)]
+pub struct UpdateMedia {
+    /// The license that the media is published under. If not given, the
+    /// license is not changed.
+    pub license: Option<ContentLicense>,
+    /// Who the media should be credited to. If not given, the attribution is
+    /// not changed. If null is given, it is cleared.
+    #[graphql(validator(max_length = 256))]
+    pub attribution: MaybeUndefined<String>,
+    /// Where the media came from. This must be an `http` or `https` URL. If
+    /// not given, the URL is not changed. If null is given, it is cleared.
+    pub attribution_url: MaybeUndefined<String>,
+    /// A description of the media for people who can't see or hear it. If
+    /// not given, the alt text is not changed. If null is given, it is
+    /// cleared.
+    #[graphql(validator(max_length = 1500))]
+    pub alt_text: MaybeUndefined<String>,
+}
+
+impl UpdateMedia {
+    pub fn validate(&self) -> Result<()> {
+        check_attribution_url(self.attribution_url.as_opt_deref().flatten())
+    }
+}
+
+impl IntoUpdateQuery for UpdateMedia {
+    fn into_update(self, thing: srql::Thing) -> Option<srql::UpdateStatement> {
+        let mut update = vec![];
+        self.license.push_field(srql::field("license"), &mut update);
+        self.attribution
+            .push_field(srql::field("attribution"), &mut update);
+        self.attribution_url
+            .push_field(srql::field("attribution_url"), &mut update);
+        self.alt_text
+            .push_field(srql::field("alt_text"), &mut update);
+        srql::obj_update_query(thing, update)
+    }
+}
+
+/// How many of the images attached to posts have alt text, so that
+/// moderators can see how accessible the instance's posts are.
+#[derive(SimpleObject, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[graphql(complex)]
+pub struct AccessibilityReport {
+    /// How many posts have images attached.
+    pub posts_with_images: u64,
+    /// How many of those posts have at least one image without alt text.
+    pub posts_missing_alt_text: u64,
+    /// How many images are attached to posts. An image attached to more than
+    /// one post is counted for each.
+    pub images: u64,
+    /// How many of those images have alt text.
+    pub described_images: u64,
+}
+
+#[ComplexObject]
+impl AccessibilityReport {
+    /// The fraction of attached images that have alt text, from 0 to 1. This
+    /// is 1 when no images are attached.
+    #[allow(clippy::cast_precision_loss)]
+    async fn coverage(&self) -> f64 {
+        if self.images == 0 {
+            1.0
+        } else {
+            self.described_images as f64 / self.images as f64
+        }
+    }
+}
+
+/// The stored bytes of media, shared by every upload with the same content.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Blob {
+    pub id: Thing,
+    pub size: u64,
+    /// How many media records refer to the blob.
+    #[serde(default)]
+    pub ref_count: i64,
+    /// When the last media record referring to the blob was removed.
+    pub unreferenced_at: Option<DateTime<Utc>>,
+    /// Whether the blob is being removed. Uploads of the same content wait
+    /// until it's gone.
+    #[serde(default)]
+    pub collecting: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use test_case::test_case;
+
+    use super::*;
+
+    #[test_case("image/png", Some("image/png"))]
+    #[test_case("Image/JPEG; charset=binary", Some("image/jpeg"))]
+    #[test_case(" video/mp4 ", Some("video/mp4"))]
+    #[test_case("audio/ogg;codecs=opus", Some("audio/ogg"))]
+    #[test_case("image/svg+xml", None)]
+    #[test_case("text/html", None)]
+    #[test_case("application/octet-stream", None)]
+    #[test_case("image/", None ; "empty subtype")]
+    #[test_case("image", None ; "no subtype")]
+    #[test_case("image/p ng", None)]
+    fn test_canonical_content_type(content_type: &str, expected: Option<&str>) {
+        assert_eq!(canonical_content_type(content_type).as_deref(), expected);
+    }
+}
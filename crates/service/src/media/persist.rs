@@ -0,0 +1,531 @@
+#[cfg(test)]
+mod tests;
+
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use axum::body::Bytes;
+use chrono::Duration as ChronoDuration;
+use serde::Deserialize;
+use tokio::time::sleep;
+use tracing::{error, instrument, warn};
+
+use super::{
+    blob_key, canonical_content_type, AccessibilityReport, Blob, Media, UpdateMedia,
+    BLOB_TABLE_NAME, MEDIA_TABLE_NAME,
+};
+use crate::{
+    account::{require_permission, CurrentAccount, Permission},
+    board::BOARD_TABLE_NAME,
+    license::resolve_license,
+    persist::Persist,
+    post::POST_TABLE_NAME,
+    prelude::*,
+    quota::{QuotaPersist, QuotaResource},
+};
+
+/// How many times an upload checks whether a blob with the same content has
+/// finished being collected, before giving up.
+const COLLECTING_RETRIES: u32 = 10;
+const COLLECTING_RETRY_DELAY: Duration = Duration::from_millis(50);
+
+pub struct MediaPersist<'a> {
+    persist: &'a Persist,
+    current: &'a CurrentAccount,
+}
+
+impl<'a> MediaPersist<'a> {
+    pub fn new(persist: &'a Persist, current: &'a CurrentAccount) -> Self {
+        Self { persist, current }
+    }
+
+    #[instrument(skip_all)]
+    pub async fn get(&self, id: &str) -> Result<Option<Media>> {
+        Ok(self.persist.db().select((MEDIA_TABLE_NAME, id)).await?)
+    }
+
+    /// Gets media by their IDs, in the same order. Media that doesn't exist
+    /// is left out.
+    #[instrument(skip_all)]
+    pub async fn get_many(&self, ids: &[srql::Thing]) -> Result<Vec<Media>> {
+        if ids.is_empty() {
+            return Ok(vec![]);
+        }
+        let media: Vec<Media> = self
+            .persist
+            .db()
+            .query(srql::SelectStatement {
+                expr: srql::Fields::all(),
+                what: srql::Values(ids.iter().cloned().map(srql::Value::from).collect()),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        let mut media: HashMap<_, _> = media.into_iter().map(|m| (m.id.to_string(), m)).collect();
+        Ok(ids
+            .iter()
+            .filter_map(|id| media.remove(&id.to_string()))
+            .collect())
+    }
+
+    /// Reports how many of the images attached to posts have alt text, either
+    /// across the instance or in a single board. Only moderators and admins
+    /// can see this.
+    #[instrument(skip_all)]
+    pub async fn accessibility_report(
+        &self,
+        board_id: Option<&str>,
+    ) -> Result<AccessibilityReport> {
+        #[derive(Deserialize)]
+        struct Attachments {
+            #[serde(default)]
+            media_ids: Vec<srql::Thing>,
+        }
+
+        require_permission(self.persist, self.current, Permission::ModerateContent).await?;
+
+        let binary = |l: srql::Idiom, o, r| -> srql::Value {
+            srql::Expression::Binary { l: l.into(), o, r }.into()
+        };
+        let mut cond = binary(
+            srql::field("media_ids"),
+            srql::Operator::NotEqual,
+            srql::Value::None,
+        );
+        if let Some(board_id) = board_id {
+            cond = srql::Expression::Binary {
+                l: cond,
+                o: srql::Operator::And,
+                r: binary(
+                    srql::field("board_id"),
+                    srql::Operator::Equal,
+                    srql::Thing::from((BOARD_TABLE_NAME, board_id)).into(),
+                ),
+            }
+            .into();
+        }
+        let posts: Vec<Attachments> = self
+            .persist
+            .db()
+            .query(srql::SelectStatement {
+                expr: srql::Fields(
+                    vec![srql::Field::Single {
+                        expr: srql::field("media_ids").into(),
+                        alias: None,
+                    }],
+                    false,
+                ),
+                what: srql::table(POST_TABLE_NAME),
+                cond: srql::Cond(cond).into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+
+        let mut seen = HashSet::new();
+        let ids: Vec<_> = posts
+            .iter()
+            .flat_map(|post| &post.media_ids)
+            .filter(|id| seen.insert(id.to_string()))
+            .cloned()
+            .collect();
+        let media: HashMap<_, _> = self
+            .get_many(&ids)
+            .await?
+            .into_iter()
+            .map(|m| (m.id.to_string(), m))
+            .collect();
+
+        let mut report = AccessibilityReport::default();
+        for post in &posts {
+            let images: Vec<_> = post
+                .media_ids
+                .iter()
+                .filter_map(|id| media.get(&id.to_string()))
+                .filter(|media| media.is_image())
+                .collect();
+            if images.is_empty() {
+                continue;
+            }
+            let described = images.iter().filter(|m| !m.missing_alt_text()).count() as u64;
+            report.posts_with_images += 1;
+            report.images += images.len() as u64;
+            report.described_images += described;
+            if described < images.len() as u64 {
+                report.posts_missing_alt_text += 1;
+            }
+        }
+        Ok(report)
+    }
+
+    /// Uploads media for the current account.
+    ///
+    /// If media with the same content has already been uploaded, the stored
+    /// bytes are shared rather than stored again.
+    #[instrument(skip_all)]
+    pub async fn upload(&self, content_type: &str, bytes: Bytes) -> Result<Media> {
+        let owner_id = self.current.id()?.to_account_thing();
+        let Some(content_type) = canonical_content_type(content_type) else {
+            return Err(Error::InputInvalid(
+                "content type must be for an image, video or audio".into(),
+            ));
+        };
+        let size = bytes.len() as u64;
+        if size > self.persist.limits().media_bytes {
+            return Err(Error::TooLong("media".into()));
+        }
+        if size == 0 {
+            return Err(Error::InputInvalid("media must not be empty".into()));
+        }
+        QuotaPersist::new(self.persist, self.current)
+            .require_available(&owner_id, &[(QuotaResource::Storage, size)])
+            .await?;
+
+        let key = blob_key(&bytes);
+        let blob_id = srql::Thing::from((BLOB_TABLE_NAME, &*key));
+        self.reference(&blob_id, size).await?;
+
+        // The blob can't be collected while it's referenced, so it's safe to
+        // store the bytes now. They're stored whenever they're missing, in
+        // case an earlier upload failed part way through.
+        let stored = match self.persist.blobs().contains(&key).await {
+            Ok(true) => Ok(()),
+            Ok(false) => self.persist.blobs().put(&key, bytes).await,
+            Err(err) => Err(err),
+        };
+        let media = match stored {
+            Ok(()) => self.create(owner_id, &blob_id, content_type, size).await,
+            Err(err) => Err(err),
+        };
+        if media.is_err() {
+            if let Err(err) = release(self.persist, &blob_id).await {
+                error!(error = ?err, %blob_id, "Failed to release blob after failed upload");
+            }
+        }
+        media
+    }
+
+    /// Changes the media's license or attribution. Only the account that
+    /// uploaded it can do this.
+    #[instrument(skip_all)]
+    pub async fn update(&self, id: &str, update: UpdateMedia) -> Result<Option<Media>> {
+        let owner_id = self.current.id()?.to_account_thing();
+        update.validate()?;
+        let Some(media) = self
+            .get(id)
+            .await?
+            .filter(|media| media.owner_id == owner_id)
+        else {
+            return Ok(None);
+        };
+
+        let media = match update.into_update(media.id.clone()) {
+            Some(update) => self.persist.db().query(update).await?.take(0)?,
+            None => Some(media),
+        };
+        Ok(media)
+    }
+
+    /// Deletes media. Only the account that uploaded it can do this.
+    ///
+    /// The stored bytes are kept for a while after the last media using them
+    /// has been deleted, and then collected.
+    #[instrument(skip_all)]
+    pub async fn delete(&self, id: &str) -> Result<Option<Media>> {
+        let owner_id = self.current.id()?.to_account_thing();
+        let media: Option<Media> = self
+            .persist
+            .db()
+            .query(srql::DeleteStatement {
+                what: srql::thing((MEDIA_TABLE_NAME, id)),
+                cond: srql::Cond(
+                    srql::Expression::Binary {
+                        l: srql::field("owner_id").into(),
+                        o: srql::Operator::Equal,
+                        r: owner_id.into(),
+                    }
+                    .into(),
+                )
+                .into(),
+                output: srql::Output::Before.into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+
+        if let Some(media) = &media {
+            release(self.persist, &media.blob_id).await?;
+        }
+        Ok(media)
+    }
+
+    /// Adds a reference to a blob, creating it if it doesn't exist yet.
+    ///
+    /// If the blob is being collected, this waits for it to be gone so that
+    /// it can be created again.
+    async fn reference(&self, blob_id: &srql::Thing, size: u64) -> Result<()> {
+        for _ in 0..COLLECTING_RETRIES {
+            let blob: Option<Blob> = self
+                .persist
+                .db()
+                .query(srql::UpdateStatement {
+                    what: srql::thing(blob_id.clone()),
+                    data: srql::Data::SetExpression(vec![
+                        (srql::field("ref_count"), srql::Operator::Inc, 1.into()),
+                        (srql::field("size"), srql::Operator::Equal, size.into()),
+                        (
+                            srql::field("unreferenced_at"),
+                            srql::Operator::Equal,
+                            srql::Value::None,
+                        ),
+                    ])
+                    .into(),
+                    cond: srql::Cond(
+                        srql::Expression::Binary {
+                            l: srql::field("collecting").into(),
+                            o: srql::Operator::NotEqual,
+                            r: true.into(),
+                        }
+                        .into(),
+                    )
+                    .into(),
+                    output: srql::Output::After.into(),
+                    ..Default::default()
+                })
+                .await?
+                .take(0)?;
+            if blob.is_some() {
+                return Ok(());
+            }
+            sleep(COLLECTING_RETRY_DELAY).await;
+        }
+
+        warn!(%blob_id, "Blob is still being collected, giving up on upload");
+        Err(Error::Overloaded)
+    }
+
+    async fn create(
+        &self,
+        owner_id: srql::Thing,
+        blob_id: &srql::Thing,
+        content_type: String,
+        size: u64,
+    ) -> Result<Media> {
+        let license = resolve_license(self.persist, None, Some(&owner_id)).await?;
+        let media: Option<Media> = self
+            .persist
+            .db()
+            .query(srql::obj_create_query(
+                MEDIA_TABLE_NAME,
+                vec![
+                    (
+                        srql::field("owner_id"),
+                        srql::Operator::Equal,
+                        owner_id.into(),
+                    ),
+                    (
+                        srql::field("blob_id"),
+                        srql::Operator::Equal,
+                        blob_id.clone().into(),
+                    ),
+                    (
+                        srql::field("content_type"),
+                        srql::Operator::Equal,
+                        content_type.into(),
+                    ),
+                    (srql::field("size"), srql::Operator::Equal, size.into()),
+                    (
+                        srql::field("license"),
+                        srql::Operator::Equal,
+                        srql::to_value(license).map_err(Error::from_err)?,
+                    ),
+                ],
+                self.persist.ids(),
+            ))
+            .await?
+            .take(0)?;
+
+        media.ok_or(Error::UnavailableIdent)
+    }
+}
+
+/// Removes a reference to a blob, noting when it became unreferenced if that
+/// was the last one.
+async fn release(persist: &Persist, blob_id: &srql::Thing) -> Result<()> {
+    let now = srql::Value::Datetime(srql::Datetime(persist.clock().now()));
+    persist
+        .db()
+        .query(srql::query([
+            srql::trans_begin(),
+            srql::Statement::Update(srql::UpdateStatement {
+                what: srql::thing(blob_id.clone()),
+                data: srql::Data::SetExpression(vec![(
+                    srql::field("ref_count"),
+                    srql::Operator::Dec,
+                    1.into(),
+                )])
+                .into(),
+                output: srql::Output::None.into(),
+                ..Default::default()
+            }),
+            srql::Statement::Update(srql::UpdateStatement {
+                what: srql::thing(blob_id.clone()),
+                data: srql::Data::SetExpression(vec![(
+                    srql::field("unreferenced_at"),
+                    srql::Operator::Equal,
+                    now,
+                )])
+                .into(),
+                cond: unreferenced_cond().into(),
+                output: srql::Output::None.into(),
+                ..Default::default()
+            }),
+            srql::trans_end(),
+        ]))
+        .await?
+        .check()?;
+    Ok(())
+}
+
+/// Removes the blobs that haven't been referenced for at least
+/// `collect_after`, returning how many were removed.
+///
+/// Each blob is marked as being collected before its bytes are removed, so
+/// that uploads of the same content wait rather than reference bytes that are
+/// about to go. Blobs that were marked but not removed, because the service
+/// stopped part way through, are removed the next time this runs.
+#[instrument(skip(persist))]
+pub async fn collect_garbage(persist: &Persist, collect_after: Duration) -> Result<u64> {
+    let cutoff = persist.clock().now()
+        - ChronoDuration::from_std(collect_after).unwrap_or_else(|_| ChronoDuration::zero());
+    let expired = srql::cond_and(
+        unreferenced_cond().into(),
+        srql::Cond(
+            srql::Expression::Binary {
+                l: srql::field("unreferenced_at").into(),
+                o: srql::Operator::LessThanOrEqual,
+                r: srql::Value::Datetime(srql::Datetime(cutoff)),
+            }
+            .into(),
+        )
+        .into(),
+    );
+    let cond = srql::Cond(
+        srql::Expression::Binary {
+            l: collecting_cond().0,
+            o: srql::Operator::Or,
+            r: expired.map_or(srql::Value::None, |cond| cond.0),
+        }
+        .into(),
+    );
+
+    let blobs: Vec<Blob> = persist
+        .db()
+        .query(srql::SelectStatement {
+            expr: srql::Fields::all(),
+            what: srql::table(BLOB_TABLE_NAME),
+            cond: cond.into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+
+    let mut collected = 0;
+    for blob in blobs {
+        // Only blobs that are still unreferenced can be marked, so an upload
+        // that got to the blob first keeps it.
+        let marked: Option<Blob> = persist
+            .db()
+            .query(srql::UpdateStatement {
+                what: srql::thing(blob.id.clone()),
+                data: srql::Data::SetExpression(vec![(
+                    srql::field("collecting"),
+                    srql::Operator::Equal,
+                    true.into(),
+                )])
+                .into(),
+                cond: unreferenced_cond().into(),
+                output: srql::Output::After.into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        if marked.is_none() {
+            continue;
+        }
+
+        persist.blobs().delete(&blob.id.to_gql_id()).await?;
+        persist.db().delete::<Option<Blob>>(blob.id).await?;
+        collected += 1;
+    }
+    Ok(collected)
+}
+
+/// The bytes of media that an account has uploaded, counting each upload
+/// separately even when their content is shared.
+pub(crate) async fn owned_media_bytes(persist: &Persist, owner_id: &srql::Thing) -> Result<u64> {
+    let cond = srql::Cond(
+        srql::Expression::Binary {
+            l: srql::field("owner_id").into(),
+            o: srql::Operator::Equal,
+            r: owner_id.clone().into(),
+        }
+        .into(),
+    );
+    sum_size(persist, MEDIA_TABLE_NAME, cond.into()).await
+}
+
+/// The bytes of media that are actually stored, counting shared content once.
+pub(crate) async fn stored_media_bytes(persist: &Persist) -> Result<u64> {
+    sum_size(persist, BLOB_TABLE_NAME, None).await
+}
+
+async fn sum_size(persist: &Persist, table: &str, cond: Option<srql::Cond>) -> Result<u64> {
+    let sum: Option<u64> = persist
+        .db()
+        .query(srql::sum_query(table, "size", cond))
+        .await?
+        .take("sum")?;
+    Ok(sum.unwrap_or_default())
+}
+
+fn unreferenced_cond() -> srql::Cond {
+    srql::Cond(
+        srql::Expression::Binary {
+            l: srql::field("ref_count").into(),
+            o: srql::Operator::LessThanOrEqual,
+            r: 0.into(),
+        }
+        .into(),
+    )
+}
+
+fn collecting_cond() -> srql::Cond {
+    srql::Cond(
+        srql::Expression::Binary {
+            l: srql::field("collecting").into(),
+            o: srql::Operator::Equal,
+            r: true.into(),
+        }
+        .into(),
+    )
+}
+
+#[cfg(test)]
+pub mod testing {
+    use crate::account::testing::TestData;
+
+    use super::MediaPersist;
+
+    pub trait MediaTestData {
+        fn media(&self) -> MediaPersist<'_>;
+    }
+
+    impl MediaTestData for TestData {
+        fn media(&self) -> MediaPersist<'_> {
+            MediaPersist::new(&self.persist, &self.current)
+        }
+    }
+}
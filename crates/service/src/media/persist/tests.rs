@@ -0,0 +1,361 @@
+use async_graphql::{MaybeUndefined, ID};
+use chrono::{TimeZone as _, Utc};
+use pretty_assertions::assert_eq;
+
+use super::{testing::MediaTestData as _, *};
+use crate::{
+    account::testing::*,
+    account::UpdateAccount,
+    board::testing::BoardTestData as _,
+    config::{LimitsConfig, QuotaConfig},
+    license::ContentLicense,
+    post::{testing::PostTestData as _, CreatePost},
+    provider::MockClock,
+};
+
+static PNG: &[u8] = b"\x89PNG\r\n\x1a\nnot really";
+
+async fn blob(data: &TestData, media: &Media) -> Option<Blob> {
+    data.persist
+        .db()
+        .select(media.blob_id.clone())
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_upload() {
+    let (data, acc) = TestData::with_user().await;
+
+    let media = data
+        .media()
+        .upload("Image/PNG; charset=binary", Bytes::from_static(PNG))
+        .await;
+    println!("{media:?}");
+    assert!(media.is_ok());
+
+    let media = media.unwrap();
+    assert_eq!(media.owner_id, acc.id);
+    assert_eq!(media.content_type, "image/png");
+    assert_eq!(media.size, PNG.len() as u64);
+    assert_eq!(media.blob_id.to_gql_id().0, blob_key(PNG));
+    assert!(data.persist.blobs().contains(&blob_key(PNG)).await.unwrap());
+    assert_eq!(
+        data.media().get(&media.id.to_gql_id()).await.unwrap(),
+        Some(media)
+    );
+}
+
+#[tokio::test]
+async fn test_upload_same_content() {
+    let (mut data, _) = TestData::with_user().await;
+    let first = data
+        .media()
+        .upload("image/png", Bytes::from_static(PNG))
+        .await
+        .unwrap();
+    let acc = data.account().create_test_user().await;
+    data.login_as(&acc);
+    let second = data
+        .media()
+        .upload("image/png", Bytes::from_static(PNG))
+        .await
+        .unwrap();
+
+    assert_ne!(first.id, second.id);
+    assert_eq!(first.blob_id, second.blob_id);
+    let blob = blob(&data, &first).await.unwrap();
+    assert_eq!(blob.ref_count, 2);
+    assert_eq!(blob.size, PNG.len() as u64);
+    assert_eq!(
+        stored_media_bytes(&data.persist).await.unwrap(),
+        PNG.len() as u64
+    );
+}
+
+#[tokio::test]
+async fn test_upload_invalid() {
+    let (mut data, _) = TestData::with_user().await;
+    data.persist = data.persist.with_limits(LimitsConfig {
+        media_bytes: 4,
+        ..Default::default()
+    });
+
+    let res = data
+        .media()
+        .upload("text/html", Bytes::from_static(b"<p>"))
+        .await;
+    assert!(matches!(res, Err(Error::InputInvalid(_))), "{res:?}");
+    let res = data.media().upload("image/png", Bytes::new()).await;
+    assert!(matches!(res, Err(Error::InputInvalid(_))), "{res:?}");
+    let res = data
+        .media()
+        .upload("image/png", Bytes::from_static(PNG))
+        .await;
+    assert_eq!(res.unwrap_err(), Error::TooLong("media".into()));
+
+    let res = TestData::new()
+        .await
+        .media()
+        .upload("image/png", Bytes::from_static(b"png"))
+        .await;
+    assert_eq!(res.unwrap_err(), Error::Unauthenticated);
+}
+
+#[tokio::test]
+async fn test_upload_quota() {
+    let (mut data, acc) = TestData::with_user().await;
+    data.persist = data.persist.with_quotas(QuotaConfig {
+        storage_bytes: PNG.len() as u64 + 4,
+        ..Default::default()
+    });
+
+    data.media()
+        .upload("image/png", Bytes::from_static(PNG))
+        .await
+        .unwrap();
+    assert_eq!(
+        owned_media_bytes(&data.persist, &acc.id).await.unwrap(),
+        PNG.len() as u64
+    );
+    // Sharing stored content doesn't make it free.
+    let res = data
+        .media()
+        .upload("image/png", Bytes::from_static(PNG))
+        .await;
+    assert_eq!(res.unwrap_err(), Error::QuotaExceeded("storage".into()));
+}
+
+#[tokio::test]
+async fn test_delete() {
+    let (mut data, _) = TestData::with_user().await;
+    let media = data
+        .media()
+        .upload("image/png", Bytes::from_static(PNG))
+        .await
+        .unwrap();
+    let id = media.id.to_gql_id();
+
+    let acc = data.account().create_test_user().await;
+    let owner = std::mem::take(&mut data.current);
+    data.login_as(&acc);
+    assert_eq!(data.media().delete(&id).await.unwrap(), None);
+
+    data.current = owner;
+    assert_eq!(data.media().delete(&id).await.unwrap(), Some(media.clone()));
+    assert_eq!(data.media().get(&id).await.unwrap(), None);
+    assert_eq!(data.media().delete(&id).await.unwrap(), None);
+
+    let blob = blob(&data, &media).await.unwrap();
+    assert_eq!(blob.ref_count, 0);
+    assert!(blob.unreferenced_at.is_some());
+}
+
+#[tokio::test]
+async fn test_license() {
+    let (mut data, _) = TestData::with_user().await;
+    data.account()
+        .update(UpdateAccount {
+            default_license: Some(ContentLicense::CcByNc),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let media = data
+        .media()
+        .upload("image/png", Bytes::from_static(PNG))
+        .await
+        .unwrap();
+    assert_eq!(media.license, ContentLicense::CcByNc);
+    let id = media.id.to_gql_id();
+
+    let update = UpdateMedia {
+        license: Some(ContentLicense::Cc0),
+        attribution: MaybeUndefined::Value("A Photographer".into()),
+        ..Default::default()
+    };
+    let acc = data.account().create_test_user().await;
+    let owner = std::mem::take(&mut data.current);
+    data.login_as(&acc);
+    assert_eq!(data.media().update(&id, update.clone()).await, Ok(None));
+
+    data.current = owner;
+    let media = data.media().update(&id, update).await.unwrap().unwrap();
+    assert_eq!(media.license, ContentLicense::Cc0);
+    assert_eq!(media.attribution.as_deref(), Some("A Photographer"));
+
+    let res = data
+        .media()
+        .update(
+            &id,
+            UpdateMedia {
+                attribution_url: MaybeUndefined::Value("not a url".into()),
+                ..Default::default()
+            },
+        )
+        .await;
+    assert!(matches!(res, Err(Error::InputInvalid(_))), "{res:?}");
+}
+
+#[tokio::test]
+async fn test_collect_garbage() {
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap());
+    let mut data = TestData::with_clock(clock.clone()).await;
+    let acc = data.account().create_test_user().await;
+    data.login_as(&acc);
+    let grace = Duration::from_hours(1);
+
+    let first = data
+        .media()
+        .upload("image/png", Bytes::from_static(PNG))
+        .await
+        .unwrap();
+    let second = data
+        .media()
+        .upload("image/png", Bytes::from_static(PNG))
+        .await
+        .unwrap();
+    data.media().delete(&first.id.to_gql_id()).await.unwrap();
+
+    // Still used by the second upload.
+    clock.advance(ChronoDuration::hours(2));
+    data.login_as(&acc);
+    assert_eq!(collect_garbage(&data.persist, grace).await.unwrap(), 0);
+
+    // Kept until the grace period has passed.
+    data.media().delete(&second.id.to_gql_id()).await.unwrap();
+    assert_eq!(collect_garbage(&data.persist, grace).await.unwrap(), 0);
+    assert!(data.persist.blobs().contains(&blob_key(PNG)).await.unwrap());
+
+    clock.advance(ChronoDuration::hours(2));
+    data.login_as(&acc);
+    assert_eq!(collect_garbage(&data.persist, grace).await.unwrap(), 1);
+    assert_eq!(blob(&data, &second).await, None);
+    assert!(!data.persist.blobs().contains(&blob_key(PNG)).await.unwrap());
+
+    // The same content can be uploaded again afterwards.
+    let media = data
+        .media()
+        .upload("image/png", Bytes::from_static(PNG))
+        .await
+        .unwrap();
+    assert_eq!(blob(&data, &media).await.unwrap().ref_count, 1);
+    assert!(data.persist.blobs().contains(&blob_key(PNG)).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_collect_interrupted() {
+    let (data, _) = TestData::with_user().await;
+    let media = data
+        .media()
+        .upload("image/png", Bytes::from_static(PNG))
+        .await
+        .unwrap();
+    data.media().delete(&media.id.to_gql_id()).await.unwrap();
+
+    // As if the service stopped after marking the blob.
+    data.persist
+        .db()
+        .query("UPDATE $blob SET collecting = true")
+        .bind(("blob", media.blob_id.clone()))
+        .await
+        .unwrap();
+    let res = data
+        .media()
+        .upload("image/png", Bytes::from_static(PNG))
+        .await;
+    assert_eq!(res.unwrap_err(), Error::Overloaded);
+
+    let grace = Duration::from_hours(1);
+    assert_eq!(collect_garbage(&data.persist, grace).await.unwrap(), 1);
+    assert_eq!(blob(&data, &media).await, None);
+}
+
+async fn post_with_media(data: &TestData, board_id: Option<ID>, media: &[&Media]) {
+    data.post()
+        .create(CreatePost {
+            board_id,
+            title: Some("Test".into()),
+            media_ids: Some(media.iter().map(|media| media.id.to_gql_id()).collect()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_accessibility_report() {
+    let (mut data, _) = TestData::with_user().await;
+    let described = data
+        .media()
+        .upload("image/png", Bytes::from_static(PNG))
+        .await
+        .unwrap();
+    let described = data
+        .media()
+        .update(
+            &described.id.to_gql_id(),
+            UpdateMedia {
+                alt_text: MaybeUndefined::Value("A cat".into()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    let undescribed = data
+        .media()
+        .upload("image/jpeg", Bytes::from_static(PNG))
+        .await
+        .unwrap();
+    let audio = data
+        .media()
+        .upload("audio/ogg", Bytes::from_static(PNG))
+        .await
+        .unwrap();
+    let board = data.generate_board().await;
+
+    post_with_media(&data, None, &[&described]).await;
+    post_with_media(&data, None, &[&undescribed, &audio]).await;
+    post_with_media(&data, None, &[&audio]).await;
+    post_with_media(&data, Some(board.id.to_gql_id()), &[&described]).await;
+    data.post()
+        .create(CreatePost {
+            title: Some("No media".into()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    let report = data.media().accessibility_report(None).await.unwrap();
+    assert_eq!(
+        report,
+        AccessibilityReport {
+            posts_with_images: 3,
+            posts_missing_alt_text: 1,
+            images: 3,
+            described_images: 2,
+        }
+    );
+    let report = data
+        .media()
+        .accessibility_report(Some(&board.id.to_gql_id()))
+        .await
+        .unwrap();
+    assert_eq!(
+        report,
+        AccessibilityReport {
+            posts_with_images: 1,
+            posts_missing_alt_text: 0,
+            images: 1,
+            described_images: 1,
+        }
+    );
+
+    let acc = data.account().create_test_user().await;
+    data.login_as(&acc);
+    assert_eq!(
+        data.media().accessibility_report(None).await,
+        Err(Error::Unauthorized)
+    );
+}
@@ -0,0 +1,41 @@
+use async_graphql::{Context, Object, ID};
+use tracing::instrument;
+
+use super::{Media, UpdateMedia};
+use crate::prelude::*;
+
+#[derive(Default)]
+pub struct MediaQuery;
+
+#[Object]
+impl MediaQuery {
+    /// Gets uploaded media by its ID.
+    #[instrument(skip_all)]
+    async fn media(&self, ctx: &Context<'_>, id: ID) -> GqlResult<Option<Media>> {
+        ctx.media_persist().get(&id).await.extend()
+    }
+}
+
+#[derive(Default)]
+pub struct MediaMutation;
+
+#[Object]
+impl MediaMutation {
+    /// Changes the license or attribution of media that the current account
+    /// uploaded.
+    #[instrument(skip_all)]
+    async fn update_media(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        update: UpdateMedia,
+    ) -> GqlResult<Option<Media>> {
+        ctx.media_persist().update(&id, update).await.extend()
+    }
+
+    /// Deletes media that the current account uploaded.
+    #[instrument(skip_all)]
+    async fn delete_media(&self, ctx: &Context<'_>, id: ID) -> GqlResult<Option<Media>> {
+        ctx.media_persist().delete(&id).await.extend()
+    }
+}
@@ -0,0 +1,215 @@
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    io::{ErrorKind, SeekFrom},
+    ops::Range,
+    path::PathBuf,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use async_trait::async_trait;
+use axum::body::Bytes;
+use ring::digest;
+use tokio::{
+    fs,
+    io::{AsyncReadExt as _, AsyncSeekExt as _},
+};
+
+use crate::prelude::*;
+
+pub type SharedBlobStore = Arc<dyn BlobStore>;
+
+/// Somewhere to keep the bytes of uploaded media.
+///
+/// Blobs are stored under a key derived from their content (see
+/// [`blob_key`]), so putting the same key twice always puts the same bytes.
+#[async_trait]
+pub trait BlobStore: Debug + Send + Sync {
+    /// Stores a blob, replacing any that's already stored under the key.
+    async fn put(&self, key: &str, bytes: Bytes) -> Result<()>;
+    /// Gets a blob, or only the given range of its bytes, if one is stored
+    /// under the key. Ranges that go past the end of the blob are cut short.
+    async fn get(&self, key: &str, range: Option<Range<u64>>) -> Result<Option<Bytes>>;
+    /// Whether a blob is stored under the key.
+    async fn contains(&self, key: &str) -> Result<bool>;
+    /// Removes a blob. Removing a blob that isn't stored does nothing.
+    async fn delete(&self, key: &str) -> Result<()>;
+}
+
+/// The key that a blob is stored under, which is the hex-encoded SHA-256
+/// hash of its content.
+pub fn blob_key(bytes: &[u8]) -> String {
+    hex::encode(digest::digest(&digest::SHA256, bytes))
+}
+
+/// Keeps blobs in memory, so they're lost when the service stops.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryBlobStore(Arc<Mutex<HashMap<String, Bytes>>>);
+
+impl MemoryBlobStore {
+    fn blobs(&self) -> std::sync::MutexGuard<'_, HashMap<String, Bytes>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[async_trait]
+impl BlobStore for MemoryBlobStore {
+    async fn put(&self, key: &str, bytes: Bytes) -> Result<()> {
+        self.blobs().insert(key.to_owned(), bytes);
+        Ok(())
+    }
+
+    async fn get(&self, key: &str, range: Option<Range<u64>>) -> Result<Option<Bytes>> {
+        let Some(bytes) = self.blobs().get(key).cloned() else {
+            return Ok(None);
+        };
+        Ok(Some(match range {
+            Some(range) => {
+                let index = |i: u64| usize::try_from(i).unwrap_or(usize::MAX).min(bytes.len());
+                bytes.slice(index(range.start)..index(range.end).max(index(range.start)))
+            }
+            None => bytes,
+        }))
+    }
+
+    async fn contains(&self, key: &str) -> Result<bool> {
+        Ok(self.blobs().contains_key(key))
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.blobs().remove(key);
+        Ok(())
+    }
+}
+
+/// Keeps blobs as files in a directory, split into subdirectories by the
+/// first two characters of their keys.
+#[derive(Debug, Clone)]
+pub struct FsBlobStore {
+    dir: PathBuf,
+}
+
+impl FsBlobStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf> {
+        // Keys are always hex, but check so that they can't escape the
+        // directory.
+        if key.len() < 3 || !key.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(Error::InputInvalid("blob key is invalid".into()));
+        }
+        Ok(self.dir.join(&key[..2]).join(key))
+    }
+}
+
+#[async_trait]
+impl BlobStore for FsBlobStore {
+    async fn put(&self, key: &str, bytes: Bytes) -> Result<()> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await.map_err(Error::from_err)?;
+        }
+        // Write to a temporary file first, so that a blob is never seen half
+        // written.
+        let partial = path.with_extension("partial");
+        fs::write(&partial, &bytes).await.map_err(Error::from_err)?;
+        fs::rename(&partial, &path).await.map_err(Error::from_err)
+    }
+
+    async fn get(&self, key: &str, range: Option<Range<u64>>) -> Result<Option<Bytes>> {
+        let mut file = match fs::File::open(self.path(key)?).await {
+            Ok(file) => file,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(Error::from_err(err)),
+        };
+        let mut bytes = Vec::new();
+        match range {
+            Some(range) => {
+                file.seek(SeekFrom::Start(range.start))
+                    .await
+                    .map_err(Error::from_err)?;
+                file.take(range.end.saturating_sub(range.start))
+                    .read_to_end(&mut bytes)
+                    .await
+            }
+            None => file.read_to_end(&mut bytes).await,
+        }
+        .map_err(Error::from_err)?;
+        Ok(Some(bytes.into()))
+    }
+
+    async fn contains(&self, key: &str) -> Result<bool> {
+        fs::try_exists(self.path(key)?)
+            .await
+            .map_err(Error::from_err)
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        match fs::remove_file(self.path(key)?).await {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(Error::from_err(err)),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    async fn round_trip(store: &dyn BlobStore) {
+        let bytes = Bytes::from_static(b"Hello");
+        let key = blob_key(&bytes);
+        assert!(!store.contains(&key).await.unwrap());
+        assert_eq!(store.get(&key, None).await.unwrap(), None);
+
+        store.put(&key, bytes.clone()).await.unwrap();
+        assert!(store.contains(&key).await.unwrap());
+        assert_eq!(store.get(&key, None).await.unwrap(), Some(bytes));
+        assert_eq!(
+            store.get(&key, Some(1..3)).await.unwrap(),
+            Some(Bytes::from_static(b"el"))
+        );
+        assert_eq!(
+            store.get(&key, Some(3..10)).await.unwrap(),
+            Some(Bytes::from_static(b"lo"))
+        );
+        assert_eq!(
+            store.get(&key, Some(8..10)).await.unwrap(),
+            Some(Bytes::new())
+        );
+
+        store.delete(&key).await.unwrap();
+        assert!(!store.contains(&key).await.unwrap());
+        store.delete(&key).await.unwrap();
+    }
+
+    #[test]
+    fn test_blob_key() {
+        assert_eq!(
+            blob_key(b"Hello"),
+            "185f8db32271fe25f561a6fc938b2e264306ec304eda518007d1764826381969"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_memory() {
+        round_trip(&MemoryBlobStore::default()).await;
+    }
+
+    #[tokio::test]
+    async fn test_fs() {
+        let dir = std::env::temp_dir().join(format!("plazer-blobs-{}", ulid::Ulid::new()));
+        let store = FsBlobStore::new(&dir);
+        round_trip(&store).await;
+
+        let key = blob_key(b"Hello");
+        store.put(&key, Bytes::from_static(b"Hello")).await.unwrap();
+        assert_eq!(std::fs::read(dir.join("18").join(&key)).unwrap(), b"Hello");
+        assert!(store.contains("../secret").await.is_err());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,290 @@
+use std::{ops::Bound, time::Duration};
+
+use async_graphql::Enum;
+use axum::headers::Range;
+use chrono::Duration as ChronoDuration;
+use jsonwebtoken::{Algorithm, Header, Validation};
+use serde::{Deserialize, Serialize};
+
+use super::Media;
+use crate::{config::MediaConfig, prelude::*, provider::SharedClock, DecodingKey, EncodingKey};
+
+/// Who a signed media URL works for.
+#[derive(Enum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MediaUrlScope {
+    /// Only requests authenticated as the account the URL was made for.
+    #[default]
+    Account,
+    /// Anyone who has the URL.
+    Public,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct MediaClaims {
+    /// The media the URL is for.
+    media: String,
+    /// The account the URL is for, if it's account-scoped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    acc: Option<String>,
+    exp: i64,
+}
+
+/// Makes and checks signed URLs for fetching media's content.
+///
+/// URLs carry a token signed with the instance's key, which says which media
+/// it's for, who it's for and when it stops working. They point at the
+/// instance itself, which serves the content once the token checks out.
+#[derive(Clone)]
+pub struct MediaUrls {
+    enc_key: EncodingKey,
+    dec_key: DecodingKey,
+    clock: SharedClock,
+    ttl: Duration,
+    referer_hosts: Vec<String>,
+    public_url: Option<String>,
+}
+
+impl MediaUrls {
+    pub fn new(
+        config: &MediaConfig,
+        enc_key: EncodingKey,
+        dec_key: DecodingKey,
+        clock: SharedClock,
+        public_url: Option<String>,
+    ) -> Self {
+        Self {
+            enc_key,
+            dec_key,
+            clock,
+            ttl: config.url_ttl,
+            referer_hosts: config.referer_hosts.clone(),
+            public_url,
+        }
+    }
+
+    /// Makes a URL for a media's content. Account-scoped URLs are for the
+    /// given account.
+    pub fn sign(&self, media: &Media, account_id: Option<&str>) -> Result<String> {
+        let ttl = ChronoDuration::from_std(self.ttl).unwrap_or_else(|_| ChronoDuration::zero());
+        let claims = MediaClaims {
+            media: media.id.to_gql_id().0,
+            acc: account_id.map(ToOwned::to_owned),
+            exp: (self.clock.now() + ttl).timestamp(),
+        };
+        let token = jsonwebtoken::encode(&Header::new(Algorithm::EdDSA), &claims, &self.enc_key)?;
+        let query = serde_urlencoded::to_string([("token", token)])
+            .expect("a string pair can always be encoded");
+        Ok(format!(
+            "{}/api/v1/media/{}/content?{query}",
+            self.public_url.as_deref().unwrap_or_default(),
+            claims.media,
+        ))
+    }
+
+    /// Checks a URL's token for a media, returning the account it's for if
+    /// it's account-scoped, and how many seconds it has left.
+    pub fn verify(&self, token: &str, media_id: &str) -> Result<(Option<String>, i64)> {
+        let mut validation = Validation::new(Algorithm::EdDSA);
+        // Expiry is checked against the service's clock instead.
+        validation.validate_exp = false;
+        validation.required_spec_claims.clear();
+        let claims = jsonwebtoken::decode::<MediaClaims>(token, &self.dec_key, &validation)?.claims;
+
+        let left = claims.exp - self.clock.now().timestamp();
+        if left <= 0 {
+            return Err(Error::JwtExpired);
+        }
+        if claims.media != media_id {
+            return Err(Error::JwtInvalid);
+        }
+        Ok((claims.acc, left))
+    }
+
+    /// Whether media can be embedded in a page, given the `Referer` of the
+    /// request for it. Requests without one are always allowed, as browsers
+    /// often leave it out.
+    pub fn allows_referer(&self, referer: Option<&str>) -> bool {
+        let Some(referer) = referer else {
+            return true;
+        };
+        if self.referer_hosts.is_empty() {
+            return true;
+        }
+        referer_host(referer).is_some_and(|host| self.referer_hosts.contains(&host))
+    }
+}
+
+/// The host of a `Referer`, lowercased and without any port.
+fn referer_host(referer: &str) -> Option<String> {
+    let (_, rest) = referer.split_once("://")?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority.rsplit('@').next()?;
+    let host = match host.strip_prefix('[') {
+        Some(ipv6) => ipv6.split(']').next()?,
+        None => host.split(':').next()?,
+    };
+    (!host.is_empty()).then(|| host.to_ascii_lowercase())
+}
+
+/// The part of a blob that a `Range` header asks for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RequestedRange {
+    /// The whole blob, which is also sent for requests with more than one
+    /// range.
+    Whole,
+    /// A half-open range of the blob's bytes.
+    Part(std::ops::Range<u64>),
+    /// A range that's outside of the blob.
+    Unsatisfiable,
+}
+
+impl RequestedRange {
+    pub fn new(range: Option<&Range>, len: u64) -> Self {
+        let Some(range) = range else {
+            return Self::Whole;
+        };
+        let mut ranges = range.iter();
+        let Some((start, end)) = ranges.next() else {
+            return Self::Whole;
+        };
+        if ranges.next().is_some() {
+            return Self::Whole;
+        }
+
+        let range = match (start, end) {
+            (Bound::Included(start), Bound::Included(end)) if start <= end => start..end + 1,
+            (Bound::Included(start), Bound::Unbounded) => start..len,
+            (Bound::Unbounded, Bound::Included(suffix)) if suffix > 0 => {
+                len.saturating_sub(suffix)..len
+            }
+            _ => return Self::Unsatisfiable,
+        };
+        if range.start >= len {
+            return Self::Unsatisfiable;
+        }
+        Self::Part(range.start..range.end.min(len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::{TimeZone as _, Utc};
+    use test_case::test_case;
+
+    use super::*;
+    use crate::{account::testing::generate_keys, license::ContentLicense, provider::MockClock};
+
+    fn urls(clock: &MockClock, referer_hosts: &[&str]) -> MediaUrls {
+        let (enc_key, dec_key) = generate_keys();
+        MediaUrls::new(
+            &MediaConfig {
+                url_ttl: Duration::from_mins(5),
+                referer_hosts: referer_hosts.iter().map(ToString::to_string).collect(),
+                ..Default::default()
+            },
+            Arc::new(enc_key),
+            Arc::new(dec_key),
+            Arc::new(clock.clone()),
+            Some("https://plazer.example".into()),
+        )
+    }
+
+    fn media(id: &str) -> Media {
+        Media {
+            id: ("media", id).into(),
+            owner_id: ("account", "owner").into(),
+            blob_id: ("media_blob", "abc").into(),
+            content_type: "image/png".into(),
+            size: 4,
+            license: ContentLicense::default(),
+            attribution: None,
+            attribution_url: None,
+            alt_text: None,
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn token(url: &str) -> String {
+        let (_, query) = url.split_once('?').unwrap();
+        let params: Vec<(String, String)> = serde_urlencoded::from_str(query).unwrap();
+        params[0].1.clone()
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let clock = MockClock::new(Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap());
+        let urls = urls(&clock, &[]);
+
+        let url = urls.sign(&media("one"), Some("viewer")).unwrap();
+        assert!(url.starts_with("https://plazer.example/api/v1/media/one/content?token="));
+        assert_eq!(
+            urls.verify(&token(&url), "one").unwrap(),
+            (Some("viewer".into()), 300)
+        );
+        assert_eq!(
+            urls.verify(&token(&url), "two").unwrap_err(),
+            Error::JwtInvalid
+        );
+
+        let url = urls.sign(&media("one"), None).unwrap();
+        clock.advance(ChronoDuration::minutes(4));
+        assert_eq!(urls.verify(&token(&url), "one").unwrap(), (None, 60));
+        clock.advance(ChronoDuration::minutes(1));
+        assert_eq!(
+            urls.verify(&token(&url), "one").unwrap_err(),
+            Error::JwtExpired
+        );
+    }
+
+    #[test]
+    fn test_verify_other_keys() {
+        let clock = MockClock::default();
+        let url = urls(&clock, &[]).sign(&media("one"), None).unwrap();
+        assert!(urls(&clock, &[]).verify(&token(&url), "one").is_err());
+    }
+
+    #[test_case(None, true ; "no referer")]
+    #[test_case(Some("https://plazer.example/posts/1"), true ; "allowed host")]
+    #[test_case(Some("https://PLAZER.example:8443"), true ; "allowed host with port")]
+    #[test_case(Some("https://user@plazer.example/"), true ; "allowed host with userinfo")]
+    #[test_case(Some("https://plazer.example.evil/"), false ; "other host")]
+    #[test_case(Some("https://evil.example/?plazer.example"), false ; "host in query")]
+    #[test_case(Some("not a url"), false ; "malformed")]
+    fn test_allows_referer(referer: Option<&str>, expected: bool) {
+        let clock = MockClock::default();
+        assert_eq!(
+            urls(&clock, &["plazer.example"]).allows_referer(referer),
+            expected
+        );
+        assert!(urls(&clock, &[]).allows_referer(referer));
+    }
+
+    #[test_case(0..4 => RequestedRange::Part(0..4) ; "start")]
+    #[test_case(2..10 => RequestedRange::Part(2..10) ; "middle")]
+    #[test_case(5..100 => RequestedRange::Part(5..10) ; "past the end")]
+    #[test_case(10..12 => RequestedRange::Unsatisfiable ; "after the end")]
+    fn test_requested_range(range: std::ops::Range<u64>) -> RequestedRange {
+        let range = Range::bytes(range).unwrap();
+        RequestedRange::new(Some(&range), 10)
+    }
+
+    #[test]
+    fn test_requested_range_open() {
+        let part = |range: Range| RequestedRange::new(Some(&range), 10);
+        assert_eq!(
+            part(Range::bytes(4..).unwrap()),
+            RequestedRange::Part(4..10)
+        );
+        assert_eq!(
+            part(Range::bytes(..=3).unwrap()),
+            RequestedRange::Part(7..10)
+        );
+        assert_eq!(
+            part(Range::bytes(..=30).unwrap()),
+            RequestedRange::Part(0..10)
+        );
+        assert_eq!(RequestedRange::new(None, 10), RequestedRange::Whole);
+    }
+}
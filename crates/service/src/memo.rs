@@ -0,0 +1,122 @@
+//! Records loaded while handling a single GraphQL request, so that resolvers
+//! and guards that look up the same record don't each query the database
+//! for it.
+//!
+//! Each HTTP request gets its own memo, which is dropped with the request.
+//! Subscriptions don't get one, as they last long enough for the records to
+//! change underneath them. Any write made while handling the request clears
+//! the memo, so records loaded after it see the change.
+
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use surrealdb::sql::Thing;
+
+type Key = (TypeId, Thing);
+
+#[derive(Debug, Default, Clone)]
+pub struct RequestMemo(Arc<Mutex<MemoState>>);
+
+#[derive(Debug, Default)]
+struct MemoState {
+    /// Bumped each time the memo is cleared, so that loads that started
+    /// before a write don't store what they read.
+    generation: u64,
+    records: HashMap<Key, Arc<dyn Any + Send + Sync>>,
+}
+
+/// The result of looking a record up in the memo.
+pub enum Memoized<T> {
+    /// The record was loaded earlier in the request, or was found not to
+    /// exist.
+    Hit(Option<T>),
+    /// The record needs loading, after which it should be stored with
+    /// [`RequestMemo::store`] and this generation.
+    Miss(u64),
+}
+
+impl RequestMemo {
+    pub fn get<T>(&self, thing: &Thing) -> Memoized<T>
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        let state = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        let record = state
+            .records
+            .get(&(TypeId::of::<T>(), thing.clone()))
+            .and_then(|record| record.downcast_ref::<Option<T>>());
+        match record {
+            Some(record) => Memoized::Hit(record.clone()),
+            None => Memoized::Miss(state.generation),
+        }
+    }
+
+    /// Keeps a record that was loaded, unless the memo has been cleared since
+    /// the load started.
+    pub fn store<T>(&self, thing: Thing, generation: u64, record: Option<T>)
+    where
+        T: Send + Sync + 'static,
+    {
+        let mut state = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        if state.generation == generation {
+            state
+                .records
+                .insert((TypeId::of::<T>(), thing), Arc::new(record));
+        }
+    }
+
+    /// Forgets every record, after something has been written.
+    pub fn clear(&self) {
+        let mut state = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        state.generation += 1;
+        state.records.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn thing(id: &str) -> Thing {
+        Thing::from(("account", id))
+    }
+
+    #[test]
+    fn test_store() {
+        let memo = RequestMemo::default();
+        let Memoized::Miss(generation) = memo.get::<String>(&thing("a")) else {
+            panic!("empty memo had a record");
+        };
+        memo.store(thing("a"), generation, Some("alice".to_owned()));
+        memo.store::<String>(thing("b"), generation, None);
+
+        assert!(matches!(
+            memo.get::<String>(&thing("a")),
+            Memoized::Hit(Some(name)) if name == "alice"
+        ));
+        assert!(matches!(
+            memo.get::<String>(&thing("b")),
+            Memoized::Hit(None)
+        ));
+        // Records are kept by type as well as ID.
+        assert!(matches!(memo.get::<u64>(&thing("a")), Memoized::Miss(_)));
+    }
+
+    #[test]
+    fn test_clear() {
+        let memo = RequestMemo::default();
+        let Memoized::Miss(generation) = memo.get::<String>(&thing("a")) else {
+            panic!("empty memo had a record");
+        };
+        memo.store(thing("a"), generation, Some("alice".to_owned()));
+        memo.clear();
+        assert!(matches!(memo.get::<String>(&thing("a")), Memoized::Miss(_)));
+
+        // A load that started before the memo was cleared isn't kept.
+        memo.store(thing("a"), generation, Some("stale".to_owned()));
+        assert!(matches!(memo.get::<String>(&thing("a")), Memoized::Miss(_)));
+    }
+}
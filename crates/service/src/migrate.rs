@@ -0,0 +1,114 @@
+//! Running and inspecting migrations by hand, without serving.
+//!
+//! The server runs migrations as it starts unless it's been told not to, in
+//! which case they're run from here before it's started.
+
+use std::fmt;
+
+use anyhow::Context as _;
+use serde::Serialize;
+
+pub use crate::migration::ScriptStatus;
+use crate::{
+    config::{ServeConfig, ServiceConfigBuilder},
+    migration::Migrations,
+    persist::Persist,
+};
+
+/// Where each subsystem's migrations are up to, and which scripts have been
+/// applied.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct MigrationReport {
+    /// The last migration that each subsystem ran, if it's run any.
+    pub subsystems: Vec<(&'static str, Option<String>)>,
+    /// The subsystems that have migrations left to run.
+    pub pending: Vec<&'static str>,
+    pub scripts: Vec<ScriptStatus>,
+}
+
+impl MigrationReport {
+    /// Whether there's nothing left to run.
+    #[must_use]
+    pub fn is_up_to_date(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+impl fmt::Display for MigrationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .subsystems
+            .iter()
+            .map(|(subsystem, _)| subsystem.len())
+            .max()
+            .unwrap_or_default();
+        for (subsystem, version) in &self.subsystems {
+            let status = if self.pending.contains(subsystem) {
+                "pending"
+            } else {
+                "ok"
+            };
+            let version = version.as_deref().unwrap_or("none");
+            writeln!(f, "[{status:^7}] {subsystem:<width$}  {version}")?;
+        }
+        for ScriptStatus {
+            version,
+            name,
+            applied_at,
+            modified,
+        } in &self.scripts
+        {
+            let status = if applied_at.is_some() {
+                "ok"
+            } else {
+                "pending"
+            };
+            write!(f, "[{status:^7}] {version:04}_{name}")?;
+            if let Some(applied_at) = applied_at {
+                write!(f, "  applied {}", applied_at.to_rfc3339())?;
+            }
+            if *modified {
+                write!(f, "  (changed since it was applied)")?;
+            }
+            writeln!(f)?;
+        }
+        if self.is_up_to_date() {
+            write!(f, "Up to date")
+        } else {
+            write!(f, "{} subsystems to migrate", self.pending.len())
+        }
+    }
+}
+
+/// Reports where migrations are up to, without running any.
+pub async fn status(builder: ServiceConfigBuilder) -> anyhow::Result<MigrationReport> {
+    let persist = connect(builder).await?;
+    Ok(MigrationReport {
+        subsystems: Migrations::versions(&persist).await?,
+        pending: Migrations::pending(&persist).await?,
+        scripts: Migrations::scripts(&persist).await?,
+    })
+}
+
+/// Runs every migration that hasn't been, in the same way that starting the
+/// server does.
+pub async fn run(builder: ServiceConfigBuilder) -> anyhow::Result<()> {
+    let persist = connect(builder).await?;
+    Migrations::run(&persist).await?;
+    Ok(())
+}
+
+/// Rolls back the scripts applied after the given version, newest first,
+/// returning the versions that were rolled back. Subsystems' migrations
+/// can't be rolled back.
+pub async fn rollback(builder: ServiceConfigBuilder, to: u32) -> anyhow::Result<Vec<u32>> {
+    let persist = connect(builder).await?;
+    Ok(Migrations::rollback(&persist, to).await?)
+}
+
+async fn connect(builder: ServiceConfigBuilder) -> anyhow::Result<Persist> {
+    let (serve, _): (ServeConfig, _) = builder.build()?.without_private_key_create().try_into()?;
+    Persist::new(serve.address, serve.namespace, serve.database, &serve.db)
+        .await
+        .context("Unable to connect to the database")
+}
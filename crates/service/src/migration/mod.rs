@@ -0,0 +1,237 @@
+mod scripts;
+
+use std::{fmt::Debug, future::Future, time::Duration};
+
+use nanorand::{Rng as _, WyRand};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::time::sleep;
+use tracing::{debug, instrument, trace};
+
+use crate::{
+    account::AccountMigration, board::BoardMigration, client_state::ClientStateMigration,
+    conversation::ConversationMigration, event::EventMigration, follow::FollowMigration,
+    notification::NotificationMigration, organization::OrganizationMigration, persist::Persist,
+    prelude::*, read_marker::ReadMarkerMigration,
+};
+
+pub use scripts::ScriptStatus;
+
+pub trait Migration: Sized + Default + Serialize + DeserializeOwned + Debug + Send + Sync {
+    const SUBSYSTEM: &'static str;
+
+    fn next(self) -> Option<Self>;
+    fn build(&self, statements: &mut Vec<srql::Statement>);
+}
+
+pub struct Migrations<'a> {
+    persist: &'a Persist,
+}
+
+static UPDATE_TABLE: &str = "updates";
+/// What scripts are listed as alongside the subsystems' migrations.
+static SCRIPTS_SUBSYSTEM: &str = "scripts";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Update<M> {
+    current: M,
+}
+
+impl Migrations<'_> {
+    #[instrument(skip_all)]
+    pub async fn run(persist: &Persist) -> surrealdb::Result<()> {
+        let migrations = Migrations { persist };
+
+        debug!("Running migrations");
+        migrations.iterate::<AccountMigration>().await?;
+        migrations.iterate::<BoardMigration>().await?;
+        migrations.iterate::<ClientStateMigration>().await?;
+        migrations.iterate::<ConversationMigration>().await?;
+        migrations.iterate::<EventMigration>().await?;
+        migrations.iterate::<FollowMigration>().await?;
+        migrations.iterate::<NotificationMigration>().await?;
+        migrations.iterate::<OrganizationMigration>().await?;
+        migrations.iterate::<ReadMarkerMigration>().await?;
+        migrations.apply_scripts().await?;
+        debug!("Migrations complete");
+
+        Ok(())
+    }
+
+    /// The subsystems that have migrations left to run, without running
+    /// them, with `scripts` standing for any scripts that haven't been
+    /// applied. Fails if the database has been migrated by a newer version.
+    #[instrument(skip_all)]
+    pub async fn pending(persist: &Persist) -> surrealdb::Result<Vec<&'static str>> {
+        let migrations = Migrations { persist };
+
+        let pending = [
+            migrations.pending_subsystem::<AccountMigration>().await?,
+            migrations.pending_subsystem::<BoardMigration>().await?,
+            migrations
+                .pending_subsystem::<ClientStateMigration>()
+                .await?,
+            migrations
+                .pending_subsystem::<ConversationMigration>()
+                .await?,
+            migrations.pending_subsystem::<EventMigration>().await?,
+            migrations.pending_subsystem::<FollowMigration>().await?,
+            migrations
+                .pending_subsystem::<NotificationMigration>()
+                .await?,
+            migrations
+                .pending_subsystem::<OrganizationMigration>()
+                .await?,
+            migrations
+                .pending_subsystem::<ReadMarkerMigration>()
+                .await?,
+            (!migrations.pending_scripts().await?.is_empty()).then_some(SCRIPTS_SUBSYSTEM),
+        ];
+        Ok(pending.into_iter().flatten().collect())
+    }
+
+    /// The last migration that each subsystem ran, or `None` for subsystems
+    /// that haven't run any, with the last script that was applied under
+    /// `scripts`.
+    #[instrument(skip_all)]
+    pub async fn versions(
+        persist: &Persist,
+    ) -> surrealdb::Result<Vec<(&'static str, Option<String>)>> {
+        let migrations = Migrations { persist };
+
+        Ok(vec![
+            migrations.version::<AccountMigration>().await?,
+            migrations.version::<BoardMigration>().await?,
+            migrations.version::<ClientStateMigration>().await?,
+            migrations.version::<ConversationMigration>().await?,
+            migrations.version::<EventMigration>().await?,
+            migrations.version::<FollowMigration>().await?,
+            migrations.version::<NotificationMigration>().await?,
+            migrations.version::<OrganizationMigration>().await?,
+            migrations.version::<ReadMarkerMigration>().await?,
+            (SCRIPTS_SUBSYSTEM, migrations.script_version().await?),
+        ])
+    }
+
+    async fn pending_subsystem<M: Migration>(&self) -> surrealdb::Result<Option<&'static str>> {
+        Ok(self.next_update::<M>().await?.map(|_| M::SUBSYSTEM))
+    }
+
+    async fn version<M: Migration>(&self) -> surrealdb::Result<(&'static str, Option<String>)> {
+        let update: Option<Update<M>> = self
+            .persist
+            .db()
+            .select((UPDATE_TABLE, M::SUBSYSTEM))
+            .await?;
+        Ok((
+            M::SUBSYSTEM,
+            update.map(|Update { current }| format!("{current:?}")),
+        ))
+    }
+
+    #[instrument(skip_all, fields(subsystem = M::SUBSYSTEM))]
+    async fn iterate<M: Migration>(&self) -> surrealdb::Result<()> {
+        let mut migrated = false;
+        while self.next_update::<M>().await?.is_some() {
+            migrated |= self
+                .in_lock(M::SUBSYSTEM, || self.apply_next::<M>())
+                .await?;
+        }
+
+        if migrated {
+            debug!("Migration complete");
+        } else {
+            debug!("No migrations to run");
+        }
+
+        Ok(())
+    }
+
+    /// Applies the next step, if another server didn't apply it while this
+    /// one waited for the lock. Returns whether it was applied.
+    async fn apply_next<M: Migration>(&self) -> surrealdb::Result<bool> {
+        let Some(update) = self.next_update::<M>().await? else {
+            return Ok(false);
+        };
+        let mut statements = vec![srql::Statement::Begin(srql::BeginStatement)];
+        update.build(&mut statements);
+        statements.push(srql::Statement::Update(iterate_complete_update(
+            M::SUBSYSTEM,
+            srql::to_value(&update)?,
+        )));
+        statements.push(srql::Statement::Commit(srql::CommitStatement));
+        self.persist
+            .db()
+            .query(srql::query(statements))
+            .await?
+            .check()?;
+        Ok(true)
+    }
+
+    /// Runs `f` while holding `lock`, waiting for it if another server has
+    /// it.
+    async fn in_lock<F, Fut, T>(&self, lock: &str, f: F) -> surrealdb::Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = surrealdb::Result<T>>,
+    {
+        let mut prng = WyRand::new();
+        loop {
+            if let Some(res) = self.persist.execute_in_lock(lock, &f).await? {
+                return res;
+            }
+            trace!(lock, "Migration locked, sleeping");
+            // Introduce a bit of jitter to avoid thundering herd.
+            sleep(Duration::from_millis(
+                5000 + prng.generate_range(0..=10_000),
+            ))
+            .await;
+        }
+    }
+
+    #[instrument(skip_all)]
+    async fn next_update<M>(&self) -> surrealdb::Result<Option<M>>
+    where
+        M: Migration,
+    {
+        if let Some::<Update<M>>(Update { current }) = self
+            .persist
+            .db()
+            .select((UPDATE_TABLE, M::SUBSYSTEM))
+            .await?
+        {
+            if let Some(next) = current.next() {
+                debug!(?next, "Next migration step");
+                Ok(Some(next))
+            } else {
+                Ok(None)
+            }
+        } else {
+            let update = M::default();
+            debug!(?update, "Beginning migration");
+            Ok(Some(update))
+        }
+    }
+}
+
+fn iterate_complete_update(subsystem: &str, update: srql::Value) -> srql::UpdateStatement {
+    srql::UpdateStatement {
+        what: srql::thing((UPDATE_TABLE, subsystem)),
+        data: srql::Data::SetExpression(vec![
+            (
+                srql::field("current"),
+                srql::Operator::Equal,
+                update.clone(),
+            ),
+            (
+                srql::field("history"),
+                srql::Operator::Inc,
+                srql::array([srql::object([
+                    ("timestamp".into(), srql::time_now()),
+                    ("update".into(), update),
+                ])]),
+            ),
+        ])
+        .into(),
+        ..Default::default()
+    }
+}
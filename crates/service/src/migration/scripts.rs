@@ -0,0 +1,281 @@
+use chrono::{DateTime, Utc};
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, instrument};
+
+use super::Migrations;
+use crate::{persist::Persist, prelude::*};
+
+/// The lock that's held while scripts are applied or rolled back.
+static SCRIPT_LOCK: &str = "migration_scripts";
+static SCRIPT_TABLE: &str = "migrations";
+
+/// A versioned change to the database, written in `SurrealQL`.
+///
+/// Scripts live in the crate's `migrations` directory, named after their
+/// version and what they do, with an `.up.surql` file that makes the change
+/// and a `.down.surql` file that undoes it. They're embedded into the binary,
+/// so they can't go missing from a deployment.
+#[derive(Debug)]
+pub(super) struct Script {
+    pub version: u32,
+    pub name: &'static str,
+    up: &'static str,
+    down: &'static str,
+}
+
+impl Script {
+    /// A hash of the change, so that scripts that were changed after they
+    /// were applied can be found.
+    fn checksum(&self) -> String {
+        hex::encode(digest::digest(&digest::SHA256, self.up.as_bytes()))
+    }
+}
+
+/// Every script, in the order they're applied. New scripts go at the end,
+/// with the next version.
+static SCRIPTS: &[Script] = &[Script {
+    version: 1,
+    name: "post_translation_lookup",
+    up: include_str!("../../migrations/0001_post_translation_lookup.up.surql"),
+    down: include_str!("../../migrations/0001_post_translation_lookup.down.surql"),
+}];
+
+/// A script that's been applied to the database.
+#[derive(Debug, Serialize, Deserialize)]
+struct AppliedScript {
+    version: u32,
+    name: String,
+    checksum: String,
+    applied_at: DateTime<Utc>,
+}
+
+/// Whether a script has been applied to the database.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ScriptStatus {
+    pub version: u32,
+    pub name: String,
+    /// When the script was applied, if it has been.
+    pub applied_at: Option<DateTime<Utc>>,
+    /// Whether the script has changed since it was applied.
+    pub modified: bool,
+}
+
+impl Migrations<'_> {
+    /// Every script and whether it's been applied. Fails if the database has
+    /// had scripts applied that this version doesn't know about.
+    #[instrument(skip_all)]
+    pub async fn scripts(persist: &Persist) -> surrealdb::Result<Vec<ScriptStatus>> {
+        let migrations = Migrations { persist };
+        let applied = migrations.applied_scripts().await?;
+
+        Ok(SCRIPTS
+            .iter()
+            .map(|script| {
+                let applied = applied
+                    .iter()
+                    .find(|applied| applied.version == script.version);
+                ScriptStatus {
+                    version: script.version,
+                    name: script.name.to_owned(),
+                    applied_at: applied.map(|applied| applied.applied_at),
+                    modified: applied.is_some_and(|applied| applied.checksum != script.checksum()),
+                }
+            })
+            .collect())
+    }
+
+    /// Rolls back every applied script newer than the given version, newest
+    /// first, returning the versions that were rolled back. Rolling back to
+    /// version 0 undoes every script.
+    #[instrument(skip_all, fields(to))]
+    pub async fn rollback(persist: &Persist, to: u32) -> surrealdb::Result<Vec<u32>> {
+        let migrations = Migrations { persist };
+        let applied = migrations.applied_scripts().await?;
+
+        let mut rolled_back = vec![];
+        for applied in applied.iter().rev().filter(|applied| applied.version > to) {
+            let script = SCRIPTS
+                .iter()
+                .find(|script| script.version == applied.version)
+                .expect("applied scripts are checked to be known");
+            migrations
+                .in_lock(SCRIPT_LOCK, || async {
+                    let mut statements = vec![srql::trans_begin()];
+                    statements.extend(srql::parse(script.down)?.0 .0);
+                    statements.push(srql::Statement::Delete(srql::DeleteStatement {
+                        what: srql::thing(script_thing(script)),
+                        output: srql::Output::None.into(),
+                        ..Default::default()
+                    }));
+                    statements.push(srql::trans_end());
+                    migrations
+                        .persist
+                        .db()
+                        .query(srql::query(statements))
+                        .await?
+                        .check()?;
+                    Ok(())
+                })
+                .await?;
+            info!(
+                version = script.version,
+                name = script.name,
+                "Rolled back migration script"
+            );
+            rolled_back.push(script.version);
+        }
+        Ok(rolled_back)
+    }
+
+    /// The scripts that haven't been applied yet.
+    pub(super) async fn pending_scripts(&self) -> surrealdb::Result<Vec<&'static Script>> {
+        let applied = self.applied_scripts().await?;
+        Ok(SCRIPTS
+            .iter()
+            .filter(|script| {
+                !applied
+                    .iter()
+                    .any(|applied| applied.version == script.version)
+            })
+            .collect())
+    }
+
+    /// The latest script that's been applied, if any have.
+    pub(super) async fn script_version(&self) -> surrealdb::Result<Option<String>> {
+        let applied = self.applied_scripts().await?;
+        Ok(applied
+            .last()
+            .map(|applied| format!("{:04}_{}", applied.version, applied.name)))
+    }
+
+    /// Applies each script that hasn't been, in order. Each script is applied
+    /// in a transaction along with the record of it, so a script that fails
+    /// leaves nothing behind.
+    #[instrument(skip_all)]
+    pub(super) async fn apply_scripts(&self) -> surrealdb::Result<()> {
+        for script in self.pending_scripts().await? {
+            self.in_lock(SCRIPT_LOCK, || async {
+                // Another server may have applied it while waiting for the
+                // lock.
+                if !self
+                    .pending_scripts()
+                    .await?
+                    .iter()
+                    .any(|pending| pending.version == script.version)
+                {
+                    return Ok(());
+                }
+
+                let mut statements = vec![srql::trans_begin()];
+                statements.extend(srql::parse(script.up)?.0 .0);
+                statements.push(srql::Statement::Create(srql::CreateStatement {
+                    what: srql::thing(script_thing(script)),
+                    data: srql::Data::SetExpression(vec![
+                        (
+                            srql::field("version"),
+                            srql::Operator::Equal,
+                            i64::from(script.version).into(),
+                        ),
+                        (
+                            srql::field("name"),
+                            srql::Operator::Equal,
+                            script.name.into(),
+                        ),
+                        (
+                            srql::field("checksum"),
+                            srql::Operator::Equal,
+                            script.checksum().into(),
+                        ),
+                        (
+                            srql::field("applied_at"),
+                            srql::Operator::Equal,
+                            srql::Value::Datetime(srql::Datetime(self.persist.clock().now())),
+                        ),
+                    ])
+                    .into(),
+                    output: srql::Output::None.into(),
+                    ..Default::default()
+                }));
+                statements.push(srql::trans_end());
+                self.persist
+                    .db()
+                    .query(srql::query(statements))
+                    .await?
+                    .check()?;
+                debug!(
+                    version = script.version,
+                    name = script.name,
+                    "Applied migration script"
+                );
+                Ok(())
+            })
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// The scripts that have been applied, oldest first. Fails if any of them
+    /// are unknown, as the database has been migrated by a newer version.
+    async fn applied_scripts(&self) -> surrealdb::Result<Vec<AppliedScript>> {
+        let mut applied: Vec<AppliedScript> = self.persist.db().select(SCRIPT_TABLE).await?;
+        applied.sort_by_key(|applied| applied.version);
+
+        for applied in &applied {
+            if !SCRIPTS
+                .iter()
+                .any(|script| script.version == applied.version)
+            {
+                return Err(surrealdb::error::Db::Thrown(format!(
+                    "Migration script {:04}_{} isn't known, the database may have been migrated by a newer version",
+                    applied.version, applied.name
+                ))
+                .into());
+            }
+        }
+        Ok(applied)
+    }
+}
+
+fn script_thing(script: &Script) -> srql::Thing {
+    srql::Thing::from((SCRIPT_TABLE, srql::Id::from(i64::from(script.version))))
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::persist::testing::persist;
+
+    #[test]
+    fn test_scripts_parse() {
+        let mut last = 0;
+        for script in SCRIPTS {
+            assert!(script.version > last, "{} is out of order", script.name);
+            last = script.version;
+            srql::parse(script.up).unwrap();
+            srql::parse(script.down).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_apply_and_rollback() {
+        let persist = persist().await;
+        let status = Migrations::scripts(&persist).await.unwrap();
+        assert_eq!(status.len(), SCRIPTS.len());
+        assert!(status
+            .iter()
+            .all(|status| status.applied_at.is_some() && !status.modified));
+
+        let versions: Vec<_> = SCRIPTS.iter().rev().map(|script| script.version).collect();
+        assert_eq!(Migrations::rollback(&persist, 0).await.unwrap(), versions);
+        let status = Migrations::scripts(&persist).await.unwrap();
+        assert!(status.iter().all(|status| status.applied_at.is_none()));
+        assert!(Migrations::rollback(&persist, 0).await.unwrap().is_empty());
+
+        Migrations::run(&persist).await.unwrap();
+        let status = Migrations::scripts(&persist).await.unwrap();
+        assert!(status.iter().all(|status| status.applied_at.is_some()));
+    }
+}
@@ -0,0 +1,9 @@
+mod models;
+mod persist;
+mod schema;
+
+pub use models::*;
+pub use persist::*;
+pub use schema::*;
+
+pub static MODERATION_TABLE_NAME: &str = "moderation_item";
@@ -0,0 +1,99 @@
+use async_graphql::{ComplexObject, Enum, SimpleObject, ID};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use surrealdb::sql::Thing;
+
+use super::MODERATION_TABLE_NAME;
+use crate::{id_obj_impls, prelude::*, query::OpaqueCursor};
+
+pub type ModerationCursor = OpaqueCursor<String>;
+
+/// Why something was sent for moderation.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModerationReason {
+    /// It was flagged as likely to be spam.
+    Spam,
+}
+
+impl QueryValue for ModerationReason {
+    fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
+        srql::to_value(self)
+            .ok()
+            .map(|value| (field, srql::Operator::Equal, value))
+    }
+}
+
+/// Something that needs to be looked at by an admin.
+#[derive(SimpleObject, Debug, Clone, Deserialize)]
+#[graphql(complex)]
+pub struct ModerationItem {
+    #[graphql(skip)]
+    pub id: Thing,
+    #[graphql(skip)]
+    pub target_id: Thing,
+    #[graphql(skip)]
+    pub board_id: Option<Thing>,
+
+    /// Why the item was sent for moderation.
+    pub reason: ModerationReason,
+    /// More details about why the item was sent for moderation, such as what
+    /// flagged it.
+    pub details: Option<String>,
+    /// How likely it is that the item is spam, out of 100.
+    pub score: Option<u8>,
+    /// Whether an admin has dealt with the item.
+    #[serde(default)]
+    pub resolved: bool,
+
+    /// A timestamp indicating the last time the item was updated.
+    pub updated_at: DateTime<Utc>,
+}
+
+#[ComplexObject]
+impl ModerationItem {
+    /// The moderation item's unique ID.
+    async fn id(&self) -> ID {
+        self.id.to_gql_id()
+    }
+
+    /// The ID of the post or account that needs to be looked at.
+    async fn target_id(&self) -> ID {
+        self.target_id.to_gql_id()
+    }
+
+    /// The ID of the board that the post was made in, if it was made in one.
+    async fn board_id(&self) -> Option<ID> {
+        self.board_id.as_ref().map(ToGqlId::to_gql_id)
+    }
+}
+
+id_obj_impls!(ModerationItem);
+
+impl ModerationItem {
+    pub fn create(params: CreateModerationItem, ids: &dyn IdGen) -> srql::CreateStatement {
+        let mut create = vec![];
+        params.append(&mut create);
+        false.push_field(srql::field("resolved"), &mut create);
+        srql::obj_create_query(MODERATION_TABLE_NAME, create, ids)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CreateModerationItem {
+    pub target_id: Thing,
+    pub board_id: Option<Thing>,
+    pub reason: ModerationReason,
+    pub details: Option<String>,
+    pub score: Option<u8>,
+}
+
+impl CreateObject for CreateModerationItem {
+    fn append(self, expr: &mut srql::SetExpr) {
+        self.target_id.push_field(srql::field("target_id"), expr);
+        self.board_id.push_field(srql::field("board_id"), expr);
+        self.reason.push_field(srql::field("reason"), expr);
+        self.details.push_field(srql::field("details"), expr);
+        self.score.push_field(srql::field("score"), expr);
+    }
+}
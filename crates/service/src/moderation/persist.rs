@@ -0,0 +1,192 @@
+#[cfg(test)]
+mod tests;
+
+use async_graphql::connection::{Connection, Edge};
+use futures::{Stream, StreamExt as _};
+use tracing::{error, instrument};
+
+use super::{CreateModerationItem, ModerationCursor, ModerationItem, MODERATION_TABLE_NAME};
+use crate::{
+    account::{require_permission, CurrentAccount, Permission},
+    persist::Persist,
+    prelude::*,
+    query::{OpaqueCursor, PaginationInput, PaginationOptions, ResultSlice},
+};
+
+pub struct ModerationPersist<'a> {
+    persist: &'a Persist,
+    current: &'a CurrentAccount,
+}
+
+impl<'a> ModerationPersist<'a> {
+    pub fn new(persist: &'a Persist, current: &'a CurrentAccount) -> Self {
+        Self { persist, current }
+    }
+
+    /// Adds an item to the moderation queue. This is done on behalf of the
+    /// instance, so the current account doesn't need to be an admin.
+    #[instrument(skip_all)]
+    pub async fn enqueue(&self, item: CreateModerationItem) -> Result<Option<ModerationItem>> {
+        let item: Option<ModerationItem> = self
+            .persist
+            .db()
+            .query(ModerationItem::create(item, self.persist.ids()))
+            .await?
+            .take(0)?;
+        if let Some(item) = &item {
+            self.persist.moderation_feed().publish(item);
+        }
+        Ok(item)
+    }
+
+    /// Lists the items in the moderation queue. Only moderators and admins
+    /// can see these.
+    #[instrument(skip_all)]
+    pub async fn list(&self) -> Result<ModerationListRequest<'a>> {
+        require_permission(self.persist, self.current, Permission::ModerateContent).await?;
+        Ok(ModerationListRequest::new(self.persist))
+    }
+
+    /// Streams items as they're added to the moderation queue. Only
+    /// moderators and admins can see these.
+    ///
+    /// Items can be limited to posts made in a single board, or to those
+    /// scored at least `min_score`, which leaves out items without a score.
+    pub async fn subscribe(
+        &self,
+        board_id: Option<srql::Thing>,
+        min_score: Option<u8>,
+    ) -> Result<impl Stream<Item = ModerationItem> + Send + 'static> {
+        require_permission(self.persist, self.current, Permission::ModerateContent).await?;
+        let added = self.persist.moderation_feed().subscribe(move |item| {
+            board_id
+                .as_ref()
+                .is_none_or(|id| item.board_id.as_ref() == Some(id))
+                && min_score.is_none_or(|min| item.score.is_some_and(|score| score >= min))
+        });
+
+        // Moderators can be demoted or restricted while subscribed, so their
+        // permission is checked again as each item arrives.
+        let (persist, current) = (self.persist.clone(), self.current.clone());
+        Ok(added.filter_map(move |item| {
+            let (persist, current) = (persist.clone(), current.clone());
+            async move {
+                match require_permission(&persist, &current, Permission::ModerateContent).await {
+                    Ok(_) => Some(item),
+                    Err(Error::Unauthorized | Error::Unauthenticated) => None,
+                    Err(err) => {
+                        error!(error = ?err, "Failed to check whether a moderation item can be seen");
+                        None
+                    }
+                }
+            }
+        }))
+    }
+
+    /// Marks an item in the moderation queue as dealt with.
+    #[instrument(skip_all)]
+    pub async fn resolve(&self, id: &str) -> Result<Option<ModerationItem>> {
+        require_permission(self.persist, self.current, Permission::ModerateContent).await?;
+
+        let Some(update) = srql::obj_update_query(
+            (MODERATION_TABLE_NAME, id).into(),
+            vec![(srql::field("resolved"), srql::Operator::Equal, true.into())],
+        ) else {
+            return Ok(None);
+        };
+
+        let item = self.persist.db().query(update).await?.take(0)?;
+        Ok(item)
+    }
+}
+
+pub struct ModerationListRequest<'a> {
+    persist: &'a Persist,
+    resolved: bool,
+    pagination: Option<PaginationInput<OpaqueCursor<String>>>,
+}
+
+impl<'a> ModerationListRequest<'a> {
+    fn new(persist: &'a Persist) -> Self {
+        Self {
+            persist,
+            resolved: false,
+            pagination: None,
+        }
+    }
+
+    /// Whether to list items that have already been dealt with instead of
+    /// those still waiting.
+    pub fn with_resolved(mut self, resolved: bool) -> Self {
+        self.resolved = resolved;
+        self
+    }
+
+    pub fn with_pagination(
+        mut self,
+        args: impl Into<PaginationInput<OpaqueCursor<String>>>,
+    ) -> Self {
+        self.pagination = Some(args.into());
+        self
+    }
+
+    #[instrument(skip_all)]
+    pub async fn execute(self) -> Result<Connection<ModerationCursor, ModerationItem>> {
+        let PaginationOptions {
+            cond,
+            order,
+            limit,
+            result_slice_opts,
+        } = (self.pagination, MODERATION_TABLE_NAME).into();
+
+        let resolved_cond = srql::Cond(
+            srql::Expression::Binary {
+                l: srql::field("resolved").into(),
+                o: srql::Operator::Equal,
+                r: self.resolved.into(),
+            }
+            .into(),
+        );
+
+        let query = srql::SelectStatement {
+            expr: srql::Fields::all(),
+            what: srql::table(MODERATION_TABLE_NAME),
+            order: srql::Orders(order.into_iter().collect()).into(),
+            cond: srql::cond_and(cond, resolved_cond.into()),
+            limit,
+            ..Default::default()
+        };
+
+        let items: Vec<ModerationItem> = self.persist.db().query(query).await?.take(0)?;
+        let ResultSlice {
+            results: items,
+            has_previous_page,
+            has_next_page,
+        } = ResultSlice::new(items, result_slice_opts);
+
+        let mut connection = Connection::new(has_previous_page, has_next_page);
+        connection.edges = items
+            .into_iter()
+            .map(|item| Edge::new(OpaqueCursor(item.id.to_gql_id().0), item))
+            .collect();
+
+        Ok(connection)
+    }
+}
+
+#[cfg(test)]
+pub mod testing {
+    use crate::account::testing::TestData;
+
+    use super::ModerationPersist;
+
+    pub trait ModerationTestData {
+        fn moderation(&self) -> ModerationPersist<'_>;
+    }
+
+    impl ModerationTestData for TestData {
+        fn moderation(&self) -> ModerationPersist<'_> {
+            ModerationPersist::new(&self.persist, &self.current)
+        }
+    }
+}
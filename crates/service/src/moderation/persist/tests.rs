@@ -0,0 +1,154 @@
+use std::time::Duration;
+
+use futures::StreamExt as _;
+use pretty_assertions::assert_eq;
+
+use super::{testing::ModerationTestData as _, *};
+use crate::{
+    account::{testing::*, AccountRole},
+    moderation::ModerationReason,
+    post::testing::PostTestData as _,
+    query::PaginationInput,
+};
+
+async fn list(data: &TestData, resolved: bool) -> Vec<srql::Thing> {
+    data.moderation()
+        .list()
+        .await
+        .unwrap()
+        .with_resolved(resolved)
+        .with_pagination(PaginationInput::new().forward(10))
+        .execute()
+        .await
+        .unwrap()
+        .edges
+        .into_iter()
+        .map(|edge| edge.node.id)
+        .collect()
+}
+
+#[tokio::test]
+async fn test_enqueue() {
+    let (data, _) = TestData::with_user().await;
+    let post = data.generate_post().await;
+
+    let res = data
+        .moderation()
+        .enqueue(CreateModerationItem {
+            target_id: post.id.clone(),
+            board_id: None,
+            reason: ModerationReason::Spam,
+            details: Some("link_density".into()),
+            score: Some(70),
+        })
+        .await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+
+    let res = res.unwrap().unwrap();
+    assert_eq!(res.target_id, post.id);
+    assert_eq!(res.reason, ModerationReason::Spam);
+    assert_eq!(res.details.as_deref(), Some("link_density"));
+    assert_eq!(res.score, Some(70));
+    assert!(!res.resolved);
+}
+
+#[tokio::test]
+async fn test_list_and_resolve() {
+    let (data, _) = TestData::with_user().await;
+    let post = data.generate_post().await;
+    let item = data
+        .moderation()
+        .enqueue(CreateModerationItem {
+            target_id: post.id.clone(),
+            board_id: None,
+            reason: ModerationReason::Spam,
+            details: None,
+            score: None,
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(list(&data, false).await, vec![item.id.clone()]);
+    assert!(list(&data, true).await.is_empty());
+
+    let res = data.moderation().resolve(&item.id.id.to_raw()).await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+    assert!(res.unwrap().unwrap().resolved);
+
+    assert!(list(&data, false).await.is_empty());
+    assert_eq!(list(&data, true).await, vec![item.id]);
+}
+
+#[tokio::test]
+async fn test_not_admin() {
+    let (mut data, _) = TestData::with_user().await;
+    let other = data.account().create_test_user().await;
+    data.login_as(&other);
+
+    let res = data.moderation().list().await.map(|_| ());
+    assert_eq!(res, Err(Error::Unauthorized));
+
+    let res = data.moderation().resolve("missing").await;
+    println!("{res:?}");
+    assert_eq!(res.map(|_| ()), Err(Error::Unauthorized));
+}
+
+async fn enqueue(
+    data: &TestData,
+    target_id: &srql::Thing,
+    board_id: Option<&srql::Thing>,
+    score: Option<u8>,
+) -> ModerationItem {
+    data.moderation()
+        .enqueue(CreateModerationItem {
+            target_id: target_id.clone(),
+            board_id: board_id.cloned(),
+            reason: ModerationReason::Spam,
+            details: None,
+            score,
+        })
+        .await
+        .unwrap()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_subscribe() {
+    let (mut data, admin) = TestData::with_user().await;
+    let moderator = data.account().create_test_user().await;
+    data.account()
+        .set_role(&moderator.id.to_gql_id(), AccountRole::Moderator)
+        .await
+        .unwrap();
+    let post = data.generate_post().await;
+    let board_id = srql::Thing::from(("board", "news"));
+    data.login_as(&moderator);
+    let mut added = Box::pin(
+        data.moderation()
+            .subscribe(Some(board_id.clone()), Some(50))
+            .await
+            .unwrap(),
+    );
+    enqueue(&data, &post.id, None, Some(90)).await;
+    enqueue(&data, &post.id, Some(&board_id), Some(10)).await;
+    enqueue(&data, &post.id, Some(&board_id), None).await;
+    let item = enqueue(&data, &post.id, Some(&board_id), Some(50)).await;
+    assert_eq!(added.next().await.unwrap().id, item.id);
+
+    // Demoted moderators stop being sent items.
+    data.login_as(&admin);
+    data.account()
+        .set_role(&moderator.id.to_gql_id(), AccountRole::User)
+        .await
+        .unwrap();
+    enqueue(&data, &post.id, Some(&board_id), Some(90)).await;
+    let next = tokio::time::timeout(Duration::from_millis(100), added.next()).await;
+    assert!(next.is_err(), "{next:?}");
+
+    data.login_as(&moderator);
+    let res = data.moderation().subscribe(None, None).await.map(|_| ());
+    assert_eq!(res, Err(Error::Unauthorized));
+}
@@ -0,0 +1,55 @@
+use async_graphql::{Context, Object, Subscription, ID};
+use futures::Stream;
+use tracing::instrument;
+
+use super::ModerationItem;
+use crate::{
+    account::{Permission, PermissionGuard},
+    board::BOARD_TABLE_NAME,
+    prelude::*,
+};
+
+#[derive(Default)]
+pub struct ModerationMutation;
+
+#[Object]
+impl ModerationMutation {
+    /// Marks an item in the moderation queue as dealt with. This can only be
+    /// done by moderators and admins.
+    #[graphql(guard = "PermissionGuard::new(Permission::ModerateContent)")]
+    #[instrument(skip_all)]
+    async fn resolve_moderation_item(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+    ) -> GqlResult<Option<ModerationItem>> {
+        ctx.moderation_persist().resolve(&id).await.extend()
+    }
+}
+
+#[derive(Default)]
+pub struct ModerationSubscription;
+
+#[Subscription]
+impl ModerationSubscription {
+    /// Sends items as they're added to the moderation queue, so that it can
+    /// be watched without polling it. Items can be limited to posts made in a
+    /// single board, or to those with a `score` of at least `minScore`. This
+    /// can only be done by moderators and admins, and items stop being sent
+    /// to accounts that lose their permission to see them.
+    #[graphql(guard = "PermissionGuard::new(Permission::ModerateContent)")]
+    async fn moderation_item_added(
+        &self,
+        ctx: &Context<'_>,
+        board_id: Option<ID>,
+        #[graphql(validator(maximum = 100))] min_score: Option<u8>,
+    ) -> async_graphql::Result<impl Stream<Item = ModerationItem>> {
+        ctx.moderation_persist()
+            .subscribe(
+                board_id.map(|id| srql::Thing::from((BOARD_TABLE_NAME, id.as_str()))),
+                min_score,
+            )
+            .await
+            .extend()
+    }
+}
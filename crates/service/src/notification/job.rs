@@ -0,0 +1,43 @@
+use std::time::Duration;
+
+use tokio::{
+    task::JoinHandle,
+    time::{interval, MissedTickBehavior},
+};
+use tracing::{debug, error, trace};
+
+use super::release_held;
+use crate::persist::Persist;
+
+/// How often notifications held during quiet hours are checked for release.
+pub const RELEASE_INTERVAL: Duration = Duration::from_mins(1);
+
+static RELEASE_LOCK: &str = "notification_release";
+
+/// Spawns a task that sends notifications that were held during quiet hours
+/// once they're over.
+pub fn spawn_releases(persist: Persist) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = interval(RELEASE_INTERVAL);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+
+            let res = persist
+                .execute_in_lock(RELEASE_LOCK, || async {
+                    let _job = persist.metrics().track_job();
+                    release_held(&persist).await
+                })
+                .await;
+
+            match res {
+                Ok(Some(Ok(0))) => trace!("No held notifications to release"),
+                Ok(Some(Ok(count))) => debug!(count, "Held notifications released"),
+                Ok(Some(Err(err))) => error!(error = ?err, "Failed to release held notifications"),
+                Ok(None) => trace!("Held notifications are already being released"),
+                Err(err) => error!(error = ?err, "Failed to lock notification releases"),
+            }
+        }
+    })
+}
@@ -0,0 +1,211 @@
+use serde::{Deserialize, Serialize};
+
+use super::NOTIFICATION_TABLE_NAME;
+use crate::{migration::Migration, prelude::*};
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NotificationMigration {
+    #[default]
+    Init,
+    Channels,
+}
+
+impl Migration for NotificationMigration {
+    const SUBSYSTEM: &'static str = "subsys_notification";
+
+    fn next(self) -> Option<Self> {
+        match self {
+            Self::Init => Some(Self::Channels),
+            Self::Channels => None,
+        }
+    }
+
+    fn build(&self, statements: &mut Vec<srql::Statement>) {
+        use NotificationMigration as S;
+        match self {
+            S::Init => Self::build_init(statements),
+            S::Channels => Self::build_channels(statements),
+        }
+    }
+}
+
+impl NotificationMigration {
+    fn build_init(statements: &mut Vec<srql::Statement>) {
+        statements.push(srql::define_index(
+            "notification_batch_index",
+            NOTIFICATION_TABLE_NAME,
+            [
+                srql::field("account_id"),
+                srql::field("kind"),
+                srql::field("subject_id"),
+            ],
+        ));
+
+        // Notifications sent before they were batched each have one actor.
+        let unset = |field: &str| {
+            srql::Cond(
+                srql::Expression::Binary {
+                    l: srql::field(field).into(),
+                    o: srql::Operator::Equal,
+                    r: srql::Value::None,
+                }
+                .into(),
+            )
+        };
+        let backfill = |field: &str, value: srql::Value| {
+            srql::Statement::Update(srql::UpdateStatement {
+                what: srql::table(NOTIFICATION_TABLE_NAME),
+                data: srql::Data::SetExpression(vec![(
+                    srql::field(field),
+                    srql::Operator::Equal,
+                    value,
+                )])
+                .into(),
+                cond: unset(field).into(),
+                ..Default::default()
+            })
+        };
+        statements.push(backfill(
+            "actor_ids",
+            srql::Value::Subquery(Box::new(srql::Subquery::Ifelse(srql::IfelseStatement {
+                exprs: vec![(
+                    srql::field("actor_id").into(),
+                    srql::array(vec![srql::field("actor_id").into()]),
+                )],
+                close: Some(srql::array(vec![])),
+            }))),
+        ));
+        statements.push(backfill("actor_count", 1u64.into()));
+    }
+
+    fn build_channels(statements: &mut Vec<srql::Statement>) {
+        statements.push(srql::define_index(
+            "notification_held_index",
+            NOTIFICATION_TABLE_NAME,
+            [srql::field("held_until")],
+        ));
+
+        // Notifications sent before they could be routed were only in-app.
+        statements.push(srql::Statement::Update(srql::UpdateStatement {
+            what: srql::table(NOTIFICATION_TABLE_NAME),
+            data: srql::Data::SetExpression(vec![(
+                srql::field("channels"),
+                srql::Operator::Equal,
+                srql::array(vec!["in_app".into()]),
+            )])
+            .into(),
+            cond: srql::Cond(
+                srql::Expression::Binary {
+                    l: srql::field("channels").into(),
+                    o: srql::Operator::Equal,
+                    r: srql::Value::None,
+                }
+                .into(),
+            )
+            .into(),
+            ..Default::default()
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::{
+        account::testing::*,
+        notification::{testing::NotificationTestData as _, Notification, NotificationKind},
+        query::PaginationInput,
+    };
+
+    #[tokio::test]
+    async fn test_backfill_actors() {
+        let (mut data, acc) = TestData::with_user().await;
+        let actor = data.account().create_test_user().await;
+        data.login_as(&acc);
+
+        // Notifications as they were stored before batching.
+        let mut ids = vec![];
+        for actor_id in [Some(actor.id.clone()), None] {
+            let mut create = vec![];
+            acc.id
+                .clone()
+                .push_field(srql::field("account_id"), &mut create);
+            NotificationKind::Quote.push_field(srql::field("kind"), &mut create);
+            actor_id.push_field(srql::field("actor_id"), &mut create);
+            let query = srql::obj_create_query(NOTIFICATION_TABLE_NAME, create, data.persist.ids());
+            let created: Option<srql::Thing> = data
+                .persist
+                .db()
+                .query(srql::query([srql::Statement::Create(query)]))
+                .await
+                .unwrap()
+                .take("id")
+                .unwrap();
+            ids.push(created.unwrap());
+        }
+
+        let mut statements = vec![];
+        NotificationMigration::Init.build(&mut statements);
+        data.persist
+            .db()
+            .query(srql::query(statements))
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+
+        let mut notifications = vec![];
+        for id in ids {
+            let notification: Option<Notification> = data.persist.db().select(id).await.unwrap();
+            notifications.push(notification.unwrap());
+        }
+        assert_eq!(notifications[0].actor_ids, vec![actor.id]);
+        assert_eq!(notifications[0].actor_count, 1);
+        assert!(notifications[1].actor_ids.is_empty());
+        assert_eq!(notifications[1].actor_count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_backfill_channels() {
+        let (data, acc) = TestData::with_user().await;
+
+        // A notification as it was stored before it could be routed.
+        let mut create = vec![];
+        acc.id
+            .clone()
+            .push_field(srql::field("account_id"), &mut create);
+        NotificationKind::Quote.push_field(srql::field("kind"), &mut create);
+        Vec::<srql::Thing>::new().push_field(srql::field("actor_ids"), &mut create);
+        1u64.push_field(srql::field("actor_count"), &mut create);
+        let query = srql::obj_create_query(NOTIFICATION_TABLE_NAME, create, data.persist.ids());
+        data.persist
+            .db()
+            .query(srql::query([srql::Statement::Create(query)]))
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+
+        let mut statements = vec![];
+        NotificationMigration::Channels.build(&mut statements);
+        data.persist
+            .db()
+            .query(srql::query(statements))
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+
+        let res = data
+            .notification()
+            .list()
+            .unwrap()
+            .with_pagination(PaginationInput::new().forward(10))
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(res.edges.len(), 1);
+    }
+}
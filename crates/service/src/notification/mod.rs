@@ -0,0 +1,20 @@
+mod job;
+mod migration;
+mod models;
+mod persist;
+mod schema;
+mod transport;
+
+pub use job::*;
+pub use migration::*;
+pub use models::*;
+pub use persist::*;
+pub use schema::*;
+pub use transport::*;
+
+pub static NOTIFICATION_TABLE_NAME: &str = "notification";
+pub static NOTIFICATION_SETTINGS_TABLE_NAME: &str = "notification_settings";
+
+/// How long a notification of a batched kind keeps collecting more of the
+/// same notification, before a new one is started.
+const NOTIFICATION_BATCH_HOURS: i64 = 24;
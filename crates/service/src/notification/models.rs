@@ -0,0 +1,614 @@
+use std::sync::Arc;
+
+use async_graphql::{ComplexObject, Context, Enum, InputObject, MaybeUndefined, SimpleObject, ID};
+use chrono::{DateTime, Duration, NaiveTime, TimeZone as _, Utc};
+use serde::{Deserialize, Serialize};
+use surrealdb::sql::Thing;
+
+use super::NOTIFICATION_TABLE_NAME;
+use crate::{
+    account::{get_recovery_request, RestrictionKind},
+    id_obj_impls,
+    locale::{viewer_locales, LanguageIdentifier, Localizer, Tz},
+    persist::Persist,
+    prelude::*,
+    query::OpaqueCursor,
+    security::{SecurityEvent, SecurityEventKind},
+};
+
+pub type NotificationCursor = OpaqueCursor<String>;
+
+/// What caused a notification to be sent.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    /// One of the account's posts was quoted.
+    Quote,
+    /// Something happened to the account that it should check was them. The
+    /// subject is the security event.
+    Security,
+    /// An account reported activity it didn't recognize and needs help
+    /// getting back. These are sent to the instance's admins, and the subject
+    /// is the account.
+    AccountRecovery,
+    /// An admin restricted the account. The subject is the account, whose
+    /// restriction says how and why.
+    Restricted,
+    /// An export of the account's data that it asked for is ready to be
+    /// downloaded. The subject is the export.
+    DataExportReady,
+    /// Someone is recovering an account that has the account as a trusted
+    /// contact, and needs it to approve. The subject is the recovery.
+    RecoveryApproval,
+    /// The account is running out of two-factor recovery codes, and should
+    /// generate new ones.
+    RecoveryCodesLow,
+    /// The account hasn't signed in for the instance's dormancy period, and
+    /// may have its user ID released if it doesn't sign in soon.
+    Dormant,
+    /// The account sent itself a notification to check its settings.
+    Test,
+}
+
+impl NotificationKind {
+    /// Whether notifications of this kind about the same subject are combined
+    /// into one, rather than sent separately, so that popular posts don't
+    /// flood the account with notifications.
+    #[must_use]
+    pub fn is_batched(self) -> bool {
+        match self {
+            Self::Quote => true,
+            Self::Security
+            | Self::AccountRecovery
+            | Self::Restricted
+            | Self::DataExportReady
+            | Self::RecoveryApproval
+            | Self::RecoveryCodesLow
+            | Self::Dormant
+            | Self::Test => false,
+        }
+    }
+
+    /// Whether notifications of this kind are sent straight away during
+    /// quiet hours. Someone who has taken over an account won't wait for
+    /// them to end, so security notifications aren't held.
+    #[must_use]
+    pub fn ignores_quiet_hours(self) -> bool {
+        match self {
+            Self::Security | Self::AccountRecovery | Self::Restricted | Self::RecoveryApproval => {
+                true
+            }
+            Self::Quote
+            | Self::DataExportReady
+            | Self::RecoveryCodesLow
+            | Self::Dormant
+            | Self::Test => false,
+        }
+    }
+}
+
+impl QueryValue for NotificationKind {
+    fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
+        srql::to_value(self)
+            .ok()
+            .map(|value| (field, srql::Operator::Equal, value))
+    }
+}
+
+/// A notification sent to an account.
+#[derive(SimpleObject, Debug, Clone, Deserialize)]
+#[graphql(complex)]
+pub struct Notification {
+    #[graphql(skip)]
+    pub id: Thing,
+    #[graphql(skip)]
+    pub account_id: Thing,
+    #[graphql(skip)]
+    pub actor_id: Option<Thing>,
+    #[graphql(skip)]
+    pub post_id: Option<Thing>,
+    #[graphql(skip)]
+    pub subject_id: Option<Thing>,
+    /// The accounts that caused the notification, most recent first.
+    #[graphql(skip)]
+    pub actor_ids: Vec<Thing>,
+
+    /// What caused the notification.
+    pub kind: NotificationKind,
+    /// How many times the notification has happened. Notifications about the
+    /// same subject are combined while they're recent, and an account that
+    /// causes the same notification again is only counted once.
+    pub actor_count: u64,
+
+    /// Where the notification is sent. Notifications that aren't sent
+    /// `IN_APP` aren't listed or streamed to the account.
+    #[serde(default = "default_channels")]
+    pub channels: Vec<NotificationChannel>,
+    /// When the account's quiet hours end, if the notification is being held
+    /// until then. Held notifications are still listed, but aren't streamed
+    /// or sent anywhere else until they're released.
+    pub held_until: Option<DateTime<Utc>>,
+
+    /// A timestamp indicating the last time the notification was updated.
+    pub updated_at: DateTime<Utc>,
+}
+
+#[ComplexObject]
+impl Notification {
+    /// The notification's unique ID.
+    async fn id(&self) -> ID {
+        self.id.to_gql_id()
+    }
+
+    /// The ID of the account that most recently caused the notification, if
+    /// any.
+    async fn actor_id(&self) -> Option<ID> {
+        self.actor_id.as_ref().map(ToGqlId::to_gql_id)
+    }
+
+    /// The IDs of the accounts that caused the notification, most recent
+    /// first. Anonymous actions are counted in `actorCount`, but aren't
+    /// listed here.
+    async fn actor_ids(
+        &self,
+        #[graphql(default = 10, validator(maximum = 100))] first: usize,
+    ) -> Vec<ID> {
+        self.actor_ids
+            .iter()
+            .take(first)
+            .map(ToGqlId::to_gql_id)
+            .collect()
+    }
+
+    /// The ID of the post that most recently caused the notification, if any.
+    /// For quotes, this is the quoting post.
+    async fn post_id(&self) -> Option<ID> {
+        self.post_id.as_ref().map(ToGqlId::to_gql_id)
+    }
+
+    /// The ID of the thing that the notification is about, if any. For
+    /// quotes, this is the post that was quoted.
+    async fn subject_id(&self) -> Option<ID> {
+        self.subject_id.as_ref().map(ToGqlId::to_gql_id)
+    }
+
+    /// The security event that the notification is about, for security
+    /// notifications.
+    async fn security_event(&self, ctx: &Context<'_>) -> GqlResult<Option<SecurityEvent>> {
+        self.get_security_event(ctx).await.extend()
+    }
+
+    /// A short description of the notification to show to the account, in
+    /// its locale.
+    async fn title(&self, ctx: &Context<'_>) -> GqlResult<String> {
+        let locales = viewer_locales(ctx).await.extend()?;
+        let localizer = ctx.data_unchecked::<Arc<Localizer>>();
+
+        match self.kind {
+            NotificationKind::Quote => self.quote_title(ctx, &locales, localizer).await,
+            NotificationKind::Security => {
+                let event = self.get_security_event(ctx).await.extend()?;
+                Ok(match event.map(|event| event.kind) {
+                    Some(SecurityEventKind::NewDevice) => {
+                        localizer.render(&locales, "notification-security-new-device", &[])
+                    }
+                    _ => localizer.render(&locales, "notification-security", &[]),
+                })
+            }
+            NotificationKind::DataExportReady => {
+                Ok(localizer.render(&locales, "notification-data-export-ready", &[]))
+            }
+            NotificationKind::RecoveryCodesLow => {
+                Ok(localizer.render(&locales, "notification-recovery-codes-low", &[]))
+            }
+            NotificationKind::Dormant => {
+                Ok(localizer.render(&locales, "notification-dormant", &[]))
+            }
+            NotificationKind::Test => Ok(localizer.render(&locales, "notification-test", &[])),
+            NotificationKind::Restricted => {
+                let account = match &self.subject_id {
+                    Some(subject_id) => ctx
+                        .account_persist()
+                        .get(&subject_id.to_gql_id())
+                        .await
+                        .extend()?,
+                    None => None,
+                };
+                let now = ctx.data_unchecked::<Persist>().clock().now();
+                Ok(
+                    match account
+                        .as_ref()
+                        .and_then(|account| account.active_restriction(now))
+                    {
+                        // Shadow limits aren't shown to the account, so one
+                        // that replaced an earlier restriction looks lifted.
+                        Some(restriction) if restriction.kind != RestrictionKind::ShadowLimited => {
+                            localizer.render(
+                                &locales,
+                                match restriction.kind {
+                                    RestrictionKind::Suspended => {
+                                        "notification-restricted-suspended"
+                                    }
+                                    _ => "notification-restricted-silenced",
+                                },
+                                &[("reason", &restriction.reason)],
+                            )
+                        }
+                        _ => localizer.render(&locales, "notification-restricted-lifted", &[]),
+                    },
+                )
+            }
+            NotificationKind::RecoveryApproval => {
+                let persist = ctx.data_unchecked::<Persist>();
+                let request = match &self.subject_id {
+                    Some(subject_id) => get_recovery_request(persist, &subject_id.id.to_raw())
+                        .await
+                        .extend()?,
+                    None => None,
+                };
+                let account = match request {
+                    Some(request) => ctx
+                        .account_persist()
+                        .get(&request.account_id.to_gql_id())
+                        .await
+                        .extend()?,
+                    None => None,
+                };
+                Ok(match account {
+                    Some(account) => localizer.render(
+                        &locales,
+                        "notification-recovery-approval",
+                        &[("account", &account.user_id)],
+                    ),
+                    None => localizer.render(&locales, "notification-recovery-approval-ended", &[]),
+                })
+            }
+            NotificationKind::AccountRecovery => {
+                let account = match &self.subject_id {
+                    Some(subject_id) => ctx
+                        .account_persist()
+                        .get(&subject_id.to_gql_id())
+                        .await
+                        .extend()?,
+                    None => None,
+                };
+                Ok(match account {
+                    Some(account) => localizer.render(
+                        &locales,
+                        "notification-account-recovery",
+                        &[("account", &account.user_id)],
+                    ),
+                    None => {
+                        localizer.render(&locales, "notification-account-recovery-deleted", &[])
+                    }
+                })
+            }
+        }
+    }
+}
+
+impl Notification {
+    async fn get_security_event(&self, ctx: &Context<'_>) -> Result<Option<SecurityEvent>> {
+        match (self.kind, &self.subject_id) {
+            (NotificationKind::Security, Some(subject_id)) => {
+                ctx.security_event_persist().get(subject_id).await
+            }
+            _ => Ok(None),
+        }
+    }
+
+    async fn quote_title(
+        &self,
+        ctx: &Context<'_>,
+        locales: &[LanguageIdentifier],
+        localizer: &Localizer,
+    ) -> GqlResult<String> {
+        let actor = match self.actor_ids.first() {
+            Some(actor_id) => ctx
+                .account_persist()
+                .get(&actor_id.to_gql_id())
+                .await
+                .extend()?,
+            None => None,
+        };
+
+        let count = self.actor_count.to_string();
+        let others = self.actor_count.saturating_sub(1).to_string();
+        Ok(match actor {
+            Some(actor) if self.actor_count > 1 => localizer.render(
+                locales,
+                "notification-quote-batch",
+                &[("actor", &actor.user_id), ("others", &others)],
+            ),
+            Some(actor) => {
+                localizer.render(locales, "notification-quote", &[("actor", &actor.user_id)])
+            }
+            None if self.actor_count > 1 => localizer.render(
+                locales,
+                "notification-quote-batch-anonymous",
+                &[("count", &count)],
+            ),
+            None => localizer.render(locales, "notification-quote-anonymous", &[]),
+        })
+    }
+}
+
+id_obj_impls!(Notification);
+
+/// The information needed to send a notification.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CreateNotification {
+    /// The account that will receive the notification.
+    pub account_id: Thing,
+    pub kind: NotificationKind,
+    pub actor_id: Option<Thing>,
+    pub post_id: Option<Thing>,
+    /// What the notification is about, which notifications of kinds that are
+    /// batched are combined by.
+    pub subject_id: Option<Thing>,
+}
+
+impl Notification {
+    pub fn create(
+        params: CreateNotification,
+        channels: Vec<NotificationChannel>,
+        held_until: Option<DateTime<Utc>>,
+        ids: &dyn IdGen,
+    ) -> srql::CreateStatement {
+        let mut create = vec![];
+        params.append(&mut create);
+        channels.push_field(srql::field("channels"), &mut create);
+        held_until.push_field(srql::field("held_until"), &mut create);
+        srql::obj_create_query(NOTIFICATION_TABLE_NAME, create, ids)
+    }
+
+    /// Whether the notification is shown in the app.
+    #[must_use]
+    pub fn in_app(&self) -> bool {
+        self.channels.contains(&NotificationChannel::InApp)
+    }
+}
+
+impl CreateObject for CreateNotification {
+    fn append(self, expr: &mut srql::SetExpr) {
+        self.account_id.push_field(srql::field("account_id"), expr);
+        self.kind.push_field(srql::field("kind"), expr);
+        let actor_ids: Vec<Thing> = self.actor_id.iter().cloned().collect();
+        actor_ids.push_field(srql::field("actor_ids"), expr);
+        1u64.push_field(srql::field("actor_count"), expr);
+        self.actor_id.push_field(srql::field("actor_id"), expr);
+        self.post_id.push_field(srql::field("post_id"), expr);
+        self.subject_id.push_field(srql::field("subject_id"), expr);
+    }
+}
+
+/// Where a notification can be sent.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationChannel {
+    /// Listed in `notifications` and streamed by `notificationAdded`.
+    InApp,
+    /// Pushed to the account's devices.
+    Push,
+    /// Emailed to the account.
+    Email,
+}
+
+fn default_channels() -> Vec<NotificationChannel> {
+    vec![NotificationChannel::InApp]
+}
+
+impl QueryValue for NotificationChannel {
+    fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
+        srql::to_value(self)
+            .ok()
+            .map(|value| (field, srql::Operator::Equal, value))
+    }
+}
+
+impl QueryValue for Vec<NotificationChannel> {
+    fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
+        srql::to_value(self)
+            .ok()
+            .map(|value| (field, srql::Operator::Equal, value))
+    }
+}
+
+/// A time of day during which notifications aren't pushed or emailed, in the
+/// account's timezone. Quiet hours can run past midnight, such as from 22:00
+/// to 07:00.
+#[derive(SimpleObject, InputObject, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[graphql(input_name = "QuietHoursInput")]
+pub struct QuietHours {
+    /// When quiet hours start.
+    pub start: NaiveTime,
+    /// When quiet hours end.
+    pub end: NaiveTime,
+}
+
+impl QuietHours {
+    pub fn validate(&self) -> Result<()> {
+        if self.start == self.end {
+            return Err(Error::InputInvalid(
+                "quiet hours must start and end at different times".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// When the quiet hours that `now` falls in end, or `None` if it isn't
+    /// in quiet hours.
+    #[must_use]
+    pub fn held_until(&self, now: DateTime<Utc>, tz: Tz) -> Option<DateTime<Utc>> {
+        let local = now.with_timezone(&tz).naive_local();
+        let time = local.time();
+        let quiet = if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        };
+        if !quiet {
+            return None;
+        }
+
+        let mut end = local.date().and_time(self.end);
+        if end <= local {
+            end += Duration::days(1);
+        }
+        // The end can be skipped over when the clocks go forward, in which
+        // case the first time after it that exists is used.
+        (0..=2)
+            .find_map(|hours| {
+                tz.from_local_datetime(&(end + Duration::hours(hours)))
+                    .earliest()
+            })
+            .map(|end| end.with_timezone(&Utc))
+    }
+}
+
+/// Where notifications of a kind are sent.
+#[derive(SimpleObject, InputObject, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[graphql(input_name = "NotificationRouteInput")]
+pub struct NotificationRoute {
+    pub kind: NotificationKind,
+    /// The channels that notifications of the kind are sent on. If empty,
+    /// they aren't sent at all.
+    pub channels: Vec<NotificationChannel>,
+}
+
+/// How an account is notified.
+#[derive(SimpleObject, Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct NotificationSettings {
+    /// When notifications are held back from being pushed or emailed.
+    pub quiet_hours: Option<QuietHours>,
+    /// Where each kind of notification is sent. Kinds that aren't listed are
+    /// only sent `IN_APP`.
+    #[serde(default)]
+    pub routes: Vec<NotificationRoute>,
+}
+
+impl NotificationSettings {
+    /// The channels that notifications of a kind are sent on.
+    #[must_use]
+    pub fn channels_for(&self, kind: NotificationKind) -> Vec<NotificationChannel> {
+        self.routes
+            .iter()
+            .find(|route| route.kind == kind)
+            .map_or_else(default_channels, |route| route.channels.clone())
+    }
+}
+
+#[derive(InputObject, Debug, Default, Clone, PartialEq, Eq)]
+pub struct UpdateNotificationSettings {
+    /// When notifications are held back from being pushed or emailed. If not
+    /// given, quiet hours are not changed. If null is given, they're turned
+    /// off.
+    pub quiet_hours: MaybeUndefined<QuietHours>,
+    /// Where each kind of notification is sent. If given, this replaces every
+    /// route. A kind can only be listed once.
+    #[graphql(validator(max_items = 16))]
+    pub routes: Option<Vec<NotificationRoute>>,
+}
+
+impl UpdateNotificationSettings {
+    pub fn validate(&self) -> Result<()> {
+        if let MaybeUndefined::Value(quiet_hours) = &self.quiet_hours {
+            quiet_hours.validate()?;
+        }
+        if let Some(routes) = &self.routes {
+            for (i, route) in routes.iter().enumerate() {
+                if routes[..i].iter().any(|other| other.kind == route.kind) {
+                    return Err(Error::InputInvalid(
+                        "each kind of notification can only be routed once".into(),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The fields to set on the account's settings.
+    pub fn into_fields(self) -> Result<srql::SetExpr> {
+        let mut update = vec![];
+        match self.quiet_hours {
+            MaybeUndefined::Undefined => {}
+            MaybeUndefined::Null => {
+                update.push((
+                    srql::field("quiet_hours"),
+                    srql::Operator::Equal,
+                    srql::Value::None,
+                ));
+            }
+            MaybeUndefined::Value(quiet_hours) => update.push((
+                srql::field("quiet_hours"),
+                srql::Operator::Equal,
+                srql::to_value(quiet_hours).map_err(Error::from_err)?,
+            )),
+        }
+        if let Some(routes) = self.routes {
+            update.push((
+                srql::field("routes"),
+                srql::Operator::Equal,
+                srql::to_value(routes).map_err(Error::from_err)?,
+            ));
+        }
+        Ok(update)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use test_case::test_case;
+
+    use super::*;
+
+    fn time(hour: u32, min: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, min, 0).unwrap()
+    }
+
+    #[test_case((22, 0), (7, 0), "2024-01-15T23:30:00Z", Some("2024-01-16T07:00:00Z") ; "overnight")]
+    #[test_case((22, 0), (7, 0), "2024-01-16T06:59:00Z", Some("2024-01-16T07:00:00Z") ; "early morning")]
+    #[test_case((22, 0), (7, 0), "2024-01-16T07:00:00Z", None ; "at the end")]
+    #[test_case((22, 0), (7, 0), "2024-01-16T12:00:00Z", None ; "during the day")]
+    #[test_case((12, 0), (13, 0), "2024-01-16T12:30:00Z", Some("2024-01-16T13:00:00Z") ; "same day")]
+    fn test_held_until(start: (u32, u32), end: (u32, u32), now: &str, expected: Option<&str>) {
+        let quiet_hours = QuietHours {
+            start: time(start.0, start.1),
+            end: time(end.0, end.1),
+        };
+        let now: DateTime<Utc> = now.parse().unwrap();
+        let expected = expected.map(|expected| expected.parse::<DateTime<Utc>>().unwrap());
+        assert_eq!(quiet_hours.held_until(now, Tz::UTC), expected);
+    }
+
+    #[test]
+    fn test_held_until_in_timezone() {
+        let quiet_hours = QuietHours {
+            start: time(22, 0),
+            end: time(7, 0),
+        };
+        // 23:30 in London during summer time is 22:30 UTC.
+        let now: DateTime<Utc> = "2024-07-01T22:30:00Z".parse().unwrap();
+        assert_eq!(
+            quiet_hours.held_until(now, Tz::Europe__London),
+            Some("2024-07-02T06:00:00Z".parse().unwrap())
+        );
+        assert_eq!(quiet_hours.held_until(now, Tz::America__New_York), None);
+    }
+
+    #[test]
+    fn test_held_until_clocks_forward() {
+        // 01:00 to 01:30 doesn't exist in London when the clocks go forward,
+        // so quiet hours end at 02:00 local time instead.
+        let quiet_hours = QuietHours {
+            start: time(0, 0),
+            end: time(1, 30),
+        };
+        let now: DateTime<Utc> = "2024-03-31T00:30:00Z".parse().unwrap();
+        assert_eq!(
+            quiet_hours.held_until(now, Tz::Europe__London),
+            Some("2024-03-31T01:30:00Z".parse().unwrap())
+        );
+    }
+}
@@ -0,0 +1,519 @@
+#[cfg(test)]
+mod tests;
+
+use async_graphql::{
+    connection::{Connection, Edge},
+    MaybeUndefined,
+};
+use chrono::{DateTime, Utc};
+use futures::Stream;
+use tracing::instrument;
+
+use super::{
+    CreateNotification, Notification, NotificationChannel, NotificationCursor,
+    NotificationDelivery, NotificationKind, NotificationSettings, UpdateNotificationSettings,
+    NOTIFICATION_BATCH_HOURS, NOTIFICATION_SETTINGS_TABLE_NAME, NOTIFICATION_TABLE_NAME,
+};
+use crate::{
+    account::{Account, CurrentAccount},
+    conversation::is_muted_reply,
+    locale::{parse_timezone, Tz},
+    persist::Persist,
+    post::Post,
+    prelude::*,
+    query::{OpaqueCursor, PaginationInput, PaginationOptions, ResultSlice, SRQL_ORDER_DESC},
+};
+
+pub struct NotificationPersist<'a> {
+    persist: &'a Persist,
+    current: &'a CurrentAccount,
+}
+
+impl<'a> NotificationPersist<'a> {
+    pub fn new(persist: &'a Persist, current: &'a CurrentAccount) -> Self {
+        Self { persist, current }
+    }
+
+    /// Sends a notification. Accounts are never notified about their own
+    /// actions, or about replies by someone they muted in that conversation,
+    /// in which case nothing is sent.
+    ///
+    /// If the notification's kind is batched and the account was recently
+    /// sent the same notification about the same subject, that notification
+    /// is updated to include this one instead.
+    ///
+    /// The account's settings choose which channels it's sent on, and nothing
+    /// is sent if it's routed to none of them. During the account's quiet
+    /// hours, it's stored but held back until they end.
+    #[instrument(skip_all)]
+    pub async fn notify(&self, notification: CreateNotification) -> Result<Option<Notification>> {
+        if notification.actor_id.as_ref() == Some(&notification.account_id) {
+            return Ok(None);
+        }
+        if let Some(post_id) = &notification.post_id {
+            let post: Option<Post> = self.persist.load(post_id.clone()).await?;
+            if let Some(post) = post {
+                if is_muted_reply(self.persist, &notification.account_id, &post).await? {
+                    return Ok(None);
+                }
+            }
+        }
+
+        let settings = settings_of(self.persist, &notification.account_id).await?;
+        let channels = settings.channels_for(notification.kind);
+        if channels.is_empty() {
+            return Ok(None);
+        }
+        let held_until = held_until(self.persist, &notification, &settings).await?;
+
+        let notification = match self.recent_batch(&notification).await? {
+            Some(batch) => {
+                self.add_to_batch(batch, notification, channels, held_until)
+                    .await?
+            }
+            None => self
+                .persist
+                .db()
+                .query(Notification::create(
+                    notification,
+                    channels,
+                    held_until,
+                    self.persist.ids(),
+                ))
+                .await?
+                .take(0)?,
+        };
+        if let Some(notification) = &notification {
+            if notification.held_until.is_none() {
+                dispatch(self.persist, notification);
+            }
+        }
+        Ok(notification)
+    }
+
+    /// Sends the current account a notification through its settings, so it
+    /// can check where notifications end up.
+    #[instrument(skip_all)]
+    pub async fn send_test(&self) -> Result<Option<Notification>> {
+        let account_id = self.current.id()?.to_account_thing();
+        self.notify(CreateNotification {
+            account_id,
+            kind: NotificationKind::Test,
+            actor_id: None,
+            post_id: None,
+            subject_id: None,
+        })
+        .await
+    }
+
+    /// Gets how the current account is notified.
+    #[instrument(skip_all)]
+    pub async fn settings(&self) -> Result<NotificationSettings> {
+        let account_id = self.current.id()?.to_account_thing();
+        settings_of(self.persist, &account_id).await
+    }
+
+    /// Changes how the current account is notified.
+    #[instrument(skip_all)]
+    pub async fn update_settings(
+        &self,
+        update: UpdateNotificationSettings,
+    ) -> Result<NotificationSettings> {
+        update.validate()?;
+        let account_id = self.current.id()?.to_account_thing();
+        let thing = settings_thing(&account_id);
+        let fields = update.into_fields()?;
+
+        let existing: Option<NotificationSettings> =
+            self.persist.db().select(thing.clone()).await?;
+        if existing.is_some() {
+            if let Some(update) = srql::obj_update_query(thing, fields) {
+                self.persist.db().query(update).await?;
+            }
+        } else {
+            self.persist
+                .db()
+                .query(srql::obj_create_query_id(
+                    NOTIFICATION_SETTINGS_TABLE_NAME,
+                    fields,
+                    thing.id,
+                ))
+                .await?;
+        }
+        settings_of(self.persist, &account_id).await
+    }
+
+    /// Finds the notification that a new one should be combined with, if
+    /// there is one.
+    async fn recent_batch(
+        &self,
+        notification: &CreateNotification,
+    ) -> Result<Option<Notification>> {
+        let Some(subject_id) = &notification.subject_id else {
+            return Ok(None);
+        };
+        if !notification.kind.is_batched() {
+            return Ok(None);
+        }
+
+        let since = self.persist.clock().now() - chrono::Duration::hours(NOTIFICATION_BATCH_HOURS);
+        let matches = |(l, o, r): srql::SetExprItem| {
+            srql::Cond(srql::Expression::Binary { l: l.into(), o, r }.into())
+        };
+        let created_since = srql::Cond(
+            srql::Expression::Binary {
+                l: srql::field("id").into(),
+                o: srql::Operator::MoreThanOrEqual,
+                r: srql::Thing::from((NOTIFICATION_TABLE_NAME.to_owned(), srql::ulid_at(since)))
+                    .into(),
+            }
+            .into(),
+        );
+        let cond = [
+            notification
+                .kind
+                .into_query_value(srql::field("kind"))
+                .map(matches),
+            subject_id
+                .clone()
+                .into_query_value(srql::field("subject_id"))
+                .map(matches),
+            Some(created_since),
+        ]
+        .into_iter()
+        .fold(
+            notification
+                .account_id
+                .clone()
+                .into_query_value(srql::field("account_id"))
+                .map(matches),
+            srql::cond_and,
+        );
+
+        let batch: Option<Notification> = self
+            .persist
+            .db()
+            .query(srql::SelectStatement {
+                expr: srql::Fields::all(),
+                what: srql::table(NOTIFICATION_TABLE_NAME),
+                cond,
+                order: srql::Orders(vec![srql::Order {
+                    order: srql::field("id"),
+                    direction: SRQL_ORDER_DESC,
+                    ..Default::default()
+                }])
+                .into(),
+                limit: srql::Limit(srql::Number::Int(1).into()).into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        Ok(batch)
+    }
+
+    /// Updates a batched notification to include a new one, making its actor
+    /// the most recent. It's sent again using the account's current settings.
+    async fn add_to_batch(
+        &self,
+        batch: Notification,
+        notification: CreateNotification,
+        channels: Vec<NotificationChannel>,
+        held_until: Option<DateTime<Utc>>,
+    ) -> Result<Option<Notification>> {
+        let mut actor_ids = batch.actor_ids;
+        let mut actor_count = batch.actor_count;
+        match &notification.actor_id {
+            Some(actor_id) => {
+                let previous = actor_ids.iter().position(|id| id == actor_id);
+                match previous {
+                    Some(previous) => {
+                        actor_ids.remove(previous);
+                    }
+                    None => actor_count += 1,
+                }
+                actor_ids.insert(0, actor_id.clone());
+            }
+            None => actor_count += 1,
+        }
+
+        let mut update = vec![];
+        actor_ids.push_field(srql::field("actor_ids"), &mut update);
+        actor_count.push_field(srql::field("actor_count"), &mut update);
+        // The latest actor and post replace the previous ones, even when
+        // there aren't any.
+        let latest =
+            |id: Option<srql::Thing>| id.map_or(MaybeUndefined::Null, MaybeUndefined::Value);
+        latest(notification.actor_id).push_field(srql::field("actor_id"), &mut update);
+        latest(notification.post_id).push_field(srql::field("post_id"), &mut update);
+        channels.push_field(srql::field("channels"), &mut update);
+        held_until
+            .map_or(MaybeUndefined::Null, MaybeUndefined::Value)
+            .push_field(srql::field("held_until"), &mut update);
+        let Some(update) = srql::obj_update_query(batch.id, update) else {
+            return Ok(None);
+        };
+
+        let notification = self.persist.db().query(update).await?.take(0)?;
+        Ok(notification)
+    }
+
+    #[instrument(skip_all)]
+    pub fn list(&self) -> Result<NotificationListRequest<'a>> {
+        let account_id = self.current.id()?.to_account_thing();
+        Ok(NotificationListRequest::new(self.persist, account_id))
+    }
+
+    /// Streams notifications to the current account as they're sent,
+    /// including batched ones as they're updated. When `kinds` is given, only
+    /// notifications of those kinds are sent.
+    pub fn subscribe(
+        &self,
+        kinds: Option<Vec<NotificationKind>>,
+    ) -> Result<impl Stream<Item = Notification> + Send + 'static> {
+        let account_id = self.current.id()?.to_account_thing();
+        Ok(self
+            .persist
+            .notification_feed()
+            .subscribe(move |notification| {
+                notification.account_id == account_id
+                    && kinds
+                        .as_ref()
+                        .is_none_or(|kinds| kinds.contains(&notification.kind))
+            }))
+    }
+
+    /// Dismisses one of the current account's notifications.
+    #[instrument(skip_all)]
+    pub async fn dismiss(&self, id: &str) -> Result<Option<Notification>> {
+        let account_id = self.current.id()?.to_account_thing();
+        let notification: Option<Notification> = self
+            .persist
+            .db()
+            .select((NOTIFICATION_TABLE_NAME, id))
+            .await?;
+        match notification {
+            Some(notification) if notification.account_id != account_id => Err(Error::Unauthorized),
+            Some(_) => Ok(self
+                .persist
+                .db()
+                .delete((NOTIFICATION_TABLE_NAME, id))
+                .await?),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Sends notifications that were held during quiet hours now that they're
+/// over, returning how many were sent.
+#[instrument(skip_all)]
+pub async fn release_held(persist: &Persist) -> Result<usize> {
+    let held_until = |o: srql::Operator, r: srql::Value| {
+        srql::Cond(
+            srql::Expression::Binary {
+                l: srql::field("held_until").into(),
+                o,
+                r,
+            }
+            .into(),
+        )
+    };
+    let due: Vec<Notification> = persist
+        .db()
+        .query(srql::SelectStatement {
+            expr: srql::Fields::all(),
+            what: srql::table(NOTIFICATION_TABLE_NAME),
+            cond: srql::cond_and(
+                held_until(srql::Operator::NotEqual, srql::Value::None).into(),
+                held_until(
+                    srql::Operator::LessThanOrEqual,
+                    srql::Value::Datetime(srql::Datetime(persist.clock().now())),
+                )
+                .into(),
+            ),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+
+    let mut released = 0;
+    for notification in due {
+        let mut update = vec![];
+        MaybeUndefined::<DateTime<Utc>>::Null.push_field(srql::field("held_until"), &mut update);
+        let Some(update) = srql::obj_update_query(notification.id, update) else {
+            continue;
+        };
+        let notification: Option<Notification> = persist.db().query(update).await?.take(0)?;
+        if let Some(notification) = notification {
+            dispatch(persist, &notification);
+            released += 1;
+        }
+    }
+    Ok(released)
+}
+
+/// Sends a notification on each of its channels.
+fn dispatch(persist: &Persist, notification: &Notification) {
+    for &channel in &notification.channels {
+        match channel {
+            NotificationChannel::InApp => persist.notification_feed().publish(notification),
+            NotificationChannel::Push | NotificationChannel::Email => {
+                persist.notification_transport().send(NotificationDelivery {
+                    channel,
+                    account_id: notification.account_id.clone(),
+                    notification_id: notification.id.clone(),
+                    kind: notification.kind,
+                });
+            }
+        }
+    }
+}
+
+async fn settings_of(persist: &Persist, account_id: &srql::Thing) -> Result<NotificationSettings> {
+    let settings: Option<NotificationSettings> =
+        persist.db().select(settings_thing(account_id)).await?;
+    Ok(settings.unwrap_or_default())
+}
+
+/// When a new notification should be held until, if it's being sent during
+/// the account's quiet hours. They're worked out in the account's timezone,
+/// or UTC if it hasn't set one.
+async fn held_until(
+    persist: &Persist,
+    notification: &CreateNotification,
+    settings: &NotificationSettings,
+) -> Result<Option<DateTime<Utc>>> {
+    let Some(quiet_hours) = settings.quiet_hours else {
+        return Ok(None);
+    };
+    if notification.kind.ignores_quiet_hours() {
+        return Ok(None);
+    }
+    let account: Option<Account> = persist.load(notification.account_id.clone()).await?;
+    let tz = account
+        .and_then(|account| account.timezone)
+        .as_deref()
+        .and_then(parse_timezone)
+        .unwrap_or(Tz::UTC);
+    Ok(quiet_hours.held_until(persist.clock().now(), tz))
+}
+
+fn settings_thing(account_id: &srql::Thing) -> srql::Thing {
+    srql::Thing {
+        tb: NOTIFICATION_SETTINGS_TABLE_NAME.to_owned(),
+        id: account_id.id.clone(),
+    }
+}
+
+pub struct NotificationListRequest<'a> {
+    persist: &'a Persist,
+    account_id: srql::Thing,
+    pagination: Option<PaginationInput<OpaqueCursor<String>>>,
+}
+
+impl<'a> NotificationListRequest<'a> {
+    fn new(persist: &'a Persist, account_id: srql::Thing) -> Self {
+        Self {
+            persist,
+            account_id,
+            pagination: None,
+        }
+    }
+
+    pub fn with_pagination(
+        mut self,
+        args: impl Into<PaginationInput<OpaqueCursor<String>>>,
+    ) -> Self {
+        self.pagination = Some(args.into());
+        self
+    }
+
+    #[instrument(skip_all)]
+    pub async fn execute(self) -> Result<Connection<NotificationCursor, Notification>> {
+        let PaginationOptions {
+            cond,
+            order,
+            limit,
+            result_slice_opts,
+        } = (self.pagination, NOTIFICATION_TABLE_NAME).into();
+
+        let account_cond = srql::Cond(
+            srql::Expression::Binary {
+                l: srql::field("account_id").into(),
+                o: srql::Operator::Equal,
+                r: self.account_id.into(),
+            }
+            .into(),
+        );
+
+        // Notifications that aren't sent in-app are only pushed or emailed.
+        let in_app_cond = NotificationChannel::InApp
+            .into_query_value(srql::field("channels"))
+            .map(|(l, _, r)| {
+                srql::Cond(
+                    srql::Expression::Binary {
+                        l: l.into(),
+                        o: srql::Operator::Contain,
+                        r,
+                    }
+                    .into(),
+                )
+            });
+
+        let query = srql::SelectStatement {
+            expr: srql::Fields::all(),
+            what: srql::table(NOTIFICATION_TABLE_NAME),
+            order: srql::Orders(order.into_iter().collect()).into(),
+            cond: srql::cond_and(srql::cond_and(cond, account_cond.into()), in_app_cond),
+            limit,
+            ..Default::default()
+        };
+
+        let notifications: Vec<Notification> = self.persist.db().query(query).await?.take(0)?;
+        let ResultSlice {
+            results: notifications,
+            has_previous_page,
+            has_next_page,
+        } = ResultSlice::new(notifications, result_slice_opts);
+
+        let mut connection = Connection::new(has_previous_page, has_next_page);
+        connection.edges = notifications
+            .into_iter()
+            .map(|notification| {
+                Edge::new(OpaqueCursor(notification.id.to_gql_id().0), notification)
+            })
+            .collect();
+
+        Ok(connection)
+    }
+}
+
+#[cfg(test)]
+pub mod testing {
+    use std::sync::Arc;
+
+    use crate::{account::testing::TestData, notification::MemoryNotificationTransport};
+
+    use super::NotificationPersist;
+
+    pub trait NotificationTestData {
+        fn notification(&self) -> NotificationPersist<'_>;
+        /// Keeps pushed and emailed notifications in memory instead of
+        /// dropping them.
+        fn record_notifications(&mut self) -> MemoryNotificationTransport;
+    }
+
+    impl NotificationTestData for TestData {
+        fn notification(&self) -> NotificationPersist<'_> {
+            NotificationPersist::new(&self.persist, &self.current)
+        }
+
+        fn record_notifications(&mut self) -> MemoryNotificationTransport {
+            let transport = MemoryNotificationTransport::default();
+            self.persist = self
+                .persist
+                .clone()
+                .with_notification_transport(Arc::new(transport.clone()));
+            transport
+        }
+    }
+}
@@ -0,0 +1,441 @@
+use async_graphql::MaybeUndefined;
+use chrono::NaiveTime;
+use pretty_assertions::assert_eq;
+
+use super::{testing::NotificationTestData as _, *};
+use crate::{
+    account::{testing::*, UpdateAccount},
+    notification::{NotificationRoute, QuietHours, NOTIFICATION_BATCH_HOURS},
+    post::{testing::PostTestData as _, CreatePost},
+    provider::MockClock,
+    query::PaginationInput,
+};
+
+#[tokio::test]
+async fn test_notify() {
+    let (mut data, acc) = TestData::with_user().await;
+    let actor = data.account().create_test_user().await;
+    data.login_as(&actor);
+    let post = data.generate_post().await;
+
+    let res = data
+        .notification()
+        .notify(CreateNotification {
+            account_id: acc.id.clone(),
+            kind: NotificationKind::Quote,
+            actor_id: Some(actor.id.clone()),
+            post_id: Some(post.id.clone()),
+            subject_id: None,
+        })
+        .await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+
+    let res = res.unwrap().unwrap();
+    assert_eq!(res.account_id, acc.id);
+    assert_eq!(res.actor_id, Some(actor.id));
+    assert_eq!(res.post_id, Some(post.id));
+    assert_eq!(res.kind, NotificationKind::Quote);
+}
+
+#[tokio::test]
+async fn test_notify_self() {
+    let (data, acc) = TestData::with_user().await;
+
+    let res = data
+        .notification()
+        .notify(CreateNotification {
+            account_id: acc.id.clone(),
+            kind: NotificationKind::Quote,
+            actor_id: Some(acc.id),
+            post_id: None,
+            subject_id: None,
+        })
+        .await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+    assert!(res.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_list() {
+    let (mut data, acc) = TestData::with_user().await;
+    let other = data.account().create_test_user().await;
+    let actor = data.account().create_test_user().await;
+
+    let mut expected = vec![];
+    for _ in 0..3 {
+        expected.push(
+            data.notification()
+                .notify(CreateNotification {
+                    account_id: acc.id.clone(),
+                    kind: NotificationKind::Quote,
+                    actor_id: Some(actor.id.clone()),
+                    post_id: None,
+                    subject_id: None,
+                })
+                .await
+                .unwrap()
+                .unwrap(),
+        );
+    }
+    data.notification()
+        .notify(CreateNotification {
+            account_id: other.id.clone(),
+            kind: NotificationKind::Quote,
+            actor_id: Some(actor.id.clone()),
+            post_id: None,
+            subject_id: None,
+        })
+        .await
+        .unwrap();
+    expected.sort_by(|a, b| b.id.cmp(&a.id));
+
+    let res = data
+        .notification()
+        .list()
+        .unwrap()
+        .with_pagination(PaginationInput::new().forward(10))
+        .execute()
+        .await;
+    assert!(res.is_ok());
+
+    let res: Vec<_> = res.unwrap().edges.into_iter().map(|e| e.node.id).collect();
+    let expected: Vec<_> = expected.into_iter().map(|n| n.id).collect();
+    assert_eq!(res, expected);
+
+    data.login_as(&other);
+    let res = data
+        .notification()
+        .list()
+        .unwrap()
+        .with_pagination(PaginationInput::new().forward(10))
+        .execute()
+        .await
+        .unwrap();
+    assert_eq!(res.edges.len(), 1);
+}
+
+#[tokio::test]
+async fn test_list_unauthenticated() {
+    let data = TestData::new().await;
+
+    let res = data.notification().list();
+    assert!(matches!(res, Err(Error::Unauthenticated)));
+}
+
+#[tokio::test]
+async fn test_dismiss() {
+    let (mut data, acc) = TestData::with_user().await;
+    let quoter = data.account().create_test_user().await;
+    let post = data.generate_post().await;
+
+    data.login_as(&quoter);
+    data.post()
+        .create(CreatePost {
+            quote_id: Some(post.id.to_gql_id()),
+            content: Some("Test".into()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    data.login_as(&acc);
+    let notifications = data
+        .notification()
+        .list()
+        .unwrap()
+        .with_pagination(PaginationInput::new().forward(10))
+        .execute()
+        .await
+        .unwrap();
+    assert_eq!(notifications.edges.len(), 1);
+    let id = notifications.edges[0].node.id.to_gql_id();
+
+    // Only the recipient can dismiss a notification.
+    data.login_as(&quoter);
+    let res = data.notification().dismiss(&id).await;
+    println!("{res:?}");
+    assert!(matches!(res, Err(Error::Unauthorized)));
+
+    data.login_as(&acc);
+    let res = data.notification().dismiss(&id).await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+    assert!(res.unwrap().is_some());
+
+    let notifications = data
+        .notification()
+        .list()
+        .unwrap()
+        .with_pagination(PaginationInput::new().forward(10))
+        .execute()
+        .await
+        .unwrap();
+    assert!(notifications.edges.is_empty());
+}
+
+#[tokio::test]
+async fn test_batch() {
+    let clock = MockClock::default();
+    let mut data = TestData::with_clock(clock.clone()).await;
+    let acc = data.account().create_test_user().await;
+    let first = data.account().create_test_user().await;
+    let second = data.account().create_test_user().await;
+    data.login_as(&acc);
+    let post = data.generate_post().await;
+
+    let quote = |actor: &AccData| CreateNotification {
+        account_id: acc.id.clone(),
+        kind: NotificationKind::Quote,
+        actor_id: Some(actor.id.clone()),
+        post_id: None,
+        subject_id: Some(post.id.clone()),
+    };
+    let notify = |notification| async {
+        data.notification()
+            .notify(notification)
+            .await
+            .unwrap()
+            .unwrap()
+    };
+
+    let batch = notify(quote(&first)).await;
+    assert_eq!(batch.actor_count, 1);
+    notify(quote(&second)).await;
+    // Accounts are only counted once, but become the most recent actor.
+    let res = notify(quote(&first)).await;
+    assert_eq!(res.id, batch.id);
+    assert_eq!(res.actor_count, 2);
+    assert_eq!(res.actor_id, Some(first.id.clone()));
+    assert_eq!(res.actor_ids, vec![first.id.clone(), second.id.clone()]);
+    let res = notify(CreateNotification {
+        actor_id: None,
+        ..quote(&first)
+    })
+    .await;
+    assert_eq!(res.actor_count, 3);
+    assert_eq!(res.actor_id, None);
+
+    // Other subjects get their own notifications.
+    let other = notify(CreateNotification {
+        subject_id: None,
+        ..quote(&first)
+    })
+    .await;
+    assert_ne!(other.id, batch.id);
+
+    // Once the batch is old enough, a new one is started.
+    clock.advance(chrono::Duration::hours(NOTIFICATION_BATCH_HOURS + 1));
+    let res = notify(quote(&second)).await;
+    assert_ne!(res.id, batch.id);
+    assert_eq!(res.actor_count, 1);
+}
+
+async fn list_ids(data: &TestData) -> Vec<srql::Thing> {
+    data.notification()
+        .list()
+        .unwrap()
+        .with_pagination(PaginationInput::new().forward(10))
+        .execute()
+        .await
+        .unwrap()
+        .edges
+        .into_iter()
+        .map(|e| e.node.id)
+        .collect()
+}
+
+fn route(kind: NotificationKind, channels: &[NotificationChannel]) -> NotificationRoute {
+    NotificationRoute {
+        kind,
+        channels: channels.to_vec(),
+    }
+}
+
+#[tokio::test]
+async fn test_routes() {
+    let (mut data, acc) = TestData::with_user().await;
+    let transport = data.record_notifications();
+    let actor = data.account().create_test_user().await;
+
+    let res = data
+        .notification()
+        .update_settings(UpdateNotificationSettings {
+            routes: Some(vec![
+                route(
+                    NotificationKind::Quote,
+                    &[NotificationChannel::Push, NotificationChannel::Email],
+                ),
+                route(NotificationKind::Test, &[]),
+            ]),
+            ..Default::default()
+        })
+        .await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+    assert_eq!(
+        res.unwrap().channels_for(NotificationKind::Security),
+        vec![NotificationChannel::InApp]
+    );
+
+    let quote = data
+        .notification()
+        .notify(CreateNotification {
+            account_id: acc.id.clone(),
+            kind: NotificationKind::Quote,
+            actor_id: Some(actor.id.clone()),
+            post_id: None,
+            subject_id: None,
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    let channels: Vec<_> = transport.take().into_iter().map(|d| d.channel).collect();
+    assert_eq!(
+        channels,
+        vec![NotificationChannel::Push, NotificationChannel::Email]
+    );
+    // It isn't shown in the app.
+    assert!(!quote.in_app());
+    assert!(list_ids(&data).await.is_empty());
+
+    // Kinds routed nowhere aren't sent at all.
+    let res = data.notification().send_test().await;
+    println!("{res:?}");
+    assert!(res.unwrap().is_none());
+    assert!(transport.take().is_empty());
+}
+
+#[tokio::test]
+async fn test_quiet_hours() {
+    let clock = MockClock::new("2024-01-15T23:00:00Z".parse().unwrap());
+    let mut data = TestData::with_clock(clock.clone()).await;
+    let transport = data.record_notifications();
+    let acc = data.account().create_test_user().await;
+    let actor = data.account().create_test_user().await;
+    data.login_as(&acc);
+    // New York is 5 hours behind UTC in January.
+    data.account()
+        .update(UpdateAccount {
+            timezone: MaybeUndefined::Value("America/New_York".into()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    data.notification()
+        .update_settings(UpdateNotificationSettings {
+            quiet_hours: MaybeUndefined::Value(QuietHours {
+                start: NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+                end: NaiveTime::from_hms_opt(8, 0, 0).unwrap(),
+            }),
+            routes: Some(vec![route(
+                NotificationKind::Quote,
+                &[NotificationChannel::InApp, NotificationChannel::Push],
+            )]),
+        })
+        .await
+        .unwrap();
+
+    let quote = data
+        .notification()
+        .notify(CreateNotification {
+            account_id: acc.id.clone(),
+            kind: NotificationKind::Quote,
+            actor_id: Some(actor.id.clone()),
+            post_id: None,
+            subject_id: None,
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        quote.held_until,
+        Some("2024-01-16T13:00:00Z".parse().unwrap())
+    );
+    assert!(transport.take().is_empty());
+    // Held notifications are still listed.
+    assert_eq!(list_ids(&data).await, vec![quote.id.clone()]);
+
+    // Security notifications aren't held.
+    let security = data
+        .notification()
+        .notify(CreateNotification {
+            account_id: acc.id.clone(),
+            kind: NotificationKind::Security,
+            actor_id: None,
+            post_id: None,
+            subject_id: None,
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(security.held_until, None);
+
+    assert_eq!(release_held(&data.persist).await.unwrap(), 0);
+    clock.advance(chrono::Duration::hours(14));
+    assert_eq!(release_held(&data.persist).await.unwrap(), 1);
+    assert_eq!(
+        transport.take(),
+        vec![NotificationDelivery {
+            channel: NotificationChannel::Push,
+            account_id: acc.id.clone(),
+            notification_id: quote.id.clone(),
+            kind: NotificationKind::Quote,
+        }]
+    );
+    let quote: Option<Notification> = data.persist.db().select(quote.id).await.unwrap();
+    assert_eq!(quote.unwrap().held_until, None);
+    assert_eq!(release_held(&data.persist).await.unwrap(), 0);
+
+    // Turning quiet hours off sends notifications straight away. The clock
+    // has moved past the old token's expiry, so sign in again first.
+    clock.advance(chrono::Duration::hours(10));
+    data.login_as(&acc);
+    data.notification()
+        .update_settings(UpdateNotificationSettings {
+            quiet_hours: MaybeUndefined::Null,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let res = data.notification().send_test().await.unwrap().unwrap();
+    assert_eq!(res.kind, NotificationKind::Test);
+    assert_eq!(res.account_id, acc.id);
+    assert_eq!(res.held_until, None);
+}
+
+#[tokio::test]
+async fn test_update_settings_invalid() {
+    let (data, _) = TestData::with_user().await;
+    let noon = NaiveTime::from_hms_opt(12, 0, 0).unwrap();
+
+    let res = data
+        .notification()
+        .update_settings(UpdateNotificationSettings {
+            quiet_hours: MaybeUndefined::Value(QuietHours {
+                start: noon,
+                end: noon,
+            }),
+            ..Default::default()
+        })
+        .await;
+    println!("{res:?}");
+    assert!(matches!(res, Err(Error::InputInvalid(_))));
+
+    let res = data
+        .notification()
+        .update_settings(UpdateNotificationSettings {
+            routes: Some(vec![
+                route(NotificationKind::Quote, &[NotificationChannel::Push]),
+                route(NotificationKind::Quote, &[NotificationChannel::Email]),
+            ]),
+            ..Default::default()
+        })
+        .await;
+    println!("{res:?}");
+    assert!(matches!(res, Err(Error::InputInvalid(_))));
+
+    let res = data.notification().settings().await.unwrap();
+    assert_eq!(res, NotificationSettings::default());
+}
@@ -0,0 +1,111 @@
+use async_graphql::{connection::Connection, Context, Object, Subscription, ID};
+use futures::Stream;
+use tracing::instrument;
+
+use super::{
+    Notification, NotificationCursor, NotificationKind, NotificationSettings,
+    UpdateNotificationSettings,
+};
+use crate::{
+    prelude::*,
+    query::{page_complexity, PaginationArgs},
+};
+
+#[derive(Default)]
+pub struct NotificationQuery;
+
+#[Object]
+impl NotificationQuery {
+    /// Lists the current account's notifications.
+    #[graphql(complexity = "page_complexity(first, last, child_complexity)")]
+    #[instrument(skip_all)]
+    async fn notifications(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> GqlResult<Connection<NotificationCursor, Notification>> {
+        ctx.notification_persist()
+            .list()
+            .extend()?
+            .with_pagination(
+                PaginationArgs {
+                    after,
+                    before,
+                    first,
+                    last,
+                }
+                .warn_if_clamped(ctx)
+                .validate()
+                .extend()?,
+            )
+            .execute()
+            .await
+            .extend()
+    }
+
+    /// Gets where the current account's notifications are sent, and when
+    /// they're held back.
+    #[instrument(skip_all)]
+    async fn notification_settings(&self, ctx: &Context<'_>) -> GqlResult<NotificationSettings> {
+        ctx.notification_persist().settings().await.extend()
+    }
+}
+
+#[derive(Default)]
+pub struct NotificationMutation;
+
+#[Object]
+impl NotificationMutation {
+    /// Dismisses a notification.
+    #[instrument(skip_all)]
+    async fn dismiss_notification(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+    ) -> GqlResult<Option<Notification>> {
+        ctx.notification_persist().dismiss(&id).await.extend()
+    }
+
+    /// Changes where the current account's notifications are sent, and when
+    /// they're held back.
+    #[instrument(skip_all)]
+    async fn update_notification_settings(
+        &self,
+        ctx: &Context<'_>,
+        update: UpdateNotificationSettings,
+    ) -> GqlResult<NotificationSettings> {
+        ctx.notification_persist()
+            .update_settings(update)
+            .await
+            .extend()
+    }
+
+    /// Sends the current account a `TEST` notification on the channels its
+    /// settings route them to, following its quiet hours. Returns nothing if
+    /// they aren't routed anywhere.
+    #[instrument(skip_all)]
+    async fn send_test_notification(&self, ctx: &Context<'_>) -> GqlResult<Option<Notification>> {
+        ctx.notification_persist().send_test().await.extend()
+    }
+}
+
+#[derive(Default)]
+pub struct NotificationSubscription;
+
+#[Subscription]
+impl NotificationSubscription {
+    /// Sends notifications to the current account as they're sent. Batched
+    /// notifications are sent again each time they're updated. When `kinds`
+    /// is given, only notifications of those kinds are sent.
+    #[allow(clippy::unused_async)]
+    async fn notification_added(
+        &self,
+        ctx: &Context<'_>,
+        kinds: Option<Vec<NotificationKind>>,
+    ) -> async_graphql::Result<impl Stream<Item = Notification>> {
+        ctx.notification_persist().subscribe(kinds).extend()
+    }
+}
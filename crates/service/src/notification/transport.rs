@@ -0,0 +1,63 @@
+use std::{
+    fmt::Debug,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use surrealdb::sql::Thing;
+use tracing::debug;
+
+use super::{NotificationChannel, NotificationKind};
+
+pub type SharedNotificationTransport = Arc<dyn NotificationTransport>;
+
+/// A notification to be sent outside the app.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NotificationDelivery {
+    /// Either [`NotificationChannel::Push`] or [`NotificationChannel::Email`].
+    pub channel: NotificationChannel,
+    pub account_id: Thing,
+    pub notification_id: Thing,
+    pub kind: NotificationKind,
+}
+
+/// Something that pushes or emails notifications to accounts.
+pub trait NotificationTransport: Debug + Send + Sync {
+    /// Sends a delivery in the background. Nothing waits on the result, so
+    /// failures are logged rather than returned.
+    fn send(&self, delivery: NotificationDelivery);
+}
+
+/// Drops deliveries, for instances that don't push or email notifications.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoNotificationTransport;
+
+impl NotificationTransport for NoNotificationTransport {
+    fn send(&self, delivery: NotificationDelivery) {
+        debug!(
+            channel = ?delivery.channel,
+            notification_id = %delivery.notification_id,
+            "No transport for notification channel, dropping"
+        );
+    }
+}
+
+/// Keeps deliveries in memory instead of sending them, so they can be
+/// checked later.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryNotificationTransport(Arc<Mutex<Vec<NotificationDelivery>>>);
+
+impl MemoryNotificationTransport {
+    /// Takes every delivery sent so far.
+    pub fn take(&self) -> Vec<NotificationDelivery> {
+        std::mem::take(&mut *self.0.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+impl NotificationTransport for MemoryNotificationTransport {
+    fn send(&self, delivery: NotificationDelivery) {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(delivery);
+    }
+}
@@ -0,0 +1,42 @@
+use std::time::Duration;
+
+use tokio::{
+    task::JoinHandle,
+    time::{interval, MissedTickBehavior},
+};
+use tracing::{debug, error, trace};
+
+use super::check_domains;
+use crate::persist::Persist;
+
+/// How often organizations' domains are checked.
+pub const DOMAIN_CHECK_INTERVAL: Duration = Duration::from_hours(1);
+
+static DOMAIN_CHECK_LOCK: &str = "organization_domain_check";
+
+/// Spawns a task that periodically checks whether organizations' domains
+/// still publish their verification tokens.
+pub fn spawn_domain_checks(persist: Persist) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = interval(DOMAIN_CHECK_INTERVAL);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+
+            let res = persist
+                .execute_in_lock(DOMAIN_CHECK_LOCK, || async {
+                    let _job = persist.metrics().track_job();
+                    check_domains(&persist).await
+                })
+                .await;
+
+            match res {
+                Ok(Some(Ok(count))) => debug!(count, "Organization domains checked"),
+                Ok(Some(Err(err))) => error!(error = ?err, "Failed to check organization domains"),
+                Ok(None) => trace!("Organization domains are already being checked"),
+                Err(err) => error!(error = ?err, "Failed to lock organization domain checks"),
+            }
+        }
+    })
+}
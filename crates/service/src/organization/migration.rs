@@ -0,0 +1,42 @@
+use serde::{Deserialize, Serialize};
+
+use super::{AFFILIATION_TABLE_NAME, ORGANIZATION_TABLE_NAME};
+use crate::{migration::Migration, prelude::*};
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrganizationMigration {
+    #[default]
+    Init,
+}
+
+impl Migration for OrganizationMigration {
+    const SUBSYSTEM: &'static str = "subsys_organization";
+
+    fn next(self) -> Option<Self> {
+        match self {
+            Self::Init => None,
+        }
+    }
+
+    fn build(&self, statements: &mut Vec<srql::Statement>) {
+        use OrganizationMigration as S;
+        match self {
+            S::Init => Self::build_init(statements),
+        }
+    }
+}
+
+impl OrganizationMigration {
+    fn build_init(statements: &mut Vec<srql::Statement>) {
+        statements.push(srql::define_uniq_index(
+            "organization_handle_index",
+            ORGANIZATION_TABLE_NAME,
+            [srql::field("handle")],
+        ));
+        statements.push(srql::define_uniq_index(
+            "affiliation_organization_account_index",
+            AFFILIATION_TABLE_NAME,
+            [srql::field("organization_id"), srql::field("account_id")],
+        ));
+    }
+}
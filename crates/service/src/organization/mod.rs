@@ -0,0 +1,33 @@
+//! Organizations are run by an account and can prove that they own a domain,
+//! either with a DNS TXT record or a well-known file served from it. Once
+//! verified, they can claim accounts as their members, which shows an
+//! affiliation badge on those accounts' profiles after they confirm it.
+//!
+//! Members can also be given roles and permissions to act as the
+//! organization, such as posting as it. Everything done as the organization
+//! is kept in its activity log, along with which member did it.
+
+mod job;
+mod migration;
+mod models;
+mod persist;
+mod schema;
+mod verify;
+
+pub use job::*;
+pub use migration::*;
+pub use models::*;
+pub use persist::*;
+pub use schema::*;
+pub use verify::*;
+
+pub static ORGANIZATION_TABLE_NAME: &str = "organization";
+pub static AFFILIATION_TABLE_NAME: &str = "affiliation";
+pub static ORGANIZATION_ACTIVITY_TABLE_NAME: &str = "organization_activity";
+
+/// The path, on the organization's domain, of the file that can hold its
+/// verification token.
+pub const WELL_KNOWN_PATH: &str = "/.well-known/plazer-verification";
+/// What the organization's verification token is prefixed with in its TXT
+/// record.
+pub const TXT_PREFIX: &str = "plazer-verification=";
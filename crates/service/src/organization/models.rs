@@ -0,0 +1,444 @@
+use async_graphql::{
+    connection::Connection, ComplexObject, Context, Enum, InputObject, SimpleObject, ID,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use surrealdb::sql::Thing;
+
+use super::{
+    AFFILIATION_TABLE_NAME, ORGANIZATION_ACTIVITY_TABLE_NAME, ORGANIZATION_TABLE_NAME, TXT_PREFIX,
+    WELL_KNOWN_PATH,
+};
+use crate::{
+    id_obj_impls,
+    prelude::*,
+    query::{page_complexity, OpaqueCursor, PaginationArgs},
+};
+
+pub type OrganizationActivityCursor = OpaqueCursor<String>;
+
+/// An organization, run by an account, that can prove it owns a domain and
+/// claim accounts as its members.
+#[derive(SimpleObject, Debug, Clone, Deserialize)]
+#[graphql(complex)]
+pub struct Organization {
+    #[graphql(skip)]
+    pub id: Thing,
+    #[graphql(skip)]
+    pub owner_id: Thing,
+
+    /// The organization's unique handle.
+    pub handle: String,
+    /// The organization's display name. If not present, the handle is
+    /// (usually) used instead.
+    pub name: Option<String>,
+    /// The domain that the organization says it owns, if it has set one.
+    pub domain: Option<String>,
+    /// The token that the domain must publish to prove that the organization
+    /// owns it. A new one is made whenever the domain changes.
+    #[graphql(skip)]
+    pub verification_token: Option<String>,
+    /// When the domain was first found to publish the verification token.
+    /// This is cleared if the domain changes or stops publishing it.
+    pub verified_at: Option<DateTime<Utc>>,
+    /// When the domain was last checked.
+    pub checked_at: Option<DateTime<Utc>>,
+
+    /// A timestamp indicating the last time the organization was updated.
+    pub updated_at: DateTime<Utc>,
+}
+
+#[ComplexObject]
+impl Organization {
+    /// The organization's unique ID.
+    async fn id(&self) -> ID {
+        self.id.to_gql_id()
+    }
+
+    /// The ID of the account that runs the organization.
+    async fn owner_id(&self) -> ID {
+        self.owner_id.to_gql_id()
+    }
+
+    /// Whether the organization has proven that it owns its domain.
+    async fn verified(&self) -> bool {
+        self.is_verified()
+    }
+
+    /// How to prove that the organization owns its domain, if it has set
+    /// one. This can only be seen by the organization's owner.
+    async fn verification(&self, ctx: &Context<'_>) -> GqlResult<Option<DomainVerification>> {
+        if ctx.current_account().id().extend()?.to_account_thing() != self.owner_id {
+            return Err(Error::Unauthorized).extend();
+        }
+        Ok(self.verification_instructions())
+    }
+
+    /// The organization's members. Everyone can see the confirmed members,
+    /// and members who can manage members can also see claims that haven't
+    /// been confirmed yet.
+    async fn members(&self, ctx: &Context<'_>) -> GqlResult<Vec<Affiliation>> {
+        ctx.organization_persist().members(self).await.extend()
+    }
+
+    /// What the current account can do as the organization. The owner can
+    /// do everything.
+    async fn permissions(&self, ctx: &Context<'_>) -> GqlResult<Vec<OrganizationPermission>> {
+        ctx.organization_persist()
+            .current_permissions(self)
+            .await
+            .extend()
+    }
+
+    /// What the organization's members have done as it, newest first. When
+    /// `actions` is given, only those actions are listed. This can only be
+    /// seen by members who can manage members.
+    #[graphql(complexity = "page_complexity(first, last, child_complexity)")]
+    async fn activity(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+        actions: Option<Vec<OrganizationAction>>,
+    ) -> GqlResult<Connection<OrganizationActivityCursor, OrganizationActivity>> {
+        ctx.organization_persist()
+            .activity(self)
+            .await
+            .extend()?
+            .with_actions(actions)
+            .with_pagination(
+                PaginationArgs {
+                    after,
+                    before,
+                    first,
+                    last,
+                }
+                .warn_if_clamped(ctx)
+                .validate()
+                .extend()?,
+            )
+            .execute()
+            .await
+            .extend()
+    }
+}
+
+id_obj_impls!(Organization);
+
+impl Organization {
+    pub fn create(
+        owner_id: Thing,
+        params: CreateOrganization,
+        ids: &dyn IdGen,
+    ) -> srql::CreateStatement {
+        let mut create = vec![];
+        owner_id.push_field(srql::field("owner_id"), &mut create);
+        params.append(&mut create);
+        srql::obj_create_query(ORGANIZATION_TABLE_NAME, create, ids)
+    }
+
+    /// Whether the organization has a domain that has been verified.
+    #[must_use]
+    pub fn is_verified(&self) -> bool {
+        self.domain.is_some() && self.verified_at.is_some()
+    }
+
+    #[must_use]
+    pub fn verification_instructions(&self) -> Option<DomainVerification> {
+        let (domain, token) = self.domain.as_ref().zip(self.verification_token.as_ref())?;
+        Some(DomainVerification {
+            txt_record: format!("{TXT_PREFIX}{token}"),
+            well_known_url: format!("https://{domain}{WELL_KNOWN_PATH}"),
+            token: token.clone(),
+        })
+    }
+}
+
+/// How an organization can prove that it owns its domain. Only one of these
+/// needs to be done.
+#[derive(SimpleObject, Debug, Clone, PartialEq, Eq)]
+pub struct DomainVerification {
+    /// A TXT record to add to the domain.
+    pub txt_record: String,
+    /// Where to serve a file containing only `token`.
+    pub well_known_url: String,
+    /// The verification token.
+    pub token: String,
+}
+
+#[derive(InputObject, Debug, Default, Clone, PartialEq, Eq)]
+pub struct CreateOrganization {
+    /// The organization's unique handle.
+    #[graphql(validator(min_length = 1, max_length = 128))]
+    pub handle: String,
+    /// The organization's display name.
+    #[graphql(validator(max_length = 128))]
+    pub name: Option<String>,
+}
+
+impl CreateObject for CreateOrganization {
+    fn append(self, expr: &mut srql::SetExpr) {
+        self.handle.push_field(srql::field("handle"), expr);
+        self.name.push_field(srql::field("name"), expr);
+    }
+}
+
+/// What a member can do as an organization.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrganizationPermission {
+    /// Create posts as the organization.
+    Post,
+    /// Edit posts made as the organization, including other members'.
+    EditPosts,
+    /// Delete posts made as the organization, including other members'.
+    DeletePosts,
+    /// Claim and remove members, change what they can do, and see the
+    /// organization's activity.
+    ManageMembers,
+}
+
+impl OrganizationPermission {
+    pub const ALL: [Self; 4] = [
+        Self::Post,
+        Self::EditPosts,
+        Self::DeletePosts,
+        Self::ManageMembers,
+    ];
+}
+
+impl QueryValue for Vec<OrganizationPermission> {
+    fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
+        srql::to_value(self)
+            .ok()
+            .map(|value| (field, srql::Operator::Equal, value))
+    }
+}
+
+/// A member's role in an organization, which decides what they can do unless
+/// they're given different permissions.
+#[derive(Enum, Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrganizationRole {
+    /// Can do everything.
+    Admin,
+    /// Can post as the organization, and edit and delete its posts.
+    Editor,
+    /// Belongs to the organization, but can't act as it.
+    #[default]
+    Member,
+}
+
+impl OrganizationRole {
+    /// What members with this role can do by default.
+    #[must_use]
+    pub fn permissions(self) -> Vec<OrganizationPermission> {
+        use OrganizationPermission as P;
+        match self {
+            Self::Admin => P::ALL.to_vec(),
+            Self::Editor => vec![P::Post, P::EditPosts, P::DeletePosts],
+            Self::Member => vec![],
+        }
+    }
+}
+
+impl QueryValue for OrganizationRole {
+    fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
+        srql::to_value(self)
+            .ok()
+            .map(|value| (field, srql::Operator::Equal, value))
+    }
+}
+
+/// An organization's claim that an account is one of its members.
+#[derive(SimpleObject, Debug, Clone, Deserialize)]
+#[graphql(complex)]
+pub struct Affiliation {
+    #[graphql(skip)]
+    pub id: Thing,
+    #[graphql(skip)]
+    pub organization_id: Thing,
+    #[graphql(skip)]
+    pub account_id: Thing,
+
+    /// Whether the account has confirmed that it belongs to the organization.
+    /// Affiliations are only shown on profiles once confirmed.
+    #[serde(default)]
+    pub confirmed: bool,
+    /// The member's role.
+    #[serde(default)]
+    pub role: OrganizationRole,
+    /// What the member can do as the organization once confirmed.
+    #[serde(default)]
+    pub permissions: Vec<OrganizationPermission>,
+
+    /// A timestamp indicating the last time the affiliation was updated.
+    pub updated_at: DateTime<Utc>,
+}
+
+#[ComplexObject]
+impl Affiliation {
+    /// The affiliation's unique ID.
+    async fn id(&self) -> ID {
+        self.id.to_gql_id()
+    }
+
+    /// The ID of the account that the organization claims.
+    async fn account_id(&self) -> ID {
+        self.account_id.to_gql_id()
+    }
+
+    /// The organization making the claim.
+    async fn organization(&self, ctx: &Context<'_>) -> GqlResult<Option<Organization>> {
+        ctx.organization_persist()
+            .get(&self.organization_id.id.to_raw())
+            .await
+            .extend()
+    }
+}
+
+id_obj_impls!(Affiliation);
+
+impl Affiliation {
+    pub fn create(
+        organization_id: Thing,
+        account_id: Thing,
+        confirmed: bool,
+        role: MemberRole,
+        ids: &dyn IdGen,
+    ) -> srql::CreateStatement {
+        let mut create = vec![];
+        organization_id.push_field(srql::field("organization_id"), &mut create);
+        account_id.push_field(srql::field("account_id"), &mut create);
+        confirmed.push_field(srql::field("confirmed"), &mut create);
+        role.append(&mut create);
+        srql::obj_create_query(AFFILIATION_TABLE_NAME, create, ids)
+    }
+
+    /// Whether the member can do something as the organization.
+    #[must_use]
+    pub fn can(&self, permission: OrganizationPermission) -> bool {
+        self.confirmed && self.permissions.contains(&permission)
+    }
+}
+
+/// A member's role, and what they can do as the organization.
+#[derive(InputObject, Debug, Default, Clone, PartialEq, Eq)]
+pub struct MemberRole {
+    /// The member's role. Defaults to `MEMBER`.
+    pub role: Option<OrganizationRole>,
+    /// What the member can do. Defaults to what the role can do.
+    pub permissions: Option<Vec<OrganizationPermission>>,
+}
+
+impl MemberRole {
+    #[must_use]
+    pub fn admin() -> Self {
+        Self {
+            role: Some(OrganizationRole::Admin),
+            permissions: None,
+        }
+    }
+}
+
+impl CreateObject for MemberRole {
+    fn append(self, expr: &mut srql::SetExpr) {
+        let role = self.role.unwrap_or_default();
+        let permissions = self.permissions.unwrap_or_else(|| role.permissions());
+        role.push_field(srql::field("role"), expr);
+        permissions.push_field(srql::field("permissions"), expr);
+    }
+}
+
+/// Something a member did as an organization.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrganizationAction {
+    /// Created a post as the organization.
+    PostCreated,
+    /// Edited a post made as the organization.
+    PostUpdated,
+    /// Deleted a post made as the organization.
+    PostDeleted,
+    /// Claimed an account as a member.
+    MemberClaimed,
+    /// Changed a member's role or permissions.
+    MemberUpdated,
+    /// Removed a member.
+    MemberRemoved,
+}
+
+impl QueryValue for OrganizationAction {
+    fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
+        srql::to_value(self)
+            .ok()
+            .map(|value| (field, srql::Operator::Equal, value))
+    }
+}
+
+/// An entry in an organization's activity log, recording which member did
+/// something as the organization.
+#[derive(SimpleObject, Debug, Clone, Deserialize)]
+#[graphql(complex)]
+pub struct OrganizationActivity {
+    #[graphql(skip)]
+    pub id: Thing,
+    #[graphql(skip)]
+    pub organization_id: Thing,
+    #[graphql(skip)]
+    pub actor_id: Thing,
+    #[graphql(skip)]
+    pub subject_id: Option<Thing>,
+
+    /// What was done.
+    pub action: OrganizationAction,
+    /// When it was done.
+    pub occurred_at: DateTime<Utc>,
+
+    /// A timestamp indicating the last time the entry was updated.
+    pub updated_at: DateTime<Utc>,
+}
+
+#[ComplexObject]
+impl OrganizationActivity {
+    /// The entry's unique ID.
+    async fn id(&self) -> ID {
+        self.id.to_gql_id()
+    }
+
+    /// The ID of the member who did it.
+    async fn actor_id(&self) -> ID {
+        self.actor_id.to_gql_id()
+    }
+
+    /// The ID of the post or affiliation that it was done to. The post or
+    /// affiliation may have since been deleted.
+    async fn subject_id(&self) -> Option<ID> {
+        self.subject_id.as_ref().map(ToGqlId::to_gql_id)
+    }
+}
+
+id_obj_impls!(OrganizationActivity);
+
+impl OrganizationActivity {
+    pub fn create(
+        organization_id: Thing,
+        actor_id: Thing,
+        action: OrganizationAction,
+        subject_id: Option<Thing>,
+        clock: &dyn Clock,
+        ids: &dyn IdGen,
+    ) -> srql::CreateStatement {
+        let mut create = vec![];
+        organization_id.push_field(srql::field("organization_id"), &mut create);
+        actor_id.push_field(srql::field("actor_id"), &mut create);
+        action.push_field(srql::field("action"), &mut create);
+        subject_id.push_field(srql::field("subject_id"), &mut create);
+        clock
+            .now()
+            .push_field(srql::field("occurred_at"), &mut create);
+        srql::obj_create_query(ORGANIZATION_ACTIVITY_TABLE_NAME, create, ids)
+    }
+}
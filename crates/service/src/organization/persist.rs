@@ -0,0 +1,767 @@
+#[cfg(test)]
+mod tests;
+
+use std::net::IpAddr;
+
+use async_graphql::{
+    connection::{Connection, Edge},
+    MaybeUndefined,
+};
+use base64::prelude::*;
+use chrono::{DateTime, Utc};
+use ring::rand::{SecureRandom as _, SystemRandom};
+use tracing::{error, instrument, warn};
+
+use super::{
+    check_domain, Affiliation, CreateOrganization, MemberRole, Organization, OrganizationAction,
+    OrganizationActivity, OrganizationActivityCursor, OrganizationPermission,
+    AFFILIATION_TABLE_NAME, ORGANIZATION_ACTIVITY_TABLE_NAME, ORGANIZATION_TABLE_NAME,
+};
+use crate::{
+    account::{Account, CurrentAccount, ACC_TABLE_NAME},
+    persist::Persist,
+    prelude::*,
+    query::{OpaqueCursor, PaginationInput, PaginationOptions, ResultSlice},
+};
+
+/// How many random bytes go into a verification token.
+const TOKEN_LEN: usize = 18;
+
+pub struct OrganizationPersist<'a> {
+    persist: &'a Persist,
+    current: &'a CurrentAccount,
+    csrng: &'a SystemRandom,
+}
+
+impl<'a> OrganizationPersist<'a> {
+    pub fn new(persist: &'a Persist, current: &'a CurrentAccount, csrng: &'a SystemRandom) -> Self {
+        Self {
+            persist,
+            current,
+            csrng,
+        }
+    }
+
+    #[instrument(skip_all)]
+    pub async fn get(&self, id: &str) -> Result<Option<Organization>> {
+        let org = self
+            .persist
+            .load(srql::Thing::from((ORGANIZATION_TABLE_NAME, id)))
+            .await?;
+        Ok(org)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn get_by_handle(&self, handle: &str) -> Result<Option<Organization>> {
+        let org = self
+            .persist
+            .db()
+            .query(srql::SelectStatement {
+                expr: srql::Fields::all(),
+                what: srql::table(ORGANIZATION_TABLE_NAME),
+                cond: field_cond("handle", srql::string(handle)).into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        Ok(org)
+    }
+
+    /// Lists the organizations run by the current account.
+    #[instrument(skip_all)]
+    pub async fn owned(&self) -> Result<Vec<Organization>> {
+        let owner = self.current.id()?.to_account_thing();
+        let orgs = self
+            .persist
+            .db()
+            .query(srql::SelectStatement {
+                expr: srql::Fields::all(),
+                what: srql::table(ORGANIZATION_TABLE_NAME),
+                cond: field_cond("owner_id", owner).into(),
+                order: by_id(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        Ok(orgs)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn create(&self, org: CreateOrganization) -> Result<Organization> {
+        let owner = self.current.id()?.to_account_thing();
+        let org: Option<Organization> = self
+            .persist
+            .db()
+            .query(Organization::create(owner, org, self.persist.ids()))
+            .await?
+            .take(0)?;
+
+        match org {
+            Some(org) => Ok(org),
+            None => Err(Error::UnavailableIdent),
+        }
+    }
+
+    /// Sets the domain that the organization says it owns, or clears it if
+    /// `None`. The organization is no longer verified until the new domain
+    /// publishes the new verification token.
+    #[instrument(skip_all)]
+    pub async fn set_domain(&self, id: &str, domain: Option<&str>) -> Result<Option<Organization>> {
+        let Some(org) = self.owned_org(id).await? else {
+            return Ok(None);
+        };
+
+        let mut update = vec![];
+        if let Some(domain) = domain.map(normalize_domain).transpose()? {
+            let mut token = [0u8; TOKEN_LEN];
+            self.csrng.fill(&mut token)?;
+            domain.push_field(srql::field("domain"), &mut update);
+            BASE64_URL_SAFE_NO_PAD
+                .encode(token)
+                .push_field(srql::field("verification_token"), &mut update);
+        } else {
+            MaybeUndefined::<String>::Null.push_field(srql::field("domain"), &mut update);
+            MaybeUndefined::<String>::Null
+                .push_field(srql::field("verification_token"), &mut update);
+        }
+        MaybeUndefined::<DateTime<Utc>>::Null.push_field(srql::field("verified_at"), &mut update);
+        MaybeUndefined::<DateTime<Utc>>::Null.push_field(srql::field("checked_at"), &mut update);
+
+        update_org(self.persist, org.id, update).await
+    }
+
+    /// Checks the organization's domain now, rather than waiting for the next
+    /// scheduled check.
+    #[instrument(skip_all)]
+    pub async fn verify(&self, id: &str) -> Result<Option<Organization>> {
+        let Some(org) = self.owned_org(id).await? else {
+            return Ok(None);
+        };
+        let Some((domain, token)) = org.domain.as_deref().zip(org.verification_token.as_deref())
+        else {
+            return Err(Error::InputInvalid(
+                "the organization doesn't have a domain to verify".into(),
+            ));
+        };
+
+        let verified = check_domain(self.persist.domains(), domain, token).await?;
+        record_check(self.persist, &org, verified).await
+    }
+
+    /// Claims that an account is a member of the organization, with the given
+    /// role. The account has to confirm the claim before it's shown on its
+    /// profile or it can act as the organization, unless it's the
+    /// organization's owner.
+    #[instrument(skip_all)]
+    pub async fn claim(&self, id: &str, account_id: &str, role: MemberRole) -> Result<Affiliation> {
+        let actor = self.current.id()?.to_account_thing();
+        let org = require_permission(
+            self.persist,
+            &srql::Thing::from((ORGANIZATION_TABLE_NAME, id)),
+            &actor,
+            OrganizationPermission::ManageMembers,
+        )
+        .await?;
+        if !org.is_verified() {
+            return Err(Error::DomainUnverified);
+        }
+        let account: Option<Account> = self
+            .persist
+            .load(srql::Thing::from((ACC_TABLE_NAME, account_id)))
+            .await?;
+        let account = account.ok_or(Error::NotFound)?;
+
+        let confirmed = account.id == org.owner_id;
+        if confirmed {
+            self.clear_confirmed(&account.id).await?;
+        }
+        let affiliation: Option<Affiliation> = self
+            .persist
+            .db()
+            .query(Affiliation::create(
+                org.id,
+                account.id,
+                confirmed,
+                role,
+                self.persist.ids(),
+            ))
+            .await?
+            .take(0)?;
+
+        let Some(affiliation) = affiliation else {
+            return Err(Error::UnavailableIdent);
+        };
+        log_activity(
+            self.persist,
+            affiliation.organization_id.clone(),
+            actor,
+            OrganizationAction::MemberClaimed,
+            Some(affiliation.id.clone()),
+        )
+        .await;
+        Ok(affiliation)
+    }
+
+    /// Changes a member's role, and what they can do as the organization.
+    /// Only members who can manage members can do this.
+    #[instrument(skip_all)]
+    pub async fn set_member_role(&self, id: &str, role: MemberRole) -> Result<Option<Affiliation>> {
+        let actor = self.current.id()?.to_account_thing();
+        let affiliation: Option<Affiliation> = self
+            .persist
+            .db()
+            .select((AFFILIATION_TABLE_NAME, id))
+            .await?;
+        let Some(affiliation) = affiliation else {
+            return Ok(None);
+        };
+        require_permission(
+            self.persist,
+            &affiliation.organization_id,
+            &actor,
+            OrganizationPermission::ManageMembers,
+        )
+        .await?;
+
+        let mut update = vec![];
+        role.append(&mut update);
+        let updated: Option<Affiliation> = match srql::obj_update_query(affiliation.id, update) {
+            Some(update) => self.persist.db().query(update).await?.take(0)?,
+            None => None,
+        };
+        if let Some(updated) = &updated {
+            log_activity(
+                self.persist,
+                updated.organization_id.clone(),
+                actor,
+                OrganizationAction::MemberUpdated,
+                Some(updated.id.clone()),
+            )
+            .await;
+        }
+        Ok(updated)
+    }
+
+    /// What the current account can do as the organization.
+    #[instrument(skip_all)]
+    pub async fn current_permissions(
+        &self,
+        org: &Organization,
+    ) -> Result<Vec<OrganizationPermission>> {
+        match self.current.id() {
+            Ok(id) => permissions_of(self.persist, org, &id.to_account_thing()).await,
+            Err(_) => Ok(vec![]),
+        }
+    }
+
+    /// Lists what has been done as the organization. Only members who can
+    /// manage members can see this.
+    #[instrument(skip_all)]
+    pub async fn activity(
+        &self,
+        org: &Organization,
+    ) -> Result<OrganizationActivityListRequest<'a>> {
+        let actor = self.current.id()?.to_account_thing();
+        if !permissions_of(self.persist, org, &actor)
+            .await?
+            .contains(&OrganizationPermission::ManageMembers)
+        {
+            return Err(Error::Unauthorized);
+        }
+        Ok(OrganizationActivityListRequest::new(
+            self.persist,
+            org.id.clone(),
+        ))
+    }
+
+    /// Lists the organization's members. Claims that haven't been confirmed
+    /// are only listed for members who can manage members.
+    #[instrument(skip_all)]
+    pub async fn members(&self, org: &Organization) -> Result<Vec<Affiliation>> {
+        let can_manage = match self.current.id() {
+            Ok(id) => permissions_of(self.persist, org, &id.to_account_thing())
+                .await?
+                .contains(&OrganizationPermission::ManageMembers),
+            Err(_) => false,
+        };
+        let cond = field_cond("organization_id", org.id.clone());
+        let cond = if can_manage {
+            Some(cond)
+        } else {
+            srql::cond_and(Some(cond), Some(field_cond("confirmed", true)))
+        };
+
+        let members = self
+            .persist
+            .db()
+            .query(srql::SelectStatement {
+                expr: srql::Fields::all(),
+                what: srql::table(AFFILIATION_TABLE_NAME),
+                cond,
+                order: by_id(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        Ok(members)
+    }
+
+    /// Lists the organizations claiming the current account, whether it has
+    /// confirmed them or not.
+    #[instrument(skip_all)]
+    pub async fn affiliations(&self) -> Result<Vec<Affiliation>> {
+        let account = self.current.id()?.to_account_thing();
+        let affiliations = self
+            .persist
+            .db()
+            .query(srql::SelectStatement {
+                expr: srql::Fields::all(),
+                what: srql::table(AFFILIATION_TABLE_NAME),
+                cond: field_cond("account_id", account).into(),
+                order: by_id(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        Ok(affiliations)
+    }
+
+    /// Confirms that the current account belongs to the organization that
+    /// claimed it. Accounts only show one affiliation, so any other that it
+    /// had confirmed is removed.
+    #[instrument(skip_all)]
+    pub async fn confirm_affiliation(&self, id: &str) -> Result<Option<Affiliation>> {
+        let account = self.current.id()?.to_account_thing();
+        let affiliation: Option<Affiliation> = self
+            .persist
+            .db()
+            .select((AFFILIATION_TABLE_NAME, id))
+            .await?;
+        let Some(affiliation) = affiliation.filter(|a| a.account_id == account) else {
+            return Ok(None);
+        };
+        if affiliation.confirmed {
+            return Ok(Some(affiliation));
+        }
+
+        self.clear_confirmed(&account).await?;
+        let mut update = vec![];
+        true.push_field(srql::field("confirmed"), &mut update);
+        let affiliation = match srql::obj_update_query(affiliation.id, update) {
+            Some(update) => self.persist.db().query(update).await?.take(0)?,
+            None => None,
+        };
+        Ok(affiliation)
+    }
+
+    /// Removes an affiliation. Either the account or a member who can manage
+    /// members can do this.
+    #[instrument(skip_all)]
+    pub async fn remove_affiliation(&self, id: &str) -> Result<Option<Affiliation>> {
+        let current = self.current.id()?.to_account_thing();
+        let affiliation: Option<Affiliation> = self
+            .persist
+            .db()
+            .select((AFFILIATION_TABLE_NAME, id))
+            .await?;
+        let Some(affiliation) = affiliation else {
+            return Ok(None);
+        };
+
+        let by_member = affiliation.account_id == current;
+        if !by_member {
+            let permitted = require_permission(
+                self.persist,
+                &affiliation.organization_id,
+                &current,
+                OrganizationPermission::ManageMembers,
+            )
+            .await;
+            if permitted.is_err() {
+                return Ok(None);
+            }
+        }
+
+        let affiliation: Option<Affiliation> = self
+            .persist
+            .db()
+            .delete((AFFILIATION_TABLE_NAME, id))
+            .await?;
+        // Members leaving aren't acting as the organization, so only removals
+        // by other members are logged.
+        if let Some(affiliation) = affiliation.as_ref().filter(|_| !by_member) {
+            log_activity(
+                self.persist,
+                affiliation.organization_id.clone(),
+                current,
+                OrganizationAction::MemberRemoved,
+                Some(affiliation.id.clone()),
+            )
+            .await;
+        }
+        Ok(affiliation)
+    }
+
+    /// Gets an organization, as long as the current account runs it.
+    async fn owned_org(&self, id: &str) -> Result<Option<Organization>> {
+        let owner = self.current.id()?.to_account_thing();
+        match self.get(id).await? {
+            Some(org) if org.owner_id != owner => Err(Error::Unauthorized),
+            org => Ok(org),
+        }
+    }
+
+    async fn clear_confirmed(&self, account: &srql::Thing) -> Result<()> {
+        self.persist
+            .db()
+            .query(srql::DeleteStatement {
+                what: srql::table(AFFILIATION_TABLE_NAME),
+                cond: srql::cond_and(
+                    Some(field_cond("account_id", account.clone())),
+                    Some(field_cond("confirmed", true)),
+                ),
+                output: srql::Output::None.into(),
+                ..Default::default()
+            })
+            .await?;
+        Ok(())
+    }
+}
+
+/// Gets the verified organization that the account has confirmed it belongs
+/// to, if any.
+#[instrument(skip_all)]
+pub async fn affiliation_of(
+    persist: &Persist,
+    account: &srql::Thing,
+) -> Result<Option<Organization>> {
+    let affiliation: Option<Affiliation> = persist
+        .db()
+        .query(srql::SelectStatement {
+            expr: srql::Fields::all(),
+            what: srql::table(AFFILIATION_TABLE_NAME),
+            cond: srql::cond_and(
+                Some(field_cond("account_id", account.clone())),
+                Some(field_cond("confirmed", true)),
+            ),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    let Some(affiliation) = affiliation else {
+        return Ok(None);
+    };
+
+    let org: Option<Organization> = persist.load(affiliation.organization_id).await?;
+    Ok(org.filter(Organization::is_verified))
+}
+
+/// What an account can do as the organization. Owners can do everything,
+/// and other accounts can do what their confirmed affiliation allows.
+pub async fn permissions_of(
+    persist: &Persist,
+    org: &Organization,
+    account: &srql::Thing,
+) -> Result<Vec<OrganizationPermission>> {
+    if &org.owner_id == account {
+        return Ok(OrganizationPermission::ALL.to_vec());
+    }
+
+    let affiliation: Option<Affiliation> = persist
+        .db()
+        .query(srql::SelectStatement {
+            expr: srql::Fields::all(),
+            what: srql::table(AFFILIATION_TABLE_NAME),
+            cond: srql::cond_and(
+                srql::cond_and(
+                    Some(field_cond("organization_id", org.id.clone())),
+                    Some(field_cond("account_id", account.clone())),
+                ),
+                Some(field_cond("confirmed", true)),
+            ),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    Ok(affiliation.map(|a| a.permissions).unwrap_or_default())
+}
+
+/// Gets an organization, as long as the account is allowed to do something
+/// as it.
+pub async fn require_permission(
+    persist: &Persist,
+    org_id: &srql::Thing,
+    account: &srql::Thing,
+    permission: OrganizationPermission,
+) -> Result<Organization> {
+    if org_id.tb != ORGANIZATION_TABLE_NAME {
+        return Err(Error::NotFound);
+    }
+    let org: Option<Organization> = persist.load(org_id.clone()).await?;
+    let org = org.ok_or(Error::NotFound)?;
+    if !permissions_of(persist, &org, account)
+        .await?
+        .contains(&permission)
+    {
+        return Err(Error::Unauthorized);
+    }
+    Ok(org)
+}
+
+/// Adds an entry to the organization's activity log. This is done after the
+/// action has already happened, so failing to log it doesn't fail the
+/// action.
+pub async fn log_activity(
+    persist: &Persist,
+    organization_id: srql::Thing,
+    actor_id: srql::Thing,
+    action: OrganizationAction,
+    subject_id: Option<srql::Thing>,
+) {
+    let res = persist
+        .db()
+        .query(OrganizationActivity::create(
+            organization_id,
+            actor_id,
+            action,
+            subject_id,
+            persist.clock(),
+            persist.ids(),
+        ))
+        .await;
+    if let Err(err) = res {
+        error!(error = ?err, ?action, "Failed to log organization activity");
+    }
+}
+
+/// Checks the domain of every organization that has one, verifying those
+/// that now publish their token and unverifying those that stopped. Domains
+/// that can't be checked are left as they are. Returns how many were checked.
+#[instrument(skip_all)]
+pub async fn check_domains(persist: &Persist) -> Result<usize> {
+    let orgs: Vec<Organization> = persist
+        .db()
+        .query(srql::SelectStatement {
+            expr: srql::Fields::all(),
+            what: srql::table(ORGANIZATION_TABLE_NAME),
+            cond: srql::Cond(
+                srql::Expression::Binary {
+                    l: srql::field("verification_token").into(),
+                    o: srql::Operator::NotEqual,
+                    r: srql::Value::None,
+                }
+                .into(),
+            )
+            .into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+
+    let mut checked = 0;
+    for org in orgs {
+        let Some((domain, token)) = org.domain.as_deref().zip(org.verification_token.as_deref())
+        else {
+            continue;
+        };
+        match check_domain(persist.domains(), domain, token).await {
+            Ok(verified) => {
+                record_check(persist, &org, verified).await?;
+                checked += 1;
+            }
+            Err(err) => warn!(error = ?err, domain, "Failed to check organization domain"),
+        }
+    }
+    Ok(checked)
+}
+
+pub struct OrganizationActivityListRequest<'a> {
+    persist: &'a Persist,
+    organization_id: srql::Thing,
+    actions: Option<Vec<OrganizationAction>>,
+    pagination: Option<PaginationInput<OpaqueCursor<String>>>,
+}
+
+impl<'a> OrganizationActivityListRequest<'a> {
+    fn new(persist: &'a Persist, organization_id: srql::Thing) -> Self {
+        Self {
+            persist,
+            organization_id,
+            actions: None,
+            pagination: None,
+        }
+    }
+
+    /// Only list entries for the given actions.
+    pub fn with_actions(mut self, actions: Option<Vec<OrganizationAction>>) -> Self {
+        self.actions = actions;
+        self
+    }
+
+    pub fn with_pagination(
+        mut self,
+        args: impl Into<PaginationInput<OpaqueCursor<String>>>,
+    ) -> Self {
+        self.pagination = Some(args.into());
+        self
+    }
+
+    #[instrument(skip_all)]
+    pub async fn execute(
+        self,
+    ) -> Result<Connection<OrganizationActivityCursor, OrganizationActivity>> {
+        let PaginationOptions {
+            cond,
+            order,
+            limit,
+            result_slice_opts,
+        } = (self.pagination, ORGANIZATION_ACTIVITY_TABLE_NAME).into();
+
+        let actions_cond = match self.actions {
+            Some(actions) => Some(srql::Cond(
+                srql::Expression::Binary {
+                    l: srql::field("action").into(),
+                    o: srql::Operator::Inside,
+                    r: srql::to_value(actions).map_err(Error::from_err)?,
+                }
+                .into(),
+            )),
+            None => None,
+        };
+
+        let query = srql::SelectStatement {
+            expr: srql::Fields::all(),
+            what: srql::table(ORGANIZATION_ACTIVITY_TABLE_NAME),
+            order: srql::Orders(order.into_iter().collect()).into(),
+            cond: srql::cond_and(
+                srql::cond_and(
+                    cond,
+                    field_cond("organization_id", self.organization_id).into(),
+                ),
+                actions_cond,
+            ),
+            limit,
+            ..Default::default()
+        };
+
+        let entries: Vec<OrganizationActivity> = self.persist.db().query(query).await?.take(0)?;
+        let ResultSlice {
+            results: entries,
+            has_previous_page,
+            has_next_page,
+        } = ResultSlice::new(entries, result_slice_opts);
+
+        let mut connection = Connection::new(has_previous_page, has_next_page);
+        connection.edges = entries
+            .into_iter()
+            .map(|entry| Edge::new(OpaqueCursor(entry.id.to_gql_id().0), entry))
+            .collect();
+
+        Ok(connection)
+    }
+}
+
+/// Stores the result of checking an organization's domain. Organizations
+/// stay verified from when they were first verified until a check fails.
+async fn record_check(
+    persist: &Persist,
+    org: &Organization,
+    verified: bool,
+) -> Result<Option<Organization>> {
+    let now = persist.clock().now();
+    let verified_at = match (verified, org.verified_at) {
+        (true, Some(verified_at)) => MaybeUndefined::Value(verified_at),
+        (true, None) => MaybeUndefined::Value(now),
+        (false, _) => MaybeUndefined::Null,
+    };
+
+    let mut update = vec![];
+    verified_at.push_field(srql::field("verified_at"), &mut update);
+    now.push_field(srql::field("checked_at"), &mut update);
+    update_org(persist, org.id.clone(), update).await
+}
+
+async fn update_org(
+    persist: &Persist,
+    id: srql::Thing,
+    update: srql::SetExpr,
+) -> Result<Option<Organization>> {
+    let org = match srql::obj_update_query(id, update) {
+        Some(update) => persist.db().query(update).await?.take(0)?,
+        None => None,
+    };
+    Ok(org)
+}
+
+fn field_cond(field: &str, value: impl Into<srql::Value>) -> srql::Cond {
+    srql::Cond(
+        srql::Expression::Binary {
+            l: srql::field(field).into(),
+            o: srql::Operator::Equal,
+            r: value.into(),
+        }
+        .into(),
+    )
+}
+
+fn by_id() -> Option<srql::Orders> {
+    srql::Orders(vec![srql::Order {
+        order: srql::field("id"),
+        direction: true,
+        ..Default::default()
+    }])
+    .into()
+}
+
+/// Lowercases the domain and checks that it's a valid, fully qualified
+/// domain name, rather than an IP address.
+fn normalize_domain(domain: &str) -> Result<String> {
+    let domain = domain.trim().trim_end_matches('.').to_ascii_lowercase();
+    let valid = domain.len() <= 253
+        && domain.parse::<IpAddr>().is_err()
+        && domain.contains('.')
+        && domain.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        });
+    if !valid {
+        return Err(Error::InputInvalid(
+            "domain is not a valid domain name".into(),
+        ));
+    }
+    Ok(domain)
+}
+
+#[cfg(test)]
+pub mod testing {
+    use std::sync::Arc;
+
+    use super::OrganizationPersist;
+    use crate::{account::testing::TestData, organization::MemoryDomainVerifier};
+
+    pub trait OrganizationTestData {
+        fn organization(&self) -> OrganizationPersist<'_>;
+        /// Keeps what domains publish in memory instead of looking it up.
+        fn mock_domains(&mut self) -> MemoryDomainVerifier;
+    }
+
+    impl OrganizationTestData for TestData {
+        fn organization(&self) -> OrganizationPersist<'_> {
+            OrganizationPersist::new(&self.persist, &self.current, &self.csrng)
+        }
+
+        fn mock_domains(&mut self) -> MemoryDomainVerifier {
+            let verifier = MemoryDomainVerifier::default();
+            self.persist = self
+                .persist
+                .clone()
+                .with_domains(Arc::new(verifier.clone()));
+            verifier
+        }
+    }
+}
@@ -0,0 +1,459 @@
+use pretty_assertions::assert_eq;
+
+use super::{testing::OrganizationTestData as _, *};
+use crate::{
+    account::testing::*,
+    organization::{MemoryDomainVerifier, OrganizationRole},
+    post::{testing::PostTestData as _, CreatePost, UpdatePost},
+    query::PaginationInput,
+};
+
+fn create(handle: &str) -> CreateOrganization {
+    CreateOrganization {
+        handle: handle.into(),
+        name: Some("Example".into()),
+    }
+}
+
+fn editor() -> MemberRole {
+    MemberRole {
+        role: Some(OrganizationRole::Editor),
+        permissions: None,
+    }
+}
+
+/// Claims an account for the organization and confirms it as that account,
+/// leaving the account logged in.
+async fn join(
+    data: &mut TestData,
+    org: &Organization,
+    account: &AccData,
+    role: MemberRole,
+) -> Affiliation {
+    let claim = data
+        .organization()
+        .claim(&org.id.id.to_raw(), &account.id.id.to_raw(), role)
+        .await
+        .unwrap();
+    data.login_as(account);
+    data.organization()
+        .confirm_affiliation(&claim.id.id.to_raw())
+        .await
+        .unwrap()
+        .unwrap()
+}
+
+async fn activity(data: &TestData, org: &Organization) -> Vec<(OrganizationAction, srql::Thing)> {
+    data.organization()
+        .activity(org)
+        .await
+        .unwrap()
+        .with_pagination(PaginationInput::new().forward(10))
+        .execute()
+        .await
+        .unwrap()
+        .edges
+        .into_iter()
+        .map(|edge| (edge.node.action, edge.node.actor_id))
+        .collect()
+}
+
+/// Creates an organization for the current account with a verified domain.
+async fn verified(data: &TestData, domains: &MemoryDomainVerifier) -> Organization {
+    let org = data.organization().create(create("example")).await.unwrap();
+    let id = org.id.id.to_raw();
+    let org = data
+        .organization()
+        .set_domain(&id, Some("example.com"))
+        .await
+        .unwrap()
+        .unwrap();
+    domains.publish_txt(
+        "example.com",
+        org.verification_instructions().unwrap().txt_record,
+    );
+    data.organization().verify(&id).await.unwrap().unwrap()
+}
+
+#[tokio::test]
+async fn test_create() {
+    let (data, acc) = TestData::with_user().await;
+
+    let org = data.organization().create(create("example")).await.unwrap();
+    assert_eq!(org.owner_id, acc.id);
+    assert_eq!(org.handle, "example");
+    assert!(!org.is_verified());
+
+    let found = data.organization().get_by_handle("example").await.unwrap();
+    assert_eq!(found, Some(org.clone()));
+    assert_eq!(data.organization().owned().await.unwrap(), vec![org]);
+
+    let res = data.organization().create(create("example")).await;
+    assert_eq!(res.unwrap_err(), Error::UnavailableIdent);
+}
+
+#[tokio::test]
+async fn test_set_domain() {
+    let (mut data, _) = TestData::with_user().await;
+    let org = data.organization().create(create("example")).await.unwrap();
+    let id = org.id.id.to_raw();
+
+    for domain in [
+        "localhost",
+        "not a domain",
+        "-bad.com",
+        "example..com",
+        "127.0.0.1",
+        "10.0.0.5",
+    ] {
+        let res = data.organization().set_domain(&id, Some(domain)).await;
+        assert!(
+            matches!(res, Err(Error::InputInvalid(_))),
+            "{domain}: {res:?}"
+        );
+    }
+
+    let org = data
+        .organization()
+        .set_domain(&id, Some("Example.COM."))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(org.domain.as_deref(), Some("example.com"));
+    let first = org.verification_instructions().unwrap();
+    assert_eq!(
+        first.well_known_url,
+        "https://example.com/.well-known/plazer-verification"
+    );
+    assert_eq!(
+        first.txt_record,
+        format!("plazer-verification={}", first.token)
+    );
+
+    // Changing the domain makes a new token, which has to be published again.
+    let org = data
+        .organization()
+        .set_domain(&id, Some("example.org"))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_ne!(org.verification_instructions().unwrap().token, first.token);
+
+    let org = data
+        .organization()
+        .set_domain(&id, None)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(org.domain, None);
+    assert_eq!(org.verification_instructions(), None);
+
+    let other = data.account().create_test_user().await;
+    data.login_as(&other);
+    let res = data
+        .organization()
+        .set_domain(&id, Some("example.net"))
+        .await;
+    assert_eq!(res.unwrap_err(), Error::Unauthorized);
+}
+
+#[tokio::test]
+async fn test_verify() {
+    let (mut data, _) = TestData::with_user().await;
+    let domains = data.mock_domains();
+    let org = data.organization().create(create("example")).await.unwrap();
+    let id = org.id.id.to_raw();
+
+    let res = data.organization().verify(&id).await;
+    assert!(matches!(res, Err(Error::InputInvalid(_))), "{res:?}");
+
+    data.organization()
+        .set_domain(&id, Some("example.com"))
+        .await
+        .unwrap()
+        .unwrap();
+    let org = data.organization().verify(&id).await.unwrap().unwrap();
+    assert!(!org.is_verified());
+    assert!(org.checked_at.is_some());
+
+    let token = org.verification_instructions().unwrap().token;
+    domains.publish_well_known("example.com", format!("{token}\n"));
+    let org = data.organization().verify(&id).await.unwrap().unwrap();
+    assert!(org.is_verified());
+}
+
+#[tokio::test]
+async fn test_check_domains() {
+    let (mut data, _) = TestData::with_user().await;
+    let domains = data.mock_domains();
+    let org = verified(&data, &domains).await;
+    let verified_at = org.verified_at;
+
+    // Organizations stay verified from when they were first verified.
+    assert_eq!(check_domains(&data.persist).await, Ok(1));
+    let org = data
+        .organization()
+        .get(&org.id.id.to_raw())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(org.verified_at, verified_at);
+
+    domains.unpublish("example.com");
+    assert_eq!(check_domains(&data.persist).await, Ok(1));
+    let org = data
+        .organization()
+        .get(&org.id.id.to_raw())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(!org.is_verified());
+}
+
+#[tokio::test]
+async fn test_claim() {
+    let (mut data, owner) = TestData::with_user().await;
+    let domains = data.mock_domains();
+    let unverified = data
+        .organization()
+        .create(create("unverified"))
+        .await
+        .unwrap();
+    let member = data.account().create_test_user().await;
+
+    let res = data
+        .organization()
+        .claim(
+            &unverified.id.id.to_raw(),
+            &member.id.id.to_raw(),
+            MemberRole::default(),
+        )
+        .await;
+    assert_eq!(res.unwrap_err(), Error::DomainUnverified);
+
+    let org = verified(&data, &domains).await;
+    let id = org.id.id.to_raw();
+    let claim = data
+        .organization()
+        .claim(&id, &member.id.id.to_raw(), MemberRole::default())
+        .await
+        .unwrap();
+    assert!(!claim.confirmed);
+    let res = data
+        .organization()
+        .claim(&id, &member.id.id.to_raw(), MemberRole::default())
+        .await;
+    assert_eq!(res.unwrap_err(), Error::UnavailableIdent);
+
+    // The owner doesn't need to confirm their own affiliation.
+    let own = data
+        .organization()
+        .claim(&id, &owner.id.id.to_raw(), MemberRole::default())
+        .await
+        .unwrap();
+    assert!(own.confirmed);
+    assert_eq!(data.organization().members(&org).await.unwrap().len(), 2);
+
+    // Only the member can confirm the claim, and nobody else sees it until then.
+    let claim_id = claim.id.id.to_raw();
+    assert_eq!(
+        data.organization().confirm_affiliation(&claim_id).await,
+        Ok(None)
+    );
+    assert_eq!(affiliation_of(&data.persist, &member.id).await, Ok(None));
+
+    data.login_as(&member);
+    assert_eq!(data.organization().members(&org).await.unwrap(), vec![own]);
+    assert_eq!(
+        data.organization().affiliations().await.unwrap(),
+        vec![claim]
+    );
+    let confirmed = data
+        .organization()
+        .confirm_affiliation(&claim_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(confirmed.confirmed);
+    assert_eq!(
+        affiliation_of(&data.persist, &member.id).await,
+        Ok(Some(org.clone()))
+    );
+
+    // The badge goes away once the domain stops being verified.
+    domains.unpublish("example.com");
+    check_domains(&data.persist).await.unwrap();
+    assert_eq!(affiliation_of(&data.persist, &member.id).await, Ok(None));
+}
+
+#[tokio::test]
+async fn test_remove_affiliation() {
+    let (mut data, _) = TestData::with_user().await;
+    let domains = data.mock_domains();
+    let org = verified(&data, &domains).await;
+    let member = data.account().create_test_user().await;
+    let claim = data
+        .organization()
+        .claim(
+            &org.id.id.to_raw(),
+            &member.id.id.to_raw(),
+            MemberRole::default(),
+        )
+        .await
+        .unwrap();
+    let claim_id = claim.id.id.to_raw();
+
+    let stranger = data.account().create_test_user().await;
+    data.login_as(&stranger);
+    assert_eq!(
+        data.organization().remove_affiliation(&claim_id).await,
+        Ok(None)
+    );
+
+    data.login_as(&member);
+    assert_eq!(
+        data.organization().remove_affiliation(&claim_id).await,
+        Ok(Some(claim))
+    );
+    assert!(data.organization().affiliations().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_member_roles() {
+    let (mut data, owner) = TestData::with_user().await;
+    let domains = data.mock_domains();
+    let org = verified(&data, &domains).await;
+    let editor_acc = data.account().create_test_user().await;
+    let other = data.account().create_test_user().await;
+
+    let affiliation = join(&mut data, &org, &editor_acc, editor()).await;
+    assert_eq!(affiliation.role, OrganizationRole::Editor);
+    assert_eq!(
+        data.organization().current_permissions(&org).await.unwrap(),
+        OrganizationRole::Editor.permissions()
+    );
+
+    // Editors can't manage members or see the activity log.
+    let res = data
+        .organization()
+        .claim(
+            &org.id.id.to_raw(),
+            &other.id.id.to_raw(),
+            MemberRole::default(),
+        )
+        .await;
+    assert_eq!(res.unwrap_err(), Error::Unauthorized);
+    let res = data
+        .organization()
+        .set_member_role(&affiliation.id.id.to_raw(), MemberRole::admin())
+        .await;
+    assert_eq!(res.unwrap_err(), Error::Unauthorized);
+    assert!(matches!(
+        data.organization().activity(&org).await,
+        Err(Error::Unauthorized)
+    ));
+
+    data.login_as(&owner);
+    let updated = data
+        .organization()
+        .set_member_role(
+            &affiliation.id.id.to_raw(),
+            MemberRole {
+                role: Some(OrganizationRole::Member),
+                permissions: Some(vec![OrganizationPermission::ManageMembers]),
+            },
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(updated.role, OrganizationRole::Member);
+    assert_eq!(
+        updated.permissions,
+        vec![OrganizationPermission::ManageMembers]
+    );
+
+    data.login_as(&editor_acc);
+    let claim = data
+        .organization()
+        .claim(
+            &org.id.id.to_raw(),
+            &other.id.id.to_raw(),
+            MemberRole::default(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(claim.role, OrganizationRole::Member);
+    assert!(claim.permissions.is_empty());
+    assert_eq!(data.organization().members(&org).await.unwrap().len(), 2);
+    assert_eq!(
+        data.organization()
+            .remove_affiliation(&claim.id.id.to_raw())
+            .await
+            .unwrap()
+            .map(|claim| claim.id),
+        Some(claim.id)
+    );
+
+    assert_eq!(
+        activity(&data, &org).await,
+        vec![
+            (OrganizationAction::MemberRemoved, editor_acc.id.clone()),
+            (OrganizationAction::MemberClaimed, editor_acc.id.clone()),
+            (OrganizationAction::MemberUpdated, owner.id.clone()),
+            (OrganizationAction::MemberClaimed, owner.id.clone()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_post_as_organization() {
+    let (mut data, owner) = TestData::with_user().await;
+    let domains = data.mock_domains();
+    let org = verified(&data, &domains).await;
+    let author = data.account().create_test_user().await;
+    let member = data.account().create_test_user().await;
+    join(&mut data, &org, &member, MemberRole::default()).await;
+    data.login_as(&owner);
+    join(&mut data, &org, &author, editor()).await;
+
+    let as_org = || CreatePost {
+        content: Some("Hello".into()),
+        organization_id: Some(org.id.to_gql_id()),
+        ..Default::default()
+    };
+    let post = data.post().create(as_org()).await.unwrap();
+    assert_eq!(post.organization_id, Some(org.id.clone()));
+    let post_id = post.id.id.to_raw();
+
+    // Members without permission can't post as the organization, or change
+    // its posts.
+    data.login_as(&member);
+    assert_eq!(
+        data.post().create(as_org()).await.unwrap_err(),
+        Error::Unauthorized
+    );
+    let update = UpdatePost {
+        content: MaybeUndefined::Value("Edited".into()),
+        ..Default::default()
+    };
+    assert_eq!(
+        data.post().update(&post_id, update.clone()).await,
+        Err(Error::Unauthorized)
+    );
+    assert_eq!(data.post().delete(&post_id).await, Err(Error::Unauthorized));
+
+    data.login_as(&owner);
+    let updated = data.post().update(&post_id, update).await.unwrap().unwrap();
+    assert_eq!(updated.content.as_deref(), Some("Edited"));
+    data.post().delete(&post_id).await.unwrap().unwrap();
+
+    let log = activity(&data, &org).await;
+    assert_eq!(
+        &log[..3],
+        [
+            (OrganizationAction::PostDeleted, owner.id.clone()),
+            (OrganizationAction::PostUpdated, owner.id.clone()),
+            (OrganizationAction::PostCreated, author.id.clone()),
+        ]
+    );
+}
@@ -0,0 +1,149 @@
+use async_graphql::{Context, Object, ID};
+use tracing::instrument;
+
+use super::{Affiliation, CreateOrganization, MemberRole, Organization};
+use crate::{policy::PoliciesAccepted, prelude::*};
+
+#[derive(Default)]
+pub struct OrganizationQuery;
+
+#[Object]
+impl OrganizationQuery {
+    /// Gets an organization by its ID or handle. If both are given, the ID
+    /// is used.
+    #[instrument(skip_all)]
+    async fn organization(
+        &self,
+        ctx: &Context<'_>,
+        id: Option<ID>,
+        handle: Option<String>,
+    ) -> GqlResult<Option<Organization>> {
+        let persist = ctx.organization_persist();
+        match (id, handle) {
+            (Some(id), _) => persist.get(&id).await,
+            (None, Some(handle)) => persist.get_by_handle(&handle).await,
+            (None, None) => Err(Error::MissingIdent),
+        }
+        .extend()
+    }
+
+    /// Lists the organizations run by the current account.
+    #[instrument(skip_all)]
+    async fn owned_organizations(&self, ctx: &Context<'_>) -> GqlResult<Vec<Organization>> {
+        ctx.organization_persist().owned().await.extend()
+    }
+
+    /// Lists the organizations that have claimed the current account as a
+    /// member, including claims it hasn't confirmed yet.
+    #[instrument(skip_all)]
+    async fn affiliations(&self, ctx: &Context<'_>) -> GqlResult<Vec<Affiliation>> {
+        ctx.organization_persist().affiliations().await.extend()
+    }
+}
+
+#[derive(Default)]
+pub struct OrganizationMutation;
+
+#[Object(guard = "PoliciesAccepted")]
+impl OrganizationMutation {
+    /// Creates an organization run by the current account.
+    #[instrument(skip_all)]
+    async fn create_organization(
+        &self,
+        ctx: &Context<'_>,
+        create: CreateOrganization,
+    ) -> GqlResult<Organization> {
+        ctx.organization_persist().create(create).await.extend()
+    }
+
+    /// Sets the domain that the organization says it owns, or clears it if
+    /// null. The organization isn't verified until the domain publishes the
+    /// token given in `verification`.
+    #[instrument(skip_all)]
+    async fn set_organization_domain(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        #[graphql(validator(max_length = 253))] domain: Option<String>,
+    ) -> GqlResult<Option<Organization>> {
+        ctx.organization_persist()
+            .set_domain(&id, domain.as_deref())
+            .await
+            .extend()
+    }
+
+    /// Checks the organization's domain for its verification token now. The
+    /// domain is also checked every hour, and the organization stops being
+    /// verified if the token is removed.
+    #[instrument(skip_all)]
+    async fn verify_organization(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+    ) -> GqlResult<Option<Organization>> {
+        ctx.organization_persist().verify(&id).await.extend()
+    }
+
+    /// Claims that an account is a member of a verified organization, by a
+    /// member who can manage members. The account has to confirm it with
+    /// `confirmAffiliation` before it's shown on its profile or it can act as
+    /// the organization. Without a `role`, the account is claimed as a
+    /// `MEMBER`.
+    #[instrument(skip_all)]
+    async fn claim_member(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        account_id: ID,
+        role: Option<MemberRole>,
+    ) -> GqlResult<Affiliation> {
+        ctx.organization_persist()
+            .claim(&id, &account_id, role.unwrap_or_default())
+            .await
+            .extend()
+    }
+
+    /// Changes a member's role, and what they can do as the organization.
+    /// When `permissions` isn't given, the member can do what the role can.
+    #[instrument(skip_all)]
+    async fn set_member_role(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        role: MemberRole,
+    ) -> GqlResult<Option<Affiliation>> {
+        ctx.organization_persist()
+            .set_member_role(&id, role)
+            .await
+            .extend()
+    }
+
+    /// Confirms that the current account belongs to the organization that
+    /// claimed it. Only one affiliation is shown at a time, so confirming one
+    /// removes any other.
+    #[instrument(skip_all)]
+    async fn confirm_affiliation(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+    ) -> GqlResult<Option<Affiliation>> {
+        ctx.organization_persist()
+            .confirm_affiliation(&id)
+            .await
+            .extend()
+    }
+
+    /// Removes an affiliation, either by the account or by a member who can
+    /// manage members.
+    #[instrument(skip_all)]
+    async fn remove_affiliation(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+    ) -> GqlResult<Option<Affiliation>> {
+        ctx.organization_persist()
+            .remove_affiliation(&id)
+            .await
+            .extend()
+    }
+}
@@ -0,0 +1,307 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Debug},
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use hyper::{body::HttpBody as _, Body, Method, Request};
+use serde::Deserialize;
+
+use super::{TXT_PREFIX, WELL_KNOWN_PATH};
+use crate::{
+    http::{public_http_client, PublicHttpClient},
+    prelude::*,
+    proxy::check_host,
+};
+
+pub type SharedDomainVerifier = Arc<dyn DomainVerifier>;
+
+/// The DNS-over-HTTPS resolver that TXT records are looked up with, as there
+/// is no system resolver API for them.
+const RESOLVER_URL: &str = "https://cloudflare-dns.com/dns-query";
+/// How long to wait for a lookup or the well-known file before giving up.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+/// Well-known files any bigger than this can't just hold a token, so aren't
+/// read any further.
+const MAX_WELL_KNOWN_BYTES: usize = 1024;
+/// The DNS record type of TXT records.
+const TXT_TYPE: u16 = 16;
+
+/// Something that looks up what a domain publishes, so organizations can
+/// prove that they own it.
+#[async_trait]
+pub trait DomainVerifier: Debug + Send + Sync {
+    /// The domain's TXT records, with each record's strings joined together.
+    async fn txt_records(&self, domain: &str) -> Result<Vec<String>>;
+    /// The content of the domain's well-known verification file, if it serves
+    /// one.
+    async fn well_known(&self, domain: &str) -> Result<Option<String>>;
+}
+
+/// Checks whether the domain publishes the token, either as a TXT record or
+/// in its well-known file. Fails only if neither could be checked, so a
+/// domain isn't treated as unverified just because a lookup failed.
+pub async fn check_domain(
+    verifier: &dyn DomainVerifier,
+    domain: &str,
+    token: &str,
+) -> Result<bool> {
+    let txt = verifier.txt_records(domain).await;
+    let expected = format!("{TXT_PREFIX}{token}");
+    if let Ok(records) = &txt {
+        if records.iter().any(|record| record.trim() == expected) {
+            return Ok(true);
+        }
+    }
+
+    match (txt, verifier.well_known(domain).await) {
+        (_, Ok(content)) => Ok(content.is_some_and(|content| content.trim() == token)),
+        (Ok(_), Err(_)) => Ok(false),
+        (Err(err), Err(_)) => Err(err),
+    }
+}
+
+/// Looks up TXT records using DNS-over-HTTPS, and fetches well-known files
+/// over HTTPS. Domains are given by accounts, so only public addresses are
+/// connected to.
+#[derive(Clone)]
+pub struct HttpDomainVerifier {
+    client: PublicHttpClient,
+}
+
+impl HttpDomainVerifier {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            client: public_http_client(),
+        }
+    }
+
+    async fn get(&self, uri: &str, accept: &str) -> Result<hyper::Response<Body>> {
+        let req = Request::builder()
+            .method(Method::GET)
+            .uri(uri)
+            .header("accept", accept)
+            .body(Body::empty())
+            .map_err(Error::from_err)?;
+        tokio::time::timeout(CHECK_TIMEOUT, self.client.request(req))
+            .await
+            .map_err(|_| Error::from("Domain check timed out"))?
+            .map_err(Error::from_err)
+    }
+}
+
+impl Default for HttpDomainVerifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for HttpDomainVerifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpDomainVerifier").finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct DnsResponse {
+    #[serde(rename = "Answer", default)]
+    answer: Vec<DnsAnswer>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DnsAnswer {
+    #[serde(rename = "type")]
+    kind: u16,
+    data: String,
+}
+
+#[async_trait]
+impl DomainVerifier for HttpDomainVerifier {
+    async fn txt_records(&self, domain: &str) -> Result<Vec<String>> {
+        let uri = format!("{RESOLVER_URL}?name={domain}&type=TXT");
+        let res = self.get(&uri, "application/dns-json").await?;
+        if !res.status().is_success() {
+            return Err(format!("DNS lookup failed with {}", res.status()).into());
+        }
+
+        let body = tokio::time::timeout(CHECK_TIMEOUT, hyper::body::to_bytes(res.into_body()))
+            .await
+            .map_err(|_| Error::from("DNS lookup timed out"))?
+            .map_err(Error::from_err)?;
+        let res: DnsResponse = serde_json::from_slice(&body)?;
+        Ok(res
+            .answer
+            .into_iter()
+            .filter(|answer| answer.kind == TXT_TYPE)
+            .map(|answer| parse_txt_data(&answer.data))
+            .collect())
+    }
+
+    async fn well_known(&self, domain: &str) -> Result<Option<String>> {
+        let uri = format!("https://{domain}{WELL_KNOWN_PATH}");
+        // The client only resolves hosts to public addresses, but connects to
+        // IP addresses without resolving them, so those are checked first.
+        check_host(&uri.parse().map_err(Error::from_err)?).await?;
+        let res = self.get(&uri, "text/plain").await?;
+        if !res.status().is_success() {
+            return Ok(None);
+        }
+
+        let mut body = res.into_body();
+        let mut content = Vec::new();
+        let read = async {
+            while let Some(chunk) = body.data().await {
+                content.extend_from_slice(&chunk.map_err(Error::from_err)?);
+                if content.len() > MAX_WELL_KNOWN_BYTES {
+                    return Ok(false);
+                }
+            }
+            Ok::<_, Error>(true)
+        };
+        let complete = tokio::time::timeout(CHECK_TIMEOUT, read)
+            .await
+            .map_err(|_| Error::from("Well-known file timed out"))??;
+
+        Ok(complete.then(|| String::from_utf8(content).ok()).flatten())
+    }
+}
+
+/// Joins the quoted strings that a TXT record's data is made of, as
+/// DNS-over-HTTPS resolvers give it in zone file format (`"abc" "def"`).
+fn parse_txt_data(data: &str) -> String {
+    if !data.starts_with('"') {
+        return data.to_owned();
+    }
+
+    let mut joined = String::with_capacity(data.len());
+    let mut quoted = false;
+    let mut chars = data.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => quoted = !quoted,
+            '\\' if quoted => joined.extend(chars.next()),
+            c if quoted => joined.push(c),
+            _ => {}
+        }
+    }
+    joined
+}
+
+/// What a domain publishes, as kept by [`MemoryDomainVerifier`].
+#[derive(Debug, Default, Clone)]
+struct Published {
+    txt: Vec<String>,
+    well_known: Option<String>,
+}
+
+/// Keeps what domains publish in memory instead of looking it up, so checks
+/// can be controlled.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryDomainVerifier(Arc<Mutex<HashMap<String, Published>>>);
+
+impl MemoryDomainVerifier {
+    fn domains(&self) -> std::sync::MutexGuard<'_, HashMap<String, Published>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Adds a TXT record to the domain.
+    pub fn publish_txt(&self, domain: &str, record: impl Into<String>) {
+        self.domains()
+            .entry(domain.to_owned())
+            .or_default()
+            .txt
+            .push(record.into());
+    }
+
+    /// Serves the content as the domain's well-known verification file.
+    pub fn publish_well_known(&self, domain: &str, content: impl Into<String>) {
+        self.domains()
+            .entry(domain.to_owned())
+            .or_default()
+            .well_known = Some(content.into());
+    }
+
+    /// Removes everything the domain publishes.
+    pub fn unpublish(&self, domain: &str) {
+        self.domains().remove(domain);
+    }
+}
+
+#[async_trait]
+impl DomainVerifier for MemoryDomainVerifier {
+    async fn txt_records(&self, domain: &str) -> Result<Vec<String>> {
+        Ok(self
+            .domains()
+            .get(domain)
+            .map(|published| published.txt.clone())
+            .unwrap_or_default())
+    }
+
+    async fn well_known(&self, domain: &str) -> Result<Option<String>> {
+        Ok(self
+            .domains()
+            .get(domain)
+            .and_then(|published| published.well_known.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_txt_data() {
+        assert_eq!(parse_txt_data("plain"), "plain");
+        assert_eq!(parse_txt_data(r#""quoted""#), "quoted");
+        assert_eq!(parse_txt_data(r#""split" " up""#), "split up");
+        assert_eq!(parse_txt_data(r#""with \"escapes\"""#), r#"with "escapes""#);
+    }
+
+    #[tokio::test]
+    async fn test_check_domain() {
+        let verifier = MemoryDomainVerifier::default();
+        assert_eq!(
+            check_domain(&verifier, "example.com", "token").await,
+            Ok(false)
+        );
+
+        verifier.publish_txt("example.com", "something-else");
+        verifier.publish_txt("example.com", "plazer-verification=token");
+        assert_eq!(
+            check_domain(&verifier, "example.com", "token").await,
+            Ok(true)
+        );
+        assert_eq!(
+            check_domain(&verifier, "example.com", "other").await,
+            Ok(false)
+        );
+
+        verifier.unpublish("example.com");
+        verifier.publish_well_known("example.com", "token\n");
+        assert_eq!(
+            check_domain(&verifier, "example.com", "token").await,
+            Ok(true)
+        );
+        assert_eq!(
+            check_domain(&verifier, "example.org", "token").await,
+            Ok(false)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_well_known_private() {
+        let verifier = HttpDomainVerifier::new();
+        for domain in ["127.0.0.1", "10.0.0.5", "localhost"] {
+            let res = verifier.well_known(domain).await;
+            assert!(
+                matches!(res, Err(Error::InputInvalid(_))),
+                "{domain}: {res:?}"
+            );
+        }
+    }
+}
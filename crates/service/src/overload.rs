@@ -0,0 +1,121 @@
+//! Limits on how many requests are handled at once.
+//!
+//! When the service is overloaded (usually because the database has slowed
+//! down), requests queue up waiting for a slot. Rather than letting every
+//! request in the queue time out, requests that have waited longer than the
+//! configured queue time are turned away with a `503` and a `Retry-After`, so
+//! the requests that do get through are still served promptly.
+
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    extract::State,
+    http::{header::RETRY_AFTER, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tokio::{
+    sync::{OwnedSemaphorePermit, Semaphore},
+    time::timeout,
+};
+use tracing::debug;
+
+use crate::{config::OverloadConfig, error::ErrorResponse, prelude::*};
+
+/// How long clients are told to wait before retrying a shed request.
+pub const RETRY_AFTER_SECS: u64 = 1;
+
+/// A limit on concurrent requests, shared between all requests it applies to.
+#[derive(Debug, Clone)]
+pub struct ConcurrencyLimit {
+    name: &'static str,
+    permits: Arc<Semaphore>,
+    max_queue_time: Duration,
+}
+
+impl ConcurrencyLimit {
+    #[must_use]
+    pub fn new(name: &'static str, max_concurrency: usize, max_queue_time: Duration) -> Self {
+        Self {
+            name,
+            permits: Arc::new(Semaphore::new(max_concurrency)),
+            max_queue_time,
+        }
+    }
+
+    /// The limit on all requests to the service.
+    #[must_use]
+    pub fn global(config: &OverloadConfig) -> Self {
+        Self::new("global", config.max_concurrency, config.max_queue_time)
+    }
+
+    /// The limit on GraphQL queries and mutations over HTTP.
+    #[must_use]
+    pub fn graphql(config: &OverloadConfig) -> Self {
+        Self::new(
+            "graphql",
+            config.max_graphql_concurrency,
+            config.max_queue_time,
+        )
+    }
+
+    /// Waits for a slot, giving up if one doesn't free up in time.
+    async fn acquire(&self) -> Option<OwnedSemaphorePermit> {
+        if let Ok(permit) = self.permits.clone().try_acquire_owned() {
+            return Some(permit);
+        }
+
+        timeout(self.max_queue_time, self.permits.clone().acquire_owned())
+            .await
+            .ok()?
+            .ok()
+    }
+}
+
+/// Middleware that applies a [`ConcurrencyLimit`] to the requests it wraps.
+pub async fn limit_concurrency<B>(
+    State(limit): State<ConcurrencyLimit>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let Some(_permit) = limit.acquire().await else {
+        debug!(limit = limit.name, "Shedding request");
+        let (status, body): ErrorResponse = Error::Overloaded.into();
+        return (status, [(RETRY_AFTER, RETRY_AFTER_SECS.to_string())], body).into_response();
+    };
+
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sheds_when_saturated() {
+        let limit = ConcurrencyLimit::new("test", 1, Duration::from_millis(10));
+
+        let permit = limit.acquire().await;
+        assert!(permit.is_some());
+        // The only slot is taken and won't be freed in time.
+        assert!(limit.acquire().await.is_none());
+
+        drop(permit);
+        assert!(limit.acquire().await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_waits_for_slot() {
+        let limit = ConcurrencyLimit::new("test", 1, Duration::from_secs(5));
+
+        let permit = limit.acquire().await.unwrap();
+        let waiting = tokio::spawn({
+            let limit = limit.clone();
+            async move { limit.acquire().await.is_some() }
+        });
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        drop(permit);
+        assert!(waiting.await.unwrap());
+    }
+}
@@ -0,0 +1,208 @@
+//! Returning what could be resolved when some fields fail.
+//!
+//! Out of the box, a field that fails takes its parent with it, all the way
+//! up to the nearest field that is in a nullable one's subtree, which is
+//! often the whole response. Per the GraphQL spec, a nullable field that
+//! fails should be null instead, with an error whose `path` points at it, and
+//! everything else resolved as normal. Only non-null fields still bubble up
+//! to their parent.
+
+use std::{
+    mem,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use async_graphql::{
+    extensions::{
+        Extension, ExtensionContext, ExtensionFactory, NextExecute, NextResolve, NextSubscribe,
+        ResolveInfo,
+    },
+    PathSegment, QueryPathNode, QueryPathSegment, Response, ServerError, ServerResult, Value,
+};
+use futures::{stream::BoxStream, StreamExt as _};
+
+/// The path to a field, in the form used for errors.
+pub fn path_of(node: &QueryPathNode<'_>) -> Vec<PathSegment> {
+    let mut path: Vec<_> = std::iter::once(node)
+        .chain(node.parents())
+        .map(|node| match node.segment {
+            QueryPathSegment::Name(name) => PathSegment::Field(name.to_owned()),
+            QueryPathSegment::Index(index) => PathSegment::Index(index),
+        })
+        .collect();
+    path.reverse();
+    path
+}
+
+/// A GraphQL extension that nulls out nullable fields that fail, rather than
+/// their parents.
+pub struct PartialResults;
+
+impl ExtensionFactory for PartialResults {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(PartialResultsExtension::default())
+    }
+}
+
+/// Stands in for an error that has already been recorded, while it bubbles up
+/// to the nearest nullable field. The path of an error is replaced at each
+/// field it passes through, so it's recorded where it happened instead.
+struct Bubbled;
+
+#[derive(Default, Clone)]
+struct PartialResultsExtension {
+    errors: Arc<Mutex<Vec<ServerError>>>,
+}
+
+impl PartialResultsExtension {
+    /// Puts the errors recorded so far into the response, in place of those
+    /// that bubbled up.
+    fn finish(&self, mut res: Response) -> Response {
+        res.errors.retain(|err| err.source::<Bubbled>().is_none());
+        let errors = mem::take(&mut *self.errors.lock().unwrap_or_else(PoisonError::into_inner));
+        res.errors.extend(errors);
+        res
+    }
+}
+
+#[async_trait::async_trait]
+impl Extension for PartialResultsExtension {
+    async fn execute(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        operation_name: Option<&str>,
+        next: NextExecute<'_>,
+    ) -> Response {
+        let res = next.run(ctx, operation_name).await;
+        self.finish(res)
+    }
+
+    fn subscribe<'s>(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        stream: BoxStream<'s, Response>,
+        next: NextSubscribe<'_>,
+    ) -> BoxStream<'s, Response> {
+        let this = self.clone();
+        next.run(ctx, stream)
+            .map(move |res| this.finish(res))
+            .boxed()
+    }
+
+    async fn resolve(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        info: ResolveInfo<'_>,
+        next: NextResolve<'_>,
+    ) -> ServerResult<Option<Value>> {
+        let nullable = !info.return_type.ends_with('!');
+        let path_node = info.path_node;
+        let mut err = match next.run(ctx, info).await {
+            Ok(value) => return Ok(value),
+            Err(err) => err,
+        };
+        if err.source::<Bubbled>().is_none() {
+            if err.path.is_empty() {
+                err.path = path_of(path_node);
+            }
+            self.errors
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push(err);
+        }
+        if nullable {
+            Ok(Some(Value::Null))
+        } else {
+            Err(ServerError {
+                source: Some(Arc::new(Bubbled)),
+                ..ServerError::new("", None)
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_graphql::{EmptyMutation, EmptySubscription, Object, Schema};
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use super::*;
+
+    struct Query;
+
+    #[Object]
+    impl Query {
+        async fn items(&self) -> Vec<Item> {
+            vec![Item(1), Item(2)]
+        }
+
+        #[allow(clippy::unused_async)]
+        async fn failed(&self) -> async_graphql::Result<Option<Item>> {
+            Err("failed".into())
+        }
+    }
+
+    struct Item(u32);
+
+    #[Object]
+    impl Item {
+        async fn value(&self) -> u32 {
+            self.0
+        }
+
+        #[allow(clippy::unused_async)]
+        async fn odd(&self) -> async_graphql::Result<Option<u32>> {
+            if self.0 % 2 == 1 {
+                return Ok(Some(self.0));
+            }
+            Err("not odd".into())
+        }
+
+        #[allow(clippy::unused_async)]
+        async fn even(&self) -> async_graphql::Result<u32> {
+            if self.0 % 2 == 1 {
+                return Err("not even".into());
+            }
+            Ok(self.0)
+        }
+    }
+
+    fn schema() -> Schema<Query, EmptyMutation, EmptySubscription> {
+        Schema::build(Query, EmptyMutation, EmptySubscription)
+            .extension(PartialResults)
+            .finish()
+    }
+
+    fn paths(res: &Response) -> Vec<serde_json::Value> {
+        res.errors
+            .iter()
+            .map(|err| serde_json::to_value(&err.path).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_nullable_field() {
+        let res = schema()
+            .execute("{ items { value odd } failed { value } }")
+            .await;
+        assert_eq!(
+            res.data.clone().into_json().unwrap(),
+            json!({
+                "items": [{ "value": 1, "odd": 1 }, { "value": 2, "odd": null }],
+                "failed": null,
+            })
+        );
+        let mut paths = paths(&res);
+        paths.sort_by_key(ToString::to_string);
+        assert_eq!(paths, vec![json!(["failed"]), json!(["items", 1, "odd"])]);
+    }
+
+    #[tokio::test]
+    async fn test_non_null_field() {
+        // The nearest nullable field is the whole response.
+        let res = schema().execute("{ items { value even } }").await;
+        assert_eq!(res.data, Value::Null);
+        assert_eq!(paths(&res), vec![json!(["items", 0, "even"])]);
+    }
+}
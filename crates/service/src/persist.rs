@@ -1,77 +1,126 @@
 use std::{future::IntoFuture, sync::Arc};
 
 use async_graphql::Context;
-use cfg_if::cfg_if;
 use ring::rand::SystemRandom;
-use surrealdb::{
-    engine,
-    opt::{capabilities::Capabilities, Config as SrlConfig},
-    Result as SrlResult, Surreal,
-};
+use surrealdb::Result as SrlResult;
 use tracing::{error, instrument};
 
 use crate::{
-    account::{AccountPersist, CurrentAccount},
+    account::{
+        Account, AccountPersist, CurrentAccount, HttpOidcClient, OidcClient, SharedAccountRepo,
+        SharedOidcClient, SurrealAccountRepo,
+    },
+    audience::AudiencePersist,
+    audit::{AuditPersist, SharedAuditSink},
     board::BoardPersist,
-    post::PostPersist,
+    bulk::BulkPersist,
+    client_state::{ClientStateFeed, ClientStatePersist},
+    client_version::ClientVersions,
+    config::{
+        AltTextPolicy, ClientVersionConfig, DbConfig, InstanceConfig, LimitsConfig, OidcConfig,
+        PostVisibilityConfig, PrivacyConfig, QuotaConfig, ReadOnlyConfig, SessionConfig,
+        TranslationConfig, DEFAULT_ALT_TEXT_POLICY, DEFAULT_DELETION_GRACE_DAYS,
+    },
+    conversation::ConversationPersist,
+    credentials::{Argon2Hasher, PasswordHasher, SharedPasswordHasher},
+    db::{Db, DbPool},
+    email::{EmailSender, NoEmailSender, SharedEmailSender},
+    event::EventPersist,
+    export::ExportPersist,
+    feed::Feed,
+    follow::FollowPersist,
+    integration::IntegrationPersist,
+    list::ListPersist,
+    loader::{Loadable, RequestLoader},
+    media::{BlobStore, MediaPersist, MemoryBlobStore, SharedBlobStore},
+    memo::RequestMemo,
+    moderation::{ModerationItem, ModerationPersist},
+    notification::{
+        NoNotificationTransport, Notification, NotificationPersist, NotificationTransport,
+        SharedNotificationTransport,
+    },
+    organization::{DomainVerifier, HttpDomainVerifier, OrganizationPersist, SharedDomainVerifier},
+    policy::PolicyPersist,
+    post::{Post, PostPersist},
     prelude::*,
+    provider::{Clock, IdGen, SharedClock, SharedIdGen, SystemClock, UlidGen},
+    quota::QuotaPersist,
+    read_marker::ReadMarkerPersist,
+    read_only::ReadOnlyMode,
+    reload::LiveConfig,
+    security::SecurityEventPersist,
+    session::SessionPersist,
+    spam::{SpamPersist, SpamPipeline},
+    stats::{LiveMetrics, RequestCounter, StatsPersist},
+    translation::TranslationPersist,
+    webhook::{HttpWebhookSender, SharedWebhookSender, WebhookPersist, WebhookSender},
     DecodingKey,
 };
 
-fn config() -> SrlConfig {
-    SrlConfig::new().capabilities(Capabilities::all())
-}
-
-cfg_if! {
-
-if #[cfg(all(
-    feature = "backend-mem",
-    not(feature = "backend-file"),
-    not(feature = "backend-tikv")
-))] {
-    pub type DbLayer = Surreal<engine::local::Db>;
-
-    async fn connect(_: String) -> SrlResult<DbLayer> {
-        Surreal::new::<engine::local::Mem>(config()).await
-    }
-} else if #[cfg(all(
-    not(feature = "backend-mem"),
-    feature = "backend-file",
-    not(feature = "backend-tikv")
-))] {
-    pub type DbLayer = Surreal<engine::local::Db>;
-
-    async fn connect(address: String) -> SrlResult<DbLayer> {
-        Surreal::new::<engine::local::RocksDb>((address, config())).await
-    }
-} else if #[cfg(all(
-    not(feature = "backend-mem"),
-    not(feature = "backend-file"),
-    feature = "backend-tikv"
-))] {
-    pub type DbLayer = Surreal<engine::local::Db>;
-
-    async fn connect(address: String) -> SrlResult<DbLayer> {
-        Surreal::new::<engine::local::TiKv>((address, config())).await
-    }
-} else {
-    pub type DbLayer = Surreal<engine::any::Any>;
-
-    async fn connect(address: String) -> SrlResult<DbLayer> {
-        engine::any::connect((address, config())).await
-    }
-}
-
-}
-
 pub trait PersistExt {
     fn current_account(&self) -> &CurrentAccount;
+    fn account_repo(&self) -> SharedAccountRepo;
     fn account_persist(&self) -> AccountPersist;
+    fn audience_persist(&self) -> AudiencePersist;
+    fn audit_persist(&self) -> AuditPersist;
     fn board_persist(&self) -> BoardPersist;
+    fn bulk_persist(&self) -> BulkPersist;
+    fn client_state_persist(&self) -> ClientStatePersist;
+    fn conversation_persist(&self) -> ConversationPersist;
+    fn event_persist(&self) -> EventPersist;
+    fn export_persist(&self) -> ExportPersist;
+    fn follow_persist(&self) -> FollowPersist;
+    fn integration_persist(&self) -> IntegrationPersist;
+    fn list_persist(&self) -> ListPersist;
+    fn media_persist(&self) -> MediaPersist;
+    fn moderation_persist(&self) -> ModerationPersist;
+    fn notification_persist(&self) -> NotificationPersist;
+    fn organization_persist(&self) -> OrganizationPersist;
+    fn policy_persist(&self) -> PolicyPersist;
     fn post_persist(&self) -> PostPersist;
+    fn quota_persist(&self) -> QuotaPersist;
+    fn read_marker_persist(&self) -> ReadMarkerPersist;
+    fn spam_persist(&self) -> SpamPersist;
+    fn security_event_persist(&self) -> SecurityEventPersist;
+    fn session_persist(&self) -> SessionPersist;
+    fn stats_persist(&self) -> StatsPersist;
+    fn translation_persist(&self) -> TranslationPersist;
+    fn webhook_persist(&self) -> WebhookPersist;
 }
 
-pub struct Persist(DbLayer);
+#[derive(Clone)]
+pub struct Persist {
+    db: DbPool,
+    clock: SharedClock,
+    ids: SharedIdGen,
+    password_hasher: SharedPasswordHasher,
+    quotas: QuotaConfig,
+    limits: LimitsConfig,
+    alt_text: AltTextPolicy,
+    post_visibility: PostVisibilityConfig,
+    client_state_feed: ClientStateFeed,
+    post_feed: Feed<Post>,
+    notification_feed: Feed<Notification>,
+    moderation_feed: Feed<ModerationItem>,
+    account_feed: Feed<Account>,
+    read_only: ReadOnlyMode,
+    client_versions: ClientVersions,
+    tenant: String,
+    region: Option<String>,
+    requests: RequestCounter,
+    metrics: LiveMetrics,
+    blobs: SharedBlobStore,
+    sessions: SessionConfig,
+    webhooks: SharedWebhookSender,
+    domains: SharedDomainVerifier,
+    oidc_client: SharedOidcClient,
+    notification_transport: SharedNotificationTransport,
+    email: SharedEmailSender,
+    audit_sinks: Arc<[SharedAuditSink]>,
+    translation: TranslationConfig,
+    memo: Option<RequestMemo>,
+    loader: RequestLoader,
+}
 
 static LOCK_TABLE: &str = "locks";
 
@@ -80,14 +129,316 @@ impl Persist {
         address: impl Into<String>,
         namespace: impl Into<String>,
         database: impl Into<String>,
+        config: &DbConfig,
     ) -> SrlResult<Self> {
-        let db = connect(address.into()).await?;
-        db.use_ns(namespace).use_db(database).await?;
-        Ok(Self(db))
+        let (namespace, database) = (namespace.into(), database.into());
+        let db = DbPool::connect(address.into(), &namespace, &database, config).await?;
+        Ok(Self {
+            db,
+            clock: Arc::new(SystemClock),
+            ids: Arc::new(UlidGen::default()),
+            password_hasher: Arc::new(Argon2Hasher::default()),
+            quotas: QuotaConfig::default(),
+            limits: LimitsConfig::default(),
+            alt_text: DEFAULT_ALT_TEXT_POLICY,
+            post_visibility: PostVisibilityConfig::default(),
+            client_state_feed: ClientStateFeed::new(),
+            post_feed: Feed::new(),
+            notification_feed: Feed::new(),
+            moderation_feed: Feed::new(),
+            account_feed: Feed::new(),
+            read_only: ReadOnlyMode::new(ReadOnlyConfig::default(), Arc::new(SystemClock)),
+            client_versions: ClientVersions::default(),
+            tenant: format!("{namespace}/{database}"),
+            region: None,
+            requests: RequestCounter::default(),
+            metrics: LiveMetrics::default(),
+            blobs: Arc::new(MemoryBlobStore::default()),
+            sessions: SessionConfig::default(),
+            webhooks: Arc::new(HttpWebhookSender::new()),
+            domains: Arc::new(HttpDomainVerifier::new()),
+            oidc_client: Arc::new(HttpOidcClient::new()),
+            notification_transport: Arc::new(NoNotificationTransport),
+            email: Arc::new(NoEmailSender),
+            audit_sinks: Arc::new([]),
+            translation: TranslationConfig::default(),
+            memo: None,
+            loader: RequestLoader::default(),
+        })
+    }
+
+    /// Replaces the clock. IDs are generated using the new clock as well, so
+    /// call [`Self::with_ids`] afterwards to use a different generator.
+    #[must_use]
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.ids = Arc::new(UlidGen::new(clock.clone()));
+        self.clock = clock;
+        self
+    }
+
+    #[must_use]
+    pub fn with_ids(mut self, ids: SharedIdGen) -> Self {
+        self.ids = ids;
+        self
+    }
+
+    /// Sets how account passwords are hashed.
+    #[must_use]
+    pub fn with_password_hasher(mut self, password_hasher: SharedPasswordHasher) -> Self {
+        self.password_hasher = password_hasher;
+        self
+    }
+
+    /// Sets how much each account can create, unless an admin has given it
+    /// different quotas.
+    #[must_use]
+    pub fn with_quotas(mut self, quotas: QuotaConfig) -> Self {
+        self.quotas = quotas;
+        self
+    }
+
+    /// Sets the region this server runs in.
+    #[must_use]
+    pub fn with_region(mut self, region: Option<String>) -> Self {
+        self.region = region;
+        self
+    }
+
+    /// Sets how long content can be.
+    #[must_use]
+    pub fn with_limits(mut self, limits: LimitsConfig) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Sets whether images attached to posts need alt text.
+    #[must_use]
+    pub fn with_alt_text_policy(mut self, alt_text: AltTextPolicy) -> Self {
+        self.alt_text = alt_text;
+        self
+    }
+
+    /// Sets who new posts can be shown to.
+    #[must_use]
+    pub fn with_post_visibility(mut self, post_visibility: PostVisibilityConfig) -> Self {
+        self.post_visibility = post_visibility;
+        self
+    }
+
+    /// Sets when writes are turned away. This uses the current clock, so call
+    /// it after [`Self::with_clock`].
+    #[must_use]
+    pub fn with_read_only(mut self, config: ReadOnlyConfig) -> Self {
+        self.read_only = ReadOnlyMode::new(config, self.clock.clone());
+        self
+    }
+
+    /// Sets the oldest version of each app that's still supported.
+    #[must_use]
+    pub fn with_client_versions(mut self, config: ClientVersionConfig) -> Self {
+        self.client_versions = ClientVersions::new(config);
+        self
+    }
+
+    /// Sets where the bytes of uploaded media are kept.
+    #[must_use]
+    pub fn with_blobs(mut self, blobs: SharedBlobStore) -> Self {
+        self.blobs = blobs;
+        self
+    }
+
+    /// Sets how long sessions last.
+    #[must_use]
+    pub fn with_sessions(mut self, sessions: SessionConfig) -> Self {
+        self.sessions = sessions;
+        self
+    }
+
+    /// Sets how webhook deliveries are sent.
+    #[must_use]
+    pub fn with_webhooks(mut self, webhooks: SharedWebhookSender) -> Self {
+        self.webhooks = webhooks;
+        self
+    }
+
+    /// Sets how organizations' domains are checked.
+    #[must_use]
+    pub fn with_domains(mut self, domains: SharedDomainVerifier) -> Self {
+        self.domains = domains;
+        self
+    }
+
+    /// Sets how sign-ins with identity providers are finished.
+    #[must_use]
+    pub fn with_oidc_client(mut self, oidc_client: SharedOidcClient) -> Self {
+        self.oidc_client = oidc_client;
+        self
+    }
+
+    /// Sets how notifications are pushed and emailed.
+    #[must_use]
+    pub fn with_notification_transport(mut self, transport: SharedNotificationTransport) -> Self {
+        self.notification_transport = transport;
+        self
+    }
+
+    /// Sets how the instance sends its own email.
+    #[must_use]
+    pub fn with_email(mut self, email: SharedEmailSender) -> Self {
+        self.email = email;
+        self
+    }
+
+    /// Sets the sinks that the audit trail is shipped to.
+    #[must_use]
+    pub fn with_audit_sinks(mut self, audit_sinks: Vec<SharedAuditSink>) -> Self {
+        self.audit_sinks = audit_sinks.into();
+        self
+    }
+
+    /// Sets how posts are translated.
+    #[must_use]
+    pub fn with_translation(mut self, translation: TranslationConfig) -> Self {
+        self.translation = translation;
+        self
+    }
+
+    /// Gives the persist its own [`RequestMemo`] and [`RequestLoader`], for
+    /// use while handling a single request.
+    #[must_use]
+    pub fn with_memo(mut self) -> Self {
+        self.memo = Some(RequestMemo::default());
+        self.loader = RequestLoader::default();
+        self
+    }
+
+    /// One of the database connections, taken from the pool in turn.
+    pub fn db(&self) -> Db<'_> {
+        self.db.get(self.memo.as_ref())
+    }
+
+    /// Loads a record by its ID. While handling a request, records that were
+    /// already loaded are reused instead of being queried again, and records
+    /// loaded at the same time are queried together.
+    pub async fn load<T: Loadable>(&self, thing: srql::Thing) -> SrlResult<Option<T>> {
+        let Some(memo) = &self.memo else {
+            return self.db().select(thing).await;
+        };
+        self.loader.load(self.db(), memo, thing).await
+    }
+
+    pub fn clock(&self) -> &dyn Clock {
+        &*self.clock
+    }
+
+    pub fn shared_clock(&self) -> SharedClock {
+        self.clock.clone()
+    }
+
+    pub fn ids(&self) -> &dyn IdGen {
+        &*self.ids
+    }
+
+    pub fn quotas(&self) -> &QuotaConfig {
+        &self.quotas
+    }
+
+    pub fn limits(&self) -> &LimitsConfig {
+        &self.limits
+    }
+
+    pub fn alt_text_policy(&self) -> AltTextPolicy {
+        self.alt_text
+    }
+
+    pub fn post_visibility(&self) -> &PostVisibilityConfig {
+        &self.post_visibility
+    }
+
+    pub fn client_state_feed(&self) -> &ClientStateFeed {
+        &self.client_state_feed
+    }
+
+    pub fn post_feed(&self) -> &Feed<Post> {
+        &self.post_feed
+    }
+
+    pub fn notification_feed(&self) -> &Feed<Notification> {
+        &self.notification_feed
+    }
+
+    pub fn moderation_feed(&self) -> &Feed<ModerationItem> {
+        &self.moderation_feed
+    }
+
+    pub fn account_feed(&self) -> &Feed<Account> {
+        &self.account_feed
+    }
+
+    pub fn read_only(&self) -> &ReadOnlyMode {
+        &self.read_only
+    }
+
+    pub fn client_versions(&self) -> &ClientVersions {
+        &self.client_versions
+    }
+
+    /// The namespace and database being used, separated by a `/`.
+    pub fn tenant(&self) -> &str {
+        &self.tenant
+    }
+
+    /// The region this server runs in, if it has been given one.
+    pub fn region(&self) -> Option<&str> {
+        self.region.as_deref()
+    }
+
+    pub fn requests(&self) -> &RequestCounter {
+        &self.requests
+    }
+
+    pub fn metrics(&self) -> &LiveMetrics {
+        &self.metrics
+    }
+
+    pub fn blobs(&self) -> &dyn BlobStore {
+        &*self.blobs
+    }
+
+    pub fn sessions(&self) -> &SessionConfig {
+        &self.sessions
+    }
+
+    pub fn webhooks(&self) -> &dyn WebhookSender {
+        &*self.webhooks
+    }
+
+    pub fn domains(&self) -> &dyn DomainVerifier {
+        &*self.domains
     }
 
-    pub fn db(&self) -> &DbLayer {
-        &self.0
+    pub fn oidc_client(&self) -> &dyn OidcClient {
+        &*self.oidc_client
+    }
+
+    pub fn notification_transport(&self) -> &dyn NotificationTransport {
+        &*self.notification_transport
+    }
+
+    pub fn email(&self) -> &dyn EmailSender {
+        &*self.email
+    }
+
+    pub fn audit_sinks(&self) -> &[SharedAuditSink] {
+        &self.audit_sinks
+    }
+
+    pub fn translation(&self) -> &TranslationConfig {
+        &self.translation
+    }
+
+    pub fn password_hasher(&self) -> &dyn PasswordHasher {
+        &*self.password_hasher
     }
 
     #[instrument(skip(self, f))]
@@ -143,22 +494,171 @@ impl PersistExt for Context<'_> {
             .unwrap_or_else(|| self.data_unchecked::<Arc<CurrentAccount>>())
     }
 
+    fn account_repo(&self) -> SharedAccountRepo {
+        self.data_opt::<SharedAccountRepo>()
+            .cloned()
+            .unwrap_or_else(|| {
+                Arc::new(SurrealAccountRepo::new(
+                    self.data_unchecked::<Persist>().clone(),
+                ))
+            })
+    }
+
     fn account_persist(&self) -> AccountPersist {
-        AccountPersist::new(
+        let persist = AccountPersist::new(
             self.data_unchecked::<Persist>(),
             self.current_account(),
             self.data_unchecked::<SystemRandom>(),
             self.data_unchecked::<DecodingKey>(),
         )
+        .with_min_age(
+            self.data_opt::<InstanceConfig>()
+                .map_or(0, |config| config.min_age),
+        )
+        .with_confusable_user_ids(
+            self.data_opt::<LiveConfig>()
+                .is_some_and(|live| live.load().features.allow_confusable_user_ids),
+        )
+        .with_public_url(
+            self.data_opt::<InstanceConfig>()
+                .and_then(|config| config.public_url.as_deref()),
+        )
+        .with_oidc(self.data_opt::<OidcConfig>())
+        .with_deletion_grace_days(
+            self.data_opt::<InstanceConfig>()
+                .map_or(DEFAULT_DELETION_GRACE_DAYS, |config| {
+                    config.deletion_grace_days
+                }),
+        )
+        .with_verified_email_required(
+            self.data_opt::<LiveConfig>()
+                .is_some_and(|live| live.load().features.require_verified_email),
+        );
+        match self.data_opt::<SharedAccountRepo>() {
+            Some(repo) => persist.with_repo(repo.clone()),
+            None => persist,
+        }
+    }
+
+    fn audience_persist(&self) -> AudiencePersist {
+        AudiencePersist::new(self.data_unchecked::<Persist>(), self.current_account())
+    }
+
+    fn audit_persist(&self) -> AuditPersist {
+        AuditPersist::new(self.data_unchecked::<Persist>(), self.current_account())
     }
 
     fn board_persist(&self) -> BoardPersist {
         BoardPersist::new(self.data_unchecked::<Persist>(), self.current_account())
     }
 
+    fn bulk_persist(&self) -> BulkPersist {
+        BulkPersist::new(self.data_unchecked::<Persist>(), self.current_account())
+    }
+
+    fn client_state_persist(&self) -> ClientStatePersist {
+        ClientStatePersist::new(self.data_unchecked::<Persist>(), self.current_account())
+    }
+
+    fn conversation_persist(&self) -> ConversationPersist {
+        ConversationPersist::new(self.data_unchecked::<Persist>(), self.current_account())
+    }
+
+    fn event_persist(&self) -> EventPersist {
+        EventPersist::new(self.data_unchecked::<Persist>(), self.current_account())
+    }
+
+    fn export_persist(&self) -> ExportPersist {
+        ExportPersist::new(self.data_unchecked::<Persist>(), self.current_account())
+    }
+
+    fn follow_persist(&self) -> FollowPersist {
+        FollowPersist::new(self.data_unchecked::<Persist>(), self.current_account())
+    }
+
+    fn integration_persist(&self) -> IntegrationPersist {
+        IntegrationPersist::new(
+            self.data_unchecked::<Persist>(),
+            self.current_account(),
+            self.data_unchecked::<SystemRandom>(),
+        )
+    }
+
+    fn list_persist(&self) -> ListPersist {
+        ListPersist::new(self.data_unchecked::<Persist>(), self.current_account())
+    }
+
+    fn media_persist(&self) -> MediaPersist {
+        MediaPersist::new(self.data_unchecked::<Persist>(), self.current_account())
+    }
+
+    fn moderation_persist(&self) -> ModerationPersist {
+        ModerationPersist::new(self.data_unchecked::<Persist>(), self.current_account())
+    }
+
+    fn notification_persist(&self) -> NotificationPersist {
+        NotificationPersist::new(self.data_unchecked::<Persist>(), self.current_account())
+    }
+
+    fn organization_persist(&self) -> OrganizationPersist {
+        OrganizationPersist::new(
+            self.data_unchecked::<Persist>(),
+            self.current_account(),
+            self.data_unchecked::<SystemRandom>(),
+        )
+    }
+
+    fn policy_persist(&self) -> PolicyPersist {
+        PolicyPersist::new(self.data_unchecked::<Persist>(), self.current_account())
+    }
+
     fn post_persist(&self) -> PostPersist {
         PostPersist::new(self.data_unchecked::<Persist>(), self.current_account())
     }
+
+    fn quota_persist(&self) -> QuotaPersist {
+        QuotaPersist::new(self.data_unchecked::<Persist>(), self.current_account())
+    }
+
+    fn read_marker_persist(&self) -> ReadMarkerPersist {
+        ReadMarkerPersist::new(self.data_unchecked::<Persist>(), self.current_account())
+    }
+
+    fn spam_persist(&self) -> SpamPersist {
+        SpamPersist::new(
+            self.data_unchecked::<Persist>(),
+            self.current_account(),
+            self.data_unchecked::<SpamPipeline>(),
+        )
+    }
+
+    fn security_event_persist(&self) -> SecurityEventPersist {
+        SecurityEventPersist::new(self.data_unchecked::<Persist>(), self.current_account())
+    }
+
+    fn session_persist(&self) -> SessionPersist {
+        SessionPersist::new(
+            self.data_unchecked::<Persist>(),
+            self.current_account(),
+            self.data_unchecked::<PrivacyConfig>(),
+        )
+    }
+
+    fn stats_persist(&self) -> StatsPersist {
+        StatsPersist::new(self.data_unchecked::<Persist>(), self.current_account())
+    }
+
+    fn translation_persist(&self) -> TranslationPersist {
+        TranslationPersist::new(self.data_unchecked::<Persist>(), self.current_account())
+    }
+
+    fn webhook_persist(&self) -> WebhookPersist {
+        WebhookPersist::new(
+            self.data_unchecked::<Persist>(),
+            self.current_account(),
+            self.data_unchecked::<SystemRandom>(),
+        )
+    }
 }
 
 #[cfg(test)]
@@ -168,6 +668,8 @@ mod test {
     use futures::join;
     use tokio::time::sleep;
 
+    use crate::{loader::Loadable, prelude::*};
+
     use super::testing::*;
 
     #[tokio::test]
@@ -196,6 +698,90 @@ mod test {
         assert!(a.unwrap().is_some());
         assert!(b.unwrap().is_none());
     }
+
+    #[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+    struct Item {
+        id: srql::Thing,
+        value: u64,
+    }
+
+    impl Loadable for Item {
+        fn record_id(&self) -> &srql::Thing {
+            &self.id
+        }
+    }
+
+    #[tokio::test]
+    async fn test_load_memo() {
+        let p = persist().await.with_memo();
+        let thing = srql::Thing::from(("item", "one"));
+        p.db().query("CREATE item:one SET value = 1").await.unwrap();
+        let value = |item: Option<Item>| item.map(|item| item.value);
+        let item: Option<Item> = p.load(thing.clone()).await.unwrap();
+        assert_eq!(value(item), Some(1));
+
+        // Another request's persist doesn't share the memo, so its write
+        // doesn't clear it.
+        let other = p.clone().with_memo();
+        other
+            .db()
+            .query("UPDATE item:one SET value = 2")
+            .await
+            .unwrap();
+        let value = |item: Option<Item>| item.map(|item| item.value);
+        let item: Option<Item> = p.load(thing.clone()).await.unwrap();
+        assert_eq!(value(item), Some(1));
+
+        p.db().query("UPDATE item:one SET value = 3").await.unwrap();
+        let item: Option<Item> = p.load(thing.clone()).await.unwrap();
+        assert_eq!(value(item), Some(3));
+
+        p.db().delete::<Option<Item>>(thing.clone()).await.unwrap();
+        let item: Option<Item> = p.load(thing).await.unwrap();
+        assert_eq!(item, None);
+    }
+
+    #[tokio::test]
+    async fn test_load_batched() {
+        #[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+        struct Other {
+            id: srql::Thing,
+        }
+
+        impl Loadable for Other {
+            fn record_id(&self) -> &srql::Thing {
+                &self.id
+            }
+        }
+
+        let p = persist().await.with_memo();
+        p.db()
+            .query("CREATE item:one SET value = 1; CREATE item:two SET value = 2; CREATE other:one")
+            .await
+            .unwrap();
+        let item = |id: &str| p.load::<Item>(srql::Thing::from(("item", id)));
+
+        // Records of the same type loaded at the same time are selected
+        // together, and those of other types in batches of their own.
+        let (one, two, missing, other) = join!(
+            item("one"),
+            item("two"),
+            item("missing"),
+            p.load::<Other>(srql::Thing::from(("other", "one"))),
+        );
+        assert_eq!(one.unwrap().map(|item| item.value), Some(1));
+        assert_eq!(two.unwrap().map(|item| item.value), Some(2));
+        assert_eq!(missing.unwrap(), None);
+        assert!(other.unwrap().is_some());
+        assert_eq!(p.loader.batches(), 2);
+
+        // Records that were already loaded, or found missing, aren't
+        // selected again.
+        let (one, missing) = join!(item("one"), item("missing"));
+        assert!(one.unwrap().is_some());
+        assert_eq!(missing.unwrap(), None);
+        assert_eq!(p.loader.batches(), 2);
+    }
 }
 
 #[cfg(test)]
@@ -203,9 +789,13 @@ pub mod testing {
     use crate::migration::Migrations;
 
     use super::*;
+    use crate::credentials::testing::test_hasher;
 
     pub async fn persist() -> Persist {
-        let persist = Persist::new("memory", "test", "test").await.unwrap();
+        let persist = Persist::new("memory", "test", "test", &DbConfig::default())
+            .await
+            .unwrap()
+            .with_password_hasher(test_hasher());
         Migrations::run(&persist).await.unwrap();
         persist
     }
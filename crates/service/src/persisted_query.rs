@@ -0,0 +1,380 @@
+//! Automatic persisted queries (APQ).
+//!
+//! Clients can send the SHA-256 hash of a query, in hex, as the `sha256Hash`
+//! of a `persistedQuery` request extension instead of its text. If the server
+//! doesn't know the query, it answers with a `PersistedQueryNotFound` error,
+//! and the client sends it again with both the text and the hash so that the
+//! server can remember it. Remembered queries are kept in a bounded cache,
+//! and can also be stored in the database so that they outlive restarts.
+//!
+//! When the instance has an allow-list, only the queries on it can be run,
+//! whether they're sent by hash or in full, and clients can't add to it.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use async_graphql::{
+    extensions::{Extension, ExtensionContext, ExtensionFactory, NextPrepareRequest},
+    ErrorExtensions as _, Pos, Request, ServerResult,
+};
+use ring::digest;
+use serde::Deserialize;
+use tracing::{debug, error};
+
+use crate::{config::PersistedQueryConfig, persist::Persist, prelude::*};
+
+static PERSISTED_QUERY_TABLE: &str = "persisted_query";
+
+/// The SHA-256 hash of a query's text, in hex, as clients send it.
+pub fn query_hash(query: &str) -> String {
+    hex::encode(digest::digest(&digest::SHA256, query.as_bytes()))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PersistedQuery {
+    version: i32,
+    sha256_hash: String,
+}
+
+#[derive(Deserialize)]
+struct StoredQuery {
+    query: String,
+}
+
+/// The queries that have been sent with their hashes, dropping the least
+/// recently used ones once it's full.
+struct QueryCache {
+    capacity: usize,
+    inner: Mutex<CacheInner>,
+}
+
+#[derive(Default)]
+struct CacheInner {
+    queries: HashMap<String, CachedQuery>,
+    uses: u64,
+}
+
+struct CachedQuery {
+    query: Arc<str>,
+    last_used: u64,
+}
+
+impl QueryCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            inner: Mutex::default(),
+        }
+    }
+
+    fn get(&self, hash: &str) -> Option<Arc<str>> {
+        let mut inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        inner.uses += 1;
+        let uses = inner.uses;
+        let cached = inner.queries.get_mut(hash)?;
+        cached.last_used = uses;
+        Some(cached.query.clone())
+    }
+
+    fn insert(&self, hash: String, query: Arc<str>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        if inner.queries.len() >= self.capacity && !inner.queries.contains_key(&hash) {
+            let oldest = inner
+                .queries
+                .iter()
+                .min_by_key(|(_, cached)| cached.last_used)
+                .map(|(hash, _)| hash.clone());
+            if let Some(oldest) = oldest {
+                inner.queries.remove(&oldest);
+            }
+        }
+        inner.uses += 1;
+        let last_used = inner.uses;
+        inner.queries.insert(hash, CachedQuery { query, last_used });
+    }
+}
+
+/// A GraphQL extension that lets clients send queries by their hash.
+pub struct PersistedQueries {
+    config: PersistedQueryConfig,
+    cache: Arc<QueryCache>,
+}
+
+impl PersistedQueries {
+    #[must_use]
+    pub fn new(config: PersistedQueryConfig) -> Self {
+        Self {
+            cache: Arc::new(QueryCache::new(config.cache_size)),
+            config,
+        }
+    }
+}
+
+impl ExtensionFactory for PersistedQueries {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(PersistedQueryExtension {
+            config: self.config.clone(),
+            cache: self.cache.clone(),
+        })
+    }
+}
+
+struct PersistedQueryExtension {
+    config: PersistedQueryConfig,
+    cache: Arc<QueryCache>,
+}
+
+impl PersistedQueryExtension {
+    /// Finds a query that has been sent before by its hash.
+    async fn load(&self, persist: Option<&Persist>, hash: &str) -> Result<Option<Arc<str>>> {
+        if let Some(query) = self.cache.get(hash) {
+            return Ok(Some(query));
+        }
+        let Some(persist) = persist.filter(|_| self.config.persist) else {
+            return Ok(None);
+        };
+
+        let stored: Option<StoredQuery> =
+            persist.db().select((PERSISTED_QUERY_TABLE, hash)).await?;
+        Ok(stored.map(|stored| {
+            let query: Arc<str> = stored.query.into();
+            self.cache.insert(hash.to_owned(), query.clone());
+            query
+        }))
+    }
+
+    /// Remembers a query sent along with its hash.
+    async fn store(&self, persist: Option<&Persist>, hash: String, query: &str) {
+        self.cache.insert(hash.clone(), query.into());
+        let Some(persist) = persist.filter(|_| self.config.persist) else {
+            return;
+        };
+
+        let res = persist
+            .db()
+            .query(srql::UpdateStatement {
+                what: srql::thing((PERSISTED_QUERY_TABLE, &*hash)),
+                data: srql::Data::SetExpression(vec![(
+                    srql::field("query"),
+                    srql::Operator::Equal,
+                    query.into(),
+                )])
+                .into(),
+                output: srql::Output::None.into(),
+                ..Default::default()
+            })
+            .await
+            .and_then(|mut res| res.take::<Option<()>>(0));
+        // The query is still cached and can be sent again, so this shouldn't
+        // fail the request.
+        if let Err(err) = res {
+            error!(error = ?err, "Failed to store persisted query");
+        }
+    }
+
+    async fn resolve(&self, persist: Option<&Persist>, mut request: Request) -> Result<Request> {
+        let persisted = match request.extensions.remove("persistedQuery") {
+            Some(value) => Some(
+                value
+                    .into_json()
+                    .ok()
+                    .and_then(|value| serde_json::from_value::<PersistedQuery>(value).ok())
+                    .filter(|persisted| persisted.version == 1)
+                    .ok_or_else(|| {
+                        Error::InputInvalid(
+                            "persistedQuery must be version 1 with a sha256Hash".into(),
+                        )
+                    })?,
+            ),
+            None => None,
+        };
+
+        if let Some(allow_list) = &self.config.allow_list {
+            let hash = match persisted {
+                Some(persisted) if request.query.is_empty() => persisted.sha256_hash,
+                _ => query_hash(&request.query),
+            };
+            let Some(query) = allow_list.get(&hash) else {
+                debug!(hash, "Turning away query that isn't on the allow-list");
+                return Err(Error::PersistedQueryNotAllowed);
+            };
+            request.query = query.clone();
+            return Ok(request);
+        }
+
+        let Some(persisted) = persisted else {
+            return Ok(request);
+        };
+        if request.query.is_empty() {
+            let query = self
+                .load(persist, &persisted.sha256_hash)
+                .await?
+                .ok_or(Error::PersistedQueryNotFound)?;
+            request.query = query.to_string();
+        } else {
+            let hash = query_hash(&request.query);
+            if hash != persisted.sha256_hash {
+                return Err(Error::InputInvalid(
+                    "persistedQuery.sha256Hash doesn't match the query".into(),
+                ));
+            }
+            self.store(persist, hash, &request.query).await;
+        }
+        Ok(request)
+    }
+}
+
+#[async_trait::async_trait]
+impl Extension for PersistedQueryExtension {
+    async fn prepare_request(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        request: Request,
+        next: NextPrepareRequest<'_>,
+    ) -> ServerResult<Request> {
+        let request = self
+            .resolve(ctx.data_opt::<Persist>(), request)
+            .await
+            .map_err(|err| err.extend().into_server_error(Pos::default()))?;
+        next.run(ctx, request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_graphql::{Request, Value};
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        account::testing::TestData,
+        schema::{schema, ServiceSchema},
+    };
+
+    static QUERY: &str = "{ __typename }";
+
+    fn schema_with(config: PersistedQueryConfig) -> ServiceSchema {
+        schema(|s| s.extension(PersistedQueries::new(config)))
+    }
+
+    fn request(query: &str, hash: &str) -> Request {
+        let mut request = Request::new(query);
+        request.extensions.insert(
+            "persistedQuery".into(),
+            Value::from_json(json!({ "version": 1, "sha256Hash": hash })).unwrap(),
+        );
+        request
+    }
+
+    fn code(res: &async_graphql::Response) -> Option<Value> {
+        res.errors
+            .first()
+            .and_then(|err| err.extensions.as_ref())
+            .and_then(|ext| ext.get("code"))
+            .cloned()
+    }
+
+    #[test]
+    fn test_query_hash() {
+        assert_eq!(
+            query_hash(QUERY),
+            "7f56e67dd21ab3f30d1ff8b7bed08893f0a0db86449836189b361dd1e56ddb4b"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_register_and_reuse() {
+        let schema = schema_with(PersistedQueryConfig::default());
+        let hash = query_hash(QUERY);
+
+        let res = schema.execute(request("", &hash)).await;
+        assert_eq!(code(&res), Some(Value::from("PersistedQueryNotFound")));
+        assert_eq!(res.errors[0].message, "PersistedQueryNotFound");
+
+        let res = schema.execute(request(QUERY, "wrong")).await;
+        assert_eq!(code(&res), Some(Value::from("InputInvalid")));
+
+        let res = schema.execute(request(QUERY, &hash)).await;
+        assert!(res.errors.is_empty(), "{:?}", res.errors);
+        let res = schema.execute(request("", &hash)).await;
+        assert!(res.errors.is_empty(), "{:?}", res.errors);
+        assert_eq!(
+            res.data,
+            Value::from_json(json!({ "__typename": "Query" })).unwrap()
+        );
+
+        // Queries sent without a hash are run as usual.
+        let res = schema.execute(QUERY).await;
+        assert!(res.errors.is_empty(), "{:?}", res.errors);
+    }
+
+    #[test]
+    fn test_cache_drops_least_recently_used() {
+        let cache = QueryCache::new(2);
+        cache.insert("a".into(), "A".into());
+        cache.insert("b".into(), "B".into());
+        assert!(cache.get("a").is_some());
+        cache.insert("c".into(), "C".into());
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("c").is_some());
+
+        let cache = QueryCache::new(0);
+        cache.insert("a".into(), "A".into());
+        assert!(cache.get("a").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_persist() {
+        let (data, _) = TestData::with_user().await;
+        let config = PersistedQueryConfig {
+            persist: true,
+            ..Default::default()
+        };
+        let with_persist = || {
+            let persist = data.persist.clone();
+            schema(|s| {
+                s.extension(PersistedQueries::new(config.clone()))
+                    .data(persist)
+            })
+        };
+        let hash = query_hash(QUERY);
+
+        let res = with_persist().execute(request(QUERY, &hash)).await;
+        assert!(res.errors.is_empty(), "{:?}", res.errors);
+        // Another server, or this one after restarting, starts with nothing
+        // cached.
+        let res = with_persist().execute(request("", &hash)).await;
+        assert!(res.errors.is_empty(), "{:?}", res.errors);
+    }
+
+    #[tokio::test]
+    async fn test_allow_list() {
+        let hash = query_hash(QUERY);
+        let schema = schema_with(PersistedQueryConfig {
+            allow_list: Some(Arc::new(HashMap::from([(hash.clone(), QUERY.to_owned())]))),
+            ..Default::default()
+        });
+
+        let res = schema.execute(request("", &hash)).await;
+        assert!(res.errors.is_empty(), "{:?}", res.errors);
+        let res = schema.execute(QUERY).await;
+        assert!(res.errors.is_empty(), "{:?}", res.errors);
+
+        let other = "{ __schema { queryType { name } } }";
+        let res = schema.execute(other).await;
+        assert_eq!(code(&res), Some(Value::from("PersistedQueryNotAllowed")));
+        let res = schema.execute(request(other, &query_hash(other))).await;
+        assert_eq!(code(&res), Some(Value::from("PersistedQueryNotAllowed")));
+        let res = schema.execute(request("", &query_hash(other))).await;
+        assert_eq!(code(&res), Some(Value::from("PersistedQueryNotAllowed")));
+    }
+}
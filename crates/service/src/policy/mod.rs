@@ -0,0 +1,13 @@
+//! Versioned instance policies (terms of service, privacy policy) and the
+//! record of which versions each account has accepted.
+
+mod models;
+mod persist;
+mod schema;
+
+pub use models::*;
+pub use persist::*;
+pub use schema::*;
+
+static POLICY_TABLE_NAME: &str = "policy";
+pub static ACCEPTANCE_TABLE_NAME: &str = "policy_acceptance";
@@ -0,0 +1,121 @@
+use async_graphql::{ComplexObject, Enum, SimpleObject, ID};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use surrealdb::sql::Thing;
+
+use super::{ACCEPTANCE_TABLE_NAME, POLICY_TABLE_NAME};
+use crate::{id_obj_impls, prelude::*};
+
+/// The kinds of policy an instance can publish.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyKind {
+    /// The terms of service.
+    Terms,
+    /// The privacy policy.
+    Privacy,
+}
+
+impl PolicyKind {
+    pub const ALL: [Self; 2] = [Self::Terms, Self::Privacy];
+}
+
+impl QueryValue for PolicyKind {
+    fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
+        srql::to_value(self)
+            .ok()
+            .map(|value| (field, srql::Operator::Equal, value))
+    }
+}
+
+/// A published version of one of the instance's policies. Published versions
+/// can't be changed, only replaced by publishing a new version.
+#[derive(SimpleObject, Debug, Clone, Deserialize)]
+#[graphql(complex)]
+pub struct Policy {
+    #[graphql(skip)]
+    pub id: Thing,
+
+    /// Which policy this is.
+    pub kind: PolicyKind,
+    /// The version of the policy, starting at 1 and increasing each time a
+    /// new version is published.
+    pub version: u32,
+    /// The text of the policy.
+    pub content: String,
+
+    /// A timestamp indicating when this version was published.
+    pub updated_at: DateTime<Utc>,
+}
+
+#[ComplexObject]
+impl Policy {
+    /// The policy version's unique ID. This is what is passed when accepting
+    /// it.
+    async fn id(&self) -> ID {
+        self.id.to_gql_id()
+    }
+}
+
+id_obj_impls!(Policy);
+
+impl Policy {
+    pub fn create(
+        kind: PolicyKind,
+        version: u32,
+        content: String,
+        ids: &dyn IdGen,
+    ) -> srql::CreateStatement {
+        let mut create = vec![];
+        kind.push_field(srql::field("kind"), &mut create);
+        version.push_field(srql::field("version"), &mut create);
+        content.push_field(srql::field("content"), &mut create);
+        srql::obj_create_query(POLICY_TABLE_NAME, create, ids)
+    }
+}
+
+/// A record of an account accepting a version of a policy.
+#[derive(SimpleObject, Debug, Clone, Deserialize)]
+#[graphql(complex)]
+pub struct PolicyAcceptance {
+    #[graphql(skip)]
+    pub id: Thing,
+    #[graphql(skip)]
+    pub account_id: Thing,
+    #[graphql(skip)]
+    pub policy_id: Thing,
+
+    /// Which policy was accepted.
+    pub kind: PolicyKind,
+    /// The version of the policy that was accepted.
+    pub version: u32,
+
+    /// A timestamp indicating when the policy was accepted.
+    pub updated_at: DateTime<Utc>,
+}
+
+#[ComplexObject]
+impl PolicyAcceptance {
+    /// The ID of the policy version that was accepted.
+    async fn policy_id(&self) -> ID {
+        self.policy_id.to_gql_id()
+    }
+}
+
+id_obj_impls!(PolicyAcceptance);
+
+impl PolicyAcceptance {
+    pub fn create(account_id: Thing, policy: &Policy, ids: &dyn IdGen) -> srql::CreateStatement {
+        let mut create = vec![];
+        account_id.push_field(srql::field("account_id"), &mut create);
+        policy
+            .id
+            .clone()
+            .push_field(srql::field("policy_id"), &mut create);
+        policy.kind.push_field(srql::field("kind"), &mut create);
+        policy
+            .version
+            .push_field(srql::field("version"), &mut create);
+        srql::obj_create_query(ACCEPTANCE_TABLE_NAME, create, ids)
+    }
+}
@@ -0,0 +1,254 @@
+#[cfg(test)]
+mod tests;
+
+use async_graphql::ID;
+use tracing::instrument;
+
+use super::{Policy, PolicyAcceptance, PolicyKind, ACCEPTANCE_TABLE_NAME, POLICY_TABLE_NAME};
+use crate::{
+    account::{require_admin, CurrentAccount},
+    persist::Persist,
+    prelude::*,
+    query::SRQL_ORDER_DESC,
+};
+
+pub struct PolicyPersist<'a> {
+    persist: &'a Persist,
+    current: &'a CurrentAccount,
+}
+
+impl<'a> PolicyPersist<'a> {
+    pub fn new(persist: &'a Persist, current: &'a CurrentAccount) -> Self {
+        Self { persist, current }
+    }
+
+    /// Gets the latest version of a policy, if one has been published.
+    #[instrument(skip_all)]
+    pub async fn latest(&self, kind: PolicyKind) -> Result<Option<Policy>> {
+        let policy = self
+            .persist
+            .db()
+            .query(srql::SelectStatement {
+                expr: srql::Fields::all(),
+                what: srql::table(POLICY_TABLE_NAME),
+                cond: srql::Cond(
+                    srql::Expression::Binary {
+                        l: srql::field("kind").into(),
+                        o: srql::Operator::Equal,
+                        r: srql::to_value(kind).map_err(Error::from_err)?,
+                    }
+                    .into(),
+                )
+                .into(),
+                order: srql::Orders(vec![srql::Order {
+                    order: srql::field("version"),
+                    direction: SRQL_ORDER_DESC,
+                    ..Default::default()
+                }])
+                .into(),
+                limit: srql::Limit(srql::Number::Int(1).into()).into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        Ok(policy)
+    }
+
+    /// Gets the latest version of each policy that has been published.
+    #[instrument(skip_all)]
+    pub async fn current(&self) -> Result<Vec<Policy>> {
+        let mut policies = vec![];
+        for kind in PolicyKind::ALL {
+            if let Some(policy) = self.latest(kind).await? {
+                policies.push(policy);
+            }
+        }
+        Ok(policies)
+    }
+
+    /// Publishes a new version of a policy. Every account has to accept it
+    /// before doing anything else that is guarded by
+    /// [`PoliciesAccepted`](super::PoliciesAccepted). Only admins can do this.
+    #[instrument(skip_all)]
+    pub async fn publish(&self, kind: PolicyKind, content: String) -> Result<Policy> {
+        require_admin(self.persist, self.current).await?;
+
+        let version = self.latest(kind).await?.map_or(0, |p| p.version) + 1;
+        let policy: Option<Policy> = self
+            .persist
+            .db()
+            .query(Policy::create(kind, version, content, self.persist.ids()))
+            .await?
+            .take(0)?;
+
+        match policy {
+            Some(policy) => Ok(policy),
+            None => Err(Error::UnavailableIdent),
+        }
+    }
+
+    /// Lists the policies that the current account has accepted, including
+    /// older versions.
+    #[instrument(skip_all)]
+    pub async fn acceptances(&self) -> Result<Vec<PolicyAcceptance>> {
+        let account_id = self.current.id()?.to_account_thing();
+        self.acceptances_of(account_id).await
+    }
+
+    async fn acceptances_of(&self, account_id: srql::Thing) -> Result<Vec<PolicyAcceptance>> {
+        let acceptances = self
+            .persist
+            .db()
+            .query(srql::SelectStatement {
+                expr: srql::Fields::all(),
+                what: srql::table(ACCEPTANCE_TABLE_NAME),
+                cond: srql::Cond(
+                    srql::Expression::Binary {
+                        l: srql::field("account_id").into(),
+                        o: srql::Operator::Equal,
+                        r: account_id.into(),
+                    }
+                    .into(),
+                )
+                .into(),
+                order: srql::Orders(vec![srql::Order {
+                    order: srql::field("id"),
+                    direction: true,
+                    ..Default::default()
+                }])
+                .into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        Ok(acceptances)
+    }
+
+    /// Lists the latest policies that the current account hasn't accepted
+    /// yet.
+    #[instrument(skip_all)]
+    pub async fn outstanding(&self) -> Result<Vec<Policy>> {
+        let accepted = self.acceptances().await?;
+        let mut current = self.current().await?;
+        current.retain(|policy| {
+            !accepted
+                .iter()
+                .any(|acceptance| acceptance.policy_id == policy.id)
+        });
+        Ok(current)
+    }
+
+    /// Fails if the current account hasn't accepted the latest version of
+    /// every policy. Anonymous requests are let through, since whatever they
+    /// try to do will need an account anyway.
+    #[instrument(skip_all)]
+    pub async fn require_accepted(&self) -> Result<()> {
+        if self.current.id().is_err() {
+            return Ok(());
+        }
+        if self.outstanding().await?.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::PoliciesNotAccepted)
+        }
+    }
+
+    /// Records the current account accepting the given policy versions.
+    /// Only the latest versions can be accepted.
+    #[instrument(skip_all)]
+    pub async fn accept(&self, policy_ids: &[ID]) -> Result<Vec<PolicyAcceptance>> {
+        let account_id = self.current.id()?.to_account_thing();
+        let current = self.current().await?;
+        let policies = policy_ids
+            .iter()
+            .map(|id| {
+                current
+                    .iter()
+                    .find(|policy| *policy == id)
+                    .ok_or(Error::PolicyInvalid)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        self.record(account_id, &policies).await
+    }
+
+    /// Checks that a new account is accepting the latest version of every
+    /// policy, returning them so they can be recorded once the account
+    /// exists.
+    pub(crate) async fn check_registration(&self, policy_ids: &[ID]) -> Result<Vec<Policy>> {
+        let current = self.current().await?;
+        if current
+            .iter()
+            .all(|policy| policy_ids.iter().any(|id| policy == id))
+        {
+            Ok(current)
+        } else {
+            Err(Error::PoliciesNotAccepted)
+        }
+    }
+
+    /// Records an account accepting policies, skipping any it has already
+    /// accepted.
+    pub(crate) async fn record(
+        &self,
+        account_id: srql::Thing,
+        policies: &[&Policy],
+    ) -> Result<Vec<PolicyAcceptance>> {
+        let existing = self.acceptances_of(account_id.clone()).await?;
+
+        let mut recorded = vec![];
+        for policy in policies {
+            if existing
+                .iter()
+                .any(|acceptance| acceptance.policy_id == policy.id)
+            {
+                continue;
+            }
+
+            let acceptance: Option<PolicyAcceptance> = self
+                .persist
+                .db()
+                .query(PolicyAcceptance::create(
+                    account_id.clone(),
+                    policy,
+                    self.persist.ids(),
+                ))
+                .await?
+                .take(0)?;
+            recorded.extend(acceptance);
+        }
+
+        Ok(recorded)
+    }
+}
+
+#[cfg(test)]
+pub mod testing {
+    use async_trait::async_trait;
+
+    use super::PolicyPersist;
+    use crate::{
+        account::testing::TestData,
+        policy::{Policy, PolicyKind},
+    };
+
+    #[async_trait]
+    pub trait PolicyTestData {
+        fn policy(&self) -> PolicyPersist<'_>;
+
+        /// Publishes a new version of a policy. The current account must be
+        /// an admin.
+        async fn publish_policy(&self, kind: PolicyKind) -> Policy {
+            self.policy()
+                .publish(kind, format!("The {kind:?} policy"))
+                .await
+                .unwrap()
+        }
+    }
+
+    impl PolicyTestData for TestData {
+        fn policy(&self) -> PolicyPersist<'_> {
+            PolicyPersist::new(&self.persist, &self.current)
+        }
+    }
+}
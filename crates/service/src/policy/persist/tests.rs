@@ -0,0 +1,125 @@
+use pretty_assertions::assert_eq;
+
+use super::{testing::PolicyTestData as _, *};
+use crate::account::{testing::*, CreateAccount};
+
+#[tokio::test]
+async fn test_publish() {
+    let (data, _) = TestData::with_user().await;
+    assert!(data.policy().current().await.unwrap().is_empty());
+
+    let terms = data.publish_policy(PolicyKind::Terms).await;
+    assert_eq!(terms.version, 1);
+    let privacy = data.publish_policy(PolicyKind::Privacy).await;
+    assert_eq!(privacy.version, 1);
+    let terms = data.publish_policy(PolicyKind::Terms).await;
+    assert_eq!(terms.version, 2);
+
+    let current = data.policy().current().await.unwrap();
+    assert_eq!(current, vec![terms, privacy]);
+}
+
+#[tokio::test]
+async fn test_publish_admin_only() {
+    let (mut data, _) = TestData::with_user().await;
+    let other = data.account().create_test_user().await;
+    data.login_as(&other);
+
+    let res = data
+        .policy()
+        .publish(PolicyKind::Terms, "Terms".into())
+        .await;
+    assert_eq!(res.unwrap_err(), Error::Unauthorized);
+}
+
+#[tokio::test]
+async fn test_register_requires_acceptance() {
+    let (mut data, _) = TestData::with_user().await;
+    let terms = data.publish_policy(PolicyKind::Terms).await;
+    data.current = CurrentAccount::default();
+
+    let create = |accepted_policy_ids| CreateAccount {
+        user_id: "new".into(),
+        pword: "password".to_owned().into(),
+        invite: None,
+        bot: None,
+        accepted_policy_ids,
+        birthdate: None,
+        honeypot: None,
+        form_shown_at: None,
+        allow_confusable: None,
+        email: None,
+    };
+    for ids in [None, Some(vec![]), Some(vec!["missing".into()])] {
+        let res = data.account().create(create(ids.clone())).await;
+        assert_eq!(res.unwrap_err(), Error::PoliciesNotAccepted, "{ids:?}");
+    }
+
+    let acc = data
+        .account()
+        .create(create(Some(vec![terms.id.to_gql_id()])))
+        .await
+        .unwrap()
+        .account;
+    data.login_as(&AccData {
+        id: acc.id.clone(),
+        user_id: acc.user_id.clone(),
+        pword: "password".to_owned().into(),
+        acc,
+    });
+
+    let acceptances = data.policy().acceptances().await.unwrap();
+    assert_eq!(acceptances.len(), 1);
+    assert_eq!(acceptances[0].policy_id, terms.id);
+    assert_eq!(acceptances[0].version, 1);
+    data.policy().require_accepted().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_reaccept() {
+    let (mut data, admin) = TestData::with_user().await;
+    data.publish_policy(PolicyKind::Terms).await;
+    let acc = data.account().create_test_user().await;
+    data.login_as(&acc);
+    data.policy().require_accepted().await.unwrap();
+
+    data.login_as(&admin);
+    let terms = data.publish_policy(PolicyKind::Terms).await;
+
+    data.login_as(&acc);
+    assert_eq!(
+        data.policy().require_accepted().await.unwrap_err(),
+        Error::PoliciesNotAccepted
+    );
+    assert_eq!(
+        data.policy().outstanding().await.unwrap(),
+        vec![terms.clone()]
+    );
+
+    let accepted = data.policy().accept(&[terms.id.to_gql_id()]).await.unwrap();
+    assert_eq!(accepted.len(), 1);
+    assert_eq!(accepted[0].version, 2);
+    assert!(data.policy().outstanding().await.unwrap().is_empty());
+    data.policy().require_accepted().await.unwrap();
+
+    // Both versions stay on record.
+    assert_eq!(data.policy().acceptances().await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_accept_old_version() {
+    let (data, _) = TestData::with_user().await;
+    let old = data.publish_policy(PolicyKind::Privacy).await;
+    data.publish_policy(PolicyKind::Privacy).await;
+
+    let res = data.policy().accept(&[old.id.to_gql_id()]).await;
+    assert_eq!(res.unwrap_err(), Error::PolicyInvalid);
+}
+
+#[tokio::test]
+async fn test_anonymous_allowed() {
+    let (mut data, _) = TestData::with_user().await;
+    data.publish_policy(PolicyKind::Terms).await;
+    data.current = CurrentAccount::default();
+    data.policy().require_accepted().await.unwrap();
+}
@@ -0,0 +1,73 @@
+use async_graphql::{Context, Guard, Object, ID};
+use async_trait::async_trait;
+use tracing::instrument;
+
+use super::{Policy, PolicyAcceptance, PolicyKind};
+use crate::prelude::*;
+
+/// Rejects requests from accounts that haven't accepted the latest version of
+/// every policy, with [`Error::PoliciesNotAccepted`].
+pub struct PoliciesAccepted;
+
+#[async_trait]
+impl Guard for PoliciesAccepted {
+    async fn check(&self, ctx: &Context<'_>) -> GqlResult<()> {
+        ctx.policy_persist().require_accepted().await.extend()
+    }
+}
+
+#[derive(Default)]
+pub struct PolicyQuery;
+
+#[Object]
+impl PolicyQuery {
+    /// Lists the latest version of each of the instance's policies. These
+    /// must all be accepted when registering.
+    #[instrument(skip_all)]
+    async fn policies(&self, ctx: &Context<'_>) -> GqlResult<Vec<Policy>> {
+        ctx.policy_persist().current().await.extend()
+    }
+
+    /// Lists the latest policies that the current account still needs to
+    /// accept. Most changes are rejected until this is empty.
+    #[instrument(skip_all)]
+    async fn outstanding_policies(&self, ctx: &Context<'_>) -> GqlResult<Vec<Policy>> {
+        ctx.policy_persist().outstanding().await.extend()
+    }
+
+    /// Lists every policy version the current account has accepted, and
+    /// when.
+    #[instrument(skip_all)]
+    async fn policy_acceptances(&self, ctx: &Context<'_>) -> GqlResult<Vec<PolicyAcceptance>> {
+        ctx.policy_persist().acceptances().await.extend()
+    }
+}
+
+#[derive(Default)]
+pub struct PolicyMutation;
+
+#[Object]
+impl PolicyMutation {
+    /// Accepts the given policy versions on behalf of the current account.
+    /// Only the latest versions can be accepted.
+    #[instrument(skip_all)]
+    async fn accept_policies(
+        &self,
+        ctx: &Context<'_>,
+        ids: Vec<ID>,
+    ) -> GqlResult<Vec<PolicyAcceptance>> {
+        ctx.policy_persist().accept(&ids).await.extend()
+    }
+
+    /// Publishes a new version of a policy, which every account will need to
+    /// accept. Only admins can do this.
+    #[instrument(skip_all)]
+    async fn publish_policy(
+        &self,
+        ctx: &Context<'_>,
+        kind: PolicyKind,
+        #[graphql(validator(min_length = 1, max_length = 262_144))] content: String,
+    ) -> GqlResult<Policy> {
+        ctx.policy_persist().publish(kind, content).await.extend()
+    }
+}
@@ -1,13 +1,62 @@
-use async_graphql::{ComplexObject, InputObject, MaybeUndefined, SimpleObject, ID};
+use async_graphql::{
+    ComplexObject, Context, Enum, InputObject, MaybeUndefined, SimpleObject, Union, ID,
+};
 use chrono::{DateTime, Utc};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use surrealdb::sql::Thing;
 
 use super::POST_TABLE_NAME;
-use crate::{board::BOARD_TABLE_NAME, id_obj_impls, prelude::*, query::OpaqueCursor};
+use crate::{
+    account::ACC_TABLE_NAME,
+    audience::AUDIENCE_TABLE_NAME,
+    board::BOARD_TABLE_NAME,
+    config::{LimitsConfig, PostVisibility},
+    event::post_count,
+    id_obj_impls,
+    license::{check_attribution_url, ContentLicense},
+    media::{Media, MEDIA_TABLE_NAME},
+    organization::{Organization, ORGANIZATION_TABLE_NAME},
+    persist::Persist,
+    prelude::*,
+    query::OpaqueCursor,
+};
 
 pub type PostCursor = OpaqueCursor<String>;
 
+/// The most media that can be attached to a single post.
+pub const MAX_POST_MEDIA: usize = 4;
+
+/// Who is allowed to reply to a post.
+#[derive(Enum, Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplyPolicy {
+    /// Anyone can reply.
+    #[default]
+    Everyone,
+    /// Only accounts that follow the author can reply.
+    Followers,
+    /// Only accounts mentioned in the post can reply.
+    Mentioned,
+    /// Nobody can reply.
+    Nobody,
+}
+
+impl QueryValue for ReplyPolicy {
+    fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
+        srql::to_value(self)
+            .ok()
+            .map(|value| (field, srql::Operator::Equal, value))
+    }
+}
+
+impl QueryValue for PostVisibility {
+    fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
+        srql::to_value(self)
+            .ok()
+            .map(|value| (field, srql::Operator::Equal, value))
+    }
+}
+
 #[derive(SimpleObject, Debug, Clone, Deserialize)]
 #[graphql(complex)]
 pub struct Post {
@@ -17,11 +66,49 @@ pub struct Post {
     pub creator_id: Option<Thing>,
     #[graphql(skip)]
     pub board_id: Option<Thing>,
+    #[graphql(skip)]
+    pub quote_id: Option<Thing>,
+    #[graphql(skip)]
+    pub reply_to_id: Option<Thing>,
+    #[graphql(skip)]
+    #[serde(default)]
+    pub mention_ids: Vec<Thing>,
+    #[graphql(skip)]
+    pub organization_id: Option<Thing>,
+    #[graphql(skip)]
+    pub audience_id: Option<Thing>,
+    #[graphql(skip)]
+    #[serde(default)]
+    pub media_ids: Vec<Thing>,
 
     /// The post's title.
     pub title: Option<String>,
     /// The post's content.
     pub content: Option<String>,
+    /// Who is allowed to reply to this post.
+    #[serde(default)]
+    pub reply_policy: ReplyPolicy,
+    /// Who can see this post.
+    #[serde(default)]
+    pub visibility: PostVisibility,
+    /// The license that the post is published under.
+    #[serde(default)]
+    pub license: ContentLicense,
+    /// Who the post's content should be credited to, if it isn't the post's
+    /// creator.
+    pub attribution: Option<String>,
+    /// Where the post's content came from, such as the page it was first
+    /// published on.
+    pub attribution_url: Option<String>,
+    /// Whether the post has been limited for spam, hiding it from everyone
+    /// but its author.
+    #[graphql(skip)]
+    #[serde(default)]
+    pub limited: bool,
+    /// Whether the post was created by a bot account.
+    #[graphql(skip)]
+    #[serde(default)]
+    pub bot: bool,
 
     /// A timestamp indicating the last time the board was updated.
     ///
@@ -50,20 +137,151 @@ impl Post {
     async fn creator_id(&self) -> Option<ID> {
         self.creator_id.as_ref().map(ToGqlId::to_gql_id)
     }
+
+    /// The ID of the post that this post quotes, if any. This cannot be changed.
+    async fn quote_id(&self) -> Option<ID> {
+        self.quote_id.as_ref().map(ToGqlId::to_gql_id)
+    }
+
+    /// The ID of the post that this post is a reply to, if any. This cannot be changed.
+    async fn reply_to_id(&self) -> Option<ID> {
+        self.reply_to_id.as_ref().map(ToGqlId::to_gql_id)
+    }
+
+    /// How many replies the post has. This is built from the event log, so
+    /// can take a few seconds to catch up with changes.
+    async fn reply_count(&self, ctx: &Context<'_>) -> GqlResult<u64> {
+        post_count(ctx.data_unchecked::<Persist>(), &self.id)
+            .await
+            .extend()
+    }
+
+    /// The IDs of the accounts mentioned in this post. This cannot be changed.
+    async fn mention_ids(&self) -> Vec<ID> {
+        self.mention_ids.iter().map(ToGqlId::to_gql_id).collect()
+    }
+
+    /// The ID of the organization that this post was made as, if any. This
+    /// cannot be changed.
+    async fn organization_id(&self) -> Option<ID> {
+        self.organization_id.as_ref().map(ToGqlId::to_gql_id)
+    }
+
+    /// The ID of the audience that this post was published to, if any. Only
+    /// the audience's owner and members can see the post. This cannot be
+    /// changed.
+    async fn audience_id(&self) -> Option<ID> {
+        self.audience_id.as_ref().map(ToGqlId::to_gql_id)
+    }
+
+    /// The IDs of the media attached to this post, in the order they should
+    /// be shown. This cannot be changed.
+    async fn media_ids(&self) -> Vec<ID> {
+        self.media_ids.iter().map(ToGqlId::to_gql_id).collect()
+    }
+
+    /// The media attached to this post, in the order they should be shown.
+    /// Media that has since been deleted is left out.
+    async fn media(&self, ctx: &Context<'_>) -> GqlResult<Vec<Media>> {
+        ctx.media_persist().get_many(&self.media_ids).await.extend()
+    }
+
+    /// The IDs of the images attached to this post that don't have alt text.
+    /// Clients can use this to remind the author to describe them.
+    async fn media_missing_alt_text(&self, ctx: &Context<'_>) -> GqlResult<Vec<ID>> {
+        let media = ctx
+            .media_persist()
+            .get_many(&self.media_ids)
+            .await
+            .extend()?;
+        Ok(media
+            .iter()
+            .filter(|media| media.missing_alt_text())
+            .map(|media| media.id.to_gql_id())
+            .collect())
+    }
+
+    /// The organization that this post was made as, if any.
+    async fn organization(&self, ctx: &Context<'_>) -> GqlResult<Option<Organization>> {
+        let Some(organization_id) = &self.organization_id else {
+            return Ok(None);
+        };
+        ctx.organization_persist()
+            .get(&organization_id.to_gql_id())
+            .await
+            .extend()
+    }
+
+    /// The URL of the text of the post's license, if it has one.
+    async fn license_url(&self) -> Option<&'static str> {
+        self.license.url()
+    }
+
+    /// Whether the current account is allowed to reply to this post.
+    ///
+    /// Clients should use this to decide whether to show a reply box.
+    async fn can_reply(&self, ctx: &Context<'_>) -> GqlResult<bool> {
+        ctx.post_persist().can_reply(self).await.extend()
+    }
+
+    /// Whether clients should collapse this post, because it is a reply by
+    /// someone the current account muted in this conversation.
+    async fn collapsed(&self, ctx: &Context<'_>) -> GqlResult<bool> {
+        ctx.conversation_persist().is_collapsed(self).await.extend()
+    }
+
+    /// The post that this post quotes, if any.
+    ///
+    /// If the quoted post has since been deleted, a placeholder is returned
+    /// instead so that clients can show that something used to be there.
+    async fn quote(&self, ctx: &Context<'_>) -> GqlResult<Option<QuotedPost>> {
+        let Some(quote_id) = &self.quote_id else {
+            return Ok(None);
+        };
+
+        let quoted = ctx
+            .post_persist()
+            .get(&quote_id.to_gql_id())
+            .await
+            .extend()?;
+        Ok(Some(match quoted {
+            Some(post) => QuotedPost::Post(post.into()),
+            None => QuotedPost::Deleted(DeletedPost {
+                id: quote_id.to_gql_id(),
+            }),
+        }))
+    }
 }
 
 id_obj_impls!(Post);
 
+/// A post that has been quoted by another post.
+#[derive(Union, Debug, Clone)]
+pub enum QuotedPost {
+    Post(Box<Post>),
+    Deleted(DeletedPost),
+}
+
+/// A placeholder for a quoted post that has been deleted.
+#[derive(SimpleObject, Debug, Clone, PartialEq, Eq)]
+pub struct DeletedPost {
+    /// The ID that the deleted post had.
+    pub id: ID,
+}
+
 impl Post {
     pub fn create(
         creator_id: Option<Thing>,
+        bot: bool,
         params: CreatePost,
+        ids: &dyn IdGen,
     ) -> (Option<(Thing, String)>, srql::CreateStatement) {
         let mut create = vec![];
         creator_id.push_field(srql::field("creator_id"), &mut create);
+        bot.push_field(srql::field("bot"), &mut create);
         let board_id = params.board_id.clone();
         params.append(&mut create);
-        let id = srql::ulid();
+        let id = ids.next_id();
         (
             board_id.map(|board_id| {
                 (
@@ -80,12 +298,62 @@ impl Post {
 pub struct CreatePost {
     /// The ID of the board that this post belongs to. This cannot be changed.
     pub board_id: Option<ID>,
-    /// The post's title.
-    #[graphql(validator(max_length = 1024))]
+    /// The ID of the post that this post quotes. This cannot be changed.
+    ///
+    /// The quoted post's author must allow their posts to be quoted.
+    pub quote_id: Option<ID>,
+    /// The ID of the post that this post is a reply to. This cannot be changed.
+    ///
+    /// The replied-to post's reply policy must allow the current account to reply.
+    pub reply_to_id: Option<ID>,
+    /// The IDs of the accounts mentioned in this post. This cannot be changed.
+    pub mention_ids: Option<Vec<ID>>,
+    /// The IDs of media that the current account uploaded to attach to this
+    /// post, in the order they should be shown. At most 4 can be attached.
+    /// This cannot be changed.
+    ///
+    /// Depending on `instanceInfo.altTextPolicy`, images may need alt text
+    /// before they can be attached.
+    #[graphql(validator(max_items = 4))]
+    pub media_ids: Option<Vec<ID>>,
+    /// Who is allowed to reply to this post. Defaults to everyone.
+    pub reply_policy: Option<ReplyPolicy>,
+    /// Who can see this post. Defaults to
+    /// `instanceInfo.defaultPostVisibility`, and has to be one of
+    /// `instanceInfo.allowedPostVisibilities`. Replies are never more
+    /// visible than the post they reply to. This cannot be changed.
+    pub visibility: Option<PostVisibility>,
+    /// The post's title. This can be at most `instanceInfo.limits.postTitle`
+    /// characters long.
     pub title: Option<String>,
-    /// The post's content.
-    #[graphql(validator(max_length = 32_768))]
+    /// The post's content. This can be at most
+    /// `instanceInfo.limits.postContent` characters long.
     pub content: Option<String>,
+    /// The ID of the organization to post as. The current account needs to
+    /// be allowed to post as it. This cannot be changed.
+    pub organization_id: Option<ID>,
+    /// The ID of one of the current account's audiences to publish the post
+    /// to, so that only its members can see it. Replies are always published
+    /// to the audience of the post they reply to. This cannot be changed.
+    pub audience_id: Option<ID>,
+    /// The license that the post is published under. Defaults to the current
+    /// account's `defaultLicense`.
+    pub license: Option<ContentLicense>,
+    /// Who the post's content should be credited to, if it isn't the current
+    /// account.
+    #[graphql(validator(max_length = 256))]
+    pub attribution: Option<String>,
+    /// Where the post's content came from. This must be an `http` or `https`
+    /// URL.
+    pub attribution_url: Option<String>,
+}
+
+impl CreatePost {
+    pub fn check_limits(&self, limits: &LimitsConfig) -> Result<()> {
+        LimitsConfig::check("title", self.title.as_deref(), limits.post_title)?;
+        LimitsConfig::check("content", self.content.as_deref(), limits.post_content)?;
+        check_attribution_url(self.attribution_url.as_deref())
+    }
 }
 
 impl CreateObject for CreatePost {
@@ -93,8 +361,52 @@ impl CreateObject for CreatePost {
         self.board_id
             .map(|id| (BOARD_TABLE_NAME, id))
             .push_field(srql::field("board_id"), expr);
+        self.quote_id
+            .map(|id| (POST_TABLE_NAME, id))
+            .push_field(srql::field("quote_id"), expr);
+        self.reply_to_id
+            .map(|id| (POST_TABLE_NAME, id))
+            .push_field(srql::field("reply_to_id"), expr);
+        if let Some(mention_ids) = self.mention_ids {
+            expr.push((
+                srql::field("mention_ids"),
+                srql::Operator::Equal,
+                srql::array(
+                    mention_ids
+                        .into_iter()
+                        .map(|id| srql::Thing::from((ACC_TABLE_NAME.to_owned(), id.0)).into())
+                        .collect::<Vec<srql::Value>>(),
+                ),
+            ));
+        }
+        if let Some(media_ids) = self.media_ids.filter(|ids| !ids.is_empty()) {
+            expr.push((
+                srql::field("media_ids"),
+                srql::Operator::Equal,
+                srql::array(
+                    media_ids
+                        .into_iter()
+                        .map(|id| srql::Thing::from((MEDIA_TABLE_NAME.to_owned(), id.0)).into())
+                        .collect::<Vec<srql::Value>>(),
+                ),
+            ));
+        }
+        self.reply_policy
+            .push_field(srql::field("reply_policy"), expr);
+        self.visibility.push_field(srql::field("visibility"), expr);
         self.title.push_field(srql::field("title"), expr);
         self.content.push_field(srql::field("content"), expr);
+        self.organization_id
+            .map(|id| (ORGANIZATION_TABLE_NAME, id))
+            .push_field(srql::field("organization_id"), expr);
+        self.audience_id
+            .map(|id| (AUDIENCE_TABLE_NAME, id))
+            .push_field(srql::field("audience_id"), expr);
+        self.license.push_field(srql::field("license"), expr);
+        self.attribution
+            .push_field(srql::field("attribution"), expr);
+        self.attribution_url
+            .push_field(srql::field("attribution_url"), expr);
     }
 }
 
@@ -102,12 +414,38 @@ impl CreateObject for CreatePost {
 pub struct UpdatePost {
     /// The post's title. If not given, the title is not changed. If null is given,
     /// the title is cleared.
-    #[graphql(validator(max_length = 1024))]
     pub title: MaybeUndefined<String>,
     /// The post's content. If not given, the content is not changed. If null is given,
     /// the content is cleared.
-    #[graphql(validator(max_length = 32_768))]
     pub content: MaybeUndefined<String>,
+    /// Who is allowed to reply to this post. If not given, the policy is not changed.
+    pub reply_policy: Option<ReplyPolicy>,
+    /// The license that the post is published under. If not given, the
+    /// license is not changed.
+    pub license: Option<ContentLicense>,
+    /// Who the post's content should be credited to. If not given, the
+    /// attribution is not changed. If null is given, it is cleared.
+    #[graphql(validator(max_length = 256))]
+    pub attribution: MaybeUndefined<String>,
+    /// Where the post's content came from. If not given, the URL is not
+    /// changed. If null is given, it is cleared.
+    pub attribution_url: MaybeUndefined<String>,
+}
+
+impl UpdatePost {
+    pub fn check_limits(&self, limits: &LimitsConfig) -> Result<()> {
+        LimitsConfig::check(
+            "title",
+            self.title.as_opt_deref().flatten(),
+            limits.post_title,
+        )?;
+        LimitsConfig::check(
+            "content",
+            self.content.as_opt_deref().flatten(),
+            limits.post_content,
+        )?;
+        check_attribution_url(self.attribution_url.as_opt_deref().flatten())
+    }
 }
 
 impl IntoUpdateQuery for UpdatePost {
@@ -115,6 +453,13 @@ impl IntoUpdateQuery for UpdatePost {
         let mut update = vec![];
         self.title.push_field(srql::field("title"), &mut update);
         self.content.push_field(srql::field("content"), &mut update);
+        self.reply_policy
+            .push_field(srql::field("reply_policy"), &mut update);
+        self.license.push_field(srql::field("license"), &mut update);
+        self.attribution
+            .push_field(srql::field("attribution"), &mut update);
+        self.attribution_url
+            .push_field(srql::field("attribution_url"), &mut update);
         srql::obj_update_query(thing, update)
     }
 }
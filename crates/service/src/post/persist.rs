@@ -1,17 +1,45 @@
 #[cfg(test)]
 mod tests;
 
-use async_graphql::connection::{Connection, Edge};
-use tracing::instrument;
+use std::collections::HashSet;
 
-use super::{CreatePost, Post, PostCursor, UpdatePost, CONTAINS_TABLE_NAME, POST_TABLE_NAME};
+use async_graphql::{
+    connection::{Connection, Edge},
+    MaybeUndefined, ID,
+};
+use futures::{Stream, StreamExt as _};
+use tracing::{error, instrument};
+
+use super::{
+    CreatePost, Post, PostCursor, ReplyPolicy, UpdatePost, CONTAINS_TABLE_NAME, MAX_POST_MEDIA,
+    POST_TABLE_NAME,
+};
 use crate::{
-    account::CurrentAccount,
+    account::{is_adult, restriction_visible_cond, Account, CurrentAccount, RestrictionKind},
+    audience::{audience_visible_cond, can_see_audience, AudiencePersist},
+    board::{require_board_permission, Board, BoardPermission, BOARD_TABLE_NAME},
+    config::{AltTextPolicy, PostVisibility},
+    conversation::is_muted_reply,
+    event::{DomainEvent, DomainEventKind},
+    follow::FollowPersist,
+    license::resolve_license,
+    media::MediaPersist,
+    notification::{CreateNotification, NotificationKind, NotificationPersist},
+    organization::{
+        log_activity, require_permission, OrganizationAction, OrganizationPermission,
+        ORGANIZATION_TABLE_NAME,
+    },
     persist::Persist,
     prelude::*,
     query::{OpaqueCursor, PaginationInput, PaginationOptions, ResultSlice},
+    quota::{text_bytes, QuotaPersist, QuotaResource},
+    webhook::{send_webhooks, WebhookEvent},
 };
 
+/// How many posts a bot can create each hour. Bots post far more regularly
+/// than people, so they get a tighter limit of their own.
+pub const BOT_MAX_POSTS_PER_HOUR: u64 = 30;
+
 pub struct PostPersist<'a> {
     persist: &'a Persist,
     current: &'a CurrentAccount,
@@ -24,55 +52,584 @@ impl<'a> PostPersist<'a> {
 
     #[instrument(skip_all)]
     pub async fn get(&self, id: &str) -> Result<Option<Post>> {
-        Ok(self.persist.db().select((POST_TABLE_NAME, id)).await?)
+        let post: Option<Post> = self
+            .persist
+            .load(srql::Thing::from((POST_TABLE_NAME, id)))
+            .await?;
+        match post {
+            Some(post)
+                if !self.can_see_visibility(&post)
+                    || !self.can_see_board(post.board_id.as_ref()).await?
+                    || !self.can_see_author(post.creator_id.as_ref()).await?
+                    || !self.can_see_audience(&post).await? =>
+            {
+                Ok(None)
+            }
+            post => Ok(post),
+        }
+    }
+
+    /// Whether the current account can see a post given its visibility,
+    /// which it can't if the post is only for signed in accounts and it
+    /// isn't signed in.
+    fn can_see_visibility(&self, post: &Post) -> bool {
+        post.visibility != PostVisibility::Instance || self.current.id().is_ok()
+    }
+
+    /// Whether the current account can see posts in a board, which it can't
+    /// if the board is age-restricted and it isn't an adult.
+    async fn can_see_board(&self, board_id: Option<&srql::Thing>) -> Result<bool> {
+        let Some(board_id) = board_id else {
+            return Ok(true);
+        };
+        let board: Option<Board> = self.persist.load(board_id.clone()).await?;
+        if !board.is_some_and(|board| board.age_restricted) {
+            return Ok(true);
+        }
+        let viewer = self.current.id().ok().map(ToAccountThing::to_account_thing);
+        is_adult(self.persist, viewer.as_ref()).await
+    }
+
+    /// Whether the current account can see posts by an author, which it
+    /// can't if an admin has restricted them. Authors always see their own
+    /// posts, and followers of silenced authors see theirs.
+    async fn can_see_author(&self, creator_id: Option<&srql::Thing>) -> Result<bool> {
+        let Some(creator_id) = creator_id else {
+            return Ok(true);
+        };
+        let author: Option<Account> = self.persist.load(creator_id.clone()).await?;
+        let now = self.persist.clock().now();
+        let Some(restriction) = author.as_ref().and_then(|a| a.active_restriction(now)) else {
+            return Ok(true);
+        };
+        let Ok(viewer) = self.current.id() else {
+            return Ok(false);
+        };
+        if viewer.to_account_thing() == *creator_id {
+            return Ok(true);
+        }
+        Ok(restriction.kind == RestrictionKind::Silenced
+            && FollowPersist::new(self.persist, self.current)
+                .is_following(&creator_id.id.to_raw())
+                .await?)
+    }
+
+    /// Whether the current account can see a post given the audience it was
+    /// published to, if any. Authors always see their own posts.
+    async fn can_see_audience(&self, post: &Post) -> Result<bool> {
+        let Some(audience_id) = &post.audience_id else {
+            return Ok(true);
+        };
+        let viewer = self.current.id().ok().map(ToAccountThing::to_account_thing);
+        if viewer.is_some() && viewer == post.creator_id {
+            return Ok(true);
+        }
+        can_see_audience(self.persist, audience_id, viewer.as_ref()).await
     }
 
     #[instrument(skip_all)]
-    pub fn list(&self) -> PostListRequest<'_> {
-        PostListRequest::new(self.persist)
+    pub fn list(&self) -> PostListRequest<'a> {
+        let viewer = self.current.id().ok().map(ToAccountThing::to_account_thing);
+        PostListRequest::new(self.persist, viewer)
     }
 
     #[instrument(skip_all)]
-    pub async fn create(&self, post: CreatePost) -> Result<Post> {
+    pub async fn create(&self, mut post: CreatePost) -> Result<Post> {
         // TODO: check config to see if anon users can create posts on this board
         // TODO: check perms to see if authd user can create posts on this board
 
-        let (ids, create) = Post::create(
-            self.current.id().map(ToAccountThing::to_account_thing).ok(),
-            post,
-        );
+        post.check_limits(self.persist.limits())?;
+        if let Some(board_id) = &post.board_id {
+            let board_id = srql::Thing::from((BOARD_TABLE_NAME.to_owned(), board_id.0.clone()));
+            if !self.can_see_board(Some(&board_id)).await? {
+                return Err(Error::AgeRestricted);
+            }
+            if post.reply_to_id.is_none() {
+                require_board_permission(
+                    self.persist,
+                    self.current,
+                    &board_id,
+                    BoardPermission::Post,
+                )
+                .await?;
+            }
+        }
 
-        let query = if let Some((board_id, post_id)) = ids {
-            vec![
-                srql::trans_begin(),
-                srql::Statement::Create(create),
-                srql::Statement::Relate(srql::RelateStatement {
-                    kind: srql::Table(CONTAINS_TABLE_NAME.to_owned()).into(),
-                    from: board_id.into(),
-                    with: srql::Thing::from((POST_TABLE_NAME.to_owned(), post_id)).into(),
-                    ..Default::default()
-                }),
-                srql::trans_end(),
-            ]
-        } else {
-            vec![srql::Statement::Create(create)]
+        let quoted = match &post.quote_id {
+            Some(quote_id) => Some(self.get_quotable(quote_id).await?),
+            None => None,
         };
+        let mut reply_visibility = None;
+        if let Some(reply_to_id) = &post.reply_to_id {
+            let Some(reply_to) = self.get(reply_to_id).await? else {
+                return Err(Error::ReplyInvalid);
+            };
+            if !self.can_reply(&reply_to).await? {
+                return Err(Error::ReplyDisallowed);
+            }
+            if let Some(board_id) = &reply_to.board_id {
+                require_board_permission(
+                    self.persist,
+                    self.current,
+                    board_id,
+                    BoardPermission::Comment,
+                )
+                .await?;
+            }
+            // Replies would otherwise show a conversation to accounts that
+            // can't see where it started.
+            post.audience_id = reply_to.audience_id.as_ref().map(ToGqlId::to_gql_id);
+            reply_visibility = Some(reply_to.visibility);
+        } else if let Some(audience_id) = &post.audience_id {
+            if AudiencePersist::new(self.persist, self.current)
+                .get(audience_id)
+                .await?
+                .is_none()
+            {
+                return Err(Error::AudienceInvalid);
+            }
+        }
 
-        let post = self.persist.db().query(query).await?.take(0)?;
+        // Replies are never more visible than what they reply to, for the
+        // same reason.
+        let visibility = self.persist.post_visibility().resolve(post.visibility)?;
+        let visibility = reply_visibility.map_or(visibility, |of| visibility.max(of));
+        // Quotes would show posts only this instance can see to everyone.
+        if quoted.as_ref().is_some_and(|quoted| {
+            quoted.visibility == PostVisibility::Instance && visibility != PostVisibility::Instance
+        }) {
+            return Err(Error::QuoteDisallowed);
+        }
+        post.visibility = Some(visibility);
+
+        let creator_id = self.current.id().map(ToAccountThing::to_account_thing).ok();
+        if let Some(organization_id) = &post.organization_id {
+            let creator_id = creator_id.as_ref().ok_or(Error::Unauthorized)?;
+            require_permission(
+                self.persist,
+                &srql::Thing::from((ORGANIZATION_TABLE_NAME, organization_id.as_str())),
+                creator_id,
+                OrganizationPermission::Post,
+            )
+            .await?;
+        }
+        if let Some(media_ids) = &post.media_ids {
+            self.check_media(creator_id.as_ref(), media_ids).await?;
+        }
+        let bot = match &creator_id {
+            Some(creator_id) => self.check_bot_limit(creator_id).await?,
+            None => false,
+        };
+        post.license =
+            Some(resolve_license(self.persist, post.license, creator_id.as_ref()).await?);
+        if let Some(creator_id) = &creator_id {
+            let bytes = text_bytes(post.title.as_deref(), post.content.as_deref());
+            QuotaPersist::new(self.persist, self.current)
+                .require_available(
+                    creator_id,
+                    &[(QuotaResource::Posts, 1), (QuotaResource::Storage, bytes)],
+                )
+                .await?;
+        }
+
+        let context_ids = [
+            post.board_id
+                .as_ref()
+                .map(|id| srql::Thing::from((BOARD_TABLE_NAME, id.as_str()))),
+            post.reply_to_id
+                .as_ref()
+                .map(|id| srql::Thing::from((POST_TABLE_NAME, id.as_str()))),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        let (ids, create) = Post::create(creator_id.clone(), bot, post, self.persist.ids());
+        let event = srql::created_thing(&create).map(|post_id| {
+            DomainEvent::create(
+                DomainEventKind::PostPublished,
+                creator_id,
+                post_id,
+                context_ids,
+                self.persist.clock(),
+                self.persist.ids(),
+            )
+        });
+
+        let mut query = vec![srql::trans_begin(), srql::Statement::Create(create)];
+        if let Some((board_id, post_id)) = ids {
+            query.push(srql::Statement::Relate(srql::RelateStatement {
+                kind: srql::Table(CONTAINS_TABLE_NAME.to_owned()).into(),
+                from: board_id.into(),
+                with: srql::Thing::from((POST_TABLE_NAME.to_owned(), post_id)).into(),
+                ..Default::default()
+            }));
+        }
+        query.extend(event.map(srql::Statement::Create));
+        query.push(srql::trans_end());
+
+        let post: Option<Post> = self.persist.db().query(query).await?.take(0)?;
 
         match post {
-            Some(post) => Ok(post),
+            Some(post) => {
+                self.persist.post_feed().publish(&post);
+                if let Some(Post {
+                    creator_id: Some(author_id),
+                    ..
+                }) = quoted
+                {
+                    self.notify_quoted(author_id, &post).await;
+                }
+                self.notify_mentioned(&post).await;
+                self.log_organization_activity(&post, OrganizationAction::PostCreated)
+                    .await;
+                Ok(post)
+            }
             None => Err(Error::UnavailableIdent),
         }
     }
 
+    /// Checks that media being attached to a post was uploaded by its
+    /// creator, and that images have alt text if the instance requires it.
+    /// Missing alt text is reported against the attachment's index, such as
+    /// `mediaIds.1`.
+    async fn check_media(&self, creator_id: Option<&srql::Thing>, media_ids: &[ID]) -> Result<()> {
+        if media_ids.is_empty() {
+            return Ok(());
+        }
+        let creator_id = creator_id.ok_or(Error::Unauthorized)?;
+        if media_ids.len() > MAX_POST_MEDIA {
+            return Err(Error::InputInvalid(format!(
+                "at most {MAX_POST_MEDIA} media can be attached"
+            )));
+        }
+
+        let require_alt_text = self.persist.alt_text_policy() == AltTextPolicy::Require;
+        let media_persist = MediaPersist::new(self.persist, self.current);
+        let mut seen = HashSet::new();
+        for (index, id) in media_ids.iter().enumerate() {
+            if !seen.insert(id) {
+                return Err(Error::InputInvalid(
+                    "media can only be attached once".into(),
+                ));
+            }
+            let Some(media) = media_persist
+                .get(id)
+                .await?
+                .filter(|media| media.owner_id == *creator_id)
+            else {
+                return Err(Error::InputInvalid(format!(
+                    "media {} doesn't exist or wasn't uploaded by this account",
+                    id.as_str()
+                )));
+            };
+            if require_alt_text && media.missing_alt_text() {
+                return Err(Error::AltTextMissing(format!("mediaIds.{index}")));
+            }
+        }
+        Ok(())
+    }
+
+    /// Streams posts that the current account can see as they're created.
+    ///
+    /// Posts can be limited to a single board, or to the replies to a single
+    /// post. When neither is given, posts by bots are left out unless
+    /// `include_bots` is set, the same as when listing posts.
+    pub fn subscribe(
+        &self,
+        board_id: Option<srql::Thing>,
+        reply_to_id: Option<srql::Thing>,
+        include_bots: bool,
+    ) -> impl Stream<Item = Post> + Send + 'static {
+        let viewer = self.current.id().ok().map(ToAccountThing::to_account_thing);
+        let include_bots = include_bots || board_id.is_some() || reply_to_id.is_some();
+        let created = self.persist.post_feed().subscribe(move |post| {
+            board_id
+                .as_ref()
+                .is_none_or(|id| post.board_id.as_ref() == Some(id))
+                && reply_to_id
+                    .as_ref()
+                    .is_none_or(|id| post.reply_to_id.as_ref() == Some(id))
+                && (include_bots || !post.bot)
+                && (!post.limited || (viewer.is_some() && post.creator_id == viewer))
+                && listed_visibility(post, viewer.as_ref(), reply_to_id.is_some())
+        });
+
+        // Whether a board is age-restricted, an author is restricted or an
+        // account is in an audience can change, so they're checked as each
+        // post arrives rather than when subscribing.
+        let (persist, current) = (self.persist.clone(), self.current.clone());
+        created.filter_map(move |post| {
+            let (persist, current) = (persist.clone(), current.clone());
+            async move {
+                let posts = PostPersist::new(&persist, &current);
+                let visible = match posts.can_see_board(post.board_id.as_ref()).await {
+                    Ok(true) => posts.can_see_author(post.creator_id.as_ref()).await,
+                    visible => visible,
+                };
+                let visible = match visible {
+                    Ok(true) => posts.can_see_audience(&post).await,
+                    visible => visible,
+                };
+                match visible {
+                    Ok(visible) => visible.then_some(post),
+                    Err(err) => {
+                        error!(error = ?err, "Failed to check whether a created post is visible");
+                        None
+                    }
+                }
+            }
+        })
+    }
+
+    /// Checks that an update doesn't take the post's creator over their
+    /// storage quota.
+    async fn check_update_storage(&self, id: &str, update: &UpdatePost) -> Result<()> {
+        if update.title.is_undefined() && update.content.is_undefined() {
+            return Ok(());
+        }
+        let Some(Post {
+            creator_id: Some(creator_id),
+            title,
+            content,
+            ..
+        }) = self.get(id).await?
+        else {
+            return Ok(());
+        };
+
+        let pick = |updated: &MaybeUndefined<String>, existing: Option<String>| match updated {
+            MaybeUndefined::Undefined => existing,
+            MaybeUndefined::Null => None,
+            MaybeUndefined::Value(value) => Some(value.clone()),
+        };
+        let before = text_bytes(title.as_deref(), content.as_deref());
+        let after = text_bytes(
+            pick(&update.title, title).as_deref(),
+            pick(&update.content, content).as_deref(),
+        );
+        if after <= before {
+            return Ok(());
+        }
+
+        QuotaPersist::new(self.persist, self.current)
+            .require_available(&creator_id, &[(QuotaResource::Storage, after - before)])
+            .await
+    }
+
+    /// Checks whether the creator is a bot, and if so whether it has reached
+    /// its hourly post limit.
+    async fn check_bot_limit(&self, creator_id: &srql::Thing) -> Result<bool> {
+        let creator: Option<Account> = self.persist.load(creator_id.clone()).await?;
+        if !creator.is_some_and(|creator| creator.bot) {
+            return Ok(false);
+        }
+
+        let since = self.persist.clock().now() - chrono::Duration::hours(1);
+        let cond = srql::cond_and(
+            srql::Cond(
+                srql::Expression::Binary {
+                    l: srql::field("creator_id").into(),
+                    o: srql::Operator::Equal,
+                    r: creator_id.clone().into(),
+                }
+                .into(),
+            )
+            .into(),
+            srql::Cond(
+                srql::Expression::Binary {
+                    l: srql::field("id").into(),
+                    o: srql::Operator::MoreThanOrEqual,
+                    r: srql::Thing::from((POST_TABLE_NAME.to_owned(), srql::ulid_at(since))).into(),
+                }
+                .into(),
+            )
+            .into(),
+        );
+        let recent: Option<u64> = self
+            .persist
+            .db()
+            .query(srql::count_query(POST_TABLE_NAME, cond))
+            .await?
+            .take("count")?;
+
+        if recent.unwrap_or_default() >= BOT_MAX_POSTS_PER_HOUR {
+            return Err(Error::RateLimited);
+        }
+        Ok(true)
+    }
+
+    /// Checks whether the current account is allowed to reply to the given post.
+    /// Authors can always reply to their own posts.
+    #[instrument(skip_all)]
+    pub async fn can_reply(&self, post: &Post) -> Result<bool> {
+        let current = self.current.id().ok().map(ToAccountThing::to_account_thing);
+        let Some(current) = current else {
+            return Ok(post.reply_policy == ReplyPolicy::Everyone);
+        };
+        if post.creator_id.as_ref() == Some(&current) {
+            return Ok(true);
+        }
+
+        Ok(match post.reply_policy {
+            ReplyPolicy::Everyone => true,
+            ReplyPolicy::Nobody => false,
+            ReplyPolicy::Mentioned => post.mention_ids.contains(&current),
+            ReplyPolicy::Followers => match &post.creator_id {
+                Some(author_id) => {
+                    FollowPersist::new(self.persist, self.current)
+                        .is_following(&author_id.to_gql_id())
+                        .await?
+                }
+                None => false,
+            },
+        })
+    }
+
+    /// Gets a post that the current account is allowed to quote. Posts
+    /// shared with an audience can't be quoted, as the quote would show
+    /// everyone else that they exist.
+    async fn get_quotable(&self, id: &str) -> Result<Post> {
+        let Some(quoted) = self.get(id).await? else {
+            return Err(Error::QuoteInvalid);
+        };
+        if quoted.audience_id.is_some() {
+            return Err(Error::QuoteDisallowed);
+        }
+
+        let current = self.current.id().ok().map(ToAccountThing::to_account_thing);
+        if let Some(author_id) = &quoted.creator_id {
+            if current.as_ref() != Some(author_id) {
+                let author: Option<Account> = self.persist.load(author_id.clone()).await?;
+                if author.is_some_and(|author| author.quotes_disabled) {
+                    return Err(Error::QuoteDisallowed);
+                }
+            }
+        }
+
+        Ok(quoted)
+    }
+
+    async fn notify_quoted(&self, author_id: srql::Thing, post: &Post) {
+        let res = NotificationPersist::new(self.persist, self.current)
+            .notify(CreateNotification {
+                account_id: author_id,
+                kind: NotificationKind::Quote,
+                actor_id: post.creator_id.clone(),
+                post_id: Some(post.id.clone()),
+                subject_id: post.quote_id.clone(),
+            })
+            .await;
+
+        // The post has already been created at this point, so failing to
+        // notify shouldn't fail the whole request.
+        if let Err(err) = res {
+            error!(error = ?err, "Failed to notify quoted author");
+        }
+    }
+
+    /// Tells the webhooks of each account mentioned in the post, other than
+    /// its author and anyone who muted the author in this conversation.
+    async fn notify_mentioned(&self, post: &Post) {
+        for (i, account_id) in post.mention_ids.iter().enumerate() {
+            let repeated = post.mention_ids[..i].contains(account_id);
+            if repeated || post.creator_id.as_ref() == Some(account_id) {
+                continue;
+            }
+            match is_muted_reply(self.persist, account_id, post).await {
+                Ok(false) => {}
+                Ok(true) => continue,
+                Err(err) => {
+                    error!(error = ?err, "Failed to check conversation mutes");
+                    continue;
+                }
+            }
+            if let Some(audience_id) = &post.audience_id {
+                match can_see_audience(self.persist, audience_id, Some(account_id)).await {
+                    Ok(true) => {}
+                    Ok(false) => continue,
+                    Err(err) => {
+                        error!(error = ?err, "Failed to check a post's audience");
+                        continue;
+                    }
+                }
+            }
+            send_webhooks(
+                self.persist,
+                WebhookEvent::MentionReceived,
+                account_id,
+                post.creator_id.as_ref(),
+                Some(&post.id),
+            )
+            .await;
+        }
+    }
+
+    /// Records a post made, edited or deleted as an organization in the
+    /// organization's activity log.
+    async fn log_organization_activity(&self, post: &Post, action: OrganizationAction) {
+        let Some(organization_id) = &post.organization_id else {
+            return;
+        };
+        let Ok(actor_id) = self.current.id().map(ToAccountThing::to_account_thing) else {
+            return;
+        };
+        log_activity(
+            self.persist,
+            organization_id.clone(),
+            actor_id,
+            action,
+            Some(post.id.clone()),
+        )
+        .await;
+    }
+
+    /// Checks that the current account can change a post made as an
+    /// organization. Other members' posts need the given permission, and
+    /// members can only change their own while they can still post as it.
+    /// Returns whether the post was made as an organization.
+    async fn check_organization_post(
+        &self,
+        id: &str,
+        permission: OrganizationPermission,
+    ) -> Result<bool> {
+        let post: Option<Post> = self.persist.db().select((POST_TABLE_NAME, id)).await?;
+        let Some(Post {
+            organization_id: Some(organization_id),
+            creator_id,
+            ..
+        }) = post
+        else {
+            return Ok(false);
+        };
+
+        let current = self.current.id()?.to_account_thing();
+        let permission = if creator_id.as_ref() == Some(&current) {
+            OrganizationPermission::Post
+        } else {
+            permission
+        };
+        require_permission(self.persist, &organization_id, &current, permission).await?;
+        Ok(true)
+    }
+
     #[instrument(skip_all)]
     pub async fn update(&self, id: &str, update: UpdatePost) -> Result<Option<Post>> {
         // TODO: check config to see if anon users can update posts
         // TODO: check perms to see if authd user can update posts
 
+        update.check_limits(self.persist.limits())?;
+        let as_organization = self
+            .check_organization_post(id, OrganizationPermission::EditPosts)
+            .await?;
+        self.check_update_storage(id, &update).await?;
+
         let post = if let Some(update) = update.into_update((POST_TABLE_NAME, id).into()) {
-            self.persist.db().query(update).await?.take(0)?
+            let post: Option<Post> = self.persist.db().query(update).await?.take(0)?;
+            if let Some(post) = post.as_ref().filter(|_| as_organization) {
+                self.log_organization_activity(post, OrganizationAction::PostUpdated)
+                    .await;
+            }
+            post
         } else {
             self.get(id).await?
         };
@@ -85,24 +642,110 @@ impl<'a> PostPersist<'a> {
         // TODO: check config to see if anon users can delete posts
         // TODO: check perms to see if authd user can delete posts
 
-        let post = self.persist.db().delete((POST_TABLE_NAME, id)).await?;
+        self.check_organization_post(id, OrganizationPermission::DeletePosts)
+            .await?;
+        let thing = srql::Thing::from((POST_TABLE_NAME, id));
+        let Some(existing) = self.persist.load::<Post>(thing.clone()).await? else {
+            return Ok(None);
+        };
+
+        let event = DomainEvent::create(
+            DomainEventKind::PostDeleted,
+            existing.creator_id,
+            thing.clone(),
+            [existing.board_id, existing.reply_to_id]
+                .into_iter()
+                .flatten()
+                .collect(),
+            self.persist.clock(),
+            self.persist.ids(),
+        );
+        let post: Option<Post> = self
+            .persist
+            .db()
+            .query(srql::query([
+                srql::trans_begin(),
+                // Only record the deletion if the post is still there to
+                // delete.
+                srql::Statement::Ifelse(srql::IfelseStatement {
+                    exprs: vec![(
+                        srql::Value::Subquery(Box::new(srql::Subquery::Select(
+                            srql::SelectStatement {
+                                expr: srql::Fields::all(),
+                                what: srql::thing(thing.clone()),
+                                ..Default::default()
+                            },
+                        ))),
+                        srql::Value::Subquery(Box::new(srql::Subquery::Create(event))),
+                    )],
+                    close: None,
+                }),
+                srql::Statement::Delete(srql::DeleteStatement {
+                    what: srql::thing(thing),
+                    output: srql::Output::Before.into(),
+                    ..Default::default()
+                }),
+                srql::trans_end(),
+            ]))
+            .await?
+            .take(1)?;
+        if let Some(post) = &post {
+            self.log_organization_activity(post, OrganizationAction::PostDeleted)
+                .await;
+        }
         Ok(post)
     }
 }
 
 pub struct PostListRequest<'a> {
     persist: &'a Persist,
+    viewer: Option<srql::Thing>,
     pagination: Option<PaginationInput<OpaqueCursor<String>>>,
+    creators: Option<Vec<srql::Thing>>,
+    board: Option<srql::Thing>,
+    reply_to: Option<srql::Thing>,
+    include_bots: bool,
 }
 
 impl<'a> PostListRequest<'a> {
-    fn new(persist: &'a Persist) -> Self {
+    fn new(persist: &'a Persist, viewer: Option<srql::Thing>) -> Self {
         Self {
             persist,
+            viewer,
             pagination: None,
+            creators: None,
+            board: None,
+            reply_to: None,
+            include_bots: false,
         }
     }
 
+    /// Only include posts in the given board.
+    pub fn with_board(mut self, board_id: srql::Thing) -> Self {
+        self.board = Some(board_id);
+        self
+    }
+
+    /// Only include replies to the given post.
+    pub fn with_reply_to(mut self, post_id: srql::Thing) -> Self {
+        self.reply_to = Some(post_id);
+        self
+    }
+
+    /// Only include posts created by one of the given accounts.
+    pub fn with_creators(mut self, creators: impl Into<Vec<srql::Thing>>) -> Self {
+        self.creators = Some(creators.into());
+        self
+    }
+
+    /// Whether to include posts by bots when no other filters are given.
+    /// Posts by bots are always included when listing a board, a
+    /// conversation or specific accounts.
+    pub fn with_bots(mut self, include_bots: bool) -> Self {
+        self.include_bots = include_bots;
+        self
+    }
+
     pub fn with_pagination(
         mut self,
         args: impl Into<PaginationInput<OpaqueCursor<String>>>,
@@ -113,6 +756,23 @@ impl<'a> PostListRequest<'a> {
 
     #[instrument(skip_all)]
     pub async fn execute(self) -> Result<Connection<PostCursor, Post>> {
+        // Bots are left out of the unfiltered feed, so they don't crowd out
+        // people in discovery.
+        let bot_cond = (!self.include_bots
+            && self.creators.is_none()
+            && self.board.is_none()
+            && self.reply_to.is_none())
+        .then(|| {
+            srql::Cond(
+                srql::Expression::Binary {
+                    l: srql::field("bot").into(),
+                    o: srql::Operator::NotEqual,
+                    r: true.into(),
+                }
+                .into(),
+            )
+        });
+
         let PaginationOptions {
             cond,
             order,
@@ -120,11 +780,99 @@ impl<'a> PostListRequest<'a> {
             result_slice_opts,
         } = (self.pagination, POST_TABLE_NAME).into();
 
+        let creators_cond = self.creators.map(|creators| {
+            srql::Cond(
+                srql::Expression::Binary {
+                    l: srql::field("creator_id").into(),
+                    o: srql::Operator::Inside,
+                    r: srql::array(
+                        creators
+                            .into_iter()
+                            .map(srql::Value::from)
+                            .collect::<Vec<_>>(),
+                    ),
+                }
+                .into(),
+            )
+        });
+
+        let field_cond = |field: &str, thing: srql::Thing| {
+            srql::Cond(
+                srql::Expression::Binary {
+                    l: srql::field(field).into(),
+                    o: srql::Operator::Equal,
+                    r: thing.into(),
+                }
+                .into(),
+            )
+        };
+        let board_cond = self.board.map(|board| field_cond("board_id", board));
+        // Unlisted posts are only listed as replies and to their authors, and
+        // posts for the instance only to signed in accounts.
+        let visibility_cond = listed_visibility_cond(self.viewer.as_ref(), self.reply_to.is_some());
+        let reply_to_cond = self
+            .reply_to
+            .map(|reply_to| field_cond("reply_to_id", reply_to));
+
+        // Posts in age-restricted boards are only shown to adults.
+        let restricted_cond = (!is_adult(self.persist, self.viewer.as_ref()).await?).then(|| {
+            srql::Cond(
+                srql::Expression::Binary {
+                    l: srql::path(&["board_id", "age_restricted"]).into(),
+                    o: srql::Operator::NotEqual,
+                    r: true.into(),
+                }
+                .into(),
+            )
+        });
+
+        // Posts by restricted authors are hidden depending on how they were
+        // restricted.
+        let restriction_cond =
+            restriction_visible_cond(self.viewer.as_ref(), self.persist.clock().now());
+
+        // Limited posts are only shown to their authors.
+        let not_limited = srql::Expression::Binary {
+            l: srql::field("limited").into(),
+            o: srql::Operator::NotEqual,
+            r: true.into(),
+        };
+        // Posts published to an audience are only shown to its owner and
+        // members.
+        let audience_cond = audience_visible_cond(self.viewer.as_ref());
+
+        let limited_cond = srql::Cond(match self.viewer {
+            Some(viewer) => srql::Expression::Binary {
+                l: not_limited.into(),
+                o: srql::Operator::Or,
+                r: srql::Expression::Binary {
+                    l: srql::field("creator_id").into(),
+                    o: srql::Operator::Equal,
+                    r: viewer.into(),
+                }
+                .into(),
+            }
+            .into(),
+            None => not_limited.into(),
+        });
+
         let query = srql::SelectStatement {
             expr: srql::Fields::all(),
             what: srql::table(POST_TABLE_NAME),
             order: srql::Orders(order.into_iter().collect()).into(),
-            cond,
+            cond: srql::cond_and(
+                srql::cond_and(
+                    srql::cond_and(cond, creators_cond),
+                    srql::cond_and(board_cond, reply_to_cond),
+                ),
+                srql::cond_and(
+                    srql::cond_and(
+                        srql::cond_and(limited_cond.into(), bot_cond),
+                        srql::cond_and(restricted_cond, restriction_cond.into()),
+                    ),
+                    srql::cond_and(audience_cond.into(), visibility_cond),
+                ),
+            ),
             limit,
             ..Default::default()
         };
@@ -146,6 +894,48 @@ impl<'a> PostListRequest<'a> {
     }
 }
 
+/// Whether a post is shown in lists of posts given its visibility. Unlisted
+/// posts are only listed as replies and to their authors, and posts for the
+/// instance only to signed in accounts.
+fn listed_visibility(post: &Post, viewer: Option<&srql::Thing>, replies: bool) -> bool {
+    match post.visibility {
+        PostVisibility::Public => true,
+        PostVisibility::Unlisted => {
+            replies || (viewer.is_some() && post.creator_id.as_ref() == viewer)
+        }
+        PostVisibility::Instance => viewer.is_some(),
+    }
+}
+
+/// A condition that only matches posts that are shown in lists of posts
+/// given their visibility, as [`listed_visibility`] does.
+fn listed_visibility_cond(viewer: Option<&srql::Thing>, replies: bool) -> Option<srql::Cond> {
+    let binary = |l: srql::Value, o, r: srql::Value| -> srql::Value {
+        srql::Expression::Binary { l, o, r }.into()
+    };
+    let is_not = |visibility: PostVisibility| {
+        binary(
+            srql::field("visibility").into(),
+            srql::Operator::NotEqual,
+            srql::string(visibility.to_string()).into(),
+        )
+    };
+    let unlisted = (!replies).then(|| match viewer {
+        Some(viewer) => binary(
+            is_not(PostVisibility::Unlisted),
+            srql::Operator::Or,
+            binary(
+                srql::field("creator_id").into(),
+                srql::Operator::Equal,
+                viewer.clone().into(),
+            ),
+        ),
+        None => is_not(PostVisibility::Unlisted),
+    });
+    let instance = viewer.is_none().then(|| is_not(PostVisibility::Instance));
+    srql::cond_and(unlisted.map(srql::Cond), instance.map(srql::Cond))
+}
+
 #[cfg(test)]
 pub mod testing {
     use async_trait::async_trait;
@@ -185,6 +975,18 @@ pub mod testing {
                 .unwrap()
         }
 
+        async fn generate_reply(&self, reply_to_id: &srql::Thing) -> Post {
+            let post_persist = self.post();
+            post_persist
+                .create(CreatePost {
+                    reply_to_id: Some(reply_to_id.to_gql_id()),
+                    content: Some("Test".into()),
+                    ..Default::default()
+                })
+                .await
+                .unwrap()
+        }
+
         async fn generate_posts(&self, count: usize) -> Vec<Post> {
             let post_persist = self.post();
             let mut posts = Vec::with_capacity(count);
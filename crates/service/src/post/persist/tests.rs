@@ -1,9 +1,22 @@
 use std::collections::VecDeque;
 
 use async_graphql::MaybeUndefined;
+use axum::body::Bytes;
+use chrono::{Duration, TimeZone as _, Utc};
 
 use super::{testing::PostTestData as _, *};
-use crate::{account::testing::*, board::testing::BoardTestData as _, query::testing::Paginator};
+use crate::{
+    account::{testing::*, RestrictionKind, UpdateAccount},
+    audience::testing::AudienceTestData as _,
+    board::{testing::BoardTestData as _, CreateBoard},
+    config::{AltTextPolicy, LimitsConfig, PostVisibility, PostVisibilityConfig},
+    follow::testing::FollowTestData as _,
+    license::ContentLicense,
+    media::{testing::MediaTestData as _, Media, UpdateMedia},
+    notification::{testing::NotificationTestData as _, NotificationKind},
+    provider::MockClock,
+    query::testing::Paginator,
+};
 
 #[tokio::test]
 async fn test_create_no_board() {
@@ -48,8 +61,19 @@ async fn test_create_with_board() {
 
     let post = CreatePost {
         board_id: Some(board.id.to_gql_id()),
+        quote_id: None,
+        reply_to_id: None,
+        mention_ids: None,
+        media_ids: None,
+        reply_policy: None,
+        visibility: None,
         title: Some("Test".into()),
         content: Some("Test".into()),
+        organization_id: None,
+        audience_id: None,
+        license: None,
+        attribution: None,
+        attribution_url: None,
     };
 
     let res = post_persist.create(post).await;
@@ -326,3 +350,800 @@ async fn test_delete_nonexistent() {
     println!("{res:?}");
     assert!(res.is_none());
 }
+
+#[tokio::test]
+async fn test_create_quote() {
+    let (mut data, acc) = TestData::with_user().await;
+    let original = data.generate_post().await;
+
+    let quoter = data.account().create_test_user().await;
+    data.login_as(&quoter);
+
+    let post = CreatePost {
+        quote_id: Some(original.id.to_gql_id()),
+        content: Some("Test".into()),
+        ..Default::default()
+    };
+
+    let res = data.post().create(post).await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+
+    let res = res.unwrap();
+    assert_eq!(res.quote_id, Some(original.id));
+
+    data.login_as(&acc);
+    let notifications = data
+        .notification()
+        .list()
+        .unwrap()
+        .with_pagination(PaginationInput::new().forward(10))
+        .execute()
+        .await
+        .unwrap();
+    assert_eq!(notifications.edges.len(), 1);
+
+    let notification = &notifications.edges[0].node;
+    assert_eq!(notification.kind, NotificationKind::Quote);
+    assert_eq!(notification.actor_id, Some(quoter.id));
+    assert_eq!(notification.post_id, Some(res.id));
+}
+
+#[tokio::test]
+async fn test_create_quote_own_disabled() {
+    let (data, _) = TestData::with_user().await;
+    data.account()
+        .update(UpdateAccount {
+            quotes_disabled: Some(true),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let original = data.generate_post().await;
+
+    let post = CreatePost {
+        quote_id: Some(original.id.to_gql_id()),
+        content: Some("Test".into()),
+        ..Default::default()
+    };
+
+    let res = data.post().create(post).await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+
+    let notifications = data
+        .notification()
+        .list()
+        .unwrap()
+        .with_pagination(PaginationInput::new().forward(10))
+        .execute()
+        .await
+        .unwrap();
+    assert!(notifications.edges.is_empty());
+}
+
+#[tokio::test]
+async fn test_create_quote_disallowed() {
+    let (mut data, _) = TestData::with_user().await;
+    data.account()
+        .update(UpdateAccount {
+            quotes_disabled: Some(true),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let original = data.generate_post().await;
+
+    let quoter = data.account().create_test_user().await;
+    data.login_as(&quoter);
+
+    let post = CreatePost {
+        quote_id: Some(original.id.to_gql_id()),
+        content: Some("Test".into()),
+        ..Default::default()
+    };
+
+    let res = data.post().create(post).await;
+    println!("{res:?}");
+    assert!(matches!(res, Err(Error::QuoteDisallowed)));
+}
+
+#[tokio::test]
+async fn test_create_quote_nonexistent() {
+    let data = TestData::new().await;
+
+    let post = CreatePost {
+        quote_id: Some("test".into()),
+        content: Some("Test".into()),
+        ..Default::default()
+    };
+
+    let res = data.post().create(post).await;
+    println!("{res:?}");
+    assert!(matches!(res, Err(Error::QuoteInvalid)));
+}
+
+async fn create_with_policy(data: &TestData, reply_policy: ReplyPolicy) -> Post {
+    data.post()
+        .create(CreatePost {
+            content: Some("Test".into()),
+            reply_policy: Some(reply_policy),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+}
+
+async fn reply_to(data: &TestData, post: &Post) -> Result<Post> {
+    data.post()
+        .create(CreatePost {
+            reply_to_id: Some(post.id.to_gql_id()),
+            content: Some("Test".into()),
+            ..Default::default()
+        })
+        .await
+}
+
+#[tokio::test]
+async fn test_reply() {
+    let (mut data, _) = TestData::with_user().await;
+    let post = data.generate_post().await;
+    assert_eq!(post.reply_policy, ReplyPolicy::Everyone);
+
+    let replier = data.account().create_test_user().await;
+    data.login_as(&replier);
+
+    let res = reply_to(&data, &post).await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+
+    let res = res.unwrap();
+    assert_eq!(res.reply_to_id, Some(post.id));
+}
+
+#[tokio::test]
+async fn test_reply_nonexistent() {
+    let data = TestData::new().await;
+
+    let res = data
+        .post()
+        .create(CreatePost {
+            reply_to_id: Some("test".into()),
+            content: Some("Test".into()),
+            ..Default::default()
+        })
+        .await;
+    println!("{res:?}");
+    assert!(matches!(res, Err(Error::ReplyInvalid)));
+}
+
+#[tokio::test]
+async fn test_reply_nobody() {
+    let (mut data, acc) = TestData::with_user().await;
+    let post = create_with_policy(&data, ReplyPolicy::Nobody).await;
+
+    let replier = data.account().create_test_user().await;
+    data.login_as(&replier);
+    assert!(!data.post().can_reply(&post).await.unwrap());
+
+    let res = reply_to(&data, &post).await;
+    println!("{res:?}");
+    assert!(matches!(res, Err(Error::ReplyDisallowed)));
+
+    // Authors can always reply to their own posts.
+    data.login_as(&acc);
+    let res = reply_to(&data, &post).await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+}
+
+#[tokio::test]
+async fn test_reply_followers() {
+    let (mut data, acc) = TestData::with_user().await;
+    let post = create_with_policy(&data, ReplyPolicy::Followers).await;
+
+    let replier = data.account().create_test_user().await;
+    data.login_as(&replier);
+
+    let res = reply_to(&data, &post).await;
+    println!("{res:?}");
+    assert!(matches!(res, Err(Error::ReplyDisallowed)));
+
+    data.follow().follow(&acc.id.id.to_raw()).await.unwrap();
+    let res = reply_to(&data, &post).await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+}
+
+#[tokio::test]
+async fn test_reply_mentioned() {
+    let (mut data, _) = TestData::with_user().await;
+    let mentioned = data.account().create_test_user().await;
+    let other = data.account().create_test_user().await;
+
+    let post = data
+        .post()
+        .create(CreatePost {
+            content: Some("Test".into()),
+            mention_ids: Some(vec![mentioned.id.to_gql_id()]),
+            reply_policy: Some(ReplyPolicy::Mentioned),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(post.mention_ids, vec![mentioned.id.clone()]);
+
+    data.login_as(&other);
+    let res = reply_to(&data, &post).await;
+    println!("{res:?}");
+    assert!(matches!(res, Err(Error::ReplyDisallowed)));
+
+    data.login_as(&mentioned);
+    let res = reply_to(&data, &post).await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+}
+
+#[tokio::test]
+async fn test_reply_anonymous() {
+    let (mut data, _) = TestData::with_user().await;
+    let everyone = data.generate_post().await;
+    let followers = create_with_policy(&data, ReplyPolicy::Followers).await;
+
+    data.current = CurrentAccount::default();
+    assert!(data.post().can_reply(&everyone).await.unwrap());
+    assert!(!data.post().can_reply(&followers).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_update_reply_policy() {
+    let (data, _) = TestData::with_user().await;
+    let post = data.generate_post().await;
+
+    let res = data
+        .post()
+        .update(
+            &post.id.to_gql_id(),
+            UpdatePost {
+                reply_policy: Some(ReplyPolicy::Nobody),
+                ..Default::default()
+            },
+        )
+        .await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+
+    let res = res.unwrap().unwrap();
+    assert_eq!(res.reply_policy, ReplyPolicy::Nobody);
+}
+
+#[tokio::test]
+async fn test_license() {
+    let (data, _) = TestData::with_user().await;
+    let post = data.generate_post().await;
+    assert_eq!(post.license, ContentLicense::AllRightsReserved);
+
+    data.account()
+        .update(UpdateAccount {
+            default_license: Some(ContentLicense::CcBy),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let post = data
+        .post()
+        .create(CreatePost {
+            content: Some("Test".into()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(post.license, ContentLicense::CcBy);
+
+    let post = data
+        .post()
+        .create(CreatePost {
+            content: Some("Test".into()),
+            license: Some(ContentLicense::Cc0),
+            attribution: Some("Someone Else".into()),
+            attribution_url: Some("https://example.com/original".into()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(post.license, ContentLicense::Cc0);
+    assert_eq!(post.attribution.as_deref(), Some("Someone Else"));
+
+    let res = data
+        .post()
+        .create(CreatePost {
+            attribution_url: Some("javascript:alert(1)".into()),
+            ..Default::default()
+        })
+        .await;
+    assert!(matches!(res, Err(Error::InputInvalid(_))), "{res:?}");
+
+    let post = data
+        .post()
+        .update(
+            &post.id.to_gql_id(),
+            UpdatePost {
+                license: Some(ContentLicense::CcBySa),
+                attribution_url: MaybeUndefined::Null,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(post.license, ContentLicense::CcBySa);
+    assert_eq!(post.attribution.as_deref(), Some("Someone Else"));
+    assert_eq!(post.attribution_url, None);
+}
+
+#[tokio::test]
+async fn test_list_scoped() {
+    let data = TestData::new().await;
+    let board = data.generate_board().await;
+    let in_board = data.generate_post_in(&board.id).await;
+    let parent = data.generate_post().await;
+    let reply = data.generate_reply(&parent.id).await;
+
+    let res = data
+        .post()
+        .list()
+        .with_board(board.id)
+        .with_pagination(PaginationInput::new().forward(10))
+        .execute()
+        .await
+        .unwrap();
+    let res: Vec<_> = res.edges.into_iter().map(|e| e.node.id).collect();
+    assert_eq!(res, vec![in_board.id]);
+
+    let res = data
+        .post()
+        .list()
+        .with_reply_to(parent.id)
+        .with_pagination(PaginationInput::new().forward(10))
+        .execute()
+        .await
+        .unwrap();
+    let res: Vec<_> = res.edges.into_iter().map(|e| e.node.id).collect();
+    assert_eq!(res, vec![reply.id]);
+}
+
+#[tokio::test]
+async fn test_list_hides_bots() {
+    let (mut data, owner) = TestData::with_user().await;
+    let board = data.generate_board().await;
+    let by_person = data.generate_post().await;
+    let bot = data.account().create_test_bot().await;
+    data.login_as(&bot);
+    let by_bot = data.generate_post().await;
+    let in_board = data.generate_post_in(&board.id).await;
+    assert!(by_bot.bot);
+    data.login_as(&owner);
+
+    let list = |include_bots| {
+        data.post()
+            .list()
+            .with_bots(include_bots)
+            .with_pagination(PaginationInput::new().forward(10))
+    };
+    let ids = |res: Connection<PostCursor, Post>| -> Vec<_> {
+        res.edges.into_iter().map(|e| e.node.id).collect()
+    };
+
+    let res = list(false).execute().await.unwrap();
+    assert_eq!(ids(res), vec![by_person.id.clone()]);
+
+    let res = list(true).execute().await.unwrap();
+    assert_eq!(
+        ids(res),
+        vec![in_board.id.clone(), by_bot.id.clone(), by_person.id]
+    );
+
+    // Filtered lists always include bots.
+    let res = list(false).with_board(board.id).execute().await.unwrap();
+    assert_eq!(ids(res), vec![in_board.id]);
+    let res = list(false)
+        .with_creators(vec![bot.id])
+        .execute()
+        .await
+        .unwrap();
+    assert_eq!(ids(res).len(), 2);
+}
+
+#[tokio::test]
+async fn test_restricted_author() {
+    async fn visible_to(data: &mut TestData, post: &Post, viewer: Option<&AccData>) -> bool {
+        match viewer {
+            Some(viewer) => data.login_as(viewer),
+            None => data.current = CurrentAccount::default(),
+        }
+        let listed = data
+            .post()
+            .list()
+            .with_pagination(PaginationInput::new().forward(10))
+            .execute()
+            .await
+            .unwrap()
+            .edges
+            .iter()
+            .any(|e| e.node.id == post.id);
+        let got = data.post().get(&post.id.to_gql_id()).await.unwrap();
+        assert_eq!(listed, got.is_some());
+        listed
+    }
+    async fn restrict(
+        data: &mut TestData,
+        admin: &AccData,
+        author: &AccData,
+        kind: RestrictionKind,
+    ) {
+        data.login_as(admin);
+        data.account()
+            .restrict(&author.id.to_gql_id(), kind, 1, "Rule breaking".into())
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap());
+    let mut data = TestData::with_clock(clock.clone()).await;
+    let admin = data.account().create_test_user().await;
+    let author = data.account().create_test_user().await;
+    let follower = data.account().create_test_user().await;
+    let stranger = data.account().create_test_user().await;
+    data.login_as(&author);
+    let post = data.generate_post().await;
+    data.login_as(&follower);
+    assert!(data.follow().follow(&author.id.id.to_raw()).await.unwrap());
+
+    restrict(&mut data, &admin, &author, RestrictionKind::Silenced).await;
+    assert!(visible_to(&mut data, &post, Some(&author)).await);
+    assert!(visible_to(&mut data, &post, Some(&follower)).await);
+    assert!(!visible_to(&mut data, &post, Some(&stranger)).await);
+    assert!(!visible_to(&mut data, &post, None).await);
+
+    restrict(&mut data, &admin, &author, RestrictionKind::ShadowLimited).await;
+    assert!(visible_to(&mut data, &post, Some(&author)).await);
+    assert!(!visible_to(&mut data, &post, Some(&follower)).await);
+
+    restrict(&mut data, &admin, &author, RestrictionKind::Suspended).await;
+    assert!(!visible_to(&mut data, &post, Some(&follower)).await);
+    assert!(!visible_to(&mut data, &post, None).await);
+
+    // Restrictions stop applying as soon as they expire.
+    clock.advance(Duration::hours(1));
+    assert!(visible_to(&mut data, &post, Some(&stranger)).await);
+    assert!(visible_to(&mut data, &post, None).await);
+}
+
+#[tokio::test]
+async fn test_bot_post_limit() {
+    let (mut data, _) = TestData::with_user().await;
+    let bot = data.account().create_test_bot().await;
+    data.login_as(&bot);
+
+    for _ in 0..BOT_MAX_POSTS_PER_HOUR {
+        data.generate_post().await;
+    }
+    let res = data.post().create(CreatePost::default()).await;
+    assert_eq!(res.unwrap_err(), Error::RateLimited);
+}
+
+#[tokio::test]
+async fn test_age_restricted_board() {
+    let (mut data, adult) = TestData::with_user().await;
+    let board = data
+        .board()
+        .create(CreateBoard {
+            handle: Some("restricted".into()),
+            age_restricted: Some(true),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let post = data.generate_post_in(&board.id).await;
+    let public = data.generate_post().await;
+
+    let minor = data.account().create_test_user_born(None).await;
+    data.login_as(&minor);
+    assert_eq!(data.post().get(&post.id.to_gql_id()).await.unwrap(), None);
+    let res = data
+        .post()
+        .list()
+        .with_pagination(PaginationInput::new().forward(10))
+        .execute()
+        .await
+        .unwrap();
+    let ids: Vec<_> = res.edges.into_iter().map(|e| e.node.id).collect();
+    assert_eq!(ids, vec![public.id.clone()]);
+
+    let res = data
+        .post()
+        .create(CreatePost {
+            board_id: Some(board.id.to_gql_id()),
+            content: Some("Test".into()),
+            ..Default::default()
+        })
+        .await;
+    assert_eq!(res.unwrap_err(), Error::AgeRestricted);
+
+    data.login_as(&adult);
+    assert_eq!(
+        data.post().get(&post.id.to_gql_id()).await.unwrap(),
+        Some(post)
+    );
+}
+
+#[tokio::test]
+async fn test_content_limits() {
+    let (mut data, _) = TestData::with_user().await;
+    data.persist = data.persist.with_limits(LimitsConfig {
+        post_title: 4,
+        post_content: 8,
+        ..Default::default()
+    });
+
+    let res = data
+        .post()
+        .create(CreatePost {
+            title: Some("Hello".into()),
+            ..Default::default()
+        })
+        .await;
+    assert_eq!(res.unwrap_err(), Error::TooLong("title".into()));
+
+    // Limits count characters, not bytes.
+    let post = data
+        .post()
+        .create(CreatePost {
+            title: Some("Test".into()),
+            content: Some("ééééééé".into()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    let res = data
+        .post()
+        .update(
+            &post.id.to_gql_id(),
+            UpdatePost {
+                content: MaybeUndefined::Value("Too long!".into()),
+                ..Default::default()
+            },
+        )
+        .await;
+    assert_eq!(res.unwrap_err(), Error::TooLong("content".into()));
+}
+
+async fn upload_media(data: &TestData, content_type: &str, alt_text: Option<&str>) -> Media {
+    let media = data
+        .media()
+        .upload(content_type, Bytes::from_static(b"not really media"))
+        .await
+        .unwrap();
+    let Some(alt_text) = alt_text else {
+        return media;
+    };
+    data.media()
+        .update(
+            &media.id.to_gql_id(),
+            UpdateMedia {
+                alt_text: MaybeUndefined::Value(alt_text.into()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap()
+        .unwrap()
+}
+
+async fn create_with_media(data: &TestData, media: &[&Media]) -> Result<Post> {
+    data.post()
+        .create(CreatePost {
+            title: Some("Test".into()),
+            media_ids: Some(media.iter().map(|media| media.id.to_gql_id()).collect()),
+            ..Default::default()
+        })
+        .await
+}
+
+#[tokio::test]
+async fn test_create_with_media() {
+    let (mut data, _) = TestData::with_user().await;
+    let image = upload_media(&data, "image/png", Some("A cat")).await;
+    let video = upload_media(&data, "video/mp4", None).await;
+
+    let post = create_with_media(&data, &[&video, &image]).await.unwrap();
+    assert_eq!(post.media_ids, vec![video.id.clone(), image.id.clone()]);
+    let attached = data.media().get_many(&post.media_ids).await.unwrap();
+    assert_eq!(attached, vec![video.clone(), image.clone()]);
+
+    let res = create_with_media(&data, &[&image, &image]).await;
+    assert!(matches!(res, Err(Error::InputInvalid(_))), "{res:?}");
+
+    let acc = data.account().create_test_user().await;
+    data.login_as(&acc);
+    let res = create_with_media(&data, &[&image]).await;
+    assert!(matches!(res, Err(Error::InputInvalid(_))), "{res:?}");
+}
+
+#[tokio::test]
+async fn test_alt_text_policy() {
+    let (mut data, _) = TestData::with_user().await;
+    let described = upload_media(&data, "image/png", Some("A cat")).await;
+    let blank = upload_media(&data, "image/jpeg", Some("  ")).await;
+    let undescribed = upload_media(&data, "image/gif", None).await;
+    let audio = upload_media(&data, "audio/ogg", None).await;
+
+    // Images can be posted without alt text unless it's required.
+    for policy in [AltTextPolicy::Off, AltTextPolicy::Nag] {
+        data.persist = data.persist.with_alt_text_policy(policy);
+        create_with_media(&data, &[&undescribed]).await.unwrap();
+    }
+
+    data.persist = data.persist.with_alt_text_policy(AltTextPolicy::Require);
+    create_with_media(&data, &[&described, &audio])
+        .await
+        .unwrap();
+    let res = create_with_media(&data, &[&described, &audio, &undescribed]).await;
+    assert_eq!(res.unwrap_err(), Error::AltTextMissing("mediaIds.2".into()));
+    let res = create_with_media(&data, &[&blank]).await;
+    assert_eq!(res.unwrap_err(), Error::AltTextMissing("mediaIds.0".into()));
+}
+
+async fn create_with_visibility(
+    data: &TestData,
+    visibility: Option<PostVisibility>,
+    reply_to: Option<&Post>,
+) -> Result<Post> {
+    data.post()
+        .create(CreatePost {
+            title: Some("Test".into()),
+            visibility,
+            reply_to_id: reply_to.map(|post| post.id.to_gql_id()),
+            ..Default::default()
+        })
+        .await
+}
+
+#[tokio::test]
+async fn test_visibility_policy() {
+    let (mut data, _) = TestData::with_user().await;
+    let public = create_with_visibility(&data, None, None).await.unwrap();
+    assert_eq!(public.visibility, PostVisibility::Public);
+
+    data.persist = data.persist.with_post_visibility(PostVisibilityConfig {
+        default: PostVisibility::Instance,
+        max: PostVisibility::Unlisted,
+    });
+    let post = create_with_visibility(&data, None, None).await.unwrap();
+    assert_eq!(post.visibility, PostVisibility::Instance);
+    let post = create_with_visibility(&data, Some(PostVisibility::Unlisted), None)
+        .await
+        .unwrap();
+    assert_eq!(post.visibility, PostVisibility::Unlisted);
+    let res = create_with_visibility(&data, Some(PostVisibility::Public), None).await;
+    assert_eq!(res.unwrap_err(), Error::VisibilityDisallowed);
+
+    // Replies are never more visible than what they reply to, even if it
+    // was posted before the instance allowed it.
+    let reply = create_with_visibility(&data, Some(PostVisibility::Unlisted), Some(&public))
+        .await
+        .unwrap();
+    assert_eq!(reply.visibility, PostVisibility::Unlisted);
+    let instance = create_with_visibility(&data, None, None).await.unwrap();
+    let reply = create_with_visibility(&data, Some(PostVisibility::Unlisted), Some(&instance))
+        .await
+        .unwrap();
+    assert_eq!(reply.visibility, PostVisibility::Instance);
+}
+
+#[tokio::test]
+async fn test_create_quote_restricted() {
+    let (mut data, _) = TestData::with_user().await;
+    let member = data.account().create_test_user().await;
+    let audience = data.generate_audience().await;
+    data.audience()
+        .add_member(&audience.id.id.to_raw(), &member.id.id.to_raw())
+        .await
+        .unwrap()
+        .unwrap();
+    let shared = data
+        .post()
+        .create(CreatePost {
+            content: Some("Close friends only".into()),
+            audience_id: Some(audience.id.to_gql_id()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let instance = create_with_visibility(&data, Some(PostVisibility::Instance), None)
+        .await
+        .unwrap();
+    let quote = |quoted: &Post, visibility| CreatePost {
+        quote_id: Some(quoted.id.to_gql_id()),
+        content: Some("Test".into()),
+        visibility,
+        ..Default::default()
+    };
+
+    // Members can see posts shared with them, but can't quote them.
+    data.login_as(&member);
+    assert!(can_get(&data, &shared).await);
+    let res = data.post().create(quote(&shared, None)).await;
+    assert_eq!(res.unwrap_err(), Error::QuoteDisallowed);
+
+    // Posts only this instance can see can only be quoted by posts like them.
+    let res = data.post().create(quote(&instance, None)).await;
+    assert_eq!(res.unwrap_err(), Error::QuoteDisallowed);
+    let res = data
+        .post()
+        .create(quote(&instance, Some(PostVisibility::Instance)))
+        .await;
+    assert_eq!(res.unwrap().visibility, PostVisibility::Instance);
+}
+
+fn raw_ids<'a>(posts: impl IntoIterator<Item = &'a Post>) -> Vec<String> {
+    let mut ids: Vec<_> = posts.into_iter().map(|post| post.id.id.to_raw()).collect();
+    ids.sort();
+    ids
+}
+
+async fn can_get(data: &TestData, post: &Post) -> bool {
+    data.post()
+        .get(&post.id.id.to_raw())
+        .await
+        .unwrap()
+        .is_some()
+}
+
+async fn listed(data: &TestData, reply_to: Option<&Post>) -> Vec<String> {
+    let mut list = data
+        .post()
+        .list()
+        .with_pagination(PaginationInput::new().forward(10));
+    if let Some(post) = reply_to {
+        list = list.with_reply_to(post.id.clone());
+    }
+    let posts = list.execute().await.unwrap();
+    raw_ids(posts.edges.iter().map(|edge| &edge.node))
+}
+
+#[tokio::test]
+async fn test_visibility_reads() {
+    let (mut data, author) = TestData::with_user().await;
+    let public = create_with_visibility(&data, None, None).await.unwrap();
+    let unlisted = create_with_visibility(&data, Some(PostVisibility::Unlisted), None)
+        .await
+        .unwrap();
+    let instance = create_with_visibility(&data, Some(PostVisibility::Instance), None)
+        .await
+        .unwrap();
+    let reply = create_with_visibility(&data, Some(PostVisibility::Unlisted), Some(&public))
+        .await
+        .unwrap();
+    // Authors see their unlisted posts listed.
+    let every = raw_ids([&public, &unlisted, &instance, &reply]);
+    assert_eq!(listed(&data, None).await, every);
+
+    let other = data.account().create_test_user().await;
+    data.login_as(&other);
+    assert!(can_get(&data, &unlisted).await);
+    assert!(can_get(&data, &instance).await);
+    assert_eq!(listed(&data, None).await, raw_ids([&public, &instance]));
+    assert_eq!(listed(&data, Some(&public)).await, raw_ids([&reply]));
+
+    data.current = CurrentAccount::default();
+    assert!(can_get(&data, &unlisted).await);
+    assert!(!can_get(&data, &instance).await);
+    assert_eq!(listed(&data, None).await, raw_ids([&public]));
+    assert_eq!(listed(&data, Some(&public)).await, raw_ids([&reply]));
+
+    data.login_as(&author);
+    assert!(can_get(&data, &instance).await);
+}
@@ -1,8 +1,15 @@
-use async_graphql::{connection::Connection, Context, Object, ID};
+use async_graphql::{connection::Connection, Context, Object, Subscription, ID};
+use futures::Stream;
 use tracing::instrument;
 
 use super::{CreatePost, Post, PostCursor, UpdatePost};
-use crate::{prelude::*, query::PaginationArgs};
+use crate::{
+    account::EmailVerified,
+    policy::PoliciesAccepted,
+    prelude::*,
+    query::{page_complexity, PaginationArgs},
+    read_marker::ReadTarget,
+};
 
 #[derive(Default)]
 pub struct PostQuery;
@@ -16,7 +23,16 @@ impl PostQuery {
     }
 
     /// Lists posts.
+    ///
+    /// Posts can be limited to a single board, or to the replies to a single
+    /// post. When one of these is given and `markRead` is set, the current
+    /// account's read marker is moved to the newest post that was fetched.
+    ///
+    /// When neither is given, posts by bots are left out unless
+    /// `includeBots` is set.
+    #[graphql(complexity = "page_complexity(first, last, child_complexity)")]
     #[instrument(skip_all)]
+    #[allow(clippy::too_many_arguments)]
     async fn posts(
         &self,
         ctx: &Context<'_>,
@@ -24,9 +40,15 @@ impl PostQuery {
         before: Option<String>,
         first: Option<i32>,
         last: Option<i32>,
+        board_id: Option<ID>,
+        reply_to_id: Option<ID>,
+        #[graphql(default)] mark_read: bool,
+        #[graphql(default)] include_bots: bool,
     ) -> GqlResult<Connection<PostCursor, Post>> {
-        ctx.post_persist()
+        let mut list = ctx
+            .post_persist()
             .list()
+            .with_bots(include_bots)
             .with_pagination(
                 PaginationArgs {
                     after,
@@ -34,24 +56,47 @@ impl PostQuery {
                     first,
                     last,
                 }
+                .warn_if_clamped(ctx)
                 .validate()
                 .extend()?,
-            )
-            .execute()
-            .await
-            .extend()
+            );
+        let mut read_target = None;
+        if let Some(board_id) = board_id {
+            list = list.with_board(ReadTarget::Board.thing(&board_id));
+            read_target = Some((ReadTarget::Board, board_id));
+        }
+        if let Some(reply_to_id) = reply_to_id {
+            list = list.with_reply_to(ReadTarget::Conversation.thing(&reply_to_id));
+            read_target = Some((ReadTarget::Conversation, reply_to_id));
+        }
+
+        let posts = list.execute().await.extend()?;
+
+        if let (true, Some((target, id))) = (mark_read, read_target) {
+            let newest = posts.edges.iter().map(|edge| &edge.node.id).max();
+            if let Some(newest) = newest {
+                ctx.read_marker_persist()
+                    .mark_read(target, &id, Some(&newest.to_gql_id()))
+                    .await
+                    .extend()?;
+            }
+        }
+
+        Ok(posts)
     }
 }
 
 #[derive(Default)]
 pub struct PostMutation;
 
-#[Object]
+#[Object(guard = "PoliciesAccepted")]
 impl PostMutation {
     /// Creates a new post.
+    #[graphql(guard = "PoliciesAccepted.and(EmailVerified)")]
     #[instrument(skip_all)]
     async fn create_post(&self, ctx: &Context<'_>, create: CreatePost) -> GqlResult<Post> {
-        ctx.post_persist().create(create).await.extend()
+        let post = ctx.post_persist().create(create).await.extend()?;
+        ctx.spam_persist().check_post(post).await.extend()
     }
 
     /// Updates a post.
@@ -71,3 +116,29 @@ impl PostMutation {
         ctx.post_persist().delete(&id).await.extend()
     }
 }
+
+#[derive(Default)]
+pub struct PostSubscription;
+
+#[Subscription]
+impl PostSubscription {
+    /// Sends posts as they're created.
+    ///
+    /// Posts can be limited to a single board, or to the replies to a single
+    /// post. When neither is given, posts by bots are left out unless
+    /// `includeBots` is set.
+    #[allow(clippy::unused_async)]
+    async fn post_created(
+        &self,
+        ctx: &Context<'_>,
+        board_id: Option<ID>,
+        reply_to_id: Option<ID>,
+        #[graphql(default)] include_bots: bool,
+    ) -> impl Stream<Item = Post> {
+        ctx.post_persist().subscribe(
+            board_id.map(|id| ReadTarget::Board.thing(&id)),
+            reply_to_id.map(|id| ReadTarget::Conversation.thing(&id)),
+            include_bots,
+        )
+    }
+}
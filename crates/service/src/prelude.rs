@@ -8,5 +8,7 @@ pub use crate::{
     conv::{AsMaybeStr, IntoGqlId, ToGqlId},
     error::{Error, GqlError, GqlResult, Result, SrlDbError, SrlError},
     persist::PersistExt as _,
+    provider::{Clock, IdGen},
     query::{srql, CreateObject, IntoUpdateQuery, QueryValue},
+    warning::WarnExt as _,
 };
@@ -0,0 +1,211 @@
+//! Sources of the current time and of new IDs.
+//!
+//! Everything that depends on either should get them from the [`Persist`]
+//! rather than calling `Utc::now()` or generating ULIDs directly, so that
+//! tests can swap in the mock implementations and control them exactly.
+//!
+//! [`Persist`]: crate::persist::Persist
+
+use std::{
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
+};
+
+use chrono::{DateTime, Duration, Utc};
+use ulid::{Generator, Ulid};
+
+pub type SharedClock = Arc<dyn Clock>;
+pub type SharedIdGen = Arc<dyn IdGen>;
+
+/// A source of the current time.
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The system's clock.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock that only moves when told to.
+///
+/// Clones share the same time, so a test can keep one and hand the other to
+/// the service.
+#[derive(Debug, Clone)]
+pub struct MockClock(Arc<Mutex<DateTime<Utc>>>);
+
+impl MockClock {
+    #[must_use]
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self(Arc::new(Mutex::new(now)))
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.time() = now;
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.time() += duration;
+    }
+
+    fn time(&self) -> MutexGuard<'_, DateTime<Utc>> {
+        // The time is always valid, even if another thread panicked while
+        // holding the lock.
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Default for MockClock {
+    /// Starts the clock at the current system time.
+    fn default() -> Self {
+        Self::new(Utc::now())
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.time()
+    }
+}
+
+/// A source of new record IDs.
+///
+/// IDs must sort in the order they were generated, as pagination relies on
+/// this to list records by when they were created.
+pub trait IdGen: Debug + Send + Sync {
+    fn next_id(&self) -> String;
+}
+
+/// Generates lowercase ULIDs using the given clock.
+///
+/// IDs generated within the same millisecond are still increasing, unlike
+/// independently generated ULIDs.
+pub struct UlidGen {
+    clock: SharedClock,
+    gen: Mutex<Generator>,
+}
+
+impl UlidGen {
+    #[must_use]
+    pub fn new(clock: SharedClock) -> Self {
+        Self {
+            clock,
+            gen: Mutex::new(Generator::new()),
+        }
+    }
+}
+
+impl Debug for UlidGen {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UlidGen")
+            .field("clock", &self.clock)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for UlidGen {
+    fn default() -> Self {
+        Self::new(Arc::new(SystemClock))
+    }
+}
+
+impl IdGen for UlidGen {
+    fn next_id(&self) -> String {
+        let now = self.clock.now().into();
+        // This only fails if more than 2^80 IDs are generated in the same
+        // millisecond, at which point giving up on ordering is fine.
+        let id = self
+            .gen
+            .lock()
+            .unwrap()
+            .generate_from_datetime(now)
+            .unwrap_or_else(|_| Ulid::from_datetime(now));
+        id.to_string().to_ascii_lowercase()
+    }
+}
+
+/// Generates ULIDs with a fixed timestamp and an increasing counter, so that
+/// the same IDs are generated every run.
+#[derive(Debug)]
+pub struct MockIdGen {
+    ms: u64,
+    next: AtomicU64,
+}
+
+impl MockIdGen {
+    /// Creates a generator whose IDs appear to have been created at the given
+    /// time.
+    #[must_use]
+    pub fn new(at: DateTime<Utc>) -> Self {
+        Self {
+            ms: u64::try_from(at.timestamp_millis()).unwrap_or_default(),
+            next: AtomicU64::new(1),
+        }
+    }
+}
+
+impl IdGen for MockIdGen {
+    fn next_id(&self) -> String {
+        let next = self.next.fetch_add(1, Ordering::Relaxed);
+        Ulid::from_parts(self.ms, next.into())
+            .to_string()
+            .to_ascii_lowercase()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone as _;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_mock_clock() {
+        let start = Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
+        let clock = MockClock::new(start);
+        let shared = clock.clone();
+        assert_eq!(shared.now(), start);
+
+        clock.advance(Duration::minutes(5));
+        assert_eq!(shared.now(), start + Duration::minutes(5));
+
+        clock.set(start);
+        assert_eq!(shared.now(), start);
+    }
+
+    #[test]
+    fn test_ulid_gen_monotonic() {
+        let start = Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
+        let gen = UlidGen::new(Arc::new(MockClock::new(start)));
+
+        let ids: Vec<_> = (0..100).map(|_| gen.next_id()).collect();
+        let mut sorted = ids.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(ids, sorted);
+
+        let id = Ulid::from_string(&ids[0]).unwrap();
+        assert_eq!(DateTime::<Utc>::from(id.datetime()), start);
+    }
+
+    #[test]
+    fn test_mock_id_gen() {
+        let start = Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
+        let a = MockIdGen::new(start);
+        let b = MockIdGen::new(start);
+
+        let a: Vec<_> = (0..3).map(|_| a.next_id()).collect();
+        let b: Vec<_> = (0..3).map(|_| b.next_id()).collect();
+        assert_eq!(a, b);
+        assert!(a[0] < a[1] && a[1] < a[2]);
+    }
+}
@@ -0,0 +1,19 @@
+//! Sending outbound links and remote media through the instance, so that
+//! remote servers never see the addresses of the accounts reading them.
+//!
+//! Links go through a redirect that tells the browser not to send a
+//! `Referer`, so the remote server can't tell which page they were followed
+//! from. Remote media is fetched by the instance itself and kept in a cache,
+//! so that it's only fetched once however many accounts see it.
+//!
+//! Both are reached through URLs signed by the instance, so that it can't be
+//! used to send anyone anywhere, or to fetch anything for anyone. Proxying is
+//! off unless the instance turns it on.
+
+mod remote;
+mod schema;
+mod url;
+
+pub use remote::*;
+pub use schema::*;
+pub use url::*;
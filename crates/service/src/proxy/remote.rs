@@ -0,0 +1,419 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+
+use axum::body::Bytes;
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use hyper::{
+    body::HttpBody as _,
+    header::{ACCEPT, CONTENT_LENGTH, CONTENT_TYPE, USER_AGENT},
+    Body, Request, Uri,
+};
+
+use crate::{
+    config::ProxyConfig,
+    http::{public_http_client, PublicHttpClient},
+    prelude::*,
+    provider::SharedClock,
+};
+
+/// How long to wait for a remote server before giving up.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Remote servers see this rather than anything about the account that's
+/// looking at their media.
+static PROXY_USER_AGENT: &str = "Plazer media proxy";
+
+/// Remote media that's been fetched.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteMediaContent {
+    pub content_type: String,
+    pub bytes: Bytes,
+}
+
+/// Fetches media from remote servers on behalf of accounts, keeping it in a
+/// [`RemoteMediaCache`] so that each is only fetched once for everyone.
+///
+/// Only images, video and audio are fetched, so that the instance doesn't
+/// serve remote pages as if they were its own. Hosts that resolve to private
+/// or local addresses are refused, so that the proxy can't be pointed at the
+/// instance's own network, both when they're checked and when they're
+/// connected to.
+#[derive(Clone)]
+pub struct RemoteMedia {
+    client: PublicHttpClient,
+    cache: RemoteMediaCache,
+    max_bytes: u64,
+}
+
+impl RemoteMedia {
+    pub fn new(config: &ProxyConfig, clock: SharedClock) -> Self {
+        Self {
+            client: public_http_client(),
+            cache: RemoteMediaCache::new(config, clock),
+            max_bytes: config.max_media_bytes,
+        }
+    }
+
+    /// How long fetched media is kept for, which is also how long browsers
+    /// can keep it.
+    pub fn cache_ttl(&self) -> Duration {
+        self.cache.ttl.to_std().unwrap_or_default()
+    }
+
+    /// Gets remote media, from the cache if it's been fetched recently.
+    pub async fn get(&self, uri: &Uri) -> Result<RemoteMediaContent> {
+        let url = uri.to_string();
+        if let Some(content) = self.cache.get(&url) {
+            return Ok(content);
+        }
+
+        let content = tokio::time::timeout(FETCH_TIMEOUT, self.fetch(uri))
+            .await
+            .map_err(|_| Error::RemoteContentUnavailable)??;
+        self.cache.insert(&url, content.clone());
+        Ok(content)
+    }
+
+    async fn fetch(&self, uri: &Uri) -> Result<RemoteMediaContent> {
+        // The client only connects to public addresses too, but checking
+        // first gives a clearer error for hosts that are never public.
+        check_host(uri).await?;
+        // Nothing from the account's own request is passed on, so the remote
+        // server only ever sees the instance.
+        let req = Request::get(uri.clone())
+            .header(USER_AGENT, PROXY_USER_AGENT)
+            .header(ACCEPT, "image/*, video/*, audio/*")
+            .body(Body::empty())
+            .map_err(Error::from_err)?;
+        let res = self
+            .client
+            .request(req)
+            .await
+            .map_err(|_| Error::RemoteContentUnavailable)?;
+        // Redirects aren't followed, as where they lead hasn't been checked.
+        if !res.status().is_success() {
+            return Err(Error::RemoteContentUnavailable);
+        }
+
+        let content_type = res
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.trim().to_ascii_lowercase())
+            .filter(|value| is_media_type(value))
+            .ok_or(Error::RemoteContentUnavailable)?;
+        let too_long = res
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok()?.parse::<u64>().ok())
+            .is_some_and(|len| len > self.max_bytes);
+        if too_long {
+            return Err(Error::TooLong("remote media".into()));
+        }
+
+        // The length can't be trusted, so the body is only read up to the
+        // limit.
+        let mut body = res.into_body();
+        let mut bytes = Vec::new();
+        while let Some(chunk) = body.data().await {
+            let chunk = chunk.map_err(|_| Error::RemoteContentUnavailable)?;
+            if (bytes.len() + chunk.len()) as u64 > self.max_bytes {
+                return Err(Error::TooLong("remote media".into()));
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+
+        Ok(RemoteMediaContent {
+            content_type,
+            bytes: bytes.into(),
+        })
+    }
+}
+
+fn is_media_type(content_type: &str) -> bool {
+    ["image/", "video/", "audio/"]
+        .iter()
+        .any(|prefix| content_type.starts_with(prefix))
+        // SVGs can carry scripts.
+        && !content_type.starts_with("image/svg")
+}
+
+/// Refuses hosts that resolve to anything but public addresses.
+pub async fn check_host(uri: &Uri) -> Result<()> {
+    let host = uri
+        .host()
+        .ok_or_else(|| Error::InputInvalid("url must have a host".into()))?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let port = uri
+        .port_u16()
+        .unwrap_or(if uri.scheme_str() == Some("https") {
+            443
+        } else {
+            80
+        });
+
+    let mut addrs = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|_| Error::RemoteContentUnavailable)?
+        .peekable();
+    if addrs.peek().is_none() {
+        return Err(Error::RemoteContentUnavailable);
+    }
+    if addrs.any(|addr| !is_public(addr.ip())) {
+        return Err(Error::InputInvalid("url must be on a public host".into()));
+    }
+    Ok(())
+}
+
+/// Whether an address is one that's reachable from the internet, rather than
+/// a private, local or reserved one.
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || a == 0
+                // Shared address space, used for carrier-grade NAT.
+                || (a == 100 && (b & 0xc0) == 64))
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public(ip.into());
+            }
+            let first = ip.segments()[0];
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // Unique local addresses.
+                || (first & 0xfe00) == 0xfc00
+                // Link-local addresses.
+                || (first & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+/// Keeps fetched remote media in memory for a while, up to a total size.
+///
+/// Once it's full, the media that was used longest ago is evicted to make
+/// room. Media bigger than the whole cache isn't kept at all.
+#[derive(Debug, Clone)]
+pub struct RemoteMediaCache {
+    clock: SharedClock,
+    ttl: ChronoDuration,
+    max_bytes: u64,
+    entries: Arc<Mutex<CacheEntries>>,
+}
+
+#[derive(Debug, Default)]
+struct CacheEntries {
+    media: HashMap<String, CachedMedia>,
+    bytes: u64,
+    /// Counts every use of the cache, so that entries can be ordered by when
+    /// they were last used.
+    uses: u64,
+}
+
+#[derive(Debug)]
+struct CachedMedia {
+    content: RemoteMediaContent,
+    fetched_at: DateTime<Utc>,
+    last_used: u64,
+}
+
+impl RemoteMediaCache {
+    pub fn new(config: &ProxyConfig, clock: SharedClock) -> Self {
+        Self {
+            clock,
+            ttl: ChronoDuration::from_std(config.cache_ttl)
+                .unwrap_or_else(|_| ChronoDuration::zero()),
+            max_bytes: config.cache_bytes,
+            entries: Arc::default(),
+        }
+    }
+
+    fn entries(&self) -> MutexGuard<'_, CacheEntries> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn get(&self, url: &str) -> Option<RemoteMediaContent> {
+        let now = self.clock.now();
+        let mut entries = self.entries();
+        entries.uses += 1;
+        let uses = entries.uses;
+
+        let cached = entries.media.get_mut(url)?;
+        if cached.fetched_at + self.ttl > now {
+            cached.last_used = uses;
+            return Some(cached.content.clone());
+        }
+        entries.remove(url);
+        None
+    }
+
+    pub fn insert(&self, url: &str, content: RemoteMediaContent) {
+        let size = content.bytes.len() as u64;
+        if size > self.max_bytes {
+            return;
+        }
+
+        let now = self.clock.now();
+        let mut entries = self.entries();
+        entries.remove(url);
+        let expired: Vec<_> = entries
+            .media
+            .iter()
+            .filter(|(_, cached)| cached.fetched_at + self.ttl <= now)
+            .map(|(url, _)| url.clone())
+            .collect();
+        for url in expired {
+            entries.remove(&url);
+        }
+        // Entries are big and few, so finding the oldest each time is fine.
+        while entries.bytes + size > self.max_bytes {
+            let Some(oldest) = entries
+                .media
+                .iter()
+                .min_by_key(|(_, cached)| cached.last_used)
+                .map(|(url, _)| url.clone())
+            else {
+                break;
+            };
+            entries.remove(&oldest);
+        }
+
+        entries.uses += 1;
+        let last_used = entries.uses;
+        entries.bytes += size;
+        entries.media.insert(
+            url.to_owned(),
+            CachedMedia {
+                content,
+                fetched_at: now,
+                last_used,
+            },
+        );
+    }
+}
+
+impl CacheEntries {
+    fn remove(&mut self, url: &str) {
+        if let Some(cached) = self.media.remove(url) {
+            self.bytes -= cached.content.bytes.len() as u64;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use test_case::test_case;
+
+    use super::*;
+    use crate::provider::MockClock;
+
+    fn cache(clock: &MockClock, cache_bytes: u64) -> RemoteMediaCache {
+        RemoteMediaCache::new(
+            &ProxyConfig {
+                enabled: true,
+                cache_bytes,
+                cache_ttl: Duration::from_mins(1),
+                ..Default::default()
+            },
+            Arc::new(clock.clone()),
+        )
+    }
+
+    fn content(bytes: &'static [u8]) -> RemoteMediaContent {
+        RemoteMediaContent {
+            content_type: "image/png".into(),
+            bytes: Bytes::from_static(bytes),
+        }
+    }
+
+    #[test]
+    fn test_cache_expiry() {
+        let clock = MockClock::default();
+        let cache = cache(&clock, 10);
+
+        cache.insert("a", content(b"aaa"));
+        assert_eq!(cache.get("a"), Some(content(b"aaa")));
+        clock.advance(ChronoDuration::seconds(59));
+        assert_eq!(cache.get("a"), Some(content(b"aaa")));
+        clock.advance(ChronoDuration::seconds(1));
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.entries().bytes, 0);
+    }
+
+    #[test]
+    fn test_cache_eviction() {
+        let clock = MockClock::default();
+        let cache = cache(&clock, 10);
+
+        cache.insert("a", content(b"aaaa"));
+        cache.insert("b", content(b"bbbb"));
+        // Using `a` makes `b` the one used longest ago.
+        assert!(cache.get("a").is_some());
+        cache.insert("c", content(b"cccc"));
+        assert_eq!(cache.get("b"), None);
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("c").is_some());
+        assert_eq!(cache.entries().bytes, 8);
+
+        // Replacing an entry doesn't count it twice.
+        cache.insert("c", content(b"cc"));
+        assert_eq!(cache.entries().bytes, 6);
+
+        // Media bigger than the whole cache isn't kept.
+        cache.insert("d", content(b"ddddddddddd"));
+        assert_eq!(cache.get("d"), None);
+        assert!(cache.get("a").is_some());
+    }
+
+    #[test_case("image/png" => true ; "image")]
+    #[test_case("video/mp4" => true ; "video")]
+    #[test_case("audio/ogg; codecs=opus" => true ; "audio with params")]
+    #[test_case("image/svg+xml" => false ; "svg")]
+    #[test_case("text/html" => false ; "html")]
+    #[test_case("application/javascript" => false ; "script")]
+    fn test_is_media_type(content_type: &str) -> bool {
+        is_media_type(content_type)
+    }
+
+    #[test_case(Ipv4Addr::new(93, 184, 216, 34).into() => true ; "public v4")]
+    #[test_case(Ipv4Addr::LOCALHOST.into() => false ; "loopback v4")]
+    #[test_case(Ipv4Addr::new(10, 1, 2, 3).into() => false ; "private v4")]
+    #[test_case(Ipv4Addr::new(169, 254, 169, 254).into() => false ; "link-local v4")]
+    #[test_case(Ipv4Addr::new(100, 64, 0, 1).into() => false ; "shared v4")]
+    #[test_case(Ipv4Addr::UNSPECIFIED.into() => false ; "unspecified v4")]
+    #[test_case("2606:4700::1111".parse::<Ipv6Addr>().unwrap().into() => true ; "public v6")]
+    #[test_case(Ipv6Addr::LOCALHOST.into() => false ; "loopback v6")]
+    #[test_case("fd00::1".parse::<Ipv6Addr>().unwrap().into() => false ; "unique local v6")]
+    #[test_case("fe80::1".parse::<Ipv6Addr>().unwrap().into() => false ; "link-local v6")]
+    #[test_case("::ffff:127.0.0.1".parse::<Ipv6Addr>().unwrap().into() => false ; "mapped loopback")]
+    fn test_is_public(ip: IpAddr) -> bool {
+        is_public(ip)
+    }
+
+    #[tokio::test]
+    async fn test_check_host() {
+        for url in [
+            "http://127.0.0.1/a.png",
+            "http://[::1]:8080/a.png",
+            "http://localhost/",
+        ] {
+            let res = check_host(&url.parse().unwrap()).await;
+            assert!(matches!(res, Err(Error::InputInvalid(_))), "{url}: {res:?}");
+        }
+    }
+}
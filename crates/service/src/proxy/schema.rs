@@ -0,0 +1,34 @@
+use async_graphql::{Context, Object};
+use tracing::instrument;
+
+use super::{ProxiedKind, ProxyUrls};
+use crate::prelude::*;
+
+#[derive(Default)]
+pub struct ProxyQuery;
+
+#[Object]
+impl ProxyQuery {
+    /// The URL to send readers to instead of an outbound link, which
+    /// redirects them without telling the remote server which page they came
+    /// from. Nothing is given back if the instance doesn't proxy links, in
+    /// which case the link can be used as it is.
+    #[instrument(skip_all)]
+    async fn proxied_link_url(&self, ctx: &Context<'_>, url: String) -> GqlResult<Option<String>> {
+        ctx.data_unchecked::<ProxyUrls>()
+            .sign(&url, ProxiedKind::Link)
+            .extend()
+    }
+
+    /// The URL to load remote media from instead of fetching it directly,
+    /// which the instance fetches and caches so that the remote server never
+    /// sees the reader. Only images, video and audio can be proxied. Nothing
+    /// is given back if the instance doesn't proxy media, in which case the
+    /// remote URL can be used as it is.
+    #[instrument(skip_all)]
+    async fn proxied_media_url(&self, ctx: &Context<'_>, url: String) -> GqlResult<Option<String>> {
+        ctx.data_unchecked::<ProxyUrls>()
+            .sign(&url, ProxiedKind::Media)
+            .extend()
+    }
+}
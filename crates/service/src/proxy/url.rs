@@ -0,0 +1,219 @@
+use hyper::Uri;
+use jsonwebtoken::{Algorithm, Header, Validation};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::ProxyConfig, prelude::*, DecodingKey, EncodingKey};
+
+/// What a proxied URL leads to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProxiedKind {
+    /// A page that the browser is redirected to.
+    Link,
+    /// Media that the instance fetches and serves itself.
+    Media,
+}
+
+impl ProxiedKind {
+    fn path(self) -> &'static str {
+        match self {
+            Self::Link => "link",
+            Self::Media => "media",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ProxyClaims {
+    /// The remote URL that's proxied.
+    url: String,
+    kind: ProxiedKind,
+}
+
+/// Makes and checks the URLs that outbound links and remote media are
+/// proxied through.
+///
+/// URLs carry a token signed with the instance's key, which says which remote
+/// URL it's for and whether it's a link or media, so that links can't be used
+/// to fetch media or the other way round. They don't expire, as posts keep
+/// their links for as long as they're around.
+#[derive(Clone)]
+pub struct ProxyUrls {
+    enabled: bool,
+    enc_key: EncodingKey,
+    dec_key: DecodingKey,
+    public_url: Option<String>,
+}
+
+impl ProxyUrls {
+    pub fn new(
+        config: &ProxyConfig,
+        enc_key: EncodingKey,
+        dec_key: DecodingKey,
+        public_url: Option<String>,
+    ) -> Self {
+        Self {
+            enabled: config.enabled,
+            enc_key,
+            dec_key,
+            public_url,
+        }
+    }
+
+    /// Whether links and media are proxied at all.
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Makes the URL that a remote URL is proxied through, or gives nothing
+    /// back if proxying is turned off, in which case the remote URL should be
+    /// used as it is.
+    pub fn sign(&self, url: &str, kind: ProxiedKind) -> Result<Option<String>> {
+        if !self.enabled {
+            return Ok(None);
+        }
+        let claims = ProxyClaims {
+            url: remote_uri(url)?.to_string(),
+            kind,
+        };
+        let token = jsonwebtoken::encode(&Header::new(Algorithm::EdDSA), &claims, &self.enc_key)?;
+        let query = serde_urlencoded::to_string([("token", token)])
+            .expect("a string pair can always be encoded");
+        Ok(Some(format!(
+            "{}/api/v1/proxy/{}?{query}",
+            self.public_url.as_deref().unwrap_or_default(),
+            kind.path(),
+        )))
+    }
+
+    /// Checks a proxied URL's token, returning the remote URL it's for.
+    /// Tokens stop working as soon as proxying is turned off.
+    pub fn verify(&self, token: &str, kind: ProxiedKind) -> Result<Uri> {
+        if !self.enabled {
+            return Err(Error::NotFound);
+        }
+        let mut validation = Validation::new(Algorithm::EdDSA);
+        validation.validate_exp = false;
+        validation.required_spec_claims.clear();
+        let claims = jsonwebtoken::decode::<ProxyClaims>(token, &self.dec_key, &validation)?.claims;
+
+        if claims.kind != kind {
+            return Err(Error::JwtInvalid);
+        }
+        remote_uri(&claims.url)
+    }
+}
+
+/// Parses a remote URL, which can only be an absolute HTTP or HTTPS one.
+/// Anything else, such as `javascript:` links, isn't proxied.
+pub(super) fn remote_uri(url: &str) -> Result<Uri> {
+    let uri: Uri = url
+        .trim()
+        .parse()
+        .map_err(|_| Error::InputInvalid("url is not a valid URL".into()))?;
+    let scheme_allowed = matches!(uri.scheme_str(), Some("http" | "https"));
+    let has_host = matches!(uri.host(), Some(host) if !host.is_empty());
+    if !scheme_allowed || !has_host {
+        return Err(Error::InputInvalid(
+            "url must be an absolute HTTP or HTTPS URL".into(),
+        ));
+    }
+    Ok(uri)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use test_case::test_case;
+
+    use super::*;
+    use crate::account::testing::generate_keys;
+
+    fn urls(enabled: bool) -> ProxyUrls {
+        let (enc_key, dec_key) = generate_keys();
+        ProxyUrls::new(
+            &ProxyConfig {
+                enabled,
+                ..Default::default()
+            },
+            Arc::new(enc_key),
+            Arc::new(dec_key),
+            Some("https://plazer.example".into()),
+        )
+    }
+
+    fn token(url: &str) -> String {
+        let (_, query) = url.split_once('?').unwrap();
+        let params: Vec<(String, String)> = serde_urlencoded::from_str(query).unwrap();
+        params[0].1.clone()
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let urls = urls(true);
+
+        let url = urls
+            .sign("https://remote.example/page?a=1", ProxiedKind::Link)
+            .unwrap()
+            .unwrap();
+        assert!(url.starts_with("https://plazer.example/api/v1/proxy/link?token="));
+        assert_eq!(
+            urls.verify(&token(&url), ProxiedKind::Link).unwrap(),
+            "https://remote.example/page?a=1"
+        );
+        assert_eq!(
+            urls.verify(&token(&url), ProxiedKind::Media).unwrap_err(),
+            Error::JwtInvalid
+        );
+
+        let url = urls
+            .sign("http://remote.example/image.png", ProxiedKind::Media)
+            .unwrap()
+            .unwrap();
+        assert!(url.starts_with("https://plazer.example/api/v1/proxy/media?token="));
+        assert_eq!(
+            urls.verify(&token(&url), ProxiedKind::Media).unwrap(),
+            "http://remote.example/image.png"
+        );
+    }
+
+    #[test]
+    fn test_disabled() {
+        let url = urls(true)
+            .sign("https://remote.example/", ProxiedKind::Link)
+            .unwrap()
+            .unwrap();
+
+        let disabled = urls(false);
+        assert_eq!(
+            disabled.sign("https://remote.example/", ProxiedKind::Link),
+            Ok(None)
+        );
+        assert_eq!(
+            disabled
+                .verify(&token(&url), ProxiedKind::Link)
+                .unwrap_err(),
+            Error::NotFound
+        );
+    }
+
+    #[test]
+    fn test_verify_other_keys() {
+        let url = urls(true)
+            .sign("https://remote.example/", ProxiedKind::Link)
+            .unwrap()
+            .unwrap();
+        assert!(urls(true).verify(&token(&url), ProxiedKind::Link).is_err());
+    }
+
+    #[test_case("https://remote.example/", true ; "https")]
+    #[test_case("http://remote.example:8080/a?b", true ; "http with port")]
+    #[test_case("javascript:alert(1)", false ; "javascript")]
+    #[test_case("ftp://remote.example/", false ; "other scheme")]
+    #[test_case("/relative/path", false ; "relative")]
+    #[test_case("not a url", false ; "malformed")]
+    fn test_remote_uri(url: &str, valid: bool) {
+        assert_eq!(remote_uri(url).is_ok(), valid, "{url}");
+    }
+}
@@ -8,7 +8,7 @@ use std::{
     ops::{Deref, DerefMut},
 };
 
-use async_graphql::{connection::CursorType, ID};
+use async_graphql::{connection::CursorType, Context, ID};
 use serde::{de::DeserializeOwned, Serialize};
 
 use crate::prelude::*;
@@ -18,6 +18,18 @@ pub const PAGE_EXTRA: i64 = 2;
 pub const SRQL_ORDER_ASC: bool = true;
 pub const SRQL_ORDER_DESC: bool = false;
 
+/// The complexity of a field listing a page of a connection: what is selected
+/// on each item, for as many items as can be on the page.
+pub fn page_complexity(first: Option<i32>, last: Option<i32>, child_complexity: usize) -> usize {
+    let limit = first
+        .or(last)
+        .map_or(MAX_LIMIT, i64::from)
+        .clamp(1, MAX_LIMIT);
+    usize::try_from(limit)
+        .unwrap_or(1)
+        .saturating_mul(child_complexity)
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct PaginationArgs {
     pub after: Option<String>,
@@ -27,6 +39,23 @@ pub struct PaginationArgs {
 }
 
 impl PaginationArgs {
+    /// Warns the client if it asked for more items than fit on a page, as
+    /// only [`MAX_LIMIT`] are returned.
+    #[must_use]
+    pub fn warn_if_clamped(self, ctx: &Context<'_>) -> Self {
+        if self
+            .first
+            .or(self.last)
+            .is_some_and(|limit| i64::from(limit) > MAX_LIMIT)
+        {
+            ctx.warn(
+                "PageLimitClamped",
+                format!("At most {MAX_LIMIT} items are returned per page"),
+            );
+        }
+        self
+    }
+
     pub fn validate<Cursor>(self) -> Result<PaginationInput<Cursor>>
     where
         Cursor: CursorType + Debug + Default + Clone,
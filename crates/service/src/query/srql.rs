@@ -1,8 +1,11 @@
 use std::collections::BTreeMap;
 
+use chrono::{DateTime, Utc};
 pub use surrealdb::sql::{statements::*, *};
 use ulid::Ulid;
 
+use crate::provider::IdGen;
+
 #[inline]
 pub fn query(statements: impl Into<Vec<Statement>>) -> Query {
     Query(Statements(statements.into()))
@@ -23,6 +26,17 @@ pub fn field(field: impl Into<String>) -> Idiom {
     Idiom(vec![Part::Field(Ident(field.into()))])
 }
 
+/// A field reached by following record links, such as `board_id.handle`.
+#[inline]
+pub fn path(fields: &[&str]) -> Idiom {
+    Idiom(
+        fields
+            .iter()
+            .map(|field| Part::Field(Ident((*field).to_owned())))
+            .collect(),
+    )
+}
+
 #[inline]
 pub fn array(array: impl Into<Vec<Value>>) -> Value {
     Value::Array(array.into().into())
@@ -48,15 +62,108 @@ pub fn trans_end() -> Statement {
     Statement::Commit(CommitStatement)
 }
 
+/// Combines two optional conditions so that both must hold.
+pub fn cond_and(l: Option<Cond>, r: Option<Cond>) -> Option<Cond> {
+    match (l, r) {
+        (Some(Cond(l)), Some(Cond(r))) => Cond(
+            Expression::Binary {
+                l,
+                o: Operator::And,
+                r,
+            }
+            .into(),
+        )
+        .into(),
+        (Some(cond), None) | (None, Some(cond)) => Some(cond),
+        (None, None) => None,
+    }
+}
+
+/// Builds a query that counts the records in a table that match a condition.
+///
+/// The result is in the `count` field, which is missing if nothing matched.
+pub fn count_query(table: &str, cond: Option<Cond>) -> SelectStatement {
+    SelectStatement {
+        expr: Fields(
+            vec![Field::Single {
+                expr: Function::Normal("count".into(), vec![]).into(),
+                alias: Some(field("count")),
+            }],
+            false,
+        ),
+        what: self::table(table),
+        cond,
+        group: Groups(vec![]).into(),
+        ..Default::default()
+    }
+}
+
+/// Builds a query that counts the records in a table by the value of one of
+/// their fields, leaving out records without one.
+///
+/// Each result has the value in its `key` field and the count in `count`.
+pub fn count_by_query(table: &str, by: &str) -> SelectStatement {
+    SelectStatement {
+        expr: Fields(
+            vec![
+                Field::Single {
+                    expr: field(by).into(),
+                    alias: Some(field("key")),
+                },
+                Field::Single {
+                    expr: Function::Normal("count".into(), vec![]).into(),
+                    alias: Some(field("count")),
+                },
+            ],
+            false,
+        ),
+        what: self::table(table),
+        cond: Cond(
+            Expression::Binary {
+                l: field(by).into(),
+                o: Operator::NotEqual,
+                r: Value::None,
+            }
+            .into(),
+        )
+        .into(),
+        group: Groups(vec![Group(field("key"))]).into(),
+        ..Default::default()
+    }
+}
+
+/// Builds a query that sums a field over the records in a table that match a
+/// condition.
+///
+/// The result is in the `sum` field, which is missing if nothing matched.
+pub fn sum_query(table: &str, sum: &str, cond: Option<Cond>) -> SelectStatement {
+    SelectStatement {
+        expr: Fields(
+            vec![Field::Single {
+                expr: Function::Normal("math::sum".into(), vec![field(sum).into()]).into(),
+                alias: Some(field("sum")),
+            }],
+            false,
+        ),
+        what: self::table(table),
+        cond,
+        group: Groups(vec![]).into(),
+        ..Default::default()
+    }
+}
+
 pub type SetExprItem = (Idiom, Operator, Value);
 pub type SetExpr = Vec<SetExprItem>;
 
-pub fn ulid() -> String {
-    Ulid::new().to_string().to_ascii_lowercase()
+/// The smallest ULID that can be generated at the given time. Since IDs are
+/// ULIDs, this can be used to filter records by when they were created.
+pub fn ulid_at(time: DateTime<Utc>) -> String {
+    let ms = u64::try_from(time.timestamp_millis()).unwrap_or_default();
+    Ulid::from_parts(ms, 0).to_string().to_ascii_lowercase()
 }
 
-pub fn obj_create_query(table: &str, data: SetExpr) -> CreateStatement {
-    obj_create_query_id(table, data, ulid().into())
+pub fn obj_create_query(table: &str, data: SetExpr, ids: &dyn IdGen) -> CreateStatement {
+    obj_create_query_id(table, data, ids.next_id().into())
 }
 
 pub fn obj_create_query_id(table: &str, mut data: SetExpr, id: Id) -> CreateStatement {
@@ -75,6 +182,14 @@ pub fn obj_create_query_id(table: &str, mut data: SetExpr, id: Id) -> CreateStat
     }
 }
 
+/// The record that a statement from [`obj_create_query`] creates.
+pub fn created_thing(create: &CreateStatement) -> Option<Thing> {
+    match create.what.0.first() {
+        Some(Value::Thing(thing)) => Some(thing.clone()),
+        _ => None,
+    }
+}
+
 pub fn obj_update_query(thing: Thing, mut update: SetExpr) -> Option<UpdateStatement> {
     if update.is_empty() {
         return None;
@@ -114,6 +229,22 @@ pub fn define_uniq_index(
     }))
 }
 
+/// Defines an index that speeds up lookups, without requiring the indexed
+/// fields to be unique.
+pub fn define_index(
+    index: impl Into<String>,
+    table: &str,
+    fields: impl Into<Vec<Idiom>>,
+) -> Statement {
+    Statement::Define(DefineStatement::Index(DefineIndexStatement {
+        name: index.into().into(),
+        what: table.into(),
+        cols: Idioms(fields.into()),
+        index: Index::Idx,
+        ..Default::default()
+    }))
+}
+
 #[inline]
 pub fn time_now() -> Value {
     Value::Function(Box::new(Function::Normal("time::now".into(), vec![])))
@@ -27,6 +27,52 @@ impl QueryValue for String {
     }
 }
 
+impl QueryValue for bool {
+    fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
+        Some((field, srql::Operator::Equal, srql::Value::Bool(self)))
+    }
+}
+
+impl QueryValue for u8 {
+    fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
+        Some((
+            field,
+            srql::Operator::Equal,
+            srql::Value::Number(srql::Number::Int(self.into())),
+        ))
+    }
+}
+
+impl QueryValue for u32 {
+    fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
+        Some((
+            field,
+            srql::Operator::Equal,
+            srql::Value::Number(srql::Number::Int(self.into())),
+        ))
+    }
+}
+
+impl QueryValue for i32 {
+    fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
+        Some((
+            field,
+            srql::Operator::Equal,
+            srql::Value::Number(srql::Number::Int(self.into())),
+        ))
+    }
+}
+
+impl QueryValue for u64 {
+    fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
+        Some((
+            field,
+            srql::Operator::Equal,
+            srql::Value::Number(srql::Number::Int(i64::try_from(self).unwrap_or(i64::MAX))),
+        ))
+    }
+}
+
 impl QueryValue for (&str, ID) {
     fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
         let (table, id) = self;
@@ -40,6 +86,16 @@ impl QueryValue for srql::Thing {
     }
 }
 
+impl QueryValue for Vec<srql::Thing> {
+    fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
+        Some((
+            field,
+            srql::Operator::Equal,
+            srql::array(self.into_iter().map(srql::Value::Thing).collect::<Vec<_>>()),
+        ))
+    }
+}
+
 impl QueryValue for DateTime<Utc> {
     fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
         Some((
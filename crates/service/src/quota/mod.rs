@@ -0,0 +1,14 @@
+//! Limits on how much each account can create.
+//!
+//! Every account gets the instance's [`QuotaConfig`](crate::config::QuotaConfig)
+//! unless an admin has set different quotas for it.
+
+mod models;
+mod persist;
+mod schema;
+
+pub use models::*;
+pub use persist::*;
+pub use schema::*;
+
+pub static QUOTA_TABLE_NAME: &str = "quota";
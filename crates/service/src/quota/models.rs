@@ -0,0 +1,135 @@
+use std::fmt;
+
+use async_graphql::{InputObject, MaybeUndefined, SimpleObject};
+use serde::Deserialize;
+use surrealdb::sql::Thing;
+
+use crate::{config::QuotaConfig, prelude::*};
+
+/// Something that accounts only have so much of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuotaResource {
+    Posts,
+    Boards,
+    Lists,
+    Storage,
+}
+
+impl fmt::Display for QuotaResource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Posts => "post",
+            Self::Boards => "board",
+            Self::Lists => "list",
+            Self::Storage => "storage",
+        })
+    }
+}
+
+/// How much an account can create. Limits that are `null` are unlimited.
+#[derive(SimpleObject, Debug, Default, Clone, PartialEq, Eq)]
+pub struct Quota {
+    /// The number of posts the account can make.
+    pub posts: Option<u32>,
+    /// The number of boards the account can create.
+    pub boards: Option<u32>,
+    /// The number of lists the account can create.
+    pub lists: Option<u32>,
+    /// The number of bytes of post titles and content, and of uploaded media,
+    /// the account can store.
+    pub storage_bytes: Option<u64>,
+}
+
+impl Quota {
+    /// Works out an account's quotas from the instance's defaults and any
+    /// that an admin has set for it.
+    pub fn new(defaults: &QuotaConfig, custom: Option<&CustomQuota>) -> Self {
+        Self {
+            posts: limit(custom.and_then(|c| c.posts).unwrap_or(defaults.posts)),
+            boards: limit(custom.and_then(|c| c.boards).unwrap_or(defaults.boards)),
+            lists: limit(custom.and_then(|c| c.lists).unwrap_or(defaults.lists)),
+            storage_bytes: limit(
+                custom
+                    .and_then(|c| c.storage_bytes)
+                    .unwrap_or(defaults.storage_bytes),
+            ),
+        }
+    }
+
+    /// Whether an account with the given usage can add `amount` more of a
+    /// resource.
+    pub fn allows(&self, usage: &QuotaUsage, resource: QuotaResource, amount: u64) -> bool {
+        let (used, limit) = match resource {
+            QuotaResource::Posts => (usage.posts.into(), self.posts.map(u64::from)),
+            QuotaResource::Boards => (usage.boards.into(), self.boards.map(u64::from)),
+            QuotaResource::Lists => (usage.lists.into(), self.lists.map(u64::from)),
+            QuotaResource::Storage => (usage.storage_bytes, self.storage_bytes),
+        };
+        limit.is_none_or(|limit| used.saturating_add(amount) <= limit)
+    }
+}
+
+fn limit<T: Default + PartialEq>(limit: T) -> Option<T> {
+    (limit != T::default()).then_some(limit)
+}
+
+/// How much an account has created.
+#[derive(SimpleObject, Debug, Default, Clone, PartialEq, Eq)]
+pub struct QuotaUsage {
+    /// The number of posts the account has made.
+    pub posts: u32,
+    /// The number of boards the account has created.
+    pub boards: u32,
+    /// The number of lists the account has created.
+    pub lists: u32,
+    /// The number of bytes of post titles and content, and of uploaded media,
+    /// the account has stored.
+    pub storage_bytes: u64,
+}
+
+/// Quotas that an admin has set for an account, replacing the instance's
+/// defaults. A limit of 0 means there is no limit.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct CustomQuota {
+    pub id: Thing,
+    pub posts: Option<u32>,
+    pub boards: Option<u32>,
+    pub lists: Option<u32>,
+    pub storage_bytes: Option<u64>,
+}
+
+/// Changes to an account's quotas. A limit of 0 means there is no limit.
+#[derive(InputObject, Debug, Default, Clone, PartialEq, Eq)]
+pub struct SetQuota {
+    /// The number of posts the account can make. If not given, this is not
+    /// changed. If null is given, the instance's default is used.
+    pub posts: MaybeUndefined<u32>,
+    /// The number of boards the account can create. If not given, this is not
+    /// changed. If null is given, the instance's default is used.
+    pub boards: MaybeUndefined<u32>,
+    /// The number of lists the account can create. If not given, this is not
+    /// changed. If null is given, the instance's default is used.
+    pub lists: MaybeUndefined<u32>,
+    /// The number of bytes of post titles and content, and of uploaded media,
+    /// the account can store. If not given, this is not changed. If null is
+    /// given, the instance's default is used.
+    pub storage_bytes: MaybeUndefined<u64>,
+}
+
+impl SetQuota {
+    pub fn into_fields(self) -> srql::SetExpr {
+        let mut fields = vec![];
+        self.posts.push_field(srql::field("posts"), &mut fields);
+        self.boards.push_field(srql::field("boards"), &mut fields);
+        self.lists.push_field(srql::field("lists"), &mut fields);
+        self.storage_bytes
+            .push_field(srql::field("storage_bytes"), &mut fields);
+        fields
+    }
+}
+
+impl IntoUpdateQuery for SetQuota {
+    fn into_update(self, thing: srql::Thing) -> Option<srql::UpdateStatement> {
+        srql::obj_update_query(thing, self.into_fields())
+    }
+}
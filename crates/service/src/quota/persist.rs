@@ -0,0 +1,202 @@
+#[cfg(test)]
+mod tests;
+
+use serde::Deserialize;
+use tracing::instrument;
+
+use super::{CustomQuota, Quota, QuotaResource, QuotaUsage, SetQuota, QUOTA_TABLE_NAME};
+use crate::{
+    account::{require_admin, CurrentAccount},
+    board::BOARD_TABLE_NAME,
+    list::LIST_TABLE_NAME,
+    media::owned_media_bytes,
+    persist::Persist,
+    post::POST_TABLE_NAME,
+    prelude::*,
+};
+
+pub struct QuotaPersist<'a> {
+    persist: &'a Persist,
+    current: &'a CurrentAccount,
+}
+
+impl<'a> QuotaPersist<'a> {
+    pub fn new(persist: &'a Persist, current: &'a CurrentAccount) -> Self {
+        Self { persist, current }
+    }
+
+    /// Gets an account's quotas. Only the account itself and admins can see
+    /// these.
+    #[instrument(skip_all)]
+    pub async fn get(&self, account_id: &srql::Thing) -> Result<Quota> {
+        self.require_own_or_admin(account_id).await?;
+        self.quota_of(account_id).await
+    }
+
+    /// Gets how much of its quotas an account has used. Only the account
+    /// itself and admins can see this.
+    #[instrument(skip_all)]
+    pub async fn usage(&self, account_id: &srql::Thing) -> Result<QuotaUsage> {
+        self.require_own_or_admin(account_id).await?;
+        self.usage_of(account_id).await
+    }
+
+    /// Changes an account's quotas. Only admins can do this.
+    #[instrument(skip_all)]
+    pub async fn set(&self, account_id: &str, set: SetQuota) -> Result<Quota> {
+        require_admin(self.persist, self.current).await?;
+
+        let account_id = account_id.to_account_thing();
+        let thing = quota_thing(&account_id);
+        let existing: Option<CustomQuota> = self.persist.db().select(thing.clone()).await?;
+        if existing.is_some() {
+            if let Some(update) = set.into_update(thing) {
+                self.persist.db().query(update).await?;
+            }
+        } else {
+            self.persist
+                .db()
+                .query(srql::obj_create_query_id(
+                    QUOTA_TABLE_NAME,
+                    set.into_fields(),
+                    thing.id,
+                ))
+                .await?;
+        }
+        self.quota_of(&account_id).await
+    }
+
+    /// Fails with [`Error::QuotaExceeded`] if an account can't add the given
+    /// amounts of each resource.
+    pub(crate) async fn require_available(
+        &self,
+        account_id: &srql::Thing,
+        wanted: &[(QuotaResource, u64)],
+    ) -> Result<()> {
+        let quota = self.quota_of(account_id).await?;
+        if quota == Quota::default() {
+            return Ok(());
+        }
+
+        let usage = self.usage_of(account_id).await?;
+        match wanted
+            .iter()
+            .find(|(resource, amount)| !quota.allows(&usage, *resource, *amount))
+        {
+            Some((resource, _)) => Err(Error::QuotaExceeded(resource.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    async fn require_own_or_admin(&self, account_id: &srql::Thing) -> Result<()> {
+        if self.current.id()?.to_account_thing() == *account_id {
+            return Ok(());
+        }
+        require_admin(self.persist, self.current).await?;
+        Ok(())
+    }
+
+    async fn quota_of(&self, account_id: &srql::Thing) -> Result<Quota> {
+        let custom: Option<CustomQuota> = self.persist.db().select(quota_thing(account_id)).await?;
+        Ok(Quota::new(self.persist.quotas(), custom.as_ref()))
+    }
+
+    async fn usage_of(&self, account_id: &srql::Thing) -> Result<QuotaUsage> {
+        #[derive(Deserialize)]
+        struct PostText {
+            title: Option<String>,
+            content: Option<String>,
+        }
+
+        let posts: Vec<PostText> = self
+            .persist
+            .db()
+            .query(srql::SelectStatement {
+                expr: srql::Fields(
+                    vec![
+                        srql::Field::Single {
+                            expr: srql::field("title").into(),
+                            alias: None,
+                        },
+                        srql::Field::Single {
+                            expr: srql::field("content").into(),
+                            alias: None,
+                        },
+                    ],
+                    false,
+                ),
+                what: srql::table(POST_TABLE_NAME),
+                cond: owned_by("creator_id", account_id).into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        let storage_bytes = posts
+            .iter()
+            .map(|post| text_bytes(post.title.as_deref(), post.content.as_deref()))
+            .sum::<u64>()
+            + owned_media_bytes(self.persist, account_id).await?;
+
+        Ok(QuotaUsage {
+            posts: u32::try_from(posts.len()).unwrap_or(u32::MAX),
+            boards: self
+                .count(BOARD_TABLE_NAME, owned_by("creator_id", account_id))
+                .await?,
+            lists: self
+                .count(LIST_TABLE_NAME, owned_by("owner_id", account_id))
+                .await?,
+            storage_bytes,
+        })
+    }
+
+    async fn count(&self, table: &str, cond: srql::Cond) -> Result<u32> {
+        let count: Option<u32> = self
+            .persist
+            .db()
+            .query(srql::count_query(table, cond.into()))
+            .await?
+            .take("count")?;
+        Ok(count.unwrap_or_default())
+    }
+}
+
+/// The number of bytes that a post's title and content count for against
+/// the storage quota.
+pub fn text_bytes(title: Option<&str>, content: Option<&str>) -> u64 {
+    let len = title.map_or(0, str::len) + content.map_or(0, str::len);
+    u64::try_from(len).unwrap_or(u64::MAX)
+}
+
+fn quota_thing(account_id: &srql::Thing) -> srql::Thing {
+    srql::Thing {
+        tb: QUOTA_TABLE_NAME.to_owned(),
+        id: account_id.id.clone(),
+    }
+}
+
+fn owned_by(field: &str, account_id: &srql::Thing) -> srql::Cond {
+    srql::Cond(
+        srql::Expression::Binary {
+            l: srql::field(field).into(),
+            o: srql::Operator::Equal,
+            r: account_id.clone().into(),
+        }
+        .into(),
+    )
+}
+
+#[cfg(test)]
+pub mod testing {
+    use super::QuotaPersist;
+    use crate::account::testing::TestData;
+
+    pub trait QuotaTestData {
+        fn quota(&self) -> QuotaPersist<'_>;
+    }
+
+    impl QuotaTestData for TestData {
+        fn quota(&self) -> QuotaPersist<'_> {
+            QuotaPersist::new(&self.persist, &self.current)
+        }
+    }
+}
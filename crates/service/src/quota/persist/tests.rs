@@ -0,0 +1,181 @@
+use async_graphql::MaybeUndefined;
+use pretty_assertions::assert_eq;
+
+use super::{testing::QuotaTestData as _, *};
+use crate::{
+    account::testing::*,
+    board::{testing::BoardTestData as _, CreateBoard},
+    config::QuotaConfig,
+    list::{testing::ListTestData as _, CreateList},
+    post::{testing::PostTestData as _, CreatePost, UpdatePost},
+};
+
+fn text_post(content: &str) -> CreatePost {
+    CreatePost {
+        content: Some(content.into()),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn test_unlimited_by_default() {
+    let (data, acc) = TestData::with_user().await;
+    for _ in 0..3 {
+        data.generate_post().await;
+    }
+
+    assert_eq!(data.quota().get(&acc.id).await.unwrap(), Quota::default());
+}
+
+#[tokio::test]
+async fn test_instance_defaults() {
+    let (mut data, acc) = TestData::with_user().await;
+    data.persist = data.persist.with_quotas(QuotaConfig {
+        posts: 2,
+        boards: 1,
+        lists: 1,
+        storage_bytes: 0,
+    });
+
+    data.generate_post().await;
+    data.generate_post().await;
+    let res = data.post().create(text_post("Test")).await;
+    assert_eq!(
+        res.unwrap_err(),
+        Error::QuotaExceeded(QuotaResource::Posts.to_string())
+    );
+
+    data.generate_board().await;
+    let res = data
+        .board()
+        .create(CreateBoard {
+            handle: Some("other".into()),
+            ..Default::default()
+        })
+        .await;
+    assert_eq!(
+        res.unwrap_err(),
+        Error::QuotaExceeded(QuotaResource::Boards.to_string())
+    );
+
+    data.generate_list().await;
+    assert!(matches!(
+        data.list()
+            .create(CreateList {
+                name: "Other".into(),
+                ..Default::default()
+            })
+            .await,
+        Err(Error::QuotaExceeded(_))
+    ));
+
+    assert_eq!(
+        data.quota().usage(&acc.id).await.unwrap(),
+        QuotaUsage {
+            posts: 2,
+            boards: 1,
+            lists: 1,
+            storage_bytes: 8,
+        }
+    );
+}
+
+#[tokio::test]
+async fn test_storage() {
+    let (mut data, _) = TestData::with_user().await;
+    data.persist = data.persist.with_quotas(QuotaConfig {
+        storage_bytes: 10,
+        ..Default::default()
+    });
+
+    let post = data.post().create(text_post("hello")).await.unwrap();
+    let res = data.post().create(text_post("world!")).await;
+    assert_eq!(
+        res.unwrap_err(),
+        Error::QuotaExceeded(QuotaResource::Storage.to_string())
+    );
+
+    let update = |content: &str| UpdatePost {
+        content: MaybeUndefined::Value(content.into()),
+        ..Default::default()
+    };
+    let id = post.id.to_gql_id();
+    let res = data.post().update(&id, update("hello, world!")).await;
+    assert_eq!(
+        res.unwrap_err(),
+        Error::QuotaExceeded(QuotaResource::Storage.to_string())
+    );
+    data.post().update(&id, update("hi")).await.unwrap();
+    data.post().create(text_post("world!")).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_set() {
+    let (mut data, admin) = TestData::with_user().await;
+    data.persist = data.persist.with_quotas(QuotaConfig {
+        posts: 1,
+        ..Default::default()
+    });
+    let other = data.account().create_test_user().await;
+    let other_id = other.id.to_gql_id();
+
+    let quota = data
+        .quota()
+        .set(
+            &other_id,
+            SetQuota {
+                posts: MaybeUndefined::Value(2),
+                lists: MaybeUndefined::Value(3),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(quota.posts, Some(2));
+    assert_eq!(quota.lists, Some(3));
+    assert_eq!(quota.boards, None);
+
+    data.login_as(&other);
+    data.generate_post().await;
+    data.generate_post().await;
+    assert!(data.post().create(text_post("Test")).await.is_err());
+
+    // Only admins can change quotas, or see other accounts' quotas.
+    let res = data
+        .quota()
+        .set(&admin.id.to_gql_id(), SetQuota::default())
+        .await;
+    assert_eq!(res.unwrap_err(), Error::Unauthorized);
+    let res = data.quota().get(&admin.id).await;
+    assert_eq!(res.unwrap_err(), Error::Unauthorized);
+
+    // Going back to the default, and then removing the limit entirely.
+    data.login_as(&admin);
+    let quota = data
+        .quota()
+        .set(
+            &other_id,
+            SetQuota {
+                posts: MaybeUndefined::Null,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(quota.posts, Some(1));
+    assert_eq!(quota.lists, Some(3));
+
+    let quota = data
+        .quota()
+        .set(
+            &other_id,
+            SetQuota {
+                posts: MaybeUndefined::Value(0),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(quota.posts, None);
+    assert_eq!(data.quota().usage(&other.id).await.unwrap().posts, 2);
+}
@@ -0,0 +1,23 @@
+use async_graphql::{Context, Object, ID};
+use tracing::instrument;
+
+use super::{Quota, SetQuota};
+use crate::prelude::*;
+
+#[derive(Default)]
+pub struct QuotaMutation;
+
+#[Object]
+impl QuotaMutation {
+    /// Changes how much an account can create, replacing the instance's
+    /// defaults. Only admins can do this.
+    #[instrument(skip_all)]
+    async fn set_quota(
+        &self,
+        ctx: &Context<'_>,
+        account_id: ID,
+        quota: SetQuota,
+    ) -> GqlResult<Quota> {
+        ctx.quota_persist().set(&account_id, quota).await.extend()
+    }
+}
@@ -0,0 +1,663 @@
+//! Limits on how often each client can make requests.
+//!
+//! Requests are split into groups with their own budgets, so that signing in
+//! can be limited much more strictly than reading. Clients are told apart by
+//! the account they're signed in as, or by their address when they aren't,
+//! and each has its own budget for each group. Requests over budget are
+//! turned away with a `RateLimited` error saying how many seconds to wait in
+//! `retryAfter`, and in a `Retry-After` header.
+//!
+//! Every limited response tells the client where its budget stands, in
+//! `RateLimit-Limit`, `RateLimit-Remaining` and `RateLimit-Reset` headers and
+//! in the `rateLimit` extension of GraphQL responses, so that clients can
+//! slow down before they're turned away.
+//!
+//! Budgets are kept in memory, so each server process limits requests on its
+//! own. They're read from the live config on each request, so reloading them
+//! takes effect straight away, without forgetting what's been used up.
+
+use std::{
+    collections::{HashMap, HashSet},
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+};
+
+use async_graphql::{
+    extensions::{Extension, ExtensionContext, ExtensionFactory, NextParseQuery, NextRequest},
+    parser::types::{ExecutableDocument, OperationType, Selection, SelectionSet},
+    ErrorExtensions as _, Pos, Response, ServerResult, Value, Variables,
+};
+use axum::{
+    extract::{ConnectInfo, State},
+    headers::{authorization::Bearer, Authorization, HeaderMapExt as _},
+    http::{
+        header::{HeaderName, RETRY_AFTER},
+        HeaderMap, Method, Request,
+    },
+    middleware::Next,
+    response::Response as HttpResponse,
+    TypedHeader,
+};
+use chrono::{DateTime, Duration, Utc};
+use tracing::debug;
+
+use crate::{
+    account::{authenticate, CurrentAccount},
+    config::RateBudget,
+    prelude::*,
+    provider::SharedClock,
+    reload::LiveConfig,
+    session::ClientMeta,
+    DecodingKey,
+};
+
+/// Mutations that are limited by the `auth` budget, as they can be used to
+/// guess at credentials or tokens.
+static AUTH_MUTATIONS: &[&str] = &[
+    "login",
+    "verifyTwoFactor",
+    "beginPasskeyLogin",
+    "finishPasskeyLogin",
+    "beginExternalLogin",
+    "finishExternalLogin",
+    "devLogin",
+    "refresh",
+    "refreshToken",
+    "createAccount",
+    "verifyEmail",
+    "requestPasswordReset",
+    "resetPassword",
+    "requestRecovery",
+    "recoverAccount",
+    "disownSecurityEvent",
+    "restoreAccount",
+];
+
+/// How many clients are tracked before the ones with their full budget are
+/// forgotten.
+const PRUNE_AT: usize = 10_000;
+
+/// A group of routes that share a budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RateGroup {
+    Auth,
+    Read,
+    Write,
+}
+
+impl RateGroup {
+    /// The group of a REST request that isn't for signing in.
+    fn of_method(method: &Method) -> Self {
+        if matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS) {
+            Self::Read
+        } else {
+            Self::Write
+        }
+    }
+
+    /// The strictest group of the operations in a GraphQL document.
+    /// Subscriptions aren't limited, as they only cost anything while
+    /// they're being set up.
+    fn of_document(document: &ExecutableDocument) -> Option<Self> {
+        document
+            .operations
+            .iter()
+            .filter_map(|(_, operation)| match operation.node.ty {
+                OperationType::Query => Some(Self::Read),
+                OperationType::Mutation => {
+                    let auth = selects_auth_mutation(
+                        document,
+                        &operation.node.selection_set.node,
+                        &mut HashSet::new(),
+                    );
+                    Some(if auth { Self::Auth } else { Self::Write })
+                }
+                OperationType::Subscription => None,
+            })
+            .max_by_key(|group| match group {
+                Self::Read => 0,
+                Self::Write => 1,
+                Self::Auth => 2,
+            })
+    }
+}
+
+/// Whether a mutation's top-level fields include any in [`AUTH_MUTATIONS`],
+/// looking through inline fragments and fragment spreads, so that they can't
+/// be used to hide one. Each fragment is only looked through once, in case
+/// they spread each other.
+fn selects_auth_mutation<'a>(
+    document: &'a ExecutableDocument,
+    selection_set: &'a SelectionSet,
+    seen: &mut HashSet<&'a str>,
+) -> bool {
+    selection_set.items.iter().any(|item| match &item.node {
+        Selection::Field(field) => AUTH_MUTATIONS.contains(&field.node.name.node.as_str()),
+        Selection::InlineFragment(fragment) => {
+            selects_auth_mutation(document, &fragment.node.selection_set.node, seen)
+        }
+        Selection::FragmentSpread(spread) => {
+            let name = spread.node.fragment_name.node.as_str();
+            seen.insert(name)
+                && document.fragments.get(name).is_some_and(|fragment| {
+                    selects_auth_mutation(document, &fragment.node.selection_set.node, seen)
+                })
+        }
+    })
+}
+
+static RATE_LIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+static RATE_LIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+static RATE_LIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+
+/// Where a client's budget for a group stands after a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateStatus {
+    /// How many requests the budget allows in each window.
+    pub limit: u32,
+    /// How many more requests can be made straight away.
+    pub remaining: u32,
+    /// How many seconds until the full budget is back, or until the next
+    /// request can be made when it's been used up.
+    pub reset: u64,
+}
+
+impl RateStatus {
+    fn insert_headers(&self, headers: &mut HeaderMap) {
+        headers.insert(RATE_LIMIT_LIMIT.clone(), self.limit.into());
+        headers.insert(RATE_LIMIT_REMAINING.clone(), self.remaining.into());
+        headers.insert(RATE_LIMIT_RESET.clone(), self.reset.into());
+    }
+
+    fn to_value(self) -> Value {
+        Value::from_json(serde_json::json!({
+            "limit": self.limit,
+            "remaining": self.remaining,
+            "reset": self.reset,
+        }))
+        .unwrap_or_default()
+    }
+}
+
+/// Who a budget belongs to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum RateKey {
+    Account(String),
+    Ip(IpAddr),
+}
+
+impl RateKey {
+    fn new(current: Option<&CurrentAccount>, ip: Option<IpAddr>) -> Option<Self> {
+        current
+            .and_then(|current| current.id().ok())
+            .map(|id| Self::Account(id.to_string()))
+            .or_else(|| ip.map(Self::Ip))
+    }
+}
+
+/// The budgets of every client, shared between everything that's limited.
+#[derive(Clone)]
+pub struct RateLimiter {
+    inner: Arc<RateLimiterInner>,
+}
+
+struct RateLimiterInner {
+    live: LiveConfig,
+    clock: SharedClock,
+    budgets: Mutex<Budgets>,
+}
+
+#[derive(Default)]
+struct Budgets {
+    /// When each client will have their full budget back.
+    refilled_at: HashMap<(RateGroup, RateKey), DateTime<Utc>>,
+    prune_at: usize,
+}
+
+impl RateLimiter {
+    #[must_use]
+    pub fn new(live: LiveConfig, clock: SharedClock) -> Self {
+        Self {
+            inner: Arc::new(RateLimiterInner {
+                live,
+                clock,
+                budgets: Mutex::new(Budgets {
+                    prune_at: PRUNE_AT,
+                    ..Default::default()
+                }),
+            }),
+        }
+    }
+
+    /// The limit on a group of REST routes. When no group is given, requests
+    /// that change something are limited by the `write` budget, and the rest
+    /// by the `read` budget.
+    #[must_use]
+    pub fn routes(&self, group: Option<RateGroup>, dec_key: DecodingKey) -> RateLimitRoutes {
+        RateLimitRoutes {
+            limiter: self.clone(),
+            group,
+            dec_key,
+        }
+    }
+
+    fn budget(&self, group: RateGroup) -> RateBudget {
+        let config = &self.inner.live.load().rate_limits;
+        match group {
+            RateGroup::Auth => config.auth,
+            RateGroup::Read => config.read,
+            RateGroup::Write => config.write,
+        }
+    }
+
+    /// Takes a request from a client's budget, and returns what's left of
+    /// it, or nothing if the group isn't limited. If it's been used up, fails
+    /// with how many seconds to wait before trying again as its `reset`.
+    fn check(
+        &self,
+        group: RateGroup,
+        key: RateKey,
+    ) -> std::result::Result<Option<RateStatus>, RateStatus> {
+        let budget = self.budget(group);
+        if budget.requests == 0 {
+            return Ok(None);
+        }
+        let window = Duration::from_std(budget.per).unwrap_or_else(|_| Duration::zero());
+        let interval = window / i32::try_from(budget.requests).unwrap_or(i32::MAX);
+
+        // Each request pushes back when the budget is refilled by the time
+        // it takes to earn one request back. Requests are turned away while
+        // that's more than a window away.
+        let now = self.inner.clock.now();
+        let mut budgets = self
+            .inner
+            .budgets
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        budgets.prune(now);
+        let refilled_at = budgets.refilled_at.get(&(group, key.clone())).copied();
+        let refilled_at = refilled_at.filter(|at| *at > now).unwrap_or(now) + interval;
+        let over = refilled_at - now - window;
+        if over > Duration::zero() {
+            return Err(RateStatus {
+                limit: budget.requests,
+                remaining: 0,
+                reset: ceil_secs(over).max(1),
+            });
+        }
+        budgets.refilled_at.insert((group, key), refilled_at);
+
+        let remaining =
+            (window - (refilled_at - now)).num_milliseconds() / interval.num_milliseconds().max(1);
+        Ok(Some(RateStatus {
+            limit: budget.requests,
+            remaining: u32::try_from(remaining).unwrap_or_default(),
+            reset: ceil_secs(refilled_at - now),
+        }))
+    }
+}
+
+/// A duration in whole seconds, rounded up.
+fn ceil_secs(duration: Duration) -> u64 {
+    u64::try_from((duration.num_milliseconds() + 999) / 1000).unwrap_or_default()
+}
+
+impl Budgets {
+    /// Forgets the clients that have their full budget back once there are
+    /// too many, as they're no different from clients that haven't made any
+    /// requests.
+    fn prune(&mut self, now: DateTime<Utc>) {
+        if self.refilled_at.len() < self.prune_at {
+            return;
+        }
+        self.refilled_at.retain(|_, refilled_at| *refilled_at > now);
+        self.prune_at = PRUNE_AT.max(self.refilled_at.len() * 2);
+    }
+}
+
+/// A [`RateLimiter`] for a group of REST routes.
+#[derive(Clone)]
+pub struct RateLimitRoutes {
+    limiter: RateLimiter,
+    group: Option<RateGroup>,
+    dec_key: DecodingKey,
+}
+
+/// Middleware that turns away REST requests from clients that have used up
+/// their budget for the routes it wraps.
+///
+/// Clients are told apart by the account their access token is for. Clients
+/// that aren't signed in, or that use an API key, are told apart by their
+/// address, as finding the account an API key is for needs the database.
+pub async fn limit_rate<B>(
+    State(routes): State<RateLimitRoutes>,
+    req: Request<B>,
+    next: Next<B>,
+) -> HttpResponse {
+    let group = routes
+        .group
+        .unwrap_or_else(|| RateGroup::of_method(req.method()));
+    let header = req
+        .headers()
+        .typed_get::<Authorization<Bearer>>()
+        .map(TypedHeader);
+    let current = authenticate(header, &routes.dec_key, &routes.limiter.inner.clock).ok();
+    let ip = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+
+    let Some(key) = RateKey::new(current.as_ref(), ip) else {
+        return next.run(req).await;
+    };
+    match routes.limiter.check(group, key) {
+        Ok(status) => {
+            let mut res = next.run(req).await;
+            if let Some(status) = status {
+                status.insert_headers(res.headers_mut());
+            }
+            res
+        }
+        Err(status) => {
+            debug!(
+                ?group,
+                retry_after = status.reset,
+                "Turning away request over its rate limit"
+            );
+            let mut res = Error::RateLimited.into_retry_response(status.reset);
+            status.insert_headers(res.headers_mut());
+            res
+        }
+    }
+}
+
+/// A GraphQL extension that turns away requests from clients that have used
+/// up their budget. Mutations that sign in are limited by the `auth` budget,
+/// other mutations by the `write` budget, and queries by the `read` budget.
+///
+/// Requests over a WebSocket from clients that aren't signed in aren't
+/// limited, as they can't be told apart.
+pub struct RateLimitGuard(pub RateLimiter);
+
+impl ExtensionFactory for RateLimitGuard {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(RateLimitExtension {
+            limiter: self.0.clone(),
+            status: Mutex::default(),
+        })
+    }
+}
+
+struct RateLimitExtension {
+    limiter: RateLimiter,
+    /// Where the client's budget stands, and whether the request was turned
+    /// away, once it's been checked.
+    status: Mutex<Option<(RateStatus, bool)>>,
+}
+
+impl RateLimitExtension {
+    fn status(&self) -> std::sync::MutexGuard<'_, Option<(RateStatus, bool)>> {
+        self.status
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+#[async_trait::async_trait]
+impl Extension for RateLimitExtension {
+    async fn request(&self, ctx: &ExtensionContext<'_>, next: NextRequest<'_>) -> Response {
+        let mut res = next.run(ctx).await;
+        if let Some((status, limited)) = *self.status() {
+            if limited {
+                res.http_headers.insert(RETRY_AFTER, status.reset.into());
+            }
+            status.insert_headers(&mut res.http_headers);
+            res.extensions.insert("rateLimit".into(), status.to_value());
+        }
+        res
+    }
+
+    async fn parse_query(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        query: &str,
+        variables: &Variables,
+        next: NextParseQuery<'_>,
+    ) -> ServerResult<ExecutableDocument> {
+        let document = next.run(ctx, query, variables).await?;
+        let Some(group) = RateGroup::of_document(&document) else {
+            return Ok(document);
+        };
+        let current = ctx
+            .data_opt::<CurrentAccount>()
+            .or_else(|| ctx.data_opt::<Arc<CurrentAccount>>().map(AsRef::as_ref));
+        let ip = ctx.data_opt::<ClientMeta>().and_then(|client| client.ip);
+        let Some(key) = RateKey::new(current, ip) else {
+            return Ok(document);
+        };
+
+        match self.limiter.check(group, key) {
+            Ok(status) => {
+                *self.status() = status.map(|status| (status, false));
+                Ok(document)
+            }
+            Err(status) => {
+                let retry_after = status.reset;
+                debug!(
+                    ?group,
+                    retry_after, "Turning away request over its rate limit"
+                );
+                *self.status() = Some((status, true));
+                Err(Error::RateLimited
+                    .extend()
+                    .extend_with(|_, e| e.set("retryAfter", retry_after))
+                    .into_server_error(Pos::default()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+    use crate::{
+        config::{RateLimitConfig, ReloadableConfig},
+        provider::MockClock,
+    };
+
+    fn rate_limits(auth: u32) -> ReloadableConfig {
+        ReloadableConfig {
+            rate_limits: RateLimitConfig {
+                auth: RateBudget::per_minute(auth),
+                read: RateBudget::per_minute(60),
+                write: RateBudget::per_minute(0),
+            },
+            ..Default::default()
+        }
+    }
+
+    fn limiter(clock: &MockClock) -> RateLimiter {
+        RateLimiter::new(
+            LiveConfig::new(rate_limits(2), None),
+            Arc::new(clock.clone()),
+        )
+    }
+
+    /// How many requests are left, or how long to wait.
+    fn check(
+        limiter: &RateLimiter,
+        group: RateGroup,
+        key: RateKey,
+    ) -> std::result::Result<u32, u64> {
+        limiter
+            .check(group, key)
+            .map(|status| status.map_or(u32::MAX, |status| status.remaining))
+            .map_err(|status| status.reset)
+    }
+
+    fn ip(last: u8) -> RateKey {
+        RateKey::Ip(IpAddr::V4(Ipv4Addr::new(127, 0, 0, last)))
+    }
+
+    #[test]
+    fn test_budget() {
+        let clock = MockClock::default();
+        let limiter = limiter(&clock);
+
+        assert_eq!(check(&limiter, RateGroup::Auth, ip(1)), Ok(1));
+        assert_eq!(check(&limiter, RateGroup::Auth, ip(1)), Ok(0));
+        // One request is earned back every 30 seconds.
+        assert_eq!(check(&limiter, RateGroup::Auth, ip(1)), Err(30));
+
+        // Other clients and groups have their own budgets.
+        assert_eq!(check(&limiter, RateGroup::Auth, ip(2)), Ok(1));
+        assert_eq!(check(&limiter, RateGroup::Read, ip(1)), Ok(59));
+        let account = RateKey::Account("account:1".into());
+        assert_eq!(check(&limiter, RateGroup::Auth, account), Ok(1));
+
+        clock.advance(Duration::seconds(20));
+        assert_eq!(check(&limiter, RateGroup::Auth, ip(1)), Err(10));
+        clock.advance(Duration::seconds(10));
+        assert_eq!(check(&limiter, RateGroup::Auth, ip(1)), Ok(0));
+        assert_eq!(check(&limiter, RateGroup::Auth, ip(1)), Err(30));
+
+        clock.advance(Duration::minutes(5));
+        assert_eq!(check(&limiter, RateGroup::Auth, ip(1)), Ok(1));
+        assert_eq!(check(&limiter, RateGroup::Auth, ip(1)), Ok(0));
+        assert_eq!(check(&limiter, RateGroup::Auth, ip(1)), Err(30));
+    }
+
+    #[test]
+    fn test_status() {
+        let clock = MockClock::default();
+        let limiter = limiter(&clock);
+
+        let status = |remaining, reset| RateStatus {
+            limit: 2,
+            remaining,
+            reset,
+        };
+        assert_eq!(
+            limiter.check(RateGroup::Auth, ip(1)),
+            Ok(Some(status(1, 30)))
+        );
+        clock.advance(Duration::seconds(10));
+        assert_eq!(
+            limiter.check(RateGroup::Auth, ip(1)),
+            Ok(Some(status(0, 50)))
+        );
+        assert_eq!(limiter.check(RateGroup::Auth, ip(1)), Err(status(0, 20)));
+        assert_eq!(limiter.check(RateGroup::Write, ip(1)), Ok(None));
+
+        let mut headers = HeaderMap::new();
+        status(1, 30).insert_headers(&mut headers);
+        assert_eq!(headers["ratelimit-limit"], "2");
+        assert_eq!(headers["ratelimit-remaining"], "1");
+        assert_eq!(headers["ratelimit-reset"], "30");
+    }
+
+    #[test]
+    fn test_reloaded_budget() {
+        let clock = MockClock::default();
+        let live = LiveConfig::new(rate_limits(2), None);
+        let limiter = RateLimiter::new(live.clone(), Arc::new(clock.clone()));
+
+        assert_eq!(check(&limiter, RateGroup::Auth, ip(1)), Ok(1));
+        assert_eq!(check(&limiter, RateGroup::Auth, ip(1)), Ok(0));
+        assert_eq!(check(&limiter, RateGroup::Auth, ip(1)), Err(30));
+
+        // What's been used up is kept, but is earned back at the new rate.
+        assert!(live.set(rate_limits(6)));
+        assert_eq!(check(&limiter, RateGroup::Auth, ip(1)), Err(10));
+        clock.advance(Duration::seconds(10));
+        assert_eq!(check(&limiter, RateGroup::Auth, ip(1)), Ok(0));
+        assert!(live.set(rate_limits(0)));
+        assert_eq!(check(&limiter, RateGroup::Auth, ip(1)), Ok(u32::MAX));
+    }
+
+    #[test]
+    fn test_unlimited() {
+        let limiter = limiter(&MockClock::default());
+        for _ in 0..1000 {
+            assert_eq!(check(&limiter, RateGroup::Write, ip(1)), Ok(u32::MAX));
+        }
+    }
+
+    #[test]
+    fn test_prune() {
+        let clock = MockClock::default();
+        let limiter = limiter(&clock);
+        for last in 0..=u8::MAX {
+            limiter.check(RateGroup::Read, ip(last)).unwrap();
+        }
+
+        clock.advance(Duration::minutes(1));
+        let mut budgets = limiter.inner.budgets.lock().unwrap();
+        budgets.prune_at = 1;
+        budgets.prune(clock.now());
+        assert!(budgets.refilled_at.is_empty());
+    }
+
+    fn group(query: &str) -> Option<RateGroup> {
+        RateGroup::of_document(&async_graphql::parser::parse_query(query).unwrap())
+    }
+
+    #[test]
+    fn test_group_of_document() {
+        assert_eq!(group("{ me { id } }"), Some(RateGroup::Read));
+        assert_eq!(
+            group("mutation { createPost(create: {}) { id } }"),
+            Some(RateGroup::Write)
+        );
+        assert_eq!(
+            group(r#"mutation { login(creds: { userId: "a", pword: "b" }) { __typename } }"#),
+            Some(RateGroup::Auth)
+        );
+        assert_eq!(
+            group("query A { me { id } } mutation B { refreshToken(token: \"\") }"),
+            Some(RateGroup::Auth)
+        );
+        assert_eq!(group("subscription { postCreated { id } }"), None);
+    }
+
+    #[test]
+    fn test_group_of_inline_fragment() {
+        assert_eq!(
+            group("mutation { ... on Mutation { login(creds: {}) { __typename } } }"),
+            Some(RateGroup::Auth)
+        );
+        assert_eq!(
+            group("mutation { ... on Mutation { ... on Mutation { refreshToken(token: \"\") } } }"),
+            Some(RateGroup::Auth)
+        );
+        assert_eq!(
+            group("mutation { ... on Mutation { createPost(create: {}) { id } } }"),
+            Some(RateGroup::Write)
+        );
+    }
+
+    #[test]
+    fn test_group_of_fragment_spread() {
+        assert_eq!(
+            group("mutation { ...Login } fragment Login on Mutation { login(creds: {}) { id } }"),
+            Some(RateGroup::Auth)
+        );
+        assert_eq!(
+            group("mutation { ... on Mutation { ...R } } fragment R on Mutation { refreshToken }"),
+            Some(RateGroup::Auth)
+        );
+        assert_eq!(
+            group("mutation { ...Post } fragment Post on Mutation { createPost { id } }"),
+            Some(RateGroup::Write)
+        );
+        // Fragments that spread each other don't loop forever.
+        assert_eq!(
+            group(
+                "mutation { ...A } fragment A on Mutation { ...B } fragment B on Mutation { ...A }"
+            ),
+            Some(RateGroup::Write)
+        );
+    }
+}
@@ -0,0 +1,37 @@
+use serde::{Deserialize, Serialize};
+
+use super::READ_MARKER_TABLE_NAME;
+use crate::{migration::Migration, prelude::*};
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReadMarkerMigration {
+    #[default]
+    Init,
+}
+
+impl Migration for ReadMarkerMigration {
+    const SUBSYSTEM: &'static str = "subsys_read_marker";
+
+    fn next(self) -> Option<Self> {
+        match self {
+            Self::Init => None,
+        }
+    }
+
+    fn build(&self, statements: &mut Vec<srql::Statement>) {
+        use ReadMarkerMigration as S;
+        match self {
+            S::Init => Self::build_init(statements),
+        }
+    }
+}
+
+impl ReadMarkerMigration {
+    fn build_init(statements: &mut Vec<srql::Statement>) {
+        statements.push(srql::define_uniq_index(
+            "read_marker_account_target_index",
+            READ_MARKER_TABLE_NAME,
+            [srql::field("account_id"), srql::field("target_id")],
+        ));
+    }
+}
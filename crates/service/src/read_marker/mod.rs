@@ -0,0 +1,11 @@
+mod migration;
+mod models;
+mod persist;
+mod schema;
+
+pub use migration::*;
+pub use models::*;
+pub use persist::*;
+pub use schema::*;
+
+pub static READ_MARKER_TABLE_NAME: &str = "read_marker";
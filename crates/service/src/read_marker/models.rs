@@ -0,0 +1,112 @@
+use async_graphql::{ComplexObject, Context, Enum, SimpleObject, ID};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use surrealdb::sql::Thing;
+
+use super::READ_MARKER_TABLE_NAME;
+use crate::{board::BOARD_TABLE_NAME, id_obj_impls, post::POST_TABLE_NAME, prelude::*};
+
+/// What a read marker keeps track of.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadTarget {
+    /// The posts in a board.
+    Board,
+    /// The replies to a post.
+    Conversation,
+}
+
+impl ReadTarget {
+    pub fn thing(self, id: &str) -> Thing {
+        let table = match self {
+            Self::Board => BOARD_TABLE_NAME,
+            Self::Conversation => POST_TABLE_NAME,
+        };
+        (table.to_owned(), id.to_owned()).into()
+    }
+
+    /// The field that links posts to a target of this kind.
+    pub fn post_field(self) -> srql::Idiom {
+        match self {
+            Self::Board => srql::field("board_id"),
+            Self::Conversation => srql::field("reply_to_id"),
+        }
+    }
+}
+
+impl QueryValue for ReadTarget {
+    fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
+        srql::to_value(self)
+            .ok()
+            .map(|value| (field, srql::Operator::Equal, value))
+    }
+}
+
+/// The point up to which an account has read a board or conversation.
+#[derive(SimpleObject, Debug, Clone, Deserialize)]
+#[graphql(complex)]
+pub struct ReadMarker {
+    #[graphql(skip)]
+    pub id: Thing,
+    #[graphql(skip)]
+    pub account_id: Thing,
+    #[graphql(skip)]
+    pub target_id: Thing,
+    #[graphql(skip)]
+    pub last_read_id: Option<Thing>,
+    /// How many posts in the target had been read when the marker was last
+    /// moved. Markers from before this was kept don't have it.
+    #[graphql(skip)]
+    pub read_count: Option<u64>,
+
+    /// What kind of thing this marker keeps track of.
+    pub target: ReadTarget,
+
+    /// A timestamp indicating the last time the marker was moved.
+    pub updated_at: DateTime<Utc>,
+}
+
+#[ComplexObject]
+impl ReadMarker {
+    /// The read marker's unique ID.
+    async fn id(&self) -> ID {
+        self.id.to_gql_id()
+    }
+
+    /// The ID of the board or post that this marker keeps track of.
+    async fn target_id(&self) -> ID {
+        self.target_id.to_gql_id()
+    }
+
+    /// The ID of the newest post that has been read, if any.
+    async fn last_read_id(&self) -> Option<ID> {
+        self.last_read_id.as_ref().map(ToGqlId::to_gql_id)
+    }
+
+    /// The number of posts that have been made since the last read post.
+    /// This can take a few seconds to include new posts.
+    async fn unread_count(&self, ctx: &Context<'_>) -> GqlResult<usize> {
+        ctx.read_marker_persist().unread_count(self).await.extend()
+    }
+}
+
+id_obj_impls!(ReadMarker);
+
+impl ReadMarker {
+    pub fn create(
+        account_id: Thing,
+        target: ReadTarget,
+        target_id: Thing,
+        last_read_id: Option<Thing>,
+        read_count: u64,
+        ids: &dyn IdGen,
+    ) -> srql::CreateStatement {
+        let mut create = vec![];
+        account_id.push_field(srql::field("account_id"), &mut create);
+        target.push_field(srql::field("target"), &mut create);
+        target_id.push_field(srql::field("target_id"), &mut create);
+        last_read_id.push_field(srql::field("last_read_id"), &mut create);
+        read_count.push_field(srql::field("read_count"), &mut create);
+        srql::obj_create_query(READ_MARKER_TABLE_NAME, create, ids)
+    }
+}
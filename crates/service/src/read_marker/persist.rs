@@ -0,0 +1,326 @@
+#[cfg(test)]
+mod tests;
+
+use tracing::instrument;
+
+use super::{ReadMarker, ReadTarget, READ_MARKER_TABLE_NAME};
+use crate::{
+    account::CurrentAccount,
+    board::BoardPersist,
+    event::post_count,
+    persist::Persist,
+    post::{PostPersist, POST_TABLE_NAME},
+    prelude::*,
+    query::{SRQL_ORDER_ASC, SRQL_ORDER_DESC},
+};
+
+pub struct ReadMarkerPersist<'a> {
+    persist: &'a Persist,
+    current: &'a CurrentAccount,
+}
+
+impl<'a> ReadMarkerPersist<'a> {
+    pub fn new(persist: &'a Persist, current: &'a CurrentAccount) -> Self {
+        Self { persist, current }
+    }
+
+    /// Gets the current account's read marker for a board or conversation.
+    #[instrument(skip_all)]
+    pub async fn get(&self, target: ReadTarget, id: &str) -> Result<Option<ReadMarker>> {
+        let account_id = self.current.id()?.to_account_thing();
+        let markers: Vec<ReadMarker> = self
+            .persist
+            .db()
+            .query(srql::SelectStatement {
+                expr: srql::Fields::all(),
+                what: srql::table(READ_MARKER_TABLE_NAME),
+                cond: srql::cond_and(
+                    field_eq("account_id", account_id).into(),
+                    field_eq("target_id", target.thing(id)).into(),
+                ),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        Ok(markers.into_iter().next())
+    }
+
+    /// Lists all of the current account's read markers.
+    #[instrument(skip_all)]
+    pub async fn list(&self) -> Result<Vec<ReadMarker>> {
+        let account_id = self.current.id()?.to_account_thing();
+        let markers = self
+            .persist
+            .db()
+            .query(srql::SelectStatement {
+                expr: srql::Fields::all(),
+                what: srql::table(READ_MARKER_TABLE_NAME),
+                cond: field_eq("account_id", account_id).into(),
+                order: srql::Orders(vec![srql::Order {
+                    order: srql::field("id"),
+                    direction: SRQL_ORDER_ASC,
+                    ..Default::default()
+                }])
+                .into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        Ok(markers)
+    }
+
+    /// Marks a board or conversation as read up to the given post. If no post
+    /// is given, everything currently in it is marked as read.
+    ///
+    /// Markers only ever move forwards, so marking an older post as read does
+    /// nothing.
+    #[instrument(skip_all)]
+    pub async fn mark_read(
+        &self,
+        target: ReadTarget,
+        id: &str,
+        last_read_id: Option<&str>,
+    ) -> Result<ReadMarker> {
+        let account_id = self.current.id()?.to_account_thing();
+
+        let exists = match target {
+            ReadTarget::Board => BoardPersist::new(self.persist, self.current)
+                .get(id)
+                .await?
+                .is_some(),
+            ReadTarget::Conversation => PostPersist::new(self.persist, self.current)
+                .get(id)
+                .await?
+                .is_some(),
+        };
+        if !exists {
+            return Err(Error::ReadTargetInvalid);
+        }
+
+        let target_id = target.thing(id);
+        let last_read_id = match last_read_id {
+            Some(last_read_id) => Some(srql::Thing::from((
+                POST_TABLE_NAME.to_owned(),
+                last_read_id.to_owned(),
+            ))),
+            None => self.newest_post(target, target_id.clone()).await?,
+        };
+
+        let existing = self.get(target, id).await?;
+        if let Some(marker) = &existing {
+            if marker.last_read_id >= last_read_id {
+                return Ok(marker.clone());
+            }
+        }
+        let read_count =
+            count_read(self.persist, target, &target_id, last_read_id.as_ref()).await?;
+
+        let query = match existing {
+            Some(marker) => {
+                let mut update = vec![];
+                last_read_id.push_field(srql::field("last_read_id"), &mut update);
+                read_count.push_field(srql::field("read_count"), &mut update);
+                let Some(update) = srql::obj_update_query(marker.id, update) else {
+                    return Err("".into());
+                };
+                srql::Statement::Update(update)
+            }
+            None => srql::Statement::Create(ReadMarker::create(
+                account_id,
+                target,
+                target_id,
+                last_read_id,
+                read_count,
+                self.persist.ids(),
+            )),
+        };
+
+        let marker = self.persist.db().query(query).await?.take(0)?;
+        match marker {
+            Some(marker) => Ok(marker),
+            None => Err(Error::UnavailableIdent),
+        }
+    }
+
+    /// Counts the posts made in a marker's board or conversation since the
+    /// last read post, from the number of posts in it and the number that
+    /// had been read. Markers from before the number read was kept are
+    /// counted directly.
+    #[instrument(skip_all)]
+    pub async fn unread_count(&self, marker: &ReadMarker) -> Result<usize> {
+        if let Some(read_count) = marker.read_count {
+            let posts = post_count(self.persist, &marker.target_id).await?;
+            return Ok(usize::try_from(posts.saturating_sub(read_count)).unwrap_or(usize::MAX));
+        }
+
+        let mut cond = Some(field_eq_idiom(
+            marker.target.post_field(),
+            marker.target_id.clone(),
+        ));
+        if let Some(last_read_id) = &marker.last_read_id {
+            cond = srql::cond_and(
+                cond,
+                srql::Cond(
+                    srql::Expression::Binary {
+                        l: srql::field("id").into(),
+                        o: srql::Operator::MoreThan,
+                        r: last_read_id.clone().into(),
+                    }
+                    .into(),
+                )
+                .into(),
+            );
+        }
+
+        let count: Option<usize> = self
+            .persist
+            .db()
+            .query(srql::count_query(POST_TABLE_NAME, cond))
+            .await?
+            .take("count")?;
+        Ok(count.unwrap_or_default())
+    }
+
+    async fn newest_post(
+        &self,
+        target: ReadTarget,
+        target_id: srql::Thing,
+    ) -> Result<Option<srql::Thing>> {
+        let newest = self
+            .persist
+            .db()
+            .query(srql::SelectStatement {
+                expr: srql::Fields(
+                    vec![srql::Field::Single {
+                        expr: srql::field("id").into(),
+                        alias: None,
+                    }],
+                    true,
+                ),
+                what: srql::table(POST_TABLE_NAME),
+                cond: field_eq_idiom(target.post_field(), target_id).into(),
+                order: srql::Orders(vec![srql::Order {
+                    order: srql::field("id"),
+                    direction: SRQL_ORDER_DESC,
+                    ..Default::default()
+                }])
+                .into(),
+                limit: srql::Limit(srql::Number::Int(1).into()).into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        Ok(newest)
+    }
+}
+
+/// Recounts how many posts each read marker has read, correcting those that
+/// have drifted, such as when read posts are deleted. Returns how many were
+/// corrected.
+#[instrument(skip_all)]
+pub async fn reconcile_read_counts(persist: &Persist) -> Result<usize> {
+    let markers: Vec<ReadMarker> = persist
+        .db()
+        .query(srql::SelectStatement {
+            expr: srql::Fields::all(),
+            what: srql::table(READ_MARKER_TABLE_NAME),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+
+    let mut corrected = 0;
+    for marker in markers {
+        let read_count = count_read(
+            persist,
+            marker.target,
+            &marker.target_id,
+            marker.last_read_id.as_ref(),
+        )
+        .await?;
+        if marker.read_count == Some(read_count) {
+            continue;
+        }
+
+        persist
+            .db()
+            .query(srql::UpdateStatement {
+                what: srql::thing(marker.id),
+                data: srql::Data::SetExpression(vec![(
+                    srql::field("read_count"),
+                    srql::Operator::Equal,
+                    read_count.into(),
+                )])
+                .into(),
+                output: srql::Output::None.into(),
+                ..Default::default()
+            })
+            .await?
+            .check()?;
+        corrected += 1;
+    }
+    Ok(corrected)
+}
+
+/// Counts the posts in a board or conversation up to and including the last
+/// read one.
+async fn count_read(
+    persist: &Persist,
+    target: ReadTarget,
+    target_id: &srql::Thing,
+    last_read_id: Option<&srql::Thing>,
+) -> Result<u64> {
+    let Some(last_read_id) = last_read_id else {
+        return Ok(0);
+    };
+    let cond = srql::cond_and(
+        field_eq_idiom(target.post_field(), target_id.clone()).into(),
+        srql::Cond(
+            srql::Expression::Binary {
+                l: srql::field("id").into(),
+                o: srql::Operator::LessThanOrEqual,
+                r: last_read_id.clone().into(),
+            }
+            .into(),
+        )
+        .into(),
+    );
+    let count: Option<u64> = persist
+        .db()
+        .query(srql::count_query(POST_TABLE_NAME, cond))
+        .await?
+        .take("count")?;
+    Ok(count.unwrap_or_default())
+}
+
+fn field_eq(field: &str, value: srql::Thing) -> srql::Cond {
+    field_eq_idiom(srql::field(field), value)
+}
+
+fn field_eq_idiom(field: srql::Idiom, value: srql::Thing) -> srql::Cond {
+    srql::Cond(
+        srql::Expression::Binary {
+            l: field.into(),
+            o: srql::Operator::Equal,
+            r: value.into(),
+        }
+        .into(),
+    )
+}
+
+#[cfg(test)]
+pub mod testing {
+    use crate::account::testing::TestData;
+
+    use super::ReadMarkerPersist;
+
+    pub trait ReadMarkerTestData {
+        fn read_marker(&self) -> ReadMarkerPersist<'_>;
+    }
+
+    impl ReadMarkerTestData for TestData {
+        fn read_marker(&self) -> ReadMarkerPersist<'_> {
+            ReadMarkerPersist::new(&self.persist, &self.current)
+        }
+    }
+}
@@ -0,0 +1,216 @@
+use pretty_assertions::assert_eq;
+
+use super::{testing::ReadMarkerTestData as _, *};
+use crate::{
+    account::testing::*,
+    board::testing::BoardTestData as _,
+    event::{catch_up, PostCountsProjection},
+    post::testing::PostTestData as _,
+};
+
+/// Unread counts are taken from the post counts projection, so it has to
+/// catch up with new posts before they're counted.
+async fn unread_count(data: &TestData, marker: &ReadMarker) -> usize {
+    catch_up(&data.persist, &PostCountsProjection)
+        .await
+        .unwrap();
+    data.read_marker().unread_count(marker).await.unwrap()
+}
+
+#[tokio::test]
+async fn test_mark_read_board() {
+    let (data, acc) = TestData::with_user().await;
+    let board = data.generate_board().await;
+    let board_id = board.id.to_gql_id();
+    data.generate_post_in(&board.id).await;
+    let newest = data.generate_post_in(&board.id).await;
+    // Posts outside the board shouldn't be counted.
+    data.generate_post().await;
+
+    let res = data
+        .read_marker()
+        .mark_read(ReadTarget::Board, &board_id, None)
+        .await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+
+    let marker = res.unwrap();
+    assert_eq!(marker.account_id, acc.id);
+    assert_eq!(marker.target, ReadTarget::Board);
+    assert_eq!(marker.target_id, board.id);
+    assert_eq!(marker.last_read_id, Some(newest.id));
+    assert_eq!(unread_count(&data, &marker).await, 0);
+
+    data.generate_post_in(&board.id).await;
+    data.generate_post_in(&board.id).await;
+    assert_eq!(unread_count(&data, &marker).await, 2);
+}
+
+#[tokio::test]
+async fn test_mark_read_conversation() {
+    let (data, _) = TestData::with_user().await;
+    let post = data.generate_post().await;
+    let post_id = post.id.to_gql_id();
+
+    let first = data.generate_reply(&post.id).await;
+    data.generate_reply(&post.id).await;
+    data.generate_reply(&post.id).await;
+
+    let res = data
+        .read_marker()
+        .mark_read(
+            ReadTarget::Conversation,
+            &post_id,
+            Some(&first.id.to_gql_id()),
+        )
+        .await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+
+    let marker = res.unwrap();
+    assert_eq!(marker.last_read_id, Some(first.id));
+    assert_eq!(unread_count(&data, &marker).await, 2);
+}
+
+#[tokio::test]
+async fn test_mark_read_empty() {
+    let (data, _) = TestData::with_user().await;
+    let board = data.generate_board().await;
+
+    let marker = data
+        .read_marker()
+        .mark_read(ReadTarget::Board, &board.id.to_gql_id(), None)
+        .await
+        .unwrap();
+    assert!(marker.last_read_id.is_none());
+
+    data.generate_post_in(&board.id).await;
+    assert_eq!(unread_count(&data, &marker).await, 1);
+}
+
+#[tokio::test]
+async fn test_mark_read_backwards() {
+    let (data, _) = TestData::with_user().await;
+    let board = data.generate_board().await;
+    let board_id = board.id.to_gql_id();
+    let older = data.generate_post_in(&board.id).await;
+    let newer = data.generate_post_in(&board.id).await;
+
+    data.read_marker()
+        .mark_read(ReadTarget::Board, &board_id, Some(&newer.id.to_gql_id()))
+        .await
+        .unwrap();
+
+    let res = data
+        .read_marker()
+        .mark_read(ReadTarget::Board, &board_id, Some(&older.id.to_gql_id()))
+        .await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+    assert_eq!(res.unwrap().last_read_id, Some(newer.id));
+
+    let markers = data.read_marker().list().await.unwrap();
+    assert_eq!(markers.len(), 1);
+}
+
+#[tokio::test]
+async fn test_mark_read_nonexistent() {
+    let (data, _) = TestData::with_user().await;
+
+    let res = data
+        .read_marker()
+        .mark_read(ReadTarget::Board, "test", None)
+        .await;
+    println!("{res:?}");
+    assert!(matches!(res, Err(Error::ReadTargetInvalid)));
+}
+
+#[tokio::test]
+async fn test_mark_read_unauthenticated() {
+    let data = TestData::new().await;
+    let board = data.generate_board().await;
+
+    let res = data
+        .read_marker()
+        .mark_read(ReadTarget::Board, &board.id.to_gql_id(), None)
+        .await;
+    println!("{res:?}");
+    assert!(matches!(res, Err(Error::Unauthenticated)));
+}
+
+#[tokio::test]
+async fn test_list() {
+    let (mut data, acc) = TestData::with_user().await;
+    let boards = data.generate_boards(2).await;
+    let mut expected = vec![];
+    for board in &boards {
+        expected.push(
+            data.read_marker()
+                .mark_read(ReadTarget::Board, &board.id.to_gql_id(), None)
+                .await
+                .unwrap()
+                .id,
+        );
+    }
+    let second = expected[1].clone();
+    expected.sort();
+
+    let other = data.account().create_test_user().await;
+    data.login_as(&other);
+    data.read_marker()
+        .mark_read(ReadTarget::Board, &boards[0].id.to_gql_id(), None)
+        .await
+        .unwrap();
+
+    data.login_as(&acc);
+    let res = data.read_marker().list().await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+
+    let res: Vec<_> = res.unwrap().into_iter().map(|marker| marker.id).collect();
+    assert_eq!(res, expected);
+
+    let res = data
+        .read_marker()
+        .get(ReadTarget::Board, &boards[1].id.to_gql_id())
+        .await
+        .unwrap();
+    assert_eq!(res.map(|marker| marker.id), Some(second));
+}
+
+#[tokio::test]
+async fn test_reconcile_read_counts() {
+    let (data, _) = TestData::with_user().await;
+    let board = data.generate_board().await;
+    data.generate_post_in(&board.id).await;
+    data.generate_post_in(&board.id).await;
+    let marker = data
+        .read_marker()
+        .mark_read(ReadTarget::Board, &board.id.to_gql_id(), None)
+        .await
+        .unwrap();
+    assert_eq!(marker.read_count, Some(2));
+    assert_eq!(reconcile_read_counts(&data.persist).await.unwrap(), 0);
+
+    // A read post being deleted leaves the number read too high.
+    let post = data.generate_post_in(&board.id).await;
+    let marker = data
+        .read_marker()
+        .mark_read(ReadTarget::Board, &board.id.to_gql_id(), None)
+        .await
+        .unwrap();
+    assert_eq!(marker.read_count, Some(3));
+    data.post().delete(&post.id.id.to_raw()).await.unwrap();
+    data.generate_post_in(&board.id).await;
+    assert_eq!(unread_count(&data, &marker).await, 0);
+
+    assert_eq!(reconcile_read_counts(&data.persist).await.unwrap(), 1);
+    let marker = data
+        .read_marker()
+        .get(ReadTarget::Board, &board.id.to_gql_id())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(marker.read_count, Some(2));
+    assert_eq!(unread_count(&data, &marker).await, 1);
+}
@@ -0,0 +1,47 @@
+use async_graphql::{Context, Object, ID};
+use tracing::instrument;
+
+use super::{ReadMarker, ReadTarget};
+use crate::prelude::*;
+
+#[derive(Default)]
+pub struct ReadMarkerQuery;
+
+#[Object]
+impl ReadMarkerQuery {
+    /// Gets the current account's read marker for a board or conversation.
+    #[instrument(skip_all)]
+    async fn read_marker(
+        &self,
+        ctx: &Context<'_>,
+        target: ReadTarget,
+        id: ID,
+    ) -> GqlResult<Option<ReadMarker>> {
+        ctx.read_marker_persist().get(target, &id).await.extend()
+    }
+}
+
+#[derive(Default)]
+pub struct ReadMarkerMutation;
+
+#[Object]
+impl ReadMarkerMutation {
+    /// Marks a board or conversation as read up to the given post. If no post
+    /// is given, everything currently in it is marked as read.
+    ///
+    /// Markers only ever move forwards, so marking an older post as read does
+    /// nothing.
+    #[instrument(skip_all)]
+    async fn mark_read(
+        &self,
+        ctx: &Context<'_>,
+        target: ReadTarget,
+        id: ID,
+        last_read_id: Option<ID>,
+    ) -> GqlResult<ReadMarker> {
+        ctx.read_marker_persist()
+            .mark_read(target, &id, last_read_id.as_deref().map(String::as_str))
+            .await
+            .extend()
+    }
+}
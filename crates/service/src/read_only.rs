@@ -0,0 +1,321 @@
+//! Turning away writes while the instance's storage is having problems.
+//!
+//! Admins can make the instance read-only, and it becomes read-only by itself
+//! when enough writes fail in a row. While it is, mutations and REST writes
+//! are rejected with a `ReadOnly` error, but queries and subscriptions keep
+//! working, so the instance stays partly usable until storage recovers.
+//!
+//! When the instance became read-only by itself, writes are tried again once
+//! the cooldown has passed. If the next one fails too, the cooldown starts
+//! over.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
+
+use async_graphql::{
+    extensions::{Extension, ExtensionContext, ExtensionFactory, NextExecute, NextParseQuery},
+    parser::types::{ExecutableDocument, OperationType, Selection},
+    ErrorExtensions as _, Pos, Response, ServerResult, Value, Variables,
+};
+use axum::{
+    extract::State,
+    http::{header::RETRY_AFTER, Method, Request},
+    middleware::Next,
+    response::{IntoResponse, Response as HttpResponse},
+};
+use chrono::{DateTime, Utc};
+use tracing::{debug, warn};
+
+use crate::{config::ReadOnlyConfig, error::ErrorResponse, prelude::*, provider::SharedClock};
+
+/// Mutations that still work while the instance is read-only, so that people
+/// can sign in and admins can make it writable again.
+static ALLOWED_MUTATIONS: &[&str] = &[
+    "login",
+    "verifyTwoFactor",
+    "beginPasskeyLogin",
+    "finishPasskeyLogin",
+    "devLogin",
+    "refresh",
+    "refreshToken",
+    "setReadOnly",
+    "setMinClientVersion",
+];
+
+/// Whether the instance is read-only, shared between everything that writes.
+#[derive(Clone)]
+pub struct ReadOnlyMode {
+    inner: Arc<ReadOnlyInner>,
+}
+
+struct ReadOnlyInner {
+    config: ReadOnlyConfig,
+    clock: SharedClock,
+    forced: AtomicBool,
+    failures: Mutex<Failures>,
+}
+
+#[derive(Default)]
+struct Failures {
+    in_a_row: u32,
+    tripped_until: Option<DateTime<Utc>>,
+}
+
+impl ReadOnlyMode {
+    #[must_use]
+    pub fn new(config: ReadOnlyConfig, clock: SharedClock) -> Self {
+        Self {
+            inner: Arc::new(ReadOnlyInner {
+                forced: AtomicBool::new(config.enabled),
+                config,
+                clock,
+                failures: Mutex::default(),
+            }),
+        }
+    }
+
+    /// Whether writes are being turned away right now.
+    pub fn is_read_only(&self) -> bool {
+        if self.inner.forced.load(Ordering::Relaxed) {
+            return true;
+        }
+        let now = self.inner.clock.now();
+        self.failures()
+            .tripped_until
+            .is_some_and(|until| now < until)
+    }
+
+    /// Makes the instance read-only, or writable again. Making it writable
+    /// also forgets about any failed writes.
+    pub fn set(&self, enabled: bool) {
+        self.inner.forced.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            *self.failures() = Failures::default();
+        }
+    }
+
+    /// Records whether a write succeeded, making the instance read-only for
+    /// a while if too many have failed in a row.
+    pub fn record_write(&self, ok: bool) {
+        let mut failures = self.failures();
+        if ok {
+            *failures = Failures::default();
+            return;
+        }
+
+        failures.in_a_row = failures.in_a_row.saturating_add(1);
+        let after = self.inner.config.after_failures;
+        if after > 0 && failures.in_a_row >= after {
+            let cooldown = chrono::Duration::from_std(self.inner.config.cooldown)
+                .unwrap_or_else(|_| chrono::Duration::zero());
+            if failures.tripped_until.is_none() {
+                warn!(
+                    failures = failures.in_a_row,
+                    "Writes keep failing, the instance is read-only for now"
+                );
+            }
+            failures.tripped_until = Some(self.inner.clock.now() + cooldown);
+        }
+    }
+
+    fn failures(&self) -> std::sync::MutexGuard<'_, Failures> {
+        self.inner
+            .failures
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+/// A GraphQL extension that turns mutations away while the instance is
+/// read-only, and tells the [`ReadOnlyMode`] how the ones that ran went.
+pub struct ReadOnlyGuard(pub ReadOnlyMode);
+
+impl ExtensionFactory for ReadOnlyGuard {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(ReadOnlyExtension {
+            mode: self.0.clone(),
+            writes: Mutex::default(),
+        })
+    }
+}
+
+struct ReadOnlyExtension {
+    mode: ReadOnlyMode,
+    /// The name of each operation in the request, and whether it writes.
+    writes: Mutex<Vec<(Option<String>, bool)>>,
+}
+
+impl ReadOnlyExtension {
+    fn is_write(&self, operation_name: Option<&str>) -> bool {
+        let writes = self
+            .writes
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let operation = match operation_name {
+            Some(name) => writes
+                .iter()
+                .find(|(op_name, _)| op_name.as_deref() == Some(name)),
+            None if writes.len() == 1 => writes.first(),
+            None => None,
+        };
+        operation.is_some_and(|(_, write)| *write)
+    }
+}
+
+#[async_trait::async_trait]
+impl Extension for ReadOnlyExtension {
+    async fn parse_query(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        query: &str,
+        variables: &Variables,
+        next: NextParseQuery<'_>,
+    ) -> ServerResult<ExecutableDocument> {
+        let document = next.run(ctx, query, variables).await?;
+        let writes =
+            document
+                .operations
+                .iter()
+                .map(|(name, operation)| {
+                    let write = operation.node.ty == OperationType::Mutation
+                    && operation.node.selection_set.node.items.iter().any(|selection| {
+                        !matches!(
+                            &selection.node,
+                            Selection::Field(field)
+                                if ALLOWED_MUTATIONS.contains(&field.node.name.node.as_str())
+                        )
+                    });
+                    (name.map(ToString::to_string), write)
+                })
+                .collect();
+        *self
+            .writes
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner) = writes;
+        Ok(document)
+    }
+
+    async fn execute(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        operation_name: Option<&str>,
+        next: NextExecute<'_>,
+    ) -> Response {
+        if !self.is_write(operation_name) {
+            return next.run(ctx, operation_name).await;
+        }
+        if self.mode.is_read_only() {
+            debug!("Turning away mutation while read-only");
+            return Response::from_errors(vec![Error::ReadOnly
+                .extend()
+                .into_server_error(Pos::default())]);
+        }
+
+        let res = next.run(ctx, operation_name).await;
+        let failed = res.errors.iter().any(|err| {
+            matches!(
+                err.extensions.as_ref().and_then(|ext| ext.get("code")),
+                Some(Value::String(code)) if code == "InternalServerError"
+            )
+        });
+        self.mode.record_write(!failed);
+        res
+    }
+}
+
+/// Middleware that turns away REST requests that write while the instance is
+/// read-only, and tells the [`ReadOnlyMode`] how the ones that ran went.
+pub async fn reject_writes<B>(
+    State(mode): State<ReadOnlyMode>,
+    req: Request<B>,
+    next: Next<B>,
+) -> HttpResponse {
+    if matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+        return next.run(req).await;
+    }
+    if mode.is_read_only() {
+        debug!("Turning away write while read-only");
+        let (status, body): ErrorResponse = Error::ReadOnly.into();
+        let retry_after = mode.inner.config.cooldown.as_secs().max(1);
+        return (status, [(RETRY_AFTER, retry_after.to_string())], body).into_response();
+    }
+
+    let res = next.run(req).await;
+    mode.record_write(!res.status().is_server_error());
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::provider::MockClock;
+
+    fn mode(clock: &MockClock) -> ReadOnlyMode {
+        ReadOnlyMode::new(
+            ReadOnlyConfig {
+                enabled: false,
+                after_failures: 2,
+                cooldown: Duration::from_secs(30),
+            },
+            Arc::new(clock.clone()),
+        )
+    }
+
+    #[test]
+    fn test_set() {
+        let mode = mode(&MockClock::default());
+        assert!(!mode.is_read_only());
+
+        mode.set(true);
+        assert!(mode.is_read_only());
+        // Successful writes don't make a read-only instance writable.
+        mode.record_write(true);
+        assert!(mode.is_read_only());
+
+        mode.set(false);
+        assert!(!mode.is_read_only());
+    }
+
+    #[test]
+    fn test_trips_after_failures() {
+        let clock = MockClock::default();
+        let mode = mode(&clock);
+
+        mode.record_write(false);
+        mode.record_write(true);
+        mode.record_write(false);
+        assert!(!mode.is_read_only());
+        mode.record_write(false);
+        assert!(mode.is_read_only());
+
+        clock.advance(chrono::Duration::seconds(31));
+        assert!(!mode.is_read_only());
+        // One more failure is enough to start the cooldown over.
+        mode.record_write(false);
+        assert!(mode.is_read_only());
+
+        mode.set(false);
+        assert!(!mode.is_read_only());
+        mode.record_write(false);
+        assert!(!mode.is_read_only());
+    }
+
+    #[test]
+    fn test_never_trips() {
+        let mode = ReadOnlyMode::new(
+            ReadOnlyConfig {
+                after_failures: 0,
+                ..Default::default()
+            },
+            Arc::new(MockClock::default()),
+        );
+        for _ in 0..100 {
+            mode.record_write(false);
+        }
+        assert!(!mode.is_read_only());
+    }
+}
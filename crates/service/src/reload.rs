@@ -0,0 +1,259 @@
+//! Changing some of the config while the server is running.
+//!
+//! Rate limits, feature flags and log levels can be reloaded without
+//! restarting the server, so that open connections and subscriptions aren't
+//! dropped. The config is reloaded from where it was first loaded from, when
+//! the server is sent `SIGHUP` or an admin calls `reloadConfig`. The rest of
+//! the config is only read when the server starts, so changes to it are
+//! ignored until then.
+//!
+//! Everything that reads the reloadable config does so on each request, so
+//! the new config is used from the next request on. Anything that has to do
+//! more than read it, like changing log levels, is told about changes over a
+//! watch channel.
+
+use std::sync::{Arc, OnceLock};
+
+use arc_swap::ArcSwap;
+use async_graphql::SimpleObject;
+use tokio::{sync::watch, task::JoinHandle};
+use tracing::{debug, info, warn, Level};
+
+use crate::config::{ConfigProblems, LogLevels, ReloadableConfig, ServiceConfigBuilder};
+
+/// The reloadable config, shared between everything that reads it.
+#[derive(Clone)]
+pub struct LiveConfig {
+    inner: Arc<LiveConfigInner>,
+}
+
+struct LiveConfigInner {
+    current: ArcSwap<ReloadableConfig>,
+    changed: watch::Sender<Arc<ReloadableConfig>>,
+    source: Option<ServiceConfigBuilder>,
+}
+
+/// What happened when the config was reloaded.
+#[derive(Debug, Clone, PartialEq, Eq, SimpleObject)]
+pub struct ConfigReload {
+    /// Whether any of the reloadable config changed.
+    pub changed: bool,
+    /// Why the config couldn't be reloaded. When there are any, the config
+    /// that was already in use is kept.
+    pub problems: Vec<String>,
+}
+
+impl LiveConfig {
+    /// Starts with `config`, reloading from `source` when asked to. Without
+    /// a source, the config can still be [set](Self::set), but not reloaded.
+    #[must_use]
+    pub fn new(config: ReloadableConfig, source: Option<ServiceConfigBuilder>) -> Self {
+        let config = Arc::new(config);
+        let (changed, _) = watch::channel(config.clone());
+        Self {
+            inner: Arc::new(LiveConfigInner {
+                current: ArcSwap::new(config),
+                changed,
+                source,
+            }),
+        }
+    }
+
+    /// The config to use for the current request.
+    #[must_use]
+    pub fn load(&self) -> Arc<ReloadableConfig> {
+        self.inner.current.load_full()
+    }
+
+    /// Gets told about every change to the config from now on.
+    #[must_use]
+    pub fn subscribe(&self) -> watch::Receiver<Arc<ReloadableConfig>> {
+        self.inner.changed.subscribe()
+    }
+
+    /// Replaces the config, returning whether anything changed.
+    pub fn set(&self, config: ReloadableConfig) -> bool {
+        if *self.inner.current.load_full() == config {
+            return false;
+        }
+        let config = Arc::new(config);
+        self.inner.current.store(config.clone());
+        self.inner.changed.send_replace(config);
+        true
+    }
+
+    /// Loads the config again from where it was first loaded from, and
+    /// starts using the reloadable parts of it. If it can't be loaded, the
+    /// config that's already in use is kept, and the problems with it are
+    /// returned. Returns nothing if there's nowhere to reload from.
+    #[must_use]
+    pub fn reload(&self) -> Option<ConfigReload> {
+        let source = self.inner.source.clone()?;
+        let config = match source.build() {
+            Ok(config) => config,
+            Err(err) => {
+                let problems = match err.downcast_ref::<ConfigProblems>() {
+                    Some(ConfigProblems(problems)) => problems.clone(),
+                    None => vec![format!("{err:#}")],
+                };
+                warn!(
+                    ?problems,
+                    "Unable to reload the config, keeping the old one"
+                );
+                return Some(ConfigReload {
+                    changed: false,
+                    problems,
+                });
+            }
+        };
+
+        let changed = self.set(config.reloadable());
+        info!(changed, "Reloaded the config");
+        Some(ConfigReload {
+            changed,
+            problems: vec![],
+        })
+    }
+}
+
+/// Reloads the config whenever the server is sent `SIGHUP`.
+#[cfg(unix)]
+pub fn spawn_reload_on_hangup(live: LiveConfig) -> JoinHandle<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    tokio::spawn(async move {
+        let mut hangups = match signal(SignalKind::hangup()) {
+            Ok(hangups) => hangups,
+            Err(err) => {
+                warn!(error = ?err, "Unable to listen for SIGHUP, the config can't be reloaded by it");
+                return;
+            }
+        };
+        while hangups.recv().await.is_some() {
+            debug!("Received SIGHUP");
+            if live.reload().is_none() {
+                warn!("Received SIGHUP, but there's nowhere to reload the config from");
+            }
+        }
+    })
+}
+
+/// Changes the log levels whenever they're reloaded.
+pub fn spawn_log_level_updates(live: &LiveConfig) -> JoinHandle<()> {
+    let mut changes = live.subscribe();
+    tokio::spawn(async move {
+        while changes.changed().await.is_ok() {
+            let levels = changes.borrow_and_update().log_levels;
+            set_log_levels(levels);
+        }
+    })
+}
+
+type SetLevel = Box<dyn Fn(Level) + Send + Sync>;
+
+/// Changes the level of each place logs go to.
+struct LogFilters {
+    stdout: SetLevel,
+    file: SetLevel,
+}
+
+static LOG_FILTERS: OnceLock<LogFilters> = OnceLock::new();
+
+/// Lets the log levels be changed by reloading the config. Called once
+/// logging has been set up.
+pub(crate) fn register_log_filters(
+    stdout: impl Fn(Level) + Send + Sync + 'static,
+    file: impl Fn(Level) + Send + Sync + 'static,
+) {
+    let filters = LogFilters {
+        stdout: Box::new(stdout),
+        file: Box::new(file),
+    };
+    if LOG_FILTERS.set(filters).is_err() {
+        warn!("Logging was set up more than once, only the first log levels can be reloaded");
+    }
+}
+
+/// Changes the log levels, if logging has been set up by the service.
+fn set_log_levels(levels: LogLevels) {
+    let Some(filters) = LOG_FILTERS.get() else {
+        return;
+    };
+    (filters.stdout)(levels.stdout.into());
+    (filters.file)(levels.file.into());
+    info!(stdout = %levels.stdout, file = %levels.file, "Changed the log levels");
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env, fs,
+        path::{Path, PathBuf},
+    };
+
+    use super::*;
+    use crate::config::RateBudget;
+
+    fn config_file(contents: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("plazer-reload-{}.toml", ulid::Ulid::new()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn live(path: &Path) -> LiveConfig {
+        let source = ServiceConfigBuilder::new().config_path(path.display().to_string());
+        let config = source.clone().build().unwrap().reloadable();
+        LiveConfig::new(config, Some(source))
+    }
+
+    #[test]
+    fn test_reload() {
+        let path = config_file("rate_limit_auth = 5\npublic_stats = false\n");
+        let live = live(&path);
+        let mut changes = live.subscribe();
+        assert_eq!(live.load().rate_limits.auth, RateBudget::per_minute(5));
+        assert!(!live.load().features.public_stats);
+
+        assert_eq!(
+            live.reload(),
+            Some(ConfigReload {
+                changed: false,
+                problems: vec![],
+            })
+        );
+        assert!(!changes.has_changed().unwrap());
+
+        fs::write(&path, "rate_limit_auth = 7\npublic_stats = true\n").unwrap();
+        assert_eq!(
+            live.reload(),
+            Some(ConfigReload {
+                changed: true,
+                problems: vec![],
+            })
+        );
+        assert_eq!(live.load().rate_limits.auth, RateBudget::per_minute(7));
+        assert!(live.load().features.public_stats);
+        assert!(changes.has_changed().unwrap());
+        assert!(changes.borrow_and_update().features.public_stats);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_reload_problems() {
+        let path = config_file("rate_limit_auth = 5\n");
+        let live = live(&path);
+
+        fs::write(&path, "rate_limit_auth = \"lots\"\n").unwrap();
+        let reload = live.reload().unwrap();
+        assert!(!reload.changed);
+        assert!(!reload.problems.is_empty());
+        assert_eq!(live.load().rate_limits.auth, RateBudget::per_minute(5));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_no_source() {
+        let live = LiveConfig::new(ReloadableConfig::default(), None);
+        assert_eq!(live.reload(), None);
+    }
+}
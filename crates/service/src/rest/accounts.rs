@@ -0,0 +1,101 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use tracing::instrument;
+
+use super::{
+    models::{AccountBody, CredsBody, SessionBody, VerifiedBody, VerifyEmailBody},
+    sessions::session,
+    Current, RestState,
+};
+use crate::{
+    account::{AuthenticatedAccount, CreateAccount, CurrentAccount},
+    error::{Error, ErrorResponse},
+    session::ClientMeta,
+};
+
+/// `POST /api/v1/accounts`
+#[instrument(skip_all)]
+pub async fn create(
+    State(state): State<RestState>,
+    client: ClientMeta,
+    Json(body): Json<CredsBody>,
+) -> Result<(StatusCode, Json<SessionBody>), ErrorResponse> {
+    body.validate(128)?;
+
+    let current = CurrentAccount::default();
+    let create = CreateAccount {
+        user_id: body.user_id,
+        pword: body.password,
+        invite: None,
+        bot: None,
+        accepted_policy_ids: Some(
+            body.accepted_policy_ids
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+        ),
+        birthdate: body.birthdate,
+        honeypot: body.honeypot,
+        form_shown_at: body.form_shown_at,
+        allow_confusable: None,
+        email: None,
+    };
+    let signals = create.signup_signals();
+    let acc = state.account_persist(&current).create(create).await?;
+    let account = state
+        .spam_persist(&current)
+        .check_account(acc.account, signals)
+        .await?;
+    let recorded = state
+        .session_persist(&current)
+        .record(account.id.clone(), Some(&client))
+        .await?;
+    let acc = AuthenticatedAccount::from(account).with_session(recorded);
+
+    Ok((StatusCode::CREATED, Json(session(&state, acc).await?)))
+}
+
+/// `GET /api/v1/accounts/me`
+#[instrument(skip_all)]
+pub async fn me(
+    State(state): State<RestState>,
+    Current(current): Current,
+) -> Result<Json<AccountBody>, ErrorResponse> {
+    match state.account_persist(&current).current().await? {
+        Some(acc) => Ok(Json(acc.into())),
+        None => Err(Error::NotFound.into()),
+    }
+}
+
+/// `POST /api/v1/accounts/verify-email`
+///
+/// Works without a bearer token, as the token in the body is enough.
+#[instrument(skip_all)]
+pub async fn verify_email(
+    State(state): State<RestState>,
+    Json(body): Json<VerifyEmailBody>,
+) -> Result<Json<VerifiedBody>, ErrorResponse> {
+    let current = CurrentAccount::default();
+    let verified_at = state
+        .account_persist(&current)
+        .verify_email(&body.token)
+        .await?;
+
+    Ok(Json(VerifiedBody { verified_at }))
+}
+
+/// `GET /api/v1/accounts/:id`
+#[instrument(skip_all)]
+pub async fn get(
+    State(state): State<RestState>,
+    Current(current): Current,
+    Path(id): Path<String>,
+) -> Result<Json<AccountBody>, ErrorResponse> {
+    match state.account_persist(&current).get(&id).await? {
+        Some(acc) => Ok(Json(acc.into())),
+        None => Err(Error::NotFound.into()),
+    }
+}
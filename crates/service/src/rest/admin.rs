@@ -0,0 +1,45 @@
+use axum::{
+    extract::{Query, State},
+    http::header::CONTENT_TYPE,
+    response::{IntoResponse, Response},
+    Json,
+};
+use tracing::instrument;
+
+use super::{
+    models::{UsageBody, UsageFormat, UsageQuery},
+    Current, RestState,
+};
+use crate::{
+    error::ErrorResponse,
+    stats::{usage_csv, usage_openmetrics, OPENMETRICS_CONTENT_TYPE},
+};
+
+/// `GET /api/v1/admin/usage`
+#[instrument(skip_all)]
+pub async fn usage(
+    State(state): State<RestState>,
+    Current(current): Current,
+    Query(query): Query<UsageQuery>,
+) -> Result<Response, ErrorResponse> {
+    let to = query
+        .to
+        .unwrap_or_else(|| state.persist.clock().now().date_naive());
+    let records = state.stats_persist(&current).usage(query.from, to).await?;
+
+    Ok(match query.format {
+        UsageFormat::Json => {
+            Json(records.into_iter().map(UsageBody::from).collect::<Vec<_>>()).into_response()
+        }
+        UsageFormat::Csv => (
+            [(CONTENT_TYPE, "text/csv; charset=utf-8")],
+            usage_csv(&records),
+        )
+            .into_response(),
+        UsageFormat::OpenMetrics => (
+            [(CONTENT_TYPE, OPENMETRICS_CONTENT_TYPE)],
+            usage_openmetrics(&records),
+        )
+            .into_response(),
+    })
+}
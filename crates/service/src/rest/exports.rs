@@ -0,0 +1,33 @@
+use axum::{
+    extract::{Path, State},
+    http::header::{CACHE_CONTROL, CONTENT_DISPOSITION, CONTENT_TYPE},
+    response::{IntoResponse, Response},
+};
+use tracing::instrument;
+
+use super::{Current, RestState};
+use crate::error::{Error, ErrorResponse};
+
+/// `GET /api/v1/exports/:id`
+///
+/// Downloads the archive of one of the current account's finished data
+/// exports.
+#[instrument(skip_all)]
+pub async fn download(
+    State(state): State<RestState>,
+    Current(current): Current,
+    Path(id): Path<String>,
+) -> Result<Response, ErrorResponse> {
+    let Some((_, archive)) = state.export_persist(&current).download(&id).await? else {
+        return Err(Error::NotFound.into());
+    };
+    let headers = [
+        (CONTENT_TYPE, "application/json".to_owned()),
+        (
+            CONTENT_DISPOSITION,
+            format!("attachment; filename=\"export-{id}.json\""),
+        ),
+        (CACHE_CONTROL, "private, no-store".to_owned()),
+    ];
+    Ok((headers, archive).into_response())
+}
@@ -0,0 +1,35 @@
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    Json,
+};
+use tracing::instrument;
+
+use super::{models::PostBody, RestState};
+use crate::{account::CurrentAccount, error::ErrorResponse, integration::SIGNATURE_HEADER};
+
+/// `POST /api/v1/integrations/:id/deliveries`
+///
+/// Deliveries are authenticated by their signature rather than a token, so
+/// external services only need the integration's secret.
+#[instrument(skip_all)]
+pub async fn deliver(
+    State(state): State<RestState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<(StatusCode, Json<PostBody>), ErrorResponse> {
+    let signature = headers
+        .get(SIGNATURE_HEADER)
+        .and_then(|value| value.to_str().ok());
+
+    let current = CurrentAccount::default();
+    let post = state
+        .integration_persist(&current)
+        .deliver(&id, &body, signature)
+        .await?;
+    let post = state.spam_persist(&current).check_post(post).await?;
+
+    Ok((StatusCode::CREATED, Json(post.into())))
+}
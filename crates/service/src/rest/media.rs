@@ -0,0 +1,157 @@
+use axum::{
+    body::Bytes,
+    extract::{rejection::BytesRejection, Path, Query, State},
+    headers::{ContentType, Range},
+    http::{
+        header::{
+            ACCEPT_RANGES, CACHE_CONTROL, CONTENT_RANGE, CONTENT_TYPE, REFERER,
+            X_CONTENT_TYPE_OPTIONS,
+        },
+        HeaderMap, StatusCode,
+    },
+    response::{IntoResponse, Response},
+    Json, TypedHeader,
+};
+use tracing::instrument;
+
+use super::{
+    models::{MediaBody, MediaContentQuery},
+    Current, RestState,
+};
+use crate::{
+    conv::ToGqlId as _,
+    error::{Error, ErrorResponse},
+    media::RequestedRange,
+};
+
+/// `POST /api/v1/media`
+///
+/// The body is the media itself, described by the `Content-Type` header.
+#[instrument(skip_all)]
+pub async fn upload(
+    State(state): State<RestState>,
+    Current(current): Current,
+    content_type: Option<TypedHeader<ContentType>>,
+    body: Result<Bytes, BytesRejection>,
+) -> Result<(StatusCode, Json<MediaBody>), ErrorResponse> {
+    let body = match body {
+        Ok(body) => body,
+        Err(rejection) if rejection.status() == StatusCode::PAYLOAD_TOO_LARGE => {
+            return Err(Error::TooLong("media".into()).into());
+        }
+        Err(rejection) => return Err(Error::InputInvalid(rejection.body_text()).into()),
+    };
+    let Some(TypedHeader(content_type)) = content_type else {
+        return Err(Error::InputInvalid("content type is required".into()).into());
+    };
+    state.policy_persist(&current).require_accepted().await?;
+    state
+        .account_persist(&current)
+        .require_verified_email()
+        .await?;
+
+    let media = state
+        .media_persist(&current)
+        .upload(&content_type.to_string(), body)
+        .await?;
+    Ok((StatusCode::CREATED, Json(media.into())))
+}
+
+/// `GET /api/v1/media/:id`
+#[instrument(skip_all)]
+pub async fn get(
+    State(state): State<RestState>,
+    Current(current): Current,
+    Path(id): Path<String>,
+) -> Result<Json<MediaBody>, ErrorResponse> {
+    match state.media_persist(&current).get(&id).await? {
+        Some(media) => Ok(Json(media.into())),
+        None => Err(Error::NotFound.into()),
+    }
+}
+
+/// `DELETE /api/v1/media/:id`
+#[instrument(skip_all)]
+pub async fn delete(
+    State(state): State<RestState>,
+    Current(current): Current,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ErrorResponse> {
+    match state.media_persist(&current).delete(&id).await? {
+        Some(_) => Ok(StatusCode::NO_CONTENT),
+        None => Err(Error::NotFound.into()),
+    }
+}
+
+/// `GET /api/v1/media/:id/content?token=...`
+///
+/// Serves a media's content through a signed URL, or just part of it if a
+/// `Range` is requested.
+#[instrument(skip_all)]
+pub async fn content(
+    State(state): State<RestState>,
+    Current(current): Current,
+    Path(id): Path<String>,
+    Query(query): Query<MediaContentQuery>,
+    range: Option<TypedHeader<Range>>,
+    headers: HeaderMap,
+) -> Result<Response, ErrorResponse> {
+    let referer = headers
+        .get(REFERER)
+        .and_then(|referer| referer.to_str().ok());
+    if !state.media_urls.allows_referer(referer) {
+        return Err(Error::HotlinkDisallowed.into());
+    }
+    let (account_id, expires_in) = state.media_urls.verify(&query.token, &id)?;
+    let cache = match account_id {
+        Some(account_id) => {
+            if current.id()?.as_str() != account_id {
+                return Err(Error::Unauthorized.into());
+            }
+            format!("private, max-age={expires_in}")
+        }
+        None => format!("public, max-age={expires_in}"),
+    };
+
+    let Some(media) = state.media_persist(&current).get(&id).await? else {
+        return Err(Error::NotFound.into());
+    };
+    let key = media.blob_id.to_gql_id();
+    let headers = [
+        (CONTENT_TYPE, media.content_type),
+        (ACCEPT_RANGES, "bytes".to_owned()),
+        (CACHE_CONTROL, cache),
+        (X_CONTENT_TYPE_OPTIONS, "nosniff".to_owned()),
+    ];
+
+    let range = RequestedRange::new(range.as_ref().map(|TypedHeader(range)| range), media.size);
+    let (status, range) = match range {
+        RequestedRange::Whole => (StatusCode::OK, None),
+        RequestedRange::Part(range) => (StatusCode::PARTIAL_CONTENT, Some(range)),
+        RequestedRange::Unsatisfiable => {
+            return Ok((
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                headers,
+                [(CONTENT_RANGE, format!("bytes */{}", media.size))],
+            )
+                .into_response());
+        }
+    };
+    let Some(bytes) = state.persist.blobs().get(&key, range.clone()).await? else {
+        return Err(Error::NotFound.into());
+    };
+
+    Ok(match range {
+        Some(range) => (
+            status,
+            headers,
+            [(
+                CONTENT_RANGE,
+                format!("bytes {}-{}/{}", range.start, range.end - 1, media.size),
+            )],
+            bytes,
+        )
+            .into_response(),
+        None => (status, headers, bytes).into_response(),
+    })
+}
@@ -0,0 +1,220 @@
+//! A REST API covering the core operations, for integrations that can't use
+//! GraphQL.
+//!
+//! Routes are versioned under `/api/v1`, and a machine-readable spec
+//! describing them is served at `/api/openapi.json`.
+
+mod accounts;
+mod admin;
+mod exports;
+mod integrations;
+mod media;
+mod models;
+mod posts;
+mod proxy;
+mod sessions;
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use axum::{
+    extract::{DefaultBodyLimit, FromRef, FromRequestParts},
+    http::{header::CONTENT_TYPE, request::Parts, Method},
+    middleware,
+    response::IntoResponse,
+    routing::{get, post},
+    Router,
+};
+use ring::rand::SystemRandom;
+
+use crate::{
+    account::{AccountPersist, ApiKeyScope, Authenticated, CurrentAccount},
+    client_version::require_client_version,
+    config::PrivacyConfig,
+    error::{Error, ErrorResponse},
+    export::ExportPersist,
+    integration::IntegrationPersist,
+    media::{MediaPersist, MediaUrls},
+    persist::Persist,
+    policy::PolicyPersist,
+    post::PostPersist,
+    proxy::{ProxyUrls, RemoteMedia},
+    rate_limit::{limit_rate, RateGroup, RateLimiter},
+    read_only::reject_writes,
+    reload::LiveConfig,
+    session::SessionPersist,
+    spam::{SpamPersist, SpamPipeline},
+    stats::StatsPersist,
+    DecodingKey, EncodingKey,
+};
+
+static OPENAPI: &str = include_str!("openapi.json");
+
+/// Everything the REST handlers need, as there's no GraphQL context to get it
+/// from.
+#[derive(Clone)]
+pub struct RestState {
+    pub persist: Persist,
+    pub csrng: SystemRandom,
+    pub jwt_enc_key: EncodingKey,
+    pub jwt_dec_key: DecodingKey,
+    pub spam: Arc<SpamPipeline>,
+    pub min_age: u8,
+    pub live: LiveConfig,
+    pub privacy: Arc<PrivacyConfig>,
+    pub media_urls: MediaUrls,
+    pub proxy_urls: ProxyUrls,
+    pub remote_media: RemoteMedia,
+    pub rate_limiter: RateLimiter,
+}
+
+impl FromRef<RestState> for Persist {
+    fn from_ref(state: &RestState) -> Self {
+        state.persist.clone()
+    }
+}
+
+impl FromRef<RestState> for DecodingKey {
+    fn from_ref(state: &RestState) -> Self {
+        state.jwt_dec_key.clone()
+    }
+}
+
+impl RestState {
+    fn account_persist<'a>(&'a self, current: &'a CurrentAccount) -> AccountPersist<'a> {
+        let features = self.live.load().features;
+        AccountPersist::new(&self.persist, current, &self.csrng, &self.jwt_dec_key)
+            .with_min_age(self.min_age)
+            .with_confusable_user_ids(features.allow_confusable_user_ids)
+            .with_verified_email_required(features.require_verified_email)
+    }
+
+    fn export_persist<'a>(&'a self, current: &'a CurrentAccount) -> ExportPersist<'a> {
+        ExportPersist::new(&self.persist, current)
+    }
+
+    fn integration_persist<'a>(&'a self, current: &'a CurrentAccount) -> IntegrationPersist<'a> {
+        IntegrationPersist::new(&self.persist, current, &self.csrng)
+    }
+
+    fn media_persist<'a>(&'a self, current: &'a CurrentAccount) -> MediaPersist<'a> {
+        MediaPersist::new(&self.persist, current)
+    }
+
+    fn policy_persist<'a>(&'a self, current: &'a CurrentAccount) -> PolicyPersist<'a> {
+        PolicyPersist::new(&self.persist, current)
+    }
+
+    fn post_persist<'a>(&'a self, current: &'a CurrentAccount) -> PostPersist<'a> {
+        PostPersist::new(&self.persist, current)
+    }
+
+    fn session_persist<'a>(&'a self, current: &'a CurrentAccount) -> SessionPersist<'a> {
+        SessionPersist::new(&self.persist, current, &self.privacy)
+    }
+
+    fn stats_persist<'a>(&'a self, current: &'a CurrentAccount) -> StatsPersist<'a> {
+        StatsPersist::new(&self.persist, current)
+    }
+
+    fn spam_persist<'a>(&'a self, current: &'a CurrentAccount) -> SpamPersist<'a> {
+        SpamPersist::new(&self.persist, current, &self.spam)
+    }
+}
+
+/// Builds the versioned REST routes, along with the route for their spec.
+pub fn router<S>(state: RestState) -> Router<S> {
+    // Signing in still works while the instance is read-only, so those
+    // routes are added after the layer that turns writes away.
+    //
+    // Registering, verifying emails and disowning sessions count against the
+    // stricter budget for signing in, along with signing in itself, as they
+    // take credentials or tokens that could be guessed at.
+    //
+    // Outdated apps are turned away from every route, before anything else.
+    //
+    // Media is uploaded as the raw request body, so its route allows bodies
+    // of up to the media size limit rather than the default.
+    let media_limit = usize::try_from(state.persist.limits().media_bytes).unwrap_or(usize::MAX);
+    let auth_limit = middleware::from_fn_with_state(
+        state
+            .rate_limiter
+            .routes(Some(RateGroup::Auth), state.jwt_dec_key.clone()),
+        limit_rate,
+    );
+    let auth_writes = Router::new()
+        .route("/accounts", post(accounts::create))
+        .route("/accounts/verify-email", post(accounts::verify_email))
+        .route("/sessions/disown", post(sessions::disown))
+        .route_layer(auth_limit.clone());
+    let v1 = Router::new()
+        .route("/accounts/me", get(accounts::me))
+        .route("/accounts/:id", get(accounts::get))
+        .route("/admin/usage", get(admin::usage))
+        .route("/exports/:id", get(exports::download))
+        .route("/integrations/:id/deliveries", post(integrations::deliver))
+        .route(
+            "/media",
+            post(media::upload).layer(DefaultBodyLimit::max(media_limit)),
+        )
+        .route("/media/:id", get(media::get).delete(media::delete))
+        .route("/media/:id/content", get(media::content))
+        .route("/posts", get(posts::list).post(posts::create))
+        .route("/posts/:id", get(posts::get).delete(posts::delete))
+        .route("/proxy/link", get(proxy::link))
+        .route("/proxy/media", get(proxy::media))
+        .route_layer(middleware::from_fn_with_state(
+            state.rate_limiter.routes(None, state.jwt_dec_key.clone()),
+            limit_rate,
+        ))
+        .merge(auth_writes)
+        .route_layer(middleware::from_fn_with_state(
+            state.persist.read_only().clone(),
+            reject_writes,
+        ))
+        .merge(
+            Router::new()
+                .route("/sessions", post(sessions::login))
+                .route("/sessions/refresh", post(sessions::refresh))
+                .route_layer(auth_limit),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            state.persist.client_versions().clone(),
+            require_client_version,
+        ));
+
+    Router::new()
+        .nest("/api/v1", v1)
+        .route("/api/openapi.json", get(openapi))
+        .with_state(state)
+}
+
+async fn openapi() -> impl IntoResponse {
+    ([(CONTENT_TYPE, "application/json")], OPENAPI)
+}
+
+/// The account making the request, from the bearer token if one was given.
+/// Requests made with an API key need its `write` scope to change anything,
+/// and its `read` scope otherwise.
+pub struct Current(pub CurrentAccount);
+
+#[async_trait]
+impl FromRequestParts<RestState> for Current {
+    type Rejection = ErrorResponse;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &RestState,
+    ) -> Result<Self, Self::Rejection> {
+        let Authenticated(current) = Authenticated::from_request_parts(parts, state).await?;
+        let scope = if matches!(parts.method, Method::GET | Method::HEAD | Method::OPTIONS) {
+            ApiKeyScope::Read
+        } else {
+            ApiKeyScope::Write
+        };
+        if !current.has_scope(scope) {
+            return Err(Error::ScopeMissing.into());
+        }
+        Ok(Self(current))
+    }
+}
@@ -0,0 +1,364 @@
+use async_graphql::ID;
+use chrono::{DateTime, NaiveDate, Utc};
+use secrecy::{ExposeSecret as _, SecretString};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    account::Account,
+    config::PostVisibility,
+    license::ContentLicense,
+    media::Media,
+    post::{CreatePost, Post, ReplyPolicy, MAX_POST_MEDIA},
+    prelude::*,
+    stats::UsageRecord,
+};
+
+/// A registered account.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountBody {
+    pub id: String,
+    pub user_id: String,
+    pub admin: bool,
+    pub bot: bool,
+    pub owner_id: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<Account> for AccountBody {
+    fn from(acc: Account) -> Self {
+        Self {
+            id: acc.id.to_gql_id().0,
+            user_id: acc.user_id,
+            admin: acc.admin,
+            bot: acc.bot,
+            owner_id: acc.owner_id.map(|id| id.to_gql_id().0),
+            updated_at: acc.updated_at,
+        }
+    }
+}
+
+/// An account that has been logged into, along with its tokens.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionBody {
+    pub account: AccountBody,
+    pub access_token: String,
+    pub refresh_token: String,
+    /// When the session ends unless its tokens are refreshed before then, if
+    /// it ever does.
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// The credentials for registering or logging into an account.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CredsBody {
+    pub user_id: String,
+    pub password: SecretString,
+    /// Only used when registering.
+    #[serde(default)]
+    pub accepted_policy_ids: Vec<String>,
+    /// Only used when registering.
+    pub birthdate: Option<NaiveDate>,
+    /// Only used when registering. See `CreateAccount.honeypot`.
+    pub honeypot: Option<String>,
+    /// Only used when registering. See `CreateAccount.formShownAt`.
+    pub form_shown_at: Option<DateTime<Utc>>,
+    /// Only used when logging in, by accounts with two-factor authentication.
+    /// A code from the authenticator app or a recovery code.
+    pub code: Option<String>,
+}
+
+impl CredsBody {
+    /// Applies the same limits as the GraphQL inputs.
+    pub fn validate(&self, max_user_id_len: usize) -> Result<()> {
+        if self.user_id.is_empty() || self.user_id.len() > max_user_id_len {
+            return Err(Error::InputInvalid(format!(
+                "userId must be between 1 and {max_user_id_len} characters"
+            )));
+        }
+        if !(8..=1024).contains(&self.password.expose_secret().len()) {
+            return Err(Error::InputInvalid(
+                "password must be between 8 and 1024 characters".into(),
+            ));
+        }
+        if self.code.as_ref().is_some_and(|code| code.len() > 64) {
+            return Err(Error::InputInvalid(
+                "code must be at most 64 characters".into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RefreshBody {
+    pub refresh_token: String,
+}
+
+/// A token from a security event that wasn't the account.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DisownBody {
+    pub token: String,
+}
+
+/// A token that was emailed to an account to verify its address.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifyEmailBody {
+    pub token: String,
+}
+
+/// When an account's email address was verified.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifiedBody {
+    pub verified_at: DateTime<Utc>,
+}
+
+/// When an account's tokens were revoked.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RevokedBody {
+    pub revoked_at: DateTime<Utc>,
+}
+
+/// A post.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PostBody {
+    pub id: String,
+    pub creator_id: Option<String>,
+    pub board_id: Option<String>,
+    pub quote_id: Option<String>,
+    pub reply_to_id: Option<String>,
+    pub audience_id: Option<String>,
+    pub media_ids: Vec<String>,
+    pub title: Option<String>,
+    pub content: Option<String>,
+    pub reply_policy: ReplyPolicy,
+    pub visibility: PostVisibility,
+    pub license: ContentLicense,
+    /// The URL of the text of the license, if it has one.
+    pub license_url: Option<&'static str>,
+    pub attribution: Option<String>,
+    pub attribution_url: Option<String>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl From<Post> for PostBody {
+    fn from(post: Post) -> Self {
+        let id = |thing: Option<srql::Thing>| thing.map(|thing| thing.to_gql_id().0);
+        Self {
+            id: post.id.to_gql_id().0,
+            creator_id: id(post.creator_id),
+            board_id: id(post.board_id),
+            quote_id: id(post.quote_id),
+            reply_to_id: id(post.reply_to_id),
+            audience_id: id(post.audience_id),
+            media_ids: post
+                .media_ids
+                .iter()
+                .map(|thing| thing.to_gql_id().0)
+                .collect(),
+            title: post.title,
+            content: post.content,
+            reply_policy: post.reply_policy,
+            visibility: post.visibility,
+            license: post.license,
+            license_url: post.license.url(),
+            attribution: post.attribution,
+            attribution_url: post.attribution_url,
+            updated_at: post.updated_at,
+        }
+    }
+}
+
+/// A page of posts, newest first.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PostPage {
+    pub posts: Vec<PostBody>,
+    /// Pass this as `after` to get the next page. Missing if this is the
+    /// last page.
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PostListQuery {
+    pub after: Option<String>,
+    pub first: Option<i32>,
+    pub board_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreatePostBody {
+    pub board_id: Option<String>,
+    pub quote_id: Option<String>,
+    pub reply_to_id: Option<String>,
+    pub audience_id: Option<String>,
+    pub media_ids: Option<Vec<String>>,
+    pub title: Option<String>,
+    pub content: Option<String>,
+    pub reply_policy: Option<ReplyPolicy>,
+    pub visibility: Option<PostVisibility>,
+    pub license: Option<ContentLicense>,
+    pub attribution: Option<String>,
+    pub attribution_url: Option<String>,
+}
+
+impl CreatePostBody {
+    /// Applies the same limits as the GraphQL inputs.
+    pub fn validate(&self) -> Result<()> {
+        if self.title.as_ref().is_some_and(|title| title.len() > 1024) {
+            return Err(Error::InputInvalid(
+                "title must be at most 1024 characters".into(),
+            ));
+        }
+        if self
+            .content
+            .as_ref()
+            .is_some_and(|content| content.len() > 32_768)
+        {
+            return Err(Error::InputInvalid(
+                "content must be at most 32768 characters".into(),
+            ));
+        }
+        if self
+            .media_ids
+            .as_ref()
+            .is_some_and(|media_ids| media_ids.len() > MAX_POST_MEDIA)
+        {
+            return Err(Error::InputInvalid(format!(
+                "at most {MAX_POST_MEDIA} media can be attached"
+            )));
+        }
+        if self
+            .attribution
+            .as_ref()
+            .is_some_and(|attribution| attribution.len() > 256)
+        {
+            return Err(Error::InputInvalid(
+                "attribution must be at most 256 characters".into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl From<CreatePostBody> for CreatePost {
+    fn from(body: CreatePostBody) -> Self {
+        Self {
+            board_id: body.board_id.map(ID),
+            quote_id: body.quote_id.map(ID),
+            reply_to_id: body.reply_to_id.map(ID),
+            mention_ids: None,
+            media_ids: body.media_ids.map(|ids| ids.into_iter().map(ID).collect()),
+            reply_policy: body.reply_policy,
+            visibility: body.visibility,
+            title: body.title,
+            content: body.content,
+            organization_id: None,
+            audience_id: body.audience_id.map(ID),
+            license: body.license,
+            attribution: body.attribution,
+            attribution_url: body.attribution_url,
+        }
+    }
+}
+
+/// How usage records are exported.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UsageFormat {
+    #[default]
+    Json,
+    Csv,
+    OpenMetrics,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageQuery {
+    pub from: NaiveDate,
+    pub to: Option<NaiveDate>,
+    #[serde(default)]
+    pub format: UsageFormat,
+}
+
+/// How much of the instance a tenant used over a single day.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageBody {
+    pub tenant: String,
+    pub day: NaiveDate,
+    pub requests: u64,
+    pub storage_bytes: u64,
+    pub active_accounts: u64,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<UsageRecord> for UsageBody {
+    fn from(record: UsageRecord) -> Self {
+        Self {
+            tenant: record.tenant,
+            day: record.day,
+            requests: record.requests,
+            storage_bytes: record.storage_bytes,
+            active_accounts: record.active_accounts,
+            updated_at: record.updated_at,
+        }
+    }
+}
+
+/// Uploaded media.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaBody {
+    pub id: String,
+    pub owner_id: String,
+    pub content_type: String,
+    pub size: u64,
+    /// The hex-encoded SHA-256 hash of the media's content.
+    pub hash: String,
+    pub license: ContentLicense,
+    /// The URL of the text of the license, if it has one.
+    pub license_url: Option<&'static str>,
+    pub attribution: Option<String>,
+    pub attribution_url: Option<String>,
+    pub alt_text: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<Media> for MediaBody {
+    fn from(media: Media) -> Self {
+        Self {
+            id: media.id.to_gql_id().0,
+            owner_id: media.owner_id.to_gql_id().0,
+            content_type: media.content_type,
+            size: media.size,
+            hash: media.blob_id.to_gql_id().0,
+            license: media.license,
+            license_url: media.license.url(),
+            attribution: media.attribution,
+            attribution_url: media.attribution_url,
+            alt_text: media.alt_text,
+            updated_at: media.updated_at,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MediaContentQuery {
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProxyQuery {
+    pub token: String,
+}
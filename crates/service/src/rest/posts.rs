@@ -0,0 +1,103 @@
+use async_graphql::connection::CursorType as _;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use tracing::instrument;
+
+use super::{
+    models::{CreatePostBody, PostBody, PostListQuery, PostPage},
+    Current, RestState,
+};
+use crate::{
+    error::{Error, ErrorResponse},
+    query::PaginationArgs,
+    read_marker::ReadTarget,
+};
+
+/// How many posts are returned when `first` isn't given.
+const DEFAULT_PAGE_SIZE: i32 = 20;
+
+/// `GET /api/v1/posts`
+#[instrument(skip_all)]
+pub async fn list(
+    State(state): State<RestState>,
+    Current(current): Current,
+    Query(query): Query<PostListQuery>,
+) -> Result<Json<PostPage>, ErrorResponse> {
+    let mut list = state.post_persist(&current).list().with_pagination(
+        PaginationArgs {
+            after: query.after,
+            before: None,
+            first: Some(query.first.unwrap_or(DEFAULT_PAGE_SIZE)),
+            last: None,
+        }
+        .validate()?,
+    );
+    if let Some(board_id) = query.board_id {
+        list = list.with_board(ReadTarget::Board.thing(&board_id));
+    }
+
+    let posts = list.execute().await?;
+    let next_cursor = posts
+        .has_next_page
+        .then(|| posts.edges.last().map(|edge| edge.cursor.encode_cursor()))
+        .flatten();
+
+    Ok(Json(PostPage {
+        posts: posts
+            .edges
+            .into_iter()
+            .map(|edge| edge.node.into())
+            .collect(),
+        next_cursor,
+    }))
+}
+
+/// `GET /api/v1/posts/:id`
+#[instrument(skip_all)]
+pub async fn get(
+    State(state): State<RestState>,
+    Current(current): Current,
+    Path(id): Path<String>,
+) -> Result<Json<PostBody>, ErrorResponse> {
+    match state.post_persist(&current).get(&id).await? {
+        Some(post) => Ok(Json(post.into())),
+        None => Err(Error::NotFound.into()),
+    }
+}
+
+/// `POST /api/v1/posts`
+#[instrument(skip_all)]
+pub async fn create(
+    State(state): State<RestState>,
+    Current(current): Current,
+    Json(body): Json<CreatePostBody>,
+) -> Result<(StatusCode, Json<PostBody>), ErrorResponse> {
+    body.validate()?;
+    state.policy_persist(&current).require_accepted().await?;
+    state
+        .account_persist(&current)
+        .require_verified_email()
+        .await?;
+
+    let post = state.post_persist(&current).create(body.into()).await?;
+    let post = state.spam_persist(&current).check_post(post).await?;
+
+    Ok((StatusCode::CREATED, Json(post.into())))
+}
+
+/// `DELETE /api/v1/posts/:id`
+#[instrument(skip_all)]
+pub async fn delete(
+    State(state): State<RestState>,
+    Current(current): Current,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ErrorResponse> {
+    state.policy_persist(&current).require_accepted().await?;
+    match state.post_persist(&current).delete(&id).await? {
+        Some(_) => Ok(StatusCode::NO_CONTENT),
+        None => Err(Error::NotFound.into()),
+    }
+}
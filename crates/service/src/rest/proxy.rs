@@ -0,0 +1,56 @@
+use axum::{
+    extract::{Query, State},
+    http::header::{
+        CACHE_CONTROL, CONTENT_SECURITY_POLICY, CONTENT_TYPE, REFERRER_POLICY,
+        X_CONTENT_TYPE_OPTIONS,
+    },
+    response::{IntoResponse, Redirect, Response},
+};
+use tracing::instrument;
+
+use super::{models::ProxyQuery, RestState};
+use crate::{error::ErrorResponse, proxy::ProxiedKind};
+
+/// `GET /api/v1/proxy/link?token=...`
+///
+/// Redirects to an outbound link, telling the browser not to send the page
+/// it was followed from along with it.
+#[instrument(skip_all)]
+pub async fn link(
+    State(state): State<RestState>,
+    Query(query): Query<ProxyQuery>,
+) -> Result<Response, ErrorResponse> {
+    let url = state.proxy_urls.verify(&query.token, ProxiedKind::Link)?;
+    Ok((
+        [
+            (REFERRER_POLICY, "no-referrer"),
+            (CACHE_CONTROL, "private, no-store"),
+        ],
+        Redirect::to(&url.to_string()),
+    )
+        .into_response())
+}
+
+/// `GET /api/v1/proxy/media?token=...`
+///
+/// Serves remote media, fetching it if the instance hasn't already.
+#[instrument(skip_all)]
+pub async fn media(
+    State(state): State<RestState>,
+    Query(query): Query<ProxyQuery>,
+) -> Result<Response, ErrorResponse> {
+    let url = state.proxy_urls.verify(&query.token, ProxiedKind::Media)?;
+    let content = state.remote_media.get(&url).await?;
+    let max_age = state.remote_media.cache_ttl().as_secs();
+    Ok((
+        [
+            (CONTENT_TYPE, content.content_type),
+            (CACHE_CONTROL, format!("public, max-age={max_age}")),
+            (X_CONTENT_TYPE_OPTIONS, "nosniff".to_owned()),
+            (REFERRER_POLICY, "no-referrer".to_owned()),
+            (CONTENT_SECURITY_POLICY, "sandbox".to_owned()),
+        ],
+        content.bytes,
+    )
+        .into_response())
+}
@@ -0,0 +1,121 @@
+use axum::{extract::State, Json};
+use tracing::instrument;
+
+use super::{
+    models::{CredsBody, DisownBody, RefreshBody, RevokedBody, SessionBody},
+    RestState,
+};
+use crate::{
+    account::{
+        create_access_token, create_two_factor_token, issue_refresh_token, AuthCreds,
+        AuthenticatedAccount, CurrentAccount, LoginResult, TwoFactorClaims,
+    },
+    conv::ToGqlId as _,
+    error::{self, Error, ErrorResponse},
+    session::ClientMeta,
+};
+
+/// `POST /api/v1/sessions`
+#[instrument(skip_all)]
+pub async fn login(
+    State(state): State<RestState>,
+    client: ClientMeta,
+    Json(body): Json<CredsBody>,
+) -> Result<Json<SessionBody>, ErrorResponse> {
+    body.validate(64)?;
+
+    let current = CurrentAccount::default();
+    let accounts = state.account_persist(&current);
+    let login = accounts
+        .login(
+            AuthCreds {
+                user_id: body.user_id,
+                pword: body.password,
+            },
+            Some(&client),
+        )
+        .await?;
+    // The second factor is sent along with the password, rather than in a
+    // request of its own.
+    let acc = match login {
+        LoginResult::Authenticated(acc) => *acc,
+        LoginResult::TwoFactorRequired(required) => {
+            let Some(code) = body.code else {
+                return Err(Error::TwoFactorRequired.into());
+            };
+            let token = create_two_factor_token(
+                &TwoFactorClaims::new(required.account_id.to_gql_id(), state.persist.clock().now()),
+                &state.jwt_enc_key,
+            )?;
+            accounts.verify_two_factor(&token, &code).await?
+        }
+        LoginResult::ExternalLoginRedirect(_) => {
+            return Err(
+                Error::InternalServerError("logging in with a password redirected".into()).into(),
+            );
+        }
+    };
+    let recorded = state
+        .session_persist(&current)
+        .record(acc.account.id.clone(), Some(&client))
+        .await?;
+
+    Ok(Json(session(&state, acc.with_session(recorded)).await?))
+}
+
+/// `POST /api/v1/sessions/refresh`
+#[instrument(skip_all)]
+pub async fn refresh(
+    State(state): State<RestState>,
+    Json(body): Json<RefreshBody>,
+) -> Result<Json<SessionBody>, ErrorResponse> {
+    let current = CurrentAccount::default();
+    let acc = state
+        .account_persist(&current)
+        .refresh(body.refresh_token)
+        .await?;
+
+    Ok(Json(session(&state, acc).await?))
+}
+
+/// `POST /api/v1/sessions/disown`
+///
+/// Works without a bearer token, as the token in the body is enough.
+#[instrument(skip_all)]
+pub async fn disown(
+    State(state): State<RestState>,
+    Json(body): Json<DisownBody>,
+) -> Result<Json<RevokedBody>, ErrorResponse> {
+    let current = CurrentAccount::default();
+    let revoked_at = state.account_persist(&current).disown(&body.token).await?;
+
+    Ok(Json(RevokedBody { revoked_at }))
+}
+
+/// Issues new tokens for an account, which don't outlive its session.
+pub async fn session(state: &RestState, acc: AuthenticatedAccount) -> error::Result<SessionBody> {
+    let clock = state.persist.clock();
+    let expires_at = acc.expires_by(&state.persist);
+    let access_token = create_access_token(
+        &acc.partial().with_region(state.persist.region()),
+        expires_at,
+        &state.jwt_enc_key,
+        clock,
+    )?;
+    let refresh_token = issue_refresh_token(
+        &state.persist,
+        &state.csrng,
+        acc.account.id.clone(),
+        acc.session.as_ref().map(|session| session.id.clone()),
+        acc.refresh_family_id.clone(),
+        expires_at,
+    )
+    .await?;
+
+    Ok(SessionBody {
+        account: acc.account.into(),
+        access_token,
+        refresh_token,
+        expires_at,
+    })
+}
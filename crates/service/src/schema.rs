@@ -1,20 +1,93 @@
-use async_graphql::{EmptySubscription, MergedObject, Schema, SchemaBuilder};
+use async_graphql::{MergedObject, MergedSubscription, Schema, SchemaBuilder};
 
 use crate::{
-    account::{AccountMutation, AccountQuery},
+    account::{AccountMutation, AccountQuery, AccountSubscription},
+    admin::{AdminMutation, AdminQuery, AdminSubscription},
+    audience::{AudienceMutation, AudienceQuery},
     board::{BoardMutation, BoardQuery},
-    post::{PostMutation, PostQuery},
+    client_state::{ClientStateMutation, ClientStateQuery, ClientStateSubscription},
+    conversation::{ConversationMutation, ConversationQuery},
+    export::{ExportMutation, ExportQuery},
+    follow::{FollowMutation, FollowQuery},
+    instance::InstanceQuery,
+    integration::{IntegrationMutation, IntegrationQuery},
+    list::{ListMutation, ListQuery},
+    media::{MediaMutation, MediaQuery},
+    moderation::{ModerationMutation, ModerationSubscription},
+    notification::{NotificationMutation, NotificationQuery, NotificationSubscription},
+    organization::{OrganizationMutation, OrganizationQuery},
+    policy::{PolicyMutation, PolicyQuery},
+    post::{PostMutation, PostQuery, PostSubscription},
+    proxy::ProxyQuery,
+    quota::QuotaMutation,
+    read_marker::{ReadMarkerMutation, ReadMarkerQuery},
+    session::{SessionMutation, SessionQuery},
+    translation::TranslationQuery,
+    webhook::{WebhookMutation, WebhookQuery},
 };
 
 #[derive(MergedObject, Default)]
-pub struct Query(AccountQuery, BoardQuery, PostQuery);
+pub struct Query(
+    AccountQuery,
+    AdminQuery,
+    AudienceQuery,
+    BoardQuery,
+    ClientStateQuery,
+    ConversationQuery,
+    ExportQuery,
+    FollowQuery,
+    InstanceQuery,
+    IntegrationQuery,
+    ListQuery,
+    MediaQuery,
+    NotificationQuery,
+    OrganizationQuery,
+    PolicyQuery,
+    PostQuery,
+    ProxyQuery,
+    ReadMarkerQuery,
+    SessionQuery,
+    TranslationQuery,
+    WebhookQuery,
+);
 
 #[derive(MergedObject, Default)]
-pub struct Mutation(AccountMutation, BoardMutation, PostMutation);
+pub struct Mutation(
+    AccountMutation,
+    AdminMutation,
+    AudienceMutation,
+    BoardMutation,
+    ClientStateMutation,
+    ConversationMutation,
+    ExportMutation,
+    FollowMutation,
+    IntegrationMutation,
+    ListMutation,
+    MediaMutation,
+    ModerationMutation,
+    NotificationMutation,
+    OrganizationMutation,
+    PolicyMutation,
+    PostMutation,
+    QuotaMutation,
+    ReadMarkerMutation,
+    SessionMutation,
+    WebhookMutation,
+);
 
-pub type ServiceSchema = Schema<Query, Mutation, EmptySubscription>;
+#[derive(MergedSubscription, Default)]
+pub struct Subscription(
+    AccountSubscription,
+    AdminSubscription,
+    ClientStateSubscription,
+    ModerationSubscription,
+    NotificationSubscription,
+    PostSubscription,
+);
 
-type ServiceSchemaBuilder = SchemaBuilder<Query, Mutation, EmptySubscription>;
+pub type ServiceSchema = Schema<Query, Mutation, Subscription>;
+
+type ServiceSchemaBuilder = SchemaBuilder<Query, Mutation, Subscription>;
 pub fn schema<F>(adjust: F) -> ServiceSchema
 where
     F: FnOnce(ServiceSchemaBuilder) -> ServiceSchemaBuilder,
@@ -22,7 +95,7 @@ where
     adjust(Schema::build(
         Query::default(),
         Mutation::default(),
-        EmptySubscription,
+        Subscription::default(),
     ))
     .finish()
 }
@@ -0,0 +1,10 @@
+//! A log of security-related things that have happened to an account, which
+//! the account itself can look through.
+
+mod models;
+mod persist;
+
+pub use models::*;
+pub use persist::*;
+
+pub static SECURITY_EVENT_TABLE_NAME: &str = "security_event";
@@ -0,0 +1,234 @@
+use async_graphql::{ComplexObject, Context, Enum, SimpleObject, ID};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use surrealdb::sql::Thing;
+
+use super::SECURITY_EVENT_TABLE_NAME;
+use crate::{
+    account::{create_disown_token, DisownClaims, DISOWN_TOKEN_DAYS},
+    id_obj_impls,
+    persist::Persist,
+    prelude::*,
+    query::OpaqueCursor,
+    session::Session,
+    EncodingKey,
+};
+
+pub type SecurityEventCursor = OpaqueCursor<String>;
+
+/// What happened to an account.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SecurityEventKind {
+    /// The account was signed into.
+    SignedIn,
+    /// The account was signed into from a client it hasn't been used from
+    /// before.
+    NewDevice,
+    /// Every token issued for the account was revoked.
+    TokensRevoked,
+    /// One of the account's sessions was signed out. The subject is the
+    /// session.
+    SessionRevoked,
+    /// An earlier event was reported as unrecognized, so every token issued
+    /// for the account was revoked and the instance's admins were asked to
+    /// help recover it.
+    Disowned,
+    /// The account's password was reset with a code emailed to it, which
+    /// revoked every token issued for the account.
+    PasswordReset,
+    /// Two-factor authentication was turned on for the account.
+    TwoFactorEnabled,
+    /// Two-factor authentication was turned off for the account.
+    TwoFactorDisabled,
+    /// One of the account's recovery codes was used instead of a code from
+    /// its authenticator app.
+    RecoveryCodeUsed,
+    /// The account's recovery codes were replaced with new ones.
+    RecoveryCodesRegenerated,
+    /// A passkey was added to the account.
+    PasskeyAdded,
+    /// A passkey was removed from the account.
+    PasskeyRemoved,
+    /// An API key was created for the account.
+    ApiKeyCreated,
+    /// One of the account's API keys was revoked.
+    ApiKeyRevoked,
+    /// An identity from an external provider was linked to the account, so
+    /// it can be logged into with that identity.
+    ExternalIdentityLinked,
+    /// An identity from an external provider was unlinked from the account.
+    ExternalIdentityUnlinked,
+    /// The account was deleted, and will be purged once its grace period
+    /// ends.
+    AccountDeleted,
+    /// The account was restored after being deleted, before it was purged.
+    AccountRestored,
+    /// The account's trusted contacts for recovery were changed.
+    RecoveryContactsChanged,
+    /// Someone asked the account's trusted contacts to approve recovering
+    /// it.
+    RecoveryRequested,
+    /// The account was recovered with its trusted contacts' approval, which
+    /// turned off two-factor authentication so that a new password could be
+    /// chosen.
+    AccountRecovered,
+    /// The references that were left to the account after it was purged
+    /// were unlinked or anonymized. The event's detail says how many of each
+    /// there were.
+    AccountAnonymized,
+    /// The account went without signing in for the instance's dormancy
+    /// period, so it was sent a notice that its user ID may be released.
+    DormancyNoticed,
+    /// The account signed in again after being sent a dormancy notice.
+    DormancyCleared,
+    /// The account stayed dormant past its grace period, so its user ID was
+    /// released for others to register. The event's detail is the old user
+    /// ID.
+    UserIdReleased,
+}
+
+impl SecurityEventKind {
+    /// Whether the account is notified when an event of this kind happens,
+    /// so that it can report the event if it wasn't them. These are the
+    /// events that someone who has taken over an account would cause.
+    #[must_use]
+    pub fn alerts(self) -> bool {
+        match self {
+            Self::NewDevice
+            | Self::TwoFactorDisabled
+            | Self::RecoveryCodeUsed
+            | Self::RecoveryCodesRegenerated
+            | Self::PasskeyAdded
+            | Self::ApiKeyCreated
+            | Self::ExternalIdentityLinked
+            | Self::RecoveryContactsChanged
+            | Self::RecoveryRequested => true,
+            Self::SignedIn
+            | Self::TokensRevoked
+            | Self::SessionRevoked
+            | Self::Disowned
+            | Self::PasswordReset
+            | Self::TwoFactorEnabled
+            | Self::PasskeyRemoved
+            | Self::ApiKeyRevoked
+            | Self::ExternalIdentityUnlinked
+            | Self::AccountDeleted
+            | Self::AccountRestored
+            | Self::AccountRecovered
+            | Self::AccountAnonymized
+            | Self::DormancyNoticed
+            | Self::DormancyCleared
+            | Self::UserIdReleased => false,
+        }
+    }
+}
+
+impl QueryValue for SecurityEventKind {
+    fn into_query_value(self, field: srql::Idiom) -> Option<srql::SetExprItem> {
+        srql::to_value(self)
+            .ok()
+            .map(|value| (field, srql::Operator::Equal, value))
+    }
+}
+
+/// Something security-related that happened to an account.
+#[derive(SimpleObject, Debug, Clone, Deserialize)]
+#[graphql(complex)]
+pub struct SecurityEvent {
+    #[graphql(skip)]
+    pub id: Thing,
+    #[graphql(skip)]
+    pub account_id: Thing,
+    #[graphql(skip)]
+    pub session_id: Option<Thing>,
+
+    /// What happened.
+    pub kind: SecurityEventKind,
+    /// When it happened.
+    pub occurred_at: DateTime<Utc>,
+    /// The region of the server that it happened on, when the instance is
+    /// deployed across several.
+    pub region: Option<String>,
+    /// More about what happened, for events that the instance logs about
+    /// itself rather than the account.
+    #[graphql(skip)]
+    pub detail: Option<String>,
+
+    /// A timestamp indicating the last time the event was updated.
+    pub updated_at: DateTime<Utc>,
+}
+
+#[ComplexObject]
+impl SecurityEvent {
+    /// The event's unique ID.
+    async fn id(&self) -> ID {
+        self.id.to_gql_id()
+    }
+
+    /// The sign-in the event happened during, if it happened while signing
+    /// in.
+    async fn session(&self, ctx: &Context<'_>) -> GqlResult<Option<Session>> {
+        let Some(session_id) = &self.session_id else {
+            return Ok(None);
+        };
+        ctx.session_persist().get(session_id).await.extend()
+    }
+
+    /// A token for reporting that the event wasn't the account, with
+    /// `disownSecurityEvent`. Whoever has it can do this without being signed
+    /// in, so it should only be given to the account's owner.
+    ///
+    /// This is `null` for kinds of events that the account isn't notified
+    /// about, and once the event is too old to report.
+    async fn disown_token(&self, ctx: &Context<'_>) -> GqlResult<Option<String>> {
+        let now = ctx.data_unchecked::<Persist>().clock().now();
+        if !self.kind.alerts() || self.occurred_at + Duration::days(DISOWN_TOKEN_DAYS) <= now {
+            return Ok(None);
+        }
+        let claims = DisownClaims::new(
+            self.account_id.to_gql_id(),
+            self.id.to_gql_id(),
+            self.occurred_at,
+        );
+        create_disown_token(&claims, ctx.data_unchecked::<EncodingKey>())
+            .map(Some)
+            .extend()
+    }
+}
+
+id_obj_impls!(SecurityEvent);
+
+impl SecurityEvent {
+    pub fn create(
+        account_id: Thing,
+        kind: SecurityEventKind,
+        session_id: Option<Thing>,
+        region: Option<String>,
+        clock: &dyn Clock,
+        ids: &dyn IdGen,
+    ) -> srql::CreateStatement {
+        Self::create_with_detail(account_id, kind, session_id, region, None, clock, ids)
+    }
+
+    pub fn create_with_detail(
+        account_id: Thing,
+        kind: SecurityEventKind,
+        session_id: Option<Thing>,
+        region: Option<String>,
+        detail: Option<String>,
+        clock: &dyn Clock,
+        ids: &dyn IdGen,
+    ) -> srql::CreateStatement {
+        let mut create = vec![];
+        account_id.push_field(srql::field("account_id"), &mut create);
+        kind.push_field(srql::field("kind"), &mut create);
+        session_id.push_field(srql::field("session_id"), &mut create);
+        region.push_field(srql::field("region"), &mut create);
+        detail.push_field(srql::field("detail"), &mut create);
+        clock
+            .now()
+            .push_field(srql::field("occurred_at"), &mut create);
+        srql::obj_create_query(SECURITY_EVENT_TABLE_NAME, create, ids)
+    }
+}
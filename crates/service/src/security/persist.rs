@@ -0,0 +1,197 @@
+#[cfg(test)]
+mod tests;
+
+use async_graphql::connection::{Connection, Edge};
+use tracing::instrument;
+
+use super::{SecurityEvent, SecurityEventCursor, SecurityEventKind, SECURITY_EVENT_TABLE_NAME};
+use crate::{
+    account::CurrentAccount,
+    notification::{CreateNotification, NotificationKind, NotificationPersist},
+    persist::Persist,
+    prelude::*,
+    query::{OpaqueCursor, PaginationInput, PaginationOptions, ResultSlice},
+};
+
+pub struct SecurityEventPersist<'a> {
+    persist: &'a Persist,
+    current: &'a CurrentAccount,
+}
+
+impl<'a> SecurityEventPersist<'a> {
+    pub fn new(persist: &'a Persist, current: &'a CurrentAccount) -> Self {
+        Self { persist, current }
+    }
+
+    /// Adds an event to an account's log. This is done on behalf of the
+    /// instance, so the account doesn't need to be signed in.
+    ///
+    /// The account is also notified about kinds of events that
+    /// [alert](SecurityEventKind::alerts), so every path that logs them gets
+    /// that for free.
+    #[instrument(skip_all)]
+    pub async fn log(
+        &self,
+        account_id: srql::Thing,
+        kind: SecurityEventKind,
+        session_id: Option<srql::Thing>,
+    ) -> Result<SecurityEvent> {
+        let event: Option<SecurityEvent> = self
+            .persist
+            .db()
+            .query(SecurityEvent::create(
+                account_id,
+                kind,
+                session_id,
+                self.persist.region().map(Into::into),
+                self.persist.clock(),
+                self.persist.ids(),
+            ))
+            .await?
+            .take(0)?;
+
+        let Some(event) = event else {
+            return Err(Error::UnavailableIdent);
+        };
+
+        if event.kind.alerts() {
+            NotificationPersist::new(self.persist, self.current)
+                .notify(CreateNotification {
+                    account_id: event.account_id.clone(),
+                    kind: NotificationKind::Security,
+                    actor_id: None,
+                    post_id: None,
+                    subject_id: Some(event.id.clone()),
+                })
+                .await?;
+        }
+
+        Ok(event)
+    }
+
+    /// Gets one of the current account's events.
+    #[instrument(skip_all)]
+    pub async fn get(&self, id: &srql::Thing) -> Result<Option<SecurityEvent>> {
+        let account_id = self.current.id()?.to_account_thing();
+        Ok(get_any(self.persist, id)
+            .await?
+            .filter(|event| event.account_id == account_id))
+    }
+
+    /// Lists the current account's events, newest first.
+    #[instrument(skip_all)]
+    pub fn list(&self) -> Result<SecurityEventListRequest<'a>> {
+        let account_id = self.current.id()?.to_account_thing();
+        Ok(SecurityEventListRequest::new(self.persist, account_id))
+    }
+}
+
+/// Gets any account's event.
+pub(crate) async fn get_any(persist: &Persist, id: &srql::Thing) -> Result<Option<SecurityEvent>> {
+    if id.tb != SECURITY_EVENT_TABLE_NAME {
+        return Ok(None);
+    }
+    Ok(persist.db().select(id.clone()).await?)
+}
+
+pub struct SecurityEventListRequest<'a> {
+    persist: &'a Persist,
+    account_id: srql::Thing,
+    kinds: Option<Vec<SecurityEventKind>>,
+    pagination: Option<PaginationInput<OpaqueCursor<String>>>,
+}
+
+impl<'a> SecurityEventListRequest<'a> {
+    fn new(persist: &'a Persist, account_id: srql::Thing) -> Self {
+        Self {
+            persist,
+            account_id,
+            kinds: None,
+            pagination: None,
+        }
+    }
+
+    /// Only list events of the given kinds.
+    pub fn with_kinds(mut self, kinds: Option<Vec<SecurityEventKind>>) -> Self {
+        self.kinds = kinds;
+        self
+    }
+
+    pub fn with_pagination(
+        mut self,
+        args: impl Into<PaginationInput<OpaqueCursor<String>>>,
+    ) -> Self {
+        self.pagination = Some(args.into());
+        self
+    }
+
+    #[instrument(skip_all)]
+    pub async fn execute(self) -> Result<Connection<SecurityEventCursor, SecurityEvent>> {
+        let PaginationOptions {
+            cond,
+            order,
+            limit,
+            result_slice_opts,
+        } = (self.pagination, SECURITY_EVENT_TABLE_NAME).into();
+
+        let account_cond = srql::Cond(
+            srql::Expression::Binary {
+                l: srql::field("account_id").into(),
+                o: srql::Operator::Equal,
+                r: self.account_id.into(),
+            }
+            .into(),
+        );
+        let kinds_cond = match self.kinds {
+            Some(kinds) => Some(srql::Cond(
+                srql::Expression::Binary {
+                    l: srql::field("kind").into(),
+                    o: srql::Operator::Inside,
+                    r: srql::to_value(kinds).map_err(Error::from_err)?,
+                }
+                .into(),
+            )),
+            None => None,
+        };
+
+        let query = srql::SelectStatement {
+            expr: srql::Fields::all(),
+            what: srql::table(SECURITY_EVENT_TABLE_NAME),
+            order: srql::Orders(order.into_iter().collect()).into(),
+            cond: srql::cond_and(srql::cond_and(cond, account_cond.into()), kinds_cond),
+            limit,
+            ..Default::default()
+        };
+
+        let events: Vec<SecurityEvent> = self.persist.db().query(query).await?.take(0)?;
+        let ResultSlice {
+            results: events,
+            has_previous_page,
+            has_next_page,
+        } = ResultSlice::new(events, result_slice_opts);
+
+        let mut connection = Connection::new(has_previous_page, has_next_page);
+        connection.edges = events
+            .into_iter()
+            .map(|event| Edge::new(OpaqueCursor(event.id.to_gql_id().0), event))
+            .collect();
+
+        Ok(connection)
+    }
+}
+
+#[cfg(test)]
+pub mod testing {
+    use super::SecurityEventPersist;
+    use crate::account::testing::TestData;
+
+    pub trait SecurityEventTestData {
+        fn security_event(&self) -> SecurityEventPersist<'_>;
+    }
+
+    impl SecurityEventTestData for TestData {
+        fn security_event(&self) -> SecurityEventPersist<'_> {
+            SecurityEventPersist::new(&self.persist, &self.current)
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use pretty_assertions::assert_eq;
+
+use super::{testing::SecurityEventTestData as _, *};
+use crate::{
+    account::testing::*,
+    config::PrivacyConfig,
+    notification::{testing::NotificationTestData as _, NotificationKind},
+    query::PaginationInput,
+    session::{testing::SessionTestData as _, ClientMeta},
+};
+
+async fn list(data: &TestData, kinds: Option<Vec<SecurityEventKind>>) -> Vec<SecurityEvent> {
+    data.security_event()
+        .list()
+        .unwrap()
+        .with_kinds(kinds)
+        .with_pagination(PaginationInput::new().forward(10))
+        .execute()
+        .await
+        .unwrap()
+        .edges
+        .into_iter()
+        .map(|edge| edge.node)
+        .collect()
+}
+
+async fn list_kinds(data: &TestData) -> Vec<SecurityEventKind> {
+    list(data, None)
+        .await
+        .into_iter()
+        .map(|event| event.kind)
+        .collect()
+}
+
+fn client(user_agent: &str) -> ClientMeta {
+    ClientMeta {
+        ip: None,
+        user_agent: Some(user_agent.into()),
+        locales: vec![],
+        app: None,
+    }
+}
+
+#[tokio::test]
+async fn test_sign_ins() {
+    use SecurityEventKind::*;
+
+    let (data, acc) = TestData::with_user().await;
+    let privacy = PrivacyConfig::default();
+    let sessions = data.session(&privacy);
+
+    let first = sessions
+        .record(acc.id.clone(), Some(&client("Firefox")))
+        .await
+        .unwrap();
+    sessions
+        .record(acc.id.clone(), Some(&client("Firefox")))
+        .await
+        .unwrap();
+    let third = sessions
+        .record(acc.id.clone(), Some(&client("Safari")))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        list_kinds(&data).await,
+        vec![NewDevice, SignedIn, SignedIn, SignedIn]
+    );
+
+    let events = list(&data, Some(vec![NewDevice])).await;
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].session_id.as_ref(), Some(&third.id));
+
+    let events = list(&data, Some(vec![SignedIn])).await;
+    assert_eq!(events.len(), 3);
+    assert_eq!(events[2].session_id.as_ref(), Some(&first.id));
+}
+
+#[tokio::test]
+async fn test_tokens_revoked() {
+    let (data, _) = TestData::with_user().await;
+    data.account().revoke_tokens().await.unwrap();
+    assert_eq!(
+        list_kinds(&data).await,
+        vec![SecurityEventKind::TokensRevoked]
+    );
+}
+
+#[tokio::test]
+async fn test_only_own_events() {
+    let (mut data, _) = TestData::with_user().await;
+    let other = data.account().create_test_user().await;
+    data.security_event()
+        .log(other.id.clone(), SecurityEventKind::SignedIn, None)
+        .await
+        .unwrap();
+    assert!(list_kinds(&data).await.is_empty());
+
+    data.login_as(&other);
+    assert_eq!(list_kinds(&data).await, vec![SecurityEventKind::SignedIn]);
+}
+
+#[tokio::test]
+async fn test_anonymous() {
+    let (mut data, _) = TestData::with_user().await;
+    data.current = CurrentAccount::default();
+    assert!(matches!(
+        data.security_event().list(),
+        Err(Error::Unauthenticated)
+    ));
+}
+
+#[tokio::test]
+async fn test_region() {
+    let (mut data, acc) = TestData::with_user().await;
+    data.persist = data.persist.with_region(Some("eu-west".into()));
+    data.security_event()
+        .log(acc.id.clone(), SecurityEventKind::SignedIn, None)
+        .await
+        .unwrap();
+
+    let events = list(&data, None).await;
+    assert_eq!(events[0].region.as_deref(), Some("eu-west"));
+}
+
+#[tokio::test]
+async fn test_alerts_notify() {
+    let (data, acc) = TestData::with_user().await;
+    let signed_in = data
+        .security_event()
+        .log(acc.id.clone(), SecurityEventKind::SignedIn, None)
+        .await
+        .unwrap();
+    let new_device = data
+        .security_event()
+        .log(acc.id.clone(), SecurityEventKind::NewDevice, None)
+        .await
+        .unwrap();
+
+    let notifications = data
+        .notification()
+        .list()
+        .unwrap()
+        .with_pagination(PaginationInput::new().forward(10))
+        .execute()
+        .await
+        .unwrap();
+    assert_eq!(notifications.edges.len(), 1);
+    let notification = &notifications.edges[0].node;
+    assert_eq!(notification.kind, NotificationKind::Security);
+    assert_eq!(notification.subject_id.as_ref(), Some(&new_device.id));
+
+    let event = data.security_event().get(&new_device.id).await.unwrap();
+    assert_eq!(event.map(|event| event.kind), Some(new_device.kind));
+    let other = TestData::new().await;
+    assert!(matches!(
+        other.security_event().get(&signed_in.id).await,
+        Err(Error::Unauthenticated)
+    ));
+}
@@ -0,0 +1,114 @@
+use std::{convert::Infallible, net::SocketAddr};
+
+use async_graphql::SimpleObject;
+use async_trait::async_trait;
+use axum::{
+    extract::{ConnectInfo, FromRequestParts},
+    headers::UserAgent,
+    http::{header::ACCEPT_LANGUAGE, request::Parts, HeaderMap},
+    TypedHeader,
+};
+
+use crate::locale::{parse_accept_language, LanguageIdentifier};
+
+static CLIENT_NAME_HEADER: &str = "x-client-name";
+static CLIENT_VERSION_HEADER: &str = "x-client-version";
+static CLIENT_PLATFORM_HEADER: &str = "x-client-platform";
+
+/// The most characters kept of each thing a client says about its app.
+const MAX_CLIENT_APP_LEN: usize = 100;
+
+/// What a client sent about itself with a request.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ClientMeta {
+    /// The address of the connection the request came from.
+    pub ip: Option<std::net::IpAddr>,
+    /// The client's `User-Agent` header.
+    pub user_agent: Option<String>,
+    /// The locales listed in the client's `Accept-Language` header, most
+    /// preferred first.
+    pub locales: Vec<LanguageIdentifier>,
+    /// The app the client says it is, if it said.
+    pub app: Option<ClientApp>,
+}
+
+/// The app a client says it is, so that versions that are too old can be
+/// told to update.
+#[derive(SimpleObject, Debug, Clone, PartialEq, Eq)]
+pub struct ClientApp {
+    /// The app's name.
+    pub name: String,
+    /// The app's version.
+    pub version: String,
+    /// The platform the app is running on, if it said.
+    pub platform: Option<String>,
+}
+
+impl ClientApp {
+    /// The app named in a request's `X-Client-Name`, `X-Client-Version` and
+    /// `X-Client-Platform` headers. Both a name and a version are needed.
+    #[must_use]
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+        Self::new(
+            header(CLIENT_NAME_HEADER)?,
+            header(CLIENT_VERSION_HEADER)?,
+            header(CLIENT_PLATFORM_HEADER),
+        )
+    }
+
+    /// The app named in the `clientInfo` object of a GraphQL WebSocket init,
+    /// which has the same `name`, `version` and `platform` as the headers.
+    #[must_use]
+    pub fn from_init(init: &serde_json::Value) -> Option<Self> {
+        let info = init.get("clientInfo")?;
+        let field = |name| info.get(name).and_then(serde_json::Value::as_str);
+        Self::new(field("name")?, field("version")?, field("platform"))
+    }
+
+    #[must_use]
+    pub fn new(name: &str, version: &str, platform: Option<&str>) -> Option<Self> {
+        Some(Self {
+            name: clean_app_field(name)?,
+            version: clean_app_field(version)?,
+            platform: platform.and_then(clean_app_field),
+        })
+    }
+}
+
+fn clean_app_field(value: &str) -> Option<String> {
+    let value = value.trim();
+    if value.is_empty() {
+        return None;
+    }
+    Some(value.chars().take(MAX_CLIENT_APP_LEN).collect())
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ClientMeta {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let ip = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        let user_agent = Option::<TypedHeader<UserAgent>>::from_request_parts(parts, state)
+            .await
+            .ok()
+            .flatten()
+            .map(|TypedHeader(user_agent)| user_agent.as_str().to_owned());
+        let locales = parts
+            .headers
+            .get(ACCEPT_LANGUAGE)
+            .and_then(|header| header.to_str().ok())
+            .map(parse_accept_language)
+            .unwrap_or_default();
+        Ok(Self {
+            ip,
+            user_agent,
+            locales,
+            app: ClientApp::from_headers(&parts.headers),
+        })
+    }
+}
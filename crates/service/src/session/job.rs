@@ -0,0 +1,44 @@
+use std::time::Duration;
+
+use tokio::{
+    task::JoinHandle,
+    time::{interval, MissedTickBehavior},
+};
+use tracing::{debug, error, trace};
+
+use super::SessionPersist;
+use crate::{account::CurrentAccount, config::PrivacyConfig, persist::Persist};
+
+/// How often expired session details are redacted.
+pub const REDACTION_INTERVAL: Duration = Duration::from_hours(1);
+
+static REDACTION_LOCK: &str = "session_redaction";
+
+/// Spawns a task that periodically removes the IP addresses and user agents
+/// of sessions older than the instance's retention period.
+pub fn spawn_redactions(persist: Persist, privacy: PrivacyConfig) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = interval(REDACTION_INTERVAL);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let current = CurrentAccount::default();
+        let sessions = SessionPersist::new(&persist, &current, &privacy);
+
+        loop {
+            ticker.tick().await;
+
+            let res = persist
+                .execute_in_lock(REDACTION_LOCK, || async {
+                    let _job = persist.metrics().track_job();
+                    sessions.redact_expired().await
+                })
+                .await;
+
+            match res {
+                Ok(Some(Ok(count))) => debug!(count, "Expired session details redacted"),
+                Ok(Some(Err(err))) => error!(error = ?err, "Failed to redact session details"),
+                Ok(None) => trace!("Session details are already being redacted"),
+                Err(err) => error!(error = ?err, "Failed to lock session redaction"),
+            }
+        }
+    })
+}
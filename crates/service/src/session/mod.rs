@@ -0,0 +1,20 @@
+//! Records of sign-ins, along with what the client told us about itself.
+//!
+//! How much of that is kept, and for how long, is controlled by the
+//! instance's [`PrivacyConfig`](crate::config::PrivacyConfig).
+
+mod client;
+mod job;
+mod models;
+mod persist;
+mod privacy;
+mod schema;
+
+pub use client::*;
+pub use job::*;
+pub use models::*;
+pub use persist::*;
+pub use privacy::*;
+pub use schema::*;
+
+pub static SESSION_TABLE_NAME: &str = "session";
@@ -0,0 +1,214 @@
+use async_graphql::{ComplexObject, Context, SimpleObject, ID};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use serde::Deserialize;
+use surrealdb::sql::Thing;
+
+use super::{ClientApp, SESSION_TABLE_NAME};
+use crate::{
+    account::require_admin,
+    config::{PrivacyConfig, SessionConfig},
+    id_obj_impls,
+    persist::Persist,
+    prelude::*,
+};
+
+/// A sign-in to an account, and what was kept about the client that made it.
+#[derive(SimpleObject, Debug, Clone, Deserialize)]
+#[graphql(complex)]
+pub struct Session {
+    #[graphql(skip)]
+    pub id: Thing,
+    #[graphql(skip)]
+    pub account_id: Thing,
+    #[graphql(skip)]
+    pub ip: Option<String>,
+    #[graphql(skip)]
+    pub user_agent: Option<String>,
+    #[graphql(skip)]
+    pub app_name: Option<String>,
+    #[graphql(skip)]
+    pub app_version: Option<String>,
+    #[graphql(skip)]
+    pub app_platform: Option<String>,
+    /// The region of the server that the account was signed into on, when
+    /// the instance is deployed across several.
+    pub region: Option<String>,
+
+    /// When the account was signed into.
+    pub signed_in_at: DateTime<Utc>,
+    #[graphql(skip)]
+    pub last_active_at: Option<DateTime<Utc>>,
+    /// When the session was signed out, if it has been. Its tokens stopped
+    /// working then.
+    pub revoked_at: Option<DateTime<Utc>>,
+
+    /// A timestamp indicating the last time the session was updated.
+    pub updated_at: DateTime<Utc>,
+}
+
+#[ComplexObject]
+impl Session {
+    /// The session's unique ID.
+    async fn id(&self) -> ID {
+        self.id.to_gql_id()
+    }
+
+    /// The ID of the account that was signed into.
+    async fn account_id(&self) -> ID {
+        self.account_id.to_gql_id()
+    }
+
+    /// The IP address the client signed in from. Depending on the instance's
+    /// settings this may only be the network part of the address, or a hash
+    /// of it.
+    ///
+    /// This is `null` once the instance's retention period has passed, or if
+    /// the instance doesn't show it to the current account.
+    async fn ip(&self, ctx: &Context<'_>) -> GqlResult<Option<&str>> {
+        Ok(if self.can_see_metadata(ctx).await? {
+            self.ip.as_deref()
+        } else {
+            None
+        })
+    }
+
+    /// The user agent of the client that signed in.
+    ///
+    /// This is `null` once the instance's retention period has passed, or if
+    /// the instance doesn't show it to the current account.
+    async fn user_agent(&self, ctx: &Context<'_>) -> GqlResult<Option<&str>> {
+        Ok(if self.can_see_metadata(ctx).await? {
+            self.user_agent.as_deref()
+        } else {
+            None
+        })
+    }
+
+    /// The app that signed in, if it said which it was.
+    async fn app(&self) -> Option<ClientApp> {
+        Some(ClientApp {
+            name: self.app_name.clone()?,
+            version: self.app_version.clone()?,
+            platform: self.app_platform.clone(),
+        })
+    }
+
+    /// When the session's tokens were last issued, which is when it was last
+    /// known to be in use.
+    async fn last_active_at(&self) -> DateTime<Utc> {
+        self.last_active()
+    }
+
+    /// Whether this is the session that the current request was made with.
+    async fn current(&self, ctx: &Context<'_>) -> bool {
+        ctx.current_account()
+            .session_id()
+            .ok()
+            .flatten()
+            .is_some_and(|id| Thing::from((SESSION_TABLE_NAME, id.as_str())) == self.id)
+    }
+
+    /// When the session ends if its tokens aren't refreshed before then,
+    /// or `null` if it never does.
+    async fn expires_at(&self, ctx: &Context<'_>) -> GqlResult<Option<DateTime<Utc>>> {
+        let config = ctx.data_unchecked::<Persist>().sessions();
+        Ok(self.expiry(config, self.is_admin(ctx).await?))
+    }
+
+    /// When the session ends however much it's used, or `null` if it never
+    /// does.
+    async fn absolute_expires_at(&self, ctx: &Context<'_>) -> GqlResult<Option<DateTime<Utc>>> {
+        let config = ctx.data_unchecked::<Persist>().sessions();
+        Ok(self.absolute_expiry(config, self.is_admin(ctx).await?))
+    }
+}
+
+id_obj_impls!(Session);
+
+impl Session {
+    pub fn create(
+        account_id: Thing,
+        ip: Option<String>,
+        user_agent: Option<String>,
+        app: Option<ClientApp>,
+        region: Option<String>,
+        clock: &dyn Clock,
+        ids: &dyn IdGen,
+    ) -> srql::CreateStatement {
+        let mut create = vec![];
+        account_id.push_field(srql::field("account_id"), &mut create);
+        ip.push_field(srql::field("ip"), &mut create);
+        user_agent.push_field(srql::field("user_agent"), &mut create);
+        if let Some(app) = app {
+            app.name.push_field(srql::field("app_name"), &mut create);
+            app.version
+                .push_field(srql::field("app_version"), &mut create);
+            app.platform
+                .push_field(srql::field("app_platform"), &mut create);
+        }
+        region.push_field(srql::field("region"), &mut create);
+        clock
+            .now()
+            .push_field(srql::field("signed_in_at"), &mut create);
+        srql::obj_create_query(SESSION_TABLE_NAME, create, ids)
+    }
+
+    pub fn last_active(&self) -> DateTime<Utc> {
+        self.last_active_at.unwrap_or(self.signed_in_at)
+    }
+
+    /// When the session ends however much it's used, if it ever does.
+    pub fn absolute_expiry(&self, config: &SessionConfig, admin: bool) -> Option<DateTime<Utc>> {
+        let max_lifetime = ChronoDuration::from_std(config.max_lifetime(admin)?).ok()?;
+        Some(self.signed_in_at + max_lifetime)
+    }
+
+    /// When the session ends if it isn't used again before then, if it ever
+    /// does. Sessions of admins use their shorter lifetimes.
+    pub fn expiry(&self, config: &SessionConfig, admin: bool) -> Option<DateTime<Utc>> {
+        let idle_expires_at = config
+            .idle_timeout(admin)
+            .and_then(|idle_timeout| ChronoDuration::from_std(idle_timeout).ok())
+            .map(|idle_timeout| self.last_active() + idle_timeout);
+        match (idle_expires_at, self.absolute_expiry(config, admin)) {
+            (Some(idle), Some(absolute)) => Some(idle.min(absolute)),
+            (idle, absolute) => idle.or(absolute),
+        }
+    }
+
+    /// Whether the session can still be used, which it can't once it has
+    /// been revoked or has expired.
+    pub fn is_active(&self, config: &SessionConfig, admin: bool, now: DateTime<Utc>) -> bool {
+        self.revoked_at.is_none()
+            && self
+                .expiry(config, admin)
+                .is_none_or(|expires_at| expires_at > now)
+    }
+
+    async fn is_admin(&self, ctx: &Context<'_>) -> GqlResult<bool> {
+        let account = ctx
+            .account_persist()
+            .get(&self.account_id.to_gql_id())
+            .await
+            .extend()?;
+        Ok(account.is_some_and(|account| account.admin))
+    }
+
+    async fn can_see_metadata(&self, ctx: &Context<'_>) -> GqlResult<bool> {
+        let visibility = ctx.data_unchecked::<PrivacyConfig>().metadata_visibility;
+        let Ok(current) = ctx.current_account().id() else {
+            return Ok(false);
+        };
+        if visibility.owner_can_see() && current.to_account_thing() == self.account_id {
+            return Ok(true);
+        }
+        if !visibility.admins_can_see() {
+            return Ok(false);
+        }
+        match require_admin(ctx.data_unchecked::<Persist>(), ctx.current_account()).await {
+            Ok(_) => Ok(true),
+            Err(Error::Unauthorized) => Ok(false),
+            Err(err) => Err(err).extend(),
+        }
+    }
+}
@@ -0,0 +1,463 @@
+#[cfg(test)]
+mod tests;
+
+use chrono::Duration;
+use tracing::instrument;
+
+use super::{store_ip, store_user_agent, ClientMeta, Session, SESSION_TABLE_NAME};
+use crate::{
+    account::{require_admin, Account, CurrentAccount},
+    config::PrivacyConfig,
+    persist::Persist,
+    prelude::*,
+    query::SRQL_ORDER_DESC,
+    security::{SecurityEventKind, SecurityEventPersist},
+};
+
+pub struct SessionPersist<'a> {
+    persist: &'a Persist,
+    current: &'a CurrentAccount,
+    privacy: &'a PrivacyConfig,
+}
+
+impl<'a> SessionPersist<'a> {
+    pub fn new(
+        persist: &'a Persist,
+        current: &'a CurrentAccount,
+        privacy: &'a PrivacyConfig,
+    ) -> Self {
+        Self {
+            persist,
+            current,
+            privacy,
+        }
+    }
+
+    /// Records a sign-in to an account. The client's details are stored as
+    /// far as the instance's privacy settings allow.
+    ///
+    /// The sign-in is added to the account's security log, along with a
+    /// [`SecurityEventKind::NewDevice`] event if the account has been signed
+    /// into before but never with the same user agent.
+    #[instrument(skip_all)]
+    pub async fn record(
+        &self,
+        account_id: srql::Thing,
+        client: Option<&ClientMeta>,
+    ) -> Result<Session> {
+        let ip = client
+            .and_then(|client| client.ip)
+            .and_then(|ip| store_ip(ip, self.privacy));
+        let user_agent = client
+            .and_then(|client| client.user_agent.as_deref())
+            .and_then(store_user_agent);
+        let new_device = match &user_agent {
+            Some(user_agent) => self.is_new_device(&account_id, user_agent).await?,
+            None => false,
+        };
+
+        let session: Option<Session> = self
+            .persist
+            .db()
+            .query(Session::create(
+                account_id,
+                ip,
+                user_agent,
+                client.and_then(|client| client.app.clone()),
+                self.persist.region().map(Into::into),
+                self.persist.clock(),
+                self.persist.ids(),
+            ))
+            .await?
+            .take(0)?;
+        let Some(session) = session else {
+            return Err(Error::UnavailableIdent);
+        };
+
+        let events = SecurityEventPersist::new(self.persist, self.current);
+        events
+            .log(
+                session.account_id.clone(),
+                SecurityEventKind::SignedIn,
+                Some(session.id.clone()),
+            )
+            .await?;
+        if new_device {
+            events
+                .log(
+                    session.account_id.clone(),
+                    SecurityEventKind::NewDevice,
+                    Some(session.id.clone()),
+                )
+                .await?;
+        }
+
+        Ok(session)
+    }
+
+    /// Whether an account has earlier sessions, none of which were made with
+    /// the given user agent.
+    async fn is_new_device(&self, account_id: &srql::Thing, user_agent: &str) -> Result<bool> {
+        let sessions = self.list_of(account_id.clone()).await?;
+        Ok(!sessions.is_empty()
+            && !sessions
+                .iter()
+                .any(|session| session.user_agent.as_deref() == Some(user_agent)))
+    }
+
+    /// Gets one of the current account's sessions.
+    #[instrument(skip_all)]
+    pub async fn get(&self, id: &srql::Thing) -> Result<Option<Session>> {
+        let account_id = self.current.id()?.to_account_thing();
+        let session: Option<Session> = self.persist.db().select(id.clone()).await?;
+        Ok(session.filter(|session| session.account_id == account_id))
+    }
+
+    /// Lists the current account's sessions, newest first.
+    #[instrument(skip_all)]
+    pub async fn list(&self) -> Result<Vec<Session>> {
+        let account_id = self.current.id()?.to_account_thing();
+        self.list_of(account_id).await
+    }
+
+    /// Lists the current account's sessions that are still active, newest
+    /// first. Sessions that have been revoked or have expired are left out.
+    #[instrument(skip_all)]
+    pub async fn list_active(&self) -> Result<Vec<Session>> {
+        let account_id = self.current.id()?.to_account_thing();
+        let account: Option<Account> = self.persist.load(account_id.clone()).await?;
+        let admin = account.is_some_and(|account| account.admin);
+        let now = self.persist.clock().now();
+        let mut sessions = self.list_of(account_id).await?;
+        sessions.retain(|session| session.is_active(self.persist.sessions(), admin, now));
+        Ok(sessions)
+    }
+
+    /// Signs out one of the current account's sessions. Its tokens stop
+    /// working straight away, including access tokens that haven't expired
+    /// yet.
+    ///
+    /// Returns `false` if the session isn't the current account's or was
+    /// already revoked.
+    #[instrument(skip_all)]
+    pub async fn revoke(&self, id: &str) -> Result<bool> {
+        let account_id = self.current.id()?.to_account_thing();
+        let session_id = srql::Thing::from((SESSION_TABLE_NAME, id));
+
+        let mut update = vec![];
+        self.persist
+            .clock()
+            .now()
+            .push_field(srql::field("revoked_at"), &mut update);
+        let revoked: Vec<Session> = self
+            .persist
+            .db()
+            .query(srql::UpdateStatement {
+                what: srql::thing(session_id.clone()),
+                data: srql::Data::SetExpression(update).into(),
+                cond: srql::Cond(
+                    srql::Expression::Binary {
+                        l: srql::Expression::Binary {
+                            l: srql::field("account_id").into(),
+                            o: srql::Operator::Equal,
+                            r: account_id.clone().into(),
+                        }
+                        .into(),
+                        o: srql::Operator::And,
+                        r: not_revoked().into(),
+                    }
+                    .into(),
+                )
+                .into(),
+                output: srql::Output::After.into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        if revoked.is_empty() {
+            return Ok(false);
+        }
+
+        SecurityEventPersist::new(self.persist, self.current)
+            .log(
+                account_id,
+                SecurityEventKind::SessionRevoked,
+                Some(session_id),
+            )
+            .await?;
+        Ok(true)
+    }
+
+    /// Signs out every one of the current account's sessions, other than the
+    /// one the request was made with if `keep_current` is set. Returns how
+    /// many were signed out.
+    ///
+    /// Without `keep_current`, every token issued for the account is revoked
+    /// too, including ones from before sessions were tracked.
+    #[instrument(skip_all)]
+    pub async fn revoke_all(&self, keep_current: bool) -> Result<usize> {
+        let account_id = self.current.id()?.to_account_thing();
+        let keep = match self.current.session_id()? {
+            Some(session_id) if keep_current => {
+                Some(srql::Thing::from((SESSION_TABLE_NAME, session_id.as_str())))
+            }
+            _ => None,
+        };
+
+        let revoked = revoke_sessions_of(self.persist, &account_id, keep.as_ref()).await?;
+        let events = SecurityEventPersist::new(self.persist, self.current);
+        if keep.is_none() {
+            let mut update = vec![];
+            self.persist
+                .clock()
+                .now()
+                .push_field(srql::field("revoked_at"), &mut update);
+            let Some(update) = srql::obj_update_query(account_id.clone(), update) else {
+                return Err("".into());
+            };
+            self.persist.db().query(update).await?.check()?;
+            events
+                .log(account_id, SecurityEventKind::TokensRevoked, None)
+                .await?;
+        } else {
+            for session in &revoked {
+                events
+                    .log(
+                        account_id.clone(),
+                        SecurityEventKind::SessionRevoked,
+                        Some(session.id.clone()),
+                    )
+                    .await?;
+            }
+        }
+        Ok(revoked.len())
+    }
+
+    /// Lists an account's sessions, newest first. Only admins can do this.
+    #[instrument(skip_all)]
+    pub async fn list_for(&self, account_id: &str) -> Result<Vec<Session>> {
+        require_admin(self.persist, self.current).await?;
+        self.list_of(account_id.to_account_thing()).await
+    }
+
+    async fn list_of(&self, account_id: srql::Thing) -> Result<Vec<Session>> {
+        let sessions = self
+            .persist
+            .db()
+            .query(srql::SelectStatement {
+                expr: srql::Fields::all(),
+                what: srql::table(SESSION_TABLE_NAME),
+                cond: srql::Cond(
+                    srql::Expression::Binary {
+                        l: srql::field("account_id").into(),
+                        o: srql::Operator::Equal,
+                        r: account_id.into(),
+                    }
+                    .into(),
+                )
+                .into(),
+                order: srql::Orders(vec![srql::Order {
+                    order: srql::field("id"),
+                    direction: SRQL_ORDER_DESC,
+                    ..Default::default()
+                }])
+                .into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        Ok(sessions)
+    }
+
+    /// Removes the IP addresses and user agents of sessions older than the
+    /// instance's retention period, returning how many were redacted.
+    #[instrument(skip_all)]
+    pub async fn redact_expired(&self) -> Result<usize> {
+        if self.privacy.retention_days == 0 {
+            return Ok(0);
+        }
+
+        let cutoff =
+            self.persist.clock().now() - Duration::days(i64::from(self.privacy.retention_days));
+        let before_cutoff = srql::Expression::Binary {
+            l: srql::field("id").into(),
+            o: srql::Operator::LessThan,
+            r: srql::Thing::from((SESSION_TABLE_NAME.to_owned(), srql::ulid_at(cutoff))).into(),
+        };
+        let has_metadata = srql::Expression::Binary {
+            l: srql::Expression::Binary {
+                l: srql::field("ip").into(),
+                o: srql::Operator::NotEqual,
+                r: srql::Value::None,
+            }
+            .into(),
+            o: srql::Operator::Or,
+            r: srql::Expression::Binary {
+                l: srql::field("user_agent").into(),
+                o: srql::Operator::NotEqual,
+                r: srql::Value::None,
+            }
+            .into(),
+        };
+
+        let redacted: Vec<Session> = self
+            .persist
+            .db()
+            .query(srql::UpdateStatement {
+                what: srql::table(SESSION_TABLE_NAME),
+                data: srql::Data::SetExpression(vec![
+                    (srql::field("ip"), srql::Operator::Equal, srql::Value::None),
+                    (
+                        srql::field("user_agent"),
+                        srql::Operator::Equal,
+                        srql::Value::None,
+                    ),
+                ])
+                .into(),
+                cond: srql::cond_and(
+                    Some(srql::Cond(before_cutoff.into())),
+                    Some(srql::Cond(has_metadata.into())),
+                ),
+                output: srql::Output::After.into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        Ok(redacted.len())
+    }
+}
+
+/// Signs out every session of an account that hasn't been already, other
+/// than `except` if it's given, returning the sessions that were.
+pub async fn revoke_sessions_of(
+    persist: &Persist,
+    account_id: &srql::Thing,
+    except: Option<&srql::Thing>,
+) -> Result<Vec<Session>> {
+    let mut update = vec![];
+    persist
+        .clock()
+        .now()
+        .push_field(srql::field("revoked_at"), &mut update);
+    let except = except.map(|except| {
+        srql::Cond(
+            srql::Expression::Binary {
+                l: srql::field("id").into(),
+                o: srql::Operator::NotEqual,
+                r: except.clone().into(),
+            }
+            .into(),
+        )
+    });
+
+    let revoked = persist
+        .db()
+        .query(srql::UpdateStatement {
+            what: srql::table(SESSION_TABLE_NAME),
+            data: srql::Data::SetExpression(update).into(),
+            cond: srql::cond_and(
+                srql::cond_and(
+                    Some(srql::Cond(
+                        srql::Expression::Binary {
+                            l: srql::field("account_id").into(),
+                            o: srql::Operator::Equal,
+                            r: account_id.clone().into(),
+                        }
+                        .into(),
+                    )),
+                    Some(srql::Cond(not_revoked().into())),
+                ),
+                except,
+            ),
+            output: srql::Output::After.into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    Ok(revoked)
+}
+
+/// Checks that the session an access token was issued for hasn't been
+/// revoked, so that signing a session out takes effect straight away rather
+/// than once its access tokens expire. Tokens that weren't issued for a
+/// session, and requests without one, pass.
+#[instrument(skip_all)]
+pub async fn check_session(persist: &Persist, current: &CurrentAccount) -> Result<()> {
+    let Ok(Some(session_id)) = current.session_id() else {
+        return Ok(());
+    };
+    let session: Option<Session> = persist
+        .db()
+        .select((SESSION_TABLE_NAME, session_id.as_str()))
+        .await?;
+    match session {
+        Some(session) if session.revoked_at.is_none() => Ok(()),
+        _ => Err(Error::SessionRevoked),
+    }
+}
+
+fn not_revoked() -> srql::Expression {
+    srql::Expression::Binary {
+        l: srql::field("revoked_at").into(),
+        o: srql::Operator::Equal,
+        r: srql::Value::None,
+    }
+}
+
+/// Picks a session up again when its tokens are refreshed, so that it's no
+/// longer idle. Sessions that have ended, or that aren't the account's, can't
+/// be.
+#[instrument(skip_all)]
+pub async fn resume_session(
+    persist: &Persist,
+    session_id: &str,
+    account: &Account,
+) -> Result<Session> {
+    let session: Option<Session> = persist
+        .db()
+        .select((SESSION_TABLE_NAME, session_id))
+        .await?;
+    let Some(session) = session.filter(|session| session.account_id == account.id) else {
+        return Err(Error::CredentialsInvalid);
+    };
+    if session.revoked_at.is_some() {
+        return Err(Error::SessionRevoked);
+    }
+    let now = persist.clock().now();
+    if session
+        .expiry(persist.sessions(), account.admin)
+        .is_some_and(|expires_at| expires_at <= now)
+    {
+        return Err(Error::SessionExpired);
+    }
+
+    let mut update = vec![];
+    now.push_field(srql::field("last_active_at"), &mut update);
+    let resumed: Option<Session> = persist
+        .db()
+        .query(srql::UpdateStatement {
+            what: srql::thing(session.id.clone()),
+            data: srql::Data::SetExpression(update).into(),
+            output: srql::Output::After.into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    Ok(resumed.unwrap_or(session))
+}
+
+#[cfg(test)]
+pub mod testing {
+    use super::SessionPersist;
+    use crate::{account::testing::TestData, config::PrivacyConfig};
+
+    pub trait SessionTestData {
+        fn session<'a>(&'a self, privacy: &'a PrivacyConfig) -> SessionPersist<'a>;
+    }
+
+    impl SessionTestData for TestData {
+        fn session<'a>(&'a self, privacy: &'a PrivacyConfig) -> SessionPersist<'a> {
+            SessionPersist::new(&self.persist, &self.current, privacy)
+        }
+    }
+}
@@ -0,0 +1,325 @@
+use chrono::{Duration, TimeZone as _, Utc};
+use pretty_assertions::assert_eq;
+
+use super::{testing::SessionTestData as _, *};
+use crate::{
+    account::testing::*,
+    config::{IpStorage, PrivacyConfig, SessionConfig},
+    provider::MockClock,
+    session::ClientApp,
+};
+
+fn client() -> ClientMeta {
+    ClientMeta {
+        ip: Some("203.0.113.7".parse().unwrap()),
+        user_agent: Some("Mozilla/5.0".into()),
+        locales: vec![],
+        app: ClientApp::new("plazer-web", "1.2.0", Some("web")),
+    }
+}
+
+#[tokio::test]
+async fn test_record() {
+    let (data, acc) = TestData::with_user().await;
+    let privacy = PrivacyConfig::default();
+
+    let session = data
+        .session(&privacy)
+        .record(acc.id.clone(), Some(&client()))
+        .await
+        .unwrap();
+    assert_eq!(session.account_id, acc.id);
+    assert_eq!(session.ip.as_deref(), Some("203.0.113.0"));
+    assert_eq!(session.user_agent.as_deref(), Some("Mozilla/5.0"));
+    assert_eq!(session.app_name.as_deref(), Some("plazer-web"));
+    assert_eq!(session.app_version.as_deref(), Some("1.2.0"));
+    assert_eq!(session.app_platform.as_deref(), Some("web"));
+
+    let sessions = data.session(&privacy).list().await.unwrap();
+    assert_eq!(sessions, vec![session]);
+}
+
+#[tokio::test]
+async fn test_record_without_ip() {
+    let (data, acc) = TestData::with_user().await;
+    let privacy = PrivacyConfig {
+        ip_storage: IpStorage::None,
+        ..Default::default()
+    };
+
+    let session = data
+        .session(&privacy)
+        .record(acc.id.clone(), Some(&client()))
+        .await
+        .unwrap();
+    assert_eq!(session.ip, None);
+    assert_eq!(session.user_agent.as_deref(), Some("Mozilla/5.0"));
+
+    let session = data
+        .session(&privacy)
+        .record(acc.id.clone(), None)
+        .await
+        .unwrap();
+    assert_eq!(session.user_agent, None);
+}
+
+#[tokio::test]
+async fn test_record_region() {
+    let (mut data, acc) = TestData::with_user().await;
+    data.persist = data.persist.with_region(Some("eu-west".into()));
+    let privacy = PrivacyConfig::default();
+
+    let session = data
+        .session(&privacy)
+        .record(acc.id.clone(), None)
+        .await
+        .unwrap();
+    assert_eq!(session.region.as_deref(), Some("eu-west"));
+}
+
+#[tokio::test]
+async fn test_list_for_admin_only() {
+    let (mut data, admin) = TestData::with_user().await;
+    let privacy = PrivacyConfig::default();
+    let other = data.account().create_test_user().await;
+    data.session(&privacy)
+        .record(other.id.clone(), Some(&client()))
+        .await
+        .unwrap();
+
+    let sessions = data
+        .session(&privacy)
+        .list_for(&other.id.to_gql_id())
+        .await
+        .unwrap();
+    assert_eq!(sessions.len(), 1);
+
+    data.login_as(&other);
+    let res = data.session(&privacy).list_for(&admin.id.to_gql_id()).await;
+    assert_eq!(res.unwrap_err(), Error::Unauthorized);
+}
+
+#[tokio::test]
+async fn test_redact_expired() {
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap());
+    let mut data = TestData::with_clock(clock.clone()).await;
+    let acc = data.account().create_test_user().await;
+    data.login_as(&acc);
+    let privacy = PrivacyConfig {
+        retention_days: 30,
+        ..Default::default()
+    };
+    let client = client();
+
+    let old = data
+        .session(&privacy)
+        .record(acc.id.clone(), Some(&client))
+        .await
+        .unwrap();
+    clock.advance(Duration::days(20));
+    let recent = data
+        .session(&privacy)
+        .record(acc.id.clone(), Some(&client))
+        .await
+        .unwrap();
+
+    clock.advance(Duration::days(15));
+    assert_eq!(data.session(&privacy).redact_expired().await.unwrap(), 1);
+    // Sessions that have already been redacted are left alone.
+    assert_eq!(data.session(&privacy).redact_expired().await.unwrap(), 0);
+
+    data.login_as(&acc);
+    let listed = data.session(&privacy).list().await.unwrap();
+    assert_eq!(listed.len(), 2);
+    assert_eq!(listed[0], recent.id);
+    assert!(listed[0].ip.is_some());
+    assert_eq!(listed[1], old.id);
+    assert_eq!(listed[1].ip, None);
+    assert_eq!(listed[1].user_agent, None);
+}
+
+#[tokio::test]
+async fn test_redact_kept_forever() {
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap());
+    let mut data = TestData::with_clock(clock.clone()).await;
+    let acc = data.account().create_test_user().await;
+    data.login_as(&acc);
+    let privacy = PrivacyConfig {
+        retention_days: 0,
+        ..Default::default()
+    };
+
+    data.session(&privacy)
+        .record(acc.id.clone(), Some(&client()))
+        .await
+        .unwrap();
+    clock.advance(Duration::days(3650));
+    assert_eq!(data.session(&privacy).redact_expired().await.unwrap(), 0);
+}
+
+fn lifetimes() -> SessionConfig {
+    SessionConfig {
+        idle_timeout: Some(std::time::Duration::from_hours(1)),
+        max_lifetime: Some(std::time::Duration::from_hours(24)),
+        admin_idle_timeout: Some(std::time::Duration::from_mins(10)),
+        admin_max_lifetime: None,
+    }
+}
+
+#[tokio::test]
+async fn test_resume_idle() {
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap());
+    let mut data = TestData::with_clock(clock.clone()).await;
+    data.persist = data.persist.with_sessions(lifetimes());
+    let admin = data.account().create_test_user().await;
+    let acc = data.account().create_test_user().await;
+    let privacy = PrivacyConfig::default();
+    let session = data
+        .session(&privacy)
+        .record(acc.id.clone(), None)
+        .await
+        .unwrap();
+    assert_eq!(
+        session.expiry(data.persist.sessions(), false),
+        Some(clock.now() + Duration::hours(1))
+    );
+
+    // Using the session keeps it going.
+    clock.advance(Duration::minutes(50));
+    let resumed = resume_session(&data.persist, &session.id.to_gql_id(), &acc.acc)
+        .await
+        .unwrap();
+    assert_eq!(resumed.last_active(), clock.now());
+    clock.advance(Duration::minutes(50));
+    resume_session(&data.persist, &session.id.to_gql_id(), &acc.acc)
+        .await
+        .unwrap();
+
+    clock.advance(Duration::minutes(61));
+    let res = resume_session(&data.persist, &session.id.to_gql_id(), &acc.acc).await;
+    assert_eq!(res.unwrap_err(), Error::SessionExpired);
+
+    // Admins' sessions go idle sooner, and sessions only work for their own
+    // account.
+    let session = data
+        .session(&privacy)
+        .record(admin.id.clone(), None)
+        .await
+        .unwrap();
+    let res = resume_session(&data.persist, &session.id.to_gql_id(), &acc.acc).await;
+    assert_eq!(res.unwrap_err(), Error::CredentialsInvalid);
+    clock.advance(Duration::minutes(11));
+    let res = resume_session(&data.persist, &session.id.to_gql_id(), &admin.acc).await;
+    assert_eq!(res.unwrap_err(), Error::SessionExpired);
+}
+
+#[tokio::test]
+async fn test_resume_max_lifetime() {
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap());
+    let mut data = TestData::with_clock(clock.clone()).await;
+    data.persist = data.persist.with_sessions(lifetimes());
+    // The first account is an admin, which would have a shorter idle timeout.
+    data.account().create_test_user().await;
+    let acc = data.account().create_test_user().await;
+    let privacy = PrivacyConfig::default();
+    let session = data
+        .session(&privacy)
+        .record(acc.id.clone(), None)
+        .await
+        .unwrap();
+    assert_eq!(
+        session.absolute_expiry(data.persist.sessions(), false),
+        Some(clock.now() + Duration::hours(24))
+    );
+
+    // Staying active keeps the session going, but only until its max lifetime.
+    for _ in 0..24 {
+        clock.advance(Duration::minutes(59));
+        resume_session(&data.persist, &session.id.to_gql_id(), &acc.acc)
+            .await
+            .unwrap();
+    }
+    clock.advance(Duration::minutes(24));
+    let res = resume_session(&data.persist, &session.id.to_gql_id(), &acc.acc).await;
+    assert_eq!(res.unwrap_err(), Error::SessionExpired);
+}
+
+#[tokio::test]
+async fn test_revoke() {
+    let (mut data, acc) = TestData::with_user().await;
+    let other = data.account().create_test_user().await;
+    let privacy = PrivacyConfig::default();
+    let first = data
+        .session(&privacy)
+        .record(acc.id.clone(), None)
+        .await
+        .unwrap();
+    let second = data
+        .session(&privacy)
+        .record(acc.id.clone(), None)
+        .await
+        .unwrap();
+    data.login_in_session(&acc, &second.id);
+    assert_eq!(check_session(&data.persist, &data.current).await, Ok(()));
+
+    // Only the account's own sessions can be revoked.
+    data.login_as(&other);
+    let res = data.session(&privacy).revoke(&second.id.to_gql_id()).await;
+    assert_eq!(res, Ok(false));
+
+    data.login_in_session(&acc, &first.id);
+    let res = data.session(&privacy).revoke(&second.id.to_gql_id()).await;
+    assert_eq!(res, Ok(true));
+    let res = data.session(&privacy).revoke(&second.id.to_gql_id()).await;
+    assert_eq!(res, Ok(false));
+
+    let active = data.session(&privacy).list_active().await.unwrap();
+    assert_eq!(
+        active.iter().map(|s| &s.id).collect::<Vec<_>>(),
+        vec![&first.id]
+    );
+    // Revoked sessions are still listed with the rest of the sign-ins.
+    let sessions = data.session(&privacy).list().await.unwrap();
+    assert_eq!(sessions.len(), 2);
+
+    // Tokens for the revoked session stop working straight away.
+    data.login_in_session(&acc, &second.id);
+    let res = check_session(&data.persist, &data.current).await;
+    assert_eq!(res, Err(Error::SessionRevoked));
+    let res = resume_session(&data.persist, &second.id.to_gql_id(), &acc.acc).await;
+    assert_eq!(res.unwrap_err(), Error::SessionRevoked);
+
+    // Tokens that weren't issued for a session aren't affected.
+    data.login_as(&acc);
+    assert_eq!(check_session(&data.persist, &data.current).await, Ok(()));
+}
+
+#[tokio::test]
+async fn test_revoke_all() {
+    let (mut data, acc) = TestData::with_user().await;
+    let privacy = PrivacyConfig::default();
+    let mut sessions = vec![];
+    for _ in 0..3 {
+        let session = data
+            .session(&privacy)
+            .record(acc.id.clone(), None)
+            .await
+            .unwrap();
+        sessions.push(session);
+    }
+
+    data.login_in_session(&acc, &sessions[0].id);
+    let res = data.session(&privacy).revoke_all(true).await;
+    assert_eq!(res, Ok(2));
+    assert_eq!(check_session(&data.persist, &data.current).await, Ok(()));
+    let active = data.session(&privacy).list_active().await.unwrap();
+    assert_eq!(active.len(), 1);
+
+    let res = data.session(&privacy).revoke_all(false).await;
+    assert_eq!(res, Ok(1));
+    let res = check_session(&data.persist, &data.current).await;
+    assert_eq!(res, Err(Error::SessionRevoked));
+
+    let account = data.account().get(&acc.id.id.to_raw()).await.unwrap();
+    assert!(account.and_then(|account| account.revoked_at).is_some());
+}
@@ -0,0 +1,130 @@
+use std::{fmt::Write as _, net::IpAddr};
+
+use ring::hmac;
+
+use crate::config::{IpStorage, PrivacyConfig};
+
+/// The longest user agent that is stored. Anything past this is cut off.
+pub const MAX_USER_AGENT_LEN: usize = 256;
+
+/// Turns an IP address into what should be stored for it.
+#[must_use]
+pub fn store_ip(ip: IpAddr, config: &PrivacyConfig) -> Option<String> {
+    match config.ip_storage {
+        IpStorage::Full => Some(ip.to_string()),
+        IpStorage::Truncated => Some(truncate_ip(ip).to_string()),
+        IpStorage::Hashed => Some(hash_ip(ip, &config.hash_key)),
+        IpStorage::None => None,
+    }
+}
+
+/// Turns a user agent into what should be stored for it.
+#[must_use]
+pub fn store_user_agent(user_agent: &str) -> Option<String> {
+    let user_agent = user_agent.trim();
+    if user_agent.is_empty() {
+        return None;
+    }
+    Some(user_agent.chars().take(MAX_USER_AGENT_LEN).collect())
+}
+
+/// Keeps only the network part of an address: the first 3 octets of IPv4
+/// addresses, and the first 48 bits of IPv6 addresses.
+#[must_use]
+pub fn truncate_ip(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            IpAddr::from([a, b, c, 0])
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return truncate_ip(ip.into());
+            }
+            let mut segments = ip.segments();
+            segments[3..].fill(0);
+            IpAddr::from(segments)
+        }
+    }
+}
+
+/// Hashes an address with the instance's key, so that the same address can
+/// be recognised without it being recoverable.
+#[must_use]
+pub fn hash_ip(ip: IpAddr, key: &hmac::Key) -> String {
+    let ip = match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+        IpAddr::V4(_) => ip,
+    };
+    let tag = hmac::sign(key, ip.to_string().as_bytes());
+    tag.as_ref()[..16]
+        .iter()
+        .fold(String::with_capacity(32), |mut hash, byte| {
+            let _ = write!(hash, "{byte:02x}");
+            hash
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::*;
+
+    #[test_case("192.168.1.42" => "192.168.1.0"; "ipv4")]
+    #[test_case("2001:db8:85a3:8d3:1319:8a2e:370:7348" => "2001:db8:85a3::"; "ipv6")]
+    #[test_case("::ffff:10.1.2.3" => "10.1.2.0"; "mapped ipv4")]
+    fn test_truncate_ip(ip: &str) -> String {
+        truncate_ip(ip.parse().unwrap()).to_string()
+    }
+
+    #[test]
+    fn test_store_ip() {
+        let ip: IpAddr = "192.168.1.42".parse().unwrap();
+        let config = |ip_storage| PrivacyConfig {
+            ip_storage,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            store_ip(ip, &config(IpStorage::Full)).as_deref(),
+            Some("192.168.1.42")
+        );
+        assert_eq!(
+            store_ip(ip, &config(IpStorage::Truncated)).as_deref(),
+            Some("192.168.1.0")
+        );
+        assert_eq!(store_ip(ip, &config(IpStorage::None)), None);
+
+        let hashed = store_ip(ip, &config(IpStorage::Hashed)).unwrap();
+        assert_eq!(hashed.len(), 32);
+        assert!(!hashed.contains("192"));
+        assert_eq!(
+            store_ip(
+                "::ffff:192.168.1.42".parse().unwrap(),
+                &config(IpStorage::Hashed)
+            ),
+            Some(hashed)
+        );
+    }
+
+    #[test]
+    fn test_hash_ip_keyed() {
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let a = PrivacyConfig::derive_hash_key("a");
+        let b = PrivacyConfig::derive_hash_key("b");
+        assert_eq!(hash_ip(ip, &a), hash_ip(ip, &a));
+        assert_ne!(hash_ip(ip, &a), hash_ip(ip, &b));
+    }
+
+    #[test]
+    fn test_store_user_agent() {
+        assert_eq!(store_user_agent("  "), None);
+        assert_eq!(store_user_agent("curl/8.0").as_deref(), Some("curl/8.0"));
+        let long = "a".repeat(MAX_USER_AGENT_LEN * 2);
+        assert_eq!(
+            store_user_agent(&long).map(|ua| ua.len()),
+            Some(MAX_USER_AGENT_LEN)
+        );
+    }
+}
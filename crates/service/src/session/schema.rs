@@ -0,0 +1,49 @@
+use async_graphql::{Context, Object, ID};
+use tracing::instrument;
+
+use super::Session;
+use crate::prelude::*;
+
+#[derive(Default)]
+pub struct SessionQuery;
+
+#[Object]
+impl SessionQuery {
+    /// The current account's sessions that haven't been revoked or expired,
+    /// newest first. Every sign-in is listed in `Account.sessions`.
+    #[instrument(skip_all)]
+    async fn sessions(&self, ctx: &Context<'_>) -> GqlResult<Vec<Session>> {
+        ctx.session_persist().list_active().await.extend()
+    }
+}
+
+#[derive(Default)]
+pub struct SessionMutation;
+
+#[Object]
+impl SessionMutation {
+    /// Signs out one of the current account's sessions. Its tokens stop
+    /// working straight away.
+    ///
+    /// Returns `false` if the session doesn't exist or was already signed
+    /// out.
+    #[instrument(skip_all)]
+    async fn revoke_session(&self, ctx: &Context<'_>, id: ID) -> GqlResult<bool> {
+        ctx.session_persist().revoke(&id).await.extend()
+    }
+
+    /// Signs out all of the current account's sessions, returning how many
+    /// were signed out. With `keepCurrent`, the session the request was made
+    /// with stays signed in.
+    #[instrument(skip_all)]
+    async fn revoke_all_sessions(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] keep_current: bool,
+    ) -> GqlResult<usize> {
+        ctx.session_persist()
+            .revoke_all(keep_current)
+            .await
+            .extend()
+    }
+}
@@ -0,0 +1,187 @@
+use std::fmt::Write as _;
+
+/// The name the instance is shown as in link previews.
+pub static SITE_NAME: &str = "Plazer";
+
+/// How much of a post's content is used as the preview description.
+const MAX_DESCRIPTION_CHARS: usize = 200;
+
+/// What kind of page a preview is for, which decides the `og:type`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageKind {
+    Profile,
+    Post,
+}
+
+impl PageKind {
+    fn og_type(self) -> &'static str {
+        match self {
+            Self::Profile => "profile",
+            Self::Post => "article",
+        }
+    }
+}
+
+/// The metadata a link preview is built from.
+#[derive(Debug, Clone)]
+pub struct PageMeta {
+    pub kind: PageKind,
+    pub title: String,
+    pub description: Option<String>,
+    pub author: Option<String>,
+    /// The URL of the page itself.
+    pub url: String,
+    /// The URL of the oEmbed document describing the page.
+    pub oembed_url: String,
+    /// The URL of the license that the page's content is published under.
+    pub license_url: Option<String>,
+}
+
+impl PageMeta {
+    /// Renders a minimal page carrying the preview tags. The page also
+    /// contains the text itself, for anything that ignores the tags.
+    #[must_use]
+    pub fn render(&self) -> String {
+        let mut html = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+
+        let _ = writeln!(html, "<title>{}</title>", escape(&self.title));
+        // OpenGraph uses `property` and Twitter uses `name`.
+        let mut meta = |name: &str, content: &str| {
+            let attr = if name.starts_with("og:") {
+                "property"
+            } else {
+                "name"
+            };
+            let _ = writeln!(
+                html,
+                "<meta {attr}=\"{name}\" content=\"{}\">",
+                escape(content)
+            );
+        };
+        meta("og:site_name", SITE_NAME);
+        meta("og:type", self.kind.og_type());
+        meta("og:title", &self.title);
+        meta("og:url", &self.url);
+        meta("twitter:card", "summary");
+        meta("twitter:title", &self.title);
+        if let Some(description) = &self.description {
+            meta("og:description", description);
+            meta("twitter:description", description);
+        }
+
+        let _ = writeln!(
+            html,
+            "<link rel=\"canonical\" href=\"{}\">",
+            escape(&self.url)
+        );
+        let _ = writeln!(
+            html,
+            "<link rel=\"alternate\" type=\"application/json+oembed\" href=\"{}\">",
+            escape(&self.oembed_url)
+        );
+        if let Some(license_url) = &self.license_url {
+            let _ = writeln!(
+                html,
+                "<link rel=\"license\" href=\"{}\">",
+                escape(license_url)
+            );
+        }
+        html.push_str("</head>\n<body>\n");
+        let _ = writeln!(html, "<h1>{}</h1>", escape(&self.title));
+        if let Some(description) = &self.description {
+            let _ = writeln!(html, "<p>{}</p>", escape(description));
+        }
+        html.push_str("</body>\n</html>\n");
+
+        html
+    }
+}
+
+/// Cuts `content` down to a length that fits in a preview, on a character
+/// boundary.
+#[must_use]
+pub fn describe(content: &str) -> String {
+    let content = content.trim();
+    match content.char_indices().nth(MAX_DESCRIPTION_CHARS) {
+        Some((end, _)) => format!("{}…", content[..end].trim_end()),
+        None => content.to_owned(),
+    }
+}
+
+/// Escapes text for use in HTML content and quoted attributes.
+#[must_use]
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(
+            escape(r#"<a href="x">'&'</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;&#39;&amp;&#39;&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn test_describe_truncates() {
+        assert_eq!(describe("  short  "), "short");
+
+        let long = "é".repeat(MAX_DESCRIPTION_CHARS + 10);
+        let described = describe(&long);
+        assert_eq!(described.chars().count(), MAX_DESCRIPTION_CHARS + 1);
+        assert!(described.ends_with('…'));
+    }
+
+    #[test]
+    fn test_render_escapes_tags() {
+        let html = PageMeta {
+            kind: PageKind::Post,
+            title: "\"><script>".into(),
+            description: Some("a & b".into()),
+            author: None,
+            url: "/posts/1".into(),
+            oembed_url: "/api/oembed?url=%2Fposts%2F1".into(),
+            license_url: None,
+        }
+        .render();
+
+        assert!(!html.contains("<script>"));
+        assert!(html.contains(r#"<meta property="og:title" content="&quot;&gt;&lt;script&gt;">"#));
+        assert!(html.contains(r#"<meta property="og:description" content="a &amp; b">"#));
+        assert!(html.contains(r#"<meta property="og:type" content="article">"#));
+        assert!(!html.contains(r#"rel="license""#));
+    }
+
+    #[test]
+    fn test_render_license() {
+        let html = PageMeta {
+            kind: PageKind::Post,
+            title: "Title".into(),
+            description: None,
+            author: None,
+            url: "/posts/1".into(),
+            oembed_url: "/api/oembed?url=%2Fposts%2F1".into(),
+            license_url: Some("https://creativecommons.org/licenses/by/4.0/".into()),
+        }
+        .render();
+
+        assert!(html.contains(
+            r#"<link rel="license" href="https://creativecommons.org/licenses/by/4.0/">"#
+        ));
+    }
+}
@@ -0,0 +1,265 @@
+//! Server-rendered pages for public profiles and posts.
+//!
+//! The main client is a single-page app, so anything that fetches a shared
+//! link to build a preview (chat apps, mostly) wouldn't find anything useful
+//! in it. These pages carry just the `OpenGraph` and Twitter Card tags those
+//! previews are built from, and link to an oEmbed document for consumers that
+//! prefer that.
+
+mod meta;
+
+use axum::{
+    extract::{Path, Query, State},
+    response::Html,
+    routing::get,
+    Json, Router,
+};
+use ring::rand::SystemRandom;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::instrument;
+
+pub use self::meta::*;
+use crate::{
+    account::{Account, AccountPersist, CurrentAccount},
+    conv::ToGqlId as _,
+    error::{self, Error, ErrorResponse},
+    locale::{LanguageIdentifier, Localizer},
+    organization::affiliation_of,
+    persist::Persist,
+    post::{Post, PostPersist},
+    session::ClientMeta,
+    DecodingKey,
+};
+
+/// Everything needed to look up what's being previewed.
+#[derive(Clone)]
+pub struct ShareState {
+    pub persist: Persist,
+    pub csrng: SystemRandom,
+    pub jwt_dec_key: DecodingKey,
+    pub public_url: Option<String>,
+    pub localizer: Arc<Localizer>,
+}
+
+impl ShareState {
+    /// Makes a path absolute if the public URL is known.
+    fn url(&self, path: &str) -> String {
+        format!("{}{path}", self.public_url.as_deref().unwrap_or_default())
+    }
+
+    fn oembed_url(&self, path: &str) -> String {
+        let query = serde_urlencoded::to_string([("url", self.url(path))])
+            .expect("a string pair can always be encoded");
+        self.url(&format!("/api/oembed?{query}"))
+    }
+
+    /// Builds the preview for a page, if it's one that can be previewed.
+    /// Limited or restricted accounts and their posts aren't.
+    async fn page_meta(
+        &self,
+        path: &str,
+        locales: &[LanguageIdentifier],
+    ) -> error::Result<Option<PageMeta>> {
+        let current = CurrentAccount::default();
+        let accounts = AccountPersist::new(&self.persist, &current, &self.csrng, &self.jwt_dec_key);
+        let segments: Vec<_> = path.trim_matches('/').split('/').collect();
+
+        match segments[..] {
+            ["users", user_id] => {
+                let Some(acc) = accounts.get_by_user_id(user_id).await? else {
+                    return Ok(None);
+                };
+                if acc.limited || acc.active_restriction(self.persist.clock().now()).is_some() {
+                    return Ok(None);
+                }
+
+                let bot = acc
+                    .bot
+                    .then(|| self.localizer.render(locales, "share-profile-bot", &[]));
+                let affiliation = affiliation_of(&self.persist, &acc.id)
+                    .await?
+                    .and_then(|org| {
+                        let domain = org.domain?;
+                        Some(self.localizer.render(
+                            locales,
+                            "share-profile-affiliation",
+                            &[
+                                ("organization", org.name.as_ref().unwrap_or(&org.handle)),
+                                ("domain", &domain),
+                            ],
+                        ))
+                    });
+                let description: Vec<_> = bot.into_iter().chain(affiliation).collect();
+
+                Ok(Some(PageMeta {
+                    kind: PageKind::Profile,
+                    title: format!("@{}", acc.user_id),
+                    description: (!description.is_empty()).then(|| description.join(" · ")),
+                    author: Some(acc.user_id),
+                    url: self.url(path),
+                    oembed_url: self.oembed_url(path),
+                    license_url: None,
+                }))
+            }
+            ["posts", post_id] => {
+                let Some(post) = PostPersist::new(&self.persist, &current)
+                    .get(post_id)
+                    .await?
+                else {
+                    return Ok(None);
+                };
+                let author = match &post.creator_id {
+                    Some(creator_id) => accounts.get(&creator_id.to_gql_id()).await?,
+                    None => None,
+                };
+                if post.limited || author.as_ref().is_some_and(|acc| acc.limited) {
+                    return Ok(None);
+                }
+
+                Ok(Some(self.post_meta(path, post, author, locales)))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn post_meta(
+        &self,
+        path: &str,
+        post: Post,
+        author: Option<Account>,
+        locales: &[LanguageIdentifier],
+    ) -> PageMeta {
+        let author = author.map(|acc| acc.user_id);
+        let title = match (post.title, &author) {
+            (Some(title), _) => title,
+            (None, Some(author)) => {
+                self.localizer
+                    .render(locales, "share-post-title", &[("author", author)])
+            }
+            (None, None) => self
+                .localizer
+                .render(locales, "share-post-title-anonymous", &[]),
+        };
+
+        PageMeta {
+            kind: PageKind::Post,
+            title,
+            description: post.content.as_deref().map(describe),
+            author,
+            url: self.url(path),
+            oembed_url: self.oembed_url(path),
+            license_url: post.license.url().map(str::to_owned),
+        }
+    }
+}
+
+/// Builds the preview pages and the oEmbed endpoint.
+pub fn router<S>(state: ShareState) -> Router<S> {
+    Router::new()
+        .route("/users/:user_id", get(profile))
+        .route("/posts/:post_id", get(post))
+        .route("/api/oembed", get(oembed))
+        .with_state(state)
+}
+
+/// `GET /users/:user_id`
+#[instrument(skip_all)]
+async fn profile(
+    State(state): State<ShareState>,
+    client: ClientMeta,
+    Path(user_id): Path<String>,
+) -> Result<Html<String>, ErrorResponse> {
+    page(&state, &client, &format!("/users/{user_id}")).await
+}
+
+/// `GET /posts/:post_id`
+#[instrument(skip_all)]
+async fn post(
+    State(state): State<ShareState>,
+    client: ClientMeta,
+    Path(post_id): Path<String>,
+) -> Result<Html<String>, ErrorResponse> {
+    page(&state, &client, &format!("/posts/{post_id}")).await
+}
+
+async fn page(
+    state: &ShareState,
+    client: &ClientMeta,
+    path: &str,
+) -> Result<Html<String>, ErrorResponse> {
+    match state.page_meta(path, &client.locales).await? {
+        Some(meta) => Ok(Html(meta.render())),
+        None => Err(Error::NotFound.into()),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct OEmbedQuery {
+    url: String,
+    format: Option<String>,
+}
+
+/// An oEmbed `link` response.
+#[derive(Debug, Serialize)]
+struct OEmbed {
+    version: &'static str,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    author_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    author_url: Option<String>,
+    provider_name: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    provider_url: Option<String>,
+    /// Not part of the oEmbed spec, but consumers ignore fields they don't
+    /// know, and this lets those that do show how the content can be reused.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    license_url: Option<String>,
+}
+
+/// `GET /api/oembed?url=...`
+#[instrument(skip_all)]
+async fn oembed(
+    State(state): State<ShareState>,
+    client: ClientMeta,
+    Query(query): Query<OEmbedQuery>,
+) -> Result<Json<OEmbed>, ErrorResponse> {
+    if query
+        .format
+        .as_deref()
+        .is_some_and(|format| format != "json")
+    {
+        return Err(Error::InputInvalid("only the json format is supported".into()).into());
+    }
+
+    // Only links to this instance can be embedded. Without a public URL
+    // there's nothing to check the host against, so only the path is used.
+    let url = match &state.public_url {
+        Some(public_url) => query.url.strip_prefix(public_url.as_str()),
+        None => Some(query.url.as_str()),
+    };
+    let Some(uri) = url.and_then(|url| url.parse::<hyper::Uri>().ok()) else {
+        return Err(Error::NotFound.into());
+    };
+
+    let Some(meta) = state.page_meta(uri.path(), &client.locales).await? else {
+        return Err(Error::NotFound.into());
+    };
+
+    Ok(Json(OEmbed {
+        version: "1.0",
+        kind: "link",
+        author_url: meta
+            .author
+            .as_ref()
+            .map(|author| state.url(&format!("/users/{author}"))),
+        author_name: meta.author,
+        title: meta.title,
+        provider_name: SITE_NAME,
+        provider_url: state.public_url.clone(),
+        license_url: meta.license_url,
+    }))
+}
@@ -0,0 +1,394 @@
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use chrono::{DateTime, TimeZone as _, Utc};
+use hyper::{body, client::HttpConnector, Body, Client, Method, Request, Uri};
+use serde::{Deserialize, Serialize};
+use tracing::{instrument, warn};
+
+use crate::{
+    account::Account,
+    config::{SignupRiskConfig, SpamConfig},
+    persist::Persist,
+    post::POST_TABLE_NAME,
+    prelude::*,
+};
+
+const HTTP_CLASSIFIER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What kind of content is being checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpamCandidateKind {
+    Post,
+    Registration,
+}
+
+/// A piece of content to be checked for spam.
+#[derive(Debug, Clone, Copy)]
+pub struct SpamCandidate<'a> {
+    pub kind: SpamCandidateKind,
+    /// The ID of the post or account being checked.
+    pub id: &'a srql::Thing,
+    /// The account that created the content, if any.
+    pub author: Option<&'a Account>,
+    pub text: &'a str,
+    /// What the client sent along with a registration, if this is one.
+    pub signup: Option<SignupSignals>,
+}
+
+/// Signals that clients send along with registrations, which bots filling in
+/// the form automatically tend to give away.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SignupSignals {
+    /// Whether a field that people can't see was filled in.
+    pub honeypot_filled: bool,
+    /// When the registration form was shown, if the client said.
+    pub form_shown_at: Option<DateTime<Utc>>,
+}
+
+/// Something that can score content on how likely it is to be spam.
+#[async_trait]
+pub trait Classifier: Send + Sync {
+    /// The name of the classifier, used to explain why content was flagged.
+    fn name(&self) -> &'static str;
+
+    /// Scores the content from 0 to 100, where 100 is definitely spam.
+    async fn classify(&self, persist: &Persist, candidate: &SpamCandidate<'_>) -> Result<u8>;
+}
+
+/// Scores content by how many of its words are links.
+pub struct LinkDensity;
+
+#[async_trait]
+impl Classifier for LinkDensity {
+    fn name(&self) -> &'static str {
+        "link_density"
+    }
+
+    async fn classify(&self, _: &Persist, candidate: &SpamCandidate<'_>) -> Result<u8> {
+        Ok(link_density(candidate.text))
+    }
+}
+
+fn link_density(text: &str) -> u8 {
+    let (words, links) = text
+        .split_whitespace()
+        .fold((0, 0), |(words, links), word| {
+            let is_link = word.starts_with("http://") || word.starts_with("https://");
+            (words + 1, links + usize::from(is_link))
+        });
+    if words == 0 {
+        return 0;
+    }
+
+    // Content that is half links is treated as spam.
+    u8::try_from((links * 200 / words).min(100)).unwrap_or(100)
+}
+
+/// Scores posts by how many times their author has already posted the same
+/// content.
+pub struct DuplicateContent;
+
+#[async_trait]
+impl Classifier for DuplicateContent {
+    fn name(&self) -> &'static str {
+        "duplicate_content"
+    }
+
+    async fn classify(&self, persist: &Persist, candidate: &SpamCandidate<'_>) -> Result<u8> {
+        let (SpamCandidateKind::Post, Some(author)) = (candidate.kind, candidate.author) else {
+            return Ok(0);
+        };
+
+        let field_cond = |field: &str, o, value: srql::Value| {
+            srql::Cond(
+                srql::Expression::Binary {
+                    l: srql::field(field).into(),
+                    o,
+                    r: value,
+                }
+                .into(),
+            )
+        };
+        let cond = srql::cond_and(
+            srql::cond_and(
+                field_cond(
+                    "creator_id",
+                    srql::Operator::Equal,
+                    author.id.clone().into(),
+                )
+                .into(),
+                field_cond("content", srql::Operator::Equal, candidate.text.into()).into(),
+            ),
+            field_cond("id", srql::Operator::NotEqual, candidate.id.clone().into()).into(),
+        );
+
+        let count: Option<usize> = persist
+            .db()
+            .query(srql::count_query(POST_TABLE_NAME, cond))
+            .await?
+            .take("count")?;
+        let count = count.unwrap_or_default();
+        Ok(u8::try_from((count * 45).min(100)).unwrap_or(100))
+    }
+}
+
+/// Scores posts higher when their author registered recently.
+pub struct AccountAge;
+
+#[async_trait]
+impl Classifier for AccountAge {
+    fn name(&self) -> &'static str {
+        "account_age"
+    }
+
+    async fn classify(&self, persist: &Persist, candidate: &SpamCandidate<'_>) -> Result<u8> {
+        let (SpamCandidateKind::Post, Some(author)) = (candidate.kind, candidate.author) else {
+            return Ok(0);
+        };
+
+        // Account IDs are ULIDs, so they contain when the account was created.
+        let Ok(id) = ulid::Ulid::from_string(&author.id.id.to_raw()) else {
+            return Ok(0);
+        };
+        let Some(created_at) = i64::try_from(id.timestamp_ms())
+            .ok()
+            .and_then(|ms| Utc.timestamp_millis_opt(ms).single())
+        else {
+            return Ok(0);
+        };
+
+        let age = persist.clock().now() - created_at;
+        Ok(if age < chrono::Duration::hours(1) {
+            40
+        } else if age < chrono::Duration::days(1) {
+            20
+        } else {
+            0
+        })
+    }
+}
+
+/// Scores registrations by adding up the scores of the bot signals that they
+/// show.
+pub struct SignupRisk(pub SignupRiskConfig);
+
+#[async_trait]
+impl Classifier for SignupRisk {
+    fn name(&self) -> &'static str {
+        "signup_risk"
+    }
+
+    async fn classify(&self, persist: &Persist, candidate: &SpamCandidate<'_>) -> Result<u8> {
+        Ok(candidate.signup.map_or(0, |signals| {
+            signup_risk(self.0, signals, persist.clock().now())
+        }))
+    }
+}
+
+fn signup_risk(config: SignupRiskConfig, signals: SignupSignals, now: DateTime<Utc>) -> u8 {
+    let mut score = 0u8;
+    if signals.honeypot_filled {
+        score = score.saturating_add(config.honeypot_score);
+    }
+    // Forms shown in the future are treated as having been filled in
+    // instantly.
+    let too_fast = signals.form_shown_at.is_some_and(|shown_at| {
+        now - shown_at < chrono::Duration::seconds(config.min_form_secs.into())
+    });
+    if too_fast {
+        score = score.saturating_add(config.too_fast_score);
+    }
+    score.min(100)
+}
+
+#[derive(Serialize)]
+struct HttpClassifierRequest<'a> {
+    kind: SpamCandidateKind,
+    text: &'a str,
+}
+
+#[derive(Deserialize)]
+struct HttpClassifierResponse {
+    score: u8,
+}
+
+/// Scores content using an external service.
+///
+/// The content is `POST`ed to the service as `{"kind": ..., "text": ...}`,
+/// and the service must respond with `{"score": ...}`.
+pub struct HttpClassifier {
+    client: Client<HttpConnector>,
+    uri: Uri,
+}
+
+impl HttpClassifier {
+    pub fn new(uri: Uri) -> Self {
+        Self {
+            client: Client::new(),
+            uri,
+        }
+    }
+}
+
+#[async_trait]
+impl Classifier for HttpClassifier {
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    async fn classify(&self, _: &Persist, candidate: &SpamCandidate<'_>) -> Result<u8> {
+        let body = serde_json::to_vec(&HttpClassifierRequest {
+            kind: candidate.kind,
+            text: candidate.text,
+        })?;
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri(self.uri.clone())
+            .header("content-type", "application/json")
+            .body(Body::from(body))
+            .map_err(Error::from_err)?;
+
+        let res = tokio::time::timeout(HTTP_CLASSIFIER_TIMEOUT, async {
+            let res = self.client.request(req).await?;
+            body::to_bytes(res.into_body()).await
+        })
+        .await
+        .map_err(Error::from_err)?
+        .map_err(Error::from_err)?;
+
+        let HttpClassifierResponse { score } = serde_json::from_slice(&res)?;
+        Ok(score.min(100))
+    }
+}
+
+/// What should happen to content after it has been checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpamAction {
+    Allow,
+    /// The content should be sent to the moderation queue.
+    Review,
+    /// The content should be hidden from everyone but its author, and sent to
+    /// the moderation queue.
+    Limit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpamVerdict {
+    pub score: u8,
+    /// The name of the classifier that gave the highest score, if any.
+    pub classifier: Option<&'static str>,
+    pub action: SpamAction,
+}
+
+/// Runs content through a set of classifiers, and decides what to do with it
+/// based on the instance's thresholds.
+#[derive(Clone)]
+pub struct SpamPipeline {
+    review_threshold: u8,
+    limit_threshold: u8,
+    classifiers: Vec<Arc<dyn Classifier>>,
+}
+
+impl SpamPipeline {
+    /// Creates a pipeline with the built-in heuristics, plus the external
+    /// classifier if one is configured.
+    pub fn new(config: &SpamConfig) -> Self {
+        let pipeline = Self {
+            review_threshold: config.review_threshold,
+            limit_threshold: config.limit_threshold,
+            classifiers: vec![
+                Arc::new(LinkDensity),
+                Arc::new(DuplicateContent),
+                Arc::new(AccountAge),
+                Arc::new(SignupRisk(config.signup)),
+            ],
+        };
+
+        match &config.classifier_url {
+            Some(uri) => pipeline.with_classifier(HttpClassifier::new(uri.clone())),
+            None => pipeline,
+        }
+    }
+
+    #[must_use]
+    pub fn with_classifier(mut self, classifier: impl Classifier + 'static) -> Self {
+        self.classifiers.push(Arc::new(classifier));
+        self
+    }
+
+    /// Scores the content using every classifier, taking the highest score.
+    ///
+    /// Classifiers that fail are skipped, so that content can still be
+    /// created if an external classifier is unavailable.
+    #[instrument(skip_all)]
+    pub async fn verdict(&self, persist: &Persist, candidate: &SpamCandidate<'_>) -> SpamVerdict {
+        let mut score = 0;
+        let mut classifier = None;
+        for c in &self.classifiers {
+            match c.classify(persist, candidate).await {
+                Ok(s) if s > score => {
+                    score = s;
+                    classifier = Some(c.name());
+                }
+                Ok(_) => (),
+                Err(err) => warn!(classifier = c.name(), error = ?err, "Spam classifier failed"),
+            }
+        }
+
+        let action = if score >= self.limit_threshold {
+            SpamAction::Limit
+        } else if score >= self.review_threshold {
+            SpamAction::Review
+        } else {
+            SpamAction::Allow
+        };
+
+        SpamVerdict {
+            score,
+            classifier,
+            action,
+        }
+    }
+}
+
+impl Default for SpamPipeline {
+    fn default() -> Self {
+        Self::new(&SpamConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use test_case::test_case;
+
+    use super::*;
+
+    #[test_case("", 0 ; "empty")]
+    #[test_case("hello there", 0 ; "no links")]
+    #[test_case("read this https://example.com please", 50 ; "some links")]
+    #[test_case("https://example.com http://example.org", 100 ; "only links")]
+    fn test_link_density(text: &str, expected: u8) {
+        assert_eq!(link_density(text), expected);
+    }
+
+    #[test_case(false, None, 0 ; "no signals")]
+    #[test_case(false, Some(60), 0 ; "slow form")]
+    #[test_case(false, Some(1), 60 ; "fast form")]
+    #[test_case(false, Some(-30), 60 ; "form from the future")]
+    #[test_case(true, Some(60), 100 ; "honeypot")]
+    #[test_case(true, Some(1), 100 ; "capped")]
+    fn test_signup_risk(honeypot_filled: bool, shown_secs_ago: Option<i64>, expected: u8) {
+        let now = Utc.with_ymd_and_hms(2023, 1, 1, 12, 0, 0).unwrap();
+        let signals = SignupSignals {
+            honeypot_filled,
+            form_shown_at: shown_secs_ago.map(|secs| now - chrono::Duration::seconds(secs)),
+        };
+        assert_eq!(
+            signup_risk(SignupRiskConfig::default(), signals, now),
+            expected
+        );
+    }
+}
@@ -0,0 +1,5 @@
+mod classifier;
+mod persist;
+
+pub use classifier::*;
+pub use persist::*;
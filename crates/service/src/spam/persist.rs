@@ -0,0 +1,164 @@
+#[cfg(test)]
+mod tests;
+
+use serde::de::DeserializeOwned;
+use tracing::{error, instrument};
+
+use super::{
+    SignupSignals, SpamAction, SpamCandidate, SpamCandidateKind, SpamPipeline, SpamVerdict,
+};
+use crate::{
+    account::{Account, CurrentAccount},
+    moderation::{CreateModerationItem, ModerationPersist, ModerationReason},
+    persist::Persist,
+    post::Post,
+    prelude::*,
+};
+
+pub struct SpamPersist<'a> {
+    persist: &'a Persist,
+    current: &'a CurrentAccount,
+    pipeline: &'a SpamPipeline,
+}
+
+impl<'a> SpamPersist<'a> {
+    pub fn new(
+        persist: &'a Persist,
+        current: &'a CurrentAccount,
+        pipeline: &'a SpamPipeline,
+    ) -> Self {
+        Self {
+            persist,
+            current,
+            pipeline,
+        }
+    }
+
+    /// Checks a newly created post for spam, sending it for review or
+    /// limiting it depending on its score. Posts by limited accounts are
+    /// always limited.
+    #[instrument(skip_all)]
+    pub async fn check_post(&self, post: Post) -> Result<Post> {
+        let author: Option<Account> = match &post.creator_id {
+            Some(creator_id) => self.persist.load(creator_id.clone()).await?,
+            None => None,
+        };
+
+        let verdict = self
+            .pipeline
+            .verdict(
+                self.persist,
+                &SpamCandidate {
+                    kind: SpamCandidateKind::Post,
+                    id: &post.id,
+                    author: author.as_ref(),
+                    text: &[post.title.as_deref(), post.content.as_deref()]
+                        .into_iter()
+                        .flatten()
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                    signup: None,
+                },
+            )
+            .await;
+
+        let limit = verdict.action == SpamAction::Limit || author.is_some_and(|a| a.limited);
+        self.enqueue(&post.id, post.board_id.as_ref(), verdict)
+            .await;
+        if !limit {
+            return Ok(post);
+        }
+
+        Ok(self.limit(post.id.clone()).await?.unwrap_or(post))
+    }
+
+    /// Checks a newly registered account for spam, sending it for review or
+    /// limiting it depending on its score and the signals that the client
+    /// sent with it.
+    #[instrument(skip_all)]
+    pub async fn check_account(&self, account: Account, signals: SignupSignals) -> Result<Account> {
+        let verdict = self
+            .pipeline
+            .verdict(
+                self.persist,
+                &SpamCandidate {
+                    kind: SpamCandidateKind::Registration,
+                    id: &account.id,
+                    author: None,
+                    text: &account.user_id,
+                    signup: Some(signals),
+                },
+            )
+            .await;
+
+        self.enqueue(&account.id, None, verdict).await;
+        if verdict.action != SpamAction::Limit {
+            return Ok(account);
+        }
+
+        Ok(self.limit(account.id.clone()).await?.unwrap_or(account))
+    }
+
+    /// Marks a post or account as limited. This doesn't bump `updated_at`, so
+    /// that the author can't tell that anything happened.
+    async fn limit<T: DeserializeOwned>(&self, id: srql::Thing) -> Result<Option<T>> {
+        let mut update = vec![];
+        true.push_field(srql::field("limited"), &mut update);
+        let limited = self
+            .persist
+            .db()
+            .query(srql::UpdateStatement {
+                what: srql::thing(id),
+                data: srql::Data::SetExpression(update).into(),
+                output: srql::Output::After.into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+        Ok(limited)
+    }
+
+    async fn enqueue(
+        &self,
+        target_id: &srql::Thing,
+        board_id: Option<&srql::Thing>,
+        verdict: SpamVerdict,
+    ) {
+        if verdict.action == SpamAction::Allow {
+            return;
+        }
+
+        let res = ModerationPersist::new(self.persist, self.current)
+            .enqueue(CreateModerationItem {
+                target_id: target_id.clone(),
+                board_id: board_id.cloned(),
+                reason: ModerationReason::Spam,
+                details: verdict.classifier.map(Into::into),
+                score: Some(verdict.score),
+            })
+            .await;
+
+        // The content has already been created, and will still be limited if
+        // needed, so this shouldn't fail the request.
+        if let Err(err) = res {
+            error!(error = ?err, "Failed to send content for moderation");
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod testing {
+    use crate::account::testing::TestData;
+
+    use super::{SpamPersist, SpamPipeline};
+
+    pub trait SpamTestData {
+        fn spam<'a>(&'a self, pipeline: &'a SpamPipeline) -> SpamPersist<'a>;
+    }
+
+    impl SpamTestData for TestData {
+        fn spam<'a>(&'a self, pipeline: &'a SpamPipeline) -> SpamPersist<'a> {
+            SpamPersist::new(&self.persist, &self.current, pipeline)
+        }
+    }
+}
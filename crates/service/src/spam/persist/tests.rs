@@ -0,0 +1,241 @@
+use async_trait::async_trait;
+use pretty_assertions::assert_eq;
+
+use super::{testing::SpamTestData as _, *};
+use crate::{
+    account::testing::*,
+    config::SpamConfig,
+    moderation::testing::ModerationTestData as _,
+    post::{testing::PostTestData as _, CreatePost},
+    query::PaginationInput,
+    spam::Classifier,
+};
+
+struct Fixed(u8);
+
+#[async_trait]
+impl Classifier for Fixed {
+    fn name(&self) -> &'static str {
+        "fixed"
+    }
+
+    async fn classify(&self, _: &Persist, _: &SpamCandidate<'_>) -> Result<u8> {
+        Ok(self.0)
+    }
+}
+
+struct Failing;
+
+#[async_trait]
+impl Classifier for Failing {
+    fn name(&self) -> &'static str {
+        "failing"
+    }
+
+    async fn classify(&self, _: &Persist, _: &SpamCandidate<'_>) -> Result<u8> {
+        Err(Error::NotImplemented)
+    }
+}
+
+async fn queued(data: &TestData) -> Vec<srql::Thing> {
+    data.moderation()
+        .list()
+        .await
+        .unwrap()
+        .with_pagination(PaginationInput::new().forward(10))
+        .execute()
+        .await
+        .unwrap()
+        .edges
+        .into_iter()
+        .map(|edge| edge.node.target_id)
+        .collect()
+}
+
+async fn listed(data: &TestData) -> Vec<srql::Thing> {
+    data.post()
+        .list()
+        .with_pagination(PaginationInput::new().forward(10))
+        .execute()
+        .await
+        .unwrap()
+        .edges
+        .into_iter()
+        .map(|edge| edge.node.id)
+        .collect()
+}
+
+#[tokio::test]
+async fn test_check_post_allow() {
+    let (data, _) = TestData::with_user().await;
+    let pipeline = SpamPipeline::default();
+    let post = data.generate_post().await;
+
+    let res = data.spam(&pipeline).check_post(post.clone()).await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+    assert!(!res.unwrap().limited);
+    assert!(queued(&data).await.is_empty());
+}
+
+#[tokio::test]
+async fn test_check_post_review() {
+    let (data, _) = TestData::with_user().await;
+    let pipeline = SpamPipeline::new(&SpamConfig::default()).with_classifier(Fixed(60));
+    let post = data.generate_post().await;
+
+    let res = data.spam(&pipeline).check_post(post.clone()).await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+    assert!(!res.unwrap().limited);
+    assert_eq!(queued(&data).await, vec![post.id]);
+}
+
+#[tokio::test]
+async fn test_check_post_limit() {
+    let (mut data, acc) = TestData::with_user().await;
+    let pipeline = SpamPipeline::default();
+    let other = data.account().create_test_user().await;
+    data.login_as(&other);
+    let post = data
+        .post()
+        .create(CreatePost {
+            content: Some("https://spam.example https://spam.example".into()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    let res = data.spam(&pipeline).check_post(post.clone()).await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+
+    let res = res.unwrap();
+    assert!(res.limited);
+    assert_eq!(res.updated_at, post.updated_at);
+
+    // Limited posts are only shown to their authors.
+    assert_eq!(listed(&data).await, vec![post.id.clone()]);
+    data.current = CurrentAccount::default();
+    assert!(listed(&data).await.is_empty());
+
+    data.login_as(&acc);
+    assert!(listed(&data).await.is_empty());
+    assert_eq!(queued(&data).await, vec![post.id]);
+}
+
+#[tokio::test]
+async fn test_check_post_limited_author() {
+    let (data, acc) = TestData::with_user().await;
+    let pipeline = SpamPipeline::default();
+    let acc = data
+        .account()
+        .get(&acc.id.id.to_raw())
+        .await
+        .unwrap()
+        .unwrap();
+    let acc = data
+        .spam(&SpamPipeline::new(&SpamConfig::default()).with_classifier(Fixed(100)))
+        .check_account(acc, SignupSignals::default())
+        .await
+        .unwrap();
+    assert!(acc.limited);
+
+    let post = data.generate_post().await;
+    let res = data.spam(&pipeline).check_post(post).await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+    assert!(res.unwrap().limited);
+}
+
+#[tokio::test]
+async fn test_check_post_duplicate() {
+    let (data, _) = TestData::with_user().await;
+    let pipeline = SpamPipeline::default();
+    data.generate_post().await;
+    data.generate_post().await;
+    let post = data.generate_post().await;
+
+    let res = data.spam(&pipeline).check_post(post.clone()).await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+    assert!(res.unwrap().limited);
+}
+
+#[tokio::test]
+async fn test_check_failing_classifier() {
+    let (data, acc) = TestData::with_user().await;
+    let pipeline = SpamPipeline::new(&SpamConfig::default()).with_classifier(Failing);
+    let acc = data
+        .account()
+        .get(&acc.id.id.to_raw())
+        .await
+        .unwrap()
+        .unwrap();
+
+    let res = data
+        .spam(&pipeline)
+        .check_account(acc, SignupSignals::default())
+        .await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+    assert!(!res.unwrap().limited);
+    assert!(queued(&data).await.is_empty());
+}
+
+#[tokio::test]
+async fn test_thresholds() {
+    let (data, _) = TestData::with_user().await;
+    let config = SpamConfig {
+        review_threshold: 10,
+        limit_threshold: 30,
+        ..Default::default()
+    };
+    let post = data.generate_post().await;
+
+    // New accounts are scored by the account age heuristic.
+    let res = data
+        .spam(&SpamPipeline::new(&config))
+        .check_post(post)
+        .await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+    assert!(res.unwrap().limited);
+}
+
+#[tokio::test]
+async fn test_check_account_signals() {
+    let (data, acc) = TestData::with_user().await;
+    let pipeline = SpamPipeline::default();
+    let acc = data
+        .account()
+        .get(&acc.id.id.to_raw())
+        .await
+        .unwrap()
+        .unwrap();
+
+    // Taking long enough over the form doesn't count against anyone.
+    let signals = SignupSignals {
+        honeypot_filled: false,
+        form_shown_at: Some(data.persist.clock().now() - chrono::Duration::minutes(1)),
+    };
+    let acc = data
+        .spam(&pipeline)
+        .check_account(acc, signals)
+        .await
+        .unwrap();
+    assert!(!acc.limited);
+    assert!(queued(&data).await.is_empty());
+
+    let signals = SignupSignals {
+        honeypot_filled: true,
+        ..signals
+    };
+    let acc = data
+        .spam(&pipeline)
+        .check_account(acc, signals)
+        .await
+        .unwrap();
+    assert!(acc.limited);
+    assert_eq!(queued(&data).await, vec![acc.id]);
+}
@@ -0,0 +1,54 @@
+use std::time::Duration;
+
+use chrono::Duration as ChronoDuration;
+use tokio::{
+    task::JoinHandle,
+    time::{interval, MissedTickBehavior},
+};
+use tracing::{debug, error, trace};
+
+use super::{record_usage, rollup, rollup_hour};
+use crate::{persist::Persist, prelude::*};
+
+/// How often statistics are rolled up.
+pub const ROLLUP_INTERVAL: Duration = Duration::from_mins(15);
+
+static ROLLUP_LOCK: &str = "stats_rollup";
+
+/// Spawns a task that periodically rolls up the statistics for the current
+/// day and hour, and records the tenant's usage for the day.
+///
+/// The previous day and hour are rolled up as well, so that activity between
+/// the last rollup and the end of them is still counted. Requests between the last rollup and
+/// midnight count towards the new day's usage instead.
+pub fn spawn_rollups(persist: Persist) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = interval(ROLLUP_INTERVAL);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+
+            let now = persist.clock().now();
+            let today = now.date_naive();
+            let res = persist
+                .execute_in_lock(ROLLUP_LOCK, || async {
+                    let _job = persist.metrics().track_job();
+                    rollup_hour(&persist, now - ChronoDuration::hours(1)).await?;
+                    rollup_hour(&persist, now).await?;
+                    rollup(&persist, today - ChronoDuration::days(1)).await?;
+                    let stats = rollup(&persist, today).await?;
+                    let usage = record_usage(&persist, today).await?;
+                    Ok::<_, Error>((stats, usage))
+                })
+                .await;
+
+            match res {
+                Ok(Some(Ok((stats, usage)))) => debug!(?stats, ?usage, "Statistics rolled up"),
+                Ok(Some(Err(err))) => error!(error = ?err, "Failed to roll up statistics"),
+                Ok(None) => trace!("Statistics are already being rolled up"),
+                Err(err) => error!(error = ?err, "Failed to lock statistics rollup"),
+            }
+        }
+    })
+}
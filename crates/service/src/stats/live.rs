@@ -0,0 +1,187 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use async_graphql::SimpleObject;
+use chrono::{DateTime, Utc};
+use futures::Stream;
+use tokio::time::{interval_at, Instant, MissedTickBehavior};
+
+use crate::persist::Persist;
+
+/// What the instance is doing right now, as opposed to the daily statistics
+/// which are only rolled up every so often.
+///
+/// Clones share the same values.
+#[derive(Debug, Default, Clone)]
+pub struct LiveMetrics {
+    ws_connections: Arc<AtomicU64>,
+    jobs_in_progress: Arc<AtomicU64>,
+    login_lockouts: Arc<AtomicU64>,
+}
+
+impl LiveMetrics {
+    /// Counts a GraphQL WebSocket connection until the returned guard is
+    /// dropped.
+    #[must_use]
+    pub fn track_ws_connection(&self) -> InProgress {
+        InProgress::new(self.ws_connections.clone())
+    }
+
+    /// Counts a background job as doing work until the returned guard is
+    /// dropped.
+    #[must_use]
+    pub fn track_job(&self) -> InProgress {
+        InProgress::new(self.jobs_in_progress.clone())
+    }
+
+    /// Counts failed logins locking an account or address.
+    pub fn count_login_lockout(&self) {
+        self.login_lockouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn ws_connections(&self) -> u64 {
+        self.ws_connections.load(Ordering::Relaxed)
+    }
+
+    pub fn jobs_in_progress(&self) -> u64 {
+        self.jobs_in_progress.load(Ordering::Relaxed)
+    }
+
+    /// How many times failed logins have locked an account or address since
+    /// the server started.
+    pub fn login_lockouts(&self) -> u64 {
+        self.login_lockouts.load(Ordering::Relaxed)
+    }
+}
+
+/// Keeps something counted by [`LiveMetrics`] until it's dropped.
+#[derive(Debug)]
+pub struct InProgress(Arc<AtomicU64>);
+
+impl InProgress {
+    fn new(count: Arc<AtomicU64>) -> Self {
+        count.fetch_add(1, Ordering::Relaxed);
+        Self(count)
+    }
+}
+
+impl Drop for InProgress {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// A sample of the instance's live metrics.
+#[derive(SimpleObject, Debug, Clone, PartialEq)]
+pub struct LiveMetricsSample {
+    /// How many requests were handled each second, on average, since the
+    /// previous sample.
+    pub requests_per_sec: f64,
+    /// How many GraphQL WebSocket connections are open.
+    pub ws_connections: u64,
+    /// How many background jobs, such as statistics rollups and media
+    /// collection, are doing work.
+    pub jobs_in_progress: u64,
+    /// How many times failed logins locked an account or address since the
+    /// previous sample.
+    pub login_lockouts: u64,
+    /// The region of the server the sample was taken on, if it has one.
+    pub region: Option<String>,
+    /// When the sample was taken.
+    pub sampled_at: DateTime<Utc>,
+}
+
+/// Samples the instance's live metrics every `every`, starting one interval
+/// from now. The request rate is worked out from the requests handled since
+/// the previous sample, or since this was called for the first one.
+pub fn sample_live_metrics(
+    persist: Persist,
+    every: Duration,
+) -> impl Stream<Item = LiveMetricsSample> + Send + 'static {
+    let mut ticker = interval_at(Instant::now() + every, every);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut last = (Instant::now(), persist.requests().total());
+    let mut last_lockouts = persist.metrics().login_lockouts();
+
+    async_stream::stream! {
+        loop {
+            ticker.tick().await;
+            let now = (Instant::now(), persist.requests().total());
+            let elapsed = now.0.duration_since(last.0).as_secs_f64();
+            #[allow(clippy::cast_precision_loss)]
+            let requests = now.1.saturating_sub(last.1) as f64;
+            last = now;
+            let lockouts = persist.metrics().login_lockouts();
+            let login_lockouts = lockouts.saturating_sub(last_lockouts);
+            last_lockouts = lockouts;
+
+            yield LiveMetricsSample {
+                requests_per_sec: if elapsed > 0.0 { requests / elapsed } else { 0.0 },
+                ws_connections: persist.metrics().ws_connections(),
+                jobs_in_progress: persist.metrics().jobs_in_progress(),
+                login_lockouts,
+                region: persist.region().map(Into::into),
+                sampled_at: persist.clock().now(),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt as _;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::persist::testing::persist;
+
+    #[test]
+    fn test_in_progress() {
+        let metrics = LiveMetrics::default();
+        let first = metrics.track_ws_connection();
+        let second = metrics.clone().track_ws_connection();
+        let job = metrics.track_job();
+        assert_eq!(metrics.ws_connections(), 2);
+        assert_eq!(metrics.jobs_in_progress(), 1);
+
+        drop(first);
+        drop(job);
+        assert_eq!(metrics.ws_connections(), 1);
+        assert_eq!(metrics.jobs_in_progress(), 0);
+        drop(second);
+        assert_eq!(metrics.ws_connections(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_sample() {
+        let persist = persist().await;
+        let _connection = persist.metrics().track_ws_connection();
+        let mut samples = Box::pin(sample_live_metrics(
+            persist.clone(),
+            Duration::from_millis(200),
+        ));
+        for _ in 0..10 {
+            persist.requests().increment();
+        }
+        persist.metrics().count_login_lockout();
+
+        // At least 200ms will have passed, so at most 50 requests a second.
+        let sample = samples.next().await.unwrap();
+        assert!(
+            sample.requests_per_sec > 0.0 && sample.requests_per_sec <= 50.0,
+            "{sample:?}"
+        );
+        assert_eq!(sample.ws_connections, 1);
+        assert_eq!(sample.jobs_in_progress, 0);
+        assert_eq!(sample.login_lockouts, 1);
+
+        let sample = samples.next().await.unwrap();
+        assert!(sample.requests_per_sec.abs() < f64::EPSILON, "{sample:?}");
+        assert_eq!(sample.login_lockouts, 0);
+    }
+}
@@ -0,0 +1,17 @@
+mod job;
+mod live;
+mod models;
+mod persist;
+mod series;
+mod usage;
+
+pub use job::*;
+pub use live::*;
+pub use models::*;
+pub use persist::*;
+pub use series::*;
+pub use usage::*;
+
+static STATS_TABLE_NAME: &str = "daily_stats";
+static HOURLY_STATS_TABLE_NAME: &str = "hourly_stats";
+static USAGE_TABLE_NAME: &str = "daily_usage";
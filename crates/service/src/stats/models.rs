@@ -0,0 +1,129 @@
+use async_graphql::SimpleObject;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Deserialize;
+use surrealdb::sql::Thing;
+
+/// Aggregate activity on the instance over a single day, in UTC.
+///
+/// Only counts are kept, so these can't be traced back to individual accounts.
+#[derive(SimpleObject, Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct DailyStats {
+    #[graphql(skip)]
+    pub id: Thing,
+
+    /// The day that these statistics cover.
+    pub day: NaiveDate,
+    /// The number of accounts that logged in or refreshed their tokens.
+    pub active_accounts: u64,
+    /// The number of posts that were made.
+    pub posts: u64,
+    /// The number of accounts that were registered, not counting those
+    /// limited as spam.
+    pub signups: u64,
+    /// The number of accounts that were registered and limited as spam.
+    #[serde(default)]
+    pub limited_signups: u64,
+
+    /// A timestamp indicating the last time these statistics were computed.
+    ///
+    /// The current day's statistics are recomputed periodically, so will
+    /// change until the day is over.
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Aggregate activity on the instance over a single hour, which is rolled up
+/// alongside [`DailyStats`] so that it can be charted in more detail.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct HourlyStats {
+    pub id: Thing,
+
+    /// When the hour that these statistics cover starts.
+    pub hour: DateTime<Utc>,
+    pub active_accounts: u64,
+    pub posts: u64,
+    pub signups: u64,
+    pub limited_signups: u64,
+
+    pub updated_at: DateTime<Utc>,
+}
+
+/// How much of the instance a tenant used over a single day, in UTC, for
+/// billing or capacity planning.
+///
+/// Each tenant is a separate namespace and database, so this is only ever
+/// about the tenant the instance is serving.
+#[derive(SimpleObject, Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct UsageRecord {
+    #[graphql(skip)]
+    pub id: Thing,
+
+    /// The tenant, as its namespace and database separated by a `/`.
+    pub tenant: String,
+    /// The day that the usage covers.
+    pub day: NaiveDate,
+    /// The number of requests that were handled.
+    pub requests: u64,
+    /// The number of bytes of post titles and content, and of media, that were
+    /// stored as of `updatedAt`. Media with the same content is only counted
+    /// once, as it's only stored once.
+    pub storage_bytes: u64,
+    /// The number of accounts that logged in or refreshed their tokens.
+    pub active_accounts: u64,
+
+    /// A timestamp indicating the last time the usage was recorded.
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Coarse statistics about the instance, which are safe to show to anyone.
+///
+/// Every number is rounded down to a single significant figure. Accounts
+/// that have been limited as spam aren't counted, so that bots can't inflate
+/// the numbers.
+#[derive(SimpleObject, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PublicStats {
+    /// The number of registered accounts.
+    pub accounts: u64,
+    /// The number of accounts that were active on the most recent day.
+    pub active_accounts: u64,
+    /// The number of accounts that were registered on the most recent day.
+    pub signups: u64,
+    /// The number of posts that have been made.
+    pub posts: u64,
+}
+
+impl PublicStats {
+    pub fn new(accounts: u64, active_accounts: u64, signups: u64, posts: u64) -> Self {
+        Self {
+            accounts: coarse(accounts),
+            active_accounts: coarse(active_accounts),
+            signups: coarse(signups),
+            posts: coarse(posts),
+        }
+    }
+}
+
+/// Rounds a number down to a single significant figure.
+fn coarse(n: u64) -> u64 {
+    let mut magnitude = 1;
+    while n / magnitude >= 10 {
+        magnitude *= 10;
+    }
+    n / magnitude * magnitude
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::coarse;
+
+    #[test_case(0, 0)]
+    #[test_case(7, 7)]
+    #[test_case(10, 10)]
+    #[test_case(19, 10)]
+    #[test_case(999, 900)]
+    #[test_case(12_345, 10_000)]
+    fn test_coarse(n: u64, expected: u64) {
+        assert_eq!(coarse(n), expected);
+    }
+}
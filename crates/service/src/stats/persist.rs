@@ -0,0 +1,541 @@
+#[cfg(test)]
+mod tests;
+
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, Utc};
+use serde::{de::DeserializeOwned, Deserialize};
+use tracing::instrument;
+
+use super::{
+    rebucket, DailyStats, HourlyStats, PublicStats, StatsBucket, StatsMetric, StatsPoint,
+    UsageRecord, HOURLY_STATS_TABLE_NAME, MAX_STATS_BUCKETS, STATS_TABLE_NAME, USAGE_TABLE_NAME,
+};
+use crate::{
+    account::{require_admin, CurrentAccount, ACC_TABLE_NAME},
+    media::stored_media_bytes,
+    persist::Persist,
+    post::POST_TABLE_NAME,
+    prelude::*,
+    query::{SRQL_ORDER_ASC, SRQL_ORDER_DESC},
+    quota::text_bytes,
+};
+
+pub struct StatsPersist<'a> {
+    persist: &'a Persist,
+    current: &'a CurrentAccount,
+}
+
+impl<'a> StatsPersist<'a> {
+    pub fn new(persist: &'a Persist, current: &'a CurrentAccount) -> Self {
+        Self { persist, current }
+    }
+
+    /// Lists the daily statistics between two days, inclusive. Days that
+    /// haven't been rolled up are skipped.
+    ///
+    /// Only admins can see these.
+    #[instrument(skip_all)]
+    pub async fn daily(&self, from: NaiveDate, to: NaiveDate) -> Result<Vec<DailyStats>> {
+        require_admin(self.persist, self.current).await?;
+        days_between(self.persist, STATS_TABLE_NAME, from, to).await
+    }
+
+    /// Lists the tenant's usage records between two days, inclusive. Days
+    /// that haven't been recorded are skipped.
+    ///
+    /// Only admins can see these.
+    #[instrument(skip_all)]
+    pub async fn usage(&self, from: NaiveDate, to: NaiveDate) -> Result<Vec<UsageRecord>> {
+        require_admin(self.persist, self.current).await?;
+        days_between(self.persist, USAGE_TABLE_NAME, from, to).await
+    }
+
+    /// Charts a statistic between two times, split into buckets. Hourly
+    /// buckets are read from the hourly rollups, and longer ones from the
+    /// daily rollups. Buckets that overlap the range are included whole, and
+    /// ones without any rollups are skipped.
+    ///
+    /// Only admins can see these.
+    #[instrument(skip_all)]
+    pub async fn series(
+        &self,
+        metric: StatsMetric,
+        bucket: StatsBucket,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<StatsPoint>> {
+        require_admin(self.persist, self.current).await?;
+        if to <= from {
+            return Err(Error::InputInvalid("to must be after from".into()));
+        }
+        if bucket.count(from, to) > MAX_STATS_BUCKETS {
+            return Err(Error::InputInvalid(format!(
+                "the range can be split into at most {MAX_STATS_BUCKETS} buckets"
+            )));
+        }
+
+        let start = bucket.start_of(from);
+        Ok(match bucket {
+            StatsBucket::Hour => {
+                let hours: Vec<HourlyStats> = self
+                    .persist
+                    .db()
+                    .query(srql::SelectStatement {
+                        expr: srql::Fields::all(),
+                        what: srql::table(HOURLY_STATS_TABLE_NAME),
+                        cond: srql::cond_and(
+                            time_bound("hour", srql::Operator::MoreThanOrEqual, start).into(),
+                            time_bound("hour", srql::Operator::LessThan, to).into(),
+                        ),
+                        order: srql::Orders(vec![srql::Order {
+                            order: srql::field("hour"),
+                            direction: SRQL_ORDER_ASC,
+                            ..Default::default()
+                        }])
+                        .into(),
+                        ..Default::default()
+                    })
+                    .await?
+                    .take(0)?;
+                rebucket(metric, bucket, &hours)
+            }
+            StatsBucket::Day | StatsBucket::Week => {
+                // The range's end is exclusive, so a range ending at midnight
+                // doesn't include the day that starts then.
+                let last = (to - Duration::nanoseconds(1)).date_naive();
+                let days: Vec<DailyStats> =
+                    days_between(self.persist, STATS_TABLE_NAME, start.date_naive(), last).await?;
+                rebucket(metric, bucket, &days)
+            }
+        })
+    }
+
+    /// Gets the coarse statistics that can be shown to anyone.
+    #[instrument(skip_all)]
+    pub async fn public(&self) -> Result<PublicStats> {
+        let latest: Option<DailyStats> = self
+            .persist
+            .db()
+            .query(srql::SelectStatement {
+                expr: srql::Fields::all(),
+                what: srql::table(STATS_TABLE_NAME),
+                order: srql::Orders(vec![srql::Order {
+                    order: srql::field("day"),
+                    direction: SRQL_ORDER_DESC,
+                    ..Default::default()
+                }])
+                .into(),
+                limit: srql::Limit(srql::Number::Int(1).into()).into(),
+                ..Default::default()
+            })
+            .await?
+            .take(0)?;
+
+        let (active_accounts, signups) =
+            latest.map_or((0, 0), |latest| (latest.active_accounts, latest.signups));
+        Ok(PublicStats::new(
+            count(self.persist, ACC_TABLE_NAME, limited_cond(false).into()).await?,
+            active_accounts,
+            signups,
+            count(self.persist, POST_TABLE_NAME, None).await?,
+        ))
+    }
+}
+
+/// Computes and stores the statistics for a single day.
+///
+/// Posts and signups are recomputed from scratch, but only the most recent
+/// activity of each account is known, so the number of active accounts never
+/// goes down once the day is over.
+#[instrument(skip(persist))]
+pub async fn rollup(persist: &Persist, day: NaiveDate) -> Result<DailyStats> {
+    let start = day.and_time(NaiveTime::MIN).and_utc();
+    let Activity {
+        active_accounts,
+        posts,
+        signups,
+        limited_signups,
+    } = activity(persist, start, start + Duration::days(1)).await?;
+
+    let id = day.to_string();
+    let existing: Option<DailyStats> = persist.db().select((STATS_TABLE_NAME, &*id)).await?;
+    let active_accounts = existing.map_or(active_accounts, |existing| {
+        existing.active_accounts.max(active_accounts)
+    });
+
+    let update = vec![
+        (srql::field("day"), srql::Operator::Equal, id.clone().into()),
+        (
+            srql::field("active_accounts"),
+            srql::Operator::Equal,
+            active_accounts.into(),
+        ),
+        (srql::field("posts"), srql::Operator::Equal, posts.into()),
+        (
+            srql::field("signups"),
+            srql::Operator::Equal,
+            signups.into(),
+        ),
+        (
+            srql::field("limited_signups"),
+            srql::Operator::Equal,
+            limited_signups.into(),
+        ),
+        (
+            srql::field("updated_at"),
+            srql::Operator::Equal,
+            srql::time_now(),
+        ),
+    ];
+
+    let stats = persist
+        .db()
+        .query(srql::UpdateStatement {
+            what: srql::thing((STATS_TABLE_NAME, &*id)),
+            data: srql::Data::SetExpression(update).into(),
+            output: srql::Output::After.into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+
+    match stats {
+        Some(stats) => Ok(stats),
+        None => Err(Error::UnavailableIdent),
+    }
+}
+
+/// Computes and stores the statistics for the hour that a time falls in, in
+/// the same way as [`rollup`] does for days.
+#[instrument(skip(persist))]
+pub async fn rollup_hour(persist: &Persist, time: DateTime<Utc>) -> Result<HourlyStats> {
+    let start = StatsBucket::Hour.start_of(time);
+    let Activity {
+        active_accounts,
+        posts,
+        signups,
+        limited_signups,
+    } = activity(persist, start, start + Duration::hours(1)).await?;
+
+    let id = start.format("%Y-%m-%dT%H").to_string();
+    let existing: Option<HourlyStats> =
+        persist.db().select((HOURLY_STATS_TABLE_NAME, &*id)).await?;
+    let active_accounts = existing.map_or(active_accounts, |existing| {
+        existing.active_accounts.max(active_accounts)
+    });
+
+    let update = vec![
+        (
+            srql::field("hour"),
+            srql::Operator::Equal,
+            srql::Value::Datetime(srql::Datetime(start)),
+        ),
+        (
+            srql::field("active_accounts"),
+            srql::Operator::Equal,
+            active_accounts.into(),
+        ),
+        (srql::field("posts"), srql::Operator::Equal, posts.into()),
+        (
+            srql::field("signups"),
+            srql::Operator::Equal,
+            signups.into(),
+        ),
+        (
+            srql::field("limited_signups"),
+            srql::Operator::Equal,
+            limited_signups.into(),
+        ),
+        (
+            srql::field("updated_at"),
+            srql::Operator::Equal,
+            srql::time_now(),
+        ),
+    ];
+
+    let stats = persist
+        .db()
+        .query(srql::UpdateStatement {
+            what: srql::thing((HOURLY_STATS_TABLE_NAME, &*id)),
+            data: srql::Data::SetExpression(update).into(),
+            output: srql::Output::After.into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+
+    match stats {
+        Some(stats) => Ok(stats),
+        None => Err(Error::UnavailableIdent),
+    }
+}
+
+/// What happened on the instance over a time range.
+struct Activity {
+    active_accounts: u64,
+    posts: u64,
+    signups: u64,
+    limited_signups: u64,
+}
+
+async fn activity(persist: &Persist, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<Activity> {
+    let active_cond = srql::cond_and(
+        time_bound("last_active_at", srql::Operator::MoreThanOrEqual, start).into(),
+        time_bound("last_active_at", srql::Operator::LessThan, end).into(),
+    );
+    let active_accounts = count(persist, ACC_TABLE_NAME, active_cond).await?;
+    let posts = count(
+        persist,
+        POST_TABLE_NAME,
+        created_cond(POST_TABLE_NAME, start, end),
+    )
+    .await?;
+    let signups = |limited| {
+        count(
+            persist,
+            ACC_TABLE_NAME,
+            srql::cond_and(
+                created_cond(ACC_TABLE_NAME, start, end),
+                limited_cond(limited).into(),
+            ),
+        )
+    };
+    let (signups, limited_signups) = (signups(false).await?, signups(true).await?);
+
+    Ok(Activity {
+        active_accounts,
+        posts,
+        signups,
+        limited_signups,
+    })
+}
+
+/// Records the tenant's usage for a single day, adding the requests counted
+/// since the last time usage was recorded.
+///
+/// Storage is measured as it is now, so this is only meant to be called for
+/// the current day. Once the day is over, its record stays as it was last
+/// recorded.
+#[instrument(skip(persist))]
+pub async fn record_usage(persist: &Persist, day: NaiveDate) -> Result<UsageRecord> {
+    let requests = persist.requests().take();
+    let res = store_usage(persist, day, requests).await;
+    if res.is_err() {
+        persist.requests().restore(requests);
+    }
+    res
+}
+
+async fn store_usage(persist: &Persist, day: NaiveDate, requests: u64) -> Result<UsageRecord> {
+    #[derive(Deserialize)]
+    struct PostText {
+        title: Option<String>,
+        content: Option<String>,
+    }
+
+    let start = day.and_time(NaiveTime::MIN).and_utc();
+    let end = start + Duration::days(1);
+    let active_accounts = count(
+        persist,
+        ACC_TABLE_NAME,
+        srql::cond_and(
+            time_bound("last_active_at", srql::Operator::MoreThanOrEqual, start).into(),
+            time_bound("last_active_at", srql::Operator::LessThan, end).into(),
+        ),
+    )
+    .await?;
+
+    let posts: Vec<PostText> = persist
+        .db()
+        .query(srql::SelectStatement {
+            expr: srql::Fields(
+                vec![
+                    srql::Field::Single {
+                        expr: srql::field("title").into(),
+                        alias: None,
+                    },
+                    srql::Field::Single {
+                        expr: srql::field("content").into(),
+                        alias: None,
+                    },
+                ],
+                false,
+            ),
+            what: srql::table(POST_TABLE_NAME),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    let storage_bytes = posts
+        .iter()
+        .map(|post| text_bytes(post.title.as_deref(), post.content.as_deref()))
+        .sum::<u64>()
+        + stored_media_bytes(persist).await?;
+
+    let id = day.to_string();
+    let existing: Option<UsageRecord> = persist.db().select((USAGE_TABLE_NAME, &*id)).await?;
+    let (requests, active_accounts) = existing.map_or((requests, active_accounts), |existing| {
+        (
+            existing.requests + requests,
+            existing.active_accounts.max(active_accounts),
+        )
+    });
+
+    let update = vec![
+        (
+            srql::field("tenant"),
+            srql::Operator::Equal,
+            persist.tenant().into(),
+        ),
+        (srql::field("day"), srql::Operator::Equal, id.clone().into()),
+        (
+            srql::field("requests"),
+            srql::Operator::Equal,
+            requests.into(),
+        ),
+        (
+            srql::field("storage_bytes"),
+            srql::Operator::Equal,
+            storage_bytes.into(),
+        ),
+        (
+            srql::field("active_accounts"),
+            srql::Operator::Equal,
+            active_accounts.into(),
+        ),
+        (
+            srql::field("updated_at"),
+            srql::Operator::Equal,
+            srql::time_now(),
+        ),
+    ];
+
+    let usage = persist
+        .db()
+        .query(srql::UpdateStatement {
+            what: srql::thing((USAGE_TABLE_NAME, &*id)),
+            data: srql::Data::SetExpression(update).into(),
+            output: srql::Output::After.into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+
+    match usage {
+        Some(usage) => Ok(usage),
+        None => Err(Error::UnavailableIdent),
+    }
+}
+
+/// Lists the records of a table keyed by day, between two days inclusive.
+async fn days_between<T: DeserializeOwned>(
+    persist: &Persist,
+    table: &str,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<T>> {
+    // Days are stored as ISO 8601 strings, so they sort chronologically.
+    let bound = |o, day: NaiveDate| {
+        srql::Cond(
+            srql::Expression::Binary {
+                l: srql::field("day").into(),
+                o,
+                r: day.to_string().into(),
+            }
+            .into(),
+        )
+    };
+
+    let records = persist
+        .db()
+        .query(srql::SelectStatement {
+            expr: srql::Fields::all(),
+            what: srql::table(table),
+            cond: srql::cond_and(
+                bound(srql::Operator::MoreThanOrEqual, from).into(),
+                bound(srql::Operator::LessThanOrEqual, to).into(),
+            ),
+            order: srql::Orders(vec![srql::Order {
+                order: srql::field("day"),
+                direction: SRQL_ORDER_ASC,
+                ..Default::default()
+            }])
+            .into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    Ok(records)
+}
+
+async fn count(persist: &Persist, table: &str, cond: Option<srql::Cond>) -> Result<u64> {
+    let count: Option<u64> = persist
+        .db()
+        .query(srql::count_query(table, cond))
+        .await?
+        .take("count")?;
+    Ok(count.unwrap_or_default())
+}
+
+fn time_bound(field: &str, o: srql::Operator, time: DateTime<Utc>) -> srql::Cond {
+    srql::Cond(
+        srql::Expression::Binary {
+            l: srql::field(field).into(),
+            o,
+            r: srql::Value::Datetime(srql::Datetime(time)),
+        }
+        .into(),
+    )
+}
+
+/// Matches accounts that have, or haven't, been limited as spam.
+fn limited_cond(limited: bool) -> srql::Cond {
+    srql::Cond(
+        srql::Expression::Binary {
+            l: srql::field("limited").into(),
+            o: if limited {
+                srql::Operator::Equal
+            } else {
+                srql::Operator::NotEqual
+            },
+            r: true.into(),
+        }
+        .into(),
+    )
+}
+
+/// Matches records in a table that were created within a time range, based on
+/// their IDs.
+fn created_cond(table: &str, start: DateTime<Utc>, end: DateTime<Utc>) -> Option<srql::Cond> {
+    let bound = |o, time| {
+        srql::Cond(
+            srql::Expression::Binary {
+                l: srql::field("id").into(),
+                o,
+                r: srql::Thing::from((table.to_owned(), srql::ulid_at(time))).into(),
+            }
+            .into(),
+        )
+    };
+
+    srql::cond_and(
+        bound(srql::Operator::MoreThanOrEqual, start).into(),
+        bound(srql::Operator::LessThan, end).into(),
+    )
+}
+
+#[cfg(test)]
+pub mod testing {
+    use crate::account::testing::TestData;
+
+    use super::StatsPersist;
+
+    pub trait StatsTestData {
+        fn stats(&self) -> StatsPersist<'_>;
+    }
+
+    impl StatsTestData for TestData {
+        fn stats(&self) -> StatsPersist<'_> {
+            StatsPersist::new(&self.persist, &self.current)
+        }
+    }
+}
@@ -0,0 +1,263 @@
+use chrono::{Duration, Utc};
+use pretty_assertions::assert_eq;
+
+use super::{testing::StatsTestData as _, *};
+use crate::{
+    account::testing::*,
+    post::testing::PostTestData as _,
+    spam::{testing::SpamTestData as _, SignupSignals, SpamPipeline},
+};
+
+#[tokio::test]
+async fn test_rollup() {
+    let (data, _) = TestData::with_user().await;
+    data.account().create_test_user().await;
+    data.generate_posts(3).await;
+
+    let today = Utc::now().date_naive();
+    let res = rollup(&data.persist, today).await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+
+    let res = res.unwrap();
+    assert_eq!(res.day, today);
+    assert_eq!(res.active_accounts, 2);
+    assert_eq!(res.posts, 3);
+    assert_eq!(res.signups, 2);
+    assert_eq!(res.limited_signups, 0);
+
+    // Rolling up again should replace the existing statistics.
+    data.generate_post().await;
+    let res = rollup(&data.persist, today).await.unwrap();
+    assert_eq!(res.posts, 4);
+    assert_eq!(data.stats().daily(today, today).await.unwrap(), vec![res]);
+}
+
+#[tokio::test]
+async fn test_rollup_empty_day() {
+    let (data, _) = TestData::with_user().await;
+    data.generate_post().await;
+
+    let yesterday = Utc::now().date_naive() - Duration::days(1);
+    let res = rollup(&data.persist, yesterday).await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+
+    let res = res.unwrap();
+    assert_eq!(res.active_accounts, 0);
+    assert_eq!(res.posts, 0);
+    assert_eq!(res.signups, 0);
+}
+
+#[tokio::test]
+async fn test_daily() {
+    let (data, _) = TestData::with_user().await;
+    let today = Utc::now().date_naive();
+    for days in 0..3 {
+        rollup(&data.persist, today - Duration::days(days))
+            .await
+            .unwrap();
+    }
+
+    let res = data.stats().daily(today - Duration::days(1), today).await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+
+    let res: Vec<_> = res.unwrap().into_iter().map(|stats| stats.day).collect();
+    assert_eq!(res, vec![today - Duration::days(1), today]);
+}
+
+#[tokio::test]
+async fn test_daily_not_admin() {
+    let (mut data, _) = TestData::with_user().await;
+    let other = data.account().create_test_user().await;
+    data.login_as(&other);
+
+    let today = Utc::now().date_naive();
+    let res = data.stats().daily(today, today).await;
+    println!("{res:?}");
+    assert_eq!(res, Err(Error::Unauthorized));
+}
+
+#[tokio::test]
+async fn test_series() {
+    let (data, _) = TestData::with_user().await;
+    data.generate_posts(3).await;
+
+    let now = Utc::now();
+    let today = now.date_naive();
+    rollup(&data.persist, today).await.unwrap();
+    rollup(&data.persist, today - Duration::days(1))
+        .await
+        .unwrap();
+    let hour = rollup_hour(&data.persist, now).await.unwrap();
+    assert_eq!(hour.hour, StatsBucket::Hour.start_of(now));
+    assert_eq!(hour.posts, 3);
+
+    let stats = data.stats();
+    let series = |bucket, from| stats.series(StatsMetric::Posts, bucket, from, now);
+    let res = series(StatsBucket::Day, now - Duration::days(1)).await;
+    println!("{res:?}");
+    let res = res.unwrap();
+    assert_eq!(
+        res,
+        vec![
+            StatsPoint {
+                start: StatsBucket::Day.start_of(now - Duration::days(1)),
+                value: 0,
+            },
+            StatsPoint {
+                start: StatsBucket::Day.start_of(now),
+                value: 3,
+            },
+        ]
+    );
+
+    let res = series(StatsBucket::Hour, now - Duration::hours(3)).await;
+    assert_eq!(
+        res.unwrap(),
+        vec![StatsPoint {
+            start: hour.hour,
+            value: 3,
+        }]
+    );
+
+    let res = series(StatsBucket::Week, now - Duration::days(1)).await;
+    // Yesterday and today may fall in different weeks.
+    let total: u64 = res.unwrap().iter().map(|point| point.value).sum();
+    assert_eq!(total, 3);
+}
+
+#[tokio::test]
+async fn test_series_invalid() {
+    let (mut data, _) = TestData::with_user().await;
+    let now = Utc::now();
+
+    let res = data
+        .stats()
+        .series(StatsMetric::Posts, StatsBucket::Day, now, now)
+        .await;
+    assert!(matches!(res, Err(Error::InputInvalid(_))), "{res:?}");
+    let res = data
+        .stats()
+        .series(
+            StatsMetric::Posts,
+            StatsBucket::Hour,
+            now - Duration::days(365),
+            now,
+        )
+        .await;
+    assert!(matches!(res, Err(Error::InputInvalid(_))), "{res:?}");
+
+    let other = data.account().create_test_user().await;
+    data.login_as(&other);
+    let res = data
+        .stats()
+        .series(
+            StatsMetric::Posts,
+            StatsBucket::Day,
+            now - Duration::days(1),
+            now,
+        )
+        .await;
+    assert_eq!(res, Err(Error::Unauthorized));
+}
+
+#[tokio::test]
+async fn test_public() {
+    let data = TestData::new().await;
+    for _ in 0..12 {
+        data.account().create_test_user().await;
+    }
+    data.generate_posts(3).await;
+    rollup(&data.persist, Utc::now().date_naive())
+        .await
+        .unwrap();
+
+    let res = data.stats().public().await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+
+    assert_eq!(
+        res.unwrap(),
+        PublicStats {
+            accounts: 10,
+            active_accounts: 10,
+            signups: 10,
+            posts: 3,
+        }
+    );
+}
+
+#[tokio::test]
+async fn test_limited_signups() {
+    let (data, _) = TestData::with_user().await;
+    let bot = data.account().create_test_user().await;
+    let bot = data
+        .account()
+        .get(&bot.id.id.to_raw())
+        .await
+        .unwrap()
+        .unwrap();
+    let signals = SignupSignals {
+        honeypot_filled: true,
+        ..Default::default()
+    };
+    let bot = data
+        .spam(&SpamPipeline::default())
+        .check_account(bot, signals)
+        .await
+        .unwrap();
+    assert!(bot.limited);
+
+    let res = rollup(&data.persist, Utc::now().date_naive())
+        .await
+        .unwrap();
+    assert_eq!(res.signups, 1);
+    assert_eq!(res.limited_signups, 1);
+
+    let res = data.stats().public().await.unwrap();
+    assert_eq!(res.accounts, 1);
+    assert_eq!(res.signups, 1);
+}
+
+#[tokio::test]
+async fn test_record_usage() {
+    let (data, _) = TestData::with_user().await;
+    data.generate_posts(3).await;
+    for _ in 0..5 {
+        data.persist.requests().increment();
+    }
+
+    let today = Utc::now().date_naive();
+    let res = record_usage(&data.persist, today).await;
+    println!("{res:?}");
+    assert!(res.is_ok());
+
+    let res = res.unwrap();
+    assert_eq!(res.tenant, "test/test");
+    assert_eq!(res.day, today);
+    assert_eq!(res.requests, 5);
+    // The generated posts' content is "Test 0", "Test 1" and "Test 2".
+    assert_eq!(res.storage_bytes, 18);
+    assert_eq!(res.active_accounts, 1);
+
+    // Requests are added to those already recorded for the day.
+    data.persist.requests().increment();
+    data.generate_post().await;
+    let res = record_usage(&data.persist, today).await.unwrap();
+    assert_eq!(res.requests, 6);
+    assert_eq!(res.storage_bytes, 22);
+    assert_eq!(data.stats().usage(today, today).await.unwrap(), vec![res]);
+}
+
+#[tokio::test]
+async fn test_usage_not_admin() {
+    let (mut data, _) = TestData::with_user().await;
+    let other = data.account().create_test_user().await;
+    data.login_as(&other);
+
+    let today = Utc::now().date_naive();
+    let res = data.stats().usage(today, today).await;
+    assert_eq!(res, Err(Error::Unauthorized));
+}
@@ -0,0 +1,221 @@
+use async_graphql::{Enum, SimpleObject};
+use chrono::{DateTime, Datelike as _, Duration, DurationRound as _, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{DailyStats, HourlyStats};
+
+/// The most buckets that a series can be split into.
+pub const MAX_STATS_BUCKETS: i64 = 1000;
+
+/// A statistic that can be charted over time.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StatsMetric {
+    /// Accounts that logged in or refreshed their tokens. Only the most
+    /// recent activity of each account is known, so a bucket made of several
+    /// rollups has the most that were active in any one of them, rather than
+    /// adding them up and counting accounts more than once.
+    ActiveAccounts,
+    Posts,
+    /// Accounts that were registered, not counting those limited as spam.
+    Signups,
+    /// Accounts that were registered and limited as spam.
+    LimitedSignups,
+}
+
+impl StatsMetric {
+    /// Combines the values of two rollups that fall in the same bucket.
+    fn combine(self, a: u64, b: u64) -> u64 {
+        match self {
+            Self::ActiveAccounts => a.max(b),
+            Self::Posts | Self::Signups | Self::LimitedSignups => a + b,
+        }
+    }
+}
+
+/// How long each point in a series covers. Buckets are aligned to UTC, and
+/// weeks start on Monday.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StatsBucket {
+    Hour,
+    Day,
+    Week,
+}
+
+impl StatsBucket {
+    pub fn duration(self) -> Duration {
+        match self {
+            Self::Hour => Duration::hours(1),
+            Self::Day => Duration::days(1),
+            Self::Week => Duration::weeks(1),
+        }
+    }
+
+    /// The start of the bucket that a time falls in.
+    pub fn start_of(self, time: DateTime<Utc>) -> DateTime<Utc> {
+        match self {
+            Self::Hour => time
+                .duration_trunc(Duration::hours(1))
+                .expect("an hour always fits in a timestamp"),
+            Self::Day => time.date_naive().and_time(NaiveTime::MIN).and_utc(),
+            Self::Week => {
+                let day = time.date_naive();
+                let monday = day - Duration::days(day.weekday().num_days_from_monday().into());
+                monday.and_time(NaiveTime::MIN).and_utc()
+            }
+        }
+    }
+
+    /// How many buckets it takes to cover a time range.
+    pub fn count(self, from: DateTime<Utc>, to: DateTime<Utc>) -> i64 {
+        let span = (to - self.start_of(from)).num_seconds();
+        let size = self.duration().num_seconds();
+        (span + size - 1) / size
+    }
+}
+
+/// The value of a statistic over a single bucket.
+#[derive(SimpleObject, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatsPoint {
+    /// When the bucket starts.
+    pub start: DateTime<Utc>,
+    pub value: u64,
+}
+
+/// Statistics that have been rolled up over a period of time, and can be
+/// re-bucketed into longer ones.
+pub trait Rollup {
+    /// When the period starts.
+    fn start(&self) -> DateTime<Utc>;
+
+    fn value(&self, metric: StatsMetric) -> u64;
+}
+
+impl Rollup for DailyStats {
+    fn start(&self) -> DateTime<Utc> {
+        self.day.and_time(NaiveTime::MIN).and_utc()
+    }
+
+    fn value(&self, metric: StatsMetric) -> u64 {
+        match metric {
+            StatsMetric::ActiveAccounts => self.active_accounts,
+            StatsMetric::Posts => self.posts,
+            StatsMetric::Signups => self.signups,
+            StatsMetric::LimitedSignups => self.limited_signups,
+        }
+    }
+}
+
+impl Rollup for HourlyStats {
+    fn start(&self) -> DateTime<Utc> {
+        self.hour
+    }
+
+    fn value(&self, metric: StatsMetric) -> u64 {
+        match metric {
+            StatsMetric::ActiveAccounts => self.active_accounts,
+            StatsMetric::Posts => self.posts,
+            StatsMetric::Signups => self.signups,
+            StatsMetric::LimitedSignups => self.limited_signups,
+        }
+    }
+}
+
+/// Groups rollups, sorted by when they start, into buckets. Buckets without
+/// any rollups are left out.
+pub fn rebucket<R: Rollup>(
+    metric: StatsMetric,
+    bucket: StatsBucket,
+    rollups: &[R],
+) -> Vec<StatsPoint> {
+    let mut points: Vec<StatsPoint> = vec![];
+    for rollup in rollups {
+        let start = bucket.start_of(rollup.start());
+        let value = rollup.value(metric);
+        match points.last_mut() {
+            Some(point) if point.start == start => {
+                point.value = metric.combine(point.value, value);
+            }
+            _ => points.push(StatsPoint { start, value }),
+        }
+    }
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use surrealdb::sql::Thing;
+    use test_case::test_case;
+
+    use super::*;
+
+    fn time(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+
+    fn day(day: &str, active_accounts: u64, posts: u64) -> DailyStats {
+        DailyStats {
+            id: Thing::from(("daily_stats", day)),
+            day: day.parse::<NaiveDate>().unwrap(),
+            active_accounts,
+            posts,
+            signups: 0,
+            limited_signups: 0,
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test_case(StatsBucket::Hour, "2023-03-08T14:35:10Z" => time("2023-03-08T14:00:00Z"))]
+    #[test_case(StatsBucket::Day, "2023-03-08T14:35:10Z" => time("2023-03-08T00:00:00Z"))]
+    #[test_case(StatsBucket::Week, "2023-03-08T14:35:10Z" => time("2023-03-06T00:00:00Z"))]
+    #[test_case(StatsBucket::Week, "2023-03-06T00:00:00Z" => time("2023-03-06T00:00:00Z"))]
+    #[test_case(StatsBucket::Week, "2023-03-05T23:59:59Z" => time("2023-02-27T00:00:00Z"))]
+    fn test_start_of(bucket: StatsBucket, time_: &str) -> DateTime<Utc> {
+        bucket.start_of(time(time_))
+    }
+
+    #[test_case(StatsBucket::Hour, "2023-03-08T14:30:00Z", "2023-03-08T16:00:00Z" => 2)]
+    #[test_case(StatsBucket::Hour, "2023-03-08T14:30:00Z", "2023-03-08T16:00:01Z" => 3)]
+    #[test_case(StatsBucket::Day, "2023-03-01T00:00:00Z", "2023-03-08T00:00:00Z" => 7)]
+    #[test_case(StatsBucket::Week, "2023-03-08T00:00:00Z", "2023-03-14T00:00:00Z" => 2)]
+    fn test_count(bucket: StatsBucket, from: &str, to: &str) -> i64 {
+        bucket.count(time(from), time(to))
+    }
+
+    #[test]
+    fn test_rebucket() {
+        let days = [
+            day("2023-03-04", 5, 1),
+            day("2023-03-05", 3, 2),
+            day("2023-03-06", 2, 4),
+            day("2023-03-08", 7, 8),
+        ];
+
+        let points = rebucket(StatsMetric::Posts, StatsBucket::Day, &days);
+        assert_eq!(
+            points.iter().map(|point| point.value).collect::<Vec<_>>(),
+            vec![1, 2, 4, 8]
+        );
+
+        let points = rebucket(StatsMetric::Posts, StatsBucket::Week, &days);
+        assert_eq!(
+            points,
+            vec![
+                StatsPoint {
+                    start: time("2023-02-27T00:00:00Z"),
+                    value: 3,
+                },
+                StatsPoint {
+                    start: time("2023-03-06T00:00:00Z"),
+                    value: 12,
+                },
+            ]
+        );
+
+        let points = rebucket(StatsMetric::ActiveAccounts, StatsBucket::Week, &days);
+        assert_eq!(
+            points.iter().map(|point| point.value).collect::<Vec<_>>(),
+            vec![5, 7]
+        );
+    }
+}
@@ -0,0 +1,200 @@
+use std::{
+    fmt::Write as _,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use axum::{extract::State, http::Request, middleware::Next, response::Response};
+
+use super::UsageRecord;
+
+/// The content type of usage exported in the `OpenMetrics` text format.
+pub static OPENMETRICS_CONTENT_TYPE: &str =
+    "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Counts the requests the instance handles, until they are added to the
+/// current day's usage record.
+#[derive(Debug, Default, Clone)]
+pub struct RequestCounter {
+    count: Arc<AtomicU64>,
+    total: Arc<AtomicU64>,
+}
+
+impl RequestCounter {
+    pub fn increment(&self) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.total.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// How many requests have been counted since the instance started,
+    /// whether or not they've been taken.
+    pub fn total(&self) -> u64 {
+        self.total.load(Ordering::Relaxed)
+    }
+
+    /// Takes the requests counted since this was last called.
+    pub fn take(&self) -> u64 {
+        self.count.swap(0, Ordering::Relaxed)
+    }
+
+    /// Puts back requests that were taken but couldn't be recorded.
+    pub fn restore(&self, requests: u64) {
+        self.count.fetch_add(requests, Ordering::Relaxed);
+    }
+}
+
+/// Middleware that counts every request towards the instance's usage.
+pub async fn count_requests<B>(
+    State(counter): State<RequestCounter>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    counter.increment();
+    next.run(req).await
+}
+
+/// Formats usage records as CSV, with a header row.
+pub fn usage_csv(records: &[UsageRecord]) -> String {
+    let mut csv = String::from("tenant,day,requests,storage_bytes,active_accounts,updated_at\n");
+    for record in records {
+        let _ = writeln!(
+            csv,
+            "{},{},{},{},{},{}",
+            csv_field(&record.tenant),
+            record.day,
+            record.requests,
+            record.storage_bytes,
+            record.active_accounts,
+            record.updated_at.to_rfc3339(),
+        );
+    }
+    csv
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+/// Formats usage records in the `OpenMetrics` text format. Each record's day
+/// is given as a `day` label, alongside the `tenant` label.
+pub fn usage_openmetrics(records: &[UsageRecord]) -> String {
+    type Family = (
+        &'static str,
+        &'static str,
+        &'static str,
+        fn(&UsageRecord) -> u64,
+    );
+    let families: [Family; 3] = [
+        (
+            "plazer_requests",
+            "counter",
+            "Requests handled by the instance over the day.",
+            |record| record.requests,
+        ),
+        (
+            "plazer_storage_bytes",
+            "gauge",
+            "Bytes of content stored by the instance.",
+            |record| record.storage_bytes,
+        ),
+        (
+            "plazer_active_accounts",
+            "gauge",
+            "Accounts that were active over the day.",
+            |record| record.active_accounts,
+        ),
+    ];
+
+    let mut text = String::new();
+    for (name, kind, help, value) in families {
+        let _ = writeln!(text, "# TYPE {name} {kind}");
+        let _ = writeln!(text, "# HELP {name} {help}");
+        let sample = if kind == "counter" {
+            format!("{name}_total")
+        } else {
+            name.to_owned()
+        };
+        for record in records {
+            let _ = writeln!(
+                text,
+                "{sample}{{tenant=\"{}\",day=\"{}\"}} {}",
+                label_value(&record.tenant),
+                record.day,
+                value(record),
+            );
+        }
+    }
+    text.push_str("# EOF\n");
+    text
+}
+
+fn label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, TimeZone as _, Utc};
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn record(tenant: &str) -> UsageRecord {
+        UsageRecord {
+            id: ("daily_usage", "2023-10-01").into(),
+            tenant: tenant.into(),
+            day: NaiveDate::from_ymd_opt(2023, 10, 1).unwrap(),
+            requests: 120,
+            storage_bytes: 2048,
+            active_accounts: 3,
+            updated_at: Utc.with_ymd_and_hms(2023, 10, 1, 12, 0, 0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_counter() {
+        let counter = RequestCounter::default();
+        counter.increment();
+        counter.clone().increment();
+        assert_eq!(counter.take(), 2);
+        assert_eq!(counter.take(), 0);
+        counter.restore(2);
+        assert_eq!(counter.take(), 2);
+    }
+
+    #[test]
+    fn test_csv() {
+        assert_eq!(
+            usage_csv(&[record("plazer/main"), record("a,\"b\"")]),
+            "tenant,day,requests,storage_bytes,active_accounts,updated_at\n\
+             plazer/main,2023-10-01,120,2048,3,2023-10-01T12:00:00+00:00\n\
+             \"a,\"\"b\"\"\",2023-10-01,120,2048,3,2023-10-01T12:00:00+00:00\n"
+        );
+    }
+
+    #[test]
+    fn test_openmetrics() {
+        assert_eq!(
+            usage_openmetrics(&[record("plazer/\"main\"")]),
+            "# TYPE plazer_requests counter\n\
+             # HELP plazer_requests Requests handled by the instance over the day.\n\
+             plazer_requests_total{tenant=\"plazer/\\\"main\\\"\",day=\"2023-10-01\"} 120\n\
+             # TYPE plazer_storage_bytes gauge\n\
+             # HELP plazer_storage_bytes Bytes of content stored by the instance.\n\
+             plazer_storage_bytes{tenant=\"plazer/\\\"main\\\"\",day=\"2023-10-01\"} 2048\n\
+             # TYPE plazer_active_accounts gauge\n\
+             # HELP plazer_active_accounts Accounts that were active over the day.\n\
+             plazer_active_accounts{tenant=\"plazer/\\\"main\\\"\",day=\"2023-10-01\"} 3\n\
+             # EOF\n"
+        );
+    }
+}
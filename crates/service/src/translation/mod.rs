@@ -0,0 +1,19 @@
+//! Translating posts into the reader's language.
+//!
+//! Posts are translated by a [`TranslationProvider`], which the instance
+//! chooses in its config. Without one, posts can't be translated. Each post
+//! is only translated into each language once, and the translation is reused
+//! until the post is edited, so accounts can only have so many posts
+//! translated an hour that haven't been already.
+
+mod models;
+mod persist;
+mod provider;
+mod schema;
+
+pub use models::*;
+pub use persist::*;
+pub use provider::*;
+pub use schema::*;
+
+pub static POST_TRANSLATION_TABLE_NAME: &str = "post_translation";
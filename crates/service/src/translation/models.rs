@@ -0,0 +1,44 @@
+use async_graphql::{ComplexObject, SimpleObject, ID};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use surrealdb::sql::Thing;
+
+use crate::prelude::*;
+
+/// A post translated into another language.
+#[derive(SimpleObject, Debug, Clone, PartialEq, Eq, Deserialize)]
+#[graphql(complex)]
+pub struct PostTranslation {
+    #[graphql(skip)]
+    pub post_id: Thing,
+    /// The post's title, translated.
+    pub title: Option<String>,
+    /// The post's content, translated.
+    pub content: Option<String>,
+    /// The language that the post was detected to be written in, as an ISO
+    /// 639-1 code, if the provider could tell.
+    pub source_language: Option<String>,
+    /// The language that the post was translated into, as an ISO 639-1 code.
+    pub target_language: String,
+    /// The name of the provider that translated the post, such as `deepl`,
+    /// which clients may need to credit.
+    pub provider: String,
+    /// A hash of the title and content that were translated, so translations
+    /// of posts that have been edited since aren't used.
+    #[graphql(skip)]
+    pub source_hash: String,
+    /// The account that asked for the post to be translated.
+    #[graphql(skip)]
+    pub translated_by: Thing,
+    /// When the post was translated. Translations are reused until the post
+    /// is edited, so this can be well before it was asked for.
+    pub translated_at: DateTime<Utc>,
+}
+
+#[ComplexObject]
+impl PostTranslation {
+    /// The ID of the post that was translated.
+    async fn post_id(&self) -> ID {
+        self.post_id.to_gql_id()
+    }
+}
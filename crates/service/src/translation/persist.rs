@@ -0,0 +1,252 @@
+#[cfg(test)]
+mod tests;
+
+use chrono::{DateTime, Duration, Utc};
+use ring::digest;
+use tracing::instrument;
+
+use super::{PostTranslation, TranslatedText, TranslationProvider, POST_TRANSLATION_TABLE_NAME};
+use crate::{
+    account::CurrentAccount,
+    locale::LanguageIdentifier,
+    persist::Persist,
+    post::{Post, PostPersist},
+    prelude::*,
+};
+
+pub struct TranslationPersist<'a> {
+    persist: &'a Persist,
+    current: &'a CurrentAccount,
+}
+
+impl<'a> TranslationPersist<'a> {
+    pub fn new(persist: &'a Persist, current: &'a CurrentAccount) -> Self {
+        Self { persist, current }
+    }
+
+    /// Translates a post into the language of a locale, such as `fr` or
+    /// `pt-BR`. Posts that have already been translated into the language
+    /// since they were last edited aren't translated again.
+    ///
+    /// This needs to be signed in, and fails with `TranslationUnavailable` if
+    /// the instance can't translate posts, or `RateLimited` if the account has
+    /// had too many posts translated in the last hour.
+    #[instrument(skip_all)]
+    pub async fn translate_post(
+        &self,
+        id: &str,
+        target_language: &str,
+    ) -> Result<Option<PostTranslation>> {
+        let account_id = self.current.id()?.to_account_thing();
+        let config = self.persist.translation();
+        let Some(provider) = &config.provider else {
+            return Err(Error::TranslationUnavailable);
+        };
+        let target_language = language_code(target_language)?;
+        let Some(post) = PostPersist::new(self.persist, self.current).get(id).await? else {
+            return Ok(None);
+        };
+
+        let thing = translation_thing(&post.id, &target_language);
+        let source_hash = source_hash(&post);
+        let existing: Option<PostTranslation> = self.persist.db().select(thing.clone()).await?;
+        if let Some(existing) = existing.filter(|existing| existing.source_hash == source_hash) {
+            return Ok(Some(existing));
+        }
+
+        let now = self.persist.clock().now();
+        if config.per_hour > 0
+            && self
+                .translated_since(&account_id, now - Duration::hours(1))
+                .await?
+                >= config.per_hour as usize
+        {
+            return Err(Error::RateLimited);
+        }
+
+        let title = translate(&**provider, post.title.as_deref(), &target_language).await?;
+        let content = translate(&**provider, post.content.as_deref(), &target_language).await?;
+        let translation = PostTranslation {
+            post_id: post.id,
+            source_language: content
+                .as_ref()
+                .and_then(|content| content.source_language.clone())
+                .or_else(|| title.as_ref()?.source_language.clone()),
+            title: title.map(|title| title.text),
+            content: content.map(|content| content.text),
+            target_language,
+            provider: provider.name().to_owned(),
+            source_hash,
+            translated_by: account_id,
+            translated_at: now,
+        };
+        self.store(thing, &translation).await?;
+        Ok(Some(translation))
+    }
+
+    /// How many translations the account has asked for since the given time.
+    /// Translations that have been replaced since, as their posts were
+    /// edited, aren't counted.
+    async fn translated_since(
+        &self,
+        account_id: &srql::Thing,
+        since: DateTime<Utc>,
+    ) -> Result<usize> {
+        let cond = srql::cond_and(
+            Some(srql::Cond(
+                srql::Expression::Binary {
+                    l: srql::field("translated_by").into(),
+                    o: srql::Operator::Equal,
+                    r: account_id.clone().into(),
+                }
+                .into(),
+            )),
+            Some(srql::Cond(
+                srql::Expression::Binary {
+                    l: srql::field("translated_at").into(),
+                    o: srql::Operator::MoreThan,
+                    r: srql::Value::Datetime(srql::Datetime(since)),
+                }
+                .into(),
+            )),
+        );
+        let count: Option<usize> = self
+            .persist
+            .db()
+            .query(srql::count_query(POST_TRANSLATION_TABLE_NAME, cond))
+            .await?
+            .take("count")?;
+        Ok(count.unwrap_or_default())
+    }
+
+    /// Keeps a translation, replacing any earlier one of the post into the
+    /// same language.
+    async fn store(&self, thing: srql::Thing, translation: &PostTranslation) -> Result<()> {
+        let optional = |value: &Option<String>| {
+            value
+                .as_ref()
+                .map_or(srql::Value::None, |value| value.as_str().into())
+        };
+        self.persist
+            .db()
+            .query(srql::UpdateStatement {
+                what: srql::thing(thing),
+                data: srql::Data::SetExpression(vec![
+                    (
+                        srql::field("post_id"),
+                        srql::Operator::Equal,
+                        translation.post_id.clone().into(),
+                    ),
+                    (
+                        srql::field("title"),
+                        srql::Operator::Equal,
+                        optional(&translation.title),
+                    ),
+                    (
+                        srql::field("content"),
+                        srql::Operator::Equal,
+                        optional(&translation.content),
+                    ),
+                    (
+                        srql::field("source_language"),
+                        srql::Operator::Equal,
+                        optional(&translation.source_language),
+                    ),
+                    (
+                        srql::field("target_language"),
+                        srql::Operator::Equal,
+                        translation.target_language.as_str().into(),
+                    ),
+                    (
+                        srql::field("provider"),
+                        srql::Operator::Equal,
+                        translation.provider.as_str().into(),
+                    ),
+                    (
+                        srql::field("source_hash"),
+                        srql::Operator::Equal,
+                        translation.source_hash.as_str().into(),
+                    ),
+                    (
+                        srql::field("translated_by"),
+                        srql::Operator::Equal,
+                        translation.translated_by.clone().into(),
+                    ),
+                    (
+                        srql::field("translated_at"),
+                        srql::Operator::Equal,
+                        srql::Value::Datetime(srql::Datetime(translation.translated_at)),
+                    ),
+                ])
+                .into(),
+                output: srql::Output::None.into(),
+                ..Default::default()
+            })
+            .await?
+            .take::<Option<()>>(0)?;
+        Ok(())
+    }
+}
+
+async fn translate(
+    provider: &dyn TranslationProvider,
+    text: Option<&str>,
+    target_language: &str,
+) -> Result<Option<TranslatedText>> {
+    match text.filter(|text| !text.trim().is_empty()) {
+        Some(text) => provider.translate(text, target_language).await.map(Some),
+        None => Ok(None),
+    }
+}
+
+/// The lowercase ISO 639-1 code of a locale's language, which is all that
+/// posts are translated by.
+fn language_code(locale: &str) -> Result<String> {
+    let locale: LanguageIdentifier = locale
+        .parse()
+        .map_err(|_| Error::InputInvalid("targetLanguage is not a valid locale".into()))?;
+    let language = locale.language.as_str();
+    // Locales without a language have `und` in its place.
+    if language.len() != 2 {
+        return Err(Error::InputInvalid(
+            "targetLanguage must have a two-letter language".into(),
+        ));
+    }
+    Ok(language.to_owned())
+}
+
+/// Each post has at most one translation into each language, stored under an
+/// ID made from both.
+fn translation_thing(post_id: &srql::Thing, language: &str) -> srql::Thing {
+    srql::Thing::from((
+        POST_TRANSLATION_TABLE_NAME,
+        format!("{}_{language}", post_id.id.to_raw()).as_str(),
+    ))
+}
+
+fn source_hash(post: &Post) -> String {
+    let mut ctx = digest::Context::new(&digest::SHA256);
+    for text in [&post.title, &post.content] {
+        let text = text.as_deref().unwrap_or_default();
+        ctx.update(&(text.len() as u64).to_be_bytes());
+        ctx.update(text.as_bytes());
+    }
+    hex::encode(ctx.finish())
+}
+
+#[cfg(test)]
+pub mod testing {
+    use crate::account::testing::TestData;
+
+    use super::TranslationPersist;
+
+    pub trait TranslationTestData {
+        fn translation(&self) -> TranslationPersist<'_>;
+    }
+
+    impl TranslationTestData for TestData {
+        fn translation(&self) -> TranslationPersist<'_> {
+            TranslationPersist::new(&self.persist, &self.current)
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use std::sync::Arc;
+
+use async_graphql::MaybeUndefined;
+use chrono::Duration;
+use pretty_assertions::assert_eq;
+
+use super::{testing::TranslationTestData as _, *};
+use crate::{
+    account::testing::*,
+    config::TranslationConfig,
+    post::{testing::PostTestData as _, UpdatePost},
+    provider::MockClock,
+    translation::StubTranslationProvider,
+};
+
+async fn translating_data(per_hour: u32) -> (TestData, MockClock, AccData) {
+    let clock = MockClock::default();
+    let mut data = TestData::with_clock(clock.clone()).await;
+    data.persist = data.persist.with_translation(TranslationConfig {
+        provider: Some(Arc::new(StubTranslationProvider)),
+        per_hour,
+    });
+    let acc = data.account().create_test_user().await;
+    data.login_as(&acc);
+    (data, clock, acc)
+}
+
+#[tokio::test]
+async fn test_translate_post() {
+    let (data, clock, _) = translating_data(0).await;
+    let acc_id = data.current.id().unwrap().to_account_thing();
+    let post = data.generate_post().await;
+    let post_id = post.id.id.to_raw();
+
+    let res = data.translation().translate_post(&post_id, "fr-CA").await;
+    let translation = res.unwrap().unwrap();
+    assert_eq!(translation.post_id, post.id);
+    assert_eq!(translation.title, None);
+    assert_eq!(translation.content.as_deref(), Some("[fr] Test"));
+    assert_eq!(translation.source_language, None);
+    assert_eq!(translation.target_language, "fr");
+    assert_eq!(translation.provider, "stub");
+    assert_eq!(translation.translated_by, acc_id);
+
+    // Translations are reused until the post is edited.
+    clock.advance(Duration::minutes(5));
+    let res = data.translation().translate_post(&post_id, "fr").await;
+    assert_eq!(res.unwrap().unwrap(), translation);
+
+    data.post()
+        .update(
+            &post_id,
+            UpdatePost {
+                title: MaybeUndefined::Value("Hello".into()),
+                content: MaybeUndefined::Value("Edited".into()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    let res = data.translation().translate_post(&post_id, "fr").await;
+    let edited = res.unwrap().unwrap();
+    assert_eq!(edited.title.as_deref(), Some("[fr] Hello"));
+    assert_eq!(edited.content.as_deref(), Some("[fr] Edited"));
+    assert!(edited.translated_at > translation.translated_at);
+
+    let res = data.translation().translate_post(&post_id, "de").await;
+    assert_eq!(
+        res.unwrap().unwrap().content.as_deref(),
+        Some("[de] Edited")
+    );
+
+    let res = data.translation().translate_post("missing", "fr").await;
+    assert_eq!(res, Ok(None));
+    for invalid in ["", "not a locale", "und", "fra"] {
+        let res = data.translation().translate_post(&post_id, invalid).await;
+        assert!(
+            matches!(res, Err(Error::InputInvalid(_))),
+            "{invalid}: {res:?}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_translate_post_unavailable() {
+    let (mut data, _) = TestData::with_user().await;
+    let post = data.generate_post().await;
+    let post_id = post.id.id.to_raw();
+
+    let res = data.translation().translate_post(&post_id, "fr").await;
+    assert_eq!(res, Err(Error::TranslationUnavailable));
+
+    data.persist = data.persist.with_translation(TranslationConfig {
+        provider: Some(Arc::new(StubTranslationProvider)),
+        per_hour: 0,
+    });
+    data.current = CurrentAccount::default();
+    let res = data.translation().translate_post(&post_id, "fr").await;
+    assert_eq!(res, Err(Error::Unauthenticated));
+}
+
+#[tokio::test]
+async fn test_translate_post_rate_limited() {
+    let (mut data, clock, acc) = translating_data(2).await;
+    let posts = data.generate_posts(3).await;
+    let ids: Vec<_> = posts.iter().map(|post| post.id.id.to_raw()).collect();
+
+    for id in &ids[..2] {
+        let res = data.translation().translate_post(id, "fr").await;
+        assert!(res.is_ok(), "{res:?}");
+    }
+    let res = data.translation().translate_post(&ids[2], "fr").await;
+    assert_eq!(res, Err(Error::RateLimited));
+
+    // Posts that have already been translated don't count.
+    let res = data.translation().translate_post(&ids[0], "fr").await;
+    assert!(res.is_ok(), "{res:?}");
+
+    clock.advance(Duration::hours(1));
+    data.login_as(&acc);
+    let res = data.translation().translate_post(&ids[2], "fr").await;
+    assert!(res.is_ok(), "{res:?}");
+}
@@ -0,0 +1,261 @@
+use std::{
+    fmt::{self, Debug},
+    sync::Arc,
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use hyper::{Body, Method, Request};
+use secrecy::{ExposeSecret as _, SecretString};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    http::{http_client, HttpClient},
+    prelude::*,
+};
+
+pub type SharedTranslationProvider = Arc<dyn TranslationProvider>;
+
+/// How long to wait for a provider before giving up.
+const TRANSLATE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Responses from a provider any bigger than this aren't translations of a
+/// post, so aren't read any further.
+const MAX_RESPONSE_BYTES: usize = 1024 * 1024;
+
+/// The free `DeepL` API, which keys ending in `:fx` belong to.
+static DEEPL_FREE_URL: &str = "https://api-free.deepl.com";
+static DEEPL_PRO_URL: &str = "https://api.deepl.com";
+
+/// Text that a provider has translated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TranslatedText {
+    pub text: String,
+    /// The language that the provider detected the text was in, as a
+    /// lowercase ISO 639-1 code, if it could tell.
+    pub source_language: Option<String>,
+}
+
+/// Something that translates text between languages.
+#[async_trait]
+pub trait TranslationProvider: Debug + Send + Sync {
+    /// The name of the provider, which is given along with its translations.
+    fn name(&self) -> &'static str;
+
+    /// Translates text into the `target` language, given as a lowercase ISO
+    /// 639-1 code, detecting which language it's in.
+    async fn translate(&self, text: &str, target: &str) -> Result<TranslatedText>;
+}
+
+/// Marks text with the language it was meant to be translated into without
+/// changing it, so that translation can be tried out without a provider.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StubTranslationProvider;
+
+#[async_trait]
+impl TranslationProvider for StubTranslationProvider {
+    fn name(&self) -> &'static str {
+        "stub"
+    }
+
+    async fn translate(&self, text: &str, target: &str) -> Result<TranslatedText> {
+        Ok(TranslatedText {
+            text: format!("[{target}] {text}"),
+            source_language: None,
+        })
+    }
+}
+
+/// Translates text with a `LibreTranslate` server.
+#[derive(Clone)]
+pub struct LibreTranslateProvider {
+    client: HttpClient,
+    url: String,
+    api_key: Option<SecretString>,
+}
+
+impl LibreTranslateProvider {
+    #[must_use]
+    pub fn new(url: impl Into<String>, api_key: Option<SecretString>) -> Self {
+        Self {
+            client: http_client(),
+            url: url.into().trim_end_matches('/').to_owned(),
+            api_key,
+        }
+    }
+}
+
+impl Debug for LibreTranslateProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LibreTranslateProvider")
+            .field("url", &self.url)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Serialize)]
+struct LibreTranslateRequest<'a> {
+    q: &'a str,
+    source: &'a str,
+    target: &'a str,
+    format: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    api_key: Option<&'a str>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LibreTranslateResponse {
+    translated_text: String,
+    detected_language: Option<LibreTranslateDetection>,
+}
+
+#[derive(Deserialize)]
+struct LibreTranslateDetection {
+    language: String,
+}
+
+#[async_trait]
+impl TranslationProvider for LibreTranslateProvider {
+    fn name(&self) -> &'static str {
+        "libre_translate"
+    }
+
+    async fn translate(&self, text: &str, target: &str) -> Result<TranslatedText> {
+        let body = serde_json::to_vec(&LibreTranslateRequest {
+            q: text,
+            source: "auto",
+            target,
+            format: "text",
+            api_key: self
+                .api_key
+                .as_ref()
+                .map(|key| key.expose_secret().as_str()),
+        })?;
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri(format!("{}/translate", self.url))
+            .header("accept", "application/json")
+            .header("content-type", "application/json")
+            .body(Body::from(body))
+            .map_err(Error::from_err)?;
+
+        let res: LibreTranslateResponse = serde_json::from_slice(&send(&self.client, req).await?)?;
+        Ok(TranslatedText {
+            text: res.translated_text,
+            source_language: res
+                .detected_language
+                .map(|detected| detected.language.to_ascii_lowercase()),
+        })
+    }
+}
+
+/// Translates text with the `DeepL` API.
+#[derive(Clone)]
+pub struct DeeplProvider {
+    client: HttpClient,
+    url: String,
+    api_key: SecretString,
+}
+
+impl DeeplProvider {
+    /// Uses the API at `url`, or the free or pro API depending on which the
+    /// key is for if it isn't given.
+    #[must_use]
+    pub fn new(url: Option<String>, api_key: SecretString) -> Self {
+        let url = url.unwrap_or_else(|| {
+            if api_key.expose_secret().ends_with(":fx") {
+                DEEPL_FREE_URL.to_owned()
+            } else {
+                DEEPL_PRO_URL.to_owned()
+            }
+        });
+        Self {
+            client: http_client(),
+            url: url.trim_end_matches('/').to_owned(),
+            api_key,
+        }
+    }
+}
+
+impl Debug for DeeplProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DeeplProvider")
+            .field("url", &self.url)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Serialize)]
+struct DeeplRequest<'a> {
+    text: [&'a str; 1],
+    target_lang: String,
+}
+
+#[derive(Deserialize)]
+struct DeeplResponse {
+    translations: Vec<DeeplTranslation>,
+}
+
+#[derive(Deserialize)]
+struct DeeplTranslation {
+    text: String,
+    detected_source_language: Option<String>,
+}
+
+#[async_trait]
+impl TranslationProvider for DeeplProvider {
+    fn name(&self) -> &'static str {
+        "deepl"
+    }
+
+    async fn translate(&self, text: &str, target: &str) -> Result<TranslatedText> {
+        let body = serde_json::to_vec(&DeeplRequest {
+            text: [text],
+            target_lang: target.to_ascii_uppercase(),
+        })?;
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri(format!("{}/v2/translate", self.url))
+            .header(
+                "authorization",
+                format!("DeepL-Auth-Key {}", self.api_key.expose_secret()),
+            )
+            .header("content-type", "application/json")
+            .body(Body::from(body))
+            .map_err(Error::from_err)?;
+
+        let res: DeeplResponse = serde_json::from_slice(&send(&self.client, req).await?)?;
+        let translation = res
+            .translations
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::from("Translation provider didn't translate anything"))?;
+        Ok(TranslatedText {
+            text: translation.text,
+            source_language: translation
+                .detected_source_language
+                .map(|language| language.to_ascii_lowercase()),
+        })
+    }
+}
+
+/// Sends a request to a provider, failing if it doesn't succeed.
+async fn send(client: &HttpClient, req: Request<Body>) -> Result<Vec<u8>> {
+    let res = tokio::time::timeout(TRANSLATE_TIMEOUT, client.request(req))
+        .await
+        .map_err(|_| Error::from("Translation provider timed out"))?
+        .map_err(Error::from_err)?;
+    if !res.status().is_success() {
+        return Err(format!("Translation provider failed with {}", res.status()).into());
+    }
+
+    let body = tokio::time::timeout(TRANSLATE_TIMEOUT, hyper::body::to_bytes(res.into_body()))
+        .await
+        .map_err(|_| Error::from("Translation provider timed out"))?
+        .map_err(Error::from_err)?;
+    if body.len() > MAX_RESPONSE_BYTES {
+        return Err("Translation provider's response is too big".into());
+    }
+    Ok(body.to_vec())
+}
@@ -0,0 +1,32 @@
+use async_graphql::{Context, Object, ID};
+use tracing::instrument;
+
+use super::PostTranslation;
+use crate::prelude::*;
+
+#[derive(Default)]
+pub struct TranslationQuery;
+
+#[Object]
+impl TranslationQuery {
+    /// Translates a post into the language of a locale, such as `fr` or
+    /// `pt-BR`, detecting which language it's written in. Whether the
+    /// instance can translate posts is given by `instanceInfo {
+    /// translationAvailable }`.
+    ///
+    /// Translations are reused until the post is edited. Accounts can only
+    /// have so many posts translated an hour that haven't been translated
+    /// already, and are turned away with a `RateLimited` error after that.
+    #[instrument(skip_all)]
+    async fn translate_post(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        target_language: String,
+    ) -> GqlResult<Option<PostTranslation>> {
+        ctx.translation_persist()
+            .translate_post(&id, &target_language)
+            .await
+            .extend()
+    }
+}
@@ -0,0 +1,157 @@
+//! Units of work that are written to the database all at once, or not at
+//! all.
+//!
+//! The SDK can only run a transaction as a single query, with `BEGIN` and
+//! `COMMIT` around its statements, so a [`Tx`] collects the statements that
+//! make up a piece of work and sends them together when it's committed.
+//! Nothing is written until then, and dropping it writes nothing. Reads
+//! made while building it see the database as it was before, not the writes
+//! it's holding.
+//!
+//! Transactions that conflict with another that committed first are tried
+//! again a few times, as they would likely succeed on their own.
+//!
+//! Anything that makes several writes as one change, like purging an
+//! account, builds a [`Tx`] and commits it itself, so that the change
+//! happens in full or not at all.
+
+use std::time::Duration;
+
+use nanorand::{Rng as _, WyRand};
+use surrealdb::{error::Api as SrlApiError, Response};
+use tokio::time::sleep;
+use tracing::{debug, instrument};
+
+use crate::{persist::Persist, prelude::*};
+
+/// How many times a transaction is tried before its conflicts are given
+/// back as an error.
+const MAX_ATTEMPTS: u32 = 3;
+
+/// How long to wait before trying a conflicting transaction again, doubled
+/// each time.
+const RETRY_DELAY: Duration = Duration::from_millis(20);
+
+/// Statements that are committed to the database together.
+#[derive(Debug, Default, Clone)]
+pub struct Tx {
+    statements: Vec<srql::Statement>,
+}
+
+impl Tx {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a statement to the end of the transaction.
+    pub fn push(&mut self, statement: srql::Statement) {
+        self.statements.push(statement);
+    }
+
+    /// Writes every statement to the database in one transaction, giving
+    /// back the query's response. Fails with
+    /// [`Error::TransactionConflict`] if it kept conflicting with other
+    /// transactions.
+    #[instrument(skip_all, fields(statements = self.statements.len()))]
+    pub async fn commit(self, persist: &Persist) -> Result<Response> {
+        let mut statements = Vec::with_capacity(self.statements.len() + 2);
+        statements.push(srql::trans_begin());
+        statements.extend(self.statements);
+        statements.push(srql::trans_end());
+        let query = srql::query(statements);
+
+        let mut prng = WyRand::new();
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let res = match persist.db().query(query.clone()).await {
+                Ok(res) => res.check(),
+                Err(err) => Err(err),
+            };
+            match res {
+                Ok(res) => return Ok(res),
+                Err(err) if is_conflict(&err) => {
+                    if attempt == MAX_ATTEMPTS {
+                        return Err(Error::TransactionConflict);
+                    }
+                    debug!(attempt, "Transaction conflicted, retrying");
+                    let delay = RETRY_DELAY * 2u32.pow(attempt - 1);
+                    sleep(delay + delay.mul_f64(prng.generate::<f64>())).await;
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+}
+
+/// Whether the error was the transaction conflicting with another, which
+/// the key-value stores report as text.
+fn is_conflict(err: &SrlError) -> bool {
+    matches!(
+        err,
+        SrlError::Db(SrlDbError::Tx(message)) | SrlError::Api(SrlApiError::Query(message))
+            if message.to_ascii_lowercase().contains("conflict")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::persist::testing::persist;
+
+    fn create(id: &str) -> srql::Statement {
+        srql::Statement::Create(srql::CreateStatement {
+            what: srql::thing(("item", id)),
+            output: srql::Output::None.into(),
+            ..Default::default()
+        })
+    }
+
+    async fn count(persist: &Persist) -> usize {
+        let items: Vec<srql::Thing> = persist
+            .db()
+            .query("SELECT VALUE id FROM item")
+            .await
+            .unwrap()
+            .take(0)
+            .unwrap();
+        items.len()
+    }
+
+    #[tokio::test]
+    async fn test_commit() {
+        let persist = persist().await;
+        let mut tx = Tx::new();
+        tx.push(create("one"));
+        tx.push(create("two"));
+        assert_eq!(count(&persist).await, 0);
+        tx.commit(&persist).await.unwrap();
+        assert_eq!(count(&persist).await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_all_or_nothing() {
+        let persist = persist().await;
+        let mut tx = Tx::new();
+        tx.push(create("one"));
+        // Creating the same record twice fails, so the first isn't kept.
+        tx.push(create("two"));
+        tx.push(create("two"));
+        assert!(tx.commit(&persist).await.is_err());
+        assert_eq!(count(&persist).await, 0);
+    }
+
+    #[test]
+    fn test_is_conflict() {
+        assert!(is_conflict(&SrlError::Db(SrlDbError::Tx(
+            "Transaction write conflict".into()
+        ))));
+        assert!(!is_conflict(&SrlError::Db(SrlDbError::Tx(
+            "Transaction is too large".into()
+        ))));
+        assert!(!is_conflict(&SrlError::Db(SrlDbError::QueryTimedout)));
+    }
+}
@@ -0,0 +1,168 @@
+//! Warnings that resolvers can send back alongside the data they resolved,
+//! for things that clients should know about but that didn't stop the field
+//! from resolving, such as a limit being clamped or an optional part of the
+//! result being left out.
+//!
+//! Warnings are listed in the response's `warnings` extension, each with a
+//! `code`, a `message`, and the `path` of the field that raised it, in the
+//! same form as errors. Unlike errors, they never null out any data, so
+//! clients can carry on as though the request succeeded.
+
+use std::{
+    mem,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use async_graphql::{
+    extensions::{Extension, ExtensionContext, ExtensionFactory, NextExecute, NextPrepareRequest},
+    to_value, Context, PathSegment, Request, Response, ServerResult,
+};
+use serde::Serialize;
+
+use crate::partial::path_of;
+
+/// A non-fatal problem with a field.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Warning {
+    pub code: &'static str,
+    pub message: String,
+    /// The field that raised the warning.
+    pub path: Vec<PathSegment>,
+}
+
+/// The warnings raised while handling a single GraphQL request.
+#[derive(Debug, Default, Clone)]
+pub struct Warnings(Arc<Mutex<Vec<Warning>>>);
+
+impl Warnings {
+    fn push(&self, warning: Warning) {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(warning);
+    }
+
+    fn take(&self) -> Vec<Warning> {
+        mem::take(&mut *self.0.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+pub trait WarnExt {
+    /// Adds a warning to the response, for the field being resolved.
+    fn warn(&self, code: &'static str, message: impl Into<String>);
+}
+
+impl WarnExt for Context<'_> {
+    fn warn(&self, code: &'static str, message: impl Into<String>) {
+        // Requests that weren't given somewhere to put warnings, such as
+        // subscriptions, don't get them.
+        let Some(warnings) = self.data_opt::<Warnings>() else {
+            return;
+        };
+        warnings.push(Warning {
+            code,
+            message: message.into(),
+            path: self.path_node.as_ref().map(path_of).unwrap_or_default(),
+        });
+    }
+}
+
+/// A GraphQL extension that collects the warnings raised by resolvers, and
+/// adds them to the response.
+pub struct WarningCollector;
+
+impl ExtensionFactory for WarningCollector {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(WarningExtension::default())
+    }
+}
+
+#[derive(Default)]
+struct WarningExtension {
+    warnings: Warnings,
+}
+
+#[async_trait::async_trait]
+impl Extension for WarningExtension {
+    async fn prepare_request(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        request: Request,
+        next: NextPrepareRequest<'_>,
+    ) -> ServerResult<Request> {
+        next.run(ctx, request.data(self.warnings.clone())).await
+    }
+
+    async fn execute(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        operation_name: Option<&str>,
+        next: NextExecute<'_>,
+    ) -> Response {
+        let mut res = next.run(ctx, operation_name).await;
+        let warnings = self.warnings.take();
+        if !warnings.is_empty() {
+            res.extensions.insert(
+                "warnings".to_owned(),
+                to_value(warnings).expect("warnings can always be serialized"),
+            );
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_graphql::{EmptyMutation, EmptySubscription, Object, Schema};
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use super::*;
+
+    struct Query;
+
+    #[Object]
+    impl Query {
+        async fn items(&self) -> Vec<Item> {
+            vec![Item(1), Item(2)]
+        }
+    }
+
+    struct Item(u32);
+
+    #[Object]
+    impl Item {
+        async fn value(&self, ctx: &Context<'_>) -> u32 {
+            if self.0 == 2 {
+                ctx.warn("Rounded", "The value was rounded");
+            }
+            self.0
+        }
+    }
+
+    #[tokio::test]
+    async fn test_warnings() {
+        let schema = Schema::build(Query, EmptyMutation, EmptySubscription)
+            .extension(WarningCollector)
+            .finish();
+
+        let res = schema.execute("{ items { value } }").await;
+        assert!(res.errors.is_empty(), "{:?}", res.errors);
+        assert_eq!(
+            res.data.into_json().unwrap(),
+            json!({ "items": [{ "value": 1 }, { "value": 2 }] })
+        );
+        assert_eq!(
+            res.extensions["warnings"].clone().into_json().unwrap(),
+            json!([{
+                "code": "Rounded",
+                "message": "The value was rounded",
+                "path": ["items", 1, "value"],
+            }])
+        );
+
+        // Each request gets its own warnings.
+        let res = schema.execute("{ items { __typename } }").await;
+        assert!(!res.extensions.contains_key("warnings"));
+    }
+}
@@ -0,0 +1,18 @@
+//! Webhooks let accounts hear about their own events (mentions, new
+//! followers) as they happen by having signed JSON payloads sent to a URL of
+//! their choosing, so personal automations don't need to poll.
+
+mod models;
+mod persist;
+mod schema;
+mod sender;
+
+pub use models::*;
+pub use persist::*;
+pub use schema::*;
+pub use sender::*;
+
+pub static WEBHOOK_TABLE_NAME: &str = "webhook";
+
+/// The most webhooks that each account can register.
+pub const MAX_WEBHOOKS: usize = 10;
@@ -0,0 +1,135 @@
+use async_graphql::{ComplexObject, Enum, InputObject, SimpleObject, ID};
+use chrono::{DateTime, Utc};
+use secrecy::SecretString;
+use serde::{Deserialize, Serialize};
+use surrealdb::sql::Thing;
+
+use super::WEBHOOK_TABLE_NAME;
+use crate::{id_obj_impls, prelude::*};
+
+/// How many deliveries a webhook sends each hour if not given.
+pub const DEFAULT_MAX_PER_HOUR: u32 = 60;
+
+/// Something that happened to an account that its webhooks can be told
+/// about.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    /// The account was mentioned in a post.
+    MentionReceived,
+    /// Another account followed the account.
+    FollowerGained,
+}
+
+/// A URL that an account's events are sent to.
+#[derive(SimpleObject, Debug, Clone, Deserialize)]
+#[graphql(complex)]
+pub struct Webhook {
+    #[graphql(skip)]
+    pub id: Thing,
+    #[graphql(skip)]
+    pub owner_id: Thing,
+    /// The key that deliveries are signed with.
+    #[graphql(skip)]
+    pub secret: SecretString,
+
+    /// The URL that deliveries are `POST`ed to.
+    pub url: String,
+    /// The events that are sent.
+    pub events: Vec<WebhookEvent>,
+    /// How many deliveries are sent each hour. Any more are dropped until the
+    /// hour is up.
+    pub max_per_hour: u32,
+
+    /// When the current hour of deliveries started.
+    #[graphql(skip)]
+    pub window_started_at: Option<DateTime<Utc>>,
+    /// How many deliveries have been sent in the current hour.
+    #[graphql(skip)]
+    #[serde(default)]
+    pub window_deliveries: u32,
+
+    /// A timestamp indicating the last time the webhook was updated.
+    pub updated_at: DateTime<Utc>,
+}
+
+#[ComplexObject]
+impl Webhook {
+    /// The webhook's unique ID.
+    async fn id(&self) -> ID {
+        self.id.to_gql_id()
+    }
+}
+
+id_obj_impls!(Webhook);
+
+impl Webhook {
+    pub fn create(
+        owner_id: Thing,
+        secret: SecretString,
+        params: CreateWebhook,
+        ids: &dyn IdGen,
+    ) -> srql::CreateStatement {
+        let mut create = vec![];
+        owner_id.push_field(srql::field("owner_id"), &mut create);
+        secret.push_field(srql::field("secret"), &mut create);
+        0u32.push_field(srql::field("window_deliveries"), &mut create);
+        params.append(&mut create);
+        srql::obj_create_query(WEBHOOK_TABLE_NAME, create, ids)
+    }
+
+    /// Whether the webhook is sent the given event.
+    #[must_use]
+    pub fn wants(&self, event: WebhookEvent) -> bool {
+        self.events.contains(&event)
+    }
+}
+
+#[derive(InputObject, Debug, Clone, PartialEq, Eq)]
+pub struct CreateWebhook {
+    /// The `http` or `https` URL that deliveries are `POST`ed to.
+    #[graphql(validator(min_length = 1, max_length = 2048))]
+    pub url: String,
+    /// The events to send. At least one must be given.
+    pub events: Vec<WebhookEvent>,
+    /// How many deliveries are sent each hour. Defaults to 60.
+    #[graphql(validator(minimum = 1, maximum = 3600))]
+    pub max_per_hour: Option<u32>,
+}
+
+impl CreateObject for CreateWebhook {
+    fn append(self, expr: &mut srql::SetExpr) {
+        self.url.push_field(srql::field("url"), expr);
+        if let Ok(events) = srql::to_value(self.events) {
+            expr.push((srql::field("events"), srql::Operator::Equal, events));
+        }
+        self.max_per_hour
+            .unwrap_or(DEFAULT_MAX_PER_HOUR)
+            .push_field(srql::field("max_per_hour"), expr);
+    }
+}
+
+/// A newly created webhook, along with the secret that its deliveries are
+/// signed with. The secret can't be fetched again later.
+#[derive(SimpleObject, Debug)]
+pub struct CreatedWebhook {
+    pub webhook: Webhook,
+    /// The key that deliveries are signed with, using HMAC-SHA256 over the
+    /// request body. The hex-encoded signature is sent in the
+    /// `X-Plazer-Signature` header as `sha256=<signature>`.
+    pub secret: String,
+}
+
+/// The body of a delivery.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookPayload {
+    pub event: WebhookEvent,
+    /// The ID of the account that the event happened to.
+    pub account_id: String,
+    /// The ID of the account that caused the event, if any.
+    pub actor_id: Option<String>,
+    /// The ID of the post that caused the event, if any.
+    pub post_id: Option<String>,
+    pub sent_at: DateTime<Utc>,
+}
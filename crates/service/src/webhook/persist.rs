@@ -0,0 +1,266 @@
+#[cfg(test)]
+mod tests;
+
+use axum::body::Bytes;
+use base64::prelude::*;
+use chrono::{DateTime, Duration, Utc};
+use ring::{
+    hmac,
+    rand::{SecureRandom as _, SystemRandom},
+};
+use secrecy::{ExposeSecret as _, SecretString};
+use tracing::{error, instrument, warn};
+
+use super::{
+    CreateWebhook, CreatedWebhook, Webhook, WebhookDelivery, WebhookEvent, WebhookPayload,
+    MAX_WEBHOOKS, WEBHOOK_TABLE_NAME,
+};
+use crate::{account::CurrentAccount, persist::Persist, prelude::*, proxy::check_host};
+
+/// How many random bytes go into a signing secret.
+const SECRET_LEN: usize = 32;
+
+pub struct WebhookPersist<'a> {
+    persist: &'a Persist,
+    current: &'a CurrentAccount,
+    csrng: &'a SystemRandom,
+}
+
+impl<'a> WebhookPersist<'a> {
+    pub fn new(persist: &'a Persist, current: &'a CurrentAccount, csrng: &'a SystemRandom) -> Self {
+        Self {
+            persist,
+            current,
+            csrng,
+        }
+    }
+
+    /// Gets a webhook by its ID, if it's owned by the current account.
+    #[instrument(skip_all)]
+    pub async fn get(&self, id: &str) -> Result<Option<Webhook>> {
+        let owner = self.current.id()?.to_account_thing();
+        let webhook: Option<Webhook> = self.persist.db().select((WEBHOOK_TABLE_NAME, id)).await?;
+        Ok(webhook.filter(|webhook| webhook.owner_id == owner))
+    }
+
+    /// Lists all of the webhooks owned by the current account.
+    #[instrument(skip_all)]
+    pub async fn owned(&self) -> Result<Vec<Webhook>> {
+        let owner = self.current.id()?.to_account_thing();
+        owned_by(self.persist, owner).await
+    }
+
+    /// Registers a webhook that the current account's events are sent to.
+    #[instrument(skip_all)]
+    pub async fn create(&self, webhook: CreateWebhook) -> Result<CreatedWebhook> {
+        let owner = self.current.id()?.to_account_thing();
+        check_url(&webhook.url).await?;
+        if webhook.events.is_empty() {
+            return Err(Error::InputInvalid(
+                "at least one event must be given".into(),
+            ));
+        }
+        if owned_by(self.persist, owner.clone()).await?.len() >= MAX_WEBHOOKS {
+            return Err(Error::QuotaExceeded("webhooks".into()));
+        }
+
+        let mut secret = [0u8; SECRET_LEN];
+        self.csrng.fill(&mut secret)?;
+        let secret = BASE64_URL_SAFE_NO_PAD.encode(secret);
+
+        let webhook: Option<Webhook> = self
+            .persist
+            .db()
+            .query(Webhook::create(
+                owner,
+                secret.clone().into(),
+                webhook,
+                self.persist.ids(),
+            ))
+            .await?
+            .take(0)?;
+
+        match webhook {
+            Some(webhook) => Ok(CreatedWebhook { webhook, secret }),
+            None => Err(Error::UnavailableIdent),
+        }
+    }
+
+    #[instrument(skip_all)]
+    pub async fn delete(&self, id: &str) -> Result<Option<Webhook>> {
+        if self.get(id).await?.is_none() {
+            return Ok(None);
+        }
+
+        let webhook = self.persist.db().delete((WEBHOOK_TABLE_NAME, id)).await?;
+        Ok(webhook)
+    }
+}
+
+/// Tells an account's webhooks about an event that happened to it.
+///
+/// The event has already happened by the time this is called, so failing to
+/// send shouldn't fail the request that caused it, and errors are logged
+/// instead.
+#[instrument(skip_all)]
+pub async fn send_webhooks(
+    persist: &Persist,
+    event: WebhookEvent,
+    account_id: &srql::Thing,
+    actor_id: Option<&srql::Thing>,
+    post_id: Option<&srql::Thing>,
+) {
+    let payload = WebhookPayload {
+        event,
+        account_id: account_id.to_gql_id().0,
+        actor_id: actor_id.map(|id| id.to_gql_id().0),
+        post_id: post_id.map(|id| id.to_gql_id().0),
+        sent_at: persist.clock().now(),
+    };
+    if let Err(err) = try_send_webhooks(persist, account_id, &payload).await {
+        error!(error = ?err, "Failed to send webhooks");
+    }
+}
+
+async fn try_send_webhooks(
+    persist: &Persist,
+    account_id: &srql::Thing,
+    payload: &WebhookPayload,
+) -> Result<()> {
+    let webhooks = owned_by(persist, account_id.clone()).await?;
+    let mut webhooks = webhooks
+        .into_iter()
+        .filter(|webhook| webhook.wants(payload.event))
+        .peekable();
+    if webhooks.peek().is_none() {
+        return Ok(());
+    }
+
+    let body = Bytes::from(serde_json::to_vec(payload)?);
+    for webhook in webhooks {
+        if !count_delivery(persist, &webhook).await? {
+            warn!(webhook = %webhook.id, "Webhook is over its hourly limit, dropping delivery");
+            continue;
+        }
+        persist.webhooks().send(WebhookDelivery {
+            signature: sign(&webhook.secret, &body),
+            url: webhook.url,
+            body: body.clone(),
+        });
+    }
+    Ok(())
+}
+
+async fn owned_by(persist: &Persist, owner: srql::Thing) -> Result<Vec<Webhook>> {
+    let webhooks = persist
+        .db()
+        .query(srql::SelectStatement {
+            expr: srql::Fields::all(),
+            what: srql::table(WEBHOOK_TABLE_NAME),
+            cond: srql::Cond(
+                srql::Expression::Binary {
+                    l: srql::field("owner_id").into(),
+                    o: srql::Operator::Equal,
+                    r: owner.into(),
+                }
+                .into(),
+            )
+            .into(),
+            order: srql::Orders(vec![srql::Order {
+                order: srql::field("id"),
+                direction: true,
+                ..Default::default()
+            }])
+            .into(),
+            ..Default::default()
+        })
+        .await?
+        .take(0)?;
+    Ok(webhooks)
+}
+
+/// Counts a delivery against the webhook's hourly limit. Returns whether the
+/// delivery can be sent, which it can't once the limit has been reached.
+async fn count_delivery(persist: &Persist, webhook: &Webhook) -> Result<bool> {
+    let now = persist.clock().now();
+    let (window_started_at, window_deliveries) = match webhook.window_started_at {
+        Some(started_at) if !window_elapsed(started_at, now) => {
+            if webhook.window_deliveries >= webhook.max_per_hour {
+                return Ok(false);
+            }
+            (started_at, webhook.window_deliveries + 1)
+        }
+        _ => (now, 1),
+    };
+
+    let mut update = vec![];
+    window_started_at.push_field(srql::field("window_started_at"), &mut update);
+    window_deliveries.push_field(srql::field("window_deliveries"), &mut update);
+    persist
+        .db()
+        .query(srql::UpdateStatement {
+            what: srql::thing(webhook.id.clone()),
+            data: srql::Data::SetExpression(update).into(),
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(true)
+}
+
+fn window_elapsed(started_at: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+    now - started_at >= Duration::hours(1)
+}
+
+/// Only absolute `http` and `https` URLs on public hosts can be sent to, so
+/// that webhooks can't be pointed at the instance's own network.
+async fn check_url(url: &str) -> Result<()> {
+    let uri = url
+        .parse::<hyper::Uri>()
+        .map_err(|_| Error::InputInvalid("url is not a valid URL".into()))?;
+    let scheme_ok = matches!(uri.scheme_str(), Some("http" | "https"));
+    if !scheme_ok || uri.host().is_none() {
+        return Err(Error::InputInvalid(
+            "url must be an http or https URL".into(),
+        ));
+    }
+    match check_host(&uri).await {
+        // A host that doesn't resolve yet might later, and deliveries are
+        // only ever made to public addresses, so it's let through.
+        Err(Error::RemoteContentUnavailable) => Ok(()),
+        res => res,
+    }
+}
+
+/// Signs a delivery as `sha256=<hex>`, the same way integration deliveries
+/// are signed.
+fn sign(secret: &SecretString, body: &[u8]) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.expose_secret().as_bytes());
+    format!("sha256={}", hex::encode(hmac::sign(&key, body)))
+}
+
+#[cfg(test)]
+pub mod testing {
+    use std::sync::Arc;
+
+    use super::WebhookPersist;
+    use crate::{account::testing::TestData, webhook::MemoryWebhookSender};
+
+    pub trait WebhookTestData {
+        fn webhook(&self) -> WebhookPersist<'_>;
+        /// Keeps webhook deliveries in memory instead of sending them.
+        fn record_webhooks(&mut self) -> MemoryWebhookSender;
+    }
+
+    impl WebhookTestData for TestData {
+        fn webhook(&self) -> WebhookPersist<'_> {
+            WebhookPersist::new(&self.persist, &self.current, &self.csrng)
+        }
+
+        fn record_webhooks(&mut self) -> MemoryWebhookSender {
+            let sender = MemoryWebhookSender::default();
+            self.persist = self.persist.clone().with_webhooks(Arc::new(sender.clone()));
+            sender
+        }
+    }
+}
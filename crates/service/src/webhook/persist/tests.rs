@@ -0,0 +1,223 @@
+use chrono::{TimeZone as _, Utc};
+use pretty_assertions::assert_eq;
+
+use super::{testing::WebhookTestData as _, *};
+use crate::{
+    account::testing::*, follow::testing::FollowTestData as _, post::testing::PostTestData as _,
+    post::CreatePost, provider::MockClock,
+};
+
+fn create(events: Vec<WebhookEvent>) -> CreateWebhook {
+    CreateWebhook {
+        url: "https://example.com/hooks".into(),
+        events,
+        max_per_hour: Some(2),
+    }
+}
+
+fn payload(delivery: &WebhookDelivery) -> serde_json::Value {
+    serde_json::from_slice(&delivery.body).unwrap()
+}
+
+#[tokio::test]
+async fn test_create() {
+    let (data, acc) = TestData::with_user().await;
+
+    let res = data
+        .webhook()
+        .create(create(vec![WebhookEvent::FollowerGained]))
+        .await;
+    println!("{res:?}");
+    let created = res.unwrap();
+    assert_eq!(created.webhook.owner_id, acc.id);
+    assert_eq!(created.webhook.events, vec![WebhookEvent::FollowerGained]);
+    assert_eq!(created.webhook.max_per_hour, 2);
+    assert_eq!(created.webhook.secret.expose_secret(), &created.secret);
+
+    let owned = data.webhook().owned().await.unwrap();
+    assert_eq!(owned, vec![created.webhook]);
+}
+
+#[tokio::test]
+async fn test_create_invalid() {
+    let (data, _) = TestData::with_user().await;
+
+    for url in [
+        "not a url",
+        "/hooks",
+        "ftp://example.com/hooks",
+        "http://127.0.0.1:8080/hooks",
+        "http://localhost/hooks",
+        "http://10.0.0.1/hooks",
+        "http://169.254.169.254/latest/meta-data",
+        "http://[::1]/hooks",
+    ] {
+        let res = data
+            .webhook()
+            .create(CreateWebhook {
+                url: url.into(),
+                ..create(vec![WebhookEvent::FollowerGained])
+            })
+            .await;
+        assert!(matches!(res, Err(Error::InputInvalid(_))), "{url}: {res:?}");
+    }
+
+    let res = data.webhook().create(create(vec![])).await;
+    assert!(matches!(res, Err(Error::InputInvalid(_))), "{res:?}");
+}
+
+#[tokio::test]
+async fn test_create_limit() {
+    let (data, _) = TestData::with_user().await;
+    for _ in 0..MAX_WEBHOOKS {
+        data.webhook()
+            .create(create(vec![WebhookEvent::FollowerGained]))
+            .await
+            .unwrap();
+    }
+
+    let res = data
+        .webhook()
+        .create(create(vec![WebhookEvent::FollowerGained]))
+        .await;
+    assert_eq!(res.unwrap_err(), Error::QuotaExceeded("webhooks".into()));
+}
+
+#[tokio::test]
+async fn test_owned_only() {
+    let (mut data, _) = TestData::with_user().await;
+    let created = data
+        .webhook()
+        .create(create(vec![WebhookEvent::FollowerGained]))
+        .await
+        .unwrap();
+    let id = created.webhook.id.to_gql_id();
+
+    let other = data.account().create_test_user().await;
+    data.login_as(&other);
+    assert_eq!(data.webhook().get(&id).await.unwrap(), None);
+    assert_eq!(data.webhook().delete(&id).await.unwrap(), None);
+    assert!(data.webhook().owned().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_follower_gained() {
+    let (mut data, acc) = TestData::with_user().await;
+    let sent = data.record_webhooks();
+    let created = data
+        .webhook()
+        .create(create(vec![WebhookEvent::FollowerGained]))
+        .await
+        .unwrap();
+
+    let follower = data.account().create_test_user().await;
+    data.login_as(&follower);
+    assert_eq!(data.follow().follow(&acc.id.id.to_raw()).await, Ok(true));
+    // Following again doesn't gain a follower.
+    assert_eq!(data.follow().follow(&acc.id.id.to_raw()).await, Ok(false));
+
+    let sent = sent.take();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].url, "https://example.com/hooks");
+    assert_eq!(
+        sent[0].signature,
+        sign(&created.secret.into(), &sent[0].body)
+    );
+    let payload = payload(&sent[0]);
+    assert_eq!(payload["event"], "follower_gained");
+    assert_eq!(payload["accountId"], acc.id.to_gql_id().0);
+    assert_eq!(payload["actorId"], follower.id.to_gql_id().0);
+    assert_eq!(payload["postId"], serde_json::Value::Null);
+}
+
+#[tokio::test]
+async fn test_mention_received() {
+    let (mut data, acc) = TestData::with_user().await;
+    let sent = data.record_webhooks();
+    data.webhook()
+        .create(create(vec![WebhookEvent::MentionReceived]))
+        .await
+        .unwrap();
+
+    // Mentioning yourself doesn't send anything.
+    data.post()
+        .create(CreatePost {
+            content: Some("Me".into()),
+            mention_ids: Some(vec![acc.id.to_gql_id()]),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert!(sent.take().is_empty());
+
+    let author = data.account().create_test_user().await;
+    data.login_as(&author);
+    let post = data
+        .post()
+        .create(CreatePost {
+            content: Some("Hello".into()),
+            mention_ids: Some(vec![acc.id.to_gql_id(), acc.id.to_gql_id()]),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    // Being mentioned twice in the same post only sends once.
+    let sent = sent.take();
+    assert_eq!(sent.len(), 1);
+    let payload = payload(&sent[0]);
+    assert_eq!(payload["event"], "mention_received");
+    assert_eq!(payload["actorId"], author.id.to_gql_id().0);
+    assert_eq!(payload["postId"], post.id.to_gql_id().0);
+}
+
+#[tokio::test]
+async fn test_only_wanted_events() {
+    let (mut data, acc) = TestData::with_user().await;
+    let sent = data.record_webhooks();
+    data.webhook()
+        .create(create(vec![WebhookEvent::MentionReceived]))
+        .await
+        .unwrap();
+
+    let follower = data.account().create_test_user().await;
+    data.login_as(&follower);
+    data.follow().follow(&acc.id.id.to_raw()).await.unwrap();
+    assert!(sent.take().is_empty());
+}
+
+#[tokio::test]
+async fn test_hourly_limit() {
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap());
+    let mut data = TestData::with_clock(clock.clone()).await;
+    let sent = data.record_webhooks();
+    let acc = data.account().create_test_user().await;
+    data.login_as(&acc);
+    data.webhook()
+        .create(create(vec![WebhookEvent::MentionReceived]))
+        .await
+        .unwrap();
+
+    let author = data.account().create_test_user().await;
+    data.login_as(&author);
+    let posts = data.post();
+    let mention = || {
+        posts.create(CreatePost {
+            content: Some("Hi".into()),
+            mention_ids: Some(vec![acc.id.to_gql_id()]),
+            ..Default::default()
+        })
+    };
+
+    mention().await.unwrap();
+    clock.advance(Duration::minutes(30));
+    mention().await.unwrap();
+    // Deliveries over the limit are dropped, but the post is still created.
+    mention().await.unwrap();
+    assert_eq!(sent.take().len(), 2);
+
+    // The limit resets an hour after the first delivery.
+    clock.advance(Duration::minutes(30));
+    mention().await.unwrap();
+    assert_eq!(sent.take().len(), 1);
+}
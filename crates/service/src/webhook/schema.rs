@@ -0,0 +1,41 @@
+use async_graphql::{Context, Object, ID};
+use tracing::instrument;
+
+use super::{CreateWebhook, CreatedWebhook, Webhook};
+use crate::{policy::PoliciesAccepted, prelude::*};
+
+#[derive(Default)]
+pub struct WebhookQuery;
+
+#[Object]
+impl WebhookQuery {
+    /// Lists the webhooks registered by the current account.
+    #[instrument(skip_all)]
+    async fn webhooks(&self, ctx: &Context<'_>) -> GqlResult<Vec<Webhook>> {
+        ctx.webhook_persist().owned().await.extend()
+    }
+}
+
+#[derive(Default)]
+pub struct WebhookMutation;
+
+#[Object(guard = "PoliciesAccepted")]
+impl WebhookMutation {
+    /// Registers a webhook that the current account's events are sent to.
+    /// The returned secret is needed to check deliveries' signatures, and
+    /// can't be fetched again.
+    #[instrument(skip_all)]
+    async fn create_webhook(
+        &self,
+        ctx: &Context<'_>,
+        create: CreateWebhook,
+    ) -> GqlResult<CreatedWebhook> {
+        ctx.webhook_persist().create(create).await.extend()
+    }
+
+    /// Deletes a webhook. Nothing more is sent to it afterwards.
+    #[instrument(skip_all)]
+    async fn delete_webhook(&self, ctx: &Context<'_>, id: ID) -> GqlResult<Option<Webhook>> {
+        ctx.webhook_persist().delete(&id).await.extend()
+    }
+}
@@ -0,0 +1,116 @@
+use std::{
+    fmt::{self, Debug},
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+
+use axum::body::Bytes;
+use hyper::{Body, Method, Request};
+use tracing::warn;
+
+use crate::{
+    http::{public_http_client, PublicHttpClient},
+    integration::SIGNATURE_HEADER,
+};
+
+pub type SharedWebhookSender = Arc<dyn WebhookSender>;
+
+/// How long to wait for a webhook's URL to respond before giving up.
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A signed payload ready to be sent to a webhook's URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebhookDelivery {
+    pub url: String,
+    pub body: Bytes,
+    /// The `sha256=<hex>` signature of the body.
+    pub signature: String,
+}
+
+/// Something that sends deliveries to webhooks.
+pub trait WebhookSender: Debug + Send + Sync {
+    /// Sends a delivery in the background. Nothing waits on the result, so
+    /// failures are logged rather than returned.
+    fn send(&self, delivery: WebhookDelivery);
+}
+
+/// Sends deliveries over HTTP or HTTPS, only ever connecting to public
+/// addresses, however the URL's host resolves by the time it's sent to.
+#[derive(Clone)]
+pub struct HttpWebhookSender {
+    client: PublicHttpClient,
+}
+
+impl HttpWebhookSender {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            client: public_http_client(),
+        }
+    }
+}
+
+impl Default for HttpWebhookSender {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for HttpWebhookSender {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpWebhookSender").finish_non_exhaustive()
+    }
+}
+
+impl WebhookSender for HttpWebhookSender {
+    fn send(&self, delivery: WebhookDelivery) {
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri(&delivery.url)
+            .header("content-type", "application/json")
+            .header(SIGNATURE_HEADER, &delivery.signature)
+            .body(Body::from(delivery.body));
+        let req = match req {
+            Ok(req) => req,
+            Err(err) => {
+                warn!(error = ?err, url = delivery.url, "Webhook URL is invalid");
+                return;
+            }
+        };
+
+        let client = self.client.clone();
+        tokio::spawn(async move {
+            match tokio::time::timeout(SEND_TIMEOUT, client.request(req)).await {
+                Ok(Ok(res)) if res.status().is_success() => {}
+                Ok(Ok(res)) => {
+                    warn!(status = %res.status(), url = delivery.url, "Webhook was refused");
+                }
+                Ok(Err(err)) => {
+                    warn!(error = ?err, url = delivery.url, "Failed to send webhook");
+                }
+                Err(_) => warn!(url = delivery.url, "Webhook timed out"),
+            }
+        });
+    }
+}
+
+/// Keeps deliveries in memory instead of sending them, so they can be
+/// checked later.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryWebhookSender(Arc<Mutex<Vec<WebhookDelivery>>>);
+
+impl MemoryWebhookSender {
+    /// Takes every delivery sent so far.
+    pub fn take(&self) -> Vec<WebhookDelivery> {
+        std::mem::take(&mut *self.0.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+impl WebhookSender for MemoryWebhookSender {
+    fn send(&self, delivery: WebhookDelivery) {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(delivery);
+    }
+}
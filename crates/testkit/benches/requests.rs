@@ -0,0 +1,89 @@
+//! Rough timings for requests that go through the service's hot paths.
+//!
+//! Run with `cargo bench -p plazer_testkit`. Each request is timed end-to-end
+//! against a [`TestServer`], so the numbers include HTTP overhead and are only
+//! comparable between runs on the same machine.
+
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use futures::future::join_all;
+use plazer_testkit::TestServer;
+use serde_json::json;
+
+const FEED_SIZE: usize = 50;
+/// How many requests are sent at once when timing requests that queue up for
+/// the database.
+const CONCURRENCY: usize = 16;
+
+#[tokio::main]
+async fn main() {
+    let server = TestServer::start().await;
+    let client = server.register_as("bench", "bench-password").await;
+
+    // Password hashing dominates logging in, so fewer iterations are needed.
+    bench("login", 20, || server.login("bench", "bench-password")).await;
+
+    // Validates the JWT and loads the account.
+    bench("me", 200, || client.query("{ me { id userId } }")).await;
+
+    for i in 0..FEED_SIZE {
+        let _ = client
+            .request(
+                "mutation ($content: String!) { createPost(create: { content: $content }) { id } }",
+                json!({ "content": format!("Post {i}") }),
+            )
+            .await
+            .data();
+    }
+    bench("feed", 100, || {
+        client.query("{ posts(first: 50) { edges { node { id title content } } } }")
+    })
+    .await;
+
+    // Many requests at once, which wait on each other for database
+    // connections. Compare with `--db-pool-size` against a remote database.
+    bench("me x16", 50, || {
+        join_all((0..CONCURRENCY).map(|_| client.query("{ me { id userId } }")))
+    })
+    .await;
+    bench("feed x16", 20, || {
+        join_all(
+            (0..CONCURRENCY).map(|_| {
+                client.query("{ posts(first: 50) { edges { node { id title content } } } }")
+            }),
+        )
+    })
+    .await;
+
+    server.stop().await;
+}
+
+async fn bench<F, Fut>(name: &str, iterations: usize, mut f: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future,
+{
+    // Warm up caches and connections before measuring.
+    f().await;
+
+    let mut times = Vec::with_capacity(iterations);
+    for _ in 0..iterations {
+        let start = Instant::now();
+        f().await;
+        times.push(start.elapsed());
+    }
+    times.sort();
+
+    let total: Duration = times.iter().sum();
+    #[allow(clippy::cast_possible_truncation)]
+    let mean = total / iterations as u32;
+    let percentile = |p: usize| times[(times.len() * p / 100).min(times.len() - 1)];
+    println!(
+        "{name:<10} {iterations:>5} iters  mean {mean:>10.2?}  p50 {:>10.2?}  p95 {:>10.2?}",
+        percentile(50),
+        percentile(95),
+    );
+}
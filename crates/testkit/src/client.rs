@@ -0,0 +1,282 @@
+use std::net::SocketAddr;
+
+use hyper::{body, client::HttpConnector, header, Body, Method, Request, Response, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+
+use crate::Subscription;
+
+/// A GraphQL client for a [`TestServer`](crate::TestServer), optionally
+/// logged into an account.
+#[derive(Clone)]
+pub struct Client {
+    addr: SocketAddr,
+    http: hyper::Client<HttpConnector>,
+    token: Option<String>,
+    account_id: Option<String>,
+}
+
+impl Client {
+    pub(crate) fn new(addr: SocketAddr) -> Self {
+        Self {
+            addr,
+            http: hyper::Client::new(),
+            token: None,
+            account_id: None,
+        }
+    }
+
+    /// The access token that the client sends with each request, if it's
+    /// logged in.
+    #[must_use]
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+
+    /// The ID of the account that the client is logged into, if any.
+    #[must_use]
+    pub fn account_id(&self) -> Option<&str> {
+        self.account_id.as_deref()
+    }
+
+    /// Sends a query or mutation without any variables.
+    pub async fn query(&self, query: &str) -> GqlResponse {
+        self.request(query, Value::Null).await
+    }
+
+    /// Sends a query or mutation.
+    pub async fn request(&self, query: &str, variables: Value) -> GqlResponse {
+        let body = json!({ "query": query, "variables": variables });
+        let mut req = Request::builder()
+            .method(Method::POST)
+            .uri(format!("http://{}/api/graphql", self.addr))
+            .header(header::CONTENT_TYPE, "application/json");
+        if let Some(token) = &self.token {
+            req = req.header(header::AUTHORIZATION, format!("Bearer {token}"));
+        }
+        let req = req
+            .body(Body::from(body.to_string()))
+            .expect("GraphQL request is invalid");
+
+        let res = self
+            .http
+            .request(req)
+            .await
+            .expect("GraphQL request failed");
+        let status = res.status();
+        let body = body::to_bytes(res.into_body())
+            .await
+            .expect("Failed to read GraphQL response");
+        assert!(
+            status.is_success(),
+            "GraphQL request failed with {status}: {}",
+            String::from_utf8_lossy(&body)
+        );
+
+        serde_json::from_slice(&body).expect("GraphQL response is malformed")
+    }
+
+    /// Makes a request to the REST API at the given path under `/api`,
+    /// returning the status and the JSON body (or `null` if there isn't one).
+    pub async fn rest(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let body = body.map(|body| ("application/json", body.to_string().into_bytes()));
+        self.rest_raw(method, path, body).await
+    }
+
+    /// Uploads a raw body to the REST API at the given path under `/api`,
+    /// returning the status and the JSON body (or `null` if there isn't one).
+    pub async fn upload(
+        &self,
+        path: &str,
+        content_type: &str,
+        body: impl Into<Vec<u8>>,
+    ) -> (StatusCode, Value) {
+        self.rest_raw(Method::POST, path, Some((content_type, body.into())))
+            .await
+    }
+
+    async fn rest_raw(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<(&str, Vec<u8>)>,
+    ) -> (StatusCode, Value) {
+        let mut req = Request::builder()
+            .method(method)
+            .uri(format!("http://{}/api{path}", self.addr));
+        if let Some(token) = &self.token {
+            req = req.header(header::AUTHORIZATION, format!("Bearer {token}"));
+        }
+        let req = match body {
+            Some((content_type, body)) => req
+                .header(header::CONTENT_TYPE, content_type)
+                .body(Body::from(body)),
+            None => req.body(Body::empty()),
+        }
+        .expect("REST request is invalid");
+
+        let res = self.http.request(req).await.expect("REST request failed");
+        let status = res.status();
+        let body = body::to_bytes(res.into_body())
+            .await
+            .expect("Failed to read REST response");
+        let body = if body.is_empty() {
+            Value::Null
+        } else {
+            serde_json::from_slice(&body).unwrap_or_else(|_| {
+                panic!(
+                    "REST response with {status} is malformed: {}",
+                    String::from_utf8_lossy(&body)
+                )
+            })
+        };
+
+        (status, body)
+    }
+
+    /// Fetches a page that isn't part of an API, returning the status and the
+    /// body as text.
+    pub async fn page(&self, path: &str) -> (StatusCode, String) {
+        let req = Request::builder()
+            .uri(format!("http://{}{path}", self.addr))
+            .body(Body::empty())
+            .expect("Page request is invalid");
+
+        let res = self.http.request(req).await.expect("Page request failed");
+        let status = res.status();
+        let body = body::to_bytes(res.into_body())
+            .await
+            .expect("Failed to read page");
+
+        (status, String::from_utf8_lossy(&body).into_owned())
+    }
+
+    /// Fetches a path with extra headers, sending the access token if the
+    /// client is logged in, and returns the raw response.
+    pub async fn fetch(&self, path: &str, headers: &[(&str, &str)]) -> Response<Vec<u8>> {
+        self.fetch_with(Method::GET, path, headers).await
+    }
+
+    /// Like [`Client::fetch`], but with any method.
+    pub async fn fetch_with(
+        &self,
+        method: Method,
+        path: &str,
+        headers: &[(&str, &str)],
+    ) -> Response<Vec<u8>> {
+        let mut req = Request::builder()
+            .method(method)
+            .uri(format!("http://{}{path}", self.addr));
+        if let Some(token) = &self.token {
+            req = req.header(header::AUTHORIZATION, format!("Bearer {token}"));
+        }
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        let req = req.body(Body::empty()).expect("Fetch request is invalid");
+
+        let res = self.http.request(req).await.expect("Fetch request failed");
+        let (parts, body) = res.into_parts();
+        let body = body::to_bytes(body)
+            .await
+            .expect("Failed to read fetched body");
+        Response::from_parts(parts, body.to_vec())
+    }
+
+    /// Starts a subscription over a WebSocket. Queries and mutations can also
+    /// be sent this way, in which case a single response is received.
+    pub async fn subscribe(&self, query: &str, variables: Value) -> Subscription {
+        Subscription::start(self.addr, self.token.as_deref(), query, variables).await
+    }
+
+    /// Runs a mutation that returns an account and tokens as `auth`, and
+    /// returns a copy of this client that is logged into that account.
+    pub(crate) async fn authenticate(&self, mutation: &str, variables: Value) -> Self {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Auth {
+            access_token: String,
+            account: AuthAccount,
+        }
+
+        #[derive(Deserialize)]
+        struct AuthAccount {
+            id: String,
+        }
+
+        #[derive(Deserialize)]
+        struct Data {
+            auth: Auth,
+        }
+
+        let Data { auth } = self.request(mutation, variables).await.data_as();
+
+        Self {
+            token: Some(auth.access_token),
+            account_id: Some(auth.account.id),
+            ..self.clone()
+        }
+    }
+}
+
+/// A response to a GraphQL request.
+#[derive(Debug, Clone, Deserialize)]
+pub struct GqlResponse {
+    #[serde(default)]
+    pub data: Value,
+    #[serde(default)]
+    pub errors: Vec<GqlResponseError>,
+    #[serde(default)]
+    pub extensions: Value,
+}
+
+impl GqlResponse {
+    /// Gets the response data, failing if there were any errors.
+    #[must_use]
+    pub fn data(self) -> Value {
+        assert!(
+            self.errors.is_empty(),
+            "GraphQL request returned errors: {:#?}",
+            self.errors
+        );
+        self.data
+    }
+
+    /// Gets the response data as the given type, failing if there were any
+    /// errors.
+    #[must_use]
+    pub fn data_as<T: DeserializeOwned>(self) -> T {
+        serde_json::from_value(self.data()).expect("GraphQL response data has the wrong shape")
+    }
+
+    /// The error codes returned by the service, in order. Errors without a
+    /// code, such as validation errors, are skipped.
+    pub fn error_codes(&self) -> Vec<&str> {
+        self.errors
+            .iter()
+            .filter_map(GqlResponseError::code)
+            .collect()
+    }
+}
+
+/// An error in a GraphQL response.
+#[derive(Debug, Clone, Deserialize)]
+pub struct GqlResponseError {
+    pub message: String,
+    #[serde(default)]
+    pub path: Vec<Value>,
+    #[serde(default)]
+    pub extensions: Value,
+}
+
+impl GqlResponseError {
+    /// The service's error code, such as `Unauthorized`.
+    pub fn code(&self) -> Option<&str> {
+        self.extensions.get("code").and_then(Value::as_str)
+    }
+}
@@ -0,0 +1,23 @@
+//! End-to-end test helpers for the Plazer service.
+//!
+//! [`TestServer`] runs the full service against an in-memory database on a
+//! random port, and hands out [`Client`]s that talk to it over HTTP and
+//! web sockets like a real client would.
+
+#![deny(clippy::all)]
+#![deny(clippy::pedantic)]
+#![allow(clippy::module_name_repetitions)]
+#![allow(clippy::missing_errors_doc)]
+// Everything here is only used by tests, where panicking is the right way to
+// report a failure.
+#![allow(clippy::missing_panics_doc)]
+#![forbid(unsafe_code)]
+
+mod client;
+mod server;
+pub mod snapshot;
+mod ws;
+
+pub use client::*;
+pub use server::*;
+pub use ws::*;
@@ -0,0 +1,217 @@
+use std::{
+    net::{Ipv4Addr, SocketAddr, TcpListener},
+    sync::Arc,
+};
+
+use plazer_service::{
+    config::{
+        ClientVersionConfig, CorsConfig, DbConfig, DevAuthConfig, FeatureFlags, InstanceConfig,
+        LimitsConfig, LogLevels, MediaConfig, OidcConfig, OverloadConfig, PersistedQueryConfig,
+        PostVisibilityConfig, PrivacyConfig, ProxyConfig, QueryLimitsConfig, QuotaConfig,
+        RateLimitConfig, ReadOnlyConfig, ServeConfig, SessionConfig, SpamConfig, TranslationConfig,
+    },
+    provider::{SharedClock, SystemClock, UlidGen},
+    serve_on, Argon2Hasher, MemoryDomainVerifier, MemoryEmailSender, MemoryNotificationTransport,
+    MemoryOidcClient, MemoryWebhookSender, ServeError,
+};
+use ring::{
+    rand::SystemRandom,
+    signature::{self, KeyPair as _},
+};
+use serde_json::{json, Value};
+use tokio::{sync::oneshot, task::JoinHandle};
+
+use crate::Client;
+
+/// A running instance of the service, listening on a random local port.
+///
+/// The server is shut down when this is dropped.
+pub struct TestServer {
+    addr: SocketAddr,
+    shutdown: Option<oneshot::Sender<()>>,
+    handle: Option<JoinHandle<Result<(), ServeError>>>,
+}
+
+impl TestServer {
+    /// Starts a server with the default test configuration.
+    pub async fn start() -> Self {
+        Self::start_with(|_| ()).await
+    }
+
+    /// Starts a server, allowing the test configuration to be adjusted first.
+    pub async fn start_with(adjust: impl FnOnce(&mut ServeConfig)) -> Self {
+        let listener =
+            TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).expect("Failed to bind test listener");
+        let addr = listener.local_addr().expect("Test listener has no address");
+
+        let mut config = config(addr);
+        adjust(&mut config);
+
+        let (shutdown, shutdown_rx) = oneshot::channel();
+        let handle = tokio::spawn(serve_on(listener, config, async {
+            shutdown_rx.await.ok();
+        }));
+
+        let server = Self {
+            addr,
+            shutdown: Some(shutdown),
+            handle: Some(handle),
+        };
+
+        // The listener is already bound, so this waits until the server has
+        // finished starting up, and fails if it couldn't.
+        let res = server.client().query("{ instanceInfo { version } }").await;
+        assert!(
+            res.errors.is_empty(),
+            "Test server failed to start: {:#?}",
+            res.errors
+        );
+
+        server
+    }
+
+    /// The address that the server is listening on.
+    #[must_use]
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Creates a client that isn't logged in.
+    #[must_use]
+    pub fn client(&self) -> Client {
+        Client::new(self.addr)
+    }
+
+    /// Registers a new account with a random user ID, returning a client that
+    /// is logged into it.
+    pub async fn register(&self) -> Client {
+        let user_id = format!(
+            "test-{}",
+            ulid::Ulid::new().to_string().to_ascii_lowercase()
+        );
+        self.register_as(&user_id, "test-password").await
+    }
+
+    /// Registers a new account for an adult, returning a client that is
+    /// logged into it. Whatever policies the instance has published are
+    /// accepted.
+    pub async fn register_as(&self, user_id: &str, pword: &str) -> Client {
+        let client = self.client();
+        let policies = client.query("{ policies { id } }").await.data();
+        let policy_ids: Vec<&Value> = policies["policies"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|policy| &policy["id"])
+            .collect();
+
+        client
+            .authenticate(
+                "mutation ($userId: String!, $pword: String!, $policyIds: [ID!]) {
+                    auth: createAccount(create: {
+                        userId: $userId
+                        pword: $pword
+                        acceptedPolicyIds: $policyIds
+                        birthdate: \"2000-01-01\"
+                    }) {
+                        accessToken
+                        account { id }
+                    }
+                }",
+                json!({ "userId": user_id, "pword": pword, "policyIds": policy_ids }),
+            )
+            .await
+    }
+
+    /// Logs into an existing account, returning a client that is logged into
+    /// it.
+    pub async fn login(&self, user_id: &str, pword: &str) -> Client {
+        self.client()
+            .authenticate(
+                "mutation ($userId: String!, $pword: String!) {
+                    auth: login(creds: { userId: $userId, pword: $pword }) {
+                        ... on AuthenticatedAccount {
+                            accessToken
+                            account { id }
+                        }
+                    }
+                }",
+                json!({ "userId": user_id, "pword": pword }),
+            )
+            .await
+    }
+
+    /// Shuts the server down, waiting for it to finish.
+    pub async fn stop(mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            shutdown.send(()).ok();
+        }
+        if let Some(handle) = self.handle.take() {
+            handle
+                .await
+                .expect("Test server panicked")
+                .expect("Test server failed");
+        }
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            shutdown.send(()).ok();
+        }
+    }
+}
+
+fn config(addr: SocketAddr) -> ServeConfig {
+    let rng = SystemRandom::new();
+    let pkcs8_bytes =
+        signature::Ed25519KeyPair::generate_pkcs8(&rng).expect("Failed to generate JWT key");
+    let key_pair = signature::Ed25519KeyPair::from_pkcs8(pkcs8_bytes.as_ref())
+        .expect("Generated JWT key is invalid");
+    let clock: SharedClock = Arc::new(SystemClock);
+
+    ServeConfig {
+        address: "memory".into(),
+        namespace: "test".into(),
+        database: "test".into(),
+        db: DbConfig::default(),
+        jwt_enc_key: jsonwebtoken::EncodingKey::from_ed_der(pkcs8_bytes.as_ref()),
+        jwt_dec_key: jsonwebtoken::DecodingKey::from_ed_der(key_pair.public_key().as_ref()),
+        host: addr.ip(),
+        port: addr.port(),
+        cors: CorsConfig::default(),
+        instance: InstanceConfig::default(),
+        spam: SpamConfig::default(),
+        dev_auth: DevAuthConfig::default(),
+        overload: OverloadConfig::default(),
+        // Tests make requests much faster than people do, so they opt into
+        // rate limits when they're testing them.
+        rate_limits: RateLimitConfig::unlimited(),
+        features: FeatureFlags::default(),
+        log_levels: LogLevels::default(),
+        reload_from: None,
+        query_limits: QueryLimitsConfig::default(),
+        persisted_queries: PersistedQueryConfig::default(),
+        privacy: PrivacyConfig::default(),
+        quotas: QuotaConfig::default(),
+        limits: LimitsConfig::default(),
+        read_only: ReadOnlyConfig::default(),
+        client_versions: ClientVersionConfig::default(),
+        media: MediaConfig::default(),
+        post_visibility: PostVisibilityConfig::default(),
+        sessions: SessionConfig::default(),
+        clock: clock.clone(),
+        ids: Arc::new(UlidGen::new(clock)),
+        password_hasher: Arc::new(Argon2Hasher::default()),
+        webhooks: Arc::new(MemoryWebhookSender::default()),
+        domains: Arc::new(MemoryDomainVerifier::default()),
+        oidc: OidcConfig::default(),
+        oidc_client: Arc::new(MemoryOidcClient::default()),
+        notification_transport: Arc::new(MemoryNotificationTransport::default()),
+        email: Arc::new(MemoryEmailSender::default()),
+        audit_sinks: vec![],
+        translation: TranslationConfig::default(),
+        proxy: ProxyConfig::default(),
+    }
+}
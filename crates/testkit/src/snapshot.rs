@@ -0,0 +1,73 @@
+//! Snapshot assertions for GraphQL responses.
+//!
+//! Snapshots are stored as pretty-printed JSON. If a snapshot doesn't exist
+//! yet, or `PLAZER_UPDATE_SNAPSHOTS` is set, it is written instead of being
+//! compared, and should be reviewed and committed along with the test.
+
+use std::{env, fs, path::Path};
+
+use serde_json::Value;
+
+pub static ENV_VAR_UPDATE_SNAPSHOTS: &str = "PLAZER_UPDATE_SNAPSHOTS";
+
+static REDACTED: &str = "[redacted]";
+
+/// Asserts that a value matches the snapshot with the given name, stored in
+/// the calling crate's `tests/snapshots` directory.
+#[macro_export]
+macro_rules! assert_snapshot {
+    ($name:expr, $value:expr $(,)?) => {
+        $crate::snapshot::assert_snapshot(
+            ::std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots"),
+            $name,
+            &$value,
+        )
+    };
+}
+
+/// Asserts that a value matches the snapshot `<name>.json` in the given
+/// directory. Prefer [`assert_snapshot!`](crate::assert_snapshot), which
+/// finds the directory automatically.
+pub fn assert_snapshot(dir: impl AsRef<Path>, name: &str, value: &Value) {
+    let path = dir.as_ref().join(format!("{name}.json"));
+    let actual =
+        serde_json::to_string_pretty(value).expect("Snapshot could not be serialised") + "\n";
+
+    if env::var_os(ENV_VAR_UPDATE_SNAPSHOTS).is_some() || !path.exists() {
+        fs::create_dir_all(dir.as_ref()).expect("Failed to create snapshot directory");
+        fs::write(&path, actual).expect("Failed to write snapshot");
+        return;
+    }
+
+    let expected = fs::read_to_string(&path).expect("Failed to read snapshot");
+    pretty_assertions::assert_eq!(
+        expected,
+        actual,
+        "Snapshot {} does not match, set {ENV_VAR_UPDATE_SNAPSHOTS} to update it",
+        path.display()
+    );
+}
+
+/// Replaces the values of any fields with the given names, at any depth, so
+/// that IDs and timestamps don't make snapshots change between runs.
+#[must_use]
+pub fn redact(mut value: Value, fields: &[&str]) -> Value {
+    fn inner(value: &mut Value, fields: &[&str]) {
+        match value {
+            Value::Object(obj) => {
+                for (key, value) in obj {
+                    if fields.contains(&key.as_str()) && !value.is_null() {
+                        *value = REDACTED.into();
+                    } else {
+                        inner(value, fields);
+                    }
+                }
+            }
+            Value::Array(arr) => arr.iter_mut().for_each(|value| inner(value, fields)),
+            _ => (),
+        }
+    }
+
+    inner(&mut value, fields);
+    value
+}
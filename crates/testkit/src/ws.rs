@@ -0,0 +1,133 @@
+use std::net::SocketAddr;
+
+use futures::{SinkExt as _, StreamExt as _};
+use hyper::header;
+use serde_json::{json, Value};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{client::IntoClientRequest as _, Message},
+    MaybeTlsStream, WebSocketStream,
+};
+
+use crate::{GqlResponse, GqlResponseError};
+
+const SUBSCRIPTION_ID: &str = "1";
+
+/// A GraphQL subscription, using the `graphql-transport-ws` protocol.
+pub struct Subscription {
+    stream: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    complete: bool,
+}
+
+impl Subscription {
+    pub(crate) async fn start(
+        addr: SocketAddr,
+        token: Option<&str>,
+        query: &str,
+        variables: Value,
+    ) -> Self {
+        let mut req = format!("ws://{addr}/api/graphql/ws")
+            .into_client_request()
+            .expect("WebSocket request is invalid");
+        req.headers_mut().insert(
+            header::SEC_WEBSOCKET_PROTOCOL,
+            "graphql-transport-ws".parse().unwrap(),
+        );
+
+        let (stream, _) = connect_async(req).await.expect("WebSocket connect failed");
+        let mut sub = Self {
+            stream,
+            complete: false,
+        };
+
+        // The service rejects a `null` token, so leave it out entirely when
+        // not logged in.
+        let payload = match token {
+            Some(token) => json!({ "token": token }),
+            None => json!({}),
+        };
+        sub.send(json!({ "type": "connection_init", "payload": payload }))
+            .await;
+        match sub.recv().await {
+            Some(msg) if msg["type"] == "connection_ack" => (),
+            msg => panic!("WebSocket connection was not acknowledged: {msg:?}"),
+        }
+
+        sub.send(json!({
+            "id": SUBSCRIPTION_ID,
+            "type": "subscribe",
+            "payload": { "query": query, "variables": variables },
+        }))
+        .await;
+
+        sub
+    }
+
+    /// Waits for the next response, returning `None` once the subscription
+    /// has completed.
+    pub async fn next(&mut self) -> Option<GqlResponse> {
+        if self.complete {
+            return None;
+        }
+
+        while let Some(mut msg) = self.recv().await {
+            match msg["type"].as_str() {
+                Some("next") => {
+                    let payload = msg["payload"].take();
+                    return Some(
+                        serde_json::from_value(payload).expect("Subscription payload is malformed"),
+                    );
+                }
+                Some("error") => {
+                    self.complete = true;
+                    let errors: Vec<GqlResponseError> =
+                        serde_json::from_value(msg["payload"].take())
+                            .expect("Subscription errors are malformed");
+                    return Some(GqlResponse {
+                        data: Value::Null,
+                        errors,
+                        extensions: Value::Null,
+                    });
+                }
+                Some("complete") => break,
+                Some("ping") => self.send(json!({ "type": "pong" })).await,
+                _ => (),
+            }
+        }
+
+        self.complete = true;
+        None
+    }
+
+    /// Stops the subscription and closes the connection.
+    pub async fn stop(mut self) {
+        if !self.complete {
+            self.send(json!({ "id": SUBSCRIPTION_ID, "type": "complete" }))
+                .await;
+        }
+        self.stream.close(None).await.ok();
+    }
+
+    async fn send(&mut self, msg: Value) {
+        self.stream
+            .send(Message::Text(msg.to_string()))
+            .await
+            .expect("Failed to send WebSocket message");
+    }
+
+    async fn recv(&mut self) -> Option<Value> {
+        while let Some(msg) = self.stream.next().await {
+            match msg.expect("Failed to receive WebSocket message") {
+                Message::Text(text) => {
+                    return Some(
+                        serde_json::from_str(&text).expect("WebSocket message is malformed"),
+                    )
+                }
+                Message::Close(_) => return None,
+                _ => (),
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,435 @@
+use std::sync::Arc;
+
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use plazer_service::{
+    config::{MetadataVisibility, OidcConfig, OidcProviderConfig, OidcProviderKind},
+    ExternalProfile, MemoryOidcClient,
+};
+use plazer_testkit::{assert_snapshot, snapshot::redact, TestServer};
+use pretty_assertions::assert_eq;
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn test_register_and_login() {
+    let server = TestServer::start().await;
+    let client = server.register_as("testkit", "test-password").await;
+    assert!(client.token().is_some());
+
+    let res = client.query("{ me { id userId admin } }").await.data();
+    assert_eq!(res["me"]["id"], client.account_id().unwrap());
+    assert_snapshot!("register_me", redact(res, &["id"]));
+
+    let other = server.login("testkit", "test-password").await;
+    assert_eq!(other.account_id(), client.account_id());
+
+    server.stop().await;
+}
+
+#[tokio::test]
+async fn test_anonymous() {
+    let server = TestServer::start().await;
+
+    let res = server.client().query("{ me { id } }").await;
+    assert_eq!(res.error_codes(), vec!["Unauthenticated"]);
+}
+
+#[tokio::test]
+async fn test_register_random() {
+    let server = TestServer::start().await;
+    let first = server.register().await;
+    let second = server.register().await;
+    assert_ne!(first.account_id(), second.account_id());
+
+    // Only the first account on an instance is an admin.
+    let res = second.request("{ me { admin } }", json!({})).await.data();
+    assert_eq!(res, json!({ "me": { "admin": false } }));
+}
+
+#[tokio::test]
+async fn test_dev_login() {
+    let server = TestServer::start_with(|config| config.dev_auth.enabled = true).await;
+    // Seeded accounts can be logged into with the shared password as well.
+    let alice = server.login("alice", "password").await;
+
+    let res = server
+        .client()
+        .query(r#"mutation { devLogin(userId: "alice") { accessToken account { id } } }"#)
+        .await
+        .data();
+    assert_eq!(
+        res["devLogin"]["account"]["id"],
+        alice.account_id().unwrap()
+    );
+    assert!(res["devLogin"]["accessToken"].is_string());
+}
+
+#[tokio::test]
+async fn test_dev_login_disabled() {
+    let server = TestServer::start().await;
+
+    let res = server
+        .client()
+        .query(r#"mutation { devLogin(userId: "alice") { accessToken } }"#)
+        .await;
+    assert_eq!(res.error_codes(), vec!["DevAuthDisabled"]);
+}
+
+#[tokio::test]
+async fn test_min_age() {
+    let server = TestServer::start_with(|config| config.instance.min_age = 16).await;
+    let register = r#"mutation($birthdate: NaiveDate) {
+        createAccount(create: { userId: "young", pword: "password", birthdate: $birthdate }) {
+            account { id }
+        }
+    }"#;
+
+    let res = server.client().request(register, json!({})).await;
+    assert_eq!(res.error_codes(), vec!["BirthdateRequired"]);
+    let res = server
+        .client()
+        .request(register, json!({ "birthdate": "2100-01-01" }))
+        .await;
+    assert_eq!(res.error_codes(), vec!["InputInvalid"]);
+
+    let client = server.register().await;
+    let res = client.query("{ me { adult } }").await.data();
+    assert_eq!(res, json!({ "me": { "adult": true } }));
+}
+
+#[tokio::test]
+async fn test_sessions() {
+    let server = TestServer::start().await;
+    let client = server.register_as("testkit", "test-password").await;
+    server.login("testkit", "test-password").await;
+
+    let res = client.query("{ me { sessions { ip } } }").await.data();
+    assert_eq!(
+        res["me"]["sessions"],
+        json!([{ "ip": "127.0.0.0" }, { "ip": "127.0.0.0" }])
+    );
+}
+
+#[tokio::test]
+async fn test_sessions_hidden() {
+    let server = TestServer::start_with(|config| {
+        config.privacy.metadata_visibility = MetadataVisibility::None;
+    })
+    .await;
+    let client = server.register().await;
+
+    let res = client
+        .query("{ me { sessions { ip userAgent } } }")
+        .await
+        .data();
+    assert_eq!(
+        res["me"]["sessions"],
+        json!([{ "ip": null, "userAgent": null }])
+    );
+}
+
+#[tokio::test]
+async fn test_security_events() {
+    let server = TestServer::start().await;
+    let client = server.register_as("testkit", "test-password").await;
+    let res = client.query("mutation { revokeTokens }").await;
+    assert!(res.errors.is_empty());
+
+    let client = server.login("testkit", "test-password").await;
+    let res = client
+        .query(
+            "{
+                me {
+                    securityEvents(first: 10, kinds: [SIGNED_IN, TOKENS_REVOKED]) {
+                        edges { node { kind } }
+                    }
+                }
+            }",
+        )
+        .await
+        .data();
+    let kinds: Vec<&Value> = res["me"]["securityEvents"]["edges"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|edge| &edge["node"]["kind"])
+        .collect();
+    assert_eq!(kinds, vec!["SIGNED_IN", "TOKENS_REVOKED", "SIGNED_IN"]);
+}
+
+#[tokio::test]
+async fn test_timezone() {
+    let server = TestServer::start().await;
+    let client = server.register().await;
+
+    let res = client.query("{ me { timezone { name } } }").await.data();
+    assert_eq!(res, json!({ "me": { "timezone": null } }));
+
+    let res = client
+        .query(
+            r#"mutation {
+                updateAccount(update: { timezone: "Asia/Tokyo" }) {
+                    timezone { name utcOffset abbreviation }
+                }
+            }"#,
+        )
+        .await
+        .data();
+    assert_eq!(
+        res["updateAccount"]["timezone"],
+        json!({ "name": "Asia/Tokyo", "utcOffset": 9 * 3600, "abbreviation": "JST" })
+    );
+}
+
+#[tokio::test]
+async fn test_account_counts() {
+    let server = TestServer::start().await;
+    let admin = server.register().await;
+    let other = server.register().await;
+
+    let res = admin
+        .request(
+            "mutation ($id: ID!) { followAccount(id: $id) }",
+            json!({ "id": other.account_id() }),
+        )
+        .await
+        .data();
+    assert_eq!(res["followAccount"], true);
+    let res = admin
+        .query(r#"mutation { createPost(create: { content: "Hello" }) { id } }"#)
+        .await
+        .data();
+    let post_id = res["createPost"]["id"].clone();
+    let res = admin
+        .request(
+            r#"mutation ($id: ID!) { createPost(create: { content: "Hi", replyToId: $id }) { id } }"#,
+            json!({ "id": post_id }),
+        )
+        .await
+        .data();
+    assert!(res["createPost"]["id"].is_string());
+
+    // Rebuilding the projection applies every event so far, rather than
+    // waiting for it to catch up.
+    let res = admin
+        .query(r#"mutation { rebuildProjection(name: "account_counts") { name eventsApplied } }"#)
+        .await
+        .data();
+    assert_eq!(
+        res["rebuildProjection"],
+        json!({ "name": "account_counts", "eventsApplied": 5 })
+    );
+
+    let res = other
+        .query("{ me { counts { followers following postsPublished } } }")
+        .await
+        .data();
+    assert_eq!(
+        res["me"]["counts"],
+        json!({ "followers": 1, "following": 0, "postsPublished": 0 })
+    );
+
+    let res = admin
+        .query("{ me { counts { posts postsPublished } } }")
+        .await
+        .data();
+    assert_eq!(
+        res["me"]["counts"],
+        json!({ "posts": 2, "postsPublished": 2 })
+    );
+
+    let res = admin
+        .query(r#"mutation { rebuildProjection(name: "post_counts") { name } }"#)
+        .await
+        .data();
+    assert_eq!(res["rebuildProjection"]["name"], "post_counts");
+    let res = other
+        .request(
+            "query ($id: ID!) { post(id: $id) { replyCount } }",
+            json!({ "id": post_id }),
+        )
+        .await
+        .data();
+    assert_eq!(res["post"]["replyCount"], json!(1));
+
+    let res = other
+        .query(r#"mutation { rebuildProjection(name: "account_counts") { name } }"#)
+        .await;
+    assert_eq!(res.error_codes(), vec!["Unauthorized"]);
+}
+
+#[tokio::test]
+async fn test_refresh_token_rotation() {
+    let server = TestServer::start().await;
+    server.register_as("rotating", "test-password").await;
+    let anon = server.client();
+
+    let res = anon
+        .query(
+            r#"mutation {
+                login(creds: { userId: "rotating", pword: "test-password" }) {
+                    ... on AuthenticatedAccount { refreshToken }
+                }
+            }"#,
+        )
+        .await
+        .data();
+    let first = res["login"]["refreshToken"].clone();
+
+    let refresh = |token| {
+        anon.request(
+            "mutation ($token: String!) {
+                refreshToken(refreshToken: $token) { refreshToken account { userId } }
+            }",
+            json!({ "token": token }),
+        )
+    };
+    let res = refresh(first.clone()).await.data();
+    assert_eq!(res["refreshToken"]["account"]["userId"], "rotating");
+    let second = res["refreshToken"]["refreshToken"].clone();
+    assert_ne!(second, first);
+
+    // Reusing a token that has been exchanged means it was copied, so the
+    // token it was exchanged for is revoked too.
+    let res = refresh(first).await;
+    assert_eq!(res.error_codes(), vec!["CredentialsInvalid"]);
+    let res = refresh(second).await;
+    assert_eq!(res.error_codes(), vec!["CredentialsInvalid"]);
+}
+
+#[tokio::test]
+async fn test_region() {
+    let server = TestServer::start_with(|config| {
+        config.instance.region = Some("eu-west".into());
+    })
+    .await;
+    let client = server.register().await;
+
+    let res = client.fetch("/api/openapi.json", &[]).await;
+    assert_eq!(res.headers()["plazer-region"], "eu-west");
+
+    let mut validation = Validation::new(Algorithm::EdDSA);
+    validation.insecure_disable_signature_validation();
+    let claims = jsonwebtoken::decode::<Value>(
+        client.token().unwrap(),
+        &DecodingKey::from_secret(&[]),
+        &validation,
+    )
+    .unwrap()
+    .claims;
+    assert_eq!(claims["rgn"], "eu-west");
+
+    let res = client.query("{ me { sessions { region } } }").await.data();
+    assert_eq!(res["me"]["sessions"], json!([{ "region": "eu-west" }]));
+}
+
+#[tokio::test]
+async fn test_external_login() {
+    let oidc = MemoryOidcClient::default();
+    let server = TestServer::start_with(|config| {
+        config.oidc = OidcConfig::new(vec![OidcProviderConfig {
+            id: "google".into(),
+            kind: OidcProviderKind::Google,
+            name: Some("Google".into()),
+            client_id: "client".into(),
+            client_secret: "secret".into(),
+            authorization_url: None,
+            token_url: None,
+            userinfo_url: None,
+            scopes: None,
+        }])
+        .unwrap();
+        config.oidc_client = Arc::new(oidc.clone());
+    })
+    .await;
+    let client = server.register().await;
+    let profile = ExternalProfile {
+        subject: "1234".into(),
+        email: Some("test@example.com".into()),
+        email_verified: true,
+        name: None,
+    };
+
+    let res = client
+        .query("{ instanceInfo { externalLoginProviders { id name } } }")
+        .await
+        .data();
+    assert_eq!(
+        res["instanceInfo"]["externalLoginProviders"],
+        json!([{ "id": "google", "name": "Google" }])
+    );
+
+    let res = client
+        .query(
+            "mutation {
+                beginExternalLink(provider: \"google\", redirectUri: \"https://plazer.test/cb\") {
+                    url state
+                }
+            }",
+        )
+        .await
+        .data();
+    let url = res["beginExternalLink"]["url"].as_str().unwrap();
+    assert!(url.starts_with("https://accounts.google.com/o/oauth2/v2/auth?"));
+    assert!(url.contains("scope=openid+email+profile"));
+    oidc.authorize("google", "link-code", profile.clone());
+    let res = client
+        .request(
+            "mutation ($state: String!) {
+                finishExternalLink(state: $state, code: \"link-code\") { provider subject }
+            }",
+            json!({ "state": res["beginExternalLink"]["state"] }),
+        )
+        .await
+        .data();
+    assert_eq!(
+        res["finishExternalLink"],
+        json!({ "provider": "google", "subject": "1234" })
+    );
+
+    let anon = server.client();
+    let res = anon
+        .query(
+            "mutation {
+                beginExternalLogin(provider: \"google\", redirectUri: \"https://plazer.test/cb\") {
+                    ... on ExternalLoginRedirect { state }
+                }
+            }",
+        )
+        .await
+        .data();
+    oidc.authorize("google", "login-code", profile);
+    let res = anon
+        .request(
+            "mutation ($state: String!) {
+                finishExternalLogin(state: $state, code: \"login-code\") {
+                    ... on AuthenticatedAccount { account { id } }
+                }
+            }",
+            json!({ "state": res["beginExternalLogin"]["state"] }),
+        )
+        .await
+        .data();
+    assert_eq!(
+        res["finishExternalLogin"]["account"]["id"],
+        client.account_id().unwrap()
+    );
+
+    let res = anon
+        .query(
+            "mutation {
+                finishExternalLogin(state: \"unknown\", code: \"login-code\") {
+                    ... on AuthenticatedAccount { accessToken }
+                }
+            }",
+        )
+        .await;
+    assert_eq!(res.error_codes(), vec!["ExternalLoginInvalid"]);
+
+    let res = client
+        .query("{ me { externalIdentities { provider email lastUsedAt } } }")
+        .await
+        .data();
+    let identities = &res["me"]["externalIdentities"];
+    assert_eq!(identities[0]["email"], "test@example.com");
+    assert!(identities[0]["lastUsedAt"].is_string());
+}
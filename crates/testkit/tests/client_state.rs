@@ -0,0 +1,55 @@
+use std::time::Duration;
+
+use plazer_testkit::TestServer;
+use pretty_assertions::assert_eq;
+use serde_json::json;
+
+#[tokio::test]
+async fn test_sync_between_devices() {
+    let server = TestServer::start().await;
+    let client = server.register().await;
+
+    let mut sub = client
+        .subscribe(
+            r#"subscription { clientStateChanged(keys: ["draft"]) { key value deviceId } }"#,
+            json!({}),
+        )
+        .await;
+    // Give the server a moment to start listening for changes.
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let res = client
+        .query(
+            r#"mutation {
+                setClientState(set: { key: "draft", value: "Hello", deviceId: "phone" }) {
+                    accepted
+                    state { version vector { deviceId counter } }
+                }
+            }"#,
+        )
+        .await
+        .data();
+    assert_eq!(
+        res["setClientState"],
+        json!({
+            "accepted": true,
+            "state": { "version": 1, "vector": [{ "deviceId": "phone", "counter": 1 }] },
+        })
+    );
+
+    let res = sub.next().await.unwrap().data();
+    assert_eq!(
+        res["clientStateChanged"],
+        json!({ "key": "draft", "value": "Hello", "deviceId": "phone" })
+    );
+    sub.stop().await;
+
+    let res = client
+        .query(r#"{ clientStates { key value } }"#)
+        .await
+        .data();
+    assert_eq!(
+        res["clientStates"],
+        json!([{ "key": "draft", "value": "Hello" }])
+    );
+}
@@ -0,0 +1,73 @@
+use hyper::StatusCode;
+use plazer_testkit::TestServer;
+use pretty_assertions::assert_eq;
+use serde_json::{json, Value};
+
+static SET_MIN_VERSION: &str = "mutation ($name: String!, $minVersion: String) {
+    setMinClientVersion(name: $name, minVersion: $minVersion) { name minVersion }
+}";
+
+#[tokio::test]
+async fn test_min_client_version() {
+    let server = TestServer::start().await;
+    // The first account is the instance's admin.
+    let admin = server.register().await;
+    let user = server.register_as("user", "test-password").await;
+
+    let res = user
+        .request(
+            SET_MIN_VERSION,
+            json!({ "name": "plazer-ios", "minVersion": "2.1" }),
+        )
+        .await;
+    assert_eq!(res.error_codes(), vec!["Unauthorized"]);
+    let res = admin
+        .request(
+            SET_MIN_VERSION,
+            json!({ "name": "plazer-ios", "minVersion": "2.1" }),
+        )
+        .await
+        .data();
+    assert_eq!(
+        res["setMinClientVersion"],
+        json!([{ "name": "plazer-ios", "minVersion": "2.1" }])
+    );
+    let info = user
+        .query("{ instanceInfo { minClientVersions { name minVersion } } }")
+        .await
+        .data();
+    assert_eq!(
+        info["instanceInfo"]["minClientVersions"],
+        json!([{ "name": "plazer-ios", "minVersion": "2.1" }])
+    );
+
+    let app = |version| {
+        [
+            ("x-client-name", "plazer-ios"),
+            ("x-client-version", version),
+            ("x-client-platform", "ios"),
+        ]
+    };
+    let res = user.fetch("/api/v1/accounts/me", &app("2.0.3")).await;
+    assert_eq!(res.status(), StatusCode::UPGRADE_REQUIRED);
+    let body: Value = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(body["code"], "ClientOutdated");
+    assert_eq!(body["minVersion"], "2.1");
+
+    let res = user.fetch("/api/v1/accounts/me", &app("2.1.0")).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    // Clients that don't say which app they are aren't checked.
+    let res = user.fetch("/api/v1/accounts/me", &[]).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = admin
+        .request(
+            SET_MIN_VERSION,
+            json!({ "name": "plazer-ios", "minVersion": null }),
+        )
+        .await
+        .data();
+    assert_eq!(res["setMinClientVersion"], json!([]));
+    let res = user.fetch("/api/v1/accounts/me", &app("2.0.3")).await;
+    assert_eq!(res.status(), StatusCode::OK);
+}
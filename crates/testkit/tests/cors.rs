@@ -0,0 +1,65 @@
+use hyper::{Method, StatusCode};
+use plazer_testkit::TestServer;
+use pretty_assertions::assert_eq;
+
+fn header<'a>(res: &'a hyper::Response<Vec<u8>>, name: &str) -> Option<&'a str> {
+    res.headers().get(name).map(|value| value.to_str().unwrap())
+}
+
+#[tokio::test]
+async fn test_cors() {
+    let server = TestServer::start_with(|config| {
+        config.cors.origins = vec!["https://app.example.com".into()];
+    })
+    .await;
+    let client = server.client();
+
+    let res = client
+        .fetch_with(
+            Method::OPTIONS,
+            "/api/graphql",
+            &[
+                ("origin", "https://app.example.com"),
+                ("access-control-request-method", "POST"),
+                (
+                    "access-control-request-headers",
+                    "content-type, authorization",
+                ),
+            ],
+        )
+        .await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    assert_eq!(
+        header(&res, "access-control-allow-origin"),
+        Some("https://app.example.com")
+    );
+    assert_eq!(
+        header(&res, "access-control-allow-headers"),
+        Some("content-type, authorization")
+    );
+
+    let res = client
+        .fetch("/api/v1/posts", &[("origin", "https://app.example.com")])
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(
+        header(&res, "access-control-allow-origin"),
+        Some("https://app.example.com")
+    );
+
+    // Other origins get no CORS headers, so browsers block them.
+    let res = client
+        .fetch("/api/v1/posts", &[("origin", "https://evil.example.com")])
+        .await;
+    assert_eq!(header(&res, "access-control-allow-origin"), None);
+}
+
+#[tokio::test]
+async fn test_cors_off() {
+    let server = TestServer::start().await;
+    let res = server
+        .client()
+        .fetch("/api/v1/posts", &[("origin", "https://app.example.com")])
+        .await;
+    assert_eq!(header(&res, "access-control-allow-origin"), None);
+}
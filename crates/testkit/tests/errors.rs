@@ -0,0 +1,71 @@
+use plazer_testkit::TestServer;
+use pretty_assertions::assert_eq;
+use serde_json::json;
+
+#[tokio::test]
+async fn test_partial_data() {
+    let server = TestServer::start().await;
+    let admin = server.register().await;
+    let res = admin
+        .query(
+            r#"mutation {
+                createAccount(create: { userId: "helper-bot", pword: "test-password", bot: true }) {
+                    account { id }
+                }
+            }"#,
+        )
+        .await
+        .data();
+    assert!(res["createAccount"]["account"]["id"].is_string());
+
+    // The bot's owner-only field fails on its own, and the rest of the bot is
+    // still returned.
+    let res = admin.query("{ admin { bots { userId adult } } }").await;
+    assert_eq!(res.error_codes(), vec!["Unauthorized"]);
+    assert_eq!(
+        res.errors[0].path,
+        vec![json!("admin"), json!("bots"), json!(0), json!("adult")]
+    );
+    assert_eq!(
+        res.data,
+        json!({ "admin": { "bots": [{ "userId": "helper-bot", "adult": null }] } })
+    );
+}
+
+#[tokio::test]
+async fn test_partial_root() {
+    let server = TestServer::start().await;
+    server.register().await;
+    let user = server.register_as("partial-user", "test-password").await;
+
+    let res = user
+        .query("{ me { userId } admin { projections { name } } }")
+        .await;
+    assert_eq!(res.error_codes(), vec!["Unauthorized"]);
+    assert_eq!(res.errors[0].path, vec![json!("admin")]);
+    assert_eq!(
+        res.data,
+        json!({ "me": { "userId": "partial-user" }, "admin": null })
+    );
+}
+
+#[tokio::test]
+async fn test_warnings() {
+    let server = TestServer::start().await;
+    let user = server.register().await;
+
+    let res = user.query("{ posts(first: 500) { nodes { id } } }").await;
+    assert!(res.errors.is_empty(), "{:?}", res.errors);
+    assert_eq!(
+        res.extensions["warnings"],
+        json!([{
+            "code": "PageLimitClamped",
+            "message": "At most 100 items are returned per page",
+            "path": ["posts"],
+        }])
+    );
+
+    let res = user.query("{ posts(first: 10) { nodes { id } } }").await;
+    assert!(res.errors.is_empty(), "{:?}", res.errors);
+    assert!(res.extensions.get("warnings").is_none());
+}
@@ -0,0 +1,30 @@
+use hyper::StatusCode;
+use plazer_testkit::TestServer;
+use pretty_assertions::assert_eq;
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn test_probes() {
+    let server = TestServer::start().await;
+    let client = server.client();
+
+    let (status, body) = client.page("/healthz").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        serde_json::from_str::<Value>(&body).unwrap(),
+        json!({ "status": "ok" })
+    );
+
+    let (status, body) = client.page("/readyz").await;
+    assert_eq!(status, StatusCode::OK);
+    let readiness: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(readiness["status"], "ok");
+    let checks = readiness["checks"].as_array().unwrap();
+    let names: Vec<_> = checks.iter().map(|check| &check["name"]).collect();
+    assert_eq!(names, ["database", "jwt_keys", "migrations"]);
+    for check in checks {
+        assert_eq!(check["status"], "ok", "{check}");
+        assert!(check["latency_ms"].is_u64());
+        assert!(check["detail"].is_string());
+    }
+}
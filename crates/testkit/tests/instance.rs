@@ -0,0 +1,166 @@
+use std::{env, fs};
+
+use hyper::{Method, StatusCode};
+use plazer_service::config::{PostVisibility, PostVisibilityConfig, ServiceConfigBuilder};
+use plazer_testkit::TestServer;
+use pretty_assertions::assert_eq;
+use serde_json::json;
+
+static RELOAD_CONFIG: &str = "mutation { reloadConfig { changed problems } }";
+static STATS: &str = "{ instanceInfo { stats { __typename } } }";
+
+#[tokio::test]
+async fn test_content_limits() {
+    let server = TestServer::start_with(|config| {
+        config.limits.post_content = 10;
+    })
+    .await;
+    let client = server.register().await;
+
+    let info = client
+        .query("{ instanceInfo { limits { postTitle postContent } } }")
+        .await
+        .data();
+    assert_eq!(
+        info["instanceInfo"]["limits"],
+        json!({ "postTitle": 1024, "postContent": 10 })
+    );
+
+    let res = client
+        .query(r#"mutation { createPost(create: { content: "Far too long" }) { id } }"#)
+        .await;
+    assert_eq!(res.error_codes(), vec!["TooLong"]);
+    assert_eq!(res.errors[0].extensions["field"], "content");
+}
+
+#[tokio::test]
+async fn test_capabilities() {
+    let server = TestServer::start_with(|config| {
+        config.instance.public_url = Some("https://plazer.example".into());
+    })
+    .await;
+    let admin = server.register().await;
+    let user = server.register().await;
+
+    let res = user.query("{ admin { capabilities { version } } }").await;
+    assert_eq!(res.error_codes(), vec!["Unauthorized"]);
+
+    let report = admin
+        .query(
+            "{ admin { capabilities {
+                schemaHash address endpoints
+                config { databaseAddress publicUrl }
+                migrations { subsystem version }
+            } } }",
+        )
+        .await
+        .data();
+    let report = &report["admin"]["capabilities"];
+    assert_eq!(report["schemaHash"].as_str().unwrap().len(), 64);
+    assert_eq!(report["address"], server.addr().to_string());
+    assert_eq!(
+        report["endpoints"][0],
+        format!("http://{}/api/graphql", server.addr())
+    );
+    assert_eq!(
+        report["config"],
+        json!({ "databaseAddress": "memory", "publicUrl": "https://plazer.example" })
+    );
+    let migrations = report["migrations"].as_array().unwrap();
+    assert!(migrations
+        .iter()
+        .any(|migration| migration["subsystem"] == "subsys_account"
+            && migration["version"].is_string()));
+}
+
+#[tokio::test]
+async fn test_reload_config() {
+    let path = env::temp_dir().join(format!("plazer-testkit-{}.toml", ulid::Ulid::new()));
+    fs::write(&path, "public_stats = false\n").unwrap();
+    let source = ServiceConfigBuilder::new().config_path(path.display().to_string());
+    let server = TestServer::start_with(|config| {
+        config.reload_from = Some(source);
+    })
+    .await;
+    let admin = server.register().await;
+    let user = server.register().await;
+
+    let res = user.query(RELOAD_CONFIG).await;
+    assert_eq!(res.error_codes(), vec!["Unauthorized"]);
+    let info = user.query(STATS).await.data();
+    assert_eq!(info["instanceInfo"]["stats"], json!(null));
+
+    fs::write(&path, "public_stats = true\n").unwrap();
+    let res = admin.query(RELOAD_CONFIG).await.data();
+    assert_eq!(
+        res["reloadConfig"],
+        json!({ "changed": true, "problems": [] })
+    );
+    let info = user.query(STATS).await.data();
+    assert_eq!(info["instanceInfo"]["stats"]["__typename"], "PublicStats");
+
+    // A broken config is turned away, and the one in use is kept.
+    fs::write(&path, "public_stats = \"sometimes\"\n").unwrap();
+    let res = admin.query(RELOAD_CONFIG).await.data();
+    assert_eq!(res["reloadConfig"]["changed"], false);
+    assert_eq!(res["reloadConfig"]["problems"].as_array().unwrap().len(), 1);
+    let info = user.query(STATS).await.data();
+    assert_eq!(info["instanceInfo"]["stats"]["__typename"], "PublicStats");
+    fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn test_reload_config_without_source() {
+    let server = TestServer::start().await;
+    let admin = server.register().await;
+
+    let res = admin.query(RELOAD_CONFIG).await;
+    assert_eq!(res.error_codes(), vec!["InputInvalid"]);
+}
+
+#[tokio::test]
+async fn test_post_visibility() {
+    let server = TestServer::start_with(|config| {
+        config.post_visibility = PostVisibilityConfig {
+            default: PostVisibility::Instance,
+            max: PostVisibility::Unlisted,
+        };
+    })
+    .await;
+    let client = server.register().await;
+
+    let info = client
+        .query("{ instanceInfo { defaultPostVisibility allowedPostVisibilities } }")
+        .await
+        .data();
+    assert_eq!(
+        info["instanceInfo"],
+        json!({
+            "defaultPostVisibility": "INSTANCE",
+            "allowedPostVisibilities": ["UNLISTED", "INSTANCE"],
+        })
+    );
+
+    let res = client
+        .query(r#"mutation { createPost(create: { content: "Hi", visibility: PUBLIC }) { id } }"#)
+        .await;
+    assert_eq!(res.error_codes(), vec!["VisibilityDisallowed"]);
+    let res = client
+        .query(r#"mutation { createPost(create: { content: "Hi" }) { id visibility } }"#)
+        .await
+        .data();
+    assert_eq!(res["createPost"]["visibility"], "INSTANCE");
+    let id = res["createPost"]["id"].as_str().unwrap();
+
+    // Only signed in accounts can see it.
+    let anon = server.client();
+    let (status, _) = anon
+        .rest(Method::GET, &format!("/v1/posts/{id}"), None)
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, body) = client
+        .rest(Method::GET, &format!("/v1/posts/{id}"), None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["visibility"], "instance");
+}
@@ -0,0 +1,79 @@
+use std::fmt::Write as _;
+
+use hyper::{body, Body, Method, Request, StatusCode};
+use plazer_testkit::TestServer;
+use pretty_assertions::assert_eq;
+use ring::hmac;
+use serde_json::{json, Value};
+
+fn sign(secret: &str, body: &[u8]) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    let mut signature = String::from("sha256=");
+    for byte in hmac::sign(&key, body).as_ref() {
+        let _ = write!(signature, "{byte:02x}");
+    }
+    signature
+}
+
+async fn deliver(server: &TestServer, path: &str, body: &str, signature: &str) -> StatusCode {
+    let req = Request::builder()
+        .method(Method::POST)
+        .uri(format!("http://{}{path}", server.addr()))
+        .header("X-Plazer-Signature", signature)
+        .body(Body::from(body.to_owned()))
+        .unwrap();
+    let res = hyper::Client::new().request(req).await.unwrap();
+    let status = res.status();
+    body::to_bytes(res.into_body()).await.unwrap();
+    status
+}
+
+#[tokio::test]
+async fn test_deliveries() {
+    let server = TestServer::start().await;
+    let client = server.register().await;
+
+    let board = client
+        .query(r#"mutation { createBoard(create: { handle: "alerts" }) { id } }"#)
+        .await
+        .data();
+    let created = client
+        .request(
+            r#"mutation($boardId: ID!) {
+                createIntegration(create: {
+                    boardId: $boardId
+                    name: "Monitoring"
+                    contentTemplate: "{{ check }} is {{ state }}"
+                }) {
+                    integration { deliveryPath }
+                    secret
+                }
+            }"#,
+            json!({ "boardId": board["createBoard"]["id"] }),
+        )
+        .await
+        .data();
+    let path = created["createIntegration"]["integration"]["deliveryPath"]
+        .as_str()
+        .unwrap();
+    let secret = created["createIntegration"]["secret"].as_str().unwrap();
+
+    let body = r#"{"check":"api","state":"down"}"#;
+    assert_eq!(
+        deliver(&server, path, body, &sign(secret, body.as_bytes())).await,
+        StatusCode::CREATED
+    );
+    assert_eq!(
+        deliver(&server, path, body, &sign("wrong", body.as_bytes())).await,
+        StatusCode::UNAUTHORIZED
+    );
+
+    let (_, page) = server.client().rest(Method::GET, "/v1/posts", None).await;
+    let contents: Vec<&Value> = page["posts"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|post| &post["content"])
+        .collect();
+    assert_eq!(contents, vec!["api is down"]);
+}
@@ -0,0 +1,177 @@
+use hyper::{
+    header::{ACCEPT_RANGES, CACHE_CONTROL, CONTENT_RANGE, CONTENT_TYPE, X_CONTENT_TYPE_OPTIONS},
+    Method, StatusCode,
+};
+use plazer_testkit::{Client, TestServer};
+use pretty_assertions::assert_eq;
+use serde_json::{json, Value};
+
+static PNG: &[u8] = b"\x89PNG\r\n\x1a\nnot really";
+
+#[tokio::test]
+async fn test_upload_same_content() {
+    let server = TestServer::start().await;
+    let alice = server.register_as("alice", "test-password").await;
+    let bob = server.register_as("bob", "test-password").await;
+
+    let (status, first) = alice.upload("/v1/media", "image/png", PNG).await;
+    assert_eq!(status, StatusCode::CREATED, "{first}");
+    assert_eq!(first["contentType"], "image/png");
+    assert_eq!(first["size"], PNG.len());
+    let (status, second) = bob.upload("/v1/media", "IMAGE/PNG", PNG).await;
+    assert_eq!(status, StatusCode::CREATED, "{second}");
+
+    // Both uploads share the same stored content, but belong to their own
+    // accounts.
+    assert_ne!(first["id"], second["id"]);
+    assert_eq!(first["hash"], second["hash"]);
+    assert_eq!(first["ownerId"], alice.account_id().unwrap());
+    assert_eq!(second["ownerId"], bob.account_id().unwrap());
+
+    let res = bob
+        .request(
+            "query ($id: ID!) { media(id: $id) { id hash size } }",
+            json!({ "id": first["id"] }),
+        )
+        .await
+        .data();
+    assert_eq!(res["media"]["hash"], first["hash"]);
+
+    // Only the owner can delete their upload.
+    let path = format!("/v1/media/{}", first["id"].as_str().unwrap());
+    let (status, _) = bob.rest(Method::DELETE, &path, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = alice.rest(Method::DELETE, &path, None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = alice.rest(Method::GET, &path, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let path = format!("/v1/media/{}", second["id"].as_str().unwrap());
+    let (status, media) = bob.rest(Method::GET, &path, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(media["hash"], first["hash"]);
+}
+
+#[tokio::test]
+async fn test_upload_invalid() {
+    let server = TestServer::start_with(|config| config.limits.media_bytes = 8).await;
+    let client = server.register().await;
+
+    let (status, err) = client.upload("/v1/media", "image/svg+xml", "<svg/>").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(err["code"], "InputInvalid");
+    let (status, err) = client.upload("/v1/media", "image/png", PNG).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{err}");
+    assert_eq!(err["code"], "TooLong");
+    let (status, err) = server
+        .client()
+        .upload("/v1/media", "image/png", "png")
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(err["code"], "Unauthenticated");
+
+    let info = client
+        .query("{ instanceInfo { limits { mediaBytes } } }")
+        .await
+        .data();
+    assert_eq!(info["instanceInfo"]["limits"]["mediaBytes"], 8);
+}
+
+async fn media_url(client: &Client, id: &Value, scope: &str) -> String {
+    let res = client
+        .request(
+            "query ($id: ID!, $scope: MediaUrlScope!) { media(id: $id) { url(scope: $scope) } }",
+            json!({ "id": id, "scope": scope }),
+        )
+        .await
+        .data();
+    res["media"]["url"].as_str().unwrap().to_owned()
+}
+
+#[tokio::test]
+async fn test_signed_urls() {
+    let server = TestServer::start().await;
+    let alice = server.register_as("alice", "test-password").await;
+    let bob = server.register_as("bob", "test-password").await;
+    let (_, media) = alice.upload("/v1/media", "image/png", PNG).await;
+
+    // Account-scoped URLs only work for the account they were made for.
+    let url = media_url(&alice, &media["id"], "ACCOUNT").await;
+    let res = alice.fetch(&url, &[]).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()[CONTENT_TYPE], "image/png");
+    assert_eq!(res.headers()[X_CONTENT_TYPE_OPTIONS], "nosniff");
+    assert!(res.headers()[CACHE_CONTROL]
+        .to_str()
+        .unwrap()
+        .starts_with("private"));
+    assert_eq!(res.body(), PNG);
+    assert_eq!(bob.fetch(&url, &[]).await.status(), StatusCode::FORBIDDEN);
+    assert_eq!(
+        server.client().fetch(&url, &[]).await.status(),
+        StatusCode::UNAUTHORIZED
+    );
+
+    // Public ones work for anyone, but only for the media they were made for.
+    let url = media_url(&bob, &media["id"], "PUBLIC").await;
+    let res = server.client().fetch(&url, &[]).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.body(), PNG);
+    let (_, other) = bob.upload("/v1/media", "image/png", "other").await;
+    let other_url = url.replace(media["id"].as_str().unwrap(), other["id"].as_str().unwrap());
+    let res = server.client().fetch(&other_url, &[]).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    let res = server.client().fetch(&format!("{url}x"), &[]).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_signed_url_ranges() {
+    let server = TestServer::start().await;
+    let client = server.register().await;
+    let (_, media) = client.upload("/v1/media", "video/mp4", PNG).await;
+    let url = media_url(&client, &media["id"], "PUBLIC").await;
+
+    let res = client.fetch(&url, &[("Range", "bytes=1-3")]).await;
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(
+        res.headers()[CONTENT_RANGE],
+        format!("bytes 1-3/{}", PNG.len())
+    );
+    assert_eq!(res.headers()[ACCEPT_RANGES], "bytes");
+    assert_eq!(res.body(), &PNG[1..4]);
+
+    let res = client.fetch(&url, &[("Range", "bytes=-4")]).await;
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(res.body(), &PNG[PNG.len() - 4..]);
+
+    let res = client.fetch(&url, &[("Range", "bytes=100-")]).await;
+    assert_eq!(res.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(
+        res.headers()[CONTENT_RANGE],
+        format!("bytes */{}", PNG.len())
+    );
+}
+
+#[tokio::test]
+async fn test_signed_url_referer() {
+    let server = TestServer::start_with(|config| {
+        config.media.referer_hosts = vec!["plazer.example".into()];
+    })
+    .await;
+    let client = server.register().await;
+    let (_, media) = client.upload("/v1/media", "image/png", PNG).await;
+    let url = media_url(&client, &media["id"], "PUBLIC").await;
+
+    let res = client
+        .fetch(&url, &[("Referer", "https://plazer.example/posts/1")])
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let res = client
+        .fetch(&url, &[("Referer", "https://elsewhere.example/")])
+        .await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    let body: Value = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(body["code"], "HotlinkDisallowed");
+    assert_eq!(client.fetch(&url, &[]).await.status(), StatusCode::OK);
+}
@@ -0,0 +1,179 @@
+use std::{sync::Arc, time::Duration};
+
+use plazer_service::{MemoryNotificationTransport, NotificationChannel, NotificationKind};
+use plazer_testkit::TestServer;
+use pretty_assertions::assert_eq;
+use serde_json::json;
+
+#[tokio::test]
+async fn test_localized_titles() {
+    let server = TestServer::start().await;
+    let author = server.register().await;
+    let quoter = server.register_as("quoter", "test-password").await;
+
+    let post = author
+        .query(r#"mutation { createPost(create: { content: "Hello" }) { id } }"#)
+        .await
+        .data();
+    let res = quoter
+        .request(
+            r#"mutation ($quoteId: ID!) {
+                createPost(create: { quoteId: $quoteId, content: "Look" }) { id }
+            }"#,
+            json!({ "quoteId": post["createPost"]["id"] }),
+        )
+        .await;
+    assert!(res.errors.is_empty());
+
+    let title = || async {
+        let res = author
+            .query("{ notifications(first: 1) { nodes { title } } }")
+            .await
+            .data();
+        res["notifications"]["nodes"][0]["title"].clone()
+    };
+    assert_eq!(title().await, "@quoter quoted your post");
+
+    let set_locale = |locale: Option<&str>| {
+        author.request(
+            "mutation ($locale: String) { updateAccount(update: { locale: $locale }) { locale } }",
+            json!({ "locale": locale }),
+        )
+    };
+    let res = set_locale(Some("fr-CA")).await.data();
+    assert_eq!(res["updateAccount"]["locale"], "fr-CA");
+    assert_eq!(title().await, "@quoter a cité votre publication");
+
+    assert!(set_locale(None).await.errors.is_empty());
+    assert_eq!(title().await, "@quoter quoted your post");
+}
+
+#[tokio::test]
+async fn test_batched_quotes() {
+    let server = TestServer::start().await;
+    let author = server.register().await;
+    let post = author
+        .query(r#"mutation { createPost(create: { content: "Hello" }) { id } }"#)
+        .await
+        .data();
+    let post_id = &post["createPost"]["id"];
+
+    for user_id in ["first", "second"] {
+        let quoter = server.register_as(user_id, "test-password").await;
+        let res = quoter
+            .request(
+                r#"mutation ($quoteId: ID!) {
+                    createPost(create: { quoteId: $quoteId, content: "Look" }) { id }
+                }"#,
+                json!({ "quoteId": post_id }),
+            )
+            .await;
+        assert!(res.errors.is_empty());
+    }
+
+    let res = author
+        .query("{ notifications(first: 10) { nodes { title actorCount subjectId } } }")
+        .await
+        .data();
+    assert_eq!(
+        res["notifications"]["nodes"],
+        json!([{
+            "title": "@second and 1 other quoted your post",
+            "actorCount": 2,
+            "subjectId": post_id,
+        }])
+    );
+}
+
+#[tokio::test]
+async fn test_notification_added() {
+    let server = TestServer::start().await;
+    let author = server.register().await;
+    let quoter = server.register_as("quoter", "test-password").await;
+    let post = author
+        .query(r#"mutation { createPost(create: { content: "Hello" }) { id } }"#)
+        .await
+        .data();
+
+    let mut sub = author
+        .subscribe(
+            "subscription { notificationAdded(kinds: [QUOTE]) { kind actorCount } }",
+            json!({}),
+        )
+        .await;
+    // Give the server a moment to start listening for notifications.
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let res = quoter
+        .request(
+            r#"mutation ($quoteId: ID!) {
+                createPost(create: { quoteId: $quoteId, content: "Look" }) { id }
+            }"#,
+            json!({ "quoteId": post["createPost"]["id"] }),
+        )
+        .await;
+    assert!(res.errors.is_empty());
+
+    let res = sub.next().await.unwrap().data();
+    assert_eq!(
+        res["notificationAdded"],
+        json!({ "kind": "QUOTE", "actorCount": 1 })
+    );
+    sub.stop().await;
+}
+
+#[tokio::test]
+async fn test_notification_channels() {
+    let transport = MemoryNotificationTransport::default();
+    let server = TestServer::start_with(|config| {
+        config.notification_transport = Arc::new(transport.clone())
+    })
+    .await;
+    let client = server.register().await;
+
+    let res = client
+        .query(
+            r#"mutation {
+                updateNotificationSettings(update: {
+                    quietHours: { start: "22:00:00", end: "07:00:00" }
+                    routes: [{ kind: TEST, channels: [IN_APP, EMAIL] }]
+                }) {
+                    quietHours { start end }
+                    routes { kind channels }
+                }
+            }"#,
+        )
+        .await
+        .data();
+    assert_eq!(
+        res["updateNotificationSettings"],
+        json!({
+            "quietHours": { "start": "22:00:00", "end": "07:00:00" },
+            "routes": [{ "kind": "TEST", "channels": ["IN_APP", "EMAIL"] }],
+        })
+    );
+
+    let res = client
+        .query("mutation { updateNotificationSettings(update: { quietHours: null }) { quietHours { start } } }")
+        .await
+        .data();
+    assert_eq!(res["updateNotificationSettings"]["quietHours"], json!(null));
+
+    let res = client
+        .query("mutation { sendTestNotification { kind title channels heldUntil } }")
+        .await
+        .data();
+    assert_eq!(
+        res["sendTestNotification"],
+        json!({
+            "kind": "TEST",
+            "title": "This is a test notification. Your notifications are working!",
+            "channels": ["IN_APP", "EMAIL"],
+            "heldUntil": null,
+        })
+    );
+    let deliveries = transport.take();
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0].channel, NotificationChannel::Email);
+    assert_eq!(deliveries[0].kind, NotificationKind::Test);
+}
@@ -0,0 +1,199 @@
+use std::sync::Arc;
+
+use hyper::StatusCode;
+use plazer_service::MemoryDomainVerifier;
+use plazer_testkit::TestServer;
+use pretty_assertions::assert_eq;
+use serde_json::json;
+
+#[tokio::test]
+async fn test_affiliation_badge() {
+    let domains = MemoryDomainVerifier::default();
+    let server = TestServer::start_with(|config| config.domains = Arc::new(domains.clone())).await;
+    let owner = server.register().await;
+    let member = server.register_as("member", "member-password").await;
+
+    let created = owner
+        .query(
+            r#"mutation { createOrganization(create: { handle: "acme", name: "Acme" }) { id } }"#,
+        )
+        .await
+        .data();
+    let org_id = created["createOrganization"]["id"].clone();
+
+    let res = owner
+        .request(
+            r#"mutation ($id: ID!) {
+                setOrganizationDomain(id: $id, domain: "acme.test") {
+                    verified
+                    verification { txtRecord wellKnownUrl }
+                }
+            }"#,
+            json!({ "id": org_id }),
+        )
+        .await
+        .data();
+    let org = &res["setOrganizationDomain"];
+    assert_eq!(org["verified"], false);
+    assert_eq!(
+        org["verification"]["wellKnownUrl"],
+        "https://acme.test/.well-known/plazer-verification"
+    );
+    domains.publish_txt(
+        "acme.test",
+        org["verification"]["txtRecord"].as_str().unwrap(),
+    );
+
+    let res = owner
+        .request(
+            "mutation ($id: ID!) { verifyOrganization(id: $id) { verified } }",
+            json!({ "id": org_id }),
+        )
+        .await
+        .data();
+    assert_eq!(res["verifyOrganization"]["verified"], true);
+
+    let res = owner
+        .request(
+            "mutation ($id: ID!, $account: ID!) { claimMember(id: $id, accountId: $account) { id confirmed } }",
+            json!({ "id": org_id, "account": member.account_id() }),
+        )
+        .await
+        .data();
+    assert_eq!(res["claimMember"]["confirmed"], false);
+    let claim_id = res["claimMember"]["id"].clone();
+
+    // Only the owner can see how the domain was verified.
+    let res = member
+        .request(
+            "query ($id: ID!) { organization(id: $id) { verification { txtRecord } } }",
+            json!({ "id": org_id }),
+        )
+        .await;
+    assert_eq!(res.error_codes(), vec!["Unauthorized"]);
+
+    let res = member
+        .request(
+            "mutation ($id: ID!) { confirmAffiliation(id: $id) { confirmed } }",
+            json!({ "id": claim_id }),
+        )
+        .await
+        .data();
+    assert_eq!(res["confirmAffiliation"]["confirmed"], true);
+
+    let me = member
+        .query("{ me { affiliation { handle domain verified } } }")
+        .await
+        .data();
+    assert_eq!(
+        me["me"]["affiliation"],
+        json!({ "handle": "acme", "domain": "acme.test", "verified": true })
+    );
+
+    let (status, html) = server.client().page("/users/member").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(
+        html.contains(r#"<meta property="og:description" content="Member of Acme (acme.test)">"#),
+        "{html}"
+    );
+}
+
+#[tokio::test]
+async fn test_post_as_organization() {
+    let domains = MemoryDomainVerifier::default();
+    let server = TestServer::start_with(|config| config.domains = Arc::new(domains.clone())).await;
+    let owner = server.register().await;
+    let editor = server.register_as("editor", "editor-password").await;
+
+    let created = owner
+        .query(r#"mutation { createOrganization(create: { handle: "acme" }) { id } }"#)
+        .await
+        .data();
+    let org_id = created["createOrganization"]["id"].clone();
+    let res = owner
+        .request(
+            r#"mutation ($id: ID!) {
+                setOrganizationDomain(id: $id, domain: "acme.test") { verification { token } }
+            }"#,
+            json!({ "id": org_id }),
+        )
+        .await
+        .data();
+    domains.publish_well_known(
+        "acme.test",
+        res["setOrganizationDomain"]["verification"]["token"]
+            .as_str()
+            .unwrap(),
+    );
+    let res = owner
+        .request(
+            "mutation ($id: ID!) { verifyOrganization(id: $id) { verified } }",
+            json!({ "id": org_id }),
+        )
+        .await
+        .data();
+    assert_eq!(res["verifyOrganization"]["verified"], true);
+
+    let res = owner
+        .request(
+            "mutation ($id: ID!, $account: ID!) {
+                claimMember(id: $id, accountId: $account, role: { role: EDITOR }) { id role permissions }
+            }",
+            json!({ "id": org_id, "account": editor.account_id() }),
+        )
+        .await
+        .data();
+    assert_eq!(res["claimMember"]["role"], "EDITOR");
+    assert_eq!(
+        res["claimMember"]["permissions"],
+        json!(["POST", "EDIT_POSTS", "DELETE_POSTS"])
+    );
+    let res = editor
+        .request(
+            "mutation ($id: ID!) { confirmAffiliation(id: $id) { confirmed } }",
+            json!({ "id": res["claimMember"]["id"] }),
+        )
+        .await
+        .data();
+    assert_eq!(res["confirmAffiliation"]["confirmed"], true);
+
+    let res = editor
+        .request(
+            r#"mutation ($org: ID!) {
+                createPost(create: { content: "Hello", organizationId: $org }) {
+                    organization { handle }
+                }
+            }"#,
+            json!({ "org": org_id }),
+        )
+        .await
+        .data();
+    assert_eq!(
+        res["createPost"]["organization"],
+        json!({ "handle": "acme" })
+    );
+
+    // The editor can post, but can't see who did what.
+    let res = editor
+        .request(
+            "query ($id: ID!) { organization(id: $id) { activity { nodes { action } } } }",
+            json!({ "id": org_id }),
+        )
+        .await;
+    assert_eq!(res.error_codes(), vec!["Unauthorized"]);
+
+    let res = owner
+        .request(
+            "query ($id: ID!) { organization(id: $id) { activity { nodes { action actorId } } } }",
+            json!({ "id": org_id }),
+        )
+        .await
+        .data();
+    assert_eq!(
+        res["organization"]["activity"]["nodes"],
+        json!([
+            { "action": "POST_CREATED", "actorId": editor.account_id() },
+            { "action": "MEMBER_CLAIMED", "actorId": owner.account_id() },
+        ])
+    );
+}
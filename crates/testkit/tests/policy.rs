@@ -0,0 +1,46 @@
+use plazer_testkit::TestServer;
+use pretty_assertions::assert_eq;
+use serde_json::json;
+
+#[tokio::test]
+async fn test_reaccept() {
+    let server = TestServer::start().await;
+    let admin = server.register().await;
+    let publish = r#"mutation { publishPolicy(kind: TERMS, content: "Be nice") { id version } }"#;
+    let terms = admin.query(publish).await.data();
+    assert_eq!(terms["publishPolicy"]["version"], 1);
+
+    // Registering without accepting the terms is rejected.
+    let res = server
+        .client()
+        .query(r#"mutation { createAccount(create: { userId: "nope", pword: "password" }) { accessToken } }"#)
+        .await;
+    assert_eq!(res.error_codes(), vec!["PoliciesNotAccepted"]);
+
+    let client = server.register().await;
+    let create_board = r#"mutation { createBoard(create: { handle: "cats" }) { id } }"#;
+    assert!(client.query(create_board).await.errors.is_empty());
+
+    // Once new terms are published, changes are rejected until they're
+    // accepted.
+    let terms = admin.query(publish).await.data();
+    assert_eq!(terms["publishPolicy"]["version"], 2);
+    let res = client.query(create_board).await;
+    assert_eq!(res.error_codes(), vec!["PoliciesNotAccepted"]);
+
+    let res = client
+        .request(
+            "mutation($ids: [ID!]!) { acceptPolicies(ids: $ids) { version } }",
+            json!({ "ids": [terms["publishPolicy"]["id"]] }),
+        )
+        .await
+        .data();
+    assert_eq!(res, json!({ "acceptPolicies": [{ "version": 2 }] }));
+
+    let res = client.query("{ outstandingPolicies { id } }").await.data();
+    assert_eq!(res, json!({ "outstandingPolicies": [] }));
+    let res = client
+        .query(r#"mutation { createBoard(create: { handle: "dogs" }) { id } }"#)
+        .await;
+    assert!(res.errors.is_empty());
+}
@@ -0,0 +1,86 @@
+use hyper::{header::RETRY_AFTER, Method, StatusCode};
+use plazer_service::config::{RateBudget, RateLimitConfig};
+use plazer_testkit::TestServer;
+use pretty_assertions::assert_eq;
+use serde_json::{json, Value};
+
+static LOGIN: &str = r#"mutation {
+    login(creds: { userId: "alice", pword: "test-password" }) { __typename }
+}"#;
+
+#[tokio::test]
+async fn test_rate_limit() {
+    let server = TestServer::start_with(|config| {
+        config.rate_limits = RateLimitConfig {
+            auth: RateBudget::per_minute(2),
+            read: RateBudget::per_minute(3),
+            write: RateBudget::per_minute(0),
+        };
+    })
+    .await;
+    // Starting the server and registering take two reads and one sign in
+    // from this address.
+    let alice = server.register_as("alice", "test-password").await;
+    let anon = server.client();
+
+    let (status, _) = anon
+        .rest(
+            Method::POST,
+            "/v1/sessions",
+            Some(json!({ "userId": "alice", "password": "test-password" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let res = anon.query(LOGIN).await;
+    assert_eq!(res.error_codes(), vec!["RateLimited"]);
+    // One request is earned back every 30 seconds.
+    let retry_after = res.errors[0].extensions["retryAfter"].as_u64().unwrap();
+    assert!((1..=30).contains(&retry_after), "{retry_after}");
+    assert_eq!(
+        res.extensions["rateLimit"],
+        json!({ "limit": 2, "remaining": 0, "reset": retry_after })
+    );
+    let (status, body) = anon
+        .rest(
+            Method::POST,
+            "/v1/sessions",
+            Some(json!({ "userId": "alice", "password": "test-password" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(body["code"], "RateLimited");
+    assert!(body["retryAfter"].as_u64().is_some());
+
+    // Signed in clients have their own budget, whatever their address.
+    for remaining in (0..3).rev() {
+        let res = alice.fetch("/api/v1/accounts/me", &[]).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()["ratelimit-limit"], "3");
+        assert_eq!(
+            res.headers()["ratelimit-remaining"],
+            remaining.to_string().as_str()
+        );
+        assert!(res.headers().contains_key("ratelimit-reset"));
+    }
+    let res = alice.fetch("/api/v1/accounts/me", &[]).await;
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    let body: Value = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(body["code"], "RateLimited");
+    let retry_after = body["retryAfter"].as_u64().unwrap();
+    assert!((1..=20).contains(&retry_after), "{retry_after}");
+    assert_eq!(res.headers()[RETRY_AFTER], retry_after.to_string().as_str());
+    assert_eq!(res.headers()["ratelimit-remaining"], "0");
+    assert_eq!(
+        res.headers()["ratelimit-reset"],
+        retry_after.to_string().as_str()
+    );
+
+    // Writes aren't limited.
+    for _ in 0..5 {
+        let res = alice
+            .query(r#"mutation { createPost(create: { content: "Hello" }) { id } }"#)
+            .await;
+        assert!(res.errors.is_empty(), "{:#?}", res.errors);
+    }
+}
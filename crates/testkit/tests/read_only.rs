@@ -0,0 +1,69 @@
+use hyper::{Method, StatusCode};
+use plazer_testkit::{Client, GqlResponse, TestServer};
+use pretty_assertions::assert_eq;
+use serde_json::json;
+
+static CREATE_POST: &str = r#"mutation { createPost(create: { content: "Hello" }) { id } }"#;
+
+async fn set_read_only(client: &Client, enabled: bool) -> GqlResponse {
+    client
+        .request(
+            "mutation ($enabled: Boolean!) { setReadOnly(enabled: $enabled) }",
+            json!({ "enabled": enabled }),
+        )
+        .await
+}
+
+#[tokio::test]
+async fn test_read_only() {
+    let server = TestServer::start().await;
+    // The first account is the instance's admin.
+    let admin = server.register().await;
+    let user = server.register_as("user", "test-password").await;
+
+    let res = set_read_only(&user, true).await;
+    assert_eq!(res.error_codes(), vec!["Unauthorized"]);
+    let res = set_read_only(&admin, true).await.data();
+    assert_eq!(res["setReadOnly"], true);
+
+    let res = user.query(CREATE_POST).await;
+    assert_eq!(res.error_codes(), vec!["ReadOnly"]);
+    let (status, body) = user
+        .rest(
+            Method::POST,
+            "/v1/posts",
+            Some(json!({ "content": "Hello" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["code"], "ReadOnly");
+
+    // Queries and signing in keep working.
+    let info = user.query("{ instanceInfo { readOnly } }").await.data();
+    assert_eq!(info["instanceInfo"]["readOnly"], true);
+    let (status, _) = user.rest(Method::GET, "/v1/posts", None).await;
+    assert_eq!(status, StatusCode::OK);
+    server.login("user", "test-password").await;
+
+    let res = set_read_only(&admin, false).await.data();
+    assert_eq!(res["setReadOnly"], false);
+    let res = user.query(CREATE_POST).await;
+    assert!(res.errors.is_empty());
+}
+
+#[tokio::test]
+async fn test_read_only_from_start() {
+    let server = TestServer::start_with(|config| config.read_only.enabled = true).await;
+
+    let res = server
+        .client()
+        .query(
+            r#"mutation {
+                createAccount(create: { userId: "new", pword: "test-password", birthdate: "2000-01-01" }) {
+                    account { id }
+                }
+            }"#,
+        )
+        .await;
+    assert_eq!(res.error_codes(), vec!["ReadOnly"]);
+}
@@ -0,0 +1,139 @@
+use hyper::{Method, StatusCode};
+use plazer_testkit::TestServer;
+use pretty_assertions::assert_eq;
+use serde_json::json;
+
+#[tokio::test]
+async fn test_accounts_and_sessions() {
+    let server = TestServer::start().await;
+    let anon = server.client();
+
+    let creds = json!({ "userId": "rest", "password": "rest-password" });
+    let (status, created) = anon
+        .rest(Method::POST, "/v1/accounts", Some(creds.clone()))
+        .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(created["account"]["userId"], "rest");
+    let id = created["account"]["id"].as_str().unwrap();
+
+    let (status, session) = anon.rest(Method::POST, "/v1/sessions", Some(creds)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(session["account"]["id"], id);
+
+    // Tokens from the REST API work for GraphQL too, and vice versa.
+    let client = server.login("rest", "rest-password").await;
+    let (status, me) = client.rest(Method::GET, "/v1/accounts/me", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(me["id"], id);
+
+    let (status, refreshed) = anon
+        .rest(
+            Method::POST,
+            "/v1/sessions/refresh",
+            Some(json!({ "refreshToken": session["refreshToken"] })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(refreshed["account"]["id"], id);
+
+    // Refresh tokens can only be used once.
+    let (status, err) = anon
+        .rest(
+            Method::POST,
+            "/v1/sessions/refresh",
+            Some(json!({ "refreshToken": session["refreshToken"] })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(err["code"], "CredentialsInvalid");
+
+    let (status, err) = anon.rest(Method::GET, "/v1/accounts/me", None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(err["code"], "Unauthenticated");
+
+    let (status, err) = anon
+        .rest(
+            Method::POST,
+            "/v1/sessions",
+            Some(json!({ "userId": "rest", "password": "short" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(err["code"], "InputInvalid");
+}
+
+#[tokio::test]
+async fn test_posts() {
+    let server = TestServer::start().await;
+    let client = server.register().await;
+
+    let (status, post) = client
+        .rest(
+            Method::POST,
+            "/v1/posts",
+            Some(json!({ "title": "Hello", "content": "From REST" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(post["creatorId"], client.account_id().unwrap());
+    let id = post["id"].as_str().unwrap();
+
+    let (status, page) = server.client().rest(Method::GET, "/v1/posts", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(page["posts"], json!([post]));
+    assert_eq!(page["nextCursor"], json!(null));
+
+    let path = format!("/v1/posts/{id}");
+    let (status, _) = client.rest(Method::DELETE, &path, None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, err) = client.rest(Method::GET, &path, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(err["code"], "NotFound");
+}
+
+#[tokio::test]
+async fn test_openapi() {
+    let server = TestServer::start().await;
+
+    let (status, doc) = server
+        .client()
+        .rest(Method::GET, "/openapi.json", None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(doc["openapi"], "3.0.3");
+    assert!(doc["paths"]["/posts/{id}"]["delete"].is_object());
+}
+
+#[tokio::test]
+async fn test_usage_export() {
+    let server = TestServer::start().await;
+    // The first account is the instance's admin.
+    let admin = server.register().await;
+    let user = server.register_as("user", "test-password").await;
+
+    let (status, err) = user
+        .rest(Method::GET, "/v1/admin/usage?from=2000-01-01", None)
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(err["code"], "Unauthorized");
+
+    // Usage is first recorded as the server starts, alongside it handling
+    // requests, so it might take a moment to show up.
+    let mut records = json!([]);
+    for _ in 0..20 {
+        let (status, body) = admin
+            .rest(
+                Method::GET,
+                "/v1/admin/usage?from=2000-01-01&format=json",
+                None,
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        records = body;
+        if records[0].is_object() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    assert_eq!(records[0]["tenant"], "test/test");
+}
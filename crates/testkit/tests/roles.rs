@@ -0,0 +1,126 @@
+use plazer_testkit::{Client, GqlResponse, TestServer};
+use pretty_assertions::assert_eq;
+use serde_json::json;
+
+static MODERATION_QUEUE: &str = "{ admin { moderationQueue { nodes { id } } } }";
+
+async fn set_role(client: &Client, id: &str, role: &str) -> GqlResponse {
+    client
+        .request(
+            "mutation ($id: ID!, $role: AccountRole!) {
+                setAccountRole(id: $id, role: $role) { role }
+            }",
+            json!({ "id": id, "role": role }),
+        )
+        .await
+}
+
+async fn create_board_role(client: &Client, board_id: &str) -> GqlResponse {
+    client
+        .request(
+            "mutation ($boardId: ID!) {
+                createBoardRole(boardId: $boardId, create: {
+                    name: \"Commenter\",
+                    permissions: [COMMENT]
+                }) { id name permissions }
+            }",
+            json!({ "boardId": board_id }),
+        )
+        .await
+}
+
+async fn create_board_post(
+    client: &Client,
+    board_id: &str,
+    reply_to_id: Option<&str>,
+) -> GqlResponse {
+    client
+        .request(
+            "mutation ($boardId: ID!, $replyToId: ID) {
+                createPost(create: {
+                    boardId: $boardId,
+                    replyToId: $replyToId,
+                    content: \"Hello\"
+                }) { id }
+            }",
+            json!({ "boardId": board_id, "replyToId": reply_to_id }),
+        )
+        .await
+}
+
+#[tokio::test]
+async fn test_role_guards() {
+    let server = TestServer::start().await;
+    // The first account is the instance's admin.
+    let admin = server.register().await;
+    let moderator = server.register().await;
+    let user = server.register().await;
+
+    let me = admin.query("{ me { role } }").await.data();
+    assert_eq!(me["me"]["role"], "ADMIN");
+    let res = user.query(MODERATION_QUEUE).await;
+    assert_eq!(res.error_codes(), vec!["Unauthorized"]);
+    let res = set_role(&user, moderator.account_id().unwrap(), "MODERATOR").await;
+    assert_eq!(res.error_codes(), vec!["Unauthorized"]);
+
+    let res = set_role(&admin, moderator.account_id().unwrap(), "MODERATOR")
+        .await
+        .data();
+    assert_eq!(res["setAccountRole"]["role"], "MODERATOR");
+
+    // Moderators can reach the moderation queue, but not the rest of the
+    // admin namespace.
+    let res = moderator.query(MODERATION_QUEUE).await;
+    assert!(res.errors.is_empty());
+    let res = moderator.query("{ admin { projections { name } } }").await;
+    assert_eq!(res.error_codes(), vec!["Unauthorized"]);
+    let res = moderator
+        .query("mutation { setReadOnly(enabled: true) }")
+        .await;
+    assert_eq!(res.error_codes(), vec!["Unauthorized"]);
+    let res = set_role(&moderator, user.account_id().unwrap(), "MODERATOR").await;
+    assert_eq!(res.error_codes(), vec!["Unauthorized"]);
+}
+
+#[tokio::test]
+async fn test_board_roles() {
+    let server = TestServer::start().await;
+    let _admin = server.register().await;
+    let owner = server.register().await;
+    let user = server.register().await;
+
+    let res = owner
+        .query(r#"mutation { createBoard(create: { handle: "roles" }) { id } }"#)
+        .await
+        .data();
+    let board_id = res["createBoard"]["id"].as_str().unwrap().to_owned();
+    let res = create_board_role(&user, &board_id).await;
+    assert_eq!(res.error_codes(), vec!["Unauthorized"]);
+    let res = create_board_role(&owner, &board_id).await.data();
+    assert_eq!(res["createBoardRole"]["permissions"], json!(["COMMENT"]));
+    let role_id = res["createBoardRole"]["id"].as_str().unwrap().to_owned();
+
+    let res = create_board_post(&owner, &board_id, None).await.data();
+    let post_id = res["createPost"]["id"].as_str().unwrap().to_owned();
+    let res = owner
+        .request(
+            "mutation ($roleId: ID!, $accountId: ID!) {
+                assignBoardRole(roleId: $roleId, accountId: $accountId) { roleId }
+            }",
+            json!({ "roleId": role_id, "accountId": user.account_id().unwrap() }),
+        )
+        .await
+        .data();
+    assert_eq!(res["assignBoardRole"]["roleId"], role_id);
+
+    // The role replaces what members can do, so the account can only reply.
+    let res = user
+        .query(r#"{ board(handle: "roles") { permissions } }"#)
+        .await
+        .data();
+    assert_eq!(res["board"]["permissions"], json!(["COMMENT"]));
+    let res = create_board_post(&user, &board_id, None).await;
+    assert_eq!(res.error_codes(), vec!["Unauthorized"]);
+    let res = create_board_post(&user, &board_id, Some(&post_id)).await;
+    assert!(res.errors.is_empty());
+}
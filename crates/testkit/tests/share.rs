@@ -0,0 +1,78 @@
+use hyper::{Method, StatusCode};
+use plazer_testkit::TestServer;
+use pretty_assertions::assert_eq;
+use serde_json::json;
+
+#[tokio::test]
+async fn test_post_preview() {
+    let server = TestServer::start_with(|config| {
+        config.instance.public_url = Some("https://plazer.test".into());
+    })
+    .await;
+    let client = server.register_as("sharer", "sharer-password").await;
+
+    let (_, post) = client
+        .rest(
+            Method::POST,
+            "/v1/posts",
+            Some(json!({ "content": "Look <here> & there" })),
+        )
+        .await;
+    let id = post["id"].as_str().unwrap();
+
+    let (status, html) = server.client().page(&format!("/posts/{id}")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(html.contains(r#"<meta property="og:title" content="Post by @sharer">"#));
+    assert!(html
+        .contains(r#"<meta property="og:description" content="Look &lt;here&gt; &amp; there">"#));
+    assert!(html.contains(&format!(
+        r#"<meta property="og:url" content="https://plazer.test/posts/{id}">"#
+    )));
+
+    let url = format!("https://plazer.test/posts/{id}");
+    let (status, oembed) = server
+        .client()
+        .rest(Method::GET, &format!("/oembed?url={url}"), None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        oembed,
+        json!({
+            "version": "1.0",
+            "type": "link",
+            "title": "Post by @sharer",
+            "author_name": "sharer",
+            "author_url": "https://plazer.test/users/sharer",
+            "provider_name": "Plazer",
+            "provider_url": "https://plazer.test",
+        })
+    );
+
+    // Links to other sites aren't embedded.
+    let (status, _) = server
+        .client()
+        .rest(
+            Method::GET,
+            &format!("/oembed?url=https://elsewhere.test/posts/{id}"),
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_profile_preview() {
+    let server = TestServer::start().await;
+    server.register_as("profile", "profile-password").await;
+
+    let (status, html) = server.client().page("/users/profile").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(html.contains(r#"<meta property="og:type" content="profile">"#));
+    assert!(html.contains(r#"<meta name="twitter:title" content="@profile">"#));
+    assert!(html.contains(
+        r#"<link rel="alternate" type="application/json+oembed" href="/api/oembed?url=%2Fusers%2Fprofile">"#
+    ));
+
+    let (status, _) = server.client().page("/users/nobody").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
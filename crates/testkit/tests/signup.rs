@@ -0,0 +1,43 @@
+use plazer_testkit::TestServer;
+use pretty_assertions::assert_eq;
+use serde_json::json;
+
+#[tokio::test]
+async fn test_bot_signals() {
+    let server = TestServer::start().await;
+    // The first account is the instance's admin.
+    let admin = server.register().await;
+
+    let anonymous = server.client();
+    let register = |user_id: &'static str, honeypot: &'static str| {
+        anonymous.request(
+            "mutation ($userId: String!, $honeypot: String) {
+                createAccount(create: {
+                    userId: $userId
+                    pword: \"test-password\"
+                    birthdate: \"2000-01-01\"
+                    honeypot: $honeypot
+                    formShownAt: \"2000-01-01T00:00:00Z\"
+                }) { account { id } }
+            }",
+            json!({ "userId": user_id, "honeypot": honeypot }),
+        )
+    };
+    // An empty honeypot, with plenty of time taken over the form, is fine.
+    let res = register("person", "").await;
+    assert!(res.errors.is_empty());
+    let bot = register("bot", "https://example.com").await.data();
+
+    let queue = admin
+        .query("{ admin { moderationQueue(first: 10) { nodes { targetId details score } } } }")
+        .await
+        .data();
+    assert_eq!(
+        queue["admin"]["moderationQueue"]["nodes"],
+        json!([{
+            "targetId": bot["createAccount"]["account"]["id"],
+            "details": "signup_risk",
+            "score": 100,
+        }])
+    );
+}
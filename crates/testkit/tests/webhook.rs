@@ -0,0 +1,63 @@
+use std::sync::Arc;
+
+use plazer_service::MemoryWebhookSender;
+use plazer_testkit::TestServer;
+use pretty_assertions::assert_eq;
+use ring::hmac;
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn test_follower_gained() {
+    let sent = MemoryWebhookSender::default();
+    let server = TestServer::start_with(|config| config.webhooks = Arc::new(sent.clone())).await;
+    let client = server.register().await;
+    let follower = server.register_as("follower", "test-password").await;
+
+    let created = client
+        .query(
+            r#"mutation {
+                createWebhook(create: {
+                    url: "https://example.com/hooks"
+                    events: [FOLLOWER_GAINED]
+                }) {
+                    webhook { url events maxPerHour }
+                    secret
+                }
+            }"#,
+        )
+        .await
+        .data();
+    assert_eq!(
+        created["createWebhook"]["webhook"],
+        json!({
+            "url": "https://example.com/hooks",
+            "events": ["FOLLOWER_GAINED"],
+            "maxPerHour": 60,
+        })
+    );
+    let secret = created["createWebhook"]["secret"].as_str().unwrap();
+
+    let res = follower
+        .request(
+            "mutation ($id: ID!) { followAccount(id: $id) }",
+            json!({ "id": client.account_id() }),
+        )
+        .await
+        .data();
+    assert_eq!(res["followAccount"], true);
+
+    let sent = sent.take();
+    assert_eq!(sent.len(), 1);
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    let signature = sent[0].signature.strip_prefix("sha256=").unwrap();
+    let signature: Vec<u8> = (0..signature.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&signature[i..i + 2], 16).unwrap())
+        .collect();
+    assert!(hmac::verify(&key, &sent[0].body, &signature).is_ok());
+
+    let payload: Value = serde_json::from_slice(&sent[0].body).unwrap();
+    assert_eq!(payload["event"], "follower_gained");
+    assert_eq!(payload["accountId"], client.account_id().unwrap());
+    assert_eq!(payload["actorId"], follower.account_id().unwrap());
+}
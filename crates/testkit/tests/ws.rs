@@ -0,0 +1,90 @@
+use std::time::Duration;
+
+use plazer_testkit::TestServer;
+use pretty_assertions::assert_eq;
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn test_query_over_ws() {
+    let server = TestServer::start().await;
+    let client = server.register().await;
+
+    let mut sub = client.subscribe("{ me { id } }", Value::Null).await;
+    let res = sub.next().await.unwrap().data();
+    assert_eq!(res, json!({ "me": { "id": client.account_id() } }));
+    assert!(sub.next().await.is_none());
+    sub.stop().await;
+}
+
+#[tokio::test]
+async fn test_anonymous_over_ws() {
+    let server = TestServer::start().await;
+
+    let mut sub = server
+        .client()
+        .subscribe("{ me { id } }", Value::Null)
+        .await;
+    let res = sub.next().await.unwrap();
+    assert_eq!(res.error_codes(), vec!["Unauthenticated"]);
+    sub.stop().await;
+}
+
+#[tokio::test]
+async fn test_post_created_filtered() {
+    let server = TestServer::start().await;
+    let client = server.register().await;
+    let board_id = |handle: &str| {
+        let client = &client;
+        let query =
+            format!(r#"mutation {{ createBoard(create: {{ handle: "{handle}" }}) {{ id }} }}"#);
+        async move { client.query(&query).await.data()["createBoard"]["id"].clone() }
+    };
+    let (news, other) = (board_id("news").await, board_id("other").await);
+
+    let mut sub = server
+        .client()
+        .subscribe(
+            "subscription ($boardId: ID) { postCreated(boardId: $boardId) { content boardId } }",
+            json!({ "boardId": news }),
+        )
+        .await;
+    // Give the server a moment to start listening for posts.
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    for (board_id, content) in [(&other, "Elsewhere"), (&news, "Headline")] {
+        let res = client
+            .request(
+                "mutation ($boardId: ID, $content: String) {
+                    createPost(create: { boardId: $boardId, content: $content }) { id }
+                }",
+                json!({ "boardId": board_id, "content": content }),
+            )
+            .await;
+        assert!(res.errors.is_empty());
+    }
+
+    let res = sub.next().await.unwrap().data();
+    assert_eq!(
+        res["postCreated"],
+        json!({ "content": "Headline", "boardId": news })
+    );
+    sub.stop().await;
+}
+
+#[tokio::test]
+async fn test_live_metrics() {
+    let server = TestServer::start().await;
+    let admin = server.register().await;
+    let other = server.register().await;
+
+    let query = "subscription { liveMetrics(intervalSecs: 1) { wsConnections } }";
+    let mut sub = other.subscribe(query, json!({})).await;
+    let res = sub.next().await.unwrap();
+    assert_eq!(res.error_codes(), vec!["Unauthorized"]);
+    sub.stop().await;
+
+    let mut sub = admin.subscribe(query, json!({})).await;
+    let res = sub.next().await.unwrap().data();
+    assert_eq!(res["liveMetrics"], json!({ "wsConnections": 1 }));
+    sub.stop().await;
+}